@@ -0,0 +1,99 @@
+//! Fallback selection when the main app can't be reached.
+//!
+//! Without a fallback the portal request is cancelled, which breaks screen
+//! sharing in other apps whenever the main app isn't running. The policy is
+//! chosen with `--fallback=<policy>` in XDPH's `custom_picker_binary`.
+
+use serde::Deserialize;
+use std::process::Command;
+
+/// What to do when the main app can't be queried.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FallbackPolicy {
+    /// Cancel the portal request
+    #[default]
+    Cancel,
+    /// Share the focused monitor without asking
+    FocusedMonitor,
+    /// Show hyprland-share-picker, XDPH's standard chooser
+    Chooser,
+}
+
+impl FallbackPolicy {
+    /// Read the policy from command line arguments.
+    pub fn from_args<'a>(args: impl IntoIterator<Item = &'a str>) -> Result<Self, String> {
+        let mut policy = Self::default();
+        for arg in args {
+            if let Some(value) = arg.strip_prefix("--fallback=") {
+                policy = match value {
+                    "cancel" => Self::Cancel,
+                    "focused" => Self::FocusedMonitor,
+                    "chooser" => Self::Chooser,
+                    _ => return Err(format!("Unknown fallback policy: {}", value)),
+                };
+            }
+        }
+        Ok(policy)
+    }
+}
+
+/// Monitor entry from `hyprctl -j monitors`.
+#[derive(Debug, Deserialize)]
+struct HyprMonitor {
+    name: String,
+    focused: bool,
+}
+
+/// Pick a source according to `policy`, returning the line to print for XDPH.
+pub fn select(policy: FallbackPolicy, allow_token: bool) -> Option<String> {
+    match policy {
+        FallbackPolicy::Cancel => None,
+        FallbackPolicy::FocusedMonitor => {
+            let output = Command::new("hyprctl").args(["-j", "monitors"]).output().ok()?;
+            let monitor = focused_monitor(&String::from_utf8_lossy(&output.stdout))?;
+            Some(format!("[SELECTION]{}/screen:{}", crate::selection_flags(allow_token), monitor))
+        }
+        FallbackPolicy::Chooser => {
+            // The chooser reads XDPH_WINDOW_SHARING_LIST from our environment
+            let mut command = Command::new("hyprland-share-picker");
+            if allow_token {
+                command.arg("--allow-token");
+            }
+            let output = command.output().ok()?;
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .find(|line| line.starts_with("[SELECTION]"))
+                .map(str::to_string)
+        }
+    }
+}
+
+/// Name of the focused monitor in `hyprctl -j monitors` output, or the first
+/// monitor if none is focused.
+fn focused_monitor(json: &str) -> Option<String> {
+    let monitors: Vec<HyprMonitor> = serde_json::from_str(json).ok()?;
+    let monitor = monitors.iter().find(|m| m.focused).or(monitors.first())?;
+    Some(monitor.name.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_from_args() {
+        assert_eq!(FallbackPolicy::from_args([]), Ok(FallbackPolicy::Cancel));
+        assert_eq!(
+            FallbackPolicy::from_args(["--allow-token", "--fallback=focused"]),
+            Ok(FallbackPolicy::FocusedMonitor)
+        );
+        assert!(FallbackPolicy::from_args(["--fallback=ask"]).is_err());
+    }
+
+    #[test]
+    fn test_focused_monitor() {
+        let json = r#"[{"id":0,"name":"DP-1","focused":false},{"id":1,"name":"HDMI-A-1","focused":true}]"#;
+        assert_eq!(focused_monitor(json).as_deref(), Some("HDMI-A-1"));
+        assert_eq!(focused_monitor("[]"), None);
+    }
+}
@@ -1,8 +1,12 @@
 //! IPC client for communicating with the main screen-recorder app.
 //!
 //! Connects to the Unix socket server in the main app to query the current
-//! capture selection when XDPH invokes us.
+//! capture selection and sharing policy when XDPH invokes us, to report requests we had to
+//! refuse so the main app can show them, and to answer the main app's
+//! health checks.
 
+use crate::permissions::Permission;
+use crate::policy::PickerPolicy;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
@@ -12,11 +16,31 @@ use tokio::net::UnixStream;
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum IpcRequest {
-    /// Query the current capture selection.
-    QuerySelection,
+    /// Query the selection to share with the requesting app.
+    QuerySelection {
+        /// The requesting app, if known
+        #[serde(skip_serializing_if = "Option::is_none")]
+        app_id: Option<String>,
+    },
+    /// Query which apps may share the screen.
+    QueryPolicy,
+    /// Report what became of a portal request.
+    Notify { event: PortalEvent },
+    /// Report the approvals the picker remembers.
+    ReportPermissions { permissions: Vec<Permission> },
+    /// Check that the main app is answering.
+    Ping,
+}
+
+/// Portal request outcomes the picker reports to the main app.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum PortalEvent {
+    /// The request was refused
+    Denied { reason: String },
 }
 
-/// Geometry for region capture.
+/// Geometry for region capture, in logical pixels relative to the monitor.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Geometry {
     pub x: i32,
@@ -25,6 +49,34 @@ pub struct Geometry {
     pub height: u32,
 }
 
+/// The portal request the picker is run for, as the main app saw it on
+/// the session bus.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortalRequest {
+    pub handle: String,
+    pub session_handle: String,
+    /// The requesting app's ID, if the main app could find it
+    pub app_id: Option<String>,
+    /// The requesting app's process
+    pub pid: Option<u32>,
+    /// 0 for this session, 1 while the app runs, 2 until revoked
+    pub persist_mode: u32,
+    /// Whether the sources are for a remote desktop session, which the
+    /// main app serves as a portal backend
+    #[serde(default)]
+    pub remote_desktop: bool,
+}
+
+/// The main app's answer to a policy query.
+#[derive(Debug, Clone)]
+pub struct PolicyAnswer {
+    pub policy: PickerPolicy,
+    /// Whether the request is the main app's own
+    pub own_request: bool,
+    /// The request being picked for, if the main app saw it
+    pub request: Option<PortalRequest>,
+}
+
 /// IPC response from main app to picker.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -41,20 +93,99 @@ pub enum IpcResponse {
     },
     /// No selection available.
     NoSelection,
+    /// Which apps may share the screen.
+    Policy {
+        policy: PickerPolicy,
+        /// Whether the request being picked for is the main app's own
+        own_request: bool,
+        /// The request being picked for, if the main app saw it
+        #[serde(default)]
+        request: Option<PortalRequest>,
+    },
+    /// Notification received.
+    Ack,
+    /// Answer to a ping.
+    Pong,
     /// Error occurred.
     Error { message: String },
 }
 
+/// The main app's ID, which names its Flatpak.
+pub const APP_ID: &str = "com.keath.screen-recorder";
+
 /// Get the IPC socket path.
+///
+/// Must match the main app's `picker` endpoint (see `ipc::socket_path`).
+/// The picker runs on the host even when the app is a Flatpak, which puts
+/// its socket in the directory it shares with the host, so that's tried
+/// when there's no socket in the usual place.
 fn get_socket_path() -> PathBuf {
-    let runtime_dir = std::env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| "/tmp".to_string());
-    PathBuf::from(runtime_dir)
-        .join("screen-recorder")
-        .join("picker.sock")
+    let runtime_dir = PathBuf::from(std::env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| "/tmp".to_string()));
+    socket_paths(&runtime_dir)
+        .into_iter()
+        .find(|path| path.exists())
+        .unwrap_or_else(|| runtime_dir.join("screen-recorder").join("picker.sock"))
+}
+
+/// Where the app may have put its socket, most likely first.
+fn socket_paths(runtime_dir: &std::path::Path) -> [PathBuf; 2] {
+    [
+        runtime_dir.join("screen-recorder").join("picker.sock"),
+        runtime_dir.join("app").join(APP_ID).join("picker.sock"),
+    ]
+}
+
+/// Query the main app for the selection to share with `app_id`: its own
+/// if the main app set one for it, otherwise the current selection.
+pub async fn query_selection(app_id: Option<String>) -> Result<IpcResponse, String> {
+    send_request(&IpcRequest::QuerySelection { app_id }).await
 }
 
-/// Query the main app for the current capture selection.
-pub async fn query_selection() -> Result<IpcResponse, String> {
+/// Query the main app for the sharing policy and the request being picked for.
+pub async fn query_policy() -> Result<PolicyAnswer, String> {
+    match send_request(&IpcRequest::QueryPolicy).await? {
+        IpcResponse::Policy {
+            policy,
+            own_request,
+            request,
+        } => Ok(PolicyAnswer {
+            policy,
+            own_request,
+            request,
+        }),
+        IpcResponse::Error { message } => Err(message),
+        other => Err(format!("Unexpected response: {:?}", other)),
+    }
+}
+
+/// Tell the main app what became of the portal request.
+pub async fn notify(event: PortalEvent) -> Result<(), String> {
+    match send_request(&IpcRequest::Notify { event }).await? {
+        IpcResponse::Ack => Ok(()),
+        IpcResponse::Error { message } => Err(message),
+        other => Err(format!("Unexpected response: {:?}", other)),
+    }
+}
+
+/// Tell the main app which approvals the picker remembers.
+pub async fn report_permissions(permissions: Vec<Permission>) -> Result<(), String> {
+    match send_request(&IpcRequest::ReportPermissions { permissions }).await? {
+        IpcResponse::Ack => Ok(()),
+        IpcResponse::Error { message } => Err(message),
+        other => Err(format!("Unexpected response: {:?}", other)),
+    }
+}
+
+/// Check that the main app is reachable and answering.
+pub async fn ping() -> Result<(), String> {
+    match send_request(&IpcRequest::Ping).await? {
+        IpcResponse::Pong => Ok(()),
+        other => Err(format!("Unexpected response: {:?}", other)),
+    }
+}
+
+/// Send one request to the main app and read its response.
+async fn send_request(request: &IpcRequest) -> Result<IpcResponse, String> {
     let socket_path = get_socket_path();
 
     // Connect to the Unix socket
@@ -68,10 +199,9 @@ pub async fn query_selection() -> Result<IpcResponse, String> {
     let (reader, mut writer) = stream.into_split();
     let mut reader = BufReader::new(reader);
 
-    // Send query request
-    let request = IpcRequest::QuerySelection;
+    // Send request
     let request_json =
-        serde_json::to_string(&request).map_err(|e| format!("Failed to serialize request: {}", e))?;
+        serde_json::to_string(request).map_err(|e| format!("Failed to serialize request: {}", e))?;
 
     writer
         .write_all(request_json.as_bytes())
@@ -103,11 +233,37 @@ pub async fn query_selection() -> Result<IpcResponse, String> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_socket_paths() {
+        let [native, flatpak] = socket_paths(std::path::Path::new("/run/user/1000"));
+        assert_eq!(native, PathBuf::from("/run/user/1000/screen-recorder/picker.sock"));
+        assert_eq!(flatpak, PathBuf::from("/run/user/1000/app/com.keath.screen-recorder/picker.sock"));
+    }
+
     #[test]
     fn test_serialize_query_selection() {
-        let request = IpcRequest::QuerySelection;
+        let request = IpcRequest::QuerySelection { app_id: None };
         let json = serde_json::to_string(&request).unwrap();
         assert_eq!(json, r#"{"type":"query_selection"}"#);
+        let request = IpcRequest::QuerySelection {
+            app_id: Some("us.zoom.Zoom".to_string()),
+        };
+        let json = serde_json::to_string(&request).unwrap();
+        assert_eq!(json, r#"{"type":"query_selection","app_id":"us.zoom.Zoom"}"#);
+    }
+
+    #[test]
+    fn test_serialize_notify() {
+        let request = IpcRequest::Notify {
+            event: PortalEvent::Denied {
+                reason: "Unknown source type".to_string(),
+            },
+        };
+        let json = serde_json::to_string(&request).unwrap();
+        assert_eq!(
+            json,
+            r#"{"type":"notify","event":{"event":"denied","reason":"Unknown source type"}}"#
+        );
     }
 
     #[test]
@@ -150,6 +306,22 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_deserialize_policy() {
+        let json = r#"{"type":"policy","policy":{"default":"ask"},"own_request":false,"request":{"handle":"/org/freedesktop/portal/desktop/request/1_42/t","session_handle":"/s","app_id":"org.mozilla.firefox","pid":4213,"persist_mode":2}}"#;
+        match serde_json::from_str(json).unwrap() {
+            IpcResponse::Policy { request, .. } => {
+                let request = request.unwrap();
+                assert_eq!(request.app_id.as_deref(), Some("org.mozilla.firefox"));
+                assert_eq!(request.persist_mode, 2);
+            }
+            _ => panic!("Expected Policy response"),
+        }
+        // From a main app that doesn't watch for requests
+        let json = r#"{"type":"policy","policy":{},"own_request":true}"#;
+        assert!(matches!(serde_json::from_str(json).unwrap(), IpcResponse::Policy { request: None, .. }));
+    }
+
     #[test]
     fn test_deserialize_no_selection() {
         let json = r#"{"type":"no_selection"}"#;
@@ -0,0 +1,56 @@
+//! Logging to stderr and to the picker's log files.
+//!
+//! XDPH sends the picker's stderr to its own log (the journal, usually),
+//! where users rarely look, so each event also goes to the `picker` files in
+//! the main app's log directory, where the app's log viewer and diagnostics
+//! bundles find them. The files are written by the same code as the app's,
+//! so they're named, rotated and formatted the same way.
+
+#[path = "../../src-tauri/src/logging/files.rs"]
+mod files;
+
+use std::path::PathBuf;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// Log an informational line.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        tracing::info!(target: "Picker", $($arg)*)
+    };
+}
+
+/// Log a failure.
+macro_rules! error {
+    ($($arg:tt)*) => {
+        tracing::error!(target: "Picker", $($arg)*)
+    };
+}
+
+pub(crate) use {error, info};
+
+/// Start logging to stderr and, when the main app's log directory can be
+/// found, to the log files.
+pub fn init() {
+    let file = log_dir().and_then(|dir| files::layer(&dir, "picker").ok());
+    let _ = tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_ansi(false).with_writer(std::io::stderr))
+        .with(file)
+        .with(LevelFilter::INFO)
+        .try_init();
+}
+
+/// The main app's log directory: `logs/` in its data directory, which is
+/// under `~/.var/app` when the app is installed as a Flatpak.
+fn log_dir() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let flatpak = home
+        .as_ref()
+        .map(|home| home.join(".var/app").join(crate::ipc_client::APP_ID).join("data"))
+        .filter(|dir| dir.is_dir());
+    let data = flatpak
+        .or_else(|| std::env::var_os("XDG_DATA_HOME").map(PathBuf::from).filter(|dir| dir.is_absolute()))
+        .or_else(|| home.map(|home| home.join(".local/share")))?;
+    Some(data.join("screen-recorder").join("logs"))
+}
@@ -9,7 +9,8 @@
 //!
 //! 1. XDPH invokes this binary when a screencast request needs source selection
 //! 2. We connect to the main app's IPC socket
-//! 3. We query for the current capture selection
+//! 3. We query for the selection to share with the requesting app: one the
+//!    main app set for it, or the current capture selection
 //! 4. We output the selection to stdout in XDPH format: `[SELECTION]/<type>:<id>`
 //! 5. XDPH parses our output and continues the portal flow
 //!
@@ -19,98 +20,192 @@
 //! - Window: `[SELECTION]/window:<window_handle>`
 //! - Region: `[SELECTION]/region:<output>@<x>,<y>,<w>,<h>`
 //!
-//! If no selection is available or the main app isn't running, we exit with
-//! an error, causing XDPH to cancel the portal request.
+//! If no selection is available, or it isn't one XDPH can share (see
+//! `selection`), we exit with an error, causing XDPH to cancel the portal
+//! request, and tell the main app why so it can show the user. If the main app isn't running, the
+//! `--fallback=<cancel|focused|chooser>` policy decides: cancel (the
+//! default), share the focused monitor, or show hyprland-share-picker.
+//!
+//! # Per-app policy
+//!
+//! Before answering, the picker applies the main app's policy for the
+//! requesting app (see `policy`): denied apps are refused, and always
+//! allowed apps share the focused monitor while the main app isn't
+//! running, whatever the fallback. XDPH doesn't tell the picker which app
+//! is asking, so the main app watches the session bus for the request and
+//! says which app made it when asked for the policy; `--app-id=<id>` is
+//! used when it can't.
+//!
+//! Remote desktop sessions choose their sources through the same ScreenCast
+//! call, which the main app's RemoteDesktop portal backend passes on to
+//! XDPH, so they're answered like any other request and the policy applies
+//! to them too.
+//!
+//! # Remembered approvals
+//!
+//! An app that asks for its screencast to persist has the approval
+//! remembered (see `permissions`): the next time it asks, the same source
+//! is shared without querying the main app, until the approval runs out or
+//! the main app revokes it. The picker reports its approvals to the main
+//! app after each request.
+//!
+//! # Restore tokens
+//!
+//! XDPH implements the portal itself, including restore tokens. When the
+//! requesting app asks for its selection to persist, XDPH passes
+//! `--allow-token` and we answer with the `r` flag (`[SELECTION]r/...`) so
+//! XDPH issues a token for the selection. XDPH stores and validates the
+//! tokens, and restores a session from a valid token without invoking the
+//! picker, so the main app isn't queried again.
+//!
+//! Both persist modes (until the app exits, and until revoked) map to the
+//! same `--allow-token` request, and XDPH decides how long its token stays
+//! valid; the picker's remembered approvals follow the mode the app asked
+//! for.
+//!
+//! # Health checks
+//!
+//! Run with `--ping`, the picker pings the main app over IPC and exits
+//! successfully if it answers. The main app runs it this way periodically,
+//! which checks the installed binary runs and can reach the app, as XDPH
+//! will need it to.
 
+mod fallback;
 mod ipc_client;
+mod log;
+mod permissions;
+mod policy;
+mod selection;
 
-use ipc_client::{query_selection, IpcResponse};
-use std::process::ExitCode;
-
-/// Window entry from XDPH's window list.
-#[derive(Debug)]
-struct WindowEntry {
-    /// XDPH's internal handle ID (lower 32 bits)
-    handle_id: u64,
-    /// Window class
-    class: String,
-    /// Window title
-    title: String,
-    /// Hyprland window address
-    window_addr: u64,
-}
-
-/// Parse the XDPH_WINDOW_SHARING_LIST environment variable.
-/// Format: <id>[HC>]<class>[HT>]<title>[HE>]<window_addr>[HA>]...
-fn parse_window_list(env_value: &str) -> Vec<WindowEntry> {
-    let mut windows = Vec::new();
-    let mut remaining = env_value;
-
-    while !remaining.is_empty() {
-        // Parse ID
-        let Some(id_end) = remaining.find("[HC>]") else {
-            break;
-        };
-        let id_str = &remaining[..id_end];
-
-        // Parse class
-        remaining = &remaining[id_end + 5..];
-        let Some(class_end) = remaining.find("[HT>]") else {
-            break;
-        };
-        let class = &remaining[..class_end];
+use fallback::FallbackPolicy;
+use ipc_client::{
+    notify, ping, query_policy, query_selection, report_permissions, IpcResponse, PolicyAnswer, PortalEvent,
+};
+use log::{error, info};
+use permissions::{PermissionStore, SharedSource};
+use policy::{app_id_from_args, AppPolicy, PickerPolicy};
+use selection::{format_selection, parse_monitor_list, parse_window_list};
+use std::process::{Command, ExitCode};
 
-        // Parse title
-        remaining = &remaining[class_end + 5..];
-        let Some(title_end) = remaining.find("[HE>]") else {
-            break;
-        };
-        let title = &remaining[..title_end];
+/// Flags XDPH reads between `[SELECTION]` and the selection.
+fn selection_flags(allow_token: bool) -> &'static str {
+    // `r` lets XDPH issue a restore token for the selection
+    if allow_token {
+        "r"
+    } else {
+        ""
+    }
+}
 
-        // Parse window address
-        remaining = &remaining[title_end + 5..];
-        let Some(addr_end) = remaining.find("[HA>]") else {
-            break;
+#[tokio::main]
+async fn main() -> ExitCode {
+    // XDPH passes --allow-token when the app requested a persistent session
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--ping") {
+        // Not logged, since the main app pings regularly
+        return match ping().await {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("[screen-recorder-picker] {}", e);
+                ExitCode::FAILURE
+            }
         };
-        let addr_str = &remaining[..addr_end];
+    }
+    log::init();
+    let allow_token = args.iter().any(|arg| arg == "--allow-token");
+    let mut fallback_policy = match FallbackPolicy::from_args(args.iter().map(String::as_str)) {
+        Ok(policy) => policy,
+        Err(e) => {
+            error!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
 
-        // Move past this entry
-        remaining = &remaining[addr_end + 5..];
+    let args_app_id = app_id_from_args(args.iter().map(String::as_str));
 
-        // Parse the values
-        let handle_id = id_str.parse::<u64>().unwrap_or(0);
-        let window_addr = addr_str.parse::<u64>().unwrap_or(0);
+    // Log that we were invoked (visible in journalctl and the app's log viewer)
+    info!("Picker invoked (allow_token={}, app_id={:?})", allow_token, args_app_id);
 
-        windows.push(WindowEntry {
-            handle_id,
-            class: class.to_string(),
-            title: title.to_string(),
-            window_addr,
-        });
+    // Apply the main app's policy for the requesting app, or the copy kept
+    // from its last answer if it can't be reached
+    let PolicyAnswer {
+        policy,
+        own_request,
+        request,
+    } = match query_policy().await {
+        Ok(answer) => {
+            if let Err(e) = answer.policy.save_cached() {
+                error!("Failed to keep a copy of the policy: {}", e);
+            }
+            answer
+        }
+        Err(_) => PolicyAnswer {
+            policy: PickerPolicy::load_cached(),
+            own_request: false,
+            request: None,
+        },
+    };
+    // The app the main app saw make the request, or the one passed in
+    let app_id = request
+        .as_ref()
+        .and_then(|request| request.app_id.clone())
+        .or(args_app_id);
+    if let Some(request) = &request {
+        info!(
+            "{} request from {} (pid {:?}, persist mode {})",
+            if request.remote_desktop { "Remote desktop" } else { "Screencast" },
+            app_id.as_deref().unwrap_or("an unidentified app"),
+            request.pid,
+            request.persist_mode
+        );
+    }
+    let app_policy = match own_request {
+        true => AppPolicy::Ask,
+        false => policy.for_app(app_id.as_deref()),
+    };
+    info!("Policy: {:?}", app_policy);
+    match app_policy {
+        AppPolicy::Deny => {
+            let app = app_id.as_deref().unwrap_or("unidentified apps");
+            return deny(format!("Screen sharing is blocked for {}", app)).await;
+        }
+        AppPolicy::Allow => fallback_policy = FallbackPolicy::FocusedMonitor,
+        AppPolicy::Ask => {}
     }
 
-    windows
-}
-
-/// Find the XDPH handle ID for a given Hyprland window address.
-fn find_window_handle(windows: &[WindowEntry], hyprland_addr: u64) -> Option<u64> {
-    windows
-        .iter()
-        .find(|w| w.window_addr == hyprland_addr)
-        .map(|w| w.handle_id)
-}
-
-#[tokio::main]
-async fn main() -> ExitCode {
-    // Log that we were invoked (visible in journalctl)
-    eprintln!("[screen-recorder-picker] Picker invoked");
+    // Share what was shared before with an app that asked for it to be remembered
+    let mut permissions = PermissionStore::load(&policy);
+    let remembered = match own_request {
+        true => None,
+        false => app_id.as_deref().and_then(|app_id| permissions.find(app_id)).cloned(),
+    };
+    if let Some(source) = remembered {
+        info!("Sharing what was approved before: type={}, id={}", source.source_type, source.source_id);
+        let code = match selection_output(&source, allow_token) {
+            Ok(output) => {
+                info!("Output: {}", output);
+                println!("{}", output);
+                ExitCode::SUCCESS
+            }
+            Err(reason) => deny(reason).await,
+        };
+        report(&permissions).await;
+        return code;
+    }
 
-    // Query the main app for the current selection
-    let response = match query_selection().await {
+    // Query the main app for the selection to share with this app
+    let response = match query_selection(app_id.clone()).await {
         Ok(r) => r,
         Err(e) => {
-            eprintln!("[screen-recorder-picker] Failed to query main app: {}", e);
-            return ExitCode::FAILURE;
+            error!("Failed to query main app: {}", e);
+            return match fallback::select(fallback_policy, allow_token) {
+                Some(output) => {
+                    info!("Fallback ({:?}) output: {}", fallback_policy, output);
+                    println!("{}", output);
+                    ExitCode::SUCCESS
+                }
+                None => ExitCode::FAILURE,
+            };
         }
     };
 
@@ -120,99 +215,102 @@ async fn main() -> ExitCode {
             source_id,
             geometry,
         } => {
-            eprintln!(
-                "[screen-recorder-picker] Got selection: type={}, id={}",
+            info!(
+                "Got selection: type={}, id={}",
                 source_type, source_id
             );
-
-            // Format output for XDPH
-            let output = match source_type.as_str() {
-                "monitor" => {
-                    format!("[SELECTION]/screen:{}", source_id)
-                }
-                "window" => {
-                    // Parse our source_id (Hyprland window address like "0x55df589f63d0")
-                    let hyprland_addr = if source_id.starts_with("0x") {
-                        u64::from_str_radix(&source_id[2..], 16).unwrap_or(0)
-                    } else {
-                        source_id.parse::<u64>().unwrap_or(0)
-                    };
-
-                    eprintln!(
-                        "[screen-recorder-picker] Looking for window with Hyprland addr: 0x{:x}",
-                        hyprland_addr
-                    );
-
-                    // Get the window list from XDPH
-                    let window_list = std::env::var("XDPH_WINDOW_SHARING_LIST").unwrap_or_default();
-                    let windows = parse_window_list(&window_list);
-
-                    eprintln!(
-                        "[screen-recorder-picker] XDPH provided {} windows",
-                        windows.len()
-                    );
-                    for w in &windows {
-                        eprintln!(
-                            "[screen-recorder-picker]   handle={}, addr=0x{:x}, class={}, title={}",
-                            w.handle_id, w.window_addr, w.class, w.title
-                        );
-                    }
-
-                    // Find the XDPH handle for our window
-                    match find_window_handle(&windows, hyprland_addr) {
-                        Some(handle) => {
-                            eprintln!(
-                                "[screen-recorder-picker] Found XDPH handle: {}",
-                                handle
-                            );
-                            format!("[SELECTION]/window:{}", handle)
-                        }
-                        None => {
-                            eprintln!(
-                                "[screen-recorder-picker] Window not found in XDPH list, trying direct address"
-                            );
-                            // Fallback: try using the address directly (may not work)
-                            format!("[SELECTION]/window:{}", hyprland_addr)
-                        }
-                    }
-                }
-                "region" => {
-                    // Region format: screen@x,y,w,h
-                    if let Some(geom) = geometry {
-                        eprintln!("[screen-recorder-picker] Region selection: {}@{},{},{},{}", 
-                            source_id, geom.x, geom.y, geom.width, geom.height);
-                        format!(
-                            "[SELECTION]/region:{}@{},{},{},{}",
-                            source_id, geom.x, geom.y, geom.width, geom.height
-                        )
-                    } else {
-                        eprintln!("[screen-recorder-picker] Region selection missing geometry");
-                        return ExitCode::FAILURE;
-                    }
-                }
-                _ => {
-                    eprintln!(
-                        "[screen-recorder-picker] Unknown source type: {}",
-                        source_type
-                    );
-                    return ExitCode::FAILURE;
-                }
+            let source = SharedSource {
+                source_type,
+                source_id,
+                geometry,
+            };
+            let output = match selection_output(&source, allow_token) {
+                Ok(output) => output,
+                Err(reason) => return deny(reason).await,
             };
 
-            eprintln!("[screen-recorder-picker] Output: {}", output);
+            info!("Output: {}", output);
 
             // Output to stdout - this is what XDPH reads
             println!("{}", output);
+
+            // Remember the approval if the app asked for that
+            if let Some(request) = request.as_ref().filter(|_| !own_request) {
+                if permissions.grant(request, source) {
+                    if let Err(e) = permissions.save() {
+                        error!("Failed to remember the approval: {}", e);
+                    }
+                }
+            }
+            report(&permissions).await;
             ExitCode::SUCCESS
         }
         IpcResponse::NoSelection => {
-            eprintln!("[screen-recorder-picker] No capture selection available in main app");
+            error!("No capture selection available in main app");
             // Output nothing - XDPH will cancel the request
             ExitCode::FAILURE
         }
         IpcResponse::Error { message } => {
-            eprintln!("[screen-recorder-picker] Error from main app: {}", message);
+            deny(format!("Error from main app: {}", message)).await
+        }
+        IpcResponse::Ack | IpcResponse::Pong | IpcResponse::Policy { .. } => {
+            error!("Unexpected response from main app");
             ExitCode::FAILURE
         }
     }
 }
+
+/// XDPH's output for sharing `source`, if it's something XDPH can share.
+fn selection_output(source: &SharedSource, allow_token: bool) -> Result<String, String> {
+    let source_type = source.source_type.as_str();
+
+    // The window list XDPH offers for sharing, if it gave one
+    let windows = std::env::var("XDPH_WINDOW_SHARING_LIST").ok().map(|list| parse_window_list(&list));
+    if source_type == "window" {
+        match windows {
+            Some(ref windows) => {
+                info!("XDPH provided {} windows", windows.len());
+                for w in windows {
+                    info!(
+                        "  handle={}, addr=0x{:x}, class={}, title={}",
+                        w.handle_id, w.window_addr, w.class, w.title
+                    );
+                }
+            }
+            None => info!("XDPH provided no window list, passing the address through"),
+        }
+    }
+
+    // Monitor sizes to keep a region on its monitor, if hyprctl answers
+    let monitors = (source_type == "region")
+        .then(|| Command::new("hyprctl").args(["-j", "monitors"]).output().ok())
+        .flatten()
+        .and_then(|output| parse_monitor_list(&String::from_utf8_lossy(&output.stdout)));
+
+    // Format output for XDPH
+    format_selection(
+        source_type,
+        &source.source_id,
+        source.geometry.as_ref(),
+        selection_flags(allow_token),
+        windows.as_deref(),
+        monitors.as_deref(),
+    )
+}
+
+/// Tell the main app which approvals are remembered, if it's running.
+async fn report(permissions: &PermissionStore) {
+    if let Err(e) = report_permissions(permissions.permissions.clone()).await {
+        info!("Couldn't report remembered approvals: {}", e);
+    }
+}
+
+/// Refuse the portal request, letting the main app know why.
+async fn deny(reason: String) -> ExitCode {
+    error!("{}", reason);
+    if let Err(e) = notify(PortalEvent::Denied { reason }).await {
+        error!("Failed to notify main app: {}", e);
+    }
+    // Output nothing - XDPH will cancel the request
+    ExitCode::FAILURE
+}
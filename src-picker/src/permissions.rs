@@ -0,0 +1,190 @@
+//! Approvals remembered per app.
+//!
+//! When an app asks for its screencast to persist and the request is
+//! approved, the picker remembers what was shared with it in
+//! `$XDG_CONFIG_HOME/screen-recorder/picker-permissions.json` and shares the
+//! same thing next time without asking the main app. An approval the app
+//! asked to keep while it runs (persist mode 1) lasts as long as the process
+//! that asked; one it asked to keep until revoked (mode 2) lasts until the
+//! main app revokes it, which it does by recording the time in the policy
+//! (see `PickerPolicy::revoked`). The picker reports the approvals it holds
+//! to the main app after every request, so the app can list them.
+//!
+//! XDPH keeps its own restore tokens in memory and restores sessions from
+//! them without running the picker, so a revoked app can still restore a
+//! session from a token it was given until XDPH restarts.
+
+use crate::ipc_client::{Geometry, PortalRequest};
+use crate::policy::{config_file, PickerPolicy};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What was shared with an app.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharedSource {
+    /// Type of source: "monitor", "window", or "region"
+    pub source_type: String,
+    /// Source identifier (monitor name or window address)
+    pub source_id: String,
+    /// Geometry for region capture (None for monitor/window)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub geometry: Option<Geometry>,
+}
+
+/// An approval remembered for an app.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Permission {
+    pub app_id: String,
+    pub source: SharedSource,
+    /// 1 while the process that asked runs, 2 until revoked
+    pub persist_mode: u32,
+    /// The process that asked
+    pub pid: u32,
+    /// When that process started, in clock ticks after boot, to tell it
+    /// from a later process given the same ID
+    pub process_start: u64,
+    /// When the approval was given, in milliseconds since the Unix epoch
+    pub granted_at: u64,
+}
+
+impl Permission {
+    /// Whether the approval still holds under `policy`.
+    fn is_valid(&self, policy: &PickerPolicy) -> bool {
+        if policy.revoked.get(&self.app_id).is_some_and(|&revoked| self.granted_at <= revoked) {
+            return false;
+        }
+        match self.persist_mode {
+            1 => process_start(self.pid) == Some(self.process_start),
+            2 => true,
+            _ => false,
+        }
+    }
+}
+
+/// The approvals the picker remembers.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PermissionStore {
+    pub permissions: Vec<Permission>,
+}
+
+impl PermissionStore {
+    /// Load the remembered approvals, dropping those that no longer hold.
+    pub fn load(policy: &PickerPolicy) -> Self {
+        let mut store: Self = std::fs::read_to_string(config_file(FILE))
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        store.permissions.retain(|permission| permission.is_valid(policy));
+        store
+    }
+
+    /// Save the remembered approvals.
+    pub fn save(&self) -> Result<(), String> {
+        let path = config_file(FILE);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+        }
+        let json =
+            serde_json::to_string_pretty(self).map_err(|e| format!("Failed to serialize permissions: {}", e))?;
+        std::fs::write(&path, json).map_err(|e| format!("Failed to write {:?}: {}", path, e))
+    }
+
+    /// What was last shared with `app_id` under an approval that still holds.
+    pub fn find(&self, app_id: &str) -> Option<&SharedSource> {
+        self.permissions
+            .iter()
+            .rev()
+            .find(|permission| permission.app_id == app_id)
+            .map(|permission| &permission.source)
+    }
+
+    /// Remember sharing `source` with the app that made `request`, if it
+    /// asked for the approval to persist and can be identified.
+    pub fn grant(&mut self, request: &PortalRequest, source: SharedSource) -> bool {
+        let (Some(app_id), Some(pid)) = (request.app_id.clone(), request.pid) else {
+            return false;
+        };
+        let Some(process_start) = process_start(pid) else {
+            return false;
+        };
+        if !matches!(request.persist_mode, 1 | 2) {
+            return false;
+        }
+        self.permissions.retain(|permission| permission.app_id != app_id);
+        self.permissions.push(Permission {
+            app_id,
+            source,
+            persist_mode: request.persist_mode,
+            pid,
+            process_start,
+            granted_at: now_ms(),
+        });
+        true
+    }
+}
+
+const FILE: &str = "picker-permissions.json";
+
+/// When process `pid` started, in clock ticks after boot.
+fn process_start(pid: u32) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    start_time_from_stat(&stat)
+}
+
+/// The start time field of a `/proc/<pid>/stat` line. The command name
+/// before it is in parentheses and may contain spaces.
+fn start_time_from_stat(stat: &str) -> Option<u64> {
+    let fields = &stat[stat.rfind(')')? + 1..];
+    // Field 22 overall, the 20th after the command name
+    fields.split_whitespace().nth(19)?.parse().ok()
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn permission(persist_mode: u32, pid: u32, process_start: u64, granted_at: u64) -> Permission {
+        Permission {
+            app_id: "org.mozilla.firefox".to_string(),
+            source: SharedSource {
+                source_type: "monitor".to_string(),
+                source_id: "DP-1".to_string(),
+                geometry: None,
+            },
+            persist_mode,
+            pid,
+            process_start,
+            granted_at,
+        }
+    }
+
+    #[test]
+    fn test_permission_validity() {
+        let policy = PickerPolicy::default();
+        assert!(permission(2, 1, 0, 1_000).is_valid(&policy));
+        assert!(!permission(0, 1, 0, 1_000).is_valid(&policy));
+
+        // A mode 1 approval lasts only while the process that asked runs
+        let own = std::process::id();
+        let started = process_start(own).unwrap();
+        assert!(permission(1, own, started, 1_000).is_valid(&policy));
+        assert!(!permission(1, own, started + 1, 1_000).is_valid(&policy));
+
+        let mut policy = PickerPolicy::default();
+        policy.revoked.insert("org.mozilla.firefox".to_string(), 2_000);
+        assert!(!permission(2, 1, 0, 1_000).is_valid(&policy));
+        assert!(permission(2, 1, 0, 3_000).is_valid(&policy));
+    }
+
+    #[test]
+    fn test_start_time_from_stat() {
+        let stat = "4213 (Web Content) S 1 4213 4213 0 -1 4194560 100 0 0 0 5 3 0 0 20 0 12 0 987654 123 456";
+        assert_eq!(start_time_from_stat(stat), Some(987654));
+        assert_eq!(start_time_from_stat("garbage"), None);
+    }
+}
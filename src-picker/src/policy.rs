@@ -0,0 +1,117 @@
+//! Per-app screen sharing policy.
+//!
+//! The main app decides which apps may share the screen: always allow,
+//! always deny, or ask it, which shares its current selection. The picker
+//! fetches the policy over IPC on every request and keeps a copy in
+//! `$XDG_CONFIG_HOME/screen-recorder/picker-policy.json`, so a denied app
+//! stays denied while the main app isn't running.
+//!
+//! XDPH doesn't say which app is asking, so the main app tells the picker
+//! which app it saw make the request on the session bus. When it can't, or
+//! isn't running, the app ID is only known when passed with
+//! `--app-id=<id>`, and otherwise the default policy applies. The main
+//! app's own requests are always allowed.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// What to do when an app requests a screencast.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AppPolicy {
+    /// Share without asking: the main app's selection, or the focused
+    /// monitor while it isn't running
+    Allow,
+    /// Refuse the request
+    Deny,
+    /// Share the main app's selection, or follow `--fallback` while it
+    /// isn't running
+    #[default]
+    Ask,
+}
+
+/// The policy for apps requesting screencasts.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PickerPolicy {
+    /// Policy for apps without an entry, or that can't be told apart
+    pub default: AppPolicy,
+    /// Policy by app ID
+    pub apps: BTreeMap<String, AppPolicy>,
+    /// When each app's remembered approvals were revoked, in milliseconds
+    /// since the Unix epoch (see `permissions`)
+    pub revoked: BTreeMap<String, u64>,
+}
+
+impl PickerPolicy {
+    /// The policy for the app with `app_id`, if known.
+    pub fn for_app(&self, app_id: Option<&str>) -> AppPolicy {
+        app_id
+            .and_then(|app_id| self.apps.get(app_id))
+            .copied()
+            .unwrap_or(self.default)
+    }
+
+    /// The copy saved from the main app's last answer, or the default
+    /// policy if there's none.
+    pub fn load_cached() -> Self {
+        std::fs::read_to_string(cache_path())
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    /// Save a copy to apply while the main app isn't running.
+    pub fn save_cached(&self) -> Result<(), String> {
+        let path = cache_path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| format!("Failed to serialize policy: {}", e))?;
+        std::fs::write(&path, json).map_err(|e| format!("Failed to write {:?}: {}", path, e))
+    }
+}
+
+/// The app ID given with `--app-id=<id>`, if any.
+pub fn app_id_from_args<'a>(args: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    args.into_iter()
+        .find_map(|arg| arg.strip_prefix("--app-id="))
+        .filter(|app_id| !app_id.is_empty())
+        .map(str::to_string)
+}
+
+/// Where the copy of the policy is kept.
+fn cache_path() -> PathBuf {
+    config_file("picker-policy.json")
+}
+
+/// Where the picker keeps its file `name`, alongside the main app's settings.
+pub fn config_file(name: &str) -> PathBuf {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_else(|| PathBuf::from("/tmp"));
+    config_dir.join("screen-recorder").join(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_for_app() {
+        let policy: PickerPolicy =
+            serde_json::from_str(r#"{"default":"allow","apps":{"org.mozilla.firefox":"deny","com.slack.Slack":"ask"}}"#)
+                .unwrap();
+        assert_eq!(policy.for_app(Some("org.mozilla.firefox")), AppPolicy::Deny);
+        assert_eq!(policy.for_app(Some("com.slack.Slack")), AppPolicy::Ask);
+        assert_eq!(policy.for_app(Some("org.gnome.Epiphany")), AppPolicy::Allow);
+        assert_eq!(policy.for_app(None), AppPolicy::Allow);
+        assert_eq!(PickerPolicy::default().for_app(None), AppPolicy::Ask);
+
+        let args = ["--allow-token", "--app-id=org.mozilla.firefox"];
+        assert_eq!(app_id_from_args(args).as_deref(), Some("org.mozilla.firefox"));
+        assert_eq!(app_id_from_args(["--app-id="]), None);
+    }
+}
@@ -0,0 +1,263 @@
+//! Turning the main app's selection into XDPH's output, after checking it.
+//!
+//! XDPH takes whatever the picker prints, and a selection it can't share
+//! only fails later, with no reason given. So a selection is checked here
+//! first: the source type must be one XDPH knows, a region must have a
+//! size and is clamped to its monitor, and a window must be in the list of
+//! windows XDPH offered for sharing. A selection that fails is refused with
+//! a reason the main app can show.
+
+use crate::ipc_client::Geometry;
+use serde::Deserialize;
+
+/// Window entry from XDPH's window list.
+#[derive(Debug)]
+pub struct WindowEntry {
+    /// XDPH's internal handle ID (lower 32 bits)
+    pub handle_id: u64,
+    /// Window class
+    pub class: String,
+    /// Window title
+    pub title: String,
+    /// Hyprland window address
+    pub window_addr: u64,
+}
+
+/// Parse the XDPH_WINDOW_SHARING_LIST environment variable.
+/// Format: <id>[HC>]<class>[HT>]<title>[HE>]<window_addr>[HA>]...
+pub fn parse_window_list(env_value: &str) -> Vec<WindowEntry> {
+    let mut windows = Vec::new();
+    let mut remaining = env_value;
+
+    while !remaining.is_empty() {
+        // Parse ID
+        let Some(id_end) = remaining.find("[HC>]") else {
+            break;
+        };
+        let id_str = &remaining[..id_end];
+
+        // Parse class
+        remaining = &remaining[id_end + 5..];
+        let Some(class_end) = remaining.find("[HT>]") else {
+            break;
+        };
+        let class = &remaining[..class_end];
+
+        // Parse title
+        remaining = &remaining[class_end + 5..];
+        let Some(title_end) = remaining.find("[HE>]") else {
+            break;
+        };
+        let title = &remaining[..title_end];
+
+        // Parse window address
+        remaining = &remaining[title_end + 5..];
+        let Some(addr_end) = remaining.find("[HA>]") else {
+            break;
+        };
+        let addr_str = &remaining[..addr_end];
+
+        // Move past this entry
+        remaining = &remaining[addr_end + 5..];
+
+        // Parse the values
+        let handle_id = id_str.parse::<u64>().unwrap_or(0);
+        let window_addr = addr_str.parse::<u64>().unwrap_or(0);
+
+        windows.push(WindowEntry {
+            handle_id,
+            class: class.to_string(),
+            title: title.to_string(),
+            window_addr,
+        });
+    }
+
+    windows
+}
+
+/// Monitor entry from `hyprctl -j monitors`.
+#[derive(Debug, Deserialize)]
+pub struct MonitorEntry {
+    pub name: String,
+    /// Mode size in pixels
+    pub width: u32,
+    pub height: u32,
+    pub scale: f64,
+    /// Hyprland's output transform; odd ones are rotated a quarter turn
+    #[serde(default)]
+    pub transform: u32,
+}
+
+impl MonitorEntry {
+    /// Size in logical pixels, the units of region geometry.
+    fn logical_size(&self) -> (i64, i64) {
+        let scale = if self.scale > 0.0 { self.scale } else { 1.0 };
+        let width = (self.width as f64 / scale).round() as i64;
+        let height = (self.height as f64 / scale).round() as i64;
+        if self.transform % 2 == 1 {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+}
+
+/// Parse `hyprctl -j monitors` output.
+pub fn parse_monitor_list(json: &str) -> Option<Vec<MonitorEntry>> {
+    serde_json::from_str(json).ok()
+}
+
+/// `geometry` clamped to the logical bounds of `monitor`, or why it can't be.
+fn clamp_geometry(geometry: &Geometry, monitor: &MonitorEntry) -> Result<Geometry, String> {
+    let (width, height) = monitor.logical_size();
+    let left = (geometry.x as i64).max(0);
+    let top = (geometry.y as i64).max(0);
+    let right = (geometry.x as i64 + geometry.width as i64).min(width);
+    let bottom = (geometry.y as i64 + geometry.height as i64).min(height);
+    if right <= left || bottom <= top {
+        return Err(format!(
+            "Region {}x{} at {},{} is off monitor {} ({}x{})",
+            geometry.width, geometry.height, geometry.x, geometry.y, monitor.name, width, height
+        ));
+    }
+    Ok(Geometry {
+        x: left as i32,
+        y: top as i32,
+        width: (right - left) as u32,
+        height: (bottom - top) as u32,
+    })
+}
+
+/// Find the XDPH handle ID for a given Hyprland window address.
+fn find_window_handle(windows: &[WindowEntry], hyprland_addr: u64) -> Option<u64> {
+    windows
+        .iter()
+        .find(|w| w.window_addr == hyprland_addr)
+        .map(|w| w.handle_id)
+}
+
+/// Parse a Hyprland window address, like "0x55df589f63d0".
+fn parse_window_address(source_id: &str) -> Option<u64> {
+    match source_id.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => source_id.parse().ok(),
+    }
+}
+
+/// The line XDPH expects for a selection, or why it can't be shared.
+///
+/// `windows` is XDPH's window list, or `None` if it didn't provide one, in
+/// which case a window's address is passed through unchecked. Likewise a
+/// region is clamped to its monitor in `monitors`, if they could be listed.
+pub fn format_selection(
+    source_type: &str,
+    source_id: &str,
+    geometry: Option<&Geometry>,
+    flags: &str,
+    windows: Option<&[WindowEntry]>,
+    monitors: Option<&[MonitorEntry]>,
+) -> Result<String, String> {
+    if source_id.is_empty() {
+        return Err(format!("The {} selection doesn't say what to share", source_type));
+    }
+    match source_type {
+        "monitor" => Ok(format!("[SELECTION]{}/screen:{}", flags, source_id)),
+        "window" => {
+            let hyprland_addr = parse_window_address(source_id)
+                .ok_or_else(|| format!("Invalid window address: {}", source_id))?;
+            let handle = match windows {
+                Some(windows) => find_window_handle(windows, hyprland_addr).ok_or_else(|| {
+                    format!(
+                        "Window 0x{:x} isn't one the portal offers for sharing; it may have closed",
+                        hyprland_addr
+                    )
+                })?,
+                None => hyprland_addr,
+            };
+            Ok(format!("[SELECTION]{}/window:{}", flags, handle))
+        }
+        "region" => {
+            let geom = geometry.ok_or("Region selection missing geometry")?;
+            if geom.width == 0 || geom.height == 0 {
+                return Err(format!("Region selection is empty ({}x{})", geom.width, geom.height));
+            }
+            let geom = match monitors {
+                Some(monitors) => {
+                    let monitor = monitors
+                        .iter()
+                        .find(|m| m.name == source_id)
+                        .ok_or_else(|| format!("Monitor {} isn't connected", source_id))?;
+                    clamp_geometry(geom, monitor)?
+                }
+                None => geom.clone(),
+            };
+            Ok(format!(
+                "[SELECTION]{}/region:{}@{},{},{},{}",
+                flags, source_id, geom.x, geom.y, geom.width, geom.height
+            ))
+        }
+        _ => Err(format!("Unknown source type: {}", source_type)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_window_list() {
+        let windows = parse_window_list("42[HC>]firefox[HT>]Mozilla Firefox[HE>]94188632801488[HA>]");
+        assert_eq!(windows.len(), 1);
+        assert_eq!(windows[0].handle_id, 42);
+        assert_eq!(windows[0].class, "firefox");
+        assert_eq!(windows[0].title, "Mozilla Firefox");
+        assert_eq!(windows[0].window_addr, 0x55aa_0000_00d0);
+    }
+
+    #[test]
+    fn test_format_selection() {
+        let windows = parse_window_list("42[HC>]firefox[HT>]Mozilla Firefox[HE>]94188632801488[HA>]");
+        let format = |source_type, source_id, geometry: Option<&Geometry>, windows| {
+            format_selection(source_type, source_id, geometry, "r", windows, None)
+        };
+        assert_eq!(format("monitor", "DP-1", None, None), Ok("[SELECTION]r/screen:DP-1".to_string()));
+        assert_eq!(
+            format("window", "0x55aa000000d0", None, Some(&windows[..])),
+            Ok("[SELECTION]r/window:42".to_string())
+        );
+        // Without XDPH's list, the address is passed through
+        assert_eq!(
+            format("window", "0x10", None, None),
+            Ok("[SELECTION]r/window:16".to_string())
+        );
+        assert!(format("window", "0x10", None, Some(&windows[..])).is_err());
+        let geometry = Geometry { x: 10, y: 20, width: 300, height: 200 };
+        assert_eq!(
+            format("region", "DP-1", Some(&geometry), None),
+            Ok("[SELECTION]r/region:DP-1@10,20,300,200".to_string())
+        );
+        assert!(format("region", "DP-1", None, None).is_err());
+        let empty = Geometry { width: 0, ..geometry };
+        assert!(format("region", "DP-1", Some(&empty), None).is_err());
+        assert!(format("camera", "0", None, None).is_err());
+        assert!(format("monitor", "", None, None).is_err());
+    }
+
+    #[test]
+    fn test_region_clamped_to_monitor() {
+        let monitors = parse_monitor_list(
+            r#"[{"id":0,"name":"DP-1","width":3840,"height":2160,"scale":2.0,"transform":0},
+                {"id":1,"name":"HDMI-A-1","width":1920,"height":1080,"scale":1.0,"transform":1}]"#,
+        )
+        .unwrap();
+        let format = |source_id, geometry: &Geometry| {
+            format_selection("region", source_id, Some(geometry), "r", None, Some(&monitors[..]))
+        };
+        // Hanging off the right edge of a 1920x1080 logical monitor
+        let geometry = Geometry { x: 1800, y: -20, width: 300, height: 200 };
+        assert_eq!(format("DP-1", &geometry), Ok("[SELECTION]r/region:DP-1@1800,0,120,180".to_string()));
+        // The rotated monitor is 1080 wide
+        assert!(format("HDMI-A-1", &geometry).is_err());
+        assert!(format("DP-2", &geometry).is_err());
+    }
+}
@@ -1,5 +1,20 @@
+use std::path::PathBuf;
+
 fn main() {
+    bundle_hls_js();
     // Download FFmpeg binary during build (only in release or when explicitly requested)
     // For development, we'll download on first run
     tauri_build::build()
 }
+
+/// Copy hls.js from the frontend's packages for the HLS player page to
+/// serve, so viewers' browsers don't fetch it from a CDN.
+fn bundle_hls_js() {
+    let source = PathBuf::from("../node_modules/hls.js/dist/hls.min.js");
+    let target = PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("hls.min.js");
+    println!("cargo:rerun-if-changed={}", source.display());
+    if std::fs::copy(&source, &target).is_err() {
+        println!("cargo:warning=hls.js isn't installed (run pnpm install); the HLS player will only work in browsers that play HLS themselves");
+        std::fs::write(&target, "").unwrap();
+    }
+}
@@ -0,0 +1,44 @@
+# Text the backend shows, in German.
+
+## Tray menu
+
+tray-start = Aufnahme starten
+tray-stop = Aufnahme beenden
+tray-profile = Profil
+tray-open-folder = Aufnahmeordner öffnen
+tray-show = Fenster anzeigen
+tray-quit = Beenden
+tray-tooltip = Screen Recorder
+tray-tooltip-paused = Screen Recorder - Pausiert
+tray-tooltip-recording = Screen Recorder - Aufnahme läuft
+tray-tooltip-saving = Screen Recorder - Wird gespeichert
+
+## Screen reader announcements
+
+announce-started = Aufnahme gestartet
+announce-resumed = Aufnahme fortgesetzt
+announce-paused = Aufnahme pausiert
+announce-stopping = Aufnahme beendet, wird gespeichert
+announce-saved = Aufnahme gespeichert
+announce-failed = Aufnahme fehlgeschlagen: { $error }
+announce-cancelled = Aufnahme abgebrochen
+
+## Errors
+
+error-ffmpeg-unavailable = FFmpeg ist nicht verfügbar. Bitte starten Sie die Anwendung neu.
+error-monitor-not-found = Monitor nicht gefunden: { $monitor }
+error-nothing-recorded = Es wurde noch nichts aufgenommen
+
+## Was bei Fehlern zu tun ist
+
+remedy-permission-denied-macos = Erlauben Sie Screen Recorder in den Systemeinstellungen unter Datenschutz & Sicherheit > Bildschirmaufnahme und starten Sie die App neu.
+remedy-permission-denied-windows = Erlauben Sie Apps in den Einstellungen unter Datenschutz und Sicherheit > Bildschirmaufnahme, den Bildschirm aufzunehmen.
+remedy-permission-denied-linux = Erlauben Sie die Bildschirmfreigabe, wenn das Portal fragt, und prüfen Sie, ob der Hyprland-Picker installiert ist.
+remedy-target-not-found = Das Fenster oder der Bildschirm ist nicht mehr da. Aktualisieren Sie die Liste und wählen Sie es erneut aus.
+remedy-invalid-region = Wählen Sie einen Bereich, der auf einem Monitor liegt.
+remedy-not-supported = Das ist auf diesem System nicht verfügbar. Wählen Sie einen anderen Aufnahmemodus.
+remedy-capture-failed-linux = Prüfen Sie, ob xdg-desktop-portal-hyprland und PipeWire laufen, und versuchen Sie es erneut.
+remedy-ffmpeg-unavailable = Starten Sie die Anwendung neu, um FFmpeg erneut herunterzuladen, oder installieren Sie FFmpeg und fügen Sie es zum PATH hinzu.
+remedy-encoder-failed = Schalten Sie die Hardware-Kodierung aus oder wählen Sie in den Encoder-Einstellungen einen anderen Codec.
+remedy-audio-device-unavailable = Prüfen Sie, ob das Audiogerät angeschlossen ist, oder nehmen Sie ohne Ton auf.
+remedy-busy = Beenden Sie zuerst die laufende Aufnahme.
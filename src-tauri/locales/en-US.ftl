@@ -0,0 +1,45 @@
+# Text the backend shows, in US English. Every message is here; other
+# locales fall back to these for any they don't translate.
+
+## Tray menu
+
+tray-start = Start Recording
+tray-stop = Stop Recording
+tray-profile = Profile
+tray-open-folder = Open Recordings Folder
+tray-show = Show Window
+tray-quit = Quit
+tray-tooltip = Screen Recorder
+tray-tooltip-paused = Screen Recorder - Paused
+tray-tooltip-recording = Screen Recorder - Recording
+tray-tooltip-saving = Screen Recorder - Saving
+
+## Screen reader announcements
+
+announce-started = Recording started
+announce-resumed = Recording resumed
+announce-paused = Recording paused
+announce-stopping = Recording stopped, saving
+announce-saved = Recording saved
+announce-failed = Recording failed: { $error }
+announce-cancelled = Recording cancelled
+
+## Errors
+
+error-ffmpeg-unavailable = FFmpeg is not available. Please restart the application.
+error-monitor-not-found = Monitor not found: { $monitor }
+error-nothing-recorded = Nothing has been recorded yet
+
+## What to do about errors
+
+remedy-permission-denied-macos = Allow Screen Recorder under Privacy & Security > Screen Recording in System Settings, then restart it.
+remedy-permission-denied-windows = Allow apps to capture the screen under Privacy & security > Screen capture in Settings.
+remedy-permission-denied-linux = Allow the screen share when the portal asks, and check that the Hyprland picker is installed.
+remedy-target-not-found = The window or display is gone. Refresh the list and choose it again.
+remedy-invalid-region = Select a region that lies on one monitor.
+remedy-not-supported = This isn't available on this system. Choose another capture mode.
+remedy-capture-failed-linux = Check that xdg-desktop-portal-hyprland and PipeWire are running, then try again.
+remedy-ffmpeg-unavailable = Restart the application to download FFmpeg again, or install FFmpeg and add it to your PATH.
+remedy-encoder-failed = Turn off hardware encoding or choose another codec in the encoder settings.
+remedy-audio-device-unavailable = Check that the audio device is connected, or record without audio.
+remedy-busy = Stop the current recording first.
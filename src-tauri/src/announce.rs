@@ -0,0 +1,211 @@
+//! Screen reader announcements of the recording state.
+//!
+//! Recordings are often started, paused and stopped from hotkeys with the
+//! app's window hidden, where the only sign of what happened is the tray
+//! icon. While a screen reader is running, each change of phase and each
+//! second of the countdown is announced through it instead:
+//!
+//! - Windows: a UI Automation notification from the main window
+//! - Linux: spoken through Speech Dispatcher, which Orca uses, while the
+//!   accessibility bus reports a screen reader
+//! - macOS: spoken by VoiceOver, if it allows AppleScript control
+
+use crate::countdown::CountdownEvent;
+use crate::i18n;
+use crate::state::RecordingPhase;
+use crate::AppState;
+use tauri::{AppHandle, Manager};
+
+/// Announce phase changes for the lifetime of the app.
+pub async fn run(app: AppHandle) {
+    use tokio::sync::broadcast::error::RecvError;
+
+    let manager = app.state::<AppState>().recording_manager.clone();
+    let mut phases = manager.lock().await.subscribe_phase_events();
+    let mut previous = manager.lock().await.get_phase();
+    loop {
+        match phases.recv().await {
+            Ok(phase) => {
+                if let Some(text) = announcement(&previous, &phase) {
+                    announce(&app, text).await;
+                }
+                previous = phase;
+            }
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Announce a second of the countdown.
+pub fn countdown(app: &AppHandle, event: CountdownEvent) {
+    let text = match event {
+        CountdownEvent { cancelled: true, .. } => i18n::tr("announce-cancelled"),
+        CountdownEvent { remaining: 0, .. } => return,
+        CountdownEvent { remaining, .. } => remaining.to_string(),
+    };
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move { announce(&app, text).await });
+}
+
+/// Have the screen reader, if one is running, say `text`.
+pub async fn announce(app: &AppHandle, text: String) {
+    if let Err(e) = platform::announce(app, &text).await {
+        tracing::error!(target: "Announce", "Failed to announce \"{}\": {}", text, e);
+    }
+}
+
+/// What to say when the recording goes from `previous` to `phase`.
+fn announcement(previous: &RecordingPhase, phase: &RecordingPhase) -> Option<String> {
+    let text = match (previous, phase) {
+        (RecordingPhase::Paused, RecordingPhase::Recording) => i18n::tr("announce-resumed"),
+        (_, RecordingPhase::Recording) => i18n::tr("announce-started"),
+        (_, RecordingPhase::Paused) => i18n::tr("announce-paused"),
+        (_, RecordingPhase::Finalizing) => i18n::tr("announce-stopping"),
+        (_, RecordingPhase::Done { .. }) => i18n::tr("announce-saved"),
+        (_, RecordingPhase::Failed { error }) => i18n::tr_args("announce-failed", &[("error", error)]),
+        (_, RecordingPhase::Idle | RecordingPhase::Preparing) => return None,
+    };
+    Some(text)
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use tauri::{AppHandle, Manager};
+    use windows::core::BSTR;
+    use windows::Win32::Foundation::{BOOL, HWND};
+    use windows::Win32::System::Com::{CoInitializeEx, COINIT_MULTITHREADED};
+    use windows::Win32::UI::Accessibility::{
+        NotificationKind_Other, NotificationProcessing_ImportantMostRecent, UiaHostProviderFromHwnd,
+        UiaRaiseNotificationEvent,
+    };
+    use windows::Win32::UI::WindowsAndMessaging::{
+        SystemParametersInfoW, SPI_GETSCREENREADER, SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS,
+    };
+
+    /// Lets screen readers tell our announcements apart
+    const ACTIVITY_ID: &str = "ScreenRecorder.RecordingState";
+
+    pub async fn announce(app: &AppHandle, text: &str) -> Result<(), String> {
+        let Some(window) = app.get_webview_window("main") else {
+            return Ok(());
+        };
+        let hwnd = window.hwnd().map_err(|e| e.to_string())?.0 as isize;
+        let text = text.to_string();
+        tokio::task::spawn_blocking(move || raise(hwnd, &text))
+            .await
+            .map_err(|e| format!("Task error: {}", e))?
+    }
+
+    fn screen_reader_running() -> bool {
+        let mut running = BOOL(0);
+        let read = unsafe {
+            SystemParametersInfoW(
+                SPI_GETSCREENREADER,
+                0,
+                Some(&mut running as *mut BOOL as *mut _),
+                SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+            )
+        };
+        read.is_ok() && running.as_bool()
+    }
+
+    fn raise(hwnd: isize, text: &str) -> Result<(), String> {
+        if !screen_reader_running() {
+            return Ok(());
+        }
+        unsafe {
+            // Already initialized on reused threads, which is fine
+            let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+            let provider = UiaHostProviderFromHwnd(HWND(hwnd as *mut std::ffi::c_void))
+                .map_err(|e| format!("No automation provider for the window: {}", e))?;
+            UiaRaiseNotificationEvent(
+                &provider,
+                NotificationKind_Other,
+                NotificationProcessing_ImportantMostRecent,
+                &BSTR::from(text),
+                &BSTR::from(ACTIVITY_ID),
+            )
+            .map_err(|e| e.to_string())
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use tauri::AppHandle;
+
+    /// Whether the accessibility bus says a screen reader is running.
+    async fn screen_reader_running() -> zbus::Result<bool> {
+        let connection = zbus::Connection::session().await?;
+        let status = zbus::Proxy::new(&connection, "org.a11y.Bus", "/org/a11y/bus", "org.a11y.Status").await?;
+        status.get_property("ScreenReaderEnabled").await
+    }
+
+    pub async fn announce(_app: &AppHandle, text: &str) -> Result<(), String> {
+        if !screen_reader_running().await.unwrap_or(false) {
+            return Ok(());
+        }
+        let status = tokio::process::Command::new("spd-say")
+            .args(["--application-name", "Screen Recorder", "--priority", "important", text])
+            .status()
+            .await
+            .map_err(|e| format!("Failed to execute spd-say: {}", e))?;
+        if !status.success() {
+            return Err(format!("spd-say exited with {}", status));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use tauri::AppHandle;
+    use tokio::process::Command;
+
+    pub async fn announce(_app: &AppHandle, text: &str) -> Result<(), String> {
+        let running = Command::new("pgrep")
+            .args(["-x", "VoiceOver"])
+            .output()
+            .await
+            .is_ok_and(|output| output.status.success());
+        if !running {
+            return Ok(());
+        }
+        let quoted = text.replace('\\', "\\\\").replace('"', "\\\"");
+        let script = format!("tell application \"VoiceOver\" to output \"{}\"", quoted);
+        let output = Command::new("osascript")
+            .args(["-e", &script])
+            .output()
+            .await
+            .map_err(|e| format!("Failed to execute osascript: {}", e))?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+mod platform {
+    use tauri::AppHandle;
+
+    pub async fn announce(_app: &AppHandle, _text: &str) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_announcement() {
+        use RecordingPhase::*;
+        assert_eq!(announcement(&Preparing, &Recording).as_deref(), Some("Recording started"));
+        assert_eq!(announcement(&Paused, &Recording).as_deref(), Some("Recording resumed"));
+        let failed = Failed { error: "Disk full".to_string() };
+        assert_eq!(announcement(&Finalizing, &failed).as_deref(), Some("Recording failed: Disk full"));
+        assert_eq!(announcement(&Idle, &Preparing), None);
+    }
+}
@@ -0,0 +1,250 @@
+//! Keeping captured audio in step with the video.
+//!
+//! Audio and video are captured on separate threads and joined once the
+//! recording ends, so both are timed against one [`SyncClock`], started
+//! with the recording and stopped while it's paused. The audio file starts
+//! at the clock's zero, padded with silence until the first samples arrive;
+//! the video starts when the encoder writes its first frame, which the
+//! encoder notes on the clock. When muxing, the audio is trimmed by that
+//! difference, shifted by the configured audio offset, and resampled to
+//! the video's length to correct the drift between the audio device's
+//! sample clock and the system clock over long recordings.
+//!
+//! Sources rarely have the same latency (a USB microphone lags behind
+//! application audio, for one), so each [`AudioSource`] can be shifted
+//! further by an offset of its own, added to the overall one. With more
+//! than one source, the sources are shifted against each other when they're
+//! mixed (see `postprocess::mix`), and the mix by the overall offset.
+
+use crate::capture::audio::AudioSource;
+use crate::capture::pause::PauseState;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Largest audio offset that can be set, in milliseconds either way.
+const MAX_AUDIO_OFFSET_MS: i32 = 1000;
+
+/// Audio and video lengths closer than this aren't corrected, in seconds.
+const MIN_DRIFT_SECS: f64 = 0.02;
+
+/// Audio more than this fraction longer or shorter than the video has
+/// stretches missing or extra rather than a drifting clock, and isn't
+/// resampled.
+const MAX_DRIFT: f64 = 0.02;
+
+static AUDIO_OFFSET_MS: AtomicI32 = AtomicI32::new(0);
+
+/// Set how far to delay the audio against the video, in milliseconds;
+/// negative moves it earlier, compensating for latency in the audio path.
+pub fn set_audio_offset_ms(offset: i32) -> Result<(), String> {
+    if offset.abs() > MAX_AUDIO_OFFSET_MS {
+        return Err(format!("The audio offset can be at most {} ms", MAX_AUDIO_OFFSET_MS));
+    }
+    AUDIO_OFFSET_MS.store(offset, Ordering::Relaxed);
+    Ok(())
+}
+
+/// The audio offset, in milliseconds.
+pub fn audio_offset_ms() -> i32 {
+    AUDIO_OFFSET_MS.load(Ordering::Relaxed)
+}
+
+/// Offsets of individual sources by name, on top of the audio offset.
+static SOURCE_OFFSETS_MS: Mutex<BTreeMap<String, i32>> = Mutex::new(BTreeMap::new());
+
+/// Set how far to delay the audio of `source`, such as "microphone",
+/// beyond the audio offset, in milliseconds; negative moves it earlier.
+pub fn set_source_offset_ms(source: &str, offset: i32) -> Result<(), String> {
+    if AudioSource::from_name(source).is_none() {
+        let names: Vec<&str> = AudioSource::ALL.iter().map(AudioSource::name).collect();
+        return Err(format!("Unknown audio source {:?}; the sources are {}", source, names.join(", ")));
+    }
+    if offset.abs() > MAX_AUDIO_OFFSET_MS {
+        return Err(format!("The audio offset can be at most {} ms", MAX_AUDIO_OFFSET_MS));
+    }
+    let mut offsets = SOURCE_OFFSETS_MS.lock().unwrap();
+    if offset == 0 {
+        offsets.remove(source);
+    } else {
+        offsets.insert(source.to_string(), offset);
+    }
+    Ok(())
+}
+
+/// The sources with an offset of their own, in milliseconds.
+pub fn source_offsets_ms() -> BTreeMap<String, i32> {
+    SOURCE_OFFSETS_MS.lock().unwrap().clone()
+}
+
+/// How far to delay the audio of `source` in all, in milliseconds, bounded
+/// like the offsets themselves.
+pub fn offset_for_source_ms(source: AudioSource) -> i32 {
+    let offset = audio_offset_ms() + SOURCE_OFFSETS_MS.lock().unwrap().get(source.name()).copied().unwrap_or(0);
+    offset.clamp(-MAX_AUDIO_OFFSET_MS, MAX_AUDIO_OFFSET_MS)
+}
+
+/// The clock a recording's streams are timed against.
+#[derive(Debug)]
+pub struct SyncClock {
+    start: Instant,
+    pause: Arc<PauseState>,
+    /// Clock time of the video's first frame
+    video_start: Mutex<Option<Duration>>,
+}
+
+impl SyncClock {
+    /// Start a clock at zero.
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            pause: Arc::new(PauseState::default()),
+            video_start: Mutex::new(None),
+        }
+    }
+
+    /// Whether the recording is paused; the clock stops while it is.
+    pub fn pause(&self) -> &Arc<PauseState> {
+        &self.pause
+    }
+
+    /// Time recorded since the clock started, leaving out pauses.
+    pub fn now(&self) -> Duration {
+        self.pause.active_time(self.start)
+    }
+
+    /// Note that the video's first frame is being written now.
+    pub fn mark_video_start(&self) {
+        self.video_start.lock().unwrap().get_or_insert(self.now());
+    }
+
+    /// Clock time of the video's first frame, or zero if none was written.
+    pub fn video_start(&self) -> Duration {
+        self.video_start.lock().unwrap().unwrap_or_default()
+    }
+
+    /// The current position in the video.
+    pub fn video_time(&self) -> Duration {
+        self.now().saturating_sub(self.video_start())
+    }
+}
+
+impl Default for SyncClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How captured audio is lined up with the video when muxing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioSync {
+    /// Seconds cut from the start of the audio; negative delays it instead
+    pub trim_secs: f64,
+    /// Speed the trimmed audio is played at so it ends with the video
+    pub rate: f64,
+    /// Length the audio is cut or padded to, when the video was stopped on
+    /// an exact frame
+    pub length_secs: Option<f64>,
+}
+
+impl AudioSync {
+    /// Line up `audio_secs` of audio, started at the clock's zero, with
+    /// `video_secs` of video started at `video_start`.
+    pub fn plan(video_start: Duration, offset_ms: i32, audio_secs: f64, video_secs: f64) -> Self {
+        let trim_secs = video_start.as_secs_f64() - offset_ms as f64 / 1000.0;
+        let aligned = audio_secs - trim_secs;
+        let drift = aligned - video_secs;
+        let rate = if video_secs <= 0.0 || drift.abs() < MIN_DRIFT_SECS {
+            1.0
+        } else if (drift / video_secs).abs() > MAX_DRIFT {
+            tracing::info!(
+                target: "Sync",
+                "Audio is {:.2}s {} than the video, too much to be drift; not resampling",
+                drift.abs(),
+                if drift > 0.0 { "longer" } else { "shorter" }
+            );
+            1.0
+        } else {
+            aligned / video_secs
+        };
+        Self { trim_secs, rate, length_secs: None }
+    }
+
+    /// Like [`plan`](Self::plan), for video stopped on an exact frame. The
+    /// audio captured while the recording was being stopped, after the last
+    /// frame, is dropped instead of being taken for drift, and the audio is
+    /// made exactly as long as the video.
+    pub fn plan_exact(video_start: Duration, offset_ms: i32, audio_secs: f64, video_secs: f64) -> Self {
+        let trim_secs = video_start.as_secs_f64() - offset_ms as f64 / 1000.0;
+        Self {
+            length_secs: Some(video_secs),
+            ..Self::plan(video_start, offset_ms, audio_secs.min(trim_secs + video_secs), video_secs)
+        }
+    }
+
+    /// FFmpeg audio filters applying this to audio sampled at `sample_rate`,
+    /// or `None` if it's already in step.
+    pub fn filter(&self, sample_rate: u32) -> Option<String> {
+        let mut filters = Vec::new();
+        if self.trim_secs > 0.0005 {
+            filters.push(format!("atrim=start={:.3},asetpts=PTS-STARTPTS", self.trim_secs));
+        } else if self.trim_secs < -0.0005 {
+            filters.push(format!("adelay={:.0}:all=1", -self.trim_secs * 1000.0));
+        }
+        if self.rate != 1.0 {
+            // Playing the samples at a slightly different rate, then
+            // resampling back, stretches the audio without dropping any. The
+            // rate is whole samples per second, which is within 40ms an hour.
+            filters.push(format!(
+                "asetrate={:.0},aresample={}",
+                sample_rate as f64 * self.rate,
+                sample_rate
+            ));
+        }
+        if let Some(length_secs) = self.length_secs {
+            let samples = (length_secs * sample_rate as f64).round() as u64;
+            filters.push(format!("apad=whole_len={0},atrim=end_sample={0}", samples));
+        }
+        (!filters.is_empty()).then(|| filters.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_audio_sync() {
+        // The encoder took 300ms to start; the audio has drifted 0.5s long over an hour
+        let sync = AudioSync::plan(Duration::from_millis(300), 0, 3600.8, 3600.0);
+        assert!((sync.trim_secs - 0.3).abs() < 1e-9);
+        assert!((sync.rate - 3600.5 / 3600.0).abs() < 1e-9);
+        assert_eq!(
+            sync.filter(48_000).as_deref(),
+            Some("atrim=start=0.300,asetpts=PTS-STARTPTS,asetrate=48007,aresample=48000")
+        );
+
+        // A 100ms offset delays the audio instead
+        let sync = AudioSync::plan(Duration::ZERO, 100, 10.0, 10.1);
+        assert_eq!(sync.filter(48_000).as_deref(), Some("adelay=100:all=1"));
+        // Minutes of missing audio aren't drift
+        assert_eq!(AudioSync::plan(Duration::ZERO, 0, 600.0, 900.0).rate, 1.0);
+
+        // Audio recorded while a recording stopped on an exact frame is cut
+        let sync = AudioSync::plan_exact(Duration::from_millis(200), 0, 30.45, 30.0);
+        assert_eq!(sync.rate, 1.0);
+        assert_eq!(
+            sync.filter(48_000).as_deref(),
+            Some("atrim=start=0.200,asetpts=PTS-STARTPTS,apad=whole_len=1440000,atrim=end_sample=1440000")
+        );
+
+        // A source's offset adds to the overall one, and clearing it removes it
+        set_source_offset_ms("microphone", 120).unwrap();
+        assert_eq!(offset_for_source_ms(AudioSource::Microphone), audio_offset_ms() + 120);
+        assert!(set_source_offset_ms("microphone", 1500).is_err());
+        assert!(set_source_offset_ms("usb-mic", 120).is_err());
+        set_source_offset_ms("microphone", 0).unwrap();
+        assert!(!source_offsets_ms().contains_key("microphone"));
+    }
+}
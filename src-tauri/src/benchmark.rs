@@ -0,0 +1,273 @@
+//! Benchmark recommending recording settings for this machine.
+//!
+//! Each working encoder (hardware ones first, then software) encodes a
+//! moving test pattern at increasing resolutions as fast as it can, and the
+//! frame rates it reaches are compared against what recordings need. The
+//! recommendation is the highest resolution and frame rate that leaves
+//! headroom for capture and everything else running, preferring hardware
+//! encoders and H.264 for compatibility. A display can also be captured for a
+//! few seconds, which caps the resolution at its size.
+
+use crate::capture::hdr::HdrMode;
+use crate::capture::{start_capture, CaptureTarget, MonitorInfo};
+use crate::encoder::codec::VideoCodec;
+use crate::encoder::hardware::{encoder_chain, EncoderBackend};
+use crate::encoder::rate::RateControl;
+use crate::profiles::RecordingProfile;
+use ffmpeg_sidecar::command::FfmpegCommand;
+use serde::Serialize;
+use std::process::Stdio;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+/// Event emitted with [`BenchmarkProgress`] as each test finishes.
+pub const PROGRESS_EVENT: &str = "benchmark-progress";
+
+/// Codecs tested, in order of preference.
+const CODECS: [VideoCodec; 2] = [VideoCodec::H264, VideoCodec::H265];
+
+/// Resolutions tested, as (width, height), smallest first.
+const RESOLUTIONS: [(u32, u32); 4] = [(1280, 720), (1920, 1080), (2560, 1440), (3840, 2160)];
+
+/// Frame rates recommended, highest first.
+const TARGET_FPS: [u32; 2] = [60, 30];
+
+/// How much faster than real time an encoder has to be for a frame rate to be
+/// recommended, leaving room for capture and other apps.
+const HEADROOM: f64 = 1.5;
+
+/// Frames encoded per test.
+const TEST_FRAMES: u32 = 120;
+
+/// How long a display is captured for.
+const CAPTURE_DURATION: Duration = Duration::from_secs(3);
+
+/// How one encoder did at one resolution.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EncodeTrial {
+    pub backend: EncoderBackend,
+    pub codec: VideoCodec,
+    pub width: u32,
+    pub height: u32,
+    /// Frames encoded per second
+    pub fps: f64,
+}
+
+/// How fast a display was captured.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CaptureTrial {
+    pub monitor_id: String,
+    pub width: u32,
+    pub height: u32,
+    /// Frames delivered per second. Displays that don't change deliver fewer.
+    pub fps: f64,
+}
+
+/// The settings the benchmark recommends.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Recommendation {
+    /// Resolution cap, frame rate and codec, ready to save as a profile
+    pub profile: RecordingProfile,
+    /// Whether to turn hardware encoding on
+    pub hardware_encoding: bool,
+    /// FFmpeg encoder the recording will use, e.g. `h264_nvenc`
+    pub encoder: String,
+    /// The encoder preset recordings use with it, if it has presets
+    pub preset: Option<String>,
+    /// Measured frame rate divided by the recommended one
+    pub headroom: f64,
+}
+
+/// Results of a benchmark.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BenchmarkReport {
+    pub capture: Option<CaptureTrial>,
+    pub trials: Vec<EncodeTrial>,
+    /// `None` if no encoder kept up with 30 fps at 720p
+    pub recommendation: Option<Recommendation>,
+}
+
+/// How far the benchmark has got.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct BenchmarkProgress {
+    pub completed: usize,
+    /// Tests that may run; fewer do when slow encoders skip larger sizes
+    pub total: usize,
+}
+
+/// Capture `monitor` for a few seconds, counting the frames delivered.
+pub async fn capture_trial(monitor: &MonitorInfo) -> Result<CaptureTrial, String> {
+    let target = CaptureTarget::Display {
+        monitor_id: monitor.id.clone(),
+        width: monitor.width,
+        height: monitor.height,
+    };
+    let (mut frames, stop) = start_capture(target).map_err(|e| e.to_string())?;
+
+    // Time from the first frame, so setting the capture up doesn't count
+    let first = tokio::time::timeout(Duration::from_secs(15), frames.recv()).await;
+    if !matches!(first, Ok(Some(_))) {
+        stop.store(true, Ordering::Relaxed);
+        return Err("No frames were captured".to_string());
+    }
+    let started = Instant::now();
+    let mut count = 0u32;
+    while let Ok(Some(_)) = tokio::time::timeout_at((started + CAPTURE_DURATION).into(), frames.recv()).await {
+        count += 1;
+    }
+    stop.store(true, Ordering::Relaxed);
+
+    Ok(CaptureTrial {
+        monitor_id: monitor.id.clone(),
+        width: monitor.width,
+        height: monitor.height,
+        fps: count as f64 / started.elapsed().as_secs_f64(),
+    })
+}
+
+/// Encode a test pattern with each working encoder at each resolution up to
+/// `max_height`, moving on to the next encoder once one can't keep up with
+/// the lowest frame rate. Calls `progress` after each test.
+///
+/// Blocks while FFmpeg runs; call it from a blocking task.
+pub fn encode_trials(max_height: Option<u32>, mut progress: impl FnMut(BenchmarkProgress)) -> Vec<EncodeTrial> {
+    let resolutions: Vec<(u32, u32)> = RESOLUTIONS
+        .into_iter()
+        .filter(|&(_, height)| max_height.is_none_or(|max| height <= max))
+        .collect();
+    let encoders: Vec<(EncoderBackend, VideoCodec)> = CODECS
+        .into_iter()
+        .flat_map(|codec| {
+            encoder_chain(codec, RateControl::default(), true)
+                .into_iter()
+                .map(move |backend| (backend, codec))
+        })
+        .collect();
+
+    let total = encoders.len() * resolutions.len();
+    let mut completed = 0;
+    let mut trials = Vec::new();
+    for (backend, codec) in encoders {
+        for (index, &(width, height)) in resolutions.iter().enumerate() {
+            let fps = encode_fps(backend, codec, width, height);
+            completed += 1;
+            progress(BenchmarkProgress { completed, total });
+            let Some(fps) = fps else {
+                completed += resolutions.len() - index - 1;
+                break;
+            };
+            tracing::info!(target: "Benchmark", "{:?} {:?} {}x{}: {:.0} fps", backend, codec, width, height, fps);
+            trials.push(EncodeTrial { backend, codec, width, height, fps });
+            if fps < TARGET_FPS[TARGET_FPS.len() - 1] as f64 * HEADROOM {
+                completed += resolutions.len() - index - 1;
+                break;
+            }
+        }
+    }
+    progress(BenchmarkProgress { completed: total, total });
+    trials
+}
+
+/// How many frames per second `backend` encodes a `width`x`height` test
+/// pattern with `codec`, or `None` if it fails.
+fn encode_fps(backend: EncoderBackend, codec: VideoCodec, width: u32, height: u32) -> Option<f64> {
+    let pixel_format = backend.input_format(false).unwrap_or("yuv420p");
+    let source = format!("testsrc2=size={}x{}:rate=60,format={}", width, height, pixel_format);
+    let mut command = FfmpegCommand::new();
+    command
+        .args(["-hide_banner", "-nostdin", "-loglevel", "error"])
+        .args(["-f", "lavfi", "-i", &source])
+        .args(["-frames:v", &TEST_FRAMES.to_string()])
+        .args(backend.args(codec, RateControl::default()))
+        .args(["-f", "null", "-"]);
+    let inner_command = command.as_inner_mut();
+    inner_command.stdin(Stdio::null());
+    inner_command.stdout(Stdio::null());
+    inner_command.stderr(Stdio::null());
+
+    let started = Instant::now();
+    match inner_command.status() {
+        Ok(status) if status.success() => Some(TEST_FRAMES as f64 / started.elapsed().as_secs_f64()),
+        Ok(status) => {
+            tracing::warn!(target: "Benchmark", "{:?} {:?} {}x{} failed: {}", backend, codec, width, height, status);
+            None
+        }
+        Err(e) => {
+            tracing::error!(target: "Benchmark", "Failed to run FFmpeg: {}", e);
+            None
+        }
+    }
+}
+
+/// The best settings `trials` leave headroom for: the highest resolution,
+/// then frame rate, then hardware over software and H.264 over H.265.
+pub fn recommend(trials: &[EncodeTrial]) -> Option<Recommendation> {
+    let (trial, fps) = trials
+        .iter()
+        .filter_map(|trial| {
+            let fps = TARGET_FPS.into_iter().find(|&fps| trial.fps >= fps as f64 * HEADROOM)?;
+            Some((trial, fps))
+        })
+        .max_by_key(|(trial, fps)| {
+            let codec_rank = CODECS.len() - CODECS.iter().position(|&c| c == trial.codec).unwrap_or(CODECS.len());
+            (trial.height, *fps, trial.backend != EncoderBackend::Software, codec_rank)
+        })?;
+
+    let args = trial.backend.args(trial.codec, RateControl::default());
+    let preset = args
+        .iter()
+        .position(|arg| arg == "-preset")
+        .and_then(|index| args.get(index + 1))
+        .cloned();
+    Some(Recommendation {
+        profile: RecordingProfile {
+            name: "Recommended".to_string(),
+            max_width: Some(trial.width),
+            max_height: Some(trial.height),
+            fps,
+            codec: trial.codec,
+            rate_control: RateControl::default(),
+            hdr: HdrMode::ToneMap,
+            ten_bit: false,
+            audio_sources: None,
+        },
+        hardware_encoding: trial.backend != EncoderBackend::Software,
+        encoder: trial.backend.encoder_name(trial.codec).unwrap_or_default().to_string(),
+        preset,
+        headroom: trial.fps / fps as f64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trial(backend: EncoderBackend, codec: VideoCodec, height: u32, fps: f64) -> EncodeTrial {
+        EncodeTrial { backend, codec, width: height * 16 / 9, height, fps }
+    }
+
+    #[test]
+    fn test_recommend() {
+        use EncoderBackend::*;
+        let trials = [
+            trial(Software, VideoCodec::H264, 720, 200.0),
+            trial(Software, VideoCodec::H264, 1080, 80.0),
+            trial(Nvenc, VideoCodec::H265, 1080, 300.0),
+            trial(Nvenc, VideoCodec::H264, 1080, 300.0),
+            trial(Nvenc, VideoCodec::H264, 2160, 60.0),
+        ];
+        let recommendation = recommend(&trials).unwrap();
+        assert_eq!(recommendation.profile.max_height, Some(2160));
+        assert_eq!(recommendation.profile.fps, 30);
+        assert_eq!(recommendation.encoder, "h264_nvenc");
+        assert_eq!(recommendation.preset.as_deref(), Some("p1"));
+        assert!(recommendation.hardware_encoding);
+
+        // H.264 over H.265 when both keep up
+        let recommendation = recommend(&trials[..4]).unwrap();
+        assert_eq!((recommendation.profile.fps, recommendation.profile.codec), (60, VideoCodec::H264));
+        assert_eq!(recommendation.encoder, "h264_nvenc");
+
+        assert_eq!(recommend(&[trial(Software, VideoCodec::H264, 720, 20.0)]), None);
+    }
+}
@@ -0,0 +1,438 @@
+//! Live captions transcribed from the microphone while recording.
+//!
+//! With live captions on, the default microphone is transcribed as the
+//! recording runs, by the same local whisper.cpp filter the subtitle job
+//! uses (see [`crate::postprocess::subtitles`]) fed a few seconds at a
+//! time. The latest caption is burned into the frames by FFmpeg's
+//! `drawtext` filter, which rereads a small text file every frame, so it
+//! appears a few seconds after the words are spoken. Every caption is also
+//! kept with its timing, saved as `NAME.srt` next to the recording and,
+//! optionally, added to it as a subtitle track.
+//!
+//! Times come from the transcriber and leave out pauses; speech while the
+//! recording is paused isn't captioned.
+
+use crate::avsync::SyncClock;
+use crate::capture::pause::Pause;
+use crate::encryption;
+use crate::postprocess::subtitles;
+use crate::state::RecordingPhase;
+use crate::voice;
+use crate::AppState;
+use ffmpeg_sidecar::command::FfmpegCommand;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+
+/// Seconds of speech transcribed at a time. Shorter shows captions sooner
+/// but transcribes them less accurately.
+const QUEUE_SECS: u32 = 3;
+
+/// How long a caption stays up after its speech ends, if nothing follows.
+const HOLD: Duration = Duration::from_secs(4);
+
+/// Longest caption line, in characters, and how many lines are shown.
+const LINE_CHARS: usize = 42;
+const MAX_LINES: usize = 2;
+
+/// How often the transcript is checked for new captions.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Settings for live captions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LiveCaptionSettings {
+    pub enabled: bool,
+    /// Spoken language as an ISO 639-1 code such as "en"; detected if unset
+    pub language: Option<String>,
+    /// whisper.cpp model file; defaults to [`subtitles::DEFAULT_MODEL`]
+    pub model_path: Option<String>,
+    /// Draw the captions into the video
+    pub burn_in: bool,
+    /// Add the captions to the recording as a subtitle track
+    pub embed: bool,
+}
+
+impl Default for LiveCaptionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            language: None,
+            model_path: None,
+            burn_in: true,
+            embed: true,
+        }
+    }
+}
+
+impl LiveCaptionSettings {
+    /// Check that the settings are usable.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.enabled {
+            return Ok(());
+        }
+        subtitles::language_option(self.language.as_deref())?;
+        subtitles::model_path(self.model_path.as_deref())?;
+        Ok(())
+    }
+}
+
+/// A caption and when it's spoken in the video, in seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+/// One transcribed segment as the whisper filter writes it in JSON, with
+/// times in milliseconds since transcription started.
+#[derive(Debug, Deserialize)]
+struct Segment {
+    start: i64,
+    end: i64,
+    text: String,
+}
+
+/// File the burned-in caption is read from.
+fn caption_file() -> PathBuf {
+    std::env::temp_dir().join("screen-recorder-caption.txt")
+}
+
+/// File the transcriber writes segments to.
+fn transcript_file() -> PathBuf {
+    std::env::temp_dir().join("screen-recorder-transcript.json")
+}
+
+/// Replace the burned-in caption. Written to the side and renamed, so the
+/// encoder never reads half of it.
+fn show_caption(text: &str) {
+    let path = caption_file();
+    let staged = path.with_extension("txt.tmp");
+    let written = std::fs::write(&staged, text).and_then(|()| std::fs::rename(&staged, &path));
+    if let Err(e) = written {
+        tracing::error!(target: "Captions", "Failed to update the caption: {}", e);
+    }
+}
+
+/// The `drawtext` filter burning the live caption into `height`-pixel
+/// frames, clearing any caption left from before.
+pub fn drawtext_filter(height: u32) -> String {
+    show_caption("");
+    let size = (height / 20).max(12);
+    format!(
+        "drawtext=textfile={}:reload=1:expansion=none:{}:fontsize={}:fontcolor=white:\
+         box=1:boxcolor=black@0.6:boxborderw={}:line_spacing={}:x=(w-text_w)/2:y=h-text_h-h/12",
+        subtitles::filter_escape(&caption_file()),
+        font_option(),
+        size,
+        size / 3,
+        size / 4
+    )
+}
+
+/// The `drawtext` option picking the font.
+fn font_option() -> String {
+    #[cfg(target_os = "windows")]
+    return format!("fontfile={}", subtitles::filter_escape(Path::new("C:/Windows/Fonts/segoeui.ttf")));
+    #[cfg(target_os = "macos")]
+    return format!("fontfile={}", subtitles::filter_escape(Path::new("/System/Library/Fonts/Helvetica.ttc")));
+    // Looked up through fontconfig
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    return "font=Sans".to_string();
+}
+
+/// The end of `text` wrapped into lines of at most [`LINE_CHARS`], keeping
+/// the last [`MAX_LINES`] so the newest words show.
+fn wrap_caption(text: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if line.chars().count() + 1 + word.chars().count() <= LINE_CHARS => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_string()),
+        }
+    }
+    let skip = lines.len().saturating_sub(MAX_LINES);
+    lines[skip..].join("\n")
+}
+
+/// `cues` as an SRT file.
+fn srt(cues: &[Cue]) -> String {
+    let timestamp = |secs: f64| {
+        let ms = (secs.max(0.0) * 1000.0).round() as u64;
+        format!("{:02}:{:02}:{:02},{:03}", ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60, ms % 1000)
+    };
+    cues.iter()
+        .enumerate()
+        .map(|(i, cue)| format!("{}\n{} --> {}\n{}\n\n", i + 1, timestamp(cue.start), timestamp(cue.end), cue.text))
+        .collect()
+}
+
+/// Turns transcribed segments into cues in video time.
+struct CueTimer {
+    /// When transcription started
+    started: Instant,
+    /// Video time when transcription started
+    video_start: f64,
+}
+
+impl CueTimer {
+    /// Video time at `at`, leaving out `pauses`. Times within a pause are
+    /// where it began.
+    fn video_time(&self, at: Instant, pauses: &[Pause]) -> f64 {
+        let paused: Duration = pauses
+            .iter()
+            .map(|pause| {
+                let end = pause.end.map_or(at, |end| end.min(at));
+                end.saturating_duration_since(pause.start.max(self.started))
+            })
+            .sum();
+        let recorded = at.saturating_duration_since(self.started).saturating_sub(paused);
+        self.video_start + recorded.as_secs_f64()
+    }
+
+    /// The cue for `segment`, with the recording's `pauses` so far, or
+    /// `None` if there's nothing to show. The transcript runs seconds
+    /// behind, so each segment is timed by when it was spoken, and dropped
+    /// if it was mostly spoken while paused.
+    fn cue(&self, segment: &Segment, pauses: &[Pause]) -> Option<Cue> {
+        let text = segment.text.trim();
+        if text.is_empty() || text.starts_with('[') {
+            // Nothing said, or a note like "[BLANK_AUDIO]"
+            return None;
+        }
+        let spoken = |ms: i64| self.started + Duration::from_millis(ms.max(0) as u64);
+        let (spoken_start, spoken_end) = (spoken(segment.start), spoken(segment.end.max(segment.start)));
+        let start = self.video_time(spoken_start, pauses);
+        let end = self.video_time(spoken_end, pauses);
+        if (end - start) * 2.0 < (spoken_end - spoken_start).as_secs_f64() {
+            return None;
+        }
+        Some(Cue { start, end, text: text.to_string() })
+    }
+}
+
+/// Transcribes the microphone for the current recording.
+struct Transcriber {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<Vec<Cue>>,
+}
+
+impl Transcriber {
+    fn start(settings: &LiveCaptionSettings, clock: Arc<SyncClock>) -> Result<Self, String> {
+        let language = subtitles::language_option(settings.language.as_deref())?;
+        let model = subtitles::model_path(settings.model_path.as_deref())?;
+        let transcript = transcript_file();
+        let _ = std::fs::remove_file(&transcript);
+        std::fs::write(&transcript, "").map_err(|e| format!("Failed to create the transcript: {}", e))?;
+
+        let mut command = FfmpegCommand::new();
+        command
+            .args(["-hide_banner", "-nostdin"])
+            .args(voice::microphone_input()?)
+            .args(["-vn", "-af"])
+            .arg(subtitles::whisper_filter(&model, &language, QUEUE_SECS, &transcript, "json"))
+            .args(["-f", "null", "-"]);
+        let inner_command = command.as_inner_mut();
+        inner_command.stdin(Stdio::null());
+        inner_command.stdout(Stdio::null());
+        inner_command.stderr(Stdio::null());
+        let mut child = inner_command
+            .spawn()
+            .map_err(|e| format!("Failed to start FFmpeg: {}", e))?;
+
+        let timer = CueTimer {
+            started: Instant::now(),
+            video_start: clock.video_time().as_secs_f64(),
+        };
+        let burn_in = settings.burn_in;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let thread = std::thread::spawn(move || {
+            let mut cues = Vec::new();
+            let mut reader = match std::fs::File::open(&transcript) {
+                Ok(file) => BufReader::new(file),
+                Err(e) => {
+                    tracing::error!(target: "Captions", "Failed to read the transcript: {}", e);
+                    let _ = child.kill();
+                    return cues;
+                }
+            };
+            let mut shown_until: Option<Instant> = None;
+            let mut line = String::new();
+            while !stopped.load(Ordering::Relaxed) {
+                if let Ok(Some(status)) = child.try_wait() {
+                    tracing::warn!(target: "Captions", "Transcription stopped ({}); check the model and microphone", status);
+                    break;
+                }
+                // Whole lines only; a partial one is read again once it's done
+                let position = reader.stream_position().unwrap_or(0);
+                line.clear();
+                match reader.read_line(&mut line) {
+                    Ok(_) if line.ends_with('\n') => {
+                        let segment = serde_json::from_str::<Segment>(&line).ok();
+                        let cue = segment.and_then(|segment| timer.cue(&segment, &clock.pause().pauses()));
+                        if let Some(cue) = cue {
+                            if burn_in {
+                                show_caption(&wrap_caption(&cue.text));
+                                shown_until = Some(Instant::now() + HOLD);
+                            }
+                            cues.push(cue);
+                        }
+                        continue;
+                    }
+                    Ok(_) => {
+                        let _ = reader.seek(SeekFrom::Start(position));
+                    }
+                    Err(e) => {
+                        tracing::error!(target: "Captions", "Failed to read the transcript: {}", e);
+                        break;
+                    }
+                }
+                if shown_until.is_some_and(|until| Instant::now() >= until) {
+                    show_caption("");
+                    shown_until = None;
+                }
+                std::thread::sleep(POLL_INTERVAL);
+            }
+            let _ = child.kill();
+            let _ = child.wait();
+            let _ = std::fs::remove_file(&transcript);
+            cues
+        });
+        Ok(Self { stop, thread })
+    }
+
+    fn finish(self) -> Vec<Cue> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.thread.join().unwrap_or_default()
+    }
+}
+
+/// Save `cues` as subtitles of `video`, adding them as a track if `embed`.
+fn save(video: &Path, cues: &[Cue], settings: &LiveCaptionSettings) -> Result<(), String> {
+    let path = video.with_extension("srt");
+    std::fs::write(&path, srt(cues)).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    if settings.embed {
+        let language = subtitles::language_option(settings.language.as_deref())?;
+        subtitles::embed_in_place(video, &path, &language)?;
+    }
+    Ok(())
+}
+
+/// Caption recordings made with live captions on.
+///
+/// Runs for the lifetime of the app.
+pub async fn run(app: AppHandle) {
+    use tokio::sync::broadcast::error::RecvError;
+
+    let manager = app.state::<AppState>().recording_manager.clone();
+    let mut phases = manager.lock().await.subscribe_phase_events();
+    // The transcriber of the recording, then its captions waiting for the
+    // file to be saved
+    let mut transcribing: Option<(Transcriber, LiveCaptionSettings)> = None;
+    let mut finished: Option<(Vec<Cue>, LiveCaptionSettings)> = None;
+    loop {
+        match phases.recv().await {
+            Ok(RecordingPhase::Recording) if transcribing.is_none() => {
+                let manager = manager.lock().await;
+                let settings = manager.get_encoder_settings().await.live_captions;
+                if !settings.enabled {
+                    continue;
+                }
+                match Transcriber::start(&settings, manager.clock().await) {
+                    Ok(transcriber) => transcribing = Some((transcriber, settings)),
+                    Err(e) => tracing::warn!(target: "Captions", "Can't caption the recording: {}", e),
+                }
+            }
+            Ok(RecordingPhase::Finalizing) => {
+                if let Some((transcriber, settings)) = transcribing.take() {
+                    let cues = tokio::task::spawn_blocking(move || transcriber.finish()).await.unwrap_or_default();
+                    show_caption("");
+                    finished = Some((cues, settings));
+                }
+            }
+            Ok(RecordingPhase::Done { file_path: Some(path), .. }) => {
+                let Some((cues, settings)) = finished.take() else {
+                    continue;
+                };
+                let video = PathBuf::from(path);
+                if cues.is_empty() || video.is_dir() {
+                    continue;
+                }
+                // They'd sit unencrypted next to the recording
+                if encryption::is_encrypted(&video) {
+                    tracing::info!(target: "Captions", "Captions aren't saved for encrypted recordings");
+                    continue;
+                }
+                let saved = tokio::task::spawn_blocking(move || save(&video, &cues, &settings)).await;
+                match saved {
+                    Ok(Err(e)) => tracing::error!(target: "Captions", "Failed to save the captions: {}", e),
+                    Err(e) => tracing::error!(target: "Captions", "Save task error: {}", e),
+                    Ok(Ok(())) => {}
+                }
+            }
+            Ok(RecordingPhase::Done { .. } | RecordingPhase::Failed { .. } | RecordingPhase::Idle) => {
+                if let Some((transcriber, _)) = transcribing.take() {
+                    tokio::task::spawn_blocking(move || transcriber.finish());
+                }
+                finished = None;
+            }
+            Ok(_) => {}
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_live_captions() {
+        let started = Instant::now();
+        let timer = CueTimer { started, video_start: 2.0 };
+        let at = |secs: u64| started + Duration::from_secs(secs);
+        let segment: Segment = serde_json::from_str(r#"{"start":1500,"end":4000,"text":" Hello there."}"#).unwrap();
+        let cue = timer.cue(&segment, &[]).unwrap();
+        assert_eq!(cue, Cue { start: 3.5, end: 6.0, text: "Hello there.".to_string() });
+        let blank = Segment { start: 0, end: 3000, text: "[BLANK_AUDIO]".to_string() };
+        assert!(timer.cue(&blank, &[]).is_none());
+
+        // Paused from 5 to 6 seconds in, and again from 10 seconds on
+        let pauses = [
+            Pause { start: at(5), end: Some(at(6)) },
+            Pause { start: at(10), end: None },
+        ];
+        let timed = |start: i64, end: i64| {
+            let segment = Segment { start, end, text: "Hi".to_string() };
+            timer.cue(&segment, &pauses).map(|cue| (cue.start, cue.end))
+        };
+        // Spoken before the pause, though read after it
+        assert_eq!(timed(1500, 4000), Some((3.5, 6.0)));
+        // Mostly spoken after resuming, and moved back by the pause
+        assert_eq!(timed(5500, 8000), Some((7.0, 9.0)));
+        // Spoken while paused
+        assert_eq!(timed(5000, 5900), None);
+        assert_eq!(timed(10500, 12000), None);
+
+        assert_eq!(
+            srt(&[cue, Cue { start: 61.25, end: 3725.0, text: "Bye".to_string() }]),
+            "1\n00:00:03,500 --> 00:00:06,000\nHello there.\n\n2\n00:01:01,250 --> 01:02:05,000\nBye\n\n"
+        );
+
+        let long = "one two three four five six seven eight nine ten eleven twelve thirteen fourteen fifteen";
+        // Three lines long, so the first is dropped
+        assert_eq!(wrap_caption(long), "nine ten eleven twelve thirteen fourteen\nfifteen");
+    }
+}
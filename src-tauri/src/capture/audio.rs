@@ -0,0 +1,499 @@
+//! Audio capture alongside a video recording.
+//!
+//! Audio is written to a temporary WAV file while recording and muxed into
+//! the video once the encoder has finished (see `postprocess::mux`). When the
+//! audio device changes or disappears mid-recording the capture reconnects,
+//! filling the switch with silence so the track stays in step with the video.
+//!
+//! On Linux the application's streams are routed through a virtual sink
+//! made for the recording (see `linux::app_audio`).
+//!
+//! The default microphone can be recorded alongside, through FFmpeg. Each
+//! [`AudioSource`] gets a file of its own, and they're mixed into one track
+//! before muxing (see `postprocess::mix`), each shifted by its sync offset.
+//! The application audio the microphone picks up from speakers is removed
+//! before mixing, with the application's own track as the reference (see
+//! `postprocess::echo`).
+
+use super::error::CaptureError;
+use super::pause::PauseState;
+use crate::avsync::SyncClock;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Sample rate of captured audio.
+pub const SAMPLE_RATE: u32 = 48_000;
+/// Channel count of captured audio.
+pub const CHANNELS: u16 = 2;
+/// Bits per sample of captured audio (signed PCM).
+pub const BITS_PER_SAMPLE: u16 = 16;
+/// Bytes per sample frame (one sample for every channel).
+pub const BLOCK_ALIGN: u16 = CHANNELS * BITS_PER_SAMPLE / 8;
+
+/// An audio source a recording can capture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioSource {
+    /// The output of the recorded window's application
+    Application,
+    /// The default microphone
+    Microphone,
+}
+
+impl AudioSource {
+    pub const ALL: [AudioSource; 2] = [AudioSource::Application, AudioSource::Microphone];
+
+    /// The source's name, as used for its levels and sync offset.
+    pub fn name(&self) -> &'static str {
+        match self {
+            AudioSource::Application => "application",
+            AudioSource::Microphone => "microphone",
+        }
+    }
+
+    /// The source called `name`, if there is one.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|source| source.name() == name)
+    }
+}
+
+/// What audio capture does when the system's output device changes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioDeviceMode {
+    /// Move to the new default device, e.g. when a headset is plugged in
+    #[default]
+    FollowDefault,
+    /// Stay on the device the recording started with, only reconnecting if
+    /// it stops working
+    Fixed,
+}
+
+/// Whether audio captures follow the default device.
+static FOLLOW_DEFAULT: AtomicBool = AtomicBool::new(true);
+
+/// Set how audio captures handle device changes. Applies immediately, also
+/// to a running capture.
+pub fn set_device_mode(mode: AudioDeviceMode) {
+    FOLLOW_DEFAULT.store(mode == AudioDeviceMode::FollowDefault, Ordering::Relaxed);
+}
+
+/// How audio captures handle device changes.
+pub fn device_mode() -> AudioDeviceMode {
+    match FOLLOW_DEFAULT.load(Ordering::Relaxed) {
+        true => AudioDeviceMode::FollowDefault,
+        false => AudioDeviceMode::Fixed,
+    }
+}
+
+/// Level reported for digital silence (the 16-bit noise floor), in dBFS.
+pub const MIN_LEVEL_DB: f32 = -96.0;
+/// Audio peaking below this level (in dBFS) counts as silent.
+const SILENCE_DB: f32 = -60.0;
+
+/// Sample frames kept for visualizing the audio, about 40 ms.
+pub const RECENT_FRAMES: usize = 2048;
+
+/// Levels of an audio source over the last metering interval.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioLevel {
+    /// Which source was measured, such as "application"
+    pub source: String,
+    /// Highest sample level, in dBFS
+    pub peak_db: f32,
+    /// Average (RMS) level, in dBFS
+    pub rms_db: f32,
+    /// How long the source has been silent, in seconds; 0 while there is sound
+    pub silent_secs: f64,
+}
+
+/// Measures the levels of the audio written by a capture, keeping its last
+/// few samples for visualizers.
+#[derive(Default)]
+pub struct LevelMeter {
+    window: Mutex<MeterWindow>,
+    /// The last [`RECENT_FRAMES`] sample frames, mixed to mono
+    recent: Mutex<VecDeque<i16>>,
+}
+
+/// Samples seen since the meter was last read.
+#[derive(Default)]
+struct MeterWindow {
+    peak: u32,
+    sum_squares: f64,
+    samples: u64,
+    silent: Duration,
+}
+
+impl LevelMeter {
+    /// Add interleaved 16-bit sample frames to the current interval.
+    pub fn measure(&self, data: &[u8]) {
+        let mut window = self.window.lock().unwrap();
+        for sample in data.chunks_exact(2) {
+            let sample = i16::from_le_bytes([sample[0], sample[1]]) as i32;
+            window.peak = window.peak.max(sample.unsigned_abs());
+            window.sum_squares += (sample * sample) as f64;
+            window.samples += 1;
+        }
+        drop(window);
+
+        let mut recent = self.recent.lock().unwrap();
+        for frame in data.chunks_exact(BLOCK_ALIGN as usize) {
+            let sum: i32 = frame.chunks_exact(2).map(|s| i16::from_le_bytes([s[0], s[1]]) as i32).sum();
+            if recent.len() == RECENT_FRAMES {
+                recent.pop_front();
+            }
+            recent.push_back((sum / CHANNELS as i32) as i16);
+        }
+    }
+
+    /// The last sample frames measured, oldest first, mixed to mono and
+    /// scaled to -1 to 1.
+    pub fn recent(&self) -> Vec<f32> {
+        self.recent.lock().unwrap().iter().map(|&s| s as f32 / 32768.0).collect()
+    }
+
+    /// Levels of `source` since the last call, which was `interval` ago.
+    pub fn take(&self, source: &str, interval: Duration) -> AudioLevel {
+        let mut window = self.window.lock().unwrap();
+        let peak_db = to_db(window.peak as f64);
+        let rms_db = match window.samples {
+            0 => MIN_LEVEL_DB,
+            samples => to_db((window.sum_squares / samples as f64).sqrt()),
+        };
+        let silent = if peak_db < SILENCE_DB { window.silent + interval } else { Duration::ZERO };
+        *window = MeterWindow { silent, ..Default::default() };
+        AudioLevel {
+            source: source.to_string(),
+            peak_db,
+            rms_db,
+            silent_secs: silent.as_secs_f64(),
+        }
+    }
+}
+
+/// A 16-bit sample magnitude in dBFS.
+fn to_db(magnitude: f64) -> f32 {
+    if magnitude <= 0.0 {
+        return MIN_LEVEL_DB;
+    }
+    ((20.0 * (magnitude / 32768.0).log10()) as f32).max(MIN_LEVEL_DB)
+}
+
+/// An audio capture running on a background thread.
+pub struct AudioRecording {
+    source: AudioSource,
+    path: PathBuf,
+    stop_flag: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Result<(), String>>>,
+    meter: Arc<LevelMeter>,
+}
+
+impl AudioRecording {
+    /// Wrap a capture thread writing to `path` until `stop_flag` is set,
+    /// measuring its levels with `meter`.
+    pub(crate) fn new(
+        path: PathBuf,
+        stop_flag: Arc<AtomicBool>,
+        thread: JoinHandle<Result<(), String>>,
+        meter: Arc<LevelMeter>,
+    ) -> Self {
+        Self {
+            source: AudioSource::Application,
+            path,
+            stop_flag,
+            thread: Some(thread),
+            meter,
+        }
+    }
+
+    /// The captured source.
+    pub fn source(&self) -> AudioSource {
+        self.source
+    }
+
+    /// Levels of the captured audio since the last call.
+    pub fn levels(&self, interval: Duration) -> AudioLevel {
+        self.meter.take(self.source.name(), interval)
+    }
+
+    /// The last few sample frames captured, as from [`LevelMeter::recent`].
+    pub fn recent_samples(&self) -> Vec<f32> {
+        self.meter.recent()
+    }
+
+    /// Stop capturing and return the finished WAV file.
+    pub fn stop(mut self) -> Result<PathBuf, String> {
+        self.stop_flag.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread
+                .join()
+                .map_err(|_| "Audio capture thread panicked".to_string())??;
+        }
+        Ok(self.path.clone())
+    }
+}
+
+impl Drop for AudioRecording {
+    fn drop(&mut self) {
+        // Dropped without stop(), e.g. because the recording failed
+        self.stop_flag.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Start capturing the audio of the application that owns a window, timed
+/// against `clock`. Audio is dropped while the clock is paused.
+///
+/// Only that application's audio (including its child processes) is
+/// recorded, so e.g. a game can be captured without a voice chat app.
+pub fn start_app_audio_capture(window_handle: isize, clock: Arc<SyncClock>) -> Result<AudioRecording, CaptureError> {
+    #[cfg(target_os = "windows")]
+    return super::windows::audio::start_process_audio_capture(window_handle, temp_audio_path(AudioSource::Application), clock)
+        .map_err(CaptureError::PlatformError);
+    #[cfg(target_os = "linux")]
+    {
+        let pid = super::list_windows()
+            .into_iter()
+            .find(|window| window.handle == window_handle)
+            .map(|window| window.pid)
+            .ok_or_else(|| CaptureError::TargetNotFound(format!("Window with handle {} not found", window_handle)))?;
+        super::linux::app_audio::start_app_audio_capture(pid, temp_audio_path(AudioSource::Application), clock)
+            .map_err(CaptureError::PlatformError)
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    {
+        let _ = (window_handle, clock);
+        Err(CaptureError::NotImplemented(
+            "Per-application audio capture is only supported on Windows and Linux".to_string(),
+        ))
+    }
+}
+
+/// Start recording the default microphone through FFmpeg, timed against
+/// `clock`. Audio is dropped while the clock is paused.
+pub fn start_microphone_capture(clock: Arc<SyncClock>) -> Result<AudioRecording, CaptureError> {
+    let mut command = ffmpeg_sidecar::command::FfmpegCommand::new();
+    command
+        .args(["-hide_banner", "-nostdin", "-loglevel", "error"])
+        .args(crate::voice::microphone_input().map_err(CaptureError::PlatformError)?)
+        .args(["-vn", "-f", "s16le", "-ar", &SAMPLE_RATE.to_string(), "-ac", &CHANNELS.to_string(), "pipe:1"]);
+    let inner_command = command.as_inner_mut();
+    inner_command.stdin(Stdio::null());
+    inner_command.stdout(Stdio::piped());
+    inner_command.stderr(Stdio::null());
+    let mut child = inner_command
+        .spawn()
+        .map_err(|e| CaptureError::PlatformError(format!("Failed to start FFmpeg: {}", e)))?;
+    let mut stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| CaptureError::PlatformError("Failed to get FFmpeg stdout".to_string()))?;
+
+    let path = temp_audio_path(AudioSource::Microphone);
+    let meter = Arc::new(LevelMeter::default());
+    let mut writer = WavWriter::create(&path)
+        .map_err(CaptureError::PlatformError)?
+        .with_meter(meter.clone())
+        .with_pause(clock.pause().clone());
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    let stopped = stop_flag.clone();
+    let thread = std::thread::spawn(move || {
+        let result = (|| {
+            // Start in step with the video, which started with the clock
+            writer.write_silence((clock.now().as_secs_f64() * SAMPLE_RATE as f64) as u64)?;
+            // Whole sample frames, about 20 ms at a time
+            let mut buffer = vec![0u8; BLOCK_ALIGN as usize * 1024];
+            while !stopped.load(Ordering::Relaxed) {
+                stdout
+                    .read_exact(&mut buffer)
+                    .map_err(|e| format!("The microphone stopped: {}", e))?;
+                writer.write(&buffer)?;
+            }
+            Ok(())
+        })();
+        let _ = child.kill();
+        let _ = child.wait();
+        writer.finish()?;
+        result
+    });
+    let mut recording = AudioRecording::new(path, stop_flag, thread, meter);
+    recording.source = AudioSource::Microphone;
+    Ok(recording)
+}
+
+/// A unique path for a temporary audio file of `source`.
+fn temp_audio_path(source: AudioSource) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "screen-recorder-{}-{}-{}.wav",
+        source.name(),
+        std::process::id(),
+        chrono::Local::now().format("%Y%m%d%H%M%S%3f")
+    ))
+}
+
+/// Length of a WAV file written by [`WavWriter`], in seconds.
+pub fn wav_duration(path: &Path) -> Result<f64, String> {
+    let len = std::fs::metadata(path)
+        .map_err(|e| format!("Failed to read audio file: {}", e))?
+        .len();
+    Ok(len.saturating_sub(44) as f64 / BLOCK_ALIGN as f64 / SAMPLE_RATE as f64)
+}
+
+/// Writes PCM audio in the [`SAMPLE_RATE`]/[`CHANNELS`]/[`BITS_PER_SAMPLE`] format to a WAV file.
+pub struct WavWriter {
+    file: BufWriter<File>,
+    data_len: u32,
+    meter: Option<Arc<LevelMeter>>,
+    pause: Option<Arc<PauseState>>,
+}
+
+#[cfg_attr(not(any(target_os = "windows", target_os = "linux")), allow(dead_code))]
+impl WavWriter {
+    /// Create the file, with a header that is completed by [`finish`](Self::finish).
+    pub fn create(path: &Path) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("Failed to create audio file: {}", e))?;
+        let mut writer = Self {
+            file: BufWriter::new(file),
+            data_len: 0,
+            meter: None,
+            pause: None,
+        };
+        writer
+            .file
+            .write_all(&wav_header(0))
+            .map_err(|e| format!("Failed to write audio file: {}", e))?;
+        Ok(writer)
+    }
+
+    /// Measure the levels of everything written with `meter`.
+    pub fn with_meter(mut self, meter: Arc<LevelMeter>) -> Self {
+        self.meter = Some(meter);
+        self
+    }
+
+    /// Drop everything written while `pause` is paused.
+    pub fn with_pause(mut self, pause: Arc<PauseState>) -> Self {
+        self.pause = Some(pause);
+        self
+    }
+
+    /// Append interleaved sample frames.
+    pub fn write(&mut self, data: &[u8]) -> Result<(), String> {
+        if self.pause.as_ref().is_some_and(|pause| pause.is_paused()) {
+            return Ok(());
+        }
+        if let Some(meter) = &self.meter {
+            meter.measure(data);
+        }
+        self.file
+            .write_all(data)
+            .map_err(|e| format!("Failed to write audio file: {}", e))?;
+        self.data_len = self.data_len.saturating_add(data.len() as u32);
+        Ok(())
+    }
+
+    /// Append `frames` sample frames of silence.
+    pub fn write_silence(&mut self, frames: u64) -> Result<(), String> {
+        let zeros = [0u8; 4096];
+        let mut remaining = frames * BLOCK_ALIGN as u64;
+        while remaining > 0 {
+            let chunk = remaining.min(zeros.len() as u64) as usize;
+            self.write(&zeros[..chunk])?;
+            remaining -= chunk as u64;
+        }
+        Ok(())
+    }
+
+    /// Number of sample frames written so far.
+    #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+    pub fn frames(&self) -> u64 {
+        self.data_len as u64 / BLOCK_ALIGN as u64
+    }
+
+    /// Fill in the header's sizes and close the file.
+    pub fn finish(mut self) -> Result<(), String> {
+        let header = wav_header(self.data_len);
+        self.file
+            .seek(SeekFrom::Start(0))
+            .and_then(|_| self.file.write_all(&header))
+            .and_then(|_| self.file.flush())
+            .map_err(|e| format!("Failed to finish audio file: {}", e))
+    }
+}
+
+/// Canonical 44-byte PCM WAV header for `data_len` bytes of samples.
+fn wav_header(data_len: u32) -> [u8; 44] {
+    let mut header = [0u8; 44];
+    let byte_rate = SAMPLE_RATE * BLOCK_ALIGN as u32;
+    header[0..4].copy_from_slice(b"RIFF");
+    header[4..8].copy_from_slice(&data_len.saturating_add(36).to_le_bytes());
+    header[8..12].copy_from_slice(b"WAVE");
+    header[12..16].copy_from_slice(b"fmt ");
+    header[16..20].copy_from_slice(&16u32.to_le_bytes());
+    header[20..22].copy_from_slice(&1u16.to_le_bytes()); // PCM
+    header[22..24].copy_from_slice(&CHANNELS.to_le_bytes());
+    header[24..28].copy_from_slice(&SAMPLE_RATE.to_le_bytes());
+    header[28..32].copy_from_slice(&byte_rate.to_le_bytes());
+    header[32..34].copy_from_slice(&BLOCK_ALIGN.to_le_bytes());
+    header[34..36].copy_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+    header[36..40].copy_from_slice(b"data");
+    header[40..44].copy_from_slice(&data_len.to_le_bytes());
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wav_writer() {
+        let path = std::env::temp_dir().join(format!("wav-writer-test-{}.wav", std::process::id()));
+        let mut writer = WavWriter::create(&path).unwrap();
+        writer.write(&[1, 0, 2, 0]).unwrap();
+        writer.write_silence(2).unwrap();
+        assert_eq!(writer.frames(), 3);
+        writer.finish().unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(bytes.len(), 44 + 12);
+        assert_eq!(&bytes[..44], &wav_header(12));
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), 48);
+        assert_eq!(u32::from_le_bytes(bytes[28..32].try_into().unwrap()), 192_000);
+        assert_eq!(&bytes[44..48], &[1, 0, 2, 0]);
+    }
+
+    #[test]
+    fn test_level_meter() {
+        let meter = LevelMeter::default();
+        let half_scale: Vec<u8> = [16384i16, -16384].iter().flat_map(|s| s.to_le_bytes()).collect();
+        meter.measure(&half_scale);
+        let level = meter.take("application", Duration::from_millis(100));
+        assert!((level.peak_db + 6.02).abs() < 0.01);
+        assert!((level.rms_db + 6.02).abs() < 0.01);
+        assert_eq!(level.silent_secs, 0.0);
+
+        // Silence accumulates until there is sound again
+        meter.measure(&[0; 8]);
+        assert_eq!(meter.take("application", Duration::from_millis(100)).peak_db, MIN_LEVEL_DB);
+        assert!((meter.take("application", Duration::from_millis(100)).silent_secs - 0.2).abs() < 1e-9);
+
+        // Channels are mixed for visualizers, here cancelling out
+        assert_eq!(meter.recent(), [0.0; 3]);
+        let left_only: Vec<u8> = [16384i16, 0].iter().flat_map(|s| s.to_le_bytes()).collect();
+        meter.measure(&left_only.repeat(RECENT_FRAMES));
+        assert_eq!(meter.recent(), vec![0.25; RECENT_FRAMES]);
+    }
+}
@@ -0,0 +1,271 @@
+//! Virtual desktop geometry.
+//!
+//! All monitors share one desktop coordinate space whose origin is the
+//! primary monitor's top-left corner, so monitors left of or above it have
+//! negative positions. Windows lays the desktop out in physical pixels,
+//! while Wayland compositors lay it out in logical pixels; monitor sizes are
+//! always physical and are scaled into the layout's units here.
+
+use super::error::RegionError;
+use super::types::{CaptureRegion, CoordinateSpace, MonitorInfo, WindowBounds};
+
+/// Units the platform lays monitors out in.
+#[cfg(target_os = "linux")]
+pub const DESKTOP_SPACE: CoordinateSpace = CoordinateSpace::Logical;
+#[cfg(not(target_os = "linux"))]
+pub const DESKTOP_SPACE: CoordinateSpace = CoordinateSpace::Physical;
+
+/// How close, in desktop units, an edge must be to another to snap to it.
+const SNAP_DISTANCE: i32 = 12;
+
+/// The monitors making up the desktop and where they are.
+#[derive(Debug, Clone)]
+pub struct DesktopLayout {
+    monitors: Vec<MonitorInfo>,
+    space: CoordinateSpace,
+}
+
+impl DesktopLayout {
+    /// Layout of `monitors` whose positions are in `space`.
+    pub fn new(monitors: Vec<MonitorInfo>, space: CoordinateSpace) -> Self {
+        Self { monitors, space }
+    }
+
+    /// Layout of the currently connected monitors.
+    pub fn current() -> Self {
+        Self::new(super::list_monitors(), DESKTOP_SPACE)
+    }
+
+    /// Monitor with the given ID.
+    pub fn monitor(&self, monitor_id: &str) -> Option<&MonitorInfo> {
+        self.monitors.iter().find(|m| m.id == monitor_id)
+    }
+
+    /// Area a monitor covers in desktop coordinates.
+    pub fn monitor_bounds(&self, monitor: &MonitorInfo) -> WindowBounds {
+        let size = |value: u32| {
+            CoordinateSpace::Physical
+                .convert(value as f64, self.space, monitor.scale_factor)
+                .round() as u32
+        };
+        WindowBounds {
+            x: monitor.x,
+            y: monitor.y,
+            width: size(monitor.width),
+            height: size(monitor.height),
+        }
+    }
+
+    /// Turn an area of the desktop into a capture region on the monitor
+    /// showing most of it, clipped to that monitor.
+    ///
+    /// Returns `None` if the area isn't on any monitor.
+    pub fn resolve_region(&self, area: WindowBounds) -> Option<CaptureRegion> {
+        let (monitor, visible) = self
+            .monitors
+            .iter()
+            .filter_map(|monitor| Some((monitor, intersect(area, self.monitor_bounds(monitor))?)))
+            .max_by_key(|(_, visible)| visible.width as u64 * visible.height as u64)?;
+        Some(CaptureRegion {
+            monitor_id: monitor.id.clone(),
+            x: visible.x - monitor.x,
+            y: visible.y - monitor.y,
+            width: visible.width,
+            height: visible.height,
+            space: self.space,
+        })
+    }
+
+    /// Area of the desktop a capture region covers.
+    ///
+    /// Returns `None` if the region's monitor isn't connected.
+    pub fn region_bounds(&self, region: &CaptureRegion) -> Option<WindowBounds> {
+        let monitor = self.monitor(&region.monitor_id)?;
+        let region = region.to_space(self.space, monitor);
+        Some(WindowBounds {
+            x: monitor.x + region.x,
+            y: monitor.y + region.y,
+            width: region.width,
+            height: region.height,
+        })
+    }
+
+    /// `region` clipped to the monitor showing most of it, in that monitor's
+    /// physical pixels. Selections can span monitors, but captures can't.
+    ///
+    /// Returns `None` if the region isn't on any monitor.
+    pub fn clip_region(&self, region: &CaptureRegion) -> Option<CaptureRegion> {
+        let clipped = self.resolve_region(self.region_bounds(region)?)?;
+        Some(clipped.to_physical(self.monitor(&clipped.monitor_id)?))
+    }
+
+    /// `region` as it can be recorded: clamped to its monitor, or to the
+    /// monitor showing most of it if it spans several, in that monitor's
+    /// physical pixels. Captures are of one monitor, so anything beyond it
+    /// would only be black bars.
+    pub fn validate_region(&self, region: &CaptureRegion) -> Result<CaptureRegion, RegionError> {
+        if region.width == 0 || region.height == 0 {
+            return Err(RegionError::Empty { width: region.width, height: region.height });
+        }
+        if self.monitor(&region.monitor_id).is_none() {
+            return Err(RegionError::MonitorNotFound(region.monitor_id.clone()));
+        }
+        self.clip_region(region).ok_or(RegionError::OffScreen)
+    }
+
+    /// `area` with each edge moved onto the closest monitor edge, or edge of
+    /// one of `windows` near the area, within [`SNAP_DISTANCE`].
+    pub fn snap(&self, area: WindowBounds, windows: &[WindowBounds]) -> WindowBounds {
+        let reach = WindowBounds {
+            x: area.x - SNAP_DISTANCE,
+            y: area.y - SNAP_DISTANCE,
+            width: area.width + 2 * SNAP_DISTANCE as u32,
+            height: area.height + 2 * SNAP_DISTANCE as u32,
+        };
+        let targets: Vec<WindowBounds> = self
+            .monitors
+            .iter()
+            .map(|monitor| self.monitor_bounds(monitor))
+            .chain(windows.iter().copied().filter(|window| intersect(reach, *window).is_some()))
+            .collect();
+        let columns: Vec<i32> = targets.iter().flat_map(|b| [b.x, b.x + b.width as i32]).collect();
+        let rows: Vec<i32> = targets.iter().flat_map(|b| [b.y, b.y + b.height as i32]).collect();
+        let snap_edge = |edge: i32, candidates: &[i32]| {
+            candidates
+                .iter()
+                .copied()
+                .filter(|candidate| (candidate - edge).abs() <= SNAP_DISTANCE)
+                .min_by_key(|candidate| (candidate - edge).abs())
+                .unwrap_or(edge)
+        };
+
+        let left = snap_edge(area.x, &columns);
+        let right = snap_edge(area.x + area.width as i32, &columns);
+        let top = snap_edge(area.y, &rows);
+        let bottom = snap_edge(area.y + area.height as i32, &rows);
+        if right <= left || bottom <= top {
+            return area;
+        }
+        WindowBounds {
+            x: left,
+            y: top,
+            width: (right - left) as u32,
+            height: (bottom - top) as u32,
+        }
+    }
+
+    /// Consume the layout, returning its monitors.
+    pub fn into_monitors(self) -> Vec<MonitorInfo> {
+        self.monitors
+    }
+}
+
+/// Overlap of two rectangles, or `None` if they don't overlap.
+fn intersect(a: WindowBounds, b: WindowBounds) -> Option<WindowBounds> {
+    let left = a.x.max(b.x);
+    let top = a.y.max(b.y);
+    let right = (a.x + a.width as i32).min(b.x + b.width as i32);
+    let bottom = (a.y + a.height as i32).min(b.y + b.height as i32);
+    if right <= left || bottom <= top {
+        return None;
+    }
+    Some(WindowBounds {
+        x: left,
+        y: top,
+        width: (right - left) as u32,
+        height: (bottom - top) as u32,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(id: &str, x: i32, width: u32, scale_factor: f64) -> MonitorInfo {
+        MonitorInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            x,
+            y: 0,
+            width,
+            height: 1080,
+            is_primary: x == 0,
+            scale_factor,
+            model: None,
+            refresh_rate: None,
+            rotation: 0,
+        }
+    }
+
+    #[test]
+    fn test_layout_left_of_primary() {
+        // A 1080p monitor left of a 4K monitor at 200%, laid out logically
+        let layout = DesktopLayout::new(
+            vec![monitor("left", -1920, 1920, 1.0), monitor("main", 0, 3840, 2.0)],
+            CoordinateSpace::Logical,
+        );
+        assert_eq!(
+            layout.monitor_bounds(layout.monitor("main").unwrap()),
+            WindowBounds { x: 0, y: 0, width: 1920, height: 540 }
+        );
+
+        // Mostly on the left monitor, spilling onto the primary
+        let region = layout
+            .resolve_region(WindowBounds { x: -1000, y: 100, width: 1200, height: 300 })
+            .unwrap();
+        assert_eq!(region.monitor_id, "left");
+        assert_eq!((region.x, region.y, region.width, region.height), (920, 100, 1000, 300));
+        assert_eq!(
+            layout.region_bounds(&region),
+            Some(WindowBounds { x: -1000, y: 100, width: 1000, height: 300 })
+        );
+
+        // Clipped to the left monitor in physical pixels
+        let spanning = CaptureRegion { x: 1620, width: 400, ..region.clone() };
+        let clipped = layout.clip_region(&spanning).unwrap();
+        assert_eq!((clipped.monitor_id.as_str(), clipped.x, clipped.width), ("left", 1620, 300));
+
+        assert!(layout.resolve_region(WindowBounds { x: -3000, y: 0, width: 100, height: 100 }).is_none());
+    }
+
+    #[test]
+    fn test_validate_region() {
+        let layout = DesktopLayout::new(vec![monitor("main", 0, 1920, 1.0)], CoordinateSpace::Physical);
+        let region = CaptureRegion {
+            monitor_id: "main".to_string(),
+            x: 1800,
+            y: -50,
+            width: 400,
+            height: 300,
+            space: CoordinateSpace::Physical,
+        };
+        // Hanging off the right and top edges
+        let clamped = layout.validate_region(&region).unwrap();
+        assert_eq!((clamped.x, clamped.y, clamped.width, clamped.height), (1800, 0, 120, 250));
+
+        let empty = CaptureRegion { width: 0, ..region.clone() };
+        assert_eq!(layout.validate_region(&empty).unwrap_err(), RegionError::Empty { width: 0, height: 300 });
+        let unplugged = CaptureRegion { monitor_id: "HDMI-1".to_string(), ..region.clone() };
+        assert_eq!(
+            layout.validate_region(&unplugged).unwrap_err(),
+            RegionError::MonitorNotFound("HDMI-1".to_string())
+        );
+        let off_screen = CaptureRegion { x: 2000, ..region };
+        assert_eq!(layout.validate_region(&off_screen).unwrap_err(), RegionError::OffScreen);
+    }
+
+    #[test]
+    fn test_snap() {
+        let layout = DesktopLayout::new(vec![monitor("main", 0, 1920, 1.0)], CoordinateSpace::Physical);
+        let player = WindowBounds { x: 400, y: 300, width: 640, height: 360 };
+        let far = WindowBounds { x: 1500, y: 800, width: 100, height: 100 };
+
+        // Edges close to the player's snap onto it
+        let snapped = layout.snap(WindowBounds { x: 395, y: 306, width: 650, height: 350 }, &[player, far]);
+        assert_eq!(snapped, player);
+
+        // ...and to the monitor's edges; others stay put
+        let snapped = layout.snap(WindowBounds { x: 8, y: 100, width: 200, height: 200 }, &[player, far]);
+        assert_eq!(snapped, WindowBounds { x: 0, y: 100, width: 208, height: 200 });
+    }
+}
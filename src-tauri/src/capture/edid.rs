@@ -0,0 +1,112 @@
+//! Monitor model names from EDID.
+//!
+//! Every monitor describes itself to the computer with an EDID block, which
+//! holds the manufacturer's three-letter PNP ID and, usually, the model name
+//! printed on the bezel. That's what users recognize, unlike connector names
+//! or `\\.\DISPLAY1`.
+
+/// Full names of common monitor manufacturers, by PNP ID.
+const MANUFACTURERS: &[(&str, &str)] = &[
+    ("ACI", "ASUS"),
+    ("ACR", "Acer"),
+    ("AOC", "AOC"),
+    ("APP", "Apple"),
+    ("AUS", "ASUS"),
+    ("BNQ", "BenQ"),
+    ("DEL", "DELL"),
+    ("GSM", "LG"),
+    ("HPN", "HP"),
+    ("HWP", "HP"),
+    ("LEN", "Lenovo"),
+    ("MSI", "MSI"),
+    ("NEC", "NEC"),
+    ("PHL", "Philips"),
+    ("SAM", "Samsung"),
+    ("SNY", "Sony"),
+    ("VSC", "ViewSonic"),
+];
+
+/// Descriptor tag of the monitor name.
+const NAME_TAG: u8 = 0xFC;
+
+/// The model name of the monitor an EDID block describes, prefixed with its
+/// manufacturer unless the name already starts with it, e.g. `DELL U2720Q`.
+#[cfg_attr(not(any(target_os = "linux", test)), allow(dead_code))]
+pub fn model_name(edid: &[u8]) -> Option<String> {
+    if edid.len() < 128 || edid[..8] != [0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00] {
+        return None;
+    }
+    let name = monitor_name(edid)?;
+    let Some(manufacturer) = manufacturer(edid) else {
+        return Some(name);
+    };
+    if name.to_lowercase().starts_with(&manufacturer.to_lowercase()) {
+        Some(name)
+    } else {
+        Some(format!("{} {}", manufacturer, name))
+    }
+}
+
+/// The manufacturer's name, or its PNP ID if it isn't a common one.
+fn manufacturer(edid: &[u8]) -> Option<String> {
+    // Three 5-bit letters, 1 = 'A'
+    let packed = u16::from_be_bytes([edid[8], edid[9]]);
+    let id: String = [10, 5, 0]
+        .iter()
+        .map(|shift| (packed >> shift) & 0x1F)
+        .filter(|&letter| (1..=26).contains(&letter))
+        .map(|letter| (b'A' + letter as u8 - 1) as char)
+        .collect();
+    if id.len() != 3 {
+        return None;
+    }
+    let name = MANUFACTURERS.iter().find(|(pnp, _)| *pnp == id).map(|(_, name)| name.to_string());
+    Some(name.unwrap_or(id))
+}
+
+/// The name in the monitor name descriptor, if there is one.
+fn monitor_name(edid: &[u8]) -> Option<String> {
+    // Four 18-byte descriptors from byte 54; display descriptors start with
+    // three zero bytes and their tag
+    edid[54..126].chunks_exact(18).find_map(|descriptor| {
+        if descriptor[..3] != [0, 0, 0] || descriptor[3] != NAME_TAG {
+            return None;
+        }
+        // Up to 13 characters, ended by a line feed and padded with spaces
+        let text = &descriptor[5..];
+        let end = text.iter().position(|&c| c == b'\n').unwrap_or(text.len());
+        let name = String::from_utf8_lossy(&text[..end]).trim().to_string();
+        (!name.is_empty()).then_some(name)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An EDID block from `pnp` with a name descriptor holding `name`.
+    fn edid(pnp: &str, name: &str) -> Vec<u8> {
+        let mut edid = vec![0u8; 128];
+        edid[..8].copy_from_slice(&[0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00]);
+        let letters: Vec<u16> = pnp.bytes().map(|c| (c - b'A' + 1) as u16).collect();
+        let packed = (letters[0] << 10) | (letters[1] << 5) | letters[2];
+        edid[8..10].copy_from_slice(&packed.to_be_bytes());
+        // Name in the second descriptor, after a detailed timing one
+        edid[54] = 1;
+        let descriptor = &mut edid[72..90];
+        descriptor[3] = NAME_TAG;
+        let mut text = name.as_bytes().to_vec();
+        text.push(b'\n');
+        text.resize(13, b' ');
+        descriptor[5..].copy_from_slice(&text);
+        edid
+    }
+
+    #[test]
+    fn test_model_name() {
+        assert_eq!(model_name(&edid("DEL", "DELL U2720Q")).as_deref(), Some("DELL U2720Q"));
+        assert_eq!(model_name(&edid("GSM", "27GL850")).as_deref(), Some("LG 27GL850"));
+        assert_eq!(model_name(&edid("XYZ", "Panel")).as_deref(), Some("XYZ Panel"));
+        assert_eq!(model_name(&[0u8; 128]), None);
+    }
+}
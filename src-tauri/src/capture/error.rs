@@ -43,6 +43,44 @@ impl From<CaptureError> for String {
     }
 }
 
+/// Why a capture region can't be recorded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegionError {
+    /// The region has no area
+    Empty { width: u32, height: u32 },
+    /// The region's monitor isn't connected
+    MonitorNotFound(String),
+    /// No part of the region is on a monitor
+    OffScreen,
+}
+
+impl fmt::Display for RegionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegionError::Empty { width, height } => write!(f, "Region is empty ({}x{})", width, height),
+            RegionError::MonitorNotFound(id) => write!(f, "Monitor '{}' is not connected", id),
+            RegionError::OffScreen => write!(f, "Region is not on any monitor"),
+        }
+    }
+}
+
+impl std::error::Error for RegionError {}
+
+impl From<RegionError> for CaptureError {
+    fn from(err: RegionError) -> Self {
+        match err {
+            RegionError::MonitorNotFound(_) => CaptureError::TargetNotFound(err.to_string()),
+            _ => CaptureError::InvalidRegion(err.to_string()),
+        }
+    }
+}
+
+impl From<RegionError> for String {
+    fn from(err: RegionError) -> Self {
+        CaptureError::from(err).to_string()
+    }
+}
+
 /// Error type for enumeration operations.
 #[derive(Debug)]
 pub enum EnumerationError {
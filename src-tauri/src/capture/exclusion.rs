@@ -0,0 +1,237 @@
+//! Excluding windows from display and region captures.
+//!
+//! Windows matching an exclusion rule (e.g. a password manager) are tracked
+//! while recording and hidden by the capture's [`FrameMask`](super::mask::FrameMask).
+//!
+//! The recorder's own windows are kept out of recordings too, unless turned
+//! off. On Windows they're hidden from every capture with
+//! `SetWindowDisplayAffinity`, so whatever is behind them is recorded; other
+//! platforms have no such API for a screencast, so they're masked like
+//! excluded windows.
+
+use super::mask::MaskStyle;
+use super::types::WindowInfo;
+use crate::config::{load_json, save_json};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
+
+const EXCLUSIONS_FILE: &str = "exclusions.json";
+
+/// Whether the platform hides the recorder's windows from captures itself.
+const PLATFORM_HIDES_SELF: bool = cfg!(target_os = "windows");
+
+static EXCLUDE_SELF: AtomicBool = AtomicBool::new(true);
+
+/// Whether the recorder's own windows are kept out of recordings.
+pub fn excludes_self() -> bool {
+    EXCLUDE_SELF.load(Ordering::Relaxed)
+}
+
+/// Rule matching the recorder's own windows by its executable name, which is
+/// also the window class GTK gives them on Linux.
+fn own_windows_rule() -> &'static WindowRule {
+    static RULE: OnceLock<WindowRule> = OnceLock::new();
+    RULE.get_or_init(|| WindowRule {
+        process_name: std::env::current_exe().ok().and_then(|exe| {
+            let name = if PLATFORM_HIDES_SELF { exe.file_name() } else { exe.file_stem() };
+            name.map(|name| name.to_string_lossy().to_string())
+        }),
+        title: None,
+    })
+}
+
+/// Identifies windows by application and title. Every field that is set must match.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowRule {
+    /// Process (or on Linux, window class) name, compared case-insensitively
+    #[serde(default)]
+    pub process_name: Option<String>,
+    /// Text contained in the window title, compared case-insensitively
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+impl WindowRule {
+    /// Whether the rule matches `window`.
+    pub fn matches(&self, window: &WindowInfo) -> bool {
+        if self.process_name.is_none() && self.title.is_none() {
+            return false;
+        }
+        let process_matches = self
+            .process_name
+            .as_ref()
+            .is_none_or(|name| window.process_name.eq_ignore_ascii_case(name));
+        let title_matches = self
+            .title
+            .as_ref()
+            .is_none_or(|title| window.title.to_lowercase().contains(&title.to_lowercase()));
+        process_matches && title_matches
+    }
+}
+
+/// Persisted exclusion settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExclusionSettings {
+    pub rules: Vec<WindowRule>,
+    /// How excluded windows are hidden
+    #[serde(default)]
+    pub style: MaskStyle,
+    /// Keep the recorder's own windows out of recordings
+    #[serde(default = "default_exclude_self")]
+    pub exclude_self: bool,
+}
+
+fn default_exclude_self() -> bool {
+    true
+}
+
+impl Default for ExclusionSettings {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            style: MaskStyle::default(),
+            exclude_self: default_exclude_self(),
+        }
+    }
+}
+
+impl ExclusionSettings {
+    /// Whether any windows need masking.
+    pub fn masks_windows(&self) -> bool {
+        !self.rules.is_empty() || (self.exclude_self && !PLATFORM_HIDES_SELF)
+    }
+
+    /// Whether `window` is masked: it matches a rule, or it's one of the
+    /// recorder's own windows and the platform doesn't hide those.
+    pub fn masks(&self, window: &WindowInfo) -> bool {
+        self.rules.iter().any(|rule| rule.matches(window))
+            || (self.exclude_self && !PLATFORM_HIDES_SELF && own_windows_rule().matches(window))
+    }
+}
+
+/// The window exclusion list, shared with running captures so changes apply immediately.
+#[derive(Clone)]
+pub struct WindowExclusions {
+    settings: Arc<RwLock<ExclusionSettings>>,
+}
+
+impl WindowExclusions {
+    /// Load the exclusion list from disk.
+    pub fn load() -> Self {
+        let settings: ExclusionSettings = load_json(EXCLUSIONS_FILE);
+        EXCLUDE_SELF.store(settings.exclude_self, Ordering::Relaxed);
+        Self {
+            settings: Arc::new(RwLock::new(settings)),
+        }
+    }
+
+    /// The current settings.
+    pub fn get(&self) -> ExclusionSettings {
+        self.settings.read().unwrap().clone()
+    }
+
+    /// Change the settings with `update` and save them.
+    fn update(&self, update: impl FnOnce(&mut ExclusionSettings)) -> Result<ExclusionSettings, String> {
+        let mut settings = self.settings.write().unwrap();
+        update(&mut settings);
+        save_json(EXCLUSIONS_FILE, &*settings)?;
+        Ok(settings.clone())
+    }
+
+    /// Add a rule, ignoring duplicates.
+    pub fn add(&self, rule: WindowRule) -> Result<ExclusionSettings, String> {
+        if rule.process_name.is_none() && rule.title.is_none() {
+            return Err("An exclusion needs a process name or title".to_string());
+        }
+        self.update(|settings| {
+            if !settings.rules.contains(&rule) {
+                settings.rules.push(rule);
+            }
+        })
+    }
+
+    /// Remove a rule.
+    pub fn remove(&self, rule: &WindowRule) -> Result<ExclusionSettings, String> {
+        self.update(|settings| settings.rules.retain(|r| r != rule))
+    }
+
+    /// Set how excluded windows are hidden.
+    pub fn set_style(&self, style: MaskStyle) -> Result<ExclusionSettings, String> {
+        self.update(|settings| settings.style = style)
+    }
+
+    /// Set whether the recorder's own windows are kept out of recordings.
+    ///
+    /// The caller applies it to the open windows with [`hide_from_capture`].
+    pub fn set_exclude_self(&self, exclude: bool) -> Result<ExclusionSettings, String> {
+        EXCLUDE_SELF.store(exclude, Ordering::Relaxed);
+        self.update(|settings| settings.exclude_self = exclude)
+    }
+}
+
+/// Hide a window of the recorder's, by its native handle, from captures, or
+/// show it again. Does nothing where the platform can't, and its windows are
+/// masked instead.
+#[cfg_attr(not(target_os = "windows"), allow(unused_variables, dead_code))]
+pub fn hide_from_capture(hwnd: isize, hidden: bool) {
+    #[cfg(target_os = "windows")]
+    {
+        use windows::Win32::Foundation::HWND;
+        use windows::Win32::UI::WindowsAndMessaging::{SetWindowDisplayAffinity, WDA_EXCLUDEFROMCAPTURE, WDA_NONE};
+
+        let affinity = if hidden { WDA_EXCLUDEFROMCAPTURE } else { WDA_NONE };
+        // Needs Windows 10 2004; earlier versions reject it
+        if let Err(e) = unsafe { SetWindowDisplayAffinity(HWND(hwnd as *mut std::ffi::c_void), affinity) } {
+            tracing::error!(target: "Exclusion", "Failed to set window display affinity: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(title: &str, process_name: &str) -> WindowInfo {
+        WindowInfo {
+            handle: 1,
+            title: title.to_string(),
+            process_name: process_name.to_string(),
+            pid: 0,
+            class: String::new(),
+            icon: None,
+            thumbnail: None,
+        }
+    }
+
+    #[test]
+    fn test_rule_matches() {
+        let rule = WindowRule {
+            process_name: Some("KeePassXC.exe".to_string()),
+            title: None,
+        };
+        assert!(rule.matches(&window("Passwords - KeePassXC", "keepassxc.exe")));
+        assert!(!rule.matches(&window("Slack", "slack.exe")));
+
+        let rule = WindowRule {
+            process_name: None,
+            title: Some("slack".to_string()),
+        };
+        assert!(rule.matches(&window("Slack | general", "slack.exe")));
+    }
+
+    #[test]
+    fn test_masks_own_windows() {
+        let own = own_windows_rule().process_name.clone().unwrap();
+        let settings = ExclusionSettings::default();
+        assert_eq!(settings.masks(&window("Screen Recorder", &own)), !PLATFORM_HIDES_SELF);
+        assert!(!settings.masks(&window("Slack", "slack")));
+
+        // Settings saved before the option existed keep the default
+        let settings: ExclusionSettings = serde_json::from_str(r#"{"rules":[]}"#).unwrap();
+        assert!(settings.exclude_self);
+        let settings = ExclusionSettings { exclude_self: false, ..settings };
+        assert!(!settings.masks_windows());
+        assert!(!settings.masks(&window("Screen Recorder", &own)));
+    }
+}
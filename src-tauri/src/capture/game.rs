@@ -0,0 +1,56 @@
+//! Choosing game capture for window sources.
+//!
+//! Windows.Graphics.Capture misses some games running in exclusive
+//! fullscreen, which present straight to the display. Windows of
+//! applications with a game capture rule are instead recorded by duplicating
+//! the monitor they are on, cropped to the window, which also sees exclusive
+//! fullscreen output. Only Windows supports game capture (see
+//! [`Capabilities::game_capture`](super::Capabilities)).
+
+use super::exclusion::WindowRule;
+use super::types::WindowInfo;
+use crate::config::{load_json, save_json};
+use serde::{Deserialize, Serialize};
+
+const GAME_CAPTURE_FILE: &str = "game_capture.json";
+
+/// Persisted game capture settings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GameCaptureSettings {
+    /// Windows recorded with game capture
+    pub rules: Vec<WindowRule>,
+}
+
+impl GameCaptureSettings {
+    /// Whether `window` should be recorded with game capture.
+    #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+    pub fn uses_game_capture(&self, window: &WindowInfo) -> bool {
+        self.rules.iter().any(|rule| rule.matches(window))
+    }
+}
+
+/// Load the game capture settings.
+pub fn settings() -> GameCaptureSettings {
+    load_json(GAME_CAPTURE_FILE)
+}
+
+/// Record windows matching `rule` with game capture, ignoring duplicates.
+pub fn add_rule(rule: WindowRule) -> Result<GameCaptureSettings, String> {
+    if rule.process_name.is_none() && rule.title.is_none() {
+        return Err("A game capture rule needs a process name or title".to_string());
+    }
+    let mut settings = settings();
+    if !settings.rules.contains(&rule) {
+        settings.rules.push(rule);
+    }
+    save_json(GAME_CAPTURE_FILE, &settings)?;
+    Ok(settings)
+}
+
+/// Stop recording windows matching `rule` with game capture.
+pub fn remove_rule(rule: &WindowRule) -> Result<GameCaptureSettings, String> {
+    let mut settings = settings();
+    settings.rules.retain(|r| r != rule);
+    save_json(GAME_CAPTURE_FILE, &settings)?;
+    Ok(settings)
+}
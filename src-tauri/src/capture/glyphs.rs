@@ -0,0 +1,112 @@
+//! Tiny bitmap font for text drawn into generated frames.
+//!
+//! Glyphs are 3x5 pixels with a 1-pixel gap between them, scaled up by an
+//! integer factor. Only digits and the letters the app needs are included;
+//! other characters are drawn as spaces. The color bars shared by the mock
+//! capture and the test signal live here too.
+
+use super::types::CapturedFrame;
+
+/// 75% color bars, left to right: white, yellow, cyan, green, magenta, red, blue (BGRA)
+pub(crate) const COLOR_BARS: [[u8; 4]; 7] = [
+    [191, 191, 191, 255],
+    [0, 191, 191, 255],
+    [191, 191, 0, 255],
+    [0, 191, 0, 255],
+    [191, 0, 191, 255],
+    [0, 0, 191, 255],
+    [191, 0, 0, 255],
+];
+
+/// Glyph rows, top to bottom, with the leftmost pixel in bit 2.
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' | 'O' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' | 'S' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'G' => [0b111, 0b100, 0b101, 0b101, 0b111],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        _ => [0; 5],
+    }
+}
+
+/// Width of `text` drawn at `scale`, in pixels.
+pub fn text_width(text: &str, scale: usize) -> usize {
+    (text.chars().count() * 4).saturating_sub(1) * scale
+}
+
+/// Height of text drawn at `scale`, in pixels.
+pub fn text_height(scale: usize) -> usize {
+    5 * scale
+}
+
+/// Fill `x0..x1`, `y0..y1` of `frame` with `pixel`, clipped to the frame.
+///
+/// `pixel` holds one pixel in the frame's format.
+pub fn fill_rect(frame: &mut CapturedFrame, x0: usize, y0: usize, x1: usize, y1: usize, pixel: &[u8]) {
+    let (width, height) = (frame.width as usize, frame.height as usize);
+    let bpp = pixel.len();
+    let (x1, y1) = (x1.min(width), y1.min(height));
+    if x0 >= x1 {
+        return;
+    }
+    for y in y0..y1 {
+        let row = &mut frame.data[(y * width + x0) * bpp..(y * width + x1) * bpp];
+        for target in row.chunks_exact_mut(bpp) {
+            target.copy_from_slice(pixel);
+        }
+    }
+}
+
+/// Draw `text` with its top-left corner at (`x`, `y`), clipped to the frame.
+pub fn draw_text(frame: &mut CapturedFrame, x: usize, y: usize, scale: usize, text: &str, pixel: &[u8]) {
+    for (i, c) in text.chars().enumerate() {
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..3 {
+                if bits & (0b100 >> col) != 0 {
+                    let left = x + (i * 4 + col) * scale;
+                    let top = y + row * scale;
+                    fill_rect(frame, left, top, left + scale, top + scale, pixel);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::types::{ColorSpace, PixelFormat};
+
+    #[test]
+    fn test_draw_text() {
+        let mut frame = CapturedFrame {
+            width: 8,
+            height: 5,
+            data: vec![0; 8 * 5 * 4],
+            format: PixelFormat::Bgra8,
+            color: ColorSpace::SRGB,
+            captured_at: std::time::Instant::now(),
+            presented_at: None,
+        };
+        draw_text(&mut frame, 0, 0, 1, "1L", &[1, 1, 1, 1]);
+        let rows: Vec<String> = frame
+            .data
+            .chunks(8 * 4)
+            .map(|row| row.chunks(4).map(|p| if p[0] == 1 { '#' } else { '.' }).collect())
+            .collect();
+        assert_eq!(rows, [".#..#...", "##..#...", ".#..#...", ".#..#...", "###.###."]);
+        assert_eq!(text_width("1L", 1), 7);
+    }
+}
@@ -0,0 +1,208 @@
+//! HDR capture and tone mapping.
+//!
+//! HDR displays are captured as half-float scRGB: linear light with BT.709
+//! primaries where 1.0 is 80 nits and highlights go well above it. Encoding
+//! those values as if they were sRGB is what makes HDR recordings look
+//! washed out or blown out, so frames are tone mapped to 8-bit sRGB unless
+//! the recording keeps HDR end-to-end.
+
+use super::types::{CapturedFrame, PixelFormat};
+use serde::{Deserialize, Serialize};
+
+/// Luminance of scRGB 1.0 in nits.
+pub const SCRGB_WHITE_NITS: f32 = 80.0;
+
+/// SDR white level used when the display's setting can't be read.
+pub const DEFAULT_SDR_WHITE_NITS: f32 = 200.0;
+
+/// Fraction of SDR white below which content is passed through unchanged.
+const KNEE: f32 = 0.8;
+
+/// How frames from HDR displays are recorded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HdrMode {
+    /// Tone map to SDR so the recording looks right everywhere
+    #[default]
+    ToneMap,
+    /// Keep HDR and encode HDR10 (HEVC and AV1 only)
+    Passthrough,
+}
+
+/// Converts scRGB half-float frames to 8-bit or 10-bit sRGB.
+pub struct ToneMapper {
+    /// sRGB output for every half-float bit pattern
+    lut: Vec<u16>,
+    /// Bgra8 or X2Rgb10
+    output: PixelFormat,
+}
+
+impl ToneMapper {
+    /// Create a tone mapper for a display whose SDR content is shown at
+    /// `sdr_white_nits`. SDR content keeps its brightness; brighter HDR
+    /// highlights are compressed into the remaining headroom.
+    ///
+    /// With `ten_bit` set, frames are converted to packed 10-bit RGB instead
+    /// of 8-bit BGRA.
+    pub fn new(sdr_white_nits: f32, ten_bit: bool) -> Self {
+        let (output, max) = if ten_bit {
+            (PixelFormat::X2Rgb10, 1023.0)
+        } else {
+            (PixelFormat::Bgra8, 255.0)
+        };
+        let scale = SCRGB_WHITE_NITS / sdr_white_nits.max(1.0);
+        let lut = (0..=u16::MAX)
+            .map(|bits| {
+                let value = f16_to_f32(bits);
+                // Negative values are out-of-gamut colors; NaN is garbage
+                if value.is_nan() || value <= 0.0 {
+                    return 0;
+                }
+                let encoded = srgb_encode(compress(value * scale));
+                (encoded * max).round() as u16
+            })
+            .collect();
+        Self { lut, output }
+    }
+
+    /// Tone map `frame` to the output format. Frames that aren't HDR are returned as is.
+    #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+    pub fn map(&self, frame: CapturedFrame) -> CapturedFrame {
+        if frame.format != PixelFormat::Rgba16F {
+            return frame;
+        }
+        CapturedFrame {
+            width: frame.width,
+            height: frame.height,
+            data: self.map_pixels(&frame.data),
+            format: self.output,
+            color: frame.color,
+            captured_at: frame.captured_at,
+            presented_at: frame.presented_at,
+        }
+    }
+
+    /// Convert tightly packed RGBA half-float pixels to the output format.
+    pub fn map_pixels(&self, data: &[u8]) -> Vec<u8> {
+        let channel = |pixel: &[u8], i: usize| self.lut[u16::from_le_bytes([pixel[i * 2], pixel[i * 2 + 1]]) as usize];
+        let mut output = Vec::with_capacity(data.len() / 2);
+        for pixel in data.chunks_exact(8) {
+            let (r, g, b) = (channel(pixel, 0), channel(pixel, 1), channel(pixel, 2));
+            if self.output == PixelFormat::X2Rgb10 {
+                let word = (3 << 30) | (r as u32) << 20 | (g as u32) << 10 | b as u32;
+                output.extend_from_slice(&word.to_le_bytes());
+            } else {
+                output.extend_from_slice(&[b as u8, g as u8, r as u8, 255]);
+            }
+        }
+        output
+    }
+}
+
+impl Default for ToneMapper {
+    fn default() -> Self {
+        Self::new(DEFAULT_SDR_WHITE_NITS, false)
+    }
+}
+
+/// Map linear light relative to SDR white into 0..1, passing values below
+/// the knee through and rolling everything above it off smoothly.
+fn compress(x: f32) -> f32 {
+    if x <= KNEE {
+        return x;
+    }
+    let t = (x - KNEE) / (1.0 - KNEE);
+    KNEE + (1.0 - KNEE) * t / (1.0 + t)
+}
+
+/// sRGB transfer function.
+fn srgb_encode(x: f32) -> f32 {
+    if x <= 0.003_130_8 {
+        x * 12.92
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Convert IEEE 754 half-precision bits to `f32`.
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::types::ColorSpace;
+
+    #[test]
+    fn test_f16_to_f32() {
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x3555), 0.333_251_95);
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+        assert!(f16_to_f32(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn test_tone_map() {
+        let mapper = ToneMapper::new(SCRGB_WHITE_NITS, false);
+        // Red at SDR white, green at half of it, blue at twice it, alpha 1.0
+        let pixel: Vec<u8> = [0x3c00u16, 0x3800, 0x4000, 0x3c00]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let bgra = mapper.map_pixels(&pixel);
+        assert_eq!(bgra[2], 243);
+        assert_eq!(bgra[1], 188);
+        assert!(bgra[0] > bgra[2] && bgra[0] < 255);
+        assert_eq!(bgra[3], 255);
+
+        let packed = ToneMapper::new(SCRGB_WHITE_NITS, true).map_pixels(&pixel);
+        let word = u32::from_le_bytes([packed[0], packed[1], packed[2], packed[3]]);
+        assert_eq!(word >> 20 & 0x3ff, 977);
+    }
+
+    #[test]
+    fn test_tone_map_golden() {
+        // Bands of red, green, blue and white, rising from 0.25 to ~7.7x SDR white
+        let (width, height) = (64u32, 16u32);
+        let mut data = Vec::new();
+        for y in 0..height {
+            for x in 0..width as u16 {
+                let value = 0x3400 + x * 0x50;
+                let rgb = match y / 4 {
+                    0 => [value, 0, 0],
+                    1 => [0, value, 0],
+                    2 => [0, 0, value],
+                    _ => [value; 3],
+                };
+                for channel in rgb.into_iter().chain([0x3c00]) {
+                    data.extend_from_slice(&channel.to_le_bytes());
+                }
+            }
+        }
+        let frame = CapturedFrame {
+            width,
+            height,
+            data,
+            format: PixelFormat::Rgba16F,
+            color: ColorSpace::SRGB,
+            captured_at: std::time::Instant::now(),
+            presented_at: None,
+        };
+
+        let sdr = ToneMapper::default().map(frame.clone());
+        crate::golden::assert_golden("tone_map_sdr", &sdr, 1);
+
+        let packed = ToneMapper::new(DEFAULT_SDR_WHITE_NITS, true).map(frame);
+        crate::golden::assert_golden("tone_map_10bit", &packed.packed_10bit_to_bgra8().unwrap(), 1);
+    }
+}
@@ -0,0 +1,109 @@
+//! Look and timing shared by the platforms' display highlight overlays.
+//!
+//! A highlight fades in, pulses for a few seconds and fades out by itself.
+//! Showing another highlight or calling [`hide`] fades out the one showing
+//! early. Overlays poll [`Animation::next_alpha`] once per frame.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Border thickness in logical pixels.
+pub const BORDER_WIDTH: i32 = 8;
+/// #2196F3
+pub const COLOR: [u8; 3] = [0x21, 0x96, 0xF3];
+/// Time between animation frames (~60fps).
+pub const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+const FADE_IN: Duration = Duration::from_millis(150);
+const FADE_OUT: Duration = Duration::from_millis(250);
+const PULSE_PERIOD: Duration = Duration::from_millis(1000);
+/// Lowest opacity of a pulse, as a fraction of full opacity.
+const PULSE_LOW: f32 = 0.55;
+/// How long a highlight shows before fading out by itself.
+const DISMISS_AFTER: Duration = Duration::from_millis(2500);
+
+/// Incremented for each highlight shown and each [`hide`], so the highlight
+/// showing can tell it has been replaced or hidden.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// The animation of one highlight.
+pub struct Animation {
+    generation: u64,
+    started: Instant,
+    /// When the highlight started fading out
+    dismissed: Option<Instant>,
+}
+
+impl Animation {
+    /// Start animating a new highlight, dismissing any other.
+    pub fn start() -> Self {
+        Self {
+            generation: GENERATION.fetch_add(1, Ordering::SeqCst) + 1,
+            started: Instant::now(),
+            dismissed: None,
+        }
+    }
+
+    /// Opacity of the border for the next frame, or `None` once the
+    /// highlight should close.
+    pub fn next_alpha(&mut self) -> Option<u8> {
+        let now = Instant::now();
+        let elapsed = now - self.started;
+        let replaced = GENERATION.load(Ordering::SeqCst) != self.generation;
+        if self.dismissed.is_none() && (replaced || elapsed >= DISMISS_AFTER) {
+            self.dismissed = Some(now);
+        }
+        alpha_at(elapsed, self.dismissed.map(|at| now - at))
+    }
+}
+
+/// Fade out the highlight showing, if any.
+pub fn hide() {
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Opacity `elapsed` into a highlight that has been fading out for
+/// `fading`, or `None` once it has faded out.
+fn alpha_at(elapsed: Duration, fading: Option<Duration>) -> Option<u8> {
+    let fade_out = match fading {
+        Some(fading) if fading >= FADE_OUT => return None,
+        Some(fading) => 1.0 - fading.as_secs_f32() / FADE_OUT.as_secs_f32(),
+        None => 1.0,
+    };
+    let fade_in = (elapsed.as_secs_f32() / FADE_IN.as_secs_f32()).min(1.0);
+    // Starts at full opacity, dipping to PULSE_LOW halfway through each period
+    let phase = elapsed.as_secs_f32() / PULSE_PERIOD.as_secs_f32() * std::f32::consts::TAU;
+    let pulse = PULSE_LOW + (1.0 - PULSE_LOW) * (0.5 + 0.5 * phase.cos());
+    Some((fade_in * pulse * fade_out * 255.0).round() as u8)
+}
+
+/// A border pixel at `alpha`, as premultiplied BGRA bytes.
+pub fn border_pixel(alpha: u8) -> [u8; 4] {
+    let premultiply = |c: u8| (c as u16 * alpha as u16 / 255) as u8;
+    let [r, g, b] = COLOR;
+    [premultiply(b), premultiply(g), premultiply(r), alpha]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alpha_at() {
+        assert_eq!(alpha_at(Duration::ZERO, None), Some(0));
+        assert_eq!(alpha_at(PULSE_PERIOD, None), Some(255));
+        assert_eq!(alpha_at(PULSE_PERIOD * 3 / 2, None), Some((PULSE_LOW * 255.0).round() as u8));
+        assert_eq!(alpha_at(PULSE_PERIOD, Some(FADE_OUT / 2)), Some(128));
+        assert_eq!(alpha_at(PULSE_PERIOD, Some(FADE_OUT)), None);
+        assert_eq!(border_pixel(255), [0xF3, 0x96, 0x21, 0xFF]);
+    }
+
+    #[test]
+    fn test_hide() {
+        let mut animation = Animation::start();
+        assert!(animation.dismissed.is_none() && animation.next_alpha().is_some());
+        hide();
+        animation.next_alpha();
+        assert!(animation.dismissed.is_some());
+    }
+}
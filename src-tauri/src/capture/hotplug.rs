@@ -0,0 +1,174 @@
+//! Monitor hotplug detection.
+//!
+//! [`watch_monitors`] reports monitors being connected, disconnected, or
+//! changing resolution, position or scale. On Hyprland the compositor's event
+//! socket wakes the watcher as soon as a monitor is added or removed; other
+//! changes, and other platforms, are picked up by polling.
+
+use super::types::MonitorInfo;
+use serde::Serialize;
+use std::time::Duration;
+
+/// How often the monitor list is checked when no event arrives.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How the monitor layout changed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MonitorChange {
+    pub added: Vec<MonitorInfo>,
+    /// Monitors that were disconnected, as they were before
+    pub removed: Vec<MonitorInfo>,
+    /// Monitors whose resolution, position, scale or primary status changed
+    pub changed: Vec<MonitorInfo>,
+    /// All monitors now connected
+    pub monitors: Vec<MonitorInfo>,
+}
+
+/// Call `on_change` from a background thread whenever the monitors change.
+pub fn watch_monitors(on_change: impl Fn(MonitorChange) + Send + 'static) {
+    std::thread::spawn(move || {
+        let mut events = EventSource::connect();
+        let mut monitors = super::list_monitors();
+        loop {
+            events.wait();
+            let current = super::list_monitors();
+            if let Some(change) = diff(&monitors, &current) {
+                tracing::info!(
+                    target: "Monitors",
+                    "{} added, {} removed, {} changed",
+                    change.added.len(),
+                    change.removed.len(),
+                    change.changed.len()
+                );
+                on_change(change);
+            }
+            monitors = current;
+        }
+    });
+}
+
+/// Compare two monitor lists, returning `None` if nothing changed.
+fn diff(old: &[MonitorInfo], new: &[MonitorInfo]) -> Option<MonitorChange> {
+    let find = |list: &[MonitorInfo], id: &str| list.iter().find(|m| m.id == id).cloned();
+    let change = MonitorChange {
+        added: new.iter().filter(|m| find(old, &m.id).is_none()).cloned().collect(),
+        removed: old.iter().filter(|m| find(new, &m.id).is_none()).cloned().collect(),
+        changed: new
+            .iter()
+            .filter(|m| find(old, &m.id).is_some_and(|before| before != **m))
+            .cloned()
+            .collect(),
+        monitors: new.to_vec(),
+    };
+    if change.added.is_empty() && change.removed.is_empty() && change.changed.is_empty() {
+        return None;
+    }
+    Some(change)
+}
+
+/// Wakes the watcher when the monitors may have changed.
+struct EventSource {
+    /// Hyprland's event socket, if connected
+    #[cfg(target_os = "linux")]
+    socket: Option<std::io::BufReader<std::os::unix::net::UnixStream>>,
+}
+
+impl EventSource {
+    #[cfg(target_os = "linux")]
+    fn connect() -> Self {
+        let socket = hyprland_event_socket().and_then(|path| {
+            let stream = std::os::unix::net::UnixStream::connect(path).ok()?;
+            stream.set_read_timeout(Some(POLL_INTERVAL)).ok()?;
+            Some(std::io::BufReader::new(stream))
+        });
+        if socket.is_none() {
+            tracing::warn!(target: "Monitors", "Hyprland event socket unavailable, polling for monitor changes");
+        }
+        Self { socket }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn connect() -> Self {
+        Self {}
+    }
+
+    /// Wait for a monitor event, or until it's time to poll.
+    #[cfg(target_os = "linux")]
+    fn wait(&mut self) {
+        use std::io::BufRead;
+
+        let Some(socket) = self.socket.as_mut() else {
+            std::thread::sleep(POLL_INTERVAL);
+            return;
+        };
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match socket.read_line(&mut line) {
+                // Events are `name>>data` lines
+                Ok(n) if n > 0 => {
+                    if line.starts_with("monitoradded") || line.starts_with("monitorremoved") {
+                        return;
+                    }
+                }
+                Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => return,
+                // The compositor went away; fall back to polling
+                _ => {
+                    self.socket = None;
+                    return;
+                }
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn wait(&mut self) {
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Path of Hyprland's event socket, if running under Hyprland.
+#[cfg(target_os = "linux")]
+fn hyprland_event_socket() -> Option<std::path::PathBuf> {
+    let signature = std::env::var("HYPRLAND_INSTANCE_SIGNATURE").ok()?;
+    let runtime_dir = std::env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| "/tmp".to_string());
+    // Hyprland moved its sockets from /tmp to the runtime directory in 0.40
+    [runtime_dir.as_str(), "/tmp"]
+        .iter()
+        .map(|dir| std::path::Path::new(dir).join("hypr").join(&signature).join(".socket2.sock"))
+        .find(|path| path.exists())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(id: &str, width: u32) -> MonitorInfo {
+        MonitorInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            x: 0,
+            y: 0,
+            width,
+            height: 1080,
+            is_primary: false,
+            scale_factor: 1.0,
+            model: None,
+            refresh_rate: None,
+            rotation: 0,
+        }
+    }
+
+    #[test]
+    fn test_diff_monitors() {
+        let old = [monitor("DP-1", 1920), monitor("HDMI-A-1", 1920)];
+        assert_eq!(diff(&old, &old), None);
+
+        let new = [monitor("DP-1", 2560), monitor("DP-2", 1920)];
+        let change = diff(&old, &new).unwrap();
+        assert_eq!(change.added, [monitor("DP-2", 1920)]);
+        assert_eq!(change.removed, [monitor("HDMI-A-1", 1920)]);
+        assert_eq!(change.changed, [monitor("DP-1", 2560)]);
+        assert_eq!(change.monitors.len(), 2);
+    }
+}
@@ -0,0 +1,48 @@
+//! Application icons for the source picker.
+//!
+//! Backends find the icon of each window's application however their
+//! platform allows and hand it here to be encoded like thumbnails, as a
+//! base64-encoded PNG.
+
+use super::thumbnail::png_base64;
+
+/// An icon from 8-bit BGRA pixels with straight alpha, rows packed.
+///
+/// Old-style icons leave the alpha channel empty and mask the shape
+/// separately; those are made opaque rather than invisible.
+#[cfg_attr(not(any(target_os = "windows", test)), allow(dead_code))]
+pub fn from_bgra(bgra: &[u8], width: u32, height: u32) -> Result<String, String> {
+    if width == 0 || height == 0 || bgra.len() < (width * height * 4) as usize {
+        return Err(format!("Invalid icon: {}x{}", width, height));
+    }
+    let bgra = &bgra[..(width * height * 4) as usize];
+    let has_alpha = bgra.chunks_exact(4).any(|pixel| pixel[3] != 0);
+    let rgba: Vec<u8> = bgra
+        .chunks_exact(4)
+        .flat_map(|pixel| [pixel[2], pixel[1], pixel[0], if has_alpha { pixel[3] } else { 255 }])
+        .collect();
+    png_base64(&rgba, width, height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+
+    #[test]
+    fn test_from_bgra() {
+        let decode = |encoded: String| {
+            let png = base64::engine::general_purpose::STANDARD.decode(encoded).unwrap();
+            let mut reader = png::Decoder::new(png.as_slice()).read_info().unwrap();
+            let mut rgba = vec![0u8; reader.output_buffer_size()];
+            reader.next_frame(&mut rgba).unwrap();
+            rgba
+        };
+        // Blue with half alpha, then transparent
+        let rgba = decode(from_bgra(&[255, 0, 0, 128, 0, 0, 0, 0], 2, 1).unwrap());
+        assert_eq!(rgba, vec![0, 0, 255, 128, 0, 0, 0, 0]);
+        // No alpha at all: opaque
+        let rgba = decode(from_bgra(&[0, 0, 255, 0, 0, 255, 0, 0], 2, 1).unwrap());
+        assert_eq!(rgba, vec![255, 0, 0, 255, 0, 255, 0, 255]);
+    }
+}
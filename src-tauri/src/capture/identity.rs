@@ -0,0 +1,124 @@
+//! Recognizing a window after its application recreates it.
+//!
+//! Electron apps, games and others close their window and open a new one
+//! on mode changes, like going fullscreen or switching renderers. The new
+//! window has a new handle, which ends a handle-based capture. A
+//! [`WindowIdentity`] remembers what the captured window looked like, so the
+//! capture can move over to the window that replaced it (see
+//! `recovery`). Only a window of the same application and class that
+//! wasn't already open can qualify, and it needs more than that in common:
+//! the same process, or a related title, or the same title. A tie between
+//! equally likely windows isn't guessed at, so another window never ends up
+//! recorded by mistake.
+
+use super::types::WindowInfo;
+use std::collections::HashSet;
+
+/// What identifies a captured window besides its handle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowIdentity {
+    pub handle: isize,
+    pub pid: u32,
+    pub process_name: String,
+    pub class: String,
+    pub title: String,
+    /// The other windows open at the time, which can't be replacements
+    pub others: HashSet<isize>,
+}
+
+/// Score a replacement needs beyond being of the same application and class.
+const MIN_SCORE: u32 = 3;
+
+impl WindowIdentity {
+    /// The identity of `window`, among the open `windows`.
+    pub fn of(window: &WindowInfo, windows: &[WindowInfo]) -> Self {
+        Self {
+            handle: window.handle,
+            pid: window.pid,
+            process_name: window.process_name.clone(),
+            class: window.class.clone(),
+            title: window.title.clone(),
+            others: windows.iter().map(|w| w.handle).filter(|&handle| handle != window.handle).collect(),
+        }
+    }
+
+    /// The window in `windows` that most likely replaced this one, if one
+    /// clearly did.
+    pub fn successor<'a>(&self, windows: &'a [WindowInfo]) -> Option<&'a WindowInfo> {
+        let mut candidates: Vec<(u32, &WindowInfo)> = windows
+            .iter()
+            .filter(|window| window.handle != self.handle && !self.others.contains(&window.handle))
+            .filter_map(|window| Some((self.score(window)?, window)))
+            .collect();
+        candidates.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+        match candidates.as_slice() {
+            [(best, window), rest @ ..] if rest.first().is_none_or(|(next, _)| next < best) => Some(*window),
+            [(best, _), ..] => {
+                tracing::info!(
+                    target: "Identity",
+                    "Several windows of {} could have replaced 0x{:x} (score {}); not following any",
+                    self.process_name, self.handle as usize, best
+                );
+                None
+            }
+            [] => None,
+        }
+    }
+
+    /// How alike `window` is to this one, or `None` if it can't be its
+    /// replacement.
+    fn score(&self, window: &WindowInfo) -> Option<u32> {
+        if !window.process_name.eq_ignore_ascii_case(&self.process_name) || window.class != self.class {
+            return None;
+        }
+        let mut score = 0;
+        if self.pid != 0 && window.pid == self.pid {
+            score += 4;
+        }
+        let (old, new) = (self.title.to_lowercase(), window.title.to_lowercase());
+        if old == new {
+            score += 3;
+        } else if !old.is_empty() && !new.is_empty() && (old.contains(&new) || new.contains(&old)) {
+            score += 1;
+        }
+        (score >= MIN_SCORE).then_some(score)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(handle: isize, pid: u32, class: &str, title: &str) -> WindowInfo {
+        WindowInfo {
+            handle,
+            title: title.to_string(),
+            process_name: "game.exe".to_string(),
+            pid,
+            class: class.to_string(),
+            icon: None,
+            thumbnail: None,
+        }
+    }
+
+    #[test]
+    fn test_successor() {
+        let captured = window(1, 100, "GameWindow", "Game");
+        let launcher = window(3, 100, "Launcher", "Game Launcher");
+        let editor = window(8, 100, "GameWindow", "Game Editor");
+        let identity = WindowIdentity::of(&captured, &[captured.clone(), editor.clone()]);
+        let successor = |windows: &[WindowInfo]| identity.successor(windows).map(|w| w.handle);
+        // Recreated in the same process, with a new title
+        let recreated = window(2, 100, "GameWindow", "Game - Fullscreen");
+        assert_eq!(successor(&[launcher.clone(), editor, recreated]), Some(2));
+        // Restarted, with a new process
+        assert_eq!(successor(&[window(4, 200, "GameWindow", "Game")]), Some(4));
+        // Another class, or only the application and class in common
+        assert_eq!(successor(&[launcher]), None);
+        assert_eq!(successor(&[window(5, 200, "GameWindow", "Settings")]), None);
+        // Two equally likely windows
+        assert_eq!(successor(&[window(6, 100, "GameWindow", "Map"), window(7, 100, "GameWindow", "Inventory")]), None);
+        // The window itself doesn't count
+        assert_eq!(successor(&[window(1, 100, "GameWindow", "Game")]), None);
+    }
+}
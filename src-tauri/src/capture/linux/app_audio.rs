@@ -0,0 +1,295 @@
+//! Recording one application's audio through a virtual sink.
+//!
+//! PipeWire has no per-process loopback like Windows does, so each recording
+//! gets a null sink of its own. The application's playback streams are moved
+//! into it and its monitor is recorded, while a loopback plays the sink on
+//! the default output so the user still hears the application. Notification
+//! sounds and everything else keep playing on the default output and stay out
+//! of the recording.
+//!
+//! The sink and loopback are modules of PipeWire's PulseAudio server
+//! (pipewire-pulse), created with `pactl` and recorded with `parec`. Streams
+//! the application opens after recording starts are moved in as they appear,
+//! and the modules are unloaded when it stops; ones left behind by a crash
+//! are unloaded before the next recording creates its own.
+
+use crate::avsync::SyncClock;
+use crate::capture::audio::{AudioRecording, LevelMeter, WavWriter, CHANNELS, SAMPLE_RATE};
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Prefix of the sinks created for recordings.
+pub const SINK_PREFIX: &str = "screen-recorder-app-";
+
+/// How often the application is checked for new playback streams.
+const RESCAN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether the PulseAudio tools are installed and a server answers them.
+pub fn available() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+    *AVAILABLE.get_or_init(|| pactl(&["info"]).is_ok() && which("parec"))
+}
+
+/// Start recording the audio of the process `pid` and its children into a
+/// WAV file at `path`, starting at the time `clock` started.
+pub fn start_app_audio_capture(pid: u32, path: PathBuf, clock: Arc<SyncClock>) -> Result<AudioRecording, String> {
+    if pid == 0 {
+        return Err("Failed to find the window's process".to_string());
+    }
+    if !available() {
+        return Err("Recording an application's audio needs pactl and parec (pulseaudio-utils)".to_string());
+    }
+    unload_stale_modules();
+
+    let sink = VirtualSink::create(pid)?;
+    let mut moved = HashSet::new();
+    move_streams(pid, &sink.name, &mut moved);
+    let recorder = Command::new("parec")
+        .arg(format!("--device={}.monitor", sink.name))
+        .args(["--format=s16le", "--latency-msec=20"])
+        .arg(format!("--rate={}", SAMPLE_RATE))
+        .arg(format!("--channels={}", CHANNELS))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to start parec: {}", e))?;
+
+    let meter = Arc::new(LevelMeter::default());
+    let writer = WavWriter::create(&path)?
+        .with_meter(meter.clone())
+        .with_pause(clock.pause().clone());
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    let thread_stop = stop_flag.clone();
+    let thread = std::thread::spawn(move || {
+        let result = record(pid, sink, recorder, moved, writer, &thread_stop, &clock);
+        if let Err(ref e) = result {
+            tracing::error!(target: "Audio", "Capture failed: {}", e);
+        }
+        result
+    });
+    tracing::info!(target: "Audio", "Capturing audio of process {}", pid);
+    // Once parec and the loopback are connected, log how it's all wired
+    std::thread::spawn(|| {
+        std::thread::sleep(Duration::from_secs(1));
+        super::audio_graph::log_snapshot("Audio graph after starting capture");
+    });
+    Ok(AudioRecording::new(path, stop_flag, thread, meter))
+}
+
+/// Copy the sink's audio into `writer` until `stop_flag` is set, moving
+/// streams the application opens into the sink.
+fn record(
+    pid: u32,
+    sink: VirtualSink,
+    mut recorder: Child,
+    mut moved: HashSet<u32>,
+    mut writer: WavWriter,
+    stop_flag: &AtomicBool,
+    clock: &SyncClock,
+) -> Result<(), String> {
+    // Start in step with the video, which started with the clock
+    writer.write_silence((clock.now().as_secs_f64() * SAMPLE_RATE as f64) as u64)?;
+
+    let mut output = recorder.stdout.take().ok_or("parec has no output")?;
+    let mut buffer = [0u8; 4096];
+    let mut rescan_at = Instant::now() + RESCAN_INTERVAL;
+    let result = loop {
+        if stop_flag.load(Ordering::Relaxed) {
+            break Ok(());
+        }
+        // The monitor delivers silence while the application is quiet, so
+        // reads return every few milliseconds
+        match output.read(&mut buffer) {
+            Ok(0) => break Err("parec stopped".to_string()),
+            Ok(read) => writer.write(&buffer[..read])?,
+            Err(e) => break Err(format!("Failed to read from parec: {}", e)),
+        }
+        if Instant::now() >= rescan_at {
+            rescan_at = Instant::now() + RESCAN_INTERVAL;
+            move_streams(pid, &sink.name, &mut moved);
+        }
+    };
+
+    let _ = recorder.kill();
+    let _ = recorder.wait();
+    drop(sink);
+    result.and(writer.finish())
+}
+
+/// A null sink for one recording, played back on the default output.
+/// Unloaded when dropped, which returns its streams to the default output.
+struct VirtualSink {
+    name: String,
+    modules: Vec<u32>,
+}
+
+impl VirtualSink {
+    fn create(pid: u32) -> Result<Self, String> {
+        let name = format!("{}{}", SINK_PREFIX, pid);
+        let mut sink = VirtualSink { name, modules: Vec::new() };
+        let description = format!("device.description=\"Screen Recorder ({})\"", pid);
+        sink.modules.push(load_module(&[
+            "module-null-sink",
+            &format!("sink_name={}", sink.name),
+            &format!("sink_properties='{}'", description),
+            &format!("rate={}", SAMPLE_RATE),
+            &format!("channels={}", CHANNELS),
+        ])?);
+        sink.modules.push(load_module(&[
+            "module-loopback",
+            &format!("source={}.monitor", sink.name),
+            "latency_msec=20",
+            "source_dont_move=true",
+        ])?);
+        Ok(sink)
+    }
+}
+
+impl Drop for VirtualSink {
+    fn drop(&mut self) {
+        // Loopback first, so it isn't moved to another source on the way out
+        for module in self.modules.iter().rev() {
+            if let Err(e) = pactl(&["unload-module", &module.to_string()]) {
+                tracing::error!(target: "Audio", "Failed to unload module {}: {}", module, e);
+            }
+        }
+    }
+}
+
+/// Load a module, returning its index.
+fn load_module(args: &[&str]) -> Result<u32, String> {
+    let mut command = vec!["load-module"];
+    command.extend_from_slice(args);
+    let output = pactl(&command)?;
+    output
+        .trim()
+        .parse()
+        .map_err(|_| format!("Unexpected answer loading {}: {}", args[0], output.trim()))
+}
+
+/// Unload sinks and loopbacks earlier runs didn't get to.
+fn unload_stale_modules() {
+    let Ok(modules) = pactl(&["list", "short", "modules"]) else {
+        return;
+    };
+    for line in modules.lines().filter(|line| line.contains(SINK_PREFIX)) {
+        if let Some(index) = line.split_whitespace().next() {
+            tracing::info!(target: "Audio", "Unloading leftover module {}", index);
+            let _ = pactl(&["unload-module", index]);
+        }
+    }
+}
+
+/// Move the playback streams of `pid` and its children that aren't in
+/// `moved` yet into `sink`.
+fn move_streams(pid: u32, sink: &str, moved: &mut HashSet<u32>) {
+    let streams = match pactl(&["list", "sink-inputs"]) {
+        Ok(listing) => parse_sink_inputs(&listing),
+        Err(e) => {
+            tracing::error!(target: "Audio", "Failed to list playback streams: {}", e);
+            return;
+        }
+    };
+    let processes = process_tree(pid);
+    for (index, stream_pid) in streams {
+        if moved.contains(&index) || !processes.contains(&stream_pid) {
+            continue;
+        }
+        match pactl(&["move-sink-input", &index.to_string(), sink]) {
+            Ok(_) => {
+                tracing::info!(target: "Audio", "Recording playback stream {} of process {}", index, stream_pid);
+                moved.insert(index);
+            }
+            Err(e) => tracing::error!(target: "Audio", "Failed to move playback stream {}: {}", index, e),
+        }
+    }
+}
+
+/// The index and process of each stream in `pactl list sink-inputs` output.
+fn parse_sink_inputs(listing: &str) -> Vec<(u32, u32)> {
+    let mut streams = Vec::new();
+    let mut index = None;
+    for line in listing.lines().map(str::trim) {
+        if let Some(number) = line.strip_prefix("Sink Input #") {
+            index = number.parse().ok();
+        } else if let Some(value) = line.strip_prefix("application.process.id = ") {
+            let pid = value.trim_matches('"').parse().ok();
+            if let (Some(index), Some(pid)) = (index.take(), pid) {
+                streams.push((index, pid));
+            }
+        }
+    }
+    streams
+}
+
+/// `pid` and its descendants, which play the audio of browsers and other
+/// multi-process applications.
+fn process_tree(pid: u32) -> HashSet<u32> {
+    let parents: Vec<(u32, u32)> = std::fs::read_dir("/proc")
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter_map(|entry| {
+                    let child: u32 = entry.file_name().to_str()?.parse().ok()?;
+                    let stat = std::fs::read_to_string(entry.path().join("stat")).ok()?;
+                    Some((child, parent_pid(&stat)?))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut tree = HashSet::from([pid]);
+    loop {
+        let before = tree.len();
+        for &(child, parent) in &parents {
+            if tree.contains(&parent) {
+                tree.insert(child);
+            }
+        }
+        if tree.len() == before {
+            return tree;
+        }
+    }
+}
+
+/// The parent process ID in the contents of `/proc/<pid>/stat`.
+fn parent_pid(stat: &str) -> Option<u32> {
+    // The command name in parentheses may contain spaces, so fields are
+    // counted from its end: state, then parent
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Run `pactl` and return what it printed.
+fn pactl(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("pactl")
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run pactl: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Whether `program` is in PATH.
+fn which(program: &str) -> bool {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    std::env::split_paths(&path).any(|dir| dir.join(program).is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sink_inputs() {
+        let listing = "Sink Input #42\n\tDriver: PipeWire\n\tSink: 57\n\tProperties:\n\t\tapplication.name = \"Firefox\"\n\t\tapplication.process.id = \"1234\"\n\nSink Input #43\n\tProperties:\n\t\tmedia.name = \"no process\"\n\nSink Input #44\n\tProperties:\n\t\tapplication.process.id = \"99\"\n";
+        assert_eq!(parse_sink_inputs(listing), vec![(42, 1234), (44, 99)]);
+        assert_eq!(parent_pid("4321 (Web Content (x)) S 1234 4321 4321 0"), Some(1234));
+    }
+}
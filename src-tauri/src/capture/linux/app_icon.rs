@@ -0,0 +1,129 @@
+//! Application icons from desktop entries.
+//!
+//! Wayland has no way to ask a window for its icon, so the window class
+//! (the app ID) is matched to the application's desktop entry, whose `Icon`
+//! is then looked up in the hicolor theme every icon theme falls back to.
+//! Only PNG icons are used; apps that ship just an SVG go without.
+
+use base64::Engine;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Icon sizes to look for, best first.
+const ICON_SIZES: [u32; 7] = [48, 64, 32, 128, 96, 256, 24];
+
+/// Icons already looked up, by window class.
+static ICONS: Lazy<Mutex<HashMap<String, Option<String>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// The icon of the application with window class `class`, as a
+/// base64-encoded PNG.
+pub fn icon_for(class: &str) -> Option<String> {
+    if class.is_empty() {
+        return None;
+    }
+    let mut icons = ICONS.lock().unwrap();
+    icons
+        .entry(class.to_string())
+        .or_insert_with(|| {
+            let dirs = data_dirs();
+            let name = find_icon_name(&dirs, class)?;
+            let png = std::fs::read(find_icon_file(&dirs, &name)?).ok()?;
+            png.starts_with(b"\x89PNG")
+                .then(|| base64::engine::general_purpose::STANDARD.encode(png))
+        })
+        .clone()
+}
+
+/// XDG data directories, most important first.
+fn data_dirs() -> Vec<PathBuf> {
+    let home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")));
+    let system = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    home.into_iter()
+        .chain(system.split(':').filter(|dir| !dir.is_empty()).map(PathBuf::from))
+        .collect()
+}
+
+/// The `Icon` of the desktop entry for `class`: the one named after it,
+/// else the one whose `StartupWMClass` is it.
+fn find_icon_name(dirs: &[PathBuf], class: &str) -> Option<String> {
+    let read = |path: &Path| std::fs::read_to_string(path).ok().map(|contents| desktop_entry(&contents));
+    let applications = || dirs.iter().map(|dir| dir.join("applications"));
+
+    for dir in applications() {
+        for file_name in [format!("{}.desktop", class), format!("{}.desktop", class.to_lowercase())] {
+            if let Some((Some(icon), _)) = read(&dir.join(file_name)) {
+                return Some(icon);
+            }
+        }
+    }
+    applications()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "desktop"))
+        .filter_map(|entry| read(&entry.path()))
+        .find_map(|(icon, wm_class)| wm_class.filter(|wm| wm.eq_ignore_ascii_case(class)).and(icon))
+}
+
+/// A PNG file for the icon called `name`, or at that path.
+fn find_icon_file(dirs: &[PathBuf], name: &str) -> Option<PathBuf> {
+    if name.starts_with('/') {
+        return Some(PathBuf::from(name)).filter(|path| path.is_file());
+    }
+    let file_name = format!("{}.png", name);
+    ICON_SIZES
+        .iter()
+        .flat_map(|size| {
+            dirs.iter()
+                .map(move |dir| dir.join(format!("icons/hicolor/{0}x{0}/apps", size)))
+        })
+        .chain(dirs.iter().map(|dir| dir.join("pixmaps")))
+        .map(|dir| dir.join(&file_name))
+        .find(|path| path.is_file())
+}
+
+/// `Icon` and `StartupWMClass` of a desktop entry file's main group.
+fn desktop_entry(contents: &str) -> (Option<String>, Option<String>) {
+    let mut in_entry = false;
+    let (mut icon, mut wm_class) = (None, None);
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_entry = line == "[Desktop Entry]";
+            continue;
+        }
+        if !in_entry {
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            let value = Some(value.trim().to_string()).filter(|value| !value.is_empty());
+            match key.trim() {
+                "Icon" => icon = value,
+                "StartupWMClass" => wm_class = value,
+                _ => {}
+            }
+        }
+    }
+    (icon, wm_class)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_desktop_entry() {
+        let contents = "[Desktop Entry]\nName=Editor\nIcon=text-editor\nStartupWMClass=Gedit\n\n[Desktop Action new]\nIcon=document-new\n";
+        assert_eq!(
+            desktop_entry(contents),
+            (Some("text-editor".to_string()), Some("Gedit".to_string()))
+        );
+        assert_eq!(desktop_entry("# No entry\nIcon=foo\n"), (None, None));
+    }
+}
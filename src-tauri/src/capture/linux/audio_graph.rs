@@ -0,0 +1,282 @@
+//! Snapshots of the PipeWire audio graph, for debugging audio capture.
+//!
+//! Reports of out-of-sync or wrong audio usually come down to how PipeWire
+//! wired the recording up: which application streams ended up in the
+//! recording's virtual sink, what the loopback plays on, and where `parec`
+//! and the microphone reader are connected. A snapshot lists every audio
+//! node and the links between them, read with `pw-dump`, marking the nodes
+//! the app made or owns: the virtual sinks (see [`super::app_audio`]),
+//! anything pointed at them, and streams of this process and its children.
+//! Its summary, one line per connection of those nodes, is written to the
+//! log so it ends up in diagnostics bundles, which also get the nodes
+//! themselves, but not the rest of the graph.
+
+use super::app_audio::SINK_PREFIX;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::process::Command;
+
+/// An audio node, as PipeWire describes it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AudioNode {
+    pub id: u32,
+    pub name: String,
+    pub description: Option<String>,
+    /// Such as `Audio/Sink` or `Stream/Output/Audio`
+    pub media_class: String,
+    pub application: Option<String>,
+    pub pid: Option<u32>,
+    /// `running`, `idle`, `suspended` or `error`
+    pub state: Option<String>,
+    /// Made or owned by this app
+    pub ours: bool,
+}
+
+/// A link from one node's output port to another's input port.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AudioLink {
+    pub output_node: u32,
+    pub output_port: Option<String>,
+    pub input_node: u32,
+    pub input_port: Option<String>,
+    pub state: Option<String>,
+}
+
+/// The audio part of the PipeWire graph at one moment.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AudioGraph {
+    pub nodes: Vec<AudioNode>,
+    pub links: Vec<AudioLink>,
+}
+
+impl AudioGraph {
+    /// One line per connection to or from a node of ours, such as
+    /// `#52 Firefox (Stream/Output/Audio, Firefox) -> #48 screen-recorder-app-1234 (Audio/Sink) [2 ports, active]`.
+    pub fn summary(&self) -> Vec<String> {
+        let nodes: BTreeMap<u32, &AudioNode> = self.nodes.iter().map(|node| (node.id, node)).collect();
+        let describe = |id: u32| match nodes.get(&id) {
+            Some(node) => {
+                let mut about = node.media_class.clone();
+                if let Some(application) = &node.application {
+                    about.push_str(&format!(", {}", application));
+                }
+                if let Some(pid) = node.pid {
+                    about.push_str(&format!(", pid {}", pid));
+                }
+                format!("#{} {} ({})", id, node.name, about)
+            }
+            None => format!("#{}", id),
+        };
+        let ours = |id: u32| nodes.get(&id).is_some_and(|node| node.ours);
+
+        // Links join ports, so one connection between two nodes is usually
+        // a link per channel
+        let mut connections: BTreeMap<(u32, u32), (usize, HashSet<&str>)> = BTreeMap::new();
+        for link in self.links.iter().filter(|link| ours(link.output_node) || ours(link.input_node)) {
+            let (ports, states) = connections.entry((link.output_node, link.input_node)).or_default();
+            *ports += 1;
+            states.insert(link.state.as_deref().unwrap_or("unknown"));
+        }
+        let mut lines: Vec<String> = connections
+            .into_iter()
+            .map(|((from, to), (ports, states))| {
+                let mut states: Vec<&str> = states.into_iter().collect();
+                states.sort();
+                let ports = if ports == 1 { "1 port".to_string() } else { format!("{} ports", ports) };
+                format!("{} -> {} [{}, {}]", describe(from), describe(to), ports, states.join("/"))
+            })
+            .collect();
+        // Ours but connected to nothing is worth knowing too
+        for node in self.nodes.iter().filter(|node| node.ours) {
+            let linked = self.links.iter().any(|link| link.output_node == node.id || link.input_node == node.id);
+            if !linked {
+                lines.push(format!("{} is not connected", describe(node.id)));
+            }
+        }
+        lines
+    }
+
+    /// Only our nodes, the nodes linked to them and the links between,
+    /// for sharing in a diagnostics bundle. Other nodes keep no
+    /// description, which for a stream is often the title of what it plays.
+    pub fn ours_and_neighbors(&self) -> AudioGraph {
+        let ours: HashSet<u32> = self.nodes.iter().filter(|node| node.ours).map(|node| node.id).collect();
+        let links: Vec<AudioLink> = self
+            .links
+            .iter()
+            .filter(|link| ours.contains(&link.output_node) || ours.contains(&link.input_node))
+            .cloned()
+            .collect();
+        let linked: HashSet<u32> = links.iter().flat_map(|link| [link.output_node, link.input_node]).collect();
+        let nodes = self
+            .nodes
+            .iter()
+            .filter(|node| node.ours || linked.contains(&node.id))
+            .map(|node| AudioNode {
+                description: node.description.clone().filter(|_| node.ours),
+                ..node.clone()
+            })
+            .collect();
+        AudioGraph { nodes, links }
+    }
+}
+
+/// Take a snapshot of the audio graph.
+pub fn snapshot() -> Result<AudioGraph, String> {
+    let output = Command::new("pw-dump")
+        .output()
+        .map_err(|e| format!("Failed to run pw-dump (is PipeWire installed?): {}", e))?;
+    if !output.status.success() {
+        return Err(format!("pw-dump failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    let dump: Value =
+        serde_json::from_slice(&output.stdout).map_err(|e| format!("Failed to read pw-dump's output: {}", e))?;
+    Ok(parse(&dump, &own_pids()))
+}
+
+/// Take a snapshot and write its summary to the log, headed by `reason`.
+pub fn log_snapshot(reason: &str) {
+    match snapshot() {
+        Ok(graph) => {
+            tracing::info!(target: "AudioGraph", "{} ({} audio nodes, {} links)", reason, graph.nodes.len(), graph.links.len());
+            for line in graph.summary() {
+                tracing::info!(target: "AudioGraph", "  {}", line);
+            }
+        }
+        Err(e) => tracing::warn!(target: "AudioGraph", "{}: {}", reason, e),
+    }
+}
+
+/// The audio nodes and their links in `pw-dump` output, marking nodes of
+/// the processes `pids` as ours.
+fn parse(dump: &Value, pids: &HashSet<u32>) -> AudioGraph {
+    let objects = dump.as_array().map(Vec::as_slice).unwrap_or_default();
+    let of_type = |kind: &'static str| {
+        objects
+            .iter()
+            .filter(move |object| object["type"].as_str() == Some(kind))
+            .filter_map(|object| Some((object["id"].as_u64()? as u32, &object["info"])))
+    };
+    let text = |value: &Value| value.as_str().map(str::to_string);
+    // pipewire-pulse writes numbers as strings
+    let number = |value: &Value| value.as_u64().or_else(|| value.as_str()?.parse().ok()).map(|n| n as u32);
+
+    let nodes: Vec<AudioNode> = of_type("PipeWire:Interface:Node")
+        .filter_map(|(id, info)| {
+            let props = &info["props"];
+            let media_class = text(&props["media.class"]).filter(|class| class.contains("Audio"))?;
+            let pid = number(&props["application.process.id"]);
+            let refers_to_us = props
+                .as_object()
+                .is_some_and(|props| props.values().any(|value| value.as_str().is_some_and(|v| v.contains(SINK_PREFIX))));
+            Some(AudioNode {
+                id,
+                name: text(&props["node.name"]).unwrap_or_default(),
+                description: text(&props["node.description"]).or_else(|| text(&props["media.name"])),
+                media_class,
+                application: text(&props["application.name"]),
+                pid,
+                state: text(&info["state"]),
+                ours: refers_to_us || pid.is_some_and(|pid| pids.contains(&pid)),
+            })
+        })
+        .collect();
+    let audio: HashSet<u32> = nodes.iter().map(|node| node.id).collect();
+    let ports: BTreeMap<u32, String> = of_type("PipeWire:Interface:Port")
+        .filter_map(|(id, info)| Some((id, text(&info["props"]["port.name"])?)))
+        .collect();
+    let links = of_type("PipeWire:Interface:Link")
+        .filter_map(|(_, info)| {
+            let output_node = number(&info["output-node-id"])?;
+            let input_node = number(&info["input-node-id"])?;
+            let port = |key: &str| number(&info[key]).and_then(|id| ports.get(&id).cloned());
+            Some(AudioLink {
+                output_node,
+                output_port: port("output-port-id"),
+                input_node,
+                input_port: port("input-port-id"),
+                state: text(&info["state"]),
+            })
+        })
+        .filter(|link| audio.contains(&link.output_node) || audio.contains(&link.input_node))
+        .collect();
+    AudioGraph { nodes, links }
+}
+
+/// This process and its children, such as `parec` and FFmpeg.
+fn own_pids() -> HashSet<u32> {
+    let own = std::process::id();
+    let mut pids = HashSet::from([own]);
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return pids;
+    };
+    for entry in entries.flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()) else {
+            continue;
+        };
+        // The parent is the second field after the parenthesized name,
+        // which may itself contain spaces
+        let parent = std::fs::read_to_string(entry.path().join("stat")).ok().and_then(|stat| {
+            let (_, rest) = stat.rsplit_once(')')?;
+            rest.split_whitespace().nth(1)?.parse::<u32>().ok()
+        });
+        if parent == Some(own) {
+            pids.insert(pid);
+        }
+    }
+    pids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_audio_graph() {
+        let node = |id: u32, props: Value| {
+            json!({ "id": id, "type": "PipeWire:Interface:Node", "info": { "state": "running", "props": props } })
+        };
+        let port = |id: u32, name: &str| json!({ "id": id, "type": "PipeWire:Interface:Port", "info": { "props": { "port.name": name } } });
+        let link = |output: (u32, u32), input: (u32, u32)| {
+            json!({ "id": 900 + output.1, "type": "PipeWire:Interface:Link", "info": {
+                "output-node-id": output.0, "output-port-id": output.1,
+                "input-node-id": input.0, "input-port-id": input.1, "state": "active" } })
+        };
+        let dump = json!([
+            node(40, json!({ "node.name": "screen-recorder-app-1234", "media.class": "Audio/Sink" })),
+            node(41, json!({ "node.name": "Firefox", "media.class": "Stream/Output/Audio",
+                "application.name": "Firefox", "application.process.id": "1234", "media.name": "Lecture 3" })),
+            node(42, json!({ "node.name": "parec", "media.class": "Stream/Input/Audio", "application.process.id": 777 })),
+            node(43, json!({ "node.name": "alsa_output.speakers", "media.class": "Audio/Sink" })),
+            node(44, json!({ "node.name": "v4l2_camera", "media.class": "Video/Source" })),
+            port(1, "output_FL"),
+            port(2, "playback_FL"),
+            port(3, "output_FR"),
+            port(4, "playback_FR"),
+            link((41, 1), (40, 2)),
+            link((41, 3), (40, 4)),
+        ]);
+        let graph = parse(&dump, &HashSet::from([777]));
+        assert_eq!(graph.nodes.len(), 4);
+        let ours: Vec<u32> = graph.nodes.iter().filter(|node| node.ours).map(|node| node.id).collect();
+        assert_eq!(ours, [40, 42]);
+        assert_eq!(graph.links[0].output_port.as_deref(), Some("output_FL"));
+        assert_eq!(
+            graph.summary(),
+            [
+                "#41 Firefox (Stream/Output/Audio, Firefox, pid 1234) -> #40 screen-recorder-app-1234 (Audio/Sink) [2 ports, active]",
+                "#42 parec (Stream/Input/Audio, pid 777) is not connected",
+            ]
+        );
+
+        // The speakers and camera aren't connected to anything of ours
+        let shared = graph.ours_and_neighbors();
+        let ids: Vec<u32> = shared.nodes.iter().map(|node| node.id).collect();
+        assert_eq!(ids, [40, 41, 42]);
+        assert_eq!(shared.links.len(), 2);
+        assert_eq!(graph.nodes[1].description.as_deref(), Some("Lecture 3"));
+        assert_eq!(shared.nodes[1].description, None);
+    }
+}
@@ -0,0 +1,365 @@
+//! Display highlight overlay for Linux.
+//!
+//! On Wayland, the border is a wlr-layer-shell surface on the overlay layer
+//! of the output showing the area, positioned with margins from the
+//! output's logical position (from xdg-output). Compositors without layer
+//! shell fall back to X11 (or XWayland), where the border is an
+//! override-redirect window shaped to just the border. Both ignore input, so
+//! clicks go through to whatever is underneath, and animate like the Windows
+//! highlight.
+
+use std::os::fd::AsFd;
+use std::os::unix::fs::FileExt;
+use std::thread;
+
+use crate::capture::highlight::{border_pixel, Animation, BORDER_WIDTH, COLOR, FRAME_INTERVAL};
+
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
+use wayland_client::protocol::{
+    wl_buffer::{self, WlBuffer},
+    wl_compositor::WlCompositor,
+    wl_output::{self, WlOutput},
+    wl_region::WlRegion,
+    wl_registry::WlRegistry,
+    wl_shm::{self, WlShm},
+    wl_shm_pool::WlShmPool,
+    wl_surface::WlSurface,
+};
+use wayland_client::{delegate_noop, Connection, Dispatch, QueueHandle};
+use wayland_protocols::xdg::xdg_output::zv1::client::{
+    zxdg_output_manager_v1::ZxdgOutputManagerV1,
+    zxdg_output_v1::{self, ZxdgOutputV1},
+};
+use wayland_protocols_wlr::layer_shell::v1::client::{
+    zwlr_layer_shell_v1::{Layer, ZwlrLayerShellV1},
+    zwlr_layer_surface_v1::{self, Anchor, KeyboardInteractivity, ZwlrLayerSurfaceV1},
+};
+
+/// Show a highlight border around the specified area, in logical desktop
+/// coordinates. This function spawns a thread and returns immediately.
+pub fn show_highlight(x: i32, y: i32, width: i32, height: i32) {
+    if width <= 0 || height <= 0 {
+        return;
+    }
+    thread::spawn(move || {
+        let result = if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            run_layer_surface(x, y, width, height).or_else(|e| {
+                tracing::warn!(target: "Highlight", "{}; trying X11", e);
+                run_x11_window(x, y, width, height)
+            })
+        } else {
+            run_x11_window(x, y, width, height)
+        };
+        if let Err(e) = result {
+            tracing::error!(target: "Highlight", "{}", e);
+        }
+    });
+}
+
+/// Spans of `(row, first column, columns)` covering the border of a
+/// `width` x `height` buffer.
+fn border_spans(width: i32, height: i32, border: i32) -> Vec<(i32, i32, i32)> {
+    let border = border.min(width / 2).min(height / 2).max(1);
+    (0..height)
+        .flat_map(|row| {
+            if row < border || row >= height - border {
+                vec![(row, 0, width)]
+            } else {
+                vec![(row, 0, border), (row, width - border, border)]
+            }
+        })
+        .collect()
+}
+
+// ---------------------------------------------------------------------------
+// Wayland (wlr-layer-shell)
+// ---------------------------------------------------------------------------
+
+/// What's known of an output.
+#[derive(Default)]
+struct Output {
+    output: Option<WlOutput>,
+    scale: i32,
+    /// Logical position and size, from xdg-output
+    logical: Option<(i32, i32, i32, i32)>,
+}
+
+#[derive(Default)]
+struct LayerState {
+    outputs: Vec<Output>,
+    /// Serial of the configure to acknowledge, once one arrives
+    configured: Option<u32>,
+    closed: bool,
+    /// Whether each buffer is held by the compositor
+    busy: [bool; 2],
+}
+
+/// Show the border as a layer surface until the animation ends.
+fn run_layer_surface(x: i32, y: i32, width: i32, height: i32) -> Result<(), String> {
+    let connection = Connection::connect_to_env().map_err(|e| format!("No Wayland display: {}", e))?;
+    let (globals, mut queue) =
+        registry_queue_init::<LayerState>(&connection).map_err(|e| format!("Wayland registry error: {}", e))?;
+    let qh = queue.handle();
+    let mut state = LayerState::default();
+
+    let bind_error = |e| format!("Missing Wayland global: {}", e);
+    let compositor: WlCompositor = globals.bind(&qh, 4..=6, ()).map_err(bind_error)?;
+    let shm: WlShm = globals.bind(&qh, 1..=1, ()).map_err(bind_error)?;
+    let layer_shell: ZwlrLayerShellV1 = globals.bind(&qh, 1..=4, ()).map_err(bind_error)?;
+    let output_manager: Option<ZxdgOutputManagerV1> = globals.bind(&qh, 2..=3, ()).ok();
+
+    for global in globals.contents().clone_list() {
+        if global.interface == "wl_output" {
+            let index = state.outputs.len();
+            let output: WlOutput = globals.registry().bind(global.name, global.version.min(4), &qh, index);
+            if let Some(manager) = &output_manager {
+                manager.get_xdg_output(&output, &qh, index);
+            }
+            state.outputs.push(Output {
+                output: Some(output),
+                scale: 1,
+                logical: None,
+            });
+        }
+    }
+    queue.roundtrip(&mut state).map_err(|e| format!("Wayland error: {}", e))?;
+
+    // The output showing the middle of the area, or the only one
+    let (center_x, center_y) = (x + width / 2, y + height / 2);
+    let output = state
+        .outputs
+        .iter()
+        .find(|o| {
+            o.logical.is_some_and(|(ox, oy, ow, oh)| {
+                (ox..ox + ow).contains(&center_x) && (oy..oy + oh).contains(&center_y)
+            })
+        })
+        .or_else(|| state.outputs.first().filter(|_| state.outputs.len() == 1))
+        .ok_or("No output shows the highlighted area")?;
+    let (output_x, output_y, _, _) = output.logical.unwrap_or_default();
+    let scale = output.scale.max(1);
+
+    let surface = compositor.create_surface(&qh, ());
+    // An empty input region lets clicks through
+    let region = compositor.create_region(&qh, ());
+    surface.set_input_region(Some(&region));
+    region.destroy();
+    surface.set_buffer_scale(scale);
+
+    let layer_surface = layer_shell.get_layer_surface(
+        &surface,
+        output.output.as_ref(),
+        Layer::Overlay,
+        "screen-recorder-highlight".to_string(),
+        &qh,
+        (),
+    );
+    layer_surface.set_anchor(Anchor::Top | Anchor::Left);
+    layer_surface.set_margin(y - output_y, 0, 0, x - output_x);
+    layer_surface.set_size(width as u32, height as u32);
+    // Position relative to the output's edges, not other panels
+    layer_surface.set_exclusive_zone(-1);
+    layer_surface.set_keyboard_interactivity(KeyboardInteractivity::None);
+    surface.commit();
+
+    while state.configured.is_none() && !state.closed {
+        queue.blocking_dispatch(&mut state).map_err(|e| format!("Wayland error: {}", e))?;
+    }
+    if let Some(serial) = state.configured {
+        layer_surface.ack_configure(serial);
+    }
+
+    // Two buffers so one can be drawn while the compositor shows the other
+    let (buffer_width, buffer_height) = (width * scale, height * scale);
+    let stride = buffer_width * 4;
+    let buffer_size = (stride * buffer_height) as u64;
+    let file = super::shm_file("highlight", buffer_size * 2)?;
+    let pool = shm.create_pool(file.as_fd(), (buffer_size * 2) as i32, &qh, ());
+    let buffers: Vec<WlBuffer> = (0..2)
+        .map(|index| {
+            let offset = index as i32 * buffer_size as i32;
+            pool.create_buffer(offset, buffer_width, buffer_height, stride, wl_shm::Format::Argb8888, &qh, index)
+        })
+        .collect();
+
+    let spans = border_spans(buffer_width, buffer_height, BORDER_WIDTH * scale);
+    let mut animation = Animation::start();
+    let mut next = 0;
+    while let (Some(alpha), false) = (animation.next_alpha(), state.closed) {
+        if state.busy[next] {
+            queue.blocking_dispatch(&mut state).map_err(|e| format!("Wayland error: {}", e))?;
+            continue;
+        }
+        let offset = next as u64 * buffer_size;
+        let pixel = border_pixel(alpha);
+        let row = pixel.repeat(buffer_width as usize);
+        for &(y, x, columns) in &spans {
+            let at = offset + (y * stride + x * 4) as u64;
+            file.write_all_at(&row[..columns as usize * 4], at)
+                .map_err(|e| format!("Failed to draw the highlight: {}", e))?;
+        }
+
+        surface.attach(Some(&buffers[next]), 0, 0);
+        surface.damage_buffer(0, 0, buffer_width, buffer_height);
+        surface.commit();
+        state.busy[next] = true;
+        next = 1 - next;
+        queue.roundtrip(&mut state).map_err(|e| format!("Wayland error: {}", e))?;
+        thread::sleep(FRAME_INTERVAL);
+    }
+
+    layer_surface.destroy();
+    surface.destroy();
+    for buffer in buffers {
+        buffer.destroy();
+    }
+    pool.destroy();
+    let _ = connection.flush();
+    Ok(())
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for LayerState {
+    fn event(_: &mut Self, _: &WlRegistry, _: <WlRegistry as wayland_client::Proxy>::Event, _: &GlobalListContents, _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<WlOutput, usize> for LayerState {
+    fn event(state: &mut Self, _: &WlOutput, event: wl_output::Event, index: &usize, _: &Connection, _: &QueueHandle<Self>) {
+        if let wl_output::Event::Scale { factor } = event {
+            state.outputs[*index].scale = factor;
+        }
+    }
+}
+
+impl Dispatch<ZxdgOutputV1, usize> for LayerState {
+    fn event(state: &mut Self, _: &ZxdgOutputV1, event: zxdg_output_v1::Event, index: &usize, _: &Connection, _: &QueueHandle<Self>) {
+        let logical = state.outputs[*index].logical.get_or_insert_default();
+        match event {
+            zxdg_output_v1::Event::LogicalPosition { x, y } => (logical.0, logical.1) = (x, y),
+            zxdg_output_v1::Event::LogicalSize { width, height } => (logical.2, logical.3) = (width, height),
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwlrLayerSurfaceV1, ()> for LayerState {
+    fn event(state: &mut Self, _: &ZwlrLayerSurfaceV1, event: zwlr_layer_surface_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        match event {
+            zwlr_layer_surface_v1::Event::Configure { serial, .. } => state.configured = Some(serial),
+            zwlr_layer_surface_v1::Event::Closed => state.closed = true,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WlBuffer, usize> for LayerState {
+    fn event(state: &mut Self, _: &WlBuffer, event: wl_buffer::Event, index: &usize, _: &Connection, _: &QueueHandle<Self>) {
+        if let wl_buffer::Event::Release = event {
+            state.busy[*index] = false;
+        }
+    }
+}
+
+delegate_noop!(LayerState: ignore WlCompositor);
+delegate_noop!(LayerState: ignore WlSurface);
+delegate_noop!(LayerState: ignore WlRegion);
+delegate_noop!(LayerState: ignore WlShm);
+delegate_noop!(LayerState: ignore WlShmPool);
+delegate_noop!(LayerState: ignore ZwlrLayerShellV1);
+delegate_noop!(LayerState: ignore ZxdgOutputManagerV1);
+
+// ---------------------------------------------------------------------------
+// X11 (shaped override-redirect window)
+// ---------------------------------------------------------------------------
+
+/// Show the border as a shaped X11 window until the animation ends.
+fn run_x11_window(x: i32, y: i32, width: i32, height: i32) -> Result<(), String> {
+    use x11rb::connection::Connection as _;
+    use x11rb::protocol::shape::{ConnectionExt as _, SK, SO};
+    use x11rb::protocol::xproto::{
+        AtomEnum, ClipOrdering, ConnectionExt as _, CreateWindowAux, PropMode, Rectangle, WindowClass,
+    };
+    use x11rb::wrapper::ConnectionExt as _;
+
+    let x11_error = |e: &dyn std::fmt::Display| format!("X11 error: {}", e);
+    let (connection, screen_num) = x11rb::connect(None).map_err(|e| format!("No X11 display: {}", e))?;
+    let root = connection.setup().roots[screen_num].root;
+    let window = connection.generate_id().map_err(|e| x11_error(&e))?;
+    let [r, g, b] = COLOR;
+
+    connection
+        .create_window(
+            x11rb::COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            x as i16,
+            y as i16,
+            width as u16,
+            height as u16,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            x11rb::COPY_FROM_PARENT,
+            &CreateWindowAux::new()
+                .override_redirect(1)
+                .background_pixel(u32::from_be_bytes([0, r, g, b])),
+        )
+        .map_err(|e| x11_error(&e))?;
+
+    // Shape the window to the border, and give it no input area so clicks
+    // go through
+    let border: Vec<Rectangle> = border_spans(width, height, BORDER_WIDTH)
+        .into_iter()
+        .map(|(row, column, columns)| Rectangle {
+            x: column as i16,
+            y: row as i16,
+            width: columns as u16,
+            height: 1,
+        })
+        .collect();
+    connection
+        .shape_rectangles(SO::SET, SK::BOUNDING, ClipOrdering::UNSORTED, window, 0, 0, &border)
+        .map_err(|e| x11_error(&e))?;
+    connection
+        .shape_rectangles(SO::SET, SK::INPUT, ClipOrdering::UNSORTED, window, 0, 0, &[])
+        .map_err(|e| x11_error(&e))?;
+
+    // Fading needs a compositing manager; without one the border just shows
+    let opacity_atom = connection
+        .intern_atom(false, b"_NET_WM_WINDOW_OPACITY")
+        .map_err(|e| x11_error(&e))?
+        .reply()
+        .map_err(|e| x11_error(&e))?
+        .atom;
+    let set_opacity = |alpha: u8| {
+        let opacity = (alpha as u64 * u32::MAX as u64 / 255) as u32;
+        connection.change_property32(PropMode::REPLACE, window, opacity_atom, AtomEnum::CARDINAL, &[opacity])
+    };
+    set_opacity(0).map_err(|e| x11_error(&e))?;
+    connection.map_window(window).map_err(|e| x11_error(&e))?;
+
+    let mut animation = Animation::start();
+    while let Some(alpha) = animation.next_alpha() {
+        set_opacity(alpha).map_err(|e| x11_error(&e))?;
+        connection.flush().map_err(|e| x11_error(&e))?;
+        thread::sleep(FRAME_INTERVAL);
+    }
+
+    connection.destroy_window(window).map_err(|e| x11_error(&e))?;
+    connection.flush().map_err(|e| x11_error(&e))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_border_spans() {
+        let spans = border_spans(10, 6, 2);
+        // Full rows at the top and bottom, the sides in between
+        assert_eq!(spans.len(), 2 + 2 * 2 + 2);
+        assert_eq!(spans[0], (0, 0, 10));
+        assert_eq!(spans[2], (2, 0, 2));
+        assert_eq!(spans[3], (2, 8, 2));
+        assert_eq!(spans.last(), Some(&(5, 0, 10)));
+    }
+}
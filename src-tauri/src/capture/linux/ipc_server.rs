@@ -1,17 +1,25 @@
 //! IPC server for communicating with the picker service.
 //!
-//! This module runs a Unix socket server that the picker service connects to
+//! This module serves the `picker` IPC endpoint that the picker service connects to
 //! when it receives portal requests. The server responds with the current
-//! capture selection stored in app state.
+//! capture selection stored in app state, and the picker reports back what
+//! became of each request. Those reports, along with the selections handed
+//! out and the end of the app's own sessions, are published as
+//! [`PortalEvent`]s for the UI.
 
+use super::picker_policy::PickerPolicy;
+use super::portal_requests::{self, PortalRequest};
+use crate::capture::types::{CaptureRegion, MonitorInfo};
+use crate::ipc::{self, IpcListener, IpcStream};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::{UnixListener, UnixStream};
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
+use tokio::task::JoinHandle;
 
-/// Geometry for region capture.
+/// Geometry for region capture, in logical pixels relative to the monitor.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Geometry {
     pub x: i32,
@@ -20,6 +28,20 @@ pub struct Geometry {
     pub height: u32,
 }
 
+impl Geometry {
+    /// Picker geometry for `region` on `monitor`. The portal scales region
+    /// selections by the monitor's scale factor, so they must be logical.
+    pub fn from_region(region: &CaptureRegion, monitor: &MonitorInfo) -> Self {
+        let logical = region.to_logical(monitor);
+        Self {
+            x: logical.x,
+            y: logical.y,
+            width: logical.width,
+            height: logical.height,
+        }
+    }
+}
+
 /// The current capture selection stored by the main app.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CaptureSelection {
@@ -35,8 +57,54 @@ pub struct CaptureSelection {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum IpcRequest {
-    /// Query the current capture selection.
-    QuerySelection,
+    /// Query the selection to share with the requesting app.
+    QuerySelection {
+        /// The requesting app, if the picker knows it
+        #[serde(default)]
+        app_id: Option<String>,
+    },
+    /// Query which apps may share the screen.
+    QueryPolicy,
+    /// Report what became of a portal request.
+    Notify { event: PortalEvent },
+    /// Report the approvals the picker remembers.
+    ReportPermissions { permissions: Vec<PortalPermission> },
+    /// Check that the app is answering.
+    Ping,
+}
+
+/// Something that happened to a portal screencast request or session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum PortalEvent {
+    /// A selection was handed to the portal and is being shared
+    Started {
+        source_type: String,
+        source_id: String,
+        /// Whether another app requested it, rather than this one
+        external: bool,
+    },
+    /// A portal request was refused
+    Denied { reason: String },
+    /// One of this app's screencast sessions ended
+    Ended,
+}
+
+/// An approval the picker remembers for an app that asked for its
+/// screencast to persist.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortalPermission {
+    pub app_id: String,
+    /// What is shared with the app
+    pub source: CaptureSelection,
+    /// 1 while the process that asked runs, 2 until revoked
+    pub persist_mode: u32,
+    /// The process that asked
+    pub pid: u32,
+    /// When that process started, in clock ticks after boot
+    pub process_start: u64,
+    /// When the approval was given, in milliseconds since the Unix epoch
+    pub granted_at: u64,
 }
 
 /// IPC response from main app to picker.
@@ -52,37 +120,112 @@ pub enum IpcResponse {
     },
     /// No selection available.
     NoSelection,
+    /// Which apps may share the screen.
+    Policy {
+        policy: PickerPolicy,
+        /// Whether the request being picked for is this app's own
+        own_request: bool,
+        /// The request being picked for, if it was seen on the bus
+        #[serde(skip_serializing_if = "Option::is_none")]
+        request: Option<PortalRequest>,
+    },
+    /// Notification received.
+    Ack,
+    /// Answer to a ping.
+    Pong,
     /// Error occurred.
     Error { message: String },
 }
 
 /// Shared state for the IPC server.
+///
+/// Besides the selection for this app's own recordings, other apps can each
+/// be given their own, so several apps share different things at once.
+/// Requests from apps without one, or that can't be identified, are
+/// answered with the current selection.
 pub struct IpcServerState {
     /// Current capture selection (set by UI before recording starts)
     pub selection: Option<CaptureSelection>,
+    /// Selections for other apps' requests, by app ID
+    pub app_selections: HashMap<String, CaptureSelection>,
+    /// Portal requests made by this app that haven't been answered yet,
+    /// which tells its own requests apart when they can't be seen on the bus
+    pub own_requests: usize,
+    /// The request the picker is picking for, as seen on the bus, from its
+    /// policy query until the next
+    pub current_request: Option<PortalRequest>,
+    /// When the picker last asked for the selection (RFC 3339)
+    pub last_request: Option<String>,
+    /// Which apps may share the screen through the picker
+    pub policy: PickerPolicy,
+    /// Approvals the picker last reported remembering
+    pub permissions: Vec<PortalPermission>,
+    /// Publishes portal events to subscribers
+    events: broadcast::Sender<PortalEvent>,
 }
 
 impl Default for IpcServerState {
     fn default() -> Self {
-        Self { selection: None }
+        Self {
+            selection: None,
+            app_selections: HashMap::new(),
+            own_requests: 0,
+            current_request: None,
+            last_request: None,
+            policy: PickerPolicy::default(),
+            permissions: Vec::new(),
+            events: broadcast::channel(16).0,
+        }
     }
 }
 
+impl IpcServerState {
+    /// The selection to share with `app_id`: its own if it has one and the
+    /// request isn't this app's, otherwise the current selection.
+    pub fn selection_for(&self, app_id: Option<&str>) -> Option<&CaptureSelection> {
+        let app_selection = app_id
+            .filter(|_| !self.is_own_request())
+            .and_then(|app_id| self.app_selections.get(app_id));
+        app_selection.or(self.selection.as_ref())
+    }
+
+    /// Whether the request being picked for is this app's own: the request
+    /// seen on the bus came from this process, or when requests can't be
+    /// seen, this app is waiting on one of its own.
+    pub fn is_own_request(&self) -> bool {
+        match &self.current_request {
+            Some(request) => request.is_own(),
+            None => !portal_requests::watching() && self.own_requests > 0,
+        }
+    }
+
+    /// Publish a portal event.
+    pub fn notify(&self, event: PortalEvent) {
+        tracing::info!(target: "IPC", "Portal event: {:?}", event);
+        // Nobody listening is fine
+        let _ = self.events.send(event);
+    }
+
+    /// Receive portal events published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<PortalEvent> {
+        self.events.subscribe()
+    }
+}
+
+/// IPC endpoint the picker connects to.
+pub const PICKER_ENDPOINT: &str = "picker";
+
 /// Get the IPC socket path.
 pub fn get_socket_path() -> PathBuf {
-    let runtime_dir =
-        std::env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| "/tmp".to_string());
-    PathBuf::from(runtime_dir)
-        .join("screen-recorder")
-        .join("picker.sock")
+    ipc::socket_path(PICKER_ENDPOINT)
 }
 
 /// Handle a single client connection.
 async fn handle_client(
-    stream: UnixStream,
+    stream: Box<dyn IpcStream>,
     state: Arc<RwLock<IpcServerState>>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let (reader, mut writer) = stream.into_split();
+    let (reader, mut writer) = tokio::io::split(stream);
     let mut reader = BufReader::new(reader);
     let mut line = String::new();
 
@@ -110,12 +253,18 @@ async fn handle_client(
 
     // Handle request
     let response = match request {
-        IpcRequest::QuerySelection => {
+        IpcRequest::QuerySelection { app_id } => {
+            state.write().await.last_request = Some(chrono::Local::now().to_rfc3339());
             let state = state.read().await;
-            match &state.selection {
+            match state.selection_for(app_id.as_deref()) {
                 Some(sel) => {
-                    eprintln!("[IPC] Picker queried selection: type={}, id={}, geometry={:?}",
+                    tracing::info!(target: "IPC", "Picker queried selection: type={}, id={}, geometry={:?}",
                         sel.source_type, sel.source_id, sel.geometry);
+                    state.notify(PortalEvent::Started {
+                        source_type: sel.source_type.clone(),
+                        source_id: sel.source_id.clone(),
+                        external: !state.is_own_request(),
+                    });
                     IpcResponse::Selection {
                         source_type: sel.source_type.clone(),
                         source_id: sel.source_id.clone(),
@@ -123,11 +272,32 @@ async fn handle_client(
                     }
                 }
                 None => {
-                    eprintln!("[IPC] Picker queried but no selection available");
+                    tracing::info!(target: "IPC", "Picker queried but no selection available");
+                    state.notify(PortalEvent::Denied {
+                        reason: "Nothing is selected for sharing".to_string(),
+                    });
                     IpcResponse::NoSelection
                 }
             }
         }
+        IpcRequest::QueryPolicy => {
+            let mut state = state.write().await;
+            state.current_request = portal_requests::take_next();
+            IpcResponse::Policy {
+                policy: state.policy.clone(),
+                own_request: state.is_own_request(),
+                request: state.current_request.clone(),
+            }
+        }
+        IpcRequest::ReportPermissions { permissions } => {
+            state.write().await.permissions = permissions;
+            IpcResponse::Ack
+        }
+        IpcRequest::Notify { event } => {
+            state.read().await.notify(event);
+            IpcResponse::Ack
+        }
+        IpcRequest::Ping => IpcResponse::Pong,
     };
 
     // Send response
@@ -139,47 +309,76 @@ async fn handle_client(
     Ok(())
 }
 
-/// Start the IPC server.
+/// The server's accept loop, while it's running.
+static SERVER: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+
+/// Start the IPC server. It runs until the app shuts down.
 ///
 /// Returns a handle to the server state that can be used to update the selection.
 pub async fn start_ipc_server() -> Result<Arc<RwLock<IpcServerState>>, Box<dyn std::error::Error + Send + Sync>>
 {
-    let socket_path = get_socket_path();
-
-    // Create parent directory if needed
-    if let Some(parent) = socket_path.parent() {
-        tokio::fs::create_dir_all(parent).await?;
-    }
-
-    // Remove existing socket file
-    let _ = tokio::fs::remove_file(&socket_path).await;
-
-    // Bind to socket
-    let listener = UnixListener::bind(&socket_path)?;
+    let state = Arc::new(RwLock::new(IpcServerState {
+        policy: PickerPolicy::load(),
+        ..IpcServerState::default()
+    }));
+    serve(state.clone())?;
+    Ok(state)
+}
 
-    let state = Arc::new(RwLock::new(IpcServerState::default()));
-    let state_clone = state.clone();
+/// Listen on the picker endpoint and serve `state` on it.
+fn serve(state: Arc<RwLock<IpcServerState>>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut listener = ipc::bind(PICKER_ENDPOINT)?;
 
     // Spawn server task
-    tokio::spawn(async move {
+    let server = tokio::spawn(async move {
+        let shutdown = crate::shutdown::token();
         loop {
-            match listener.accept().await {
-                Ok((stream, _addr)) => {
-                    let state = state_clone.clone();
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                _ = shutdown.cancelled() => break,
+            };
+            match accepted {
+                Ok(stream) => {
+                    let state = state.clone();
                     tokio::spawn(async move {
                         if let Err(e) = handle_client(stream, state).await {
-                            eprintln!("IPC client error: {}", e);
+                            tracing::error!(target: "IPC", "Client error: {}", e);
                         }
                     });
                 }
                 Err(e) => {
-                    eprintln!("IPC accept error: {}", e);
+                    tracing::error!(target: "IPC", "Accept error: {}", e);
                 }
             }
         }
+        drop(listener);
+        let _ = std::fs::remove_file(get_socket_path());
+        tracing::info!(target: "IPC", "Server stopped");
     });
+    *SERVER.lock().unwrap() = Some(server);
+    Ok(())
+}
 
-    Ok(state)
+/// Whether the server's accept loop is running.
+pub fn server_running() -> bool {
+    SERVER.lock().unwrap().as_ref().is_some_and(|server| !server.is_finished())
+}
+
+/// Stop the server if it's running and start it again with the same state,
+/// recreating its socket.
+pub fn restart_ipc_server(state: &Arc<RwLock<IpcServerState>>) -> Result<(), String> {
+    if let Some(server) = SERVER.lock().unwrap().take() {
+        server.abort();
+    }
+    serve(state.clone()).map_err(|e| format!("Failed to restart the IPC server: {}", e))
+}
+
+/// Wait for the server to stop once the app's shutdown token is cancelled.
+pub async fn stop_ipc_server() {
+    let server = SERVER.lock().unwrap().take();
+    if let Some(server) = server {
+        let _ = server.await;
+    }
 }
 
 /// Update the current capture selection.
@@ -187,7 +386,7 @@ pub async fn set_selection(
     state: &Arc<RwLock<IpcServerState>>,
     selection: CaptureSelection,
 ) {
-    eprintln!("[IPC] Setting selection: type={}, id={}, geometry={:?}", 
+    tracing::info!(target: "IPC", "Setting selection: type={}, id={}, geometry={:?}", 
         selection.source_type, selection.source_id, selection.geometry);
     let mut state = state.write().await;
     state.selection = Some(selection);
@@ -199,9 +398,25 @@ pub async fn clear_selection(state: &Arc<RwLock<IpcServerState>>) {
     state.selection = None;
 }
 
+/// Share `selection` with `app_id` whenever it requests a screencast, or
+/// with `None`, go back to sharing the current selection with it.
+pub async fn set_app_selection(
+    state: &Arc<RwLock<IpcServerState>>,
+    app_id: String,
+    selection: Option<CaptureSelection>,
+) {
+    tracing::info!(target: "IPC", "Setting selection for {}: {:?}", app_id, selection);
+    let mut state = state.write().await;
+    match selection {
+        Some(selection) => state.app_selections.insert(app_id, selection),
+        None => state.app_selections.remove(&app_id),
+    };
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::capture::linux::picker_policy::AppPolicy;
 
     #[test]
     fn test_serialize_selection_response() {
@@ -238,6 +453,78 @@ mod tests {
     fn test_deserialize_query_request() {
         let json = r#"{"type":"query_selection"}"#;
         let request: IpcRequest = serde_json::from_str(json).unwrap();
-        assert!(matches!(request, IpcRequest::QuerySelection));
+        assert!(matches!(request, IpcRequest::QuerySelection { app_id: None }));
+        let json = r#"{"type":"query_selection","app_id":"us.zoom.Zoom"}"#;
+        let request: IpcRequest = serde_json::from_str(json).unwrap();
+        assert!(matches!(request, IpcRequest::QuerySelection { app_id: Some(id) } if id == "us.zoom.Zoom"));
+        let request: IpcRequest = serde_json::from_str(r#"{"type":"ping"}"#).unwrap();
+        assert!(matches!(request, IpcRequest::Ping));
+        assert_eq!(serde_json::to_string(&IpcResponse::Pong).unwrap(), r#"{"type":"pong"}"#);
+    }
+
+    #[test]
+    fn test_selection_for_app() {
+        let selection = |source_id: &str| CaptureSelection {
+            source_type: "monitor".to_string(),
+            source_id: source_id.to_string(),
+            geometry: None,
+        };
+        let mut state = IpcServerState {
+            selection: Some(selection("DP-1")),
+            ..IpcServerState::default()
+        };
+        state.app_selections.insert("us.zoom.Zoom".to_string(), selection("HDMI-A-1"));
+        assert_eq!(state.selection_for(Some("us.zoom.Zoom")).unwrap().source_id, "HDMI-A-1");
+        assert_eq!(state.selection_for(Some("com.obsproject.Studio")).unwrap().source_id, "DP-1");
+        assert_eq!(state.selection_for(None).unwrap().source_id, "DP-1");
+        // This app's own requests always get the current selection
+        state.current_request = Some(PortalRequest {
+            handle: "/org/freedesktop/portal/desktop/request/1_9/t".to_string(),
+            session_handle: "/org/freedesktop/portal/desktop/session/1_9/s".to_string(),
+            app_id: Some("us.zoom.Zoom".to_string()),
+            pid: Some(std::process::id()),
+            persist_mode: 0,
+            remote_desktop: false,
+        });
+        assert!(state.is_own_request());
+        assert_eq!(state.selection_for(Some("us.zoom.Zoom")).unwrap().source_id, "DP-1");
+        // Another process's request, even while this app waits on its own
+        state.current_request.as_mut().unwrap().pid = Some(1);
+        state.own_requests = 1;
+        assert!(!state.is_own_request());
+    }
+
+    #[test]
+    fn test_notify_request_roundtrip() {
+        let json = r#"{"type":"notify","event":{"event":"denied","reason":"Unknown source type"}}"#;
+        let request: IpcRequest = serde_json::from_str(json).unwrap();
+        let IpcRequest::Notify { event } = request else {
+            panic!("expected a notification");
+        };
+        assert_eq!(event, PortalEvent::Denied { reason: "Unknown source type".to_string() });
+
+        let state = IpcServerState::default();
+        let mut events = state.subscribe();
+        state.notify(PortalEvent::Ended);
+        assert_eq!(events.try_recv().unwrap(), PortalEvent::Ended);
+    }
+
+    #[test]
+    fn test_serialize_policy_response() {
+        let request: IpcRequest = serde_json::from_str(r#"{"type":"query_policy"}"#).unwrap();
+        assert!(matches!(request, IpcRequest::QueryPolicy));
+        let mut policy = PickerPolicy::default();
+        policy.apps.insert("org.mozilla.firefox".to_string(), AppPolicy::Deny);
+        let response = IpcResponse::Policy { policy, own_request: false, request: None };
+        assert_eq!(
+            serde_json::to_string(&response).unwrap(),
+            r#"{"type":"policy","policy":{"default":"ask","apps":{"org.mozilla.firefox":"deny"},"revoked":{}},"own_request":false}"#
+        );
+
+        let json = r#"{"type":"report_permissions","permissions":[{"app_id":"org.mozilla.firefox","source":{"source_type":"monitor","source_id":"DP-1"},"persist_mode":2,"pid":4213,"process_start":987654,"granted_at":1700000000000}]}"#;
+        let IpcRequest::ReportPermissions { permissions } = serde_json::from_str(json).unwrap() else {
+            panic!("expected a permission report");
+        };
+        assert_eq!(permissions[0].source.source_id, "DP-1");
     }
 }
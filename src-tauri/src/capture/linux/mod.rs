@@ -4,26 +4,87 @@
 //! - Hyprland IPC for window/monitor enumeration
 //! - xdg-desktop-portal for capture authorization
 //! - PipeWire for video/audio streaming
+//! - wlr-layer-shell (or X11) for the display highlight
+//! - wlr-screencopy for window thumbnails
+//! - Desktop entries for application icons
 //!
 //! The capture flow involves a separate picker service that auto-approves
 //! portal requests based on the user's selection in the main app UI.
 
+pub mod app_audio;
+pub mod app_icon;
+pub mod audio_graph;
+pub mod highlight;
 pub mod ipc_server;
+pub mod picker_health;
+pub mod picker_install;
+pub mod picker_policy;
 pub mod pipewire_capture;
 pub mod portal_client;
+pub mod portal_requests;
+pub mod remote_desktop;
+pub mod screencopy;
 
+use crate::capture::desktop::{DesktopLayout, DESKTOP_SPACE};
 use crate::capture::error::{CaptureError, EnumerationError};
 use crate::capture::types::{
-    CaptureRegion, FrameReceiver, MonitorInfo, StopHandle, WindowInfo,
+    Capabilities, CaptureOptions, CaptureRegion, FrameReceiver, MonitorInfo, StopHandle, WindowBounds, WindowInfo,
+};
+use crate::capture::{
+    edid, thumbnail, AsyncCaptureBackend, CaptureBackend, CaptureFuture, HighlightProvider, MonitorEnumerator,
+    WindowEnumerator,
 };
-use crate::capture::{CaptureBackend, HighlightProvider, MonitorEnumerator, WindowEnumerator};
 
-use hyprland::data::{Clients, Monitors};
+use ashpd::desktop::screencast::{CursorMode, SourceType};
+use hyprland::data::{Client, Clients, Monitors, Transforms};
 use hyprland::shared::HyprData;
-use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::sync::RwLock;
 
 use ipc_server::IpcServerState;
+use portal_client::ScreencastStream;
+
+/// How often a capture is checked for having stopped, to end its portal session.
+const SESSION_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// An unlinked file to share `purpose` buffers with the compositor through.
+pub fn shm_file(purpose: &str, size: u64) -> Result<std::fs::File, String> {
+    let dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    let path = dir.join(format!("screen-recorder-{}-{}", purpose, std::process::id()));
+    let file = std::fs::File::options()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .map_err(|e| format!("Failed to create the {} buffer: {}", purpose, e))?;
+    let _ = std::fs::remove_file(&path);
+    file.set_len(size)
+        .map_err(|e| format!("Failed to size the {} buffer: {}", purpose, e))?;
+    Ok(file)
+}
+
+/// Run `hyprctl` with `args`, failing with its output if it does.
+pub fn hyprctl(args: &[&str]) -> Result<(), String> {
+    let output = std::process::Command::new("hyprctl")
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to execute hyprctl: {}", e))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // hyprctl exits with success and prints the error instead
+    if !output.status.success() || stdout.trim() != "ok" {
+        return Err(format!(
+            "hyprctl {} failed: {}{}",
+            args.join(" "),
+            stdout.trim(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
 
 /// Global IPC server state (initialized once at startup)
 static IPC_STATE: once_cell::sync::OnceCell<Arc<RwLock<IpcServerState>>> = once_cell::sync::OnceCell::new();
@@ -31,36 +92,116 @@ static IPC_STATE: once_cell::sync::OnceCell<Arc<RwLock<IpcServerState>>> = once_
 /// Initialize the global IPC server (call once at app startup).
 pub async fn init_ipc_server() -> Result<(), String> {
     if IPC_STATE.get().is_some() {
-        eprintln!("[Linux] IPC server already initialized");
+        tracing::info!(target: "Linux", "IPC server already initialized");
         return Ok(());
     }
 
-    eprintln!("[Linux] Starting IPC server...");
+    tracing::info!(target: "Linux", "Starting IPC server...");
     let state = ipc_server::start_ipc_server()
         .await
         .map_err(|e| format!("Failed to start IPC server: {}", e))?;
 
     IPC_STATE.set(state).map_err(|_| "IPC state already set")?;
-    eprintln!("[Linux] IPC server started at {:?}", ipc_server::get_socket_path());
+    tracing::info!(target: "Linux", "IPC server started at {:?}", ipc_server::get_socket_path());
+    tokio::spawn(portal_requests::run());
+    tokio::spawn(remote_desktop::run());
     Ok(())
 }
 
+/// The picker selection for sharing a monitor, window or region with
+/// another app. Regions are checked and placed as for recordings.
+pub fn app_selection(
+    source_type: &str,
+    source_id: &str,
+    region: Option<CaptureRegion>,
+) -> Result<ipc_server::CaptureSelection, String> {
+    let geometry = match source_type {
+        "monitor" | "window" => None,
+        "region" => {
+            let region = region.ok_or("A region selection needs a region")?;
+            let monitors = LinuxBackend::new().list_monitors().map_err(|e| format!("Failed to list monitors: {}", e))?;
+            let layout = DesktopLayout::new(monitors, DESKTOP_SPACE);
+            let region = layout.validate_region(&region).map_err(|e| e.to_string())?;
+            let monitor = layout
+                .monitor(&region.monitor_id)
+                .ok_or_else(|| format!("Monitor '{}' not found", region.monitor_id))?;
+            return Ok(ipc_server::CaptureSelection {
+                source_type: source_type.to_string(),
+                source_id: region.monitor_id.clone(),
+                geometry: Some(ipc_server::Geometry::from_region(&region, monitor)),
+            });
+        }
+        other => return Err(format!("Unknown source type: {}", other)),
+    };
+    Ok(ipc_server::CaptureSelection {
+        source_type: source_type.to_string(),
+        source_id: source_id.to_string(),
+        geometry,
+    })
+}
+
 /// Get the global IPC state.
 pub fn get_ipc_state() -> Option<Arc<RwLock<IpcServerState>>> {
     IPC_STATE.get().cloned()
 }
 
+/// Wait for a capture started through the portal from synchronous code
+/// running within the tokio runtime.
+fn block_on_portal(capture: CaptureFuture<'_>) -> Result<(FrameReceiver, StopHandle), CaptureError> {
+    tokio::task::block_in_place(|| tokio::runtime::Handle::current().block_on(capture))
+}
+
+/// Tasks closing portal sessions once their captures stop.
+static SESSIONS: Mutex<Vec<tokio::task::JoinHandle<()>>> = Mutex::new(Vec::new());
+
+/// Wait for the portal sessions of captures that have been stopped to close.
+pub async fn close_sessions() {
+    let sessions = std::mem::take(&mut *SESSIONS.lock().unwrap());
+    for session in sessions {
+        let _ = session.await;
+    }
+}
+
+pub use ipc_server::stop_ipc_server;
+
+/// Tie a portal session to its capture. Once the capture stops (or fails to
+/// start), the session is closed so the compositor stops casting, and the
+/// selection the picker hands out is cleared.
+fn end_session_on_stop(
+    capture: Result<(FrameReceiver, StopHandle), String>,
+    stream: ScreencastStream,
+) -> Result<(FrameReceiver, StopHandle), CaptureError> {
+    let stop_flag = capture.as_ref().ok().map(|(_, stop)| stop.clone());
+    // On the app's runtime, which outlives any the capture was started from
+    let session = tauri::async_runtime::spawn(async move {
+        if let Some(stop_flag) = stop_flag {
+            while !stop_flag.load(Ordering::Relaxed) {
+                tokio::time::sleep(SESSION_POLL_INTERVAL).await;
+            }
+        }
+        stream.close().await;
+        if let Some(state) = get_ipc_state() {
+            ipc_server::clear_selection(&state).await;
+            state.read().await.notify(ipc_server::PortalEvent::Ended);
+        }
+    });
+    let mut sessions = SESSIONS.lock().unwrap();
+    sessions.retain(|session| !session.is_finished());
+    sessions.push(session);
+    capture.map_err(CaptureError::PlatformError)
+}
+
 /// Test the portal flow: set selection, call portal, log results.
 /// This is for validating Phase 1 implementation.
 pub async fn test_portal_flow(monitor_id: &str) -> Result<String, String> {
-    eprintln!("[Linux] === Testing Portal Flow ===");
-    eprintln!("[Linux] Monitor ID: {}", monitor_id);
+    tracing::info!(target: "Linux", "=== Testing Portal Flow ===");
+    tracing::info!(target: "Linux", "Monitor ID: {}", monitor_id);
 
     // Step 1: Ensure IPC server is running
     let ipc_state = get_ipc_state().ok_or_else(|| {
         "IPC server not initialized. Call init_ipc_server() first.".to_string()
     })?;
-    eprintln!("[Linux] Step 1: IPC server is running");
+    tracing::info!(target: "Linux", "Step 1: IPC server is running");
 
     // Step 2: Set the selection
     let selection = ipc_server::CaptureSelection {
@@ -69,11 +210,11 @@ pub async fn test_portal_flow(monitor_id: &str) -> Result<String, String> {
         geometry: None,
     };
     ipc_server::set_selection(&ipc_state, selection).await;
-    eprintln!("[Linux] Step 2: Selection set for monitor '{}'", monitor_id);
+    tracing::info!(target: "Linux", "Step 2: Selection set for monitor '{}'", monitor_id);
 
     // Step 3: Create portal client and request capture
-    eprintln!("[Linux] Step 3: Requesting screencast via portal...");
-    eprintln!("[Linux]   (This should trigger the picker service)");
+    tracing::info!(target: "Linux", "Step 3: Requesting screencast via portal...");
+    tracing::info!(target: "Linux", "  (This should trigger the picker service)");
 
     let portal_client = portal_client::PortalClient::new(ipc_state);
     
@@ -85,12 +226,13 @@ pub async fn test_portal_flow(monitor_id: &str) -> Result<String, String> {
                 stream.source_type,
                 stream.size
             );
-            eprintln!("[Linux] Step 4: {}", result);
+            tracing::info!(target: "Linux", "Step 4: {}", result);
+            stream.close().await;
             Ok(result)
         }
         Err(e) => {
             let result = format!("Portal request failed: {}", e);
-            eprintln!("[Linux] Step 4: {}", result);
+            tracing::info!(target: "Linux", "Step 4: {}", result);
             Err(result)
         }
     }
@@ -98,21 +240,33 @@ pub async fn test_portal_flow(monitor_id: &str) -> Result<String, String> {
 
 /// Linux platform capture backend using Hyprland/PipeWire.
 pub struct LinuxBackend {
-    /// IPC server state for communicating with the picker service
+    /// IPC server state for communicating with the picker service, only set
+    /// by [`LinuxBackend::initialize`]
+    #[allow(dead_code)]
     ipc_state: Option<Arc<RwLock<IpcServerState>>>,
+    /// How captures deliver frames
+    options: CaptureOptions,
 }
 
 impl LinuxBackend {
     /// Create a new Linux backend.
     pub fn new() -> Self {
-        Self { ipc_state: None }
+        Self {
+            ipc_state: None,
+            options: CaptureOptions::default(),
+        }
     }
 
     /// Check if running on Hyprland compositor.
     pub fn is_hyprland() -> bool {
         std::env::var("HYPRLAND_INSTANCE_SIGNATURE").is_ok()
     }
+}
 
+/// Backend-owned picker IPC, unused while the app shares the server started
+/// by [`init_ipc_server`].
+#[allow(dead_code)]
+impl LinuxBackend {
     /// Initialize the backend (starts IPC server).
     ///
     /// This should be called once at app startup on Linux.
@@ -183,23 +337,142 @@ impl WindowEnumerator for LinuxBackend {
                 continue;
             }
 
-            // Convert Hyprland address to isize handle
-            // The address is a hex value like "0x5638d0a12345"
-            let handle = client.address.to_string();
-            let handle = handle.trim_start_matches("0x");
-            let handle = isize::from_str_radix(handle, 16).unwrap_or(0);
+            windows.push(window_info(&client));
+        }
 
-            windows.push(WindowInfo {
-                handle,
-                title: client.title.clone(),
-                process_name: client.class.clone(),
-            });
+        Ok(windows)
+    }
+
+    fn add_thumbnails(&self, windows: &mut [WindowInfo]) {
+        if !Self::is_hyprland() {
+            return;
+        }
+        if let Err(e) = Self::copy_thumbnails(windows) {
+            tracing::warn!(target: "Linux", "Window thumbnails unavailable: {}", e);
+        }
+    }
+
+    fn list_window_bounds(&self) -> Result<Vec<(WindowInfo, WindowBounds)>, EnumerationError> {
+        if !Self::is_hyprland() {
+            return Err(EnumerationError::NotImplemented(
+                "Window tracking requires Hyprland compositor".to_string(),
+            ));
+        }
+
+        let monitors = Monitors::get().map_err(|e| {
+            EnumerationError::PlatformError(format!("Failed to get Hyprland monitors: {}", e))
+        })?;
+        let clients = Clients::get().map_err(|e| {
+            EnumerationError::PlatformError(format!("Failed to get Hyprland clients: {}", e))
+        })?;
+
+        let mut windows = Vec::new();
+        for client in clients {
+            // Only windows on a monitor's active workspace are on screen
+            let Some(monitor) = monitors.iter().find(|m| m.id == client.monitor) else {
+                continue;
+            };
+            if !client.mapped || (client.workspace.id != monitor.active_workspace.id && !client.pinned) {
+                continue;
+            }
+
+            // Hyprland positions are in logical layout coordinates, while
+            // captures are in pixels; scale the offset within the monitor
+            let scale = monitor.scale as f64;
+            let x = monitor.x + ((client.at.0 as i32 - monitor.x) as f64 * scale).round() as i32;
+            let y = monitor.y + ((client.at.1 as i32 - monitor.y) as f64 * scale).round() as i32;
+            let bounds = WindowBounds {
+                x,
+                y,
+                width: (client.size.0.max(0) as f64 * scale).round() as u32,
+                height: (client.size.1.max(0) as f64 * scale).round() as u32,
+            };
+            windows.push((window_info(&client), bounds));
         }
 
         Ok(windows)
     }
 }
 
+impl LinuxBackend {
+    /// Copy the on-screen windows among `windows` and set their thumbnails.
+    fn copy_thumbnails(windows: &mut [WindowInfo]) -> Result<(), String> {
+        let monitors = Monitors::get().map_err(|e| format!("Failed to get Hyprland monitors: {}", e))?;
+        let clients = Clients::get().map_err(|e| format!("Failed to get Hyprland clients: {}", e))?;
+
+        // Windows that are on screen, and where
+        let mut shown = Vec::new();
+        for client in clients {
+            let Some(monitor) = monitors.iter().find(|m| m.id == client.monitor) else {
+                continue;
+            };
+            if !client.mapped || (client.workspace.id != monitor.active_workspace.id && !client.pinned) {
+                continue;
+            }
+            let info = window_info(&client);
+            let Some(index) = windows.iter().position(|w| w.handle == info.handle) else {
+                continue;
+            };
+            shown.push((
+                index,
+                screencopy::Area {
+                    output: monitor.name.clone(),
+                    x: client.at.0 as i32 - monitor.x,
+                    y: client.at.1 as i32 - monitor.y,
+                    width: client.size.0 as i32,
+                    height: client.size.1 as i32,
+                },
+            ));
+        }
+
+        let areas: Vec<screencopy::Area> = shown.iter().map(|(_, area)| area.clone()).collect();
+        let images = screencopy::capture_areas(&areas)?;
+        for ((index, _), image) in shown.iter().zip(images) {
+            let Some(image) = image else {
+                continue;
+            };
+            match thumbnail::encode(&image.data, image.width, image.height, image.stride) {
+                Ok(encoded) => windows[*index].thumbnail = Some(encoded),
+                Err(e) => tracing::warn!(target: "Linux", "{}", e),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Model name from the EDID of the monitor on `connector` (e.g. `DP-1`),
+/// which the kernel exposes as `/sys/class/drm/card<N>-<connector>/edid`.
+fn edid_model(connector: &str) -> Option<String> {
+    std::fs::read_dir("/sys/class/drm")
+        .ok()?
+        .flatten()
+        .find(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            name.starts_with("card") && name.split_once('-').is_some_and(|(_, rest)| rest == connector)
+        })
+        .and_then(|entry| std::fs::read(entry.path().join("edid")).ok())
+        .and_then(|edid| edid::model_name(&edid))
+}
+
+/// Window info for a Hyprland client.
+fn window_info(client: &Client) -> WindowInfo {
+    // Convert Hyprland address to isize handle
+    // The address is a hex value like "0x5638d0a12345"
+    let handle = client.address.to_string();
+    let handle = handle.trim_start_matches("0x");
+    let handle = isize::from_str_radix(handle, 16).unwrap_or(0);
+
+    WindowInfo {
+        handle,
+        title: client.title.clone(),
+        process_name: client.class.clone(),
+        pid: client.pid.max(0) as u32,
+        class: client.class.clone(),
+        icon: app_icon::icon_for(&client.class),
+        thumbnail: None,
+    }
+}
+
 impl MonitorEnumerator for LinuxBackend {
     fn list_monitors(&self) -> Result<Vec<MonitorInfo>, EnumerationError> {
         if !Self::is_hyprland() {
@@ -215,23 +488,35 @@ impl MonitorEnumerator for LinuxBackend {
 
         let mut result = Vec::new();
         for monitor in monitors {
-            eprintln!("[Linux] Monitor {}: {}x{} at ({},{}) scale={}", 
+            tracing::info!(target: "Linux", "Monitor {}: {}x{} at ({},{}) scale={}", 
                 monitor.name, monitor.width, monitor.height, monitor.x, monitor.y, monitor.scale);
             
+            let model = edid_model(&monitor.name);
             result.push(MonitorInfo {
                 // Use monitor name as ID (e.g., "DP-1", "HDMI-A-1")
                 id: monitor.name.clone(),
-                // Display name includes description if available
-                name: if monitor.description.is_empty() {
-                    monitor.name.clone()
-                } else {
-                    format!("{} ({})", monitor.name, monitor.description)
+                // Display name leads with the model, or includes the
+                // description if there's no EDID to read it from
+                name: match &model {
+                    Some(model) => format!("{} ({})", model, monitor.name),
+                    None if monitor.description.is_empty() => monitor.name.clone(),
+                    None => format!("{} ({})", monitor.name, monitor.description),
                 },
-                x: monitor.x as i32,
-                y: monitor.y as i32,
+                x: monitor.x,
+                y: monitor.y,
                 width: monitor.width as u32,
                 height: monitor.height as u32,
                 is_primary: monitor.focused,
+                scale_factor: monitor.scale as f64,
+                model,
+                refresh_rate: Some(monitor.refresh_rate as f64).filter(|rate| *rate > 0.0),
+                // Wayland transforms turn counterclockwise
+                rotation: match monitor.transform {
+                    Transforms::Normal90 | Transforms::Flipped90 => 270,
+                    Transforms::Normal180 | Transforms::Flipped180 => 180,
+                    Transforms::Normal270 | Transforms::Flipped270 => 90,
+                    _ => 0,
+                },
             });
         }
 
@@ -239,176 +524,196 @@ impl MonitorEnumerator for LinuxBackend {
     }
 }
 
-impl CaptureBackend for LinuxBackend {
-    fn start_window_capture(
-        &self,
-        window_handle: isize,
-    ) -> Result<(FrameReceiver, StopHandle), CaptureError> {
-        // Get window info to find the address
-        let windows = self.list_windows().map_err(|e| {
-            CaptureError::PlatformError(format!("Failed to list windows: {}", e))
-        })?;
+impl AsyncCaptureBackend for LinuxBackend {
+    fn capture_window(&self, window_handle: isize, options: CaptureOptions) -> CaptureFuture<'_> {
+        Box::pin(async move {
+            // Get window info to find the address
+            let windows = self.list_windows().map_err(|e| {
+                CaptureError::PlatformError(format!("Failed to list windows: {}", e))
+            })?;
         
-        let window = windows.iter().find(|w| w.handle == window_handle).ok_or_else(|| {
-            CaptureError::TargetNotFound(format!("Window with handle {} not found", window_handle))
-        })?;
+            let window = windows.iter().find(|w| w.handle == window_handle).ok_or_else(|| {
+                CaptureError::TargetNotFound(format!("Window with handle {} not found", window_handle))
+            })?;
         
-        // The window handle is the address converted to isize, convert back to hex string
-        let window_address = format!("0x{:x}", window_handle as usize);
+            // The window handle is the address converted to isize, convert back to hex string
+            let window_address = format!("0x{:x}", window_handle as usize);
         
-        eprintln!("[Linux] Starting window capture for {} ({})", window.title, window_address);
+            tracing::info!(target: "Linux", "Starting window capture for {} ({})", window.title, window_address);
         
-        // Get IPC state
-        let ipc_state = get_ipc_state().ok_or_else(|| {
-            CaptureError::PlatformError("IPC server not initialized".to_string())
-        })?;
+            // Get IPC state
+            let ipc_state = get_ipc_state().ok_or_else(|| {
+                CaptureError::PlatformError("IPC server not initialized".to_string())
+            })?;
         
-        // Use block_in_place to run async code from sync context within tokio runtime
-        let stream = tokio::task::block_in_place(|| {
-            let rt = tokio::runtime::Handle::current();
-            let portal_client = portal_client::PortalClient::new(ipc_state);
-            rt.block_on(portal_client.request_window_capture(&window_address))
-        }).map_err(|e| CaptureError::PlatformError(e))?;
+            let mut stream = portal_client::PortalClient::new(ipc_state)
+                .request_window_capture(&window_address)
+                .await
+                .map_err(CaptureError::PlatformError)?;
         
-        eprintln!("[Linux] Portal returned node ID {} for window capture", stream.node_id);
+            tracing::info!(target: "Linux", "Portal returned node ID {} for window capture", stream.node_id);
         
-        // Get window dimensions from Hyprland
-        let (width, height) = stream.size.map(|(w, h)| (w as u32, h as u32))
-            .unwrap_or((1920, 1080)); // Fallback dimensions
+            // Get window dimensions from Hyprland
+            let (width, height) = stream.size.map(|(w, h)| (w as u32, h as u32))
+                .unwrap_or((1920, 1080)); // Fallback dimensions
         
-        // Start PipeWire capture
-        pipewire_capture::start_pipewire_capture(stream.node_id, width, height)
-            .map_err(|e| CaptureError::PlatformError(e))
+            // Start PipeWire capture
+            let capture = pipewire_capture::start_pipewire_capture(stream.node_id, stream.remote.take(), width, height, options);
+            end_session_on_stop(capture, stream)
+        })
     }
 
-    fn start_region_capture(
-        &self,
-        region: CaptureRegion,
-    ) -> Result<(FrameReceiver, StopHandle), CaptureError> {
-        eprintln!("[Linux] Starting region capture for {} ({}x{} at {},{})", 
-            region.monitor_id, region.width, region.height, region.x, region.y);
-        
-        // Validate region bounds
-        if region.width == 0 || region.height == 0 {
-            return Err(CaptureError::InvalidRegion(
-                "Region width and height must be greater than 0".to_string()
-            ));
-        }
-        
-        // Check minimum size (100x100 per spec)
-        if region.width < 100 || region.height < 100 {
-            return Err(CaptureError::InvalidRegion(
-                format!("Region must be at least 100x100 pixels (got {}x{})", region.width, region.height)
-            ));
-        }
+    fn capture_region(&self, region: CaptureRegion, options: CaptureOptions) -> CaptureFuture<'_> {
+        Box::pin(async move {
+            tracing::info!(target: "Linux", "Starting region capture for {} ({}x{} at {},{})", 
+                region.monitor_id, region.width, region.height, region.x, region.y);
         
-        // Get monitor info to validate region and get full dimensions
-        let monitors = self.list_monitors().map_err(|e| {
-            CaptureError::PlatformError(format!("Failed to list monitors: {}", e))
-        })?;
-        
-        let monitor = monitors.iter().find(|m| m.id == region.monitor_id).ok_or_else(|| {
-            CaptureError::TargetNotFound(format!("Monitor '{}' not found", region.monitor_id))
-        })?;
-        
-        // Validate region is within monitor bounds
-        if region.x < 0 || region.y < 0 {
-            return Err(CaptureError::InvalidRegion(
-                format!("Region coordinates cannot be negative ({}, {})", region.x, region.y)
-            ));
-        }
+            // Check minimum size (100x100 per spec)
+            if region.width < 100 || region.height < 100 {
+                return Err(CaptureError::InvalidRegion(
+                    format!("Region must be at least 100x100 pixels (got {}x{})", region.width, region.height)
+                ));
+            }
         
-        let region_x_end = region.x as u32 + region.width;
-        let region_y_end = region.y as u32 + region.height;
+            // Get monitor info to validate region and get full dimensions
+            let monitors = self.list_monitors().map_err(|e| {
+                CaptureError::PlatformError(format!("Failed to list monitors: {}", e))
+            })?;
         
-        if region_x_end > monitor.width || region_y_end > monitor.height {
-            return Err(CaptureError::InvalidRegion(
-                format!("Region extends beyond monitor bounds (region: {}x{} at {},{}, monitor: {}x{})",
-                    region.width, region.height, region.x, region.y, monitor.width, monitor.height)
-            ));
-        }
+            // Regions hanging off the monitor are clamped to it, and ones
+            // spanning monitors to the one showing most of them
+            let layout = DesktopLayout::new(monitors, DESKTOP_SPACE);
+            let region = layout.validate_region(&region)?;
+            let monitor = layout.monitor(&region.monitor_id).ok_or_else(|| {
+                CaptureError::TargetNotFound(format!("Monitor '{}' not found", region.monitor_id))
+            })?;
+
+            // Monitor sizes and frames are in pixels, while the picker
+            // geometry is in Hyprland's logical layout coordinates
+            let geometry = ipc_server::Geometry::from_region(&region, monitor);
         
-        // Get IPC state
-        let ipc_state = get_ipc_state().ok_or_else(|| {
-            CaptureError::PlatformError("IPC server not initialized".to_string())
-        })?;
+            // Get IPC state
+            let ipc_state = get_ipc_state().ok_or_else(|| {
+                CaptureError::PlatformError("IPC server not initialized".to_string())
+            })?;
         
-        // Use block_in_place to run async code from sync context within tokio runtime
-        let monitor_id_clone = region.monitor_id.clone();
-        let region_x = region.x;
-        let region_y = region.y;
-        let region_width = region.width;
-        let region_height = region.height;
+            let mut stream = portal_client::PortalClient::new(ipc_state)
+                .request_region_capture(&region.monitor_id, geometry)
+                .await
+                .map_err(CaptureError::PlatformError)?;
         
-        let stream = tokio::task::block_in_place(|| {
-            let rt = tokio::runtime::Handle::current();
-            let portal_client = portal_client::PortalClient::new(ipc_state);
-            rt.block_on(portal_client.request_region_capture(
-                &monitor_id_clone,
-                region_x,
-                region_y,
-                region_width,
-                region_height,
-            ))
-        }).map_err(|e| CaptureError::PlatformError(e))?;
+            tracing::info!(target: "Linux", "Portal returned node ID {} for region capture", stream.node_id);
         
-        eprintln!("[Linux] Portal returned node ID {} for region capture", stream.node_id);
+            // Use portal-reported dimensions if available, otherwise use monitor dimensions
+            let (capture_width, capture_height) = stream.size
+                .map(|(w, h)| (w as u32, h as u32))
+                .unwrap_or((monitor.width, monitor.height));
         
-        // Use portal-reported dimensions if available, otherwise use monitor dimensions
-        let (capture_width, capture_height) = stream.size
-            .map(|(w, h)| (w as u32, h as u32))
-            .unwrap_or((monitor.width, monitor.height));
+            tracing::info!(target: "Linux", "Capture stream size: {}x{}", capture_width, capture_height);
+            tracing::info!(target: "Linux", "Monitor reported size: {}x{}", monitor.width, monitor.height);
+            tracing::info!(target: "Linux", "Region from UI: {}x{} at {},{}", 
+                region.width, region.height, region.x, region.y);
         
-        eprintln!("[Linux] Capture stream size: {}x{}", capture_width, capture_height);
-        eprintln!("[Linux] Monitor reported size: {}x{}", monitor.width, monitor.height);
-        eprintln!("[Linux] Region from UI: {}x{} at {},{}", 
-            region.width, region.height, region.x, region.y);
+            // Check if the portal already cropped the stream to the region
+            // XDPH does portal-level cropping for region selections
+            let is_precropped = capture_width < monitor.width || capture_height < monitor.height;
         
-        // Check if the portal already cropped the stream to the region
-        // XDPH does portal-level cropping for region selections
-        let is_precropped = capture_width < monitor.width || capture_height < monitor.height;
-        
-        if is_precropped {
-            eprintln!("[Linux] Portal provided pre-cropped stream - using as-is (no app-level cropping)");
+            let capture = if is_precropped {
+                tracing::info!(target: "Linux", "Portal provided pre-cropped stream - using as-is (no app-level cropping)");
             
-            // The stream is already the region - just capture it directly
-            pipewire_capture::start_pipewire_capture(
-                stream.node_id,
-                capture_width,
-                capture_height,
-            )
-            .map_err(|e| CaptureError::PlatformError(e))
-        } else {
-            eprintln!("[Linux] Portal provided full monitor stream - will crop in app");
+                // The stream is already the region - just capture it directly
+                pipewire_capture::start_pipewire_capture(
+                    stream.node_id,
+                    stream.remote.take(),
+                    capture_width,
+                    capture_height,
+                    options,
+                )
+            } else {
+                tracing::info!(target: "Linux", "Portal provided full monitor stream - will crop in app");
             
-            // We got the full monitor, need to crop ourselves
-            // This shouldn't happen with XDPH region format, but handle it just in case
-            let scale_x = capture_width as f64 / monitor.width as f64;
-            let scale_y = capture_height as f64 / monitor.height as f64;
+                // We got the full monitor, need to crop ourselves
+                // This shouldn't happen with XDPH region format, but handle it just in case
+                let scale_x = capture_width as f64 / monitor.width as f64;
+                let scale_y = capture_height as f64 / monitor.height as f64;
             
-            let scaled_x = (region.x as f64 * scale_x).round() as i32;
-            let scaled_y = (region.y as f64 * scale_y).round() as i32;
-            let scaled_width = (region.width as f64 * scale_x).round() as u32;
-            let scaled_height = (region.height as f64 * scale_y).round() as u32;
+                let scaled_x = (region.x as f64 * scale_x).round() as i32;
+                let scaled_y = (region.y as f64 * scale_y).round() as i32;
+                let scaled_width = (region.width as f64 * scale_x).round() as u32;
+                let scaled_height = (region.height as f64 * scale_y).round() as u32;
             
-            eprintln!("[Linux] App-level crop region: {}x{} at {},{}", 
-                scaled_width, scaled_height, scaled_x, scaled_y);
+                tracing::info!(target: "Linux", "App-level crop region: {}x{} at {},{}", 
+                    scaled_width, scaled_height, scaled_x, scaled_y);
             
-            let crop_region = pipewire_capture::CropRegion {
-                x: scaled_x,
-                y: scaled_y,
-                width: scaled_width,
-                height: scaled_height,
-            };
+                let crop_region = pipewire_capture::CropRegion {
+                    x: scaled_x,
+                    y: scaled_y,
+                    width: scaled_width,
+                    height: scaled_height,
+                };
             
-            pipewire_capture::start_pipewire_capture_with_crop(
+                pipewire_capture::start_pipewire_capture_with_crop(
+                    stream.node_id,
+                    stream.remote.take(),
+                    capture_width,
+                    capture_height,
+                    Some(crop_region),
+                    options,
+                )
+            };
+            end_session_on_stop(capture, stream)
+        })
+    }
+
+    fn capture_display(&self, monitor_id: String, width: u32, height: u32, options: CaptureOptions) -> CaptureFuture<'_> {
+        Box::pin(async move {
+            tracing::info!(target: "Linux", "Starting display capture for {} ({}x{})", monitor_id, width, height);
+        
+            // Get IPC state
+            let ipc_state = get_ipc_state().ok_or_else(|| {
+                CaptureError::PlatformError("IPC server not initialized".to_string())
+            })?;
+        
+            let mut stream = portal_client::PortalClient::new(ipc_state)
+                .request_monitor_capture(&monitor_id)
+                .await
+                .map_err(CaptureError::PlatformError)?;
+        
+            tracing::info!(target: "Linux", "Portal returned node ID {} for display capture", stream.node_id);
+        
+            // Use portal-reported dimensions if available, otherwise use provided ones
+            let (capture_width, capture_height) = stream.size
+                .map(|(w, h)| (w as u32, h as u32))
+                .unwrap_or((width, height));
+        
+            // Start PipeWire capture
+            let capture = pipewire_capture::start_pipewire_capture(
                 stream.node_id,
+                stream.remote.take(),
                 capture_width,
                 capture_height,
-                Some(crop_region),
-            )
-            .map_err(|e| CaptureError::PlatformError(e))
-        }
+                options,
+            );
+            end_session_on_stop(capture, stream)
+        })
+    }
+}
+
+/// Captures are started with the portal flow above, blocking the calling
+/// thread until the portal answers. Needs tokio's multi-threaded runtime.
+impl CaptureBackend for LinuxBackend {
+    fn start_window_capture(
+        &self,
+        window_handle: isize,
+    ) -> Result<(FrameReceiver, StopHandle), CaptureError> {
+        block_on_portal(self.capture_window(window_handle, self.options))
+    }
+
+    fn start_region_capture(
+        &self,
+        region: CaptureRegion,
+    ) -> Result<(FrameReceiver, StopHandle), CaptureError> {
+        block_on_portal(self.capture_region(region, self.options))
     }
 
     fn start_display_capture(
@@ -417,39 +722,54 @@ impl CaptureBackend for LinuxBackend {
         width: u32,
         height: u32,
     ) -> Result<(FrameReceiver, StopHandle), CaptureError> {
-        eprintln!("[Linux] Starting display capture for {} ({}x{})", monitor_id, width, height);
-        
-        // Get IPC state
-        let ipc_state = get_ipc_state().ok_or_else(|| {
-            CaptureError::PlatformError("IPC server not initialized".to_string())
-        })?;
-        
-        // Use block_in_place to run async code from sync context within tokio runtime
-        let monitor_id_clone = monitor_id.clone();
-        let stream = tokio::task::block_in_place(|| {
-            let rt = tokio::runtime::Handle::current();
-            let portal_client = portal_client::PortalClient::new(ipc_state);
-            rt.block_on(portal_client.request_monitor_capture(&monitor_id_clone))
-        }).map_err(|e| CaptureError::PlatformError(e))?;
-        
-        eprintln!("[Linux] Portal returned node ID {} for display capture", stream.node_id);
-        
-        // Use portal-reported dimensions if available, otherwise use provided ones
-        let (capture_width, capture_height) = stream.size
-            .map(|(w, h)| (w as u32, h as u32))
-            .unwrap_or((width, height));
-        
-        // Start PipeWire capture
-        pipewire_capture::start_pipewire_capture(stream.node_id, capture_width, capture_height)
-            .map_err(|e| CaptureError::PlatformError(e))
+        block_on_portal(self.capture_display(monitor_id, width, height, self.options))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        // Captures go through the Hyprland picker. The portal says which
+        // cursor modes and source types it offers once asked at startup;
+        // without an answer, it's taken to embed the cursor and share
+        // windows. Metadata isn't read from PipeWire buffers,
+        // so it's left out
+        let hyprland = Self::is_hyprland();
+        let portal = portal_client::portal_info();
+        let shares_windows = portal.is_none_or(|info| info.source_types.contains(SourceType::Window));
+        let cursor_modes = match portal {
+            Some(info) => info
+                .cursor_modes
+                .iter()
+                .filter_map(|mode| match mode {
+                    CursorMode::Hidden => Some("hidden"),
+                    CursorMode::Embedded => Some("embedded"),
+                    CursorMode::Metadata => None,
+                })
+                .collect(),
+            None => vec!["embedded"],
+        };
+        Capabilities {
+            window_capture: hyprland && shares_windows,
+            region_capture: hyprland,
+            display_capture: hyprland,
+            app_audio: hyprland && app_audio::available(),
+            deep_color: hyprland,
+            alpha: hyprland,
+            cursor_modes: cursor_modes.into_iter().map(String::from).collect(),
+            ..Capabilities::default()
+        }
+    }
+
+    fn set_options(&mut self, options: CaptureOptions) {
+        self.options = options;
     }
 }
 
 impl HighlightProvider for LinuxBackend {
-    fn show_highlight(&self, _x: i32, _y: i32, _width: i32, _height: i32) {
-        // Highlight is less important on Wayland - portal handles selection
-        // Could potentially use layer-shell in the future
-        eprintln!("Linux display highlight not yet implemented");
+    fn show_highlight(&self, x: i32, y: i32, width: i32, height: i32) {
+        highlight::show_highlight(x, y, width, height);
+    }
+
+    fn hide_highlight(&self) {
+        crate::capture::highlight::hide();
     }
 }
 
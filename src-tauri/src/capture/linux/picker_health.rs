@@ -0,0 +1,287 @@
+//! Health of the portal picker, and restarting it.
+//!
+//! Screen sharing on Hyprland goes through a chain: xdg-desktop-portal-hyprland
+//! (XDPH) runs `screen-recorder-picker` for each request, and the picker asks
+//! this app over IPC what to share. If any link is missing the request just
+//! fails, so the app checks each one:
+//!
+//! 1. The picker binary is installed
+//! 2. XDPH's config names it as the picker
+//! 3. XDPH is running
+//! 4. The app's IPC server is accepting connections
+//! 5. The picker, run with `--ping`, reaches the app and gets an answer
+//!
+//! [`run_watchdog`] repeats the check in the background, restarting the IPC
+//! server if it has died, and emits [`HEALTH_EVENT`] when the result
+//! changes. [`restart`] restarts both the IPC server and XDPH.
+//!
+//! From inside a Flatpak, XDPH and the picker are on the host, so they're
+//! looked for and run through `flatpak-spawn --host`.
+
+use super::get_ipc_state;
+use super::ipc_server;
+use crate::sandbox;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+
+/// Event emitted with the [`PickerHealth`] when it changes.
+pub const HEALTH_EVENT: &str = "picker-health";
+
+/// How often the watchdog checks.
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Longest to wait for the picker to answer a ping.
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub(super) const PICKER_BINARY: &str = "screen-recorder-picker";
+const PORTAL_BINARY: &str = "xdg-desktop-portal-hyprland";
+const PORTAL_UNIT: &str = "xdg-desktop-portal-hyprland.service";
+
+/// The first link in the chain that's broken, if any.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PickerState {
+    NotInstalled,
+    NotConfigured,
+    PortalNotRunning,
+    IpcServerDown,
+    Unresponsive,
+    Healthy,
+}
+
+/// Result of a health check.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PickerHealth {
+    pub state: PickerState,
+    /// Where the picker binary was found
+    pub picker_path: Option<PathBuf>,
+    /// Whether XDPH's config names the picker
+    pub configured: bool,
+    pub portal_running: bool,
+    pub ipc_server_running: bool,
+    /// What went wrong pinging the app through the picker, if it was pinged
+    pub ping_error: Option<String>,
+    /// When XDPH last ran the picker for a request (RFC 3339)
+    pub last_request: Option<String>,
+    /// What to do about the first problem, if any
+    pub problem: Option<String>,
+}
+
+/// Check each link between XDPH and the app.
+pub async fn check() -> PickerHealth {
+    let picker_path = find_picker().await;
+    let configured = xdph_config_path()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .is_some_and(|config| names_picker(&config));
+    let portal_running = process_running(PORTAL_BINARY).await;
+    let ipc_server_running = ipc_server::server_running();
+    let ping_error = match picker_path {
+        Some(ref path) if ipc_server_running => ping(path).await.err(),
+        _ => None,
+    };
+    let last_request = match get_ipc_state() {
+        Some(state) => state.read().await.last_request.clone(),
+        None => None,
+    };
+
+    let (state, problem) = if picker_path.is_none() {
+        (PickerState::NotInstalled, Some(format!("{} isn't installed in PATH", PICKER_BINARY)))
+    } else if !configured {
+        (
+            PickerState::NotConfigured,
+            Some(format!("Set custom_picker_binary = {} in ~/.config/hypr/xdph.conf", PICKER_BINARY)),
+        )
+    } else if !portal_running {
+        (PickerState::PortalNotRunning, Some(format!("{} isn't running", PORTAL_BINARY)))
+    } else if !ipc_server_running {
+        (PickerState::IpcServerDown, Some("The app isn't listening for the picker".to_string()))
+    } else if let Some(ref e) = ping_error {
+        (PickerState::Unresponsive, Some(format!("The picker couldn't reach the app: {}", e)))
+    } else {
+        (PickerState::Healthy, None)
+    };
+    PickerHealth {
+        state,
+        picker_path,
+        configured,
+        portal_running,
+        ipc_server_running,
+        ping_error,
+        last_request,
+        problem,
+    }
+}
+
+/// Restart the IPC server and XDPH, then check again.
+pub async fn restart() -> Result<PickerHealth, String> {
+    let state = get_ipc_state().ok_or("The IPC server was never started")?;
+    ipc_server::restart_ipc_server(&state)?;
+    tracing::info!(target: "Picker", "Restarted the IPC server");
+
+    let output = sandbox::host_command("systemctl")
+        .args(["--user", "restart", PORTAL_UNIT])
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run systemctl: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to restart {}: {}",
+            PORTAL_UNIT,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    tracing::info!(target: "Picker", "Restarted {}", PORTAL_UNIT);
+    // Give XDPH a moment to register with the portal again
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    Ok(check().await)
+}
+
+/// Check the picker's health every [`CHECK_INTERVAL`] until the app shuts
+/// down, restarting the IPC server if it has stopped. Other compositors'
+/// portals don't use the picker, so this only runs on Hyprland.
+pub async fn run_watchdog(app: AppHandle) {
+    if std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_none() {
+        return;
+    }
+    let shutdown = crate::shutdown::token();
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    let mut last: Option<PickerHealth> = None;
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.cancelled() => return,
+        }
+
+        if !ipc_server::server_running() {
+            if let Some(state) = get_ipc_state() {
+                tracing::info!(target: "Picker", "The IPC server stopped, restarting it");
+                if let Err(e) = ipc_server::restart_ipc_server(&state) {
+                    tracing::error!(target: "Picker", "{}", e);
+                }
+            }
+        }
+
+        let health = check().await;
+        // The last request time changing alone isn't news
+        let changed = last.as_ref().is_none_or(|last| {
+            (last.state, &last.problem) != (health.state, &health.problem)
+        });
+        if changed {
+            if let Some(ref problem) = health.problem {
+                tracing::warn!(target: "Picker", "Unhealthy: {}", problem);
+            }
+            let _ = app.emit(HEALTH_EVENT, &health);
+        }
+        last = Some(health);
+    }
+}
+
+/// Run the picker's health check, which pings the app over IPC.
+async fn ping(picker: &Path) -> Result<(), String> {
+    let run = sandbox::host_command(&picker.to_string_lossy())
+        .arg("--ping")
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(PING_TIMEOUT, run)
+        .await
+        .map_err(|_| "It didn't answer".to_string())?
+        .map_err(|e| format!("Failed to run it: {}", e))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let message = stderr.trim().trim_start_matches("[screen-recorder-picker]").trim();
+    Err(if message.is_empty() {
+        format!("It exited with {}", output.status)
+    } else {
+        message.to_string()
+    })
+}
+
+/// The picker binary, found in PATH or where the install instructions put it.
+async fn find_picker() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let fallbacks = home
+        .map(|home| home.join(".local/bin"))
+        .into_iter()
+        .chain([PathBuf::from("/usr/local/bin")])
+        .map(|dir| dir.join(PICKER_BINARY));
+    if sandbox::flatpak_app_id().is_some() {
+        // The sandbox's PATH and /usr aren't the host's, so ask the host
+        let script = format!("command -v {}", PICKER_BINARY);
+        let output = sandbox::host_command("sh").args(["-c", &script]).output().await.ok()?;
+        let found = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if output.status.success() && !found.is_empty() {
+            return Some(PathBuf::from(found));
+        }
+        for path in fallbacks {
+            let test = sandbox::host_command("test").arg("-x").arg(&path).status().await;
+            if test.is_ok_and(|status| status.success()) {
+                return Some(path);
+            }
+        }
+        return None;
+    }
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(PICKER_BINARY))
+        .chain(fallbacks)
+        .find(|path| path.is_file())
+}
+
+/// XDPH's config file. A Flatpak's `XDG_CONFIG_HOME` is its own, not the
+/// host's, so there it's always `~/.config`.
+pub(super) fn xdph_config_path() -> Option<PathBuf> {
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|_| sandbox::flatpak_app_id().is_none())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config.join("hypr/xdph.conf"))
+}
+
+/// Whether an XDPH config sets the picker as `custom_picker_binary`.
+pub(super) fn names_picker(config: &str) -> bool {
+    config.lines().any(|line| {
+        let line = line.split('#').next().unwrap_or_default();
+        line.split_once('=').is_some_and(|(key, value)| {
+            key.trim() == "custom_picker_binary"
+                && value
+                    .split_whitespace()
+                    .next()
+                    .is_some_and(|binary| Path::new(binary).ends_with(PICKER_BINARY))
+        })
+    })
+}
+
+/// Whether a process running `binary` exists.
+async fn process_running(binary: &str) -> bool {
+    if sandbox::flatpak_app_id().is_some() {
+        // Host processes aren't in the sandbox's /proc
+        let found = sandbox::host_command("pgrep").args(["-f", binary]).output().await;
+        return found.is_ok_and(|output| output.status.success());
+    }
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return false;
+    };
+    entries.filter_map(Result::ok).any(|entry| {
+        std::fs::read(entry.path().join("cmdline")).is_ok_and(|cmdline| {
+            let program = cmdline.split(|&byte| byte == 0).next().unwrap_or_default();
+            Path::new(&*String::from_utf8_lossy(program)).ends_with(binary)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_picker() {
+        let config = "screencopy {\n    # custom_picker_binary = other\n    custom_picker_binary = /home/a/.local/bin/screen-recorder-picker --fallback=chooser\n}\n";
+        assert!(names_picker(config));
+        assert!(!names_picker("screencopy {\n    # custom_picker_binary = screen-recorder-picker\n}\n"));
+        assert!(!names_picker("screencopy {\n    custom_picker_binary = hyprland-share-picker\n}\n"));
+    }
+}
@@ -0,0 +1,149 @@
+//! Installing the portal picker for xdg-desktop-portal-hyprland.
+//!
+//! The picker isn't a service: XDPH starts it for each screencast request
+//! and it exits once it has answered, so there's no unit or D-Bus name to
+//! register. Installing it takes two things, which [`install`] does and
+//! [`uninstall`] undoes:
+//!
+//! - The binary, copied to `~/.local/bin`
+//! - A `custom_picker_binary` line in the `screencopy` section of
+//!   `~/.config/hypr/xdph.conf`; a picker named there before is commented
+//!   out, and the file is backed up the first time it's changed
+//!
+//! XDPH only reads its config at startup, so it's restarted after either.
+
+use super::picker_health::{self, names_picker, xdph_config_path, PickerHealth, PICKER_BINARY};
+use std::path::{Path, PathBuf};
+
+/// Install the picker binary at `source`, or if unset, the one next to the
+/// app's executable, and point XDPH at it.
+pub async fn install(source: Option<PathBuf>) -> Result<PickerHealth, String> {
+    let source = match source {
+        Some(source) => source,
+        None => bundled_picker().ok_or(
+            "No picker binary found next to the app; build it with `cargo build --release` in src-picker and choose it",
+        )?,
+    };
+    if !source.is_file() {
+        return Err(format!("{} isn't a file", source.display()));
+    }
+    let target = install_path()?;
+    if source != target {
+        std::fs::create_dir_all(target.parent().unwrap_or(Path::new("/")))
+            .map_err(|e| format!("Failed to create {}: {}", target.display(), e))?;
+        std::fs::copy(&source, &target)
+            .map_err(|e| format!("Failed to copy the picker to {}: {}", target.display(), e))?;
+        tracing::info!(target: "Picker", "Installed {}", target.display());
+    }
+
+    update_config(|config| set_picker(config, Some(&target.to_string_lossy())))?;
+    picker_health::restart().await
+}
+
+/// Stop XDPH using the picker, and remove the binary [`install`] copied.
+pub async fn uninstall() -> Result<PickerHealth, String> {
+    update_config(|config| set_picker(config, None))?;
+    let target = install_path()?;
+    match std::fs::remove_file(&target) {
+        Ok(()) => tracing::info!(target: "Picker", "Removed {}", target.display()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(format!("Failed to remove {}: {}", target.display(), e)),
+    }
+    picker_health::restart().await
+}
+
+/// Where the picker is installed: `~/.local/bin`.
+fn install_path() -> Result<PathBuf, String> {
+    let home = std::env::var_os("HOME").ok_or("HOME isn't set")?;
+    Ok(PathBuf::from(home).join(".local/bin").join(PICKER_BINARY))
+}
+
+/// The picker binary shipped alongside the app, if there is one.
+fn bundled_picker() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    Some(exe.parent()?.join(PICKER_BINARY)).filter(|path| path.is_file())
+}
+
+/// Rewrite XDPH's config with `change`, backing it up first if there's no
+/// backup yet.
+fn update_config(change: impl FnOnce(&str) -> String) -> Result<(), String> {
+    let path = xdph_config_path().ok_or("Couldn't find the config directory")?;
+    let config = std::fs::read_to_string(&path).unwrap_or_default();
+    let updated = change(&config);
+    if updated == config {
+        return Ok(());
+    }
+    let backup = path.with_extension("conf.bak");
+    if path.exists() && !backup.exists() {
+        std::fs::copy(&path, &backup).map_err(|e| format!("Failed to back up {}: {}", path.display(), e))?;
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    std::fs::write(&path, updated).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    tracing::info!(target: "Picker", "Updated {}", path.display());
+    Ok(())
+}
+
+/// `config` with the picker set to `binary`, or with `None`, no longer set.
+fn set_picker(config: &str, binary: Option<&str>) -> String {
+    let mut lines: Vec<String> = config
+        .lines()
+        .filter(|line| !names_picker(line))
+        .map(String::from)
+        .collect();
+    if let Some(binary) = binary {
+        // XDPH takes one picker, so another named here is commented out
+        for line in lines.iter_mut() {
+            let setting = line.split('#').next().unwrap_or_default();
+            if setting.split_once('=').is_some_and(|(key, _)| key.trim() == "custom_picker_binary") {
+                let indent = line.len() - line.trim_start().len();
+                line.insert_str(indent, "# ");
+            }
+        }
+        let entry = format!("    custom_picker_binary = {}", binary);
+        let section = lines.iter().position(|line| {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            line.starts_with("screencopy") && line.ends_with('{')
+        });
+        match section {
+            Some(at) => lines.insert(at + 1, entry),
+            None => {
+                if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                    lines.push(String::new());
+                }
+                lines.extend(["screencopy {".to_string(), entry, "}".to_string()]);
+            }
+        }
+    }
+    if lines.is_empty() {
+        return String::new();
+    }
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_picker() {
+        let config = "screencopy {\n    allow_token_by_default = true\n    custom_picker_binary = hyprland-share-picker\n}\n";
+        let installed = set_picker(config, Some("/home/a/.local/bin/screen-recorder-picker"));
+        assert_eq!(
+            installed,
+            "screencopy {\n    custom_picker_binary = /home/a/.local/bin/screen-recorder-picker\n    allow_token_by_default = true\n    # custom_picker_binary = hyprland-share-picker\n}\n"
+        );
+        // Installing again changes nothing
+        assert_eq!(set_picker(&installed, Some("/home/a/.local/bin/screen-recorder-picker")), installed);
+        assert_eq!(
+            set_picker(&installed, None),
+            "screencopy {\n    allow_token_by_default = true\n    # custom_picker_binary = hyprland-share-picker\n}\n"
+        );
+
+        assert_eq!(
+            set_picker("general {\n}\n", Some("screen-recorder-picker")),
+            "general {\n}\n\nscreencopy {\n    custom_picker_binary = screen-recorder-picker\n}\n"
+        );
+    }
+}
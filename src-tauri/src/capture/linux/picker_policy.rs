@@ -0,0 +1,67 @@
+//! Which applications may share the screen through the portal picker.
+//!
+//! The picker answers every screencast request on the system, not just this
+//! app's, so the policy decides per requesting app: always allow, always
+//! deny, or ask this app, which shares its current selection. The policy is
+//! kept here and handed to the picker over IPC (see `ipc_server`); the
+//! picker keeps a copy so it still applies while this app isn't running.
+//!
+//! XDPH doesn't tell the picker which app is asking, so this app finds out
+//! from the session bus (see `portal_requests`) and tells the picker along
+//! with the policy; requests from apps it can't identify fall under the
+//! default, as do all requests while it can't watch the bus (in a Flatpak).
+//! This app's own requests, told apart by the process they came from, are
+//! always allowed.
+//!
+//! The picker also remembers approvals for apps that ask for their
+//! screencast to persist. Revoking an app's approvals records the time
+//! here, and the picker drops approvals given before it.
+
+use crate::config::{load_json, save_json};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const SETTINGS_FILE: &str = "picker_policy.json";
+
+/// What the picker does when an app requests a screencast.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AppPolicy {
+    /// Share without asking: the main app's selection, or the focused
+    /// monitor while it isn't running
+    Allow,
+    /// Refuse every request
+    Deny,
+    /// Share this app's selection, or follow the picker's fallback while
+    /// it isn't running
+    #[default]
+    Ask,
+}
+
+/// The picker's policy for apps requesting screencasts.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PickerPolicy {
+    /// Policy for apps without an entry, or that can't be told apart
+    pub default: AppPolicy,
+    /// Policy by app ID, e.g. "org.mozilla.firefox"
+    pub apps: BTreeMap<String, AppPolicy>,
+    /// When each app's remembered approvals were revoked, in milliseconds
+    /// since the Unix epoch
+    pub revoked: BTreeMap<String, u64>,
+}
+
+impl PickerPolicy {
+    /// Load the saved policy.
+    pub fn load() -> Self {
+        load_json(SETTINGS_FILE)
+    }
+
+    /// Check and save the policy.
+    pub fn save(&self) -> Result<(), String> {
+        if self.apps.keys().any(|app_id| app_id.trim().is_empty()) {
+            return Err("App IDs in the picker policy can't be empty".to_string());
+        }
+        save_json(SETTINGS_FILE, self)
+    }
+}
@@ -11,16 +11,62 @@
 //! # Window Close Handling  
 //! When the captured window is closed, the PipeWire stream transitions
 //! to an error state. This triggers the stop flag and cleanly exits capture.
-
-use crate::capture::types::{CapturedFrame, FrameReceiver, StopHandle};
+//!
+//! # DMA-BUF Buffers
+//! Two formats are offered: one with a linear DMA-BUF modifier and one without.
+//! Compositors that support it then hand us the GPU buffer directly, which is
+//! mapped and read once, instead of first copying every frame into shared
+//! memory. Tiled modifiers aren't offered because they can't be read linearly
+//! through a CPU mapping. Encoding still happens in the FFmpeg process, so the
+//! frame is copied out of the mapping; handing the buffer to a hardware
+//! encoder without any copy would need an in-process encoder.
+//!
+//! # Deep Color
+//! When deep color is requested, packed 10-bit RGB formats are offered ahead
+//! of the 8-bit ones. Compositors that render at 10 bits then deliver them,
+//! and frames are tagged with the matching [`PixelFormat`].
+
+use crate::capture::queue::{frame_channel, FrameSender};
+use crate::capture::types::{CaptureOptions, CapturedFrame, ColorSpace, FrameReceiver, PixelFormat, StopHandle};
+use std::os::fd::OwnedFd;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::time::Instant;
 
 use pipewire as pw;
 use pw::spa;
 use spa::pod::Pod;
 
+/// DRM_FORMAT_MOD_LINEAR: the only DMA-BUF layout that can be read directly
+/// through a CPU mapping.
+const DRM_FORMAT_MOD_LINEAR: i64 = 0;
+
+/// Packed 10-bit formats, offered first when deep color is requested.
+const DEEP_FORMATS: [spa::param::video::VideoFormat; 2] = [
+    spa::param::video::VideoFormat::xRGB_210LE,
+    spa::param::video::VideoFormat::xBGR_210LE,
+];
+
+/// 8-bit formats, all delivered as BGRA.
+const FORMATS: [spa::param::video::VideoFormat; 8] = [
+    spa::param::video::VideoFormat::BGRx,
+    spa::param::video::VideoFormat::BGRA,
+    spa::param::video::VideoFormat::RGBx,
+    spa::param::video::VideoFormat::RGBA,
+    spa::param::video::VideoFormat::xBGR,
+    spa::param::video::VideoFormat::ABGR,
+    spa::param::video::VideoFormat::xRGB,
+    spa::param::video::VideoFormat::ARGB,
+];
+
+/// Whether the current capture is receiving DMA-BUF buffers.
+static DMABUF_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Whether the active capture is using the DMA-BUF (zero-copy) buffer path.
+pub fn dmabuf_active() -> bool {
+    DMABUF_ACTIVE.load(Ordering::Relaxed)
+}
+
 /// Region specification for cropping frames.
 #[derive(Debug, Clone, Copy)]
 pub struct CropRegion {
@@ -34,52 +80,61 @@ pub struct CropRegion {
 ///
 /// # Arguments
 /// * `node_id` - The PipeWire node ID returned by the portal
+/// * `remote` - PipeWire connection opened by the portal, if any
 /// * `width` - Expected frame width
 /// * `height` - Expected frame height
+/// * `options` - Whether to prefer 10-bit formats or keep alpha
 ///
 /// # Returns
 /// A tuple of (frame_receiver, stop_handle) for receiving frames and stopping capture.
 pub fn start_pipewire_capture(
     node_id: u32,
+    remote: Option<OwnedFd>,
     width: u32,
     height: u32,
+    options: CaptureOptions,
 ) -> Result<(FrameReceiver, StopHandle), String> {
-    start_pipewire_capture_with_crop(node_id, width, height, None)
+    start_pipewire_capture_with_crop(node_id, remote, width, height, None, options)
 }
 
 /// Start capturing from a PipeWire stream with optional cropping.
 ///
 /// # Arguments
 /// * `node_id` - The PipeWire node ID returned by the portal
+/// * `remote` - PipeWire connection opened by the portal, if any
 /// * `width` - Expected frame width (full stream width if cropping)
 /// * `height` - Expected frame height (full stream height if cropping)
 /// * `crop_region` - Optional region to crop from the stream
+/// * `options` - Whether to prefer 10-bit formats or keep alpha
 ///
 /// # Returns
 /// A tuple of (frame_receiver, stop_handle) for receiving frames and stopping capture.
 pub fn start_pipewire_capture_with_crop(
     node_id: u32,
+    remote: Option<OwnedFd>,
     width: u32,
     height: u32,
     crop_region: Option<CropRegion>,
+    options: CaptureOptions,
 ) -> Result<(FrameReceiver, StopHandle), String> {
-    let (frame_tx, frame_rx) = mpsc::channel::<CapturedFrame>(2);
+    let (frame_tx, frame_rx) = frame_channel(2);
     let stop_flag = Arc::new(AtomicBool::new(false));
+    DMABUF_ACTIVE.store(false, Ordering::Relaxed);
     let stop_flag_clone = stop_flag.clone();
 
     if let Some(crop) = crop_region {
-        eprintln!("[PipeWire] Starting capture thread for node {} ({}x{}) with crop region ({}x{} at {},{}", 
+        tracing::info!(target: "PipeWire", "Starting capture thread for node {} ({}x{}) with crop region ({}x{} at {},{}", 
             node_id, width, height, crop.width, crop.height, crop.x, crop.y);
     } else {
-        eprintln!("[PipeWire] Starting capture thread for node {} ({}x{})", node_id, width, height);
+        tracing::info!(target: "PipeWire", "Starting capture thread for node {} ({}x{})", node_id, width, height);
     }
 
     // Spawn the PipeWire capture thread
     std::thread::spawn(move || {
-        if let Err(e) = run_pipewire_capture(node_id, width, height, crop_region, frame_tx, stop_flag_clone) {
-            eprintln!("[PipeWire] Capture error: {}", e);
+        if let Err(e) = run_pipewire_capture(node_id, remote, width, height, crop_region, options, frame_tx, stop_flag_clone) {
+            tracing::error!(target: "PipeWire", "Capture error: {}", e);
         }
-        eprintln!("[PipeWire] Capture thread exited");
+        tracing::info!(target: "PipeWire", "Capture thread exited");
     });
 
     Ok((frame_rx, stop_flag))
@@ -90,7 +145,7 @@ struct StreamData {
     width: u32,
     height: u32,
     format: spa::param::video::VideoInfoRaw,
-    frame_tx: mpsc::Sender<CapturedFrame>,
+    frame_tx: FrameSender,
     stop_flag: Arc<AtomicBool>,
     /// Track if we've received any frames (for debugging)
     frames_received: u64,
@@ -98,18 +153,23 @@ struct StreamData {
     format_changes: u32,
     /// Optional region to crop from the stream
     crop_region: Option<CropRegion>,
+    /// Keep the alpha channel, making frames in padded formats opaque
+    alpha: bool,
 }
 
 /// Run the PipeWire main loop and capture frames.
+#[allow(clippy::too_many_arguments)]
 fn run_pipewire_capture(
     node_id: u32,
+    remote: Option<OwnedFd>,
     width: u32,
     height: u32,
     crop_region: Option<CropRegion>,
-    frame_tx: mpsc::Sender<CapturedFrame>,
+    options: CaptureOptions,
+    frame_tx: FrameSender,
     stop_flag: Arc<AtomicBool>,
 ) -> Result<(), String> {
-    eprintln!("[PipeWire] Initializing PipeWire...");
+    tracing::info!(target: "PipeWire", "Initializing PipeWire...");
     
     pw::init();
 
@@ -119,11 +179,19 @@ fn run_pipewire_capture(
     let context = pw::context::Context::new(&mainloop)
         .map_err(|e| format!("Failed to create context: {}", e))?;
     
-    let core = context
-        .connect(None)
-        .map_err(|e| format!("Failed to connect to PipeWire daemon: {}", e))?;
+    // A sandbox may hide the daemon's socket, leaving only the connection
+    // the portal opened; outside one, that's the fallback
+    let core = match remote {
+        Some(fd) if crate::sandbox::detect().is_some() => context.connect_fd(fd, None),
+        Some(fd) => context.connect(None).or_else(|e| {
+            tracing::warn!(target: "PipeWire", "Failed to connect to the daemon ({}), using the portal's remote", e);
+            context.connect_fd(fd, None)
+        }),
+        None => context.connect(None),
+    }
+    .map_err(|e| format!("Failed to connect to PipeWire daemon: {}", e))?;
 
-    eprintln!("[PipeWire] Connected to PipeWire daemon");
+    tracing::info!(target: "PipeWire", "Connected to PipeWire daemon");
 
     // Create stream
     let stream = pw::stream::Stream::new(
@@ -147,6 +215,7 @@ fn run_pipewire_capture(
         frames_received: 0,
         format_changes: 0,
         crop_region,
+        alpha: options.alpha,
     };
 
     // Clone mainloop for stop check
@@ -157,12 +226,12 @@ fn run_pipewire_capture(
     let _listener = stream
         .add_local_listener_with_user_data(stream_data)
         .state_changed(move |_, _, old, new| {
-            eprintln!("[PipeWire] Stream state: {:?} -> {:?}", old, new);
+            tracing::info!(target: "PipeWire", "Stream state: {:?} -> {:?}", old, new);
             
             match new {
                 pw::stream::StreamState::Error(msg) => {
                     // Stream error - likely window closed or capture target unavailable
-                    eprintln!("[PipeWire] Stream error (target may have closed): {}", msg);
+                    tracing::warn!(target: "PipeWire", "Stream error (target may have closed): {}", msg);
                     stop_flag_for_state.store(true, Ordering::SeqCst);
                     if let Some(mainloop) = mainloop_weak.upgrade() {
                         mainloop.quit();
@@ -170,22 +239,22 @@ fn run_pipewire_capture(
                 }
                 pw::stream::StreamState::Unconnected => {
                     // Stream disconnected - capture source gone
-                    eprintln!("[PipeWire] Stream disconnected - stopping capture");
+                    tracing::warn!(target: "PipeWire", "Stream disconnected - stopping capture");
                     stop_flag_for_state.store(true, Ordering::SeqCst);
                     if let Some(mainloop) = mainloop_weak.upgrade() {
                         mainloop.quit();
                     }
                 }
                 pw::stream::StreamState::Streaming => {
-                    eprintln!("[PipeWire] Stream is now streaming");
+                    tracing::info!(target: "PipeWire", "Stream is now streaming");
                 }
                 pw::stream::StreamState::Paused => {
-                    eprintln!("[PipeWire] Stream paused");
+                    tracing::info!(target: "PipeWire", "Stream paused");
                 }
                 _ => {}
             }
         })
-        .param_changed(|_, user_data, id, param| {
+        .param_changed(|stream, user_data, id, param| {
             let Some(param) = param else { return };
             if id != pw::spa::param::ParamType::Format.as_raw() {
                 return;
@@ -209,7 +278,7 @@ fn run_pipewire_capture(
             
             // Parse video format info
             if let Err(e) = user_data.format.parse(param) {
-                eprintln!("[PipeWire] Failed to parse video format: {:?}", e);
+                tracing::error!(target: "PipeWire", "Failed to parse video format: {:?}", e);
                 return;
             }
             
@@ -220,21 +289,34 @@ fn run_pipewire_capture(
             
             // Log format info
             if user_data.format_changes == 1 {
-                eprintln!("[PipeWire] Initial video format:");
+                tracing::info!(target: "PipeWire", "Initial video format:");
             } else {
-                eprintln!("[PipeWire] Format renegotiated (window resize detected):");
-                eprintln!("  old size: {}x{}", old_width, old_height);
+                tracing::info!(target: "PipeWire", "Format renegotiated (window resize detected):");
+                tracing::info!(target: "PipeWire", "  old size: {}x{}", old_width, old_height);
+            }
+            tracing::info!(target: "PipeWire", "  format: {:?}", user_data.format.format());
+            tracing::info!(target: "PipeWire", "  size: {}x{}", user_data.width, user_data.height);
+            tracing::info!(target: "PipeWire", "  framerate: {}/{}", user_data.format.framerate().num, user_data.format.framerate().denom);
+            tracing::info!(target: "PipeWire", "  modifier: {:#x}", user_data.format.modifier());
+
+            // Tell the producer which buffer types we can map
+            match build_buffers_pod() {
+                Ok(values) => {
+                    if let Some(pod) = Pod::from_bytes(&values) {
+                        if let Err(e) = stream.update_params(&mut [pod]) {
+                            tracing::error!(target: "PipeWire", "Failed to update buffer params: {}", e);
+                        }
+                    }
+                }
+                Err(e) => tracing::warn!(target: "PipeWire", "{}", e),
             }
-            eprintln!("  format: {:?}", user_data.format.format());
-            eprintln!("  size: {}x{}", user_data.width, user_data.height);
-            eprintln!("  framerate: {}/{}", user_data.format.framerate().num, user_data.format.framerate().denom);
         })
         .process(|stream, user_data| {
             if user_data.stop_flag.load(Ordering::Relaxed) {
                 // Only log once when stop flag is first detected
                 static LOGGED_STOP: AtomicBool = AtomicBool::new(false);
                 if !LOGGED_STOP.swap(true, Ordering::Relaxed) {
-                    eprintln!("[PipeWire] process: stop flag is set, skipping remaining frames");
+                    tracing::warn!(target: "PipeWire", "process: stop flag is set, skipping remaining frames");
                 }
                 return;
             }
@@ -244,7 +326,7 @@ fn run_pipewire_capture(
                     user_data.frames_received += 1;
                     // Log periodically instead of every frame
                     if user_data.frames_received == 1 || user_data.frames_received % 100 == 0 {
-                        eprintln!("[PipeWire] Processing frame #{}", user_data.frames_received);
+                        tracing::debug!(target: "PipeWire", "Processing frame #{}", user_data.frames_received);
                     }
                     process_buffer(&mut buffer, user_data);
                 }
@@ -256,65 +338,15 @@ fn run_pipewire_capture(
         .register()
         .map_err(|e| format!("Failed to register stream listener: {}", e))?;
 
-    // Request video format with preference for formats we can handle
-    // This helps negotiate a linear buffer format instead of tiled DMA-BUF
-    let obj = pw::spa::pod::object!(
-        pw::spa::utils::SpaTypes::ObjectParamFormat,
-        pw::spa::param::ParamType::EnumFormat,
-        pw::spa::pod::property!(
-            pw::spa::param::format::FormatProperties::MediaType,
-            Id,
-            pw::spa::param::format::MediaType::Video
-        ),
-        pw::spa::pod::property!(
-            pw::spa::param::format::FormatProperties::MediaSubtype,
-            Id,
-            pw::spa::param::format::MediaSubtype::Raw
-        ),
-        pw::spa::pod::property!(
-            pw::spa::param::format::FormatProperties::VideoFormat,
-            Choice,
-            Enum,
-            Id,
-            pw::spa::param::video::VideoFormat::BGRx,
-            pw::spa::param::video::VideoFormat::BGRx,
-            pw::spa::param::video::VideoFormat::BGRA,
-            pw::spa::param::video::VideoFormat::RGBx,
-            pw::spa::param::video::VideoFormat::RGBA,
-            pw::spa::param::video::VideoFormat::xBGR,
-            pw::spa::param::video::VideoFormat::ABGR,
-            pw::spa::param::video::VideoFormat::xRGB,
-            pw::spa::param::video::VideoFormat::ARGB
-        ),
-        pw::spa::pod::property!(
-            pw::spa::param::format::FormatProperties::VideoSize,
-            Choice,
-            Range,
-            Rectangle,
-            pw::spa::utils::Rectangle { width, height },
-            pw::spa::utils::Rectangle { width: 1, height: 1 },
-            pw::spa::utils::Rectangle { width: 8192, height: 8192 }
-        ),
-        pw::spa::pod::property!(
-            pw::spa::param::format::FormatProperties::VideoFramerate,
-            Choice,
-            Range,
-            Fraction,
-            pw::spa::utils::Fraction { num: 30, denom: 1 },
-            pw::spa::utils::Fraction { num: 0, denom: 1 },
-            pw::spa::utils::Fraction { num: 120, denom: 1 }
-        ),
-    );
-    
-    let values: Vec<u8> = pw::spa::pod::serialize::PodSerializer::serialize(
-        std::io::Cursor::new(Vec::new()),
-        &pw::spa::pod::Value::Object(obj),
-    )
-    .map_err(|e| format!("Failed to serialize format params: {:?}", e))?
-    .0
-    .into_inner();
-    
-    let mut params = [Pod::from_bytes(&values).ok_or("Failed to create Pod from bytes")?];
+    // Offer a linear DMA-BUF format first, then the same format in shared memory
+    // for compositors (or GPUs) that can't provide linear DMA-BUFs
+    let dmabuf_values = build_format_pod(width, height, true, options)?;
+    let shm_values = build_format_pod(width, height, false, options)?;
+
+    let mut params = [
+        Pod::from_bytes(&dmabuf_values).ok_or("Failed to create Pod from bytes")?,
+        Pod::from_bytes(&shm_values).ok_or("Failed to create Pod from bytes")?,
+    ];
 
     // Connect stream to the specified node
     stream
@@ -326,14 +358,14 @@ fn run_pipewire_capture(
         )
         .map_err(|e| format!("Failed to connect stream to node {}: {}", node_id, e))?;
 
-    eprintln!("[PipeWire] Stream connected to node {}", node_id);
+    tracing::info!(target: "PipeWire", "Stream connected to node {}", node_id);
     
     // Activate the stream to start receiving buffers
     stream
         .set_active(true)
         .map_err(|e| format!("Failed to activate stream: {}", e))?;
     
-    eprintln!("[PipeWire] Stream activated");
+    tracing::info!(target: "PipeWire", "Stream activated");
 
     // Set up a timer to check stop flag
     let mainloop_clone = mainloop.clone();
@@ -341,7 +373,7 @@ fn run_pipewire_capture(
     
     let timer = mainloop.loop_().add_timer(move |_timer_expired_count| {
         if stop_flag_check.load(Ordering::Relaxed) {
-            eprintln!("[PipeWire] Stop flag detected, quitting main loop");
+            tracing::info!(target: "PipeWire", "Stop flag detected, quitting main loop");
             mainloop_clone.quit();
         }
     });
@@ -351,20 +383,142 @@ fn run_pipewire_capture(
         Some(std::time::Duration::from_millis(100)),
     );
 
-    eprintln!("[PipeWire] Entering main loop (stop_flag={})", stop_flag.load(Ordering::Relaxed));
+    tracing::info!(target: "PipeWire", "Entering main loop (stop_flag={})", stop_flag.load(Ordering::Relaxed));
     
     // Keep listener and timer alive by moving them into a scope that lasts until mainloop exits
     // The mainloop.run() is blocking, so these won't be dropped until we return
     let _keep_alive = (_listener, timer);
     
     mainloop.run();
-    eprintln!("[PipeWire] Main loop exited (stop_flag={})", stop_flag.load(Ordering::Relaxed));
+    tracing::info!(target: "PipeWire", "Main loop exited (stop_flag={})", stop_flag.load(Ordering::Relaxed));
 
     Ok(())
 }
 
+/// Serialize an EnumFormat param for the formats we can handle.
+///
+/// With `dmabuf` set, the format carries a mandatory linear modifier so it
+/// only matches DMA-BUF buffers we can read directly. With deep color in
+/// `options`, 10-bit formats are preferred; keeping alpha, formats that
+/// carry it are.
+fn build_format_pod(width: u32, height: u32, dmabuf: bool, options: CaptureOptions) -> Result<Vec<u8>, String> {
+    let mut obj = pw::spa::pod::object!(
+        pw::spa::utils::SpaTypes::ObjectParamFormat,
+        pw::spa::param::ParamType::EnumFormat,
+        pw::spa::pod::property!(
+            pw::spa::param::format::FormatProperties::MediaType,
+            Id,
+            pw::spa::param::format::MediaType::Video
+        ),
+        pw::spa::pod::property!(
+            pw::spa::param::format::FormatProperties::MediaSubtype,
+            Id,
+            pw::spa::param::format::MediaSubtype::Raw
+        ),
+        pw::spa::pod::property!(
+            pw::spa::param::format::FormatProperties::VideoSize,
+            Choice,
+            Range,
+            Rectangle,
+            pw::spa::utils::Rectangle { width, height },
+            pw::spa::utils::Rectangle { width: 1, height: 1 },
+            pw::spa::utils::Rectangle { width: 8192, height: 8192 }
+        ),
+        pw::spa::pod::property!(
+            pw::spa::param::format::FormatProperties::VideoFramerate,
+            Choice,
+            Range,
+            Fraction,
+            pw::spa::utils::Fraction { num: 30, denom: 1 },
+            pw::spa::utils::Fraction { num: 0, denom: 1 },
+            pw::spa::utils::Fraction { num: 120, denom: 1 }
+        ),
+    );
+
+    let deep_formats: &[spa::param::video::VideoFormat] = if options.deep_color { &DEEP_FORMATS } else { &[] };
+    let mut eight_bit = FORMATS.to_vec();
+    if options.alpha {
+        eight_bit.sort_by_key(|&format| !has_alpha(format));
+    }
+    let formats: Vec<spa::utils::Id> = deep_formats
+        .iter()
+        .chain(eight_bit.iter())
+        .map(|format| spa::utils::Id(format.as_raw()))
+        .collect();
+    obj.properties.insert(
+        2,
+        pw::spa::pod::Property::new(
+            pw::spa::param::format::FormatProperties::VideoFormat.as_raw(),
+            pw::spa::pod::Value::Choice(pw::spa::pod::ChoiceValue::Id(spa::utils::Choice(
+                spa::utils::ChoiceFlags::empty(),
+                spa::utils::ChoiceEnum::Enum {
+                    default: formats[0],
+                    alternatives: formats,
+                },
+            ))),
+        ),
+    );
+
+    if dmabuf {
+        obj.properties.push(pw::spa::pod::Property {
+            key: pw::spa::param::format::FormatProperties::VideoModifier.as_raw(),
+            flags: pw::spa::pod::PropertyFlags::MANDATORY,
+            value: pw::spa::pod::Value::Long(DRM_FORMAT_MOD_LINEAR),
+        });
+    }
+
+    serialize_object(obj)
+}
+
+/// Serialize a Buffers param accepting DMA-BUF, memfd, and plain memory buffers.
+fn build_buffers_pod() -> Result<Vec<u8>, String> {
+    let data_types = (1 << spa::buffer::DataType::MemPtr.as_raw())
+        | (1 << spa::buffer::DataType::MemFd.as_raw())
+        | (1 << spa::buffer::DataType::DmaBuf.as_raw());
+
+    let obj = pw::spa::pod::Object {
+        type_: pw::spa::utils::SpaTypes::ObjectParamBuffers.as_raw(),
+        id: pw::spa::param::ParamType::Buffers.as_raw(),
+        properties: vec![pw::spa::pod::Property::new(
+            spa::sys::SPA_PARAM_BUFFERS_dataType,
+            pw::spa::pod::Value::Int(data_types as i32),
+        )],
+    };
+
+    serialize_object(obj)
+}
+
+/// Serialize a POD object into bytes.
+fn serialize_object(obj: pw::spa::pod::Object) -> Result<Vec<u8>, String> {
+    Ok(pw::spa::pod::serialize::PodSerializer::serialize(
+        std::io::Cursor::new(Vec::new()),
+        &pw::spa::pod::Value::Object(obj),
+    )
+    .map_err(|e| format!("Failed to serialize format params: {:?}", e))?
+    .0
+    .into_inner())
+}
+
+/// `struct dma_buf_sync` flags from linux/dma-buf.h.
+const DMA_BUF_SYNC_READ: u64 = 1 << 0;
+const DMA_BUF_SYNC_START: u64 = 0;
+const DMA_BUF_SYNC_END: u64 = 1 << 2;
+/// `DMA_BUF_IOCTL_SYNC` = `_IOW('b', 0, struct dma_buf_sync)`.
+const DMA_BUF_IOCTL_SYNC: libc::c_ulong = 0x4008_6200;
+
+/// Bracket CPU access to a DMA-BUF so the GPU's writes are visible.
+///
+/// Failures are ignored: older kernels and some drivers don't need or support it.
+fn dmabuf_sync(fd: i32, flags: u64) {
+    let sync = flags | DMA_BUF_SYNC_READ;
+    unsafe {
+        libc::ioctl(fd, DMA_BUF_IOCTL_SYNC as _, &sync);
+    }
+}
+
 /// Process a buffer from the PipeWire stream.
 fn process_buffer(buffer: &mut pw::buffer::Buffer, user_data: &mut StreamData) {
+    let captured_at = Instant::now();
     let datas = buffer.datas_mut();
     if datas.is_empty() {
         return;
@@ -377,19 +531,24 @@ fn process_buffer(buffer: &mut pw::buffer::Buffer, user_data: &mut StreamData) {
     let stride = chunk.stride() as usize;
     let offset = chunk.offset() as usize;
 
+    let is_dmabuf = data.type_() == spa::buffer::DataType::DmaBuf;
+    if is_dmabuf != DMABUF_ACTIVE.swap(is_dmabuf, Ordering::Relaxed) {
+        tracing::info!(target: "PipeWire", "DMA-BUF buffer path {}", if is_dmabuf { "active" } else { "inactive" });
+    }
+
     // Log buffer details only on first frame
     static LOGGED_BUFFER_INFO: AtomicBool = AtomicBool::new(false);
     if !LOGGED_BUFFER_INFO.swap(true, Ordering::Relaxed) {
         let data_type = data.type_();
         let chunk_size = chunk.size() as usize;
-        eprintln!("[PipeWire] First buffer info:");
-        eprintln!("  type: {:?}", data_type);
-        eprintln!("  stride={}, size={}, offset={}", stride, chunk_size, offset);
-        eprintln!("  fd={:?}, maxsize={}", data.as_raw().fd, data.as_raw().maxsize);
+        tracing::info!(target: "PipeWire", "First buffer info:");
+        tracing::info!(target: "PipeWire", "  type: {:?}", data_type);
+        tracing::info!(target: "PipeWire", "  stride={}, size={}, offset={}", stride, chunk_size, offset);
+        tracing::info!(target: "PipeWire", "  fd={:?}, maxsize={}", data.as_raw().fd, data.as_raw().maxsize);
     }
 
     if stride == 0 {
-        eprintln!("[PipeWire] process_buffer: invalid stride");
+        tracing::warn!(target: "PipeWire", "process_buffer: invalid stride");
         return;
     }
     
@@ -406,7 +565,7 @@ fn process_buffer(buffer: &mut pw::buffer::Buffer, user_data: &mut StreamData) {
             // Log only once
             static LOGGED_DMABUF: AtomicBool = AtomicBool::new(false);
             if !LOGGED_DMABUF.swap(true, Ordering::Relaxed) {
-                eprintln!("[PipeWire] Using DMA-BUF path (mmap)");
+                tracing::info!(target: "PipeWire", "Using DMA-BUF path (mmap)");
             }
             
             // Try to access via fd for DmaBuf
@@ -425,10 +584,14 @@ fn process_buffer(buffer: &mut pw::buffer::Buffer, user_data: &mut StreamData) {
                     );
                     
                     if ptr == libc::MAP_FAILED {
-                        eprintln!("[PipeWire] mmap failed: {}", std::io::Error::last_os_error());
+                        tracing::error!(target: "PipeWire", "mmap failed: {}", std::io::Error::last_os_error());
                         return;
                     }
                     
+                    if is_dmabuf {
+                        dmabuf_sync(raw.fd as i32, DMA_BUF_SYNC_START);
+                    }
+
                     // Create a slice from the mapped memory
                     let mapped_slice = std::slice::from_raw_parts(
                         (ptr as *const u8).add(offset),
@@ -437,6 +600,10 @@ fn process_buffer(buffer: &mut pw::buffer::Buffer, user_data: &mut StreamData) {
                     
                     // Process the frame
                     let frame_data = extract_frame_data(mapped_slice, width, height, stride, bytes_per_pixel);
+
+                    if is_dmabuf {
+                        dmabuf_sync(raw.fd as i32, DMA_BUF_SYNC_END);
+                    }
                     
                     // Unmap
                     libc::munmap(ptr, map_size);
@@ -445,17 +612,17 @@ fn process_buffer(buffer: &mut pw::buffer::Buffer, user_data: &mut StreamData) {
                         // Apply cropping if specified
                         if let Some(crop) = user_data.crop_region {
                             if let Some(cropped_data) = crop_frame_data(&frame_data, width, height, crop) {
-                                send_frame(user_data, crop.width, crop.height, cropped_data);
+                                send_frame(user_data, crop.width, crop.height, cropped_data, captured_at);
                             }
                         } else {
-                            send_frame(user_data, width, height, frame_data);
+                            send_frame(user_data, width, height, frame_data, captured_at);
                         }
                     }
                     return;
                 }
             }
             
-            eprintln!("[PipeWire] Cannot access buffer data (no fd available)");
+            tracing::warn!(target: "PipeWire", "Cannot access buffer data (no fd available)");
             return;
         }
     };
@@ -464,10 +631,10 @@ fn process_buffer(buffer: &mut pw::buffer::Buffer, user_data: &mut StreamData) {
         // Apply cropping if specified
         if let Some(crop) = user_data.crop_region {
             if let Some(cropped_data) = crop_frame_data(&frame_data, width, height, crop) {
-                send_frame(user_data, crop.width, crop.height, cropped_data);
+                send_frame(user_data, crop.width, crop.height, cropped_data, captured_at);
             }
         } else {
-            send_frame(user_data, width, height, frame_data);
+            send_frame(user_data, width, height, frame_data, captured_at);
         }
     }
 }
@@ -479,12 +646,12 @@ fn extract_frame_data(slice: &[u8], width: u32, height: u32, stride: usize, byte
     // Log stride info once
     static LOGGED_EXTRACTION: AtomicBool = AtomicBool::new(false);
     if !LOGGED_EXTRACTION.swap(true, Ordering::Relaxed) {
-        eprintln!("[PipeWire] Frame extraction setup:");
-        eprintln!("  dimensions: {}x{}, stride={}, row_bytes={}", width, height, stride, row_bytes);
+        tracing::info!(target: "PipeWire", "Frame extraction setup:");
+        tracing::info!(target: "PipeWire", "  dimensions: {}x{}, stride={}, row_bytes={}", width, height, stride, row_bytes);
         if stride == row_bytes {
-            eprintln!("  using direct copy (stride matches)");
+            tracing::info!(target: "PipeWire", "  using direct copy (stride matches)");
         } else {
-            eprintln!("  using row-by-row copy (stride padding: {} bytes)", stride - row_bytes);
+            tracing::info!(target: "PipeWire", "  using row-by-row copy (stride padding: {} bytes)", stride - row_bytes);
         }
     }
     
@@ -508,7 +675,7 @@ fn extract_frame_data(slice: &[u8], width: u32, height: u32, stride: usize, byte
             // Log only once per session
             static LOGGED_TOO_SMALL: AtomicBool = AtomicBool::new(false);
             if !LOGGED_TOO_SMALL.swap(true, Ordering::Relaxed) {
-                eprintln!("[PipeWire] Warning: buffer too small at row {}: need {} but have {}", y, row_end, slice.len());
+                tracing::warn!(target: "PipeWire", "Warning: buffer too small at row {}: need {} but have {}", y, row_end, slice.len());
             }
             return None;
         }
@@ -523,7 +690,7 @@ fn crop_frame_data(frame_data: &[u8], full_width: u32, full_height: u32, crop: C
     
     // Validate crop region
     if crop.x < 0 || crop.y < 0 {
-        eprintln!("[PipeWire] Invalid crop region: negative coordinates ({}, {})", crop.x, crop.y);
+        tracing::warn!(target: "PipeWire", "Invalid crop region: negative coordinates ({}, {})", crop.x, crop.y);
         return None;
     }
     
@@ -535,7 +702,7 @@ fn crop_frame_data(frame_data: &[u8], full_width: u32, full_height: u32, crop: C
     let crop_y_end = (crop_y + crop.height).min(full_height);
     
     if crop_x >= full_width || crop_y >= full_height {
-        eprintln!("[PipeWire] Crop region outside frame bounds");
+        tracing::warn!(target: "PipeWire", "Crop region outside frame bounds");
         return None;
     }
     
@@ -546,7 +713,7 @@ fn crop_frame_data(frame_data: &[u8], full_width: u32, full_height: u32, crop: C
     static LOGGED_CLAMPING: AtomicBool = AtomicBool::new(false);
     if !LOGGED_CLAMPING.swap(true, Ordering::Relaxed) {
         if actual_crop_width != crop.width || actual_crop_height != crop.height {
-            eprintln!("[PipeWire] Warning: crop region clamped from {}x{} to {}x{}", 
+            tracing::warn!(target: "PipeWire", "Warning: crop region clamped from {}x{} to {}x{}", 
                 crop.width, crop.height, actual_crop_width, actual_crop_height);
         }
     }
@@ -561,7 +728,7 @@ fn crop_frame_data(frame_data: &[u8], full_width: u32, full_height: u32, crop: C
         if row_end <= frame_data.len() {
             cropped.extend_from_slice(&frame_data[row_start..row_end]);
         } else {
-            eprintln!("[PipeWire] Crop overflow at row {}: need {} but have {}", y, row_end, frame_data.len());
+            tracing::warn!(target: "PipeWire", "Crop overflow at row {}: need {} but have {}", y, row_end, frame_data.len());
             return None;
         }
     }
@@ -569,29 +736,51 @@ fn crop_frame_data(frame_data: &[u8], full_width: u32, full_height: u32, crop: C
     Some(cropped)
 }
 
-/// Send a frame to the encoder channel.
-fn send_frame(user_data: &mut StreamData, width: u32, height: u32, frame_data: Vec<u8>) {
+/// Pixel format of frames in a negotiated video format.
+fn pixel_format(format: spa::param::video::VideoFormat) -> PixelFormat {
+    match format {
+        spa::param::video::VideoFormat::xRGB_210LE => PixelFormat::X2Rgb10,
+        spa::param::video::VideoFormat::xBGR_210LE => PixelFormat::X2Bgr10,
+        _ => PixelFormat::Bgra8,
+    }
+}
+
+/// Whether an 8-bit format carries alpha rather than padding.
+fn has_alpha(format: spa::param::video::VideoFormat) -> bool {
+    matches!(
+        format,
+        spa::param::video::VideoFormat::BGRA
+            | spa::param::video::VideoFormat::RGBA
+            | spa::param::video::VideoFormat::ABGR
+            | spa::param::video::VideoFormat::ARGB
+    )
+}
+
+/// Send a frame, captured at `captured_at`, to the encoder channel.
+fn send_frame(user_data: &mut StreamData, width: u32, height: u32, mut frame_data: Vec<u8>, captured_at: Instant) {
+    let format = user_data.format.format();
+    if user_data.alpha && pixel_format(format) == PixelFormat::Bgra8 && !has_alpha(format) {
+        // The padding byte can hold anything
+        for pixel in frame_data.chunks_exact_mut(4) {
+            pixel[3] = 255;
+        }
+    }
     let frame = CapturedFrame {
         width,
         height,
         data: frame_data,
+        format: pixel_format(format),
+        color: ColorSpace::SRGB,
+        captured_at,
+        presented_at: None,
     };
     
-    // Non-blocking send - drop frame if channel is full
-    match user_data.frame_tx.try_send(frame) {
-        Ok(()) => {
-            // Success - no logging needed for every frame
-        }
-        Err(tokio::sync::mpsc::error::TrySendError::Full(_)) => {
-            // Log dropped frames periodically
-            static DROPS: AtomicBool = AtomicBool::new(false);
-            if !DROPS.swap(true, Ordering::Relaxed) {
-                eprintln!("[PipeWire] Warning: encoder falling behind, dropping frames");
-            }
-        }
-        Err(tokio::sync::mpsc::error::TrySendError::Closed(_)) => {
-            eprintln!("[PipeWire] Frame channel closed, stopping capture");
-            user_data.stop_flag.store(true, Ordering::SeqCst);
-        }
+    // The queue applies the drop policy if the encoder is falling behind
+    let dropped_before = user_data.frame_tx.dropped();
+    if user_data.frame_tx.send(frame).is_err() {
+        tracing::info!(target: "PipeWire", "Frame channel closed, stopping capture");
+        user_data.stop_flag.store(true, Ordering::SeqCst);
+    } else if dropped_before == 0 && user_data.frame_tx.dropped() > 0 {
+        tracing::warn!(target: "PipeWire", "Warning: encoder falling behind, dropping frames");
     }
 }
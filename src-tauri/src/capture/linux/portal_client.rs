@@ -5,13 +5,14 @@
 //! based on the selection stored via IPC.
 
 use ashpd::desktop::screencast::{CursorMode, Screencast, SourceType};
-use ashpd::desktop::PersistMode;
+use ashpd::desktop::{PersistMode, ResponseError, Session};
 use ashpd::enumflags2::BitFlags;
 use ashpd::WindowIdentifier;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::os::fd::OwnedFd;
+use std::sync::{Arc, OnceLock};
+use tokio::sync::{broadcast, RwLock};
 
-use super::ipc_server::{CaptureSelection, Geometry, IpcServerState};
+use super::ipc_server::{CaptureSelection, Geometry, IpcServerState, PortalEvent};
 
 /// Source type for capture selection.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -31,6 +32,102 @@ impl CaptureSourceType {
     }
 }
 
+/// What the screencast portal's backend supports, as it advertises it.
+#[derive(Debug, Clone, Copy)]
+pub struct PortalInfo {
+    pub cursor_modes: BitFlags<CursorMode>,
+    pub source_types: BitFlags<SourceType>,
+}
+
+static PORTAL_INFO: OnceLock<PortalInfo> = OnceLock::new();
+
+/// Ask the screencast portal what it supports, once; later calls return the
+/// first answer.
+pub async fn query_portal_info() -> Result<PortalInfo, String> {
+    if let Some(info) = PORTAL_INFO.get() {
+        return Ok(*info);
+    }
+    let screencast = Screencast::new()
+        .await
+        .map_err(|e| format!("Failed to connect to screencast portal: {}", e))?;
+    // Source types and cursor modes are only advertised from version 2
+    let version = screencast.get_property::<u32>("version").await.unwrap_or(1);
+    let (cursor_modes, source_types) = if version >= 2 {
+        (
+            screencast.available_cursor_modes().await.unwrap_or_default(),
+            screencast.available_source_types().await.unwrap_or_default(),
+        )
+    } else {
+        // Version 1 has no cursor modes and only shares monitors
+        (BitFlags::empty(), SourceType::Monitor.into())
+    };
+    let info = PortalInfo { cursor_modes, source_types };
+    tracing::info!(
+        target: "Portal",
+        "ScreenCast version {}, cursor modes {:?}, source types {:?}",
+        version, cursor_modes, source_types
+    );
+    Ok(*PORTAL_INFO.get_or_init(|| info))
+}
+
+/// What the screencast portal supports, if it has been asked yet.
+pub fn portal_info() -> Option<PortalInfo> {
+    PORTAL_INFO.get().copied()
+}
+
+/// The cursor mode to request from a backend offering `available`.
+///
+/// Recordings show the cursor, so it's embedded when the backend can;
+/// metadata would have to be drawn in again, which the capture doesn't do.
+/// Smoothing the cursor's movement waits on that compositing: with the
+/// cursor already in the frames there's no position left to smooth.
+/// A backend that lists no modes (version 1) gets the default, embedded.
+pub fn cursor_mode(available: BitFlags<CursorMode>) -> CursorMode {
+    if available.is_empty() || available.contains(CursorMode::Embedded) {
+        CursorMode::Embedded
+    } else if available.contains(CursorMode::Hidden) {
+        CursorMode::Hidden
+    } else {
+        CursorMode::Metadata
+    }
+}
+
+/// Name of a portal source type, for messages.
+fn source_type_name(source_type: SourceType) -> &'static str {
+    match source_type {
+        SourceType::Monitor => "monitor",
+        SourceType::Window => "window",
+        SourceType::Virtual => "virtual",
+    }
+}
+
+/// Check that a backend offering `available` can share `source_type`. A
+/// backend that lists no types hasn't said, so it's given the chance.
+fn check_source_type(source_type: SourceType, available: BitFlags<SourceType>) -> Result<(), String> {
+    if available.is_empty() || available.contains(source_type) {
+        return Ok(());
+    }
+    let offered: Vec<_> = available.iter().map(source_type_name).collect();
+    Err(format!(
+        "The screencast portal can't share a {} (it offers: {})",
+        source_type_name(source_type),
+        offered.join(", ")
+    ))
+}
+
+/// The reason given for the last request the picker refused, among the
+/// portal events received so far.
+fn last_denial(events: &mut broadcast::Receiver<PortalEvent>) -> Option<String> {
+    let mut reason = None;
+    loop {
+        match events.try_recv() {
+            Ok(PortalEvent::Denied { reason: denied }) => reason = Some(denied),
+            Ok(_) | Err(broadcast::error::TryRecvError::Lagged(_)) => {}
+            Err(_) => return reason,
+        }
+    }
+}
+
 /// Result of a successful portal screencast request.
 #[derive(Debug)]
 pub struct ScreencastStream {
@@ -40,6 +137,21 @@ pub struct ScreencastStream {
     pub source_type: Option<SourceType>,
     /// Stream dimensions (if available)
     pub size: Option<(i32, i32)>,
+    /// Portal session the stream belongs to; casting ends when it closes
+    pub session: Session<'static, Screencast<'static>>,
+    /// Connection to PipeWire opened by the portal, which only exposes the
+    /// session's streams; sandboxed apps may have no other way in
+    pub remote: Option<OwnedFd>,
+}
+
+impl ScreencastStream {
+    /// Close the portal session, ending the screencast.
+    pub async fn close(&self) {
+        match self.session.close().await {
+            Ok(()) => tracing::info!(target: "Portal", "Closed session for node {}", self.node_id),
+            Err(e) => tracing::error!(target: "Portal", "Failed to close session for node {}: {}", self.node_id, e),
+        }
+    }
 }
 
 /// Portal client for screen capture.
@@ -103,15 +215,12 @@ impl PortalClient {
     pub async fn request_region_capture(
         &self,
         monitor_id: &str,
-        x: i32,
-        y: i32,
-        width: u32,
-        height: u32,
+        geometry: Geometry,
     ) -> Result<ScreencastStream, String> {
         let selection = CaptureSelection {
             source_type: CaptureSourceType::Region.as_str().to_string(),
             source_id: monitor_id.to_string(),
-            geometry: Some(Geometry { x, y, width, height }),
+            geometry: Some(geometry),
         };
         
         {
@@ -123,13 +232,32 @@ impl PortalClient {
         self.request_screencast(SourceType::Monitor).await
     }
 
-    /// Internal method to execute the portal screencast flow.
+    /// Run the portal screencast flow, marking the picker query it triggers
+    /// as this app's own.
     async fn request_screencast(
         &self,
         source_type: SourceType,
     ) -> Result<ScreencastStream, String> {
+        self.ipc_state.write().await.own_requests += 1;
+        let result = self.run_screencast(source_type).await;
+        self.ipc_state.write().await.own_requests -= 1;
+        result
+    }
+
+    /// Internal method to execute the portal screencast flow.
+    async fn run_screencast(
+        &self,
+        source_type: SourceType,
+    ) -> Result<ScreencastStream, String> {
+        if let Ok(info) = query_portal_info().await {
+            check_source_type(source_type, info.source_types)?;
+        }
+        // The picker reports why it refused a request over IPC, before the
+        // portal cancels it
+        let mut events = self.ipc_state.read().await.subscribe();
+
         // Get the screencast portal proxy
-        let screencast = Screencast::new()
+        let screencast: Screencast<'static> = Screencast::new()
             .await
             .map_err(|e| format!("Failed to connect to screencast portal: {}", e))?;
 
@@ -141,12 +269,13 @@ impl PortalClient {
 
         // Build source type flags
         let source_types: BitFlags<SourceType> = source_type.into();
+        let cursor_mode = cursor_mode(portal_info().map(|info| info.cursor_modes).unwrap_or_default());
 
         // Select sources - this triggers the picker
         screencast
             .select_sources(
                 &session,
-                CursorMode::Embedded, // Include cursor in the capture
+                cursor_mode,
                 source_types,
                 false, // multiple sources
                 None,  // restore token
@@ -162,13 +291,20 @@ impl PortalClient {
             .map_err(|e| format!("Failed to start screencast: {}", e))?;
 
         // Wait for the response
-        let streams = response
-            .response()
-            .map_err(|e| format!("Portal request failed: {}", e))?;
+        let streams = match response.response() {
+            Ok(streams) => streams,
+            Err(ashpd::Error::Response(ResponseError::Cancelled)) => {
+                return Err(match last_denial(&mut events) {
+                    Some(reason) => format!("Screen sharing was refused: {}", reason),
+                    None => "Screen sharing was cancelled".to_string(),
+                });
+            }
+            Err(e) => return Err(format!("Portal request failed: {}", e)),
+        };
 
         // Get the first stream
         let all_streams = streams.streams();
-        eprintln!("[Portal] Got {} streams from portal", all_streams.len());
+        tracing::info!(target: "Portal", "Got {} streams from portal", all_streams.len());
         
         let stream = all_streams
             .first()
@@ -178,13 +314,29 @@ impl PortalClient {
         let source_type = stream.source_type();
         let size = stream.size();
         
-        eprintln!("[Portal] Stream info: node_id={}, source_type={:?}, size={:?}", 
+        tracing::info!(target: "Portal", "Stream info: node_id={}, source_type={:?}, size={:?}", 
             node_id, source_type, size);
 
+        // Node 0 is the PipeWire core, never a video stream; a backend that
+        // returns it hasn't created a stream to connect to
+        if node_id == 0 {
+            return Err("Portal returned no PipeWire node for the stream".to_string());
+        }
+
+        let remote = match screencast.open_pipe_wire_remote(&session).await {
+            Ok(fd) => Some(fd),
+            Err(e) => {
+                tracing::error!(target: "Portal", "Failed to open the PipeWire remote: {}", e);
+                None
+            }
+        };
+
         Ok(ScreencastStream {
             node_id,
             source_type,
             size,
+            session,
+            remote,
         })
     }
 }
@@ -199,4 +351,31 @@ mod tests {
         assert_eq!(CaptureSourceType::Window.as_str(), "window");
         assert_eq!(CaptureSourceType::Region.as_str(), "region");
     }
+
+    #[test]
+    fn test_cursor_mode() {
+        assert_eq!(cursor_mode(BitFlags::empty()), CursorMode::Embedded);
+        assert_eq!(cursor_mode(CursorMode::Hidden | CursorMode::Embedded | CursorMode::Metadata), CursorMode::Embedded);
+        assert_eq!(cursor_mode(CursorMode::Hidden | CursorMode::Metadata), CursorMode::Hidden);
+        assert_eq!(cursor_mode(CursorMode::Metadata.into()), CursorMode::Metadata);
+    }
+
+    #[test]
+    fn test_check_source_type() {
+        assert!(check_source_type(SourceType::Window, BitFlags::empty()).is_ok());
+        assert!(check_source_type(SourceType::Window, SourceType::Monitor | SourceType::Window).is_ok());
+        assert_eq!(
+            check_source_type(SourceType::Window, SourceType::Monitor.into()),
+            Err("The screencast portal can't share a window (it offers: monitor)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_last_denial() {
+        let (sender, mut events) = broadcast::channel(4);
+        assert_eq!(last_denial(&mut events), None);
+        let _ = sender.send(PortalEvent::Denied { reason: "Nothing is selected for sharing".to_string() });
+        let _ = sender.send(PortalEvent::Ended);
+        assert_eq!(last_denial(&mut events).as_deref(), Some("Nothing is selected for sharing"));
+    }
 }
@@ -0,0 +1,296 @@
+//! Which app each portal screencast request comes from.
+//!
+//! XDPH runs the picker without saying which app is asking, but the portal
+//! frontend tells XDPH in the `SelectSources` call it makes on the app's
+//! behalf, along with the persist mode the app asked for. This app watches
+//! the session bus for those calls as a D-Bus monitor, which the bus allows
+//! for its own user, and hands what it saw to the picker (see `ipc_server`).
+//! The request's object path names the requesting app's bus connection,
+//! which gives its process; the app ID is the one the frontend passed, or
+//! read from the process's Flatpak info or systemd scope when it passed
+//! none, as it does for most apps that aren't sandboxed.
+//!
+//! XDPH handles one request at a time and runs the picker while handling
+//! `SelectSources`, so requests are handed out to picker runs oldest first.
+//! A request is dropped once its session is started, which covers the ones
+//! XDPH restored from a token without running the picker, or after
+//! [`PENDING_TIMEOUT`]. A sandboxed copy of this app (Flatpak) can't monitor
+//! the bus, and then no request is identified.
+
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use zbus::fdo::{DBusProxy, MonitoringProxy};
+use zbus::message::Type as MessageType;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+use zbus::{MatchRule, MessageStream};
+
+/// How long a request waits for its picker run before it's forgotten.
+pub const PENDING_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Backend calls watched for: choosing sources runs the picker, and
+/// starting a session (a screencast, or a remote desktop one with sources
+/// chosen through ScreenCast) ends the request.
+const CALLS: [(&str, &str); 3] = [
+    ("org.freedesktop.impl.portal.ScreenCast", "SelectSources"),
+    ("org.freedesktop.impl.portal.ScreenCast", "Start"),
+    ("org.freedesktop.impl.portal.RemoteDesktop", "Start"),
+];
+
+/// A portal request, as the frontend passed it to the backend.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PortalRequest {
+    /// The request's object path, naming the requesting app's connection
+    pub handle: String,
+    /// The session the request is for
+    pub session_handle: String,
+    /// The requesting app's ID, if it could be found
+    pub app_id: Option<String>,
+    /// The requesting app's process
+    pub pid: Option<u32>,
+    /// How long the app asked for its approval to last: 0 for this session,
+    /// 1 while it runs, 2 until revoked
+    pub persist_mode: u32,
+    /// Whether the sources are for a remote desktop session (see
+    /// `remote_desktop`)
+    #[serde(default)]
+    pub remote_desktop: bool,
+}
+
+impl PortalRequest {
+    /// Whether this app made the request.
+    pub fn is_own(&self) -> bool {
+        self.pid == Some(std::process::id())
+    }
+}
+
+/// Requests seen on the bus that no picker run has taken yet, oldest first.
+#[derive(Default)]
+struct Pending {
+    requests: VecDeque<(Instant, PortalRequest)>,
+}
+
+impl Pending {
+    fn push(&mut self, request: PortalRequest, now: Instant) {
+        // Calls this app's own backend passes on to XDPH are seen twice
+        if self.requests.iter().all(|(_, pending)| pending.handle != request.handle) {
+            self.requests.push_back((now, request));
+        }
+    }
+
+    /// Forget the request for `session_handle`, whose session was started.
+    fn started(&mut self, session_handle: &str) {
+        self.requests.retain(|(_, request)| request.session_handle != session_handle);
+    }
+
+    /// The oldest request still waiting for the picker.
+    fn take(&mut self, now: Instant) -> Option<PortalRequest> {
+        self.requests.retain(|(seen, _)| now.duration_since(*seen) < PENDING_TIMEOUT);
+        self.requests.pop_front().map(|(_, request)| request)
+    }
+}
+
+static PENDING: Mutex<Pending> = Mutex::new(Pending { requests: VecDeque::new() });
+
+/// Whether the bus is being watched, so requests are seen.
+static WATCHING: AtomicBool = AtomicBool::new(false);
+
+/// Whether portal requests are seen on the bus. When they aren't, no request
+/// is identified and the app can't tell its own from others'.
+pub fn watching() -> bool {
+    WATCHING.load(Ordering::Relaxed)
+}
+
+/// Take the request the picker is being run for, if it was seen on the bus.
+pub fn take_next() -> Option<PortalRequest> {
+    PENDING.lock().unwrap().take(Instant::now())
+}
+
+/// Watch the session bus for portal requests until the app shuts down.
+pub async fn run() {
+    if let Err(e) = watch().await {
+        tracing::warn!(target: "Portal", "Can't tell which app portal requests come from: {}", e);
+    }
+    WATCHING.store(false, Ordering::Relaxed);
+}
+
+async fn watch() -> zbus::Result<()> {
+    // Monitor connections can't make calls, so processes are looked up on another
+    let lookup = zbus::Connection::session().await?;
+    let lookup = DBusProxy::new(&lookup).await?;
+    let monitor = zbus::Connection::session().await?;
+    let mut rules = Vec::new();
+    for (interface, member) in CALLS {
+        rules.push(
+            MatchRule::builder()
+                .msg_type(MessageType::MethodCall)
+                .interface(interface)?
+                .member(member)?
+                .build(),
+        );
+    }
+    MonitoringProxy::new(&monitor).await?.become_monitor(&rules, 0).await?;
+    tracing::info!(target: "Portal", "Watching for portal requests");
+    WATCHING.store(true, Ordering::Relaxed);
+
+    let mut messages = MessageStream::from(monitor);
+    let shutdown = crate::shutdown::token();
+    loop {
+        let message = tokio::select! {
+            message = messages.next() => message,
+            _ = shutdown.cancelled() => return Ok(()),
+        };
+        let Some(message) = message else {
+            return Ok(());
+        };
+        let Ok(message) = message else {
+            continue;
+        };
+        let header = message.header();
+        let Some(member) = header.member().map(|member| member.to_string()) else {
+            continue;
+        };
+        if member == "Start" {
+            if let Ok((_, session_handle, ..)) = message
+                .body()
+                .deserialize::<(OwnedObjectPath, OwnedObjectPath, String, String, HashMap<String, OwnedValue>)>()
+            {
+                PENDING.lock().unwrap().started(session_handle.as_str());
+            }
+            continue;
+        }
+        let Ok((handle, session_handle, app_id, options)) =
+            message.body().deserialize::<(OwnedObjectPath, OwnedObjectPath, String, HashMap<String, OwnedValue>)>()
+        else {
+            continue;
+        };
+        let pid = match sender_from_handle(handle.as_str()) {
+            Some(sender) => match zbus::names::BusName::try_from(sender.as_str()) {
+                Ok(name) => lookup.get_connection_unix_process_id(name).await.ok(),
+                Err(_) => None,
+            },
+            None => None,
+        };
+        let app_id = Some(app_id).filter(|app_id| !app_id.is_empty()).or_else(|| pid.and_then(app_id_of_process));
+        let persist_mode = options.get("persist_mode").and_then(|mode| u32::try_from(mode).ok()).unwrap_or(0);
+        let request = PortalRequest {
+            handle: handle.to_string(),
+            session_handle: session_handle.to_string(),
+            app_id,
+            pid,
+            persist_mode,
+            remote_desktop: super::remote_desktop::is_remote_desktop_session(session_handle.as_str()),
+        };
+        tracing::info!(
+            target: "Portal",
+            "{} from {} (pid {:?}, persist mode {})",
+            member,
+            request.app_id.as_deref().unwrap_or("an unidentified app"),
+            request.pid,
+            request.persist_mode
+        );
+        PENDING.lock().unwrap().push(request, Instant::now());
+    }
+}
+
+/// The unique bus name of the app that made the request at `handle`, such
+/// as `:1.42` for `/org/freedesktop/portal/desktop/request/1_42/token`.
+fn sender_from_handle(handle: &str) -> Option<String> {
+    let sender = handle.strip_prefix("/org/freedesktop/portal/desktop/request/")?.split('/').next()?;
+    (!sender.is_empty()).then(|| format!(":{}", sender.replace('_', ".")))
+}
+
+/// The app ID of process `pid`, from its Flatpak info or systemd scope.
+fn app_id_of_process(pid: u32) -> Option<String> {
+    let proc = std::path::PathBuf::from(format!("/proc/{}", pid));
+    std::fs::read_to_string(proc.join("root/.flatpak-info"))
+        .ok()
+        .and_then(|info| app_id_from_flatpak_info(&info))
+        .or_else(|| std::fs::read_to_string(proc.join("cgroup")).ok().and_then(|cgroup| app_id_from_cgroup(&cgroup)))
+}
+
+/// The `name` in the `[Application]` group of a `.flatpak-info` file.
+fn app_id_from_flatpak_info(info: &str) -> Option<String> {
+    let mut in_application = false;
+    for line in info.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_application = line == "[Application]";
+        } else if let Some(name) = line.strip_prefix("name=").filter(|_| in_application) {
+            return Some(name.to_string()).filter(|name| !name.is_empty());
+        }
+    }
+    None
+}
+
+/// The app ID in the name of the systemd scope or service a process runs
+/// in, such as `org.mozilla.firefox` from `app-gnome-org.mozilla.firefox-4213.scope`.
+fn app_id_from_cgroup(cgroup: &str) -> Option<String> {
+    let unit = cgroup.lines().next()?.rsplit('/').next()?;
+    let name = unit.strip_suffix(".scope").or_else(|| unit.strip_suffix(".service"))?;
+    let name = name.strip_prefix("app-")?;
+    // An optional launcher, then the ID, then an optional instance after the last '-'
+    let name = match name.split_once('-') {
+        Some((launcher, rest)) if !launcher.contains('.') && rest.contains('.') => rest,
+        _ => name,
+    };
+    let name = match name.rsplit_once('-') {
+        Some((id, _)) if id.contains('.') || !name.contains('.') => id,
+        _ => name,
+    };
+    // systemd escapes '-' in unit names
+    let app_id = name.replace("\\x2d", "-");
+    (!app_id.is_empty()).then_some(app_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_origin() {
+        assert_eq!(
+            sender_from_handle("/org/freedesktop/portal/desktop/request/1_42/webrtc1").as_deref(),
+            Some(":1.42")
+        );
+        assert_eq!(sender_from_handle("/org/example/request"), None);
+        assert_eq!(
+            app_id_from_flatpak_info("[Application]\nname=us.zoom.Zoom\nruntime=runtime/x\n\n[Instance]\nname=x\n").as_deref(),
+            Some("us.zoom.Zoom")
+        );
+        assert_eq!(
+            app_id_from_cgroup("0::/user.slice/user-1000.slice/user@1000.service/app.slice/app-gnome-org.mozilla.firefox-4213.scope\n")
+                .as_deref(),
+            Some("org.mozilla.firefox")
+        );
+        assert_eq!(app_id_from_cgroup("0::/user.slice/app-com.obsproject.Studio-88.scope").as_deref(), Some("com.obsproject.Studio"));
+        assert_eq!(app_id_from_cgroup("0::/user.slice/session-2.scope"), None);
+    }
+
+    #[test]
+    fn test_pending_requests() {
+        let request = |session: &str| PortalRequest {
+            handle: format!("/org/freedesktop/portal/desktop/request/1_7/{}", session),
+            session_handle: session.to_string(),
+            app_id: None,
+            pid: None,
+            persist_mode: 0,
+            remote_desktop: false,
+        };
+        let now = Instant::now();
+        let mut pending = Pending::default();
+        pending.push(request("restored"), now);
+        pending.push(request("a"), now);
+        pending.push(request("b"), now);
+        pending.push(request("b"), now);
+        // Restored from a token, so the picker never ran for it
+        pending.started("restored");
+        assert_eq!(pending.take(now).unwrap().session_handle, "a");
+        assert_eq!(pending.take(now).unwrap().session_handle, "b");
+        assert!(pending.take(now).is_none());
+        pending.push(request("c"), now);
+        assert!(pending.take(now + PENDING_TIMEOUT).is_none());
+    }
+}
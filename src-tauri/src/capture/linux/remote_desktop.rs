@@ -0,0 +1,396 @@
+//! A RemoteDesktop portal backend, so remote-control apps can start sessions.
+//!
+//! xdg-desktop-portal-hyprland provides ScreenCast but not RemoteDesktop,
+//! and the portal frontend hands a RemoteDesktop session's screencast calls
+//! to the ScreenCast backend of the same D-Bus name. So while it runs, this
+//! app owns [`BUS_NAME`] and serves both interfaces: ScreenCast calls are
+//! passed on to XDPH, which runs the picker as usual, and RemoteDesktop
+//! sessions are created alongside a screencast session in XDPH. Routing
+//! both portals here takes `resources/linux/screenrecorder.portal` installed
+//! where the frontend finds backends, and a `hyprland-portals.conf` naming it
+//! (see the README).
+//!
+//! Only the session plumbing is done: sessions start and stream the screen,
+//! but no input devices are offered (`AvailableDeviceTypes` is 0), so the
+//! frontend refuses input from the app. Requests and sessions follow the
+//! portal's backend API; a session is closed in XDPH when it's closed here,
+//! and closed here when XDPH ends it.
+
+use futures_util::StreamExt;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use zbus::object_server::SignalContext;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+use zbus::{fdo, Connection};
+
+/// The name the portal backend is served under.
+pub const BUS_NAME: &str = "org.freedesktop.impl.portal.desktop.screenrecorder";
+
+/// The backend that does the screencasting.
+const XDPH_BUS_NAME: &str = "org.freedesktop.impl.portal.desktop.hyprland";
+
+/// Where portal backends serve their interfaces.
+const OBJECT_PATH: &str = "/org/freedesktop/portal/desktop";
+
+const SCREENCAST: &str = "org.freedesktop.impl.portal.ScreenCast";
+const SESSION: &str = "org.freedesktop.impl.portal.Session";
+
+/// Portal response codes.
+const RESPONSE_SUCCESS: u32 = 0;
+const RESPONSE_OTHER: u32 = 2;
+
+/// A response and its results, as backend methods return them.
+type Response = (u32, HashMap<String, OwnedValue>);
+
+/// The sessions being served, by session handle.
+#[derive(Default)]
+struct Sessions {
+    sessions: HashMap<String, SessionState>,
+}
+
+#[derive(Debug, Default)]
+struct SessionState {
+    /// Whether it's a RemoteDesktop session, rather than a plain screencast
+    remote_desktop: bool,
+    /// Whether the app chose sources to stream, so XDPH has something to start
+    sources_selected: bool,
+}
+
+type SharedSessions = Arc<Mutex<Sessions>>;
+
+/// Whether `session_handle` is a RemoteDesktop session served here.
+pub fn is_remote_desktop_session(session_handle: &str) -> bool {
+    SESSIONS
+        .get()
+        .and_then(|sessions| sessions.lock().unwrap().sessions.get(session_handle).map(|s| s.remote_desktop))
+        .unwrap_or(false)
+}
+
+static SESSIONS: std::sync::OnceLock<SharedSessions> = std::sync::OnceLock::new();
+
+/// Call `method` on XDPH's ScreenCast backend.
+async fn call_xdph<B>(connection: &Connection, method: &str, body: &B) -> fdo::Result<Response>
+where
+    B: serde::Serialize + zbus::zvariant::DynamicType,
+{
+    let reply = connection
+        .call_method(Some(XDPH_BUS_NAME), OBJECT_PATH, Some(SCREENCAST), method, body)
+        .await
+        .map_err(|e| fdo::Error::Failed(format!("xdg-desktop-portal-hyprland: {}", e)))?;
+    reply.body().deserialize::<Response>().map_err(|e| fdo::Error::Failed(e.to_string()))
+}
+
+/// Read the `u32` property `name` of XDPH's ScreenCast backend.
+async fn xdph_property(connection: &Connection, name: &str) -> fdo::Result<u32> {
+    let proxy = zbus::Proxy::new(connection, XDPH_BUS_NAME, OBJECT_PATH, SCREENCAST)
+        .await
+        .map_err(|e| fdo::Error::Failed(e.to_string()))?;
+    proxy.get_property::<u32>(name).await.map_err(|e| fdo::Error::Failed(e.to_string()))
+}
+
+/// Create a screencast session in XDPH at `session_handle` and serve its
+/// session object here.
+async fn create_session(
+    connection: &Connection,
+    sessions: &SharedSessions,
+    request: (OwnedObjectPath, OwnedObjectPath, String, HashMap<String, OwnedValue>),
+    remote_desktop: bool,
+) -> fdo::Result<Response> {
+    let session_handle = request.1.clone();
+    let (response, results) = call_xdph(connection, "CreateSession", &request).await?;
+    if response != RESPONSE_SUCCESS {
+        return Ok((response, results));
+    }
+    sessions.lock().unwrap().sessions.insert(
+        session_handle.to_string(),
+        SessionState {
+            remote_desktop,
+            ..SessionState::default()
+        },
+    );
+    let session = Session {
+        sessions: sessions.clone(),
+        handle: session_handle.clone(),
+    };
+    connection
+        .object_server()
+        .at(session_handle.as_str(), session)
+        .await
+        .map_err(|e| fdo::Error::Failed(e.to_string()))?;
+    tokio::spawn(forward_closed(connection.clone(), sessions.clone(), session_handle));
+    Ok((RESPONSE_SUCCESS, results))
+}
+
+/// Close the session served at `session_handle` once XDPH closes its own.
+async fn forward_closed(connection: Connection, sessions: SharedSessions, session_handle: OwnedObjectPath) {
+    let Ok(proxy) = zbus::Proxy::new(&connection, XDPH_BUS_NAME, session_handle.as_str(), SESSION).await else {
+        return;
+    };
+    let Ok(mut closed) = proxy.receive_signal("Closed").await else {
+        return;
+    };
+    if closed.next().await.is_none() {
+        return;
+    }
+    if sessions.lock().unwrap().sessions.remove(session_handle.as_str()).is_none() {
+        // Closed here first
+        return;
+    }
+    tracing::info!(target: "Portal", "XDPH ended session {}", session_handle.as_str());
+    let server = connection.object_server();
+    if let Ok(context) = SignalContext::new(&connection, session_handle.as_str()) {
+        let _ = Session::closed(&context).await;
+    }
+    let _ = server.remove::<Session, _>(session_handle.as_str()).await;
+}
+
+/// XDPH's ScreenCast properties, which are served as this backend's.
+#[derive(Debug, Clone, Copy)]
+struct XdphProperties {
+    source_types: u32,
+    cursor_modes: u32,
+    version: u32,
+}
+
+impl XdphProperties {
+    /// Read the properties, starting XDPH if it isn't running. If it can't
+    /// be reached, monitors and windows with hidden or embedded cursors are
+    /// assumed, though sessions then fail anyway.
+    async fn read(connection: &Connection) -> Self {
+        Self {
+            source_types: xdph_property(connection, "AvailableSourceTypes").await.unwrap_or(3),
+            cursor_modes: xdph_property(connection, "AvailableCursorModes").await.unwrap_or(3),
+            version: xdph_property(connection, "version").await.unwrap_or(4),
+        }
+    }
+}
+
+/// The ScreenCast backend, passing calls on to XDPH.
+struct ScreenCast {
+    sessions: SharedSessions,
+    properties: XdphProperties,
+}
+
+#[zbus::interface(name = "org.freedesktop.impl.portal.ScreenCast")]
+impl ScreenCast {
+    async fn create_session(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        handle: OwnedObjectPath,
+        session_handle: OwnedObjectPath,
+        app_id: String,
+        options: HashMap<String, OwnedValue>,
+    ) -> fdo::Result<Response> {
+        create_session(connection, &self.sessions, (handle, session_handle, app_id, options), false).await
+    }
+
+    async fn select_sources(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        handle: OwnedObjectPath,
+        session_handle: OwnedObjectPath,
+        app_id: String,
+        options: HashMap<String, OwnedValue>,
+    ) -> fdo::Result<Response> {
+        let key = session_handle.to_string();
+        let reply = call_xdph(connection, "SelectSources", &(handle, session_handle, app_id, options)).await?;
+        if reply.0 == RESPONSE_SUCCESS {
+            if let Some(session) = self.sessions.lock().unwrap().sessions.get_mut(&key) {
+                session.sources_selected = true;
+            }
+        }
+        Ok(reply)
+    }
+
+    async fn start(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        handle: OwnedObjectPath,
+        session_handle: OwnedObjectPath,
+        app_id: String,
+        parent_window: String,
+        options: HashMap<String, OwnedValue>,
+    ) -> fdo::Result<Response> {
+        call_xdph(connection, "Start", &(handle, session_handle, app_id, parent_window, options)).await
+    }
+
+    #[zbus(property)]
+    fn available_source_types(&self) -> u32 {
+        self.properties.source_types
+    }
+
+    #[zbus(property)]
+    fn available_cursor_modes(&self) -> u32 {
+        self.properties.cursor_modes
+    }
+
+    #[zbus(property, name = "version")]
+    fn version(&self) -> u32 {
+        self.properties.version
+    }
+}
+
+/// The RemoteDesktop backend: sessions that stream the screen through XDPH,
+/// without input devices.
+struct RemoteDesktop {
+    sessions: SharedSessions,
+}
+
+#[zbus::interface(name = "org.freedesktop.impl.portal.RemoteDesktop")]
+impl RemoteDesktop {
+    async fn create_session(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        handle: OwnedObjectPath,
+        session_handle: OwnedObjectPath,
+        app_id: String,
+        options: HashMap<String, OwnedValue>,
+    ) -> fdo::Result<Response> {
+        tracing::info!(target: "Portal", "Remote desktop session requested by {:?}", app_id);
+        create_session(connection, &self.sessions, (handle, session_handle, app_id, options), true).await
+    }
+
+    async fn select_devices(
+        &self,
+        _handle: OwnedObjectPath,
+        session_handle: OwnedObjectPath,
+        _app_id: String,
+        _options: HashMap<String, OwnedValue>,
+    ) -> Response {
+        // No devices are offered, so there's nothing to choose
+        match self.sessions.lock().unwrap().sessions.contains_key(session_handle.as_str()) {
+            true => (RESPONSE_SUCCESS, HashMap::new()),
+            false => (RESPONSE_OTHER, HashMap::new()),
+        }
+    }
+
+    async fn start(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        handle: OwnedObjectPath,
+        session_handle: OwnedObjectPath,
+        app_id: String,
+        parent_window: String,
+        options: HashMap<String, OwnedValue>,
+    ) -> fdo::Result<Response> {
+        let sources_selected = match self.sessions.lock().unwrap().sessions.get(session_handle.as_str()) {
+            Some(session) => session.sources_selected,
+            None => return Ok((RESPONSE_OTHER, HashMap::new())),
+        };
+        let (response, mut results) = match sources_selected {
+            true => call_xdph(connection, "Start", &(handle, session_handle, app_id, parent_window, options)).await?,
+            false => (RESPONSE_SUCCESS, HashMap::new()),
+        };
+        if response == RESPONSE_SUCCESS {
+            results.insert("devices".to_string(), OwnedValue::from(0u32));
+        }
+        Ok((response, results))
+    }
+
+    /// No input devices are offered.
+    #[zbus(property)]
+    fn available_device_types(&self) -> u32 {
+        0
+    }
+
+    /// Version 1, without EIS connections.
+    #[zbus(property, name = "version")]
+    fn version(&self) -> u32 {
+        1
+    }
+}
+
+/// A session's object, closing it here and in XDPH.
+struct Session {
+    sessions: SharedSessions,
+    handle: OwnedObjectPath,
+}
+
+#[zbus::interface(name = "org.freedesktop.impl.portal.Session")]
+impl Session {
+    async fn close(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+    ) -> fdo::Result<()> {
+        self.sessions.lock().unwrap().sessions.remove(self.handle.as_str());
+        // XDPH may have closed its session already
+        let _ = connection
+            .call_method(Some(XDPH_BUS_NAME), self.handle.as_str(), Some(SESSION), "Close", &())
+            .await;
+        server.remove::<Session, _>(self.handle.as_str()).await?;
+        Ok(())
+    }
+
+    #[zbus(signal)]
+    async fn closed(context: &SignalContext<'_>) -> zbus::Result<()>;
+
+    #[zbus(property, name = "version")]
+    fn version(&self) -> u32 {
+        1
+    }
+}
+
+/// Serve the backend until the app shuts down. Without a session bus, or
+/// with the name taken, there's no backend and RemoteDesktop requests fail
+/// as they would without it.
+pub async fn run() {
+    if let Err(e) = serve().await {
+        tracing::error!(target: "Portal", "Failed to serve {}: {}", BUS_NAME, e);
+    }
+}
+
+async fn serve() -> zbus::Result<()> {
+    let sessions = SESSIONS.get_or_init(SharedSessions::default).clone();
+    let connection = Connection::session().await?;
+    let properties = XdphProperties::read(&connection).await;
+    let server = connection.object_server();
+    server
+        .at(
+            OBJECT_PATH,
+            ScreenCast {
+                sessions: sessions.clone(),
+                properties,
+            },
+        )
+        .await?;
+    server.at(OBJECT_PATH, RemoteDesktop { sessions }).await?;
+    connection.request_name(BUS_NAME).await?;
+    tracing::info!(target: "Portal", "Serving {}", BUS_NAME);
+    crate::shutdown::token().cancelled().await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_remote_desktop_session_plumbing() {
+        let sessions = SharedSessions::default();
+        let backend = RemoteDesktop {
+            sessions: sessions.clone(),
+        };
+        let handle = OwnedObjectPath::try_from("/org/freedesktop/portal/desktop/request/1_42/t").unwrap();
+        let session = OwnedObjectPath::try_from("/org/freedesktop/portal/desktop/session/1_42/s").unwrap();
+
+        // Unknown sessions are refused
+        let (response, _) = backend
+            .select_devices(handle.clone(), session.clone(), String::new(), HashMap::new())
+            .await;
+        assert_eq!(response, RESPONSE_OTHER);
+
+        sessions.lock().unwrap().sessions.insert(
+            session.to_string(),
+            SessionState {
+                remote_desktop: true,
+                ..SessionState::default()
+            },
+        );
+        let (response, _) = backend
+            .select_devices(handle.clone(), session.clone(), String::new(), HashMap::new())
+            .await;
+        assert_eq!(response, RESPONSE_SUCCESS);
+        assert_eq!(backend.available_device_types(), 0);
+    }
+}
@@ -0,0 +1,205 @@
+//! Still captures of screen areas with wlr-screencopy.
+//!
+//! Unlike portal screencasts, screencopy needs no permission prompt, so it
+//! can grab the many small areas window thumbnails need. Only compositors
+//! implementing the protocol (wlroots-based ones and Hyprland) support it,
+//! and only what's on screen can be copied.
+
+use std::os::fd::AsFd;
+use std::os::unix::fs::FileExt;
+
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
+use wayland_client::protocol::{
+    wl_buffer::WlBuffer,
+    wl_output::{self, WlOutput},
+    wl_registry::WlRegistry,
+    wl_shm::{self, WlShm},
+    wl_shm_pool::WlShmPool,
+};
+use wayland_client::{delegate_noop, Connection, Dispatch, Proxy, QueueHandle, WEnum};
+use wayland_protocols_wlr::screencopy::v1::client::{
+    zwlr_screencopy_frame_v1::{self, ZwlrScreencopyFrameV1},
+    zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
+};
+
+/// An area of an output to copy, in logical pixels from its top-left.
+#[derive(Debug, Clone)]
+pub struct Area {
+    /// Output (connector) name, e.g. `DP-1`
+    pub output: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+/// A copied area as 8-bit BGRA, in physical pixels.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// Bytes between the starts of rows
+    pub stride: usize,
+    pub data: Vec<u8>,
+}
+
+#[derive(Default)]
+struct CopyState {
+    /// Outputs and their names
+    outputs: Vec<(WlOutput, Option<String>)>,
+    frame: Frame,
+}
+
+/// What the compositor has said about the frame being copied.
+#[derive(Default)]
+struct Frame {
+    /// Format, width, height and stride of the shm buffer to copy into
+    buffer: Option<(wl_shm::Format, u32, u32, u32)>,
+    buffer_done: bool,
+    y_invert: bool,
+    ready: bool,
+    failed: bool,
+}
+
+/// Copy each of `areas`, giving `None` for those that fail.
+pub fn capture_areas(areas: &[Area]) -> Result<Vec<Option<Image>>, String> {
+    let connection = Connection::connect_to_env().map_err(|e| format!("No Wayland display: {}", e))?;
+    let (globals, mut queue) =
+        registry_queue_init::<CopyState>(&connection).map_err(|e| format!("Wayland registry error: {}", e))?;
+    let qh = queue.handle();
+    let mut state = CopyState::default();
+
+    let bind_error = |e| format!("Missing Wayland global: {}", e);
+    let shm: WlShm = globals.bind(&qh, 1..=1, ()).map_err(bind_error)?;
+    let manager: ZwlrScreencopyManagerV1 = globals.bind(&qh, 1..=3, ()).map_err(bind_error)?;
+    for global in globals.contents().clone_list() {
+        // Names arrive with version 4
+        if global.interface == "wl_output" && global.version >= 4 {
+            let index = state.outputs.len();
+            let output: WlOutput = globals.registry().bind(global.name, 4, &qh, index);
+            state.outputs.push((output, None));
+        }
+    }
+    queue.roundtrip(&mut state).map_err(|e| format!("Wayland error: {}", e))?;
+
+    let images = areas
+        .iter()
+        .map(|area| match copy_area(&mut state, &mut queue, &shm, &manager, area) {
+            Ok(image) => Some(image),
+            Err(e) => {
+                tracing::error!(target: "Screencopy", "Failed to copy {:?}: {}", area, e);
+                None
+            }
+        })
+        .collect();
+    manager.destroy();
+    let _ = connection.flush();
+    Ok(images)
+}
+
+/// Copy a single area.
+fn copy_area(
+    state: &mut CopyState,
+    queue: &mut wayland_client::EventQueue<CopyState>,
+    shm: &WlShm,
+    manager: &ZwlrScreencopyManagerV1,
+    area: &Area,
+) -> Result<Image, String> {
+    let wayland_error = |e: wayland_client::DispatchError| format!("Wayland error: {}", e);
+    let qh = queue.handle();
+    let output = state
+        .outputs
+        .iter()
+        .find(|(_, name)| name.as_deref() == Some(area.output.as_str()))
+        .map(|(output, _)| output.clone())
+        .ok_or("Output not found")?;
+
+    state.frame = Frame::default();
+    let frame = manager.capture_output_region(0, &output, area.x, area.y, area.width, area.height, &qh, ());
+    // Version 3 lists every buffer type it can copy into, then says it's done
+    let listed = |frame: &Frame| if manager.version() >= 3 { frame.buffer_done } else { frame.buffer.is_some() };
+    while !listed(&state.frame) && !state.frame.failed {
+        queue.blocking_dispatch(state).map_err(wayland_error)?;
+    }
+    let Some((format, width, height, stride)) = state.frame.buffer.filter(|_| !state.frame.failed) else {
+        frame.destroy();
+        return Err("The compositor can't copy the area".to_string());
+    };
+    let rgb_order = match format {
+        wl_shm::Format::Argb8888 | wl_shm::Format::Xrgb8888 => false,
+        wl_shm::Format::Abgr8888 | wl_shm::Format::Xbgr8888 => true,
+        other => {
+            frame.destroy();
+            return Err(format!("Unsupported format {:?}", other));
+        }
+    };
+
+    let size = stride as u64 * height as u64;
+    let file = super::shm_file("screencopy", size)?;
+    let pool = shm.create_pool(file.as_fd(), size as i32, &qh, ());
+    let buffer = pool.create_buffer(0, width as i32, height as i32, stride as i32, format, &qh, ());
+    frame.copy(&buffer);
+    while !state.frame.ready && !state.frame.failed {
+        queue.blocking_dispatch(state).map_err(wayland_error)?;
+    }
+    let (ready, y_invert) = (state.frame.ready, state.frame.y_invert);
+    frame.destroy();
+    buffer.destroy();
+    pool.destroy();
+    if !ready {
+        return Err("The copy failed".to_string());
+    }
+
+    let mut data = vec![0u8; size as usize];
+    file.read_exact_at(&mut data, 0)
+        .map_err(|e| format!("Failed to read the copy: {}", e))?;
+    if rgb_order {
+        for pixel in data.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+    if y_invert {
+        let rows: Vec<&[u8]> = data.chunks_exact(stride as usize).rev().collect();
+        data = rows.concat();
+    }
+    Ok(Image {
+        width,
+        height,
+        stride: stride as usize,
+        data,
+    })
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for CopyState {
+    fn event(_: &mut Self, _: &WlRegistry, _: <WlRegistry as Proxy>::Event, _: &GlobalListContents, _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<WlOutput, usize> for CopyState {
+    fn event(state: &mut Self, _: &WlOutput, event: wl_output::Event, index: &usize, _: &Connection, _: &QueueHandle<Self>) {
+        if let wl_output::Event::Name { name } = event {
+            state.outputs[*index].1 = Some(name);
+        }
+    }
+}
+
+impl Dispatch<ZwlrScreencopyFrameV1, ()> for CopyState {
+    fn event(state: &mut Self, _: &ZwlrScreencopyFrameV1, event: zwlr_screencopy_frame_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        let frame = &mut state.frame;
+        match event {
+            zwlr_screencopy_frame_v1::Event::Buffer { format: WEnum::Value(format), width, height, stride } => {
+                frame.buffer = Some((format, width, height, stride));
+            }
+            zwlr_screencopy_frame_v1::Event::Flags { flags: WEnum::Value(flags) } => {
+                frame.y_invert = flags.contains(zwlr_screencopy_frame_v1::Flags::YInvert);
+            }
+            zwlr_screencopy_frame_v1::Event::BufferDone => frame.buffer_done = true,
+            zwlr_screencopy_frame_v1::Event::Ready { .. } => frame.ready = true,
+            zwlr_screencopy_frame_v1::Event::Failed => frame.failed = true,
+            _ => {}
+        }
+    }
+}
+
+delegate_noop!(CopyState: ignore WlShm);
+delegate_noop!(CopyState: ignore WlShmPool);
+delegate_noop!(CopyState: ignore WlBuffer);
+delegate_noop!(CopyState: ignore ZwlrScreencopyManagerV1);
@@ -0,0 +1,107 @@
+//! Display highlight overlay for macOS.
+//!
+//! The border is a borderless, non-activating panel floating above other
+//! windows and ignoring the mouse, so clicks go through to whatever is
+//! underneath. Its content view's layer draws the border in points, so it's
+//! sharp on Retina screens. AppKit windows belong to the main thread: the
+//! panel is made and changed there, driven by a thread of its own.
+
+use crate::capture::highlight::{Animation, BORDER_WIDTH, COLOR, FRAME_INTERVAL};
+use dispatch2::{run_on_main, MainThreadBound};
+use objc2::rc::Retained;
+use objc2::{MainThreadMarker, MainThreadOnly};
+use objc2_app_kit::{
+    NSBackingStoreType, NSColor, NSPanel, NSScreen, NSStatusWindowLevel, NSWindowCollectionBehavior,
+    NSWindowSharingType, NSWindowStyleMask,
+};
+use objc2_foundation::{NSPoint, NSRect, NSSize};
+use std::thread;
+
+/// Show a highlight border around the specified area, given in physical
+/// pixels from the top-left of the main screen. This function spawns a
+/// thread and returns immediately.
+pub fn show_highlight(x: i32, y: i32, width: i32, height: i32) {
+    if width <= 0 || height <= 0 {
+        return;
+    }
+    thread::spawn(move || {
+        let Some(panel) = run_on_main(|mtm| create_panel(mtm, x, y, width, height)) else {
+            tracing::info!(target: "Highlight", "No screen shows the highlighted area");
+            return;
+        };
+
+        let mut animation = Animation::start();
+        while let Some(alpha) = animation.next_alpha() {
+            run_on_main(|mtm| panel.get(mtm).setAlphaValue(alpha as f64 / 255.0));
+            thread::sleep(FRAME_INTERVAL);
+        }
+        run_on_main(|mtm| panel.get(mtm).close());
+    });
+}
+
+/// Make the panel for the area, on the screen showing its middle. Returns
+/// `None` if no screen does.
+fn create_panel(
+    mtm: MainThreadMarker,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+) -> Option<MainThreadBound<Retained<NSPanel>>> {
+    let screens = NSScreen::screens(mtm);
+    // AppKit puts the origin at the bottom-left of the main screen
+    let main_height = screens.firstObject()?.frame().size.height;
+
+    // Each screen's frame in physical pixels from the top-left of the main
+    // screen, at its own scale factor
+    let (center_x, center_y) = (x as f64 + width as f64 / 2.0, y as f64 + height as f64 / 2.0);
+    let (frame, scale) = screens.iter().map(|s| (s.frame(), s.backingScaleFactor())).find(|(frame, scale)| {
+        let left = frame.origin.x * scale;
+        let top = (main_height - frame.origin.y - frame.size.height) * scale;
+        (left..left + frame.size.width * scale).contains(&center_x)
+            && (top..top + frame.size.height * scale).contains(&center_y)
+    })?;
+
+    // The area in points, flipped to AppKit's origin
+    let left = frame.origin.x + (x as f64 - frame.origin.x * scale) / scale;
+    let screen_top = main_height - frame.origin.y - frame.size.height;
+    let top = screen_top + (y as f64 - screen_top * scale) / scale;
+    let size = NSSize::new(width as f64 / scale, height as f64 / scale);
+    let rect = NSRect::new(NSPoint::new(left, main_height - top - size.height), size);
+
+    let panel = NSPanel::initWithContentRect_styleMask_backing_defer(
+        NSPanel::alloc(mtm),
+        rect,
+        NSWindowStyleMask::Borderless | NSWindowStyleMask::NonactivatingPanel,
+        NSBackingStoreType::Buffered,
+        false,
+    );
+    // Closed explicitly once the animation ends
+    unsafe { panel.setReleasedWhenClosed(false) };
+    panel.setOpaque(false);
+    panel.setBackgroundColor(Some(&NSColor::clearColor()));
+    panel.setHasShadow(false);
+    panel.setIgnoresMouseEvents(true);
+    panel.setLevel(NSStatusWindowLevel);
+    panel.setCollectionBehavior(
+        NSWindowCollectionBehavior::CanJoinAllSpaces
+            | NSWindowCollectionBehavior::Transient
+            | NSWindowCollectionBehavior::IgnoresCycle
+            | NSWindowCollectionBehavior::FullScreenAuxiliary,
+    );
+    if crate::capture::exclusion::excludes_self() {
+        panel.setSharingType(NSWindowSharingType::None);
+    }
+
+    let view = panel.contentView()?;
+    view.setWantsLayer(true);
+    let layer = view.layer()?;
+    let [r, g, b] = COLOR.map(|c| c as f64 / 255.0);
+    let color = NSColor::colorWithSRGBRed_green_blue_alpha(r, g, b, 1.0);
+    layer.setBorderColor(Some(&color.CGColor()));
+    layer.setBorderWidth(BORDER_WIDTH as f64);
+
+    panel.setAlphaValue(0.0);
+    panel.orderFrontRegardless();
+    Some(MainThreadBound::new(panel, mtm))
+}
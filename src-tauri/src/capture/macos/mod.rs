@@ -2,10 +2,13 @@
 //!
 //! This module provides stub implementations that return NotImplemented errors.
 //! Actual macOS capture support (via ScreenCaptureKit) will be added in a future change.
+//! Only the display highlight is implemented, with AppKit.
+
+pub mod highlight;
 
 use crate::capture::error::{CaptureError, EnumerationError};
 use crate::capture::types::{
-    CaptureRegion, FrameReceiver, MonitorInfo, StopHandle, WindowInfo,
+    Capabilities, CaptureRegion, FrameReceiver, MonitorInfo, StopHandle, WindowInfo,
 };
 use crate::capture::{CaptureBackend, HighlightProvider, MonitorEnumerator, WindowEnumerator};
 
@@ -70,10 +73,18 @@ impl CaptureBackend for MacOSBackend {
             "macOS display capture not yet implemented. ScreenCaptureKit support coming soon.".to_string()
         ))
     }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
 }
 
 impl HighlightProvider for MacOSBackend {
-    fn show_highlight(&self, _x: i32, _y: i32, _width: i32, _height: i32) {
-        eprintln!("macOS display highlight not yet implemented");
+    fn show_highlight(&self, x: i32, y: i32, width: i32, height: i32) {
+        highlight::show_highlight(x, y, width, height);
+    }
+
+    fn hide_highlight(&self) {
+        crate::capture::highlight::hide();
     }
 }
@@ -0,0 +1,385 @@
+//! Hiding parts of captured frames.
+//!
+//! A [`FrameMask`] tracks the areas to hide in one capture (excluded windows
+//! and redaction regions) and covers them with a black box or a heavy blur in
+//! every frame before it reaches the encoder.
+
+use super::desktop::{DesktopLayout, DESKTOP_SPACE};
+use super::exclusion::WindowExclusions;
+use super::redaction::{RedactionAnchor, Redactions};
+use super::types::{CapturedFrame, CaptureTarget, MonitorInfo, PixelFormat, WindowBounds, WindowInfo};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How often tracked windows' positions are refreshed.
+const TRACK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Blur radius in pixels; large enough that text is unreadable.
+const BLUR_RADIUS: usize = 24;
+
+/// How a hidden area is covered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MaskStyle {
+    /// Solid black box
+    #[default]
+    Black,
+    /// Heavy blur of the contents
+    Blur,
+}
+
+/// What a capture shows, for placing masked areas in its frames.
+enum MaskedCapture {
+    /// An area of the screen, in the coordinate space of [`MonitorInfo`] positions
+    Screen {
+        area: WindowBounds,
+        monitors: Vec<MonitorInfo>,
+    },
+    /// A single window, captured at its own size
+    Window { window: WindowInfo },
+}
+
+/// Tracks the areas to hide within one capture.
+struct MaskTracker {
+    capture: MaskedCapture,
+    exclusions: WindowExclusions,
+    redactions: Redactions,
+    /// Areas to hide, relative to the captured area
+    rects: Mutex<Vec<(WindowBounds, MaskStyle)>>,
+    /// Whether a failure to find windows has been logged
+    warned: AtomicBool,
+}
+
+impl MaskTracker {
+    /// Recompute the areas to hide.
+    fn refresh(&self) {
+        let exclusions = self.exclusions.get();
+        let redactions = self.redactions.list();
+
+        let rects = match &self.capture {
+            MaskedCapture::Window { window } => {
+                // Window captures show only the one window; place its redactions.
+                // Its size is only known per frame, so they are clipped there.
+                let area = WindowBounds { x: 0, y: 0, width: 0, height: 0 };
+                redactions
+                    .iter()
+                    .filter(|r| matches!(&r.anchor, RedactionAnchor::Window { window: rule } if rule.matches(window)))
+                    .filter_map(|r| Some((relative_rect(r.bounds(), area)?, r.style)))
+                    .collect()
+            }
+            MaskedCapture::Screen { area, monitors } => {
+                let mut rects: Vec<(WindowBounds, MaskStyle)> = Vec::new();
+
+                for redaction in &redactions {
+                    if let RedactionAnchor::Monitor { monitor_id } = &redaction.anchor {
+                        if let Some(monitor) = monitors.iter().find(|m| &m.id == monitor_id) {
+                            let bounds = redaction.bounds().offset(monitor.x, monitor.y);
+                            rects.extend(relative_rect(bounds, *area).map(|rect| (rect, redaction.style)));
+                        }
+                    }
+                }
+
+                // Excluded windows and window redactions follow the windows
+                let window_redactions: Vec<_> = redactions
+                    .iter()
+                    .filter_map(|r| match &r.anchor {
+                        RedactionAnchor::Window { window } => Some((window, r)),
+                        RedactionAnchor::Monitor { .. } => None,
+                    })
+                    .collect();
+                if exclusions.masks_windows() || !window_redactions.is_empty() {
+                    match self.windows() {
+                        Some(windows) => {
+                            for (window, bounds) in windows {
+                                if exclusions.masks(&window) {
+                                    rects.extend(relative_rect(bounds, *area).map(|rect| (rect, exclusions.style)));
+                                }
+                                for (rule, redaction) in &window_redactions {
+                                    if rule.matches(&window) {
+                                        let bounds = redaction.bounds().offset(bounds.x, bounds.y);
+                                        rects.extend(relative_rect(bounds, *area).map(|rect| (rect, redaction.style)));
+                                    }
+                                }
+                            }
+                        }
+                        // Keep the last known positions rather than exposing the windows
+                        None => return,
+                    }
+                }
+
+                rects
+            }
+        };
+
+        *self.rects.lock().unwrap() = rects;
+    }
+
+    /// Windows currently on screen, logging the first failure.
+    fn windows(&self) -> Option<Vec<(WindowInfo, WindowBounds)>> {
+        match super::list_window_bounds() {
+            Ok(windows) => Some(windows),
+            Err(e) => {
+                if !self.warned.swap(true, Ordering::Relaxed) {
+                    tracing::error!(target: "Mask", "Failed to track windows: {}", e);
+                }
+                None
+            }
+        }
+    }
+}
+
+/// Hides excluded windows and redaction regions in the frames of one capture.
+pub struct FrameMask {
+    tracker: Arc<MaskTracker>,
+    stop_flag: Arc<AtomicBool>,
+}
+
+impl FrameMask {
+    /// Start tracking the areas to hide in a capture of `target`.
+    ///
+    /// Returns `None` if the captured monitor or window can't be found.
+    pub fn start(target: &CaptureTarget, exclusions: &WindowExclusions, redactions: &Redactions) -> Option<Self> {
+        let capture = match target {
+            CaptureTarget::Window { window_handle } => {
+                let window = super::list_windows().into_iter().find(|w| w.handle == *window_handle)?;
+                MaskedCapture::Window { window }
+            }
+            CaptureTarget::Region { region } => {
+                // The area the capture backend records
+                let layout = DesktopLayout::new(super::list_monitors(), DESKTOP_SPACE);
+                let region = layout.clip_region(region)?;
+                let monitor = layout.monitor(&region.monitor_id)?;
+                let area = WindowBounds {
+                    x: monitor.x + region.x,
+                    y: monitor.y + region.y,
+                    width: region.width,
+                    height: region.height,
+                };
+                MaskedCapture::Screen { area, monitors: layout.into_monitors() }
+            }
+            CaptureTarget::Display {
+                monitor_id,
+                width,
+                height,
+            } => {
+                let monitors = super::list_monitors();
+                let monitor = monitors.iter().find(|m| &m.id == monitor_id)?;
+                let area = WindowBounds {
+                    x: monitor.x,
+                    y: monitor.y,
+                    width: *width,
+                    height: *height,
+                };
+                MaskedCapture::Screen { area, monitors }
+            }
+        };
+
+        let tracker = Arc::new(MaskTracker {
+            capture,
+            exclusions: exclusions.clone(),
+            redactions: redactions.clone(),
+            rects: Mutex::new(Vec::new()),
+            warned: AtomicBool::new(false),
+        });
+        let stop_flag = Arc::new(AtomicBool::new(false));
+
+        // Find the areas before the first frame, then keep tracking them
+        tracker.refresh();
+        let thread_tracker = tracker.clone();
+        let thread_stop = stop_flag.clone();
+        std::thread::spawn(move || {
+            while !thread_stop.load(Ordering::Relaxed) {
+                std::thread::sleep(TRACK_INTERVAL);
+                thread_tracker.refresh();
+            }
+        });
+
+        Some(Self { tracker, stop_flag })
+    }
+
+    /// Hide the tracked areas in `frame`.
+    pub fn apply(&self, frame: &mut CapturedFrame) {
+        let rects = self.tracker.rects.lock().unwrap().clone();
+        if rects.is_empty() {
+            return;
+        }
+
+        // The frame may be scaled relative to the captured area
+        let (width, height) = match self.tracker.capture {
+            MaskedCapture::Screen { area, .. } => (area.width, area.height),
+            MaskedCapture::Window { .. } => (frame.width, frame.height),
+        };
+        let scale_x = frame.width as f64 / width.max(1) as f64;
+        let scale_y = frame.height as f64 / height.max(1) as f64;
+        for (rect, style) in rects {
+            let x0 = ((rect.x as f64 * scale_x) as u32).min(frame.width);
+            let y0 = ((rect.y as f64 * scale_y) as u32).min(frame.height);
+            let x1 = (((rect.x as f64 + rect.width as f64) * scale_x).ceil() as u32).min(frame.width);
+            let y1 = (((rect.y as f64 + rect.height as f64) * scale_y).ceil() as u32).min(frame.height);
+            match style {
+                // The blur works on 8-bit channels; deeper frames are blacked out instead
+                MaskStyle::Blur if frame.format == PixelFormat::Bgra8 => blur(frame, x0, y0, x1, y1),
+                _ => fill_black(frame, x0, y0, x1, y1),
+            }
+        }
+    }
+}
+
+impl Drop for FrameMask {
+    fn drop(&mut self) {
+        self.stop_flag.store(true, Ordering::Relaxed);
+    }
+}
+
+impl WindowBounds {
+    /// These bounds moved by (`dx`, `dy`).
+    fn offset(self, dx: i32, dy: i32) -> Self {
+        Self {
+            x: self.x + dx,
+            y: self.y + dy,
+            ..self
+        }
+    }
+}
+
+/// `bounds` relative to `area`, clipped to it; `None` if they don't overlap.
+///
+/// An `area` with zero size (e.g. a window whose size isn't known yet) doesn't clip.
+fn relative_rect(bounds: WindowBounds, area: WindowBounds) -> Option<WindowBounds> {
+    let (area_width, area_height) = match (area.width, area.height) {
+        (0, 0) => (i32::MAX, i32::MAX),
+        (width, height) => (width as i32, height as i32),
+    };
+    let x0 = (bounds.x - area.x).max(0);
+    let y0 = (bounds.y - area.y).max(0);
+    let x1 = (bounds.x - area.x).saturating_add(bounds.width as i32).min(area_width);
+    let y1 = (bounds.y - area.y).saturating_add(bounds.height as i32).min(area_height);
+    if x1 <= x0 || y1 <= y0 {
+        return None;
+    }
+    Some(WindowBounds {
+        x: x0,
+        y: y0,
+        width: (x1 - x0) as u32,
+        height: (y1 - y0) as u32,
+    })
+}
+
+/// Fill `x0..x1`, `y0..y1` with opaque black.
+fn fill_black(frame: &mut CapturedFrame, x0: u32, y0: u32, x1: u32, y1: u32) {
+    let black = frame.format.black();
+    let bpp = black.len();
+    let stride = frame.width as usize * bpp;
+    for y in y0 as usize..y1 as usize {
+        let row = &mut frame.data[y * stride + x0 as usize * bpp..y * stride + x1 as usize * bpp];
+        for pixel in row.chunks_exact_mut(bpp) {
+            pixel.copy_from_slice(black);
+        }
+    }
+}
+
+/// Blur `x0..x1`, `y0..y1` with two passes of a separable box blur.
+fn blur(frame: &mut CapturedFrame, x0: u32, y0: u32, x1: u32, y1: u32) {
+    let (width, height) = ((x1 - x0) as usize, (y1 - y0) as usize);
+    if width == 0 || height == 0 {
+        return;
+    }
+    let stride = frame.width as usize * 4;
+    let offset = y0 as usize * stride + x0 as usize * 4;
+
+    for _ in 0..2 {
+        for y in 0..height {
+            box_blur_line(&mut frame.data, offset + y * stride, 4, width);
+        }
+        for x in 0..width {
+            box_blur_line(&mut frame.data, offset + x * 4, stride, height);
+        }
+    }
+}
+
+/// Box blur `len` BGRA pixels starting at `start`, `step` bytes apart.
+fn box_blur_line(data: &mut [u8], start: usize, step: usize, len: usize) {
+    let line: Vec<[u8; 4]> = (0..len)
+        .map(|i| {
+            let p = start + i * step;
+            [data[p], data[p + 1], data[p + 2], data[p + 3]]
+        })
+        .collect();
+
+    // Running sums over a window clamped at the edges
+    let radius = BLUR_RADIUS.min(len.saturating_sub(1) / 2).max(1);
+    let mut sums = [0u32; 4];
+    let at = |i: isize| line[i.clamp(0, len as isize - 1) as usize];
+    for i in -(radius as isize)..=radius as isize {
+        for (sum, value) in sums.iter_mut().zip(at(i)) {
+            *sum += value as u32;
+        }
+    }
+    let count = (2 * radius + 1) as u32;
+    for i in 0..len {
+        let p = start + i * step;
+        for c in 0..4 {
+            data[p + c] = (sums[c] / count) as u8;
+        }
+        let (add, remove) = (at(i as isize + radius as isize + 1), at(i as isize - radius as isize));
+        for c in 0..4 {
+            sums[c] = sums[c] + add[c] as u32 - remove[c] as u32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::types::ColorSpace;
+
+    #[test]
+    fn test_mask_regions() {
+        let area = WindowBounds { x: 1920, y: 0, width: 1920, height: 1080 };
+        let inside = WindowBounds { x: 1800, y: 100, width: 400, height: 300 };
+        assert_eq!(
+            relative_rect(inside, area),
+            Some(WindowBounds { x: 0, y: 100, width: 280, height: 300 })
+        );
+        assert_eq!(relative_rect(WindowBounds { x: 0, y: 0, width: 800, height: 600 }, area), None);
+
+        let mut frame = CapturedFrame {
+            width: 4,
+            height: 2,
+            data: vec![255; 4 * 2 * 4],
+            format: PixelFormat::Bgra8,
+            color: ColorSpace::SRGB,
+            captured_at: std::time::Instant::now(),
+            presented_at: None,
+        };
+        fill_black(&mut frame, 1, 0, 3, 1);
+        assert_eq!(&frame.data[..16], &[255, 255, 255, 255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255, 255]);
+        assert!(frame.data[16..].iter().all(|&b| b == 255));
+    }
+
+    #[test]
+    fn test_blur_averages() {
+        let mut frame = CapturedFrame {
+            width: 8,
+            height: 1,
+            data: [[0, 0, 0, 255], [255, 255, 255, 255]].repeat(4).concat(),
+            format: PixelFormat::Bgra8,
+            color: ColorSpace::SRGB,
+            captured_at: std::time::Instant::now(),
+            presented_at: None,
+        };
+        blur(&mut frame, 0, 0, 8, 1);
+        // Alternating black and white blurs to grey
+        assert!(frame.data.chunks(4).all(|p| (64..=192).contains(&p[0])));
+    }
+
+    #[test]
+    fn test_mask_golden() {
+        let mut frame = crate::capture::mock::synthesize_frame(64, 36, 12);
+        blur(&mut frame, 8, 4, 40, 20);
+        fill_black(&mut frame, 44, 24, 60, 32);
+        crate::golden::assert_golden("mask_blur_fill", &frame, 1);
+    }
+}
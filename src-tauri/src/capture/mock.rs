@@ -0,0 +1,303 @@
+//! Synthetic capture backend for headless testing.
+//!
+//! Enabled by the `mock-capture` feature, which makes it the platform
+//! backend, so the encoder, muxer and overlay stages can run in CI without a
+//! display. Frames are deterministic: color bars over a gradient that moves
+//! with each frame, with the frame number burned into the top-left corner.
+
+use crate::capture::error::{CaptureError, EnumerationError};
+use crate::capture::glyphs;
+use crate::capture::queue::{frame_channel, FrameSender};
+use crate::capture::types::{
+    Capabilities, CapturedFrame, CaptureRegion, ColorSpace, FrameReceiver, MonitorInfo, PixelFormat, StopHandle, WindowBounds, WindowInfo,
+};
+use crate::capture::{CaptureBackend, HighlightProvider, MonitorEnumerator, WindowEnumerator};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Handle of the single mock window.
+pub const MOCK_WINDOW_HANDLE: isize = 1;
+
+/// Capture backend that synthesizes frames instead of capturing the screen.
+pub struct MockBackend {
+    frame_rate: u32,
+    frame_limit: Option<u64>,
+}
+
+impl MockBackend {
+    /// Create a mock backend delivering frames at 30 fps until stopped.
+    pub fn new() -> Self {
+        Self {
+            frame_rate: 30,
+            frame_limit: None,
+        }
+    }
+
+    /// Deliver frames at `frame_rate` fps, or as fast as the receiver takes
+    /// them if 0.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn with_frame_rate(mut self, frame_rate: u32) -> Self {
+        self.frame_rate = frame_rate;
+        self
+    }
+
+    /// End each capture after `frame_limit` frames, closing its receiver.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn with_frame_limit(mut self, frame_limit: u64) -> Self {
+        self.frame_limit = Some(frame_limit);
+        self
+    }
+
+    fn monitors() -> Vec<MonitorInfo> {
+        let monitor = |id: &str, x: i32, width: u32, height: u32| MonitorInfo {
+            id: id.to_string(),
+            name: format!("Mock {}x{}", width, height),
+            x,
+            y: 0,
+            width,
+            height,
+            is_primary: x == 0,
+            scale_factor: 1.0,
+            model: None,
+            refresh_rate: Some(60.0),
+            rotation: 0,
+        };
+        vec![monitor("mock-0", 0, 1920, 1080), monitor("mock-1", 1920, 1280, 720)]
+    }
+
+    fn window() -> (WindowInfo, WindowBounds) {
+        let info = WindowInfo {
+            handle: MOCK_WINDOW_HANDLE,
+            title: "Mock Window".to_string(),
+            process_name: "mock".to_string(),
+            pid: std::process::id(),
+            class: "MockWindow".to_string(),
+            icon: None,
+            thumbnail: None,
+        };
+        (info, WindowBounds { x: 100, y: 100, width: 800, height: 600 })
+    }
+
+    fn monitor(monitor_id: &str) -> Result<MonitorInfo, CaptureError> {
+        Self::monitors()
+            .into_iter()
+            .find(|m| m.id == monitor_id)
+            .ok_or_else(|| CaptureError::TargetNotFound(format!("Monitor {}", monitor_id)))
+    }
+
+    /// Start a thread synthesizing `width`x`height` frames.
+    fn start(&self, width: u32, height: u32) -> Result<(FrameReceiver, StopHandle), CaptureError> {
+        if width == 0 || height == 0 {
+            return Err(CaptureError::InvalidParameters(format!(
+                "Invalid capture size: {}x{}",
+                width, height
+            )));
+        }
+
+        let (frame_tx, frame_rx) = frame_channel(30);
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let stop = stop_flag.clone();
+        let frame_rate = self.frame_rate;
+        let frame_limit = self.frame_limit;
+
+        std::thread::spawn(move || run_source(frame_tx, stop, width, height, frame_rate, frame_limit));
+
+        Ok((frame_rx, stop_flag))
+    }
+}
+
+impl Default for MockBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WindowEnumerator for MockBackend {
+    fn list_windows(&self) -> Result<Vec<WindowInfo>, EnumerationError> {
+        Ok(vec![Self::window().0])
+    }
+
+    fn list_window_bounds(&self) -> Result<Vec<(WindowInfo, WindowBounds)>, EnumerationError> {
+        Ok(vec![Self::window()])
+    }
+}
+
+impl MonitorEnumerator for MockBackend {
+    fn list_monitors(&self) -> Result<Vec<MonitorInfo>, EnumerationError> {
+        Ok(Self::monitors())
+    }
+}
+
+impl CaptureBackend for MockBackend {
+    fn start_window_capture(
+        &self,
+        window_handle: isize,
+    ) -> Result<(FrameReceiver, StopHandle), CaptureError> {
+        if window_handle != MOCK_WINDOW_HANDLE {
+            return Err(CaptureError::TargetNotFound(format!("Window {}", window_handle)));
+        }
+        let (_, bounds) = Self::window();
+        self.start(bounds.width, bounds.height)
+    }
+
+    fn start_region_capture(
+        &self,
+        region: CaptureRegion,
+    ) -> Result<(FrameReceiver, StopHandle), CaptureError> {
+        let region = region.to_physical(&Self::monitor(&region.monitor_id)?);
+        self.start(region.width, region.height)
+    }
+
+    fn start_display_capture(
+        &self,
+        monitor_id: String,
+        width: u32,
+        height: u32,
+    ) -> Result<(FrameReceiver, StopHandle), CaptureError> {
+        let monitor = Self::monitor(&monitor_id)?;
+        if width == 0 || height == 0 {
+            return self.start(monitor.width, monitor.height);
+        }
+        self.start(width, height)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            window_capture: true,
+            region_capture: true,
+            display_capture: true,
+            max_fps: (self.frame_rate > 0).then_some(self.frame_rate),
+            ..Capabilities::default()
+        }
+    }
+}
+
+impl HighlightProvider for MockBackend {
+    fn show_highlight(&self, x: i32, y: i32, width: i32, height: i32) {
+        tracing::info!(target: "Mock", "Highlight at ({}, {}) {}x{}", x, y, width, height);
+    }
+
+    fn hide_highlight(&self) {
+        tracing::info!(target: "Mock", "Highlight hidden");
+    }
+}
+
+/// Deliver frames until stopped, the frame limit is reached or the receiver
+/// goes away.
+fn run_source(
+    frame_tx: FrameSender,
+    stop_flag: StopHandle,
+    width: u32,
+    height: u32,
+    frame_rate: u32,
+    frame_limit: Option<u64>,
+) {
+    let started = Instant::now();
+    let mut index = 0u64;
+    while !stop_flag.load(Ordering::Relaxed) && frame_limit.is_none_or(|limit| index < limit) {
+        if frame_tx.send(synthesize_frame(width, height, index)).is_err() {
+            break;
+        }
+        index += 1;
+
+        // Pace against the start time so delivery doesn't drift
+        if frame_rate > 0 {
+            let due = started + Duration::from_secs_f64(index as f64 / frame_rate as f64);
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                std::thread::sleep(wait);
+            }
+        }
+    }
+}
+
+/// Frame number `index` of a `width`x`height` mock capture.
+///
+/// The top two thirds are color bars, the bottom third a grayscale ramp
+/// that moves 4 pixels right per frame, and the frame number is drawn in
+/// black on white in the top-left corner.
+pub fn synthesize_frame(width: u32, height: u32, index: u64) -> CapturedFrame {
+    let (w, h) = (width as usize, height as usize);
+    let mut data = vec![0u8; w * h * 4];
+    let bars_height = h * 2 / 3;
+    let shift = (index as usize).wrapping_mul(4);
+
+    for (y, row) in data.chunks_exact_mut(w * 4).enumerate() {
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            if y < bars_height {
+                pixel.copy_from_slice(&glyphs::COLOR_BARS[x * glyphs::COLOR_BARS.len() / w]);
+            } else {
+                let level = ((x + w - shift % w) % w * 256 / w) as u8;
+                pixel.copy_from_slice(&[level, level, level, 255]);
+            }
+        }
+    }
+
+    let mut frame = CapturedFrame {
+        width,
+        height,
+        data,
+        format: PixelFormat::Bgra8,
+        color: ColorSpace::SRGB,
+        captured_at: Instant::now(),
+        presented_at: None,
+    };
+
+    // Black digits in a white box with a 1-pixel margin
+    let scale = (h / 108).max(1);
+    let counter = index.to_string();
+    let box_width = glyphs::text_width(&counter, scale) + 2 * scale;
+    let box_height = glyphs::text_height(scale) + 2 * scale;
+    glyphs::fill_rect(&mut frame, 0, 0, box_width, box_height, &[255, 255, 255, 255]);
+    glyphs::draw_text(&mut frame, scale, scale, scale, &counter, &[0, 0, 0, 255]);
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_synthesize_frame() {
+        let frame = synthesize_frame(64, 48, 7);
+        assert_eq!(frame.data.len(), 64 * 48 * 4);
+        assert_eq!(frame.data, synthesize_frame(64, 48, 7).data);
+
+        // The counter and gradient change from frame to frame; the bars don't
+        let next = synthesize_frame(64, 48, 8);
+        assert_ne!(frame.data, next.data);
+        let bars_row = 20 * 64 * 4;
+        assert_eq!(frame.data[bars_row..bars_row + 64 * 4], next.data[bars_row..bars_row + 64 * 4]);
+        assert_eq!(frame.data[bars_row + 63 * 4..bars_row + 64 * 4], glyphs::COLOR_BARS[6]);
+    }
+
+    #[tokio::test]
+    async fn test_capture_frame_limit() {
+        let backend = MockBackend::new().with_frame_rate(0).with_frame_limit(5);
+        let (mut frames, _stop) = backend.start_display_capture("mock-1".to_string(), 0, 0).unwrap();
+
+        let mut count = 0;
+        while let Some(frame) = frames.recv().await {
+            assert_eq!((frame.width, frame.height), (1280, 720));
+            count += 1;
+        }
+        assert_eq!(count, 5);
+        assert!(backend.start_window_capture(42).is_err());
+        assert_eq!(backend.capabilities().max_fps, None);
+        assert_eq!(MockBackend::new().capabilities().max_fps, Some(30));
+    }
+
+    #[tokio::test]
+    async fn test_blocking_backend() {
+        use crate::capture::{AsyncCaptureBackend, BlockingBackend, CaptureOptions};
+
+        let backend = BlockingBackend::new(MockBackend::new().with_frame_rate(0).with_frame_limit(3));
+        let (mut frames, _stop) = backend
+            .capture_display("mock-1".to_string(), 640, 360, CaptureOptions::default())
+            .await
+            .unwrap();
+        let frame = frames.recv().await.unwrap();
+        assert_eq!((frame.width, frame.height), (640, 360));
+        assert!(backend.capture_window(42, CaptureOptions::default()).await.is_err());
+    }
+}
@@ -3,7 +3,27 @@
 //! This module provides platform-agnostic interfaces for screen capture operations,
 //! with platform-specific implementations selected at compile time.
 
+pub mod audio;
+pub mod desktop;
+pub mod edid;
 pub mod error;
+pub mod exclusion;
+pub mod game;
+pub mod glyphs;
+pub mod hdr;
+pub mod highlight;
+pub mod hotplug;
+pub mod icon;
+pub mod identity;
+pub mod mask;
+#[cfg(any(test, feature = "mock-capture"))]
+pub mod mock;
+pub mod pause;
+pub mod queue;
+pub mod recovery;
+pub mod redaction;
+pub mod refresh;
+pub mod thumbnail;
 pub mod types;
 
 // Platform-specific modules
@@ -15,21 +35,61 @@ pub mod linux;
 pub mod macos;
 
 // Re-export common types for convenience
-pub use error::{CaptureError, EnumerationError};
-pub use types::{CapturedFrame, CaptureRegion, FrameReceiver, MonitorInfo, StopHandle, WindowInfo};
+pub use error::{CaptureError, EnumerationError, RegionError};
+pub use hdr::HdrMode;
+pub use types::{
+    Capabilities, CaptureOptions, CapturedFrame, ColorSpace, CaptureRegion, CaptureTarget, CoordinateSpace, FrameReceiver, MonitorInfo, PixelFormat,
+    StopHandle, WindowBounds, WindowInfo, WindowState,
+};
 
 // Platform-specific backend aliases
-#[cfg(target_os = "windows")]
+#[cfg(all(target_os = "windows", not(feature = "mock-capture")))]
 pub use windows::WindowsBackend as PlatformBackend;
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", not(feature = "mock-capture")))]
 pub use linux::LinuxBackend as PlatformBackend;
-#[cfg(target_os = "macos")]
+#[cfg(all(target_os = "macos", not(feature = "mock-capture")))]
 pub use macos::MacOSBackend as PlatformBackend;
+#[cfg(feature = "mock-capture")]
+pub use mock::MockBackend as PlatformBackend;
+
+// Backends with an asynchronous capture API implement it directly; the
+// others run their synchronous one on a blocking thread
+#[cfg(all(target_os = "linux", not(feature = "mock-capture")))]
+pub type AsyncPlatformBackend = linux::LinuxBackend;
+#[cfg(any(not(target_os = "linux"), feature = "mock-capture"))]
+pub type AsyncPlatformBackend = BlockingBackend<PlatformBackend>;
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
 
 /// Trait for window enumeration operations.
 pub trait WindowEnumerator: Send + Sync {
     /// List all visible, capturable windows.
     fn list_windows(&self) -> Result<Vec<WindowInfo>, EnumerationError>;
+
+    /// List the windows currently shown on screen with their bounds, in the
+    /// same coordinate space as [`MonitorInfo`] positions.
+    fn list_window_bounds(&self) -> Result<Vec<(WindowInfo, WindowBounds)>, EnumerationError> {
+        Err(EnumerationError::NotImplemented(
+            "Window bounds are not available on this platform".to_string(),
+        ))
+    }
+
+    /// Fill in the thumbnails of `windows` where the platform can grab them.
+    /// Windows it can't are left without one.
+    fn add_thumbnails(&self, _windows: &mut [WindowInfo]) {}
+
+    /// Whether a window is still open, and whether it's minimized.
+    ///
+    /// By default a window is open while it's listed; platforms that can
+    /// tell minimized windows apart override this.
+    fn window_state(&self, window_handle: isize) -> WindowState {
+        match self.list_windows() {
+            Ok(windows) if !windows.iter().any(|w| w.handle == window_handle) => WindowState::Closed,
+            _ => WindowState::Open,
+        }
+    }
 }
 
 /// Trait for monitor/display enumeration operations.
@@ -66,12 +126,93 @@ pub trait CaptureBackend: Send + Sync {
         width: u32,
         height: u32,
     ) -> Result<(FrameReceiver, StopHandle), CaptureError>;
+
+    /// What this backend can capture on the current system.
+    fn capabilities(&self) -> Capabilities;
+
+    /// Choose how captures started afterwards deliver frames. Backends
+    /// ignore options they can't honor and deliver 8-bit SDR frames instead.
+    fn set_options(&mut self, _options: CaptureOptions) {}
+}
+
+/// A capture being started by an [`AsyncCaptureBackend`].
+pub type CaptureFuture<'a> = Pin<Box<dyn Future<Output = Result<(FrameReceiver, StopHandle), CaptureError>> + Send + 'a>>;
+
+/// Trait for capture operations on platforms whose capture APIs are
+/// asynchronous, such as the xdg-desktop-portal and PipeWire on Linux.
+///
+/// Starting a capture resolves once the platform has handed over a stream,
+/// without holding a thread while the user answers a picker or the
+/// compositor sets the stream up. Frames then arrive on the
+/// [`FrameReceiver`], which is awaited with [`FrameReceiver::recv`].
+/// Backends with only a synchronous API are adapted with [`BlockingBackend`].
+pub trait AsyncCaptureBackend: Send + Sync {
+    /// Start capturing a window by its handle/ID, delivering frames as
+    /// chosen by `options`.
+    fn capture_window(&self, window_handle: isize, options: CaptureOptions) -> CaptureFuture<'_>;
+
+    /// Start capturing a screen region.
+    fn capture_region(&self, region: CaptureRegion, options: CaptureOptions) -> CaptureFuture<'_>;
+
+    /// Start capturing an entire display.
+    fn capture_display(&self, monitor_id: String, width: u32, height: u32, options: CaptureOptions) -> CaptureFuture<'_>;
+}
+
+/// Adapts a synchronous [`CaptureBackend`] to [`AsyncCaptureBackend`] by
+/// starting its captures on tokio's blocking thread pool.
+#[allow(dead_code)]
+pub struct BlockingBackend<B> {
+    inner: Arc<Mutex<B>>,
+}
+
+#[allow(dead_code)]
+impl<B: CaptureBackend + 'static> BlockingBackend<B> {
+    pub fn new(backend: B) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(backend)),
+        }
+    }
+
+    /// Run `start` on the backend, set to `options`, on a blocking thread.
+    fn spawn<F>(&self, options: CaptureOptions, start: F) -> CaptureFuture<'_>
+    where
+        F: FnOnce(&B) -> Result<(FrameReceiver, StopHandle), CaptureError> + Send + 'static,
+    {
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let mut backend = inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                backend.set_options(options);
+                start(&backend)
+            })
+            .await
+            .map_err(|e| CaptureError::PlatformError(format!("Capture thread failed: {}", e)))?
+        })
+    }
+}
+
+impl<B: CaptureBackend + 'static> AsyncCaptureBackend for BlockingBackend<B> {
+    fn capture_window(&self, window_handle: isize, options: CaptureOptions) -> CaptureFuture<'_> {
+        self.spawn(options, move |backend| backend.start_window_capture(window_handle))
+    }
+
+    fn capture_region(&self, region: CaptureRegion, options: CaptureOptions) -> CaptureFuture<'_> {
+        self.spawn(options, move |backend| backend.start_region_capture(region))
+    }
+
+    fn capture_display(&self, monitor_id: String, width: u32, height: u32, options: CaptureOptions) -> CaptureFuture<'_> {
+        self.spawn(options, move |backend| backend.start_display_capture(monitor_id, width, height))
+    }
 }
 
 /// Trait for visual highlight rendering.
 pub trait HighlightProvider: Send + Sync {
-    /// Show a highlight border around the specified area.
+    /// Show a highlight border around the specified area. It dismisses
+    /// itself after a few seconds, and replaces any highlight showing.
     fn show_highlight(&self, x: i32, y: i32, width: i32, height: i32);
+
+    /// Dismiss the highlight showing, if any.
+    fn hide_highlight(&self);
 }
 
 /// Get the platform-specific capture backend.
@@ -79,14 +220,125 @@ pub fn get_backend() -> PlatformBackend {
     PlatformBackend::new()
 }
 
+/// Get the platform's asynchronous capture backend.
+pub fn get_async_backend() -> AsyncPlatformBackend {
+    #[cfg(all(target_os = "linux", not(feature = "mock-capture")))]
+    return linux::LinuxBackend::new();
+    #[cfg(any(not(target_os = "linux"), feature = "mock-capture"))]
+    return BlockingBackend::new(PlatformBackend::new());
+}
+
 // Convenience functions that use the platform backend
 
+/// `region` checked against the connected monitors and clamped to the one
+/// it's recorded from; see [`desktop::DesktopLayout::validate_region`].
+pub fn validate_region(region: &CaptureRegion) -> Result<CaptureRegion, RegionError> {
+    let layout = desktop::DesktopLayout::current();
+    let clamped = layout.validate_region(region)?;
+    if let Some(monitor) = layout.monitor(&region.monitor_id) {
+        let requested = region.to_physical(monitor);
+        if (requested.x, requested.y, requested.width, requested.height, &requested.monitor_id)
+            != (clamped.x, clamped.y, clamped.width, clamped.height, &clamped.monitor_id)
+        {
+            tracing::info!(
+                target: "Capture",
+                "Region clamped to {}x{} at {},{} on {}",
+                clamped.width, clamped.height, clamped.x, clamped.y, clamped.monitor_id
+            );
+        }
+    }
+    Ok(clamped)
+}
+
+/// Start capturing the given target with the platform backend.
+///
+/// Frames are always 8-bit SDR; see [`start_capture_with`] for HDR and deep color.
+pub fn start_capture(target: CaptureTarget) -> Result<(FrameReceiver, StopHandle), CaptureError> {
+    start_capture_with(target, CaptureOptions::default())
+}
+
+/// Start capturing the given target, delivering frames as chosen by `options`.
+pub fn start_capture_with(
+    target: CaptureTarget,
+    options: CaptureOptions,
+) -> Result<(FrameReceiver, StopHandle), CaptureError> {
+    let mut backend = get_backend();
+    backend.set_options(options);
+    match target {
+        CaptureTarget::Window { window_handle } => backend.start_window_capture(window_handle),
+        CaptureTarget::Region { region } => backend.start_region_capture(validate_region(&region)?),
+        CaptureTarget::Display {
+            monitor_id,
+            width,
+            height,
+        } => backend.start_display_capture(monitor_id, width, height),
+    }
+}
+
+/// Start capturing the given target with the platform's asynchronous
+/// backend, delivering frames as chosen by `options`.
+///
+/// Unlike [`start_capture_with`], this doesn't block the calling thread
+/// while the capture starts, and works on any tokio runtime.
+pub async fn start_capture_async(
+    target: CaptureTarget,
+    options: CaptureOptions,
+) -> Result<(FrameReceiver, StopHandle), CaptureError> {
+    let backend = get_async_backend();
+    match target {
+        CaptureTarget::Window { window_handle } => backend.capture_window(window_handle, options).await,
+        CaptureTarget::Region { region } => backend.capture_region(validate_region(&region)?, options).await,
+        CaptureTarget::Display {
+            monitor_id,
+            width,
+            height,
+        } => backend.capture_display(monitor_id, width, height, options).await,
+    }
+}
+
+/// Whether the active capture receives frames as GPU buffers mapped directly,
+/// without the compositor copying them into shared memory first.
+pub fn zero_copy_active() -> bool {
+    #[cfg(target_os = "linux")]
+    return linux::pipewire_capture::dmabuf_active();
+    #[cfg(not(target_os = "linux"))]
+    return false;
+}
+
+/// What the platform backend can do on the current system.
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        sandbox: crate::sandbox::detect().map(|sandbox| sandbox.name().to_string()),
+        ..get_backend().capabilities()
+    }
+}
+
 /// List all visible, capturable windows.
 pub fn list_windows() -> Vec<WindowInfo> {
     let backend = get_backend();
     backend.list_windows().unwrap_or_default()
 }
 
+/// List all capturable windows with their thumbnails, where the platform
+/// can grab them. Slower than [`list_windows`].
+pub fn list_windows_with_thumbnails() -> Vec<WindowInfo> {
+    let backend = get_backend();
+    let mut windows = backend.list_windows().unwrap_or_default();
+    backend.add_thumbnails(&mut windows);
+    windows
+}
+
+/// List the windows currently shown on screen with their bounds.
+pub fn list_window_bounds() -> Result<Vec<(WindowInfo, WindowBounds)>, EnumerationError> {
+    let backend = get_backend();
+    backend.list_window_bounds()
+}
+
+/// Whether a window is still open, and whether it's minimized.
+pub fn window_state(window_handle: isize) -> WindowState {
+    get_backend().window_state(window_handle)
+}
+
 /// List all connected monitors.
 pub fn list_monitors() -> Vec<MonitorInfo> {
     let backend = get_backend();
@@ -98,3 +350,9 @@ pub fn show_highlight(x: i32, y: i32, width: i32, height: i32) {
     let backend = get_backend();
     backend.show_highlight(x, y, width, height);
 }
+
+/// Dismiss the highlight showing, if any.
+pub fn hide_highlight() {
+    let backend = get_backend();
+    backend.hide_highlight();
+}
@@ -0,0 +1,99 @@
+//! Pausing a recording.
+//!
+//! While a recording is paused the encoder lets its frame times pass
+//! without writing and captured audio is dropped, so the paused stretch is
+//! left out of the file rather than filled with a frozen picture. Anything
+//! that keeps to the wall clock subtracts [`PauseState::paused_time`] to
+//! stay in step.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Whether a recording is paused, shared by everything recording it.
+#[derive(Debug, Default)]
+pub struct PauseState {
+    inner: Mutex<PauseInner>,
+}
+
+#[derive(Debug, Default)]
+struct PauseInner {
+    /// When the current pause began
+    since: Option<Instant>,
+    /// Length of the pauses that have ended
+    total: Duration,
+    /// The pauses that have ended, oldest first
+    ended: Vec<Pause>,
+}
+
+/// A stretch of time a recording was paused.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pause {
+    pub start: Instant,
+    /// When it ended, or `None` if it hasn't
+    pub end: Option<Instant>,
+}
+
+impl PauseState {
+    /// Pause or resume. Returns false if it already was.
+    pub fn set_paused(&self, paused: bool) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        match (paused, inner.since) {
+            (true, None) => inner.since = Some(Instant::now()),
+            (false, Some(since)) => {
+                let now = Instant::now();
+                inner.total += now - since;
+                inner.since = None;
+                inner.ended.push(Pause { start: since, end: Some(now) });
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.inner.lock().unwrap().since.is_some()
+    }
+
+    /// Time spent paused so far, including the current pause.
+    pub fn paused_time(&self) -> Duration {
+        let inner = self.inner.lock().unwrap();
+        inner.total + inner.since.map_or(Duration::ZERO, |since| since.elapsed())
+    }
+
+    /// Every pause so far, oldest first, for timing something that
+    /// happened earlier than it's handled.
+    pub fn pauses(&self) -> Vec<Pause> {
+        let inner = self.inner.lock().unwrap();
+        let current = inner.since.map(|start| Pause { start, end: None });
+        inner.ended.iter().copied().chain(current).collect()
+    }
+
+    /// Time since `start` that wasn't spent paused.
+    pub fn active_time(&self, start: Instant) -> Duration {
+        start.elapsed().saturating_sub(self.paused_time())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pause_state() {
+        let pause = PauseState::default();
+        assert!(!pause.set_paused(false));
+        assert!(pause.set_paused(true));
+        assert!(!pause.set_paused(true));
+        assert!(pause.is_paused());
+        assert_eq!(pause.pauses()[0].end, None);
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(pause.set_paused(false));
+        let paused = pause.paused_time();
+        assert!(paused >= Duration::from_millis(20));
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(pause.paused_time(), paused);
+        let pauses = pause.pauses();
+        assert_eq!(pauses.len(), 1);
+        assert!(pauses[0].end.is_some_and(|end| end >= pauses[0].start + Duration::from_millis(20)));
+    }
+}
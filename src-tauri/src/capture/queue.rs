@@ -0,0 +1,300 @@
+//! Bounded frame queue between a capture backend and its consumer.
+//!
+//! Capture runs at whatever rate the platform delivers frames, so when the
+//! encoder falls behind something has to give. The queue holds a fixed number
+//! of frames and applies a [`DropPolicy`] when it is full, counting every frame
+//! it discards so slow machines degrade visibly instead of growing memory.
+
+use super::types::CapturedFrame;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use tokio::sync::Notify;
+
+/// What to do with a new frame when the queue is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DropPolicy {
+    /// Discard the oldest queued frame to make room (lowest latency)
+    #[default]
+    DropOldest,
+    /// Discard the incoming frame
+    DropNewest,
+    /// Wait for the consumer to make room, slowing the capture source
+    Block,
+}
+
+impl DropPolicy {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => DropPolicy::DropNewest,
+            2 => DropPolicy::Block,
+            _ => DropPolicy::DropOldest,
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            DropPolicy::DropOldest => 0,
+            DropPolicy::DropNewest => 1,
+            DropPolicy::Block => 2,
+        }
+    }
+}
+
+/// Policy applied to frame queues created from now on.
+static DEFAULT_POLICY: AtomicU8 = AtomicU8::new(0);
+
+/// Set the drop policy used by new captures.
+pub fn set_drop_policy(policy: DropPolicy) {
+    DEFAULT_POLICY.store(policy.as_u8(), Ordering::Relaxed);
+}
+
+/// The drop policy used by new captures.
+pub fn drop_policy() -> DropPolicy {
+    DropPolicy::from_u8(DEFAULT_POLICY.load(Ordering::Relaxed))
+}
+
+/// Frame counts for a queue.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueueStats {
+    /// Frames handed to the consumer
+    pub delivered: u64,
+    /// Frames discarded because the queue was full
+    pub dropped: u64,
+    /// Frames currently waiting in the queue
+    pub depth: usize,
+}
+
+/// Error returned by [`FrameReceiver::try_recv`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// No frame is waiting
+    Empty,
+    /// All senders are gone and the queue is drained
+    Disconnected,
+}
+
+/// Error returned by [`FrameSender::send`] when the receiver is gone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueClosed;
+
+struct State {
+    frames: VecDeque<CapturedFrame>,
+    senders: usize,
+    receiver_alive: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    capacity: usize,
+    policy: DropPolicy,
+    /// Wakes a blocked sender when space frees up or the receiver goes away
+    space_available: Condvar,
+    /// Wakes the receiver when a frame arrives or the last sender goes away
+    frame_available: Notify,
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+}
+
+/// Create a frame queue holding up to `capacity` frames, using the current
+/// default [`DropPolicy`].
+pub fn frame_channel(capacity: usize) -> (FrameSender, FrameReceiver) {
+    frame_channel_with_policy(capacity, drop_policy())
+}
+
+/// Create a frame queue holding up to `capacity` frames with an explicit policy.
+pub fn frame_channel_with_policy(capacity: usize, policy: DropPolicy) -> (FrameSender, FrameReceiver) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            frames: VecDeque::with_capacity(capacity.max(1)),
+            senders: 1,
+            receiver_alive: true,
+        }),
+        capacity: capacity.max(1),
+        policy,
+        space_available: Condvar::new(),
+        frame_available: Notify::new(),
+        delivered: AtomicU64::new(0),
+        dropped: AtomicU64::new(0),
+    });
+
+    (
+        FrameSender {
+            shared: shared.clone(),
+        },
+        FrameReceiver { shared },
+    )
+}
+
+/// Sending half of a frame queue, used by capture backends.
+pub struct FrameSender {
+    shared: Arc<Shared>,
+}
+
+impl FrameSender {
+    /// Queue a frame, applying the drop policy if the queue is full.
+    ///
+    /// With [`DropPolicy::Block`] this blocks the calling (capture) thread until
+    /// there is room. Fails only once the receiver has been dropped.
+    pub fn send(&self, frame: CapturedFrame) -> Result<(), QueueClosed> {
+        let shared = &*self.shared;
+        let mut state = shared.state.lock().unwrap();
+
+        if shared.policy == DropPolicy::Block {
+            while state.receiver_alive && state.frames.len() >= shared.capacity {
+                state = shared.space_available.wait(state).unwrap();
+            }
+        }
+        if !state.receiver_alive {
+            return Err(QueueClosed);
+        }
+
+        if state.frames.len() >= shared.capacity {
+            shared.dropped.fetch_add(1, Ordering::Relaxed);
+            match shared.policy {
+                DropPolicy::DropNewest => return Ok(()),
+                DropPolicy::DropOldest | DropPolicy::Block => {
+                    state.frames.pop_front();
+                }
+            }
+        }
+
+        state.frames.push_back(frame);
+        drop(state);
+        shared.frame_available.notify_one();
+        Ok(())
+    }
+
+    /// Number of frames dropped so far.
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Clone for FrameSender {
+    fn clone(&self) -> Self {
+        self.shared.state.lock().unwrap().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for FrameSender {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            self.shared.frame_available.notify_one();
+        }
+    }
+}
+
+/// Receiving half of a frame queue, used by the encoder.
+pub struct FrameReceiver {
+    shared: Arc<Shared>,
+}
+
+impl FrameReceiver {
+    /// Wait for the next frame. Returns `None` once all senders are gone and
+    /// the queue is drained.
+    pub async fn recv(&mut self) -> Option<CapturedFrame> {
+        loop {
+            match self.try_recv() {
+                Ok(frame) => return Some(frame),
+                Err(TryRecvError::Disconnected) => return None,
+                Err(TryRecvError::Empty) => self.shared.frame_available.notified().await,
+            }
+        }
+    }
+
+    /// Take the next frame if one is waiting.
+    pub fn try_recv(&mut self) -> Result<CapturedFrame, TryRecvError> {
+        let mut state = self.shared.state.lock().unwrap();
+        match state.frames.pop_front() {
+            Some(frame) => {
+                drop(state);
+                self.shared.delivered.fetch_add(1, Ordering::Relaxed);
+                self.shared.space_available.notify_one();
+                Ok(frame)
+            }
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Current frame counts.
+    pub fn stats(&self) -> QueueStats {
+        QueueStats {
+            delivered: self.shared.delivered.load(Ordering::Relaxed),
+            dropped: self.shared.dropped.load(Ordering::Relaxed),
+            depth: self.shared.state.lock().unwrap().frames.len(),
+        }
+    }
+}
+
+impl Drop for FrameReceiver {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.receiver_alive = false;
+        state.frames.clear();
+        drop(state);
+        self.shared.space_available.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::types::{ColorSpace, PixelFormat};
+
+    fn frame(n: u8) -> CapturedFrame {
+        CapturedFrame {
+            width: 1,
+            height: 1,
+            data: vec![n; 4],
+            format: PixelFormat::Bgra8,
+            color: ColorSpace::SRGB,
+            captured_at: std::time::Instant::now(),
+            presented_at: None,
+        }
+    }
+
+    #[test]
+    fn test_drop_policies() {
+        let (tx, mut rx) = frame_channel_with_policy(2, DropPolicy::DropOldest);
+        for n in 0..4 {
+            tx.send(frame(n)).unwrap();
+        }
+        assert_eq!(rx.try_recv().unwrap().data[0], 2);
+        assert_eq!(rx.try_recv().unwrap().data[0], 3);
+        assert_eq!(rx.stats(), QueueStats { delivered: 2, dropped: 2, depth: 0 });
+
+        let (tx, mut rx) = frame_channel_with_policy(2, DropPolicy::DropNewest);
+        for n in 0..4 {
+            tx.send(frame(n)).unwrap();
+        }
+        assert_eq!(rx.try_recv().unwrap().data[0], 0);
+        assert_eq!(rx.try_recv().unwrap().data[0], 1);
+        assert_eq!(tx.dropped(), 2);
+
+        drop(tx);
+        assert!(matches!(rx.try_recv(), Err(TryRecvError::Disconnected)));
+    }
+
+    #[test]
+    fn test_block_waits_for_receiver() {
+        let (tx, mut rx) = frame_channel_with_policy(1, DropPolicy::Block);
+        tx.send(frame(0)).unwrap();
+
+        let sender = std::thread::spawn(move || tx.send(frame(1)));
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(rx.try_recv().unwrap().data[0], 0);
+        sender.join().unwrap().unwrap();
+        assert_eq!(rx.try_recv().unwrap().data[0], 1);
+        assert_eq!(rx.stats().dropped, 0);
+    }
+}
@@ -0,0 +1,450 @@
+//! Recovery from captures lost mid-recording.
+//!
+//! A capture ends on its own when the GPU resets, the captured window closes
+//! or the monitor is unplugged. [`start_recoverable_capture`] wraps a capture
+//! so the recording shows a "no signal" frame instead of ending, while the
+//! same source is captured again. Window captures also watch their window,
+//! handling it being minimized or closed as chosen by [`WindowLostBehavior`].
+//! A window its application closes and opens again, as some do on mode
+//! changes, is followed to the new window without any of that (see
+//! `identity`).
+//!
+//! What happens is published as [`CaptureEvent`]s. When the capture can't be
+//! re-established within the timeout, the frame queue closes and
+//! [`CaptureEvent::Ended`] tells the app to stop the recording.
+
+use super::glyphs;
+use super::identity::WindowIdentity;
+use super::queue::{frame_channel, FrameSender};
+use super::types::{
+    CaptureOptions, CaptureTarget, CapturedFrame, ColorSpace, FrameReceiver, PixelFormat, StopHandle, WindowState,
+};
+use super::{list_windows, start_capture_async, start_capture_with, window_state, CaptureError};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+/// How long to wait between attempts to capture the source again.
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the stop flag is checked while waiting for frames.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often a captured window is checked for being minimized or closed.
+const WINDOW_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long after a captured window closes to look for the window
+/// replacing it, and how often.
+const REATTACH_WAIT: Duration = Duration::from_secs(3);
+const REATTACH_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// What a window recording shows while its window is minimized or closed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowLostBehavior {
+    /// A "no signal" frame, until the window returns
+    #[default]
+    Placeholder,
+    /// The window's last frame, until it returns
+    Freeze,
+    /// A black frame, until the window returns
+    Black,
+    /// End the recording when the window closes; while it's minimized, its
+    /// last frame is shown
+    Stop,
+}
+
+/// How lost captures are handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RecoveryOptions {
+    /// How long to keep trying to capture the source again; zero ends the
+    /// capture as soon as it's lost
+    pub timeout: Duration,
+    pub window_lost: WindowLostBehavior,
+}
+
+/// Something that happened to a running capture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum CaptureEvent {
+    WindowMinimized,
+    WindowRestored,
+    WindowClosed,
+    /// The window was closed and replaced by a new one of the same
+    /// application, which is now captured
+    WindowReattached,
+    /// The capture was lost and is being re-established
+    Lost,
+    /// The capture was re-established
+    Recovered,
+    /// The capture ended for good; the recording should be stopped to
+    /// finish the file
+    Ended,
+}
+
+/// Start capturing `target`, capturing it again whenever it's lost, and
+/// publishing what happens to `events`.
+pub fn start_recoverable_capture(
+    target: CaptureTarget,
+    options: CaptureOptions,
+    recovery: RecoveryOptions,
+    events: broadcast::Sender<CaptureEvent>,
+) -> Result<(FrameReceiver, StopHandle), CaptureError> {
+    let (frames, capture_stop) = start_capture_with(target.clone(), options)?;
+
+    // Windows get a new handle if their application recreates them or restarts
+    let identity = match &target {
+        CaptureTarget::Window { window_handle } => {
+            let windows = list_windows();
+            windows
+                .iter()
+                .find(|w| w.handle == *window_handle)
+                .map(|window| WindowIdentity::of(window, &windows))
+        }
+        _ => None,
+    };
+
+    let (frame_tx, frame_rx) = frame_channel(30);
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    let controller = RecoveryController {
+        target,
+        identity,
+        options,
+        recovery,
+        stop_flag: stop_flag.clone(),
+        frame_tx,
+        events,
+    };
+
+    std::thread::spawn(move || {
+        let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                tracing::error!(target: "Recovery", "Failed to create runtime: {}", e);
+                capture_stop.store(true, Ordering::Relaxed);
+                return;
+            }
+        };
+        runtime.block_on(controller.run(frames, capture_stop));
+    });
+
+    Ok((frame_rx, stop_flag))
+}
+
+/// What ended forwarding from a capture.
+enum Forwarded {
+    /// The recording was stopped
+    Stopped,
+    /// The capture ended; `received` is whether it delivered any frames and
+    /// `window_closed` whether its window was closed
+    Lost { received: bool, window_closed: bool },
+}
+
+/// Forwards frames from the current capture, replacing it when it's lost.
+struct RecoveryController {
+    target: CaptureTarget,
+    /// What the captured window looks like, if known
+    identity: Option<WindowIdentity>,
+    options: CaptureOptions,
+    recovery: RecoveryOptions,
+    stop_flag: StopHandle,
+    frame_tx: FrameSender,
+    events: broadcast::Sender<CaptureEvent>,
+}
+
+impl RecoveryController {
+    async fn run(mut self, mut frames: FrameReceiver, mut capture_stop: StopHandle) {
+        // Size and format of the recording, from the first frame
+        let mut output = None;
+        let mut lost_since = Instant::now();
+
+        loop {
+            let forwarded = self.forward(&mut frames, &mut output).await;
+            capture_stop.store(true, Ordering::Relaxed);
+            let Forwarded::Lost { received, window_closed } = forwarded else {
+                return;
+            };
+            if window_closed {
+                if let Some((new_frames, new_stop)) = self.reattach().await {
+                    frames = new_frames;
+                    capture_stop = new_stop;
+                    continue;
+                }
+                if self.stop_flag.load(Ordering::Relaxed) {
+                    return;
+                }
+                self.emit(CaptureEvent::WindowClosed);
+                if self.recovery.window_lost == WindowLostBehavior::Stop {
+                    self.emit(CaptureEvent::Ended);
+                    return;
+                }
+            }
+            let Some(output) = output else {
+                tracing::warn!(target: "Recovery", "Capture ended before its first frame");
+                self.emit(CaptureEvent::Ended);
+                return;
+            };
+
+            // A new capture that ends without frames doesn't restart the clock
+            if received {
+                self.emit(CaptureEvent::Lost);
+                lost_since = Instant::now();
+                if let Some(frame) = self.lost_frame(output) {
+                    if self.frame_tx.send(frame).is_err() {
+                        return;
+                    }
+                }
+            }
+
+            match self.reacquire(lost_since).await {
+                Some((new_frames, new_stop)) => {
+                    frames = new_frames;
+                    capture_stop = new_stop;
+                }
+                None => {
+                    if !self.stop_flag.load(Ordering::Relaxed) {
+                        self.emit(CaptureEvent::Ended);
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Forward frames until the capture ends or the recording is stopped,
+    /// watching the captured window if there is one.
+    async fn forward(&self, frames: &mut FrameReceiver, output: &mut Option<(u32, u32, PixelFormat)>) -> Forwarded {
+        let mut received = false;
+        let mut minimized = false;
+        let mut window_checked = Instant::now();
+        loop {
+            if self.stop_flag.load(Ordering::Relaxed) {
+                return Forwarded::Stopped;
+            }
+
+            let due = window_checked.elapsed() >= WINDOW_CHECK_INTERVAL;
+            if let Some(window_handle) = self.window_handle().filter(|_| due) {
+                window_checked = Instant::now();
+                match window_state(window_handle) {
+                    WindowState::Closed => return Forwarded::Lost { received, window_closed: true },
+                    WindowState::Minimized if !minimized => {
+                        minimized = true;
+                        self.emit(CaptureEvent::WindowMinimized);
+                        if let Some(frame) = output.and_then(|output| self.lost_frame(output)) {
+                            if self.frame_tx.send(frame).is_err() {
+                                return Forwarded::Stopped;
+                            }
+                        }
+                    }
+                    WindowState::Open if minimized => {
+                        minimized = false;
+                        self.emit(CaptureEvent::WindowRestored);
+                    }
+                    _ => {}
+                }
+            }
+
+            let frame = match tokio::time::timeout(STOP_POLL_INTERVAL, frames.recv()).await {
+                Ok(Some(frame)) => frame,
+                Ok(None) => {
+                    let window_closed = self.window_handle().is_some_and(|h| window_state(h) == WindowState::Closed);
+                    return Forwarded::Lost { received, window_closed };
+                }
+                Err(_) => continue,
+            };
+            if !received && output.is_some() {
+                self.emit(CaptureEvent::Recovered);
+            }
+            received = true;
+
+            let (_, _, format) = *output.get_or_insert((frame.width, frame.height, frame.format));
+            if frame.format != format {
+                // The encoder can't switch formats mid-recording
+                continue;
+            }
+            // Size changes are left to the encoder's resolution change policy;
+            // placeholders match the latest size so they don't trigger one
+            *output = Some((frame.width, frame.height, format));
+            if self.frame_tx.send(frame).is_err() {
+                return Forwarded::Stopped;
+            }
+        }
+    }
+
+    /// Start capturing the source again, retrying until the timeout has
+    /// passed since it was lost.
+    async fn reacquire(&mut self, lost_since: Instant) -> Option<(FrameReceiver, StopHandle)> {
+        loop {
+            if self.stop_flag.load(Ordering::Relaxed) {
+                return None;
+            }
+            if lost_since.elapsed() >= self.recovery.timeout {
+                tracing::warn!(target: "Recovery", "Giving up after {}s", self.recovery.timeout.as_secs());
+                return None;
+            }
+            tokio::time::sleep(RETRY_INTERVAL).await;
+            self.follow_window();
+            // This runs on the controller's own single-threaded runtime,
+            // which the blocking start can't be called from
+            match start_capture_async(self.target.clone(), self.options).await {
+                Ok(capture) => return Some(capture),
+                Err(e) => tracing::warn!(target: "Recovery", "Retry failed: {}", e),
+            }
+        }
+    }
+
+    /// Handle of the captured window, for window captures.
+    fn window_handle(&self) -> Option<isize> {
+        match self.target {
+            CaptureTarget::Window { window_handle } => Some(window_handle),
+            _ => None,
+        }
+    }
+
+    /// Capture the window that replaced the captured one, if one appears
+    /// shortly after it closed.
+    async fn reattach(&mut self) -> Option<(FrameReceiver, StopHandle)> {
+        let identity = self.identity.clone()?;
+        let deadline = Instant::now() + REATTACH_WAIT;
+        while Instant::now() < deadline && !self.stop_flag.load(Ordering::Relaxed) {
+            let windows = list_windows();
+            if let Some(window) = identity.successor(&windows) {
+                let target = CaptureTarget::Window { window_handle: window.handle };
+                match start_capture_async(target.clone(), self.options).await {
+                    Ok(capture) => {
+                        tracing::info!(
+                            target: "Recovery",
+                            "Window 0x{:x} ({}, \"{}\") was replaced by 0x{:x} (\"{}\"); capturing that instead",
+                            identity.handle as usize,
+                            identity.process_name,
+                            identity.title,
+                            window.handle as usize,
+                            window.title
+                        );
+                        self.identity = Some(WindowIdentity::of(window, &windows));
+                        self.target = target;
+                        self.emit(CaptureEvent::WindowReattached);
+                        return Some(capture);
+                    }
+                    Err(e) => tracing::error!(target: "Recovery", "Failed to capture the replacement window: {}", e),
+                }
+            }
+            tokio::time::sleep(REATTACH_POLL_INTERVAL).await;
+        }
+        None
+    }
+
+    /// Follow the captured window to a new handle if it was closed and
+    /// reopened.
+    fn follow_window(&mut self) {
+        let (Some(window_handle), Some(identity)) = (self.window_handle(), &self.identity) else {
+            return;
+        };
+        let windows = list_windows();
+        if windows.iter().any(|w| w.handle == window_handle) {
+            return;
+        }
+        if let Some(reopened) = identity.successor(&windows) {
+            tracing::info!(target: "Recovery", "Window reopened as 0x{:x}", reopened.handle as usize);
+            self.target = CaptureTarget::Window { window_handle: reopened.handle };
+            self.identity = Some(WindowIdentity::of(reopened, &windows));
+        }
+    }
+
+    /// The frame to show while the source is lost, or `None` to keep the
+    /// last one.
+    fn lost_frame(&self, (width, height, format): (u32, u32, PixelFormat)) -> Option<CapturedFrame> {
+        if self.window_handle().is_none() {
+            return Some(placeholder_frame(width, height, format));
+        }
+        match self.recovery.window_lost {
+            WindowLostBehavior::Placeholder => Some(placeholder_frame(width, height, format)),
+            WindowLostBehavior::Black => Some(CapturedFrame {
+                width,
+                height,
+                data: format.black().repeat(width as usize * height as usize),
+                format,
+                color: ColorSpace::SRGB,
+                captured_at: Instant::now(),
+                presented_at: None,
+            }),
+            WindowLostBehavior::Freeze | WindowLostBehavior::Stop => None,
+        }
+    }
+
+    /// Publish a capture event.
+    fn emit(&self, event: CaptureEvent) {
+        tracing::info!(target: "Recovery", "{:?}", event);
+        let _ = self.events.send(event);
+    }
+}
+
+/// A dark grey frame reading "NO SIGNAL", shown while the capture is lost.
+fn placeholder_frame(width: u32, height: u32, format: PixelFormat) -> CapturedFrame {
+    // Background and text pixels in each format
+    let (background, text): (&[u8], &[u8]) = match format {
+        PixelFormat::Bgra8 => (&[32, 32, 32, 255], &[255, 255, 255, 255]),
+        // Half floats: 0.0144 (sRGB 32) and 1.0
+        PixelFormat::Rgba16F => (
+            &[0x5c, 0x23, 0x5c, 0x23, 0x5c, 0x23, 0x00, 0x3c],
+            &[0x00, 0x3c, 0x00, 0x3c, 0x00, 0x3c, 0x00, 0x3c],
+        ),
+        // 128 in each 10-bit channel, with the padding bits set
+        PixelFormat::X2Rgb10 | PixelFormat::X2Bgr10 => (&[0x80, 0x00, 0x02, 0xc8], &[0xff; 4]),
+    };
+
+    let mut frame = CapturedFrame {
+        width,
+        height,
+        data: background.repeat(width as usize * height as usize),
+        format,
+        color: ColorSpace::SRGB,
+        captured_at: Instant::now(),
+        presented_at: None,
+    };
+    let text_content = "NO SIGNAL";
+    let (w, h) = (width as usize, height as usize);
+    let scale = (w / 60).min(h / 20).max(1);
+    let x = w.saturating_sub(glyphs::text_width(text_content, scale)) / 2;
+    let y = h.saturating_sub(glyphs::text_height(scale)) / 2;
+    glyphs::draw_text(&mut frame, x, y, scale, text_content, text);
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_placeholder_frame() {
+        let placeholder = placeholder_frame(120, 40, PixelFormat::X2Rgb10);
+        assert_eq!(placeholder.data.len(), 120 * 40 * 4);
+        let word = u32::from_le_bytes([placeholder.data[0], placeholder.data[1], placeholder.data[2], placeholder.data[3]]);
+        assert_eq!((word >> 20 & 0x3ff, word >> 10 & 0x3ff, word & 0x3ff), (128, 128, 128));
+    }
+
+    #[test]
+    fn test_window_lost_frame() {
+        let controller = |window_lost| RecoveryController {
+            target: CaptureTarget::Window { window_handle: 1 },
+            identity: None,
+            options: CaptureOptions::default(),
+            recovery: RecoveryOptions { timeout: Duration::ZERO, window_lost },
+            stop_flag: Arc::new(AtomicBool::new(false)),
+            frame_tx: frame_channel(1).0,
+            events: broadcast::channel(1).0,
+        };
+        let output = (2, 1, PixelFormat::Bgra8);
+
+        assert!(controller(WindowLostBehavior::Freeze).lost_frame(output).is_none());
+        assert!(controller(WindowLostBehavior::Stop).lost_frame(output).is_none());
+        let black = controller(WindowLostBehavior::Black).lost_frame(output).unwrap();
+        assert_eq!(black.data, [0, 0, 0, 255, 0, 0, 0, 255]);
+        assert_eq!(
+            serde_json::to_string(&CaptureEvent::WindowClosed).unwrap(),
+            r#"{"event":"window_closed"}"#
+        );
+    }
+}
@@ -0,0 +1,131 @@
+//! Persistent redaction regions.
+//!
+//! A redaction region is a rectangle on a monitor or within a window that is
+//! hidden in every recording, e.g. where an email client shows addresses.
+//! Regions are stored in `redactions.json` in the app data directory and
+//! applied by the capture's [`FrameMask`](super::mask::FrameMask).
+
+use super::exclusion::WindowRule;
+use super::mask::MaskStyle;
+use super::types::WindowBounds;
+use crate::config::{load_json, save_json};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+
+const REDACTIONS_FILE: &str = "redactions.json";
+
+/// What a redaction region's position is relative to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum RedactionAnchor {
+    /// The top-left corner of a monitor, by ID
+    Monitor { monitor_id: String },
+    /// The top-left corner of any window matching the rule
+    Window { window: WindowRule },
+}
+
+/// A named rectangle hidden in every recording.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedactionRegion {
+    pub name: String,
+    pub anchor: RedactionAnchor,
+    /// Position in pixels relative to the anchor
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    #[serde(default)]
+    pub style: MaskStyle,
+}
+
+impl RedactionRegion {
+    /// The region's rectangle relative to its anchor.
+    pub fn bounds(&self) -> WindowBounds {
+        WindowBounds {
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    /// Check that the region can be used.
+    fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Redaction name cannot be empty".to_string());
+        }
+        if self.width == 0 || self.height == 0 {
+            return Err("Redaction must have a non-zero size".to_string());
+        }
+        if let RedactionAnchor::Window { window } = &self.anchor {
+            if window.process_name.is_none() && window.title.is_none() {
+                return Err("A window redaction needs a process name or title".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The saved redaction regions, shared with running captures so changes apply immediately.
+#[derive(Clone)]
+pub struct Redactions {
+    regions: Arc<RwLock<Vec<RedactionRegion>>>,
+}
+
+impl Redactions {
+    /// Load the redaction regions from disk.
+    pub fn load() -> Self {
+        Self {
+            regions: Arc::new(RwLock::new(load_json(REDACTIONS_FILE))),
+        }
+    }
+
+    /// All regions.
+    pub fn list(&self) -> Vec<RedactionRegion> {
+        self.regions.read().unwrap().clone()
+    }
+
+    /// Add a region, replacing any existing region with the same name.
+    pub fn upsert(&self, region: RedactionRegion) -> Result<(), String> {
+        region.validate()?;
+        let mut regions = self.regions.write().unwrap();
+        match regions.iter_mut().find(|r| r.name == region.name) {
+            Some(existing) => *existing = region,
+            None => regions.push(region),
+        }
+        save_json(REDACTIONS_FILE, &*regions)
+    }
+
+    /// Delete a region by name.
+    pub fn delete(&self, name: &str) -> Result<(), String> {
+        let mut regions = self.regions.write().unwrap();
+        let before = regions.len();
+        regions.retain(|r| r.name != name);
+        if regions.len() == before {
+            return Err(format!("Redaction {:?} not found", name));
+        }
+        save_json(REDACTIONS_FILE, &*regions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redaction_json() {
+        let region: RedactionRegion = serde_json::from_str(
+            r#"{"name": "Inbox", "anchor": {"type": "window", "window": {"process_name": "outlook.exe"}},
+                "x": 0, "y": 120, "width": 300, "height": 800, "style": "blur"}"#,
+        )
+        .unwrap();
+        assert_eq!(region.style, MaskStyle::Blur);
+        assert!(region.validate().is_ok());
+
+        let invalid = RedactionRegion {
+            width: 0,
+            ..region
+        };
+        assert!(invalid.validate().is_err());
+    }
+}
@@ -0,0 +1,102 @@
+//! Matching a recording's frame rate to the captured display's refresh rate.
+//!
+//! Capture backends deliver a frame each time the compositor presents one,
+//! so on a 120 or 144 Hz monitor frames arrive at that rate. Recording them
+//! at a fixed 60 fps shows some for one output frame and some for two,
+//! which judders. Matched, the recording takes the refresh rate of the
+//! monitor the target is on (see [`target_fps`]), and the encoder locks its
+//! frame times to the presented frames (see `encoder::schedule`).
+
+use super::types::{CaptureTarget, MonitorInfo, WindowBounds};
+
+/// Highest frame rate a recording is matched to.
+const MAX_MATCHED_FPS: u32 = 240;
+
+/// The monitor `target` is on. Windows are on the monitor holding their
+/// center, given their `window_bounds`.
+pub fn monitor_of<'a>(
+    target: &CaptureTarget,
+    monitors: &'a [MonitorInfo],
+    window_bounds: Option<WindowBounds>,
+) -> Option<&'a MonitorInfo> {
+    match target {
+        CaptureTarget::Display { monitor_id, .. } => monitors.iter().find(|m| &m.id == monitor_id),
+        CaptureTarget::Region { region } => monitors.iter().find(|m| m.id == region.monitor_id),
+        CaptureTarget::Window { .. } => {
+            let bounds = window_bounds?;
+            let (x, y) = (bounds.x + bounds.width as i32 / 2, bounds.y + bounds.height as i32 / 2);
+            monitors.iter().find(|m| {
+                (m.x..m.x + m.width as i32).contains(&x) && (m.y..m.y + m.height as i32).contains(&y)
+            })
+        }
+    }
+}
+
+/// Frame rate to record a display refreshing at `rate` Hz with; fractional
+/// rates like 59.94 round to the nearest whole one.
+pub fn fps_for(rate: f64) -> u32 {
+    (rate.round() as u32).clamp(1, MAX_MATCHED_FPS)
+}
+
+/// The connected monitor `target` is on, if it can be found.
+pub fn target_monitor(target: &CaptureTarget) -> Option<MonitorInfo> {
+    let window_bounds = match target {
+        CaptureTarget::Window { window_handle } => super::list_window_bounds()
+            .ok()?
+            .into_iter()
+            .find(|(window, _)| window.handle == *window_handle)
+            .map(|(_, bounds)| bounds),
+        _ => None,
+    };
+    monitor_of(target, &super::list_monitors(), window_bounds).cloned()
+}
+
+/// Frame rate matching the refresh rate of the monitor `target` is on, if
+/// it can be found.
+pub fn target_fps(target: &CaptureTarget) -> Option<u32> {
+    target_monitor(target)?.refresh_rate.map(fps_for)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::types::{CaptureRegion, CoordinateSpace};
+
+    fn refresh_rate(target: &CaptureTarget, monitors: &[MonitorInfo], window_bounds: Option<WindowBounds>) -> Option<f64> {
+        monitor_of(target, monitors, window_bounds)?.refresh_rate
+    }
+
+    fn monitor(id: &str, x: i32, refresh_rate: Option<f64>) -> MonitorInfo {
+        MonitorInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            x,
+            y: 0,
+            width: 1920,
+            height: 1080,
+            is_primary: x == 0,
+            scale_factor: 1.0,
+            model: None,
+            refresh_rate,
+            rotation: 0,
+        }
+    }
+
+    #[test]
+    fn test_refresh_rate() {
+        let monitors = [monitor("DP-1", 0, Some(59.94)), monitor("DP-2", 1920, Some(143.9)), monitor("HDMI-1", 3840, None)];
+        let display = CaptureTarget::Display { monitor_id: "DP-2".to_string(), width: 1920, height: 1080 };
+        assert_eq!(refresh_rate(&display, &monitors, None).map(fps_for), Some(144));
+        let region = CaptureTarget::Region {
+            region: CaptureRegion { monitor_id: "DP-1".to_string(), x: 0, y: 0, width: 640, height: 480, space: CoordinateSpace::Logical },
+        };
+        assert_eq!(refresh_rate(&region, &monitors, None).map(fps_for), Some(60));
+        // Mostly on the second monitor
+        let window = CaptureTarget::Window { window_handle: 1 };
+        let bounds = WindowBounds { x: 1800, y: 100, width: 800, height: 600 };
+        assert_eq!(refresh_rate(&window, &monitors, Some(bounds)), Some(143.9));
+        assert_eq!(refresh_rate(&window, &monitors, None), None);
+        let unknown = CaptureTarget::Display { monitor_id: "HDMI-1".to_string(), width: 1920, height: 1080 };
+        assert_eq!(refresh_rate(&unknown, &monitors, None), None);
+    }
+}
@@ -0,0 +1,95 @@
+//! Small preview images of windows for the source picker.
+//!
+//! Backends grab a window's pixels however their platform allows and hand
+//! them here to be shrunk and encoded as PNG, base64-encoded so they can go
+//! straight into an `<img>` data URL.
+
+use base64::Engine;
+
+/// Longest side of a thumbnail, in pixels.
+pub const THUMBNAIL_SIZE: u32 = 240;
+
+/// A thumbnail of an 8-bit BGRA image whose rows are `stride` bytes apart,
+/// as a base64-encoded PNG.
+pub fn encode(bgra: &[u8], width: u32, height: u32, stride: usize) -> Result<String, String> {
+    if width == 0 || height == 0 || bgra.len() < stride * (height as usize - 1) + width as usize * 4 {
+        return Err(format!("Invalid thumbnail source: {}x{}", width, height));
+    }
+    let (thumb_width, thumb_height, rgba) = shrink(bgra, width, height, stride, THUMBNAIL_SIZE);
+    png_base64(&rgba, thumb_width, thumb_height)
+}
+
+/// An 8-bit RGBA image as a base64-encoded PNG.
+pub fn png_base64(rgba: &[u8], width: u32, height: u32) -> Result<String, String> {
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(rgba))
+        .map_err(|e| format!("Failed to encode PNG: {}", e))?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(png))
+}
+
+/// Shrink a BGRA image to fit in `max` x `max`, averaging the pixels each
+/// thumbnail pixel covers. Returns the new size and its opaque RGBA pixels.
+fn shrink(bgra: &[u8], width: u32, height: u32, stride: usize, max: u32) -> (u32, u32, Vec<u8>) {
+    let scale = (max as f64 / width.max(height) as f64).min(1.0);
+    let thumb_width = ((width as f64 * scale).round() as u32).max(1);
+    let thumb_height = ((height as f64 * scale).round() as u32).max(1);
+
+    let mut rgba = Vec::with_capacity((thumb_width * thumb_height * 4) as usize);
+    for ty in 0..thumb_height {
+        let (y0, y1) = span(ty, thumb_height, height);
+        for tx in 0..thumb_width {
+            let (x0, x1) = span(tx, thumb_width, width);
+            let mut sum = [0u32; 3];
+            for y in y0..y1 {
+                let row = &bgra[y as usize * stride..];
+                for x in x0..x1 {
+                    let pixel = &row[x as usize * 4..x as usize * 4 + 3];
+                    for (total, &value) in sum.iter_mut().zip(pixel) {
+                        *total += value as u32;
+                    }
+                }
+            }
+            let count = (y1 - y0) * (x1 - x0);
+            let [b, g, r] = sum.map(|total| (total / count) as u8);
+            rgba.extend_from_slice(&[r, g, b, 255]);
+        }
+    }
+    (thumb_width, thumb_height, rgba)
+}
+
+/// Source pixels `[start, end)` that thumbnail pixel `index` of `count`
+/// covers, out of `size`.
+fn span(index: u32, count: u32, size: u32) -> (u32, u32) {
+    let start = (index as u64 * size as u64 / count as u64) as u32;
+    let end = ((index as u64 + 1) * size as u64 / count as u64) as u32;
+    (start, end.max(start + 1).min(size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shrink() {
+        // 4x2 image: left half black, right half white, with padded rows
+        let stride = 20;
+        let mut bgra = vec![0u8; stride * 2];
+        for y in 0..2 {
+            for x in 2..4 {
+                bgra[y * stride + x * 4..y * stride + x * 4 + 4].copy_from_slice(&[255, 255, 255, 255]);
+            }
+        }
+        let (width, height, rgba) = shrink(&bgra, 4, 2, stride, 2);
+        assert_eq!((width, height), (2, 1));
+        assert_eq!(rgba, vec![0, 0, 0, 255, 255, 255, 255, 255]);
+
+        let encoded = encode(&bgra, 4, 2, stride).unwrap();
+        let png = base64::engine::general_purpose::STANDARD.decode(encoded).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+    }
+}
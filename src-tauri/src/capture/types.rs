@@ -1,9 +1,10 @@
 //! Shared types for cross-platform capture operations.
 
+use super::hdr::HdrMode;
 use serde::{Deserialize, Serialize};
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::time::{Duration, Instant};
 
 /// Information about a capturable window.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,10 +15,44 @@ pub struct WindowInfo {
     pub title: String,
     /// Process name (executable name)
     pub process_name: String,
+    /// ID of the process that owns the window, or 0 if unknown
+    #[serde(default)]
+    pub pid: u32,
+    /// Window class (Windows) or Wayland app ID (Hyprland), or empty if
+    /// unknown; with the process, it tells a recreated window apart from
+    /// the application's other windows
+    #[serde(default)]
+    pub class: String,
+    /// Application icon as a base64-encoded PNG, if one was found
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    /// Small preview image as a base64-encoded PNG, when asked for and the
+    /// platform can grab one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<String>,
+}
+
+/// Whether a window is still there to be captured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowState {
+    Open,
+    /// Only detected on Windows so far
+    #[cfg_attr(not(windows), allow(dead_code))]
+    Minimized,
+    Closed,
+}
+
+/// Screen-space bounds of a window or area.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowBounds {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
 }
 
 /// Information about a display monitor.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MonitorInfo {
     /// Unique identifier (platform-specific)
     pub id: String,
@@ -33,6 +68,184 @@ pub struct MonitorInfo {
     pub height: u32,
     /// Whether this is the primary monitor
     pub is_primary: bool,
+    /// Physical pixels per logical pixel (1.5 at 150% scaling)
+    pub scale_factor: f64,
+    /// Manufacturer and model from the monitor's EDID, e.g. `DELL U2720Q`
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Refresh rate in Hz, if known
+    #[serde(default)]
+    pub refresh_rate: Option<f64>,
+    /// Clockwise rotation in degrees: 0, 90, 180 or 270
+    #[serde(default)]
+    pub rotation: u32,
+}
+
+/// Units of a position or size.
+///
+/// Captured frames are always in physical pixels. UI toolkits and Wayland
+/// compositors report logical pixels, which are physical pixels divided by
+/// the display's scale factor.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CoordinateSpace {
+    Logical,
+    #[default]
+    Physical,
+}
+
+impl CoordinateSpace {
+    /// Convert a coordinate in this space to `target` on a display with
+    /// `scale_factor`.
+    pub fn convert(self, value: f64, target: CoordinateSpace, scale_factor: f64) -> f64 {
+        let scale_factor = if scale_factor > 0.0 { scale_factor } else { 1.0 };
+        match (self, target) {
+            (CoordinateSpace::Logical, CoordinateSpace::Physical) => value * scale_factor,
+            (CoordinateSpace::Physical, CoordinateSpace::Logical) => value / scale_factor,
+            _ => value,
+        }
+    }
+}
+
+/// Layout of a captured frame's pixel data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 8-bit BGRA in sRGB
+    #[default]
+    Bgra8,
+    /// 16-bit float RGBA in linear scRGB, as captured from HDR displays
+    Rgba16F,
+    /// 10-bit RGB packed into little-endian 32-bit words (2 unused high bits, then R, G, B)
+    X2Rgb10,
+    /// 10-bit BGR packed into little-endian 32-bit words (2 unused high bits, then B, G, R)
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    X2Bgr10,
+}
+
+impl PixelFormat {
+    /// Bytes per pixel.
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            PixelFormat::Bgra8 | PixelFormat::X2Rgb10 | PixelFormat::X2Bgr10 => 4,
+            PixelFormat::Rgba16F => 8,
+        }
+    }
+
+    /// Whether the format has more than 8 bits per channel.
+    pub fn is_deep(&self) -> bool {
+        *self != PixelFormat::Bgra8
+    }
+
+    /// One opaque black pixel in this format.
+    pub fn black(&self) -> &'static [u8] {
+        match self {
+            PixelFormat::Bgra8 => &[0, 0, 0, 255],
+            // Half-float alpha of 1.0
+            PixelFormat::Rgba16F => &[0, 0, 0, 0, 0, 0, 0x00, 0x3c],
+            // Padding bits set, as captures deliver them
+            PixelFormat::X2Rgb10 | PixelFormat::X2Bgr10 => &[0, 0, 0, 0xc0],
+        }
+    }
+
+    /// FFmpeg name of the format, for raw video input.
+    pub fn ffmpeg_name(&self) -> &'static str {
+        match self {
+            PixelFormat::Bgra8 => "bgra",
+            PixelFormat::Rgba16F => "rgbaf16le",
+            PixelFormat::X2Rgb10 => "x2rgb10le",
+            PixelFormat::X2Bgr10 => "x2bgr10le",
+        }
+    }
+}
+
+/// A video colorspace standard, setting the color primaries and, for YUV
+/// video, the matrix converting to and from RGB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorStandard {
+    /// SD video
+    Bt601,
+    /// HD video, and the primaries of sRGB
+    Bt709,
+    /// UHD and HDR video
+    Bt2020,
+}
+
+impl ColorStandard {
+    /// FFmpeg name of the standard's primaries.
+    pub fn primaries(&self) -> &'static str {
+        match self {
+            ColorStandard::Bt601 => "smpte170m",
+            ColorStandard::Bt709 => "bt709",
+            ColorStandard::Bt2020 => "bt2020",
+        }
+    }
+
+    /// FFmpeg name of the standard's YUV matrix, as tagged in streams.
+    pub fn matrix(&self) -> &'static str {
+        match self {
+            ColorStandard::Bt601 => "smpte170m",
+            ColorStandard::Bt709 => "bt709",
+            ColorStandard::Bt2020 => "bt2020nc",
+        }
+    }
+
+    /// Name of the standard's YUV matrix for FFmpeg's software scaler.
+    pub fn scale_matrix(&self) -> &'static str {
+        match self {
+            ColorStandard::Bt601 => "bt601",
+            ColorStandard::Bt709 => "bt709",
+            ColorStandard::Bt2020 => "bt2020",
+        }
+    }
+}
+
+/// Range of the values encoding each channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorRange {
+    /// 16-235 in 8 bits, as video usually is
+    Limited,
+    /// The whole 0-255, as RGB and JPEG are
+    Full,
+}
+
+impl ColorRange {
+    /// FFmpeg name of the range.
+    pub fn ffmpeg_name(&self) -> &'static str {
+        match self {
+            ColorRange::Limited => "tv",
+            ColorRange::Full => "pc",
+        }
+    }
+}
+
+/// The colorspace of frames or video. Players guess when video doesn't say,
+/// and guess differently, so it's tracked from capture to the encoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColorSpace {
+    pub standard: ColorStandard,
+    pub range: ColorRange,
+}
+
+impl ColorSpace {
+    /// Screen content: sRGB, which has BT.709 primaries, at full range.
+    pub const SRGB: ColorSpace = ColorSpace {
+        standard: ColorStandard::Bt709,
+        range: ColorRange::Full,
+    };
+
+    /// BT.709 at limited range, what players expect of HD video.
+    pub const VIDEO: ColorSpace = ColorSpace {
+        standard: ColorStandard::Bt709,
+        range: ColorRange::Limited,
+    };
+}
+
+impl Default for ColorSpace {
+    fn default() -> Self {
+        Self::VIDEO
+    }
 }
 
 /// A captured frame with its dimensions and pixel data.
@@ -40,8 +253,107 @@ pub struct MonitorInfo {
 pub struct CapturedFrame {
     pub width: u32,
     pub height: u32,
-    /// BGRA pixel data
+    /// Pixel data, tightly packed in `format`
     pub data: Vec<u8>,
+    pub format: PixelFormat,
+    /// Colorspace of the pixels
+    pub color: ColorSpace,
+    /// When the frame was captured, on the monotonic clock. The encoder
+    /// places frames in the video by this rather than by when they arrive.
+    pub captured_at: Instant,
+    /// When the backend says the frame was presented, on its own clock, if
+    /// it reports one
+    pub presented_at: Option<Duration>,
+}
+
+impl CapturedFrame {
+    /// Convert a packed 10-bit frame to 8-bit BGRA by dropping the low bits.
+    /// Returns `None` for other formats.
+    pub fn packed_10bit_to_bgra8(&self) -> Option<CapturedFrame> {
+        let (r_shift, b_shift) = match self.format {
+            PixelFormat::X2Rgb10 => (20, 0),
+            PixelFormat::X2Bgr10 => (0, 20),
+            _ => return None,
+        };
+        let mut data = Vec::with_capacity(self.data.len());
+        for pixel in self.data.chunks_exact(4) {
+            let word = u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+            let channel = |shift: u32| (((word >> shift) & 0x3ff) >> 2) as u8;
+            data.extend_from_slice(&[channel(b_shift), channel(10), channel(r_shift), 255]);
+        }
+        Some(CapturedFrame {
+            width: self.width,
+            height: self.height,
+            data,
+            format: PixelFormat::Bgra8,
+            color: self.color,
+            captured_at: self.captured_at,
+            presented_at: self.presented_at,
+        })
+    }
+
+    /// Convert premultiplied alpha, as compositors deliver it, to the
+    /// straight alpha encoders expect. Only 8-bit BGRA frames are converted.
+    #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+    pub fn unpremultiply_alpha(&mut self) {
+        if self.format != PixelFormat::Bgra8 {
+            return;
+        }
+        for pixel in self.data.chunks_exact_mut(4) {
+            let alpha = pixel[3] as u32;
+            if alpha == 0 || alpha == 255 {
+                continue;
+            }
+            for channel in &mut pixel[..3] {
+                *channel = ((*channel as u32 * 255 + alpha / 2) / alpha).min(255) as u8;
+            }
+        }
+    }
+}
+
+/// What a capture backend can do on this system.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub window_capture: bool,
+    pub region_capture: bool,
+    pub display_capture: bool,
+    /// Cursor position and shape are delivered alongside frames instead of
+    /// only being drawn into them
+    pub cursor_metadata: bool,
+    /// Ways captures can treat the cursor: `hidden`, `embedded` in frames,
+    /// or as `metadata` alongside them
+    pub cursor_modes: Vec<String>,
+    /// The audio of a captured window's application can be recorded
+    pub app_audio: bool,
+    /// HDR displays can be recorded in HDR
+    pub hdr: bool,
+    /// Frames with more than 8 bits per channel can be delivered
+    pub deep_color: bool,
+    /// Transparent windows can be recorded with their alpha channel
+    pub alpha: bool,
+    /// A highlight can be shown around the capture target
+    pub highlight: bool,
+    /// Windows can be recorded with game capture, which also sees games
+    /// running in exclusive fullscreen
+    pub game_capture: bool,
+    /// Highest frame rate captures deliver, or `None` if only limited by the
+    /// display's refresh rate
+    pub max_fps: Option<u32>,
+    /// Sandbox the app runs in (`flatpak` or `snap`), which captures go
+    /// through the portal from
+    pub sandbox: Option<String>,
+}
+
+/// Options for how a capture delivers frames.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CaptureOptions {
+    /// How content from HDR displays is delivered
+    pub hdr: HdrMode,
+    /// Prefer frames with more than 8 bits per channel where the platform can provide them
+    pub deep_color: bool,
+    /// Keep the alpha channel of transparent windows, as straight alpha;
+    /// otherwise frames are treated as opaque
+    pub alpha: bool,
 }
 
 /// Region specification for capture.
@@ -57,10 +369,139 @@ pub struct CaptureRegion {
     pub width: u32,
     /// Region height
     pub height: u32,
+    /// Units of the position and size
+    #[serde(default)]
+    pub space: CoordinateSpace,
+}
+
+impl CaptureRegion {
+    /// This region in `space` on `monitor`.
+    ///
+    /// Edges are converted rather than sizes so that adjacent regions stay
+    /// adjacent after rounding.
+    pub fn to_space(&self, space: CoordinateSpace, monitor: &MonitorInfo) -> CaptureRegion {
+        let convert = |value: i64| self.space.convert(value as f64, space, monitor.scale_factor).round() as i64;
+        let left = convert(self.x as i64);
+        let top = convert(self.y as i64);
+        let right = convert(self.x as i64 + self.width as i64);
+        let bottom = convert(self.y as i64 + self.height as i64);
+        CaptureRegion {
+            monitor_id: self.monitor_id.clone(),
+            x: left as i32,
+            y: top as i32,
+            width: (right - left).max(0) as u32,
+            height: (bottom - top).max(0) as u32,
+            space,
+        }
+    }
+
+    /// This region in physical pixels, as cropped from captured frames.
+    pub fn to_physical(&self, monitor: &MonitorInfo) -> CaptureRegion {
+        self.to_space(CoordinateSpace::Physical, monitor)
+    }
+
+    /// This region in logical pixels, as used by compositors.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub fn to_logical(&self, monitor: &MonitorInfo) -> CaptureRegion {
+        self.to_space(CoordinateSpace::Logical, monitor)
+    }
+}
+
+/// What to capture: a window, a region of a monitor, or a whole display.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum CaptureTarget {
+    /// A single window by handle/ID
+    Window { window_handle: isize },
+    /// A region of a monitor
+    Region { region: CaptureRegion },
+    /// An entire display
+    Display {
+        monitor_id: String,
+        width: u32,
+        height: u32,
+    },
 }
 
 /// Handle to stop an ongoing capture.
 pub type StopHandle = Arc<AtomicBool>;
 
 /// Receiver for captured frames.
-pub type FrameReceiver = mpsc::Receiver<CapturedFrame>;
+pub use super::queue::FrameReceiver;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packed_10bit_to_bgra8() {
+        // R = 1023, G = 512, B = 4
+        let word: u32 = (3 << 30) | (1023 << 20) | (512 << 10) | 4;
+        let frame = CapturedFrame {
+            width: 1,
+            height: 1,
+            data: word.to_le_bytes().to_vec(),
+            format: PixelFormat::X2Rgb10,
+            color: ColorSpace::SRGB,
+            captured_at: Instant::now(),
+            presented_at: None,
+        };
+        assert_eq!(frame.packed_10bit_to_bgra8().unwrap().data, [1, 128, 255, 255]);
+
+        let frame = CapturedFrame {
+            format: PixelFormat::X2Bgr10,
+            ..frame
+        };
+        assert_eq!(frame.packed_10bit_to_bgra8().unwrap().data, [255, 128, 1, 255]);
+    }
+
+    #[test]
+    fn test_unpremultiply_alpha() {
+        let mut frame = CapturedFrame {
+            width: 3,
+            height: 1,
+            // Half-transparent white, transparent, opaque
+            data: vec![128, 128, 128, 128, 0, 0, 0, 0, 10, 20, 30, 255],
+            format: PixelFormat::Bgra8,
+            color: ColorSpace::SRGB,
+            captured_at: Instant::now(),
+            presented_at: None,
+        };
+        frame.unpremultiply_alpha();
+        assert_eq!(frame.data, [255, 255, 255, 128, 0, 0, 0, 0, 10, 20, 30, 255]);
+    }
+
+    #[test]
+    fn test_region_coordinate_conversion() {
+        let monitor = MonitorInfo {
+            id: "DP-1".to_string(),
+            name: "DP-1".to_string(),
+            x: 0,
+            y: 0,
+            width: 3840,
+            height: 2160,
+            is_primary: true,
+            scale_factor: 1.5,
+            model: None,
+            refresh_rate: None,
+            rotation: 0,
+        };
+        let region = CaptureRegion {
+            monitor_id: "DP-1".to_string(),
+            x: 101,
+            y: 50,
+            width: 201,
+            height: 100,
+            space: CoordinateSpace::Logical,
+        };
+
+        let physical = region.to_physical(&monitor);
+        assert_eq!((physical.x, physical.y, physical.width, physical.height), (152, 75, 301, 150));
+        assert_eq!(physical.space, CoordinateSpace::Physical);
+        // Converting to the same space is a no-op
+        assert_eq!(physical.to_physical(&monitor).width, 301);
+
+        let logical = physical.to_logical(&monitor);
+        assert_eq!((logical.x, logical.y, logical.width, logical.height), (101, 50, 201, 100));
+    }
+}
@@ -0,0 +1,434 @@
+//! Per-application audio capture using WASAPI process loopback.
+//!
+//! Requires Windows 10 build 20348 or later. The capture includes the target
+//! process and its children, so e.g. a browser's audio is captured even
+//! though it is played by a separate renderer process.
+//!
+//! The stream is reopened when it's invalidated (the device it was playing on
+//! was unplugged) and, unless the device mode is fixed, when the default
+//! output device changes, so the capture follows the application's audio to
+//! a newly plugged in headset.
+
+use crate::capture::audio::{device_mode, AudioDeviceMode, AudioRecording, LevelMeter, WavWriter, BITS_PER_SAMPLE, BLOCK_ALIGN, CHANNELS, SAMPLE_RATE};
+use crate::avsync::SyncClock;
+use std::mem::ManuallyDrop;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use windows::core::{implement, Interface, HRESULT, IUnknown, PCWSTR, PROPVARIANT};
+use windows::Win32::Foundation::{CloseHandle, HANDLE, HWND, PROPERTYKEY};
+use windows::Win32::Media::Audio::{
+    ActivateAudioInterfaceAsync, IActivateAudioInterfaceAsyncOperation,
+    IActivateAudioInterfaceCompletionHandler, IActivateAudioInterfaceCompletionHandler_Impl,
+    eConsole, eRender, EDataFlow, ERole, IAudioCaptureClient, IAudioClient, IMMDeviceEnumerator,
+    IMMNotificationClient, IMMNotificationClient_Impl, MMDeviceEnumerator, AUDCLNT_BUFFERFLAGS_SILENT,
+    AUDCLNT_SHAREMODE_SHARED, DEVICE_STATE,
+    AUDCLNT_STREAMFLAGS_AUTOCONVERTPCM, AUDCLNT_STREAMFLAGS_EVENTCALLBACK, AUDCLNT_STREAMFLAGS_LOOPBACK,
+    AUDIOCLIENT_ACTIVATION_PARAMS, AUDIOCLIENT_ACTIVATION_PARAMS_0,
+    AUDIOCLIENT_ACTIVATION_TYPE_PROCESS_LOOPBACK, AUDIOCLIENT_PROCESS_LOOPBACK_PARAMS,
+    PROCESS_LOOPBACK_MODE_INCLUDE_TARGET_PROCESS_TREE, VIRTUAL_AUDIO_DEVICE_PROCESS_LOOPBACK,
+    WAVEFORMATEX, WAVE_FORMAT_PCM,
+};
+use windows::Win32::System::Com::{CoCreateInstance, CoInitializeEx, CoUninitialize, CLSCTX_ALL, COINIT_MULTITHREADED};
+use windows::Win32::System::Threading::{CreateEventW, WaitForSingleObject};
+use windows::Win32::System::Variant::VT_BLOB;
+use windows::Win32::UI::WindowsAndMessaging::GetWindowThreadProcessId;
+
+/// Shared-mode buffer duration, in 100ns units (200ms).
+const BUFFER_DURATION: i64 = 2_000_000;
+
+/// How far the audio may fall behind the wall clock before silence is
+/// inserted. Process loopback delivers no packets while the application is
+/// quiet, so without padding the audio would drift ahead of the video.
+const MAX_GAP: Duration = Duration::from_millis(100);
+
+/// How long to wait before trying again to reopen a lost stream.
+const REOPEN_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Completion handler for the asynchronous audio client activation.
+#[implement(IActivateAudioInterfaceCompletionHandler)]
+struct ActivationHandler {
+    done: Mutex<Option<Sender<()>>>,
+}
+
+impl IActivateAudioInterfaceCompletionHandler_Impl for ActivationHandler_Impl {
+    fn ActivateCompleted(&self, _operation: Option<&IActivateAudioInterfaceAsyncOperation>) -> windows::core::Result<()> {
+        if let Some(done) = self.done.lock().unwrap().take() {
+            let _ = done.send(());
+        }
+        Ok(())
+    }
+}
+
+/// Start capturing the audio of the process that owns `window_handle` into a WAV file at `path`,
+/// starting at the time `clock` started and leaving out the time it's paused.
+pub fn start_process_audio_capture(window_handle: isize, path: PathBuf, clock: Arc<SyncClock>) -> Result<AudioRecording, String> {
+    let mut process_id = 0u32;
+    unsafe { GetWindowThreadProcessId(HWND(window_handle as *mut std::ffi::c_void), Some(&mut process_id)) };
+    if process_id == 0 {
+        return Err("Failed to find the window's process".to_string());
+    }
+
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    let meter = Arc::new(LevelMeter::default());
+    let (ready_tx, ready_rx) = mpsc::channel();
+    let thread_path = path.clone();
+    let thread_stop = stop_flag.clone();
+    let thread_meter = meter.clone();
+    let thread = std::thread::spawn(move || capture_thread(process_id, &thread_path, &thread_stop, thread_meter, clock, ready_tx));
+
+    // Wait until the stream is running so setup errors fail the recording start
+    match ready_rx.recv() {
+        Ok(Ok(())) => {
+            tracing::info!(target: "Audio", "Capturing audio of process {}", process_id);
+            Ok(AudioRecording::new(path, stop_flag, thread, meter))
+        }
+        Ok(Err(e)) => {
+            let _ = thread.join();
+            Err(e)
+        }
+        Err(_) => Err("Audio capture thread exited unexpectedly".to_string()),
+    }
+}
+
+fn capture_thread(
+    process_id: u32,
+    path: &Path,
+    stop_flag: &AtomicBool,
+    meter: Arc<LevelMeter>,
+    clock: Arc<SyncClock>,
+    ready: Sender<Result<(), String>>,
+) -> Result<(), String> {
+    unsafe {
+        if let Err(e) = CoInitializeEx(None, COINIT_MULTITHREADED).ok() {
+            let error = format!("Failed to initialize COM: {}", e);
+            let _ = ready.send(Err(error.clone()));
+            return Err(error);
+        }
+    }
+
+    let writer = WavWriter::create(path).map(|writer| writer.with_meter(meter).with_pause(clock.pause().clone()));
+    let result = match writer.and_then(|writer| Ok((open_stream(process_id)?, writer))) {
+        Ok((stream, writer)) => {
+            let _ = ready.send(Ok(()));
+            record(process_id, stream, writer, stop_flag, &clock)
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(path);
+            let _ = ready.send(Err(e.clone()));
+            Err(e)
+        }
+    };
+
+    unsafe { CoUninitialize() };
+    if let Err(ref e) = result {
+        tracing::error!(target: "Audio", "Capture failed: {}", e);
+    }
+    result
+}
+
+/// A started process loopback stream.
+struct LoopbackStream {
+    client: IAudioClient,
+    capture: IAudioCaptureClient,
+    event: HANDLE,
+}
+
+/// Activate and start a loopback stream for `process_id`.
+fn open_stream(process_id: u32) -> Result<LoopbackStream, String> {
+    let client = activate_client(process_id)?;
+
+    let format = WAVEFORMATEX {
+        wFormatTag: WAVE_FORMAT_PCM as u16,
+        nChannels: CHANNELS,
+        nSamplesPerSec: SAMPLE_RATE,
+        nAvgBytesPerSec: SAMPLE_RATE * BLOCK_ALIGN as u32,
+        nBlockAlign: BLOCK_ALIGN,
+        wBitsPerSample: BITS_PER_SAMPLE,
+        cbSize: 0,
+    };
+
+    unsafe {
+        // Process loopback streams have no mix format; ask for ours and let
+        // the audio engine convert
+        client
+            .Initialize(
+                AUDCLNT_SHAREMODE_SHARED,
+                AUDCLNT_STREAMFLAGS_LOOPBACK | AUDCLNT_STREAMFLAGS_EVENTCALLBACK | AUDCLNT_STREAMFLAGS_AUTOCONVERTPCM,
+                BUFFER_DURATION,
+                0,
+                &format,
+                None,
+            )
+            .map_err(|e| format!("Failed to initialize audio client: {}", e))?;
+
+        let event = CreateEventW(None, false, false, None).map_err(|e| format!("Failed to create event: {}", e))?;
+        let stream = LoopbackStream {
+            capture: client
+                .GetService()
+                .map_err(|e| format!("Failed to get capture client: {}", e))?,
+            client,
+            event,
+        };
+        stream
+            .client
+            .SetEventHandle(event)
+            .and_then(|_| stream.client.Start())
+            .map_err(|e| format!("Failed to start audio capture: {}", e))?;
+        Ok(stream)
+    }
+}
+
+/// Activate an audio client capturing `process_id` and its children.
+fn activate_client(process_id: u32) -> Result<IAudioClient, String> {
+    let mut params = AUDIOCLIENT_ACTIVATION_PARAMS {
+        ActivationType: AUDIOCLIENT_ACTIVATION_TYPE_PROCESS_LOOPBACK,
+        Anonymous: AUDIOCLIENT_ACTIVATION_PARAMS_0 {
+            ProcessLoopbackParams: AUDIOCLIENT_PROCESS_LOOPBACK_PARAMS {
+                TargetProcessId: process_id,
+                ProcessLoopbackMode: PROCESS_LOOPBACK_MODE_INCLUDE_TARGET_PROCESS_TREE,
+            },
+        },
+    };
+
+    // The parameters are passed as a VT_BLOB pointing at `params`. The
+    // PROPVARIANT doesn't own the blob, so it must not be cleared on drop.
+    let raw = windows::core::imp::PROPVARIANT {
+        Anonymous: windows::core::imp::PROPVARIANT_0 {
+            Anonymous: windows::core::imp::PROPVARIANT_0_0 {
+                vt: VT_BLOB.0,
+                wReserved1: 0,
+                wReserved2: 0,
+                wReserved3: 0,
+                Anonymous: windows::core::imp::PROPVARIANT_0_0_0 {
+                    blob: windows::core::imp::BLOB {
+                        cbSize: std::mem::size_of::<AUDIOCLIENT_ACTIVATION_PARAMS>() as u32,
+                        pBlobData: &mut params as *mut _ as *mut u8,
+                    },
+                },
+            },
+        },
+    };
+    let activation_params = ManuallyDrop::new(unsafe { PROPVARIANT::from_raw(raw) });
+
+    let (done_tx, done_rx) = mpsc::channel();
+    let handler: IActivateAudioInterfaceCompletionHandler = ActivationHandler {
+        done: Mutex::new(Some(done_tx)),
+    }
+    .into();
+
+    unsafe {
+        let operation = ActivateAudioInterfaceAsync(
+            VIRTUAL_AUDIO_DEVICE_PROCESS_LOOPBACK,
+            &IAudioClient::IID,
+            Some(&*activation_params as *const PROPVARIANT),
+            &handler,
+        )
+        .map_err(|e| format!("Failed to activate process audio: {}", e))?;
+
+        done_rx
+            .recv_timeout(Duration::from_secs(5))
+            .map_err(|_| "Timed out activating process audio".to_string())?;
+
+        let mut result = HRESULT(0);
+        let mut interface: Option<IUnknown> = None;
+        operation
+            .GetActivateResult(&mut result, &mut interface)
+            .and_then(|_| result.ok())
+            .map_err(|e| format!("Failed to activate process audio (requires Windows 10 20348 or later): {}", e))?;
+        interface
+            .ok_or_else(|| "Process audio activation returned no client".to_string())?
+            .cast()
+            .map_err(|e| format!("Failed to get audio client: {}", e))
+    }
+}
+
+/// Copy captured audio into `writer` until `stop_flag` is set, reopening
+/// the stream when it's lost or the default device changes.
+fn record(
+    process_id: u32,
+    stream: LoopbackStream,
+    mut writer: WavWriter,
+    stop_flag: &AtomicBool,
+    clock: &SyncClock,
+) -> Result<(), String> {
+    let watcher = DeviceWatcher::register()
+        .inspect_err(|e| tracing::warn!(target: "Audio", "Not watching for device changes: {}", e))
+        .ok();
+    let mut stream = Some(stream);
+    let mut reopen_at = Instant::now();
+
+    while !stop_flag.load(Ordering::Relaxed) {
+        let device_changed = watcher.as_ref().is_some_and(|watcher| watcher.take_changed())
+            && device_mode() == AudioDeviceMode::FollowDefault;
+
+        match &stream {
+            Some(current) => {
+                unsafe { WaitForSingleObject(current.event, 100) };
+                let lost = !current.drain(&mut writer)?;
+                if lost || device_changed {
+                    tracing::warn!(
+                        target: "Audio",
+                        "{}, reconnecting",
+                        if lost { "Audio stream lost" } else { "Default audio device changed" }
+                    );
+                    if let Some(stream) = stream.take() {
+                        stream.close();
+                    }
+                    reopen_at = Instant::now();
+                }
+            }
+            None => std::thread::sleep(Duration::from_millis(100)),
+        }
+
+        if stream.is_none() && Instant::now() >= reopen_at {
+            reopen_at = Instant::now() + REOPEN_INTERVAL;
+            // Fill the switch with silence so the audio stays in step with the video
+            pad_to_clock(&mut writer, clock, Duration::ZERO)?;
+            match open_stream(process_id) {
+                Ok(reopened) => {
+                    tracing::info!(target: "Audio", "Audio stream reconnected");
+                    stream = Some(reopened);
+                }
+                Err(e) => tracing::error!(target: "Audio", "Failed to reconnect audio: {}", e),
+            }
+        }
+
+        // Keep the audio in step with the wall clock while the app is silent
+        pad_to_clock(&mut writer, clock, MAX_GAP)?;
+    }
+
+    if let Some(stream) = stream {
+        stream.drain(&mut writer)?;
+        stream.close();
+    }
+    if let Some(watcher) = watcher {
+        watcher.unregister();
+    }
+    writer.finish()
+}
+
+/// Write silence up to the time on `clock` if the audio has fallen more
+/// than `tolerance` behind it.
+fn pad_to_clock(writer: &mut WavWriter, clock: &SyncClock, tolerance: Duration) -> Result<(), String> {
+    let expected = (clock.now().as_secs_f64() * SAMPLE_RATE as f64) as u64;
+    let tolerance = (tolerance.as_secs_f64() * SAMPLE_RATE as f64) as u64;
+    if expected > writer.frames() + tolerance {
+        writer.write_silence(expected - writer.frames())?;
+    }
+    Ok(())
+}
+
+impl LoopbackStream {
+    /// Write all packets currently available. Returns false if the stream
+    /// has stopped working, e.g. because its device was removed.
+    fn drain(&self, writer: &mut WavWriter) -> Result<bool, String> {
+        unsafe {
+            loop {
+                match self.capture.GetNextPacketSize() {
+                    Ok(0) => break,
+                    Ok(_) => {}
+                    Err(e) => {
+                        tracing::error!(target: "Audio", "Audio capture error: {}", e);
+                        return Ok(false);
+                    }
+                }
+                let mut data = std::ptr::null_mut();
+                let mut frames = 0u32;
+                let mut flags = 0u32;
+                if let Err(e) = self.capture.GetBuffer(&mut data, &mut frames, &mut flags, None, None) {
+                    tracing::error!(target: "Audio", "Audio capture error: {}", e);
+                    return Ok(false);
+                }
+
+                let result = if flags & AUDCLNT_BUFFERFLAGS_SILENT.0 as u32 != 0 || data.is_null() {
+                    writer.write_silence(frames as u64)
+                } else {
+                    writer.write(std::slice::from_raw_parts(data, frames as usize * BLOCK_ALIGN as usize))
+                };
+
+                let released = self.capture.ReleaseBuffer(frames);
+                result?;
+                if let Err(e) = released {
+                    tracing::error!(target: "Audio", "Audio capture error: {}", e);
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn close(self) {
+        unsafe {
+            let _ = self.client.Stop();
+            let _ = CloseHandle(self.event);
+        }
+    }
+}
+
+/// Watches for the default output device changing.
+struct DeviceWatcher {
+    enumerator: IMMDeviceEnumerator,
+    client: IMMNotificationClient,
+    changed: Arc<AtomicBool>,
+}
+
+impl DeviceWatcher {
+    fn register() -> Result<Self, String> {
+        let changed = Arc::new(AtomicBool::new(false));
+        let client: IMMNotificationClient = DeviceNotifications { changed: changed.clone() }.into();
+        unsafe {
+            let enumerator: IMMDeviceEnumerator = CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)
+                .map_err(|e| format!("Failed to create device enumerator: {}", e))?;
+            enumerator
+                .RegisterEndpointNotificationCallback(&client)
+                .map_err(|e| format!("Failed to register for device changes: {}", e))?;
+            Ok(Self { enumerator, client, changed })
+        }
+    }
+
+    /// Whether the default device changed since the last call.
+    fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::Relaxed)
+    }
+
+    fn unregister(self) {
+        unsafe {
+            let _ = self.enumerator.UnregisterEndpointNotificationCallback(&self.client);
+        }
+    }
+}
+
+/// Device notification callback for [`DeviceWatcher`].
+#[implement(IMMNotificationClient)]
+struct DeviceNotifications {
+    changed: Arc<AtomicBool>,
+}
+
+impl IMMNotificationClient_Impl for DeviceNotifications_Impl {
+    fn OnDeviceStateChanged(&self, _device_id: &PCWSTR, _new_state: DEVICE_STATE) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn OnDeviceAdded(&self, _device_id: &PCWSTR) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn OnDeviceRemoved(&self, _device_id: &PCWSTR) -> windows::core::Result<()> {
+        Ok(())
+    }
+
+    fn OnDefaultDeviceChanged(&self, flow: EDataFlow, role: ERole, _device_id: &PCWSTR) -> windows::core::Result<()> {
+        // Applications play to the console role's default output device
+        if flow == eRender && role == eConsole {
+            self.changed.store(true, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    fn OnPropertyValueChanged(&self, _device_id: &PCWSTR, _key: &PROPERTYKEY) -> windows::core::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,38 @@
+//! Per-monitor DPI handling.
+//!
+//! Window and monitor APIs report coordinates scaled for the calling
+//! thread's DPI awareness, while captured frames are always in physical
+//! pixels. Running those calls per-monitor aware keeps everything physical
+//! on mixed-scaling setups.
+
+use windows::Win32::Graphics::Gdi::HMONITOR;
+use windows::Win32::UI::HiDpi::{
+    GetDpiForMonitor, SetThreadDpiAwarenessContext, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2, MDT_EFFECTIVE_DPI,
+};
+
+/// DPI of a monitor at 100% scaling.
+const BASE_DPI: f64 = 96.0;
+
+/// Run `f` with the calling thread per-monitor DPI aware, so that window and
+/// monitor coordinates it sees are physical pixels.
+pub fn physical<T>(f: impl FnOnce() -> T) -> T {
+    unsafe {
+        let previous = SetThreadDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2);
+        let result = f();
+        // An invalid context means the thread's awareness wasn't changed
+        if !previous.is_invalid() {
+            SetThreadDpiAwarenessContext(previous);
+        }
+        result
+    }
+}
+
+/// Scale factor of a monitor (1.25 at 125%), or 1.0 if it can't be read.
+pub fn monitor_scale_factor(monitor: HMONITOR) -> f64 {
+    let mut dpi_x = 0;
+    let mut dpi_y = 0;
+    match unsafe { GetDpiForMonitor(monitor, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y) } {
+        Ok(()) if dpi_x > 0 => dpi_x as f64 / BASE_DPI,
+        _ => 1.0,
+    }
+}
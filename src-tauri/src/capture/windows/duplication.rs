@@ -0,0 +1,287 @@
+//! Game capture using DXGI Desktop Duplication.
+//!
+//! Duplicates the monitor a window is on and crops to the window's client
+//! area. Unlike Windows.Graphics.Capture this sees games presenting in
+//! exclusive fullscreen. The cursor isn't included, which suits games that
+//! draw their own. Duplication is lost when the game changes display mode or
+//! leaves fullscreen; it is then set up again on the window's current monitor.
+
+use crate::capture::queue::{frame_channel, FrameSender};
+use crate::capture::types::{CapturedFrame, ColorSpace, FrameReceiver, PixelFormat};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use windows::core::Interface;
+use windows::Win32::Foundation::{HMODULE, HWND, POINT, RECT};
+use windows::Win32::Graphics::Direct3D::D3D_DRIVER_TYPE_UNKNOWN;
+use windows::Win32::Graphics::Direct3D11::{
+    D3D11CreateDevice, ID3D11Device, ID3D11DeviceContext, ID3D11Texture2D, D3D11_CPU_ACCESS_READ,
+    D3D11_CREATE_DEVICE_BGRA_SUPPORT, D3D11_MAPPED_SUBRESOURCE, D3D11_MAP_READ, D3D11_SDK_VERSION,
+    D3D11_TEXTURE2D_DESC, D3D11_USAGE_STAGING,
+};
+use windows::Win32::Graphics::Dxgi::{
+    CreateDXGIFactory1, IDXGIAdapter, IDXGIFactory1, IDXGIOutput1, IDXGIOutputDuplication, IDXGIResource,
+    DXGI_ERROR_ACCESS_LOST, DXGI_ERROR_WAIT_TIMEOUT, DXGI_OUTDUPL_FRAME_INFO,
+};
+use windows::Win32::Graphics::Gdi::{ClientToScreen, MonitorFromWindow, HMONITOR, MONITOR_DEFAULTTONEAREST};
+use windows::Win32::System::Performance::QueryPerformanceFrequency;
+use windows::Win32::UI::WindowsAndMessaging::{GetClientRect, IsWindow};
+
+/// How long to wait for a new frame before checking whether to stop.
+const FRAME_TIMEOUT_MS: u32 = 100;
+
+/// How long to wait before setting duplication up again after losing it.
+const RETRY_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Start game capture of a window and return a receiver for frames.
+///
+/// Returns a tuple of (frame_receiver, stop_flag).
+/// Set stop_flag to true to stop capture.
+pub fn start_capture(window_handle: isize) -> Result<(FrameReceiver, Arc<AtomicBool>), String> {
+    let hwnd = HWND(window_handle as *mut std::ffi::c_void);
+    // Fail early if the monitor can't be duplicated at all
+    drop(Duplication::for_window(hwnd)?);
+
+    let (frame_tx, frame_rx) = frame_channel(30);
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    let thread_stop = stop_flag.clone();
+    std::thread::spawn(move || {
+        let hwnd = HWND(window_handle as *mut std::ffi::c_void);
+        if let Err(e) = capture_loop(hwnd, &frame_tx, &thread_stop) {
+            tracing::error!(target: "GameCapture", "Capture error: {}", e);
+        }
+        thread_stop.store(true, Ordering::Relaxed);
+    });
+
+    tracing::info!(target: "GameCapture", "Capturing window {:#x} with desktop duplication", window_handle);
+    Ok((frame_rx, stop_flag))
+}
+
+fn capture_loop(hwnd: HWND, frame_tx: &FrameSender, stop_flag: &AtomicBool) -> Result<(), String> {
+    let mut duplication: Option<Duplication> = None;
+    while !stop_flag.load(Ordering::Relaxed) {
+        if !unsafe { IsWindow(hwnd) }.as_bool() {
+            tracing::info!(target: "GameCapture", "Window closed");
+            return Ok(());
+        }
+
+        let current = match duplication.as_mut() {
+            Some(current) => current,
+            None => match Duplication::for_window(hwnd) {
+                Ok(new) => duplication.insert(new),
+                Err(e) => {
+                    tracing::warn!(target: "GameCapture", "{}", e);
+                    std::thread::sleep(RETRY_INTERVAL);
+                    continue;
+                }
+            },
+        };
+
+        match current.next_frame(hwnd) {
+            Ok(Some(frame)) => {
+                // Queue the frame; the queue applies the drop policy if it is full
+                let _ = frame_tx.send(frame);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                // Mode switches and fullscreen transitions invalidate the duplication
+                tracing::warn!(target: "GameCapture", "Duplication lost ({}), restarting", e);
+                duplication = None;
+                std::thread::sleep(RETRY_INTERVAL);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A duplicated monitor and the staging texture its frames are read through.
+struct Duplication {
+    device: ID3D11Device,
+    context: ID3D11DeviceContext,
+    duplication: IDXGIOutputDuplication,
+    /// The monitor's position on the virtual desktop
+    bounds: RECT,
+    staging: Option<(ID3D11Texture2D, u32, u32)>,
+}
+
+impl Duplication {
+    /// Duplicate the monitor showing most of `hwnd`.
+    fn for_window(hwnd: HWND) -> Result<Self, String> {
+        let monitor = unsafe { MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST) };
+        Self::for_monitor(monitor)
+    }
+
+    fn for_monitor(monitor: HMONITOR) -> Result<Self, String> {
+        unsafe {
+            let factory: IDXGIFactory1 =
+                CreateDXGIFactory1().map_err(|e| format!("Failed to create DXGI factory: {}", e))?;
+            let mut adapter_index = 0;
+            while let Ok(adapter) = factory.EnumAdapters1(adapter_index) {
+                let mut output_index = 0;
+                while let Ok(output) = adapter.EnumOutputs(output_index) {
+                    output_index += 1;
+                    let Ok(desc) = output.GetDesc() else { continue };
+                    if desc.Monitor != monitor {
+                        continue;
+                    }
+
+                    // Duplication needs a device on the adapter driving the monitor
+                    let adapter: IDXGIAdapter = adapter.cast().map_err(|e| format!("Failed to get adapter: {}", e))?;
+                    let mut device = None;
+                    let mut context = None;
+                    D3D11CreateDevice(
+                        &adapter,
+                        D3D_DRIVER_TYPE_UNKNOWN,
+                        HMODULE::default(),
+                        D3D11_CREATE_DEVICE_BGRA_SUPPORT,
+                        None,
+                        D3D11_SDK_VERSION,
+                        Some(&mut device),
+                        None,
+                        Some(&mut context),
+                    )
+                    .map_err(|e| format!("Failed to create D3D11 device: {}", e))?;
+                    let device: ID3D11Device = device.ok_or("D3D11 returned no device")?;
+                    let context = context.ok_or("D3D11 returned no device context")?;
+
+                    let duplication = output
+                        .cast::<IDXGIOutput1>()
+                        .and_then(|output| output.DuplicateOutput(&device))
+                        .map_err(|e| format!("Failed to duplicate monitor: {}", e))?;
+                    return Ok(Self {
+                        device,
+                        context,
+                        duplication,
+                        bounds: desc.DesktopCoordinates,
+                        staging: None,
+                    });
+                }
+                adapter_index += 1;
+            }
+            Err("Could not find the window's monitor for game capture".to_string())
+        }
+    }
+
+    /// Wait for the next frame and crop it to `hwnd`'s client area. Returns
+    /// `None` if the screen didn't change or the window isn't on this monitor.
+    fn next_frame(&mut self, hwnd: HWND) -> Result<Option<CapturedFrame>, String> {
+        unsafe {
+            let mut info = DXGI_OUTDUPL_FRAME_INFO::default();
+            let mut resource: Option<IDXGIResource> = None;
+            if let Err(e) = self.duplication.AcquireNextFrame(FRAME_TIMEOUT_MS, &mut info, &mut resource) {
+                if e.code() == DXGI_ERROR_WAIT_TIMEOUT {
+                    return Ok(None);
+                }
+                if e.code() == DXGI_ERROR_ACCESS_LOST {
+                    return Err("access lost".to_string());
+                }
+                return Err(e.to_string());
+            }
+            let captured_at = Instant::now();
+
+            // Copy to a CPU-readable texture, then release the frame as soon as possible
+            let copied = resource
+                .ok_or_else(|| "Duplication returned no frame".to_string())
+                .and_then(|resource| resource.cast::<ID3D11Texture2D>().map_err(|e| e.to_string()))
+                .and_then(|texture| {
+                    let staging = self.staging_for(&texture)?;
+                    self.context.CopyResource(&staging, &texture);
+                    Ok(staging)
+                });
+            let _ = self.duplication.ReleaseFrame();
+            let staging = copied?;
+
+            let Some((x, y, width, height)) = self.window_crop(hwnd) else {
+                return Ok(None);
+            };
+            let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
+            self.context
+                .Map(&staging, 0, D3D11_MAP_READ, 0, Some(&mut mapped))
+                .map_err(|e| format!("Failed to read frame: {}", e))?;
+            let bpp = PixelFormat::Bgra8.bytes_per_pixel();
+            let pitch = mapped.RowPitch as usize;
+            let mut data = Vec::with_capacity(width as usize * height as usize * bpp);
+            for row in y..y + height {
+                let start = row as usize * pitch + x as usize * bpp;
+                let source = std::slice::from_raw_parts((mapped.pData as *const u8).add(start), width as usize * bpp);
+                data.extend_from_slice(source);
+            }
+            self.context.Unmap(&staging, 0);
+
+            Ok(Some(CapturedFrame {
+                width,
+                height,
+                data,
+                format: PixelFormat::Bgra8,
+                color: ColorSpace::SRGB,
+                captured_at,
+                presented_at: qpc_time(info.LastPresentTime),
+            }))
+        }
+    }
+
+    /// Staging texture matching `texture`'s size, created on first use.
+    fn staging_for(&mut self, texture: &ID3D11Texture2D) -> Result<ID3D11Texture2D, String> {
+        let mut desc = D3D11_TEXTURE2D_DESC::default();
+        unsafe { texture.GetDesc(&mut desc) };
+        if let Some((staging, width, height)) = &self.staging {
+            if (*width, *height) == (desc.Width, desc.Height) {
+                return Ok(staging.clone());
+            }
+        }
+
+        let staging_desc = D3D11_TEXTURE2D_DESC {
+            MipLevels: 1,
+            ArraySize: 1,
+            Usage: D3D11_USAGE_STAGING,
+            BindFlags: 0,
+            CPUAccessFlags: D3D11_CPU_ACCESS_READ.0 as u32,
+            MiscFlags: 0,
+            ..desc
+        };
+        let mut staging = None;
+        unsafe { self.device.CreateTexture2D(&staging_desc, None, Some(&mut staging)) }
+            .map_err(|e| format!("Failed to create staging texture: {}", e))?;
+        let staging = staging.ok_or("D3D11 returned no staging texture")?;
+        self.staging = Some((staging.clone(), desc.Width, desc.Height));
+        Ok(staging)
+    }
+
+    /// The part of this monitor showing `hwnd`'s client area, as
+    /// (x, y, width, height) relative to the monitor.
+    fn window_crop(&self, hwnd: HWND) -> Option<(u32, u32, u32, u32)> {
+        let mut client = RECT::default();
+        let mut origin = POINT::default();
+        unsafe {
+            GetClientRect(hwnd, &mut client).ok()?;
+            if !ClientToScreen(hwnd, &mut origin).as_bool() {
+                return None;
+            }
+        }
+        let left = origin.x.max(self.bounds.left);
+        let top = origin.y.max(self.bounds.top);
+        let right = (origin.x + client.right).min(self.bounds.right);
+        let bottom = (origin.y + client.bottom).min(self.bounds.bottom);
+        if right <= left || bottom <= top {
+            return None;
+        }
+        Some((
+            (left - self.bounds.left) as u32,
+            (top - self.bounds.top) as u32,
+            (right - left) as u32,
+            (bottom - top) as u32,
+        ))
+    }
+}
+
+/// A performance counter reading as time since the counter started, or
+/// `None` for zero, which duplication reports when only the pointer moved.
+fn qpc_time(ticks: i64) -> Option<Duration> {
+    let mut frequency = 0i64;
+    if ticks <= 0 || unsafe { QueryPerformanceFrequency(&mut frequency) }.is_err() || frequency <= 0 {
+        return None;
+    }
+    let secs = ticks / frequency;
+    let nanos = (ticks % frequency) as u128 * 1_000_000_000 / frequency as u128;
+    Some(Duration::new(secs as u64, nanos as u32))
+}
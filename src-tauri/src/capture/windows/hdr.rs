@@ -0,0 +1,141 @@
+//! HDR display detection using DXGI and the display configuration API.
+
+use crate::capture::hdr::{HdrMode, ToneMapper, DEFAULT_SDR_WHITE_NITS, SCRGB_WHITE_NITS};
+use crate::capture::types::{CaptureOptions, PixelFormat};
+use std::ffi::OsString;
+use std::os::windows::ffi::OsStringExt;
+use windows::Win32::Devices::Display::{
+    DisplayConfigGetDeviceInfo, GetDisplayConfigBufferSizes, QueryDisplayConfig, DISPLAYCONFIG_DEVICE_INFO_GET_SDR_WHITE_LEVEL,
+    DISPLAYCONFIG_DEVICE_INFO_GET_SOURCE_NAME, DISPLAYCONFIG_MODE_INFO, DISPLAYCONFIG_PATH_INFO, DISPLAYCONFIG_SDR_WHITE_LEVEL,
+    DISPLAYCONFIG_SOURCE_DEVICE_NAME, QDC_ONLY_ACTIVE_PATHS,
+};
+use windows::Win32::Foundation::HWND;
+use windows::Win32::Graphics::Dxgi::Common::DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020;
+use windows::Win32::Graphics::Dxgi::{CreateDXGIFactory1, IDXGIFactory1, IDXGIOutput6};
+use windows::Win32::Graphics::Gdi::{GetMonitorInfoW, MonitorFromWindow, MONITORINFOEXW, MONITOR_DEFAULTTONEAREST};
+use windows::core::Interface;
+use windows_capture::settings::ColorFormat;
+
+/// Convert a NUL-terminated UTF-16 buffer to a string.
+pub(super) fn wide_to_string(wide: &[u16]) -> String {
+    let len = wide.iter().position(|&c| c == 0).unwrap_or(wide.len());
+    OsString::from_wide(&wide[..len]).to_string_lossy().to_string()
+}
+
+/// The SDR white level of a monitor in nits if it is in HDR mode, or `None`
+/// for SDR monitors.
+fn hdr_white_level(monitor_id: &str) -> Option<f32> {
+    if !is_hdr_enabled(monitor_id) {
+        return None;
+    }
+    Some(sdr_white_level(monitor_id).unwrap_or_else(|| {
+        tracing::warn!(target: "HDR", "Could not read SDR white level of {}, assuming {} nits", monitor_id, DEFAULT_SDR_WHITE_NITS);
+        DEFAULT_SDR_WHITE_NITS
+    }))
+}
+
+/// Device ID of the monitor showing most of a window.
+pub fn window_monitor_id(window_handle: isize) -> Option<String> {
+    unsafe {
+        let monitor = MonitorFromWindow(HWND(window_handle as *mut std::ffi::c_void), MONITOR_DEFAULTTONEAREST);
+        let mut info = MONITORINFOEXW::default();
+        info.monitorInfo.cbSize = std::mem::size_of::<MONITORINFOEXW>() as u32;
+        if !GetMonitorInfoW(monitor, &mut info as *mut _ as *mut _).as_bool() {
+            return None;
+        }
+        Some(wide_to_string(&info.szDevice))
+    }
+}
+
+/// Whether the output for a monitor uses the HDR10 (PQ) color space.
+fn is_hdr_enabled(monitor_id: &str) -> bool {
+    unsafe {
+        let Ok(factory) = CreateDXGIFactory1::<IDXGIFactory1>() else {
+            return false;
+        };
+        let mut adapter_index = 0;
+        while let Ok(adapter) = factory.EnumAdapters1(adapter_index) {
+            let mut output_index = 0;
+            while let Ok(output) = adapter.EnumOutputs(output_index) {
+                if let Ok(desc) = output.cast::<IDXGIOutput6>().and_then(|output| output.GetDesc1()) {
+                    if wide_to_string(&desc.DeviceName) == monitor_id {
+                        return desc.ColorSpace == DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020;
+                    }
+                }
+                output_index += 1;
+            }
+            adapter_index += 1;
+        }
+        false
+    }
+}
+
+/// The "SDR content brightness" setting of a monitor in nits.
+fn sdr_white_level(monitor_id: &str) -> Option<f32> {
+    unsafe {
+        let mut path_count = 0;
+        let mut mode_count = 0;
+        GetDisplayConfigBufferSizes(QDC_ONLY_ACTIVE_PATHS, &mut path_count, &mut mode_count).ok().ok()?;
+        let mut paths = vec![DISPLAYCONFIG_PATH_INFO::default(); path_count as usize];
+        let mut modes = vec![DISPLAYCONFIG_MODE_INFO::default(); mode_count as usize];
+        QueryDisplayConfig(
+            QDC_ONLY_ACTIVE_PATHS,
+            &mut path_count,
+            paths.as_mut_ptr(),
+            &mut mode_count,
+            modes.as_mut_ptr(),
+            None,
+        )
+        .ok()
+        .ok()?;
+        paths.truncate(path_count as usize);
+
+        for path in paths {
+            let mut source = DISPLAYCONFIG_SOURCE_DEVICE_NAME::default();
+            source.header.r#type = DISPLAYCONFIG_DEVICE_INFO_GET_SOURCE_NAME;
+            source.header.size = std::mem::size_of::<DISPLAYCONFIG_SOURCE_DEVICE_NAME>() as u32;
+            source.header.adapterId = path.sourceInfo.adapterId;
+            source.header.id = path.sourceInfo.id;
+            if DisplayConfigGetDeviceInfo(&mut source.header) != 0
+                || wide_to_string(&source.viewGdiDeviceName) != monitor_id
+            {
+                continue;
+            }
+
+            let mut white_level = DISPLAYCONFIG_SDR_WHITE_LEVEL::default();
+            white_level.header.r#type = DISPLAYCONFIG_DEVICE_INFO_GET_SDR_WHITE_LEVEL;
+            white_level.header.size = std::mem::size_of::<DISPLAYCONFIG_SDR_WHITE_LEVEL>() as u32;
+            white_level.header.adapterId = path.targetInfo.adapterId;
+            white_level.header.id = path.targetInfo.id;
+            if DisplayConfigGetDeviceInfo(&mut white_level.header) != 0 {
+                return None;
+            }
+            // Reported in thousandths of scRGB white
+            return Some(white_level.SDRWhiteLevel as f32 / 1000.0 * SCRGB_WHITE_NITS);
+        }
+        None
+    }
+}
+
+/// Surface format for capturing a monitor and, when its HDR content is
+/// recorded as SDR, the tone mapper to apply to the captured frames.
+///
+/// SDR monitors are composited at 8 bits, so deep color only changes the
+/// output of the tone mapper.
+pub fn capture_format(monitor_id: Option<&str>, options: CaptureOptions) -> (ColorFormat, PixelFormat, Option<ToneMapper>) {
+    let Some(white_level) = monitor_id.and_then(hdr_white_level) else {
+        return (ColorFormat::Bgra8, PixelFormat::Bgra8, None);
+    };
+    tracing::info!(
+        target: "HDR",
+        "{} is in HDR mode (SDR white {:.0} nits), capturing in FP16 ({:?})",
+        monitor_id.unwrap_or_default(),
+        white_level,
+        options.hdr
+    );
+    let tone_mapper = match options.hdr {
+        HdrMode::ToneMap => Some(ToneMapper::new(white_level, options.deep_color)),
+        HdrMode::Passthrough => None,
+    };
+    (ColorFormat::Rgba16F, PixelFormat::Rgba16F, tone_mapper)
+}
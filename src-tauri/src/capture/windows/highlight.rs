@@ -1,39 +1,38 @@
 //! Native display highlight using Windows APIs.
 //!
 //! Creates a transparent layered window with a colored border to highlight a monitor.
-//! Uses UpdateLayeredWindow for flicker-free alpha animation.
+//! Uses UpdateLayeredWindow for flicker-free alpha animation. The window is
+//! click-through and never activated, and is drawn in physical pixels with
+//! the border scaled to the monitor's DPI.
 
+use super::dpi;
+use crate::capture::highlight::{border_pixel, Animation, BORDER_WIDTH, FRAME_INTERVAL};
 use std::thread;
-use std::time::Instant;
 
 use windows::core::PCWSTR;
 use windows::Win32::Foundation::{COLORREF, HINSTANCE, HWND, LPARAM, LRESULT, POINT, SIZE, WPARAM};
 use windows::Win32::Graphics::Gdi::{
-    CreateCompatibleDC, CreateDIBSection, DeleteDC, DeleteObject, GetDC, ReleaseDC, SelectObject,
-    BLENDFUNCTION, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS, HBITMAP, HDC, HGDIOBJ,
+    CreateCompatibleDC, CreateDIBSection, DeleteDC, DeleteObject, GetDC, MonitorFromPoint, ReleaseDC, SelectObject,
+    BLENDFUNCTION, MONITOR_DEFAULTTONEAREST, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS, HBITMAP, HDC, HGDIOBJ,
 };
 use windows::Win32::System::LibraryLoader::GetModuleHandleW;
 use windows::Win32::UI::WindowsAndMessaging::{
     CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, GetMessageW, KillTimer,
     PostQuitMessage, RegisterClassW, SetTimer, ShowWindow, UpdateLayeredWindow, CS_HREDRAW,
     CS_VREDRAW, MSG, SW_SHOWNOACTIVATE, ULW_ALPHA, WM_DESTROY, WM_TIMER, WNDCLASSW, WS_EX_LAYERED,
-    WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_EX_TRANSPARENT, WS_POPUP,
+    WS_EX_NOACTIVATE, WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_EX_TRANSPARENT, WS_POPUP,
 };
 
-const BORDER_WIDTH: i32 = 8;
-const ANIMATION_DURATION_MS: u64 = 800;
 const TIMER_ID: usize = 1;
-const TIMER_INTERVAL_MS: u32 = 16; // ~60fps
 
-
-
-/// Show a highlight border around the specified monitor area.
-/// This function spawns a thread and returns immediately.
+/// Show a highlight border around the specified monitor area, given in
+/// physical pixels. This function spawns a thread and returns immediately.
 pub fn show_highlight(x: i32, y: i32, width: i32, height: i32) {
     thread::spawn(move || {
-        unsafe {
+        // Keep the window in physical pixels so it lines up on scaled monitors
+        dpi::physical(|| unsafe {
             run_highlight_window(x, y, width, height);
-        }
+        });
     });
 }
 
@@ -46,7 +45,7 @@ struct RenderState {
     pt_dst: POINT,
     size: SIZE,
     pt_src: POINT,
-    start_time: Instant,
+    animation: Animation,
 }
 
 thread_local! {
@@ -71,7 +70,8 @@ unsafe fn run_highlight_window(x: i32, y: i32, width: i32, height: i32) {
 
     // Create layered window
     let hwnd = CreateWindowExW(
-        WS_EX_LAYERED | WS_EX_TOPMOST | WS_EX_TOOLWINDOW | WS_EX_TRANSPARENT,
+        // Transparent to clicks, and never takes focus
+        WS_EX_LAYERED | WS_EX_TOPMOST | WS_EX_TOOLWINDOW | WS_EX_TRANSPARENT | WS_EX_NOACTIVATE,
         PCWSTR(class_name.as_ptr()),
         PCWSTR::null(),
         WS_POPUP,
@@ -89,6 +89,9 @@ unsafe fn run_highlight_window(x: i32, y: i32, width: i32, height: i32) {
     if hwnd.0.is_null() {
         return;
     }
+    if crate::capture::exclusion::excludes_self() {
+        crate::capture::exclusion::hide_from_capture(hwnd.0 as isize, true);
+    }
 
     // Create the border bitmap with per-pixel alpha (32-bit ARGB)
     let screen_dc = GetDC(HWND::default());
@@ -130,42 +133,23 @@ unsafe fn run_highlight_window(x: i32, y: i32, width: i32, height: i32) {
 
     let old_bitmap = SelectObject(mem_dc, bitmap);
 
-    // Draw directly to the pixel buffer
-    // Windows DIB is BGRA in memory, and for AC_SRC_ALPHA we need premultiplied alpha
-    // On little-endian, u32 0xAARRGGBB becomes bytes [BB, GG, RR, AA] in memory
-    // But Windows DIB expects [BB, GG, RR, AA] which is u32 as 0xAARRGGBB... 
-    // Actually, let's write bytes directly to be safe
-    let pixels = bits as *mut u8;
-    let stride = (width * 4) as usize; // 4 bytes per pixel
-
-    // #2196F3 = R:0x21, G:0x96, B:0xF3
-    let r: u8 = 0x21;
-    let g: u8 = 0x96;
-    let b: u8 = 0xF3;
-    let a: u8 = 0xFF;
-
-    for py in 0..height {
-        for px in 0..width {
-            let idx = (py as usize) * stride + (px as usize) * 4;
-            let is_border = py < BORDER_WIDTH
-                || py >= height - BORDER_WIDTH
-                || px < BORDER_WIDTH
-                || px >= width - BORDER_WIDTH;
-
-            if is_border {
-                // BGRA order, premultiplied (since alpha=255, RGB values stay the same)
-                *pixels.add(idx) = b;     // Blue
-                *pixels.add(idx + 1) = g; // Green
-                *pixels.add(idx + 2) = r; // Red
-                *pixels.add(idx + 3) = a; // Alpha
-            } else {
-                // Fully transparent
-                *pixels.add(idx) = 0;
-                *pixels.add(idx + 1) = 0;
-                *pixels.add(idx + 2) = 0;
-                *pixels.add(idx + 3) = 0;
-            }
-        }
+    // Draw the border at the same apparent thickness at any scaling
+    let center = POINT { x: x + width / 2, y: y + height / 2 };
+    let scale_factor = dpi::monitor_scale_factor(MonitorFromPoint(center, MONITOR_DEFAULTTONEAREST));
+    let border_width = (BORDER_WIDTH as f64 * scale_factor).round() as i32;
+
+    // Windows DIB is BGRA in memory, and AC_SRC_ALPHA needs premultiplied
+    // alpha; the fade is applied through SourceConstantAlpha instead
+    let pixels = std::slice::from_raw_parts_mut(bits as *mut u8, (width * height * 4) as usize);
+    let color = border_pixel(255);
+    for (index, pixel) in pixels.chunks_exact_mut(4).enumerate() {
+        let px = index as i32 % width;
+        let py = index as i32 / width;
+        let is_border = py < border_width
+            || py >= height - border_width
+            || px < border_width
+            || px >= width - border_width;
+        pixel.copy_from_slice(if is_border { &color } else { &[0; 4] });
     }
 
     // Store render state
@@ -182,7 +166,7 @@ unsafe fn run_highlight_window(x: i32, y: i32, width: i32, height: i32) {
             pt_dst,
             size,
             pt_src,
-            start_time: Instant::now(),
+            animation: Animation::start(),
         });
     });
 
@@ -193,7 +177,7 @@ unsafe fn run_highlight_window(x: i32, y: i32, width: i32, height: i32) {
     let _ = ShowWindow(hwnd, SW_SHOWNOACTIVATE);
 
     // Start animation timer
-    SetTimer(hwnd, TIMER_ID, TIMER_INTERVAL_MS, None);
+    SetTimer(hwnd, TIMER_ID, FRAME_INTERVAL.as_millis() as u32, None);
 
     // Message loop
     let mut msg = MSG::default();
@@ -246,34 +230,18 @@ unsafe extern "system" fn highlight_wnd_proc(
 ) -> LRESULT {
     match msg {
         WM_TIMER => {
-            let elapsed = RENDER_STATE.with(|state| {
-                state.borrow().as_ref().map(|s| s.start_time.elapsed())
+            let alpha = RENDER_STATE.with(|state| {
+                state.borrow_mut().as_mut().map(|s| s.animation.next_alpha())
             });
 
-            if let Some(elapsed) = elapsed {
-                let elapsed_ms = elapsed.as_millis() as u64;
-
-                if elapsed_ms >= ANIMATION_DURATION_MS {
-                    // Animation complete, close window
+            match alpha {
+                Some(Some(alpha)) => update_window_alpha(hwnd, alpha),
+                Some(None) => {
+                    // Faded out, or replaced by another highlight
                     KillTimer(hwnd, TIMER_ID).ok();
                     DestroyWindow(hwnd).ok();
-                } else {
-                    // Calculate alpha based on animation progress
-                    // Fade in for first 15%, hold until 70%, fade out
-                    let progress = elapsed_ms as f32 / ANIMATION_DURATION_MS as f32;
-                    let alpha = if progress < 0.15 {
-                        // Fade in
-                        (progress / 0.15 * 255.0) as u8
-                    } else if progress < 0.70 {
-                        // Hold
-                        255
-                    } else {
-                        // Fade out
-                        ((1.0 - (progress - 0.70) / 0.30) * 255.0) as u8
-                    };
-
-                    update_window_alpha(hwnd, alpha);
                 }
+                None => {}
             }
             LRESULT(0)
         }
@@ -1,5 +1,9 @@
 //! Windows platform capture implementation.
 
+pub mod audio;
+mod dpi;
+mod duplication;
+mod hdr;
 mod highlight;
 mod monitor_list;
 mod recorder;
@@ -7,18 +11,23 @@ mod region;
 mod window_list;
 
 use crate::capture::error::{CaptureError, EnumerationError};
+use crate::capture::game;
 use crate::capture::types::{
-    CapturedFrame, CaptureRegion, FrameReceiver, MonitorInfo, StopHandle, WindowInfo,
+    Capabilities, CaptureOptions, CapturedFrame, CaptureRegion, CoordinateSpace, FrameReceiver, MonitorInfo, StopHandle, WindowBounds, WindowInfo, WindowState,
 };
 use crate::capture::{CaptureBackend, HighlightProvider, MonitorEnumerator, WindowEnumerator};
 
 /// Windows platform capture backend.
-pub struct WindowsBackend;
+pub struct WindowsBackend {
+    options: CaptureOptions,
+}
 
 impl WindowsBackend {
     /// Create a new Windows backend.
     pub fn new() -> Self {
-        Self
+        Self {
+            options: CaptureOptions::default(),
+        }
     }
 }
 
@@ -32,6 +41,20 @@ impl WindowEnumerator for WindowsBackend {
     fn list_windows(&self) -> Result<Vec<WindowInfo>, EnumerationError> {
         Ok(window_list::list_windows())
     }
+
+    fn list_window_bounds(&self) -> Result<Vec<(WindowInfo, WindowBounds)>, EnumerationError> {
+        Ok(window_list::list_window_bounds())
+    }
+
+    fn window_state(&self, window_handle: isize) -> WindowState {
+        window_list::window_state(window_handle)
+    }
+
+    fn add_thumbnails(&self, windows: &mut [WindowInfo]) {
+        for window in windows {
+            window.thumbnail = window_list::thumbnail(window.handle);
+        }
+    }
 }
 
 impl MonitorEnumerator for WindowsBackend {
@@ -45,14 +68,20 @@ impl CaptureBackend for WindowsBackend {
         &self,
         window_handle: isize,
     ) -> Result<(FrameReceiver, StopHandle), CaptureError> {
-        recorder::start_capture(window_handle).map_err(|e| CaptureError::PlatformError(e))
+        let settings = game::settings();
+        let window = window_list::list_windows().into_iter().find(|w| w.handle == window_handle);
+        if let Some(window) = window.filter(|w| settings.uses_game_capture(w)) {
+            tracing::info!(target: "Capture", "Using game capture for {}", window.title);
+            return duplication::start_capture(window_handle).map_err(|e| CaptureError::PlatformError(e));
+        }
+        recorder::start_capture(window_handle, self.options).map_err(|e| CaptureError::PlatformError(e))
     }
 
     fn start_region_capture(
         &self,
         region: CaptureRegion,
     ) -> Result<(FrameReceiver, StopHandle), CaptureError> {
-        region::start_region_capture(region).map_err(|e| CaptureError::PlatformError(e))
+        region::start_region_capture(region, self.options).map_err(|e| CaptureError::PlatformError(e))
     }
 
     fn start_display_capture(
@@ -68,8 +97,33 @@ impl CaptureBackend for WindowsBackend {
             y: 0,
             width,
             height,
+            space: CoordinateSpace::Physical,
         };
-        region::start_region_capture(region).map_err(|e| CaptureError::PlatformError(e))
+        region::start_region_capture(region, self.options).map_err(|e| CaptureError::PlatformError(e))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        // Windows.Graphics.Capture draws the cursor into frames and delivers
+        // them at up to the display's refresh rate
+        Capabilities {
+            window_capture: true,
+            region_capture: true,
+            display_capture: true,
+            cursor_metadata: false,
+            cursor_modes: vec!["embedded".to_string()],
+            app_audio: true,
+            hdr: true,
+            deep_color: true,
+            alpha: true,
+            highlight: true,
+            game_capture: true,
+            max_fps: None,
+            sandbox: None,
+        }
+    }
+
+    fn set_options(&mut self, options: CaptureOptions) {
+        self.options = options;
     }
 }
 
@@ -77,6 +131,10 @@ impl HighlightProvider for WindowsBackend {
     fn show_highlight(&self, x: i32, y: i32, width: i32, height: i32) {
         highlight::show_highlight(x, y, width, height);
     }
+
+    fn hide_highlight(&self) {
+        crate::capture::highlight::hide();
+    }
 }
 
 // Re-export CapturedFrame for backward compatibility with encoder
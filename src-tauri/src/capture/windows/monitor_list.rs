@@ -1,26 +1,34 @@
 //! Monitor enumeration using Windows API.
 
+use super::dpi;
+use super::hdr::wide_to_string;
 use crate::capture::types::MonitorInfo;
 use std::ffi::OsString;
 use std::os::windows::ffi::OsStringExt;
+use windows::Win32::Devices::Display::{
+    DisplayConfigGetDeviceInfo, GetDisplayConfigBufferSizes, QueryDisplayConfig, DISPLAYCONFIG_DEVICE_INFO_GET_SOURCE_NAME,
+    DISPLAYCONFIG_DEVICE_INFO_GET_TARGET_NAME, DISPLAYCONFIG_MODE_INFO, DISPLAYCONFIG_PATH_INFO, DISPLAYCONFIG_ROTATION_ROTATE180,
+    DISPLAYCONFIG_ROTATION_ROTATE270, DISPLAYCONFIG_ROTATION_ROTATE90, DISPLAYCONFIG_SOURCE_DEVICE_NAME,
+    DISPLAYCONFIG_TARGET_DEVICE_NAME, QDC_ONLY_ACTIVE_PATHS,
+};
 use windows::Win32::Foundation::{BOOL, LPARAM, RECT};
 use windows::Win32::Graphics::Gdi::{
     EnumDisplayDevicesW, EnumDisplayMonitors, GetMonitorInfoW, HDC, HMONITOR, MONITORINFOEXW,
     DISPLAY_DEVICEW, DISPLAY_DEVICE_ACTIVE,
 };
 
-/// List all connected monitors.
+/// List all connected monitors, with positions and sizes in physical pixels.
 pub fn list_monitors() -> Vec<MonitorInfo> {
     let mut monitors: Vec<MonitorInfo> = Vec::new();
 
-    unsafe {
+    dpi::physical(|| unsafe {
         let _ = EnumDisplayMonitors(
             HDC::default(),
             None,
             Some(enum_monitor_callback),
             LPARAM(&mut monitors as *mut Vec<MonitorInfo> as isize),
         );
-    }
+    });
 
     // Sort so primary is first
     monitors.sort_by(|a, b| b.is_primary.cmp(&a.is_primary));
@@ -55,8 +63,12 @@ unsafe extern "system" fn enum_monitor_callback(
             .to_string_lossy()
             .to_string();
 
-        // Get friendly display name
-        let display_name = get_display_friendly_name(&device_name)
+        // Get friendly display name, preferring the monitor's own
+        let target = display_target(&device_name).unwrap_or_default();
+        let display_name = target
+            .model
+            .clone()
+            .or_else(|| get_display_friendly_name(&device_name))
             .unwrap_or_else(|| format_monitor_name(&device_name, is_primary));
 
         monitors.push(MonitorInfo {
@@ -67,12 +79,85 @@ unsafe extern "system" fn enum_monitor_callback(
             width: (rect.right - rect.left) as u32,
             height: (rect.bottom - rect.top) as u32,
             is_primary,
+            scale_factor: dpi::monitor_scale_factor(hmonitor),
+            model: target.model,
+            refresh_rate: target.refresh_rate,
+            rotation: target.rotation,
         });
     }
 
     BOOL(1) // Continue enumeration
 }
 
+/// What the display configuration says about the monitor a display shows on.
+#[derive(Default)]
+struct DisplayTarget {
+    /// Name from the monitor's EDID
+    model: Option<String>,
+    refresh_rate: Option<f64>,
+    rotation: u32,
+}
+
+/// Model, refresh rate and rotation of the monitor showing a display, by
+/// device name (`\\.\DISPLAY1`).
+fn display_target(device_name: &str) -> Option<DisplayTarget> {
+    unsafe {
+        let mut path_count = 0;
+        let mut mode_count = 0;
+        GetDisplayConfigBufferSizes(QDC_ONLY_ACTIVE_PATHS, &mut path_count, &mut mode_count).ok().ok()?;
+        let mut paths = vec![DISPLAYCONFIG_PATH_INFO::default(); path_count as usize];
+        let mut modes = vec![DISPLAYCONFIG_MODE_INFO::default(); mode_count as usize];
+        QueryDisplayConfig(
+            QDC_ONLY_ACTIVE_PATHS,
+            &mut path_count,
+            paths.as_mut_ptr(),
+            &mut mode_count,
+            modes.as_mut_ptr(),
+            None,
+        )
+        .ok()
+        .ok()?;
+        paths.truncate(path_count as usize);
+
+        for path in paths {
+            let mut source = DISPLAYCONFIG_SOURCE_DEVICE_NAME::default();
+            source.header.r#type = DISPLAYCONFIG_DEVICE_INFO_GET_SOURCE_NAME;
+            source.header.size = std::mem::size_of::<DISPLAYCONFIG_SOURCE_DEVICE_NAME>() as u32;
+            source.header.adapterId = path.sourceInfo.adapterId;
+            source.header.id = path.sourceInfo.id;
+            if DisplayConfigGetDeviceInfo(&mut source.header) != 0
+                || wide_to_string(&source.viewGdiDeviceName) != device_name
+            {
+                continue;
+            }
+
+            // Built-in panels often have no name, so it's fine to go without
+            let mut target = DISPLAYCONFIG_TARGET_DEVICE_NAME::default();
+            target.header.r#type = DISPLAYCONFIG_DEVICE_INFO_GET_TARGET_NAME;
+            target.header.size = std::mem::size_of::<DISPLAYCONFIG_TARGET_DEVICE_NAME>() as u32;
+            target.header.adapterId = path.targetInfo.adapterId;
+            target.header.id = path.targetInfo.id;
+            let model = (DisplayConfigGetDeviceInfo(&mut target.header) == 0)
+                .then(|| wide_to_string(&target.monitorFriendlyDeviceName))
+                .filter(|name| !name.is_empty());
+
+            let rate = path.targetInfo.refreshRate;
+            return Some(DisplayTarget {
+                model,
+                refresh_rate: (rate.Denominator != 0 && rate.Numerator != 0)
+                    .then(|| rate.Numerator as f64 / rate.Denominator as f64),
+                rotation: match path.targetInfo.rotation {
+                    DISPLAYCONFIG_ROTATION_ROTATE90 => 90,
+                    DISPLAYCONFIG_ROTATION_ROTATE180 => 180,
+                    DISPLAYCONFIG_ROTATION_ROTATE270 => 270,
+                    _ => 0,
+                },
+            });
+        }
+        None
+    }
+}
+
 /// Get friendly display name from device name.
 fn get_display_friendly_name(device_name: &str) -> Option<String> {
     unsafe {
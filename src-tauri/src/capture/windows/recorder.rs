@@ -1,15 +1,18 @@
 //! Window recording using Windows.Graphics.Capture API via windows-capture crate.
 
-use crate::capture::types::CapturedFrame;
+use super::hdr::{capture_format, window_monitor_id};
+use crate::capture::hdr::ToneMapper;
+use crate::capture::queue::{frame_channel, FrameSender};
+use crate::capture::types::{CaptureOptions, CapturedFrame, ColorSpace, FrameReceiver, PixelFormat};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::time::Instant;
 use windows_capture::{
     capture::{Context, GraphicsCaptureApiHandler},
     frame::Frame,
     graphics_capture_api::InternalCaptureControl,
     settings::{
-        ColorFormat, CursorCaptureSettings, DirtyRegionSettings, DrawBorderSettings,
+        CursorCaptureSettings, DirtyRegionSettings, DrawBorderSettings,
         MinimumUpdateIntervalSettings, SecondaryWindowSettings, Settings,
     },
     window::Window,
@@ -17,14 +20,23 @@ use windows_capture::{
 
 /// Flags passed to the capture handler.
 pub struct CaptureFlags {
-    pub frame_tx: mpsc::Sender<CapturedFrame>,
+    pub frame_tx: FrameSender,
     pub stop_flag: Arc<AtomicBool>,
+    /// Format of the captured surface
+    pub format: PixelFormat,
+    /// Converts HDR frames to SDR, if they are recorded as SDR
+    pub tone_mapper: Option<ToneMapper>,
+    /// Keep the window's transparency
+    pub alpha: bool,
 }
 
 /// Frame capture handler that sends frames through a channel.
 struct CaptureHandler {
-    frame_tx: mpsc::Sender<CapturedFrame>,
+    frame_tx: FrameSender,
     stop_flag: Arc<AtomicBool>,
+    format: PixelFormat,
+    tone_mapper: Option<ToneMapper>,
+    alpha: bool,
 }
 
 impl GraphicsCaptureApiHandler for CaptureHandler {
@@ -35,6 +47,9 @@ impl GraphicsCaptureApiHandler for CaptureHandler {
         Ok(Self {
             frame_tx: ctx.flags.frame_tx,
             stop_flag: ctx.flags.stop_flag,
+            format: ctx.flags.format,
+            tone_mapper: ctx.flags.tone_mapper,
+            alpha: ctx.flags.alpha,
         })
     }
 
@@ -48,6 +63,7 @@ impl GraphicsCaptureApiHandler for CaptureHandler {
             capture_control.stop();
             return Ok(());
         }
+        let captured_at = Instant::now();
 
         // Get frame buffer
         let mut buffer = frame.buffer()?;
@@ -57,7 +73,7 @@ impl GraphicsCaptureApiHandler for CaptureHandler {
 
         // Calculate stride (bytes per row in the buffer) - may include padding for GPU alignment
         let buffer_stride = raw_data.len() / height as usize;
-        let expected_stride = (width as usize) * 4; // BGRA = 4 bytes per pixel
+        let expected_stride = (width as usize) * self.format.bytes_per_pixel();
 
         // Copy pixel data, handling stride padding if present
         let data = if buffer_stride == expected_stride {
@@ -76,14 +92,24 @@ impl GraphicsCaptureApiHandler for CaptureHandler {
             output
         };
 
-        let captured_frame = CapturedFrame {
+        let mut captured_frame = CapturedFrame {
             width,
             height,
             data,
+            format: self.format,
+            color: ColorSpace::SRGB,
+            captured_at,
+            presented_at: None,
         };
+        if let Some(ref tone_mapper) = self.tone_mapper {
+            captured_frame = tone_mapper.map(captured_frame);
+        }
+        if self.alpha {
+            captured_frame.unpremultiply_alpha();
+        }
 
-        // Try to send frame, don't block if channel is full (drop frame instead)
-        let _ = self.frame_tx.try_send(captured_frame);
+        // Queue the frame; the queue applies the drop policy if it is full
+        let _ = self.frame_tx.send(captured_frame);
 
         Ok(())
     }
@@ -96,26 +122,35 @@ impl GraphicsCaptureApiHandler for CaptureHandler {
 }
 
 /// Start capturing a window and return a receiver for frames.
+/// Windows on HDR monitors are captured in FP16 and tone mapped unless
+/// `options` keep HDR.
 ///
 /// Returns a tuple of (frame_receiver, stop_flag).
 /// Set stop_flag to true to stop capture.
 pub fn start_capture(
     window_handle: isize,
-) -> Result<(mpsc::Receiver<CapturedFrame>, Arc<AtomicBool>), String> {
+    options: CaptureOptions,
+) -> Result<(FrameReceiver, Arc<AtomicBool>), String> {
     // Find window by handle
     let window = Window::from_raw_hwnd(window_handle as *mut std::ffi::c_void);
 
     // Create channel for frames (bounded to prevent memory growth)
-    let (frame_tx, frame_rx) = mpsc::channel::<CapturedFrame>(30); // ~1 second buffer at 30fps
+    let (frame_tx, frame_rx) = frame_channel(30); // ~1 second buffer at 30fps
 
     // Create stop flag
     let stop_flag = Arc::new(AtomicBool::new(false));
     let stop_flag_clone = stop_flag.clone();
 
+    // The format is chosen for the monitor the window starts on
+    let (color_format, format, tone_mapper) = capture_format(window_monitor_id(window_handle).as_deref(), options);
+
     // Create flags for the handler
     let flags = CaptureFlags {
         frame_tx,
         stop_flag: stop_flag_clone,
+        format,
+        tone_mapper,
+        alpha: options.alpha,
     };
 
     // Configure capture settings with all required parameters
@@ -126,14 +161,14 @@ pub fn start_capture(
         SecondaryWindowSettings::Default,
         MinimumUpdateIntervalSettings::Default,
         DirtyRegionSettings::Default,
-        ColorFormat::Bgra8,
+        color_format,
         flags,
     );
 
     // Start capture in a separate thread
     std::thread::spawn(move || {
         if let Err(e) = CaptureHandler::start(settings) {
-            eprintln!("Capture error: {}", e);
+            tracing::error!(target: "Capture", "Capture error: {}", e);
         }
     });
 
@@ -1,32 +1,42 @@
 //! Region recording using Windows.Graphics.Capture API for monitor capture with cropping.
 
-use crate::capture::types::{CapturedFrame, CaptureRegion};
+use super::hdr::capture_format;
+use crate::capture::desktop::DesktopLayout;
+use crate::capture::hdr::ToneMapper;
+use crate::capture::queue::{frame_channel, FrameSender};
+use crate::capture::types::{CaptureOptions, CapturedFrame, CaptureRegion, ColorSpace, FrameReceiver, PixelFormat};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::time::Instant;
 use windows_capture::{
     capture::{Context, GraphicsCaptureApiHandler},
     frame::Frame,
     graphics_capture_api::InternalCaptureControl,
     monitor::Monitor,
     settings::{
-        ColorFormat, CursorCaptureSettings, DirtyRegionSettings, DrawBorderSettings,
+        CursorCaptureSettings, DirtyRegionSettings, DrawBorderSettings,
         MinimumUpdateIntervalSettings, SecondaryWindowSettings, Settings,
     },
 };
 
 /// Flags passed to the region capture handler.
 pub struct RegionCaptureFlags {
-    pub frame_tx: mpsc::Sender<CapturedFrame>,
+    pub frame_tx: FrameSender,
     pub stop_flag: Arc<AtomicBool>,
     pub region: CaptureRegion,
+    /// Format of the captured surface
+    pub format: PixelFormat,
+    /// Converts HDR frames to SDR, if they are recorded as SDR
+    pub tone_mapper: Option<ToneMapper>,
 }
 
 /// Frame capture handler for monitor-based region capture.
 struct RegionCaptureHandler {
-    frame_tx: mpsc::Sender<CapturedFrame>,
+    frame_tx: FrameSender,
     stop_flag: Arc<AtomicBool>,
     region: CaptureRegion,
+    format: PixelFormat,
+    tone_mapper: Option<ToneMapper>,
     #[allow(dead_code)]
     frame_count: u64,
     #[allow(dead_code)]
@@ -42,6 +52,8 @@ impl GraphicsCaptureApiHandler for RegionCaptureHandler {
             frame_tx: ctx.flags.frame_tx,
             stop_flag: ctx.flags.stop_flag,
             region: ctx.flags.region,
+            format: ctx.flags.format,
+            tone_mapper: ctx.flags.tone_mapper,
             frame_count: 0,
             dropped_count: 0,
         })
@@ -57,6 +69,7 @@ impl GraphicsCaptureApiHandler for RegionCaptureHandler {
             capture_control.stop();
             return Ok(());
         }
+        let captured_at = Instant::now();
 
         // Get frame buffer
         let mut buffer = frame.buffer()?;
@@ -87,29 +100,30 @@ impl GraphicsCaptureApiHandler for RegionCaptureHandler {
             region_y,
             region_width,
             region_height,
+            self.format.bytes_per_pixel(),
         );
 
-        let captured_frame = CapturedFrame {
+        let mut captured_frame = CapturedFrame {
             width: region_width,
             height: region_height,
             data: cropped_data,
+            format: self.format,
+            color: ColorSpace::SRGB,
+            captured_at,
+            presented_at: None,
         };
+        if let Some(ref tone_mapper) = self.tone_mapper {
+            captured_frame = tone_mapper.map(captured_frame);
+        }
 
-        // Try to send frame
-        match self.frame_tx.try_send(captured_frame) {
-            Ok(()) => {
-                self.frame_count += 1;
-            }
-            Err(mpsc::error::TrySendError::Full(_)) => {
-                // Channel full, drop frame (encoder can't keep up)
-                self.dropped_count += 1;
-            }
-            Err(mpsc::error::TrySendError::Closed(_)) => {
-                // Channel closed, stop capture
-                capture_control.stop();
-                return Ok(());
-            }
+        // Queue the frame; the queue drops frames if the encoder can't keep up
+        if self.frame_tx.send(captured_frame).is_err() {
+            // Channel closed, stop capture
+            capture_control.stop();
+            return Ok(());
         }
+        self.frame_count += 1;
+        self.dropped_count = self.frame_tx.dropped();
 
         Ok(())
     }
@@ -123,11 +137,13 @@ impl GraphicsCaptureApiHandler for RegionCaptureHandler {
 /// Crop a frame buffer to the specified region.
 ///
 /// # Arguments
-/// * `data` - Source BGRA pixel data
+/// * `data` - Source pixel data
 /// * `full_width` - Full frame width in pixels
 /// * `buffer_stride` - Bytes per row in the source buffer (may include padding)
 /// * `x`, `y` - Region top-left position
 /// * `width`, `height` - Region dimensions
+/// * `pixel_stride` - Bytes per pixel
+#[allow(clippy::too_many_arguments)]
 fn crop_frame(
     data: &[u8],
     _full_width: u32,
@@ -136,8 +152,8 @@ fn crop_frame(
     y: u32,
     width: u32,
     height: u32,
+    pixel_stride: usize,
 ) -> Vec<u8> {
-    let pixel_stride = 4usize; // BGRA
     let output_row_bytes = (width as usize) * pixel_stride;
     let mut output = Vec::with_capacity(output_row_bytes * height as usize);
 
@@ -176,35 +192,37 @@ fn find_monitor_by_id(monitor_id: &str) -> Result<Monitor, String> {
 }
 
 /// Start capturing a screen region and return a receiver for cropped frames.
+/// HDR monitors are captured in FP16 and tone mapped unless `options` keep HDR.
 ///
 /// Returns a tuple of (frame_receiver, stop_flag).
 /// Set stop_flag to true to stop capture.
 pub fn start_region_capture(
     region: CaptureRegion,
-) -> Result<(mpsc::Receiver<CapturedFrame>, Arc<AtomicBool>), String> {
-    // Validate dimensions
-    if region.width == 0 || region.height == 0 {
-        return Err(format!(
-            "Invalid region dimensions: {}x{}",
-            region.width, region.height
-        ));
-    }
+    options: CaptureOptions,
+) -> Result<(FrameReceiver, Arc<AtomicBool>), String> {
+    // Frames are cropped in physical pixels, from one monitor; regions
+    // hanging off it are clamped rather than padded with black
+    let region = DesktopLayout::current().validate_region(&region)?;
 
     // Find the monitor
     let monitor = find_monitor_by_id(&region.monitor_id)?;
 
     // Create channel for frames (larger buffer for region capture which may have bursty delivery)
-    let (frame_tx, frame_rx) = mpsc::channel::<CapturedFrame>(120);
+    let (frame_tx, frame_rx) = frame_channel(120);
 
     // Create stop flag
     let stop_flag = Arc::new(AtomicBool::new(false));
     let stop_flag_clone = stop_flag.clone();
 
+    let (color_format, format, tone_mapper) = capture_format(Some(&region.monitor_id), options);
+
     // Create flags for the handler
     let flags = RegionCaptureFlags {
         frame_tx,
         stop_flag: stop_flag_clone,
         region,
+        format,
+        tone_mapper,
     };
 
     // Configure capture settings
@@ -215,14 +233,14 @@ pub fn start_region_capture(
         SecondaryWindowSettings::Default,
         MinimumUpdateIntervalSettings::Default,
         DirtyRegionSettings::Default,
-        ColorFormat::Bgra8,
+        color_format,
         flags,
     );
 
     // Start capture in a separate thread
     std::thread::spawn(move || {
         if let Err(e) = RegionCaptureHandler::start(settings) {
-            eprintln!("Region capture error: {}", e);
+            tracing::error!(target: "Capture", "Region capture error: {}", e);
         }
     });
 
@@ -243,7 +261,7 @@ mod tests {
         }
 
         // Crop a 2x2 region starting at (1, 1)
-        let cropped = crop_frame(&data, 4, 16, 1, 1, 2, 2);
+        let cropped = crop_frame(&data, 4, 16, 1, 1, 2, 2, 4);
 
         // Expected: pixels 5,6 and 9,10
         assert_eq!(cropped.len(), 2 * 2 * 4); // 2x2 pixels, 4 bytes each
@@ -1,34 +1,168 @@
 //! Window enumeration using Windows API.
 
-use crate::capture::types::WindowInfo;
+use crate::capture::types::{WindowBounds, WindowInfo, WindowState};
 use std::ffi::OsString;
 use std::os::windows::ffi::OsStringExt;
-use windows::Win32::Foundation::{BOOL, HWND, LPARAM};
+use windows::Win32::Foundation::{BOOL, HWND, LPARAM, RECT, WPARAM};
+use windows::Win32::Graphics::Gdi::{
+    CreateCompatibleBitmap, CreateCompatibleDC, DeleteDC, DeleteObject, GetDC, GetDIBits, GetObjectW, ReleaseDC,
+    SelectObject, BITMAP, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS, HBITMAP, HDC,
+};
+use windows::Win32::Storage::Xps::{PrintWindow, PRINT_WINDOW_FLAGS};
 use windows::Win32::System::ProcessStatus::GetModuleBaseNameW;
 use windows::Win32::System::Threading::{OpenProcess, PROCESS_QUERY_INFORMATION, PROCESS_VM_READ};
 use windows::Win32::UI::WindowsAndMessaging::{
-    EnumWindows, GetWindowTextLengthW, GetWindowTextW, GetWindowThreadProcessId, IsIconic,
-    IsWindowVisible, GA_ROOTOWNER, GetAncestor, GetWindow, GetWindowLongW,
-    GWL_EXSTYLE, GW_OWNER, WS_EX_TOOLWINDOW,
+    EnumWindows, GetWindowRect, GetWindowTextLengthW, GetWindowTextW, GetWindowThreadProcessId, IsIconic,
+    IsWindow, IsWindowVisible, GA_ROOTOWNER, GetAncestor, GetWindow, GetWindowLongW,
+    GWL_EXSTYLE, GW_OWNER, WS_EX_TOOLWINDOW, GetClassLongPtrW, GetClassNameW, GetIconInfo, SendMessageTimeoutW, GCLP_HICON,
+    HICON, ICONINFO, ICON_BIG, SMTO_ABORTIFHUNG, WM_GETICON,
 };
 
 /// List all visible, capturable windows.
 pub fn list_windows() -> Vec<WindowInfo> {
-    let mut windows: Vec<WindowInfo> = Vec::new();
+    list_window_bounds().into_iter().map(|(window, _)| window).collect()
+}
+
+/// List all visible, capturable windows with their screen bounds.
+pub fn list_window_bounds() -> Vec<(WindowInfo, WindowBounds)> {
+    let mut windows: Vec<(WindowInfo, WindowBounds)> = Vec::new();
 
     unsafe {
         let _ = EnumWindows(
             Some(enum_window_callback),
-            LPARAM(&mut windows as *mut Vec<WindowInfo> as isize),
+            LPARAM(&mut windows as *mut Vec<(WindowInfo, WindowBounds)> as isize),
         );
     }
 
     windows
 }
 
+/// Whether a window is still open, and whether it's minimized.
+///
+/// Minimized windows aren't listed, so they're checked directly.
+pub fn window_state(window_handle: isize) -> WindowState {
+    let hwnd = HWND(window_handle as *mut std::ffi::c_void);
+    unsafe {
+        if !IsWindow(hwnd).as_bool() {
+            WindowState::Closed
+        } else if IsIconic(hwnd).as_bool() {
+            WindowState::Minimized
+        } else {
+            WindowState::Open
+        }
+    }
+}
+
+/// A thumbnail of a window, drawn by the window itself so it's right even
+/// when other windows cover it.
+pub fn thumbnail(window_handle: isize) -> Option<String> {
+    // PW_RENDERFULLCONTENT: also draws DirectComposition content, such as
+    // browsers and other GPU-rendered windows
+    const RENDER_FULL_CONTENT: PRINT_WINDOW_FLAGS = PRINT_WINDOW_FLAGS(2);
+
+    let hwnd = HWND(window_handle as *mut std::ffi::c_void);
+    let mut rect = RECT::default();
+    unsafe { GetWindowRect(hwnd, &mut rect).ok()? };
+    let (width, height) = (rect.right - rect.left, rect.bottom - rect.top);
+    if width <= 0 || height <= 0 {
+        return None;
+    }
+
+    let mut data = vec![0u8; width as usize * height as usize * 4];
+    let copied = unsafe {
+        let screen_dc = GetDC(HWND::default());
+        let mem_dc = CreateCompatibleDC(screen_dc);
+        let bitmap = CreateCompatibleBitmap(screen_dc, width, height);
+        let old_bitmap = SelectObject(mem_dc, bitmap);
+
+        let printed = PrintWindow(hwnd, mem_dc, RENDER_FULL_CONTENT).as_bool();
+        SelectObject(mem_dc, old_bitmap);
+        let rows = printed && read_bitmap(mem_dc, bitmap, width, height, &mut data);
+
+        let _ = DeleteObject(bitmap);
+        let _ = DeleteDC(mem_dc);
+        ReleaseDC(HWND::default(), screen_dc);
+        rows
+    };
+    if !copied {
+        tracing::warn!(target: "WindowList", "Failed to draw window {} for its thumbnail", window_handle);
+        return None;
+    }
+
+    crate::capture::thumbnail::encode(&data, width as u32, height as u32, width as usize * 4)
+        .map_err(|e| tracing::warn!(target: "WindowList", "{}", e))
+        .ok()
+}
+
+/// Read a `width` x `height` bitmap into `data` as top-down BGRA.
+unsafe fn read_bitmap(dc: HDC, bitmap: HBITMAP, width: i32, height: i32, data: &mut [u8]) -> bool {
+    let mut bmi = BITMAPINFO {
+        bmiHeader: BITMAPINFOHEADER {
+            biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+            biWidth: width,
+            biHeight: -height, // Top-down rows
+            biPlanes: 1,
+            biBitCount: 32,
+            biCompression: BI_RGB.0,
+            ..Default::default()
+        },
+        bmiColors: [Default::default()],
+    };
+    GetDIBits(dc, bitmap, 0, height as u32, Some(data.as_mut_ptr().cast()), &mut bmi, DIB_RGB_COLORS) == height
+}
+
+/// The icon a window shows in its title bar and the taskbar.
+unsafe fn app_icon(hwnd: HWND) -> Option<String> {
+    // Hung windows don't answer, so don't wait long for them
+    let mut answer = 0usize;
+    let _ = SendMessageTimeoutW(
+        hwnd,
+        WM_GETICON,
+        WPARAM(ICON_BIG as usize),
+        LPARAM(0),
+        SMTO_ABORTIFHUNG,
+        100,
+        Some(&mut answer),
+    );
+    if answer == 0 {
+        answer = GetClassLongPtrW(hwnd, GCLP_HICON);
+    }
+    if answer == 0 {
+        return None;
+    }
+
+    let mut info = ICONINFO::default();
+    GetIconInfo(HICON(answer as *mut std::ffi::c_void), &mut info).ok()?;
+    let mut bitmap = BITMAP::default();
+    let described = !info.hbmColor.is_invalid()
+        && GetObjectW(
+            info.hbmColor,
+            std::mem::size_of::<BITMAP>() as i32,
+            Some(&mut bitmap as *mut BITMAP as *mut std::ffi::c_void),
+        ) != 0;
+
+    let (width, height) = (bitmap.bmWidth, bitmap.bmHeight);
+    let mut data = vec![0u8; (width.max(0) * height.max(0) * 4) as usize];
+    let read = described && width > 0 && height > 0 && {
+        let screen_dc = GetDC(HWND::default());
+        let read = read_bitmap(screen_dc, info.hbmColor, width, height, &mut data);
+        ReleaseDC(HWND::default(), screen_dc);
+        read
+    };
+    let _ = DeleteObject(info.hbmColor);
+    let _ = DeleteObject(info.hbmMask);
+    if !read {
+        return None;
+    }
+
+    crate::capture::icon::from_bgra(&data, width as u32, height as u32)
+        .map_err(|e| tracing::warn!(target: "WindowList", "{}", e))
+        .ok()
+}
+
 /// Callback for EnumWindows that filters and collects window info.
 unsafe extern "system" fn enum_window_callback(hwnd: HWND, lparam: LPARAM) -> BOOL {
-    let windows = &mut *(lparam.0 as *mut Vec<WindowInfo>);
+    let windows = &mut *(lparam.0 as *mut Vec<(WindowInfo, WindowBounds)>);
 
     // Skip invisible windows
     if !IsWindowVisible(hwnd).as_bool() {
@@ -110,11 +244,31 @@ unsafe extern "system" fn enum_window_callback(hwnd: HWND, lparam: LPARAM) -> BO
         String::from("Unknown")
     };
 
-    windows.push(WindowInfo {
-        handle: hwnd.0 as isize,
-        title,
-        process_name,
-    });
+    let mut class_buf: Vec<u16> = vec![0; 256];
+    let class_len = GetClassNameW(hwnd, &mut class_buf).max(0) as usize;
+    let class = OsString::from_wide(&class_buf[..class_len]).to_string_lossy().to_string();
+
+    let mut rect = RECT::default();
+    let _ = GetWindowRect(hwnd, &mut rect);
+    let bounds = WindowBounds {
+        x: rect.left,
+        y: rect.top,
+        width: (rect.right - rect.left).max(0) as u32,
+        height: (rect.bottom - rect.top).max(0) as u32,
+    };
+
+    windows.push((
+        WindowInfo {
+            handle: hwnd.0 as isize,
+            title,
+            process_name,
+            pid: process_id,
+            class,
+            icon: app_icon(hwnd),
+            thumbnail: None,
+        },
+        bounds,
+    ));
 
     BOOL(1) // Continue enumeration
 }
@@ -0,0 +1,519 @@
+//! Headless command-line mode.
+//!
+//! Drives the same capture/encode pipeline as the UI without opening a
+//! window, for scripting and capturing CI test runs:
+//!
+//! ```text
+//! screen-recorder record --monitor 1 --duration 5m --output out.mkv
+//! screen-recorder list
+//! screen-recorder test-signal --duration 30s
+//! ```
+
+use crate::capture::{list_monitors, list_windows, MonitorInfo};
+use crate::encoder::{ensure_ffmpeg_blocking, EncoderSettings};
+use crate::limits::{LimitReason, RecordingLimits, StopPoint};
+use crate::profiles::Profiles;
+use crate::state::RecordingManager;
+use crate::test_signal;
+use crate::window_match::{find_window, WindowQuery};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How long past `--duration` to wait for the encoder to stop on its own.
+const STOP_GRACE: Duration = Duration::from_secs(5);
+
+const USAGE: &str = "\
+Usage:
+  screen-recorder record [options]   Record without opening the app window
+  screen-recorder list               List monitors and windows that can be recorded
+  screen-recorder test-signal [options]
+                                     Record color bars and a tone, then check the file's audio sync
+
+Record options:
+  --monitor <N|ID>     Monitor to record, by 1-based index or ID (default: primary)
+  --window <HANDLE>    Window to record, by handle from `list`
+  --app <NAME>         Window to record, by application name (matched loosely)
+  --title <TEXT>       Window to record, by text in its title; with --app, of that application
+  --app-audio          Also record the window's application audio (Windows)
+  --duration <TIME>    Stop after exactly TIME, e.g. 90, 90s, 5m, 1h (default: until Ctrl+C)
+  --max-size <MB>      Stop once the file reaches MB megabytes
+  --output <FILE>      Output file; the container follows the extension (default: Videos folder)
+  --profile <NAME>     Recording profile to use (default: the one selected in the app)
+  --no-gpu             Use software color conversion
+
+Test signal options:
+  --duration <TIME>    Length of the test recording (default: 10s)
+  --output <FILE>      Output file; the container follows the extension (default: Videos folder)
+  --profile <NAME>     Recording profile to test (default: the one selected in the app)
+  --no-gpu             Use software color conversion";
+
+/// What to record.
+#[derive(Debug, Clone, PartialEq)]
+enum Source {
+    /// A monitor by 1-based index or ID; `None` for the primary monitor
+    Monitor(Option<String>),
+    /// A window by handle
+    Window(isize),
+    /// The window best matching an application name and/or title
+    MatchingWindow(WindowQuery),
+}
+
+/// Parsed `record` options.
+#[derive(Debug, Clone, PartialEq)]
+struct RecordOptions {
+    source: Source,
+    duration: Option<Duration>,
+    max_size_mb: Option<u64>,
+    output: Option<PathBuf>,
+    profile: Option<String>,
+    app_audio: bool,
+    gpu_conversion: bool,
+}
+
+/// Parsed `test-signal` options.
+#[derive(Debug, Clone, PartialEq)]
+struct TestSignalOptions {
+    duration: Duration,
+    output: Option<PathBuf>,
+    profile: Option<String>,
+    gpu_conversion: bool,
+}
+
+/// A parsed command line.
+#[derive(Debug, Clone, PartialEq)]
+enum Command {
+    Record(RecordOptions),
+    TestSignal(TestSignalOptions),
+    List,
+    Help,
+}
+
+/// Run the CLI if the arguments name a CLI command.
+///
+/// Returns the process exit code, or `None` if the app should start normally.
+pub fn run(args: &[String]) -> Option<i32> {
+    let command = match parse_args(args)? {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            return Some(2);
+        }
+    };
+
+    attach_console();
+
+    let result = match command {
+        Command::Help => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Command::List => {
+            list();
+            Ok(())
+        }
+        Command::Record(options) => tokio::runtime::Runtime::new()
+            .map_err(|e| format!("Failed to start runtime: {}", e))
+            .and_then(|runtime| runtime.block_on(record(options))),
+        Command::TestSignal(options) => tokio::runtime::Runtime::new()
+            .map_err(|e| format!("Failed to start runtime: {}", e))
+            .and_then(|runtime| runtime.block_on(test_signal(options))),
+    };
+
+    match result {
+        Ok(()) => Some(0),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            Some(1)
+        }
+    }
+}
+
+/// Parse the command line (including the program name).
+///
+/// Returns `None` when no CLI command was given.
+fn parse_args(args: &[String]) -> Option<Result<Command, String>> {
+    let mut args = args.iter().skip(1);
+    let command = match args.next()?.as_str() {
+        "record" => parse_record_options(args).map(Command::Record),
+        "test-signal" => parse_test_signal_options(args).map(Command::TestSignal),
+        "list" => Ok(Command::List),
+        "help" | "--help" | "-h" => Ok(Command::Help),
+        // Not ours; e.g. arguments passed by the OS when launching the app
+        _ => return None,
+    };
+    Some(command)
+}
+
+fn parse_record_options<'a>(mut args: impl Iterator<Item = &'a String>) -> Result<RecordOptions, String> {
+    let mut options = RecordOptions {
+        source: Source::Monitor(None),
+        duration: None,
+        max_size_mb: None,
+        output: None,
+        profile: None,
+        app_audio: false,
+        gpu_conversion: true,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{} requires a value", name))
+        };
+        match arg.as_str() {
+            "--monitor" => options.source = Source::Monitor(Some(value("--monitor")?)),
+            "--window" => {
+                let handle = value("--window")?;
+                let handle = parse_handle(&handle).ok_or_else(|| format!("Invalid window handle: {}", handle))?;
+                options.source = Source::Window(handle);
+            }
+            "--app" | "--title" => {
+                let text = value(arg)?;
+                let mut query = match options.source {
+                    Source::MatchingWindow(ref query) => query.clone(),
+                    _ => WindowQuery::default(),
+                };
+                match arg.as_str() {
+                    "--app" => query.app = Some(text),
+                    _ => query.title = Some(text),
+                }
+                options.source = Source::MatchingWindow(query);
+            }
+            "--duration" => options.duration = Some(parse_duration(&value("--duration")?)?),
+            "--max-size" => {
+                let size = value("--max-size")?;
+                let size = size.parse().ok().filter(|&mb| mb > 0).ok_or_else(|| format!("Invalid size: {}", size))?;
+                options.max_size_mb = Some(size);
+            }
+            "--output" => options.output = Some(PathBuf::from(value("--output")?)),
+            "--profile" => options.profile = Some(value("--profile")?),
+            "--app-audio" => options.app_audio = true,
+            "--no-gpu" => options.gpu_conversion = false,
+            other => return Err(format!("Unknown option: {}", other)),
+        }
+    }
+
+    Ok(options)
+}
+
+fn parse_test_signal_options<'a>(mut args: impl Iterator<Item = &'a String>) -> Result<TestSignalOptions, String> {
+    let mut options = TestSignalOptions {
+        duration: Duration::from_secs_f64(test_signal::DEFAULT_SECS),
+        output: None,
+        profile: None,
+        gpu_conversion: true,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{} requires a value", name))
+        };
+        match arg.as_str() {
+            "--duration" => options.duration = parse_duration(&value("--duration")?)?,
+            "--output" => options.output = Some(PathBuf::from(value("--output")?)),
+            "--profile" => options.profile = Some(value("--profile")?),
+            "--no-gpu" => options.gpu_conversion = false,
+            other => return Err(format!("Unknown option: {}", other)),
+        }
+    }
+
+    Ok(options)
+}
+
+/// Parse a window handle in decimal or `0x` hex.
+fn parse_handle(text: &str) -> Option<isize> {
+    match text.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok().map(|h| h as isize),
+        None => text.parse().ok(),
+    }
+}
+
+/// Parse a duration like `90`, `90s`, `5m`, or `1h` (decimals allowed).
+fn parse_duration(text: &str) -> Result<Duration, String> {
+    let (number, unit) = match text.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(i) => text.split_at(i),
+        None => (text, "s"),
+    };
+    let scale = match unit {
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => return Err(format!("Invalid duration unit in {:?} (use s, m, or h)", text)),
+    };
+    let seconds = number
+        .parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite() && *n > 0.0)
+        .ok_or_else(|| format!("Invalid duration: {:?}", text))?;
+    Ok(Duration::from_secs_f64(seconds * scale))
+}
+
+/// Print the recordable monitors and windows.
+fn list() {
+    println!("Monitors:");
+    for (index, monitor) in list_monitors().iter().enumerate() {
+        println!(
+            "  {}  {}  {} ({}x{}){}",
+            index + 1,
+            monitor.id,
+            monitor.name,
+            monitor.width,
+            monitor.height,
+            if monitor.is_primary { " [primary]" } else { "" }
+        );
+    }
+
+    println!("Windows:");
+    for window in list_windows() {
+        println!("  0x{:x}  {}  ({})", window.handle as usize, window.title, window.process_name);
+    }
+}
+
+/// Find a monitor by 1-based index or ID, or the primary monitor.
+fn find_monitor(monitors: &[MonitorInfo], selector: Option<&str>) -> Result<MonitorInfo, String> {
+    let found = match selector {
+        None => monitors.iter().find(|m| m.is_primary).or(monitors.first()),
+        Some(selector) => match selector.parse::<usize>() {
+            Ok(index) if index >= 1 => monitors.get(index - 1),
+            _ => monitors.iter().find(|m| m.id == selector),
+        },
+    };
+    found
+        .cloned()
+        .ok_or_else(|| format!("Monitor {} not found (see `screen-recorder list`)", selector.unwrap_or("")))
+}
+
+/// Record until the duration elapses or Ctrl+C is pressed.
+async fn record(options: RecordOptions) -> Result<(), String> {
+    tokio::task::spawn_blocking(ensure_ffmpeg_blocking)
+        .await
+        .map_err(|e| format!("Task error: {}", e))??;
+
+    // The Linux backend requests captures from the portal through the IPC server
+    #[cfg(target_os = "linux")]
+    crate::capture::linux::init_ipc_server().await?;
+
+    let manager = RecordingManager::new();
+    let mut settings = manager.get_encoder_settings().await;
+    apply_profile(&mut settings, options.profile.as_deref())?;
+    settings.gpu_conversion = options.gpu_conversion;
+    settings.output_path = options.output.clone();
+    manager.set_encoder_settings(settings).await;
+    manager
+        .set_limits(RecordingLimits {
+            max_duration_mins: None,
+            max_size_mb: options.max_size_mb,
+        })
+        .await?;
+
+    match options.source {
+        Source::Monitor(selector) => {
+            let monitor = find_monitor(&list_monitors(), selector.as_deref())?;
+            eprintln!("Recording monitor {} ({}x{})", monitor.name, monitor.width, monitor.height);
+            manager
+                .start_display_recording(monitor.id, monitor.width, monitor.height)
+                .await?;
+        }
+        Source::Window(handle) => {
+            eprintln!("Recording window 0x{:x}", handle as usize);
+            manager.start_recording(handle, options.app_audio).await?;
+        }
+        Source::MatchingWindow(query) => {
+            let windows = list_windows();
+            let window = find_window(&windows, &query)?;
+            eprintln!(
+                "Recording window 0x{:x}: {} ({})",
+                window.handle as usize, window.title, window.process_name
+            );
+            manager.start_recording(window.handle, options.app_audio).await?;
+        }
+    }
+
+    // Stop early if the disk is about to fill up
+    let mut disk_events = manager.subscribe_disk_events();
+    let disk_full = async move {
+        while let Ok(event) = disk_events.recv().await {
+            if event.stopping {
+                eprintln!("Only {} MB free on the output disk", event.free_bytes / (1024 * 1024));
+                return;
+            }
+        }
+        std::future::pending().await
+    };
+    let mut limit_events = manager.subscribe_limit_events();
+    let limit_reached = async move {
+        match limit_events.recv().await {
+            Ok(event) if event.reason == LimitReason::StopPoint => eprintln!("Recorded {:.3}s", event.elapsed_secs),
+            Ok(event) => eprintln!("Recording {:?} limit reached", event.reason),
+            Err(_) => std::future::pending().await,
+        }
+    };
+
+    match options.duration {
+        Some(duration) => {
+            eprintln!("Recording for {:.0}s (Ctrl+C to stop early)...", duration.as_secs_f64());
+            // The encoder stops on the exact frame; the timer only covers a
+            // capture that never delivers one
+            manager.schedule_stop(StopPoint::Duration { secs: duration.as_secs_f64() }).await?;
+            tokio::select! {
+                _ = tokio::time::sleep(duration + STOP_GRACE) => {}
+                _ = tokio::signal::ctrl_c() => {}
+                _ = disk_full => {}
+                _ = limit_reached => {}
+            }
+        }
+        None => {
+            eprintln!("Recording (Ctrl+C to stop)...");
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = disk_full => {}
+                _ = limit_reached => {}
+            }
+        }
+    }
+
+    eprintln!("Stopping...");
+    let result = manager.stop_recording().await?;
+    match (result.success, result.file_path, result.error) {
+        (true, Some(path), _) => {
+            // The path is the command's output, so it goes to stdout for scripts
+            println!("{}", path);
+            Ok(())
+        }
+        (_, _, error) => Err(error.unwrap_or_else(|| "Recording failed".to_string())),
+    }
+}
+
+/// Record a test signal and report how the file came out.
+async fn test_signal(options: TestSignalOptions) -> Result<(), String> {
+    tokio::task::spawn_blocking(ensure_ffmpeg_blocking)
+        .await
+        .map_err(|e| format!("Task error: {}", e))??;
+
+    let mut settings = RecordingManager::new().get_encoder_settings().await;
+    apply_profile(&mut settings, options.profile.as_deref())?;
+    settings.gpu_conversion = options.gpu_conversion;
+    eprintln!("Recording a test signal for {:.0}s...", options.duration.as_secs_f64());
+    let report = test_signal::record(settings, options.duration.as_secs_f64(), options.output).await?;
+
+    println!("{}", report.path);
+    eprintln!(
+        "{} {}x{} at {:.2} fps ({}), {:.3}s of {:.3}s, {}",
+        report.codec,
+        report.width,
+        report.height,
+        report.fps,
+        report.encoder,
+        report.duration_secs,
+        report.expected_secs,
+        if report.has_audio { "with audio" } else { "no audio" }
+    );
+    if !report.sync_offsets_ms.is_empty() {
+        let mean = report.sync_offsets_ms.iter().sum::<f64>() / report.sync_offsets_ms.len() as f64;
+        eprintln!("Audio is {:+.1} ms from the picture on average over {} marks", mean, report.sync_offsets_ms.len());
+    }
+    if report.passed {
+        eprintln!("Passed");
+        Ok(())
+    } else {
+        Err(report.problems.join("; "))
+    }
+}
+
+/// Apply the profile named `name`, or the one selected in the app, to
+/// `settings`.
+fn apply_profile(settings: &mut EncoderSettings, name: Option<&str>) -> Result<(), String> {
+    let profiles = Profiles::load();
+    let profile = match name {
+        Some(name) => Some(
+            profiles
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("Profile {:?} not found", name))?,
+        ),
+        None => profiles.selected().cloned(),
+    };
+    if let Some(profile) = profile {
+        eprintln!("Using profile {}", profile.name);
+        profile.apply(settings);
+    }
+    Ok(())
+}
+
+/// Release builds on Windows have no console; reattach to the launching terminal.
+fn attach_console() {
+    #[cfg(target_os = "windows")]
+    unsafe {
+        use windows::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+        let _ = AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        std::iter::once("screen-recorder")
+            .chain(line.split_whitespace())
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(parse_args(&args("")), None);
+        assert_eq!(
+            parse_args(&args("record --monitor 1 --duration 5m --output out.mkv")),
+            Some(Ok(Command::Record(RecordOptions {
+                source: Source::Monitor(Some("1".to_string())),
+                duration: Some(Duration::from_secs(300)),
+                max_size_mb: None,
+                output: Some(PathBuf::from("out.mkv")),
+                profile: None,
+                app_audio: false,
+                gpu_conversion: true,
+            })))
+        );
+        assert!(matches!(
+            parse_args(&args("record --window 0x1a2b --app-audio --no-gpu")),
+            Some(Ok(Command::Record(RecordOptions {
+                source: Source::Window(0x1a2b),
+                app_audio: true,
+                gpu_conversion: false,
+                ..
+            })))
+        ));
+        let query = WindowQuery { app: Some("chrome".to_string()), title: Some("Meet".to_string()) };
+        assert!(matches!(
+            parse_args(&args("record --app chrome --title Meet")),
+            Some(Ok(Command::Record(RecordOptions { source: Source::MatchingWindow(q), .. }))) if q == query
+        ));
+        assert!(matches!(parse_args(&args("record --duration")), Some(Err(_))));
+        assert_eq!(
+            parse_args(&args("test-signal --duration 30s --no-gpu")),
+            Some(Ok(Command::TestSignal(TestSignalOptions {
+                duration: Duration::from_secs(30),
+                output: None,
+                profile: None,
+                gpu_conversion: false,
+            })))
+        );
+        assert!(matches!(
+            parse_args(&args("record --max-size 500")),
+            Some(Ok(Command::Record(RecordOptions { max_size_mb: Some(500), .. })))
+        ));
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("1.5m").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("1h").unwrap(), Duration::from_secs(3600));
+        assert!(parse_duration("5d").is_err());
+        assert!(parse_duration("-1s").is_err());
+    }
+}
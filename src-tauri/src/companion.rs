@@ -0,0 +1,296 @@
+//! Remote control from a phone or another machine on the local network.
+//!
+//! When the recording machine's screen is the content, touching its UI
+//! mid-capture ends up in the recording. The companion server exposes the
+//! control API (see [`crate::control`]) on every interface, under a token
+//! of its own, and advertises itself over mDNS as `_screenrec._tcp` so
+//! companion apps can find it. Any browser can also open the `/remote` page
+//! it serves, with the token in the URL, to start, stop, pause and add
+//! markers while watching the recording's status.
+//!
+//! The API is plain HTTP: anyone on the network who sees the token can
+//! control the recorder, so the companion is off by default and the token
+//! can be replaced at any time. Settings are saved in `companion.json`.
+
+use crate::config::{load_json, save_json};
+use crate::control;
+use crate::http;
+use crate::mdns::{self, Service};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use tauri::AppHandle;
+
+const SETTINGS_FILE: &str = "companion.json";
+
+/// Default port for the companion server.
+pub const DEFAULT_PORT: u16 = 8789;
+
+/// DNS-SD service type companions browse for.
+const SERVICE_TYPE: &str = "_screenrec._tcp.local";
+
+/// The running server and mDNS responder, if any.
+static SERVER: Mutex<Vec<tauri::async_runtime::JoinHandle<()>>> = Mutex::new(Vec::new());
+
+/// Companion server settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompanionSettings {
+    /// Serve the companion API while the app is open
+    pub enabled: bool,
+    /// TCP port on every interface
+    pub port: u16,
+    /// Secret companions must send with every request
+    pub token: String,
+    /// Name companions show for this machine; the host name if empty
+    pub name: String,
+}
+
+impl Default for CompanionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: DEFAULT_PORT,
+            token: String::new(),
+            name: String::new(),
+        }
+    }
+}
+
+impl CompanionSettings {
+    /// Load the saved settings, making a token on first use.
+    pub fn load() -> Self {
+        let mut settings: Self = load_json(SETTINGS_FILE);
+        if settings.token.is_empty() {
+            settings.token = control::new_token();
+            if let Err(e) = save_json(SETTINGS_FILE, &settings) {
+                tracing::error!(target: "Companion", "Failed to save the token: {}", e);
+            }
+        }
+        settings
+    }
+
+    /// Check and save the settings.
+    pub fn save(&self) -> Result<(), String> {
+        if self.port == 0 {
+            return Err("The companion server needs a port".to_string());
+        }
+        if self.token.is_empty() {
+            return Err("The companion server needs a token".to_string());
+        }
+        save_json(SETTINGS_FILE, self)
+    }
+
+    /// Name advertised for this machine.
+    fn display_name(&self) -> String {
+        match self.name.trim() {
+            "" => format!("Screen Recorder on {}", host_name()),
+            name => name.to_string(),
+        }
+    }
+}
+
+/// The companion settings with where to reach the server.
+#[derive(Debug, Clone, Serialize)]
+pub struct CompanionInfo {
+    #[serde(flatten)]
+    pub settings: CompanionSettings,
+    /// The remote control page, token included, for opening on a phone
+    pub url: String,
+}
+
+impl From<CompanionSettings> for CompanionInfo {
+    fn from(settings: CompanionSettings) -> Self {
+        let url = remote_url(http::local_ip(), &settings);
+        Self { settings, url }
+    }
+}
+
+/// Address of the remote control page at `ip`.
+fn remote_url(ip: IpAddr, settings: &CompanionSettings) -> String {
+    format!("http://{}:{}/remote?token={}", ip, settings.port, settings.token)
+}
+
+/// Start or stop the server and its mDNS advertisement to match
+/// `settings`, replacing any running server. Stopping the server
+/// disconnects its clients, so those still on a replaced token lose access.
+pub fn apply(app: &AppHandle, settings: &CompanionSettings) -> Result<(), String> {
+    let mut server = SERVER.lock().unwrap();
+    server.drain(..).for_each(|task| task.abort());
+    if !settings.enabled {
+        return Ok(());
+    }
+
+    let listener = std::net::TcpListener::bind(("0.0.0.0", settings.port))
+        .map_err(|e| format!("Failed to bind the companion server to port {}: {}", settings.port, e))?;
+    listener
+        .set_nonblocking(true)
+        .map_err(|e| format!("Failed to configure the companion server socket: {}", e))?;
+    let ip = http::local_ip();
+    tracing::info!(target: "Companion", "Listening on http://{}:{}/remote", ip, settings.port);
+    server.push(tauri::async_runtime::spawn(control::serve(app.clone(), listener, settings.token.clone())));
+
+    // The responder only answers with IPv4 addresses, and other devices
+    // can't reach this one's loopback address
+    let ip = match ip {
+        IpAddr::V4(ip) if !ip.is_loopback() => ip,
+        _ => {
+            tracing::warn!(target: "Companion", "No network address to advertise; not advertising over mDNS");
+            return Ok(());
+        }
+    };
+    let service = Service {
+        service_type: SERVICE_TYPE.to_string(),
+        instance: settings.display_name(),
+        host: format!("screen-recorder-{}.local", dns_label(&host_name())),
+        ip,
+        port: settings.port,
+        txt: vec![
+            format!("version={}", env!("CARGO_PKG_VERSION")),
+            "path=/remote".to_string(),
+        ],
+    };
+    server.push(tauri::async_runtime::spawn(async move {
+        if let Err(e) = mdns::advertise(service).await {
+            tracing::error!(target: "Companion", "{}", e);
+        }
+    }));
+    Ok(())
+}
+
+/// This machine's name, as best it can be found.
+fn host_name() -> String {
+    let from_env = std::env::var("COMPUTERNAME").or_else(|_| std::env::var("HOSTNAME")).ok();
+    let from_file = || std::fs::read_to_string("/proc/sys/kernel/hostname").ok();
+    let from_command = || {
+        std::process::Command::new("hostname")
+            .output()
+            .ok()
+            .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+    from_env
+        .or_else(from_file)
+        .or_else(from_command)
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "computer".to_string())
+}
+
+/// `name` as a DNS label: lowercase letters, digits and hyphens.
+fn dns_label(name: &str) -> String {
+    let name = name.split('.').next().unwrap_or_default();
+    let label: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .take(40)
+        .collect();
+    match label.trim_matches('-') {
+        "" => "computer".to_string(),
+        label => label.to_string(),
+    }
+}
+
+/// The remote control page. The token comes from the page's URL and is
+/// sent as a bearer token with every call.
+pub const REMOTE_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Screen Recorder</title>
+<style>
+  body { font-family: system-ui, sans-serif; margin: 0; padding: 1.5rem; background: #111; color: #eee; }
+  h1 { font-size: 1.2rem; margin: 0 0 1rem; }
+  #status { font-size: 2rem; margin-bottom: 1rem; }
+  select, button { width: 100%; font-size: 1.2rem; padding: 0.8rem; margin-bottom: 0.6rem; border-radius: 0.5rem; border: 0; }
+  button { background: #333; color: #eee; }
+  button.record { background: #c62828; }
+  #error { color: #ef9a9a; min-height: 1.5rem; }
+</style>
+</head>
+<body>
+<h1>Screen Recorder</h1>
+<div id="status">Connecting…</div>
+<select id="monitor"></select>
+<button class="record" onclick="call('/start?monitor=' + encodeURIComponent(monitor.value))">Start</button>
+<button onclick="call('/pause')">Pause</button>
+<button onclick="call('/resume')">Resume</button>
+<button onclick="call('/marker')">Marker</button>
+<button onclick="call('/stop')">Stop</button>
+<div id="error"></div>
+<script>
+  const token = new URLSearchParams(location.search).get('token') || '';
+  const headers = { Authorization: 'Bearer ' + token };
+  const status = document.getElementById('status');
+  const monitor = document.getElementById('monitor');
+  const error = document.getElementById('error');
+
+  async function api(path, method) {
+    const response = await fetch(path, { method, headers });
+    const body = await response.json();
+    if (!response.ok) throw new Error(body.error || response.statusText);
+    return body;
+  }
+
+  async function call(path) {
+    try {
+      await api(path, 'POST');
+      error.textContent = '';
+      refresh();
+    } catch (e) {
+      error.textContent = e.message;
+    }
+  }
+
+  function clock(seconds) {
+    const s = Math.floor(seconds);
+    return Math.floor(s / 60) + ':' + String(s % 60).padStart(2, '0');
+  }
+
+  async function refresh() {
+    try {
+      const state = await api('/status', 'GET');
+      const phase = state.phase.phase;
+      const active = phase === 'recording' || phase === 'paused';
+      status.textContent = phase + (active ? ' ' + clock(state.elapsed_seconds) : '');
+    } catch (e) {
+      status.textContent = 'Disconnected';
+      error.textContent = e.message;
+    }
+  }
+
+  api('/sources', 'GET').then((sources) => {
+    for (const m of sources.monitors) {
+      const option = document.createElement('option');
+      option.value = m.id;
+      option.textContent = m.name + ' (' + m.width + 'x' + m.height + ')';
+      monitor.appendChild(option);
+    }
+  }).catch((e) => { error.textContent = e.message; });
+  refresh();
+  setInterval(refresh, 1000);
+</script>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_companion_names() {
+        assert_eq!(dns_label("Sam's MacBook Pro.local"), "sam-s-macbook-pro");
+        assert_eq!(dns_label("..."), "computer");
+        let settings = CompanionSettings {
+            token: "abc".to_string(),
+            name: " Studio ".to_string(),
+            ..CompanionSettings::default()
+        };
+        assert_eq!(settings.display_name(), "Studio");
+        assert_eq!(
+            remote_url(IpAddr::from([192, 168, 1, 20]), &settings),
+            "http://192.168.1.20:8789/remote?token=abc"
+        );
+    }
+}
@@ -0,0 +1,52 @@
+//! Application data directory and JSON persistence helpers.
+
+use directories::ProjectDirs;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// Get the per-user application data directory, creating it if needed.
+pub fn data_dir() -> Result<PathBuf, String> {
+    let dirs = ProjectDirs::from("com", "keath", "screen-recorder")
+        .ok_or("Could not determine application data directory")?;
+    let dir = dirs.data_dir().to_path_buf();
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create data directory {}: {}", dir.display(), e))?;
+    Ok(dir)
+}
+
+/// Load a JSON file from the data directory.
+///
+/// Returns the default value if the file doesn't exist or can't be parsed.
+pub fn load_json<T: DeserializeOwned + Default>(file_name: &str) -> T {
+    let path = match data_dir() {
+        Ok(dir) => dir.join(file_name),
+        Err(e) => {
+            tracing::error!(target: "Config", "{}", e);
+            return T::default();
+        }
+    };
+
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+            tracing::warn!(target: "Config", "Ignoring invalid {}: {}", path.display(), e);
+            T::default()
+        }),
+        Err(_) => T::default(),
+    }
+}
+
+/// Save a value as JSON in the data directory.
+///
+/// Writes to a temporary file first so a crash never leaves a truncated file.
+pub fn save_json<T: Serialize>(file_name: &str, value: &T) -> Result<(), String> {
+    let path = data_dir()?.join(file_name);
+    let tmp_path = path.with_extension("json.tmp");
+
+    let contents = serde_json::to_string_pretty(value)
+        .map_err(|e| format!("Failed to serialize {}: {}", file_name, e))?;
+    std::fs::write(&tmp_path, contents)
+        .map_err(|e| format!("Failed to write {}: {}", tmp_path.display(), e))?;
+    std::fs::rename(&tmp_path, &path)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
@@ -0,0 +1,358 @@
+//! Local HTTP and WebSocket API for controlling the recorder from outside
+//! the app, e.g. from Stream Deck plugins, scripts and test harnesses.
+//!
+//! The server only listens on the loopback interface, and every request must
+//! carry the API token, as an `Authorization: Bearer` header or a `token`
+//! query parameter (for WebSocket clients that can't set headers):
+//!
+//! - `GET /status`: the recording phase, elapsed seconds and live statistics
+//! - `GET /sources`: the monitors and windows that can be recorded
+//! - `POST /start?monitor=ID` or `POST /start?window=HANDLE`: start recording
+//! - `POST /stop`, `POST /pause`, `POST /resume`
+//! - `POST /marker?label=TEXT`: add a chapter marker
+//! - `GET /events`: a WebSocket receiving the app's recording events as
+//!   `{"event": name, "payload": ...}` text messages
+//! - `GET /metrics`: Prometheus metrics, when built with the `metrics`
+//!   feature (see `metrics`)
+//! - `GET /remote`: a page with the controls, for phones (see `companion`);
+//!   it's served without the token and asks for it in its own URL
+//!
+//! Responses are JSON; failures are `{"error": message}`.
+
+use crate::capture::{list_monitors, list_windows};
+use crate::config::{load_json, save_json};
+use crate::http::{self, Request};
+use crate::state::RecordingPhase;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Mutex;
+use subtle::ConstantTimeEq;
+use tauri::{AppHandle, Manager};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+
+const SETTINGS_FILE: &str = "control_api.json";
+
+/// Default port for the control API.
+pub const DEFAULT_PORT: u16 = 8788;
+
+/// The running server, if any.
+static SERVER: Mutex<Option<tauri::async_runtime::JoinHandle<()>>> = Mutex::new(None);
+
+/// Control API settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControlSettings {
+    /// Serve the API while the app is open
+    pub enabled: bool,
+    /// TCP port on the loopback interface
+    pub port: u16,
+    /// Secret clients must send with every request
+    pub token: String,
+}
+
+impl Default for ControlSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: DEFAULT_PORT,
+            token: String::new(),
+        }
+    }
+}
+
+impl ControlSettings {
+    /// Load the saved settings, making a token on first use.
+    pub fn load() -> Self {
+        let mut settings: Self = load_json(SETTINGS_FILE);
+        if settings.token.is_empty() {
+            settings.token = new_token();
+            if let Err(e) = save_json(SETTINGS_FILE, &settings) {
+                tracing::error!(target: "Control", "Failed to save the API token: {}", e);
+            }
+        }
+        settings
+    }
+
+    /// Check and save the settings.
+    pub fn save(&self) -> Result<(), String> {
+        if self.port == 0 {
+            return Err("The control API needs a port".to_string());
+        }
+        if self.token.is_empty() {
+            return Err("The control API needs a token".to_string());
+        }
+        save_json(SETTINGS_FILE, self)
+    }
+}
+
+/// A new random API token.
+pub fn new_token() -> String {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).expect("Failed to read random bytes");
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Start or stop the server to match `settings`, replacing any running
+/// server.
+pub fn apply(app: &AppHandle, settings: ControlSettings) -> Result<(), String> {
+    let mut server = SERVER.lock().unwrap();
+    if let Some(running) = server.take() {
+        running.abort();
+    }
+    if !settings.enabled {
+        return Ok(());
+    }
+
+    let listener = std::net::TcpListener::bind(("127.0.0.1", settings.port))
+        .map_err(|e| format!("Failed to bind the control API to port {}: {}", settings.port, e))?;
+    listener
+        .set_nonblocking(true)
+        .map_err(|e| format!("Failed to configure the control API socket: {}", e))?;
+    tracing::info!(target: "Control", "Listening on http://127.0.0.1:{}/", settings.port);
+    *server = Some(tauri::async_runtime::spawn(serve(app.clone(), listener, settings.token)));
+    Ok(())
+}
+
+/// Accept clients until the server is aborted. Aborting it also disconnects
+/// its clients, so `/events` streams don't outlive a changed token.
+pub async fn serve(app: AppHandle, listener: std::net::TcpListener, token: String) {
+    let listener = match TcpListener::from_std(listener) {
+        Ok(listener) => listener,
+        Err(e) => {
+            tracing::error!(target: "Control", "Failed to start: {}", e);
+            return;
+        }
+    };
+    // Dropped with this task, which aborts every client
+    let mut clients = JoinSet::new();
+    let limit = http::client_limit();
+    loop {
+        let accepted = tokio::select! {
+            accepted = http::accept(&listener, &limit) => accepted,
+            Some(_) = clients.join_next() => continue,
+        };
+        match accepted {
+            Ok((stream, permit)) => {
+                let app = app.clone();
+                let token = token.clone();
+                clients.spawn(async move {
+                    let _permit = permit;
+                    if let Err(e) = handle_client(stream, app, &token).await {
+                        tracing::warn!(target: "Control", "Client error: {}", e);
+                    }
+                });
+            }
+            Err(e) => {
+                tracing::error!(target: "Control", "Accept error: {}", e);
+            }
+        }
+    }
+}
+
+/// Whether `request` carries `token`.
+fn authorized(request: &Request, token: &str) -> bool {
+    let bearer = request
+        .header("authorization")
+        .and_then(|value| value.strip_prefix("Bearer "));
+    bearer
+        .or(request.query("token"))
+        .is_some_and(|given| bool::from(given.as_bytes().ct_eq(token.as_bytes())))
+}
+
+/// Serve a single request.
+async fn handle_client(mut stream: TcpStream, app: AppHandle, token: &str) -> std::io::Result<()> {
+    let Some(request) = http::read_request(&mut stream).await? else {
+        return Ok(());
+    };
+
+    if request.method == "GET" && request.path == "/remote" {
+        let page = crate::companion::REMOTE_PAGE.as_bytes();
+        return http::write_response(&mut stream, 200, "text/html; charset=utf-8", &[], page).await;
+    }
+
+    #[cfg(feature = "metrics")]
+    if request.path == "/metrics" && authorized(&request, token) {
+        let body = metrics(&app).await;
+        return http::write_response(&mut stream, 200, "text/plain; version=0.0.4", &[], body.as_bytes()).await;
+    }
+
+    let (status, body) = if !authorized(&request, token) {
+        (401, json!({ "error": "Missing or wrong API token" }))
+    } else if request.path == "/events" && request.is_websocket() {
+        http::accept_websocket(&mut stream, &request).await?;
+        return stream_events(stream, app).await;
+    } else {
+        match route(&request, &app).await {
+            Ok(body) => (200, body),
+            Err((status, error)) => (status, json!({ "error": error })),
+        }
+    };
+    http::write_response(&mut stream, status, "application/json", &[], body.to_string().as_bytes()).await
+}
+
+/// Run the request's command, returning its JSON result or a status and
+/// error message.
+async fn route(request: &Request, app: &AppHandle) -> Result<Value, (u16, String)> {
+    let state = app.state::<AppState>();
+    let failed = |e: String| (409, e);
+
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/status") => {
+            let manager = state.recording_manager.lock().await;
+            let phase = manager.get_phase();
+            let stats = match phase {
+                RecordingPhase::Recording | RecordingPhase::Paused => manager.get_stats().await.ok(),
+                _ => None,
+            };
+            Ok(json!({
+                "phase": phase,
+                "elapsed_seconds": manager.get_elapsed_seconds().await,
+                "stats": stats,
+            }))
+        }
+        ("GET", "/sources") => Ok(json!({ "monitors": list_monitors(), "windows": list_windows() })),
+        ("POST", "/start") => {
+            if let Some(monitor) = request.query("monitor") {
+                crate::start_display_recording(monitor.to_string(), app.clone(), state)
+                    .await
+                    .map_err(|e| failed(e.to_string()))?;
+            } else if let Some(window) = request.query("window") {
+                let handle = window.parse().map_err(|_| (400, format!("Invalid window handle: {}", window)))?;
+                crate::start_recording(handle, None, app.clone(), state)
+                    .await
+                    .map_err(|e| failed(e.to_string()))?;
+            } else {
+                return Err((400, "Give a monitor or window to record".to_string()));
+            }
+            Ok(json!({}))
+        }
+        ("POST", "/stop") => to_json(crate::stop_recording(state).await.map_err(failed)?),
+        ("POST", "/pause") => {
+            state.recording_manager.lock().await.pause_recording().await.map_err(failed)?;
+            Ok(json!({}))
+        }
+        ("POST", "/resume") => {
+            state.recording_manager.lock().await.resume_recording().await.map_err(failed)?;
+            Ok(json!({}))
+        }
+        ("POST", "/marker") => {
+            let label = request.query("label").map(str::to_string);
+            to_json(crate::add_marker(label, state).await.map_err(failed)?)
+        }
+        (_, "/status" | "/sources" | "/start" | "/stop" | "/pause" | "/resume" | "/marker" | "/events") => {
+            Err((405, "Method not allowed".to_string()))
+        }
+        _ => Err((404, "Not found".to_string())),
+    }
+}
+
+/// The recorder's metrics in Prometheus' format.
+#[cfg(feature = "metrics")]
+async fn metrics(app: &AppHandle) -> String {
+    let (phase, stats) = {
+        let manager = app.state::<AppState>().recording_manager.clone();
+        let manager = manager.lock().await;
+        let phase = manager.get_phase();
+        let stats = match phase {
+            RecordingPhase::Recording | RecordingPhase::Paused => manager.get_stats().await.ok(),
+            _ => None,
+        };
+        (phase, stats)
+    };
+    let disk_free_bytes = crate::encoder::default_output_dir()
+        .ok()
+        .and_then(|dir| crate::disk::free_space(&dir).ok());
+    crate::metrics::render(&crate::metrics::Snapshot { phase, stats, disk_free_bytes })
+}
+
+/// A command's result as JSON.
+fn to_json<T: Serialize>(value: T) -> Result<Value, (u16, String)> {
+    serde_json::to_value(value).map_err(|e| (500, e.to_string()))
+}
+
+/// Send the recorder's events to a WebSocket client until it disconnects.
+async fn stream_events(stream: TcpStream, app: AppHandle) -> std::io::Result<()> {
+    use http::opcode;
+    use tokio::sync::broadcast::error::RecvError;
+
+    let manager = app.state::<AppState>().recording_manager.clone();
+    let (mut phases, mut limits, mut disk) = {
+        let manager = manager.lock().await;
+        (
+            manager.subscribe_phase_events(),
+            manager.subscribe_limit_events(),
+            manager.subscribe_disk_events(),
+        )
+    };
+    let (mut reader, mut writer) = stream.into_split();
+    // Frames are read on a task of their own so that waiting for one
+    // doesn't hold up events, and stopped with the stream
+    let (frames, mut received) = tokio::sync::mpsc::channel(8);
+    let mut reading = JoinSet::new();
+    reading.spawn(async move {
+        while let Ok(frame) = http::read_websocket_frame(&mut reader).await {
+            if frames.send(frame).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    loop {
+        let event = tokio::select! {
+            frame = received.recv() => match frame {
+                Some((opcode::PING, payload)) => {
+                    http::write_websocket_frame(&mut writer, opcode::PONG, &payload).await?;
+                    continue;
+                }
+                // Messages from the client are ignored
+                Some((opcode, _)) if opcode != opcode::CLOSE => continue,
+                _ => {
+                    let _ = http::write_websocket_frame(&mut writer, opcode::CLOSE, &[]).await;
+                    return Ok(());
+                }
+            },
+            phase = phases.recv() => phase.map(|p| message("recording-phase", &p)),
+            limit = limits.recv() => limit.map(|l| message("recording-limit", &l)),
+            space = disk.recv() => space.map(|d| message("disk-space", &d)),
+        };
+        match event {
+            Ok(text) => http::write_websocket_frame(&mut writer, opcode::TEXT, text.as_bytes()).await?,
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return Ok(()),
+        }
+    }
+}
+
+/// An event as a WebSocket message.
+fn message<T: Serialize>(event: &str, payload: &T) -> String {
+    json!({ "event": event, "payload": payload }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(headers: &[(&str, &str)], query: &[(&str, &str)]) -> Request {
+        let pairs = |pairs: &[(&str, &str)]| pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Request {
+            method: "GET".to_string(),
+            path: "/status".to_string(),
+            query: pairs(query),
+            headers: pairs(headers),
+        }
+    }
+
+    #[test]
+    fn test_authorized() {
+        let token = new_token();
+        assert_eq!(token.len(), 32);
+        let bearer = format!("Bearer {}", token);
+        assert!(authorized(&request(&[("authorization", &bearer)], &[]), &token));
+        assert!(authorized(&request(&[], &[("token", &token)]), &token));
+        assert!(!authorized(&request(&[], &[]), &token));
+        assert!(!authorized(&request(&[("authorization", "Bearer nope")], &[]), &token));
+        assert!(!authorized(&request(&[("authorization", &bearer[..20])], &[]), &token));
+    }
+}
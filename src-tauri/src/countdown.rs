@@ -0,0 +1,104 @@
+//! Countdown before recordings start.
+//!
+//! Recordings started from the app count down first, so they don't open
+//! with the user arranging windows. Each second is published as a
+//! `countdown` event, which the frontend shows in an overlay; capture only
+//! starts once the countdown completes, and cancelling it means the
+//! recording never starts.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::Duration;
+
+/// Event published for each second of the countdown.
+pub const COUNTDOWN_EVENT: &str = "countdown";
+
+/// Longest countdown allowed, in seconds.
+const MAX_SECONDS: u32 = 10;
+
+/// Time for the overlay to close before capture starts, so it isn't recorded.
+const OVERLAY_CLOSE_DELAY: Duration = Duration::from_millis(200);
+
+static SECONDS: AtomicU32 = AtomicU32::new(3);
+static RUNNING: AtomicBool = AtomicBool::new(false);
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// A second of the countdown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CountdownEvent {
+    /// Seconds until recording starts; 0 when it's starting
+    pub remaining: u32,
+    /// The countdown was cancelled and the recording won't start
+    pub cancelled: bool,
+}
+
+/// Set the countdown length in seconds; 0 starts recordings immediately.
+pub fn set_seconds(seconds: u32) -> Result<(), String> {
+    if seconds > MAX_SECONDS {
+        return Err(format!("The countdown can be at most {} seconds", MAX_SECONDS));
+    }
+    SECONDS.store(seconds, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Countdown length in seconds.
+pub fn seconds() -> u32 {
+    SECONDS.load(Ordering::Relaxed)
+}
+
+/// Cancel the running countdown, if any.
+pub fn cancel() {
+    if RUNNING.load(Ordering::Relaxed) {
+        CANCELLED.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Count down, passing each second to `on_tick`. Returns an error if the
+/// countdown is cancelled or another one is already running.
+pub async fn run(mut on_tick: impl FnMut(CountdownEvent)) -> Result<(), String> {
+    let seconds = seconds();
+    if seconds == 0 {
+        return Ok(());
+    }
+    if RUNNING.swap(true, Ordering::Relaxed) {
+        return Err("A recording is already counting down".to_string());
+    }
+    CANCELLED.store(false, Ordering::Relaxed);
+
+    let mut result = Ok(());
+    for remaining in (1..=seconds).rev() {
+        on_tick(CountdownEvent { remaining, cancelled: false });
+        // Check often so cancelling feels immediate
+        for _ in 0..10 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            if CANCELLED.load(Ordering::Relaxed) {
+                result = Err("Countdown cancelled".to_string());
+                break;
+            }
+        }
+        if result.is_err() {
+            break;
+        }
+    }
+
+    on_tick(CountdownEvent {
+        remaining: 0,
+        cancelled: result.is_err(),
+    });
+    RUNNING.store(false, Ordering::Relaxed);
+    if result.is_ok() {
+        tokio::time::sleep(OVERLAY_CLOSE_DELAY).await;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_seconds() {
+        assert!(set_seconds(MAX_SECONDS + 1).is_err());
+        assert_eq!(seconds(), 3);
+    }
+}
@@ -0,0 +1,145 @@
+//! D-Bus control interface on the session bus (Linux).
+//!
+//! The app owns `org.screenrecorder.Control` and serves an object at
+//! `/org/screenrecorder/Control`, so GNOME extensions, keybinding daemons and
+//! the picker service can drive it without going through the UI:
+//!
+//! - `StartRecording(s monitor_id)` records a display; an empty id records
+//!   the primary one
+//! - `StopRecording() -> s` stops and returns the saved file's path
+//! - `PauseRecording()` and `ResumeRecording()`
+//! - `GetStatus() -> (s phase, t elapsed_seconds)`
+//! - the `StateChanged(s phase, s detail)` signal, sent on every phase change
+//!   with the file path of a finished recording or the error of a failed one
+//!
+//! Phases are named as in the `recording-phase` event.
+
+use crate::capture::list_monitors;
+use crate::state::RecordingPhase;
+use crate::AppState;
+use tauri::{AppHandle, Manager};
+use zbus::fdo;
+use zbus::object_server::SignalContext;
+
+const BUS_NAME: &str = "org.screenrecorder.Control";
+const OBJECT_PATH: &str = "/org/screenrecorder/Control";
+
+struct Control {
+    app: AppHandle,
+}
+
+#[zbus::interface(name = "org.screenrecorder.Control")]
+impl Control {
+    async fn start_recording(&self, monitor_id: String) -> fdo::Result<()> {
+        let monitor_id = if monitor_id.is_empty() {
+            let monitors = list_monitors();
+            let primary = monitors.iter().find(|m| m.is_primary).or(monitors.first());
+            primary
+                .map(|m| m.id.clone())
+                .ok_or_else(|| fdo::Error::Failed("No monitors found".to_string()))?
+        } else {
+            monitor_id
+        };
+        crate::start_display_recording(monitor_id, self.app.clone(), self.app.state())
+            .await
+            .map_err(|e| fdo::Error::Failed(e.to_string()))
+    }
+
+    async fn stop_recording(&self) -> fdo::Result<String> {
+        let result = crate::stop_recording(self.app.state()).await.map_err(fdo::Error::Failed)?;
+        Ok(result.file_path.unwrap_or_default())
+    }
+
+    async fn pause_recording(&self) -> fdo::Result<()> {
+        let manager = self.app.state::<AppState>().recording_manager.clone();
+        let manager = manager.lock().await;
+        manager.pause_recording().await.map_err(fdo::Error::Failed)
+    }
+
+    async fn resume_recording(&self) -> fdo::Result<()> {
+        let manager = self.app.state::<AppState>().recording_manager.clone();
+        let manager = manager.lock().await;
+        manager.resume_recording().await.map_err(fdo::Error::Failed)
+    }
+
+    async fn get_status(&self) -> (String, u64) {
+        let manager = self.app.state::<AppState>().recording_manager.clone();
+        let manager = manager.lock().await;
+        (manager.get_phase().name().to_string(), manager.get_elapsed_seconds().await)
+    }
+
+    #[zbus(signal)]
+    async fn state_changed(context: &SignalContext<'_>, phase: &str, detail: &str) -> zbus::Result<()>;
+}
+
+/// The `StateChanged` detail for `phase`.
+fn detail(phase: &RecordingPhase) -> &str {
+    match phase {
+        RecordingPhase::Done { file_path, .. } => file_path.as_deref().unwrap_or_default(),
+        RecordingPhase::Failed { error } => error,
+        _ => "",
+    }
+}
+
+/// Connect to the session bus, owning the name and serving the interface.
+async fn connect(app: AppHandle) -> zbus::Result<zbus::Connection> {
+    zbus::connection::Builder::session()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, Control { app })?
+        .build()
+        .await
+}
+
+/// Serve the control interface and signal phase changes until the app
+/// exits. Without a session bus, or with another instance owning the name,
+/// there's no interface.
+pub async fn run(app: AppHandle) {
+    let manager = app.state::<AppState>().recording_manager.clone();
+    let mut phases = manager.lock().await.subscribe_phase_events();
+
+    let connection = match connect(app.clone()).await {
+        Ok(connection) => connection,
+        Err(e) => {
+            tracing::error!(target: "DBus", "Failed to serve {}: {}", BUS_NAME, e);
+            return;
+        }
+    };
+    let interface = match connection.object_server().interface::<_, Control>(OBJECT_PATH).await {
+        Ok(interface) => interface,
+        Err(e) => {
+            tracing::error!(target: "DBus", "{}", e);
+            return;
+        }
+    };
+    tracing::info!(target: "DBus", "Serving {}", BUS_NAME);
+
+    loop {
+        use tokio::sync::broadcast::error::RecvError;
+
+        match phases.recv().await {
+            Ok(phase) => {
+                let context = interface.signal_context();
+                if let Err(e) = Control::state_changed(context, phase.name(), detail(&phase)).await {
+                    tracing::error!(target: "DBus", "Failed to signal {}: {}", phase.name(), e);
+                }
+            }
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detail() {
+        let done = RecordingPhase::Done {
+            file_path: Some("/tmp/a.mp4".to_string()),
+            segments: Vec::new(),
+        };
+        assert_eq!(detail(&done), "/tmp/a.mp4");
+        assert_eq!(detail(&RecordingPhase::Recording), "");
+    }
+}
@@ -0,0 +1,287 @@
+//! Video input devices: webcams and capture cards.
+//!
+//! Devices are listed through FFmpeg's platform input devices (DirectShow on
+//! Windows, AVFoundation on macOS, Video4Linux2 on Linux), so every device
+//! listed here can be opened by FFmpeg by its `id`.
+
+use crate::encoder::ffmpeg::ffmpeg_stderr;
+use serde::{Deserialize, Serialize};
+
+/// A video input device.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VideoDevice {
+    /// What FFmpeg opens the device by: the DirectShow device path, the
+    /// AVFoundation index, or the V4L2 device node
+    pub id: String,
+    /// Human-readable name, such as "Logitech BRIO" or "Cam Link 4K"
+    pub name: String,
+    /// Resolutions and formats the device can deliver; may be empty if the
+    /// device couldn't be queried
+    pub modes: Vec<VideoMode>,
+}
+
+/// A resolution and format a device can deliver.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VideoMode {
+    pub width: u32,
+    pub height: u32,
+    /// Pixel format or compression, such as "yuyv422", "nv12" or "mjpeg"
+    pub format: String,
+    /// Highest frame rate at this resolution, where the platform reports it
+    pub max_fps: Option<f64>,
+}
+
+/// List the video input devices connected to this machine.
+///
+/// Runs FFmpeg once per device to read its modes; call it from a blocking
+/// task.
+pub fn list_video_devices() -> Result<Vec<VideoDevice>, String> {
+    #[cfg(target_os = "windows")]
+    {
+        let stderr = ffmpeg_stderr(["-list_devices", "true", "-f", "dshow", "-i", "dummy"])?;
+        Ok(parse_dshow_devices(&stderr, "video")
+            .into_iter()
+            .map(|(id, name)| {
+                let modes = ffmpeg_stderr(["-list_options", "true", "-f", "dshow", "-i", &format!("video={}", id)])
+                    .map(|stderr| parse_dshow_modes(&stderr))
+                    .unwrap_or_default();
+                VideoDevice { id, name, modes }
+            })
+            .collect())
+    }
+    #[cfg(target_os = "macos")]
+    {
+        let stderr = ffmpeg_stderr(["-f", "avfoundation", "-list_devices", "true", "-i", ""])?;
+        Ok(parse_avfoundation_devices(&stderr)
+            .into_iter()
+            .map(|(id, name)| {
+                // Asking for an impossible size makes FFmpeg list the supported modes
+                let modes = ffmpeg_stderr(["-f", "avfoundation", "-video_size", "1x1", "-i", &id])
+                    .map(|stderr| parse_avfoundation_modes(&stderr))
+                    .unwrap_or_default();
+                VideoDevice { id, name, modes }
+            })
+            .collect())
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let entries = std::fs::read_dir("/sys/class/video4linux")
+            .map_err(|e| format!("Failed to list video devices: {}", e))?;
+        let mut devices: Vec<VideoDevice> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let node = entry.file_name().to_string_lossy().to_string();
+                let name = std::fs::read_to_string(entry.path().join("name")).ok()?.trim().to_string();
+                let id = format!("/dev/{}", node);
+                let modes = ffmpeg_stderr(["-f", "v4l2", "-list_formats", "all", "-i", &id])
+                    .map(|stderr| parse_v4l2_modes(&stderr))
+                    .unwrap_or_default();
+                // Drivers also expose metadata nodes, which have no video formats
+                (!modes.is_empty()).then_some(VideoDevice { id, name, modes })
+            })
+            .collect();
+        devices.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(devices)
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+    Err("Video devices are not supported on this platform".to_string())
+}
+
+/// FFmpeg input arguments opening the device `id` at `width`x`height`.
+pub fn input_args(id: &str, width: u32, height: u32) -> Vec<String> {
+    let size = format!("{}x{}", width, height);
+    #[cfg(target_os = "windows")]
+    let args = ["-f", "dshow", "-video_size", &size, "-i", &format!("video={}", id)];
+    // AVFoundation fails to open cameras without a frame rate they support
+    #[cfg(target_os = "macos")]
+    let args = ["-f", "avfoundation", "-framerate", "30", "-video_size", &size, "-i", id];
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let args = ["-f", "v4l2", "-video_size", &size, "-i", id];
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+/// Strip the `[dshow @ 0x...]` style prefix FFmpeg puts on device log lines.
+fn log_message(line: &str) -> &str {
+    match line.trim_start().strip_prefix('[') {
+        Some(rest) => rest.split_once(']').map_or(line, |(_, message)| message),
+        None => line,
+    }
+}
+
+/// Devices of `kind` ("video" or "audio") in `ffmpeg -list_devices true -f
+/// dshow` output, as (device path, name). The path is unique even for
+/// identical cameras.
+#[cfg_attr(not(any(target_os = "windows", test)), allow(dead_code))]
+pub(crate) fn parse_dshow_devices(stderr: &str, kind: &str) -> Vec<(String, String)> {
+    let suffix = format!("({})", kind);
+    let mut devices = Vec::new();
+    let mut lines = stderr.lines().map(log_message).peekable();
+    while let Some(line) = lines.next() {
+        let Some(name) = line.trim().strip_suffix(suffix.as_str()).and_then(|n| n.trim().strip_prefix('"')) else {
+            continue;
+        };
+        let name = name.trim_end_matches('"').to_string();
+        let id = lines
+            .peek()
+            .and_then(|next| next.trim().strip_prefix("Alternative name"))
+            .map(|alternative| alternative.trim().trim_matches('"').to_string())
+            .unwrap_or_else(|| name.clone());
+        devices.push((id, name));
+    }
+    devices
+}
+
+/// Modes in `ffmpeg -list_options true -f dshow` output, such as
+/// `pixel_format=yuyv422  min s=640x480 fps=5 max s=640x480 fps=30`.
+#[cfg_attr(not(any(target_os = "windows", test)), allow(dead_code))]
+fn parse_dshow_modes(stderr: &str) -> Vec<VideoMode> {
+    let mut modes = Vec::new();
+    for line in stderr.lines().map(log_message) {
+        let mut tokens = line.split_whitespace();
+        let Some(format) = tokens
+            .next()
+            .and_then(|t| t.strip_prefix("pixel_format=").or_else(|| t.strip_prefix("vcodec=")))
+        else {
+            continue;
+        };
+        let Some((_, max)) = line.split_once(" max ") else {
+            continue;
+        };
+        let size = max.split_whitespace().find_map(|t| t.strip_prefix("s=")).and_then(parse_size);
+        let fps = max.split_whitespace().find_map(|t| t.strip_prefix("fps=")).and_then(|f| f.parse().ok());
+        if let Some((width, height)) = size {
+            let mode = VideoMode { width, height, format: format.to_string(), max_fps: fps };
+            if !modes.contains(&mode) {
+                modes.push(mode);
+            }
+        }
+    }
+    modes
+}
+
+/// Cameras in `ffmpeg -f avfoundation -list_devices true` output, as
+/// (index, name). Screens, which AVFoundation lists too, are left out.
+#[cfg_attr(not(any(target_os = "macos", test)), allow(dead_code))]
+fn parse_avfoundation_devices(stderr: &str) -> Vec<(String, String)> {
+    let mut devices = Vec::new();
+    let mut in_video = false;
+    for line in stderr.lines().map(log_message) {
+        let line = line.trim();
+        if line.ends_with("video devices:") {
+            in_video = true;
+        } else if line.ends_with("audio devices:") {
+            in_video = false;
+        } else if let (true, Some((index, name))) = (in_video, line.strip_prefix('[').and_then(|l| l.split_once(']'))) {
+            let name = name.trim();
+            if !name.starts_with("Capture screen") {
+                devices.push((index.to_string(), name.to_string()));
+            }
+        }
+    }
+    devices
+}
+
+/// Modes AVFoundation lists when asked for an unsupported size, such as
+/// `1280x720@[1.000000 30.000000]fps`.
+#[cfg_attr(not(any(target_os = "macos", test)), allow(dead_code))]
+fn parse_avfoundation_modes(stderr: &str) -> Vec<VideoMode> {
+    let mut modes = Vec::new();
+    for line in stderr.lines().map(log_message) {
+        let Some((size, rates)) = line.trim().split_once("@[") else {
+            continue;
+        };
+        let max_fps = rates
+            .trim_end_matches("]fps")
+            .split_whitespace()
+            .filter_map(|rate| rate.parse::<f64>().ok())
+            .reduce(f64::max);
+        if let Some((width, height)) = parse_size(size) {
+            modes.push(VideoMode { width, height, format: String::new(), max_fps });
+        }
+    }
+    modes
+}
+
+/// Modes in `ffmpeg -f v4l2 -list_formats all` output, such as
+/// `Raw : yuyv422 : YUYV 4:2:2 : 640x480 1280x720`. V4L2 doesn't report
+/// frame rates this way.
+#[cfg_attr(not(any(target_os = "linux", test)), allow(dead_code))]
+fn parse_v4l2_modes(stderr: &str) -> Vec<VideoMode> {
+    let mut modes = Vec::new();
+    for line in stderr.lines().map(log_message) {
+        let Some((kind, rest)) = line.split_once(':') else {
+            continue;
+        };
+        let fields: Vec<&str> = rest.split(" : ").map(str::trim).collect();
+        let [format, _, sizes] = fields[..] else {
+            continue;
+        };
+        if !matches!(kind.trim(), "Raw" | "Compressed") {
+            continue;
+        }
+        for (width, height) in sizes.split_whitespace().filter_map(parse_size) {
+            modes.push(VideoMode { width, height, format: format.to_string(), max_fps: None });
+        }
+    }
+    modes
+}
+
+fn parse_size(size: &str) -> Option<(u32, u32)> {
+    let (width, height) = size.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dshow() {
+        let devices = "[dshow @ 0000021b] \"Cam Link 4K\" (video)\n\
+            [dshow @ 0000021b]   Alternative name \"@device_pnp_\\\\?\\usb#vid_0fd9&pid_0066\"\n\
+            [dshow @ 0000021b] \"Microphone (Cam Link 4K)\" (audio)\n\
+            [dshow @ 0000021b]   Alternative name \"@device_cm_{33D9A762}\\wave_{A1B2}\"\n\
+            dummy: Immediate exit requested";
+        assert_eq!(
+            parse_dshow_devices(devices, "video"),
+            [("@device_pnp_\\\\?\\usb#vid_0fd9&pid_0066".to_string(), "Cam Link 4K".to_string())]
+        );
+        assert_eq!(
+            parse_dshow_devices(devices, "audio"),
+            [("@device_cm_{33D9A762}\\wave_{A1B2}".to_string(), "Microphone (Cam Link 4K)".to_string())]
+        );
+
+        let options = "[dshow @ 0000021b] DirectShow video device options (from video devices)\n\
+            [dshow @ 0000021b]  Pin \"Capture\" (alternative pin name \"0\")\n\
+            [dshow @ 0000021b]   vcodec=mjpeg  min s=1920x1080 fps=5 max s=1920x1080 fps=60\n\
+            [dshow @ 0000021b]   pixel_format=nv12  min s=3840x2160 fps=29.97 max s=3840x2160 fps=30 (tv, bt709)\n\
+            [dshow @ 0000021b]   pixel_format=nv12  min s=3840x2160 fps=29.97 max s=3840x2160 fps=30 (tv, bt709)";
+        assert_eq!(
+            parse_dshow_modes(options),
+            [
+                VideoMode { width: 1920, height: 1080, format: "mjpeg".to_string(), max_fps: Some(60.0) },
+                VideoMode { width: 3840, height: 2160, format: "nv12".to_string(), max_fps: Some(30.0) },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_avfoundation_and_v4l2() {
+        let devices = "[AVFoundation indev @ 0x7f8] AVFoundation video devices:\n\
+            [AVFoundation indev @ 0x7f8] [0] FaceTime HD Camera\n\
+            [AVFoundation indev @ 0x7f8] [1] Capture screen 0\n\
+            [AVFoundation indev @ 0x7f8] AVFoundation audio devices:\n\
+            [AVFoundation indev @ 0x7f8] [0] MacBook Pro Microphone";
+        assert_eq!(parse_avfoundation_devices(devices), [("0".to_string(), "FaceTime HD Camera".to_string())]);
+        let modes = "[avfoundation @ 0x7f8] Supported modes:\n\
+            [avfoundation @ 0x7f8]   1280x720@[1.000000 30.000000]fps";
+        assert_eq!(parse_avfoundation_modes(modes)[0].max_fps, Some(30.0));
+
+        let formats = "[video4linux2,v4l2 @ 0x55] Raw       :     yuyv422 :           YUYV 4:2:2 : 640x480 1280x720\n\
+            [video4linux2,v4l2 @ 0x55] Compressed:       mjpeg :          Motion-JPEG : 1920x1080\n\
+            /dev/video0: Immediate exit requested";
+        let modes = parse_v4l2_modes(formats);
+        assert_eq!(modes.len(), 3);
+        assert_eq!((modes[2].width, modes[2].format.as_str()), (1920, "mjpeg"));
+    }
+}
@@ -0,0 +1,323 @@
+//! Diagnostics bundles for bug reports.
+//!
+//! A bundle is a zip file with what's needed to work out why recording
+//! fails on someone's machine: the app's and the picker's logs (see
+//! [`crate::logging`]), what the capture backend and encoders can do, recent
+//! recording stats, the settings, and on Linux how PipeWire's audio nodes are
+//! connected. It's only made when the user asks for one, and is saved
+//! next to their recordings for them to look over and attach to a report;
+//! nothing is sent anywhere. Tokens, keys and upload endpoints are taken out
+//! of the settings, and the home folder and user name out of everything.
+
+use crate::config::data_dir;
+use crate::logging::LOG_DIR;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+/// Settings files left out of bundles: the library lists the user's
+/// recordings by title.
+const EXCLUDED_FILES: &[&str] = &["library.json"];
+
+/// Settings whose values are replaced with [`REDACTED`], by part of their name.
+const SECRET_KEYS: &[&str] = &["token", "secret", "password", "access_key", "headers", "url"];
+
+const REDACTED: &str = "[redacted]";
+
+/// Write a bundle to the recordings folder, returning its path.
+/// `encoder_settings` are the settings in effect, which aren't saved.
+///
+/// This may probe the encoders, which can take half a minute; call it from
+/// a blocking task.
+pub fn create_bundle(encoder_settings: Value) -> Result<PathBuf, String> {
+    let scrubber = Scrubber::for_user();
+    let mut zip = ZipWriter::default();
+
+    let system = json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "os": std::env::consts::OS,
+        "arch": std::env::consts::ARCH,
+        "session_type": std::env::var("XDG_SESSION_TYPE").ok(),
+        "desktop": std::env::var("XDG_CURRENT_DESKTOP").ok(),
+        "created_at": chrono::Local::now().to_rfc3339(),
+    });
+    zip.add_json("system.json", &system, &scrubber);
+
+    let capture = json!({
+        "capabilities": crate::capture::capabilities(),
+        "monitors": crate::capture::list_monitors(),
+        "gpu_adapters": crate::gpu::list_adapters(),
+    });
+    zip.add_json("capture.json", &capture, &scrubber);
+
+    let encoders = match crate::encoder::support::encoder_support(false) {
+        Ok(support) => json!(support),
+        Err(e) => json!({ "error": e }),
+    };
+    zip.add_json("encoders.json", &encoders, &scrubber);
+    #[cfg(target_os = "linux")]
+    {
+        let audio_graph = match crate::capture::linux::audio_graph::snapshot() {
+            Ok(graph) => json!({ "summary": graph.summary(), "graph": graph.ours_and_neighbors() }),
+            Err(e) => json!({ "error": e }),
+        };
+        zip.add_json("audio_graph.json", &audio_graph, &scrubber);
+    }
+    zip.add_json("stats.json", &json!(crate::stats::recent()), &scrubber);
+    zip.add_json("settings/encoder.json", &encoder_settings, &scrubber);
+
+    let dir = data_dir()?;
+    let mut files: Vec<_> = std::fs::read_dir(&dir)
+        .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    files.sort();
+    for path in files {
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if !name.ends_with(".json") || EXCLUDED_FILES.contains(&name) {
+            continue;
+        }
+        let value = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_else(|| json!({ "error": "Couldn't be read" }));
+        zip.add_json(&format!("settings/{}", name), &value, &scrubber);
+    }
+
+    let mut logs: Vec<_> = std::fs::read_dir(dir.join(LOG_DIR))
+        .map(|entries| entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect())
+        .unwrap_or_default();
+    logs.sort();
+    for path in logs {
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if let Ok(log) = std::fs::read(&path) {
+            let log = scrubber.scrub(&String::from_utf8_lossy(&log));
+            zip.add(&format!("logs/{}", name), log.as_bytes());
+        }
+    }
+
+    let path = crate::encoder::default_output_dir()?.join(format!(
+        "screen-recorder-diagnostics_{}.zip",
+        chrono::Local::now().format("%Y-%m-%d_%H%M%S")
+    ));
+    std::fs::write(&path, zip.finish())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    tracing::info!(target: "Diagnostics", "Wrote {}", path.display());
+    Ok(path)
+}
+
+/// Replace the values of settings named like secrets, at any depth.
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                let key = key.to_ascii_lowercase();
+                if !SECRET_KEYS.iter().any(|secret| key.contains(secret)) {
+                    redact(value);
+                } else if !value.is_null() && *value != json!("") {
+                    *value = json!(REDACTED);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// Takes the user's home folder and name out of text.
+struct Scrubber {
+    home: Vec<String>,
+    user: Option<String>,
+}
+
+impl Scrubber {
+    fn for_user() -> Self {
+        let home = directories::UserDirs::new().map(|dirs| dirs.home_dir().to_path_buf());
+        let user = std::env::var("USER").or_else(|_| std::env::var("USERNAME")).ok();
+        Self::new(home.as_deref(), user)
+    }
+
+    fn new(home: Option<&Path>, user: Option<String>) -> Self {
+        let mut paths = Vec::new();
+        if let Some(home) = home.map(|home| home.to_string_lossy().to_string()) {
+            // As it's written in JSON, too, where backslashes are escaped
+            let escaped = home.replace('\\', "\\\\");
+            if escaped != home {
+                paths.push(escaped);
+            }
+            paths.push(home);
+        }
+        Self {
+            home: paths.into_iter().filter(|path| path.len() > 1).collect(),
+            // Too short a name would take out parts of other words
+            user: user.filter(|user| user.chars().count() >= 3),
+        }
+    }
+
+    fn scrub(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for home in &self.home {
+            text = text.replace(home.as_str(), "~");
+        }
+        match self.user {
+            Some(ref user) => replace_word(&text, user, "<user>"),
+            None => text,
+        }
+    }
+}
+
+/// Replace `word` in `text` where it isn't part of a longer word.
+fn replace_word(text: &str, word: &str, with: &str) -> String {
+    let bounded = |c: Option<char>| !c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    let mut scrubbed = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find(word) {
+        let before = rest[..at].chars().next_back().or_else(|| scrubbed.chars().next_back());
+        let after = rest[at + word.len()..].chars().next();
+        scrubbed.push_str(&rest[..at]);
+        scrubbed.push_str(if bounded(before) && bounded(after) { with } else { word });
+        rest = &rest[at + word.len()..];
+    }
+    scrubbed.push_str(rest);
+    scrubbed
+}
+
+/// Writes an uncompressed zip archive in memory. The bundle is small, and
+/// mostly text, so compression isn't worth a dependency.
+#[derive(Default)]
+struct ZipWriter {
+    data: Vec<u8>,
+    directory: Vec<u8>,
+    entries: u16,
+}
+
+impl ZipWriter {
+    /// Add `value` as pretty-printed JSON, with secrets and the user's
+    /// details taken out.
+    fn add_json(&mut self, name: &str, value: &Value, scrubber: &Scrubber) {
+        let mut value = value.clone();
+        redact(&mut value);
+        let text = serde_json::to_string_pretty(&value).unwrap_or_default();
+        self.add(name, scrubber.scrub(&text).as_bytes());
+    }
+
+    fn add(&mut self, name: &str, contents: &[u8]) {
+        let (time, date) = dos_time(chrono::Local::now().naive_local());
+        let crc = crc32(contents);
+        let offset = self.data.len() as u32;
+        // Version 2.0, UTF-8 names, stored without compression
+        let fields = |header: &mut Vec<u8>| {
+            header.extend_from_slice(&20u16.to_le_bytes());
+            header.extend_from_slice(&0x0800u16.to_le_bytes());
+            header.extend_from_slice(&0u16.to_le_bytes());
+            header.extend_from_slice(&time.to_le_bytes());
+            header.extend_from_slice(&date.to_le_bytes());
+            header.extend_from_slice(&crc.to_le_bytes());
+            header.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            header.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            header.extend_from_slice(&0u16.to_le_bytes());
+        };
+
+        self.data.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        fields(&mut self.data);
+        self.data.extend_from_slice(name.as_bytes());
+        self.data.extend_from_slice(contents);
+
+        self.directory.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        self.directory.extend_from_slice(&20u16.to_le_bytes());
+        fields(&mut self.directory);
+        // No comment, disk 0, no attributes, then where the entry starts
+        self.directory.extend_from_slice(&[0; 10]);
+        self.directory.extend_from_slice(&offset.to_le_bytes());
+        self.directory.extend_from_slice(name.as_bytes());
+        self.entries += 1;
+    }
+
+    /// The archive's bytes.
+    fn finish(mut self) -> Vec<u8> {
+        let offset = self.data.len() as u32;
+        let size = self.directory.len() as u32;
+        self.data.append(&mut self.directory);
+        self.data.extend_from_slice(&0x06054b50u32.to_le_bytes());
+        self.data.extend_from_slice(&[0; 4]);
+        self.data.extend_from_slice(&self.entries.to_le_bytes());
+        self.data.extend_from_slice(&self.entries.to_le_bytes());
+        self.data.extend_from_slice(&size.to_le_bytes());
+        self.data.extend_from_slice(&offset.to_le_bytes());
+        self.data.extend_from_slice(&0u16.to_le_bytes());
+        self.data
+    }
+}
+
+/// A time in the MS-DOS format zip uses, as (time, date).
+fn dos_time(time: chrono::NaiveDateTime) -> (u16, u16) {
+    use chrono::{Datelike, Timelike};
+    let dos_time = (time.hour() << 11 | time.minute() << 5 | (time.second() / 2)) as u16;
+    let dos_date = ((time.year().clamp(1980, 2107) - 1980) << 9) as u16 | (time.month() << 5 | time.day()) as u16;
+    (dos_time, dos_date)
+}
+
+/// CRC-32 (IEEE) of `data`, as zip uses.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scrub() {
+        let scrubber = Scrubber::new(Some(Path::new("/home/alice")), Some("alice".to_string()));
+        assert_eq!(
+            scrubber.scrub("Saved /home/alice/Videos/a.mp4 for alice (malice, alice_2)"),
+            "Saved ~/Videos/a.mp4 for <user> (malice, alice_2)"
+        );
+
+        let mut settings = json!({
+            "token": "abc",
+            "targets": [{ "s3": { "bucket": "b", "secret_key": "xyz", "access_key": "" } }],
+            "webhook": { "url": "https://example.com/hook", "headers": { "X-Key": "1" } },
+        });
+        redact(&mut settings);
+        assert_eq!(
+            settings,
+            json!({
+                "token": REDACTED,
+                "targets": [{ "s3": { "bucket": "b", "secret_key": REDACTED, "access_key": "" } }],
+                "webhook": { "url": REDACTED, "headers": REDACTED },
+            })
+        );
+    }
+
+    #[test]
+    fn test_zip_writer() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+
+        let mut zip = ZipWriter::default();
+        zip.add("a.txt", b"hello");
+        zip.add("logs/b.log", b"world");
+        let data = zip.finish();
+
+        assert_eq!(&data[..4], &0x04034b50u32.to_le_bytes());
+        // The end record counts both entries and points at the directory
+        let end = &data[data.len() - 22..];
+        assert_eq!(&end[..4], &0x06054b50u32.to_le_bytes());
+        assert_eq!(u16::from_le_bytes([end[10], end[11]]), 2);
+        let directory = u32::from_le_bytes(end[16..20].try_into().unwrap()) as usize;
+        assert_eq!(&data[directory..directory + 4], &0x02014b50u32.to_le_bytes());
+        assert_eq!(&data[30..35], b"a.txt");
+        assert_eq!(&data[35..40], b"hello");
+    }
+}
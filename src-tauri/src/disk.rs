@@ -0,0 +1,183 @@
+//! Free disk space monitoring during recordings.
+//!
+//! A recording that fills its disk ends with FFmpeg unable to write the
+//! index, leaving a truncated file most players can't open. While recording,
+//! the free space on the output volume is checked every couple of seconds;
+//! warnings are published as it drops below each threshold, and once it
+//! drops below the stop threshold the recording is stopped so the file is
+//! finalized while there's still room.
+
+use crate::stats::StatsCollector;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// How often free space is checked while recording.
+const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Free space thresholds, in megabytes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiskSpaceSettings {
+    /// Warn as free space drops below each of these
+    pub warning_mb: Vec<u64>,
+    /// Stop the recording when free space drops below this; 0 never stops
+    pub stop_mb: u64,
+}
+
+impl Default for DiskSpaceSettings {
+    fn default() -> Self {
+        Self {
+            warning_mb: vec![10 * 1024, 2 * 1024],
+            stop_mb: 500,
+        }
+    }
+}
+
+/// Free space crossing a threshold during a recording.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiskSpaceEvent {
+    /// Free space on the output volume
+    pub free_bytes: u64,
+    /// Threshold crossed, in megabytes
+    pub threshold_mb: u64,
+    /// The recording is being stopped; the frontend should finish it
+    pub stopping: bool,
+}
+
+/// Tracks which thresholds a recording has crossed, so each is reported once.
+#[derive(Debug)]
+pub struct DiskMonitor {
+    settings: DiskSpaceSettings,
+    /// Lowest warning threshold reported so far
+    warned_mb: Option<u64>,
+}
+
+impl DiskMonitor {
+    pub fn new(settings: DiskSpaceSettings) -> Self {
+        Self { settings, warned_mb: None }
+    }
+
+    /// Check `free_bytes` against the thresholds, returning the event to
+    /// publish, if any.
+    pub fn check(&mut self, free_bytes: u64) -> Option<DiskSpaceEvent> {
+        let free_mb = free_bytes / (1024 * 1024);
+        if self.settings.stop_mb > 0 && free_mb < self.settings.stop_mb {
+            return Some(DiskSpaceEvent {
+                free_bytes,
+                threshold_mb: self.settings.stop_mb,
+                stopping: true,
+            });
+        }
+
+        // Only the lowest threshold crossed is reported when several are at once
+        let crossed = self
+            .settings
+            .warning_mb
+            .iter()
+            .copied()
+            .filter(|&threshold| free_mb < threshold && self.warned_mb.is_none_or(|warned| threshold < warned))
+            .min()?;
+        self.warned_mb = Some(crossed);
+        Some(DiskSpaceEvent {
+            free_bytes,
+            threshold_mb: crossed,
+            stopping: false,
+        })
+    }
+}
+
+/// Free space available to this user on the volume holding `path`.
+pub fn free_space(path: &Path) -> Result<u64, String> {
+    #[cfg(target_os = "windows")]
+    {
+        use windows::core::HSTRING;
+        use windows::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+        let mut available = 0u64;
+        unsafe { GetDiskFreeSpaceExW(&HSTRING::from(path.as_os_str()), Some(&mut available), None, None) }
+            .map_err(|e| format!("Failed to get free space: {}", e))?;
+        Ok(available)
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = std::ffi::CString::new(path.as_os_str().as_bytes()).map_err(|e| e.to_string())?;
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+            return Err(format!("Failed to get free space: {}", std::io::Error::last_os_error()));
+        }
+        Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+    }
+    #[cfg(not(any(target_os = "windows", unix)))]
+    {
+        let _ = path;
+        Err("Free space is not available on this platform".to_string())
+    }
+}
+
+/// Watch the free space where `stats`' recording is written until
+/// `stop_flag` is set, publishing threshold crossings to `events`. Sets
+/// `stop_flag` itself when space runs out, which finalizes the file.
+pub async fn watch(
+    stats: Arc<StatsCollector>,
+    stop_flag: Arc<AtomicBool>,
+    settings: DiskSpaceSettings,
+    events: broadcast::Sender<DiskSpaceEvent>,
+) {
+    let mut monitor = DiskMonitor::new(settings);
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    while !stop_flag.load(Ordering::Relaxed) {
+        interval.tick().await;
+
+        // The file only exists once the encoder has started
+        let Some(dir) = stats.output_path().and_then(|path| path.parent().map(Path::to_path_buf)) else {
+            continue;
+        };
+        let free_bytes = match free_space(&dir) {
+            Ok(free_bytes) => free_bytes,
+            Err(e) => {
+                tracing::warn!(target: "Disk", "{}", e);
+                return;
+            }
+        };
+        if let Some(event) = monitor.check(free_bytes) {
+            tracing::info!(target: "Disk", "{} MB free on {}", free_bytes / (1024 * 1024), dir.display());
+            if event.stopping {
+                tracing::info!(target: "Disk", "Stopping the recording before the disk fills");
+                stop_flag.store(true, Ordering::Relaxed);
+            }
+            let _ = events.send(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: u64 = 1024 * 1024;
+
+    #[test]
+    fn test_disk_monitor() {
+        let mut monitor = DiskMonitor::new(DiskSpaceSettings {
+            warning_mb: vec![2048, 10240],
+            stop_mb: 500,
+        });
+        assert_eq!(monitor.check(20_000 * MB), None);
+        assert_eq!(monitor.check(9_000 * MB).map(|e| e.threshold_mb), Some(10240));
+        assert_eq!(monitor.check(8_000 * MB), None);
+        // Skipping past a threshold reports only the lowest
+        let mut fresh = DiskMonitor::new(DiskSpaceSettings::default());
+        assert_eq!(fresh.check(1_000 * MB).map(|e| e.threshold_mb), Some(2048));
+        assert_eq!(fresh.check(1_000 * MB), None);
+
+        let event = monitor.check(400 * MB).unwrap();
+        assert!(event.stopping);
+        assert_eq!(event.threshold_mb, 500);
+        assert!(free_space(&std::env::temp_dir()).unwrap() > 0);
+    }
+}
@@ -0,0 +1,316 @@
+//! Temporarily switching the captured monitor's display mode.
+//!
+//! A demo recorded on a presenter's 4K monitor at 150% looks nothing like
+//! one recorded at 1080p and 100%. With a display mode override set, the
+//! monitor being recorded is switched to the chosen resolution and scale as
+//! the recording starts, and back once the capture stops. Regions are left
+//! alone, since they were drawn on the monitor as it is.
+//!
+//! - Windows: a temporary display mode, which Windows also undoes if the app
+//!   quits. Scaling has no public API, so the scale is left as it is.
+//! - Linux: a Hyprland monitor rule
+//! - macOS: not supported
+
+use crate::capture::refresh;
+use crate::capture::{CaptureTarget, MonitorInfo};
+use crate::state::RecordingPhase;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+
+/// Largest resolution a monitor can be switched to.
+const MAX_WIDTH: u32 = 7680;
+const MAX_HEIGHT: u32 = 4320;
+
+/// Mode to record the captured monitor in, if overridden.
+static OVERRIDE: Mutex<Option<DisplayMode>> = Mutex::new(None);
+
+/// How to put back the monitor switched for the current recording.
+static ENGAGED: Mutex<Option<ModeChanged>> = Mutex::new(None);
+
+/// A monitor's resolution and scale.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DisplayMode {
+    pub width: u32,
+    pub height: u32,
+    /// Keeps the current refresh rate if not given
+    #[serde(default)]
+    pub refresh_rate: Option<f64>,
+    /// Keeps the current scale if not given, such as 1.0 for 100%
+    #[serde(default)]
+    pub scale: Option<f64>,
+}
+
+impl DisplayMode {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(64..=MAX_WIDTH).contains(&self.width) || !(64..=MAX_HEIGHT).contains(&self.height) {
+            return Err(format!("Unsupported resolution: {}x{}", self.width, self.height));
+        }
+        if self.refresh_rate.is_some_and(|rate| rate.is_nan() || rate <= 0.0) {
+            return Err("Refresh rate must be greater than zero".to_string());
+        }
+        if self.scale.is_some_and(|scale| !(0.25..=4.0).contains(&scale)) {
+            return Err("Scale must be between 25% and 400%".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether `monitor` is already in this mode.
+    fn matches(&self, monitor: &MonitorInfo) -> bool {
+        monitor.width == self.width
+            && monitor.height == self.height
+            && (self.refresh_rate.is_none() || self.refresh_rate == monitor.refresh_rate)
+            && (self.scale.is_none() || self.scale == Some(monitor.scale_factor))
+    }
+}
+
+/// How to put a switched monitor back.
+pub struct ModeChanged(platform::Previous);
+
+/// Switch `monitor` to `mode`, returning how to switch it back, or `None`
+/// if it's already in it.
+///
+/// May run a command; call it from a blocking context.
+pub fn set(monitor: &MonitorInfo, mode: &DisplayMode) -> Result<Option<ModeChanged>, String> {
+    if mode.matches(monitor) {
+        return Ok(None);
+    }
+    let previous = platform::set_mode(monitor, mode)?;
+    tracing::info!(target: "DisplayMode", "Switched {} to {}x{}", monitor.id, mode.width, mode.height);
+    Ok(Some(ModeChanged(previous)))
+}
+
+/// Switch a monitor back to the mode it was in before [`set`].
+pub fn reset(changed: ModeChanged) -> Result<(), String> {
+    platform::restore_mode(changed.0)
+}
+
+/// Set the mode recordings switch the captured monitor to, or `None` to
+/// record it as it is.
+pub fn set_override(mode: Option<DisplayMode>) -> Result<(), String> {
+    if let Some(ref mode) = mode {
+        mode.validate()?;
+    }
+    *OVERRIDE.lock().unwrap() = mode;
+    Ok(())
+}
+
+/// The mode recordings switch the captured monitor to, if set.
+pub fn override_mode() -> Option<DisplayMode> {
+    *OVERRIDE.lock().unwrap()
+}
+
+/// Switch the monitor `target` is on to the override for a recording,
+/// returning the target with the monitor's new size.
+pub async fn engage(target: CaptureTarget) -> Result<CaptureTarget, String> {
+    let Some(mode) = override_mode() else {
+        return Ok(target);
+    };
+    if matches!(target, CaptureTarget::Region { .. }) {
+        tracing::info!(target: "DisplayMode", "Recording a region; leaving its monitor as it is");
+        return Ok(target);
+    }
+    tokio::task::spawn_blocking(move || {
+        let monitor = refresh::target_monitor(&target).ok_or("The captured monitor wasn't found")?;
+        let Some(changed) = set(&monitor, &mode)? else {
+            return Ok(target);
+        };
+        *ENGAGED.lock().unwrap() = Some(changed);
+        Ok(match target {
+            CaptureTarget::Display { monitor_id, .. } => CaptureTarget::Display {
+                monitor_id,
+                width: mode.width,
+                height: mode.height,
+            },
+            target => target,
+        })
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Put the monitor switched for the recording back, if one was.
+///
+/// May run a command; call it from a blocking context.
+pub fn restore() {
+    let Some(changed) = ENGAGED.lock().unwrap().take() else {
+        return;
+    };
+    match reset(changed) {
+        Ok(()) => tracing::info!(target: "DisplayMode", "Restored"),
+        Err(e) => tracing::error!(target: "DisplayMode", "Failed to restore: {}", e),
+    }
+}
+
+/// Switch monitors back as recordings stop.
+///
+/// Runs for the lifetime of the app.
+pub async fn run(app: AppHandle) {
+    use tokio::sync::broadcast::error::RecvError;
+
+    let manager = app.state::<AppState>().recording_manager.clone();
+    let mut phases = manager.lock().await.subscribe_phase_events();
+    loop {
+        match phases.recv().await {
+            Ok(RecordingPhase::Finalizing | RecordingPhase::Failed { .. }) => {
+                let _ = tokio::task::spawn_blocking(restore).await;
+            }
+            Ok(_) => {}
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Hyprland monitor rule putting `monitor` in the given mode, where it is.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn monitor_rule(monitor: &MonitorInfo, width: u32, height: u32, refresh_rate: Option<f64>, scale: f64) -> String {
+    let mode = match refresh_rate {
+        Some(rate) => format!("{}x{}@{}", width, height, rate),
+        None => format!("{}x{}", width, height),
+    };
+    let mut rule = format!("{},{},{}x{},{}", monitor.id, mode, monitor.x, monitor.y, scale);
+    // Rotations are clockwise, Wayland transforms counterclockwise
+    let transform = match monitor.rotation {
+        90 => 3,
+        180 => 2,
+        270 => 1,
+        _ => 0,
+    };
+    if transform != 0 {
+        rule.push_str(&format!(",transform,{}", transform));
+    }
+    rule
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::{monitor_rule, DisplayMode};
+    use crate::capture::linux::hyprctl;
+    use crate::capture::MonitorInfo;
+
+    /// Rule with the monitor's previous mode
+    pub struct Previous(String);
+
+    pub fn set_mode(monitor: &MonitorInfo, mode: &DisplayMode) -> Result<Previous, String> {
+        if std::env::var("HYPRLAND_INSTANCE_SIGNATURE").is_err() {
+            return Err("Changing the display mode requires the Hyprland compositor".to_string());
+        }
+        let previous = monitor_rule(monitor, monitor.width, monitor.height, monitor.refresh_rate, monitor.scale_factor);
+        let rule = monitor_rule(
+            monitor,
+            mode.width,
+            mode.height,
+            mode.refresh_rate.or(monitor.refresh_rate),
+            mode.scale.unwrap_or(monitor.scale_factor),
+        );
+        hyprctl(&["keyword", "monitor", &rule])?;
+        Ok(Previous(previous))
+    }
+
+    pub fn restore_mode(previous: Previous) -> Result<(), String> {
+        hyprctl(&["keyword", "monitor", &previous.0])
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use super::DisplayMode;
+    use crate::capture::MonitorInfo;
+    use windows::core::HSTRING;
+    use windows::Win32::Foundation::HWND;
+    use windows::Win32::Graphics::Gdi::{
+        ChangeDisplaySettingsExW, EnumDisplaySettingsW, CDS_FULLSCREEN, CDS_TYPE, DEVMODEW, DISP_CHANGE_SUCCESSFUL,
+        DM_DISPLAYFREQUENCY, DM_PELSHEIGHT, DM_PELSWIDTH, ENUM_CURRENT_SETTINGS,
+    };
+
+    /// Device name of the switched monitor
+    pub struct Previous(String);
+
+    pub fn set_mode(monitor: &MonitorInfo, mode: &DisplayMode) -> Result<Previous, String> {
+        if mode.scale.is_some_and(|scale| scale != monitor.scale_factor) {
+            tracing::info!(target: "DisplayMode", "Windows has no API to change scaling; keeping {}%", monitor.scale_factor * 100.0);
+        }
+        let device = HSTRING::from(monitor.id.as_str());
+        let mut devmode = DEVMODEW {
+            dmSize: std::mem::size_of::<DEVMODEW>() as u16,
+            ..Default::default()
+        };
+        if !unsafe { EnumDisplaySettingsW(&device, ENUM_CURRENT_SETTINGS, &mut devmode) }.as_bool() {
+            return Err(format!("Failed to read the display mode of {}", monitor.id));
+        }
+        devmode.dmPelsWidth = mode.width;
+        devmode.dmPelsHeight = mode.height;
+        devmode.dmFields = DM_PELSWIDTH | DM_PELSHEIGHT;
+        if let Some(rate) = mode.refresh_rate {
+            devmode.dmDisplayFrequency = rate.round() as u32;
+            devmode.dmFields |= DM_DISPLAYFREQUENCY;
+        }
+        // Temporary: Windows puts the saved mode back if the app quits
+        let result = unsafe { ChangeDisplaySettingsExW(&device, Some(&devmode), HWND::default(), CDS_FULLSCREEN, None) };
+        if result != DISP_CHANGE_SUCCESSFUL {
+            return Err(format!("{} doesn't support {}x{} ({:?})", monitor.id, mode.width, mode.height, result));
+        }
+        Ok(Previous(monitor.id.clone()))
+    }
+
+    pub fn restore_mode(previous: Previous) -> Result<(), String> {
+        // Without a mode, the one saved in the registry is restored
+        let device = HSTRING::from(previous.0.as_str());
+        let result = unsafe { ChangeDisplaySettingsExW(&device, None, HWND::default(), CDS_TYPE(0), None) };
+        if result != DISP_CHANGE_SUCCESSFUL {
+            return Err(format!("Failed to restore the display mode of {} ({:?})", previous.0, result));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+mod platform {
+    use super::DisplayMode;
+    use crate::capture::MonitorInfo;
+
+    pub struct Previous;
+
+    pub fn set_mode(_monitor: &MonitorInfo, _mode: &DisplayMode) -> Result<Previous, String> {
+        Err("Changing the display mode is not supported on this platform".to_string())
+    }
+
+    pub fn restore_mode(_previous: Previous) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_mode() {
+        let monitor = MonitorInfo {
+            id: "DP-1".to_string(),
+            name: "DP-1".to_string(),
+            x: 2560,
+            y: 0,
+            width: 3840,
+            height: 2160,
+            is_primary: false,
+            scale_factor: 1.5,
+            model: None,
+            refresh_rate: Some(144.0),
+            rotation: 90,
+        };
+        assert_eq!(
+            monitor_rule(&monitor, 1920, 1080, Some(60.0), 1.0),
+            "DP-1,1920x1080@60,2560x0,1,transform,3"
+        );
+        assert_eq!(monitor_rule(&monitor, 1920, 1080, None, 1.25), "DP-1,1920x1080,2560x0,1.25,transform,3");
+
+        let mode = DisplayMode { width: 3840, height: 2160, refresh_rate: None, scale: None };
+        assert!(mode.matches(&monitor));
+        assert!(!DisplayMode { scale: Some(1.0), ..mode }.matches(&monitor));
+        assert!(DisplayMode { width: 0, ..mode }.validate().is_err());
+        assert!(DisplayMode { scale: Some(8.0), ..mode }.validate().is_err());
+    }
+}
@@ -0,0 +1,346 @@
+//! Do not disturb while recording.
+//!
+//! When enabled, the desktop's do-not-disturb mode is turned on as a
+//! recording starts and put back how it was once the recording ends, so
+//! notification banners don't pop up in recordings. If it was already on,
+//! it's left alone.
+//!
+//! - Windows: the Do Not Disturb (Focus Assist) setting, for new notifications
+//! - Linux: GNOME's notification banners, an inhibition on KDE Plasma, or
+//!   pausing dunst, mako (its `do-not-disturb` mode) or SwayNotificationCenter
+//! - macOS: Focus has no API, so the Shortcuts "Screen Recorder Focus On" and
+//!   "Screen Recorder Focus Off" are run; create them with the Set Focus
+//!   action. Whether a Focus was already on can't be told, so it's always
+//!   turned off afterwards.
+
+use crate::state::RecordingPhase;
+use crate::AppState;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// What was changed to turn do not disturb on, to undo it.
+static ENGAGED: Mutex<Option<platform::Engaged>> = Mutex::new(None);
+
+/// Set whether recordings turn do not disturb on.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether recordings turn do not disturb on.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Turn do not disturb on as recordings start and restore it as they end.
+///
+/// Runs for the lifetime of the app.
+pub async fn run(app: AppHandle) {
+    use tokio::sync::broadcast::error::RecvError;
+
+    let manager = app.state::<AppState>().recording_manager.clone();
+    let mut phases = manager.lock().await.subscribe_phase_events();
+    loop {
+        match phases.recv().await {
+            Ok(RecordingPhase::Preparing) if enabled() => {
+                let _ = engage().await;
+            }
+            Ok(RecordingPhase::Done { .. } | RecordingPhase::Failed { .. }) => {
+                let _ = tokio::task::spawn_blocking(restore).await;
+            }
+            Ok(_) => {}
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Turn do not disturb on, remembering how to undo it.
+pub async fn engage() -> Result<(), String> {
+    if ENGAGED.lock().unwrap().is_some() {
+        return Ok(());
+    }
+    match platform::engage().await {
+        Ok(Some(engaged)) => {
+            tracing::info!(target: "DoNotDisturb", "Turned on for the recording");
+            *ENGAGED.lock().unwrap() = Some(engaged);
+        }
+        Ok(None) => tracing::info!(target: "DoNotDisturb", "Already on"),
+        Err(e) => {
+            tracing::error!(target: "DoNotDisturb", "Failed to turn on: {}", e);
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Put do not disturb back how it was before the recording, if it was
+/// turned on for it.
+///
+/// May run a command; call it from a blocking task.
+pub fn restore() {
+    let Some(engaged) = ENGAGED.lock().unwrap().take() else {
+        return;
+    };
+    match platform::restore(engaged) {
+        Ok(()) => tracing::info!(target: "DoNotDisturb", "Restored"),
+        Err(e) => tracing::error!(target: "DoNotDisturb", "Failed to restore: {}", e),
+    }
+}
+
+/// Notification daemons whose do-not-disturb mode can be turned on.
+#[cfg_attr(not(any(target_os = "linux", test)), allow(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Daemon {
+    Gnome,
+    Plasma,
+    Dunst,
+    Mako,
+    Swaync,
+}
+
+impl Daemon {
+    /// The daemon going by `name` in its `GetServerInformation` reply.
+    #[cfg_attr(not(any(target_os = "linux", test)), allow(dead_code))]
+    fn from_server_name(name: &str) -> Option<Self> {
+        match name {
+            "gnome-shell" => Some(Self::Gnome),
+            "Plasma" => Some(Self::Plasma),
+            "dunst" => Some(Self::Dunst),
+            "mako" => Some(Self::Mako),
+            "SwayNotificationCenter" | "swaync" => Some(Self::Swaync),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::Daemon;
+    use crate::process::run;
+    use std::collections::HashMap;
+
+    const NOTIFICATIONS: &str = "org.freedesktop.Notifications";
+    const NOTIFICATIONS_PATH: &str = "/org/freedesktop/Notifications";
+    const GNOME_SCHEMA: &str = "org.gnome.desktop.notifications";
+    const MAKO_MODE: &str = "do-not-disturb";
+
+    pub enum Engaged {
+        /// GNOME's banners were turned off
+        GnomeBanners,
+        /// Plasma lifts the inhibition once the connection holding it closes
+        PlasmaInhibition(#[allow(dead_code)] zbus::Connection),
+        DunstPaused,
+        MakoMode,
+        SwayncDnd,
+    }
+
+    pub async fn engage() -> Result<Option<Engaged>, String> {
+        let connection = zbus::Connection::session()
+            .await
+            .map_err(|e| format!("No session bus: {}", e))?;
+        let reply = connection
+            .call_method(Some(NOTIFICATIONS), NOTIFICATIONS_PATH, Some(NOTIFICATIONS), "GetServerInformation", &())
+            .await
+            .map_err(|e| format!("No notification daemon: {}", e))?;
+        let (name, _vendor, _version, _spec): (String, String, String, String) =
+            reply.body().deserialize().map_err(|e| e.to_string())?;
+
+        match Daemon::from_server_name(&name) {
+            Some(Daemon::Gnome) => {
+                if run("gsettings", &["get", GNOME_SCHEMA, "show-banners"])? == "false" {
+                    return Ok(None);
+                }
+                run("gsettings", &["set", GNOME_SCHEMA, "show-banners", "false"])?;
+                Ok(Some(Engaged::GnomeBanners))
+            }
+            Some(Daemon::Plasma) => {
+                let hints: HashMap<&str, zbus::zvariant::Value> = HashMap::new();
+                connection
+                    .call_method(
+                        Some(NOTIFICATIONS),
+                        NOTIFICATIONS_PATH,
+                        Some(NOTIFICATIONS),
+                        "Inhibit",
+                        &("screen-recorder", "Recording the screen", hints),
+                    )
+                    .await
+                    .map_err(|e| format!("Failed to inhibit notifications: {}", e))?;
+                Ok(Some(Engaged::PlasmaInhibition(connection)))
+            }
+            Some(Daemon::Dunst) => {
+                if run("dunstctl", &["is-paused"])? == "true" {
+                    return Ok(None);
+                }
+                run("dunstctl", &["set-paused", "true"])?;
+                Ok(Some(Engaged::DunstPaused))
+            }
+            Some(Daemon::Mako) => {
+                if run("makoctl", &["mode"])?.lines().any(|mode| mode == MAKO_MODE) {
+                    return Ok(None);
+                }
+                run("makoctl", &["mode", "-a", MAKO_MODE])?;
+                Ok(Some(Engaged::MakoMode))
+            }
+            Some(Daemon::Swaync) => {
+                if run("swaync-client", &["--get-dnd", "--skip-wait"])? == "true" {
+                    return Ok(None);
+                }
+                run("swaync-client", &["--dnd-on", "--skip-wait"])?;
+                Ok(Some(Engaged::SwayncDnd))
+            }
+            None => Err(format!("Not supported with the {} notification daemon", name)),
+        }
+    }
+
+    pub fn restore(engaged: Engaged) -> Result<(), String> {
+        match engaged {
+            Engaged::GnomeBanners => run("gsettings", &["set", GNOME_SCHEMA, "show-banners", "true"]).map(drop),
+            Engaged::PlasmaInhibition(connection) => {
+                drop(connection);
+                Ok(())
+            }
+            Engaged::DunstPaused => run("dunstctl", &["set-paused", "false"]).map(drop),
+            Engaged::MakoMode => run("makoctl", &["mode", "-r", MAKO_MODE]).map(drop),
+            Engaged::SwayncDnd => run("swaync-client", &["--dnd-off", "--skip-wait"]).map(drop),
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use windows::core::{w, PCWSTR};
+    use windows::Win32::Foundation::ERROR_SUCCESS;
+    use windows::Win32::System::Registry::{
+        RegDeleteKeyValueW, RegGetValueW, RegSetKeyValueW, HKEY_CURRENT_USER, REG_DWORD, RRF_RT_REG_DWORD,
+    };
+
+    const KEY: PCWSTR = w!("Software\\Microsoft\\Windows\\CurrentVersion\\Notifications\\Settings");
+    /// 0 while Do Not Disturb is on
+    const TOASTS_ENABLED: PCWSTR = w!("NOC_GLOBAL_SETTING_TOASTS_ENABLED");
+
+    pub struct Engaged {
+        /// The setting before, or `None` if it wasn't set
+        previous: Option<u32>,
+    }
+
+    fn toasts_enabled() -> Option<u32> {
+        let mut value = 0u32;
+        let mut size = std::mem::size_of::<u32>() as u32;
+        let status = unsafe {
+            RegGetValueW(
+                HKEY_CURRENT_USER,
+                KEY,
+                TOASTS_ENABLED,
+                RRF_RT_REG_DWORD,
+                None,
+                Some(&mut value as *mut u32 as *mut _),
+                Some(&mut size),
+            )
+        };
+        (status == ERROR_SUCCESS).then_some(value)
+    }
+
+    fn set_toasts_enabled(value: u32) -> Result<(), String> {
+        let status = unsafe {
+            RegSetKeyValueW(
+                HKEY_CURRENT_USER,
+                KEY,
+                TOASTS_ENABLED,
+                REG_DWORD.0,
+                Some(&value as *const u32 as *const _),
+                std::mem::size_of::<u32>() as u32,
+            )
+        };
+        if status != ERROR_SUCCESS {
+            return Err(format!("Failed to write the notification setting: {:?}", status));
+        }
+        Ok(())
+    }
+
+    pub async fn engage() -> Result<Option<Engaged>, String> {
+        let previous = toasts_enabled();
+        if previous == Some(0) {
+            return Ok(None);
+        }
+        set_toasts_enabled(0)?;
+        Ok(Some(Engaged { previous }))
+    }
+
+    pub fn restore(engaged: Engaged) -> Result<(), String> {
+        match engaged.previous {
+            Some(value) => set_toasts_enabled(value),
+            None => {
+                let status = unsafe { RegDeleteKeyValueW(HKEY_CURRENT_USER, KEY, TOASTS_ENABLED) };
+                if status != ERROR_SUCCESS {
+                    return Err(format!("Failed to reset the notification setting: {:?}", status));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::process::Command;
+
+    const FOCUS_ON: &str = "Screen Recorder Focus On";
+    const FOCUS_OFF: &str = "Screen Recorder Focus Off";
+
+    pub struct Engaged;
+
+    fn run_shortcut(name: &str) -> Result<(), String> {
+        let output = Command::new("shortcuts")
+            .args(["run", name])
+            .output()
+            .map_err(|e| format!("Failed to execute shortcuts: {}", e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "Running the \"{}\" shortcut failed (create it with the Set Focus action): {}",
+                name,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(())
+    }
+
+    pub async fn engage() -> Result<Option<Engaged>, String> {
+        tokio::task::spawn_blocking(|| run_shortcut(FOCUS_ON))
+            .await
+            .map_err(|e| format!("Task error: {}", e))??;
+        Ok(Some(Engaged))
+    }
+
+    pub fn restore(_engaged: Engaged) -> Result<(), String> {
+        run_shortcut(FOCUS_OFF)
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+mod platform {
+    pub struct Engaged;
+
+    pub async fn engage() -> Result<Option<Engaged>, String> {
+        Err("Not supported on this platform".to_string())
+    }
+
+    pub fn restore(_engaged: Engaged) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_server_name() {
+        assert_eq!(Daemon::from_server_name("gnome-shell"), Some(Daemon::Gnome));
+        assert_eq!(Daemon::from_server_name("Plasma"), Some(Daemon::Plasma));
+        assert_eq!(Daemon::from_server_name("SwayNotificationCenter"), Some(Daemon::Swaync));
+        assert_eq!(Daemon::from_server_name("xfce4-notifyd"), None);
+    }
+}
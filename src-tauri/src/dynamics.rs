@@ -0,0 +1,149 @@
+//! Dynamics processing of captured audio: a noise gate, a compressor and
+//! makeup gain.
+//!
+//! Narration recorded without a DAW pass tends to have room noise between
+//! sentences and swing between mumbled and shouted. The gate pulls down
+//! whatever stays under its threshold, the compressor evens out what's
+//! above its own, and the makeup gain brings the result back up. They run
+//! as FFmpeg filters when the audio is muxed into the video, after it has
+//! been lined up (see `postprocess::mux`), so they apply to every captured
+//! track, and leave the temporary WAV untouched.
+
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+/// Noise gate settings.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GateSettings {
+    /// Level below which the audio is turned down, in dBFS
+    pub threshold_db: f64,
+    /// How far it's turned down, in dB
+    #[serde(default = "default_gate_range_db")]
+    pub range_db: f64,
+}
+
+fn default_gate_range_db() -> f64 {
+    24.0
+}
+
+/// Compressor settings.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CompressorSettings {
+    /// Level above which the audio is compressed, in dBFS
+    pub threshold_db: f64,
+    /// How much, e.g. 4.0 for 4:1
+    pub ratio: f64,
+}
+
+/// Which dynamics processing to apply, in the order listed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct DynamicsSettings {
+    #[serde(default)]
+    pub gate: Option<GateSettings>,
+    #[serde(default)]
+    pub compressor: Option<CompressorSettings>,
+    /// Gain applied last, in dB
+    #[serde(default)]
+    pub makeup_gain_db: f64,
+}
+
+/// Range thresholds may be set in, in dBFS.
+const THRESHOLD_RANGE_DB: (f64, f64) = (-80.0, 0.0);
+/// Largest gate range, in dB.
+const MAX_GATE_RANGE_DB: f64 = 80.0;
+/// Range of compressor ratios.
+const RATIO_RANGE: (f64, f64) = (1.0, 20.0);
+/// Largest makeup gain, in dB either way.
+const MAX_MAKEUP_GAIN_DB: f64 = 24.0;
+
+static SETTINGS: Mutex<DynamicsSettings> = Mutex::new(DynamicsSettings {
+    gate: None,
+    compressor: None,
+    makeup_gain_db: 0.0,
+});
+
+/// Set the dynamics processing for recordings saved from now on.
+pub fn set_settings(settings: DynamicsSettings) -> Result<(), String> {
+    let in_range = |value: f64, (min, max): (f64, f64)| (min..=max).contains(&value);
+    if let Some(gate) = settings.gate {
+        if !in_range(gate.threshold_db, THRESHOLD_RANGE_DB) || !in_range(gate.range_db, (0.0, MAX_GATE_RANGE_DB)) {
+            return Err(format!(
+                "The gate threshold must be {} to {} dB and its range 0 to {} dB",
+                THRESHOLD_RANGE_DB.0, THRESHOLD_RANGE_DB.1, MAX_GATE_RANGE_DB
+            ));
+        }
+    }
+    if let Some(compressor) = settings.compressor {
+        if !in_range(compressor.threshold_db, THRESHOLD_RANGE_DB) || !in_range(compressor.ratio, RATIO_RANGE) {
+            return Err(format!(
+                "The compressor threshold must be {} to {} dB and its ratio {}:1 to {}:1",
+                THRESHOLD_RANGE_DB.0, THRESHOLD_RANGE_DB.1, RATIO_RANGE.0, RATIO_RANGE.1
+            ));
+        }
+    }
+    if settings.makeup_gain_db.abs() > MAX_MAKEUP_GAIN_DB {
+        return Err(format!("The makeup gain can be at most {} dB", MAX_MAKEUP_GAIN_DB));
+    }
+    *SETTINGS.lock().unwrap() = settings;
+    Ok(())
+}
+
+/// The dynamics processing applied to recordings.
+pub fn settings() -> DynamicsSettings {
+    *SETTINGS.lock().unwrap()
+}
+
+impl DynamicsSettings {
+    /// FFmpeg audio filters applying these settings, or `None` if there's
+    /// nothing to do.
+    pub fn filter(&self) -> Option<String> {
+        let mut filters = Vec::new();
+        if let Some(gate) = self.gate {
+            // agate takes its threshold and range as linear amplitudes
+            filters.push(format!(
+                "agate=threshold={:.6}:range={:.6}:attack=5:release=150",
+                db_to_amplitude(gate.threshold_db),
+                db_to_amplitude(-gate.range_db)
+            ));
+        }
+        if let Some(compressor) = self.compressor {
+            filters.push(format!(
+                "acompressor=threshold={:.6}:ratio={}:attack=10:release=150",
+                db_to_amplitude(compressor.threshold_db),
+                compressor.ratio
+            ));
+        }
+        if self.makeup_gain_db != 0.0 {
+            filters.push(format!("volume={}dB", self.makeup_gain_db));
+        }
+        (!filters.is_empty()).then(|| filters.join(","))
+    }
+}
+
+fn db_to_amplitude(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dynamics_filter() {
+        assert_eq!(DynamicsSettings::default().filter(), None);
+        let settings = DynamicsSettings {
+            gate: Some(GateSettings { threshold_db: -40.0, range_db: 20.0 }),
+            compressor: Some(CompressorSettings { threshold_db: -20.0, ratio: 4.0 }),
+            makeup_gain_db: 6.0,
+        };
+        assert_eq!(
+            settings.filter().as_deref(),
+            Some(
+                "agate=threshold=0.010000:range=0.100000:attack=5:release=150,\
+                 acompressor=threshold=0.100000:ratio=4:attack=10:release=150,volume=6dB"
+            )
+        );
+        let too_loud = DynamicsSettings { makeup_gain_db: 40.0, ..settings };
+        assert!(set_settings(too_loud).is_err());
+    }
+}
@@ -0,0 +1,225 @@
+//! Adaptive bitrate for the live HLS stream.
+//!
+//! A viewer on a slow network can't download segments as fast as they're
+//! made, and playback stalls. The HLS server times how long each segment
+//! takes to reach a viewer, up to the viewer closing the connection, and
+//! compares it with the segment's length: when sending takes most of it the
+//! network is congested, and the stream's bitrate is lowered, then its frame
+//! rate halved once the bitrate is at its minimum. After a run of segments
+//! sent with time to spare it's raised again, a step at a time. Changes are
+//! published as [`CongestionEvent`]s for the frontend to warn about.
+
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// Event emitted with a [`CongestionEvent`] when the stream's quality changes.
+pub const CONGESTION_EVENT: &str = "network-congestion";
+
+/// Share of a segment's length its sending can take before the network
+/// counts as congested.
+const CONGESTED_LOAD: f64 = 0.7;
+
+/// Share of a segment's length below which sending is comfortable.
+const CALM_LOAD: f64 = 0.3;
+
+/// Comfortable segments in a row before quality is raised.
+const CALM_SEGMENTS: u32 = 5;
+
+/// Segments ignored after a change, since those already made or in flight
+/// are at the old bitrate.
+const HOLDOFF_SEGMENTS: u32 = 2;
+
+/// How much the bitrate drops when congested, and rises when calm.
+const DECREASE: f64 = 0.7;
+const INCREASE: f64 = 1.15;
+
+/// How the live stream adapts to the network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdaptiveBitrate {
+    /// Adapt the bitrate; without this the stream is encoded at constant quality
+    pub enabled: bool,
+    /// Bitrate the stream starts at and returns to
+    pub max_bitrate_kbps: u32,
+    /// Lowest bitrate it drops to
+    pub min_bitrate_kbps: u32,
+    /// Halve the frame rate when even the lowest bitrate is too much
+    pub lower_fps: bool,
+}
+
+impl AdaptiveBitrate {
+    /// Check that the bitrate range is usable.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min_bitrate_kbps == 0 {
+            return Err("Minimum bitrate must be greater than zero".to_string());
+        }
+        if self.min_bitrate_kbps > self.max_bitrate_kbps {
+            return Err("Minimum bitrate must not exceed the maximum".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl Default for AdaptiveBitrate {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_bitrate_kbps: 6000,
+            min_bitrate_kbps: 800,
+            lower_fps: true,
+        }
+    }
+}
+
+/// What the stream is encoded at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct StreamQuality {
+    pub bitrate_kbps: u32,
+    /// Every other frame is dropped
+    pub half_fps: bool,
+}
+
+/// The stream's quality changing with the network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CongestionEvent {
+    /// Lowered because of congestion, rather than raised again
+    pub congested: bool,
+    pub quality: StreamQuality,
+}
+
+/// Picks the stream's quality from how long segments take to send.
+pub struct BitrateController {
+    settings: AdaptiveBitrate,
+    quality: StreamQuality,
+    calm: u32,
+    holdoff: u32,
+}
+
+impl BitrateController {
+    /// Start at the highest quality `settings` allow.
+    pub fn new(settings: AdaptiveBitrate) -> Self {
+        Self {
+            settings,
+            quality: StreamQuality {
+                bitrate_kbps: settings.max_bitrate_kbps,
+                half_fps: false,
+            },
+            calm: 0,
+            holdoff: 0,
+        }
+    }
+
+    /// The quality to encode at now.
+    pub fn quality(&self) -> StreamQuality {
+        self.quality
+    }
+
+    /// Note that a `duration` long segment took `send_time` to reach a viewer,
+    /// returning the change of quality it calls for, if any.
+    pub fn observe(&mut self, send_time: Duration, duration: Duration) -> Option<CongestionEvent> {
+        if self.holdoff > 0 {
+            self.holdoff -= 1;
+            return None;
+        }
+        let load = send_time.as_secs_f64() / duration.as_secs_f64().max(0.001);
+        let before = self.quality;
+
+        let congested = if load > CONGESTED_LOAD {
+            self.calm = 0;
+            if self.quality.bitrate_kbps > self.settings.min_bitrate_kbps {
+                let lowered = (self.quality.bitrate_kbps as f64 * DECREASE) as u32;
+                self.quality.bitrate_kbps = lowered.max(self.settings.min_bitrate_kbps);
+            } else if self.settings.lower_fps {
+                self.quality.half_fps = true;
+            }
+            true
+        } else if load < CALM_LOAD {
+            self.calm += 1;
+            if self.calm < CALM_SEGMENTS {
+                return None;
+            }
+            self.calm = 0;
+            if self.quality.half_fps {
+                self.quality.half_fps = false;
+            } else {
+                let raised = (self.quality.bitrate_kbps as f64 * INCREASE).ceil() as u32;
+                self.quality.bitrate_kbps = raised.min(self.settings.max_bitrate_kbps);
+            }
+            false
+        } else {
+            self.calm = 0;
+            return None;
+        };
+
+        if self.quality == before {
+            return None;
+        }
+        self.holdoff = HOLDOFF_SEGMENTS;
+        Some(CongestionEvent {
+            congested,
+            quality: self.quality,
+        })
+    }
+}
+
+fn events() -> &'static broadcast::Sender<CongestionEvent> {
+    static EVENTS: OnceLock<broadcast::Sender<CongestionEvent>> = OnceLock::new();
+    EVENTS.get_or_init(|| broadcast::channel(16).0)
+}
+
+/// Receive the stream's quality changes.
+pub fn subscribe() -> broadcast::Receiver<CongestionEvent> {
+    events().subscribe()
+}
+
+/// Tell subscribers the stream's quality changed.
+pub fn publish(event: CongestionEvent) {
+    tracing::info!(
+        target: "HLS",
+        "{} bitrate to {} kbps{}",
+        if event.congested { "Network congested, lowering" } else { "Raising" },
+        event.quality.bitrate_kbps,
+        if event.quality.half_fps { " at half frame rate" } else { "" }
+    );
+    let _ = events().send(event);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_observe() {
+        let settings = AdaptiveBitrate {
+            enabled: true,
+            max_bitrate_kbps: 4000,
+            min_bitrate_kbps: 2000,
+            lower_fps: true,
+        };
+        let mut controller = BitrateController::new(settings);
+        let segment = Duration::from_secs(2);
+        let slow = Duration::from_millis(1800);
+        let fast = Duration::from_millis(200);
+
+        let event = controller.observe(slow, segment).unwrap();
+        assert!(event.congested);
+        assert_eq!(event.quality.bitrate_kbps, 2800);
+        // Segments at the old bitrate are ignored
+        assert_eq!(controller.observe(slow, segment), None);
+        assert_eq!(controller.observe(slow, segment), None);
+        assert_eq!(controller.observe(slow, segment).unwrap().quality.bitrate_kbps, 2000);
+        for _ in 0..HOLDOFF_SEGMENTS {
+            controller.observe(slow, segment);
+        }
+        assert!(controller.observe(slow, segment).unwrap().quality.half_fps);
+
+        // Recovers the frame rate first, then bitrate
+        for _ in 0..HOLDOFF_SEGMENTS + CALM_SEGMENTS - 1 {
+            assert_eq!(controller.observe(fast, segment), None);
+        }
+        let event = controller.observe(fast, segment).unwrap();
+        assert!(!event.congested);
+        assert_eq!(event.quality, StreamQuality { bitrate_kbps: 2000, half_fps: false });
+    }
+}
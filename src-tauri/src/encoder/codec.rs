@@ -0,0 +1,355 @@
+//! Output codecs and sizing for recordings.
+
+use super::rate::{cbr_args, vbr_args, RateControl};
+use crate::capture::hdr::SCRGB_WHITE_NITS;
+use crate::capture::ColorSpace;
+use serde::{Deserialize, Serialize};
+
+/// Video codec (and with it, container) for recordings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoCodec {
+    /// H.264 in MP4, the most compatible choice
+    #[default]
+    H264,
+    /// H.265/HEVC in MP4, smaller files at the same quality
+    H265,
+    /// VP9 in WebM
+    Vp9,
+    /// AV1 in MP4, the smallest files but the slowest encoder
+    Av1,
+    /// Animated GIF, for short clips
+    Gif,
+    /// FFV1 in MKV: lossless RGB for archival, with large files
+    Ffv1,
+    /// Ut Video in MKV: lossless RGB that's faster to encode than FFV1
+    /// but larger
+    UtVideo,
+    /// H.264 in RGB (libx264rgb) in MKV: lossless by default, or
+    /// near-lossless at a chosen quality, in much smaller files than FFV1
+    X264Rgb,
+    /// ProRes 4444 in MOV: a 10-bit intermediate for editing, with alpha
+    ProRes,
+    /// A folder of PNG images, one per frame, with alpha
+    Png,
+}
+
+impl VideoCodec {
+    /// File extension of the default container for this codec.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            VideoCodec::H264 | VideoCodec::H265 | VideoCodec::Av1 => "mp4",
+            VideoCodec::Vp9 => "webm",
+            VideoCodec::Gif => "gif",
+            VideoCodec::Ffv1 | VideoCodec::UtVideo | VideoCodec::X264Rgb => "mkv",
+            VideoCodec::ProRes => "mov",
+            VideoCodec::Png => "png",
+        }
+    }
+
+    /// Whether recordings are a folder of images rather than a video file.
+    pub fn is_image_sequence(&self) -> bool {
+        *self == VideoCodec::Png
+    }
+
+    /// Whether recordings can carry audio.
+    pub fn has_audio(&self) -> bool {
+        !matches!(self, VideoCodec::Gif | VideoCodec::Png)
+    }
+
+    /// RGB pixel format the codec encodes, for the codecs that keep frames
+    /// in RGB rather than converting them to YUV.
+    pub fn rgb_format(&self) -> Option<&'static str> {
+        match self {
+            VideoCodec::Ffv1 | VideoCodec::X264Rgb => Some("bgr0"),
+            VideoCodec::UtVideo => Some("gbrp"),
+            VideoCodec::Png => Some("rgb24"),
+            _ => None,
+        }
+    }
+
+    /// Pixel format keeping the alpha channel, for the codecs that can
+    /// encode transparency.
+    pub fn alpha_format(&self) -> Option<&'static str> {
+        match self {
+            VideoCodec::Vp9 => Some("yuva420p"),
+            VideoCodec::ProRes => Some("yuva444p10le"),
+            VideoCodec::Png => Some("rgba"),
+            _ => None,
+        }
+    }
+
+    /// Whether frames must be converted on the CPU (the codec needs a filter
+    /// graph or format the GPU conversion backends can't provide).
+    pub fn requires_cpu_conversion(&self) -> bool {
+        matches!(self, VideoCodec::Gif | VideoCodec::ProRes) || self.rgb_format().is_some()
+    }
+
+    /// Whether the codec can be encoded with 10 bits per channel.
+    pub fn supports_10bit(&self) -> bool {
+        matches!(self, VideoCodec::H265 | VideoCodec::Vp9 | VideoCodec::Av1)
+    }
+
+    /// Whether the codec can carry HDR10 video.
+    pub fn supports_hdr(&self) -> bool {
+        matches!(self, VideoCodec::H265 | VideoCodec::Av1)
+    }
+
+    /// FFmpeg's software encoder for this codec.
+    pub fn software_encoder(&self) -> &'static str {
+        match self {
+            VideoCodec::H264 => "libx264",
+            VideoCodec::H265 => "libx265",
+            VideoCodec::Vp9 => "libvpx-vp9",
+            VideoCodec::Av1 => "libsvtav1",
+            VideoCodec::Gif => "gif",
+            VideoCodec::Ffv1 => "ffv1",
+            VideoCodec::UtVideo => "utvideo",
+            VideoCodec::X264Rgb => "libx264rgb",
+            VideoCodec::ProRes => "prores_ks",
+            VideoCodec::Png => "png",
+        }
+    }
+
+    /// Quality level (CRF, or its nearest equivalent) for constant quality
+    /// encoding.
+    pub fn constant_quality(&self) -> u32 {
+        match self {
+            VideoCodec::H265 => 28,
+            VideoCodec::Vp9 => 32,
+            VideoCodec::Av1 => 35,
+            // x264's CRF 0 is lossless
+            VideoCodec::X264Rgb => 0,
+            _ => 23,
+        }
+    }
+
+    /// Whether the software encoder has a lossless mode.
+    pub fn supports_lossless(&self) -> bool {
+        matches!(
+            self,
+            VideoCodec::H264
+                | VideoCodec::H265
+                | VideoCodec::Vp9
+                | VideoCodec::Ffv1
+                | VideoCodec::UtVideo
+                | VideoCodec::X264Rgb
+                | VideoCodec::Png
+        )
+    }
+
+    /// Whether the rate control setting applies: GIF and ProRes have no
+    /// rate control, and FFV1, Ut Video and PNG are always lossless.
+    pub fn has_rate_control(&self) -> bool {
+        !matches!(
+            self,
+            VideoCodec::Gif | VideoCodec::Ffv1 | VideoCodec::UtVideo | VideoCodec::ProRes | VideoCodec::Png
+        )
+    }
+
+    /// FFmpeg software encoder arguments with the given rate control.
+    pub fn args(&self, rate_control: RateControl) -> Vec<String> {
+        // Fast presets: encoding has to keep up with real-time capture
+        let preset: &[&str] = match self {
+            VideoCodec::H264 | VideoCodec::H265 | VideoCodec::X264Rgb => &["-preset", "ultrafast"],
+            VideoCodec::Vp9 => &["-deadline", "realtime", "-cpu-used", "8", "-row-mt", "1"],
+            VideoCodec::Av1 => &["-preset", "12"],
+            VideoCodec::Gif => &["-loop", "0"],
+            // Every frame a keyframe, in slices encoded in parallel, each
+            // with a checksum so damage to an archive can be detected
+            VideoCodec::Ffv1 => &["-level", "3", "-g", "1", "-slices", "16", "-slicecrc", "1"],
+            // 4444 keeps full chroma and alpha
+            VideoCodec::ProRes => &["-profile:v", "4444", "-vendor", "apl0"],
+            VideoCodec::UtVideo | VideoCodec::Png => &[],
+        };
+        let mut args: Vec<String> = ["-c:v", self.software_encoder()]
+            .iter()
+            .chain(preset)
+            .map(|arg| arg.to_string())
+            .collect();
+        if !self.has_rate_control() {
+            return args;
+        }
+
+        let rate_args: Vec<String> = match rate_control {
+            RateControl::Quality { quality } => {
+                let crf = quality.unwrap_or(self.constant_quality()).to_string();
+                match self {
+                    // Constant quality mode for libvpx
+                    VideoCodec::Vp9 => vec!["-crf".to_string(), crf, "-b:v".to_string(), "0".to_string()],
+                    _ => vec!["-crf".to_string(), crf],
+                }
+            }
+            RateControl::Vbr { bitrate_kbps, max_bitrate_kbps } => {
+                vbr_args(bitrate_kbps, max_bitrate_kbps.unwrap_or(bitrate_kbps))
+            }
+            RateControl::Cbr { bitrate_kbps } => {
+                let mut args = cbr_args(bitrate_kbps);
+                if *self == VideoCodec::H264 {
+                    // x264 only pads to a true constant rate when asked to
+                    args.extend(["-x264-params".to_string(), "nal-hrd=cbr".to_string()]);
+                }
+                args
+            }
+            RateControl::Lossless => {
+                let lossless: &[&str] = match self {
+                    VideoCodec::H264 | VideoCodec::X264Rgb => &["-qp", "0"],
+                    VideoCodec::H265 => &["-x265-params", "lossless=1"],
+                    VideoCodec::Vp9 => &["-lossless", "1"],
+                    // SVT-AV1 has no lossless mode; this is its best quality
+                    _ => &["-crf", "0"],
+                };
+                lossless.iter().map(|arg| arg.to_string()).collect()
+            }
+        };
+        args.extend(rate_args);
+        args
+    }
+
+    /// Encoder arguments signaling the colorspace of SDR video made from
+    /// frames in `input`: their primaries with the sRGB transfer of screen
+    /// content, and the matrix and range of the `output` conversion.
+    pub fn sdr_color_args(&self, input: ColorSpace, output: ColorSpace) -> Vec<String> {
+        if *self == VideoCodec::Gif {
+            return Vec::new();
+        }
+        let primaries = input.standard.primaries();
+        if self.rgb_format().is_some() {
+            // RGB codecs keep the captured RGB values
+            return color_args(primaries, "iec61966-2-1", "gbr", input.range.ffmpeg_name());
+        }
+        color_args(primaries, "iec61966-2-1", output.standard.matrix(), output.range.ffmpeg_name())
+    }
+
+    /// Arguments for `encoder` signaling HDR10 (PQ transfer, BT.2020
+    /// primaries) in the stream.
+    pub fn hdr10_args(&self, encoder: &str) -> Vec<String> {
+        let mut args = color_args("bt2020", "smpte2084", "bt2020nc", "tv");
+        if encoder == "libx265" {
+            // x265 only writes the HDR10 SEI messages when asked to
+            args.extend(["-x265-params".to_string(), "hdr10=1:repeat-headers=1".to_string()]);
+        }
+        args
+    }
+
+    /// Filter converting linear scRGB half-float frames (1.0 = 80 nits) to
+    /// 10-bit HDR10 at `width`x`height`.
+    pub fn hdr10_filter(width: u32, height: u32) -> String {
+        format!(
+            "format=gbrpf32le,zscale=w={}:h={}:tin=linear:pin=bt709:min=gbr:rin=full:npl={}:t=smpte2084:p=bt2020:m=bt2020nc:r=tv,format=yuv420p10le",
+            width, height, SCRGB_WHITE_NITS
+        )
+    }
+
+    /// Filter scaling BGRA frames to `width`x`height` in the RGB `format`
+    /// of a lossless codec. Frames that aren't scaled keep their exact pixels.
+    pub fn rgb_filter(width: u32, height: u32, format: &str) -> String {
+        format!("scale={}:{}:flags=lanczos,format={}", width, height, format)
+    }
+
+    /// Filter converting BGRA frames to `width`x`height` in `format`, with
+    /// the matrix and range of `color` if it's YUV. Used for the codecs that
+    /// need 4:4:4 or alpha rather than the usual 4:2:0.
+    pub fn format_filter(width: u32, height: u32, format: &str, color: ColorSpace) -> String {
+        format!(
+            "scale={}:{}:flags=lanczos:out_color_matrix={}:out_range={},format={}",
+            width,
+            height,
+            color.standard.scale_matrix(),
+            color.range.ffmpeg_name(),
+            format
+        )
+    }
+
+    /// Filter converting BGRA frames to what a GIF encoder needs, at `width`x`height`.
+    ///
+    /// A palette is generated per frame so the filter can stream instead of
+    /// buffering the whole recording.
+    pub fn gif_filter(width: u32, height: u32) -> String {
+        format!(
+            "scale={}:{}:flags=lanczos,split[a][b];[a]palettegen=stats_mode=single[p];[b][p]paletteuse=new=1",
+            width, height
+        )
+    }
+}
+
+/// Arguments tagging the stream (and container) with a colorspace.
+fn color_args(primaries: &str, transfer: &str, matrix: &str, range: &str) -> Vec<String> {
+    [
+        "-color_primaries",
+        primaries,
+        "-color_trc",
+        transfer,
+        "-colorspace",
+        matrix,
+        "-color_range",
+        range,
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect()
+}
+
+/// Output size for a `width`x`height` capture limited to the given maximums,
+/// keeping the aspect ratio and rounding down to even dimensions.
+pub fn output_size(width: u32, height: u32, max_width: Option<u32>, max_height: Option<u32>) -> (u32, u32) {
+    let mut scale = 1.0f64;
+    if let Some(max_width) = max_width.filter(|&w| w > 0 && w < width) {
+        scale = scale.min(max_width as f64 / width as f64);
+    }
+    if let Some(max_height) = max_height.filter(|&h| h > 0 && h < height) {
+        scale = scale.min(max_height as f64 / height as f64);
+    }
+
+    let scaled = |value: u32| ((value as f64 * scale).round() as u32).max(2) & !1;
+    (scaled(width), scaled(height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_size() {
+        assert_eq!(output_size(3840, 2160, Some(1920), Some(1080)), (1920, 1080));
+        assert_eq!(output_size(1280, 720, Some(1920), Some(1080)), (1280, 720));
+        assert_eq!(output_size(2560, 1600, None, Some(1080)), (1728, 1080));
+        assert_eq!(output_size(1281, 721, None, None), (1280, 720));
+    }
+
+    #[test]
+    fn test_codec_args() {
+        assert_eq!(
+            VideoCodec::H264.args(RateControl::default()),
+            ["-c:v", "libx264", "-preset", "ultrafast", "-crf", "23"]
+        );
+        assert!(VideoCodec::H264
+            .args(RateControl::Vbr { bitrate_kbps: 8000, max_bitrate_kbps: None })
+            .windows(2)
+            .any(|w| w == ["-b:v", "8000k"]));
+        assert!(VideoCodec::H264
+            .args(RateControl::Cbr { bitrate_kbps: 6000 })
+            .windows(2)
+            .any(|w| w == ["-minrate", "6000k"]));
+        assert_eq!(VideoCodec::Ffv1.args(RateControl::default())[..2], ["-c:v", "ffv1"]);
+        assert!(VideoCodec::X264Rgb
+            .args(RateControl::default())
+            .ends_with(&["-crf".to_string(), "0".to_string()]));
+        assert!(VideoCodec::Vp9.args(RateControl::Lossless).ends_with(&["-lossless".to_string(), "1".to_string()]));
+        assert_eq!(
+            VideoCodec::ProRes.args(RateControl::default()),
+            ["-c:v", "prores_ks", "-profile:v", "4444", "-vendor", "apl0"]
+        );
+    }
+
+    #[test]
+    fn test_alpha_formats() {
+        assert_eq!(VideoCodec::H264.alpha_format(), None);
+        assert_eq!(VideoCodec::Vp9.alpha_format(), Some("yuva420p"));
+        assert!(VideoCodec::Png.is_image_sequence() && !VideoCodec::Png.has_audio());
+        assert_eq!(
+            VideoCodec::format_filter(1280, 720, "yuva444p10le", ColorSpace::VIDEO),
+            "scale=1280:720:flags=lanczos:out_color_matrix=bt709:out_range=tv,format=yuva444p10le"
+        );
+    }
+}
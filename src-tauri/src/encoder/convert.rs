@@ -0,0 +1,204 @@
+//! Color conversion and scaling stage of the encoder.
+//!
+//! Captured frames are BGRA, but the encoder wants NV12/YUV 4:2:0 (or its
+//! 10-bit equivalent, for recordings that avoid banding). At 4K60 the
+//! conversion (and any scaling) is a significant CPU cost, so when a supported
+//! GPU is available the frames are uploaded and converted with FFmpeg's
+//! hardware filters (CUDA, VAAPI, or Quick Sync) before being handed back to
+//! the encoder. Otherwise frames that don't need scaling are converted in
+//! process (see [`super::yuv`]), and FFmpeg's software scaler does the rest.
+
+use crate::capture::ColorSpace;
+use crate::gpu::{GpuTarget, GpuVendor};
+use ffmpeg_sidecar::command::FfmpegCommand;
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::sync::OnceLock;
+
+/// Where BGRA→NV12 conversion and scaling run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConversionBackend {
+    /// NVIDIA GPUs via CUDA
+    Cuda,
+    /// Intel/AMD GPUs on Linux via VA-API
+    Vaapi,
+    /// Intel GPUs via Quick Sync Video
+    Qsv,
+    /// Software conversion (in process, or libswscale when scaling)
+    Cpu,
+}
+
+/// Render node used for VA-API when the GPU's isn't known.
+const VAAPI_DEVICE: &str = "/dev/dri/renderD128";
+
+impl ConversionBackend {
+    /// GPU backends to try on this platform, in order of preference.
+    fn candidates() -> &'static [ConversionBackend] {
+        #[cfg(target_os = "windows")]
+        return &[ConversionBackend::Cuda, ConversionBackend::Qsv];
+        #[cfg(target_os = "linux")]
+        return &[ConversionBackend::Cuda, ConversionBackend::Vaapi, ConversionBackend::Qsv];
+        #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+        return &[];
+    }
+
+    /// Whether this backend runs on the GPU.
+    pub fn is_gpu(&self) -> bool {
+        *self != ConversionBackend::Cpu
+    }
+
+    /// Maker of the GPUs this backend runs on, or `None` if it isn't tied to one.
+    pub fn vendor(&self) -> Option<GpuVendor> {
+        match self {
+            ConversionBackend::Cuda => Some(GpuVendor::Nvidia),
+            ConversionBackend::Qsv => Some(GpuVendor::Intel),
+            ConversionBackend::Vaapi | ConversionBackend::Cpu => None,
+        }
+    }
+
+    /// Global FFmpeg arguments (before the input) needed by this backend,
+    /// opening its device on `target`, or FFmpeg's default GPU without one.
+    pub fn device_args(&self, target: Option<&GpuTarget>) -> Vec<String> {
+        let device = match (self, target) {
+            (ConversionBackend::Cuda, Some(target)) => format!("cuda=gpu:{}", target.ordinal),
+            (ConversionBackend::Cuda, None) => "cuda=gpu".to_string(),
+            (ConversionBackend::Vaapi, _) => format!(
+                "vaapi=gpu:{}",
+                target.and_then(|target| target.adapter.render_node.as_deref()).unwrap_or(VAAPI_DEVICE)
+            ),
+            (ConversionBackend::Qsv, Some(target)) => format!("qsv=gpu:hw_any,child_device={}", target.child_device()),
+            (ConversionBackend::Qsv, None) => "qsv=gpu".to_string(),
+            (ConversionBackend::Cpu, _) => return Vec::new(),
+        };
+        vec![
+            "-init_hw_device".to_string(),
+            device,
+            "-filter_hw_device".to_string(),
+            "gpu".to_string(),
+        ]
+    }
+
+    /// Whether this backend can convert to `color`. The CUDA and Quick Sync
+    /// filters can't be given a matrix or range and always produce BT.709
+    /// at limited range.
+    pub fn supports(&self, color: ColorSpace) -> bool {
+        match self {
+            ConversionBackend::Cuda | ConversionBackend::Qsv => color == ColorSpace::VIDEO,
+            ConversionBackend::Vaapi | ConversionBackend::Cpu => true,
+        }
+    }
+
+    /// Video filter converting BGRA input to `width`x`height` NV12 in system
+    /// memory, or with `ten_bit` set, to 10-bit 4:2:0, in the matrix and
+    /// range of `color`.
+    pub fn filter(&self, width: u32, height: u32, ten_bit: bool, color: ColorSpace) -> String {
+        // GPU filters produce P010; software encoders want planar 10-bit
+        let (gpu_format, download) = if ten_bit {
+            ("p010", "hwdownload,format=p010le,format=yuv420p10le")
+        } else {
+            ("nv12", "hwdownload,format=nv12")
+        };
+        match self {
+            ConversionBackend::Cuda => format!(
+                "hwupload_cuda,scale_cuda={}:{}:format={},{}",
+                width, height, gpu_format, download
+            ),
+            ConversionBackend::Vaapi => format!(
+                "format=bgra,hwupload,scale_vaapi=w={}:h={}:format={}:out_color_matrix={}:out_range={},{}",
+                width,
+                height,
+                gpu_format,
+                color.standard.matrix(),
+                color.range.ffmpeg_name(),
+                download
+            ),
+            ConversionBackend::Qsv => format!(
+                "format=bgra,hwupload=extra_hw_frames=16,vpp_qsv=w={}:h={}:format={},{}",
+                width, height, gpu_format, download
+            ),
+            ConversionBackend::Cpu => format!(
+                "scale={}:{}:out_color_matrix={}:out_range={},format={}",
+                width,
+                height,
+                color.standard.scale_matrix(),
+                color.range.ffmpeg_name(),
+                if ten_bit { "yuv420p10le" } else { "yuv420p" }
+            ),
+        }
+    }
+
+    /// Check whether this backend works on this machine by converting a test frame.
+    fn probe(&self) -> bool {
+        let mut command = FfmpegCommand::new();
+        command
+            .args(["-hide_banner", "-nostdin", "-loglevel", "error"])
+            .args(self.device_args(None))
+            .args(["-f", "lavfi", "-i", "color=c=black:s=256x256,format=bgra"])
+            .args(["-vf", &self.filter(256, 256, false, ColorSpace::VIDEO)])
+            .args(["-frames:v", "1", "-f", "null", "-"]);
+
+        let inner_command = command.as_inner_mut();
+        inner_command.stdin(Stdio::null());
+        inner_command.stdout(Stdio::null());
+        inner_command.stderr(Stdio::null());
+
+        inner_command.status().map(|s| s.success()).unwrap_or(false)
+    }
+}
+
+/// Detect the best available conversion backend.
+///
+/// Probing runs FFmpeg once per candidate, so the result is cached.
+pub fn detect_backend() -> ConversionBackend {
+    static DETECTED: OnceLock<ConversionBackend> = OnceLock::new();
+
+    *DETECTED.get_or_init(|| {
+        let backend = ConversionBackend::candidates()
+            .iter()
+            .copied()
+            .find(|backend| backend.probe())
+            .unwrap_or(ConversionBackend::Cpu);
+        tracing::info!(target: "Encoder", "Color conversion backend: {:?}", backend);
+        backend
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::types::{ColorRange, ColorStandard};
+
+    #[test]
+    fn test_cpu_backend_args() {
+        let backend = ConversionBackend::Cpu;
+        assert!(!backend.is_gpu());
+        assert!(backend.device_args(None).is_empty());
+        assert_eq!(
+            backend.filter(1920, 1080, false, ColorSpace::VIDEO),
+            "scale=1920:1080:out_color_matrix=bt709:out_range=tv,format=yuv420p"
+        );
+        assert!(backend.filter(1920, 1080, true, ColorSpace::VIDEO).ends_with("format=yuv420p10le"));
+        let sd_full = ColorSpace {
+            standard: ColorStandard::Bt601,
+            range: ColorRange::Full,
+        };
+        assert_eq!(
+            backend.filter(640, 480, false, sd_full),
+            "scale=640:480:out_color_matrix=bt601:out_range=pc,format=yuv420p"
+        );
+        assert!(!ConversionBackend::Cuda.supports(sd_full));
+    }
+
+    #[test]
+    fn test_cuda_filter() {
+        assert_eq!(
+            ConversionBackend::Cuda.filter(1280, 720, false, ColorSpace::VIDEO),
+            "hwupload_cuda,scale_cuda=1280:720:format=nv12,hwdownload,format=nv12"
+        );
+        assert_eq!(
+            ConversionBackend::Cuda.filter(1280, 720, true, ColorSpace::VIDEO),
+            "hwupload_cuda,scale_cuda=1280:720:format=p010,hwdownload,format=p010le,format=yuv420p10le"
+        );
+    }
+}
@@ -0,0 +1,146 @@
+//! Recording into memory, written to disk later.
+//!
+//! Short recordings can be held in RAM and written out in one go when they
+//! stop, so a spinning disk isn't woken up and an SD card isn't hit with
+//! many small writes. FFmpeg writes the container to a pipe, as for
+//! encrypted recordings, and it's collected in a buffer of bounded size.
+//! The buffer is written out whenever it fills up, after which recording
+//! carries on into the emptied buffer, and optionally at a fixed interval;
+//! the file isn't created until the first write.
+//!
+//! Whatever is still in memory is lost if the app crashes, and the file's
+//! size on disk lags behind the recording, so size limits and disk space
+//! checks see it late.
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Largest memory buffer allowed, in MB.
+pub const MAX_LIMIT_MB: u32 = 4096;
+
+/// How recordings are held in memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryBuffer {
+    /// Hold recordings in memory instead of writing them as they're encoded
+    pub enabled: bool,
+    /// Memory the buffer may use, in MB; a recording larger than this is
+    /// written out each time the buffer fills
+    pub limit_mb: u32,
+    /// Also write the buffer out this often, in seconds; 0 only writes when
+    /// the recording stops or the buffer fills
+    pub flush_interval_secs: u32,
+}
+
+impl MemoryBuffer {
+    /// Check that the limit is usable.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.limit_mb == 0 || self.limit_mb > MAX_LIMIT_MB {
+            return Err(format!("Memory buffer must be between 1 and {} MB", MAX_LIMIT_MB));
+        }
+        Ok(())
+    }
+}
+
+impl Default for MemoryBuffer {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            limit_mb: 256,
+            flush_interval_secs: 0,
+        }
+    }
+}
+
+/// Collects everything written to it in memory, writing it to `path` when
+/// the buffer fills, the flush interval passes, or it's flushed.
+pub struct DeferredWriter {
+    path: PathBuf,
+    file: Option<File>,
+    buffer: Vec<u8>,
+    limit: usize,
+    interval: Option<Duration>,
+    last_write: Instant,
+}
+
+impl DeferredWriter {
+    /// Buffer a recording to be written to `path` as `settings` say.
+    pub fn new(path: PathBuf, settings: &MemoryBuffer) -> Self {
+        Self {
+            path,
+            file: None,
+            buffer: Vec::new(),
+            limit: settings.limit_mb as usize * 1024 * 1024,
+            interval: (settings.flush_interval_secs > 0)
+                .then(|| Duration::from_secs(settings.flush_interval_secs.into())),
+            last_write: Instant::now(),
+        }
+    }
+
+    /// Write the buffer to the file, creating it if this is the first write.
+    fn write_out(&mut self) -> std::io::Result<()> {
+        self.last_write = Instant::now();
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let file = match self.file {
+            Some(ref mut file) => file,
+            None => self.file.insert(File::create(&self.path)?),
+        };
+        file.write_all(&self.buffer)?;
+        file.flush()?;
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+impl Write for DeferredWriter {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        // Fill the buffer to its limit, then write it out before going on
+        let taken = data.len().min(self.limit - self.buffer.len());
+        self.buffer.extend_from_slice(&data[..taken]);
+        if self.buffer.len() == self.limit {
+            tracing::info!(
+                target: "Encoder",
+                "Memory buffer full, writing {} MB to disk",
+                self.limit / (1024 * 1024)
+            );
+            self.write_out()?;
+        } else if self.interval.is_some_and(|interval| self.last_write.elapsed() >= interval) {
+            self.write_out()?;
+        }
+        Ok(taken)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.write_out()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deferred_writer() {
+        let path = std::env::temp_dir().join(format!("deferred-writer-test-{}.mp4", std::process::id()));
+        let settings = MemoryBuffer {
+            enabled: true,
+            limit_mb: 1,
+            flush_interval_secs: 0,
+        };
+        let mut writer = DeferredWriter::new(path.clone(), &settings);
+        let chunk = vec![7u8; 600 * 1024];
+
+        writer.write_all(&chunk).unwrap();
+        assert!(!path.exists(), "written before the buffer filled");
+        writer.write_all(&chunk).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 1024 * 1024);
+        writer.flush().unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 1200 * 1024);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
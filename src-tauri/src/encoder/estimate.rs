@@ -0,0 +1,140 @@
+//! Predicting how large recordings will be before they're made.
+//!
+//! With a bitrate set (VBR or CBR) the file grows at that rate. Constant
+//! quality and lossless recordings grow with how much the picture changes,
+//! so their bitrate is estimated from typical bits per pixel for the codec
+//! on screen content, halving for every 6 quality levels above the codec's
+//! default as x264's CRF does. Those estimates are rough: a static desktop
+//! comes out well under them and fast-moving games over.
+
+use super::codec::{output_size, VideoCodec};
+use super::rate::RateControl;
+use super::EncoderSettings;
+use serde::Serialize;
+
+/// Bitrate of the audio track added to recordings.
+const AUDIO_KBPS: u32 = 192;
+
+/// Predicted bitrate and size of recordings with some settings.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SizeEstimate {
+    /// Size of the encoded frames
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    /// Average bitrate of the video and audio, in kilobits per second
+    pub bitrate_kbps: u32,
+    pub bytes_per_minute: u64,
+    /// Whether the bitrate is a guess from the codec's quality rather than
+    /// set by the rate control
+    pub approximate: bool,
+    /// Free space where recordings are saved, if known
+    pub free_bytes: Option<u64>,
+    /// How many minutes of recording fit in the free space
+    pub minutes_available: Option<f64>,
+    /// Whether a recording of the requested length fits in the free space
+    pub fits: Option<bool>,
+}
+
+impl SizeEstimate {
+    /// Check the estimate against `free_bytes` of disk space, and whether a
+    /// recording of `duration_mins` fits.
+    pub fn with_free_space(self, free_bytes: u64, duration_mins: Option<u32>) -> Self {
+        let minutes_available = free_bytes as f64 / self.bytes_per_minute.max(1) as f64;
+        Self {
+            free_bytes: Some(free_bytes),
+            minutes_available: Some(minutes_available),
+            fits: duration_mins.map(|mins| mins as f64 <= minutes_available),
+            ..self
+        }
+    }
+}
+
+/// Estimate recordings of a `width`x`height` source with `settings`, with
+/// an audio track if `audio`.
+pub fn estimate(settings: &EncoderSettings, width: u32, height: u32, audio: bool) -> SizeEstimate {
+    let (width, height) = output_size(width, height, settings.max_width, settings.max_height);
+    let fps = settings.fps.max(1);
+    let codec = settings.codec;
+    let pixels_per_sec = width as f64 * height as f64 * fps as f64;
+    let (video_kbps, approximate) = match settings.rate_control {
+        _ if !codec.has_rate_control() => (pixels_per_sec * bits_per_pixel(codec, None) / 1000.0, true),
+        RateControl::Vbr { bitrate_kbps, .. } | RateControl::Cbr { bitrate_kbps } => (bitrate_kbps as f64, false),
+        RateControl::Quality { quality } => {
+            let quality = quality.unwrap_or(codec.constant_quality());
+            (pixels_per_sec * bits_per_pixel(codec, Some(quality)) / 1000.0, true)
+        }
+        RateControl::Lossless => (pixels_per_sec * bits_per_pixel(codec, None) / 1000.0, true),
+    };
+    let audio_kbps = if audio && codec.has_audio() { AUDIO_KBPS } else { 0 };
+    let bitrate_kbps = video_kbps.round() as u32 + audio_kbps;
+    SizeEstimate {
+        width,
+        height,
+        fps,
+        bitrate_kbps,
+        bytes_per_minute: bitrate_kbps as u64 * 1000 / 8 * 60,
+        approximate,
+        free_bytes: None,
+        minutes_available: None,
+        fits: None,
+    }
+}
+
+/// Typical bits per pixel of screen content encoded with `codec` at
+/// `quality`, or losslessly (or at its only setting) without one.
+fn bits_per_pixel(codec: VideoCodec, quality: Option<u32>) -> f64 {
+    let (default, lossless) = match codec {
+        VideoCodec::H264 => (0.1, 2.0),
+        VideoCodec::H265 | VideoCodec::Vp9 => (0.06, 1.6),
+        VideoCodec::Av1 => (0.045, 1.6),
+        VideoCodec::Gif => (0.5, 0.5),
+        VideoCodec::Ffv1 => (3.0, 3.0),
+        VideoCodec::UtVideo => (4.5, 4.5),
+        // Lossless by default
+        VideoCodec::X264Rgb => (1.5, 1.5),
+        VideoCodec::ProRes => (5.0, 5.0),
+        VideoCodec::Png => (3.5, 3.5),
+    };
+    match quality {
+        Some(quality) => default * 2f64.powf((codec.constant_quality() as f64 - quality as f64) / 6.0),
+        None => lossless,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate() {
+        let settings = EncoderSettings {
+            fps: 30,
+            codec: VideoCodec::H264,
+            rate_control: RateControl::Cbr { bitrate_kbps: 8000 },
+            ..EncoderSettings::default()
+        };
+        let estimate = estimate(&settings, 2560, 1440, true);
+        assert_eq!(estimate.bitrate_kbps, 8192);
+        assert_eq!(estimate.bytes_per_minute, 61_440_000);
+        assert!(!estimate.approximate);
+        let estimate = estimate.with_free_space(1_000_000_000, Some(20));
+        assert_eq!(estimate.fits, Some(false));
+        assert!((estimate.minutes_available.unwrap() - 16.276).abs() < 0.001);
+
+        // Capped to 1080p, and twice the bitrate six quality levels up
+        let settings = EncoderSettings {
+            max_height: Some(1080),
+            rate_control: RateControl::Quality { quality: Some(17) },
+            ..settings
+        };
+        let sharper = super::estimate(&settings, 3840, 2160, false);
+        let default = super::estimate(&EncoderSettings { rate_control: RateControl::default(), ..settings.clone() }, 3840, 2160, false);
+        assert_eq!((sharper.width, sharper.height), (1920, 1080));
+        assert!(sharper.approximate);
+        assert!(sharper.bitrate_kbps.abs_diff(default.bitrate_kbps * 2) <= 1);
+
+        let gif = EncoderSettings { codec: VideoCodec::Gif, ..settings };
+        assert!(super::estimate(&gif, 640, 360, true).bitrate_kbps > 0);
+    }
+}
@@ -0,0 +1,425 @@
+//! Helpers for running one-off FFmpeg jobs on existing files.
+
+use ffmpeg_sidecar::command::FfmpegCommand;
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Run FFmpeg with the given arguments and wait for it to exit.
+///
+/// This blocks; call it from a blocking task. On failure the last line of
+/// FFmpeg's stderr is included in the error.
+pub fn run_ffmpeg<I, S>(args: I) -> Result<(), String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<std::ffi::OsStr>,
+{
+    let mut command = FfmpegCommand::new();
+    command.args(["-hide_banner", "-nostdin"]).args(args);
+
+    let inner_command = command.as_inner_mut();
+    inner_command.stdin(Stdio::null());
+    inner_command.stdout(Stdio::null());
+    inner_command.stderr(Stdio::piped());
+
+    let mut child = inner_command
+        .spawn()
+        .map_err(|e| format!("Failed to start FFmpeg: {}", e))?;
+
+    let mut stderr_output = String::new();
+    if let Some(mut stderr) = child.stderr.take() {
+        let _ = stderr.read_to_string(&mut stderr_output);
+    }
+
+    let status = child
+        .wait()
+        .map_err(|e| format!("FFmpeg process error: {}", e))?;
+
+    if !status.success() {
+        return Err(format!(
+            "FFmpeg failed: {}",
+            stderr_output.lines().last().unwrap_or("unknown error")
+        ));
+    }
+
+    Ok(())
+}
+
+/// Run FFmpeg like [`run_ffmpeg`], reporting progress and honoring cancellation.
+///
+/// `duration` is the expected output duration in seconds; `on_progress` receives
+/// the completed fraction (0.0 to 1.0). Setting `cancel` kills FFmpeg.
+pub fn run_ffmpeg_with_progress<I, S>(
+    args: I,
+    duration: f64,
+    cancel: &AtomicBool,
+    mut on_progress: impl FnMut(f64),
+) -> Result<(), String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<std::ffi::OsStr>,
+{
+    let mut command = FfmpegCommand::new();
+    command
+        .args(["-hide_banner", "-nostdin", "-nostats"])
+        // Machine-readable key=value progress on stdout
+        .args(["-progress", "pipe:1"])
+        .args(args);
+
+    let inner_command = command.as_inner_mut();
+    inner_command.stdin(Stdio::null());
+    inner_command.stdout(Stdio::piped());
+    inner_command.stderr(Stdio::piped());
+
+    let mut child = inner_command
+        .spawn()
+        .map_err(|e| format!("Failed to start FFmpeg: {}", e))?;
+
+    // Drain stderr on a separate thread so FFmpeg never blocks on a full pipe
+    let stderr_reader = child.stderr.take().map(|mut stderr| {
+        std::thread::spawn(move || {
+            let mut output = String::new();
+            let _ = stderr.read_to_string(&mut output);
+            output
+        })
+    });
+
+    if let Some(stdout) = child.stdout.take() {
+        for line in BufReader::new(stdout).lines() {
+            if cancel.load(Ordering::Relaxed) {
+                let _ = child.kill();
+                break;
+            }
+            let Ok(line) = line else { break };
+            // out_time_us and (despite its name) out_time_ms are both microseconds
+            let micros = line
+                .strip_prefix("out_time_us=")
+                .or_else(|| line.strip_prefix("out_time_ms="));
+            if let Some(micros) = micros.and_then(|v| v.trim().parse::<f64>().ok()) {
+                if duration > 0.0 {
+                    on_progress((micros / 1_000_000.0 / duration).clamp(0.0, 1.0));
+                }
+            }
+        }
+    }
+
+    let status = child
+        .wait()
+        .map_err(|e| format!("FFmpeg process error: {}", e))?;
+    let stderr_output = stderr_reader
+        .and_then(|handle| handle.join().ok())
+        .unwrap_or_default();
+
+    if cancel.load(Ordering::Relaxed) {
+        return Err("Cancelled".to_string());
+    }
+    if !status.success() {
+        return Err(format!(
+            "FFmpeg failed: {}",
+            stderr_output.lines().last().unwrap_or("unknown error")
+        ));
+    }
+
+    on_progress(1.0);
+    Ok(())
+}
+
+/// Get the timestamps (in seconds) of the video keyframes in a file.
+///
+/// Only keyframes are decoded, so this is fast even for long recordings.
+pub fn keyframe_times(path: &Path) -> Result<Vec<f64>, String> {
+    let mut command = FfmpegCommand::new();
+    command
+        .args(["-hide_banner", "-nostdin", "-skip_frame", "nokey", "-i"])
+        .arg(path.to_string_lossy().to_string())
+        .args(["-map", "0:v:0", "-vf", "showinfo", "-f", "null", "-"]);
+
+    let inner_command = command.as_inner_mut();
+    inner_command.stdin(Stdio::null());
+    inner_command.stdout(Stdio::null());
+    inner_command.stderr(Stdio::piped());
+
+    let output = inner_command
+        .output()
+        .map_err(|e| format!("Failed to start FFmpeg: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("Could not read keyframes from {}", path.display()));
+    }
+
+    Ok(parse_showinfo_times(&String::from_utf8_lossy(&output.stderr)))
+}
+
+/// Extract `pts_time` values from showinfo filter output.
+fn parse_showinfo_times(stderr: &str) -> Vec<f64> {
+    stderr
+        .lines()
+        .filter(|line| line.contains("Parsed_showinfo"))
+        .filter_map(|line| {
+            let rest = &line[line.find("pts_time:")? + "pts_time:".len()..];
+            rest.split_whitespace().next()?.parse().ok()
+        })
+        .collect()
+}
+
+/// Basic information about a media file.
+#[derive(Debug, Clone, Default)]
+pub struct MediaInfo {
+    /// Duration in seconds
+    pub duration: f64,
+    pub width: u32,
+    pub height: u32,
+    /// Name of the video codec, such as `h264`
+    pub video_codec: String,
+    /// Video frame rate, or 0 if not reported
+    pub fps: f64,
+    pub has_audio: bool,
+}
+
+/// Probe a media file for its duration, video format, and whether it has audio.
+///
+/// Uses `ffmpeg -i`, which prints the stream summary to stderr, so no separate
+/// ffprobe binary is required.
+pub fn probe(path: &Path) -> Result<MediaInfo, String> {
+    // FFmpeg exits with an error because no output is given; only stderr matters
+    let stderr = ffmpeg_stderr(["-i".to_string(), path.to_string_lossy().to_string()])?;
+    parse_media_info(&stderr).ok_or_else(|| format!("Could not read media information from {}", path.display()))
+}
+
+/// Read the container-level metadata tags of a media file, with the keys
+/// in lowercase (Matroska stores them in uppercase).
+pub fn probe_tags(path: &Path) -> Result<BTreeMap<String, String>, String> {
+    let stderr = ffmpeg_stderr(["-i".to_string(), path.to_string_lossy().to_string()])?;
+    Ok(parse_tags(&stderr))
+}
+
+/// Run FFmpeg and return what it printed to stderr, whether or not it
+/// succeeded. For commands that only print information, such as listing
+/// devices, and then exit with an error.
+pub fn ffmpeg_stderr<I, S>(args: I) -> Result<String, String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<std::ffi::OsStr>,
+{
+    let mut command = FfmpegCommand::new();
+    command.args(["-hide_banner", "-nostdin"]).args(args);
+
+    let inner_command = command.as_inner_mut();
+    inner_command.stdin(Stdio::null());
+    inner_command.stdout(Stdio::null());
+    inner_command.stderr(Stdio::piped());
+
+    let output = inner_command
+        .output()
+        .map_err(|e| format!("Failed to start FFmpeg: {}", e))?;
+    Ok(String::from_utf8_lossy(&output.stderr).into_owned())
+}
+
+/// Run FFmpeg and return what it printed to stdout, for commands that print
+/// help or version information there.
+pub fn ffmpeg_stdout<I, S>(args: I) -> Result<String, String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<std::ffi::OsStr>,
+{
+    let mut command = FfmpegCommand::new();
+    command.args(["-hide_banner", "-nostdin"]).args(args);
+
+    let inner_command = command.as_inner_mut();
+    inner_command.stdin(Stdio::null());
+    inner_command.stdout(Stdio::piped());
+    inner_command.stderr(Stdio::null());
+
+    let output = inner_command
+        .output()
+        .map_err(|e| format!("Failed to start FFmpeg: {}", e))?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Run FFmpeg, passing what it writes to stdout to `on_data` as it arrives,
+/// for raw output too large to hold at once, such as decoded audio.
+pub fn ffmpeg_stream_stdout<I, S>(args: I, mut on_data: impl FnMut(&[u8])) -> Result<(), String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<std::ffi::OsStr>,
+{
+    let mut command = FfmpegCommand::new();
+    command.args(["-hide_banner", "-nostdin"]).args(args);
+
+    let inner_command = command.as_inner_mut();
+    inner_command.stdin(Stdio::null());
+    inner_command.stdout(Stdio::piped());
+    inner_command.stderr(Stdio::piped());
+
+    let mut child = inner_command
+        .spawn()
+        .map_err(|e| format!("Failed to start FFmpeg: {}", e))?;
+
+    // Drain stderr on a separate thread so FFmpeg never blocks on a full pipe
+    let stderr_reader = child.stderr.take().map(|mut stderr| {
+        std::thread::spawn(move || {
+            let mut output = String::new();
+            let _ = stderr.read_to_string(&mut output);
+            output
+        })
+    });
+
+    if let Some(mut stdout) = child.stdout.take() {
+        let mut buffer = [0u8; 64 * 1024];
+        loop {
+            match stdout.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => on_data(&buffer[..read]),
+            }
+        }
+    }
+
+    let status = child
+        .wait()
+        .map_err(|e| format!("FFmpeg process error: {}", e))?;
+    if !status.success() {
+        let stderr_output = stderr_reader
+            .and_then(|handle| handle.join().ok())
+            .unwrap_or_default();
+        return Err(format!(
+            "FFmpeg failed: {}",
+            stderr_output.lines().last().unwrap_or("unknown error")
+        ));
+    }
+    Ok(())
+}
+
+/// Parse the stream summary FFmpeg prints for an input file.
+fn parse_media_info(stderr: &str) -> Option<MediaInfo> {
+    let mut info = MediaInfo::default();
+    let mut found_duration = false;
+
+    for line in stderr.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("Duration:") {
+            let timestamp = rest.split(',').next()?.trim();
+            info.duration = parse_timestamp(timestamp)?;
+            found_duration = true;
+        } else if line.starts_with("Stream") && line.contains("Video:") && info.width == 0 {
+            // e.g. "Stream #0:0: Video: h264 (High), yuv420p(progressive), 1920x1080, ..., 30 fps, ..."
+            if let Some((width, height)) = line.split([',', ' ']).find_map(parse_dimensions) {
+                info.width = width;
+                info.height = height;
+            }
+            let (_, video) = line.split_once("Video:")?;
+            info.video_codec = video.split_whitespace().next().unwrap_or_default().trim_end_matches(',').to_string();
+            info.fps = video
+                .split(',')
+                .find_map(|field| field.trim().strip_suffix(" fps")?.parse().ok())
+                .unwrap_or(0.0);
+        } else if line.starts_with("Stream") && line.contains("Audio:") {
+            info.has_audio = true;
+        }
+    }
+
+    found_duration.then_some(info)
+}
+
+/// Parse the input's own metadata block from FFmpeg's summary, which comes
+/// before the duration; the streams' metadata blocks come after.
+fn parse_tags(stderr: &str) -> BTreeMap<String, String> {
+    let mut tags: BTreeMap<String, String> = BTreeMap::new();
+    let mut in_block = false;
+    let mut last_key: Option<String> = None;
+    for line in stderr.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("Duration:") {
+            break;
+        }
+        if trimmed == "Metadata:" {
+            in_block = true;
+            continue;
+        }
+        if !in_block {
+            continue;
+        }
+        let Some((key, value)) = trimmed.split_once(':') else {
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim());
+        if key.is_empty() {
+            // Further lines of a multi-line value
+            if let Some(tag) = last_key.as_ref().and_then(|key| tags.get_mut(key)) {
+                tag.push('\n');
+                tag.push_str(value);
+            }
+        } else {
+            let key = key.to_lowercase();
+            tags.insert(key.clone(), value.to_string());
+            last_key = Some(key);
+        }
+    }
+    tags
+}
+
+/// Parse an `HH:MM:SS.ss` timestamp into seconds.
+fn parse_timestamp(timestamp: &str) -> Option<f64> {
+    let mut parts = timestamp.split(':');
+    let hours: f64 = parts.next()?.parse().ok()?;
+    let minutes: f64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+/// Parse a `WIDTHxHEIGHT` token.
+fn parse_dimensions(token: &str) -> Option<(u32, u32)> {
+    let (width, height) = token.trim().split_once('x')?;
+    let (width, height) = (width.parse().ok()?, height.parse().ok()?);
+    // Rules out codec tags such as "0x31637661"
+    (width > 0 && height > 0).then_some((width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_media_info() {
+        let stderr = "Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'recording.mp4':\n  \
+            Duration: 00:01:05.50, start: 0.000000, bitrate: 1200 kb/s\n  \
+            Stream #0:0[0x1](und): Video: h264 (High) (avc1 / 0x31637661), yuv420p(progressive), 1920x1080, 1195 kb/s, 30 fps\n\
+            At least one output file must be specified";
+        let info = parse_media_info(stderr).unwrap();
+        assert!((info.duration - 65.5).abs() < 0.001);
+        assert_eq!((info.width, info.height), (1920, 1080));
+        assert_eq!((info.video_codec.as_str(), info.fps, info.has_audio), ("h264", 30.0, false));
+    }
+
+    #[test]
+    fn test_parse_showinfo_times() {
+        let stderr = "[Parsed_showinfo_0 @ 0x55d] n:   0 pts:      0 pts_time:0       duration:512\n\
+            [Parsed_showinfo_0 @ 0x55d] n:   1 pts: 128000 pts_time:8.33333 duration:512\n\
+            frame=    2 fps=0.0 q=-0.0 Lsize=N/A time=00:00:08.36";
+        assert_eq!(parse_showinfo_times(stderr), vec![0.0, 8.33333]);
+    }
+
+    #[test]
+    fn test_parse_tags() {
+        let stderr = "Input #0, matroska,webm, from 'recording.mkv':\n  \
+            Metadata:\n    \
+            title           : Demo: part 1\n    \
+            COMMENT         : First line\n                    \
+            : second line\n    \
+            ENCODER         : Lavf61.1.100\n  \
+            Duration: 00:00:05.00, start: 0.000000, bitrate: 900 kb/s\n  \
+            Stream #0:0: Video: h264 (High), yuv420p(progressive), 1280x720, 30 fps\n    \
+            Metadata:\n      \
+            DURATION        : 00:00:05.000000000";
+        let tags = parse_tags(stderr);
+        assert_eq!(tags.len(), 3);
+        assert_eq!(tags["title"], "Demo: part 1");
+        assert_eq!(tags["comment"], "First line\nsecond line");
+        assert!(parse_tags("No such file or directory").is_empty());
+    }
+
+    #[test]
+    fn test_parse_media_info_missing() {
+        assert!(parse_media_info("No such file or directory").is_none());
+    }
+}
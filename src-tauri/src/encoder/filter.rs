@@ -0,0 +1,125 @@
+//! Per-frame filters run on captured frames before they're encoded.
+//!
+//! Each recording runs a [`FilterChain`]: the mask hiding excluded windows
+//! and redactions comes first, then any filters registered with
+//! [`register_filter`]. Filters see every captured frame in order, before
+//! motion detection, the encoder and the other sinks, so whatever they draw
+//! or change is in the file, the preview and live outputs alike.
+//!
+//! Registering filters is behind the `frame-filters` feature, which exposes
+//! it (and the frame types) from the library for code building the app with
+//! its own filters, such as overlays or color effects.
+
+use crate::capture::mask::FrameMask;
+use crate::capture::CapturedFrame;
+
+/// A stage changing captured frames in place.
+pub trait FrameFilter: Send {
+    /// Change `frame`. Frames come in the capture's pixel format, which is
+    /// 8-bit BGRA unless the recording keeps 10-bit or HDR color.
+    fn process(&mut self, frame: &mut CapturedFrame);
+}
+
+impl FrameFilter for FrameMask {
+    fn process(&mut self, frame: &mut CapturedFrame) {
+        self.apply(frame);
+    }
+}
+
+/// Makes a new instance of a filter for each recording.
+#[cfg(feature = "frame-filters")]
+pub type FilterFactory = fn() -> Box<dyn FrameFilter>;
+
+#[cfg(feature = "frame-filters")]
+static FACTORIES: std::sync::Mutex<Vec<FilterFactory>> = std::sync::Mutex::new(Vec::new());
+
+/// Run a filter made by `factory` in every recording started from now on,
+/// after the filters registered before it.
+#[cfg(feature = "frame-filters")]
+pub fn register_filter(factory: FilterFactory) {
+    FACTORIES.lock().unwrap().push(factory);
+}
+
+/// The filters a recording runs, in order.
+#[derive(Default)]
+pub struct FilterChain {
+    filters: Vec<Box<dyn FrameFilter>>,
+}
+
+impl FilterChain {
+    /// The filters for a new recording: `mask`, if there is one, then the
+    /// registered filters.
+    pub fn for_recording(mask: Option<FrameMask>) -> Self {
+        let mut chain = Self::default();
+        if let Some(mask) = mask {
+            chain.push(Box::new(mask));
+        }
+        #[cfg(feature = "frame-filters")]
+        for factory in FACTORIES.lock().unwrap().iter() {
+            chain.push(factory());
+        }
+        chain
+    }
+
+    /// Add a filter after the others.
+    pub fn push(&mut self, filter: Box<dyn FrameFilter>) {
+        self.filters.push(filter);
+    }
+
+    /// Run every filter on `frame`.
+    pub fn process(&mut self, frame: &mut CapturedFrame) {
+        for filter in &mut self.filters {
+            filter.process(frame);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::{ColorSpace, PixelFormat};
+    use std::time::Instant;
+
+    /// Inverts the color channels.
+    struct Invert;
+
+    impl FrameFilter for Invert {
+        fn process(&mut self, frame: &mut CapturedFrame) {
+            for pixel in frame.data.chunks_exact_mut(4) {
+                for channel in &mut pixel[..3] {
+                    *channel = 255 - *channel;
+                }
+            }
+        }
+    }
+
+    /// Counts the frames it sees and sets the first byte to the count.
+    struct Count(u8);
+
+    impl FrameFilter for Count {
+        fn process(&mut self, frame: &mut CapturedFrame) {
+            self.0 += 1;
+            frame.data[0] = self.0;
+        }
+    }
+
+    #[test]
+    fn test_filter_chain() {
+        let mut chain = FilterChain::for_recording(None);
+        chain.push(Box::new(Invert));
+        chain.push(Box::new(Count(0)));
+        let mut frame = CapturedFrame {
+            width: 1,
+            height: 1,
+            data: vec![10, 20, 30, 255],
+            format: PixelFormat::Bgra8,
+            color: ColorSpace::SRGB,
+            captured_at: Instant::now(),
+            presented_at: None,
+        };
+        chain.process(&mut frame);
+        chain.process(&mut frame);
+        // Filters run in order and keep their state between frames
+        assert_eq!(frame.data, [2, 20, 30, 255]);
+    }
+}
@@ -0,0 +1,282 @@
+//! Hardware video encoders and the fallback chain between them.
+//!
+//! GPU encoders (NVENC, Quick Sync, AMF, VideoToolbox) take most of the
+//! encoding load off the CPU, but whether they work depends on the GPU, its
+//! driver and how FFmpeg was built. Recordings try the encoders in
+//! [`encoder_chain`] order, moving on to the next one when an encoder fails
+//! to start or fails mid-recording, and end with the software encoder.
+
+use super::codec::VideoCodec;
+use super::rate::{cbr_args, vbr_args, RateControl};
+use super::convert::ConversionBackend;
+use crate::gpu::{GpuTarget, GpuVendor};
+use ffmpeg_sidecar::command::FfmpegCommand;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::{Mutex, OnceLock};
+
+/// Which encoder implementation a recording uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EncoderBackend {
+    /// NVIDIA GPUs
+    Nvenc,
+    /// Intel GPUs via Quick Sync Video
+    Qsv,
+    /// AMD GPUs on Windows
+    Amf,
+    /// Apple's hardware encoder on macOS
+    VideoToolbox,
+    /// The codec's software encoder (x264, x265, libvpx, SVT-AV1)
+    Software,
+}
+
+impl EncoderBackend {
+    /// Hardware encoders to try on this platform, in order of preference.
+    pub fn candidates() -> &'static [EncoderBackend] {
+        #[cfg(target_os = "windows")]
+        return &[EncoderBackend::Nvenc, EncoderBackend::Qsv, EncoderBackend::Amf];
+        #[cfg(target_os = "linux")]
+        return &[EncoderBackend::Nvenc, EncoderBackend::Qsv];
+        #[cfg(target_os = "macos")]
+        return &[EncoderBackend::VideoToolbox];
+        #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+        return &[];
+    }
+
+    /// Maker of the GPUs this backend encodes on, or `None` for software.
+    pub fn vendor(&self) -> Option<GpuVendor> {
+        match self {
+            EncoderBackend::Nvenc => Some(GpuVendor::Nvidia),
+            EncoderBackend::Qsv => Some(GpuVendor::Intel),
+            EncoderBackend::Amf => Some(GpuVendor::Amd),
+            EncoderBackend::VideoToolbox => Some(GpuVendor::Apple),
+            EncoderBackend::Software => None,
+        }
+    }
+
+    /// FFmpeg encoder implementing `codec` with this backend, if there is one.
+    pub fn encoder_name(&self, codec: VideoCodec) -> Option<&'static str> {
+        use VideoCodec::*;
+        match (self, codec) {
+            (EncoderBackend::Software, codec) => Some(codec.software_encoder()),
+            (EncoderBackend::Nvenc, H264) => Some("h264_nvenc"),
+            (EncoderBackend::Nvenc, H265) => Some("hevc_nvenc"),
+            (EncoderBackend::Nvenc, Av1) => Some("av1_nvenc"),
+            (EncoderBackend::Qsv, H264) => Some("h264_qsv"),
+            (EncoderBackend::Qsv, H265) => Some("hevc_qsv"),
+            (EncoderBackend::Qsv, Vp9) => Some("vp9_qsv"),
+            (EncoderBackend::Qsv, Av1) => Some("av1_qsv"),
+            (EncoderBackend::Amf, H264) => Some("h264_amf"),
+            (EncoderBackend::Amf, H265) => Some("hevc_amf"),
+            (EncoderBackend::Amf, Av1) => Some("av1_amf"),
+            (EncoderBackend::VideoToolbox, H264) => Some("h264_videotoolbox"),
+            (EncoderBackend::VideoToolbox, H265) => Some("hevc_videotoolbox"),
+            _ => None,
+        }
+    }
+
+    /// Whether this backend can encode `codec` with `rate_control`.
+    pub fn supports(&self, codec: VideoCodec, rate_control: RateControl) -> bool {
+        if self.encoder_name(codec).is_none() {
+            return false;
+        }
+        match (self, rate_control) {
+            (EncoderBackend::Software, RateControl::Lossless) => codec.supports_lossless(),
+            // Of the hardware encoders only NVENC's H.264 and HEVC are lossless
+            (EncoderBackend::Nvenc, RateControl::Lossless) => matches!(codec, VideoCodec::H264 | VideoCodec::H265),
+            (_, RateControl::Lossless) => false,
+            _ => true,
+        }
+    }
+
+    /// FFmpeg encoder arguments for `codec` with the given rate control.
+    pub fn args(&self, codec: VideoCodec, rate_control: RateControl) -> Vec<String> {
+        let Some(name) = self.encoder_name(codec).filter(|_| *self != EncoderBackend::Software) else {
+            return codec.args(rate_control);
+        };
+
+        // Lowest-latency presets: encoding has to keep up with real-time capture
+        let preset: &[&str] = match self {
+            EncoderBackend::Nvenc if rate_control == RateControl::Lossless => &["-preset", "p1", "-tune", "lossless"],
+            EncoderBackend::Nvenc => &["-preset", "p1", "-tune", "ll"],
+            EncoderBackend::Qsv => &["-preset", "veryfast"],
+            EncoderBackend::Amf => &["-quality", "speed"],
+            EncoderBackend::VideoToolbox => &["-realtime", "1"],
+            EncoderBackend::Software => &[],
+        };
+        let mut args: Vec<String> = ["-c:v", name].iter().chain(preset).map(|arg| arg.to_string()).collect();
+
+        let (mode, rate_args): (&[&str], Vec<String>) = match rate_control {
+            RateControl::Quality { quality } => {
+                let quality = quality.unwrap_or(codec.constant_quality()).to_string();
+                let quality_args: Vec<&str> = match self {
+                    EncoderBackend::Nvenc => vec!["-rc", "vbr", "-cq", &quality, "-b:v", "0"],
+                    EncoderBackend::Qsv => vec!["-global_quality", &quality],
+                    EncoderBackend::Amf => vec!["-rc", "cqp", "-qp_i", &quality, "-qp_p", &quality],
+                    // VideoToolbox's quality scale runs the other way, 1-100
+                    _ => vec!["-q:v", "65"],
+                };
+                (&[], quality_args.iter().map(|arg| arg.to_string()).collect())
+            }
+            RateControl::Vbr { bitrate_kbps, max_bitrate_kbps } => {
+                let mode: &[&str] = match self {
+                    EncoderBackend::Nvenc => &["-rc", "vbr"],
+                    EncoderBackend::Amf => &["-rc", "vbr_peak"],
+                    _ => &[],
+                };
+                (mode, vbr_args(bitrate_kbps, max_bitrate_kbps.unwrap_or(bitrate_kbps)))
+            }
+            RateControl::Cbr { bitrate_kbps } => {
+                let mode: &[&str] = match self {
+                    EncoderBackend::Nvenc | EncoderBackend::Amf => &["-rc", "cbr"],
+                    EncoderBackend::VideoToolbox => &["-constant_bit_rate", "1"],
+                    // QSV switches to CBR when the maximum equals the target
+                    _ => &[],
+                };
+                (mode, cbr_args(bitrate_kbps))
+            }
+            // Set by the tune above
+            RateControl::Lossless => (&[], Vec::new()),
+        };
+        args.extend(mode.iter().map(|arg| arg.to_string()));
+        args.extend(rate_args);
+        args
+    }
+
+    /// Global FFmpeg arguments (before the input) opening this backend's
+    /// device on `target`. The encoder picks the device up by its type;
+    /// Quick Sync shares the one color conversion opened when that runs on
+    /// Quick Sync too.
+    pub fn device_args(&self, target: Option<&GpuTarget>, conversion: ConversionBackend) -> Vec<String> {
+        let device = match (self, target) {
+            (EncoderBackend::Qsv, Some(_)) if conversion == ConversionBackend::Qsv => return Vec::new(),
+            (EncoderBackend::Qsv, Some(target)) => format!("qsv=enc:hw_any,child_device={}", target.child_device()),
+            (EncoderBackend::Amf, Some(target)) => format!("d3d11va=enc:{}", target.adapter.index),
+            _ => return Vec::new(),
+        };
+        vec!["-init_hw_device".to_string(), device]
+    }
+
+    /// Encoder arguments selecting `target`, for encoders that take the GPU
+    /// as an option rather than a device. NVENC counts GPUs the way CUDA
+    /// does, which FFmpeg is told to do by PCI bus (see `CUDA_DEVICE_ORDER`).
+    pub fn target_args(&self, target: Option<&GpuTarget>) -> Vec<String> {
+        match (self, target) {
+            (EncoderBackend::Nvenc, Some(target)) => vec!["-gpu".to_string(), target.ordinal.to_string()],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Pixel format the encoder's input has to be converted to, if it can't
+    /// take the planar YUV the software encoders use.
+    pub fn input_format(&self, ten_bit: bool) -> Option<&'static str> {
+        match self {
+            EncoderBackend::Software => None,
+            _ if ten_bit => Some("p010le"),
+            _ => Some("nv12"),
+        }
+    }
+
+    /// Check whether this backend can encode `codec` on this machine by
+    /// encoding a test frame.
+    fn probe(&self, codec: VideoCodec) -> bool {
+        let Some(name) = self.encoder_name(codec) else {
+            return false;
+        };
+        let mut command = FfmpegCommand::new();
+        command
+            .args(["-hide_banner", "-nostdin", "-loglevel", "error"])
+            .args(["-f", "lavfi", "-i", "color=c=black:s=256x256,format=nv12"])
+            .args(["-frames:v", "1", "-c:v", name, "-f", "null", "-"]);
+
+        let inner_command = command.as_inner_mut();
+        inner_command.stdin(Stdio::null());
+        inner_command.stdout(Stdio::null());
+        inner_command.stderr(Stdio::null());
+
+        inner_command.status().map(|s| s.success()).unwrap_or(false)
+    }
+}
+
+/// Encoders to try for `codec` with `rate_control`, in order: the working
+/// hardware encoders that support it if `hardware` is set, then software.
+///
+/// Probing runs FFmpeg once per encoder, so the results are cached.
+pub fn encoder_chain(codec: VideoCodec, rate_control: RateControl, hardware: bool) -> Vec<EncoderBackend> {
+    static PROBED: OnceLock<Mutex<HashMap<&'static str, bool>>> = OnceLock::new();
+
+    let mut chain = Vec::new();
+    if hardware {
+        let mut probed = PROBED.get_or_init(Default::default).lock().unwrap();
+        for &backend in EncoderBackend::candidates() {
+            let Some(name) = backend.encoder_name(codec).filter(|_| backend.supports(codec, rate_control)) else {
+                continue;
+            };
+            let works = *probed.entry(name).or_insert_with(|| {
+                let works = backend.probe(codec);
+                tracing::info!(target: "Encoder", "{} {}", name, if works { "available" } else { "unavailable" });
+                works
+            });
+            if works {
+                chain.push(backend);
+            }
+        }
+    }
+    chain.push(EncoderBackend::Software);
+    chain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoder_args() {
+        assert_eq!(
+            EncoderBackend::Nvenc.args(VideoCodec::H264, RateControl::default()),
+            ["-c:v", "h264_nvenc", "-preset", "p1", "-tune", "ll", "-rc", "vbr", "-cq", "23", "-b:v", "0"]
+        );
+        assert!(EncoderBackend::Qsv
+            .args(VideoCodec::H265, RateControl::Vbr { bitrate_kbps: 8000, max_bitrate_kbps: None })
+            .windows(2)
+            .any(|w| w == ["-b:v", "8000k"]));
+        assert_eq!(
+            EncoderBackend::Software.args(VideoCodec::Vp9, RateControl::default()),
+            VideoCodec::Vp9.args(RateControl::default())
+        );
+        assert!(EncoderBackend::Nvenc.supports(VideoCodec::H265, RateControl::Lossless));
+        assert!(!EncoderBackend::Qsv.supports(VideoCodec::H264, RateControl::Lossless));
+        assert_eq!(EncoderBackend::VideoToolbox.encoder_name(VideoCodec::Av1), None);
+        assert_eq!(EncoderBackend::Nvenc.input_format(true), Some("p010le"));
+        assert_eq!(encoder_chain(VideoCodec::Gif, RateControl::default(), false), [EncoderBackend::Software]);
+    }
+
+    #[test]
+    fn test_device_args() {
+        let target = |vendor, index, ordinal| GpuTarget {
+            adapter: crate::gpu::GpuAdapter {
+                index,
+                name: String::new(),
+                vendor,
+                dedicated_memory_mb: 0,
+                render_node: Some("/dev/dri/renderD129".to_string()),
+            },
+            ordinal,
+        };
+        let nvidia = target(GpuVendor::Nvidia, 1, 1);
+        assert!(EncoderBackend::Nvenc.device_args(Some(&nvidia), ConversionBackend::Cpu).is_empty());
+        assert_eq!(EncoderBackend::Nvenc.target_args(Some(&nvidia)), ["-gpu", "1"]);
+        let intel = target(GpuVendor::Intel, 1, 0);
+        assert_eq!(
+            EncoderBackend::Qsv.device_args(Some(&intel), ConversionBackend::Cpu),
+            ["-init_hw_device", "qsv=enc:hw_any,child_device=/dev/dri/renderD129"]
+        );
+        // Conversion already opened the device
+        assert!(EncoderBackend::Qsv.device_args(Some(&intel), ConversionBackend::Qsv).is_empty());
+        assert_eq!(ConversionBackend::Vaapi.device_args(Some(&intel))[1], "vaapi=gpu:/dev/dri/renderD129");
+        assert_eq!(ConversionBackend::Cuda.device_args(Some(&nvidia))[1], "cuda=gpu:1");
+        assert!(EncoderBackend::Software.device_args(None, ConversionBackend::Cpu).is_empty());
+    }
+}
@@ -0,0 +1,491 @@
+//! Live HLS output, served to this machine or the local network.
+//!
+//! While a recording is running, a second FFmpeg process encodes the frames
+//! into a rolling HLS playlist in a private temporary directory, and a small embedded
+//! HTTP server exposes it (plus a player page) to watch with just a browser.
+//! The server only listens on the loopback interface unless LAN viewing is
+//! turned on; then colleagues on the LAN can watch too, but every path
+//! starts with a token only the URL the app hands out carries. With
+//! adaptive bitrate on, the server times
+//! segment downloads and the encoder is restarted at a lower bitrate when
+//! viewers can't keep up (see [`super::abr`]).
+
+use super::abr::{self, AdaptiveBitrate, BitrateController, StreamQuality};
+use super::codec::VideoCodec;
+use super::convert::ConversionBackend;
+use super::rate::vbr_args;
+use super::sink::FrameSink;
+use super::write_raw_frame;
+use crate::capture::{CapturedFrame, ColorSpace};
+use crate::control;
+use crate::http;
+use ffmpeg_sidecar::command::FfmpegCommand;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use subtle::ConstantTimeEq;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Default port for the HLS server.
+pub const DEFAULT_PORT: u16 = 8787;
+
+/// Playlist file name inside the output directory.
+const PLAYLIST_NAME: &str = "index.m3u8";
+
+/// Length of each segment, in seconds.
+const SEGMENT_SECONDS: u32 = 2;
+
+/// hls.js, copied from the frontend's packages by the build script; empty if
+/// they weren't installed.
+const HLS_JS: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/hls.min.js"));
+
+/// Player page served at the root URL. Safari plays HLS natively; other
+/// browsers use hls.js, served alongside it.
+const PLAYER_HTML: &str = r#"<!doctype html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Screen Recorder - Live</title>
+<style>
+  body { margin: 0; background: #111; display: flex; align-items: center; justify-content: center; height: 100vh; }
+  video { max-width: 100%; max-height: 100%; }
+</style>
+</head>
+<body>
+<video id="video" controls autoplay muted playsinline></video>
+<script src="hls.min.js"></script>
+<script>
+  const video = document.getElementById("video");
+  if (video.canPlayType("application/vnd.apple.mpegurl")) {
+    video.src = "index.m3u8";
+  } else if (window.Hls && Hls.isSupported()) {
+    const hls = new Hls({ liveSyncDurationCount: 2 });
+    hls.loadSource("index.m3u8");
+    hls.attachMedia(video);
+  }
+</script>
+</body>
+</html>
+"#;
+
+/// HLS output settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HlsSettings {
+    /// Whether recordings are also served as a live HLS stream
+    pub enabled: bool,
+    /// TCP port for the embedded HTTP server
+    pub port: u16,
+    /// Serve the stream to the local network rather than only this machine
+    #[serde(default)]
+    pub lan: bool,
+    /// Secret the stream's paths start with when it's served to the LAN
+    #[serde(default)]
+    pub token: String,
+    /// Lower the bitrate when viewers' networks can't keep up
+    #[serde(default)]
+    pub adaptive: AdaptiveBitrate,
+}
+
+impl Default for HlsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: DEFAULT_PORT,
+            lan: false,
+            token: String::new(),
+            adaptive: AdaptiveBitrate::default(),
+        }
+    }
+}
+
+impl HlsSettings {
+    /// The settings for a stream on `port`, with a new token if it's served
+    /// to the LAN.
+    pub fn new(enabled: bool, port: u16, lan: bool, adaptive: AdaptiveBitrate) -> Self {
+        Self {
+            enabled,
+            port,
+            lan,
+            token: if lan { control::new_token() } else { String::new() },
+            adaptive,
+        }
+    }
+
+    /// URL viewers should open.
+    pub fn stream_url(&self) -> String {
+        if self.lan {
+            format!("http://{}:{}/{}/", http::local_ip(), self.port, self.token)
+        } else {
+            format!("http://127.0.0.1:{}/", self.port)
+        }
+    }
+}
+
+/// Create a directory for the live playlist and segments that only this
+/// user can open, in the runtime directory where there is one. It's new
+/// each time, with a random name, so nothing can be planted there first.
+fn create_output_dir() -> Result<PathBuf, String> {
+    let parent = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_dir())
+        .unwrap_or_else(std::env::temp_dir);
+    let dir = parent.join(format!("screen-recorder-hls-{}", control::new_token()));
+    let mut builder = std::fs::DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder
+        .create(&dir)
+        .map_err(|e| format!("Failed to create HLS directory: {}", e))?;
+    Ok(dir)
+}
+
+/// Frame sink that encodes a live HLS stream and serves it over HTTP.
+pub struct HlsSink {
+    output_dir: PathBuf,
+    child: Option<Child>,
+    stdin: Option<ChildStdin>,
+    width: u32,
+    height: u32,
+    fps: u32,
+    server: tokio::task::JoinHandle<()>,
+    /// Set with adaptive bitrate on
+    controller: Option<Arc<Mutex<BitrateController>>>,
+    /// What the running encoder was started at
+    quality: Option<StreamQuality>,
+    frames: u64,
+}
+
+impl HlsSink {
+    /// Create the output directory and start the HTTP server on the port in
+    /// `settings`. Frames are expected at `fps`.
+    ///
+    /// The FFmpeg process is started lazily once the first frame reveals the
+    /// capture dimensions. Must be called from within the tokio runtime.
+    pub fn new(settings: &HlsSettings, fps: u32) -> Result<Self, String> {
+        let port = settings.port;
+        let host = if settings.lan { "0.0.0.0" } else { "127.0.0.1" };
+        let listener = std::net::TcpListener::bind((host, port))
+            .map_err(|e| format!("Failed to bind HLS server to port {}: {}", port, e))?;
+        listener
+            .set_nonblocking(true)
+            .map_err(|e| format!("Failed to configure HLS server socket: {}", e))?;
+        let listener = TcpListener::from_std(listener)
+            .map_err(|e| format!("Failed to start HLS server: {}", e))?;
+        // A new directory, so viewers never see segments from a previous recording
+        let output_dir = create_output_dir()?;
+
+        let controller = settings
+            .adaptive
+            .enabled
+            .then(|| Arc::new(Mutex::new(BitrateController::new(settings.adaptive))));
+        let token = settings.lan.then(|| settings.token.clone());
+        let server = tokio::spawn(serve(listener, output_dir.clone(), token, controller.clone()));
+        tracing::info!(target: "HLS", "Serving live stream on port {}", port);
+
+        Ok(Self {
+            output_dir,
+            child: None,
+            stdin: None,
+            width: 0,
+            height: 0,
+            fps,
+            server,
+            controller,
+            quality: None,
+            frames: 0,
+        })
+    }
+
+    /// Start the FFmpeg HLS encoder for the given frame dimensions.
+    fn start_encoder(&mut self, width: u32, height: u32) -> Result<(), String> {
+        // Even dimensions are required by the H.264 encoder
+        let width = width & !1;
+        let height = height & !1;
+        if width == 0 || height == 0 {
+            return Err(format!("Invalid dimensions: {}x{}", width, height));
+        }
+
+        let segment_pattern = self.output_dir.join("segment_%05d.ts");
+        let playlist = self.output_dir.join(PLAYLIST_NAME);
+        // Every other frame is dropped at half frame rate
+        let fps = match self.quality {
+            Some(StreamQuality { half_fps: true, .. }) => (self.fps / 2).max(1),
+            _ => self.fps,
+        };
+        let keyframe_interval = (fps * SEGMENT_SECONDS).to_string();
+        // A restarted encoder carries on the playlist rather than replacing it
+        let hls_flags = if self.quality.is_some() {
+            "delete_segments+independent_segments+append_list+discont_start+omit_endlist"
+        } else {
+            "delete_segments+independent_segments"
+        };
+        let rate_args = match self.quality {
+            Some(quality) => vbr_args(quality.bitrate_kbps, quality.bitrate_kbps),
+            None => Vec::new(),
+        };
+
+        let mut command = FfmpegCommand::new();
+        command
+            .args(["-f", "rawvideo"])
+            .args(["-pix_fmt", "bgra"])
+            .args(["-s", &format!("{}x{}", width, height)])
+            .args(["-r", &fps.to_string()])
+            .args(["-i", "-"])
+            .args(["-c:v", "libx264"])
+            .args(["-preset", "veryfast"])
+            .args(["-tune", "zerolatency"])
+            // Fixed GOP so every segment starts with a keyframe
+            .args(["-g", &keyframe_interval])
+            .args(["-sc_threshold", "0"])
+            .args(rate_args)
+            // Converted and tagged explicitly; FFmpeg's default is untagged BT.601
+            .args(["-vf", &ConversionBackend::Cpu.filter(width, height, false, ColorSpace::VIDEO)])
+            .args(VideoCodec::H264.sdr_color_args(ColorSpace::SRGB, ColorSpace::VIDEO))
+            .args(["-f", "hls"])
+            .args(["-hls_time", &SEGMENT_SECONDS.to_string()])
+            .args(["-hls_list_size", "6"])
+            .args(["-hls_flags", hls_flags])
+            .arg("-hls_segment_filename")
+            .arg(segment_pattern.to_string_lossy().to_string())
+            .args(["-y"])
+            .arg(playlist.to_string_lossy().to_string());
+
+        let inner_command = command.as_inner_mut();
+        inner_command.stdin(Stdio::piped());
+        inner_command.stdout(Stdio::null());
+        inner_command.stderr(Stdio::null());
+
+        let mut child = inner_command
+            .spawn()
+            .map_err(|e| format!("Failed to start FFmpeg for HLS: {}", e))?;
+        let stdin = child.stdin.take().ok_or("Failed to get FFmpeg stdin")?;
+
+        self.width = width;
+        self.height = height;
+        self.stdin = Some(stdin);
+        self.child = Some(child);
+        Ok(())
+    }
+
+    /// Close the encoder input and wait for FFmpeg to exit.
+    fn stop_encoder(&mut self) {
+        drop(self.stdin.take());
+        if let Some(mut child) = self.child.take() {
+            let _ = child.wait();
+        }
+    }
+}
+
+impl FrameSink for HlsSink {
+    fn name(&self) -> &str {
+        "HLS"
+    }
+
+    fn write_frame(&mut self, frame: &CapturedFrame) -> Result<(), String> {
+        if let Some(controller) = &self.controller {
+            let quality = controller.lock().unwrap().quality();
+            if self.quality != Some(quality) {
+                // Restarted at the new rate; viewers see a discontinuity
+                self.stop_encoder();
+                self.quality = Some(quality);
+            }
+            self.frames += 1;
+            if quality.half_fps && self.frames.is_multiple_of(2) {
+                return Ok(());
+            }
+        }
+        if self.child.is_none() {
+            self.start_encoder(frame.width, frame.height)?;
+        }
+
+        match self.stdin {
+            Some(ref mut stdin) => write_raw_frame(stdin, frame, self.width, self.height),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), String> {
+        self.stop_encoder();
+        Ok(())
+    }
+}
+
+impl Drop for HlsSink {
+    fn drop(&mut self) {
+        self.stop_encoder();
+        self.server.abort();
+        let _ = std::fs::remove_dir_all(&self.output_dir);
+        tracing::info!(target: "HLS", "Live stream stopped");
+    }
+}
+
+/// Accept HTTP connections until the task is aborted. With a `token`, only
+/// paths under it are served. Segment downloads are timed for `controller`,
+/// if set.
+async fn serve(
+    listener: TcpListener,
+    dir: PathBuf,
+    token: Option<String>,
+    controller: Option<Arc<Mutex<BitrateController>>>,
+) {
+    let limit = http::client_limit();
+    loop {
+        match http::accept(&listener, &limit).await {
+            Ok((stream, permit)) => {
+                let dir = dir.clone();
+                let token = token.clone();
+                let controller = controller.clone();
+                tokio::spawn(async move {
+                    let _permit = permit;
+                    if let Err(e) = handle_client(stream, &dir, token.as_deref(), controller.as_deref()).await {
+                        tracing::warn!(target: "HLS", "Client error: {}", e);
+                    }
+                });
+            }
+            Err(e) => {
+                tracing::error!(target: "HLS", "Accept error: {}", e);
+            }
+        }
+    }
+}
+
+/// Serve a single request for the player page, playlist, or a segment.
+async fn handle_client(
+    mut stream: TcpStream,
+    dir: &Path,
+    token: Option<&str>,
+    controller: Option<&Mutex<BitrateController>>,
+) -> std::io::Result<()> {
+    let Some(request) = http::read_request(&mut stream).await? else {
+        return Ok(());
+    };
+
+    if request.method != "GET" {
+        return http::write_response(&mut stream, 405, "text/plain", &[], b"Method not allowed").await;
+    }
+
+    let Some(name) = file_name(&request.path, token) else {
+        return http::write_response(&mut stream, 404, "text/plain", &[], b"Not found").await;
+    };
+    if name.is_empty() || name == "index.html" {
+        return http::write_response(
+            &mut stream,
+            200,
+            "text/html; charset=utf-8",
+            &[],
+            PLAYER_HTML.as_bytes(),
+        )
+        .await;
+    }
+
+    if name == "hls.min.js" && !HLS_JS.is_empty() {
+        let headers = [("Cache-Control", "max-age=86400")];
+        return http::write_response(&mut stream, 200, "text/javascript", &headers, HLS_JS).await;
+    }
+
+    let Some(content_type) = content_type_for(name) else {
+        return http::write_response(&mut stream, 404, "text/plain", &[], b"Not found").await;
+    };
+
+    match tokio::fs::read(dir.join(name)).await {
+        Ok(body) => {
+            let headers = [
+                ("Access-Control-Allow-Origin", "*"),
+                ("Cache-Control", "no-cache"),
+            ];
+            let started = Instant::now();
+            http::write_response(&mut stream, 200, content_type, &headers, &body).await?;
+            if let Some(controller) = controller.filter(|_| content_type == "video/mp2t") {
+                let send_time = wait_for_delivery(&mut stream, started).await;
+                let event = controller
+                    .lock()
+                    .unwrap()
+                    .observe(send_time, Duration::from_secs(SEGMENT_SECONDS.into()));
+                if let Some(event) = event {
+                    abr::publish(event);
+                }
+            }
+            Ok(())
+        }
+        Err(_) => http::write_response(&mut stream, 404, "text/plain", &[], b"Not found").await,
+    }
+}
+
+/// How long since `started` the client took to receive everything written to
+/// `stream`. Writes finish once the data is queued on the socket, so this
+/// waits for the client to close its end, which it does after reading the
+/// whole response (`Connection: close`). Gives up after a few segments' time.
+async fn wait_for_delivery(stream: &mut TcpStream, started: Instant) -> Duration {
+    let limit = Duration::from_secs((SEGMENT_SECONDS * 3).into());
+    let _ = stream.shutdown().await;
+    let mut buf = [0u8; 512];
+    let _ = tokio::time::timeout(limit, async {
+        while let Ok(n) = stream.read(&mut buf).await {
+            if n == 0 {
+                break;
+            }
+        }
+    })
+    .await;
+    started.elapsed()
+}
+
+/// The file `path` asks for, if it's under `token` when there is one.
+fn file_name<'a>(path: &'a str, token: Option<&str>) -> Option<&'a str> {
+    let path = path.strip_prefix('/')?;
+    let Some(token) = token else {
+        return Some(path);
+    };
+    let (given, name) = path.split_once('/')?;
+    bool::from(given.as_bytes().ct_eq(token.as_bytes())).then_some(name)
+}
+
+/// Content type for a servable file name, or `None` if the name is not allowed.
+///
+/// Only flat file names produced by the HLS muxer are served, which also rules
+/// out path traversal.
+fn content_type_for(name: &str) -> Option<&'static str> {
+    let valid_chars = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+    if !valid_chars || name.contains("..") {
+        return None;
+    }
+
+    if name == PLAYLIST_NAME {
+        Some("application/vnd.apple.mpegurl")
+    } else if name.starts_with("segment_") && name.ends_with(".ts") {
+        Some("video/mp2t")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_type_for() {
+        assert_eq!(content_type_for("index.m3u8"), Some("application/vnd.apple.mpegurl"));
+        assert_eq!(content_type_for("segment_00001.ts"), Some("video/mp2t"));
+        assert_eq!(content_type_for("../etc/passwd"), None);
+        assert_eq!(content_type_for("secret.txt"), None);
+    }
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name("/index.m3u8", None), Some("index.m3u8"));
+        assert_eq!(file_name("/abc/index.m3u8", Some("abc")), Some("index.m3u8"));
+        assert_eq!(file_name("/abc/", Some("abc")), Some(""));
+        assert_eq!(file_name("/index.m3u8", Some("abc")), None);
+        assert_eq!(file_name("/abd/index.m3u8", Some("abc")), None);
+        assert_eq!(file_name("/ab/index.m3u8", Some("abc")), None);
+    }
+}
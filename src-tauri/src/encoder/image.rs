@@ -0,0 +1,207 @@
+//! Still image encoding and decoding using FFmpeg.
+
+use crate::capture::{CapturedFrame, ColorSpace, PixelFormat};
+use chrono::Local;
+use directories::UserDirs;
+use ffmpeg_sidecar::command::FfmpegCommand;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+/// Output format for still images.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    #[default]
+    Png,
+    Jpeg,
+    Webp,
+}
+
+impl ImageFormat {
+    /// File extension for this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Webp => "webp",
+        }
+    }
+
+    /// FFmpeg codec arguments for this format.
+    fn codec_args(&self) -> &'static [&'static str] {
+        match self {
+            ImageFormat::Png => &["-c:v", "png"],
+            // qscale 2 is visually lossless for screen content
+            ImageFormat::Jpeg => &["-c:v", "mjpeg", "-q:v", "2"],
+            ImageFormat::Webp => &["-c:v", "libwebp", "-quality", "90"],
+        }
+    }
+}
+
+/// Encode a single frame to an image file.
+///
+/// This blocks until FFmpeg exits; call it from a blocking task.
+pub fn encode_image(frame: &CapturedFrame, format: ImageFormat, path: &Path) -> Result<(), String> {
+    if frame.width == 0 || frame.height == 0 {
+        return Err(format!("Invalid dimensions: {}x{}", frame.width, frame.height));
+    }
+
+    let mut command = FfmpegCommand::new();
+    command
+        .args(["-f", "rawvideo"])
+        .args(["-pix_fmt", "bgra"])
+        .args(["-s", &format!("{}x{}", frame.width, frame.height)])
+        .args(["-i", "-"])
+        .args(["-frames:v", "1"])
+        .args(format.codec_args())
+        .args(["-n"])
+        .arg(path.to_string_lossy().to_string());
+
+    let inner_command = command.as_inner_mut();
+    inner_command.stdin(Stdio::piped());
+    inner_command.stdout(Stdio::null());
+    inner_command.stderr(Stdio::piped());
+
+    let mut child = inner_command
+        .spawn()
+        .map_err(|e| format!("Failed to start FFmpeg: {}", e))?;
+
+    {
+        let mut stdin = child.stdin.take().ok_or("Failed to get FFmpeg stdin")?;
+        super::write_raw_frame(&mut stdin, frame, frame.width, frame.height)?;
+        stdin
+            .flush()
+            .map_err(|e| format!("Failed to write frame: {}", e))?;
+        // stdin is dropped here so FFmpeg sees end of input
+    }
+
+    let mut stderr_output = String::new();
+    if let Some(mut stderr) = child.stderr.take() {
+        let _ = stderr.read_to_string(&mut stderr_output);
+    }
+
+    let status = child
+        .wait()
+        .map_err(|e| format!("FFmpeg process error: {}", e))?;
+
+    if !status.success() {
+        return Err(format!(
+            "FFmpeg failed to encode image: {}",
+            stderr_output.lines().last().unwrap_or("unknown error")
+        ));
+    }
+
+    Ok(())
+}
+
+/// Decode an image file to a BGRA frame, keeping its alpha channel.
+///
+/// This blocks until FFmpeg exits; call it from a blocking task.
+pub fn load_image(path: &Path) -> Result<CapturedFrame, String> {
+    let info = super::ffmpeg::probe(path)?;
+    if info.width == 0 || info.height == 0 {
+        return Err(format!("{} has no picture", path.display()));
+    }
+    decode_image(path, info.width, info.height, None)
+}
+
+/// Decode an image file to a `width`x`height` BGRA frame, scaled to fit
+/// and centered on transparency.
+///
+/// This blocks until FFmpeg exits; call it from a blocking task.
+pub fn load_image_fitted(path: &Path, width: u32, height: u32) -> Result<CapturedFrame, String> {
+    let filter = format!(
+        "format=bgra,scale={0}:{1}:force_original_aspect_ratio=decrease,pad={0}:{1}:(ow-iw)/2:(oh-ih)/2:color=black@0",
+        width, height
+    );
+    decode_image(path, width, height, Some(&filter))
+}
+
+/// Decode the first picture of `path`, which `filter` leaves `width`x`height`.
+fn decode_image(path: &Path, width: u32, height: u32, filter: Option<&str>) -> Result<CapturedFrame, String> {
+    let mut command = FfmpegCommand::new();
+    command.args(["-i", &path.to_string_lossy()]);
+    if let Some(filter) = filter {
+        command.args(["-vf", filter]);
+    }
+    command
+        .args(["-frames:v", "1"])
+        .args(["-f", "rawvideo"])
+        .args(["-pix_fmt", "bgra"])
+        .arg("-");
+
+    let inner_command = command.as_inner_mut();
+    inner_command.stdin(Stdio::null());
+    inner_command.stdout(Stdio::piped());
+    inner_command.stderr(Stdio::null());
+
+    let output = inner_command
+        .output()
+        .map_err(|e| format!("Failed to start FFmpeg: {}", e))?;
+    let size = width as usize * height as usize * PixelFormat::Bgra8.bytes_per_pixel();
+    if !output.status.success() || output.stdout.len() < size {
+        return Err(format!("FFmpeg failed to decode {}", path.display()));
+    }
+
+    let mut data = output.stdout;
+    data.truncate(size);
+    Ok(CapturedFrame {
+        width,
+        height,
+        data,
+        format: PixelFormat::Bgra8,
+        color: ColorSpace::SRGB,
+        captured_at: std::time::Instant::now(),
+        presented_at: None,
+    })
+}
+
+/// Generate a unique screenshot filename in the user's Pictures folder.
+pub fn generate_screenshot_path(format: ImageFormat) -> Result<PathBuf, String> {
+    let user_dirs = UserDirs::new().ok_or("Could not determine user directories")?;
+
+    let output_dir = user_dirs
+        .picture_dir()
+        .map(|p| p.to_path_buf())
+        .unwrap_or_else(|| user_dirs.home_dir().to_path_buf());
+
+    let timestamp = Local::now().format("%Y-%m-%d_%H%M%S");
+    Ok(unused_path(&output_dir, &format!("screenshot_{}", timestamp), format.extension()))
+}
+
+/// `stem.extension` in `dir`, with a numeric suffix if that file already
+/// exists, e.g. two screenshots taken within the same second.
+fn unused_path(dir: &Path, stem: &str, extension: &str) -> PathBuf {
+    let mut candidate = dir.join(format!("{}.{}", stem, extension));
+    let mut counter = 2;
+    while candidate.exists() {
+        candidate = dir.join(format!("{}_{}.{}", stem, counter, extension));
+        counter += 1;
+    }
+    candidate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_serde() {
+        let format: ImageFormat = serde_json::from_str("\"jpeg\"").unwrap();
+        assert_eq!(format, ImageFormat::Jpeg);
+        assert_eq!(format.extension(), "jpg");
+    }
+
+    #[test]
+    fn test_unused_path() {
+        let dir = std::env::temp_dir().join(format!("screenshot-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let first = unused_path(&dir, "screenshot", "png");
+        assert_eq!(first, dir.join("screenshot.png"));
+        std::fs::write(&first, b"").unwrap();
+        assert_eq!(unused_path(&dir, "screenshot", "png"), dir.join("screenshot_2.png"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
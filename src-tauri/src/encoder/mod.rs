@@ -1,30 +1,364 @@
 //! Video encoding module using FFmpeg via ffmpeg-sidecar.
 
-use crate::capture::CapturedFrame;
+pub mod abr;
+pub mod codec;
+pub mod convert;
+pub mod deferred;
+pub mod estimate;
+pub mod ffmpeg;
+pub mod filter;
+pub mod hardware;
+pub mod hls;
+pub mod image;
+pub mod motion;
+pub mod ndi;
+pub mod options;
+pub mod pipeline;
+pub mod rate;
+pub mod replay;
+pub mod resize;
+pub mod schedule;
+pub mod sink;
+pub mod standby;
+pub mod static_content;
+pub mod still;
+pub mod support;
+pub mod yuv;
+
+use crate::avsync::SyncClock;
+use crate::capture::hdr::{HdrMode, ToneMapper};
+use crate::capture::recovery::{RecoveryOptions, WindowLostBehavior};
+use crate::capture::queue::TryRecvError;
+use crate::capture::types::ColorRange;
+use crate::capture::{CaptureOptions, CapturedFrame, ColorSpace, FrameReceiver, PixelFormat};
+use crate::encryption;
+use crate::journal;
+use crate::limits::StopAt;
+use crate::gpu::{GpuPlacement, GpuTarget};
+use crate::stats::{Stage, StatsCollector};
 use chrono::Local;
+use codec::VideoCodec;
+use convert::ConversionBackend;
+use deferred::{DeferredWriter, MemoryBuffer};
+use filter::FilterChain;
+use motion::{MotionDetector, MotionSettings};
+use static_content::StaticContentSettings;
+use crate::idle::IdlePauseSettings;
+use crate::input_track::InputTrackSettings;
+use crate::captions::{self, LiveCaptionSettings};
+use resize::ResolutionChangePolicy;
+use schedule::FrameSchedule;
+use yuv::Layout;
 use directories::UserDirs;
+use hardware::EncoderBackend;
+use options::AdvancedOptions;
+use pipeline::EncodePipeline;
+use rate::RateControl;
 use ffmpeg_sidecar::command::FfmpegCommand;
 use std::io::Write;
-use std::path::PathBuf;
-use std::process::{ChildStdin, Stdio};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::thread::JoinHandle;
+use sink::{finish_sinks, write_to_sinks, FrameSink};
+
+/// Settings for the video encoder.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EncoderSettings {
+    /// Use the GPU for color conversion and scaling when available
+    pub gpu_conversion: bool,
+    /// Encode on the GPU when a hardware encoder works, falling back to the
+    /// software encoder. Off unless turned on, so recordings keep the
+    /// software encoder's output until asked otherwise.
+    pub hardware_encoding: bool,
+    /// Only use hardware encoding and GPU conversion on this GPU (an index
+    /// from [`crate::gpu::list_adapters`]) instead of preferring the GPU
+    /// doing the capture
+    pub gpu_adapter: Option<u32>,
+    /// Write to this file instead of a generated name in the Videos folder.
+    /// The container is chosen from the extension.
+    pub output_path: Option<PathBuf>,
+    /// Encrypt the recording as it's written, saving it with an `.enc`
+    /// extension; see [`crate::encryption`]. Recordings with audio,
+    /// a replay buffer or an HLS stream refuse to start encrypted.
+    pub encrypt: bool,
+    /// Hold the recording in memory and write it to disk later; see
+    /// [`deferred`]. Image sequences are always written directly.
+    pub memory_buffer: MemoryBuffer,
+    /// Output frame rate
+    pub fps: u32,
+    /// Scale the output down to fit within this width
+    pub max_width: Option<u32>,
+    /// Scale the output down to fit within this height
+    pub max_height: Option<u32>,
+    pub codec: VideoCodec,
+    /// How file size is traded for quality
+    pub rate_control: RateControl,
+    /// Extra encoder options passed through to FFmpeg
+    pub advanced_options: AdvancedOptions,
+    /// How HDR displays are recorded
+    pub hdr: HdrMode,
+    /// Encode 10 bits per channel (HEVC, VP9 and AV1) so gradients don't band
+    pub ten_bit: bool,
+    /// How long to keep trying to capture the source again when it's lost
+    /// mid-recording, showing a "no signal" frame meanwhile; 0 ends the
+    /// recording instead
+    pub recovery_timeout_secs: u32,
+    /// What window recordings show while the window is minimized or closed
+    pub window_lost: WindowLostBehavior,
+    /// What happens when the capture changes resolution mid-recording
+    pub resolution_change: ResolutionChangePolicy,
+    /// Only write frames while something on screen is changing
+    pub motion: MotionSettings,
+    /// Hold back frames while the picture is still, at a variable frame rate
+    pub static_content: StaticContentSettings,
+    /// Pause while there's no input and nothing on screen changes
+    pub idle_pause: IdlePauseSettings,
+    /// Save the mouse and keyboard input next to the recording
+    pub input_track: InputTrackSettings,
+    /// Transcribe the microphone into captions while recording
+    pub live_captions: LiveCaptionSettings,
+    /// Record the recorded window's application audio, when the recording
+    /// asks for it
+    pub application_audio: bool,
+    /// Record the default microphone, mixed with any application audio
+    pub microphone: bool,
+    /// Remove the application audio the microphone picks up from speakers,
+    /// when both are recorded
+    pub echo_cancellation: bool,
+    /// YUV matrix and range SDR video is converted to
+    pub color: ColorSpace,
+    /// Keep the alpha channel of transparent windows (VP9, ProRes and PNG
+    /// sequences), for overlays
+    pub alpha: bool,
+    /// Record at the refresh rate of the captured display instead of `fps`,
+    /// with frame times locked to the frames it presents
+    pub match_refresh_rate: bool,
+    /// Name of the profile last applied to these settings, for recordings'
+    /// tags
+    pub profile: Option<String>,
+}
+
+impl Default for EncoderSettings {
+    fn default() -> Self {
+        Self {
+            gpu_conversion: true,
+            hardware_encoding: false,
+            gpu_adapter: None,
+            output_path: None,
+            encrypt: false,
+            memory_buffer: MemoryBuffer::default(),
+            fps: DEFAULT_FPS,
+            max_width: None,
+            max_height: None,
+            codec: VideoCodec::default(),
+            rate_control: RateControl::default(),
+            advanced_options: AdvancedOptions::new(),
+            hdr: HdrMode::default(),
+            ten_bit: false,
+            recovery_timeout_secs: DEFAULT_RECOVERY_TIMEOUT_SECS,
+            window_lost: WindowLostBehavior::default(),
+            resolution_change: ResolutionChangePolicy::default(),
+            motion: MotionSettings::default(),
+            static_content: StaticContentSettings::default(),
+            idle_pause: IdlePauseSettings::default(),
+            input_track: InputTrackSettings::default(),
+            live_captions: LiveCaptionSettings::default(),
+            application_audio: true,
+            microphone: false,
+            echo_cancellation: true,
+            color: ColorSpace::default(),
+            alpha: false,
+            match_refresh_rate: false,
+            profile: None,
+        }
+    }
+}
+
+impl EncoderSettings {
+    /// Conversion backend to use with these settings.
+    pub fn conversion_backend(&self) -> ConversionBackend {
+        // The GPU filters drop the alpha channel
+        if self.gpu_conversion && !self.codec.requires_cpu_conversion() && !self.encodes_alpha() {
+            Some(convert::detect_backend())
+                .filter(|backend| backend.supports(self.color))
+                .unwrap_or(ConversionBackend::Cpu)
+        } else {
+            ConversionBackend::Cpu
+        }
+    }
+
+    /// Encoders to try with these settings, in order.
+    pub fn encoder_chain(&self) -> Vec<EncoderBackend> {
+        // Hardware encoders drop the alpha channel
+        hardware::encoder_chain(self.codec, self.rate_control, self.hardware_encoding && !self.encodes_alpha())
+    }
+
+    /// HDR mode to capture with. HDR is only kept for codecs that can carry it.
+    pub fn capture_hdr_mode(&self) -> HdrMode {
+        if self.codec.supports_hdr() {
+            self.hdr
+        } else {
+            HdrMode::ToneMap
+        }
+    }
+
+    /// Whether video is encoded with 10 bits per channel. HDR is always 10-bit.
+    pub fn encodes_10bit(&self) -> bool {
+        self.ten_bit && self.codec.supports_10bit()
+    }
+
+    /// Whether the alpha channel is kept: only VP9, ProRes and PNG carry it.
+    pub fn encodes_alpha(&self) -> bool {
+        self.alpha && self.codec.alpha_format().is_some()
+    }
+
+    /// Whether still frames are held back. GIFs and image sequences keep
+    /// every frame.
+    pub fn holds_still_frames(&self) -> bool {
+        self.static_content.enabled && self.codec != VideoCodec::Gif && !self.codec.is_image_sequence()
+    }
+
+    /// How lost captures are handled with these settings.
+    pub fn recovery_options(&self) -> RecoveryOptions {
+        RecoveryOptions {
+            timeout: std::time::Duration::from_secs(self.recovery_timeout_secs as u64),
+            window_lost: self.window_lost,
+        }
+    }
+
+    /// Capture options matching these settings.
+    pub fn capture_options(&self) -> CaptureOptions {
+        CaptureOptions {
+            hdr: self.capture_hdr_mode(),
+            // Alpha is only captured in 8 bits
+            deep_color: self.encodes_10bit() && !self.encodes_alpha(),
+            alpha: self.encodes_alpha(),
+        }
+    }
+}
 
-/// Video encoder that receives frames and encodes to MP4.
+/// Video encoder that receives frames and encodes them to a file.
 pub struct VideoEncoder {
-    stdin: Option<ChildStdin>,
+    /// Converts and pipes frames to FFmpeg on their own threads
+    pipeline: Option<EncodePipeline>,
     child: Option<std::process::Child>,
+    /// Copies FFmpeg's output into the file, for encrypted and
+    /// memory-buffered recordings
+    writer: Option<JoinHandle<Result<(), String>>>,
     output_path: PathBuf,
     width: u32,
     height: u32,
+    /// Pixel format of the input frames
+    format: PixelFormat,
+    /// Colorspace of the input frames
+    color: ColorSpace,
+    conversion: ConversionBackend,
+    backend: EncoderBackend,
+    settings: EncoderSettings,
+    /// Where the pipeline reports its stages' timings
+    stats: Option<Arc<StatsCollector>>,
+    /// GPUs color conversion and encoding run on, if chosen
+    conversion_target: Option<GpuTarget>,
+    encode_target: Option<GpuTarget>,
+    /// Frames written so far
+    frames: u64,
+    /// Whether the file is a fragmented MP4 to remux once finished
+    fragmented: bool,
+}
+
+/// Takes a recording out of the journal of unfinished ones when dropped.
+struct JournalGuard(PathBuf);
+
+impl Drop for JournalGuard {
+    fn drop(&mut self) {
+        journal::end(&self.0);
+    }
+}
+
+/// A finished recording file, complete and safe to move, open or upload.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedFile {
+    /// The file, or the folder of an image sequence
+    pub path: PathBuf,
+    /// Size of the encoded frames
+    pub width: u32,
+    pub height: u32,
+    pub frames: u64,
+    pub duration_secs: f64,
+    pub size_bytes: u64,
+}
+
+impl RecordedFile {
+    /// Read the file's size again, after it's been changed.
+    pub fn update_size(&mut self) {
+        self.size_bytes = output_size(&self.path);
+    }
+}
+
+/// Size of a recording on disk, adding up the images of image sequences.
+fn output_size(path: &Path) -> u64 {
+    match std::fs::read_dir(path) {
+        Ok(entries) => entries
+            .flatten()
+            .filter_map(|entry| entry.metadata().ok())
+            .map(|metadata| metadata.len())
+            .sum(),
+        Err(_) => std::fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0),
+    }
 }
 
 impl VideoEncoder {
-    /// Create a new encoder with the given frame dimensions.
+    /// Create a new encoder with the given frame dimensions and format, writing
+    /// to the settings' output path or a generated file in the Videos folder.
     /// Dimensions will be rounded down to even numbers for codec compatibility.
-    pub fn new(width: u32, height: u32) -> Result<Self, String> {
-        let output_path = generate_output_path()?;
+    /// HDR frames are encoded as HDR10.
+    pub fn new(
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+        color: ColorSpace,
+        conversion: ConversionBackend,
+        backend: EncoderBackend,
+        settings: &EncoderSettings,
+    ) -> Result<Self, String> {
+        let Some(encoder) = backend.encoder_name(settings.codec) else {
+            return Err(format!("No {:?} encoder for {:?}", backend, settings.codec));
+        };
+        options::validate(&settings.advanced_options)?;
+        tracing::info!(target: "Encoder", "Using {}", encoder);
+
+        let output_path = match settings.output_path.clone() {
+            Some(path) => {
+                if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                    std::fs::create_dir_all(parent)
+                        .map_err(|e| format!("Failed to create output directory: {}", e))?;
+                }
+                path
+            }
+            None => generate_output_path("recording", settings.codec.extension())?,
+        };
+        // Image sequences go in a folder named for the recording
+        let output_path = if settings.codec.is_image_sequence() {
+            if settings.encrypt {
+                return Err("Image sequences can't be encrypted".to_string());
+            }
+            let folder = output_path.with_extension("");
+            std::fs::create_dir_all(&folder).map_err(|e| format!("Failed to create {}: {}", folder.display(), e))?;
+            folder
+        } else {
+            output_path
+        };
+        let output_path = if settings.encrypt && output_path.extension().is_none_or(|ext| ext != encryption::EXTENSION) {
+            let mut path = output_path.into_os_string();
+            path.push(format!(".{}", encryption::EXTENSION));
+            PathBuf::from(path)
+        } else {
+            output_path
+        };
 
         // Ensure dimensions are even (required by many codecs including h264)
         let width = width & !1;
@@ -35,97 +369,305 @@ impl VideoEncoder {
         }
 
         Ok(Self {
-            stdin: None,
+            pipeline: None,
             child: None,
+            writer: None,
             output_path,
             width,
             height,
+            format,
+            color,
+            conversion,
+            backend,
+            settings: settings.clone(),
+            stats: None,
+            conversion_target: None,
+            encode_target: None,
+            frames: 0,
+            fragmented: false,
         })
     }
 
     /// Start the FFmpeg encoding process.
     pub fn start(&mut self) -> Result<(), String> {
+        let settings = &self.settings;
+        let extension = self
+            .container_path()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        // movflags only apply to MP4/MOV; other containers reject them
+        let is_mp4 = extension == "mp4" || extension == "mov";
+
+        let (out_width, out_height) =
+            codec::output_size(self.width, self.height, settings.max_width, settings.max_height);
+        let hdr = self.format == PixelFormat::Rgba16F;
+        let yuv_layout = self.yuv_layout(out_width, out_height);
+        let mut filter = if yuv_layout.is_some() {
+            // Converted before it's sent
+            "null".to_string()
+        } else if settings.codec == VideoCodec::Gif {
+            VideoCodec::gif_filter(out_width, out_height)
+        } else if hdr {
+            VideoCodec::hdr10_filter(out_width, out_height)
+        } else if let Some(format) = settings.codec.alpha_format().filter(|_| settings.encodes_alpha()) {
+            VideoCodec::format_filter(out_width, out_height, format, settings.color)
+        } else if let Some(format) = settings.codec.rgb_format() {
+            VideoCodec::rgb_filter(out_width, out_height, format)
+        } else if settings.codec == VideoCodec::ProRes {
+            VideoCodec::format_filter(out_width, out_height, "yuv444p10le", settings.color)
+        } else {
+            // BGRA -> 4:2:0 conversion and scaling (on the GPU when available)
+            self.conversion.filter(out_width, out_height, settings.encodes_10bit(), settings.color)
+        };
+        // Drawn on the captured frames, before they're scaled and converted
+        if settings.live_captions.enabled && settings.live_captions.burn_in {
+            if hdr {
+                tracing::info!(target: "Encoder", "Live captions aren't drawn into HDR recordings");
+            } else {
+                filter = format!("{},{}", captions::drawtext_filter(self.height), filter);
+            }
+        }
+        // Compares the frames as they'll be encoded
+        if settings.holds_still_frames() {
+            filter.push_str(&format!(",{}", settings.static_content.filter(settings.fps)));
+        }
+        if let Some(format) = self.backend.input_format(hdr || settings.encodes_10bit()) {
+            filter.push_str(&format!(",format={}", format));
+        }
+
         // Build the FFmpeg command using std::process for better stdin control
         let mut command = FfmpegCommand::new();
         command
+            // Hardware devices for GPU color conversion and encoding, if used
+            .args(self.conversion.device_args(self.conversion_target.as_ref()))
+            .args(self.backend.device_args(self.encode_target.as_ref(), self.conversion))
             // Input: raw video frames from stdin
             .args(["-f", "rawvideo"])
-            .args(["-pix_fmt", "bgra"])
+            .args(["-pix_fmt", yuv_layout.map_or(self.format.ffmpeg_name(), |layout| layout.ffmpeg_name())])
             .args(["-s", &format!("{}x{}", self.width, self.height)])
-            .args(["-r", "30"]) // 30 FPS
+            .args(["-r", &settings.fps.to_string()]);
+        if yuv_layout.is_some() {
+            command
+                .args(["-colorspace", settings.color.standard.matrix()])
+                .args(["-color_range", settings.color.range.ffmpeg_name()]);
+        }
+        command
             .args(["-i", "-"]) // Read from stdin
-            // Output: H.264 in MP4 container
-            .args(["-c:v", "libx264"])
-            .args(["-preset", "ultrafast"]) // Fast encoding for real-time
-            .args(["-crf", "23"]) // Good quality/size balance
-            .args(["-pix_fmt", "yuv420p"]) // Compatible pixel format
-            .args(["-movflags", "+faststart"]) // Web-optimized MP4
-            .args(["-y"]) // Overwrite output
-            .arg(self.output_path.to_string_lossy().to_string());
+            .args(["-vf", &filter])
+            // Note which encoder wrote the file, since it depends on the machine
+            .args(["-metadata", &format!("comment=Encoded with {}", self.encoder_name())]);
+        let mut codec_args = self.backend.args(settings.codec, settings.rate_control);
+        codec_args.extend(self.backend.target_args(self.encode_target.as_ref()));
+        if hdr {
+            codec_args.extend(settings.codec.hdr10_args(self.encoder_name()));
+        } else {
+            codec_args.extend(settings.codec.sdr_color_args(self.color, settings.color));
+        }
+        if settings.holds_still_frames() {
+            codec_args.extend(settings.static_content.output_args());
+        }
+        codec_args.extend(options::args(&settings.advanced_options));
+        command.args(rate::merge_params(codec_args));
+        // Containers that can't be piped are written directly instead
+        let memory_buffer = (settings.memory_buffer.enabled && pipe_format(&extension).is_ok())
+            .then_some(settings.memory_buffer);
+        let piped = settings.encrypt || memory_buffer.is_some();
+        let key = if settings.encrypt { Some(encryption::recording_key()?) } else { None };
+        if piped {
+            // Piped output can't be seeked back to write an index, so MP4 is
+            // written fragmented
+            if is_mp4 {
+                command.args(["-movflags", "+frag_keyframe+empty_moov+default_base_moof"]);
+            }
+            command.args(["-f", pipe_format(&extension)?]).arg("pipe:1");
+        } else {
+            if is_mp4 {
+                // Fragmented while recording, so that a crash leaves every
+                // complete fragment readable; remuxed with its index at the
+                // front once finished
+                command.args(["-movflags", "+frag_keyframe+empty_moov+default_base_moof"]);
+                self.fragmented = true;
+            }
+            let output = if settings.codec.is_image_sequence() {
+                command.args(["-f", "image2"]);
+                self.output_path.join("frame_%06d.png")
+            } else {
+                self.output_path.clone()
+            };
+            command
+                .args(["-y"]) // Overwrite output
+                .arg(output.to_string_lossy().to_string());
+        }
 
         // Get the inner command and configure stdin
         let inner_command = command.as_inner_mut();
+        // Number CUDA GPUs by PCI bus, as the adapter lists are
+        inner_command.env("CUDA_DEVICE_ORDER", "PCI_BUS_ID");
         inner_command.stdin(Stdio::piped());
-        inner_command.stdout(Stdio::null());
+        inner_command.stdout(if piped { Stdio::piped() } else { Stdio::null() });
         inner_command.stderr(Stdio::piped());
 
         let mut child = inner_command
             .spawn()
             .map_err(|e| format!("Failed to start FFmpeg: {}", e))?;
 
+        if piped {
+            let mut stdout = child.stdout.take().ok_or("Failed to get FFmpeg stdout")?;
+            let mut out: Box<dyn Write + Send> = match memory_buffer {
+                Some(memory_buffer) => Box::new(DeferredWriter::new(self.output_path.clone(), &memory_buffer)),
+                None => {
+                    let file = std::fs::File::create(&self.output_path)
+                        .map_err(|e| format!("Failed to create {}: {}", self.output_path.display(), e))?;
+                    Box::new(std::io::BufWriter::new(file))
+                }
+            };
+            self.writer = Some(match key {
+                Some(key) => {
+                    let mut writer = encryption::EncryptedWriter::new(out, &key)?;
+                    std::thread::spawn(move || {
+                        std::io::copy(&mut stdout, &mut writer)
+                            .map_err(|e| format!("Failed to encrypt recording: {}", e))?;
+                        writer.finish().map(|_| ())
+                    })
+                }
+                None => std::thread::spawn(move || {
+                    std::io::copy(&mut stdout, &mut out)
+                        .and_then(|_| out.flush())
+                        .map_err(|e| format!("Failed to write recording: {}", e))
+                }),
+            });
+        }
+
         let stdin = child
             .stdin
             .take()
             .ok_or("Failed to get FFmpeg stdin")?;
 
-        self.stdin = Some(stdin);
         self.child = Some(child);
+        let (width, height) = (self.width, self.height);
+        let convert: pipeline::Convert = match yuv_layout {
+            Some(layout) => {
+                // Converts frames to YUV before they're sent to FFmpeg, when it isn't doing it
+                let mut converter = yuv::Converter::new(self.settings.color, layout);
+                tracing::info!(target: "Encoder", "Converting to {} with {:?}", layout.ffmpeg_name(), converter.kernel());
+                Box::new(move |frame, buffer| {
+                    if frame.width < width || frame.height < height || frame.format != PixelFormat::Bgra8 {
+                        skip_frame(format_args!(
+                            "{}x{} {:?} doesn't fit encoder {}x{}",
+                            frame.width, frame.height, frame.format, width, height
+                        ));
+                        return Ok(());
+                    }
+                    let stride = frame.width as usize * 4;
+                    converter.convert(&frame.data, stride, width as usize, height as usize, buffer);
+                    Ok(())
+                })
+            }
+            None => Box::new(move |frame, buffer| write_raw_frame(buffer, frame, width, height)),
+        };
+        self.pipeline = Some(EncodePipeline::start(stdin, convert, self.stats.clone())?);
+        // Image sequences are readable however far they got
+        if !settings.codec.is_image_sequence() {
+            journal::begin(&self.output_path);
+        }
 
         Ok(())
     }
 
-    /// Write a frame to the encoder.
-    pub fn write_frame(&mut self, frame: &CapturedFrame) -> Result<(), String> {
-        // Handle frames that may be slightly larger than encoder dimensions
-        // (can happen due to even-dimension rounding)
-        if frame.width < self.width || frame.height < self.height {
-            eprintln!(
-                "Skipping frame: dimensions {}x{} smaller than encoder {}x{}",
-                frame.width, frame.height, self.width, self.height
-            );
-            return Ok(());
+    /// Report the pipeline's stage timings to `stats`. Takes effect when the
+    /// encoder starts.
+    pub fn set_stats(&mut self, stats: Arc<StatsCollector>) {
+        self.stats = Some(stats);
+    }
+
+    /// Run color conversion and encoding on the GPUs `placement` chooses for
+    /// them. Takes effect when the encoder starts.
+    pub fn set_placement(&mut self, placement: &GpuPlacement) {
+        self.conversion_target = placement.conversion_target(self.conversion);
+        self.encode_target = placement.encode_target(self.backend);
+    }
+
+    /// The layout frames are converted to before they're sent to FFmpeg, or
+    /// `None` if FFmpeg converts them: only 8-bit frames encoded at 8-bit
+    /// 4:2:0, at their own size, without a GPU to convert on.
+    fn yuv_layout(&self, out_width: u32, out_height: u32) -> Option<Layout> {
+        let settings = &self.settings;
+        let plain_yuv = settings.codec != VideoCodec::Gif
+            && settings.codec.rgb_format().is_none()
+            && settings.codec != VideoCodec::ProRes
+            && !settings.encodes_alpha()
+            && !settings.encodes_10bit();
+        let unscaled = (out_width, out_height) == (self.width, self.height);
+        if self.conversion != ConversionBackend::Cpu
+            || self.format != PixelFormat::Bgra8
+            || self.color.range != ColorRange::Full
+            || !plain_yuv
+            || !unscaled
+        {
+            return None;
+        }
+        match self.backend.input_format(false) {
+            None => Some(Layout::I420),
+            Some("nv12") => Some(Layout::Nv12),
+            Some(_) => None,
         }
+    }
 
-        if let Some(ref mut stdin) = self.stdin {
-            // If frame is larger than encoder dimensions, crop it
-            if frame.width == self.width && frame.height == self.height {
-                // Exact match, write directly
-                stdin
-                    .write_all(&frame.data)
-                    .map_err(|e| format!("Failed to write frame: {}", e))?;
-            } else {
-                // Need to crop - extract only the rows/columns we need
-                let src_row_bytes = (frame.width * 4) as usize;
-                let dst_row_bytes = (self.width * 4) as usize;
-                
-                for y in 0..self.height as usize {
-                    let src_start = y * src_row_bytes;
-                    let src_end = src_start + dst_row_bytes;
-                    if src_end <= frame.data.len() {
-                        stdin
-                            .write_all(&frame.data[src_start..src_end])
-                            .map_err(|e| format!("Failed to write frame row: {}", e))?;
-                    }
-                }
-            }
+    /// Path of the file being written.
+    pub fn output_path(&self) -> &Path {
+        &self.output_path
+    }
+
+    /// Path of the file being written, without the `.enc` extension of
+    /// encrypted recordings.
+    pub fn container_path(&self) -> PathBuf {
+        if self.settings.encrypt {
+            self.output_path.with_extension("")
+        } else {
+            self.output_path.clone()
         }
+    }
+
+    /// Conversion backend in use.
+    pub fn conversion(&self) -> ConversionBackend {
+        self.conversion
+    }
+
+    /// Encoder backend in use.
+    pub fn backend(&self) -> EncoderBackend {
+        self.backend
+    }
+
+    /// Name of the FFmpeg encoder in use.
+    pub fn encoder_name(&self) -> &'static str {
+        // Checked when the encoder was created
+        self.backend.encoder_name(self.settings.codec).unwrap_or_default()
+    }
+
+    /// Queue a frame for the encoder, waiting only if it's a few frames
+    /// behind. Fails once FFmpeg has stopped taking frames.
+    pub fn write_frame(&mut self, frame: Arc<CapturedFrame>) -> Result<(), String> {
+        let Some(ref mut pipeline) = self.pipeline else {
+            return Ok(());
+        };
+        pipeline.write_frame(frame)?;
+        self.frames += 1;
         Ok(())
     }
 
     /// Finalize the encoding and close the output file.
-    pub fn finish(mut self) -> Result<PathBuf, String> {
-        // Close stdin to signal end of input
-        drop(self.stdin.take());
+    ///
+    /// Returns once FFmpeg has encoded the frames it was holding back, written
+    /// the container's trailer and index, and exited, and everything it wrote
+    /// has reached the file.
+    pub fn finish(mut self) -> Result<RecordedFile, String> {
+        // Write the queued frames and close stdin to signal end of input.
+        // If FFmpeg failed, its own error says more than the broken pipe.
+        let written = self.pipeline.take().map_or(Ok(()), |mut pipeline| pipeline.finish());
+        // Whatever happens below, the file isn't left behind by a crash
+        let _finished = JournalGuard(self.output_path.clone());
 
         // Wait for FFmpeg to finish
         if let Some(mut child) = self.child.take() {
@@ -152,13 +694,116 @@ impl VideoEncoder {
                 return Err(error_msg);
             }
         }
+        written?;
+
+        if let Some(writer) = self.writer.take() {
+            writer
+                .join()
+                .map_err(|_| "Output thread panicked".to_string())??;
+        }
+
+        if self.fragmented {
+            // The fragmented file plays, just without fast seeking
+            if let Err(e) = defragment(&self.output_path) {
+                tracing::warn!(target: "Encoder", "Keeping the fragmented recording: {}", e);
+            }
+        }
+
+        let (width, height) =
+            codec::output_size(self.width, self.height, self.settings.max_width, self.settings.max_height);
+        Ok(RecordedFile {
+            size_bytes: output_size(&self.output_path),
+            path: self.output_path,
+            width,
+            height,
+            frames: self.frames,
+            duration_secs: self.frames as f64 / self.settings.fps.max(1) as f64,
+        })
+    }
+}
 
-        Ok(self.output_path)
+/// Rewrite the fragmented MP4 at `path` as a regular one with its index at
+/// the front, in place.
+fn defragment(path: &Path) -> Result<(), String> {
+    let output = crate::postprocess::derived_path(path, "defragmented");
+    let mut args: Vec<String> = vec!["-i".to_string(), path.to_string_lossy().to_string()];
+    args.extend(["-map", "0", "-c", "copy", "-movflags", "+faststart", "-y"].map(String::from));
+    args.push(output.to_string_lossy().to_string());
+    let remuxed = ffmpeg::run_ffmpeg(args).and_then(|()| {
+        std::fs::rename(&output, path).map_err(|e| format!("Failed to replace the recording: {}", e))
+    });
+    if remuxed.is_err() {
+        let _ = std::fs::remove_file(&output);
     }
+    remuxed
 }
 
-/// Generate a unique output filename in the user's Videos folder.
-fn generate_output_path() -> Result<PathBuf, String> {
+/// FFmpeg muxer for writing a container with the extension `extension` to
+/// a pipe.
+fn pipe_format(extension: &str) -> Result<&'static str, String> {
+    match extension {
+        "mp4" => Ok("mp4"),
+        "mov" => Ok("mov"),
+        "mkv" => Ok("matroska"),
+        "webm" => Ok("webm"),
+        "gif" => Ok("gif"),
+        _ => Err(format!("Can't write .{} recordings through a pipe", extension)),
+    }
+}
+
+/// Frames skipped for not fitting the encoder since the last recording
+/// finished. Only the first is logged, so a source that keeps sending the
+/// wrong size doesn't write a warning for every frame.
+static SKIPPED_FRAMES: AtomicU64 = AtomicU64::new(0);
+
+fn skip_frame(reason: std::fmt::Arguments) {
+    if SKIPPED_FRAMES.fetch_add(1, Ordering::Relaxed) == 0 {
+        tracing::warn!(target: "Encoder", "Skipping frame: {} (further skips are counted)", reason);
+    }
+}
+
+/// Write a frame's raw pixel data to an FFmpeg input, cropped to `width`x`height`.
+///
+/// Frames may be slightly larger than the output dimensions because of
+/// even-dimension rounding; frames smaller than the output are skipped.
+pub(crate) fn write_raw_frame(
+    out: &mut impl Write,
+    frame: &CapturedFrame,
+    width: u32,
+    height: u32,
+) -> Result<(), String> {
+    if frame.width < width || frame.height < height {
+        skip_frame(format_args!(
+            "dimensions {}x{} smaller than encoder {}x{}",
+            frame.width, frame.height, width, height
+        ));
+        return Ok(());
+    }
+
+    if frame.width == width && frame.height == height {
+        // Exact match, write directly
+        out.write_all(&frame.data)
+            .map_err(|e| format!("Failed to write frame: {}", e))?;
+    } else {
+        // Need to crop - extract only the rows/columns we need
+        let bytes_per_pixel = frame.format.bytes_per_pixel();
+        let src_row_bytes = frame.width as usize * bytes_per_pixel;
+        let dst_row_bytes = width as usize * bytes_per_pixel;
+
+        for y in 0..height as usize {
+            let src_start = y * src_row_bytes;
+            let src_end = src_start + dst_row_bytes;
+            if src_end <= frame.data.len() {
+                out.write_all(&frame.data[src_start..src_end])
+                    .map_err(|e| format!("Failed to write frame row: {}", e))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Folder recordings are saved to by default: the user's Videos folder.
+pub(crate) fn default_output_dir() -> Result<PathBuf, String> {
     let user_dirs = UserDirs::new().ok_or("Could not determine user directories")?;
     
     // Try Videos directory first, fall back to home directory
@@ -169,73 +814,232 @@ fn generate_output_path() -> Result<PathBuf, String> {
             let home = user_dirs.home_dir().to_path_buf();
             let videos = home.join("Videos");
             // Try to create Videos directory if it doesn't exist
-            if !videos.exists() {
-                if std::fs::create_dir_all(&videos).is_ok() {
-                    return videos;
-                }
+            if !videos.exists() && std::fs::create_dir_all(&videos).is_ok() {
+                return videos;
             }
             // Fall back to home directory
             home
         });
+    Ok(output_dir)
+}
 
+/// Generate a unique output filename starting with `prefix` (such as
+/// "recording") with the given extension in the user's Videos folder.
+pub(crate) fn generate_output_path(prefix: &str, extension: &str) -> Result<PathBuf, String> {
+    let output_dir = default_output_dir()?;
     let timestamp = Local::now().format("%Y-%m-%d_%H%M%S");
-    let filename = format!("recording_{}.mp4", timestamp);
+    let filename = format!("{}_{}.{}", prefix, timestamp, extension);
 
     Ok(output_dir.join(filename))
 }
 
-/// Target frame rate for output video
-const TARGET_FPS: u32 = 30;
-/// Frame interval in milliseconds
-const FRAME_INTERVAL_MS: u64 = 1000 / TARGET_FPS as u64;
+/// Default frame rate for output video
+pub const DEFAULT_FPS: u32 = 30;
 
-/// Encoding task that receives frames from a channel and encodes them.
-/// Maintains consistent frame rate by duplicating frames when needed.
-pub async fn encode_frames(
-    mut frame_rx: mpsc::Receiver<CapturedFrame>,
-    stop_flag: Arc<AtomicBool>,
-) -> Result<PathBuf, String> {
-    eprintln!("[Encoder] encode_frames task started, waiting for first frame...");
-    
-    // Wait for first frame to get dimensions
-    let first_frame = frame_rx
-        .recv()
-        .await
-        .ok_or_else(|| {
-            eprintln!("[Encoder] recv() returned None - channel closed without frames");
-            "No frames received".to_string()
-        })?;
-    
-    eprintln!("[Encoder] Got first frame: {}x{}", first_frame.width, first_frame.height);
+/// Default time to spend re-establishing a lost capture
+pub const DEFAULT_RECOVERY_TIMEOUT_SECS: u32 = 30;
 
-    eprintln!("[Encoder] Creating VideoEncoder...");
-    let mut encoder = VideoEncoder::new(first_frame.width, first_frame.height)
+/// Start an encoder sized for `first_frame` and write that frame, trying
+/// each backend in `chain` until one works.
+///
+/// If GPU conversion fails, each backend is also tried with CPU conversion.
+fn start_encoder(
+    first_frame: &Arc<CapturedFrame>,
+    conversion: ConversionBackend,
+    settings: &EncoderSettings,
+    chain: &[EncoderBackend],
+    placement: &GpuPlacement,
+    stats: &Arc<StatsCollector>,
+) -> Result<VideoEncoder, String> {
+    let mut error = "No encoders left to try".to_string();
+    for &backend in chain {
+        let conversions: &[ConversionBackend] = if conversion.is_gpu() {
+            &[conversion, ConversionBackend::Cpu]
+        } else {
+            &[ConversionBackend::Cpu]
+        };
+        for &conversion in conversions {
+            match try_start_encoder(first_frame, conversion, backend, settings, placement, stats) {
+                Ok(encoder) => return Ok(encoder),
+                Err(e) => {
+                    tracing::warn!(target: "Encoder", "{:?} with {:?} conversion failed ({}), trying the next", backend, conversion, e);
+                    error = e;
+                }
+            }
+        }
+    }
+    Err(error)
+}
+
+/// The encoders in `chain` after `failed`.
+fn chain_after(chain: &[EncoderBackend], failed: EncoderBackend) -> &[EncoderBackend] {
+    let next = chain.iter().position(|&b| b == failed).map_or(chain.len(), |i| i + 1);
+    &chain[next..]
+}
+
+/// The encoders in `chain` from `current` on.
+fn chain_from(chain: &[EncoderBackend], current: EncoderBackend) -> &[EncoderBackend] {
+    let start = chain.iter().position(|&b| b == current).unwrap_or(0);
+    &chain[start..]
+}
+
+/// Create and start an encoder sized for `first_frame`, and write that frame.
+fn try_start_encoder(
+    first_frame: &Arc<CapturedFrame>,
+    conversion: ConversionBackend,
+    backend: EncoderBackend,
+    settings: &EncoderSettings,
+    placement: &GpuPlacement,
+    stats: &Arc<StatsCollector>,
+) -> Result<VideoEncoder, String> {
+    tracing::info!(target: "Encoder", "Creating VideoEncoder...");
+    let mut encoder = VideoEncoder::new(
+        first_frame.width,
+        first_frame.height,
+        first_frame.format,
+        first_frame.color,
+        conversion,
+        backend,
+        settings,
+    )
         .map_err(|e| {
-            eprintln!("[Encoder] Failed to create encoder: {}", e);
+            tracing::error!(target: "Encoder", "Failed to create encoder: {}", e);
             e
         })?;
+    encoder.set_stats(stats.clone());
+    encoder.set_placement(placement);
     
-    eprintln!("[Encoder] Starting FFmpeg...");
+    tracing::info!(target: "Encoder", "Starting FFmpeg...");
     encoder.start()
         .map_err(|e| {
-            eprintln!("[Encoder] Failed to start FFmpeg: {}", e);
+            tracing::error!(target: "Encoder", "Failed to start FFmpeg: {}", e);
             e
         })?;
 
-    eprintln!("[Encoder] Writing first frame...");
+    tracing::info!(target: "Encoder", "Writing first frame...");
     // Write first frame
-    encoder.write_frame(&first_frame)
+    encoder.write_frame(first_frame.clone())
         .map_err(|e| {
-            eprintln!("[Encoder] Failed to write first frame: {}", e);
+            tracing::error!(target: "Encoder", "Failed to write first frame: {}", e);
             e
         })?;
+
+    Ok(encoder)
+}
+
+/// Encoding task that receives frames from a channel and encodes them.
+/// Maintains consistent frame rate by duplicating frames when needed.
+/// Every frame written to the file is also delivered to the additional `sinks`.
+/// Encoders are chosen to suit the GPU `placement`.
+/// Every captured frame goes through `filters` first.
+/// The file ends on the exact frame `stop_at` stops it at, if it's given one.
+/// Progress is reported to `stats`. The first frame's time is noted on
+/// `clock`, and nothing is written while it's paused.
+/// Returns the recorded files, in order; there's more than one only if the
+/// capture changed resolution with [`ResolutionChangePolicy::NewSegment`]
+/// or an encoder failed mid-recording.
+#[allow(clippy::too_many_arguments)]
+pub async fn encode_frames(
+    mut frame_rx: FrameReceiver,
+    stop_flag: Arc<AtomicBool>,
+    mut sinks: Vec<Box<dyn FrameSink>>,
+    settings: EncoderSettings,
+    placement: GpuPlacement,
+    stats: Arc<StatsCollector>,
+    mut filters: FilterChain,
+    clock: Arc<SyncClock>,
+    stop_at: Arc<StopAt>,
+) -> Result<Vec<RecordedFile>, String> {
+    tracing::info!(target: "Encoder", "encode_frames task started, waiting for first frame...");
     
-    eprintln!("[Encoder] Encoder initialized, entering main loop...");
+    // Wait for first frame to get dimensions
+    let mut first_frame = frame_rx
+        .recv()
+        .await
+        .ok_or_else(|| {
+            tracing::warn!(target: "Encoder", "recv() returned None - channel closed without frames");
+            "No frames received".to_string()
+        })?;
+    
+    tracing::info!(target: "Encoder", "Got first frame: {}x{}", first_frame.width, first_frame.height);
+    stats.frame_captured();
+    filters.process(&mut first_frame);
+    // Idle pause watches for screen changes the same way
+    let mut motion =
+        (settings.motion.enabled || settings.idle_pause.enabled).then(|| MotionDetector::new(&settings.motion));
+    if let Some(ref mut motion) = motion {
+        motion.observe(&first_frame, std::time::Instant::now());
+    }
+
+    // The GPU conversion filters only take 8-bit BGRA input. Detecting the
+    // conversion backend and probing the hardware encoders run FFmpeg the
+    // first time, so keep them off the async runtime.
+    let gpu_input = first_frame.format == PixelFormat::Bgra8;
+    let (conversion, encoders) = {
+        let settings = settings.clone();
+        tokio::task::spawn_blocking(move || {
+            let conversion = match gpu_input {
+                true => settings.conversion_backend(),
+                false => ConversionBackend::Cpu,
+            };
+            (conversion, settings.encoder_chain())
+        })
+        .await
+        .map_err(|e| format!("Task error: {}", e))?
+    };
+    let conversion = if placement.allows_conversion(conversion) {
+        conversion
+    } else {
+        ConversionBackend::Cpu
+    };
+    // Sinks only take 8-bit BGRA, so deeper frames are converted for them as they arrive
+    let convert_for_sinks = first_frame.format.is_deep() && !sinks.is_empty();
+    let sink_tone_mapper = ToneMapper::default();
+    let sdr_frame = |frame: &CapturedFrame| match frame.format {
+        _ if !convert_for_sinks => None,
+        PixelFormat::Rgba16F => Some(CapturedFrame {
+            width: frame.width,
+            height: frame.height,
+            data: sink_tone_mapper.map_pixels(&frame.data),
+            format: PixelFormat::Bgra8,
+            color: frame.color,
+            captured_at: frame.captured_at,
+            presented_at: frame.presented_at,
+        }),
+        _ => frame.packed_10bit_to_bgra8(),
+    };
+    // Frames are shared with the encoder's pipeline, and written again
+    // while there's no newer one
+    let first_frame = Arc::new(first_frame);
+    let chain = placement.order_encoders(encoders);
+    let mut encoder = start_encoder(&first_frame, conversion, &settings, &chain, &placement, &stats)?;
+    let encode_adapter = placement.encode_adapter(encoder.backend());
+    if let (Some(capture), Some(encode)) = (&placement.capture, &encode_adapter) {
+        if capture.index != encode.index {
+            tracing::info!(target: "Encoder", "Frames captured on {} are copied through system memory to {}", capture.name, encode.name);
+        }
+    }
+    stats.set_capture_gpu(placement.capture.clone());
+    // Size of the frames the current segment was started with, and the
+    // segments already finished
+    let mut segment_size = (first_frame.width, first_frame.height);
+    let first_path = encoder.container_path();
+    let mut segments = Vec::new();
+    let mut last_sink_frame = sdr_frame(&first_frame);
+    write_to_sinks(&mut sinks, last_sink_frame.as_ref().unwrap_or(&first_frame));
+    stats.set_output_path(encoder.output_path().to_path_buf());
+    stats.set_encoder(encoder.encoder_name(), encode_adapter);
+    stats.frame_encoded(std::time::Duration::ZERO);
+    
+    tracing::info!(target: "Encoder", "Encoder initialized, entering main loop...");
 
     let mut frames_written = 1u64;
+    let mut segment_frames = 1u64;
     let start_time = std::time::Instant::now();
+    clock.mark_video_start();
     let mut last_frame = first_frame;
-    let mut next_frame_time = start_time + std::time::Duration::from_millis(FRAME_INTERVAL_MS);
+    let frame_interval = std::time::Duration::from_secs_f64(1.0 / settings.fps.max(1) as f64);
+    let mut schedule = FrameSchedule::new(start_time, frame_interval);
 
     // Process frames with timing
     loop {
@@ -246,40 +1050,189 @@ pub async fn encode_frames(
             break;
         }
 
-        // Try to receive a new frame (non-blocking)
-        match frame_rx.try_recv() {
-            Ok(frame) => {
-                last_frame = frame;
+        // Try to receive a new frame (non-blocking). Frame times before it
+        // was captured still show the previous frame, however late it arrived.
+        let received = match frame_rx.try_recv() {
+            Ok(frame) => Some(frame),
+            // No new frame available, we'll duplicate the last one if needed
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                break;
             }
-            Err(mpsc::error::TryRecvError::Empty) => {
-                // No new frame available, we'll duplicate the last one if needed
+        };
+        let until = received.as_ref().map_or(now, |frame| frame.captured_at.min(now));
+        if settings.match_refresh_rate {
+            if let Some(ref frame) = received {
+                schedule.align_to(frame.captured_at);
             }
-            Err(mpsc::error::TryRecvError::Disconnected) => {
-                break;
+        }
+
+        // While the session is locked, live outputs show the standby picture
+        let standby = if sinks.is_empty() { None } else { standby::picture(segment_size.0, segment_size.1) };
+
+        // While paused or nothing's moving, let the frame times pass without
+        // writing, except the standby picture to live outputs
+        let still = settings.motion.enabled && motion.as_mut().is_some_and(|motion| !motion.is_active(until));
+        if clock.pause().is_paused() || still {
+            let due = schedule.take_due(until);
+            if let Some(ref picture) = standby {
+                for _ in 0..due {
+                    write_to_sinks(&mut sinks, picture);
+                }
             }
         }
 
         // Write frame(s) to maintain target FPS
-        while next_frame_time <= now {
-            encoder.write_frame(&last_frame)?;
+        for _ in 0..schedule.take_due(until) {
+            // A scheduled stop ends the file on its exact frame
+            if stop_at.reached(frames_written, || stats.file_size()) {
+                stop_flag.store(true, Ordering::Relaxed);
+                break;
+            }
+            let write_start = std::time::Instant::now();
+            if let Err(e) = encoder.write_frame(last_frame.clone()) {
+                // FFmpeg exited: continue with the next encoder in the chain
+                tracing::error!(target: "Encoder", "{} failed mid-recording: {}", encoder.encoder_name(), e);
+                // An encoder that failed within its first second never
+                // produced a usable file, so its replacement overwrites it
+                let replaces_file = segment_frames < settings.fps as u64;
+                let mut next_settings = settings.clone();
+                next_settings.output_path = Some(if replaces_file {
+                    encoder.container_path()
+                } else {
+                    resize::segment_path(&first_path, segments.len() + 2)
+                });
+                let next = start_encoder(&last_frame, encoder.conversion(), &next_settings, chain_after(&chain, encoder.backend()), &placement, &stats)?;
+                match std::mem::replace(&mut encoder, next).finish() {
+                    Ok(path) if !replaces_file => segments.push(path),
+                    Ok(_) => {}
+                    Err(e) => tracing::error!(target: "Encoder", "Failed encoder's output is unusable: {}", e),
+                }
+                segment_frames = 0;
+                stats.set_output_path(encoder.output_path().to_path_buf());
+                stats.set_encoder(encoder.encoder_name(), placement.encode_adapter(encoder.backend()));
+            }
+            stats.frame_encoded(write_start.elapsed());
+            segment_frames += 1;
+            let sink_frame = standby.as_deref().or(last_sink_frame.as_ref()).unwrap_or(&last_frame);
+            write_to_sinks(&mut sinks, sink_frame);
             frames_written += 1;
-            next_frame_time += std::time::Duration::from_millis(FRAME_INTERVAL_MS);
         }
+        stats.update_queue(frame_rx.stats());
 
-
+        if let Some(mut frame) = received {
+            stats.frame_captured();
+            let process_start = std::time::Instant::now();
+            filters.process(&mut frame);
+            let mut frame = Arc::new(frame);
+            if (frame.width, frame.height) != segment_size {
+                let next = match settings.resolution_change {
+                    ResolutionChangePolicy::Scale => None,
+                    ResolutionChangePolicy::NewSegment => {
+                        let mut segment_settings = settings.clone();
+                        segment_settings.output_path = Some(resize::segment_path(&first_path, segments.len() + 2));
+                        start_encoder(&frame, encoder.conversion(), &segment_settings, chain_from(&chain, encoder.backend()), &placement, &stats)
+                            .map_err(|e| tracing::warn!(target: "Encoder", "Failed to start new segment ({}), scaling instead", e))
+                            .ok()
+                    }
+                };
+                match next {
+                    Some(next) => {
+                        tracing::info!(
+                            target: "Encoder",
+                            "Capture resized to {}x{}, continuing in {:?}",
+                            frame.width, frame.height, next.output_path()
+                        );
+                        segments.push(std::mem::replace(&mut encoder, next).finish()?);
+                        segment_size = (frame.width, frame.height);
+                        segment_frames = 0;
+                        stats.set_output_path(encoder.output_path().to_path_buf());
+                        stats.set_encoder(encoder.encoder_name(), placement.encode_adapter(encoder.backend()));
+                    }
+                    None => frame = Arc::new(resize::letterbox(&frame, segment_size.0, segment_size.1)),
+                }
+            }
+            if let Some(ref mut motion) = motion {
+                motion.observe(&frame, frame.captured_at);
+                if let Some(last) = motion.last_motion() {
+                    stats.screen_changed(last);
+                }
+            }
+            last_sink_frame = sdr_frame(&frame);
+            stats.stage_done(Stage::Process, process_start.elapsed());
+            last_frame = frame;
+            // More frames may be queued behind it
+            continue;
+        }
 
         // Sleep until next frame time (with some margin for processing)
-        let sleep_duration = next_frame_time.saturating_duration_since(std::time::Instant::now());
+        let sleep_duration = schedule.next().saturating_duration_since(std::time::Instant::now());
         if !sleep_duration.is_zero() {
             tokio::time::sleep(sleep_duration.min(std::time::Duration::from_millis(10))).await;
         }
     }
 
     let elapsed = start_time.elapsed().as_secs_f64();
-    println!("Recording complete: {:.1}s, {} frames", elapsed, frames_written);
+    tracing::info!(target: "Encoder", "Recording complete: {:.1}s, {} frames", elapsed, frames_written);
+    let skipped = SKIPPED_FRAMES.swap(0, Ordering::Relaxed);
+    if skipped > 0 {
+        tracing::warn!(target: "Encoder", "Skipped {} frames that didn't fit the encoder", skipped);
+    }
+    let queue_stats = frame_rx.stats();
+    if queue_stats.dropped > 0 {
+        tracing::warn!(
+            target: "Encoder",
+            "Capture queue dropped {} of {} frames ({:?})",
+            queue_stats.dropped,
+            queue_stats.dropped + queue_stats.delivered,
+            crate::capture::queue::drop_policy()
+        );
+    }
 
     // Finalize
-    encoder.finish()
+    finish_sinks(sinks);
+    segments.push(encoder.finish()?);
+    Ok(segments)
+}
+
+/// Task encoding a recording, resolving to the files written.
+type EncodingTask = tokio::task::JoinHandle<Result<Vec<RecordedFile>, String>>;
+
+/// A recording being encoded, which can be stopped and waited for.
+///
+/// Setting the stop flag only asks the recording to stop; its files are
+/// still being written for a while after. [`RecordingHandle::finalize`]
+/// waits until they're complete.
+pub struct RecordingHandle {
+    stop_flag: Arc<AtomicBool>,
+    task: EncodingTask,
+}
+
+impl RecordingHandle {
+    /// Run `encoding`, such as [`encode_frames`], which stops when
+    /// `stop_flag` is set.
+    pub fn spawn(
+        stop_flag: Arc<AtomicBool>,
+        encoding: impl std::future::Future<Output = Result<Vec<RecordedFile>, String>> + Send + 'static,
+    ) -> Self {
+        Self {
+            stop_flag,
+            task: tokio::spawn(encoding),
+        }
+    }
+
+    /// Ask the recording to stop, without waiting for it.
+    pub fn stop(&self) {
+        self.stop_flag.store(true, Ordering::Relaxed);
+    }
+
+    /// Stop the recording and wait for its files to be finalized: the
+    /// encoder's delayed frames flushed, the container trailers and indexes
+    /// written, and encrypted or memory-buffered output written out.
+    pub async fn finalize(self) -> Result<Vec<RecordedFile>, String> {
+        self.stop();
+        self.task.await.map_err(|e| format!("Task error: {}", e))?
+    }
 }
 
 /// Initialize FFmpeg (download if needed). Should be called once at app startup.
@@ -0,0 +1,174 @@
+//! Recording only while something on screen changes.
+//!
+//! With motion-triggered recording, each captured frame is compared with the
+//! previous one at thumbnail size. Once the picture has been still for a
+//! while, frames stop being written, and writing resumes as soon as
+//! something moves again, so hours of monitoring a long-running job or a
+//! kiosk keep only the stretches where something happened.
+
+use crate::capture::CapturedFrame;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Width of the thumbnails frames are compared at.
+const THUMBNAIL_WIDTH: u32 = 64;
+
+/// How much a thumbnail pixel must change to count as moving, out of 255.
+const PIXEL_THRESHOLD: u8 = 16;
+
+/// Settings for motion-triggered recording.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MotionSettings {
+    pub enabled: bool,
+    /// Percentage of the picture that must change to count as motion
+    pub threshold_percent: f32,
+    /// Keep recording this many seconds after the last motion
+    pub idle_secs: u32,
+}
+
+impl Default for MotionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold_percent: 0.5,
+            idle_secs: 3,
+        }
+    }
+}
+
+impl MotionSettings {
+    /// Check that the settings are usable.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=100.0).contains(&self.threshold_percent) {
+            return Err("The motion threshold must be between 0 and 100 percent".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Tracks whether the capture is moving, from the frames it delivers.
+pub struct MotionDetector {
+    threshold: f32,
+    idle: Duration,
+    previous: Option<(u32, u32, Vec<u8>)>,
+    last_motion: Option<Instant>,
+    active: bool,
+}
+
+impl MotionDetector {
+    pub fn new(settings: &MotionSettings) -> Self {
+        Self {
+            threshold: settings.threshold_percent / 100.0,
+            idle: Duration::from_secs(settings.idle_secs as u64),
+            previous: None,
+            last_motion: None,
+            active: true,
+        }
+    }
+
+    /// Compare a newly captured `frame` with the previous one. The first
+    /// frame, and any frame of a new size, counts as motion.
+    pub fn observe(&mut self, frame: &CapturedFrame, now: Instant) {
+        let current = thumbnail(frame);
+        let moved = match &self.previous {
+            Some(previous) if previous.0 == current.0 && previous.1 == current.1 => {
+                let changed = previous
+                    .2
+                    .iter()
+                    .zip(&current.2)
+                    .filter(|(a, b)| a.abs_diff(**b) > PIXEL_THRESHOLD)
+                    .count();
+                changed as f32 >= self.threshold * current.2.len().max(1) as f32
+            }
+            _ => true,
+        };
+        if moved {
+            self.last_motion = Some(now);
+        }
+        self.previous = Some(current);
+    }
+
+    /// When the picture last moved.
+    pub fn last_motion(&self) -> Option<Instant> {
+        self.last_motion
+    }
+
+    /// Whether frames should be written at `now`: there was motion within
+    /// the idle time.
+    pub fn is_active(&mut self, now: Instant) -> bool {
+        let active = self.last_motion.is_some_and(|last| now.duration_since(last) <= self.idle);
+        if active != self.active {
+            tracing::info!(target: "Motion", "{}", if active { "Motion detected, recording" } else { "No motion, pausing" });
+            self.active = active;
+        }
+        active
+    }
+}
+
+/// A small grayscale copy of `frame`, as (width, height, pixels).
+///
+/// Pixels are sampled rather than averaged, and reduced to the mean of their
+/// bytes; that isn't brightness in every pixel format, but it changes
+/// whenever the pixel does, which is all the comparison needs.
+fn thumbnail(frame: &CapturedFrame) -> (u32, u32, Vec<u8>) {
+    let bpp = frame.format.bytes_per_pixel();
+    let width = THUMBNAIL_WIDTH.min(frame.width);
+    let height = (frame.height as u64 * width as u64 / frame.width.max(1) as u64).max(1) as u32;
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        let src_y = (y as u64 * frame.height as u64 / height as u64) as usize;
+        for x in 0..width {
+            let src_x = (x as u64 * frame.width as u64 / width as u64) as usize;
+            let offset = (src_y * frame.width as usize + src_x) * bpp;
+            let value = frame
+                .data
+                .get(offset..offset + bpp)
+                .map_or(0, |pixel| pixel.iter().map(|&b| b as usize).sum::<usize>() / bpp);
+            pixels.push(value as u8);
+        }
+    }
+    (width, height, pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::{ColorSpace, PixelFormat};
+
+    #[test]
+    fn test_motion_detector() {
+        let frame = |shade: u8, box_width: usize| {
+            let mut data = vec![shade; 640 * 360 * 4];
+            // A box in the top left corner, as from a window opening
+            for row in data.chunks_exact_mut(640 * 4).take(180) {
+                row[..box_width * 4].fill(255);
+            }
+            CapturedFrame {
+                width: 640,
+                height: 360,
+                data,
+                format: PixelFormat::Bgra8,
+                color: ColorSpace::SRGB,
+                captured_at: Instant::now(),
+                presented_at: None,
+            }
+        };
+        let mut detector = MotionDetector::new(&MotionSettings {
+            enabled: true,
+            threshold_percent: 1.0,
+            idle_secs: 2,
+        });
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        detector.observe(&frame(0, 0), at(0));
+        assert!(detector.is_active(at(1)));
+        // Slight noise isn't motion
+        detector.observe(&frame(10, 0), at(1));
+        assert!(!detector.is_active(at(3)));
+        detector.observe(&frame(10, 320), at(4));
+        assert!(detector.is_active(at(4)));
+        assert!(!detector.is_active(at(7)));
+    }
+}
@@ -0,0 +1,292 @@
+//! NDI network output.
+//!
+//! Publishes the live capture as an NDI source so other machines on the LAN
+//! (OBS, vMix, NDI Studio Monitor) can ingest it directly. The NDI runtime is
+//! proprietary and installed separately, so it is loaded dynamically at the
+//! moment an NDI output is created rather than linked at build time.
+
+use super::sink::FrameSink;
+use crate::capture::CapturedFrame;
+use libloading::Library;
+use serde::{Deserialize, Serialize};
+use std::ffi::{c_char, c_float, c_int, c_void, CString};
+use std::path::PathBuf;
+
+/// Default NDI source name shown to receivers.
+pub const DEFAULT_SOURCE_NAME: &str = "Screen Recorder";
+
+/// FourCC for 8-bit BGRA frames ('B','G','R','A').
+const FOURCC_BGRA: u32 =
+    (b'B' as u32) | ((b'G' as u32) << 8) | ((b'R' as u32) << 16) | ((b'A' as u32) << 24);
+/// NDIlib_frame_format_type_progressive
+const FRAME_FORMAT_PROGRESSIVE: c_int = 1;
+/// NDIlib_send_timecode_synthesize
+const TIMECODE_SYNTHESIZE: i64 = i64::MAX;
+
+/// NDI output settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NdiSettings {
+    /// Whether recordings are also published over NDI
+    pub enabled: bool,
+    /// Source name advertised on the network
+    pub source_name: String,
+}
+
+impl Default for NdiSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            source_name: DEFAULT_SOURCE_NAME.to_string(),
+        }
+    }
+}
+
+/// NDIlib_send_create_t
+#[repr(C)]
+struct NdiSendCreate {
+    p_ndi_name: *const c_char,
+    p_groups: *const c_char,
+    clock_video: bool,
+    clock_audio: bool,
+}
+
+/// NDIlib_video_frame_v2_t
+#[repr(C)]
+struct NdiVideoFrameV2 {
+    xres: c_int,
+    yres: c_int,
+    fourcc: u32,
+    frame_rate_n: c_int,
+    frame_rate_d: c_int,
+    picture_aspect_ratio: c_float,
+    frame_format_type: c_int,
+    timecode: i64,
+    p_data: *const u8,
+    line_stride_in_bytes: c_int,
+    p_metadata: *const c_char,
+    timestamp: i64,
+}
+
+type InitializeFn = unsafe extern "C" fn() -> bool;
+type DestroyFn = unsafe extern "C" fn();
+type SendCreateFn = unsafe extern "C" fn(*const NdiSendCreate) -> *mut c_void;
+type SendDestroyFn = unsafe extern "C" fn(*mut c_void);
+type SendVideoFn = unsafe extern "C" fn(*mut c_void, *const NdiVideoFrameV2);
+
+/// Dynamically loaded NDI runtime entry points.
+struct NdiRuntime {
+    destroy: DestroyFn,
+    send_create: SendCreateFn,
+    send_destroy: SendDestroyFn,
+    send_video: SendVideoFn,
+    /// Keeps the library mapped while the function pointers are in use
+    _library: Library,
+}
+
+impl NdiRuntime {
+    /// Locate and load the NDI runtime library.
+    fn load() -> Result<Self, String> {
+        let mut last_error = String::from("no candidate paths");
+
+        for candidate in runtime_candidates() {
+            // SAFETY: loading the NDI runtime runs its initializers, which have no
+            // preconditions beyond being loaded once per process.
+            match unsafe { Library::new(&candidate) } {
+                Ok(library) => return unsafe { Self::from_library(library) },
+                Err(e) => last_error = format!("{}: {}", candidate.display(), e),
+            }
+        }
+
+        Err(format!(
+            "NDI runtime not found. Install the NDI Tools/Runtime from ndi.video ({})",
+            last_error
+        ))
+    }
+
+    /// Resolve the entry points we use and initialize the library.
+    unsafe fn from_library(library: Library) -> Result<Self, String> {
+        let initialize: InitializeFn = *library
+            .get(b"NDIlib_initialize\0")
+            .map_err(|e| format!("NDIlib_initialize missing: {}", e))?;
+        let destroy: DestroyFn = *library
+            .get(b"NDIlib_destroy\0")
+            .map_err(|e| format!("NDIlib_destroy missing: {}", e))?;
+        let send_create: SendCreateFn = *library
+            .get(b"NDIlib_send_create\0")
+            .map_err(|e| format!("NDIlib_send_create missing: {}", e))?;
+        let send_destroy: SendDestroyFn = *library
+            .get(b"NDIlib_send_destroy\0")
+            .map_err(|e| format!("NDIlib_send_destroy missing: {}", e))?;
+        let send_video: SendVideoFn = *library
+            .get(b"NDIlib_send_send_video_v2\0")
+            .map_err(|e| format!("NDIlib_send_send_video_v2 missing: {}", e))?;
+
+        if !initialize() {
+            return Err("NDI runtime failed to initialize (unsupported CPU?)".to_string());
+        }
+
+        Ok(Self {
+            destroy,
+            send_create,
+            send_destroy,
+            send_video,
+            _library: library,
+        })
+    }
+}
+
+impl Drop for NdiRuntime {
+    fn drop(&mut self) {
+        unsafe { (self.destroy)() };
+    }
+}
+
+/// Candidate locations of the NDI runtime library for this platform.
+fn runtime_candidates() -> Vec<PathBuf> {
+    #[cfg(target_os = "windows")]
+    const LIBRARY_NAMES: &[&str] = &["Processing.NDI.Lib.x64.dll"];
+    #[cfg(target_os = "linux")]
+    const LIBRARY_NAMES: &[&str] = &["libndi.so.6", "libndi.so.5", "libndi.so"];
+    #[cfg(target_os = "macos")]
+    const LIBRARY_NAMES: &[&str] = &["libndi.dylib"];
+
+    let mut candidates = Vec::new();
+
+    // The NDI installers export the runtime directory through these variables
+    for var in ["NDI_RUNTIME_DIR_V6", "NDI_RUNTIME_DIR_V5"] {
+        if let Ok(dir) = std::env::var(var) {
+            for name in LIBRARY_NAMES {
+                candidates.push(PathBuf::from(&dir).join(name));
+            }
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    candidates.push(PathBuf::from("/usr/local/lib/libndi.dylib"));
+
+    // Fall back to the system library search path
+    for name in LIBRARY_NAMES {
+        candidates.push(PathBuf::from(name));
+    }
+
+    candidates
+}
+
+/// Check whether the NDI runtime can be loaded on this machine.
+pub fn check_runtime() -> Result<(), String> {
+    NdiRuntime::load().map(|_| ())
+}
+
+/// Frame sink that publishes frames as an NDI source.
+pub struct NdiSink {
+    runtime: NdiRuntime,
+    instance: *mut c_void,
+    name: String,
+    fps: u32,
+}
+
+// SAFETY: an NDI send instance may be used from any thread as long as calls are
+// not concurrent, which `&mut self` on every use guarantees.
+unsafe impl Send for NdiSink {}
+
+impl NdiSink {
+    /// Create an NDI sender advertised under `source_name`, sending at `fps`.
+    pub fn new(source_name: &str, fps: u32) -> Result<Self, String> {
+        let runtime = NdiRuntime::load()?;
+
+        let source_name = if source_name.trim().is_empty() {
+            DEFAULT_SOURCE_NAME
+        } else {
+            source_name
+        };
+        let c_name = CString::new(source_name)
+            .map_err(|_| "NDI source name must not contain NUL characters".to_string())?;
+
+        let settings = NdiSendCreate {
+            p_ndi_name: c_name.as_ptr(),
+            p_groups: std::ptr::null(),
+            // The encoder loop already paces frames at the target rate
+            clock_video: false,
+            clock_audio: false,
+        };
+
+        let instance = unsafe { (runtime.send_create)(&settings) };
+        if instance.is_null() {
+            return Err(format!("Failed to create NDI sender '{}'", source_name));
+        }
+
+        tracing::info!(target: "NDI", "Publishing source '{}'", source_name);
+
+        Ok(Self {
+            runtime,
+            instance,
+            name: format!("NDI ({})", source_name),
+            fps,
+        })
+    }
+}
+
+impl FrameSink for NdiSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn write_frame(&mut self, frame: &CapturedFrame) -> Result<(), String> {
+        let expected = (frame.width as usize) * (frame.height as usize) * 4;
+        if frame.data.len() < expected {
+            return Err(format!(
+                "frame buffer too small ({} < {} bytes)",
+                frame.data.len(),
+                expected
+            ));
+        }
+
+        let video = NdiVideoFrameV2 {
+            xres: frame.width as c_int,
+            yres: frame.height as c_int,
+            fourcc: FOURCC_BGRA,
+            frame_rate_n: self.fps as c_int,
+            frame_rate_d: 1,
+            picture_aspect_ratio: 0.0, // square pixels
+            frame_format_type: FRAME_FORMAT_PROGRESSIVE,
+            timecode: TIMECODE_SYNTHESIZE,
+            p_data: frame.data.as_ptr(),
+            line_stride_in_bytes: (frame.width * 4) as c_int,
+            p_metadata: std::ptr::null(),
+            timestamp: 0,
+        };
+
+        // Synchronous send: NDI copies the frame before returning
+        unsafe { (self.runtime.send_video)(self.instance, &video) };
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), String> {
+        // Resources are released in Drop
+        Ok(())
+    }
+}
+
+impl Drop for NdiSink {
+    fn drop(&mut self) {
+        unsafe { (self.runtime.send_destroy)(self.instance) };
+        tracing::info!(target: "NDI", "Source '{}' closed", self.name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fourcc_bgra() {
+        assert_eq!(FOURCC_BGRA.to_le_bytes(), *b"BGRA");
+    }
+
+    #[test]
+    fn test_default_settings() {
+        let settings = NdiSettings::default();
+        assert!(!settings.enabled);
+        assert_eq!(settings.source_name, DEFAULT_SOURCE_NAME);
+    }
+}
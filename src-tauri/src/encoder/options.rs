@@ -0,0 +1,95 @@
+//! Advanced encoder options passed through to FFmpeg.
+//!
+//! Power users can set encoder options the app doesn't expose, such as
+//! x264's `tune` or NVENC's `rc-lookahead`, as a map of option names to
+//! values. Each entry is passed as `-name value` after the app's own encoder
+//! arguments, so it overrides them. Options the app manages itself (inputs,
+//! outputs, filters, codec selection) are rejected; the list is reported by
+//! the `get_encoder_capabilities` command.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Option names, without the leading `-`, mapped to their values.
+pub type AdvancedOptions = BTreeMap<String, String>;
+
+/// Options the app sets itself, which advanced options can't override.
+/// Stream specifiers are ignored, so `c` also covers `c:v`.
+pub const RESERVED_OPTIONS: &[&str] = &[
+    // Inputs, outputs and streams
+    "i", "f", "y", "n", "map", "an", "vn", "sn", "dn",
+    // Codec selection and the raw frame format
+    "c", "codec", "vcodec", "s", "r", "pix_fmt",
+    // Filters and hardware devices
+    "vf", "filter", "filter_complex", "lavfi", "filter_script", "init_hw_device", "filter_hw_device", "hwaccel",
+    // Limits that would cut the recording short
+    "t", "to", "ss", "fs", "frames",
+    // Reporting
+    "progress", "report", "stats", "loglevel", "v",
+];
+
+/// How advanced options are applied, for the capabilities API.
+pub const ADVANCED_OPTIONS_HELP: &str = "Each option is passed to FFmpeg as `-name value` after the app's own \
+encoder arguments, overriding them; x264-params and x265-params are merged with the app's. Options apply to \
+every encoder in the fallback chain, so an option only one encoder understands can stop the others from \
+starting.";
+
+/// Encoder features, for the settings UI and scripts.
+#[derive(Debug, Clone, Serialize)]
+pub struct EncoderCapabilities {
+    /// FFmpeg encoders recordings will try with the current settings, in order
+    pub encoders: Vec<&'static str>,
+    /// Options `advanced_options` can't set
+    pub reserved_options: &'static [&'static str],
+    pub advanced_options_help: &'static str,
+}
+
+/// Check that `options` can be passed to FFmpeg.
+pub fn validate(options: &AdvancedOptions) -> Result<(), String> {
+    for (name, value) in options {
+        if name.starts_with('-') {
+            return Err(format!("Write option {:?} without the leading '-'", name));
+        }
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || "_-:.".contains(c)) {
+            return Err(format!("Invalid option name {:?}", name));
+        }
+        let base = name.split(':').next().unwrap_or_default();
+        if RESERVED_OPTIONS.contains(&base) {
+            return Err(format!("Option {:?} is managed by the app and can't be overridden", name));
+        }
+        if value.chars().any(char::is_control) {
+            return Err(format!("Value of option {:?} contains control characters", name));
+        }
+    }
+    Ok(())
+}
+
+/// FFmpeg arguments for `options`.
+pub fn args(options: &AdvancedOptions) -> Vec<String> {
+    options
+        .iter()
+        .flat_map(|(name, value)| [format!("-{}", name), value.clone()])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advanced_options() {
+        let options = AdvancedOptions::from([
+            ("tune".to_string(), "animation".to_string()),
+            ("rc-lookahead".to_string(), "20".to_string()),
+        ]);
+        assert!(validate(&options).is_ok());
+        assert_eq!(args(&options), ["-rc-lookahead", "20", "-tune", "animation"]);
+
+        let invalid = |name: &str, value: &str| validate(&AdvancedOptions::from([(name.to_string(), value.to_string())])).is_err();
+        assert!(invalid("-tune", "film"));
+        assert!(invalid("c:v", "libx265"));
+        assert!(invalid("vf", "scale=640:-2"));
+        assert!(invalid("tune", "film\n-y"));
+        assert!(invalid("tune film", "x"));
+    }
+}
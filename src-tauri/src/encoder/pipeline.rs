@@ -0,0 +1,206 @@
+//! Conversion and writing stages of the encoder, each on its own thread.
+//!
+//! A recording runs as a chain of stages joined by bounded queues:
+//!
+//! 1. Capture: the backend's own thread or callback, which hands frames to
+//!    the capture queue and never waits (see [`crate::capture::queue`])
+//! 2. Processing: the encoding task, which filters frames, paces them to the
+//!    frame rate and feeds live outputs
+//! 3. Conversion: turns frames into FFmpeg's input format
+//! 4. Writing: pipes them to FFmpeg
+//!
+//! With each on its own thread, a slow write only holds up processing once
+//! the queues between them are full, and converting a 4K frame overlaps
+//! writing the one before. How long each stage takes is reported in the
+//! recording stats.
+
+use crate::capture::CapturedFrame;
+use crate::stats::{Stage, StatsCollector};
+use std::io::Write;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+/// Frames each queue holds before the stage feeding it waits.
+const QUEUE_DEPTH: usize = 2;
+
+/// Turns a frame into FFmpeg's input. Leaving the buffer empty skips the frame.
+pub type Convert = Box<dyn FnMut(&CapturedFrame, &mut Vec<u8>) -> Result<(), String> + Send>;
+
+/// The conversion and writing threads feeding one FFmpeg process.
+pub struct EncodePipeline {
+    frames: Option<SyncSender<Arc<CapturedFrame>>>,
+    convert: Option<JoinHandle<Result<(), String>>>,
+    write: Option<JoinHandle<Result<(), String>>>,
+}
+
+impl EncodePipeline {
+    /// Start converting frames with `convert` and writing them to `out`,
+    /// FFmpeg's input, reporting each stage's time to `stats`.
+    pub fn start(
+        out: impl Write + Send + 'static,
+        convert: Convert,
+        stats: Option<Arc<StatsCollector>>,
+    ) -> Result<Self, String> {
+        let (frame_tx, frame_rx) = sync_channel(QUEUE_DEPTH);
+        let (data_tx, data_rx) = sync_channel(QUEUE_DEPTH);
+        // Written buffers go back to be converted into again
+        let (spent_tx, spent_rx) = sync_channel(QUEUE_DEPTH + 2);
+
+        let convert_stats = stats.clone();
+        let convert = std::thread::Builder::new()
+            .name("encoder-convert".to_string())
+            .spawn(move || run_convert(frame_rx, data_tx, spent_rx, convert, convert_stats))
+            .map_err(|e| format!("Failed to start the conversion thread: {}", e))?;
+        let write = std::thread::Builder::new()
+            .name("encoder-write".to_string())
+            .spawn(move || run_write(data_rx, spent_tx, out, stats))
+            .map_err(|e| format!("Failed to start the writing thread: {}", e))?;
+
+        Ok(Self {
+            frames: Some(frame_tx),
+            convert: Some(convert),
+            write: Some(write),
+        })
+    }
+
+    /// Queue a frame, waiting if the stages are behind. Fails once a stage
+    /// has stopped, with the reason it did.
+    pub fn write_frame(&mut self, frame: Arc<CapturedFrame>) -> Result<(), String> {
+        let Some(frames) = &self.frames else {
+            return Err("The encoder has stopped".to_string());
+        };
+        if frames.send(frame).is_ok() {
+            return Ok(());
+        }
+        self.finish().and(Err("The encoder has stopped".to_string()))
+    }
+
+    /// Write the queued frames and stop the stages, closing the output.
+    pub fn finish(&mut self) -> Result<(), String> {
+        drop(self.frames.take());
+        let convert = join(self.convert.take(), "conversion");
+        let write = join(self.write.take(), "writing");
+        // A conversion failure stops the writer with nothing to report
+        convert.and(write)
+    }
+}
+
+impl Drop for EncodePipeline {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+/// Wait for a stage's thread, if it's still running.
+fn join(handle: Option<JoinHandle<Result<(), String>>>, stage: &str) -> Result<(), String> {
+    match handle.map(JoinHandle::join) {
+        Some(Ok(result)) => result,
+        Some(Err(_)) => Err(format!("The encoder's {} thread panicked", stage)),
+        None => Ok(()),
+    }
+}
+
+fn run_convert(
+    frames: Receiver<Arc<CapturedFrame>>,
+    data: SyncSender<Vec<u8>>,
+    spent: Receiver<Vec<u8>>,
+    mut convert: Convert,
+    stats: Option<Arc<StatsCollector>>,
+) -> Result<(), String> {
+    for frame in frames {
+        let started = Instant::now();
+        let mut buffer = spent.try_recv().unwrap_or_default();
+        buffer.clear();
+        convert(&frame, &mut buffer)?;
+        if let Some(stats) = &stats {
+            stats.stage_done(Stage::Convert, started.elapsed());
+        }
+        if buffer.is_empty() {
+            continue;
+        }
+        if data.send(buffer).is_err() {
+            // The writer failed and has its error
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn run_write(
+    data: Receiver<Vec<u8>>,
+    spent: SyncSender<Vec<u8>>,
+    mut out: impl Write,
+    stats: Option<Arc<StatsCollector>>,
+) -> Result<(), String> {
+    for buffer in data {
+        let started = Instant::now();
+        out.write_all(&buffer)
+            .map_err(|e| format!("Failed to write frame: {}", e))?;
+        if let Some(stats) = &stats {
+            stats.stage_done(Stage::Write, started.elapsed());
+        }
+        let _ = spent.try_send(buffer);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::mock;
+    use std::sync::Mutex;
+
+    /// Output that counts what's written to it, failing past `limit` bytes.
+    struct Counter {
+        written: Arc<Mutex<usize>>,
+        limit: usize,
+    }
+
+    impl Write for Counter {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            let mut written = self.written.lock().unwrap();
+            if *written + data.len() > self.limit {
+                return Err(std::io::ErrorKind::BrokenPipe.into());
+            }
+            *written += data.len();
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn start(limit: usize) -> (EncodePipeline, Arc<Mutex<usize>>) {
+        let written = Arc::new(Mutex::new(0));
+        let out = Counter {
+            written: written.clone(),
+            limit,
+        };
+        let stats = Arc::new(StatsCollector::new());
+        let convert: Convert = Box::new(|frame, buffer| {
+            buffer.extend_from_slice(&frame.data);
+            Ok(())
+        });
+        let pipeline = EncodePipeline::start(out, convert, Some(stats)).unwrap();
+        (pipeline, written)
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let frame = Arc::new(mock::synthesize_frame(16, 16, 0));
+        let (mut pipeline, written) = start(usize::MAX);
+        for _ in 0..10 {
+            pipeline.write_frame(frame.clone()).unwrap();
+        }
+        pipeline.finish().unwrap();
+        assert_eq!(*written.lock().unwrap(), 10 * frame.data.len());
+
+        // A failed write stops the pipeline, and its error comes back
+        let (mut pipeline, _) = start(3 * frame.data.len());
+        let error = (0..20).find_map(|_| pipeline.write_frame(frame.clone()).err()).unwrap();
+        assert!(error.starts_with("Failed to write frame"), "{}", error);
+    }
+}
@@ -0,0 +1,145 @@
+//! Rate control: how the encoder trades file size for quality.
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// Rate control mode for recordings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum RateControl {
+    /// Constant quality: CRF with software encoders, constant QP or the
+    /// encoder's quality target with hardware ones. Best for local
+    /// recordings. `quality` overrides the codec's default level; lower is
+    /// better.
+    Quality {
+        #[serde(default)]
+        quality: Option<u32>,
+    },
+    /// Variable bitrate averaging `bitrate_kbps`, peaking at
+    /// `max_bitrate_kbps` (the average if unset)
+    Vbr {
+        bitrate_kbps: u32,
+        #[serde(default)]
+        max_bitrate_kbps: Option<u32>,
+    },
+    /// Constant bitrate, for streaming
+    Cbr { bitrate_kbps: u32 },
+    /// Mathematically lossless encoding of the converted video, for
+    /// archival. Files are very large.
+    Lossless,
+}
+
+impl Default for RateControl {
+    fn default() -> Self {
+        RateControl::Quality { quality: None }
+    }
+}
+
+impl RateControl {
+    /// Check the mode's numbers make sense.
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            RateControl::Vbr { bitrate_kbps, max_bitrate_kbps } => {
+                if bitrate_kbps == 0 {
+                    return Err("Bitrate must be greater than zero".to_string());
+                }
+                if max_bitrate_kbps.is_some_and(|max| max < bitrate_kbps) {
+                    return Err("Maximum bitrate can't be below the average".to_string());
+                }
+            }
+            RateControl::Cbr { bitrate_kbps: 0 } => return Err("Bitrate must be greater than zero".to_string()),
+            RateControl::Quality { quality: Some(quality) } if quality > 63 => {
+                return Err("Quality level must be between 0 and 63".to_string());
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Read a rate control setting, also accepting the bare `bitrate_kbps`
+    /// number (or null) older profiles stored, as VBR capped at that rate.
+    pub fn deserialize_compat<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Stored {
+            Mode(RateControl),
+            Bitrate(u32),
+            Unset(()),
+        }
+        Ok(match Stored::deserialize(deserializer)? {
+            Stored::Mode(mode) => mode,
+            Stored::Bitrate(bitrate_kbps) => RateControl::Vbr { bitrate_kbps, max_bitrate_kbps: None },
+            Stored::Unset(()) => RateControl::default(),
+        })
+    }
+}
+
+/// Arguments for variable bitrate averaging `kbps` and peaking at
+/// `max_kbps`, with a two-second buffer.
+pub(super) fn vbr_args(kbps: u32, max_kbps: u32) -> Vec<String> {
+    vec![
+        "-b:v".to_string(),
+        format!("{}k", kbps),
+        "-maxrate".to_string(),
+        format!("{}k", max_kbps),
+        "-bufsize".to_string(),
+        format!("{}k", max_kbps * 2),
+    ]
+}
+
+/// Arguments holding the bitrate at `kbps`, with a one-second buffer.
+pub(super) fn cbr_args(kbps: u32) -> Vec<String> {
+    let rate = format!("{}k", kbps);
+    ["-b:v", &rate, "-minrate", &rate, "-maxrate", &rate, "-bufsize", &rate]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect()
+}
+
+/// Merge repeated x264/x265 parameter lists in `args` into one, since
+/// FFmpeg only keeps the last (e.g. lossless mode and HDR10 both set
+/// `-x265-params`).
+pub(super) fn merge_params(args: Vec<String>) -> Vec<String> {
+    let mut merged: Vec<String> = Vec::with_capacity(args.len());
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let value = match arg.as_str() {
+            "-x264-params" | "-x265-params" => args.next(),
+            _ => None,
+        };
+        match value {
+            Some(value) => match merged.iter().position(|a| *a == arg) {
+                Some(i) => merged[i + 1] = format!("{}:{}", merged[i + 1], value),
+                None => merged.extend([arg, value]),
+            },
+            None => merged.push(arg),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_control_compat() {
+        let read = |json: &str| RateControl::deserialize_compat(&mut serde_json::Deserializer::from_str(json)).unwrap();
+        assert_eq!(read("40000"), RateControl::Vbr { bitrate_kbps: 40000, max_bitrate_kbps: None });
+        assert_eq!(read("null"), RateControl::default());
+        assert_eq!(read(r#"{"mode": "cbr", "bitrate_kbps": 6000}"#), RateControl::Cbr { bitrate_kbps: 6000 });
+        assert_eq!(read(r#"{"mode": "lossless"}"#), RateControl::Lossless);
+
+        assert!(RateControl::Vbr { bitrate_kbps: 8000, max_bitrate_kbps: Some(4000) }.validate().is_err());
+        assert!(RateControl::Cbr { bitrate_kbps: 0 }.validate().is_err());
+        assert!(RateControl::Quality { quality: Some(18) }.validate().is_ok());
+    }
+
+    #[test]
+    fn test_merge_params() {
+        let args = ["-c:v", "libx265", "-x265-params", "lossless=1", "-x265-params", "hdr10=1", "-y"];
+        assert_eq!(
+            merge_params(args.iter().map(|a| a.to_string()).collect()),
+            ["-c:v", "libx265", "-x265-params", "lossless=1:hdr10=1", "-y"]
+        );
+    }
+}
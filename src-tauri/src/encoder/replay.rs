@@ -0,0 +1,270 @@
+//! Instant replay buffer kept alongside a recording.
+//!
+//! While a recording is running, a second FFmpeg process encodes the frames
+//! into short rolling segments in a temporary directory, overwriting the
+//! oldest so only the last minute or so is kept. Saving a replay joins the
+//! newest segments into a clip with a stream copy, so highlights can be saved
+//! during a long session without stopping or re-encoding the recording.
+
+use super::ffmpeg::run_ffmpeg;
+use super::codec::VideoCodec;
+use super::convert::ConversionBackend;
+use super::sink::FrameSink;
+use super::write_raw_frame;
+use crate::capture::{CapturedFrame, ColorSpace};
+use ffmpeg_sidecar::command::FfmpegCommand;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Stdio};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Default length of saved replays, in seconds.
+pub const DEFAULT_DURATION_SECS: u32 = 30;
+
+/// Length of each buffered segment, in seconds.
+const SEGMENT_SECS: u32 = 2;
+
+/// Replay buffer settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplaySettings {
+    /// Whether recordings keep a replay buffer
+    pub enabled: bool,
+    /// How many seconds a saved replay covers
+    pub duration_secs: u32,
+}
+
+impl Default for ReplaySettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            duration_secs: DEFAULT_DURATION_SECS,
+        }
+    }
+}
+
+/// The buffered segments of a running replay buffer.
+///
+/// Shared between the sink writing it and replays being saved; the segments
+/// are deleted once both are done with them.
+pub struct ReplayBuffer {
+    dir: PathBuf,
+    duration_secs: u32,
+}
+
+impl ReplayBuffer {
+    /// Join the last `duration_secs` of buffered video into a clip at `output`.
+    ///
+    /// This blocks; call it from a blocking task.
+    pub fn save(&self, output: &Path) -> Result<(), String> {
+        let entries = std::fs::read_dir(&self.dir).map_err(|e| format!("Failed to read replay buffer: {}", e))?;
+        let segments: Vec<(PathBuf, SystemTime)> = entries
+            .flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().ends_with(".ts"))
+            .filter_map(|entry| Some((entry.path(), entry.metadata().ok()?.modified().ok()?)))
+            .collect();
+        // The newest segment is still being written, so take one more to cover the duration
+        let count = self.duration_secs.div_ceil(SEGMENT_SECS) as usize + 1;
+        let segments = newest_segments(segments, count);
+        if segments.is_empty() {
+            return Err("The replay buffer is empty".to_string());
+        }
+
+        let list = self.dir.join("replay.txt");
+        std::fs::write(&list, concat_list(&segments)).map_err(|e| format!("Failed to write segment list: {}", e))?;
+        let result = run_ffmpeg([
+            "-f".to_string(),
+            "concat".to_string(),
+            "-safe".to_string(),
+            "0".to_string(),
+            "-i".to_string(),
+            list.to_string_lossy().to_string(),
+            "-c".to_string(),
+            "copy".to_string(),
+            "-movflags".to_string(),
+            "+faststart".to_string(),
+            "-y".to_string(),
+            output.to_string_lossy().to_string(),
+        ]);
+        let _ = std::fs::remove_file(&list);
+        if let Err(e) = result {
+            let _ = std::fs::remove_file(output);
+            return Err(e);
+        }
+        tracing::info!(target: "Replay", "Saved {} segments to {}", segments.len(), output.display());
+        Ok(())
+    }
+}
+
+impl Drop for ReplayBuffer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Frame sink that keeps the recording's last few seconds encoded in a
+/// rolling buffer.
+pub struct ReplaySink {
+    buffer: Arc<ReplayBuffer>,
+    child: Option<Child>,
+    stdin: Option<ChildStdin>,
+    width: u32,
+    height: u32,
+    fps: u32,
+}
+
+impl ReplaySink {
+    /// Create the buffer directory for frames arriving at `fps`.
+    ///
+    /// The FFmpeg process is started lazily once the first frame reveals the
+    /// capture dimensions.
+    pub fn new(settings: &ReplaySettings, fps: u32) -> Result<Self, String> {
+        if settings.duration_secs == 0 {
+            return Err("Replay duration must be greater than zero".to_string());
+        }
+        let dir = std::env::temp_dir().join(format!("screen-recorder-replay-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create replay buffer directory: {}", e))?;
+        tracing::info!(target: "Replay", "Buffering the last {} seconds", settings.duration_secs);
+
+        Ok(Self {
+            buffer: Arc::new(ReplayBuffer {
+                dir,
+                duration_secs: settings.duration_secs,
+            }),
+            child: None,
+            stdin: None,
+            width: 0,
+            height: 0,
+            fps,
+        })
+    }
+
+    /// Handle for saving replays from the buffer.
+    pub fn buffer(&self) -> Arc<ReplayBuffer> {
+        self.buffer.clone()
+    }
+
+    /// Start the FFmpeg segment encoder for the first frame's size and format.
+    fn start_encoder(&mut self, frame: &CapturedFrame) -> Result<(), String> {
+        // Even dimensions are required by the H.264 encoder
+        let width = frame.width & !1;
+        let height = frame.height & !1;
+        if width == 0 || height == 0 {
+            return Err(format!("Invalid dimensions: {}x{}", width, height));
+        }
+
+        // Keep a couple of spare segments so the oldest needed isn't overwritten mid-save
+        let segment_count = self.buffer.duration_secs.div_ceil(SEGMENT_SECS) + 3;
+        let segment_pattern = self.buffer.dir.join("segment_%03d.ts");
+        let keyframe_interval = (self.fps * SEGMENT_SECS).to_string();
+
+        let mut command = FfmpegCommand::new();
+        command
+            .args(["-f", "rawvideo"])
+            .args(["-pix_fmt", frame.format.ffmpeg_name()])
+            .args(["-s", &format!("{}x{}", width, height)])
+            .args(["-r", &self.fps.to_string()])
+            .args(["-i", "-"])
+            .args(["-c:v", "libx264"])
+            .args(["-preset", "veryfast"])
+            // Fixed GOP so every segment starts with a keyframe and can be joined
+            .args(["-g", &keyframe_interval])
+            .args(["-sc_threshold", "0"])
+            // Converted and tagged explicitly; FFmpeg's default is untagged BT.601
+            .args(["-vf", &ConversionBackend::Cpu.filter(width, height, false, ColorSpace::VIDEO)])
+            .args(VideoCodec::H264.sdr_color_args(frame.color, ColorSpace::VIDEO))
+            .args(["-f", "segment"])
+            .args(["-segment_time", &SEGMENT_SECS.to_string()])
+            .args(["-segment_wrap", &segment_count.to_string()])
+            .arg("-y")
+            .arg(segment_pattern.to_string_lossy().to_string());
+
+        let inner_command = command.as_inner_mut();
+        inner_command.stdin(Stdio::piped());
+        inner_command.stdout(Stdio::null());
+        inner_command.stderr(Stdio::null());
+
+        let mut child = inner_command
+            .spawn()
+            .map_err(|e| format!("Failed to start FFmpeg for the replay buffer: {}", e))?;
+        let stdin = child.stdin.take().ok_or("Failed to get FFmpeg stdin")?;
+
+        self.width = width;
+        self.height = height;
+        self.stdin = Some(stdin);
+        self.child = Some(child);
+        Ok(())
+    }
+
+    /// Close the encoder input and wait for FFmpeg to exit.
+    fn stop_encoder(&mut self) {
+        drop(self.stdin.take());
+        if let Some(mut child) = self.child.take() {
+            let _ = child.wait();
+        }
+    }
+}
+
+impl FrameSink for ReplaySink {
+    fn name(&self) -> &str {
+        "replay buffer"
+    }
+
+    fn write_frame(&mut self, frame: &CapturedFrame) -> Result<(), String> {
+        if self.child.is_none() {
+            self.start_encoder(frame)?;
+        }
+
+        match self.stdin {
+            Some(ref mut stdin) => write_raw_frame(stdin, frame, self.width, self.height),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), String> {
+        self.stop_encoder();
+        Ok(())
+    }
+}
+
+impl Drop for ReplaySink {
+    fn drop(&mut self) {
+        self.stop_encoder();
+    }
+}
+
+/// The newest `count` segments, oldest first.
+fn newest_segments(mut segments: Vec<(PathBuf, SystemTime)>, count: usize) -> Vec<PathBuf> {
+    segments.sort_by_key(|(_, modified)| *modified);
+    let skip = segments.len().saturating_sub(count);
+    segments.into_iter().skip(skip).map(|(path, _)| path).collect()
+}
+
+/// FFmpeg concat demuxer list joining `segments`.
+fn concat_list(segments: &[PathBuf]) -> String {
+    segments
+        .iter()
+        .map(|path| format!("file '{}'\n", path.to_string_lossy().replace('\'', "'\\''")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_newest_segments() {
+        // Segment numbers wrap around, so order comes from modification times
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let segments = vec![
+            (PathBuf::from("segment_000.ts"), at(40)),
+            (PathBuf::from("segment_001.ts"), at(20)),
+            (PathBuf::from("segment_002.ts"), at(30)),
+        ];
+        let newest = newest_segments(segments, 2);
+        assert_eq!(newest, [PathBuf::from("segment_002.ts"), PathBuf::from("segment_000.ts")]);
+        assert_eq!(concat_list(&newest[..1]), "file 'segment_002.ts'\n");
+    }
+}
@@ -0,0 +1,118 @@
+//! Handling for captures that change resolution mid-recording.
+//!
+//! The encoder's frame size is fixed when FFmpeg starts, so frames of a new
+//! size (a game switching display mode, a window being resized) are either
+//! scaled to the original size or start a new output file.
+
+use crate::capture::CapturedFrame;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// What to do when the capture's resolution changes during a recording.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResolutionChangePolicy {
+    /// Scale new frames to the original size, keeping their aspect ratio
+    /// with black bars
+    #[default]
+    Scale,
+    /// Finish the current file and continue in a new one at the new size
+    NewSegment,
+}
+
+/// `frame` scaled to fit `width`x`height` with its aspect ratio kept,
+/// centered on black.
+///
+/// Uses nearest-neighbor sampling so any pixel format can be scaled without
+/// decoding it; resolution changes are rare enough that quality matters less
+/// than keeping up with the capture.
+pub fn letterbox(frame: &CapturedFrame, width: u32, height: u32) -> CapturedFrame {
+    let bpp = frame.format.bytes_per_pixel();
+    let (src_w, src_h) = (frame.width as usize, frame.height as usize);
+    let (dst_w, dst_h) = (width as usize, height as usize);
+    let mut data = frame.format.black().repeat(dst_w * dst_h);
+    if src_w == 0 || src_h == 0 {
+        return CapturedFrame {
+            width,
+            height,
+            data,
+            format: frame.format,
+            color: frame.color,
+            captured_at: frame.captured_at,
+            presented_at: frame.presented_at,
+        };
+    }
+
+    // Largest size with the source's aspect ratio that fits
+    let (fit_w, fit_h) = if src_w * dst_h > dst_w * src_h {
+        (dst_w, (src_h * dst_w / src_w).max(1))
+    } else {
+        ((src_w * dst_h / src_h).max(1), dst_h)
+    };
+    let (left, top) = ((dst_w - fit_w) / 2, (dst_h - fit_h) / 2);
+
+    for y in 0..fit_h {
+        let src_row = (y * src_h / fit_h) * src_w;
+        let dst_row = (top + y) * dst_w + left;
+        for x in 0..fit_w {
+            let src = (src_row + x * src_w / fit_w) * bpp;
+            let dst = (dst_row + x) * bpp;
+            data[dst..dst + bpp].copy_from_slice(&frame.data[src..src + bpp]);
+        }
+    }
+
+    CapturedFrame {
+        width,
+        height,
+        data,
+        format: frame.format,
+        color: frame.color,
+        captured_at: frame.captured_at,
+        presented_at: frame.presented_at,
+    }
+}
+
+/// Path of segment `index` (counting from 1) of a recording whose first
+/// segment is `first`: `recording.mp4` continues in `recording_part2.mp4`.
+pub fn segment_path(first: &Path, index: usize) -> PathBuf {
+    let stem = first.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+    let name = match first.extension() {
+        Some(ext) => format!("{}_part{}.{}", stem, index, ext.to_string_lossy()),
+        None => format!("{}_part{}", stem, index),
+    };
+    first.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::{ColorSpace, PixelFormat};
+    use crate::capture::mock::synthesize_frame;
+    use crate::golden::assert_golden;
+    use std::time::Instant;
+
+    #[test]
+    fn test_letterbox() {
+        // 2x1 scaled into 4x4: doubled and centered vertically
+        let frame = CapturedFrame {
+            width: 2,
+            height: 1,
+            data: vec![1, 1, 1, 255, 2, 2, 2, 255],
+            format: PixelFormat::Bgra8,
+            color: ColorSpace::SRGB,
+            captured_at: Instant::now(),
+            presented_at: None,
+        };
+        let scaled = letterbox(&frame, 4, 4);
+        let pixels: Vec<u8> = scaled.data.chunks(4).map(|p| p[0]).collect();
+        assert_eq!(pixels, [0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 0, 0, 0, 0]);
+
+        // A 4:3 mode change recorded into a 16:9 output gets pillarboxed
+        assert_golden("letterbox_4x3", &letterbox(&synthesize_frame(160, 120, 3), 192, 108), 0);
+
+        assert_eq!(
+            segment_path(Path::new("/videos/recording.mp4"), 2),
+            Path::new("/videos/recording_part2.mp4")
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! Timing output frames from when frames were captured.
+//!
+//! The video has a fixed frame rate, so each output frame time shows the
+//! latest frame captured by then. Frames are matched to frame times by
+//! their capture timestamps rather than by when the encoder gets to them:
+//! under load several frames can wait in the queue, and placing them by
+//! arrival would repeat one for a stretch and then play the rest late and
+//! back to back.
+//!
+//! When the frame rate matches the display's refresh rate, frames are
+//! captured as the display presents them, one per frame time. Frame times
+//! that fall right on those presents would show some frames twice and skip
+//! others as the timestamps jitter, so the schedule locks itself midway
+//! between them (see [`FrameSchedule::align_to`]).
+
+use std::time::{Duration, Instant};
+
+/// How much of its distance from the midpoint between presented frames the
+/// schedule moves at each frame; small, so one late frame barely moves it.
+const ALIGN_GAIN: f64 = 0.125;
+
+/// The output's frame times.
+pub struct FrameSchedule {
+    next: Instant,
+    interval: Duration,
+}
+
+impl FrameSchedule {
+    /// Frame times every `interval`, the first after `start`, which shows
+    /// the first frame.
+    pub fn new(start: Instant, interval: Duration) -> Self {
+        Self {
+            next: start + interval,
+            interval,
+        }
+    }
+
+    /// The next frame time.
+    pub fn next(&self) -> Instant {
+        self.next
+    }
+
+    /// Move past the frame times up to and including `until`, returning how
+    /// many there were.
+    pub fn take_due(&mut self, until: Instant) -> u64 {
+        let mut due = 0;
+        while self.next <= until {
+            self.next += self.interval;
+            due += 1;
+        }
+        due
+    }
+
+    /// Move the frame times toward midway between frames presented every
+    /// frame interval, given one captured at `captured_at`. The correction
+    /// follows a sine of where the frame time falls between presents, so
+    /// it's smooth where the frame times sit on a present and jitter on
+    /// either side of it can't cancel out and hold them there.
+    pub fn align_to(&mut self, captured_at: Instant) {
+        let interval = self.interval.as_secs_f64();
+        let ahead = if self.next >= captured_at {
+            (self.next - captured_at).as_secs_f64()
+        } else {
+            -(captured_at - self.next).as_secs_f64()
+        };
+        let shift = ALIGN_GAIN * interval / std::f64::consts::TAU * (std::f64::consts::TAU * ahead / interval).sin();
+        let magnitude = Duration::from_secs_f64(shift.abs());
+        if shift > 0.0 {
+            self.next += magnitude;
+        } else {
+            self.next -= magnitude;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_placed_by_capture_time() {
+        let start = Instant::now();
+        let ms = |ms: u64| start + Duration::from_millis(ms);
+        let mut schedule = FrameSchedule::new(start, Duration::from_millis(100));
+
+        // A frame captured at 250ms but dequeued at 500ms takes over from
+        // 300ms; the first frame still fills the two frame times before it
+        assert_eq!(schedule.take_due(ms(250)), 2);
+        assert_eq!(schedule.take_due(ms(500)), 3);
+        // A frame captured before the next frame time takes no frame times
+        // of the previous one
+        assert_eq!(schedule.take_due(ms(520)), 0);
+        assert_eq!(schedule.next(), ms(600));
+    }
+
+    #[test]
+    fn test_align_to_presented_frames() {
+        let start = Instant::now();
+        let interval = Duration::from_millis(10);
+        let mut schedule = FrameSchedule::new(start, interval);
+
+        // Frames presented right on the frame times, with a little jitter
+        let mut due = Vec::new();
+        for frame in 1..200u64 {
+            let jitter = if frame % 2 == 0 { 1 } else { 0 };
+            let captured_at = start + interval * frame as u32 + Duration::from_millis(jitter);
+            schedule.align_to(captured_at);
+            due.push(schedule.take_due(captured_at));
+        }
+        // Once locked in between, every frame takes exactly one frame time
+        assert!(due[100..].iter().all(|&due| due == 1));
+        let phase = (schedule.next() - start).as_secs_f64() % 0.01;
+        assert!((0.004..0.007).contains(&phase), "phase {phase}");
+    }
+}
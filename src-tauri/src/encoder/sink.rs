@@ -0,0 +1,228 @@
+//! Output sinks that receive frames alongside the file encoder.
+
+use super::hls::{HlsSettings, HlsSink};
+use super::ndi::{NdiSettings, NdiSink};
+use super::replay::ReplaySettings;
+use crate::capture::CapturedFrame;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Wait before restarting a failed output the first time; it doubles with
+/// each failure in a row.
+const RETRY_INITIAL: Duration = Duration::from_secs(1);
+
+/// Longest wait between restarts.
+const RETRY_MAX: Duration = Duration::from_secs(60);
+
+/// How long a restarted output has to run before its failures stop counting.
+const RETRY_RESET: Duration = Duration::from_secs(30);
+
+/// A destination for frames produced by the recording pipeline.
+///
+/// Sinks receive frames at the same cadence as the file encoder. A sink that
+/// returns an error is disabled without interrupting the recording; wrap it
+/// in a [`RetryingSink`] to have it restarted instead.
+pub trait FrameSink: Send {
+    /// Short name used in log messages.
+    fn name(&self) -> &str;
+
+    /// Deliver a frame to the sink.
+    fn write_frame(&mut self, frame: &CapturedFrame) -> Result<(), String>;
+
+    /// Flush and release the sink.
+    fn finish(self: Box<Self>) -> Result<(), String>;
+}
+
+/// Settings for the additional outputs attached to each recording.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OutputSettings {
+    /// NDI network source output
+    pub ndi: NdiSettings,
+    /// Live HLS stream served on the LAN
+    pub hls: HlsSettings,
+    /// Instant replay buffer; its sink is created by the recording manager,
+    /// which keeps a handle for saving replays
+    pub replay: ReplaySettings,
+}
+
+impl OutputSettings {
+    /// Create the sinks enabled by these settings, for frames delivered at `fps`.
+    pub fn create_sinks(&self, fps: u32) -> Result<Vec<Box<dyn FrameSink>>, String> {
+        let mut sinks: Vec<Box<dyn FrameSink>> = Vec::new();
+
+        if self.ndi.enabled {
+            let source_name = self.ndi.source_name.clone();
+            sinks.push(Box::new(RetryingSink::new("NDI", move || {
+                Ok(Box::new(NdiSink::new(&source_name, fps)?))
+            })?));
+        }
+
+        if self.hls.enabled {
+            let hls = self.hls.clone();
+            sinks.push(Box::new(RetryingSink::new("HLS", move || Ok(Box::new(HlsSink::new(&hls, fps)?)))?));
+        }
+
+        Ok(sinks)
+    }
+}
+
+/// Creates a sink.
+type SinkFactory = Box<dyn FnMut() -> Result<Box<dyn FrameSink>, String> + Send>;
+
+/// A live output that's restarted when it fails, instead of being dropped for
+/// the rest of the recording.
+///
+/// The recording file is written regardless, so a failing output loses only
+/// what it would have streamed. Frames are skipped while it's down, and it's
+/// recreated with exponential backoff, picking up from the frame at hand.
+pub struct RetryingSink {
+    name: String,
+    create: SinkFactory,
+    sink: Option<Box<dyn FrameSink>>,
+    /// Failures since the output last ran for [`RETRY_RESET`]
+    failures: u32,
+    started: Instant,
+    retry_at: Instant,
+}
+
+impl RetryingSink {
+    /// Create the output with `create`, which is called again to restart it.
+    pub fn new(
+        name: &str,
+        mut create: impl FnMut() -> Result<Box<dyn FrameSink>, String> + Send + 'static,
+    ) -> Result<Self, String> {
+        let sink = create()?;
+        let now = Instant::now();
+        Ok(Self {
+            name: name.to_string(),
+            create: Box::new(create),
+            sink: Some(sink),
+            failures: 0,
+            started: now,
+            retry_at: now,
+        })
+    }
+
+    /// Stop the failed output and schedule its restart.
+    fn fail(&mut self, error: &str) {
+        drop(self.sink.take());
+        if self.started.elapsed() >= RETRY_RESET {
+            self.failures = 0;
+        }
+        let delay = retry_delay(self.failures);
+        self.failures += 1;
+        self.retry_at = Instant::now() + delay;
+        tracing::warn!(
+            target: "Encoder",
+            "{} output failed, retrying in {}s: {}",
+            self.name,
+            delay.as_secs(),
+            error
+        );
+    }
+}
+
+impl FrameSink for RetryingSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn write_frame(&mut self, frame: &CapturedFrame) -> Result<(), String> {
+        if self.sink.is_none() {
+            if Instant::now() < self.retry_at {
+                return Ok(());
+            }
+            match (self.create)() {
+                Ok(sink) => {
+                    tracing::info!(target: "Encoder", "{} output restarted", self.name);
+                    self.sink = Some(sink);
+                    self.started = Instant::now();
+                }
+                Err(e) => {
+                    self.fail(&e);
+                    return Ok(());
+                }
+            }
+        }
+        if let Some(Err(e)) = self.sink.as_mut().map(|sink| sink.write_frame(frame)) {
+            self.fail(&e);
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), String> {
+        match self.sink.take() {
+            Some(sink) => sink.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// How long to wait after `failures` failures in a row before restarting.
+fn retry_delay(failures: u32) -> Duration {
+    RETRY_INITIAL.saturating_mul(1 << failures.min(16)).min(RETRY_MAX)
+}
+
+/// Write a frame to every sink, dropping any sink that fails.
+pub fn write_to_sinks(sinks: &mut Vec<Box<dyn FrameSink>>, frame: &CapturedFrame) {
+    sinks.retain_mut(|sink| match sink.write_frame(frame) {
+        Ok(()) => true,
+        Err(e) => {
+            tracing::warn!(target: "Encoder", "Disabling {} output: {}", sink.name(), e);
+            false
+        }
+    });
+}
+
+/// Finish all sinks, logging (but not propagating) errors.
+pub fn finish_sinks(sinks: Vec<Box<dyn FrameSink>>) {
+    for sink in sinks {
+        let name = sink.name().to_string();
+        if let Err(e) = sink.finish() {
+            tracing::error!(target: "Encoder", "Failed to finish {} output: {}", name, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::mock;
+
+    /// A sink that fails every write.
+    struct FailingSink;
+
+    impl FrameSink for FailingSink {
+        fn name(&self) -> &str {
+            "Failing"
+        }
+
+        fn write_frame(&mut self, _frame: &CapturedFrame) -> Result<(), String> {
+            Err("disconnected".to_string())
+        }
+
+        fn finish(self: Box<Self>) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_retrying_sink() {
+        assert_eq!(retry_delay(0), Duration::from_secs(1));
+        assert_eq!(retry_delay(3), Duration::from_secs(8));
+        assert_eq!(retry_delay(40), RETRY_MAX);
+
+        let mut created = 0;
+        let retrying = RetryingSink::new("Failing", move || {
+            created += 1;
+            assert_eq!(created, 1, "restarted before the backoff elapsed");
+            Ok(Box::new(FailingSink))
+        })
+        .unwrap();
+        let mut sinks: Vec<Box<dyn FrameSink>> = vec![Box::new(retrying)];
+        let frame = mock::synthesize_frame(16, 16, 0);
+        write_to_sinks(&mut sinks, &frame);
+        write_to_sinks(&mut sinks, &frame);
+        assert_eq!(sinks.len(), 1);
+    }
+}
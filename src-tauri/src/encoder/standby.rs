@@ -0,0 +1,88 @@
+//! Standby picture for live outputs while the session is locked.
+//!
+//! A paused recording stops feeding its live outputs, which leaves viewers
+//! looking at a frozen frame, and a recording that carries on would stream
+//! the lock screen. While standby is on, live outputs are sent a "be right
+//! back" image (or black) at the recording's frame rate instead, whatever the
+//! file is doing.
+
+use super::image::load_image_fitted;
+use crate::capture::{CapturedFrame, ColorSpace, PixelFormat};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Standby, while it's on.
+struct Standby {
+    /// Image to show; black without one
+    image: Option<PathBuf>,
+    /// The image as last fitted to the outputs
+    picture: Option<Arc<CapturedFrame>>,
+}
+
+static STANDBY: Mutex<Option<Standby>> = Mutex::new(None);
+
+/// Start sending live outputs `image`, or black.
+pub fn begin(image: Option<PathBuf>) {
+    *STANDBY.lock().unwrap() = Some(Standby { image, picture: None });
+}
+
+/// Go back to sending live outputs what's recorded.
+pub fn end() {
+    *STANDBY.lock().unwrap() = None;
+}
+
+/// The picture to send `width`x`height` live outputs, while standby is on.
+///
+/// Decodes the image the first time it's needed at a size, which blocks
+/// while FFmpeg runs.
+pub fn picture(width: u32, height: u32) -> Option<Arc<CapturedFrame>> {
+    let mut standby = STANDBY.lock().unwrap();
+    let standby = standby.as_mut()?;
+    if let Some(picture) = standby.picture.as_ref().filter(|p| (p.width, p.height) == (width, height)) {
+        return Some(picture.clone());
+    }
+
+    let mut picture = match standby.image.as_deref().map(|path| load_image_fitted(path, width, height)) {
+        Some(Ok(picture)) => picture,
+        Some(Err(e)) => {
+            tracing::warn!(target: "Standby", "Showing black instead of the standby image: {}", e);
+            black(width, height)
+        }
+        None => black(width, height),
+    };
+    // Outputs that keep alpha, like NDI, would otherwise show through
+    for pixel in picture.data.chunks_exact_mut(4) {
+        pixel[3] = 255;
+    }
+    let picture = Arc::new(picture);
+    standby.picture = Some(picture.clone());
+    Some(picture)
+}
+
+/// An opaque black frame.
+fn black(width: u32, height: u32) -> CapturedFrame {
+    CapturedFrame {
+        width,
+        height,
+        data: [0, 0, 0, 255].repeat(width as usize * height as usize),
+        format: PixelFormat::Bgra8,
+        color: ColorSpace::SRGB,
+        captured_at: std::time::Instant::now(),
+        presented_at: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_picture() {
+        begin(None);
+        let picture = picture(4, 2).unwrap();
+        assert_eq!((picture.width, picture.height), (4, 2));
+        assert!(picture.data.chunks_exact(4).all(|pixel| pixel == [0, 0, 0, 255]));
+        end();
+        assert!(super::picture(4, 2).is_none());
+    }
+}
@@ -0,0 +1,82 @@
+//! Saving space while the picture doesn't change.
+//!
+//! Recordings of an IDE or a terminal are still most of the time, yet the
+//! encoder is given every frame: rate-controlled modes spend their bitrate
+//! refining a picture that isn't changing, and every few seconds a keyframe
+//! repeats it whole. With the static content saver on, FFmpeg compares each
+//! converted frame with the last one it kept (`mpdecimate`, in 8x8 blocks)
+//! and once the picture has been still for a while, stops passing frames to
+//! the encoder until something changes. The file is written at a variable
+//! frame rate, so the last frame is simply shown for longer. Keyframe
+//! intervals count frames, so keyframes spread out over still stretches, and
+//! the full frame rate and interval come back with the first frame that
+//! changes. A frame still gets through, and a keyframe is forced, every
+//! [`MAX_STILL_SECS`], so players can seek.
+//!
+//! Unlike motion-triggered recording (see [`super::motion`]), no time is
+//! cut from the recording. Needs FFmpeg 7.1 or later.
+
+use serde::{Deserialize, Serialize};
+
+/// Longest a still stretch goes without a frame, and a keyframe.
+pub const MAX_STILL_SECS: u32 = 10;
+
+/// Settings for the static content saver.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StaticContentSettings {
+    pub enabled: bool,
+    /// Seconds the picture must be still before frames are held back
+    pub still_secs: u32,
+}
+
+impl Default for StaticContentSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            still_secs: 2,
+        }
+    }
+}
+
+impl StaticContentSettings {
+    /// Check that the settings are usable.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..MAX_STILL_SECS).contains(&self.still_secs) {
+            return Err(format!("The still time must be between 1 and {} seconds", MAX_STILL_SECS - 1));
+        }
+        Ok(())
+    }
+
+    /// Filter holding back still frames of a `fps` recording, for the end of
+    /// its filter chain.
+    pub fn filter(&self, fps: u32) -> String {
+        let fps = fps.max(1);
+        format!("mpdecimate=keep={}:max={}", fps * self.still_secs, fps * MAX_STILL_SECS)
+    }
+
+    /// Output options writing the frames that are kept at their own times.
+    pub fn output_args(&self) -> Vec<String> {
+        vec![
+            "-fps_mode".to_string(),
+            "vfr".to_string(),
+            "-force_key_frames".to_string(),
+            format!("expr:gte(t,n_forced*{})", MAX_STILL_SECS),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_static_content_args() {
+        let settings = StaticContentSettings { enabled: true, still_secs: 3 };
+        assert!(settings.validate().is_ok());
+        assert_eq!(settings.filter(30), "mpdecimate=keep=90:max=300");
+        assert_eq!(settings.output_args()[3], "expr:gte(t,n_forced*10)");
+        assert!(StaticContentSettings { still_secs: 0, ..settings }.validate().is_err());
+        assert!(StaticContentSettings { still_secs: MAX_STILL_SECS, ..settings }.validate().is_err());
+    }
+}
@@ -0,0 +1,125 @@
+//! Stills grabbed from a recording while it runs.
+//!
+//! The still sink rides along with every recording and hands the next frame
+//! it's given to whoever asked for one. Sinks get frames at the encoder's
+//! cadence, after masks and redactions, so a still is what the video shows
+//! at that moment and arrives within a frame. Frames are only copied when a
+//! still is wanted.
+
+use super::image::ImageFormat;
+use super::sink::FrameSink;
+use crate::capture::{CapturedFrame, PixelFormat};
+use crate::screenshot;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// How long to wait for the recording to deliver a frame.
+const GRAB_TIMEOUT: Duration = Duration::from_secs(2);
+
+type Waiting = Arc<Mutex<Vec<oneshot::Sender<CapturedFrame>>>>;
+
+/// Grabs stills from the current recording.
+#[derive(Clone, Default)]
+pub struct StillGrabber {
+    waiting: Waiting,
+}
+
+impl StillGrabber {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A sink for a new recording, answering grabs with its frames.
+    pub fn sink(&self) -> Box<dyn FrameSink> {
+        Box::new(StillSink { waiting: self.waiting.clone() })
+    }
+
+    /// The next frame of the recording, as 8-bit BGRA.
+    pub async fn grab(&self) -> Result<CapturedFrame, String> {
+        let (sender, frame) = oneshot::channel();
+        self.waiting.lock().unwrap().push(sender);
+        let frame = match tokio::time::timeout(GRAB_TIMEOUT, frame).await {
+            Ok(Ok(frame)) => frame,
+            _ => return Err("The recording didn't deliver a frame".to_string()),
+        };
+        match frame.format {
+            PixelFormat::Bgra8 => Ok(frame),
+            PixelFormat::X2Rgb10 | PixelFormat::X2Bgr10 => {
+                frame.packed_10bit_to_bgra8().ok_or_else(|| "Unsupported frame format".to_string())
+            }
+            PixelFormat::Rgba16F => Err("Stills of HDR recordings aren't supported".to_string()),
+        }
+    }
+
+    /// Save the next frame as a PNG in the Pictures folder, returning its
+    /// path.
+    pub async fn save(&self) -> Result<PathBuf, String> {
+        let path = screenshot::save_frame(self.grab().await?, ImageFormat::Png).await?;
+        tracing::info!(target: "Recording", "Saved a still to {}", path.display());
+        Ok(path)
+    }
+}
+
+struct StillSink {
+    waiting: Waiting,
+}
+
+impl FrameSink for StillSink {
+    fn name(&self) -> &str {
+        "Still"
+    }
+
+    fn write_frame(&mut self, frame: &CapturedFrame) -> Result<(), String> {
+        let waiting = std::mem::take(&mut *self.waiting.lock().unwrap());
+        for sender in waiting {
+            let _ = sender.send(frame.clone());
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), String> {
+        // Grabs still waiting fail rather than hang
+        self.waiting.lock().unwrap().clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::ColorSpace;
+    use std::time::Instant;
+
+    fn frame(value: u8) -> CapturedFrame {
+        CapturedFrame {
+            width: 2,
+            height: 1,
+            data: vec![value; 8],
+            format: PixelFormat::Bgra8,
+            color: ColorSpace::default(),
+            captured_at: Instant::now(),
+            presented_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_grab_still() {
+        let grabber = StillGrabber::new();
+        let mut sink = grabber.sink();
+        // Nobody's waiting for this one
+        sink.write_frame(&frame(1)).unwrap();
+
+        let grab = tokio::spawn({
+            let grabber = grabber.clone();
+            async move { grabber.grab().await }
+        });
+        while grabber.waiting.lock().unwrap().is_empty() {
+            tokio::task::yield_now().await;
+        }
+        sink.write_frame(&frame(2)).unwrap();
+        assert_eq!(grab.await.unwrap().unwrap().data, vec![2; 8]);
+        assert!(grabber.waiting.lock().unwrap().is_empty());
+    }
+}
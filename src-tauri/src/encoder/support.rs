@@ -0,0 +1,271 @@
+//! What each encoder on this machine can do.
+//!
+//! Hardware encoders differ by GPU generation and driver: the largest frame
+//! they take, the codec profiles and bit depths they offer, whether they use
+//! B-frames, and how many encodes they run at once (consumer NVIDIA cards
+//! allow a handful). Each is found out by encoding small test clips with
+//! FFmpeg and reading its encoder help, which takes a while, so the results
+//! are saved in `encoder_support.json` and reused until FFmpeg or the GPUs
+//! change.
+
+use super::codec::VideoCodec;
+use super::ffmpeg::ffmpeg_stdout;
+use super::hardware::EncoderBackend;
+use super::rate::RateControl;
+use crate::config::{load_json, save_json};
+use crate::gpu::{self, GpuAdapter};
+use ffmpeg_sidecar::command::FfmpegCommand;
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+
+const SUPPORT_FILE: &str = "encoder_support.json";
+
+/// Codecs probed for each backend.
+const CODECS: [VideoCodec; 4] = [VideoCodec::H264, VideoCodec::H265, VideoCodec::Vp9, VideoCodec::Av1];
+
+/// Frame sizes tried, smallest first.
+const SIZES: [(u32, u32); 4] = [(1920, 1080), (3840, 2160), (4096, 4096), (7680, 4320)];
+
+/// Encodes started at once to count a hardware encoder's sessions.
+const SESSION_TEST: u32 = 8;
+
+/// What one encoder can do.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EncoderProbe {
+    pub backend: EncoderBackend,
+    pub codec: VideoCodec,
+    /// FFmpeg encoder name, e.g. `hevc_nvenc`
+    pub encoder: String,
+    /// Whether it encodes on this machine at all; nothing else is probed if not
+    pub available: bool,
+    /// Largest frame tried that it encoded
+    pub max_width: u32,
+    pub max_height: u32,
+    /// Codec profiles it offers, e.g. `main`, `main10`; empty if it doesn't say
+    pub profiles: Vec<String>,
+    /// Takes 10-bit input
+    pub ten_bit: bool,
+    pub b_frames: bool,
+    /// Encodes it ran at once, up to 8; `None` for software encoders, which
+    /// aren't limited
+    pub max_sessions: Option<u32>,
+    /// GPUs it can run on, by adapter index
+    pub adapters: Vec<u32>,
+}
+
+/// Every encoder's capabilities, as probed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EncoderSupport {
+    /// FFmpeg's version line, part of what the results depend on
+    pub ffmpeg_version: String,
+    pub adapters: Vec<GpuAdapter>,
+    pub encoders: Vec<EncoderProbe>,
+}
+
+impl EncoderSupport {
+    /// Whether these results were probed with the same FFmpeg and GPUs.
+    fn matches(&self, ffmpeg_version: &str, adapters: &[GpuAdapter]) -> bool {
+        self.ffmpeg_version == ffmpeg_version && self.adapters == adapters
+    }
+}
+
+/// The encoders' capabilities, probing them if they haven't been with this
+/// FFmpeg and these GPUs, or if `refresh` is set.
+///
+/// Probing runs FFmpeg many times and can take half a minute; call it from a
+/// blocking task.
+pub fn encoder_support(refresh: bool) -> Result<EncoderSupport, String> {
+    let ffmpeg_version = ffmpeg_stdout(["-version"])?.lines().next().unwrap_or_default().to_string();
+    let adapters = gpu::list_adapters();
+    let cached: EncoderSupport = load_json(SUPPORT_FILE);
+    if !refresh && cached.matches(&ffmpeg_version, &adapters) {
+        return Ok(cached);
+    }
+
+    tracing::info!(target: "Encoder", "Probing encoder capabilities with {}", ffmpeg_version);
+    let mut encoders = Vec::new();
+    for backend in EncoderBackend::candidates().iter().copied().chain([EncoderBackend::Software]) {
+        for codec in CODECS {
+            let Some(name) = backend.encoder_name(codec) else {
+                continue;
+            };
+            let probe = probe_encoder(backend, codec, name, &adapters);
+            if probe.available {
+                tracing::info!(target: "Encoder", "{}: up to {}x{}", name, probe.max_width, probe.max_height);
+            } else {
+                tracing::warn!(target: "Encoder", "{}: unavailable", name);
+            }
+            encoders.push(probe);
+        }
+    }
+    let support = EncoderSupport {
+        ffmpeg_version,
+        adapters,
+        encoders,
+    };
+    if let Err(e) = save_json(SUPPORT_FILE, &support) {
+        tracing::error!(target: "Encoder", "Failed to save encoder capabilities: {}", e);
+    }
+    Ok(support)
+}
+
+/// Find out what `name` can do.
+fn probe_encoder(backend: EncoderBackend, codec: VideoCodec, name: &str, adapters: &[GpuAdapter]) -> EncoderProbe {
+    let adapters = adapters
+        .iter()
+        .filter(|adapter| backend.vendor() == Some(adapter.vendor))
+        .map(|adapter| adapter.index)
+        .collect();
+    let mut probe = EncoderProbe {
+        backend,
+        codec,
+        encoder: name.to_string(),
+        available: false,
+        max_width: 0,
+        max_height: 0,
+        profiles: Vec::new(),
+        ten_bit: false,
+        b_frames: false,
+        max_sessions: None,
+        adapters,
+    };
+
+    for (width, height) in SIZES {
+        if !test_encode(backend, codec, width, height, &[]) {
+            break;
+        }
+        (probe.max_width, probe.max_height) = (width, height);
+    }
+    probe.available = probe.max_width > 0;
+    if !probe.available {
+        return probe;
+    }
+
+    if let Ok(help) = ffmpeg_stdout(["-h", &format!("encoder={}", name)]) {
+        probe.profiles = parse_profiles(&help);
+        probe.ten_bit = parse_pixel_formats(&help)
+            .iter()
+            .any(|format| format.ends_with("10le") || format.ends_with("10be"));
+    }
+    probe.b_frames = test_encode(backend, codec, 640, 360, &["-bf", "2"]);
+    if backend != EncoderBackend::Software {
+        probe.max_sessions = Some(count_sessions(backend, codec));
+    }
+    probe
+}
+
+/// The test encode arguments for `backend`: a few frames of a test pattern
+/// in the pixel format it takes.
+fn test_args(backend: EncoderBackend, codec: VideoCodec, width: u32, height: u32, frames: u32) -> Vec<String> {
+    let pixel_format = backend.input_format(false).unwrap_or("yuv420p");
+    let mut args: Vec<String> = [
+        "-hide_banner",
+        "-nostdin",
+        "-loglevel",
+        "error",
+        "-f",
+        "lavfi",
+        "-i",
+        &format!("testsrc2=size={}x{}:rate=30,format={}", width, height, pixel_format),
+        "-frames:v",
+        &frames.to_string(),
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect();
+    args.extend(backend.args(codec, RateControl::default()));
+    args
+}
+
+/// A quiet FFmpeg command running `args` and discarding the output.
+fn null_command(args: Vec<String>) -> FfmpegCommand {
+    let mut command = FfmpegCommand::new();
+    command.args(args).args(["-f", "null", "-"]);
+    let inner_command = command.as_inner_mut();
+    inner_command.stdin(Stdio::null());
+    inner_command.stdout(Stdio::null());
+    inner_command.stderr(Stdio::null());
+    command
+}
+
+/// Whether `backend` encodes a few `width`x`height` frames with `extra` arguments.
+fn test_encode(backend: EncoderBackend, codec: VideoCodec, width: u32, height: u32, extra: &[&str]) -> bool {
+    let mut args = test_args(backend, codec, width, height, 5);
+    args.extend(extra.iter().map(|arg| arg.to_string()));
+    null_command(args).as_inner_mut().status().is_ok_and(|status| status.success())
+}
+
+/// How many of [`SESSION_TEST`] encodes started together succeed. Each runs
+/// for a couple of seconds in real time so they overlap.
+fn count_sessions(backend: EncoderBackend, codec: VideoCodec) -> u32 {
+    let children: Vec<_> = (0..SESSION_TEST)
+        .filter_map(|_| {
+            let mut args = vec!["-re".to_string()];
+            args.extend(test_args(backend, codec, 640, 360, 60));
+            null_command(args).as_inner_mut().spawn().ok()
+        })
+        .collect();
+    children
+        .into_iter()
+        .filter_map(|mut child| child.wait().ok())
+        .filter(|status| status.success())
+        .count() as u32
+}
+
+/// The named values of the `profile` option in `ffmpeg -h encoder=...` output.
+fn parse_profiles(help: &str) -> Vec<String> {
+    let mut profiles = Vec::new();
+    let mut in_profile = false;
+    for line in help.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with('-') {
+            in_profile = trimmed.split_whitespace().next() == Some("-profile");
+            continue;
+        }
+        // Named values are indented further than options
+        let indent = line.len() - trimmed.len();
+        if in_profile && indent > 2 {
+            if let Some(name) = trimmed.split_whitespace().next() {
+                profiles.push(name.to_string());
+            }
+        } else {
+            in_profile = false;
+        }
+    }
+    profiles
+}
+
+/// The pixel formats in `ffmpeg -h encoder=...` output.
+fn parse_pixel_formats(help: &str) -> Vec<String> {
+    help.lines()
+        .find_map(|line| line.trim().strip_prefix("Supported pixel formats:"))
+        .map(|formats| formats.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NVENC_HELP: &str = "\
+Encoder hevc_nvenc [NVIDIA NVENC hevc encoder]:
+    General capabilities: dr1 delay hardware
+    Supported pixel formats: yuv420p nv12 p010le yuv444p cuda
+hevc_nvenc AVOptions:
+  -preset            <int>        E..V....... Set the encoding preset (from 0 to 18) (default p4)
+     default         0            E..V.......
+  -profile           <int>        E..V....... Set the encoding profile (from 0 to 4) (default main)
+     main            0            E..V.......
+     main10          1            E..V.......
+     rext            2            E..V.......
+  -level             <int>        E..V....... Set the encoding level restriction (from 0 to 186) (default auto)
+     auto            0            E..V.......
+";
+
+    #[test]
+    fn test_parse_help() {
+        assert_eq!(parse_profiles(NVENC_HELP), ["main", "main10", "rext"]);
+        assert_eq!(parse_pixel_formats(NVENC_HELP), ["yuv420p", "nv12", "p010le", "yuv444p", "cuda"]);
+        assert!(parse_profiles("Encoder gif [GIF]:\n").is_empty());
+    }
+}
@@ -0,0 +1,477 @@
+//! In-process BGRA to YUV 4:2:0 conversion.
+//!
+//! Without a GPU to convert on, turning captured BGRA into the 4:2:0 YUV
+//! encoders take is most of a recording's CPU time. When no scaling is
+//! needed, frames are converted here rather than by FFmpeg's scaler, which
+//! also cuts what goes down the pipe to FFmpeg from 4 bytes a pixel to 1.5.
+//! The conversion runs with AVX2 or SSE4.1 on x86-64 and NEON on ARM,
+//! picked when the converter is created, and falls back to plain Rust.
+//!
+//! Every kernel does the same fixed-point arithmetic, so they produce
+//! identical output: coefficients are scaled by 2^14 and rounded, and chroma
+//! is taken from each 2x2 block averaged vertically, then horizontally, with
+//! rounding.
+
+use crate::capture::types::{ColorRange, ColorStandard};
+use crate::capture::ColorSpace;
+
+/// Fractional bits of the fixed-point coefficients.
+const SHIFT: i32 = 14;
+
+/// Added before shifting to round to nearest.
+const ROUND: i32 = 1 << (SHIFT - 1);
+
+/// How the chroma planes are laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// Separate U and V planes (`yuv420p`), for software encoders
+    I420,
+    /// One plane of interleaved U and V (`nv12`), for hardware encoders
+    Nv12,
+}
+
+impl Layout {
+    /// FFmpeg name of the pixel format.
+    pub fn ffmpeg_name(&self) -> &'static str {
+        match self {
+            Layout::I420 => "yuv420p",
+            Layout::Nv12 => "nv12",
+        }
+    }
+}
+
+/// Instruction set a conversion runs with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kernel {
+    Scalar,
+    #[cfg(target_arch = "x86_64")]
+    Sse41,
+    #[cfg(target_arch = "x86_64")]
+    Avx2,
+    #[cfg(target_arch = "aarch64")]
+    Neon,
+}
+
+impl Kernel {
+    /// Kernels this CPU can run, fastest first.
+    pub fn available() -> Vec<Kernel> {
+        let mut kernels = Vec::new();
+        #[cfg(target_arch = "x86_64")]
+        {
+            let sse41 = is_x86_feature_detected!("sse4.1");
+            if sse41 && is_x86_feature_detected!("avx2") {
+                kernels.push(Kernel::Avx2);
+            }
+            if sse41 {
+                kernels.push(Kernel::Sse41);
+            }
+        }
+        #[cfg(target_arch = "aarch64")]
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            kernels.push(Kernel::Neon);
+        }
+        kernels.push(Kernel::Scalar);
+        kernels
+    }
+
+    /// The fastest kernel this CPU can run.
+    pub fn detect() -> Kernel {
+        Self::available()[0]
+    }
+}
+
+/// Fixed-point conversion coefficients, each as (B, G, R) weights.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Coefficients {
+    y: [i16; 3],
+    u: [i16; 3],
+    v: [i16; 3],
+    y_offset: i16,
+}
+
+impl Coefficients {
+    /// Coefficients converting full-range RGB to `color`.
+    fn new(color: ColorSpace) -> Self {
+        let (kr, kb) = match color.standard {
+            ColorStandard::Bt601 => (0.299, 0.114),
+            ColorStandard::Bt709 => (0.2126, 0.0722),
+            ColorStandard::Bt2020 => (0.2627, 0.0593),
+        };
+        let kg = 1.0 - kr - kb;
+        let (y_scale, c_scale, y_offset) = match color.range {
+            ColorRange::Limited => (219.0 / 255.0, 224.0 / 255.0, 16),
+            ColorRange::Full => (1.0, 1.0, 0),
+        };
+        let fixed = |x: f64| (x * (1 << SHIFT) as f64).round() as i16;
+
+        // Green takes up the rounding so gray has no chroma
+        let (ub, ur) = (fixed(0.5 * c_scale), fixed(-0.5 * kr / (1.0 - kb) * c_scale));
+        let (vr, vb) = (fixed(0.5 * c_scale), fixed(-0.5 * kb / (1.0 - kr) * c_scale));
+        Self {
+            y: [fixed(kb * y_scale), fixed(kg * y_scale), fixed(kr * y_scale)],
+            u: [ub, -ub - ur, ur],
+            v: [vb, -vr - vb, vr],
+            y_offset,
+        }
+    }
+}
+
+/// Weighted sum of a pixel's channels with `k`, rounded and shifted.
+fn dot(k: [i16; 3], b: u8, g: u8, r: u8) -> i32 {
+    (k[0] as i32 * b as i32 + k[1] as i32 * g as i32 + k[2] as i32 * r as i32 + ROUND) >> SHIFT
+}
+
+/// Average of two samples, rounding up.
+fn average(a: u8, b: u8) -> u8 {
+    ((a as u16 + b as u16 + 1) >> 1) as u8
+}
+
+/// Converts BGRA frames to YUV 4:2:0.
+pub struct Converter {
+    coefficients: Coefficients,
+    layout: Layout,
+    kernel: Kernel,
+    /// A row of each chroma plane, for interleaving into NV12
+    chroma_row: Vec<u8>,
+}
+
+impl Converter {
+    /// Convert to `color` in `layout`, with the fastest kernel this CPU runs.
+    pub fn new(color: ColorSpace, layout: Layout) -> Self {
+        Self::with_kernel(color, layout, Kernel::detect())
+    }
+
+    /// Convert with `kernel`, which has to be one of [`Kernel::available`].
+    pub fn with_kernel(color: ColorSpace, layout: Layout, kernel: Kernel) -> Self {
+        Self {
+            coefficients: Coefficients::new(color),
+            layout,
+            kernel,
+            chroma_row: Vec::new(),
+        }
+    }
+
+    /// The kernel conversions run with.
+    pub fn kernel(&self) -> Kernel {
+        self.kernel
+    }
+
+    /// Convert the top-left `width`x`height` of a frame of BGRA rows
+    /// `stride` bytes apart into `out`. Dimensions must be even.
+    pub fn convert(&mut self, src: &[u8], stride: usize, width: usize, height: usize, out: &mut Vec<u8>) {
+        debug_assert!(width.is_multiple_of(2) && height.is_multiple_of(2));
+        debug_assert!(src.len() >= stride * (height.max(1) - 1) + width * 4);
+        let luma_size = width * height;
+        out.resize(luma_size * 3 / 2, 0);
+        let (y_plane, chroma) = out.split_at_mut(luma_size);
+        let chroma_width = width / 2;
+        self.chroma_row.resize(width, 0);
+
+        for row in (0..height).step_by(2) {
+            let top = &src[row * stride..][..width * 4];
+            let bottom = &src[(row + 1) * stride..][..width * 4];
+            self.luma(top, &mut y_plane[row * width..][..width]);
+            self.luma(bottom, &mut y_plane[(row + 1) * width..][..width]);
+
+            let chroma_offset = row / 2 * chroma_width;
+            match self.layout {
+                Layout::I420 => {
+                    let (u_plane, v_plane) = chroma.split_at_mut(luma_size / 4);
+                    let u = &mut u_plane[chroma_offset..][..chroma_width];
+                    let v = &mut v_plane[chroma_offset..][..chroma_width];
+                    chroma_rows(self.kernel, &self.coefficients, top, bottom, u, v);
+                }
+                Layout::Nv12 => {
+                    let (u, v) = self.chroma_row.split_at_mut(chroma_width);
+                    chroma_rows(self.kernel, &self.coefficients, top, bottom, u, v);
+                    let uv = &mut chroma[chroma_offset * 2..][..width];
+                    for (pair, (&u, &v)) in uv.chunks_exact_mut(2).zip(u.iter().zip(v.iter())) {
+                        pair[0] = u;
+                        pair[1] = v;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Convert a row of pixels to luma.
+    fn luma(&self, src: &[u8], dst: &mut [u8]) {
+        let done = match self.kernel {
+            Kernel::Scalar => 0,
+            // Safety: the kernel was only chosen if the CPU has the feature
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Sse41 => unsafe { x86::luma_sse41(&self.coefficients, src, dst) },
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx2 => unsafe { x86::luma_avx2(&self.coefficients, src, dst) },
+            #[cfg(target_arch = "aarch64")]
+            Kernel::Neon => unsafe { neon::luma(&self.coefficients, src, dst) },
+        };
+        luma_scalar(&self.coefficients, &src[done * 4..], &mut dst[done..]);
+    }
+}
+
+/// Convert two rows of pixels to a row of each chroma plane.
+fn chroma_rows(kernel: Kernel, c: &Coefficients, top: &[u8], bottom: &[u8], u: &mut [u8], v: &mut [u8]) {
+    let done = match kernel {
+        Kernel::Scalar => 0,
+        // Safety: the kernel was only chosen if the CPU has the feature
+        #[cfg(target_arch = "x86_64")]
+        Kernel::Sse41 | Kernel::Avx2 => unsafe { x86::chroma_sse41(c, top, bottom, u, v) },
+        #[cfg(target_arch = "aarch64")]
+        Kernel::Neon => unsafe { neon::chroma(c, top, bottom, u, v) },
+    };
+    chroma_scalar(c, &top[done * 8..], &bottom[done * 8..], &mut u[done..], &mut v[done..]);
+}
+
+fn luma_scalar(c: &Coefficients, src: &[u8], dst: &mut [u8]) {
+    for (pixel, y) in src.chunks_exact(4).zip(dst.iter_mut()) {
+        *y = (dot(c.y, pixel[0], pixel[1], pixel[2]) + c.y_offset as i32).clamp(0, 255) as u8;
+    }
+}
+
+fn chroma_scalar(c: &Coefficients, top: &[u8], bottom: &[u8], u: &mut [u8], v: &mut [u8]) {
+    for (i, (u, v)) in u.iter_mut().zip(v.iter_mut()).enumerate() {
+        let channel = |offset: usize| {
+            let left = average(top[i * 8 + offset], bottom[i * 8 + offset]);
+            let right = average(top[i * 8 + 4 + offset], bottom[i * 8 + 4 + offset]);
+            average(left, right)
+        };
+        let (b, g, r) = (channel(0), channel(1), channel(2));
+        *u = (dot(c.u, b, g, r) + 128).clamp(0, 255) as u8;
+        *v = (dot(c.v, b, g, r) + 128).clamp(0, 255) as u8;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use super::{Coefficients, ROUND, SHIFT};
+    use std::arch::x86_64::*;
+
+    /// `k` repeated for two pixels of 16-bit channels, alpha weighted 0.
+    #[target_feature(enable = "sse4.1")]
+    unsafe fn weights(k: [i16; 3]) -> __m128i {
+        _mm_setr_epi16(k[0], k[1], k[2], 0, k[0], k[1], k[2], 0)
+    }
+
+    /// Weighted sums of four BGRA pixels, rounded and shifted.
+    #[target_feature(enable = "sse4.1")]
+    unsafe fn dot4(pixels: __m128i, weights: __m128i) -> __m128i {
+        let low = _mm_cvtepu8_epi16(pixels);
+        let high = _mm_cvtepu8_epi16(_mm_srli_si128::<8>(pixels));
+        let sums = _mm_hadd_epi32(_mm_madd_epi16(low, weights), _mm_madd_epi16(high, weights));
+        _mm_srai_epi32::<SHIFT>(_mm_add_epi32(sums, _mm_set1_epi32(ROUND)))
+    }
+
+    /// Convert 16 pixels at a time, returning how many were converted.
+    #[target_feature(enable = "sse4.1")]
+    pub unsafe fn luma_sse41(c: &Coefficients, src: &[u8], dst: &mut [u8]) -> usize {
+        let weights = weights(c.y);
+        let offset = _mm_set1_epi32(c.y_offset as i32);
+        let count = dst.len().min(src.len() / 4) / 16 * 16;
+        for x in (0..count).step_by(16) {
+            let pixels = src.as_ptr().add(x * 4) as *const __m128i;
+            let y = |i: usize| _mm_add_epi32(dot4(_mm_loadu_si128(pixels.add(i)), weights), offset);
+            let low = _mm_packs_epi32(y(0), y(1));
+            let high = _mm_packs_epi32(y(2), y(3));
+            _mm_storeu_si128(dst.as_mut_ptr().add(x) as *mut __m128i, _mm_packus_epi16(low, high));
+        }
+        count
+    }
+
+    /// Convert 32 pixels at a time, returning how many were converted.
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn luma_avx2(c: &Coefficients, src: &[u8], dst: &mut [u8]) -> usize {
+        let k = c.y;
+        let weights = _mm256_setr_epi16(
+            k[0], k[1], k[2], 0, k[0], k[1], k[2], 0, k[0], k[1], k[2], 0, k[0], k[1], k[2], 0,
+        );
+        let offset = _mm256_set1_epi32(c.y_offset as i32);
+        let round = _mm256_set1_epi32(ROUND);
+        // Weighted sums of eight pixels, in order
+        let dot8 = |pixels: *const __m128i| {
+            let first = _mm256_madd_epi16(_mm256_cvtepu8_epi16(_mm_loadu_si128(pixels)), weights);
+            let second = _mm256_madd_epi16(_mm256_cvtepu8_epi16(_mm_loadu_si128(pixels.add(1))), weights);
+            // Lanes hold pixels 0, 1, 4, 5 | 2, 3, 6, 7
+            let sums = _mm256_permute4x64_epi64::<0b11_01_10_00>(_mm256_hadd_epi32(first, second));
+            let y = _mm256_srai_epi32::<SHIFT>(_mm256_add_epi32(sums, round));
+            _mm256_add_epi32(y, offset)
+        };
+        let count = dst.len().min(src.len() / 4) / 32 * 32;
+        for x in (0..count).step_by(32) {
+            let pixels = src.as_ptr().add(x * 4) as *const __m128i;
+            let low = _mm256_permute4x64_epi64::<0b11_01_10_00>(_mm256_packs_epi32(dot8(pixels), dot8(pixels.add(2))));
+            let high =
+                _mm256_permute4x64_epi64::<0b11_01_10_00>(_mm256_packs_epi32(dot8(pixels.add(4)), dot8(pixels.add(6))));
+            let bytes = _mm256_permute4x64_epi64::<0b11_01_10_00>(_mm256_packus_epi16(low, high));
+            _mm256_storeu_si256(dst.as_mut_ptr().add(x) as *mut __m256i, bytes);
+        }
+        count
+    }
+
+    /// Convert two rows of 8 pixels at a time to 4 samples of each chroma
+    /// plane, returning how many samples were converted.
+    #[target_feature(enable = "sse4.1")]
+    pub unsafe fn chroma_sse41(c: &Coefficients, top: &[u8], bottom: &[u8], u: &mut [u8], v: &mut [u8]) -> usize {
+        let (u_weights, v_weights) = (weights(c.u), weights(c.v));
+        let offset = _mm_set1_epi32(128);
+        let count = u.len().min(v.len()).min(top.len().min(bottom.len()) / 8) / 4 * 4;
+        for x in (0..count).step_by(4) {
+            let top = top.as_ptr().add(x * 8) as *const __m128i;
+            let bottom = bottom.as_ptr().add(x * 8) as *const __m128i;
+            let first = _mm_castsi128_ps(_mm_avg_epu8(_mm_loadu_si128(top), _mm_loadu_si128(bottom)));
+            let second = _mm_castsi128_ps(_mm_avg_epu8(_mm_loadu_si128(top.add(1)), _mm_loadu_si128(bottom.add(1))));
+            let even = _mm_castps_si128(_mm_shuffle_ps::<0b10_00_10_00>(first, second));
+            let odd = _mm_castps_si128(_mm_shuffle_ps::<0b11_01_11_01>(first, second));
+            let pixels = _mm_avg_epu8(even, odd);
+
+            let u_samples = _mm_add_epi32(dot4(pixels, u_weights), offset);
+            let v_samples = _mm_add_epi32(dot4(pixels, v_weights), offset);
+            let bytes = _mm_packus_epi16(_mm_packs_epi32(u_samples, v_samples), _mm_setzero_si128());
+            (u.as_mut_ptr().add(x) as *mut i32).write_unaligned(_mm_cvtsi128_si32(bytes));
+            (v.as_mut_ptr().add(x) as *mut i32).write_unaligned(_mm_extract_epi32::<1>(bytes));
+        }
+        count
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use super::{Coefficients, SHIFT};
+    use std::arch::aarch64::*;
+
+    /// Convert 8 pixels at a time, returning how many were converted.
+    #[target_feature(enable = "neon")]
+    pub unsafe fn luma(c: &Coefficients, src: &[u8], dst: &mut [u8]) -> usize {
+        // Luma weights are all positive
+        let [kb, kg, kr] = c.y.map(|k| k as u16);
+        let offset = vdupq_n_u16(c.y_offset as u16);
+        let count = dst.len().min(src.len() / 4) / 8 * 8;
+        for x in (0..count).step_by(8) {
+            let pixels = vld4_u8(src.as_ptr().add(x * 4));
+            let (b, g, r) = (vmovl_u8(pixels.0), vmovl_u8(pixels.1), vmovl_u8(pixels.2));
+            let low = vmull_n_u16(vget_low_u16(b), kb);
+            let low = vmlal_n_u16(low, vget_low_u16(g), kg);
+            let low = vmlal_n_u16(low, vget_low_u16(r), kr);
+            let high = vmull_n_u16(vget_high_u16(b), kb);
+            let high = vmlal_n_u16(high, vget_high_u16(g), kg);
+            let high = vmlal_n_u16(high, vget_high_u16(r), kr);
+            let sums = vcombine_u16(vrshrn_n_u32::<SHIFT>(low), vrshrn_n_u32::<SHIFT>(high));
+            let y = vaddq_u16(sums, offset);
+            vst1_u8(dst.as_mut_ptr().add(x), vqmovn_u16(y));
+        }
+        count
+    }
+
+    /// One chroma sample per 2x2 block of 16-pixel rows, each channel
+    /// averaged vertically, then horizontally.
+    #[target_feature(enable = "neon")]
+    unsafe fn block_average(top: uint8x16_t, bottom: uint8x16_t) -> int16x8_t {
+        let vertical = vrhaddq_u8(top, bottom);
+        let pairs = vuzpq_u8(vertical, vertical);
+        let average = vrhadd_u8(vget_low_u8(pairs.0), vget_low_u8(pairs.1));
+        vreinterpretq_s16_u16(vmovl_u8(average))
+    }
+
+    /// Weighted sums of eight averaged pixels, rounded, shifted and centered.
+    #[target_feature(enable = "neon")]
+    unsafe fn chroma_samples(k: [i16; 3], b: int16x8_t, g: int16x8_t, r: int16x8_t) -> uint8x8_t {
+        let low = vmull_n_s16(vget_low_s16(b), k[0]);
+        let low = vmlal_n_s16(low, vget_low_s16(g), k[1]);
+        let low = vmlal_n_s16(low, vget_low_s16(r), k[2]);
+        let high = vmull_n_s16(vget_high_s16(b), k[0]);
+        let high = vmlal_n_s16(high, vget_high_s16(g), k[1]);
+        let high = vmlal_n_s16(high, vget_high_s16(r), k[2]);
+        let samples = vcombine_s16(vrshrn_n_s32::<SHIFT>(low), vrshrn_n_s32::<SHIFT>(high));
+        vqmovun_s16(vaddq_s16(samples, vdupq_n_s16(128)))
+    }
+
+    /// Convert two rows of 16 pixels at a time to 8 samples of each chroma
+    /// plane, returning how many samples were converted.
+    #[target_feature(enable = "neon")]
+    pub unsafe fn chroma(c: &Coefficients, top: &[u8], bottom: &[u8], u: &mut [u8], v: &mut [u8]) -> usize {
+        let count = u.len().min(v.len()).min(top.len().min(bottom.len()) / 8) / 8 * 8;
+        for x in (0..count).step_by(8) {
+            let upper = vld4q_u8(top.as_ptr().add(x * 8));
+            let lower = vld4q_u8(bottom.as_ptr().add(x * 8));
+            let b = block_average(upper.0, lower.0);
+            let g = block_average(upper.1, lower.1);
+            let r = block_average(upper.2, lower.2);
+            vst1_u8(u.as_mut_ptr().add(x), chroma_samples(c.u, b, g, r));
+            vst1_u8(v.as_mut_ptr().add(x), chroma_samples(c.v, b, g, r));
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pixels of every value, in a pattern that doesn't repeat along rows.
+    fn test_frame(width: usize, height: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_u32;
+        (0..width * height * 4)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_kernels_match_scalar() {
+        // Widths that leave a remainder for every kernel
+        let (width, height, stride) = (70, 6, 72 * 4);
+        let frame = test_frame(stride / 4, height);
+        let color = ColorSpace {
+            standard: ColorStandard::Bt601,
+            range: ColorRange::Full,
+        };
+        for (color, layout) in [(ColorSpace::VIDEO, Layout::I420), (color, Layout::Nv12)] {
+            let mut expected = Vec::new();
+            Converter::with_kernel(color, layout, Kernel::Scalar).convert(&frame, stride, width, height, &mut expected);
+            for kernel in Kernel::available() {
+                let mut out = Vec::new();
+                Converter::with_kernel(color, layout, kernel).convert(&frame, stride, width, height, &mut out);
+                assert!(out == expected, "{:?} differs from the scalar conversion", kernel);
+            }
+        }
+    }
+
+    #[test]
+    fn test_convert() {
+        // White, black and pure red 2x2 blocks
+        let mut frame = Vec::new();
+        for _ in 0..2 {
+            frame.extend([255, 255, 255, 255].repeat(2));
+            frame.extend([0, 0, 0, 255].repeat(2));
+            frame.extend([0, 0, 255, 255].repeat(2));
+        }
+        let mut out = Vec::new();
+        Converter::with_kernel(ColorSpace::VIDEO, Layout::I420, Kernel::Scalar).convert(&frame, 24, 6, 2, &mut out);
+        assert_eq!(&out[..6], [235, 235, 16, 16, 63, 63]);
+        assert_eq!(&out[12..15], [128, 128, 102]);
+        assert_eq!(&out[15..18], [128, 128, 240]);
+    }
+
+    /// Conversion speed of each kernel. Run with
+    /// `cargo test --release bench_kernels -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_kernels() {
+        let (width, height) = (3840, 2160);
+        let frame = test_frame(width, height);
+        let mut out = Vec::new();
+        for kernel in Kernel::available() {
+            let mut converter = Converter::with_kernel(ColorSpace::VIDEO, Layout::Nv12, kernel);
+            let started = std::time::Instant::now();
+            for _ in 0..20 {
+                converter.convert(&frame, width * 4, width, height, &mut out);
+            }
+            let per_frame = started.elapsed() / 20;
+            println!("{:?}: {:.2} ms per 4K frame", kernel, per_frame.as_secs_f64() * 1000.0);
+        }
+    }
+}
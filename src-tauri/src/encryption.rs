@@ -0,0 +1,264 @@
+//! Encryption of recordings at rest.
+//!
+//! Encrypted recordings are never written to disk in the clear: FFmpeg
+//! writes the container to a pipe and it's encrypted as it arrives. Each
+//! file gets its own random key, stored in the file's header wrapped with
+//! the recording key, which is created on first use and kept in the OS
+//! keychain (Keychain on macOS, Credential Manager on Windows, the Secret
+//! Service on Linux). Content is encrypted with AES-256-GCM in 64 KiB
+//! chunks using the STREAM construction, so truncating or reordering a file
+//! is detected when it's decrypted.
+//!
+//! Losing the keychain entry makes encrypted recordings unreadable.
+
+use aes_gcm::aead::generic_array::GenericArray;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::stream::{DecryptorBE32, EncryptorBE32};
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Identifies encrypted recordings and the format version.
+const MAGIC: &[u8; 8] = b"SRENC\x00\x01\n";
+
+/// Extension appended to encrypted recordings, as in `recording.mp4.enc`.
+pub const EXTENSION: &str = "enc";
+
+/// Plaintext bytes per encrypted chunk.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Bytes the authentication tag adds to each chunk.
+const TAG_SIZE: usize = 16;
+
+/// Bytes of the per-file nonce prefix; STREAM uses the other 5 bytes of the
+/// 12-byte nonce for the chunk counter and last-chunk flag.
+const STREAM_NONCE_SIZE: usize = 7;
+
+const DAMAGED: &str = "The recording is damaged or incomplete";
+
+const KEYCHAIN_SERVICE: &str = "screen-recorder";
+const KEYCHAIN_ENTRY: &str = "recording-key";
+
+/// Get the recording key from the OS keychain, creating it on first use.
+pub fn recording_key() -> Result<Key<Aes256Gcm>, String> {
+    let entry = keyring::Entry::new(KEYCHAIN_SERVICE, KEYCHAIN_ENTRY)
+        .map_err(|e| format!("Failed to open the keychain: {}", e))?;
+    match entry.get_secret() {
+        Ok(secret) if secret.len() == 32 => Ok(*Key::<Aes256Gcm>::from_slice(&secret)),
+        Ok(_) => Err("The recording key in the keychain is invalid".to_string()),
+        Err(keyring::Error::NoEntry) => {
+            tracing::info!(target: "Encryption", "Creating the recording key");
+            let key = Aes256Gcm::generate_key(OsRng);
+            entry
+                .set_secret(&key)
+                .map_err(|e| format!("Failed to save the recording key: {}", e))?;
+            Ok(key)
+        }
+        Err(e) => Err(format!("Failed to read the recording key: {}", e)),
+    }
+}
+
+/// Encrypts everything written to it into `out`. [`finish`](Self::finish)
+/// must be called to write the final chunk; a file without it fails to
+/// decrypt, as a truncated one would.
+pub struct EncryptedWriter<W: Write> {
+    out: W,
+    encryptor: EncryptorBE32<Aes256Gcm>,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> EncryptedWriter<W> {
+    /// Write the header for a new file key wrapped with `key` to `out`.
+    pub fn new(mut out: W, key: &Key<Aes256Gcm>) -> Result<Self, String> {
+        let file_key = Aes256Gcm::generate_key(OsRng);
+        let wrap_nonce = Aes256Gcm::generate_nonce(OsRng);
+        let wrapped = Aes256Gcm::new(key)
+            .encrypt(&wrap_nonce, file_key.as_slice())
+            .map_err(|_| "Failed to wrap the file key".to_string())?;
+        let mut stream_nonce = [0u8; STREAM_NONCE_SIZE];
+        OsRng.fill_bytes(&mut stream_nonce);
+
+        out.write_all(MAGIC)
+            .and_then(|_| out.write_all(&wrap_nonce))
+            .and_then(|_| out.write_all(&wrapped))
+            .and_then(|_| out.write_all(&stream_nonce))
+            .map_err(|e| format!("Failed to write encryption header: {}", e))?;
+
+        Ok(Self {
+            out,
+            encryptor: EncryptorBE32::new(&file_key, GenericArray::from_slice(&stream_nonce)),
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+        })
+    }
+
+    /// Encrypt the remaining data as the last chunk and flush.
+    pub fn finish(mut self) -> Result<W, String> {
+        let chunk = self
+            .encryptor
+            .encrypt_last(self.buffer.as_slice())
+            .map_err(|_| "Failed to encrypt the last chunk".to_string())?;
+        self.out
+            .write_all(&chunk)
+            .and_then(|_| self.out.flush())
+            .map_err(|e| format!("Failed to write encrypted file: {}", e))?;
+        Ok(self.out)
+    }
+}
+
+impl<W: Write> Write for EncryptedWriter<W> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        // A full chunk is only written once more data arrives, since the
+        // last chunk is encrypted differently
+        if self.buffer.len() == CHUNK_SIZE && !data.is_empty() {
+            let chunk = self
+                .encryptor
+                .encrypt_next(self.buffer.as_slice())
+                .map_err(|_| std::io::Error::other("Failed to encrypt chunk"))?;
+            self.out.write_all(&chunk)?;
+            self.buffer.clear();
+        }
+        let len = data.len().min(CHUNK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&data[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Decrypt a stream written by [`EncryptedWriter`] into `out`, reporting
+/// the bytes read so far to `on_progress`.
+pub fn decrypt(
+    mut input: impl Read,
+    out: &mut impl Write,
+    key: &Key<Aes256Gcm>,
+    cancel: &AtomicBool,
+    mut on_progress: impl FnMut(u64),
+) -> Result<(), String> {
+    let mut header = [0u8; MAGIC.len() + 12 + 32 + TAG_SIZE + STREAM_NONCE_SIZE];
+    input
+        .read_exact(&mut header)
+        .map_err(|_| "Not an encrypted recording".to_string())?;
+    if &header[..MAGIC.len()] != MAGIC {
+        return Err("Not an encrypted recording".to_string());
+    }
+    let (wrap_nonce, rest) = header[MAGIC.len()..].split_at(12);
+    let (wrapped, stream_nonce) = rest.split_at(32 + TAG_SIZE);
+    let file_key = Aes256Gcm::new(key)
+        .decrypt(GenericArray::from_slice(wrap_nonce), wrapped)
+        .map_err(|_| "The recording was encrypted with a different key".to_string())?;
+    let mut decryptor =
+        DecryptorBE32::<Aes256Gcm>::new(Key::<Aes256Gcm>::from_slice(&file_key), GenericArray::from_slice(stream_nonce));
+
+    // Read a chunk ahead so the last one can be told apart
+    let mut read = header.len() as u64;
+    let mut chunk = read_chunk(&mut input)?;
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            return Err("Cancelled".to_string());
+        }
+        let next = read_chunk(&mut input)?;
+        read += chunk.len() as u64;
+        if next.is_empty() {
+            break;
+        }
+        let plaintext = decryptor.decrypt_next(chunk.as_slice()).map_err(|_| DAMAGED.to_string())?;
+        out.write_all(&plaintext)
+            .map_err(|e| format!("Failed to write decrypted file: {}", e))?;
+        on_progress(read);
+        chunk = next;
+    }
+    let plaintext = decryptor.decrypt_last(chunk.as_slice()).map_err(|_| DAMAGED.to_string())?;
+    out.write_all(&plaintext)
+        .map_err(|e| format!("Failed to write decrypted file: {}", e))?;
+    on_progress(read);
+    Ok(())
+}
+
+/// Read up to one encrypted chunk; empty at the end of the stream.
+fn read_chunk(input: &mut impl Read) -> Result<Vec<u8>, String> {
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE + TAG_SIZE);
+    input
+        .take((CHUNK_SIZE + TAG_SIZE) as u64)
+        .read_to_end(&mut chunk)
+        .map_err(|e| format!("Failed to read encrypted file: {}", e))?;
+    Ok(chunk)
+}
+
+/// Whether the file at `path` is an encrypted recording.
+pub fn is_encrypted(path: &Path) -> bool {
+    let mut magic = [0u8; MAGIC.len()];
+    File::open(path).and_then(|mut file| file.read_exact(&mut magic)).is_ok() && &magic == MAGIC
+}
+
+/// Decrypt the recording at `path` to `output`, or next to it without the
+/// `.enc` extension, and return the decrypted file's path. An existing file
+/// at the output path is never overwritten. `on_progress` receives the
+/// completed fraction.
+pub fn decrypt_file(
+    path: &Path,
+    output: Option<PathBuf>,
+    cancel: &AtomicBool,
+    mut on_progress: impl FnMut(f64),
+) -> Result<PathBuf, String> {
+    let output = match output {
+        Some(output) => output,
+        None if path.extension().is_some_and(|ext| ext == EXTENSION) => path.with_extension(""),
+        None => return Err("Choose where to save the decrypted recording".to_string()),
+    };
+    let key = recording_key()?;
+    let size = std::fs::metadata(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+        .len();
+    let input = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let file = File::options()
+        .write(true)
+        .create_new(true)
+        .open(&output)
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::AlreadyExists => format!("{} already exists", output.display()),
+            _ => format!("Failed to create {}: {}", output.display(), e),
+        })?;
+    let mut out = BufWriter::new(file);
+
+    let decrypted = decrypt(BufReader::new(input), &mut out, &key, cancel, |read| {
+        on_progress(read as f64 / size.max(1) as f64)
+    })
+    .and_then(|_| out.flush().map_err(|e| format!("Failed to write decrypted file: {}", e)));
+    if let Err(e) = decrypted {
+        drop(out);
+        let _ = std::fs::remove_file(&output);
+        return Err(e);
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let key = Aes256Gcm::generate_key(OsRng);
+        let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 100).map(|i| (i % 251) as u8).collect();
+        let mut writer = EncryptedWriter::new(Vec::new(), &key).unwrap();
+        writer.write_all(&data).unwrap();
+        let encrypted = writer.finish().unwrap();
+        assert_eq!(&encrypted[..MAGIC.len()], MAGIC);
+
+        let cancel = AtomicBool::new(false);
+        let mut decrypted = Vec::new();
+        decrypt(encrypted.as_slice(), &mut decrypted, &key, &cancel, |_| {}).unwrap();
+        assert_eq!(decrypted, data);
+
+        // Dropping the last chunk is detected
+        let truncated = &encrypted[..encrypted.len() - 100 - TAG_SIZE];
+        assert!(decrypt(truncated, &mut Vec::new(), &key, &cancel, |_| {}).is_err());
+        let other_key = Aes256Gcm::generate_key(OsRng);
+        assert!(decrypt(encrypted.as_slice(), &mut Vec::new(), &other_key, &cancel, |_| {}).is_err());
+    }
+}
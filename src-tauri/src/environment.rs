@@ -0,0 +1,522 @@
+//! Tidying the desktop for recordings.
+//!
+//! With desktop cleanup on, the desktop icons are hidden and the captured
+//! monitor's wallpaper is swapped for a plain color as a recording starts,
+//! and both are put back once the capture stops. Cleanup is cosmetic: a
+//! step that fails is logged and the recording goes ahead. The settings are
+//! saved in `desktop_cleanup.json`.
+//!
+//! - Windows: Explorer's "Show desktop icons", and the monitor's wallpaper
+//! - Linux: GNOME's desktop icons extension (most other Wayland desktops
+//!   have no icons); the monitor's wallpaper with hyprpaper, or GNOME's
+//!   background, which covers every monitor
+//! - macOS: Finder's desktop, and the wallpaper of every desktop
+
+use crate::capture::refresh;
+use crate::capture::CaptureTarget;
+use crate::config::{load_json, save_json};
+use crate::state::RecordingPhase;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+
+const SETTINGS_FILE: &str = "desktop_cleanup.json";
+
+/// Side of the plain wallpaper image, which is stretched to the monitor.
+const WALLPAPER_SIZE: u32 = 16;
+
+/// What's tidied before a recording.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DesktopCleanup {
+    pub hide_icons: bool,
+    pub plain_wallpaper: bool,
+    /// Color of the plain wallpaper, as `#rrggbb`
+    pub wallpaper_color: String,
+}
+
+impl Default for DesktopCleanup {
+    fn default() -> Self {
+        Self {
+            hide_icons: false,
+            plain_wallpaper: false,
+            wallpaper_color: "#1e1e1e".to_string(),
+        }
+    }
+}
+
+impl DesktopCleanup {
+    /// Load the saved settings.
+    pub fn load() -> Self {
+        load_json(SETTINGS_FILE)
+    }
+
+    /// Check and save the settings.
+    pub fn save(&self) -> Result<(), String> {
+        parse_color(&self.wallpaper_color)?;
+        save_json(SETTINGS_FILE, self)
+    }
+}
+
+/// Desktop icons that were hidden, to show again.
+pub struct IconsHidden(platform::IconsHidden);
+
+/// Something changed for the recording, to undo.
+enum Tidied {
+    Icons(IconsHidden),
+    Wallpaper(platform::WallpaperChanged),
+}
+
+/// What was changed for the current recording.
+static TIDIED: Mutex<Vec<Tidied>> = Mutex::new(Vec::new());
+
+/// Hide the desktop icons, returning how to show them again, or `None` if
+/// they're already hidden or there are none.
+///
+/// May run a command; call it from a blocking context.
+pub fn hide_desktop_icons() -> Result<Option<IconsHidden>, String> {
+    Ok(platform::hide_desktop_icons()?.map(IconsHidden))
+}
+
+/// Show desktop icons hidden by [`hide_desktop_icons`].
+pub fn show_desktop_icons(hidden: IconsHidden) -> Result<(), String> {
+    platform::show_desktop_icons(hidden.0)
+}
+
+/// Tidy the desktop for a recording of `target`, as the settings say.
+pub async fn engage(target: &CaptureTarget) {
+    let settings = DesktopCleanup::load();
+    if !settings.hide_icons && !settings.plain_wallpaper {
+        return;
+    }
+    let target = target.clone();
+    let _ = tokio::task::spawn_blocking(move || {
+        let mut tidied = TIDIED.lock().unwrap();
+        if settings.hide_icons {
+            match hide_desktop_icons() {
+                Ok(Some(hidden)) => tidied.push(Tidied::Icons(hidden)),
+                Ok(None) => {}
+                Err(e) => tracing::error!(target: "Environment", "Failed to hide desktop icons: {}", e),
+            }
+        }
+        if settings.plain_wallpaper {
+            let changed = refresh::target_monitor(&target)
+                .ok_or_else(|| "The captured monitor wasn't found".to_string())
+                .and_then(|monitor| {
+                    let color = parse_color(&settings.wallpaper_color)?;
+                    platform::set_wallpaper(&monitor, &write_wallpaper(color)?, color)
+                });
+            match changed {
+                Ok(changed) => tidied.push(Tidied::Wallpaper(changed)),
+                Err(e) => tracing::error!(target: "Environment", "Failed to set a plain wallpaper: {}", e),
+            }
+        }
+        if !tidied.is_empty() {
+            tracing::info!(target: "Environment", "Tidied the desktop for the recording");
+        }
+    })
+    .await;
+}
+
+/// Put back what was tidied for the recording.
+///
+/// May run commands; call it from a blocking task.
+pub fn restore() {
+    let tidied = std::mem::take(&mut *TIDIED.lock().unwrap());
+    if tidied.is_empty() {
+        return;
+    }
+    for change in tidied.into_iter().rev() {
+        let result = match change {
+            Tidied::Icons(hidden) => show_desktop_icons(hidden),
+            Tidied::Wallpaper(changed) => platform::restore_wallpaper(changed),
+        };
+        if let Err(e) = result {
+            tracing::error!(target: "Environment", "Failed to restore the desktop: {}", e);
+        }
+    }
+    tracing::info!(target: "Environment", "Restored the desktop");
+}
+
+/// Put the desktop back as recordings stop.
+///
+/// Runs for the lifetime of the app.
+pub async fn run(app: AppHandle) {
+    use tokio::sync::broadcast::error::RecvError;
+
+    let manager = app.state::<AppState>().recording_manager.clone();
+    let mut phases = manager.lock().await.subscribe_phase_events();
+    loop {
+        match phases.recv().await {
+            Ok(RecordingPhase::Finalizing | RecordingPhase::Failed { .. }) => {
+                let _ = tokio::task::spawn_blocking(restore).await;
+            }
+            Ok(_) => {}
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// The red, green and blue of a `#rrggbb` color.
+fn parse_color(color: &str) -> Result<[u8; 3], String> {
+    let invalid = || format!("Wallpaper color must be #rrggbb: {}", color);
+    let hex = color.strip_prefix('#').filter(|hex| hex.len() == 6).ok_or_else(invalid)?;
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2).ok_or_else(invalid)?, 16).map_err(|_| invalid());
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Write a plain wallpaper image in `color`, returning its path.
+fn write_wallpaper(color: [u8; 3]) -> Result<PathBuf, String> {
+    let path = std::env::temp_dir().join(format!(
+        "screen-recorder-wallpaper-{:02x}{:02x}{:02x}.png",
+        color[0], color[1], color[2]
+    ));
+    let file = std::fs::File::create(&path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), WALLPAPER_SIZE, WALLPAPER_SIZE);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let pixels = color.repeat((WALLPAPER_SIZE * WALLPAPER_SIZE) as usize);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&pixels))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+/// The wallpaper of each monitor from `hyprctl hyprpaper listactive`,
+/// whose lines read `MONITOR = PATH`.
+#[cfg_attr(not(any(target_os = "linux", test)), allow(dead_code))]
+fn parse_active_wallpapers(output: &str) -> Vec<(String, String)> {
+    output
+        .lines()
+        .filter_map(|line| line.split_once(" = "))
+        .map(|(monitor, path)| (monitor.trim().to_string(), path.trim().to_string()))
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::parse_active_wallpapers;
+    use crate::capture::MonitorInfo;
+    use crate::process::run;
+    use std::path::Path;
+
+    /// GNOME's desktop icons extension
+    const DESKTOP_ICONS: &str = "ding@rastersoft.com";
+    const GNOME_BACKGROUND: &str = "org.gnome.desktop.background";
+
+    /// GNOME's desktop icons extension was turned off
+    pub struct IconsHidden;
+
+    pub enum WallpaperChanged {
+        /// hyprpaper's wallpaper of a monitor
+        Hyprpaper { monitor: String, previous: String },
+        /// GNOME's background options and color
+        Gnome { options: String, color: String },
+    }
+
+    /// Run a hyprpaper command; hyprctl exits with success and prints errors.
+    fn hyprpaper(args: &[&str]) -> Result<(), String> {
+        let mut command = vec!["hyprpaper"];
+        command.extend_from_slice(args);
+        match run("hyprctl", &command)?.as_str() {
+            "ok" => Ok(()),
+            output => Err(format!("hyprctl {} failed: {}", command.join(" "), output)),
+        }
+    }
+
+    pub fn hide_desktop_icons() -> Result<Option<IconsHidden>, String> {
+        // Without the extension (or GNOME) there are no icons to hide
+        let enabled = run("gnome-extensions", &["list", "--enabled"])
+            .map(|list| list.lines().any(|extension| extension == DESKTOP_ICONS))
+            .unwrap_or(false);
+        if !enabled {
+            return Ok(None);
+        }
+        run("gnome-extensions", &["disable", DESKTOP_ICONS])?;
+        Ok(Some(IconsHidden))
+    }
+
+    pub fn show_desktop_icons(_hidden: IconsHidden) -> Result<(), String> {
+        run("gnome-extensions", &["enable", DESKTOP_ICONS]).map(drop)
+    }
+
+    pub fn set_wallpaper(monitor: &MonitorInfo, image: &Path, color: [u8; 3]) -> Result<WallpaperChanged, String> {
+        let image = image.to_string_lossy();
+        if let Ok(active) = run("hyprctl", &["hyprpaper", "listactive"]) {
+            let previous = parse_active_wallpapers(&active)
+                .into_iter()
+                .find(|(name, _)| *name == monitor.id)
+                .map(|(_, path)| path)
+                .ok_or_else(|| format!("hyprpaper has no wallpaper on {}", monitor.id))?;
+            hyprpaper(&["preload", &image])?;
+            hyprpaper(&["wallpaper", &format!("{},{}", monitor.id, image)])?;
+            return Ok(WallpaperChanged::Hyprpaper { monitor: monitor.id.clone(), previous });
+        }
+        let options = run("gsettings", &["get", GNOME_BACKGROUND, "picture-options"])
+            .map_err(|_| "Needs hyprpaper or GNOME".to_string())?;
+        let previous_color = run("gsettings", &["get", GNOME_BACKGROUND, "primary-color"])?;
+        // GNOME's background covers every monitor; a solid color replaces
+        // the picture without needing the image
+        let rgb = format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2]);
+        run("gsettings", &["set", GNOME_BACKGROUND, "primary-color", &rgb])?;
+        run("gsettings", &["set", GNOME_BACKGROUND, "color-shading-type", "solid"])?;
+        run("gsettings", &["set", GNOME_BACKGROUND, "picture-options", "none"])?;
+        Ok(WallpaperChanged::Gnome { options, color: previous_color })
+    }
+
+    pub fn restore_wallpaper(changed: WallpaperChanged) -> Result<(), String> {
+        match changed {
+            WallpaperChanged::Hyprpaper { monitor, previous } => {
+                hyprpaper(&["wallpaper", &format!("{},{}", monitor, previous)])
+            }
+            WallpaperChanged::Gnome { options, color } => {
+                // gsettings prints strings quoted
+                run("gsettings", &["set", GNOME_BACKGROUND, "primary-color", color.trim_matches('\'')])?;
+                run("gsettings", &["set", GNOME_BACKGROUND, "picture-options", options.trim_matches('\'')]).map(drop)
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use crate::capture::MonitorInfo;
+    use std::path::Path;
+    use windows::core::{w, HSTRING, PCWSTR, PWSTR};
+    use windows::Win32::Foundation::{HWND, LPARAM, WPARAM};
+    use windows::Win32::System::Com::{CoCreateInstance, CoInitializeEx, CoTaskMemFree, CLSCTX_ALL, COINIT_MULTITHREADED};
+    use windows::Win32::UI::Shell::{DesktopWallpaper, IDesktopWallpaper};
+    use windows::Win32::UI::WindowsAndMessaging::{FindWindowExW, FindWindowW, IsWindowVisible, SendMessageW, WM_COMMAND};
+
+    /// The desktop menu's "Show desktop icons" command, which toggles them
+    const TOGGLE_DESKTOP_ICONS: usize = 0x7402;
+
+    pub struct IconsHidden;
+
+    pub struct WallpaperChanged {
+        /// The monitor's device path, as the wallpaper API names it
+        monitor: String,
+        previous: String,
+    }
+
+    /// The desktop's shell view and the list showing its icons.
+    fn desktop_view() -> Option<(HWND, HWND)> {
+        unsafe {
+            let progman = FindWindowW(w!("Progman"), PCWSTR::null()).ok()?;
+            let mut view = FindWindowExW(progman, HWND::default(), w!("SHELLDLL_DefView"), PCWSTR::null()).ok();
+            // With a wallpaper slideshow the view is moved to a WorkerW window
+            let mut worker = HWND::default();
+            while view.is_none() {
+                worker = FindWindowExW(HWND::default(), worker, w!("WorkerW"), PCWSTR::null()).ok()?;
+                view = FindWindowExW(worker, HWND::default(), w!("SHELLDLL_DefView"), PCWSTR::null()).ok();
+            }
+            let view = view?;
+            let icons = FindWindowExW(view, HWND::default(), w!("SysListView32"), PCWSTR::null()).ok()?;
+            Some((view, icons))
+        }
+    }
+
+    fn toggle_desktop_icons(view: HWND) {
+        unsafe {
+            SendMessageW(view, WM_COMMAND, WPARAM(TOGGLE_DESKTOP_ICONS), LPARAM(0));
+        }
+    }
+
+    pub fn hide_desktop_icons() -> Result<Option<IconsHidden>, String> {
+        let (view, icons) = desktop_view().ok_or("The desktop window wasn't found")?;
+        if !unsafe { IsWindowVisible(icons) }.as_bool() {
+            return Ok(None);
+        }
+        toggle_desktop_icons(view);
+        Ok(Some(IconsHidden))
+    }
+
+    pub fn show_desktop_icons(_hidden: IconsHidden) -> Result<(), String> {
+        let (view, icons) = desktop_view().ok_or("The desktop window wasn't found")?;
+        if !unsafe { IsWindowVisible(icons) }.as_bool() {
+            toggle_desktop_icons(view);
+        }
+        Ok(())
+    }
+
+    /// A string the wallpaper API allocated, freed after reading.
+    fn take_string(string: PWSTR) -> String {
+        unsafe {
+            let value = string.to_string().unwrap_or_default();
+            CoTaskMemFree(Some(string.0 as *const _));
+            value
+        }
+    }
+
+    fn desktop_wallpaper() -> Result<IDesktopWallpaper, String> {
+        unsafe {
+            let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+            CoCreateInstance(&DesktopWallpaper, None, CLSCTX_ALL)
+                .map_err(|e| format!("Failed to open the wallpaper settings: {}", e))
+        }
+    }
+
+    pub fn set_wallpaper(monitor: &MonitorInfo, image: &Path, _color: [u8; 3]) -> Result<WallpaperChanged, String> {
+        let wallpaper = desktop_wallpaper()?;
+        unsafe {
+            let count = wallpaper.GetMonitorDevicePathCount().map_err(|e| e.to_string())?;
+            for index in 0..count {
+                let path = take_string(wallpaper.GetMonitorDevicePathAt(index).map_err(|e| e.to_string())?);
+                let path_string = HSTRING::from(path.as_str());
+                let Ok(rect) = wallpaper.GetMonitorRECT(&path_string) else {
+                    continue;
+                };
+                if rect.left != monitor.x || rect.top != monitor.y {
+                    continue;
+                }
+                let previous = take_string(wallpaper.GetWallpaper(&path_string).map_err(|e| e.to_string())?);
+                wallpaper
+                    .SetWallpaper(&path_string, &HSTRING::from(image.as_os_str()))
+                    .map_err(|e| format!("Failed to set the wallpaper: {}", e))?;
+                return Ok(WallpaperChanged { monitor: path, previous });
+            }
+        }
+        Err(format!("{} isn't one of the desktop's monitors", monitor.id))
+    }
+
+    pub fn restore_wallpaper(changed: WallpaperChanged) -> Result<(), String> {
+        let wallpaper = desktop_wallpaper()?;
+        unsafe {
+            wallpaper
+                .SetWallpaper(&HSTRING::from(changed.monitor.as_str()), &HSTRING::from(changed.previous.as_str()))
+                .map_err(|e| format!("Failed to restore the wallpaper: {}", e))
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use crate::capture::MonitorInfo;
+    use crate::process::run;
+    use std::path::Path;
+
+    pub struct IconsHidden {
+        /// Finder's `CreateDesktop` setting before, if it was set
+        previous: Option<String>,
+    }
+
+    pub struct WallpaperChanged {
+        /// Picture of each desktop, in System Events' order
+        previous: Vec<String>,
+    }
+
+    /// Finder only picks up `CreateDesktop` when it restarts.
+    fn restart_finder() -> Result<(), String> {
+        run("killall", &["Finder"]).map(drop)
+    }
+
+    pub fn hide_desktop_icons() -> Result<Option<IconsHidden>, String> {
+        let previous = run("defaults", &["read", "com.apple.finder", "CreateDesktop"]).ok();
+        if matches!(previous.as_deref(), Some("0" | "false")) {
+            return Ok(None);
+        }
+        run("defaults", &["write", "com.apple.finder", "CreateDesktop", "-bool", "false"])?;
+        restart_finder()?;
+        Ok(Some(IconsHidden { previous }))
+    }
+
+    pub fn show_desktop_icons(hidden: IconsHidden) -> Result<(), String> {
+        match hidden.previous {
+            Some(value) => run("defaults", &["write", "com.apple.finder", "CreateDesktop", &value])?,
+            None => run("defaults", &["delete", "com.apple.finder", "CreateDesktop"])?,
+        };
+        restart_finder()
+    }
+
+    /// Quote `text` as an AppleScript string.
+    fn quoted(text: &str) -> String {
+        format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+
+    pub fn set_wallpaper(_monitor: &MonitorInfo, image: &Path, _color: [u8; 3]) -> Result<WallpaperChanged, String> {
+        // Printed as a comma-separated list
+        let pictures = run("osascript", &["-e", "tell application \"System Events\" to get picture of every desktop"])?;
+        let previous = pictures.split(", ").map(str::to_string).collect();
+        run(
+            "osascript",
+            &[
+                "-e",
+                &format!(
+                    "tell application \"System Events\" to set picture of every desktop to {}",
+                    quoted(&image.to_string_lossy())
+                ),
+            ],
+        )?;
+        Ok(WallpaperChanged { previous })
+    }
+
+    pub fn restore_wallpaper(changed: WallpaperChanged) -> Result<(), String> {
+        for (index, picture) in changed.previous.iter().enumerate() {
+            run(
+                "osascript",
+                &[
+                    "-e",
+                    &format!(
+                        "tell application \"System Events\" to set picture of desktop {} to {}",
+                        index + 1,
+                        quoted(picture)
+                    ),
+                ],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+mod platform {
+    use crate::capture::MonitorInfo;
+    use std::path::Path;
+
+    pub struct IconsHidden;
+    pub struct WallpaperChanged;
+
+    pub fn hide_desktop_icons() -> Result<Option<IconsHidden>, String> {
+        Ok(None)
+    }
+
+    pub fn show_desktop_icons(_hidden: IconsHidden) -> Result<(), String> {
+        Ok(())
+    }
+
+    pub fn set_wallpaper(_monitor: &MonitorInfo, _image: &Path, _color: [u8; 3]) -> Result<WallpaperChanged, String> {
+        Err("Not supported on this platform".to_string())
+    }
+
+    pub fn restore_wallpaper(_changed: WallpaperChanged) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wallpaper() {
+        assert_eq!(parse_color("#1e1e1e"), Ok([0x1e, 0x1e, 0x1e]));
+        assert_eq!(parse_color("#FF8000"), Ok([0xff, 0x80, 0x00]));
+        assert!(parse_color("1e1e1e").is_err());
+        assert!(parse_color("#1e1e1").is_err());
+        assert!(parse_color("#1e1e1g").is_err());
+        assert_eq!(
+            parse_active_wallpapers("DP-1 = /home/me/a.png\nHDMI-A-1 = /home/me/b b.jpg\n"),
+            vec![
+                ("DP-1".to_string(), "/home/me/a.png".to_string()),
+                ("HDMI-A-1".to_string(), "/home/me/b b.jpg".to_string())
+            ]
+        );
+        let image = write_wallpaper([0x12, 0x34, 0x56]).unwrap();
+        assert!(image.ends_with("screen-recorder-wallpaper-123456.png"));
+        assert!(std::fs::metadata(&image).unwrap().len() > 0);
+    }
+}
@@ -0,0 +1,188 @@
+//! Errors the frontend can act on.
+//!
+//! Capture, enumeration, encoder and audio failures are flattened into one
+//! [`AppError`] with a stable code, so the frontend can tell a missing
+//! permission from a closed window without matching on message text. Each
+//! error serializes as `{ code, message, remediation, settings_url }`: the
+//! remediation is a translated hint at what to do about it, and the
+//! settings URL, where there is one, opens the system settings that fix it.
+
+use crate::capture::{CaptureError, EnumerationError, RegionError};
+use crate::i18n;
+use crate::permissions::{self, Permission};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt;
+
+/// An error reported to the frontend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppError {
+    /// The system didn't allow capturing the screen
+    PermissionDenied(String),
+    /// The window or display to capture isn't there
+    TargetNotFound(String),
+    /// The region can't be recorded
+    InvalidRegion(String),
+    /// Other invalid capture parameters
+    InvalidParameters(String),
+    /// Not possible on this platform or system
+    NotSupported(String),
+    /// Cancelled by the user, such as in a portal dialog
+    Cancelled,
+    /// The platform's capture failed
+    CaptureFailed(String),
+    /// Windows or monitors couldn't be listed
+    EnumerationFailed(String),
+    /// FFmpeg couldn't be found or downloaded
+    FfmpegUnavailable,
+    /// The encoder couldn't start
+    EncoderFailed(String),
+    /// The audio device couldn't be opened
+    AudioDeviceUnavailable(String),
+    /// A recording is already running or saving
+    Busy,
+    /// Anything else
+    Other(String),
+}
+
+impl AppError {
+    /// The stable code the frontend keys on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::PermissionDenied(_) => "PERMISSION_DENIED",
+            AppError::TargetNotFound(_) => "TARGET_NOT_FOUND",
+            AppError::InvalidRegion(_) => "INVALID_REGION",
+            AppError::InvalidParameters(_) => "INVALID_PARAMETERS",
+            AppError::NotSupported(_) => "NOT_SUPPORTED",
+            AppError::Cancelled => "CANCELLED",
+            AppError::CaptureFailed(_) => "CAPTURE_FAILED",
+            AppError::EnumerationFailed(_) => "ENUMERATION_FAILED",
+            AppError::FfmpegUnavailable => "FFMPEG_UNAVAILABLE",
+            AppError::EncoderFailed(_) => "ENCODER_FAILED",
+            AppError::AudioDeviceUnavailable(_) => "AUDIO_DEVICE_UNAVAILABLE",
+            AppError::Busy => "BUSY",
+            AppError::Other(_) => "UNKNOWN",
+        }
+    }
+
+    /// What the user can do about it, in the current locale.
+    pub fn remediation(&self) -> Option<String> {
+        let id = match self {
+            AppError::PermissionDenied(_) if cfg!(target_os = "macos") => "remedy-permission-denied-macos",
+            AppError::PermissionDenied(_) if cfg!(target_os = "windows") => "remedy-permission-denied-windows",
+            AppError::PermissionDenied(_) => "remedy-permission-denied-linux",
+            AppError::TargetNotFound(_) => "remedy-target-not-found",
+            AppError::InvalidRegion(_) => "remedy-invalid-region",
+            AppError::NotSupported(_) => "remedy-not-supported",
+            AppError::CaptureFailed(_) if cfg!(target_os = "linux") => "remedy-capture-failed-linux",
+            AppError::FfmpegUnavailable => "remedy-ffmpeg-unavailable",
+            AppError::EncoderFailed(_) => "remedy-encoder-failed",
+            AppError::AudioDeviceUnavailable(_) => "remedy-audio-device-unavailable",
+            AppError::Busy => "remedy-busy",
+            _ => return None,
+        };
+        Some(i18n::tr(id))
+    }
+
+    /// System settings that fix it, for the frontend to open.
+    pub fn settings_url(&self) -> Option<&'static str> {
+        match self {
+            AppError::PermissionDenied(_) => permissions::settings_url(Permission::ScreenRecording),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::PermissionDenied(msg) => write!(f, "Permission denied: {}", msg),
+            AppError::TargetNotFound(msg) => write!(f, "Capture target not found: {}", msg),
+            AppError::InvalidRegion(msg) => write!(f, "Invalid region: {}", msg),
+            AppError::InvalidParameters(msg) => write!(f, "Invalid parameters: {}", msg),
+            AppError::NotSupported(msg) => write!(f, "Not implemented: {}", msg),
+            AppError::Cancelled => write!(f, "Capture cancelled"),
+            AppError::CaptureFailed(msg) => write!(f, "Platform error: {}", msg),
+            AppError::EnumerationFailed(msg) => write!(f, "Enumeration error: {}", msg),
+            AppError::FfmpegUnavailable => write!(f, "{}", i18n::tr("error-ffmpeg-unavailable")),
+            AppError::EncoderFailed(msg) => write!(f, "Encoder failed: {}", msg),
+            AppError::AudioDeviceUnavailable(msg) => write!(f, "Audio device unavailable: {}", msg),
+            AppError::Busy => write!(f, "Already recording or saving"),
+            AppError::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut error = serializer.serialize_struct("AppError", 4)?;
+        error.serialize_field("code", self.code())?;
+        error.serialize_field("message", &self.to_string())?;
+        error.serialize_field("remediation", &self.remediation())?;
+        error.serialize_field("settings_url", &self.settings_url())?;
+        error.end()
+    }
+}
+
+impl From<CaptureError> for AppError {
+    fn from(err: CaptureError) -> Self {
+        match err {
+            CaptureError::TargetNotFound(msg) => AppError::TargetNotFound(msg),
+            CaptureError::PermissionDenied(msg) => AppError::PermissionDenied(msg),
+            CaptureError::InvalidParameters(msg) => AppError::InvalidParameters(msg),
+            CaptureError::InvalidRegion(msg) => AppError::InvalidRegion(msg),
+            CaptureError::PlatformError(msg) => AppError::CaptureFailed(msg),
+            CaptureError::NotImplemented(msg) => AppError::NotSupported(msg),
+            CaptureError::Cancelled => AppError::Cancelled,
+        }
+    }
+}
+
+impl From<RegionError> for AppError {
+    fn from(err: RegionError) -> Self {
+        CaptureError::from(err).into()
+    }
+}
+
+impl From<EnumerationError> for AppError {
+    fn from(err: EnumerationError) -> Self {
+        match err {
+            EnumerationError::PlatformError(msg) => AppError::EnumerationFailed(msg),
+            EnumerationError::NotImplemented(msg) => AppError::NotSupported(msg),
+        }
+    }
+}
+
+impl From<String> for AppError {
+    fn from(msg: String) -> Self {
+        AppError::Other(msg)
+    }
+}
+
+impl From<AppError> for String {
+    fn from(err: AppError) -> Self {
+        err.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_app_error_serialization() {
+        let error = AppError::from(CaptureError::PermissionDenied("Screen Recording is off".to_string()));
+        assert_eq!(error.code(), "PERMISSION_DENIED");
+        let json = serde_json::to_value(&error).unwrap();
+        assert_eq!(json["code"], "PERMISSION_DENIED");
+        assert_eq!(json["message"], "Permission denied: Screen Recording is off");
+        assert!(json["remediation"].is_string());
+
+        let error = AppError::from(RegionError::MonitorNotFound("HDMI-1".to_string()));
+        assert_eq!(error, AppError::TargetNotFound("Monitor 'HDMI-1' is not connected".to_string()));
+        let json = serde_json::to_value(AppError::from("Disk full".to_string())).unwrap();
+        assert_eq!(json["code"], "UNKNOWN");
+        assert!(json["remediation"].is_null() && json["settings_url"].is_null());
+    }
+}
@@ -0,0 +1,140 @@
+//! Golden-frame comparisons for the pixel pipeline's tests.
+//!
+//! Tests run known frames through a stage (tone mapping, format conversion,
+//! masking) and compare the result against a stored image in
+//! `testdata/golden`, allowing each channel to be off by a tolerance so
+//! faster implementations with different rounding still pass. Output
+//! scaling is done by FFmpeg filters and isn't covered here; only the
+//! letterboxing of resized captures is.
+//!
+//! Images are stored as 8-bit RGBA PAM files. Run the tests with
+//! `UPDATE_GOLDEN=1` to write the current output as the new golden images,
+//! then review the changed files before committing them.
+
+use crate::capture::types::{CapturedFrame, ColorSpace, PixelFormat};
+use crate::screenshot::bgra_to_rgba;
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// Directory of the stored golden images.
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("testdata").join("golden")
+}
+
+/// Assert that a BGRA `frame` matches the golden image `name` to within
+/// `tolerance` per channel.
+///
+/// On a mismatch the frame is written to the temp directory for inspection.
+pub fn assert_golden(name: &str, frame: &CapturedFrame, tolerance: u8) {
+    assert_eq!(frame.format, PixelFormat::Bgra8, "golden images are 8-bit BGRA");
+    let path = golden_dir().join(format!("{}.pam", name));
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(golden_dir()).unwrap();
+        std::fs::write(&path, encode_pam(frame)).unwrap();
+        return;
+    }
+
+    let data = std::fs::read(&path)
+        .unwrap_or_else(|e| panic!("No golden image at {:?} ({}); run with UPDATE_GOLDEN=1 to create it", path, e));
+    let golden = decode_pam(&data).unwrap_or_else(|e| panic!("Invalid golden image {:?}: {}", path, e));
+
+    let mismatch = if (golden.width, golden.height) != (frame.width, frame.height) {
+        Some(format!(
+            "size {}x{} differs from golden {}x{}",
+            frame.width, frame.height, golden.width, golden.height
+        ))
+    } else {
+        frame
+            .data
+            .iter()
+            .zip(&golden.data)
+            .position(|(a, b)| a.abs_diff(*b) > tolerance)
+            .map(|i| {
+                let pixel = i / 4;
+                format!(
+                    "pixel ({}, {}) channel {} is {}, golden is {}",
+                    pixel % frame.width as usize,
+                    pixel / frame.width as usize,
+                    i % 4,
+                    frame.data[i],
+                    golden.data[i]
+                )
+            })
+    };
+
+    if let Some(mismatch) = mismatch {
+        let actual = std::env::temp_dir().join(format!("{}.actual.pam", name));
+        let _ = std::fs::write(&actual, encode_pam(frame));
+        panic!("Frame doesn't match golden image {}: {} (output saved to {:?})", name, mismatch, actual);
+    }
+}
+
+/// Encode a BGRA frame as an RGBA PAM image.
+fn encode_pam(frame: &CapturedFrame) -> Vec<u8> {
+    let mut data = format!(
+        "P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n",
+        frame.width, frame.height
+    )
+    .into_bytes();
+    data.extend(bgra_to_rgba(&frame.data));
+    data
+}
+
+/// Decode an RGBA PAM image into a BGRA frame.
+fn decode_pam(data: &[u8]) -> Result<CapturedFrame, String> {
+    const END: &[u8] = b"ENDHDR\n";
+    let header_len = data
+        .windows(END.len())
+        .position(|w| w == END)
+        .ok_or("Missing ENDHDR")?
+        + END.len();
+    let header = std::str::from_utf8(&data[..header_len]).map_err(|e| e.to_string())?;
+
+    let (mut width, mut height) = (0, 0);
+    for line in header.lines() {
+        match line.split_once(' ') {
+            Some(("WIDTH", value)) => width = value.parse().map_err(|_| "Invalid WIDTH")?,
+            Some(("HEIGHT", value)) => height = value.parse().map_err(|_| "Invalid HEIGHT")?,
+            Some(("DEPTH", value)) if value != "4" => return Err(format!("Unsupported depth {}", value)),
+            Some(("MAXVAL", value)) if value != "255" => return Err(format!("Unsupported maxval {}", value)),
+            _ => {}
+        }
+    }
+
+    let pixels = &data[header_len..];
+    if pixels.len() != width as usize * height as usize * 4 {
+        return Err(format!("Expected {}x{} pixels, found {} bytes", width, height, pixels.len()));
+    }
+    Ok(CapturedFrame {
+        width,
+        height,
+        data: bgra_to_rgba(pixels),
+        format: PixelFormat::Bgra8,
+        color: ColorSpace::SRGB,
+        captured_at: Instant::now(),
+        presented_at: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pam_roundtrip() {
+        let frame = CapturedFrame {
+            width: 2,
+            height: 1,
+            data: vec![1, 2, 3, 4, 5, 6, 7, 8],
+            format: PixelFormat::Bgra8,
+            color: ColorSpace::SRGB,
+            captured_at: Instant::now(),
+            presented_at: None,
+        };
+        let decoded = decode_pam(&encode_pam(&frame)).unwrap();
+        assert_eq!((decoded.width, decoded.height), (2, 1));
+        assert_eq!(decoded.data, frame.data);
+        assert!(decode_pam(b"P7\nWIDTH 2\nHEIGHT 2\nENDHDR\n").is_err());
+    }
+}
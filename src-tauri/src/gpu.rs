@@ -0,0 +1,411 @@
+//! GPU adapters and which of them a recording runs on.
+//!
+//! On machines with more than one GPU (typically a laptop's integrated GPU
+//! plus a discrete one), the compositor captures frames on the GPU driving
+//! the display while a hardware encoder may live on the other. Frames always
+//! reach the encoder through system memory, so this works, but it costs an
+//! extra copy across the bus. By default recordings prefer the hardware
+//! encoder on the GPU doing the capture; the encoder can instead be pinned to
+//! a chosen GPU.
+//!
+//! FFmpeg opens the GPU APIs on the first GPU that has them unless told
+//! otherwise, so the GPU chosen here is passed on as a [`GpuTarget`]: NVENC
+//! and CUDA by their position among the NVIDIA GPUs, Quick Sync and VA-API
+//! by render node on Linux and adapter index on Windows.
+
+use crate::capture::CaptureTarget;
+use crate::encoder::convert::ConversionBackend;
+use crate::encoder::hardware::EncoderBackend;
+use serde::{Deserialize, Serialize};
+
+/// Who made a GPU, which decides the hardware encoders it has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GpuVendor {
+    Nvidia,
+    Intel,
+    Amd,
+    Apple,
+    Other,
+}
+
+impl GpuVendor {
+    /// Vendor with the given PCI vendor ID.
+    pub fn from_pci_id(id: u32) -> Self {
+        match id {
+            0x10de => GpuVendor::Nvidia,
+            0x8086 => GpuVendor::Intel,
+            0x1002 | 0x1022 => GpuVendor::Amd,
+            0x106b => GpuVendor::Apple,
+            _ => GpuVendor::Other,
+        }
+    }
+}
+
+/// A GPU on this machine.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GpuAdapter {
+    /// Position in the platform's adapter list, used to pin the encoder
+    pub index: u32,
+    /// Human-readable name, such as "NVIDIA GeForce RTX 4060 Laptop GPU"
+    pub name: String,
+    pub vendor: GpuVendor,
+    /// Dedicated video memory in megabytes; 0 for integrated GPUs or where
+    /// the platform doesn't report it
+    pub dedicated_memory_mb: u64,
+    /// DRM render node, such as `/dev/dri/renderD129` (Linux)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub render_node: Option<String>,
+}
+
+/// A GPU as FFmpeg's hardware devices address it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GpuTarget {
+    pub adapter: GpuAdapter,
+    /// Position among this machine's GPUs from the same vendor. CUDA numbers
+    /// NVIDIA GPUs this way when ordering them by PCI bus, which the adapter
+    /// lists follow on most machines.
+    pub ordinal: u32,
+}
+
+impl GpuTarget {
+    /// The device Quick Sync opens its session on: the render node on
+    /// Linux, the DXGI adapter index on Windows.
+    pub fn child_device(&self) -> String {
+        match &self.adapter.render_node {
+            Some(render_node) => render_node.clone(),
+            None => self.adapter.index.to_string(),
+        }
+    }
+}
+
+/// List the GPUs on this machine. Software renderers aren't included.
+pub fn list_adapters() -> Vec<GpuAdapter> {
+    #[cfg(target_os = "windows")]
+    return windows::list_adapters();
+    #[cfg(target_os = "linux")]
+    return linux::list_adapters();
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    return Vec::new();
+}
+
+/// The GPU `target` is captured on, if it can be told.
+fn capture_adapter(target: &CaptureTarget, adapters: &[GpuAdapter]) -> Option<GpuAdapter> {
+    #[cfg(target_os = "windows")]
+    let index = windows::capture_adapter_index(target);
+    // The compositor renders on the GPU the firmware set up as primary
+    #[cfg(target_os = "linux")]
+    let index = {
+        let _ = target;
+        linux::primary_adapter_index()
+    };
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    let index = {
+        let _ = target;
+        None::<u32>
+    };
+    match index {
+        Some(index) => adapters.iter().find(|adapter| adapter.index == index).cloned(),
+        // With a single GPU there's no question
+        None if adapters.len() == 1 => adapters.first().cloned(),
+        None => None,
+    }
+}
+
+/// Which GPUs a recording captures and encodes on.
+#[derive(Debug, Clone, Default)]
+pub struct GpuPlacement {
+    adapters: Vec<GpuAdapter>,
+    /// GPU the frames are captured on, if known
+    pub capture: Option<GpuAdapter>,
+    /// GPU hardware encoding is pinned to, if any
+    pub pinned: Option<GpuAdapter>,
+}
+
+impl GpuPlacement {
+    /// Work out where a recording of `target` runs, with hardware encoding
+    /// pinned to the adapter at `pinned` if set.
+    pub fn resolve(target: &CaptureTarget, pinned: Option<u32>) -> Self {
+        let adapters = list_adapters();
+        let capture = capture_adapter(target, &adapters);
+        let pinned = pinned.and_then(|index| {
+            let adapter = adapters.iter().find(|adapter| adapter.index == index).cloned();
+            if adapter.is_none() {
+                tracing::warn!(target: "GPU", "Pinned adapter {} not found, placing the encoder automatically", index);
+            }
+            adapter
+        });
+        if let Some(ref capture) = capture {
+            tracing::info!(target: "GPU", "Capturing on {}", capture.name);
+        }
+        Self { adapters, capture, pinned }
+    }
+
+    /// Reorder `chain` for this placement: only encoders on the pinned GPU,
+    /// or otherwise those on the capturing GPU first. The software encoder
+    /// stays last.
+    pub fn order_encoders(&self, mut chain: Vec<EncoderBackend>) -> Vec<EncoderBackend> {
+        if let Some(ref pinned) = self.pinned {
+            chain.retain(|backend| backend.vendor().is_none_or(|vendor| vendor == pinned.vendor));
+        } else if let Some(ref capture) = self.capture {
+            // Stable, so the preference order within each group is kept
+            chain.sort_by_key(|backend| match backend.vendor() {
+                Some(vendor) if vendor == capture.vendor => 0,
+                Some(_) => 1,
+                None => 2,
+            });
+        }
+        chain
+    }
+
+    /// Whether GPU conversion with `conversion` stays on the pinned GPU.
+    pub fn allows_conversion(&self, conversion: ConversionBackend) -> bool {
+        match (&self.pinned, conversion.vendor()) {
+            (Some(pinned), Some(vendor)) => vendor == pinned.vendor,
+            _ => true,
+        }
+    }
+
+    /// The GPU `backend` encodes on, or `None` for the software encoder.
+    pub fn encode_adapter(&self, backend: EncoderBackend) -> Option<GpuAdapter> {
+        self.adapter_from(&[backend.vendor()?])
+    }
+
+    /// The GPU `backend` encodes on, as FFmpeg addresses it.
+    pub fn encode_target(&self, backend: EncoderBackend) -> Option<GpuTarget> {
+        self.target(self.encode_adapter(backend)?)
+    }
+
+    /// The GPU color conversion with `conversion` runs on, as FFmpeg
+    /// addresses it. VA-API runs on Intel and AMD GPUs alike.
+    pub fn conversion_target(&self, conversion: ConversionBackend) -> Option<GpuTarget> {
+        let adapter = match (conversion, conversion.vendor()) {
+            (ConversionBackend::Vaapi, _) => self.adapter_from(&[GpuVendor::Intel, GpuVendor::Amd])?,
+            (_, Some(vendor)) => self.adapter_from(&[vendor])?,
+            (_, None) => return None,
+        };
+        self.target(adapter)
+    }
+
+    /// The first GPU from one of `vendors`: the pinned one, the capturing
+    /// one, then the others in order.
+    fn adapter_from(&self, vendors: &[GpuVendor]) -> Option<GpuAdapter> {
+        [&self.pinned, &self.capture]
+            .into_iter()
+            .flatten()
+            .chain(&self.adapters)
+            .find(|adapter| vendors.contains(&adapter.vendor))
+            .cloned()
+    }
+
+    fn target(&self, adapter: GpuAdapter) -> Option<GpuTarget> {
+        let ordinal = self
+            .adapters
+            .iter()
+            .filter(|other| other.vendor == adapter.vendor && other.index < adapter.index)
+            .count() as u32;
+        Some(GpuTarget { adapter, ordinal })
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod windows {
+    use super::{GpuAdapter, GpuVendor};
+    use crate::capture::CaptureTarget;
+    use windows::Win32::Foundation::HWND;
+    use windows::Win32::Graphics::Dxgi::{CreateDXGIFactory1, IDXGIFactory1, DXGI_ADAPTER_FLAG_SOFTWARE};
+    use windows::Win32::Graphics::Gdi::{MonitorFromWindow, MONITOR_DEFAULTTONEAREST};
+
+    pub fn list_adapters() -> Vec<GpuAdapter> {
+        let Ok(factory) = (unsafe { CreateDXGIFactory1::<IDXGIFactory1>() }) else {
+            return Vec::new();
+        };
+        let mut adapters = Vec::new();
+        let mut index = 0;
+        while let Ok(adapter) = unsafe { factory.EnumAdapters1(index) } {
+            if let Ok(desc) = unsafe { adapter.GetDesc1() } {
+                if desc.Flags & DXGI_ADAPTER_FLAG_SOFTWARE.0 as u32 == 0 {
+                    let name_len = desc.Description.iter().position(|&c| c == 0).unwrap_or(desc.Description.len());
+                    adapters.push(GpuAdapter {
+                        index,
+                        name: String::from_utf16_lossy(&desc.Description[..name_len]),
+                        vendor: GpuVendor::from_pci_id(desc.VendorId),
+                        dedicated_memory_mb: desc.DedicatedVideoMemory as u64 / (1024 * 1024),
+                        render_node: None,
+                    });
+                }
+            }
+            index += 1;
+        }
+        adapters
+    }
+
+    /// Index of the adapter driving the monitor `target` is on.
+    pub fn capture_adapter_index(target: &CaptureTarget) -> Option<u32> {
+        let monitor_id = match target {
+            CaptureTarget::Display { monitor_id, .. } => Some(monitor_id.as_str()),
+            CaptureTarget::Region { region } => Some(region.monitor_id.as_str()),
+            CaptureTarget::Window { .. } => None,
+        };
+        let window_monitor = match target {
+            CaptureTarget::Window { window_handle } => {
+                let hwnd = HWND(*window_handle as *mut std::ffi::c_void);
+                Some(unsafe { MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST) })
+            }
+            _ => None,
+        };
+
+        let factory = unsafe { CreateDXGIFactory1::<IDXGIFactory1>() }.ok()?;
+        let mut index = 0;
+        while let Ok(adapter) = unsafe { factory.EnumAdapters1(index) } {
+            let mut output_index = 0;
+            while let Ok(output) = unsafe { adapter.EnumOutputs(output_index) } {
+                output_index += 1;
+                let Ok(desc) = (unsafe { output.GetDesc() }) else { continue };
+                let name_len = desc.DeviceName.iter().position(|&c| c == 0).unwrap_or(desc.DeviceName.len());
+                let device_name = String::from_utf16_lossy(&desc.DeviceName[..name_len]);
+                if monitor_id == Some(device_name.as_str()) || window_monitor == Some(desc.Monitor) {
+                    return Some(index);
+                }
+            }
+            index += 1;
+        }
+        None
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::{GpuAdapter, GpuVendor};
+    use std::path::{Path, PathBuf};
+
+    /// DRM card devices (`/sys/class/drm/cardN`), in card order.
+    fn cards() -> Vec<PathBuf> {
+        let Ok(entries) = std::fs::read_dir("/sys/class/drm") else {
+            return Vec::new();
+        };
+        let mut cards: Vec<(u32, PathBuf)> = entries
+            .flatten()
+            .filter_map(|entry| {
+                // Connectors are listed too, as cardN-HDMI-A-1 and so on
+                let number = entry.file_name().to_str()?.strip_prefix("card")?.parse().ok()?;
+                Some((number, entry.path()))
+            })
+            .collect();
+        cards.sort();
+        cards.into_iter().map(|(_, path)| path).collect()
+    }
+
+    fn read_attribute(card: &Path, name: &str) -> Option<String> {
+        std::fs::read_to_string(card.join("device").join(name))
+            .ok()
+            .map(|value| value.trim().to_string())
+    }
+
+    pub fn list_adapters() -> Vec<GpuAdapter> {
+        cards()
+            .iter()
+            .enumerate()
+            .filter_map(|(index, card)| {
+                let vendor_id = read_attribute(card, "vendor")?;
+                let vendor = GpuVendor::from_pci_id(u32::from_str_radix(vendor_id.trim_start_matches("0x"), 16).ok()?);
+                // sysfs has no marketing names, so name GPUs by vendor and PCI slot
+                let slot = read_attribute(card, "uevent")
+                    .and_then(|uevent| uevent.lines().find_map(|line| line.strip_prefix("PCI_SLOT_NAME=").map(str::to_string)))
+                    .unwrap_or_else(|| card.file_name().unwrap_or_default().to_string_lossy().to_string());
+                Some(GpuAdapter {
+                    index: index as u32,
+                    name: format!("{:?} GPU ({})", vendor, slot),
+                    vendor,
+                    // Only amdgpu reports its VRAM here
+                    dedicated_memory_mb: read_attribute(card, "mem_info_vram_total")
+                        .and_then(|bytes| bytes.parse::<u64>().ok())
+                        .map_or(0, |bytes| bytes / (1024 * 1024)),
+                    render_node: render_node(card),
+                })
+            })
+            .collect()
+    }
+
+    /// The render node of `card`, listed beside it under its device.
+    fn render_node(card: &Path) -> Option<String> {
+        std::fs::read_dir(card.join("device").join("drm"))
+            .ok()?
+            .flatten()
+            .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+            .find(|name| name.starts_with("renderD"))
+            .map(|name| format!("/dev/dri/{}", name))
+    }
+
+    /// Index of the GPU the firmware used for the boot display.
+    pub fn primary_adapter_index() -> Option<u32> {
+        cards()
+            .iter()
+            .position(|card| read_attribute(card, "boot_vga").as_deref() == Some("1"))
+            .map(|index| index as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adapter(index: u32, vendor: GpuVendor) -> GpuAdapter {
+        GpuAdapter {
+            index,
+            name: format!("{:?}", vendor),
+            vendor,
+            dedicated_memory_mb: 0,
+            render_node: Some(format!("/dev/dri/renderD{}", 128 + index)),
+        }
+    }
+
+    #[test]
+    fn test_order_encoders() {
+        let intel = adapter(0, GpuVendor::Intel);
+        let nvidia = adapter(1, GpuVendor::Nvidia);
+        let chain = vec![EncoderBackend::Nvenc, EncoderBackend::Qsv, EncoderBackend::Software];
+
+        // Prefer the encoder on the GPU doing the capture
+        let placement = GpuPlacement {
+            adapters: vec![intel.clone(), nvidia.clone()],
+            capture: Some(intel.clone()),
+            pinned: None,
+        };
+        let ordered = placement.order_encoders(chain.clone());
+        assert_eq!(ordered, [EncoderBackend::Qsv, EncoderBackend::Nvenc, EncoderBackend::Software]);
+        assert_eq!(placement.encode_adapter(EncoderBackend::Nvenc), Some(nvidia.clone()));
+        assert_eq!(placement.encode_adapter(EncoderBackend::Software), None);
+
+        // Pinning keeps only that GPU's encoders
+        let placement = GpuPlacement {
+            pinned: Some(nvidia),
+            ..placement
+        };
+        assert_eq!(placement.order_encoders(chain), [EncoderBackend::Nvenc, EncoderBackend::Software]);
+        assert!(!placement.allows_conversion(ConversionBackend::Qsv));
+        assert!(placement.allows_conversion(ConversionBackend::Cuda));
+        assert_eq!(GpuVendor::from_pci_id(0x1002), GpuVendor::Amd);
+    }
+
+    #[test]
+    fn test_gpu_targets() {
+        let intel = adapter(0, GpuVendor::Intel);
+        let first_nvidia = adapter(1, GpuVendor::Nvidia);
+        let second_nvidia = adapter(2, GpuVendor::Nvidia);
+        let placement = GpuPlacement {
+            adapters: vec![intel.clone(), first_nvidia, second_nvidia.clone()],
+            capture: Some(intel),
+            pinned: Some(second_nvidia),
+        };
+        // NVIDIA GPUs are numbered among themselves
+        let target = placement.encode_target(EncoderBackend::Nvenc).unwrap();
+        assert_eq!((target.adapter.index, target.ordinal), (2, 1));
+        assert_eq!(placement.conversion_target(ConversionBackend::Cuda).unwrap().ordinal, 1);
+        // Quick Sync and VA-API open the Intel GPU's render node
+        let target = placement.encode_target(EncoderBackend::Qsv).unwrap();
+        assert_eq!(target.child_device(), "/dev/dri/renderD128");
+        assert_eq!(placement.conversion_target(ConversionBackend::Vaapi).unwrap().adapter.index, 0);
+        assert_eq!(placement.conversion_target(ConversionBackend::Cpu), None);
+    }
+}
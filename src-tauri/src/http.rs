@@ -0,0 +1,366 @@
+//! Minimal HTTP/1.1 helpers for the app's local network endpoints.
+//!
+//! The app only serves a handful of simple endpoints without request
+//! bodies, so a tiny request parser over tokio sockets keeps the dependency
+//! footprint small. WebSocket support is limited to what pushing events
+//! needs: the handshake and unfragmented frames.
+
+use base64::Engine;
+use sha1::{Digest, Sha1};
+use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Maximum accepted size of the request line and headers.
+const MAX_HEADER_BYTES: usize = 8192;
+
+/// How long a client has to send the request line and headers.
+const HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Most clients a server keeps connected at once.
+const MAX_CLIENTS: usize = 32;
+
+/// GUID appended to a client's key to accept a WebSocket handshake (RFC 6455).
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Largest client WebSocket frame accepted.
+const MAX_CLIENT_FRAME_BYTES: u64 = 4096;
+
+/// A parsed HTTP request head.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    /// Request path without the query string
+    pub path: String,
+    /// Decoded query parameters, in order
+    pub query: Vec<(String, String)>,
+    /// Headers, with lowercase names
+    pub headers: Vec<(String, String)>,
+}
+
+impl Request {
+    /// The first query parameter named `key`.
+    pub fn query(&self, key: &str) -> Option<&str> {
+        self.query.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    /// The first header named `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_ascii_lowercase();
+        self.headers.iter().find(|(k, _)| *k == name).map(|(_, v)| v.as_str())
+    }
+
+    /// Whether the client asks to upgrade the connection to a WebSocket.
+    pub fn is_websocket(&self) -> bool {
+        self.header("upgrade").is_some_and(|v| v.eq_ignore_ascii_case("websocket"))
+            && self.header("sec-websocket-key").is_some()
+    }
+}
+
+/// Connections a server allows at once; see [`accept`].
+pub fn client_limit() -> Arc<Semaphore> {
+    Arc::new(Semaphore::new(MAX_CLIENTS))
+}
+
+/// Accept a connection once one of `limit`'s slots is free. The slot is
+/// held until the returned permit is dropped with the connection.
+pub async fn accept(
+    listener: &TcpListener,
+    limit: &Arc<Semaphore>,
+) -> std::io::Result<(TcpStream, OwnedSemaphorePermit)> {
+    let permit = limit.clone().acquire_owned().await.expect("client limit is never closed");
+    let (stream, _addr) = listener.accept().await?;
+    Ok((stream, permit))
+}
+
+/// Read and parse a request from the stream.
+///
+/// Returns `Ok(None)` if the client closed the connection before sending a
+/// request, and an error if it doesn't finish the headers within
+/// [`HEADER_TIMEOUT`].
+pub async fn read_request<S: AsyncRead + Unpin>(stream: &mut S) -> std::io::Result<Option<Request>> {
+    tokio::time::timeout(HEADER_TIMEOUT, read_head(stream))
+        .await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out reading request headers"))?
+}
+
+async fn read_head<S: AsyncRead + Unpin>(stream: &mut S) -> std::io::Result<Option<Request>> {
+    let mut buf = Vec::with_capacity(1024);
+    let mut chunk = [0u8; 1024];
+
+    loop {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if buf.windows(4).any(|w| w == b"\r\n\r\n") {
+            break;
+        }
+        if buf.len() > MAX_HEADER_BYTES {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "request headers too large",
+            ));
+        }
+    }
+
+    Ok(parse_request(&String::from_utf8_lossy(&buf)))
+}
+
+/// Parse the request line and headers.
+fn parse_request(text: &str) -> Option<Request> {
+    let mut lines = text.lines();
+    let mut request_line = lines.next()?.split_whitespace();
+    let method = request_line.next()?.to_string();
+    let target = request_line.next()?;
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect();
+    let headers = lines
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+
+    Some(Request {
+        method,
+        path: path.to_string(),
+        query,
+        headers,
+    })
+}
+
+/// Decode `%XX` escapes and `+` in a query component.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (_, Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+            (b'+', None) => decoded.push(b' '),
+            (byte, None) => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Write a complete response and close the exchange.
+pub async fn write_response<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    status: u16,
+    content_type: &str,
+    extra_headers: &[(&str, &str)],
+    body: &[u8],
+) -> std::io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        status,
+        status_text(status),
+        content_type,
+        body.len()
+    );
+    for (key, value) in extra_headers {
+        head.push_str(&format!("{}: {}\r\n", key, value));
+    }
+    head.push_str("\r\n");
+
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.flush().await
+}
+
+/// Reason phrase for the status codes we use.
+fn status_text(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+/// The `Sec-WebSocket-Accept` value answering a client's `Sec-WebSocket-Key`.
+fn websocket_accept(key: &str) -> String {
+    let digest = Sha1::new()
+        .chain_update(key.trim().as_bytes())
+        .chain_update(WEBSOCKET_GUID.as_bytes())
+        .finalize();
+    base64::engine::general_purpose::STANDARD.encode(digest)
+}
+
+/// Accept a WebSocket upgrade requested by `request`.
+pub async fn accept_websocket<S: AsyncWrite + Unpin>(stream: &mut S, request: &Request) -> std::io::Result<()> {
+    let key = request.header("sec-websocket-key").unwrap_or_default();
+    let head = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        websocket_accept(key)
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.flush().await
+}
+
+/// WebSocket frame types.
+pub mod opcode {
+    pub const TEXT: u8 = 0x1;
+    pub const CLOSE: u8 = 0x8;
+    pub const PING: u8 = 0x9;
+    pub const PONG: u8 = 0xA;
+}
+
+/// Frame `payload` as an unmasked, unfragmented WebSocket frame.
+fn websocket_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Frame `payload` as a frame from a WebSocket client, which are masked
+/// with `mask`.
+pub fn client_websocket_frame(opcode: u8, payload: &[u8], mask: [u8; 4]) -> Vec<u8> {
+    let masked: Vec<u8> = payload.iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4]).collect();
+    let mut frame = websocket_frame(opcode, &masked);
+    // The mask goes after the length, flagged in its first byte
+    frame[1] |= 0x80;
+    let head_len = frame.len() - masked.len();
+    frame.splice(head_len..head_len, mask);
+    frame
+}
+
+/// Send a WebSocket frame.
+pub async fn write_websocket_frame<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    opcode: u8,
+    payload: &[u8],
+) -> std::io::Result<()> {
+    stream.write_all(&websocket_frame(opcode, payload)).await?;
+    stream.flush().await
+}
+
+/// Read a frame sent by a WebSocket client. Returns its opcode and unmasked
+/// payload.
+pub async fn read_websocket_frame<S: AsyncRead + Unpin>(stream: &mut S) -> std::io::Result<(u8, Vec<u8>)> {
+    let mut head = [0u8; 2];
+    stream.read_exact(&mut head).await?;
+    let mut len = (head[1] & 0x7F) as u64;
+    if len == 126 {
+        let mut bytes = [0u8; 2];
+        stream.read_exact(&mut bytes).await?;
+        len = u16::from_be_bytes(bytes) as u64;
+    } else if len == 127 {
+        let mut bytes = [0u8; 8];
+        stream.read_exact(&mut bytes).await?;
+        len = u64::from_be_bytes(bytes);
+    }
+    if len > MAX_CLIENT_FRAME_BYTES {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "WebSocket frame too large",
+        ));
+    }
+
+    // Client frames are always masked
+    let mut mask = [0u8; 4];
+    if head[1] & 0x80 != 0 {
+        stream.read_exact(&mut mask).await?;
+    }
+    let mut payload = vec![0u8; len as usize];
+    stream.read_exact(&mut payload).await?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok((head[0] & 0x0F, payload))
+}
+
+/// Best-effort detection of this machine's LAN address.
+///
+/// Connecting a UDP socket only selects a route; no packets are sent.
+pub fn local_ip() -> IpAddr {
+    UdpSocket::bind("0.0.0.0:0")
+        .and_then(|socket| {
+            socket.connect("192.0.2.1:80")?;
+            socket.local_addr()
+        })
+        .map(|addr| addr.ip())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let request =
+            parse_request("GET /index.m3u8?token=abc HTTP/1.1\r\nHost: example\r\nRange: bytes=0-\r\n\r\n")
+                .unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/index.m3u8");
+        assert_eq!(request.query("token"), Some("abc"));
+        assert_eq!(request.header("range"), Some("bytes=0-"));
+        assert!(!request.is_websocket());
+
+        let request = parse_request("POST /marker?label=Q%26A+intro HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(request.query("label"), Some("Q&A intro"));
+    }
+
+    #[test]
+    fn test_websocket_handshake() {
+        // Sample handshake from RFC 6455
+        assert_eq!(websocket_accept("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+        assert_eq!(websocket_frame(opcode::TEXT, b"hi"), [0x81, 2, b'h', b'i']);
+        assert_eq!(websocket_frame(opcode::TEXT, &[0; 200])[..4], [0x81, 126, 0, 200]);
+    }
+
+    #[tokio::test]
+    async fn test_read_websocket_frame() {
+        // A masked "Hello" from RFC 6455
+        let frame: &[u8] = &[0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58];
+        let (opcode, payload) = read_websocket_frame(&mut &frame[..]).await.unwrap();
+        assert_eq!(opcode, opcode::TEXT);
+        assert_eq!(payload, b"Hello");
+        assert_eq!(client_websocket_frame(opcode::TEXT, b"Hello", [0x37, 0xfa, 0x21, 0x3d]), frame);
+    }
+
+    #[test]
+    fn test_parse_request_invalid() {
+        assert!(parse_request("").is_none());
+    }
+}
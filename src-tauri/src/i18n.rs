@@ -0,0 +1,162 @@
+//! Translations of the text the backend shows.
+//!
+//! The tray menu, screen reader announcements and common errors come from
+//! Rust rather than the frontend, so they're translated here with Fluent.
+//! Messages live in `locales/<locale>.ftl`, built into the app. `en-US` has
+//! every message and stands in for any a translation lacks. The locale is
+//! the system's until the frontend sets the one it displays.
+
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource};
+use std::sync::{OnceLock, RwLock};
+use unic_langid::LanguageIdentifier;
+
+/// Translations, by locale.
+const LOCALES: &[(&str, &str)] = &[
+    ("en-US", include_str!("../locales/en-US.ftl")),
+    ("de", include_str!("../locales/de.ftl")),
+];
+
+/// Locale used for anything not translated.
+const FALLBACK: &str = "en-US";
+
+/// Messages in one locale, with the fallback's behind them.
+struct Localizer {
+    locale: &'static str,
+    bundle: FluentBundle<FluentResource>,
+    fallback: FluentBundle<FluentResource>,
+}
+
+impl Localizer {
+    /// Load the messages of `locale`, one of [`LOCALES`].
+    fn new(locale: &'static str) -> Self {
+        Self {
+            locale,
+            bundle: bundle(locale),
+            fallback: bundle(FALLBACK),
+        }
+    }
+
+    /// The message `id` with `args` filled in, or `id` if there's no such
+    /// message.
+    fn format(&self, id: &str, args: &[(&str, &str)]) -> String {
+        let mut fluent_args = FluentArgs::new();
+        for (name, value) in args {
+            fluent_args.set(*name, *value);
+        }
+        for bundle in [&self.bundle, &self.fallback] {
+            let Some(pattern) = bundle.get_message(id).and_then(|message| message.value()) else {
+                continue;
+            };
+            let mut errors = Vec::new();
+            let text = bundle.format_pattern(pattern, Some(&fluent_args), &mut errors);
+            if !errors.is_empty() {
+                tracing::error!(target: "I18n", "Errors formatting {} in {}: {:?}", id, self.locale, errors);
+            }
+            return text.into_owned();
+        }
+        tracing::info!(target: "I18n", "No message {}", id);
+        id.to_string()
+    }
+}
+
+/// A bundle of the messages of `locale`, one of [`LOCALES`].
+fn bundle(locale: &'static str) -> FluentBundle<FluentResource> {
+    let source = LOCALES.iter().find(|(name, _)| *name == locale).map(|(_, source)| *source).unwrap_or("");
+    let id: LanguageIdentifier = locale.parse().unwrap_or_default();
+    let mut bundle = FluentBundle::new_concurrent(vec![id]);
+    // Isolation marks would end up in tray menus and speech
+    bundle.set_use_isolating(false);
+    let resource = FluentResource::try_new(source.to_string()).unwrap_or_else(|(resource, errors)| {
+        tracing::error!(target: "I18n", "Errors parsing {}: {:?}", locale, errors);
+        resource
+    });
+    if let Err(errors) = bundle.add_resource(resource) {
+        tracing::error!(target: "I18n", "Errors loading {}: {:?}", locale, errors);
+    }
+    bundle
+}
+
+/// The available locale that best matches `requested`, a BCP 47 tag like
+/// `de-AT`: the same tag, or the same language.
+fn negotiate(requested: &str) -> Option<&'static str> {
+    let requested: LanguageIdentifier = requested.replace('_', "-").parse().ok()?;
+    let available = || LOCALES.iter().map(|(name, _)| *name);
+    available()
+        .find(|name| name.parse::<LanguageIdentifier>().is_ok_and(|id| id == requested))
+        .or_else(|| {
+            available().find(|name| {
+                name.parse::<LanguageIdentifier>()
+                    .is_ok_and(|id| id.language == requested.language)
+            })
+        })
+}
+
+/// The system's locale, if it's one there are translations for.
+fn system_locale() -> &'static str {
+    // Tests expect the fallback's text, whatever the machine's locale
+    if cfg!(test) {
+        return FALLBACK;
+    }
+    sys_locale::get_locale()
+        // POSIX locales carry an encoding, like de_DE.UTF-8
+        .and_then(|locale| negotiate(locale.split('.').next().unwrap_or_default()))
+        .unwrap_or(FALLBACK)
+}
+
+fn localizer() -> &'static RwLock<Localizer> {
+    static LOCALIZER: OnceLock<RwLock<Localizer>> = OnceLock::new();
+    LOCALIZER.get_or_init(|| RwLock::new(Localizer::new(system_locale())))
+}
+
+/// Show text in the locale closest to `requested`, returning the one chosen.
+/// Locales without translations fall back to `en-US`.
+pub fn set_locale(requested: &str) -> String {
+    let locale = negotiate(requested).unwrap_or(FALLBACK);
+    let mut localizer = localizer().write().unwrap();
+    if localizer.locale != locale {
+        *localizer = Localizer::new(locale);
+        tracing::info!(target: "I18n", "Locale set to {}", locale);
+    }
+    locale.to_string()
+}
+
+/// The locale text is shown in.
+pub fn locale() -> String {
+    localizer().read().unwrap().locale.to_string()
+}
+
+/// The message `id` in the current locale.
+pub fn tr(id: &str) -> String {
+    localizer().read().unwrap().format(id, &[])
+}
+
+/// The message `id` in the current locale, with its `{ $name }` arguments
+/// filled in from `args`.
+pub fn tr_args(id: &str, args: &[(&str, &str)]) -> String {
+    localizer().read().unwrap().format(id, args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate("en-US"), Some("en-US"));
+        assert_eq!(negotiate("de_AT"), Some("de"));
+        assert_eq!(negotiate("en-GB"), Some("en-US"));
+        assert_eq!(negotiate("ja"), None);
+    }
+
+    #[test]
+    fn test_format() {
+        let german = Localizer::new("de");
+        assert_eq!(german.format("tray-quit", &[]), "Beenden");
+        assert_eq!(
+            german.format("announce-failed", &[("error", "Disk full")]),
+            "Aufnahme fehlgeschlagen: Disk full"
+        );
+        assert_eq!(german.format("no-such-message", &[]), "no-such-message");
+    }
+}
@@ -0,0 +1,230 @@
+//! Pausing recordings while nobody's at the computer.
+//!
+//! Long working-session captures pick up stretches where the user stepped
+//! away. With idle pause on, a recording that has had no keyboard or mouse
+//! input and no change on screen for a while is paused, and resumed as soon
+//! as either comes back, so the dead air is left out of the file. Only
+//! pauses made this way are resumed automatically; a recording the user
+//! paused stays paused.
+//!
+//! Screen changes are spotted by the encoder as it processes frames, like
+//! motion-triggered recording. Input idle time comes from the OS: Windows
+//! and macOS report it, and on Linux it's read from GNOME's idle monitor;
+//! elsewhere only the screen counts.
+
+use crate::state::RecordingPhase;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+
+/// Event emitted with whether the recording was paused (`true`) or resumed
+/// (`false`) for being idle.
+pub const IDLE_EVENT: &str = "recording-idle";
+
+/// How often the recording is checked for activity.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Shortest idle time allowed, so pauses don't chop up ordinary work.
+const MIN_IDLE_SECS: u32 = 10;
+
+/// Settings for pausing idle recordings.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdlePauseSettings {
+    pub enabled: bool,
+    /// Pause after this many seconds without input or screen changes
+    pub idle_secs: u32,
+}
+
+impl Default for IdlePauseSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            idle_secs: 60,
+        }
+    }
+}
+
+impl IdlePauseSettings {
+    /// Check that the settings are usable.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.idle_secs < MIN_IDLE_SECS {
+            return Err(format!("The idle time must be at least {} seconds", MIN_IDLE_SECS));
+        }
+        Ok(())
+    }
+
+    /// Whether a recording whose input and screen have been idle for
+    /// `input` and `screen` is idle. Unknown input idle time goes by the
+    /// screen alone.
+    fn is_idle(&self, input: Option<Duration>, screen: Duration) -> bool {
+        let idle = input.map_or(screen, |input| input.min(screen));
+        idle >= Duration::from_secs(self.idle_secs as u64)
+    }
+}
+
+/// How long since the last keyboard or mouse input, if the OS says.
+async fn input_idle_time() -> Option<Duration> {
+    platform::input_idle_time().await
+}
+
+/// Pause recordings while they're idle and resume them on activity.
+///
+/// Runs for the lifetime of the app.
+pub async fn run(app: AppHandle) {
+    use tokio::sync::broadcast::error::RecvError;
+
+    let manager = app.state::<AppState>().recording_manager.clone();
+    let mut phases = manager.lock().await.subscribe_phase_events();
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    // Settings of the current recording, while it's watched
+    let mut watching: Option<IdlePauseSettings> = None;
+    // Whether the current pause was made here
+    let mut auto_paused = false;
+    loop {
+        tokio::select! {
+            phase = phases.recv() => match phase {
+                Ok(RecordingPhase::Recording) => {
+                    if watching.is_none() {
+                        let settings = manager.lock().await.get_encoder_settings().await.idle_pause;
+                        watching = settings.enabled.then_some(settings);
+                    }
+                    // Resumed, here or by the user
+                    auto_paused = false;
+                }
+                Ok(RecordingPhase::Paused) => {}
+                Ok(_) => {
+                    watching = None;
+                    auto_paused = false;
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            },
+            _ = interval.tick() => {
+                let Some(settings) = watching else {
+                    continue;
+                };
+                let input = input_idle_time().await;
+                let manager = manager.lock().await;
+                let Some(stats) = manager.stats_collector().await else {
+                    continue;
+                };
+                let paused = manager.is_paused().await;
+                if paused && !auto_paused {
+                    continue;
+                }
+                let idle = settings.is_idle(input, stats.since_screen_change());
+                if idle && !paused {
+                    tracing::info!(target: "Idle", "No activity for {}s, pausing the recording", settings.idle_secs);
+                    match manager.pause_recording().await {
+                        Ok(()) => {
+                            auto_paused = true;
+                            let _ = app.emit(IDLE_EVENT, true);
+                        }
+                        Err(e) => tracing::error!(target: "Idle", "Failed to pause: {}", e),
+                    }
+                } else if !idle && paused {
+                    tracing::info!(target: "Idle", "Activity, resuming the recording");
+                    match manager.resume_recording().await {
+                        Ok(()) => {
+                            auto_paused = false;
+                            let _ = app.emit(IDLE_EVENT, false);
+                        }
+                        Err(e) => tracing::error!(target: "Idle", "Failed to resume: {}", e),
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::time::Duration;
+    use windows::Win32::System::SystemInformation::GetTickCount;
+    use windows::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
+
+    pub async fn input_idle_time() -> Option<Duration> {
+        let mut info = LASTINPUTINFO {
+            cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+            dwTime: 0,
+        };
+        if !unsafe { GetLastInputInfo(&mut info) }.as_bool() {
+            return None;
+        }
+        // Both tick counts wrap around every 49.7 days
+        let idle_ms = unsafe { GetTickCount() }.wrapping_sub(info.dwTime);
+        Some(Duration::from_millis(idle_ms as u64))
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::time::Duration;
+
+    /// `kCGEventSourceStateCombinedSessionState`
+    const COMBINED_SESSION_STATE: i32 = 0;
+    /// `kCGAnyInputEventType`
+    const ANY_INPUT_EVENT: u32 = !0;
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGEventSourceSecondsSinceLastEventType(state: i32, event_type: u32) -> f64;
+    }
+
+    pub async fn input_idle_time() -> Option<Duration> {
+        let secs = unsafe { CGEventSourceSecondsSinceLastEventType(COMBINED_SESSION_STATE, ANY_INPUT_EVENT) };
+        Duration::try_from_secs_f64(secs).ok()
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::time::Duration;
+
+    /// Idle time from GNOME's idle monitor; other desktops don't offer one
+    /// to apps under Wayland.
+    async fn mutter_idle_time() -> zbus::Result<u64> {
+        let connection = zbus::Connection::session().await?;
+        let monitor = zbus::Proxy::new(
+            &connection,
+            "org.gnome.Mutter.IdleMonitor",
+            "/org/gnome/Mutter/IdleMonitor/Core",
+            "org.gnome.Mutter.IdleMonitor",
+        )
+        .await?;
+        monitor.call("GetIdletime", &()).await
+    }
+
+    pub async fn input_idle_time() -> Option<Duration> {
+        mutter_idle_time().await.ok().map(Duration::from_millis)
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+mod platform {
+    use std::time::Duration;
+
+    pub async fn input_idle_time() -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idle_pause_settings() {
+        let settings = IdlePauseSettings { enabled: true, idle_secs: 60 };
+        let secs = Duration::from_secs;
+        assert!(settings.is_idle(Some(secs(90)), secs(60)));
+        // Typing on a still screen, or a video playing untouched
+        assert!(!settings.is_idle(Some(secs(5)), secs(120)));
+        assert!(!settings.is_idle(Some(secs(120)), secs(5)));
+        assert!(settings.is_idle(None, secs(61)));
+        assert!(settings.validate().is_ok());
+        assert!(IdlePauseSettings { idle_secs: 2, ..settings }.validate().is_err());
+    }
+}
@@ -0,0 +1,561 @@
+//! Input-event track saved alongside recordings.
+//!
+//! With the input track on, the mouse and keyboard are sampled while the
+//! recording runs and the events are written next to the video as
+//! `NAME.input.json`, timed against the video like markers are, so pauses
+//! are left out. Tools can then follow the real interaction, e.g. to zoom
+//! where the user clicked. Positions are relative to the top-left corner of
+//! the captured area, in the desktop's units (see
+//! [`crate::capture::desktop`]); the area is saved with them. Keystrokes are
+//! only timed, never identified. Clicks can also be written as an EDL of
+//! markers (`NAME.input.edl`) for editors to import.
+//!
+//! The OS is polled rather than hooked. Windows and macOS report the
+//! pointer and keys anywhere; Linux needs X11 (or XWayland, which only sees
+//! input over X11 windows), as Wayland doesn't let apps watch input.
+
+use crate::avsync::SyncClock;
+use crate::capture::desktop::DesktopLayout;
+use crate::capture::{list_window_bounds, CaptureTarget, WindowBounds};
+use crate::encryption;
+use crate::state::RecordingPhase;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+/// How often the mouse and keyboard are sampled.
+const POLL_INTERVAL: Duration = Duration::from_millis(16);
+
+/// Settings for the input track.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputTrackSettings {
+    pub enabled: bool,
+    /// Include when keys were pressed
+    pub keystrokes: bool,
+    /// Also write the clicks as EDL markers
+    pub edl: bool,
+}
+
+impl Default for InputTrackSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            keystrokes: true,
+            edl: false,
+        }
+    }
+}
+
+/// A mouse button.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Button {
+    Left,
+    Right,
+    Middle,
+}
+
+impl Button {
+    const ALL: [Button; 3] = [Button::Left, Button::Right, Button::Middle];
+
+    /// The button's bit in [`InputState::buttons`].
+    fn bit(self) -> u8 {
+        match self {
+            Button::Left => 1,
+            Button::Right => 2,
+            Button::Middle => 4,
+        }
+    }
+}
+
+/// Something the user did, `t` seconds into the video.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum InputEvent {
+    Move { t: f64, x: i32, y: i32 },
+    Down { t: f64, button: Button, x: i32, y: i32 },
+    Up { t: f64, button: Button, x: i32, y: i32 },
+    Key { t: f64 },
+}
+
+/// The mouse and keyboard at one moment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputState {
+    /// Pointer position on the desktop
+    pub position: (i32, i32),
+    /// Buttons held, as [`Button::bit`]s
+    pub buttons: u8,
+    /// Keys pressed since sampling started
+    pub key_presses: u64,
+}
+
+/// The input-event track of a recording, as saved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputTrack {
+    /// What was captured
+    pub source: Option<CaptureTarget>,
+    /// Desktop area positions are relative to, if it could be found
+    pub area: Option<WindowBounds>,
+    pub events: Vec<InputEvent>,
+}
+
+/// Turns input samples into events.
+struct TrackBuilder {
+    origin: (i32, i32),
+    keystrokes: bool,
+    last: Option<InputState>,
+    events: Vec<InputEvent>,
+}
+
+impl TrackBuilder {
+    fn new(area: Option<WindowBounds>, keystrokes: bool) -> Self {
+        Self {
+            origin: area.map_or((0, 0), |area| (area.x, area.y)),
+            keystrokes,
+            last: None,
+            events: Vec::new(),
+        }
+    }
+
+    /// Add the events between the last sample and `state`, taken `t` seconds
+    /// into the video. The first sample only sets where things start.
+    fn update(&mut self, t: f64, state: InputState) {
+        let (x, y) = (state.position.0 - self.origin.0, state.position.1 - self.origin.1);
+        let Some(last) = self.last.replace(state) else {
+            self.events.push(InputEvent::Move { t, x, y });
+            return;
+        };
+        if state.position != last.position {
+            self.events.push(InputEvent::Move { t, x, y });
+        }
+        for button in Button::ALL {
+            let (was, is) = (last.buttons & button.bit() != 0, state.buttons & button.bit() != 0);
+            match (was, is) {
+                (false, true) => self.events.push(InputEvent::Down { t, button, x, y }),
+                (true, false) => self.events.push(InputEvent::Up { t, button, x, y }),
+                _ => {}
+            }
+        }
+        if self.keystrokes {
+            let presses = state.key_presses.saturating_sub(last.key_presses);
+            self.events.extend((0..presses).map(|_| InputEvent::Key { t }));
+        }
+    }
+}
+
+/// Samples input for the current recording on a thread of its own.
+struct Sampler {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<TrackBuilder>,
+}
+
+impl Sampler {
+    fn start(clock: Arc<SyncClock>, area: Option<WindowBounds>, settings: InputTrackSettings) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let thread = std::thread::spawn(move || {
+            let mut builder = TrackBuilder::new(area, settings.keystrokes);
+            let mut poller = match platform::Poller::new() {
+                Ok(poller) => poller,
+                Err(e) => {
+                    tracing::warn!(target: "Input", "Can't watch the mouse and keyboard: {}", e);
+                    return builder;
+                }
+            };
+            while !stopped.load(Ordering::Relaxed) {
+                // Paused time is left out of the video, and so of the track
+                if !clock.pause().is_paused() {
+                    if let Some(state) = poller.poll() {
+                        builder.update(clock.video_time().as_secs_f64(), state);
+                    }
+                }
+                std::thread::sleep(POLL_INTERVAL);
+            }
+            builder
+        });
+        Self { stop, thread }
+    }
+
+    fn finish(self) -> Vec<InputEvent> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.thread.join().map(|builder| builder.events).unwrap_or_default()
+    }
+}
+
+/// A recording's input track and how to save it.
+struct Pending {
+    track: InputTrack,
+    settings: InputTrackSettings,
+    /// Frame rate of the recording, for EDL timecodes
+    fps: u32,
+}
+
+/// Where `target` is on the desktop.
+fn captured_area(target: &CaptureTarget) -> Option<WindowBounds> {
+    let layout = DesktopLayout::current();
+    match target {
+        CaptureTarget::Display { monitor_id, .. } => Some(layout.monitor_bounds(layout.monitor(monitor_id)?)),
+        CaptureTarget::Region { region } => layout.region_bounds(region),
+        CaptureTarget::Window { window_handle } => list_window_bounds()
+            .ok()?
+            .into_iter()
+            .find(|(window, _)| window.handle == *window_handle)
+            .map(|(_, bounds)| bounds),
+    }
+}
+
+/// Save `track` next to `video`, with the clicks as EDL markers at `fps` if
+/// `edl`.
+fn save(video: &Path, track: &InputTrack, edl: bool, fps: u32) -> Result<(), String> {
+    let json = serde_json::to_string(track).map_err(|e| e.to_string())?;
+    let path = video.with_extension("input.json");
+    std::fs::write(&path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    if edl {
+        let title = video.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        let path = video.with_extension("input.edl");
+        std::fs::write(&path, click_edl(&title, &track.events, fps))
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+/// An EDL with a marker at every click, in the form DaVinci Resolve and
+/// other editors import markers from.
+fn click_edl(title: &str, events: &[InputEvent], fps: u32) -> String {
+    let fps = fps.max(1) as u64;
+    let timecode = |frame: u64| {
+        let secs = frame / fps;
+        format!("{:02}:{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60, frame % fps)
+    };
+    let mut out = format!("TITLE: {}\nFCM: NON-DROP FRAME\n\n", title);
+    let clicks = events.iter().filter_map(|event| match event {
+        InputEvent::Down { t, button, x, y } => Some((*t, *button, *x, *y)),
+        _ => None,
+    });
+    for (i, (t, button, x, y)) in clicks.enumerate() {
+        let frame = (t * fps as f64).round() as u64;
+        let (start, end) = (timecode(frame), timecode(frame + 1));
+        out.push_str(&format!(
+            "{:03}  001      V     C        {} {} {} {}\n |C:ResolveColorBlue |M:{:?} click at {},{} |D:1\n\n",
+            i + 1,
+            start,
+            end,
+            start,
+            end,
+            button,
+            x,
+            y
+        ));
+    }
+    out
+}
+
+/// Record the input track of recordings made with it on.
+///
+/// Runs for the lifetime of the app.
+pub async fn run(app: AppHandle) {
+    use tokio::sync::broadcast::error::RecvError;
+
+    let manager = app.state::<AppState>().recording_manager.clone();
+    let mut phases = manager.lock().await.subscribe_phase_events();
+    // The track being recorded, then waiting for the file to be saved
+    let mut sampling: Option<(Sampler, Pending)> = None;
+    let mut finished: Option<Pending> = None;
+    loop {
+        match phases.recv().await {
+            Ok(RecordingPhase::Recording) if sampling.is_none() => {
+                let manager = manager.lock().await;
+                let settings = manager.get_encoder_settings().await;
+                if !settings.input_track.enabled {
+                    continue;
+                }
+                let source = manager.current_target().await;
+                let area = source.as_ref().and_then(captured_area);
+                let sampler = Sampler::start(manager.clock().await, area, settings.input_track);
+                let track = InputTrack { source, area, events: Vec::new() };
+                sampling = Some((sampler, Pending { track, settings: settings.input_track, fps: settings.fps }));
+            }
+            Ok(RecordingPhase::Finalizing) => {
+                if let Some((sampler, mut pending)) = sampling.take() {
+                    pending.track.events = sampler.finish();
+                    finished = Some(pending);
+                }
+            }
+            Ok(RecordingPhase::Done { file_path: Some(path), .. }) => {
+                let Some(Pending { track, settings, fps }) = finished.take() else {
+                    continue;
+                };
+                let video = Path::new(&path);
+                // It would sit unencrypted next to the recording
+                if encryption::is_encrypted(video) {
+                    tracing::info!(target: "Input", "Input tracks aren't saved for encrypted recordings");
+                    continue;
+                }
+                // A continued recording already has the track of its first part
+                if video.with_extension("input.json").exists() {
+                    tracing::info!(target: "Input", "{} already has an input track; not replacing it", video.display());
+                    continue;
+                }
+                if let Err(e) = save(video, &track, settings.edl, fps) {
+                    tracing::error!(target: "Input", "{}", e);
+                }
+            }
+            Ok(RecordingPhase::Done { .. } | RecordingPhase::Failed { .. } | RecordingPhase::Idle) => {
+                if let Some((sampler, _)) = sampling.take() {
+                    sampler.finish();
+                }
+                finished = None;
+            }
+            Ok(_) => {}
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use super::InputState;
+    use windows::Win32::Foundation::POINT;
+    use windows::Win32::UI::Input::KeyboardAndMouse::GetAsyncKeyState;
+    use windows::Win32::UI::WindowsAndMessaging::GetCursorPos;
+
+    /// Virtual key codes of the mouse buttons, in [`super::Button`] bit order.
+    const BUTTON_KEYS: [i32; 3] = [0x01, 0x02, 0x04];
+
+    pub struct Poller {
+        keys_down: [bool; 256],
+        key_presses: u64,
+    }
+
+    impl Poller {
+        pub fn new() -> Result<Self, String> {
+            Ok(Self { keys_down: [false; 256], key_presses: 0 })
+        }
+
+        pub fn poll(&mut self) -> Option<InputState> {
+            let mut point = POINT::default();
+            unsafe { GetCursorPos(&mut point) }.ok()?;
+            let held = |key: i32| (unsafe { GetAsyncKeyState(key) } as u16) & 0x8000 != 0;
+            let buttons = BUTTON_KEYS
+                .iter()
+                .enumerate()
+                .filter(|(_, key)| held(**key))
+                .fold(0, |bits, (i, _)| bits | 1 << i);
+            // Everything from backspace up is a key; below are mouse buttons.
+            // Shift, Ctrl and Alt are skipped in favor of their left and
+            // right keys, which go down with them.
+            for key in (0x08..256).filter(|key| !(0x10..=0x12).contains(key)) {
+                let down = held(key as i32);
+                if down && !self.keys_down[key] {
+                    self.key_presses += 1;
+                }
+                self.keys_down[key] = down;
+            }
+            Some(InputState {
+                position: (point.x, point.y),
+                buttons,
+                key_presses: self.key_presses,
+            })
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::InputState;
+    use std::ffi::c_void;
+
+    /// `kCGEventSourceStateCombinedSessionState`
+    const COMBINED_SESSION_STATE: i32 = 0;
+    /// `kCGEventKeyDown`
+    const KEY_DOWN: u32 = 10;
+    /// `kCGMouseButtonLeft`, `kCGMouseButtonRight` and `kCGMouseButtonCenter`,
+    /// in [`super::Button`] bit order
+    const BUTTONS: [u32; 3] = [0, 1, 2];
+
+    #[repr(C)]
+    struct CGPoint {
+        x: f64,
+        y: f64,
+    }
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGEventCreate(source: *const c_void) -> *mut c_void;
+        fn CGEventGetLocation(event: *mut c_void) -> CGPoint;
+        fn CGEventSourceButtonState(state: i32, button: u32) -> bool;
+        fn CGEventSourceCounterForEventType(state: i32, event_type: u32) -> u32;
+        fn CGMainDisplayID() -> u32;
+        fn CGDisplayPixelsWide(display: u32) -> usize;
+        fn CGDisplayBounds(display: u32) -> CGRect;
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFRelease(object: *const c_void);
+    }
+
+    #[repr(C)]
+    struct CGSize {
+        width: f64,
+        height: f64,
+    }
+
+    #[repr(C)]
+    struct CGRect {
+        origin: CGPoint,
+        size: CGSize,
+    }
+
+    pub struct Poller {
+        /// Key-down count when sampling started
+        first_count: u32,
+        /// Physical pixels per point, as the desktop is laid out in pixels
+        scale: f64,
+    }
+
+    impl Poller {
+        pub fn new() -> Result<Self, String> {
+            let scale = unsafe {
+                let display = CGMainDisplayID();
+                let points = CGDisplayBounds(display).size.width;
+                if points > 0.0 {
+                    CGDisplayPixelsWide(display) as f64 / points
+                } else {
+                    1.0
+                }
+            };
+            let first_count = unsafe { CGEventSourceCounterForEventType(COMBINED_SESSION_STATE, KEY_DOWN) };
+            Ok(Self { first_count, scale })
+        }
+
+        pub fn poll(&mut self) -> Option<InputState> {
+            let location = unsafe {
+                let event = CGEventCreate(std::ptr::null());
+                if event.is_null() {
+                    return None;
+                }
+                let location = CGEventGetLocation(event);
+                CFRelease(event);
+                location
+            };
+            let buttons = BUTTONS
+                .iter()
+                .enumerate()
+                .filter(|(_, button)| unsafe { CGEventSourceButtonState(COMBINED_SESSION_STATE, **button) })
+                .fold(0, |bits, (i, _)| bits | 1 << i);
+            let count = unsafe { CGEventSourceCounterForEventType(COMBINED_SESSION_STATE, KEY_DOWN) };
+            Some(InputState {
+                position: ((location.x * self.scale).round() as i32, (location.y * self.scale).round() as i32),
+                buttons,
+                key_presses: count.wrapping_sub(self.first_count) as u64,
+            })
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::InputState;
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{ConnectionExt, KeyButMask, Window};
+    use x11rb::rust_connection::RustConnection;
+
+    /// Pointer buttons 1 to 3 (left, middle, right) as [`super::Button`] bits.
+    const BUTTONS: [(KeyButMask, u8); 3] =
+        [(KeyButMask::BUTTON1, 1), (KeyButMask::BUTTON3, 2), (KeyButMask::BUTTON2, 4)];
+
+    pub struct Poller {
+        connection: RustConnection,
+        root: Window,
+        keys_down: [u8; 32],
+        key_presses: u64,
+    }
+
+    impl Poller {
+        pub fn new() -> Result<Self, String> {
+            let (connection, screen) = x11rb::connect(None).map_err(|e| format!("No X11 display: {}", e))?;
+            let root = connection.setup().roots[screen].root;
+            Ok(Self { connection, root, keys_down: [0; 32], key_presses: 0 })
+        }
+
+        pub fn poll(&mut self) -> Option<InputState> {
+            let pointer = self.connection.query_pointer(self.root).ok()?.reply().ok()?;
+            let keymap = self.connection.query_keymap().ok()?.reply().ok()?;
+            for (was, is) in self.keys_down.iter().zip(keymap.keys) {
+                self.key_presses += (is & !was).count_ones() as u64;
+            }
+            self.keys_down = keymap.keys;
+            let buttons = BUTTONS
+                .iter()
+                .filter(|(mask, _)| pointer.mask.contains(*mask))
+                .fold(0, |bits, (_, bit)| bits | bit);
+            Some(InputState {
+                position: (pointer.root_x as i32, pointer.root_y as i32),
+                buttons,
+                key_presses: self.key_presses,
+            })
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+mod platform {
+    use super::InputState;
+
+    pub struct Poller;
+
+    impl Poller {
+        pub fn new() -> Result<Self, String> {
+            Err("Not supported on this platform".to_string())
+        }
+
+        pub fn poll(&mut self) -> Option<InputState> {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_track_builder() {
+        let area = WindowBounds { x: 1920, y: 0, width: 1280, height: 720 };
+        let mut builder = TrackBuilder::new(Some(area), true);
+        let state = |x, buttons, key_presses| InputState { position: (x, 100), buttons, key_presses };
+        builder.update(0.0, state(2000, 0, 5));
+        builder.update(0.5, state(2000, 0, 5));
+        builder.update(1.0, state(2010, 1, 7));
+        builder.update(1.5, state(2010, 0, 7));
+        assert_eq!(
+            builder.events,
+            [
+                InputEvent::Move { t: 0.0, x: 80, y: 100 },
+                InputEvent::Move { t: 1.0, x: 90, y: 100 },
+                InputEvent::Down { t: 1.0, button: Button::Left, x: 90, y: 100 },
+                InputEvent::Key { t: 1.0 },
+                InputEvent::Key { t: 1.0 },
+                InputEvent::Up { t: 1.5, button: Button::Left, x: 90, y: 100 },
+            ]
+        );
+        assert_eq!(
+            serde_json::to_string(&builder.events[2]).unwrap(),
+            r#"{"type":"down","t":1.0,"button":"left","x":90,"y":100}"#
+        );
+
+        let edl = click_edl("Demo", &builder.events, 30);
+        assert!(edl.starts_with("TITLE: Demo\nFCM: NON-DROP FRAME\n\n"));
+        assert!(edl.contains(
+            "001  001      V     C        00:00:01:00 00:00:01:01 00:00:01:00 00:00:01:01\n |C:ResolveColorBlue |M:Left click at 90,100 |D:1\n"
+        ));
+    }
+}
@@ -0,0 +1,132 @@
+//! Local IPC transport between the app and its helper processes.
+//!
+//! Helpers (the Linux portal picker, the CLI) reach the running app through
+//! a named endpoint: a Unix socket in the user's runtime directory on Linux
+//! and macOS, and a named pipe on Windows. Servers accept connections
+//! through [`IpcListener`] and both sides exchange bytes over an
+//! [`IpcStream`], so the protocols on top don't depend on the platform.
+
+use std::future::Future;
+use std::io;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// A connected, bidirectional IPC stream.
+pub trait IpcStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> IpcStream for T {}
+
+/// Accepts connections on an IPC endpoint.
+pub trait IpcListener: Send {
+    /// Wait for the next client to connect.
+    fn accept(&mut self) -> impl Future<Output = io::Result<Box<dyn IpcStream>>> + Send;
+}
+
+/// Socket path for an endpoint.
+///
+/// In a Flatpak, only `app/<app id>` in the runtime directory is shared with
+/// the host, where the picker runs, so sockets go there instead.
+#[cfg(unix)]
+pub fn socket_path(endpoint: &str) -> std::path::PathBuf {
+    let runtime_dir = std::path::PathBuf::from(
+        std::env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| "/tmp".to_string()),
+    );
+    let dir = match crate::sandbox::flatpak_app_id() {
+        Some(app_id) => runtime_dir.join("app").join(app_id),
+        None => runtime_dir.join("screen-recorder"),
+    };
+    dir.join(format!("{}.sock", endpoint))
+}
+
+/// Pipe name for an endpoint.
+#[cfg(windows)]
+pub fn pipe_name(endpoint: &str) -> String {
+    format!(r"\\.\pipe\screen-recorder-{}", endpoint)
+}
+
+/// Start listening on an endpoint, replacing a stale one left by a previous run.
+#[cfg(unix)]
+pub fn bind(endpoint: &str) -> io::Result<impl IpcListener> {
+    let path = socket_path(endpoint);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let _ = std::fs::remove_file(&path);
+    tokio::net::UnixListener::bind(&path)
+}
+
+/// Start listening on an endpoint.
+#[cfg(windows)]
+pub fn bind(endpoint: &str) -> io::Result<impl IpcListener> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let name = pipe_name(endpoint);
+    // Fail if another instance already owns the pipe
+    let next = ServerOptions::new().first_pipe_instance(true).create(&name)?;
+    Ok(PipeListener { name, next })
+}
+
+/// Connect to an endpoint served by the app.
+#[cfg_attr(not(test), allow(dead_code))]
+pub async fn connect(endpoint: &str) -> io::Result<Box<dyn IpcStream>> {
+    #[cfg(unix)]
+    let stream = tokio::net::UnixStream::connect(socket_path(endpoint)).await?;
+    #[cfg(windows)]
+    let stream = tokio::net::windows::named_pipe::ClientOptions::new().open(pipe_name(endpoint))?;
+    Ok(Box::new(stream))
+}
+
+#[cfg(unix)]
+impl IpcListener for tokio::net::UnixListener {
+    async fn accept(&mut self) -> io::Result<Box<dyn IpcStream>> {
+        let (stream, _addr) = tokio::net::UnixListener::accept(self).await?;
+        Ok(Box::new(stream))
+    }
+}
+
+/// Named pipe server. Each pipe instance serves one client, so a new
+/// instance is created for the next client as each one connects.
+#[cfg(windows)]
+pub struct PipeListener {
+    name: String,
+    next: tokio::net::windows::named_pipe::NamedPipeServer,
+}
+
+#[cfg(windows)]
+impl IpcListener for PipeListener {
+    async fn accept(&mut self) -> io::Result<Box<dyn IpcStream>> {
+        use tokio::net::windows::named_pipe::ServerOptions;
+
+        self.next.connect().await?;
+        let next = ServerOptions::new().create(&self.name)?;
+        Ok(Box::new(std::mem::replace(&mut self.next, next)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_roundtrip() {
+        let endpoint = format!("test-{}", std::process::id());
+        let mut listener = bind(&endpoint).unwrap();
+
+        let server = tokio::spawn(async move {
+            let mut stream = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4];
+            stream.read_exact(&mut buf).await.unwrap();
+            stream.write_all(&buf).await.unwrap();
+        });
+
+        let mut client = connect(&endpoint).await.unwrap();
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+        server.await.unwrap();
+
+        #[cfg(unix)]
+        let _ = std::fs::remove_file(socket_path(&endpoint));
+    }
+}
@@ -0,0 +1,179 @@
+//! Background jobs for long-running post-processing work.
+//!
+//! Each job runs on a blocking thread and reports progress to the frontend
+//! through `job-progress` events. Jobs can be cancelled by ID. The latest
+//! progress of every job started this session is kept, so the frontend can
+//! list jobs and read their results without having caught every event.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter};
+
+/// Event emitted whenever a job's progress or status changes.
+pub const JOB_PROGRESS_EVENT: &str = "job-progress";
+
+/// Lifecycle status of a job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// Progress update for a job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobProgress {
+    pub job_id: u64,
+    /// Kind of job (e.g. "trim")
+    pub kind: String,
+    pub status: JobStatus,
+    /// Completed fraction, 0.0 to 1.0
+    pub progress: f64,
+    /// Output file, once completed
+    pub output_path: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Handle given to a running job for reporting progress and checking cancellation.
+pub struct JobContext {
+    app: AppHandle,
+    job_id: u64,
+    kind: String,
+    cancel: Arc<AtomicBool>,
+    records: Arc<Mutex<BTreeMap<u64, JobProgress>>>,
+}
+
+impl JobContext {
+    /// Cancellation flag, suitable for passing to FFmpeg helpers.
+    pub fn cancel_flag(&self) -> &AtomicBool {
+        &self.cancel
+    }
+
+    /// Whether the job has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    /// Report the completed fraction of the job.
+    pub fn report(&self, progress: f64) {
+        self.emit(JobStatus::Running, progress, None, None);
+    }
+
+    fn emit(&self, status: JobStatus, progress: f64, output_path: Option<String>, error: Option<String>) {
+        let update = JobProgress {
+            job_id: self.job_id,
+            kind: self.kind.clone(),
+            status,
+            progress,
+            output_path,
+            error,
+        };
+        self.records.lock().unwrap().insert(self.job_id, update.clone());
+        if let Err(e) = self.app.emit(JOB_PROGRESS_EVENT, update) {
+            tracing::error!(target: "Jobs", "Failed to emit progress: {}", e);
+        }
+    }
+}
+
+/// Tracks running jobs so they can be cancelled, and the progress of every job.
+#[derive(Default)]
+pub struct JobManager {
+    next_id: AtomicU64,
+    running: Arc<Mutex<HashMap<u64, Arc<AtomicBool>>>>,
+    /// Latest progress of each job, by ID
+    records: Arc<Mutex<BTreeMap<u64, JobProgress>>>,
+}
+
+impl JobManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a job on a blocking thread and return its ID.
+    ///
+    /// The job returns the path of the file it produced.
+    pub fn spawn<F>(&self, app: AppHandle, kind: &str, work: F) -> u64
+    where
+        F: FnOnce(&JobContext) -> Result<PathBuf, String> + Send + 'static,
+    {
+        let job_id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.running.lock().unwrap().insert(job_id, cancel.clone());
+
+        let context = JobContext {
+            app,
+            job_id,
+            kind: kind.to_string(),
+            cancel,
+            records: self.records.clone(),
+        };
+        let running = self.running.clone();
+
+        tokio::task::spawn_blocking(move || {
+            context.report(0.0);
+            let result = work(&context);
+            running.lock().unwrap().remove(&job_id);
+
+            match result {
+                Ok(path) => {
+                    tracing::info!(target: "Jobs", "{} job {} completed: {}", context.kind, job_id, path.display());
+                    context.emit(
+                        JobStatus::Completed,
+                        1.0,
+                        Some(path.to_string_lossy().to_string()),
+                        None,
+                    );
+                }
+                Err(_) if context.is_cancelled() => {
+                    tracing::info!(target: "Jobs", "{} job {} cancelled", context.kind, job_id);
+                    context.emit(JobStatus::Cancelled, 0.0, None, None);
+                }
+                Err(e) => {
+                    tracing::error!(target: "Jobs", "{} job {} failed: {}", context.kind, job_id, e);
+                    context.emit(JobStatus::Failed, 0.0, None, Some(e));
+                }
+            }
+        });
+
+        job_id
+    }
+
+    /// Request cancellation of a running job.
+    pub fn cancel(&self, job_id: u64) -> Result<(), String> {
+        match self.running.lock().unwrap().get(&job_id) {
+            Some(cancel) => {
+                cancel.store(true, Ordering::Relaxed);
+                Ok(())
+            }
+            None => Err(format!("Job {} is not running", job_id)),
+        }
+    }
+
+    /// The latest progress of every job this session, oldest first.
+    pub fn list(&self) -> Vec<JobProgress> {
+        self.records.lock().unwrap().values().cloned().collect()
+    }
+
+    /// The latest progress of a job.
+    pub fn get(&self, job_id: u64) -> Result<JobProgress, String> {
+        self.records
+            .lock()
+            .unwrap()
+            .get(&job_id)
+            .cloned()
+            .ok_or_else(|| format!("Job {} not found", job_id))
+    }
+
+    /// Forget the jobs that have finished, failed or been cancelled.
+    pub fn clear_finished(&self) {
+        self.records
+            .lock()
+            .unwrap()
+            .retain(|_, job| job.status == JobStatus::Running);
+    }
+}
@@ -0,0 +1,191 @@
+//! Recovering recordings interrupted by a crash.
+//!
+//! Each recording file is noted in a journal in the data directory as its
+//! encoder starts, and taken out once the file is finalized. Entries still
+//! there at the next launch are recordings the app never finished (it
+//! crashed, or the machine lost power), so their files have no trailer or
+//! index. Each is repaired by copying its streams into a new file, which
+//! FFmpeg can do with what was written of a Matroska or WebM file and with
+//! the complete fragments of an MP4, which is written fragmented until it's
+//! finalized for this reason. Repaired files replace the broken ones and are
+//! added to the library flagged as recovered; ones that can't be repaired
+//! are left as they are and reported, so they don't go missing unnoticed.
+
+use crate::config::{load_json, save_json};
+use crate::encoder::ffmpeg::{probe, run_ffmpeg};
+use crate::library::{Library, RecordingEntry};
+use crate::postprocess::derived_path;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const JOURNAL_FILE: &str = "recordings_in_progress.json";
+
+/// Event emitted with the [`RecordingEntry`]s of recordings recovered at launch.
+pub const RECOVERED_EVENT: &str = "recordings-recovered";
+
+/// Event emitted with the [`Unrecoverable`] recordings found at launch.
+pub const UNRECOVERABLE_EVENT: &str = "recordings-unrecoverable";
+
+/// A recording file being written.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub path: PathBuf,
+    /// When its encoder started (RFC 3339)
+    pub started_at: String,
+}
+
+/// An interrupted recording that couldn't be repaired, left as it was.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Unrecoverable {
+    pub path: PathBuf,
+    pub started_at: String,
+    /// Why it couldn't be repaired
+    pub error: String,
+}
+
+/// What came of repairing interrupted recordings.
+#[derive(Debug, Default)]
+pub struct Recovery {
+    /// Repaired recordings, added to the library
+    pub recovered: Vec<RecordingEntry>,
+    /// Ones still on disk that couldn't be repaired
+    pub unrecoverable: Vec<Unrecoverable>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Journal {
+    recordings: Vec<JournalEntry>,
+}
+
+/// Serializes changes to the journal between encoders.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Change the journal's entries with `change`.
+fn update(change: impl FnOnce(&mut Vec<JournalEntry>)) {
+    let _guard = LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut journal: Journal = load_json(JOURNAL_FILE);
+    change(&mut journal.recordings);
+    if let Err(e) = save_json(JOURNAL_FILE, &journal) {
+        tracing::error!(target: "Journal", "{}", e);
+    }
+}
+
+/// Note that a recording is being written to `path`.
+pub fn begin(path: &Path) {
+    update(|recordings| {
+        recordings.push(JournalEntry {
+            path: path.to_path_buf(),
+            started_at: chrono::Local::now().to_rfc3339(),
+        })
+    });
+}
+
+/// Note that the recording at `path` is finished.
+pub fn end(path: &Path) {
+    update(|recordings| recordings.retain(|entry| entry.path != path));
+}
+
+/// Take the recordings a previous run left unfinished out of the journal.
+/// Call this at launch, before anything is recorded.
+pub fn take_interrupted() -> Vec<JournalEntry> {
+    let mut interrupted = Vec::new();
+    update(|recordings| interrupted = std::mem::take(recordings));
+    interrupted
+}
+
+/// Repair the recordings in `interrupted` and add them to `library`. Ones
+/// that can't be repaired are left as they are.
+///
+/// This runs FFmpeg; call it from a blocking task.
+pub fn recover(interrupted: Vec<JournalEntry>, library: &Arc<tokio::sync::Mutex<Library>>) -> Recovery {
+    let mut recovery = Recovery::default();
+    for entry in interrupted {
+        tracing::warn!(target: "Journal", "Recording {} started {} wasn't finished", entry.path.display(), entry.started_at);
+        let repaired = match repair(&entry.path) {
+            Ok(repaired) => repaired,
+            Err(e) => {
+                tracing::warn!(target: "Journal", "Couldn't recover {}: {}", entry.path.display(), e);
+                if entry.path.exists() {
+                    recovery.unrecoverable.push(Unrecoverable {
+                        path: entry.path,
+                        started_at: entry.started_at,
+                        error: e,
+                    });
+                }
+                continue;
+            }
+        };
+        tracing::info!(target: "Journal", "Recovered {}", repaired.display());
+        match library.blocking_lock().add_recovered(&repaired) {
+            Ok(added) => recovery.recovered.push(added),
+            Err(e) => tracing::error!(target: "Library", "Failed to add recording: {}", e),
+        }
+    }
+    recovery
+}
+
+/// Copy what can be read of the recording at `path` into a new file,
+/// deleting the broken one. Returns the new file.
+fn repair(path: &Path) -> Result<PathBuf, String> {
+    if !path.is_file() {
+        return Err("The file is gone".to_string());
+    }
+    if crate::encryption::is_encrypted(path) {
+        return Err("Encrypted recordings can't be repaired".to_string());
+    }
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    if !["mkv", "webm", "mp4", "mov"].contains(&extension.as_str()) {
+        return Err(format!(".{} recordings can't be repaired", extension));
+    }
+
+    let output = derived_path(path, "recovered");
+    let repaired = run_ffmpeg(repair_args(path, &output)).and_then(|()| match probe(&output) {
+        Ok(info) if info.duration > 0.0 => Ok(()),
+        _ => Err("Nothing could be read from it".to_string()),
+    });
+    if let Err(e) = repaired {
+        // Don't leave a partial file behind
+        let _ = std::fs::remove_file(&output);
+        return Err(e);
+    }
+    if let Err(e) = std::fs::remove_file(path) {
+        tracing::error!(target: "Journal", "Failed to remove {}: {}", path.display(), e);
+    }
+    Ok(output)
+}
+
+/// FFmpeg arguments copying every readable stream of `input` into `output`,
+/// carrying on past the damage at its end.
+fn repair_args(input: &Path, output: &Path) -> Vec<String> {
+    let mut args: Vec<String> = ["-err_detect", "ignore_err", "-i"].map(String::from).to_vec();
+    args.push(input.to_string_lossy().to_string());
+    args.extend(["-map", "0", "-c", "copy"].map(String::from));
+    if output.extension().is_some_and(|ext| ext == "mp4" || ext == "mov") {
+        args.extend(["-movflags", "+faststart"].map(String::from));
+    }
+    args.extend(["-y".to_string(), output.to_string_lossy().to_string()]);
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repair_args() {
+        let args = repair_args(Path::new("/videos/a.mkv"), Path::new("/videos/a_recovered.mkv"));
+        assert_eq!(
+            args,
+            ["-err_detect", "ignore_err", "-i", "/videos/a.mkv", "-map", "0", "-c", "copy", "-y", "/videos/a_recovered.mkv"]
+        );
+        let args = repair_args(Path::new("/videos/a.mp4"), Path::new("/videos/a_recovered.mp4"));
+        assert!(args.windows(2).any(|pair| pair == ["-movflags", "+faststart"]));
+
+        assert_eq!(repair(Path::new("/nonexistent/a.mkv")), Err("The file is gone".to_string()));
+    }
+}
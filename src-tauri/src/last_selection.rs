@@ -0,0 +1,129 @@
+//! The last capture selection, so it can be recorded again without picking
+//! it.
+//!
+//! Window handles don't survive the window closing, let alone a restart, so
+//! windows are remembered by title and application and matched back up
+//! against the open windows; monitors are matched by ID, then by name.
+
+use crate::capture::{CaptureRegion, MonitorInfo, WindowInfo};
+use crate::config::{load_json, save_json};
+use serde::{Deserialize, Serialize};
+
+const SETTINGS_FILE: &str = "last_selection.json";
+
+/// What was last recorded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum LastSelection {
+    Window { title: String, process_name: String },
+    Display { monitor_id: String, monitor_name: String },
+    Region { region: CaptureRegion },
+}
+
+/// The last selection, matched up with what's there now.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum LastTarget {
+    Window { window: WindowInfo },
+    Display { monitor: MonitorInfo },
+    Region { region: CaptureRegion },
+}
+
+/// File contents; `None` until something is recorded.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Saved {
+    selection: Option<LastSelection>,
+}
+
+impl LastSelection {
+    /// Load the last selection, if any.
+    pub fn load() -> Option<Self> {
+        load_json::<Saved>(SETTINGS_FILE).selection
+    }
+
+    /// Remember this as the last selection.
+    pub fn remember(self) {
+        if let Err(e) = save_json(SETTINGS_FILE, &Saved { selection: Some(self) }) {
+            tracing::error!(target: "LastSelection", "Failed to save: {}", e);
+        }
+    }
+
+    /// Match the selection up with the open `windows` and connected
+    /// `monitors`.
+    pub fn resolve(self, windows: &[WindowInfo], monitors: &[MonitorInfo]) -> Result<LastTarget, String> {
+        match self {
+            Self::Window { title, process_name } => find_window(windows, &title, &process_name)
+                .map(|window| LastTarget::Window { window: window.clone() })
+                .ok_or_else(|| format!("\"{}\" is no longer open", title)),
+            Self::Display { monitor_id, monitor_name } => monitors
+                .iter()
+                .find(|m| m.id == monitor_id)
+                .or_else(|| monitors.iter().find(|m| m.name == monitor_name))
+                .map(|monitor| LastTarget::Display { monitor: monitor.clone() })
+                .ok_or_else(|| format!("{} is not connected", monitor_name)),
+            Self::Region { region } => {
+                if monitors.iter().any(|m| m.id == region.monitor_id) {
+                    Ok(LastTarget::Region { region })
+                } else {
+                    Err("The last region's monitor is not connected".to_string())
+                }
+            }
+        }
+    }
+}
+
+/// The open window most likely to be the one last recorded: the same title
+/// in the same application, then a title of that application's that one
+/// contains the other (documents and tabs change the rest), then the
+/// application's only window.
+fn find_window<'a>(windows: &'a [WindowInfo], title: &str, process_name: &str) -> Option<&'a WindowInfo> {
+    let same_app: Vec<&WindowInfo> = windows
+        .iter()
+        .filter(|w| w.process_name.eq_ignore_ascii_case(process_name))
+        .collect();
+    let title = title.to_lowercase();
+    same_app
+        .iter()
+        .find(|w| w.title.to_lowercase() == title)
+        .or_else(|| {
+            same_app.iter().find(|w| {
+                let other = w.title.to_lowercase();
+                !other.is_empty() && !title.is_empty() && (other.contains(&title) || title.contains(&other))
+            })
+        })
+        .or_else(|| (same_app.len() == 1).then(|| &same_app[0]))
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(handle: isize, title: &str, process_name: &str) -> WindowInfo {
+        WindowInfo {
+            handle,
+            title: title.to_string(),
+            process_name: process_name.to_string(),
+            pid: 0,
+            class: String::new(),
+            icon: None,
+            thumbnail: None,
+        }
+    }
+
+    #[test]
+    fn test_find_window() {
+        let windows = [
+            window(1, "notes.txt - Editor", "editor"),
+            window(2, "Inbox - Mail", "mail"),
+            window(3, "Slides", "editor"),
+        ];
+        let found = |title, process| find_window(&windows, title, process).map(|w| w.handle);
+        assert_eq!(found("Slides", "Editor"), Some(3));
+        assert_eq!(found("notes.txt", "editor"), Some(1));
+        // The app's only window, though its title changed
+        assert_eq!(found("Drafts - Mail", "mail"), Some(2));
+        assert_eq!(found("Other", "editor"), None);
+        assert_eq!(found("Slides", "browser"), None);
+    }
+}
@@ -1,15 +1,160 @@
 //! Screen Recorder Tauri application.
 
+mod announce;
+mod avsync;
+mod benchmark;
 mod capture;
+mod captions;
+mod cli;
+mod companion;
+mod config;
+mod control;
+mod countdown;
+#[cfg(target_os = "linux")]
+mod dbus;
+mod devices;
+mod diagnostics;
+mod disk;
+mod display_mode;
+mod dnd;
+mod dynamics;
 mod encoder;
+mod encryption;
+mod environment;
+mod error;
+#[cfg(test)]
+mod golden;
+mod gpu;
+mod http;
+mod i18n;
+mod idle;
+mod input_track;
+// Only the Linux picker uses IPC so far
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+mod ipc;
+mod jobs;
+mod journal;
+mod last_selection;
+mod library;
+mod limits;
+mod logging;
+mod magnifier;
+mod mdns;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod permissions;
+mod postprocess;
+mod power;
+mod preview;
+mod process;
+mod profiles;
+mod recipes;
+mod region_selector;
+mod retention;
+mod sandbox;
+mod scene;
+mod screenshot;
+#[cfg(feature = "scripting")]
+mod scripting;
+mod session;
+mod shutdown;
 mod state;
+mod stats;
+mod tags;
+mod test_signal;
+mod tray;
+mod upload;
+mod virtual_display;
+mod voice;
+mod webhooks;
+mod window_match;
+
+#[cfg(feature = "frame-filters")]
+pub use capture::{CapturedFrame, PixelFormat};
+#[cfg(feature = "frame-filters")]
+pub use encoder::filter::{register_filter, FilterFactory, FrameFilter};
 
-use capture::{list_monitors, list_windows, show_highlight, CaptureRegion, MonitorInfo, WindowInfo};
-use encoder::ensure_ffmpeg_blocking;
-use state::{RecordingManager, RecordingResult, RecordingState};
+use capture::{
+    hide_highlight, list_monitors, list_windows, show_highlight, Capabilities, CaptureRegion, CaptureTarget, ColorSpace, CoordinateSpace, HdrMode, MonitorInfo,
+    WindowBounds, WindowInfo,
+};
+use benchmark::BenchmarkReport;
+use test_signal::TestSignalReport;
+use capture::audio::AudioDeviceMode;
+use capture::desktop::DesktopLayout;
+use capture::exclusion::{ExclusionSettings, WindowRule};
+use capture::game::GameCaptureSettings;
+use capture::mask::MaskStyle;
+use capture::redaction::RedactionRegion;
+use capture::queue::DropPolicy;
+use capture::recovery::WindowLostBehavior;
+use companion::{CompanionInfo, CompanionSettings};
+use control::ControlSettings;
+use environment::DesktopCleanup;
+use error::AppError;
+use encoder::abr::AdaptiveBitrate;
+use encoder::convert::ConversionBackend;
+use encoder::deferred::MemoryBuffer;
+use encoder::hardware::EncoderBackend;
+use encoder::{ensure_ffmpeg_blocking, EncoderSettings};
+use encoder::hls::HlsSettings;
+use encoder::estimate::SizeEstimate;
+use encoder::image::ImageFormat;
+use encoder::motion::MotionSettings;
+use encoder::static_content::StaticContentSettings;
+use idle::IdlePauseSettings;
+use input_track::InputTrackSettings;
+use captions::LiveCaptionSettings;
+use encoder::ndi::NdiSettings;
+use encoder::options::{AdvancedOptions, EncoderCapabilities};
+use encoder::rate::RateControl;
+use encoder::replay::ReplaySettings;
+use encoder::resize::ResolutionChangePolicy;
+use encoder::sink::OutputSettings;
+use encoder::support::EncoderSupport;
+use devices::VideoDevice;
+use disk::DiskSpaceSettings;
+use gpu::GpuAdapter;
+use jobs::{JobManager, JobProgress};
+use last_selection::{LastSelection, LastTarget};
+use library::{Marker, RecordingEntry};
+use limits::{RecordingLimits, StopPoint};
+use magnifier::MagnifierSample;
+use permissions::{Permission, PermissionReport, PermissionStatus};
+use postprocess::concat::ConcatClip;
+use postprocess::export::{CropRect, ExportOptions};
+use postprocess::intermediate::IntermediateCodec;
+use postprocess::loudness::LoudnessTarget;
+use postprocess::remux::Container;
+use postprocess::scrub::ScrubInfo;
+use postprocess::speed::SpeedOptions;
+use postprocess::subtitles::SubtitleOptions;
+use postprocess::thumbnail::ThumbnailInfo;
+use postprocess::trim::TrimMode;
+use power::{PowerPolicy, PowerState};
+use display_mode::DisplayMode;
+use profiles::{Profiles, RecordingProfile};
+use recipes::{Recipe, Recipes};
+use scene::chroma::ChromaKey;
+use scene::layout::{Layout, Layouts};
+use scene::{Scene, SceneSource, Scenes, Transition};
+use region_selector::{AspectRatio, RegionSelectorSettings};
+use retention::{RetentionReport, RetentionSettings};
+use screenshot::ScreenshotResult;
+use session::LockPolicy;
+use state::{RecordingManager, RecordingPhase, RecordingResult};
+use stats::RecordingStats;
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use tauri::State;
+use tauri::{AppHandle, Manager, State};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, ShortcutState};
 use tokio::sync::Mutex;
+use upload::{UploadEntry, UploadQueue, UploadTarget, UploadTargets};
+use tags::TagSettings;
+use webhooks::WebhookSettings;
+use window_match::WindowQuery;
+use voice::VoiceTriggerSettings;
 
 #[cfg(target_os = "linux")]
 use capture::linux;
@@ -17,6 +162,11 @@ use capture::linux;
 /// Application state wrapper.
 pub struct AppState {
     recording_manager: Arc<Mutex<RecordingManager>>,
+    jobs: JobManager,
+    uploads: UploadQueue,
+    profiles: Mutex<Profiles>,
+    scenes: Mutex<Scenes>,
+    layouts: Mutex<Layouts>,
     ffmpeg_ready: bool,
 }
 
@@ -25,173 +175,2414 @@ impl AppState {
         // Initialize FFmpeg at startup (downloads if needed)
         let ffmpeg_ready = match ensure_ffmpeg_blocking() {
             Ok(()) => {
-                println!("FFmpeg initialized successfully");
+                tracing::info!(target: "FFmpeg", "FFmpeg initialized successfully");
                 true
             }
             Err(e) => {
-                eprintln!("Failed to initialize FFmpeg: {}", e);
+                tracing::error!(target: "FFmpeg", "Failed to initialize FFmpeg: {}", e);
                 false
             }
         };
 
-        // Initialize Linux IPC server in a dedicated thread with its own runtime
+        // Serve the picker's IPC endpoint on the app's runtime, where the
+        // server waits for connections until the app shuts down
         #[cfg(target_os = "linux")]
-        {
-            use std::sync::mpsc;
-            let (tx, rx) = mpsc::channel();
-            
-            // Spawn a thread that will run the IPC server for the lifetime of the app
-            std::thread::spawn(move || {
-                let rt = tokio::runtime::Runtime::new().unwrap();
-                rt.block_on(async {
-                    match linux::init_ipc_server().await {
-                        Ok(()) => {
-                            eprintln!("[AppState] Linux IPC server initialized");
-                            tx.send(Ok(())).ok();
-                            // Keep the runtime alive - the IPC server runs in a spawned task
-                            // We need to keep this thread alive so the runtime doesn't drop
-                            loop {
-                                tokio::time::sleep(tokio::time::Duration::from_secs(3600)).await;
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("[AppState] Failed to init Linux IPC server: {}", e);
-                            tx.send(Err(e.clone())).ok();
-                        }
-                    }
-                });
-            });
-            
-            // Wait for IPC server to be ready (with timeout)
-            match rx.recv_timeout(std::time::Duration::from_secs(5)) {
-                Ok(Ok(())) => eprintln!("[AppState] IPC server ready"),
-                Ok(Err(e)) => eprintln!("[AppState] IPC server failed: {}", e),
-                Err(_) => eprintln!("[AppState] Timeout waiting for IPC server"),
-            }
+        match tauri::async_runtime::block_on(linux::init_ipc_server()) {
+            Ok(()) => tracing::info!(target: "AppState", "IPC server ready"),
+            Err(e) => tracing::error!(target: "AppState", "IPC server failed: {}", e),
+        }
+
+        // New recordings use the selected profile's settings
+        let profiles = Profiles::load();
+        let mut encoder_settings = EncoderSettings::default();
+        if let Some(profile) = profiles.selected() {
+            profile.apply(&mut encoder_settings);
         }
 
         Self {
-            recording_manager: Arc::new(Mutex::new(RecordingManager::new())),
+            recording_manager: Arc::new(Mutex::new(
+                RecordingManager::new().with_encoder_settings(encoder_settings),
+            )),
+            jobs: JobManager::new(),
+            uploads: UploadQueue::new(),
+            profiles: Mutex::new(profiles),
+            scenes: Mutex::new(Scenes::load()),
+            layouts: Mutex::new(Layouts::load()),
             ffmpeg_ready,
         }
     }
 }
 
-/// Get list of capturable windows.
+/// Get list of capturable windows, with `thumbnails` of those the platform
+/// can grab.
+#[tauri::command]
+async fn get_windows(thumbnails: Option<bool>) -> Result<Vec<WindowInfo>, String> {
+    if !thumbnails.unwrap_or(false) {
+        return Ok(list_windows());
+    }
+    tokio::task::spawn_blocking(capture::list_windows_with_thumbnails)
+        .await
+        .map_err(|e| format!("Task error: {}", e))
+}
+
+/// Get list of available monitors.
+#[tauri::command]
+fn get_monitors() -> Vec<MonitorInfo> {
+    list_monitors()
+}
+
+/// Get what the capture backend can do on this system, so unsupported
+/// options can be hidden.
+#[tauri::command]
+fn get_capabilities() -> Capabilities {
+    capture::capabilities()
+}
+
+/// Get whether the permissions recording needs have been given.
+#[tauri::command]
+fn get_permissions() -> PermissionReport {
+    permissions::report()
+}
+
+/// Ask for a permission: with the OS's dialog if it hasn't been decided,
+/// or by opening its settings if it's been refused.
+#[tauri::command]
+async fn request_permission(permission: Permission) -> Result<PermissionStatus, String> {
+    tokio::task::spawn_blocking(move || permissions::request(permission))
+        .await
+        .map_err(|e| format!("Task error: {}", e))
+}
+
+/// Get where the recording is in its lifecycle. Changes are also sent as
+/// `recording-phase` events.
+#[tauri::command]
+async fn get_recording_phase(state: State<'_, AppState>) -> Result<RecordingPhase, String> {
+    let manager = state.recording_manager.lock().await;
+    Ok(manager.get_phase())
+}
+
+/// Start recording the specified window.
+///
+/// With `app_audio`, only the audio of the window's application is recorded
+/// alongside it (Windows only).
+#[tauri::command]
+async fn start_recording(
+    window_handle: isize,
+    app_audio: Option<bool>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    if !state.ffmpeg_ready {
+        return Err(AppError::FfmpegUnavailable);
+    }
+    count_down(&app, &state).await?;
+    let manager = state.recording_manager.lock().await;
+    manager.start_recording(window_handle, app_audio.unwrap_or(false)).await?;
+    if let Some(window) = list_windows().into_iter().find(|w| w.handle == window_handle) {
+        LastSelection::Window {
+            title: window.title,
+            process_name: window.process_name,
+        }
+        .remember();
+    }
+    Ok(())
+}
+
+/// Find the open window that best matches an application name and/or text
+/// in its title (see `window_match`).
+#[tauri::command]
+fn find_window(app_name: Option<String>, title: Option<String>) -> Result<WindowInfo, String> {
+    let query = WindowQuery { app: app_name, title };
+    window_match::find_window(&list_windows(), &query).cloned()
+}
+
+/// Start recording the open window that best matches an application name
+/// and/or text in its title, for scripts, which can't know the handle
+/// ahead of time. Returns the window recorded.
+#[tauri::command]
+async fn start_matching_window_recording(
+    app_name: Option<String>,
+    title: Option<String>,
+    app_audio: Option<bool>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<WindowInfo, AppError> {
+    let query = WindowQuery { app: app_name, title };
+    let window = window_match::find_window(&list_windows(), &query)
+        .map_err(AppError::TargetNotFound)?
+        .clone();
+    tracing::info!(
+        target: "Recording",
+        "Matched {:?} to \"{}\" ({}, 0x{:x})",
+        query, window.title, window.process_name, window.handle as usize
+    );
+    start_recording(window.handle, app_audio, app, state).await?;
+    Ok(window)
+}
+
+/// Start recording a screen region.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn start_region_recording(
+    monitor_id: String,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    space: Option<CoordinateSpace>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    if !state.ffmpeg_ready {
+        return Err(AppError::FfmpegUnavailable);
+    }
+    let region = CaptureRegion {
+        monitor_id,
+        x,
+        y,
+        width,
+        height,
+        space: space.unwrap_or_default(),
+    };
+    // Refuse a region that can't be recorded before counting down
+    capture::validate_region(&region)?;
+    count_down(&app, &state).await?;
+
+    let manager = state.recording_manager.lock().await;
+    manager.start_region_recording(region.clone()).await?;
+    RegionSelectorSettings::remember_region(&region);
+    LastSelection::Region { region }.remember();
+    Ok(())
+}
+
+/// Start recording an entire display.
+#[tauri::command]
+async fn start_display_recording(
+    monitor_id: String,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    if !state.ffmpeg_ready {
+        return Err(AppError::FfmpegUnavailable);
+    }
+    count_down(&app, &state).await?;
+
+    // Find the monitor to get its dimensions
+    let monitors = list_monitors();
+    let monitor = monitors
+        .iter()
+        .find(|m| m.id == monitor_id)
+        .ok_or_else(|| AppError::TargetNotFound(i18n::tr_args("error-monitor-not-found", &[("monitor", &monitor_id)])))?;
+
+    let manager = state.recording_manager.lock().await;
+    manager
+        .start_display_recording(monitor_id.clone(), monitor.width, monitor.height)
+        .await?;
+    LastSelection::Display {
+        monitor_id,
+        monitor_name: monitor.name.clone(),
+    }
+    .remember();
+    Ok(())
+}
+
+/// The last window, display, or region recorded, matched up with the open
+/// windows and connected monitors, for recording it again.
+#[tauri::command]
+fn resolve_last_selection() -> Result<LastTarget, String> {
+    LastSelection::load()
+        .ok_or_else(|| i18n::tr("error-nothing-recorded"))?
+        .resolve(&list_windows(), &list_monitors())
+}
+
+/// Count down before a recording starts, publishing each second as a
+/// `countdown` event. Skipped if a recording is already running, so starting
+/// fails straight away.
+async fn count_down(app: &AppHandle, state: &AppState) -> Result<(), String> {
+    use tauri::Emitter;
+
+    if !state.recording_manager.lock().await.get_phase().is_idle() {
+        return Ok(());
+    }
+    countdown::run(|event| {
+        if let Err(e) = app.emit(countdown::COUNTDOWN_EVENT, event) {
+            tracing::error!(target: "Countdown", "Failed to emit countdown: {}", e);
+        }
+        announce::countdown(app, event);
+    })
+    .await
+}
+
+/// Set how many seconds recordings count down before starting; 0 starts
+/// them immediately.
+#[tauri::command]
+fn set_countdown(seconds: u32) -> Result<(), String> {
+    countdown::set_seconds(seconds)
+}
+
+/// Get how many seconds recordings count down before starting.
+#[tauri::command]
+fn get_countdown() -> u32 {
+    countdown::seconds()
+}
+
+/// Show the backend's text (tray menu, announcements, errors) in the
+/// locale closest to `locale`, such as the frontend's `navigator.language`.
+/// Returns the locale chosen.
+#[tauri::command]
+fn set_locale(locale: String) -> String {
+    i18n::set_locale(&locale)
+}
+
+/// Get the locale the backend's text is shown in.
+#[tauri::command]
+fn get_locale() -> String {
+    i18n::locale()
+}
+
+/// Cancel the countdown of a recording that's about to start. The start
+/// command then fails and nothing is recorded.
+#[tauri::command]
+fn cancel_countdown() {
+    countdown::cancel()
+}
+
+/// Stop the current recording and save the file.
+#[tauri::command]
+async fn stop_recording(state: State<'_, AppState>) -> Result<RecordingResult, String> {
+    let manager = state.recording_manager.lock().await;
+    manager.stop_recording().await
+}
+
+/// Get elapsed recording time in seconds.
+#[tauri::command]
+async fn get_elapsed_time(state: State<'_, AppState>) -> Result<u64, String> {
+    let manager = state.recording_manager.lock().await;
+    Ok(manager.get_elapsed_seconds().await)
+}
+
+/// Turn the live preview on or off.
+///
+/// Returns the URL of the MJPEG preview stream when enabled. While recording,
+/// the preview shows the recording; otherwise use `set_preview_source`.
+#[tauri::command]
+async fn set_preview_enabled(enabled: bool, state: State<'_, AppState>) -> Result<Option<String>, String> {
+    let preview = state.recording_manager.lock().await.preview();
+    if enabled {
+        preview.enable().map(Some)
+    } else {
+        preview.disable();
+        Ok(None)
+    }
+}
+
+/// Publish the preview's frames unencoded in shared memory as well, or stop.
+///
+/// Returns the memory-mapped file and the preview server URL serving its
+/// latest frame when turned on. The preview must be enabled.
+#[tauri::command]
+async fn set_preview_shared(
+    enabled: bool,
+    state: State<'_, AppState>,
+) -> Result<Option<preview::SharedPreviewInfo>, String> {
+    let preview = state.recording_manager.lock().await.preview();
+    preview.set_shared(enabled)
+}
+
+/// Choose what the preview shows when not recording, or `None` to stop previewing.
+#[tauri::command]
+async fn set_preview_source(
+    target: Option<CaptureTarget>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let manager = state.recording_manager.lock().await;
+    let preview = manager.preview();
+    match target {
+        Some(_) if !manager.get_phase().is_idle() => {
+            Err("The preview follows the recording while recording".to_string())
+        }
+        Some(target) => preview.start_capture(target),
+        None => {
+            preview.stop_capture();
+            Ok(())
+        }
+    }
+}
+
+/// Get live statistics (fps, dropped frames, bitrate, etc.) for the current recording.
+#[tauri::command]
+async fn get_recording_stats(state: State<'_, AppState>) -> Result<RecordingStats, String> {
+    let manager = state.recording_manager.lock().await;
+    manager.get_stats().await
+}
+
+/// Collect the logs, capture and encoder capabilities, recent stats and
+/// settings into a zip for a bug report, with secrets and the user's name
+/// taken out. Returns the zip's path.
+#[tauri::command]
+async fn create_diagnostics_bundle(state: State<'_, AppState>) -> Result<String, String> {
+    let settings = state.recording_manager.lock().await.get_encoder_settings().await;
+    let settings = serde_json::to_value(settings).map_err(|e| format!("Failed to serialize settings: {}", e))?;
+    let path = tokio::task::spawn_blocking(move || diagnostics::create_bundle(settings))
+        .await
+        .map_err(|e| format!("Task error: {}", e))??;
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// The latest lines from the app's and the portal picker's logs matching
+/// `query`, oldest first.
+#[tauri::command]
+async fn query_logs(query: logging::LogQuery) -> Result<Vec<logging::LogLine>, String> {
+    tokio::task::spawn_blocking(move || logging::query(&query))
+        .await
+        .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Get the settings for additional recording outputs (NDI, etc.).
+#[tauri::command]
+async fn get_output_settings(state: State<'_, AppState>) -> Result<OutputSettings, String> {
+    let manager = state.recording_manager.lock().await;
+    Ok(manager.get_output_settings().await)
+}
+
+/// Enable or disable publishing recordings as an NDI source on the local network.
+#[tauri::command]
+async fn set_ndi_output(
+    enabled: bool,
+    source_name: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let settings = NdiSettings {
+        enabled,
+        source_name: source_name.unwrap_or_else(|| encoder::ndi::DEFAULT_SOURCE_NAME.to_string()),
+    };
+    let manager = state.recording_manager.lock().await;
+    manager.set_ndi_settings(settings).await
+}
+
+/// List the saved recording profiles.
+#[tauri::command]
+async fn list_profiles(state: State<'_, AppState>) -> Result<Vec<RecordingProfile>, String> {
+    Ok(state.profiles.lock().await.list())
+}
+
+/// Get the profile applied to new recordings, if any.
+#[tauri::command]
+async fn get_selected_profile(state: State<'_, AppState>) -> Result<Option<RecordingProfile>, String> {
+    Ok(state.profiles.lock().await.selected().cloned())
+}
+
+/// Select the profile applied to subsequent recordings.
+#[tauri::command]
+async fn select_profile(name: String, state: State<'_, AppState>) -> Result<RecordingProfile, String> {
+    let profile = state.profiles.lock().await.select(&name)?;
+    apply_profile(&state, &profile).await;
+    Ok(profile)
+}
+
+/// Save a profile, replacing any profile with the same name.
+#[tauri::command]
+async fn save_profile(profile: RecordingProfile, state: State<'_, AppState>) -> Result<(), String> {
+    let mut profiles = state.profiles.lock().await;
+    profiles.upsert(profile.clone())?;
+    if profiles.selected().is_some_and(|selected| selected.name == profile.name) {
+        apply_profile(&state, &profile).await;
+    }
+    Ok(())
+}
+
+/// Delete a profile.
+#[tauri::command]
+async fn delete_profile(name: String, state: State<'_, AppState>) -> Result<(), String> {
+    state.profiles.lock().await.delete(&name)
+}
+
+/// Predict the bitrate and size per minute of recording a `width`x`height`
+/// source with the profile `profile` (the current settings if not given),
+/// checked against the free space where recordings are saved and, if
+/// `duration_mins` is given, whether a recording that long fits.
+#[tauri::command]
+async fn estimate_recording_size(
+    profile: Option<String>,
+    width: u32,
+    height: u32,
+    duration_mins: Option<u32>,
+    state: State<'_, AppState>,
+) -> Result<SizeEstimate, String> {
+    let mut settings = state.recording_manager.lock().await.get_encoder_settings().await;
+    if let Some(name) = profile {
+        let profiles = state.profiles.lock().await;
+        let profile = profiles.get(&name).ok_or_else(|| format!("Profile {:?} not found", name))?;
+        profile.apply(&mut settings);
+    }
+    // Application audio is only recorded from windows; assume the source is one
+    let audio = settings.application_audio || settings.microphone;
+    let estimate = encoder::estimate::estimate(&settings, width, height, audio);
+    let dir = match settings.output_path.as_deref().and_then(std::path::Path::parent) {
+        Some(dir) => dir.to_path_buf(),
+        None => encoder::default_output_dir()?,
+    };
+    Ok(match disk::free_space(&dir) {
+        Ok(free_bytes) => estimate.with_free_space(free_bytes, duration_mins),
+        Err(e) => {
+            tracing::warn!(target: "Disk", "{}", e);
+            estimate
+        }
+    })
+}
+
+/// List the saved recording recipes.
+#[tauri::command]
+fn list_recipes() -> Vec<Recipe> {
+    Recipes::load().recipes
+}
+
+/// Save a recipe, replacing any recipe with the same name.
+#[tauri::command]
+fn save_recipe(recipe: Recipe) -> Result<(), String> {
+    Recipes::load().upsert(recipe)
+}
+
+/// Delete a recipe.
+#[tauri::command]
+fn delete_recipe(name: String) -> Result<(), String> {
+    Recipes::load().delete(&name)
+}
+
+/// Start recording `target` from a recipe: its profile is applied and its
+/// pre-roll actions run first, and all of it is undone once the recording
+/// ends.
+#[tauri::command]
+async fn start_recipe_recording(
+    name: String,
+    target: CaptureTarget,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    if !state.ffmpeg_ready {
+        return Err(AppError::FfmpegUnavailable);
+    }
+    let recipe = Recipes::load()
+        .get(&name)
+        .cloned()
+        .ok_or_else(|| AppError::InvalidParameters(format!("Recipe {:?} not found", name)))?;
+    if let CaptureTarget::Region { ref region } = target {
+        capture::validate_region(region)?;
+    }
+    recipes::apply(&app, &recipe, &target).await?;
+
+    let started = async {
+        count_down(&app, &state).await?;
+        let manager = state.recording_manager.lock().await;
+        match target {
+            CaptureTarget::Window { window_handle } => manager.start_recording(window_handle, false).await,
+            CaptureTarget::Region { region } => manager.start_region_recording(region).await,
+            CaptureTarget::Display { monitor_id, .. } => {
+                // The recipe may have changed the display's resolution
+                let monitor = list_monitors()
+                    .into_iter()
+                    .find(|m| m.id == monitor_id)
+                    .ok_or_else(|| AppError::TargetNotFound(i18n::tr_args("error-monitor-not-found", &[("monitor", &monitor_id)])))?;
+                manager.start_display_recording(monitor_id, monitor.width, monitor.height).await
+            }
+        }
+    }
+    .await;
+    if started.is_err() {
+        recipes::revert(&app).await;
+    }
+    started
+}
+
+/// Export a profile as JSON for sharing.
+#[tauri::command]
+async fn export_profile(name: String, state: State<'_, AppState>) -> Result<String, String> {
+    state.profiles.lock().await.export(&name)
+}
+
+/// Import a profile shared as JSON.
+#[tauri::command]
+async fn import_profile(json: String, state: State<'_, AppState>) -> Result<RecordingProfile, String> {
+    state.profiles.lock().await.import(&json)
+}
+
+/// List the saved scenes.
+#[tauri::command]
+async fn list_scenes(state: State<'_, AppState>) -> Result<Vec<Scene>, String> {
+    Ok(state.scenes.lock().await.list())
+}
+
+/// Save a scene, replacing any scene with the same name.
+#[tauri::command]
+async fn save_scene(scene: Scene, app: AppHandle, state: State<'_, AppState>) -> Result<(), String> {
+    let mut scenes = state.scenes.lock().await;
+    scenes.upsert(scene)?;
+    register_scene_hotkeys(&app, &scenes.list());
+    Ok(())
+}
+
+/// Delete a saved scene.
+#[tauri::command]
+async fn delete_scene(name: String, app: AppHandle, state: State<'_, AppState>) -> Result<(), String> {
+    let mut scenes = state.scenes.lock().await;
+    scenes.delete(&name)?;
+    register_scene_hotkeys(&app, &scenes.list());
+    Ok(())
+}
+
+/// List the built-in and saved scene layouts.
+#[tauri::command]
+async fn list_layouts(state: State<'_, AppState>) -> Result<Vec<Layout>, String> {
+    Ok(state.layouts.lock().await.list())
+}
+
+/// Save a scene layout, replacing any saved layout with the same name.
+#[tauri::command]
+async fn save_layout(layout: Layout, state: State<'_, AppState>) -> Result<(), String> {
+    state.layouts.lock().await.upsert(layout)
+}
+
+/// Delete a saved scene layout.
+#[tauri::command]
+async fn delete_layout(name: String, state: State<'_, AppState>) -> Result<(), String> {
+    state.layouts.lock().await.delete(&name)
+}
+
+/// Arrange a saved scene's screen and camera by a layout, saving and
+/// returning the arranged scene.
+#[tauri::command]
+async fn apply_layout(scene: String, layout: String, state: State<'_, AppState>) -> Result<Scene, String> {
+    let layout = state
+        .layouts
+        .lock()
+        .await
+        .get(&layout)
+        .ok_or_else(|| format!("Layout {:?} not found", layout))?;
+    let mut scene = state
+        .scenes
+        .lock()
+        .await
+        .get(&scene)
+        .cloned()
+        .ok_or_else(|| format!("Scene {:?} not found", scene))?;
+    // Sizing sources may enumerate windows or probe images, so keep it off the async runtime
+    let scene = tokio::task::spawn_blocking(move || {
+        layout.apply(&mut scene, SceneSource::size);
+        scene
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?;
+    state.scenes.lock().await.upsert(scene.clone())?;
+    Ok(scene)
+}
+
+/// Start recording a saved scene, composing its sources into one picture.
+#[tauri::command]
+async fn start_scene_recording(name: String, app: AppHandle, state: State<'_, AppState>) -> Result<(), String> {
+    if !state.ffmpeg_ready {
+        return Err(i18n::tr("error-ffmpeg-unavailable"));
+    }
+    let scene = state
+        .scenes
+        .lock()
+        .await
+        .get(&name)
+        .cloned()
+        .ok_or_else(|| format!("Scene {:?} not found", name))?;
+    count_down(&app, &state).await?;
+
+    let manager = state.recording_manager.lock().await;
+    manager.start_scene_recording(scene).await
+}
+
+/// Switch the scene being recorded to a saved scene, with `transition` or
+/// the default one.
+#[tauri::command]
+async fn switch_scene(
+    name: String,
+    transition: Option<Transition>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let scene = state
+        .scenes
+        .lock()
+        .await
+        .get(&name)
+        .cloned()
+        .ok_or_else(|| format!("Scene {:?} not found", name))?;
+    let manager = state.recording_manager.lock().await;
+    manager
+        .switch_scene(scene, transition.unwrap_or_else(scene::default_transition))
+        .await
+}
+
+/// Set or clear the chroma key of an item in a saved scene.
+///
+/// Takes effect immediately if the scene is being recorded.
+#[tauri::command]
+async fn set_chroma_key(
+    scene: String,
+    item: String,
+    key: Option<ChromaKey>,
+    state: State<'_, AppState>,
+) -> Result<Scene, String> {
+    if let Some(key) = key {
+        key.validate()?;
+    }
+    let saved = {
+        let mut scenes = state.scenes.lock().await;
+        let mut saved = scenes
+            .get(&scene)
+            .cloned()
+            .ok_or_else(|| format!("Scene {:?} not found", scene))?;
+        let scene_item = saved
+            .items
+            .iter_mut()
+            .find(|i| i.name == item)
+            .ok_or_else(|| format!("{:?} has no item {:?}", scene, item))?;
+        scene_item.chroma_key = key;
+        scenes.upsert(saved.clone())?;
+        saved
+    };
+    let manager = state.recording_manager.lock().await;
+    manager.set_chroma_key(&scene, &item, key).await?;
+    Ok(saved)
+}
+
+/// Set the transition used when switching scenes by hotkey, or without
+/// choosing one.
+#[tauri::command]
+fn set_scene_transition(transition: Transition) -> Result<(), String> {
+    scene::set_default_transition(transition)
+}
+
+/// Get the windows hidden in display and region recordings.
+#[tauri::command]
+async fn get_window_exclusions(state: State<'_, AppState>) -> Result<ExclusionSettings, String> {
+    let manager = state.recording_manager.lock().await;
+    Ok(manager.exclusions().get())
+}
+
+/// Hide windows matching `rule` in display and region recordings.
+///
+/// Takes effect immediately, including in a recording in progress.
+#[tauri::command]
+async fn add_window_exclusion(
+    rule: WindowRule,
+    state: State<'_, AppState>,
+) -> Result<ExclusionSettings, String> {
+    let manager = state.recording_manager.lock().await;
+    manager.exclusions().add(rule)
+}
+
+/// Stop hiding windows matching `rule`.
+#[tauri::command]
+async fn remove_window_exclusion(
+    rule: WindowRule,
+    state: State<'_, AppState>,
+) -> Result<ExclusionSettings, String> {
+    let manager = state.recording_manager.lock().await;
+    manager.exclusions().remove(&rule)
+}
+
+/// Set whether excluded windows are covered with a black box or blurred.
+#[tauri::command]
+async fn set_window_exclusion_style(
+    style: MaskStyle,
+    state: State<'_, AppState>,
+) -> Result<ExclusionSettings, String> {
+    let manager = state.recording_manager.lock().await;
+    manager.exclusions().set_style(style)
+}
+
+/// Set whether the app's own windows are kept out of recordings.
+#[tauri::command]
+async fn set_exclude_self(
+    enabled: bool,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<ExclusionSettings, String> {
+    let manager = state.recording_manager.lock().await;
+    let settings = manager.exclusions().set_exclude_self(enabled)?;
+    hide_own_windows(&app, enabled);
+    Ok(settings)
+}
+
+/// Hide the app's windows from captures where the platform can, or show them again.
+#[cfg_attr(not(target_os = "windows"), allow(unused_variables))]
+fn hide_own_windows(app: &AppHandle, hidden: bool) {
+    #[cfg(target_os = "windows")]
+    for window in app.webview_windows().values() {
+        if let Ok(hwnd) = window.hwnd() {
+            capture::exclusion::hide_from_capture(hwnd.0 as isize, hidden);
+        }
+    }
+}
+
+/// Get the windows recorded with game capture.
+#[tauri::command]
+async fn get_game_capture_rules() -> Result<GameCaptureSettings, String> {
+    Ok(capture::game::settings())
+}
+
+/// Record windows matching `rule` with game capture, so games in exclusive
+/// fullscreen can be recorded. Applies from the next recording.
+#[tauri::command]
+async fn add_game_capture_rule(rule: WindowRule) -> Result<GameCaptureSettings, String> {
+    capture::game::add_rule(rule)
+}
+
+/// Stop recording windows matching `rule` with game capture.
+#[tauri::command]
+async fn remove_game_capture_rule(rule: WindowRule) -> Result<GameCaptureSettings, String> {
+    capture::game::remove_rule(&rule)
+}
+
+/// List the regions hidden in every recording.
+#[tauri::command]
+async fn list_redactions(state: State<'_, AppState>) -> Result<Vec<RedactionRegion>, String> {
+    let manager = state.recording_manager.lock().await;
+    Ok(manager.redactions().list())
+}
+
+/// Save a redaction region, replacing any region with the same name.
+///
+/// Takes effect immediately, including in a recording in progress.
+#[tauri::command]
+async fn save_redaction(region: RedactionRegion, state: State<'_, AppState>) -> Result<(), String> {
+    let manager = state.recording_manager.lock().await;
+    manager.redactions().upsert(region)
+}
+
+/// Delete a redaction region.
+#[tauri::command]
+async fn delete_redaction(name: String, state: State<'_, AppState>) -> Result<(), String> {
+    let manager = state.recording_manager.lock().await;
+    manager.redactions().delete(&name)
+}
+
+/// Apply a profile's settings to subsequent recordings.
+async fn apply_profile(state: &AppState, profile: &RecordingProfile) {
+    let manager = state.recording_manager.lock().await;
+    let mut settings = manager.get_encoder_settings().await;
+    profile.apply(&mut settings);
+    manager.set_encoder_settings(settings).await;
+}
+
+/// Enable or disable GPU color conversion and scaling for subsequent recordings.
+///
+/// Returns the conversion backend that will be used.
+#[tauri::command]
+async fn set_gpu_conversion(
+    enabled: bool,
+    state: State<'_, AppState>,
+) -> Result<ConversionBackend, String> {
+    let manager = state.recording_manager.lock().await;
+    let mut settings = manager.get_encoder_settings().await;
+    settings.gpu_conversion = enabled;
+    manager.set_encoder_settings(settings.clone()).await;
+    drop(manager);
+
+    // Detection probes FFmpeg, so keep it off the async runtime
+    tokio::task::spawn_blocking(move || settings.conversion_backend())
+        .await
+        .map_err(|e| format!("Task error: {}", e))
+}
+
+/// List the GPUs on this machine.
+#[tauri::command]
+async fn get_gpu_adapters() -> Result<Vec<GpuAdapter>, String> {
+    Ok(gpu::list_adapters())
+}
+
+/// Pin hardware encoding and GPU conversion of subsequent recordings to the
+/// GPU at `index` (from `get_gpu_adapters`), or with `None`, prefer the GPU
+/// doing the capture.
+#[tauri::command]
+async fn set_gpu_adapter(index: Option<u32>, state: State<'_, AppState>) -> Result<(), String> {
+    if let Some(index) = index {
+        if !gpu::list_adapters().iter().any(|adapter| adapter.index == index) {
+            return Err(format!("No GPU adapter {}", index));
+        }
+    }
+    let manager = state.recording_manager.lock().await;
+    let mut settings = manager.get_encoder_settings().await;
+    settings.gpu_adapter = index;
+    manager.set_encoder_settings(settings).await;
+    Ok(())
+}
+
+/// Set how HDR displays are recorded by subsequent recordings.
+///
+/// Returns the mode that will be used: HDR is only kept with codecs that can carry it.
+#[tauri::command]
+async fn set_hdr_mode(mode: HdrMode, state: State<'_, AppState>) -> Result<HdrMode, String> {
+    let manager = state.recording_manager.lock().await;
+    let mut settings = manager.get_encoder_settings().await;
+    settings.hdr = mode;
+    manager.set_encoder_settings(settings.clone()).await;
+    Ok(settings.capture_hdr_mode())
+}
+
+/// Enable or disable 10-bit encoding for subsequent recordings.
+///
+/// Returns whether recordings will be 10-bit: only HEVC, VP9 and AV1 support it.
+#[tauri::command]
+async fn set_ten_bit(enabled: bool, state: State<'_, AppState>) -> Result<bool, String> {
+    let manager = state.recording_manager.lock().await;
+    let mut settings = manager.get_encoder_settings().await;
+    settings.ten_bit = enabled;
+    manager.set_encoder_settings(settings.clone()).await;
+    Ok(settings.encodes_10bit())
+}
+
+/// Keep the alpha channel of transparent windows in subsequent recordings,
+/// for overlays.
+///
+/// Returns whether recordings will have alpha: only VP9, ProRes and PNG
+/// sequences carry it.
+#[tauri::command]
+async fn set_alpha(enabled: bool, state: State<'_, AppState>) -> Result<bool, String> {
+    let manager = state.recording_manager.lock().await;
+    let mut settings = manager.get_encoder_settings().await;
+    settings.alpha = enabled;
+    manager.set_encoder_settings(settings.clone()).await;
+    Ok(settings.encodes_alpha())
+}
+
+/// Set the YUV matrix and range subsequent SDR recordings are converted to
+/// and tagged with. BT.709 at limited range plays the same everywhere;
+/// BT.601 suits SD workflows, and full range keeps every RGB level.
+#[tauri::command]
+async fn set_output_color(color: ColorSpace, state: State<'_, AppState>) -> Result<(), String> {
+    let manager = state.recording_manager.lock().await;
+    let mut settings = manager.get_encoder_settings().await;
+    settings.color = color;
+    manager.set_encoder_settings(settings).await;
+    Ok(())
+}
+
+/// Set how long subsequent recordings keep trying to capture their source
+/// again when it's lost, e.g. because the monitor was unplugged. 0 ends the
+/// recording instead.
+#[tauri::command]
+async fn set_recovery_timeout(secs: u32, state: State<'_, AppState>) -> Result<(), String> {
+    let manager = state.recording_manager.lock().await;
+    let mut settings = manager.get_encoder_settings().await;
+    settings.recovery_timeout_secs = secs;
+    manager.set_encoder_settings(settings).await;
+    Ok(())
+}
+
+/// Set what subsequent window recordings show while the window is minimized
+/// or closed, or whether they stop when it closes.
+#[tauri::command]
+async fn set_window_lost_behavior(behavior: WindowLostBehavior, state: State<'_, AppState>) -> Result<(), String> {
+    let manager = state.recording_manager.lock().await;
+    let mut settings = manager.get_encoder_settings().await;
+    settings.window_lost = behavior;
+    manager.set_encoder_settings(settings).await;
+    Ok(())
+}
+
+/// Set whether subsequent recordings scale frames or start a new file when
+/// the capture changes resolution.
+#[tauri::command]
+async fn set_resolution_change_policy(policy: ResolutionChangePolicy, state: State<'_, AppState>) -> Result<(), String> {
+    let manager = state.recording_manager.lock().await;
+    let mut settings = manager.get_encoder_settings().await;
+    settings.resolution_change = policy;
+    manager.set_encoder_settings(settings).await;
+    Ok(())
+}
+
+/// Set whether subsequent recordings run at the refresh rate of the display
+/// they capture.
+#[tauri::command]
+async fn set_match_refresh_rate(enabled: bool, state: State<'_, AppState>) -> Result<(), String> {
+    let manager = state.recording_manager.lock().await;
+    let mut settings = manager.get_encoder_settings().await;
+    settings.match_refresh_rate = enabled;
+    manager.set_encoder_settings(settings).await;
+    Ok(())
+}
+
+/// Set the resolution and scale the captured monitor is switched to while
+/// recording, or `None` to record it as it is.
+#[tauri::command]
+fn set_display_mode_override(mode: Option<DisplayMode>) -> Result<(), String> {
+    display_mode::set_override(mode)
+}
+
+/// Get the resolution and scale the captured monitor is switched to while
+/// recording, if set.
+#[tauri::command]
+fn get_display_mode_override() -> Option<DisplayMode> {
+    display_mode::override_mode()
+}
+
+/// Set whether subsequent recordings only keep the stretches where
+/// something on screen changes.
+#[tauri::command]
+async fn set_motion_recording(settings: MotionSettings, state: State<'_, AppState>) -> Result<(), String> {
+    settings.validate()?;
+    let manager = state.recording_manager.lock().await;
+    let mut encoder_settings = manager.get_encoder_settings().await;
+    encoder_settings.motion = settings;
+    manager.set_encoder_settings(encoder_settings).await;
+    Ok(())
+}
+
+/// Set whether subsequent recordings hold back frames while the picture is
+/// still, shrinking mostly-static recordings without cutting any time.
+#[tauri::command]
+async fn set_static_content(settings: StaticContentSettings, state: State<'_, AppState>) -> Result<(), String> {
+    settings.validate()?;
+    let manager = state.recording_manager.lock().await;
+    let mut encoder_settings = manager.get_encoder_settings().await;
+    encoder_settings.static_content = settings;
+    manager.set_encoder_settings(encoder_settings).await;
+    Ok(())
+}
+
+/// Set whether subsequent recordings pause while there's no input and
+/// nothing on screen changes, resuming on activity. Pauses made this way
+/// are reported with `recording-idle` events.
+#[tauri::command]
+async fn set_idle_pause(settings: IdlePauseSettings, state: State<'_, AppState>) -> Result<(), String> {
+    settings.validate()?;
+    let manager = state.recording_manager.lock().await;
+    let mut encoder_settings = manager.get_encoder_settings().await;
+    encoder_settings.idle_pause = settings;
+    manager.set_encoder_settings(encoder_settings).await;
+    Ok(())
+}
+
+/// Set whether subsequent recordings save the mouse and keyboard input in a
+/// sidecar file next to the video.
+#[tauri::command]
+async fn set_input_track(settings: InputTrackSettings, state: State<'_, AppState>) -> Result<(), String> {
+    let manager = state.recording_manager.lock().await;
+    let mut encoder_settings = manager.get_encoder_settings().await;
+    encoder_settings.input_track = settings;
+    manager.set_encoder_settings(encoder_settings).await;
+    Ok(())
+}
+
+/// Set whether subsequent recordings transcribe the microphone into live
+/// captions, and how they're shown.
+#[tauri::command]
+async fn set_live_captions(settings: LiveCaptionSettings, state: State<'_, AppState>) -> Result<(), String> {
+    settings.validate()?;
+    let manager = state.recording_manager.lock().await;
+    let mut encoder_settings = manager.get_encoder_settings().await;
+    encoder_settings.live_captions = settings;
+    manager.set_encoder_settings(encoder_settings).await;
+    Ok(())
+}
+
+/// Set extra encoder options passed through to FFmpeg for subsequent
+/// recordings, replacing any set before.
+#[tauri::command]
+async fn set_advanced_encoder_options(options: AdvancedOptions, state: State<'_, AppState>) -> Result<(), String> {
+    encoder::options::validate(&options)?;
+    let manager = state.recording_manager.lock().await;
+    let mut settings = manager.get_encoder_settings().await;
+    settings.advanced_options = options;
+    manager.set_encoder_settings(settings).await;
+    Ok(())
+}
+
+/// List the webcams and capture cards connected to this machine, with the
+/// resolutions and formats each supports.
+#[tauri::command]
+async fn get_video_devices() -> Result<Vec<VideoDevice>, String> {
+    // Listing runs FFmpeg for every device, so keep it off the async runtime
+    tokio::task::spawn_blocking(devices::list_video_devices)
+        .await
+        .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Get the encoders recordings will use and which advanced encoder options
+/// can be set.
+#[tauri::command]
+async fn get_encoder_capabilities(state: State<'_, AppState>) -> Result<EncoderCapabilities, String> {
+    let settings = state.recording_manager.lock().await.get_encoder_settings().await;
+    // Probing runs FFmpeg, so keep it off the async runtime
+    let encoders = tokio::task::spawn_blocking(move || {
+        settings
+            .encoder_chain()
+            .iter()
+            .filter_map(|backend| backend.encoder_name(settings.codec))
+            .collect()
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?;
+    Ok(EncoderCapabilities {
+        encoders,
+        reserved_options: encoder::options::RESERVED_OPTIONS,
+        advanced_options_help: encoder::options::ADVANCED_OPTIONS_HELP,
+    })
+}
+
+/// Get what each encoder can do on this machine: frame sizes, profiles,
+/// 10-bit input, B-frames and concurrent sessions, per GPU.
+///
+/// The first call probes every encoder, which takes a while; the results are
+/// cached until FFmpeg or the GPUs change, or `refresh` is set.
+#[tauri::command]
+async fn get_encoder_support(refresh: Option<bool>, state: State<'_, AppState>) -> Result<EncoderSupport, String> {
+    if !state.ffmpeg_ready {
+        return Err(i18n::tr("error-ffmpeg-unavailable"));
+    }
+    let refresh = refresh.unwrap_or(false);
+    tokio::task::spawn_blocking(move || encoder::support::encoder_support(refresh))
+        .await
+        .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Set the rate control mode for subsequent recordings.
+#[tauri::command]
+async fn set_rate_control(rate_control: RateControl, state: State<'_, AppState>) -> Result<(), String> {
+    rate_control.validate()?;
+    let manager = state.recording_manager.lock().await;
+    let mut settings = manager.get_encoder_settings().await;
+    settings.rate_control = rate_control;
+    manager.set_encoder_settings(settings).await;
+    Ok(())
+}
+
+/// Enable or disable hardware encoding for subsequent recordings.
+///
+/// Returns the encoders recordings will try, in order.
+#[tauri::command]
+async fn set_hardware_encoding(
+    enabled: bool,
+    state: State<'_, AppState>,
+) -> Result<Vec<EncoderBackend>, String> {
+    let manager = state.recording_manager.lock().await;
+    let mut settings = manager.get_encoder_settings().await;
+    settings.hardware_encoding = enabled;
+    manager.set_encoder_settings(settings.clone()).await;
+    drop(manager);
+
+    // Probing runs FFmpeg, so keep it off the async runtime
+    tokio::task::spawn_blocking(move || settings.encoder_chain())
+        .await
+        .map_err(|e| format!("Task error: {}", e))
+}
+
+/// Get the color conversion backend recordings will use.
+#[tauri::command]
+async fn get_conversion_backend(state: State<'_, AppState>) -> Result<ConversionBackend, String> {
+    let settings = state.recording_manager.lock().await.get_encoder_settings().await;
+    tokio::task::spawn_blocking(move || settings.conversion_backend())
+        .await
+        .map_err(|e| format!("Task error: {}", e))
+}
+
+/// Set what happens to captured frames when the encoder can't keep up.
+///
+/// Applies to captures started after the change.
+#[tauri::command]
+fn set_frame_drop_policy(policy: DropPolicy) {
+    capture::queue::set_drop_policy(policy);
+}
+
+/// Get the current frame drop policy.
+#[tauri::command]
+fn get_frame_drop_policy() -> DropPolicy {
+    capture::queue::drop_policy()
+}
+
+/// Set how subsequent recordings are made cheaper on battery or while the
+/// machine is thermally throttling.
+#[tauri::command]
+fn set_power_policy(policy: PowerPolicy) -> Result<(), String> {
+    power::set_policy(policy)
+}
+
+/// Get the power policy.
+#[tauri::command]
+fn get_power_policy() -> PowerPolicy {
+    power::policy()
+}
+
+/// Get whether the machine is on battery or thermally throttling.
+#[tauri::command]
+async fn get_power_state() -> Result<PowerState, String> {
+    tokio::task::spawn_blocking(power::current)
+        .await
+        .map_err(|e| format!("Task error: {}", e))
+}
+
+/// Set whether recordings turn on the desktop's do-not-disturb mode while
+/// they run.
+#[tauri::command]
+fn set_do_not_disturb(enabled: bool) {
+    dnd::set_enabled(enabled)
+}
+
+/// Get whether recordings turn on do not disturb.
+#[tauri::command]
+fn get_do_not_disturb() -> bool {
+    dnd::enabled()
+}
+
+/// Set whether recordings are paused or stopped while the session is
+/// locked or the screensaver runs.
+#[tauri::command]
+fn set_lock_policy(policy: LockPolicy) {
+    session::set_policy(policy)
+}
+
+/// Get the session lock policy.
+#[tauri::command]
+fn get_lock_policy() -> LockPolicy {
+    session::policy()
+}
+
+/// Set whether audio capture follows the system's default output device
+/// when it changes.
+///
+/// Applies immediately, also to a running recording.
+#[tauri::command]
+fn set_audio_device_mode(mode: AudioDeviceMode) {
+    capture::audio::set_device_mode(mode);
+}
+
+/// Get how audio capture handles device changes.
+#[tauri::command]
+fn get_audio_device_mode() -> AudioDeviceMode {
+    capture::audio::device_mode()
+}
+
+/// Set how far application audio is delayed against the video, in
+/// milliseconds; negative moves it earlier. Applies to recordings saved from
+/// now on.
+#[tauri::command]
+fn set_audio_offset(offset_ms: i32) -> Result<(), String> {
+    avsync::set_audio_offset_ms(offset_ms)
+}
+
+/// Get the audio offset, in milliseconds.
+#[tauri::command]
+fn get_audio_offset() -> i32 {
+    avsync::audio_offset_ms()
+}
+
+/// Set the noise gate, compressor and makeup gain applied to captured audio.
+/// Applies to recordings saved from now on.
+#[tauri::command]
+fn set_audio_dynamics(settings: dynamics::DynamicsSettings) -> Result<(), String> {
+    dynamics::set_settings(settings)
+}
+
+/// Get the dynamics processing applied to captured audio.
+#[tauri::command]
+fn get_audio_dynamics() -> dynamics::DynamicsSettings {
+    dynamics::settings()
+}
+
+/// Set the loudness (EBU R128) recordings are normalized to when their audio
+/// is added, or `None` to leave them as recorded. Applies to recordings
+/// saved from now on.
+#[tauri::command]
+fn set_loudness_target(target: Option<LoudnessTarget>) -> Result<(), String> {
+    postprocess::loudness::set_target(target)
+}
+
+/// Get the loudness recordings are normalized to, if any.
+#[tauri::command]
+fn get_loudness_target() -> Option<LoudnessTarget> {
+    postprocess::loudness::target()
+}
+
+/// Set how far one audio source, "application" or "microphone", is delayed
+/// beyond the audio offset, in milliseconds; negative moves it earlier and 0
+/// clears it. The sources are shifted against each other when they're mixed.
+/// Applies to recordings saved from now on.
+#[tauri::command]
+fn set_audio_source_offset(source: String, offset_ms: i32) -> Result<(), String> {
+    avsync::set_source_offset_ms(&source, offset_ms)
+}
+
+/// Get the audio sources' own offsets, in milliseconds, by source.
+#[tauri::command]
+fn get_audio_source_offsets() -> BTreeMap<String, i32> {
+    avsync::source_offsets_ms()
+}
+
+/// Enable or disable serving recordings as a live HLS stream, to this machine
+/// or with `lan` to the local network, optionally adapting its bitrate to
+/// viewers' networks. Each time LAN viewing is turned on the stream gets a
+/// new token, so URLs handed out before stop working.
+///
+/// Returns the URL viewers should open when enabled.
+#[tauri::command]
+async fn set_hls_output(
+    enabled: bool,
+    port: Option<u16>,
+    lan: Option<bool>,
+    adaptive: Option<AdaptiveBitrate>,
+    state: State<'_, AppState>,
+) -> Result<Option<String>, String> {
+    let settings = HlsSettings::new(
+        enabled,
+        port.unwrap_or(encoder::hls::DEFAULT_PORT),
+        lan.unwrap_or(false),
+        adaptive.unwrap_or_default(),
+    );
+    let url = enabled.then(|| settings.stream_url());
+    let manager = state.recording_manager.lock().await;
+    manager.set_hls_settings(settings).await?;
+    Ok(url)
+}
+
+/// Get the free disk space thresholds at which recordings warn and stop.
+#[tauri::command]
+async fn get_disk_space_settings(state: State<'_, AppState>) -> Result<DiskSpaceSettings, String> {
+    let manager = state.recording_manager.lock().await;
+    Ok(manager.get_disk_space_settings().await)
+}
+
+/// Set the free disk space thresholds at which subsequent recordings warn
+/// and stop.
+#[tauri::command]
+async fn set_disk_space_settings(settings: DiskSpaceSettings, state: State<'_, AppState>) -> Result<(), String> {
+    let manager = state.recording_manager.lock().await;
+    manager.set_disk_space_settings(settings).await;
+    Ok(())
+}
+
+/// Get the duration and file size limits applied to each recording.
+#[tauri::command]
+async fn get_recording_limits(state: State<'_, AppState>) -> Result<RecordingLimits, String> {
+    let manager = state.recording_manager.lock().await;
+    Ok(manager.get_limits().await)
+}
+
+/// Set the duration and file size limits of subsequent recordings. A
+/// recording reaching one is stopped and a `recording-limit` event says why.
+#[tauri::command]
+async fn set_recording_limits(limits: RecordingLimits, state: State<'_, AppState>) -> Result<(), String> {
+    let manager = state.recording_manager.lock().await;
+    manager.set_limits(limits).await
+}
+
+/// Stop the current recording on an exact frame: once it's `secs` long, or
+/// at the next marker added. Reaching it sends a `recording-limit` event with
+/// the `stop_point` reason, and the recording should then be stopped.
+#[tauri::command]
+async fn schedule_stop(point: StopPoint, state: State<'_, AppState>) -> Result<(), String> {
+    let manager = state.recording_manager.lock().await;
+    manager.schedule_stop(point).await
+}
+
+/// Remove the current recording's scheduled stop.
+#[tauri::command]
+async fn cancel_scheduled_stop(state: State<'_, AppState>) -> Result<(), String> {
+    state.recording_manager.lock().await.cancel_scheduled_stop();
+    Ok(())
+}
+
+/// Enable or disable keeping a replay buffer of the last `duration_secs`
+/// (30 by default) during subsequent recordings.
+#[tauri::command]
+async fn set_replay_buffer(
+    enabled: bool,
+    duration_secs: Option<u32>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let settings = ReplaySettings {
+        enabled,
+        duration_secs: duration_secs.unwrap_or(encoder::replay::DEFAULT_DURATION_SECS),
+    };
+    let manager = state.recording_manager.lock().await;
+    manager.set_replay_settings(settings).await
+}
+
+/// Save the replay buffer as a clip while the recording continues.
+///
+/// Returns the path of the clip.
+#[tauri::command]
+async fn save_replay(state: State<'_, AppState>) -> Result<String, String> {
+    let manager = state.recording_manager.lock().await;
+    let clip = manager.save_replay().await?;
+    Ok(clip.to_string_lossy().to_string())
+}
+
+/// Get the URL of the live HLS stream, if enabled.
+#[tauri::command]
+async fn get_hls_url(state: State<'_, AppState>) -> Result<Option<String>, String> {
+    let manager = state.recording_manager.lock().await;
+    let settings = manager.get_output_settings().await.hls;
+    Ok(settings.enabled.then(|| settings.stream_url()))
+}
+
+/// Benchmark the encoders on this machine and recommend recording settings.
+///
+/// With `monitor_id`, that display is captured for a few seconds first and
+/// the resolutions tested are capped at its size. Progress is sent as
+/// `benchmark-progress` events; the whole run takes up to a minute or two.
+#[tauri::command]
+async fn run_benchmark(
+    monitor_id: Option<String>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<BenchmarkReport, String> {
+    use tauri::Emitter;
+
+    if !state.ffmpeg_ready {
+        return Err(i18n::tr("error-ffmpeg-unavailable"));
+    }
+    if !state.recording_manager.lock().await.get_phase().is_idle() {
+        return Err("Stop recording before running the benchmark".to_string());
+    }
+
+    let capture = match monitor_id {
+        Some(monitor_id) => {
+            let monitor = list_monitors()
+                .into_iter()
+                .find(|m| m.id == monitor_id)
+                .ok_or_else(|| i18n::tr_args("error-monitor-not-found", &[("monitor", &monitor_id)]))?;
+            Some(benchmark::capture_trial(&monitor).await?)
+        }
+        None => None,
+    };
+    let max_height = capture.as_ref().map(|capture| capture.height);
+    let trials = tokio::task::spawn_blocking(move || {
+        benchmark::encode_trials(max_height, |progress| {
+            let _ = app.emit(benchmark::PROGRESS_EVENT, progress);
+        })
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?;
+    Ok(BenchmarkReport {
+        recommendation: benchmark::recommend(&trials),
+        capture,
+        trials,
+    })
+}
+
+/// Record color bars and a tone through the encoder and muxer with the
+/// current settings, then check the file's length and audio sync.
+///
+/// Nothing is captured. The recording lasts `duration_secs` (10 seconds by
+/// default) and is kept with the user's recordings.
+#[tauri::command]
+async fn record_test_signal(duration_secs: Option<f64>, state: State<'_, AppState>) -> Result<TestSignalReport, String> {
+    if !state.ffmpeg_ready {
+        return Err(i18n::tr("error-ffmpeg-unavailable"));
+    }
+    let manager = state.recording_manager.lock().await;
+    if !manager.get_phase().is_idle() {
+        return Err("Stop recording before recording a test signal".to_string());
+    }
+    let settings = manager.get_encoder_settings().await;
+    drop(manager);
+    test_signal::record(settings, duration_secs.unwrap_or(test_signal::DEFAULT_SECS), None).await
+}
+
+/// Capture a single still image of a window, region, or display.
+///
+/// The image is saved to the Pictures folder in the given format (PNG by
+/// default), or copied to the clipboard instead when `copy_to_clipboard` is set.
+#[tauri::command]
+async fn take_screenshot(
+    target: CaptureTarget,
+    format: Option<ImageFormat>,
+    copy_to_clipboard: Option<bool>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<ScreenshotResult, String> {
+    if !state.ffmpeg_ready {
+        return Err(i18n::tr("error-ffmpeg-unavailable"));
+    }
+
+    let frame = screenshot::capture_frame(target).await?;
+    let (width, height) = (frame.width, frame.height);
+
+    if copy_to_clipboard.unwrap_or(false) {
+        let rgba = screenshot::bgra_to_rgba(&frame.data);
+        app.clipboard()
+            .write_image(&tauri::image::Image::new_owned(rgba, width, height))
+            .map_err(|e| format!("Failed to copy screenshot to clipboard: {}", e))?;
+        return Ok(ScreenshotResult {
+            file_path: None,
+            copied_to_clipboard: true,
+            width,
+            height,
+        });
+    }
+
+    let path = screenshot::save_frame(frame, format.unwrap_or_default()).await?;
+    Ok(ScreenshotResult {
+        file_path: Some(path.to_string_lossy().to_string()),
+        copied_to_clipboard: false,
+        width,
+        height,
+    })
+}
+
+/// Get the thumbnail for a recording, generating it on demand if missing.
+///
+/// Set `include_sprite` to also get a hover-scrub sprite sheet.
+#[tauri::command]
+async fn get_thumbnail(
+    file_path: String,
+    include_sprite: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<ThumbnailInfo, String> {
+    if !state.ffmpeg_ready {
+        return Err(i18n::tr("error-ffmpeg-unavailable"));
+    }
+
+    let include_sprite = include_sprite.unwrap_or(false);
+    tokio::task::spawn_blocking(move || {
+        postprocess::thumbnail::ensure_thumbnails(std::path::Path::new(&file_path), include_sprite)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Get the filmstrip and audio waveform of a recording for scrubbing through
+/// it, generating them on demand if missing.
+#[tauri::command]
+async fn get_scrub_data(file_path: String, state: State<'_, AppState>) -> Result<ScrubInfo, String> {
+    if !state.ffmpeg_ready {
+        return Err(i18n::tr("error-ffmpeg-unavailable"));
+    }
+
+    tokio::task::spawn_blocking(move || postprocess::scrub::ensure_scrub_data(std::path::Path::new(&file_path)))
+        .await
+        .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Trim the head and/or tail of a recording into a new file.
+///
+/// Runs as a background job; returns the job ID. Progress and the result are
+/// reported through `job-progress` events. An `end_secs` of 0 means the end
+/// of the recording.
+#[tauri::command]
+async fn trim_recording(
+    file_path: String,
+    start_secs: f64,
+    end_secs: f64,
+    mode: Option<TrimMode>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<u64, String> {
+    if !state.ffmpeg_ready {
+        return Err(i18n::tr("error-ffmpeg-unavailable"));
+    }
+
+    let mode = mode.unwrap_or_default();
+    Ok(state.jobs.spawn(app, "trim", move |context| {
+        postprocess::trim::trim(std::path::Path::new(&file_path), start_secs, end_secs, mode, context)
+    }))
+}
+
+/// Copy a recording into another container without re-encoding it.
+///
+/// Runs as a background job; returns the job ID.
+#[tauri::command]
+async fn remux_recording(
+    file_path: String,
+    container: Container,
+    loudness: Option<LoudnessTarget>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<u64, String> {
+    if !state.ffmpeg_ready {
+        return Err(i18n::tr("error-ffmpeg-unavailable"));
+    }
+
+    Ok(state.jobs.spawn(app, "remux", move |context| {
+        postprocess::remux::remux(std::path::Path::new(&file_path), container, loudness, context)
+    }))
+}
+
+/// Export a cropped and/or resized copy of a recording.
+///
+/// Runs as a background job; returns the job ID. If only one of `width` and
+/// `height` is given, the aspect ratio is preserved.
+#[tauri::command]
+async fn export_recording(
+    file_path: String,
+    crop: Option<CropRect>,
+    width: Option<u32>,
+    height: Option<u32>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<u64, String> {
+    if !state.ffmpeg_ready {
+        return Err(i18n::tr("error-ffmpeg-unavailable"));
+    }
+
+    let options = ExportOptions { crop, width, height };
+    Ok(state.jobs.spawn(app, "export", move |context| {
+        postprocess::export::export(std::path::Path::new(&file_path), &options, context)
+    }))
+}
+
+/// Export a recording to ProRes or DNxHR, which editors scrub through far
+/// more smoothly than H.264.
+///
+/// Runs as a background job; returns the job ID.
+#[tauri::command]
+async fn export_intermediate(
+    file_path: String,
+    codec: IntermediateCodec,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<u64, String> {
+    if !state.ffmpeg_ready {
+        return Err(i18n::tr("error-ffmpeg-unavailable"));
+    }
+
+    Ok(state.jobs.spawn(app, "intermediate", move |context| {
+        postprocess::intermediate::export(std::path::Path::new(&file_path), codec, context)
+    }))
+}
+
+/// Export a recording with the whole of it, or ranges of it, played faster or
+/// slower.
+///
+/// Runs as a background job; returns the job ID.
+#[tauri::command]
+async fn export_speed(
+    file_path: String,
+    options: SpeedOptions,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<u64, String> {
+    if !state.ffmpeg_ready {
+        return Err(i18n::tr("error-ffmpeg-unavailable"));
+    }
+
+    Ok(state.jobs.spawn(app, "speed", move |context| {
+        postprocess::speed::export(std::path::Path::new(&file_path), &options, context)
+    }))
+}
+
+/// Join recordings into one file, each cut to its range.
+///
+/// Runs as a background job; returns the job ID. The job's output is the
+/// joined file, next to the first recording.
+#[tauri::command]
+async fn concat_recordings(clips: Vec<ConcatClip>, app: AppHandle, state: State<'_, AppState>) -> Result<u64, String> {
+    if !state.ffmpeg_ready {
+        return Err(i18n::tr("error-ffmpeg-unavailable"));
+    }
+
+    Ok(state.jobs.spawn(app, "concat", move |context| postprocess::concat::concat(&clips, context)))
+}
+
+/// Generate subtitles from the narration of a recording with a local
+/// speech-to-text model.
+///
+/// Runs as a background job; returns the job ID. The job's output is the SRT
+/// file, or the subtitled copy of the recording if `options.embed` is set.
+#[tauri::command]
+async fn generate_subtitles(
+    file_path: String,
+    options: Option<SubtitleOptions>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<u64, String> {
+    if !state.ffmpeg_ready {
+        return Err(i18n::tr("error-ffmpeg-unavailable"));
+    }
+
+    let options = options.unwrap_or_default();
+    Ok(state.jobs.spawn(app, "subtitles", move |context| {
+        postprocess::subtitles::generate(std::path::Path::new(&file_path), &options, context)
+    }))
+}
+
+/// Encrypt subsequent recordings as they're written. Enabling it creates the
+/// recording key in the OS keychain if there isn't one yet.
+#[tauri::command]
+async fn set_encryption(enabled: bool, state: State<'_, AppState>) -> Result<(), String> {
+    if enabled {
+        tokio::task::spawn_blocking(encryption::recording_key)
+            .await
+            .map_err(|e| format!("Task error: {}", e))??;
+    }
+    let manager = state.recording_manager.lock().await;
+    let mut settings = manager.get_encoder_settings().await;
+    settings.encrypt = enabled;
+    manager.set_encoder_settings(settings).await;
+    Ok(())
+}
+
+/// Hold subsequent recordings in memory, writing them to disk when they stop,
+/// when the buffer fills, or at the settings' interval.
+#[tauri::command]
+async fn set_memory_buffer(settings: MemoryBuffer, state: State<'_, AppState>) -> Result<(), String> {
+    if settings.enabled {
+        settings.validate()?;
+    }
+    let manager = state.recording_manager.lock().await;
+    let mut encoder_settings = manager.get_encoder_settings().await;
+    encoder_settings.memory_buffer = settings;
+    manager.set_encoder_settings(encoder_settings).await;
+    Ok(())
+}
+
+/// Decrypt an encrypted recording to `output_path`, or next to it without
+/// the `.enc` extension, as a background job. Returns the job ID.
+#[tauri::command]
+async fn decrypt_recording(
+    file_path: String,
+    output_path: Option<String>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<u64, String> {
+    Ok(state.jobs.spawn(app, "decrypt", move |context| {
+        encryption::decrypt_file(
+            std::path::Path::new(&file_path),
+            output_path.map(Into::into),
+            context.cancel_flag(),
+            |progress| context.report(progress),
+        )
+    }))
+}
+
+/// Get the saved upload targets, with their credentials redacted.
+#[tauri::command]
+async fn get_upload_targets() -> Result<UploadTargets, String> {
+    tokio::task::spawn_blocking(|| UploadTargets::load().redacted())
+        .await
+        .map_err(|e| format!("Task error: {}", e))
+}
+
+/// Save an upload target, replacing any target with the same name. Its
+/// credentials go to the OS keychain; redacted ones keep their saved value.
+#[tauri::command]
+async fn save_upload_target(target: UploadTarget) -> Result<UploadTargets, String> {
+    tokio::task::spawn_blocking(move || UploadTargets::save_target(target).map(UploadTargets::redacted))
+        .await
+        .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Delete the upload target called `name`.
+#[tauri::command]
+async fn remove_upload_target(name: String) -> Result<UploadTargets, String> {
+    tokio::task::spawn_blocking(move || UploadTargets::remove_target(&name).map(UploadTargets::redacted))
+        .await
+        .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// A link to an uploaded recording, copied to the clipboard.
+#[derive(Clone, serde::Serialize)]
+struct ShareLink {
+    file_path: std::path::PathBuf,
+    url: String,
+}
+
+/// Start the queued upload `id` as a background job. Once it's uploaded, the
+/// link is saved in the library, copied to the clipboard and announced with
+/// a `share-link` event.
+async fn start_upload(id: u64, app: AppHandle, state: &AppState) {
+    use tauri::Emitter;
+
+    let uploads = state.uploads.clone();
+    let library = state.recording_manager.lock().await.library();
+    let handle = app.clone();
+    let job_id = state.jobs.spawn(app, "upload", move |context| {
+        let file_path = uploads.run(id, context)?;
+        let Some(url) = uploads.url(id) else {
+            return Ok(file_path);
+        };
+        if let Err(e) = library.blocking_lock().set_share_url(&file_path, &url) {
+            tracing::error!(target: "Upload", "Failed to save share link: {}", e);
+        }
+        if let Err(e) = handle.clipboard().write_text(url.clone()) {
+            tracing::error!(target: "Upload", "Failed to copy share link: {}", e);
+        }
+        let _ = handle.emit(
+            "share-link",
+            ShareLink {
+                file_path: file_path.clone(),
+                url,
+            },
+        );
+        Ok(file_path)
+    });
+    state.uploads.set_job(id, job_id);
+}
+
+/// Upload a recording to the target called `target`.
+///
+/// Runs as a background job with progress reported through `job-progress`
+/// events; returns the upload's ID in the queue.
+#[tauri::command]
+async fn upload_recording(
+    file_path: String,
+    target: String,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<u64, String> {
+    let id = state.uploads.add(file_path.into(), &target)?;
+    start_upload(id, app, &state).await;
+    Ok(id)
+}
+
+/// Upload a recording to the first saved target and copy its link once it's
+/// done; the one-click version of `upload_recording`.
+#[tauri::command]
+async fn share_recording(file_path: String, app: AppHandle, state: State<'_, AppState>) -> Result<u64, String> {
+    let target = UploadTargets::load()
+        .targets
+        .into_iter()
+        .next()
+        .ok_or("Add an upload target to share recordings")?;
+    upload_recording(file_path, target.name, app, state).await
+}
+
+/// List this session's uploads, oldest first.
+#[tauri::command]
+async fn get_uploads(state: State<'_, AppState>) -> Result<Vec<UploadEntry>, String> {
+    Ok(state.uploads.list())
+}
+
+/// Cancel a running upload.
+#[tauri::command]
+async fn cancel_upload(id: u64, state: State<'_, AppState>) -> Result<(), String> {
+    state.jobs.cancel(state.uploads.job_id(id)?)
+}
+
+/// Try a failed or cancelled upload again.
+#[tauri::command]
+async fn retry_upload(id: u64, app: AppHandle, state: State<'_, AppState>) -> Result<(), String> {
+    state.uploads.requeue(id)?;
+    start_upload(id, app, &state).await;
+    Ok(())
+}
+
+/// Remove a finished, failed or cancelled upload from the queue.
+#[tauri::command]
+async fn remove_upload(id: u64, state: State<'_, AppState>) -> Result<(), String> {
+    state.uploads.remove(id)
+}
+
+/// Cancel a running background job.
+#[tauri::command]
+async fn cancel_job(job_id: u64, state: State<'_, AppState>) -> Result<(), String> {
+    state.jobs.cancel(job_id)
+}
+
+/// List this session's background jobs with their latest progress, oldest
+/// first.
+#[tauri::command]
+async fn list_jobs(state: State<'_, AppState>) -> Result<Vec<JobProgress>, String> {
+    Ok(state.jobs.list())
+}
+
+/// Get the latest progress of a background job, including its output once
+/// it's completed.
+#[tauri::command]
+async fn get_job(job_id: u64, state: State<'_, AppState>) -> Result<JobProgress, String> {
+    state.jobs.get(job_id)
+}
+
+/// Forget the background jobs that are no longer running.
+#[tauri::command]
+async fn clear_jobs(state: State<'_, AppState>) -> Result<(), String> {
+    state.jobs.clear_finished();
+    Ok(())
+}
+
+/// Place a marker at the current position of the active recording.
+#[tauri::command]
+async fn add_marker(label: Option<String>, state: State<'_, AppState>) -> Result<Marker, String> {
+    let manager = state.recording_manager.lock().await;
+    manager.add_marker(label).await
+}
+
+/// Save the frame the active recording is showing as a PNG, without
+/// interrupting it. Returns the path of the still.
+#[tauri::command]
+async fn save_still(state: State<'_, AppState>) -> Result<String, String> {
+    // Not holding the manager while the still is encoded
+    let stills = state.recording_manager.lock().await.stills().await?;
+    let path = stills.save().await?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// Get the chapter markers of a recording in the library.
+#[tauri::command]
+async fn get_recording_markers(id: u64, state: State<'_, AppState>) -> Result<Vec<Marker>, String> {
+    let library = state.recording_manager.lock().await.library();
+    let library = library.lock().await;
+    Ok(library.get(id)?.markers.clone())
+}
+
+/// Global hotkey that places a marker in the active recording.
+const MARKER_SHORTCUT: &str = "CommandOrControl+Shift+M";
+
+/// Global hotkey that saves a still of the active recording.
+const STILL_SHORTCUT: &str = "CommandOrControl+Alt+P";
+
+/// Global hotkey that records the last selection again.
+const RECORD_LAST_SHORTCUT: &str = "CommandOrControl+Shift+R";
+
+/// Event asking the frontend to record the last selection again.
+const RECORD_LAST_EVENT: &str = "record-last";
+
+/// Scene hotkeys registered, so they can be replaced when scenes change.
+static SCENE_HOTKEYS: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+
+/// Register the hotkeys of `scenes` to switch to them, replacing the ones
+/// registered before.
+fn register_scene_hotkeys(app: &AppHandle, scenes: &[Scene]) {
+    let shortcuts = app.global_shortcut();
+    let mut registered = SCENE_HOTKEYS.lock().unwrap();
+    for hotkey in registered.drain(..) {
+        let _ = shortcuts.unregister(hotkey.as_str());
+    }
+    for scene in scenes {
+        let Some(hotkey) = scene.hotkey.clone() else {
+            continue;
+        };
+        let name = scene.name.clone();
+        let result = shortcuts.on_shortcut(hotkey.as_str(), move |app, _shortcut, event| {
+            if event.state == ShortcutState::Pressed {
+                tauri::async_runtime::spawn(switch_scene_from_hotkey(app.clone(), name.clone()));
+            }
+        });
+        match result {
+            Ok(()) => registered.push(hotkey),
+            Err(e) => tracing::error!(target: "Hotkey", "Failed to register {} for scene {:?}: {}", hotkey, scene.name, e),
+        }
+    }
+}
+
+/// Switch to a scene from its hotkey and tell the frontend about it.
+async fn switch_scene_from_hotkey(app: AppHandle, name: String) {
+    use tauri::Emitter;
+    let state = app.state::<AppState>();
+    let Some(scene) = state.scenes.lock().await.get(&name).cloned() else {
+        return;
+    };
+    let manager = state.recording_manager.lock().await;
+    match manager.switch_scene(scene, scene::default_transition()).await {
+        Ok(()) => {
+            let _ = app.emit("scene-switched", name);
+        }
+        Err(e) => tracing::error!(target: "Hotkey", "Failed to switch to scene {:?}: {}", name, e),
+    }
+}
+
+/// Place a marker from the hotkey and tell the frontend about it.
+async fn add_marker_from_hotkey(app: AppHandle) {
+    use tauri::Emitter;
+    let state = app.state::<AppState>();
+    let manager = state.recording_manager.lock().await;
+    match manager.add_marker(None).await {
+        Ok(marker) => {
+            let _ = app.emit("marker-added", marker);
+        }
+        Err(e) => tracing::error!(target: "Hotkey", "Failed to add marker: {}", e),
+    }
+}
+
+/// Save a still from the hotkey and tell the frontend where it went.
+async fn save_still_from_hotkey(app: AppHandle) {
+    use tauri::Emitter;
+    let state = app.state::<AppState>();
+    let stills = state.recording_manager.lock().await.stills().await;
+    let saved = match stills {
+        Ok(stills) => stills.save().await,
+        Err(e) => Err(e),
+    };
+    match saved {
+        Ok(path) => {
+            let _ = app.emit("still-saved", path.to_string_lossy().to_string());
+        }
+        Err(e) => tracing::error!(target: "Hotkey", "Failed to save a still: {}", e),
+    }
+}
+
+/// List recordings in the library, newest first, optionally filtered by a search query.
+#[tauri::command]
+async fn list_recordings(
+    query: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Vec<RecordingEntry>, String> {
+    let library = state.recording_manager.lock().await.library();
+    let library = library.lock().await;
+    Ok(library.list(query.as_deref()))
+}
+
+/// Append the next recording to the library recording `id` (an MKV or
+/// WebM, such as one recovered after a crash) instead of saving it as a new
+/// file, or stop doing so with `None`. Applies to one recording.
+#[tauri::command]
+async fn set_continue_recording(
+    id: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Option<RecordingEntry>, String> {
+    let manager = state.recording_manager.lock().await;
+    manager.set_continue_target(id).await
+}
+
+/// Get the library recording the next recording is appended to.
+#[tauri::command]
+async fn get_continue_recording(state: State<'_, AppState>) -> Result<Option<RecordingEntry>, String> {
+    let manager = state.recording_manager.lock().await;
+    Ok(manager.continue_target().await)
+}
+
+/// Rename a recording's file on disk.
+#[tauri::command]
+async fn rename_recording(
+    id: u64,
+    new_name: String,
+    state: State<'_, AppState>,
+) -> Result<RecordingEntry, String> {
+    let library = state.recording_manager.lock().await.library();
+    let mut library = library.lock().await;
+    library.rename(id, &new_name)
+}
+
+/// Remove a recording from the library, deleting the file too if `delete_file` is set.
+#[tauri::command]
+async fn delete_recording(
+    id: u64,
+    delete_file: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let library = state.recording_manager.lock().await.library();
+    let mut library = library.lock().await;
+    library.delete(id, delete_file.unwrap_or(false))
+}
+
+/// Get the retention policy for old recordings.
+#[tauri::command]
+async fn get_retention_settings() -> Result<RetentionSettings, String> {
+    Ok(RetentionSettings::load())
+}
+
+/// Set the retention policy for old recordings.
+#[tauri::command]
+async fn set_retention_settings(settings: RetentionSettings) -> Result<(), String> {
+    settings.save()
+}
+
+/// Apply the retention policy now, or with `dry_run` only report which
+/// recordings it would remove. `settings` defaults to the saved policy, so
+/// changes can be previewed before they're saved.
+#[tauri::command]
+async fn run_retention(
+    settings: Option<RetentionSettings>,
+    dry_run: bool,
+    state: State<'_, AppState>,
+) -> Result<RetentionReport, String> {
+    let settings = settings.unwrap_or_else(RetentionSettings::load);
+    let library = state.recording_manager.lock().await.library();
+    tokio::task::spawn_blocking(move || retention::run(&mut library.blocking_lock(), &settings, dry_run))
+        .await
+        .map_err(|e| format!("Task error: {}", e))
+}
+
+/// Get the voice trigger settings.
+#[tauri::command]
+async fn get_voice_trigger() -> Result<VoiceTriggerSettings, String> {
+    Ok(VoiceTriggerSettings::load())
+}
+
+/// Set the voice trigger settings, starting or stopping the microphone
+/// monitor to match.
+#[tauri::command]
+async fn set_voice_trigger(settings: VoiceTriggerSettings, app: AppHandle) -> Result<(), String> {
+    settings.save()?;
+    voice::apply(&app, settings);
+    Ok(())
+}
+
+/// Get the control API settings, including the token clients must send.
+#[tauri::command]
+async fn get_control_api() -> Result<ControlSettings, String> {
+    Ok(ControlSettings::load())
+}
+
+/// Turn the local control API on or off, serving it on `port`.
+#[tauri::command]
+async fn set_control_api(enabled: bool, port: u16, app: AppHandle) -> Result<ControlSettings, String> {
+    let settings = ControlSettings {
+        enabled,
+        port,
+        ..ControlSettings::load()
+    };
+    control::apply(&app, settings.clone())?;
+    settings.save()?;
+    Ok(settings)
+}
+
+/// Replace the control API token, so clients given the old one lose access.
+#[tauri::command]
+async fn regenerate_control_token(app: AppHandle) -> Result<ControlSettings, String> {
+    let settings = ControlSettings {
+        token: control::new_token(),
+        ..ControlSettings::load()
+    };
+    control::apply(&app, settings.clone())?;
+    settings.save()?;
+    Ok(settings)
+}
+
+/// Get the companion server settings and the address of its remote control
+/// page.
+#[tauri::command]
+async fn get_companion() -> Result<CompanionInfo, String> {
+    Ok(CompanionSettings::load().into())
+}
+
+/// Turn the companion server on or off, serving it to the local network on
+/// `port` and advertising it as `name` (the host name if empty).
+#[tauri::command]
+async fn set_companion(enabled: bool, port: u16, name: String, app: AppHandle) -> Result<CompanionInfo, String> {
+    let settings = CompanionSettings {
+        enabled,
+        port,
+        name,
+        ..CompanionSettings::load()
+    };
+    companion::apply(&app, &settings)?;
+    settings.save()?;
+    Ok(settings.into())
+}
+
+/// Replace the companion token, so phones given the old one lose access.
+#[tauri::command]
+async fn regenerate_companion_token(app: AppHandle) -> Result<CompanionInfo, String> {
+    let settings = CompanionSettings {
+        token: control::new_token(),
+        ..CompanionSettings::load()
+    };
+    companion::apply(&app, &settings)?;
+    settings.save()?;
+    Ok(settings.into())
+}
+
+/// Get the webhooks notified of recording events.
+#[tauri::command]
+async fn get_webhooks() -> Result<WebhookSettings, String> {
+    Ok(WebhookSettings::load())
+}
+
+/// Replace the webhooks notified of recording events.
+#[tauri::command]
+async fn set_webhooks(settings: WebhookSettings) -> Result<(), String> {
+    settings.save()
+}
+
+/// Get the metadata tags written into recordings.
+#[tauri::command]
+async fn get_metadata_tags() -> Result<TagSettings, String> {
+    Ok(TagSettings::load())
+}
+
+/// Set the metadata tags written into recordings as they're finalized.
+#[tauri::command]
+async fn set_metadata_tags(settings: TagSettings) -> Result<(), String> {
+    settings.save()
+}
+
+/// Get what's tidied on the desktop before recording.
+#[tauri::command]
+async fn get_desktop_cleanup() -> Result<DesktopCleanup, String> {
+    Ok(DesktopCleanup::load())
+}
+
+/// Set what's tidied on the desktop before recording: hiding the icons and
+/// a plain wallpaper on the captured monitor.
+#[tauri::command]
+async fn set_desktop_cleanup(settings: DesktopCleanup) -> Result<(), String> {
+    settings.save()
+}
+
+/// Show a recording in the system file manager.
+#[tauri::command]
+async fn reveal_recording(id: u64, state: State<'_, AppState>) -> Result<(), String> {
+    let library = state.recording_manager.lock().await.library();
+    let file_path = library.lock().await.get(id)?.file_path.clone();
+    tauri_plugin_opener::reveal_item_in_dir(&file_path)
+        .map_err(|e| format!("Failed to reveal recording: {}", e))
+}
+
+/// Show a highlight border on the specified monitor.
+#[tauri::command]
+async fn show_display_highlight(
+    monitor_id: String,
+) -> Result<(), String> {
+    // Find the monitor
+    let layout = DesktopLayout::current();
+    let monitor = layout
+        .monitor(&monitor_id)
+        .ok_or_else(|| i18n::tr_args("error-monitor-not-found", &[("monitor", &monitor_id)]))?;
+
+    let bounds = layout.monitor_bounds(monitor);
+    show_highlight(bounds.x, bounds.y, bounds.width as i32, bounds.height as i32);
+
+    Ok(())
+}
+
+/// Dismiss the display highlight before it fades out by itself.
+#[tauri::command]
+fn hide_display_highlight() {
+    hide_highlight();
+}
+
+/// Turn an area of the desktop, as the region selector sees it, into a
+/// capture region on the monitor showing most of it.
+#[tauri::command]
+fn resolve_desktop_region(x: i32, y: i32, width: u32, height: u32) -> Result<CaptureRegion, String> {
+    DesktopLayout::current()
+        .resolve_region(WindowBounds { x, y, width, height })
+        .ok_or_else(|| "Region is not on any monitor".to_string())
+}
+
+/// Settle an area of the desktop, as the region selector sees it, once the
+/// user stops moving it: with `snap`, its edges are snapped to the edges of
+/// the monitors and of the windows around it, and with `aspect_ratio` its
+/// size is fitted to the ratio, keeping its width if `keep_width` is set.
+#[tauri::command]
+fn fit_desktop_region(
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    snap: bool,
+    aspect_ratio: Option<AspectRatio>,
+    keep_width: bool,
+) -> WindowBounds {
+    let mut area = WindowBounds { x, y, width, height };
+    if snap {
+        let windows: Vec<WindowBounds> = capture::list_window_bounds()
+            .unwrap_or_default()
+            .into_iter()
+            // Not the selector itself
+            .filter(|(window, _)| window.title != "Region Selection")
+            .map(|(_, bounds)| bounds)
+            .collect();
+        area = DesktopLayout::current().snap(area, &windows);
+    }
+    if let Some(ratio) = aspect_ratio {
+        (area.width, area.height) = ratio.fit(area.width, area.height, keep_width);
+    }
+    area
+}
+
+/// Get the region selector settings.
+#[tauri::command]
+async fn get_region_selector_settings() -> Result<RegionSelectorSettings, String> {
+    Ok(RegionSelectorSettings::load())
+}
+
+/// Set the region selector settings, applying them to an open selector.
+#[tauri::command]
+async fn set_region_selector_settings(settings: RegionSelectorSettings, app: AppHandle) -> Result<(), String> {
+    use tauri::Emitter;
+
+    settings.save()?;
+    app.emit("region-selector-settings", &settings)
+        .map_err(|e| format!("Failed to emit settings: {}", e))
+}
+
+/// A region of the given size in the middle of a monitor, by default the
+/// primary one, for placing a preset-sized selection.
+#[tauri::command]
+fn preset_region(width: u32, height: u32, monitor_id: Option<String>) -> Result<CaptureRegion, String> {
+    region_selector::preset_region(&capture::list_monitors(), monitor_id.as_deref(), width, height)
+        .ok_or_else(|| "No monitors found".to_string())
+}
+
+/// Area of the desktop a capture region covers, as the region selector sees
+/// it.
+#[tauri::command]
+fn desktop_region_bounds(region: CaptureRegion) -> Result<WindowBounds, String> {
+    DesktopLayout::current()
+        .region_bounds(&region)
+        .ok_or_else(|| format!("Monitor {} is not connected", region.monitor_id))
+}
+
+/// Create a virtual display to record, returning its monitor ID.
+#[tauri::command]
+fn create_virtual_display(width: u32, height: u32) -> Result<String, String> {
+    virtual_display::create(width, height)
+}
+
+/// Remove a virtual display created with `create_virtual_display`.
+#[tauri::command]
+fn remove_virtual_display(monitor_id: String) -> Result<(), String> {
+    virtual_display::remove(&monitor_id)
+}
+
+/// Monitor IDs of the virtual displays created with `create_virtual_display`.
 #[tauri::command]
-fn get_windows() -> Vec<WindowInfo> {
-    list_windows()
+fn list_virtual_displays() -> Vec<String> {
+    virtual_display::created()
 }
 
-/// Get list of available monitors.
+/// Capture the stills the region selector's magnifier samples from.
 #[tauri::command]
-fn get_monitors() -> Vec<MonitorInfo> {
-    list_monitors()
+async fn prepare_magnifier() -> Result<(), String> {
+    magnifier::prepare().await
 }
 
-/// Get current recording state.
+/// The pixels around a point of the desktop, as the region selector sees it.
 #[tauri::command]
-async fn get_recording_state(state: State<'_, AppState>) -> Result<RecordingState, String> {
-    let manager = state.recording_manager.lock().await;
-    Ok(manager.get_state().await)
+fn sample_magnifier(x: f64, y: f64) -> Result<MagnifierSample, String> {
+    magnifier::sample(x, y)
 }
 
-/// Start recording the specified window.
+/// Drop the magnifier's stills once the region selector closes.
 #[tauri::command]
-async fn start_recording(
-    window_handle: isize,
-    state: State<'_, AppState>,
-) -> Result<(), String> {
-    if !state.ffmpeg_ready {
-        return Err("FFmpeg is not available. Please restart the application.".to_string());
-    }
-    let manager = state.recording_manager.lock().await;
-    manager.start_recording(window_handle).await
+fn release_magnifier() {
+    magnifier::release();
 }
 
-/// Start recording a screen region.
-#[tauri::command]
-async fn start_region_recording(
-    monitor_id: String,
-    x: i32,
-    y: i32,
-    width: u32,
-    height: u32,
-    state: State<'_, AppState>,
-) -> Result<(), String> {
-    if !state.ffmpeg_ready {
-        return Err("FFmpeg is not available. Please restart the application.".to_string());
+/// Emit events about recordings' captures to the frontend as `capture-event`
+/// events. The frontend stops the recording on `ended`, finishing the file.
+async fn forward_capture_events(app: AppHandle) {
+    use tauri::Emitter;
+    use tokio::sync::broadcast::error::RecvError;
+
+    let manager = app.state::<AppState>().recording_manager.clone();
+    let mut events = manager.lock().await.subscribe_capture_events();
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                let _ = app.emit("capture-event", event);
+            }
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        }
     }
+}
 
-    let region = CaptureRegion {
-        monitor_id,
-        x,
-        y,
-        width,
-        height,
-    };
+/// Emit each change of the recording's phase to the frontend as a
+/// `recording-phase` event, tagged by its `phase`.
+async fn forward_phase_events(app: AppHandle) {
+    use tauri::Emitter;
+    use tokio::sync::broadcast::error::RecvError;
 
-    let manager = state.recording_manager.lock().await;
-    manager.start_region_recording(region).await
+    let manager = app.state::<AppState>().recording_manager.clone();
+    let mut events = manager.lock().await.subscribe_phase_events();
+    loop {
+        match events.recv().await {
+            Ok(phase) => {
+                let _ = app.emit("recording-phase", phase);
+            }
+            // Phases were missed, so send the one the recording is in now
+            Err(RecvError::Lagged(_)) => {
+                let _ = app.emit("recording-phase", manager.lock().await.get_phase());
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
 }
 
-/// Start recording an entire display.
-#[tauri::command]
-async fn start_display_recording(
-    monitor_id: String,
-    state: State<'_, AppState>,
-) -> Result<(), String> {
-    if !state.ffmpeg_ready {
-        return Err("FFmpeg is not available. Please restart the application.".to_string());
+/// Emit warnings as the recording's disk fills up to the frontend as
+/// `disk-space` events. The frontend stops the recording on `stopping`,
+/// finishing the file.
+async fn forward_disk_events(app: AppHandle) {
+    use tauri::Emitter;
+    use tokio::sync::broadcast::error::RecvError;
+
+    let manager = app.state::<AppState>().recording_manager.clone();
+    let mut events = manager.lock().await.subscribe_disk_events();
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                let _ = app.emit("disk-space", event);
+            }
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        }
     }
+}
 
-    // Find the monitor to get its dimensions
-    let monitors = list_monitors();
-    let monitor = monitors
-        .iter()
-        .find(|m| m.id == monitor_id)
-        .ok_or_else(|| format!("Monitor not found: {}", monitor_id))?;
+/// Emit recordings reaching their duration or size limit to the frontend as
+/// `recording-limit` events. The frontend then stops the recording, which
+/// finishes the file.
+async fn forward_limit_events(app: AppHandle) {
+    use tauri::Emitter;
+    use tokio::sync::broadcast::error::RecvError;
 
-    let manager = state.recording_manager.lock().await;
-    manager
-        .start_display_recording(monitor_id, monitor.width, monitor.height)
-        .await
+    let manager = app.state::<AppState>().recording_manager.clone();
+    let mut events = manager.lock().await.subscribe_limit_events();
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                let _ = app.emit("recording-limit", event);
+            }
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        }
+    }
 }
 
-/// Stop the current recording and save the file.
-#[tauri::command]
-async fn stop_recording(state: State<'_, AppState>) -> Result<RecordingResult, String> {
-    let manager = state.recording_manager.lock().await;
-    manager.stop_recording().await
-}
+/// Emit the changes the power policy makes to recordings to the frontend as
+/// `quality-degraded` events.
+async fn forward_quality_events(app: AppHandle) {
+    use tauri::Emitter;
+    use tokio::sync::broadcast::error::RecvError;
 
-/// Get elapsed recording time in seconds.
-#[tauri::command]
-async fn get_elapsed_time(state: State<'_, AppState>) -> Result<u64, String> {
-    let manager = state.recording_manager.lock().await;
-    Ok(manager.get_elapsed_seconds().await)
+    let manager = app.state::<AppState>().recording_manager.clone();
+    let mut events = manager.lock().await.subscribe_quality_events();
+    loop {
+        match events.recv().await {
+            Ok(degradation) => {
+                let _ = app.emit(power::DEGRADED_EVENT, degradation);
+            }
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        }
+    }
 }
 
-/// Show a highlight border on the specified monitor.
-#[tauri::command]
-async fn show_display_highlight(
-    monitor_id: String,
-) -> Result<(), String> {
-    // Find the monitor
-    let monitors = list_monitors();
-    let monitor = monitors
-        .iter()
-        .find(|m| m.id == monitor_id)
-        .ok_or_else(|| format!("Monitor not found: {}", monitor_id))?;
+/// Emit the live stream's bitrate changes to the frontend as
+/// `network-congestion` events.
+async fn forward_congestion_events(app: AppHandle) {
+    use tauri::Emitter;
+    use tokio::sync::broadcast::error::RecvError;
 
-    show_highlight(
-        monitor.x,
-        monitor.y,
-        monitor.width as i32,
-        monitor.height as i32,
-    );
+    let mut events = encoder::abr::subscribe();
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                let _ = app.emit(encoder::abr::CONGESTION_EVENT, event);
+            }
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
 
-    Ok(())
+/// Emit picker activity to the frontend as `portal-event` events.
+#[cfg(target_os = "linux")]
+async fn forward_portal_events(app: AppHandle) {
+    use tauri::Emitter;
+    use tokio::sync::broadcast::error::RecvError;
+
+    let Some(state) = linux::get_ipc_state() else {
+        return;
+    };
+    let mut events = state.read().await.subscribe();
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                let _ = app.emit("portal-event", event);
+            }
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        }
+    }
 }
 
 /// Configure Hyprland window rules for the region selector.
@@ -199,11 +2590,11 @@ async fn show_display_highlight(
 #[cfg(target_os = "linux")]
 #[tauri::command]
 async fn configure_region_selector_window(window_label: String) -> Result<(), String> {
-    eprintln!("[configure_region_selector] Configuring Hyprland rules for window: {}", window_label);
+    tracing::info!(target: "configure_region_selector", "Configuring Hyprland rules for window: {}", window_label);
     
     // Check if we're on Hyprland
     if std::env::var("HYPRLAND_INSTANCE_SIGNATURE").is_err() {
-        eprintln!("[configure_region_selector] Not on Hyprland, skipping");
+        tracing::warn!(target: "configure_region_selector", "Not on Hyprland, skipping");
         return Ok(());
     }
     
@@ -227,20 +2618,20 @@ async fn configure_region_selector_window(window_label: String) -> Result<(), St
     // Execute commands via hyprctl
     for rule in rules {
         let output = std::process::Command::new("hyprctl")
-            .args(&["keyword", "windowrulev2", rule])
+            .args(["keyword", "windowrulev2", rule])
             .output();
             
         match output {
             Ok(result) => {
                 if result.status.success() {
-                    eprintln!("[configure_region_selector] Applied: {}", rule);
+                    tracing::info!(target: "configure_region_selector", "Applied: {}", rule);
                 } else {
                     let err = String::from_utf8_lossy(&result.stderr);
-                    eprintln!("[configure_region_selector] Failed to apply rule: {} - {}", rule, err);
+                    tracing::error!(target: "configure_region_selector", "Failed to apply rule: {} - {}", rule, err);
                 }
             }
             Err(e) => {
-                eprintln!("[configure_region_selector] Failed to execute hyprctl: {}", e);
+                tracing::error!(target: "configure_region_selector", "Failed to execute hyprctl: {}", e);
             }
         }
     }
@@ -274,8 +2665,9 @@ async fn get_region_selector_position() -> Result<(i32, i32, i32, i32), String>
     
     for client in clients {
         if client.title == "Region Selection" {
-            eprintln!("[get_region_selector_position] Found window at ({}, {}) size {}x{}", 
+            tracing::info!(target: "get_region_selector_position", "Found window at ({}, {}) size {}x{}", 
                 client.at.0, client.at.1, client.size.0, client.size.1);
+            // Hyprland reports logical layout coordinates
             return Ok((client.at.0 as i32, client.at.1 as i32, client.size.0 as i32, client.size.1 as i32));
         }
     }
@@ -290,6 +2682,36 @@ async fn get_region_selector_position() -> Result<(i32, i32, i32, i32), String>
     Err("Only available on Linux".to_string())
 }
 
+/// Move and resize the region selector window through Hyprland, in logical
+/// layout coordinates. Tauri's setPosition() doesn't work on Wayland either.
+#[cfg(target_os = "linux")]
+#[tauri::command]
+async fn move_region_selector(x: i32, y: i32, width: u32, height: u32) -> Result<(), String> {
+    let window = "title:^(Region Selection)$";
+    let batch = format!(
+        "dispatch movewindowpixel exact {} {},{} ; dispatch resizewindowpixel exact {} {},{}",
+        x, y, window, width, height, window
+    );
+    let output = std::process::Command::new("hyprctl")
+        .args(["--batch", &batch])
+        .output()
+        .map_err(|e| format!("Failed to execute hyprctl: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to move the region selector: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    Ok(())
+}
+
+/// Stub for non-Linux platforms.
+#[cfg(not(target_os = "linux"))]
+#[tauri::command]
+async fn move_region_selector(_x: i32, _y: i32, _width: u32, _height: u32) -> Result<(), String> {
+    Err("Only available on Linux".to_string())
+}
+
 /// Stub for non-Linux platforms.
 #[cfg(not(target_os = "linux"))]
 #[tauri::command]
@@ -302,7 +2724,7 @@ async fn configure_region_selector_window(_window_label: String) -> Result<(), S
 #[cfg(target_os = "linux")]
 #[tauri::command]
 async fn test_linux_portal(monitor_id: String) -> Result<String, String> {
-    eprintln!("[test_linux_portal] Called with monitor_id: {}", monitor_id);
+    tracing::info!(target: "test_linux_portal", "Called with monitor_id: {}", monitor_id);
     linux::test_portal_flow(&monitor_id).await
 }
 
@@ -313,26 +2735,623 @@ async fn test_linux_portal(_monitor_id: String) -> Result<String, String> {
     Err("Portal test is only available on Linux".to_string())
 }
 
+/// Check each link between the portal and the app's picker (Linux only).
+#[cfg(target_os = "linux")]
+#[tauri::command]
+async fn get_picker_health() -> Result<linux::picker_health::PickerHealth, String> {
+    Ok(linux::picker_health::check().await)
+}
+
+/// Stub for non-Linux platforms.
+#[cfg(not(target_os = "linux"))]
+#[tauri::command]
+async fn get_picker_health() -> Result<(), String> {
+    Err("The portal picker is only used on Linux".to_string())
+}
+
+/// Snapshot how PipeWire's audio nodes are connected, marking the ones
+/// recordings use, and write its summary to the log (Linux only).
+#[cfg(target_os = "linux")]
+#[tauri::command]
+async fn get_audio_graph() -> Result<linux::audio_graph::AudioGraph, String> {
+    let graph = tokio::task::spawn_blocking(linux::audio_graph::snapshot)
+        .await
+        .map_err(|e| format!("Task error: {}", e))??;
+    for line in graph.summary() {
+        tracing::info!(target: "AudioGraph", "{}", line);
+    }
+    Ok(graph)
+}
+
+/// Stub for non-Linux platforms.
+#[cfg(not(target_os = "linux"))]
+#[tauri::command]
+async fn get_audio_graph() -> Result<(), String> {
+    Err("The PipeWire audio graph is only available on Linux".to_string())
+}
+
+/// Restart the picker's IPC server and xdg-desktop-portal-hyprland, and
+/// check the picker's health again (Linux only).
+#[cfg(target_os = "linux")]
+#[tauri::command]
+async fn restart_picker_service() -> Result<linux::picker_health::PickerHealth, String> {
+    linux::picker_health::restart().await
+}
+
+/// Stub for non-Linux platforms.
+#[cfg(not(target_os = "linux"))]
+#[tauri::command]
+async fn restart_picker_service() -> Result<(), String> {
+    Err("The portal picker is only used on Linux".to_string())
+}
+
+/// Install the portal picker from `picker_path`, or the binary next to the
+/// app, and point xdg-desktop-portal-hyprland at it (Linux only).
+#[cfg(target_os = "linux")]
+#[tauri::command]
+async fn install_picker(picker_path: Option<String>) -> Result<linux::picker_health::PickerHealth, String> {
+    linux::picker_install::install(picker_path.map(std::path::PathBuf::from)).await
+}
+
+/// Stub for non-Linux platforms.
+#[cfg(not(target_os = "linux"))]
+#[tauri::command]
+async fn install_picker(_picker_path: Option<String>) -> Result<(), String> {
+    Err("The portal picker is only used on Linux".to_string())
+}
+
+/// Stop xdg-desktop-portal-hyprland using the portal picker and remove it
+/// (Linux only).
+#[cfg(target_os = "linux")]
+#[tauri::command]
+async fn uninstall_picker() -> Result<linux::picker_health::PickerHealth, String> {
+    linux::picker_install::uninstall().await
+}
+
+/// Stub for non-Linux platforms.
+#[cfg(not(target_os = "linux"))]
+#[tauri::command]
+async fn uninstall_picker() -> Result<(), String> {
+    Err("The portal picker is only used on Linux".to_string())
+}
+
+/// Get which apps may share the screen through the portal picker (Linux
+/// only).
+#[cfg(target_os = "linux")]
+#[tauri::command]
+async fn get_picker_policy() -> Result<linux::picker_policy::PickerPolicy, String> {
+    match linux::get_ipc_state() {
+        Some(state) => Ok(state.read().await.policy.clone()),
+        None => Ok(linux::picker_policy::PickerPolicy::load()),
+    }
+}
+
+/// Stub for non-Linux platforms.
+#[cfg(not(target_os = "linux"))]
+#[tauri::command]
+async fn get_picker_policy() -> Result<(), String> {
+    Err("The portal picker is only used on Linux".to_string())
+}
+
+/// Save which apps may share the screen through the portal picker, which
+/// gets it on its next request (Linux only).
+///
+/// Per-app entries match the app the request was seen coming from on the
+/// session bus. A Flatpak build of this app can't watch the bus, so there
+/// only the default applies, unless the picker is given `--app-id=<id>`.
+#[cfg(target_os = "linux")]
+#[tauri::command]
+async fn set_picker_policy(mut policy: linux::picker_policy::PickerPolicy) -> Result<(), String> {
+    // Revocations are only changed by revoke_portal_permission
+    policy.revoked = match linux::get_ipc_state() {
+        Some(state) => state.read().await.policy.revoked.clone(),
+        None => linux::picker_policy::PickerPolicy::load().revoked,
+    };
+    policy.save()?;
+    if let Some(state) = linux::get_ipc_state() {
+        state.write().await.policy = policy;
+    }
+    Ok(())
+}
+
+/// Stub for non-Linux platforms.
+#[cfg(not(target_os = "linux"))]
+#[tauri::command]
+async fn set_picker_policy(_policy: serde_json::Value) -> Result<(), String> {
+    Err("The portal picker is only used on Linux".to_string())
+}
+
+/// Get the selections other apps are given when they request a screencast
+/// through the portal picker, by app ID (Linux only).
+#[cfg(target_os = "linux")]
+#[tauri::command]
+async fn get_portal_app_selections(
+) -> Result<std::collections::HashMap<String, linux::ipc_server::CaptureSelection>, String> {
+    let state = linux::get_ipc_state().ok_or("IPC server not initialized")?;
+    let selections = state.read().await.app_selections.clone();
+    Ok(selections)
+}
+
+/// Stub for non-Linux platforms.
+#[cfg(not(target_os = "linux"))]
+#[tauri::command]
+async fn get_portal_app_selections() -> Result<(), String> {
+    Err("The portal picker is only used on Linux".to_string())
+}
+
+/// Share a monitor, window or region with `app_id` whenever it requests a
+/// screencast through the portal picker, while other apps get theirs or the
+/// current selection. Without a source type, the app goes back to getting
+/// the current selection (Linux only).
+#[cfg(target_os = "linux")]
+#[tauri::command]
+async fn set_portal_app_selection(
+    app_id: String,
+    source_type: Option<String>,
+    source_id: Option<String>,
+    region: Option<CaptureRegion>,
+) -> Result<(), String> {
+    if app_id.trim().is_empty() {
+        return Err("App ID can't be empty".to_string());
+    }
+    let state = linux::get_ipc_state().ok_or("IPC server not initialized")?;
+    let selection = match source_type {
+        Some(source_type) => Some(linux::app_selection(&source_type, source_id.as_deref().unwrap_or_default(), region)?),
+        None => None,
+    };
+    linux::ipc_server::set_app_selection(&state, app_id, selection).await;
+    Ok(())
+}
+
+/// Stub for non-Linux platforms.
+#[cfg(not(target_os = "linux"))]
+#[tauri::command]
+async fn set_portal_app_selection(
+    _app_id: String,
+    _source_type: Option<String>,
+    _source_id: Option<String>,
+    _region: Option<CaptureRegion>,
+) -> Result<(), String> {
+    Err("The portal picker is only used on Linux".to_string())
+}
+
+/// Get the approvals the portal picker remembers for apps that asked for
+/// their screencasts to persist, as it last reported them (Linux only).
+#[cfg(target_os = "linux")]
+#[tauri::command]
+async fn get_portal_permissions() -> Result<Vec<linux::ipc_server::PortalPermission>, String> {
+    match linux::get_ipc_state() {
+        Some(state) => Ok(state.read().await.permissions.clone()),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Stub for non-Linux platforms.
+#[cfg(not(target_os = "linux"))]
+#[tauri::command]
+async fn get_portal_permissions() -> Result<(), String> {
+    Err("The portal picker is only used on Linux".to_string())
+}
+
+/// Revoke the approvals the portal picker remembers for `app_id`. The
+/// picker drops them on its next request (Linux only).
+#[cfg(target_os = "linux")]
+#[tauri::command]
+async fn revoke_portal_permission(app_id: String) -> Result<(), String> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    match linux::get_ipc_state() {
+        Some(state) => {
+            let mut state = state.write().await;
+            let mut policy = state.policy.clone();
+            policy.revoked.insert(app_id.clone(), now);
+            policy.save()?;
+            state.policy = policy;
+            state.permissions.retain(|permission| permission.app_id != app_id);
+        }
+        None => {
+            let mut policy = linux::picker_policy::PickerPolicy::load();
+            policy.revoked.insert(app_id, now);
+            policy.save()?;
+        }
+    }
+    Ok(())
+}
+
+/// Stub for non-Linux platforms.
+#[cfg(not(target_os = "linux"))]
+#[tauri::command]
+async fn revoke_portal_permission(_app_id: String) -> Result<(), String> {
+    Err("The portal picker is only used on Linux".to_string())
+}
+
+/// Run a headless CLI command if `args` name one.
+///
+/// Returns the exit code, or `None` if the app should start normally.
+pub fn run_cli(args: &[String]) -> Option<i32> {
+    cli::run(args)
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    // Log to files as well as stderr, for self-diagnosis and bug reports
+    logging::init();
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_clipboard_manager::init())
+        .plugin(tauri_plugin_global_shortcut::Builder::new().build())
         .manage(AppState::new())
+        .setup(|app| {
+            // Push live recording stats to the frontend
+            let manager = app.state::<AppState>().recording_manager.clone();
+            tauri::async_runtime::spawn(stats::run_reporter(app.handle().clone(), manager.clone()));
+            // Push audio levels for VU meters and silent-microphone warnings
+            tauri::async_runtime::spawn(stats::run_level_reporter(app.handle().clone(), manager));
+            // Tell the frontend when a recording's capture is lost or its window closes
+            tauri::async_runtime::spawn(forward_phase_events(app.handle().clone()));
+            #[cfg(feature = "scripting")]
+            tauri::async_runtime::spawn(scripting::run(app.handle().clone()));
+            // Count recordings for the metrics endpoint
+            #[cfg(feature = "metrics")]
+            {
+                let manager = app.state::<AppState>().recording_manager.clone();
+                tauri::async_runtime::spawn(async move {
+                    let phases = manager.lock().await.subscribe_phase_events();
+                    metrics::count_recordings(phases).await
+                });
+            }
+            tauri::async_runtime::spawn(forward_capture_events(app.handle().clone()));
+            tauri::async_runtime::spawn(forward_quality_events(app.handle().clone()));
+            tauri::async_runtime::spawn(forward_congestion_events(app.handle().clone()));
+            tauri::async_runtime::spawn(forward_disk_events(app.handle().clone()));
+            tauri::async_runtime::spawn(forward_limit_events(app.handle().clone()));
+            tauri::async_runtime::spawn(power::run_monitor(app.handle().clone()));
+            // Keep the app's own windows out of recordings
+            hide_own_windows(app.handle(), capture::exclusion::excludes_self());
+            // Pause recordings while the session is locked
+            tauri::async_runtime::spawn(session::run_monitor(app.handle().clone()));
+            // Tell screen reader users what the recording is doing
+            tauri::async_runtime::spawn(announce::run(app.handle().clone()));
+            // Keep notifications out of recordings
+            tauri::async_runtime::spawn(dnd::run(app.handle().clone()));
+            tauri::async_runtime::spawn(recipes::run(app.handle().clone()));
+            tauri::async_runtime::spawn(display_mode::run(app.handle().clone()));
+            tauri::async_runtime::spawn(environment::run(app.handle().clone()));
+            // Leave the dead air out of long recordings
+            tauri::async_runtime::spawn(idle::run(app.handle().clone()));
+            tauri::async_runtime::spawn(input_track::run(app.handle().clone()));
+            tauri::async_runtime::spawn(captions::run(app.handle().clone()));
+            // Recording controls in the system tray
+            match tray::create(app.handle()) {
+                Ok(()) => {
+                    tauri::async_runtime::spawn(tray::run_indicator(app.handle().clone()));
+                }
+                Err(e) => tracing::error!(target: "Tray", "Failed to create tray icon: {}", e),
+            }
+            // Repair recordings a crash left unfinished, before any new ones start
+            let interrupted = journal::take_interrupted();
+            if !interrupted.is_empty() {
+                let handle = app.handle().clone();
+                tauri::async_runtime::spawn(async move {
+                    use tauri::Emitter;
+                    let library = handle.state::<AppState>().recording_manager.lock().await.library();
+                    let recovery =
+                        tauri::async_runtime::spawn_blocking(move || journal::recover(interrupted, &library)).await;
+                    match recovery {
+                        Ok(recovery) => {
+                            if !recovery.recovered.is_empty() {
+                                let _ = handle.emit(journal::RECOVERED_EVENT, recovery.recovered);
+                            }
+                            // Left on disk, for the user to keep or delete
+                            if !recovery.unrecoverable.is_empty() {
+                                let _ = handle.emit(journal::UNRECOVERABLE_EVENT, recovery.unrecoverable);
+                            }
+                        }
+                        Err(e) => tracing::error!(target: "Journal", "Recovery task error: {}", e),
+                    }
+                });
+            }
+            // Clean up old recordings on the retention policy's schedule
+            let retention_manager = app.state::<AppState>().recording_manager.clone();
+            tauri::async_runtime::spawn(async move {
+                let library = retention_manager.lock().await.library();
+                retention::run_scheduler(library).await
+            });
+            // Start and stop recordings by voice, if enabled
+            voice::apply(app.handle(), VoiceTriggerSettings::load());
+            if let Err(e) = control::apply(app.handle(), ControlSettings::load()) {
+                tracing::error!(target: "Control", "{}", e);
+            }
+            if let Err(e) = companion::apply(app.handle(), &CompanionSettings::load()) {
+                tracing::error!(target: "Companion", "{}", e);
+            }
+            // Ask the screencast portal which cursor modes it offers
+            #[cfg(target_os = "linux")]
+            tauri::async_runtime::spawn(async {
+                if let Err(e) = linux::portal_client::query_portal_info().await {
+                    tracing::warn!(target: "Portal", "{}", e);
+                }
+            });
+            // Tell the frontend when the screen is shared through the portal
+            #[cfg(target_os = "linux")]
+            tauri::async_runtime::spawn(forward_portal_events(app.handle().clone()));
+            // Watch that screen sharing through the portal picker still works
+            #[cfg(target_os = "linux")]
+            tauri::async_runtime::spawn(linux::picker_health::run_watchdog(app.handle().clone()));
+            // Let desktop tools drive recordings over the session bus
+            #[cfg(target_os = "linux")]
+            tauri::async_runtime::spawn(dbus::run(app.handle().clone()));
+            // Tell the frontend when monitors are connected, disconnected or reconfigured
+            let handle = app.handle().clone();
+            capture::hotplug::watch_monitors(move |change| {
+                use tauri::Emitter;
+                let _ = handle.emit("monitors-changed", change);
+            });
+            // Place markers from the hotkey, even while another app has focus
+            let registered = app.global_shortcut().on_shortcut(MARKER_SHORTCUT, |app, _shortcut, event| {
+                if event.state == ShortcutState::Pressed {
+                    tauri::async_runtime::spawn(add_marker_from_hotkey(app.clone()));
+                }
+            });
+            if let Err(e) = registered {
+                tracing::error!(target: "Hotkey", "Failed to register {}: {}", MARKER_SHORTCUT, e);
+            }
+            // Save stills of the recording from the hotkey
+            let registered = app.global_shortcut().on_shortcut(STILL_SHORTCUT, |app, _shortcut, event| {
+                if event.state == ShortcutState::Pressed {
+                    tauri::async_runtime::spawn(save_still_from_hotkey(app.clone()));
+                }
+            });
+            if let Err(e) = registered {
+                tracing::error!(target: "Hotkey", "Failed to register {}: {}", STILL_SHORTCUT, e);
+            }
+            // Record the last selection again from the hotkey. The frontend
+            // holds the selection and recording state, so it starts it
+            let registered = app.global_shortcut().on_shortcut(RECORD_LAST_SHORTCUT, |app, _shortcut, event| {
+                if event.state == ShortcutState::Pressed {
+                    use tauri::Emitter;
+                    let _ = app.emit(RECORD_LAST_EVENT, ());
+                }
+            });
+            if let Err(e) = registered {
+                tracing::error!(target: "Hotkey", "Failed to register {}: {}", RECORD_LAST_SHORTCUT, e);
+            }
+            // Switch scenes from their hotkeys
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let scenes = handle.state::<AppState>().scenes.lock().await.list();
+                register_scene_hotkeys(&handle, &scenes);
+            });
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             get_windows,
             get_monitors,
-            get_recording_state,
+            get_video_devices,
+            get_capabilities,
+            get_permissions,
+            request_permission,
+            get_recording_phase,
             start_recording,
+            find_window,
+            start_matching_window_recording,
             start_region_recording,
             start_display_recording,
             stop_recording,
+            set_countdown,
+            get_countdown,
+            cancel_countdown,
+            set_locale,
+            get_locale,
             get_elapsed_time,
+            get_output_settings,
+            set_ndi_output,
+            set_hls_output,
+            get_hls_url,
+            set_replay_buffer,
+            get_disk_space_settings,
+            set_disk_space_settings,
+            get_recording_limits,
+            set_recording_limits,
+            schedule_stop,
+            cancel_scheduled_stop,
+            save_replay,
+            list_profiles,
+            get_selected_profile,
+            select_profile,
+            save_profile,
+            delete_profile,
+            estimate_recording_size,
+            export_profile,
+            import_profile,
+            list_recipes,
+            save_recipe,
+            delete_recipe,
+            start_recipe_recording,
+            list_scenes,
+            save_scene,
+            delete_scene,
+            start_scene_recording,
+            switch_scene,
+            set_scene_transition,
+            set_chroma_key,
+            list_layouts,
+            save_layout,
+            delete_layout,
+            apply_layout,
+            get_window_exclusions,
+            add_window_exclusion,
+            remove_window_exclusion,
+            set_window_exclusion_style,
+            set_exclude_self,
+            get_game_capture_rules,
+            add_game_capture_rule,
+            remove_game_capture_rule,
+            list_redactions,
+            save_redaction,
+            delete_redaction,
+            set_gpu_conversion,
+            get_gpu_adapters,
+            set_gpu_adapter,
+            set_hdr_mode,
+            set_ten_bit,
+            set_output_color,
+            set_alpha,
+            set_recovery_timeout,
+            set_window_lost_behavior,
+            set_resolution_change_policy,
+            set_match_refresh_rate,
+            set_display_mode_override,
+            get_display_mode_override,
+            set_motion_recording,
+            set_static_content,
+            set_idle_pause,
+            set_input_track,
+            set_live_captions,
+            get_conversion_backend,
+            set_hardware_encoding,
+            set_rate_control,
+            set_advanced_encoder_options,
+            get_encoder_capabilities,
+            get_encoder_support,
+            get_recording_stats,
+            create_diagnostics_bundle,
+            query_logs,
+            set_preview_enabled,
+            set_preview_shared,
+            set_preview_source,
+            set_frame_drop_policy,
+            get_frame_drop_policy,
+            set_power_policy,
+            get_power_policy,
+            get_power_state,
+            set_do_not_disturb,
+            get_do_not_disturb,
+            set_lock_policy,
+            get_lock_policy,
+            set_audio_device_mode,
+            get_audio_device_mode,
+            set_audio_offset,
+            get_audio_offset,
+            set_audio_dynamics,
+            get_audio_dynamics,
+            set_loudness_target,
+            get_loudness_target,
+            set_audio_source_offset,
+            get_audio_source_offsets,
+            take_screenshot,
+            run_benchmark,
+            record_test_signal,
+            get_thumbnail,
+            get_scrub_data,
+            trim_recording,
+            remux_recording,
+            export_recording,
+            export_intermediate,
+            concat_recordings,
+            export_speed,
+            generate_subtitles,
+            get_upload_targets,
+            save_upload_target,
+            remove_upload_target,
+            set_encryption,
+            set_memory_buffer,
+            decrypt_recording,
+            upload_recording,
+            share_recording,
+            get_uploads,
+            cancel_upload,
+            retry_upload,
+            remove_upload,
+            cancel_job,
+            list_jobs,
+            get_job,
+            clear_jobs,
+            add_marker,
+            save_still,
+            get_recording_markers,
+            list_recordings,
+            rename_recording,
+            set_continue_recording,
+            get_continue_recording,
+            delete_recording,
+            get_retention_settings,
+            set_retention_settings,
+            run_retention,
+            get_voice_trigger,
+            set_voice_trigger,
+            get_control_api,
+            set_control_api,
+            regenerate_control_token,
+            get_companion,
+            set_companion,
+            regenerate_companion_token,
+            get_webhooks,
+            set_webhooks,
+            get_metadata_tags,
+            set_metadata_tags,
+            get_desktop_cleanup,
+            set_desktop_cleanup,
+            reveal_recording,
             show_display_highlight,
+            hide_display_highlight,
+            resolve_desktop_region,
+            fit_desktop_region,
+            get_region_selector_settings,
+            resolve_last_selection,
+            preset_region,
+            desktop_region_bounds,
+            set_region_selector_settings,
+            prepare_magnifier,
+            sample_magnifier,
+            release_magnifier,
+            create_virtual_display,
+            remove_virtual_display,
+            list_virtual_displays,
             configure_region_selector_window,
             get_region_selector_position,
+            move_region_selector,
             is_hyprland,
             test_linux_portal,
+            get_picker_health,
+            restart_picker_service,
+            get_audio_graph,
+            install_picker,
+            uninstall_picker,
+            get_picker_policy,
+            set_picker_policy,
+            get_portal_app_selections,
+            set_portal_app_selection,
+            get_portal_permissions,
+            revoke_portal_permission,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| match event {
+            // Finish the recording and close sessions before the process goes
+            tauri::RunEvent::ExitRequested { api, .. } if !shutdown::finished() => {
+                api.prevent_exit();
+                if shutdown::begin() {
+                    let app = app.clone();
+                    tauri::async_runtime::spawn(async move {
+                        shutdown::run(&app).await;
+                        app.exit(0);
+                    });
+                }
+            }
+            tauri::RunEvent::Exit => {
+                // Headless outputs outlive the app otherwise
+                virtual_display::remove_all();
+                // Don't leave notifications off if the app quits mid-recording
+                dnd::restore();
+                // Nor the desktop set up for a recipe or tidied, or a
+                // switched monitor
+                recipes::restore();
+                environment::restore();
+                display_mode::restore();
+            }
+            _ => {}
+        });
 }
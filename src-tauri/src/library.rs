@@ -0,0 +1,361 @@
+//! Recording library: a JSON index of finished recordings.
+
+use crate::capture::CaptureTarget;
+use crate::config;
+use crate::postprocess::thumbnail;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// File name of the library index in the data directory.
+const LIBRARY_FILE: &str = "library.json";
+
+/// A marker placed during recording.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Marker {
+    /// Offset from the start of the recording in seconds
+    pub time_secs: f64,
+    /// Optional user-provided label
+    pub label: Option<String>,
+}
+
+/// A recording tracked by the library.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingEntry {
+    pub id: u64,
+    pub file_path: String,
+    /// Display name (the file name without extension)
+    pub name: String,
+    /// Creation time (RFC 3339)
+    pub created_at: String,
+    pub duration_secs: f64,
+    pub width: u32,
+    pub height: u32,
+    pub size_bytes: u64,
+    /// What was captured, if known
+    pub source: Option<CaptureTarget>,
+    #[serde(default)]
+    pub markers: Vec<Marker>,
+    /// Link to the most recent upload of the recording
+    #[serde(default)]
+    pub share_url: Option<String>,
+    /// Whether the recording was interrupted and repaired at the next launch
+    #[serde(default)]
+    pub recovered: bool,
+    /// Metadata tags in the file, such as its title and author
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    /// Whether the file no longer exists on disk (computed when listing)
+    #[serde(default, skip_deserializing)]
+    pub missing: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LibraryIndex {
+    next_id: u64,
+    recordings: Vec<RecordingEntry>,
+}
+
+/// Persistent library of recordings.
+pub struct Library {
+    index: LibraryIndex,
+}
+
+impl Library {
+    /// Load the library index from the data directory.
+    pub fn load() -> Self {
+        Self {
+            index: config::load_json(LIBRARY_FILE),
+        }
+    }
+
+    fn save(&self) -> Result<(), String> {
+        config::save_json(LIBRARY_FILE, &self.index)
+    }
+
+    /// Add a finished recording, reading its metadata from disk.
+    ///
+    /// This probes the file with FFmpeg; call it from a blocking task.
+    pub fn add(
+        &mut self,
+        path: &Path,
+        source: Option<CaptureTarget>,
+        markers: Vec<Marker>,
+    ) -> Result<RecordingEntry, String> {
+        let info = crate::encoder::ffmpeg::probe(path).unwrap_or_else(|e| {
+            tracing::warn!(target: "Library", "{}", e);
+            Default::default()
+        });
+        let size_bytes = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+        let tags = crate::encoder::ffmpeg::probe_tags(path).unwrap_or_default();
+
+        self.index.next_id += 1;
+        let entry = RecordingEntry {
+            id: self.index.next_id,
+            file_path: path.to_string_lossy().to_string(),
+            name: display_name(path),
+            created_at: chrono::Local::now().to_rfc3339(),
+            duration_secs: info.duration,
+            width: info.width,
+            height: info.height,
+            size_bytes,
+            source,
+            markers,
+            share_url: None,
+            recovered: false,
+            tags,
+            missing: false,
+        };
+
+        self.index.recordings.push(entry.clone());
+        self.save()?;
+        Ok(entry)
+    }
+
+    /// Add a recording repaired after it was interrupted, flagged as recovered.
+    ///
+    /// This probes the file with FFmpeg; call it from a blocking task.
+    pub fn add_recovered(&mut self, path: &Path) -> Result<RecordingEntry, String> {
+        let entry = self.add(path, None, Vec::new())?;
+        if let Some(added) = self.index.recordings.last_mut() {
+            added.recovered = true;
+        }
+        self.save()?;
+        Ok(RecordingEntry { recovered: true, ..entry })
+    }
+
+    /// Update the recording `id` after another was appended to its file,
+    /// adding the appended one's `markers` after what was there before.
+    ///
+    /// This probes the file with FFmpeg; call it from a blocking task.
+    pub fn append(&mut self, id: u64, markers: Vec<Marker>) -> Result<RecordingEntry, String> {
+        let entry = self
+            .index
+            .recordings
+            .iter_mut()
+            .find(|entry| entry.id == id)
+            .ok_or_else(|| format!("Recording {} not found", id))?;
+        let path = PathBuf::from(&entry.file_path);
+        let info = crate::encoder::ffmpeg::probe(&path)?;
+
+        let offset = entry.duration_secs;
+        entry.markers.extend(markers.into_iter().map(|marker| Marker {
+            time_secs: marker.time_secs + offset,
+            ..marker
+        }));
+        entry.duration_secs = info.duration;
+        entry.size_bytes = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        let entry = entry.clone();
+        self.save()?;
+        Ok(entry)
+    }
+
+    /// List recordings, newest first, optionally filtered by a search query
+    /// matched against the name, path and tags.
+    pub fn list(&self, query: Option<&str>) -> Vec<RecordingEntry> {
+        let query = query.map(str::trim).filter(|q| !q.is_empty()).map(str::to_lowercase);
+
+        self.index
+            .recordings
+            .iter()
+            .rev()
+            .filter(|entry| match query {
+                Some(ref q) => {
+                    entry.name.to_lowercase().contains(q)
+                        || entry.file_path.to_lowercase().contains(q)
+                        || entry.tags.values().any(|value| value.to_lowercase().contains(q))
+                }
+                None => true,
+            })
+            .map(|entry| RecordingEntry {
+                missing: !Path::new(&entry.file_path).exists(),
+                ..entry.clone()
+            })
+            .collect()
+    }
+
+    /// Look up a recording by ID.
+    pub fn get(&self, id: u64) -> Result<&RecordingEntry, String> {
+        self.index
+            .recordings
+            .iter()
+            .find(|entry| entry.id == id)
+            .ok_or_else(|| format!("Recording {} not found", id))
+    }
+
+    /// Rename a recording's file (keeping its extension and directory).
+    pub fn rename(&mut self, id: u64, new_name: &str) -> Result<RecordingEntry, String> {
+        let new_name = new_name.trim();
+        if new_name.is_empty() || new_name.contains(['/', '\\']) || new_name.starts_with('.') {
+            return Err("Invalid file name".to_string());
+        }
+
+        let entry = self
+            .index
+            .recordings
+            .iter_mut()
+            .find(|entry| entry.id == id)
+            .ok_or_else(|| format!("Recording {} not found", id))?;
+
+        let old_path = PathBuf::from(&entry.file_path);
+        let new_path = match old_path.extension() {
+            Some(extension) => {
+                old_path.with_file_name(format!("{}.{}", new_name, extension.to_string_lossy()))
+            }
+            None => old_path.with_file_name(new_name),
+        };
+        if new_path.exists() {
+            return Err(format!("A file named {} already exists", new_path.display()));
+        }
+
+        std::fs::rename(&old_path, &new_path)
+            .map_err(|e| format!("Failed to rename recording: {}", e))?;
+
+        // Keep generated thumbnails alongside the renamed file
+        for (old, new) in thumbnail::generated_paths(&old_path).into_iter().zip(thumbnail::generated_paths(&new_path)) {
+            if old.exists() {
+                let _ = std::fs::rename(old, new);
+            }
+        }
+
+        entry.file_path = new_path.to_string_lossy().to_string();
+        entry.name = display_name(&new_path);
+        let entry = entry.clone();
+        self.save()?;
+        Ok(entry)
+    }
+
+    /// Move a recording's file, with its thumbnails, into `dir`.
+    pub fn move_to(&mut self, id: u64, dir: &Path) -> Result<RecordingEntry, String> {
+        let entry = self
+            .index
+            .recordings
+            .iter_mut()
+            .find(|entry| entry.id == id)
+            .ok_or_else(|| format!("Recording {} not found", id))?;
+
+        let old_path = PathBuf::from(&entry.file_path);
+        let new_path = dir.join(old_path.file_name().ok_or("Recording has no file name")?);
+        if new_path.exists() {
+            return Err(format!("A file named {} already exists", new_path.display()));
+        }
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        move_file(&old_path, &new_path)?;
+        for (old, new) in thumbnail::generated_paths(&old_path).into_iter().zip(thumbnail::generated_paths(&new_path)) {
+            if old.exists() {
+                let _ = move_file(&old, &new);
+            }
+        }
+
+        entry.file_path = new_path.to_string_lossy().to_string();
+        let entry = entry.clone();
+        self.save()?;
+        Ok(entry)
+    }
+
+    /// Store the link to an upload of the recording at `path`. Returns the
+    /// updated entry, or `None` if the file isn't in the library.
+    pub fn set_share_url(&mut self, path: &Path, url: &str) -> Result<Option<RecordingEntry>, String> {
+        let Some(entry) = self
+            .index
+            .recordings
+            .iter_mut()
+            .find(|entry| Path::new(&entry.file_path) == path)
+        else {
+            return Ok(None);
+        };
+        entry.share_url = Some(url.to_string());
+        let entry = entry.clone();
+        self.save()?;
+        Ok(Some(entry))
+    }
+
+    /// Remove a recording from the library, optionally deleting its files.
+    pub fn delete(&mut self, id: u64, delete_file: bool) -> Result<(), String> {
+        let position = self
+            .index
+            .recordings
+            .iter()
+            .position(|entry| entry.id == id)
+            .ok_or_else(|| format!("Recording {} not found", id))?;
+
+        if delete_file {
+            let path = PathBuf::from(&self.index.recordings[position].file_path);
+            if path.exists() {
+                std::fs::remove_file(&path)
+                    .map_err(|e| format!("Failed to delete recording: {}", e))?;
+            }
+            for generated in thumbnail::generated_paths(&path) {
+                let _ = std::fs::remove_file(generated);
+            }
+        }
+
+        self.index.recordings.remove(position);
+        self.save()
+    }
+}
+
+/// Move a file, copying it when `to` is on another volume.
+fn move_file(from: &Path, to: &Path) -> Result<(), String> {
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    std::fs::copy(from, to).map_err(|e| format!("Failed to move {}: {}", from.display(), e))?;
+    std::fs::remove_file(from).map_err(|e| format!("Failed to remove {}: {}", from.display(), e))
+}
+
+/// Display name for a recording file.
+fn display_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: u64, file_path: &str) -> RecordingEntry {
+        RecordingEntry {
+            id,
+            file_path: file_path.to_string(),
+            name: display_name(Path::new(file_path)),
+            created_at: String::new(),
+            duration_secs: 0.0,
+            width: 0,
+            height: 0,
+            size_bytes: 0,
+            source: None,
+            markers: Vec::new(),
+            share_url: None,
+            recovered: false,
+            tags: BTreeMap::new(),
+            missing: false,
+        }
+    }
+
+    #[test]
+    fn test_list_search() {
+        let library = Library {
+            index: LibraryIndex {
+                next_id: 2,
+                recordings: vec![entry(1, "/v/demo.mp4"), entry(2, "/v/bug-report.mp4")],
+            },
+        };
+
+        let all = library.list(None);
+        assert_eq!(all.iter().map(|e| e.id).collect::<Vec<_>>(), vec![2, 1]);
+        assert!(all.iter().all(|e| e.missing));
+
+        let found = library.list(Some("BUG"));
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].name, "bug-report");
+
+        let mut tagged = entry(3, "/v/untitled.mp4");
+        tagged.tags.insert("title".to_string(), "Quarterly Review".to_string());
+        let library = Library {
+            index: LibraryIndex { next_id: 3, recordings: vec![tagged] },
+        };
+        assert_eq!(library.list(Some("quarterly")).len(), 1);
+    }
+}
@@ -0,0 +1,263 @@
+//! Per-recording duration and file size limits, and scheduled stops.
+//!
+//! While recording, the elapsed time and the output file's size are checked
+//! every second. When either passes its limit the recording is stopped,
+//! which finalizes the file, and an event says which limit ended it.
+//!
+//! A [`StopPoint`] scheduled during a recording is exact instead: it's
+//! turned into a frame count that the encoder checks before writing each
+//! frame ([`StopAt`]), so the file ends on that frame, and the audio is cut
+//! on the matching sample when it's muxed in.
+
+use crate::stats::StatsCollector;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// How often the limits are checked while recording.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Limits applied to each recording; `None` means unlimited.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordingLimits {
+    /// Stop after this many minutes
+    pub max_duration_mins: Option<u32>,
+    /// Stop once the file reaches this many megabytes
+    pub max_size_mb: Option<u64>,
+}
+
+/// Which limit ended a recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitReason {
+    Duration,
+    FileSize,
+    /// A scheduled [`StopPoint`]
+    StopPoint,
+}
+
+/// A recording reaching one of its limits. The recording should be stopped
+/// to finish the file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LimitReached {
+    pub reason: LimitReason,
+    /// Seconds recorded
+    pub elapsed_secs: f64,
+    /// Size of the output file
+    pub file_size_bytes: u64,
+}
+
+impl RecordingLimits {
+    /// Check that the limits are usable.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_duration_mins == Some(0) || self.max_size_mb == Some(0) {
+            return Err("Recording limits must be greater than zero".to_string());
+        }
+        Ok(())
+    }
+
+    /// The limit a recording `elapsed` long with a `file_size` byte file has
+    /// reached, if any.
+    pub fn check(&self, elapsed: Duration, file_size: u64) -> Option<LimitReason> {
+        if self
+            .max_duration_mins
+            .is_some_and(|mins| elapsed >= Duration::from_secs(mins as u64 * 60))
+        {
+            return Some(LimitReason::Duration);
+        }
+        if self.max_size_mb.is_some_and(|mb| file_size >= mb.saturating_mul(1024 * 1024)) {
+            return Some(LimitReason::FileSize);
+        }
+        None
+    }
+}
+
+/// A point to stop the current recording at, on an exact frame.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopPoint {
+    /// Stop once the recording is exactly this many seconds long, not
+    /// counting time paused
+    Duration { secs: f64 },
+    /// Stop on the frame of the next marker added
+    NextMarker,
+}
+
+/// Where the current recording stops, shared with its encoder.
+pub struct StopAt {
+    fps: AtomicU32,
+    /// Frames to stop after, or `u64::MAX` for none
+    target: AtomicU64,
+    /// Frames written so far
+    written: AtomicU64,
+    at_next_marker: AtomicBool,
+    /// Frames written when the recording stopped at the target
+    stopped: Mutex<Option<u64>>,
+    events: broadcast::Sender<LimitReached>,
+}
+
+impl StopAt {
+    /// No stop point yet, publishing to `events` when one is reached.
+    pub fn new(events: broadcast::Sender<LimitReached>) -> Self {
+        Self {
+            fps: AtomicU32::new(30),
+            target: AtomicU64::new(u64::MAX),
+            written: AtomicU64::new(0),
+            at_next_marker: AtomicBool::new(false),
+            stopped: Mutex::new(None),
+            events,
+        }
+    }
+
+    /// Clear the stop point for a new recording at `fps`.
+    pub fn reset(&self, fps: u32) {
+        self.fps.store(fps.max(1), Ordering::Relaxed);
+        self.cancel();
+        self.written.store(0, Ordering::Relaxed);
+        *self.stopped.lock().unwrap() = None;
+    }
+
+    /// Stop the recording at `point`, replacing any stop point set before.
+    pub fn schedule(&self, point: StopPoint) -> Result<(), String> {
+        match point {
+            StopPoint::Duration { secs } => {
+                if !secs.is_finite() || secs <= 0.0 {
+                    return Err("The stop time must be greater than zero".to_string());
+                }
+                let frames = ((secs * self.fps() as f64).round() as u64).max(1);
+                if frames <= self.written.load(Ordering::Relaxed) {
+                    return Err(format!("The recording is already longer than {:.3}s", secs));
+                }
+                self.at_next_marker.store(false, Ordering::Relaxed);
+                self.target.store(frames, Ordering::Relaxed);
+            }
+            StopPoint::NextMarker => {
+                self.target.store(u64::MAX, Ordering::Relaxed);
+                self.at_next_marker.store(true, Ordering::Relaxed);
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the stop point.
+    pub fn cancel(&self) {
+        self.target.store(u64::MAX, Ordering::Relaxed);
+        self.at_next_marker.store(false, Ordering::Relaxed);
+    }
+
+    /// Note a marker added `time_secs` into the recording, returning whether
+    /// the recording stops at it. It stops on the frame showing that time,
+    /// or the next one if that has already been written.
+    pub fn marker_added(&self, time_secs: f64) -> bool {
+        if !self.at_next_marker.swap(false, Ordering::Relaxed) {
+            return false;
+        }
+        let frames = (time_secs * self.fps() as f64).ceil() as u64;
+        let frames = frames.max(self.written.load(Ordering::Relaxed) + 1);
+        self.target.store(frames, Ordering::Relaxed);
+        true
+    }
+
+    /// Whether a recording `frames` frames long has reached the stop point,
+    /// so no more frames should be written. Called by the encoder before
+    /// writing each frame; `file_size` is only asked once it has.
+    pub fn reached(&self, frames: u64, file_size: impl FnOnce() -> u64) -> bool {
+        self.written.store(frames, Ordering::Relaxed);
+        if frames < self.target.load(Ordering::Relaxed) {
+            return false;
+        }
+        self.target.store(u64::MAX, Ordering::Relaxed);
+        *self.stopped.lock().unwrap() = Some(frames);
+        let elapsed_secs = frames as f64 / self.fps() as f64;
+        tracing::info!(target: "Limits", "Stopping the recording at the stop point: {} frames ({:.3}s)", frames, elapsed_secs);
+        let _ = self.events.send(LimitReached {
+            reason: LimitReason::StopPoint,
+            elapsed_secs,
+            file_size_bytes: file_size(),
+        });
+        true
+    }
+
+    /// Length of the video in seconds, if the recording stopped at its
+    /// stop point.
+    pub fn stopped_secs(&self) -> Option<f64> {
+        let frames = (*self.stopped.lock().unwrap())?;
+        Some(frames as f64 / self.fps() as f64)
+    }
+
+    fn fps(&self) -> u32 {
+        self.fps.load(Ordering::Relaxed)
+    }
+}
+
+/// Watch `stats`' recording until `stop_flag` is set, setting it and
+/// publishing to `events` when a limit is reached.
+pub async fn watch(
+    stats: Arc<StatsCollector>,
+    stop_flag: Arc<AtomicBool>,
+    limits: RecordingLimits,
+    events: broadcast::Sender<LimitReached>,
+) {
+    if limits == RecordingLimits::default() {
+        return;
+    }
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    while !stop_flag.load(Ordering::Relaxed) {
+        interval.tick().await;
+
+        let elapsed = stats.elapsed();
+        let file_size = stats.file_size();
+        if let Some(reason) = limits.check(elapsed, file_size) {
+            tracing::info!(target: "Limits", "Stopping the recording: {:?} limit reached", reason);
+            stop_flag.store(true, Ordering::Relaxed);
+            let _ = events.send(LimitReached {
+                reason,
+                elapsed_secs: elapsed.as_secs_f64(),
+                file_size_bytes: file_size,
+            });
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_limits() {
+        let limits = RecordingLimits {
+            max_duration_mins: Some(10),
+            max_size_mb: Some(100),
+        };
+        assert_eq!(limits.check(Duration::from_secs(599), 1024), None);
+        assert_eq!(limits.check(Duration::from_secs(600), 1024), Some(LimitReason::Duration));
+        assert_eq!(limits.check(Duration::from_secs(1), 100 * 1024 * 1024), Some(LimitReason::FileSize));
+        assert_eq!(RecordingLimits::default().check(Duration::from_secs(86_400), u64::MAX), None);
+        assert!(RecordingLimits { max_duration_mins: Some(0), max_size_mb: None }.validate().is_err());
+    }
+
+    #[test]
+    fn test_stop_at() {
+        let events = broadcast::channel(4).0;
+        let mut reached = events.subscribe();
+        let stop_at = StopAt::new(events);
+        stop_at.reset(60);
+        stop_at.schedule(StopPoint::Duration { secs: 30.0 }).unwrap();
+        assert!(!stop_at.reached(1799, || 0));
+        assert!(stop_at.reached(1800, || 1024));
+        assert_eq!(stop_at.stopped_secs(), Some(30.0));
+        assert_eq!(reached.try_recv().unwrap().reason, LimitReason::StopPoint);
+        assert!(stop_at.schedule(StopPoint::Duration { secs: 10.0 }).is_err());
+
+        // A marker 2.01s in stops after the frame showing it
+        stop_at.reset(30);
+        assert!(!stop_at.marker_added(1.0));
+        stop_at.schedule(StopPoint::NextMarker).unwrap();
+        assert!(stop_at.marker_added(2.01));
+        assert!(!stop_at.reached(60, || 0));
+        assert!(stop_at.reached(61, || 0));
+    }
+}
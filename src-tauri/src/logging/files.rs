@@ -0,0 +1,33 @@
+//! The log files, shared with the portal picker, which includes this file,
+//! so that both write lines the app's log viewer can read.
+//!
+//! Each component writes `<component>.<date>.log` in the log directory,
+//! starting a new file every day and keeping the last [`KEEP_FILES`]. Each
+//! line is
+//!
+//! ```text
+//! 2026-01-01T12:00:00.000000Z  INFO Encoder: Started h264_nvenc
+//! ```
+
+use std::path::Path;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{fmt, Layer};
+
+/// Daily files kept for each component.
+const KEEP_FILES: usize = 7;
+
+/// A layer writing events to `component`'s log in `dir`.
+pub fn layer<S>(dir: &Path, component: &str) -> Result<impl Layer<S>, String>
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+{
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(component)
+        .filename_suffix("log")
+        .max_log_files(KEEP_FILES)
+        .build(dir)
+        .map_err(|e| format!("Failed to open the {} log in {}: {}", component, dir.display(), e))?;
+    Ok(fmt::layer().with_ansi(false).with_writer(appender))
+}
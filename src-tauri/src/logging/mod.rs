@@ -0,0 +1,276 @@
+//! Log files, and searching them from the app.
+//!
+//! The app logs through `tracing`, with the part of the app an event comes
+//! from as its target (`Encoder`, `Portal`). Events go to the console and
+//! to daily files in `logs/` in the data directory, one set per component:
+//! `app` for the app and `picker` for the portal picker, which writes its
+//! own (see [`files`]).
+//!
+//! [`query`] reads the lines back, filtered by component, level and target,
+//! so portal failures can be looked into without a terminal.
+
+mod files;
+
+use crate::config::data_dir;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// Directory in the data directory the logs are kept in.
+pub const LOG_DIR: &str = "logs";
+
+/// The components writing logs, by file name.
+pub const COMPONENTS: &[&str] = &["app", "picker"];
+
+/// Lines a query returns by default, and at most.
+const DEFAULT_LIMIT: usize = 200;
+const MAX_LIMIT: usize = 5000;
+
+/// How severe a log line is, most severe first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    fn as_str(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace]
+            .into_iter()
+            .find(|level| level.as_str() == s)
+    }
+}
+
+/// A line from the logs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogLine {
+    /// When it was logged (RFC 3339, UTC)
+    pub time: String,
+    /// Which component logged it, such as `app` or `picker`
+    pub component: String,
+    pub level: Level,
+    /// The part of the component it came from, such as `Encoder`
+    pub target: String,
+    pub message: String,
+}
+
+/// Which log lines to return.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LogQuery {
+    /// Only lines from this component; all of them if unset
+    pub component: Option<String>,
+    /// Only lines at least this severe
+    pub level: Option<Level>,
+    /// Only lines whose target contains this, ignoring case
+    pub target: Option<String>,
+    /// Only lines logged after this time, for following the log
+    pub since: Option<String>,
+    /// Most lines to return, the latest ones; 200 if unset
+    pub limit: Option<usize>,
+}
+
+/// Start logging: install the `tracing` subscriber, writing to the console
+/// and the app's log file, and log panics. Call this once, first thing at
+/// launch.
+///
+/// Libraries' events are logged too, such as the portal client's requests
+/// and responses, which otherwise aren't shown anywhere.
+pub fn init() {
+    let (file, error) = match data_dir().and_then(|dir| files::layer(&dir.join(LOG_DIR), "app")) {
+        Ok(file) => (Some(file), None),
+        Err(e) => (None, Some(e)),
+    };
+    // Everything the portal client does, and the more important events from
+    // everything else
+    let filter = Targets::new()
+        .with_default(LevelFilter::INFO)
+        .with_target("ashpd", LevelFilter::DEBUG);
+    let installed = tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+        .with(file)
+        .with(filter)
+        .try_init();
+    if installed.is_err() {
+        eprintln!("[Logging] A tracing subscriber is already installed");
+        return;
+    }
+    if let Some(e) = error {
+        tracing::error!(target: "Logging", "{}", e);
+    }
+
+    let report = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        tracing::error!(target: "Panic", "{}", info);
+        report(info);
+    }));
+}
+
+/// Path of the `component`'s logs in `dir`, newest first.
+fn log_files(dir: &Path, component: &str) -> Vec<PathBuf> {
+    let prefix = format!("{}.", component);
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map(|entries| entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect())
+        .unwrap_or_default();
+    // Named by date, which sorts as text
+    files.retain(|path| {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix(&prefix)?.strip_suffix(".log"))
+            .is_some_and(|date| chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok())
+    });
+    files.sort();
+    files.reverse();
+    files
+}
+
+fn parse_line(component: &str, line: &str) -> Option<LogLine> {
+    let (time, rest) = line.split_once(' ')?;
+    let (level, rest) = rest.trim_start().split_once(' ')?;
+    let (target, message) = rest.trim_start().split_once(": ")?;
+    Some(LogLine {
+        time: time.to_string(),
+        component: component.to_string(),
+        level: Level::parse(level)?,
+        target: target.to_string(),
+        message: message.to_string(),
+    })
+}
+
+/// The latest log lines matching `query`, oldest first.
+pub fn query(query: &LogQuery) -> Result<Vec<LogLine>, String> {
+    query_dir(&data_dir()?.join(LOG_DIR), query)
+}
+
+fn query_dir(dir: &Path, query: &LogQuery) -> Result<Vec<LogLine>, String> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let target = query.target.as_ref().map(|target| target.to_lowercase());
+    let matches = |line: &LogLine| {
+        query.level.is_none_or(|level| line.level <= level)
+            && target.as_ref().is_none_or(|target| line.target.to_lowercase().contains(target))
+    };
+
+    let mut lines = Vec::new();
+    for component in COMPONENTS {
+        if query.component.as_ref().is_some_and(|wanted| wanted != component) {
+            continue;
+        }
+        // Newest first, stopping once there are enough
+        let mut found = Vec::new();
+        'files: for path in log_files(dir, component) {
+            let Ok(file) = File::open(path) else {
+                continue;
+            };
+            let file_lines: Vec<String> = BufReader::new(file).lines().map_while(Result::ok).collect();
+            for text in file_lines.iter().rev() {
+                let Some(line) = parse_line(component, text) else {
+                    continue;
+                };
+                if query.since.as_ref().is_some_and(|since| line.time <= *since) {
+                    break 'files;
+                }
+                if matches(&line) {
+                    found.push(line);
+                    if found.len() == limit {
+                        break 'files;
+                    }
+                }
+            }
+        }
+        lines.extend(found);
+    }
+
+    // Lines are only compared by time, so those logged together keep their order
+    lines.reverse();
+    lines.sort_by(|a, b| a.time.cmp(&b.time));
+    let skip = lines.len().saturating_sub(limit);
+    Ok(lines.split_off(skip))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_lines() {
+        let dir = std::env::temp_dir().join(format!("log-lines-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let subscriber = tracing_subscriber::registry().with(files::layer(&dir, "app").unwrap());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(target: "Encoder", "Started h264_nvenc");
+            tracing::warn!(target: "Portal", attempt = 2, "Request timed out");
+        });
+
+        let lines = query_dir(&dir, &LogQuery::default()).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        let summary: Vec<_> = lines
+            .iter()
+            .map(|line| (line.component.as_str(), line.level, line.target.as_str(), line.message.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("app", Level::Info, "Encoder", "Started h264_nvenc"),
+                ("app", Level::Warn, "Portal", "Request timed out attempt=2"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_query() {
+        let dir = std::env::temp_dir().join(format!("log-query-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let line = |second: u32, level: &str, target: &str, message: &str| {
+            format!("2026-01-01T12:00:{:02}.000000Z {:>5} {}: {}\n", second, level, target, message)
+        };
+        // The app's log started a new file between these
+        let old = line(0, "INFO", "Portal", "Requesting a screencast") + &line(1, "ERROR", "Portal", "Failed: denied");
+        std::fs::write(dir.join("app.2025-12-31.log"), old).unwrap();
+        std::fs::write(dir.join("app.2026-01-01.log"), line(3, "INFO", "Encoder", "Started")).unwrap();
+        std::fs::write(dir.join("picker.2026-01-01.log"), line(2, "INFO", "picker", "Picker invoked")).unwrap();
+        std::fs::write(dir.join("app.log"), line(4, "INFO", "Encoder", "From an older version")).unwrap();
+
+        let times = |query: LogQuery| -> Vec<String> {
+            query_dir(&dir, &query).unwrap().into_iter().map(|line| line.time[17..19].to_string()).collect()
+        };
+        assert_eq!(times(LogQuery::default()), ["00", "01", "02", "03"]);
+        assert_eq!(times(LogQuery { limit: Some(2), ..Default::default() }), ["02", "03"]);
+        let portal = LogQuery {
+            target: Some("portal".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(times(portal), ["00", "01"]);
+        let errors = LogQuery {
+            level: Some(Level::Warn),
+            ..Default::default()
+        };
+        assert_eq!(times(errors), ["01"]);
+        let recent = LogQuery {
+            component: Some("app".to_string()),
+            since: Some("2026-01-01T12:00:01.000000Z".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(times(recent), ["03"]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,155 @@
+//! Pixel magnifier for the region selector.
+//!
+//! A still of every monitor is captured when the selector opens, and the
+//! loupe samples the pixels around the cursor from those stills rather than
+//! capturing the screen on every mouse move.
+
+use crate::capture::desktop::{DesktopLayout, DESKTOP_SPACE};
+use crate::capture::{CaptureTarget, CapturedFrame, CoordinateSpace, MonitorInfo, PixelFormat, WindowBounds};
+use crate::screenshot;
+use serde::Serialize;
+use std::sync::Mutex;
+
+/// Pixels shown on each side of the one under the cursor.
+pub const RADIUS: u32 = 7;
+
+/// A monitor's still and where it is on the desktop.
+struct Still {
+    monitor: MonitorInfo,
+    bounds: WindowBounds,
+    /// 8-bit BGRA
+    frame: CapturedFrame,
+}
+
+/// Stills of the monitors while the selector is open.
+static STILLS: Mutex<Vec<Still>> = Mutex::new(Vec::new());
+
+/// The pixels around a point of the desktop.
+#[derive(Debug, Clone, Serialize)]
+pub struct MagnifierSample {
+    /// Monitor showing the point
+    pub monitor_id: String,
+    /// Physical pixel under the point, relative to the monitor
+    pub x: i32,
+    pub y: i32,
+    /// Color of that pixel as `#RRGGBB`
+    pub color: String,
+    /// Side of the square of pixels around it
+    pub size: u32,
+    /// RGBA pixels of the square, row by row. Pixels off the monitor are
+    /// transparent.
+    pub pixels: Vec<u8>,
+}
+
+/// Capture a still of every monitor, replacing any taken before.
+pub async fn prepare() -> Result<(), String> {
+    let layout = DesktopLayout::current();
+    let mut stills = Vec::new();
+    for monitor in layout.clone().into_monitors() {
+        let target = CaptureTarget::Display {
+            monitor_id: monitor.id.clone(),
+            width: monitor.width,
+            height: monitor.height,
+        };
+        let frame = screenshot::capture_frame(target).await?;
+        let frame = match frame.format {
+            PixelFormat::Bgra8 => frame,
+            _ => frame
+                .packed_10bit_to_bgra8()
+                .ok_or_else(|| format!("Can't magnify {:?} frames", frame.format))?,
+        };
+        stills.push(Still {
+            bounds: layout.monitor_bounds(&monitor),
+            monitor,
+            frame,
+        });
+    }
+    tracing::info!(target: "Magnifier", "Captured {} monitor(s)", stills.len());
+    *STILLS.lock().unwrap() = stills;
+    Ok(())
+}
+
+/// Drop the stills.
+pub fn release() {
+    STILLS.lock().unwrap().clear();
+}
+
+/// The pixels around a point of the desktop, in desktop units.
+pub fn sample(x: f64, y: f64) -> Result<MagnifierSample, String> {
+    let stills = STILLS.lock().unwrap();
+    let still = stills
+        .iter()
+        .find(|still| {
+            let b = still.bounds;
+            (b.x as f64..b.x as f64 + b.width as f64).contains(&x)
+                && (b.y as f64..b.y as f64 + b.height as f64).contains(&y)
+        })
+        .ok_or("Point is not on a captured monitor")?;
+
+    let physical = |offset: f64| {
+        DESKTOP_SPACE
+            .convert(offset, CoordinateSpace::Physical, still.monitor.scale_factor)
+            .floor() as i32
+    };
+    let (px, py) = (physical(x - still.bounds.x as f64), physical(y - still.bounds.y as f64));
+    let pixels = crop(&still.frame, px, py, RADIUS);
+    let center = ((RADIUS * (2 * RADIUS + 1) + RADIUS) * 4) as usize;
+    let [r, g, b, _] = [pixels[center], pixels[center + 1], pixels[center + 2], pixels[center + 3]];
+    Ok(MagnifierSample {
+        monitor_id: still.monitor.id.clone(),
+        x: px,
+        y: py,
+        color: format!("#{:02X}{:02X}{:02X}", r, g, b),
+        size: 2 * RADIUS + 1,
+        pixels,
+    })
+}
+
+/// RGBA pixels of the square `radius` pixels around (`x`, `y`) in a BGRA
+/// frame, transparent where the square leaves the frame.
+fn crop(frame: &CapturedFrame, x: i32, y: i32, radius: u32) -> Vec<u8> {
+    let radius = radius as i32;
+    let mut pixels = Vec::with_capacity(((2 * radius + 1) * (2 * radius + 1) * 4) as usize);
+    for row in y - radius..=y + radius {
+        for column in x - radius..=x + radius {
+            let inside = (0..frame.width as i32).contains(&column) && (0..frame.height as i32).contains(&row);
+            if inside {
+                let i = (row as usize * frame.width as usize + column as usize) * 4;
+                let [b, g, r] = [frame.data[i], frame.data[i + 1], frame.data[i + 2]];
+                pixels.extend_from_slice(&[r, g, b, 255]);
+            } else {
+                pixels.extend_from_slice(&[0, 0, 0, 0]);
+            }
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::ColorSpace;
+    use std::time::Instant;
+
+    #[test]
+    fn test_crop() {
+        // 2x2 frame: blue, green / red, white
+        let frame = CapturedFrame {
+            width: 2,
+            height: 2,
+            data: vec![255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 255, 255, 255, 255],
+            format: PixelFormat::Bgra8,
+            color: ColorSpace::default(),
+            captured_at: Instant::now(),
+            presented_at: None,
+        };
+        let pixels = crop(&frame, 0, 0, 1);
+        assert_eq!(pixels.len(), 9 * 4);
+        // Top row and left column are off the frame
+        assert!(pixels[..12].iter().all(|&c| c == 0));
+        assert_eq!(&pixels[12..16], &[0, 0, 0, 0]);
+        assert_eq!(&pixels[16..20], &[0, 0, 255, 255]);
+        assert_eq!(&pixels[20..24], &[0, 255, 0, 255]);
+        assert_eq!(&pixels[32..36], &[255, 255, 255, 255]);
+    }
+}
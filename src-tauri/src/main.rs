@@ -2,5 +2,10 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(code) = screen_recorder_lib::run_cli(&args) {
+        std::process::exit(code);
+    }
+
     screen_recorder_lib::run()
 }
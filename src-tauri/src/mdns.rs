@@ -0,0 +1,244 @@
+//! Minimal multicast DNS responder for advertising a service on the LAN.
+//!
+//! Only what DNS-SD browsing needs is implemented: the service is announced
+//! when it starts, and any query naming the service type, the instance or
+//! its host is answered with the full set of records (PTR, SRV, TXT and an
+//! IPv4 A record), sent to the multicast group. The socket shares port 5353
+//! with the system's own responder (Avahi, Bonjour) where there is one.
+
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT: u16 = 5353;
+
+/// Name queried to list the service types on the network.
+const SERVICES_NAME: &str = "_services._dns-sd._udp.local";
+
+/// How long other machines cache the records, in seconds.
+const TTL: u32 = 120;
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+/// Marks records only this responder answers for, so caches replace them.
+const CACHE_FLUSH: u16 = 0x8000;
+
+/// A service to advertise.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Service {
+    /// Service type, such as `_http._tcp.local`
+    pub service_type: String,
+    /// Name shown to people browsing, unique on the network
+    pub instance: String,
+    /// Host name the service is reached at, such as `recorder.local`
+    pub host: String,
+    pub ip: Ipv4Addr,
+    pub port: u16,
+    /// `key=value` entries of the TXT record
+    pub txt: Vec<String>,
+}
+
+impl Service {
+    /// Full name of the instance.
+    fn instance_name(&self) -> String {
+        format!("{}.{}", self.instance, self.service_type)
+    }
+
+    /// Whether a question about `name` is about this service.
+    fn answers(&self, name: &str) -> bool {
+        [SERVICES_NAME, &self.service_type, &self.instance_name(), &self.host]
+            .iter()
+            .any(|ours| ours.eq_ignore_ascii_case(name))
+    }
+}
+
+/// Advertise `service` until the task is aborted.
+pub async fn advertise(service: Service) -> Result<(), String> {
+    let socket = bind().map_err(|e| format!("Failed to open the mDNS socket: {}", e))?;
+    let group = SocketAddr::V4(SocketAddrV4::new(MDNS_GROUP, MDNS_PORT));
+    let response = response(&service);
+
+    // Announce twice, a second apart, as RFC 6762 asks
+    for _ in 0..2 {
+        socket
+            .send_to(&response, group)
+            .await
+            .map_err(|e| format!("Failed to announce over mDNS: {}", e))?;
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+
+    let mut packet = [0u8; 9000];
+    loop {
+        let (len, _) = match socket.recv_from(&mut packet).await {
+            Ok(received) => received,
+            Err(e) => {
+                tracing::warn!(target: "mDNS", "Receive error: {}", e);
+                continue;
+            }
+        };
+        let asked = questions(&packet[..len]).is_some_and(|names| names.iter().any(|name| service.answers(name)));
+        if asked {
+            if let Err(e) = socket.send_to(&response, group).await {
+                tracing::error!(target: "mDNS", "Failed to answer: {}", e);
+            }
+        }
+    }
+}
+
+/// A socket on the mDNS port, joined to the group.
+fn bind() -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.bind(&SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, MDNS_PORT)).into())?;
+    socket.join_multicast_v4(&MDNS_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_multicast_ttl_v4(255)?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// An mDNS response with every record of `service`.
+fn response(service: &Service) -> Vec<u8> {
+    let instance = service.instance_name();
+    let mut srv = Vec::new();
+    srv.extend_from_slice(&[0, 0, 0, 0]); // priority and weight
+    srv.extend_from_slice(&service.port.to_be_bytes());
+    write_name(&mut srv, &service.host);
+    let mut txt = Vec::new();
+    for entry in &service.txt {
+        let entry = &entry.as_bytes()[..entry.len().min(255)];
+        txt.push(entry.len() as u8);
+        txt.extend_from_slice(entry);
+    }
+    if txt.is_empty() {
+        txt.push(0);
+    }
+    let mut service_type = Vec::new();
+    write_name(&mut service_type, &service.service_type);
+    let mut instance_ptr = Vec::new();
+    write_name(&mut instance_ptr, &instance);
+
+    let records: [(&str, u16, u16, &[u8]); 5] = [
+        (SERVICES_NAME, TYPE_PTR, CLASS_IN, &service_type),
+        (&service.service_type, TYPE_PTR, CLASS_IN, &instance_ptr),
+        (&instance, TYPE_SRV, CLASS_IN | CACHE_FLUSH, &srv),
+        (&instance, TYPE_TXT, CLASS_IN | CACHE_FLUSH, &txt),
+        (&service.host, TYPE_A, CLASS_IN | CACHE_FLUSH, &service.ip.octets()),
+    ];
+
+    // ID 0, an authoritative answer, no questions
+    let mut packet = vec![0, 0, 0x84, 0, 0, 0];
+    packet.extend_from_slice(&(records.len() as u16).to_be_bytes());
+    packet.extend_from_slice(&[0, 0, 0, 0]);
+    for (name, record_type, class, data) in records {
+        write_name(&mut packet, name);
+        packet.extend_from_slice(&record_type.to_be_bytes());
+        packet.extend_from_slice(&class.to_be_bytes());
+        packet.extend_from_slice(&TTL.to_be_bytes());
+        packet.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        packet.extend_from_slice(data);
+    }
+    packet
+}
+
+/// Write `name` as DNS labels, without compression.
+fn write_name(out: &mut Vec<u8>, name: &str) {
+    for label in name.trim_end_matches('.').split('.') {
+        let label = &label.as_bytes()[..label.len().min(63)];
+        out.push(label.len() as u8);
+        out.extend_from_slice(label);
+    }
+    out.push(0);
+}
+
+/// Names asked about in a query, or `None` for responses and packets that
+/// can't be read.
+fn questions(packet: &[u8]) -> Option<Vec<String>> {
+    let header = packet.get(..12)?;
+    if header[2] & 0x80 != 0 {
+        return None;
+    }
+    let count = u16::from_be_bytes([header[4], header[5]]);
+    let mut offset = 12;
+    let mut names = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let (name, next) = read_name(packet, offset)?;
+        names.push(name);
+        // Type and class
+        offset = next + 4;
+    }
+    Some(names)
+}
+
+/// Read the name at `offset`, following compression pointers. Returns the
+/// name and the offset after it.
+fn read_name(packet: &[u8], mut offset: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Bounds the pointers followed, so a loop of them can't hang
+    for _ in 0..128 {
+        let len = *packet.get(offset)? as usize;
+        match len {
+            0 => {
+                return Some((labels.join("."), end.unwrap_or(offset + 1)));
+            }
+            len if len & 0xC0 == 0xC0 => {
+                let pointer = (len & 0x3F) << 8 | *packet.get(offset + 1)? as usize;
+                end.get_or_insert(offset + 2);
+                offset = pointer;
+            }
+            len => {
+                let label = packet.get(offset + 1..offset + 1 + len)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                offset += 1 + len;
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mdns() {
+        let service = Service {
+            service_type: "_screenrec._tcp.local".to_string(),
+            instance: "Studio PC".to_string(),
+            host: "screen-recorder-studio.local".to_string(),
+            ip: Ipv4Addr::new(192, 168, 1, 20),
+            port: 8789,
+            txt: vec!["path=/remote".to_string()],
+        };
+
+        // A query for the service type, with the second question's name
+        // compressed to point at the first
+        let mut query = vec![0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0];
+        write_name(&mut query, "_screenrec._tcp.local");
+        query.extend_from_slice(&[0, 12, 0, 1]);
+        query.extend_from_slice(&[0xC0, 12, 0, 12, 0, 1]);
+        let names = questions(&query).unwrap();
+        assert_eq!(names, ["_screenrec._tcp.local", "_screenrec._tcp.local"]);
+        assert!(service.answers(&names[0]));
+        assert!(service.answers("Studio PC._SCREENREC._tcp.local"));
+        assert!(!service.answers("_http._tcp.local"));
+
+        // Responses aren't questions, not even our own
+        let response = response(&service);
+        assert!(questions(&response).is_none());
+        assert_eq!(u16::from_be_bytes([response[6], response[7]]), 5);
+        let (first, _) = read_name(&response, 12).unwrap();
+        assert_eq!(first, SERVICES_NAME);
+        assert!(response.ends_with(&[0, 4, 192, 168, 1, 20]));
+
+        // A pointer to itself is given up on
+        assert!(read_name(&[0xC0, 0], 0).is_none());
+    }
+}
@@ -0,0 +1,133 @@
+//! Prometheus metrics for long-running captures (the `metrics` feature).
+//!
+//! Kiosk and monitoring deployments leave the recorder running unattended
+//! for days, so the control API serves the pipeline's health at `/metrics`
+//! in Prometheus' text format: the live statistics of the current
+//! recording, free disk space where it's written, and counts of recordings
+//! started, saved and failed since the app started. Prometheus scrapes it
+//! with the control API token as a bearer token.
+
+use crate::state::RecordingPhase;
+use crate::stats::RecordingStats;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+static STARTED: AtomicU64 = AtomicU64::new(0);
+static SAVED: AtomicU64 = AtomicU64::new(0);
+static FAILED: AtomicU64 = AtomicU64::new(0);
+
+/// Count recordings as they start and end, until the app quits.
+pub async fn count_recordings(mut phases: broadcast::Receiver<RecordingPhase>) {
+    let mut previous = RecordingPhase::Idle;
+    loop {
+        let phase = match phases.recv().await {
+            Ok(phase) => phase,
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return,
+        };
+        match (&previous, &phase) {
+            (RecordingPhase::Preparing, RecordingPhase::Recording) => STARTED.fetch_add(1, Ordering::Relaxed),
+            (_, RecordingPhase::Done { .. }) => SAVED.fetch_add(1, Ordering::Relaxed),
+            (_, RecordingPhase::Failed { .. }) => FAILED.fetch_add(1, Ordering::Relaxed),
+            _ => 0,
+        };
+        previous = phase;
+    }
+}
+
+/// What the metrics are read from at a scrape.
+pub struct Snapshot {
+    pub phase: RecordingPhase,
+    /// Statistics of the current recording, if one is running
+    pub stats: Option<RecordingStats>,
+    /// Free space where recordings are written
+    pub disk_free_bytes: Option<u64>,
+}
+
+/// The metrics in Prometheus' text exposition format.
+pub fn render(snapshot: &Snapshot) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: f64| {
+        let _ = write!(out, "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n");
+    };
+    let recording = matches!(snapshot.phase, RecordingPhase::Recording | RecordingPhase::Paused);
+    metric(
+        "screen_recorder_recording",
+        "gauge",
+        "Whether a recording is running (1) or not (0).",
+        recording as u8 as f64,
+    );
+    metric(
+        "screen_recorder_paused",
+        "gauge",
+        "Whether the recording is paused.",
+        matches!(snapshot.phase, RecordingPhase::Paused) as u8 as f64,
+    );
+    for (name, help, counter) in [
+        ("screen_recorder_recordings_started_total", "Recordings started.", &STARTED),
+        ("screen_recorder_recordings_saved_total", "Recordings saved.", &SAVED),
+        (
+            "screen_recorder_recordings_failed_total",
+            "Recordings that failed to start or save, including capture and encoder errors.",
+            &FAILED,
+        ),
+    ] {
+        metric(name, "counter", help, counter.load(Ordering::Relaxed) as f64);
+    }
+    if let Some(free) = snapshot.disk_free_bytes {
+        metric(
+            "screen_recorder_disk_free_bytes",
+            "gauge",
+            "Free space where recordings are written.",
+            free as f64,
+        );
+    }
+    if let Some(ref stats) = snapshot.stats {
+        for (name, help, value) in [
+            ("screen_recorder_capture_fps", "Frames per second arriving from the capture.", stats.capture_fps),
+            ("screen_recorder_encode_fps", "Frames per second written to the output.", stats.encode_fps),
+            (
+                "screen_recorder_dropped_frames",
+                "Frames dropped by the capture queue during the current recording.",
+                stats.dropped_frames as f64,
+            ),
+            ("screen_recorder_queue_depth", "Frames waiting in the capture queue.", stats.queue_depth as f64),
+            (
+                "screen_recorder_encode_latency_seconds",
+                "Average time to hand a frame to the encoder.",
+                stats.encode_latency_ms / 1000.0,
+            ),
+            ("screen_recorder_output_bitrate_bps", "Output bitrate, in bits per second.", stats.bitrate_kbps * 1000.0),
+            ("screen_recorder_output_file_bytes", "Size of the output file.", stats.file_size_bytes as f64),
+            ("screen_recorder_recording_seconds", "Length of the current recording.", stats.elapsed_secs),
+        ] {
+            metric(name, "gauge", help, value);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let idle = render(&Snapshot { phase: RecordingPhase::Idle, stats: None, disk_free_bytes: Some(1024) });
+        assert!(idle.contains(
+            "# HELP screen_recorder_recording Whether a recording is running (1) or not (0).\n\
+             # TYPE screen_recorder_recording gauge\n\
+             screen_recorder_recording 0\n"
+        ));
+        assert!(idle.contains("\nscreen_recorder_disk_free_bytes 1024\n"));
+        assert!(!idle.contains("screen_recorder_capture_fps"));
+
+        let stats = RecordingStats { capture_fps: 59.5, dropped_frames: 3, bitrate_kbps: 8000.0, ..Default::default() };
+        let recording = render(&Snapshot { phase: RecordingPhase::Recording, stats: Some(stats), disk_free_bytes: None });
+        assert!(recording.contains("\nscreen_recorder_recording 1\n"));
+        assert!(recording.contains("\nscreen_recorder_capture_fps 59.5\n"));
+        assert!(recording.contains("\nscreen_recorder_dropped_frames 3\n"));
+        assert!(recording.contains("\nscreen_recorder_output_bitrate_bps 8000000\n"));
+    }
+}
@@ -0,0 +1,326 @@
+//! Checking and requesting the OS permissions recording needs.
+//!
+//! Without screen recording permission, macOS hands out black frames and
+//! Windows refuses the capture, well after the recording has started. So
+//! permissions are checked before a recording starts: one that hasn't been
+//! decided yet is asked for with the OS's own dialog, and one that's been
+//! refused fails the start with an error pointing at the settings to change.
+//!
+//! - macOS: screen recording, the microphone (for the voice trigger) and
+//!   accessibility each need the user's consent. macOS can't tell screen
+//!   recording that was refused from never asked, so it counts as not
+//!   decided until it's been asked for once.
+//! - Windows: desktop apps aren't asked, but screen capture and the
+//!   microphone can be turned off in the privacy settings.
+//! - Linux: the portal asks on every capture, so nothing is needed up front.
+
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+
+/// A permission recording can need.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    ScreenRecording,
+    Microphone,
+    Accessibility,
+}
+
+impl Permission {
+    fn name(self) -> &'static str {
+        match self {
+            Permission::ScreenRecording => "Screen recording",
+            Permission::Microphone => "Microphone",
+            Permission::Accessibility => "Accessibility",
+        }
+    }
+}
+
+/// Whether a permission has been given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PermissionStatus {
+    Granted,
+    Denied,
+    /// Not asked for yet; requesting it shows the OS's dialog (macOS)
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    NotDetermined,
+    /// The platform doesn't ask for it
+    NotRequired,
+}
+
+/// Status of every permission, for the frontend.
+#[derive(Debug, Clone, Serialize)]
+pub struct PermissionReport {
+    pub screen_recording: PermissionStatus,
+    pub microphone: PermissionStatus,
+    pub accessibility: PermissionStatus,
+}
+
+/// Whether `permission` has been given.
+pub fn status(permission: Permission) -> PermissionStatus {
+    platform::status(permission)
+}
+
+/// Status of every permission.
+pub fn report() -> PermissionReport {
+    PermissionReport {
+        screen_recording: status(Permission::ScreenRecording),
+        microphone: status(Permission::Microphone),
+        accessibility: status(Permission::Accessibility),
+    }
+}
+
+/// System settings where `permission` is given, if the platform has them.
+pub fn settings_url(permission: Permission) -> Option<&'static str> {
+    platform::settings_url(permission)
+}
+
+/// Ask for `permission`: with the OS's dialog if it hasn't been decided,
+/// or by opening its settings if it's been refused. May block until the
+/// dialog is answered.
+pub fn request(permission: Permission) -> PermissionStatus {
+    match status(permission) {
+        PermissionStatus::NotDetermined => platform::prompt(permission).unwrap_or_else(|| open_settings(permission)),
+        PermissionStatus::Denied => open_settings(permission),
+        status => status,
+    }
+}
+
+/// Check `permission` before it's needed, asking for it if it hasn't been
+/// decided yet. Fails if it's been refused.
+pub fn preflight(permission: Permission) -> Result<(), AppError> {
+    let status = match status(permission) {
+        PermissionStatus::NotDetermined => platform::prompt(permission).unwrap_or(PermissionStatus::NotDetermined),
+        status => status,
+    };
+    match status {
+        PermissionStatus::Denied => {
+            tracing::warn!(target: "Permissions", "{} permission is off", permission.name());
+            Err(AppError::PermissionDenied(format!("{} permission is off", permission.name())))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Open the settings for `permission`, returning its status meanwhile.
+fn open_settings(permission: Permission) -> PermissionStatus {
+    if let Some(url) = settings_url(permission) {
+        if let Err(e) = tauri_plugin_opener::open_url(url, None::<&str>) {
+            tracing::error!(target: "Permissions", "Failed to open {}: {}", url, e);
+        }
+    }
+    status(permission)
+}
+
+/// Status from a value in Windows' consent store ("Allow" or "Deny"). A
+/// missing value means the default, which is allowed.
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn from_consent(value: Option<&str>) -> PermissionStatus {
+    match value {
+        Some("Deny") => PermissionStatus::Denied,
+        _ => PermissionStatus::Granted,
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::{Permission, PermissionStatus};
+    use objc2::msg_send;
+    use objc2::runtime::{AnyClass, AnyObject, Bool};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGPreflightScreenCaptureAccess() -> bool;
+        fn CGRequestScreenCaptureAccess() -> bool;
+    }
+
+    #[link(name = "ApplicationServices", kind = "framework")]
+    extern "C" {
+        fn AXIsProcessTrusted() -> bool;
+    }
+
+    #[link(name = "AVFoundation", kind = "framework")]
+    extern "C" {
+        static AVMediaTypeAudio: *const AnyObject;
+    }
+
+    /// Values of `AVAuthorizationStatus`
+    const AV_NOT_DETERMINED: isize = 0;
+    const AV_AUTHORIZED: isize = 3;
+
+    /// Whether screen recording has been asked for since the app started
+    static SCREEN_ASKED: AtomicBool = AtomicBool::new(false);
+
+    fn capture_device() -> Option<&'static AnyClass> {
+        AnyClass::get(c"AVCaptureDevice")
+    }
+
+    pub fn status(permission: Permission) -> PermissionStatus {
+        match permission {
+            Permission::ScreenRecording => match unsafe { CGPreflightScreenCaptureAccess() } {
+                true => PermissionStatus::Granted,
+                false if SCREEN_ASKED.load(Ordering::Relaxed) => PermissionStatus::Denied,
+                false => PermissionStatus::NotDetermined,
+            },
+            Permission::Microphone => {
+                let Some(class) = capture_device() else {
+                    return PermissionStatus::NotDetermined;
+                };
+                let status: isize = unsafe { msg_send![class, authorizationStatusForMediaType: AVMediaTypeAudio] };
+                match status {
+                    AV_AUTHORIZED => PermissionStatus::Granted,
+                    AV_NOT_DETERMINED => PermissionStatus::NotDetermined,
+                    _ => PermissionStatus::Denied,
+                }
+            }
+            Permission::Accessibility => match unsafe { AXIsProcessTrusted() } {
+                true => PermissionStatus::Granted,
+                false => PermissionStatus::NotDetermined,
+            },
+        }
+    }
+
+    /// Show the OS's dialog for `permission`, if it has one.
+    pub fn prompt(permission: Permission) -> Option<PermissionStatus> {
+        match permission {
+            Permission::ScreenRecording => {
+                // Only shows the dialog the first time; afterwards the
+                // setting has to be changed in System Settings
+                SCREEN_ASKED.store(true, Ordering::Relaxed);
+                Some(match unsafe { CGRequestScreenCaptureAccess() } {
+                    true => PermissionStatus::Granted,
+                    false => PermissionStatus::Denied,
+                })
+            }
+            Permission::Microphone => {
+                let class = capture_device()?;
+                let (sender, answer) = std::sync::mpsc::channel();
+                let handler = block2::RcBlock::new(move |granted: Bool| {
+                    let _ = sender.send(granted.as_bool());
+                });
+                let _: () = unsafe {
+                    msg_send![class, requestAccessForMediaType: AVMediaTypeAudio, completionHandler: &*handler]
+                };
+                answer.recv().ok().map(|granted| match granted {
+                    true => PermissionStatus::Granted,
+                    false => PermissionStatus::Denied,
+                })
+            }
+            Permission::Accessibility => None,
+        }
+    }
+
+    pub fn settings_url(permission: Permission) -> Option<&'static str> {
+        Some(match permission {
+            Permission::ScreenRecording => "x-apple.systempreferences:com.apple.preference.security?Privacy_ScreenCapture",
+            Permission::Microphone => "x-apple.systempreferences:com.apple.preference.security?Privacy_Microphone",
+            Permission::Accessibility => "x-apple.systempreferences:com.apple.preference.security?Privacy_Accessibility",
+        })
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use super::{from_consent, Permission, PermissionStatus};
+    use windows::core::HSTRING;
+    use windows::Win32::Foundation::ERROR_SUCCESS;
+    use windows::Win32::System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_SZ};
+
+    const CONSENT_STORE: &str = "Software\\Microsoft\\Windows\\CurrentVersion\\CapabilityAccessManager\\ConsentStore";
+
+    /// The consent store's value for `key`, such as "Allow" or "Deny".
+    fn consent_value(key: &str) -> Option<String> {
+        let mut buffer = [0u16; 32];
+        let mut size = std::mem::size_of_val(&buffer) as u32;
+        let status = unsafe {
+            RegGetValueW(
+                HKEY_CURRENT_USER,
+                &HSTRING::from(format!("{}\\{}", CONSENT_STORE, key)),
+                &HSTRING::from("Value"),
+                RRF_RT_REG_SZ,
+                None,
+                Some(buffer.as_mut_ptr() as *mut _),
+                Some(&mut size),
+            )
+        };
+        if status != ERROR_SUCCESS {
+            return None;
+        }
+        let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+        Some(String::from_utf16_lossy(&buffer[..len]))
+    }
+
+    /// Whether `capability` is on for the user and for desktop apps.
+    fn consent(capability: &str) -> PermissionStatus {
+        let user = from_consent(consent_value(capability).as_deref());
+        let desktop_apps = from_consent(consent_value(&format!("{}\\NonPackaged", capability)).as_deref());
+        if user == PermissionStatus::Denied || desktop_apps == PermissionStatus::Denied {
+            PermissionStatus::Denied
+        } else {
+            PermissionStatus::Granted
+        }
+    }
+
+    pub fn status(permission: Permission) -> PermissionStatus {
+        match permission {
+            Permission::ScreenRecording => consent("graphicsCaptureProgrammatic"),
+            Permission::Microphone => consent("microphone"),
+            Permission::Accessibility => PermissionStatus::NotRequired,
+        }
+    }
+
+    /// Desktop apps aren't shown a dialog.
+    pub fn prompt(_permission: Permission) -> Option<PermissionStatus> {
+        None
+    }
+
+    pub fn settings_url(permission: Permission) -> Option<&'static str> {
+        match permission {
+            Permission::ScreenRecording => Some("ms-settings:privacy-graphicscaptureprogrammatic"),
+            Permission::Microphone => Some("ms-settings:privacy-microphone"),
+            Permission::Accessibility => None,
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+mod platform {
+    use super::{Permission, PermissionStatus};
+
+    /// The portal asks for each capture.
+    pub fn status(_permission: Permission) -> PermissionStatus {
+        PermissionStatus::NotRequired
+    }
+
+    pub fn prompt(_permission: Permission) -> Option<PermissionStatus> {
+        None
+    }
+
+    pub fn settings_url(_permission: Permission) -> Option<&'static str> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permission_status() {
+        assert_eq!(from_consent(Some("Deny")), PermissionStatus::Denied);
+        assert_eq!(from_consent(Some("Allow")), PermissionStatus::Granted);
+        assert_eq!(from_consent(None), PermissionStatus::Granted);
+        assert_eq!(
+            serde_json::to_string(&PermissionStatus::NotDetermined).unwrap(),
+            r#""not_determined""#
+        );
+        assert_eq!(
+            serde_json::from_str::<Permission>(r#""screen_recording""#).unwrap(),
+            Permission::ScreenRecording
+        );
+        #[cfg(target_os = "linux")]
+        assert!(preflight(Permission::ScreenRecording).is_ok());
+    }
+}
@@ -0,0 +1,131 @@
+//! Writing recording markers into the file as chapters, with its tags.
+//!
+//! Each marker starts a chapter that runs to the next marker, so players
+//! can jump between them: MP4 gets a chapter track and MKV/WebM native
+//! chapters. The chapters and any tags (see [`crate::tags`]) are remuxed in
+//! together with the streams copied.
+
+use crate::encoder::ffmpeg::{probe, run_ffmpeg};
+use crate::library::Marker;
+use std::path::Path;
+
+/// Write `markers` into `video` in place as chapters.
+///
+/// Does nothing for formats without chapters (GIF) or without markers.
+pub fn add_chapters(video: &Path, markers: &[Marker]) -> Result<(), String> {
+    add_metadata(video, markers, &[])
+}
+
+/// Write `markers` into `video` in place as chapters, and set the container
+/// `tags` (key and value), keeping the tags already there.
+///
+/// Does nothing for formats without metadata (GIF) or with nothing to write.
+pub fn add_metadata(video: &Path, markers: &[Marker], tags: &[(String, String)]) -> Result<(), String> {
+    let extension = video
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if (markers.is_empty() && tags.is_empty()) || extension == "gif" {
+        return Ok(());
+    }
+
+    let mut args = vec!["-i".to_string(), video.to_string_lossy().to_string()];
+    let metadata = video.with_extension("chapters.txt");
+    if !markers.is_empty() {
+        let duration = probe(video)?.duration;
+        std::fs::write(&metadata, ffmetadata(markers, duration))
+            .map_err(|e| format!("Failed to write chapters: {}", e))?;
+        args.extend([
+            "-f".to_string(),
+            "ffmetadata".to_string(),
+            "-i".to_string(),
+            metadata.to_string_lossy().to_string(),
+            "-map_chapters".to_string(),
+            "1".to_string(),
+        ]);
+    }
+
+    // Write next to the video so the final rename stays on one filesystem
+    let chaptered = video.with_extension(format!("chapters.{}", extension));
+    args.extend(["-map".to_string(), "0".to_string(), "-c".to_string(), "copy".to_string()]);
+    for (key, value) in tags {
+        args.extend(["-metadata".to_string(), format!("{}={}", key, value)]);
+    }
+    if extension == "mp4" || extension == "mov" {
+        args.extend(["-movflags".to_string(), "+faststart".to_string()]);
+    }
+    args.extend(["-y".to_string(), chaptered.to_string_lossy().to_string()]);
+
+    let result = run_ffmpeg(args);
+    let _ = std::fs::remove_file(&metadata);
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&chaptered);
+        return Err(e);
+    }
+    std::fs::rename(&chaptered, video).map_err(|e| format!("Failed to replace recording: {}", e))
+}
+
+/// FFmpeg metadata file with a chapter per marker, the last ending at
+/// `duration`. Markers past the end are dropped, and a chapter is added for
+/// the part before the first marker.
+fn ffmetadata(markers: &[Marker], duration: f64) -> String {
+    let mut starts: Vec<(f64, String)> = markers
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| m.time_secs < duration)
+        .map(|(i, m)| {
+            let title = m.label.clone().unwrap_or_else(|| format!("Marker {}", i + 1));
+            (m.time_secs.max(0.0), title)
+        })
+        .collect();
+    starts.sort_by(|a, b| a.0.total_cmp(&b.0));
+    if starts.first().is_none_or(|(start, _)| *start > 0.0) {
+        starts.insert(0, (0.0, "Start".to_string()));
+    }
+
+    let ms = |secs: f64| (secs * 1000.0).round() as u64;
+    let mut out = ";FFMETADATA1\n".to_string();
+    for (i, (start, title)) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).map_or(duration, |next| next.0);
+        out.push_str(&format!(
+            "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            ms(*start),
+            ms(end),
+            escape(title)
+        ));
+    }
+    out
+}
+
+/// Escape the characters the metadata format treats specially.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ffmetadata() {
+        let markers = [
+            Marker { time_secs: 75.5, label: Some("Q&A; part=2".to_string()) },
+            Marker { time_secs: 10.0, label: None },
+            Marker { time_secs: 200.0, label: None },
+        ];
+        assert_eq!(
+            ffmetadata(&markers, 120.0),
+            ";FFMETADATA1\n\
+             [CHAPTER]\nTIMEBASE=1/1000\nSTART=0\nEND=10000\ntitle=Start\n\
+             [CHAPTER]\nTIMEBASE=1/1000\nSTART=10000\nEND=75500\ntitle=Marker 2\n\
+             [CHAPTER]\nTIMEBASE=1/1000\nSTART=75500\nEND=120000\ntitle=Q&A\\; part\\=2\n"
+        );
+    }
+}
@@ -0,0 +1,283 @@
+//! Joining recordings into one file, e.g. the takes of a tutorial.
+//!
+//! Recordings made with the same settings (codec, size, frame rate and
+//! tracks) that aren't trimmed are joined with a stream copy, which is
+//! lossless and fast. Otherwise each clip is cut to its range and re-encoded
+//! to the first clip's size and frame rate, letterboxed if its aspect ratio
+//! differs, with silence standing in for clips without audio.
+//!
+//! A recording can also be continued: the next one is appended to it in
+//! place, so a long capture interrupted by a crash still ends up as one file.
+
+use super::derived_path;
+use super::trim::concat_escape;
+use crate::encoder::ffmpeg::{probe, run_ffmpeg, run_ffmpeg_with_progress, MediaInfo};
+use crate::jobs::JobContext;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Frame rate used when the first clip doesn't report one.
+const DEFAULT_FPS: f64 = 30.0;
+
+/// Containers that can be appended to with a stream copy. Their files can be
+/// read up to wherever they were cut off, so recovered ones qualify too.
+const APPENDABLE: [&str; 2] = ["mkv", "webm"];
+
+/// A recording to join, and the range of it to use.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConcatClip {
+    pub file_path: String,
+    /// Where the clip starts in the recording, in seconds
+    #[serde(default)]
+    pub start_secs: Option<f64>,
+    /// Where the clip ends; the end of the recording if unset
+    #[serde(default)]
+    pub end_secs: Option<f64>,
+}
+
+/// A clip with its recording probed and its range checked.
+struct Clip {
+    path: PathBuf,
+    info: MediaInfo,
+    start: f64,
+    end: f64,
+}
+
+impl Clip {
+    fn resolve(clip: &ConcatClip) -> Result<Self, String> {
+        let path = PathBuf::from(&clip.file_path);
+        let info = probe(&path)?;
+        let start = clip.start_secs.unwrap_or(0.0).max(0.0);
+        let end = clip.end_secs.filter(|end| *end > 0.0).unwrap_or(info.duration).min(info.duration);
+        if start >= end {
+            return Err(format!(
+                "Invalid range {:.2}s to {:.2}s for {} ({:.2}s long)",
+                start,
+                end,
+                path.display(),
+                info.duration
+            ));
+        }
+        Ok(Self { path, info, start, end })
+    }
+
+    fn duration(&self) -> f64 {
+        self.end - self.start
+    }
+
+    fn is_whole(&self) -> bool {
+        self.start == 0.0 && self.end >= self.info.duration
+    }
+}
+
+/// Join `clips`, in order, into a new file next to the first one. Returns
+/// the output path.
+pub fn concat(clips: &[ConcatClip], context: &JobContext) -> Result<PathBuf, String> {
+    if clips.len() < 2 {
+        return Err("Choose at least two recordings to join".to_string());
+    }
+    let clips = clips.iter().map(Clip::resolve).collect::<Result<Vec<_>, _>>()?;
+    let duration = clips.iter().map(Clip::duration).sum();
+
+    let copy = can_copy(&clips);
+    let first = &clips[0].path;
+    let extension = match copy {
+        true => first.extension().and_then(|e| e.to_str()).unwrap_or("mp4"),
+        false => "mp4",
+    };
+    let output = derived_path(&first.with_extension(extension), "merged");
+    tracing::info!(
+        target: "Concat",
+        "{} clips ({:.1}s, {}) -> {}",
+        clips.len(),
+        duration,
+        if copy { "stream copy" } else { "re-encoding" },
+        output.display()
+    );
+
+    let list = output.with_extension("concat.txt");
+    let result = (|| {
+        let args = if copy {
+            let entries: String = clips
+                .iter()
+                .map(|clip| format!("file '{}'\n", concat_escape(&clip.path)))
+                .collect();
+            std::fs::write(&list, entries).map_err(|e| format!("Failed to write concat list: {}", e))?;
+            copy_args(&list, &output)
+        } else {
+            normalize_args(&clips, &output)
+        };
+        run_ffmpeg_with_progress(args, duration, context.cancel_flag(), |fraction| context.report(fraction))
+    })();
+
+    let _ = std::fs::remove_file(&list);
+    if let Err(e) = result {
+        // Don't leave a partial file behind
+        let _ = std::fs::remove_file(&output);
+        return Err(e);
+    }
+
+    Ok(output)
+}
+
+/// Whether recordings can be appended to the file at `path`.
+pub fn is_appendable(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| APPENDABLE.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// Append the recording `next` to the end of `existing`, replacing
+/// `existing` with the joined file and deleting `next`. Both have to be
+/// recorded with the same settings, since they're joined with a stream copy.
+///
+/// This runs FFmpeg; call it from a blocking task.
+pub fn append(existing: &Path, next: &Path) -> Result<(), String> {
+    if !is_appendable(existing) {
+        return Err("Only MKV and WebM recordings can be continued".to_string());
+    }
+    let clips = [existing, next]
+        .iter()
+        .map(|path| {
+            Clip::resolve(&ConcatClip {
+                file_path: path.to_string_lossy().to_string(),
+                start_secs: None,
+                end_secs: None,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if !can_copy(&clips) {
+        return Err(format!("{} wasn't recorded with the same settings as {}", next.display(), existing.display()));
+    }
+
+    let output = derived_path(existing, "continued");
+    tracing::info!(target: "Concat", "Appending {} to {}", next.display(), existing.display());
+    let list = output.with_extension("concat.txt");
+    let entries = format!("file '{}'\nfile '{}'\n", concat_escape(existing), concat_escape(next));
+    let result = std::fs::write(&list, entries)
+        .map_err(|e| format!("Failed to write concat list: {}", e))
+        .and_then(|()| run_ffmpeg(copy_args(&list, &output)))
+        .and_then(|()| std::fs::rename(&output, existing).map_err(|e| format!("Failed to replace {}: {}", existing.display(), e)));
+    let _ = std::fs::remove_file(&list);
+    if let Err(e) = result {
+        // Don't leave a partial file behind
+        let _ = std::fs::remove_file(&output);
+        return Err(e);
+    }
+    if let Err(e) = std::fs::remove_file(next) {
+        tracing::error!(target: "Concat", "Failed to remove {}: {}", next.display(), e);
+    }
+    Ok(())
+}
+
+/// Whether the clips are whole recordings in the same format, which the
+/// concat demuxer can join without re-encoding.
+fn can_copy(clips: &[Clip]) -> bool {
+    let first = &clips[0];
+    clips.iter().all(|clip| {
+        clip.is_whole()
+            && clip.path.extension() == first.path.extension()
+            && clip.info.video_codec == first.info.video_codec
+            && (clip.info.width, clip.info.height) == (first.info.width, first.info.height)
+            && clip.info.fps == first.info.fps
+            && clip.info.has_audio == first.info.has_audio
+    })
+}
+
+/// FFmpeg arguments joining the files in the concat `list` with a stream copy.
+fn copy_args(list: &Path, output: &Path) -> Vec<String> {
+    let mut args: Vec<String> = ["-f", "concat", "-safe", "0", "-i"].map(String::from).to_vec();
+    args.push(list.to_string_lossy().to_string());
+    args.extend(["-map", "0", "-c", "copy", "-movflags", "+faststart", "-y"].map(String::from));
+    args.push(output.to_string_lossy().to_string());
+    args
+}
+
+/// FFmpeg arguments cutting each clip to its range and re-encoding them to
+/// the first clip's size and frame rate.
+fn normalize_args(clips: &[Clip], output: &Path) -> Vec<String> {
+    let first = &clips[0].info;
+    let (width, height) = (first.width & !1, first.height & !1);
+    let fps = if first.fps > 0.0 { first.fps } else { DEFAULT_FPS };
+    let audio = clips.iter().any(|clip| clip.info.has_audio);
+
+    let mut args = Vec::new();
+    let mut filters = Vec::new();
+    let mut joined = String::new();
+    for (index, clip) in clips.iter().enumerate() {
+        args.extend([
+            "-ss".to_string(),
+            format!("{:.3}", clip.start),
+            "-t".to_string(),
+            format!("{:.3}", clip.duration()),
+            "-i".to_string(),
+            clip.path.to_string_lossy().to_string(),
+        ]);
+        filters.push(format!(
+            "[{index}:v:0]scale={width}:{height}:force_original_aspect_ratio=decrease,\
+             pad={width}:{height}:(ow-iw)/2:(oh-ih)/2,setsar=1,fps={fps},format=yuv420p[v{index}]"
+        ));
+        joined.push_str(&format!("[v{}]", index));
+        if audio {
+            filters.push(match clip.info.has_audio {
+                true => format!("[{index}:a:0]aresample=48000,aformat=channel_layouts=stereo[a{index}]"),
+                false => format!("anullsrc=r=48000:cl=stereo,atrim=duration={:.3}[a{index}]", clip.duration()),
+            });
+            joined.push_str(&format!("[a{}]", index));
+        }
+    }
+    filters.push(format!("{}concat=n={}:v=1:a={}[v]{}", joined, clips.len(), u8::from(audio), if audio { "[a]" } else { "" }));
+
+    args.extend(["-filter_complex".to_string(), filters.join(";"), "-map".to_string(), "[v]".to_string()]);
+    if audio {
+        args.extend(["-map", "[a]", "-c:a", "aac", "-b:a", "192k"].map(String::from));
+    }
+    // Like exports, joined files are deliverables, so favor quality over speed
+    args.extend(["-c:v", "libx264", "-preset", "medium", "-crf", "20", "-movflags", "+faststart", "-y"].map(String::from));
+    args.push(output.to_string_lossy().to_string());
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clip(path: &str, width: u32, fps: f64, has_audio: bool, start: f64, end: f64) -> Clip {
+        Clip {
+            path: PathBuf::from(path),
+            info: MediaInfo {
+                duration: 60.0,
+                width,
+                height: width * 9 / 16,
+                video_codec: "h264".to_string(),
+                fps,
+                has_audio,
+            },
+            start,
+            end,
+        }
+    }
+
+    #[test]
+    fn test_concat_plan() {
+        let whole = [clip("a.mp4", 1920, 30.0, true, 0.0, 60.0), clip("b.mp4", 1920, 30.0, true, 0.0, 60.0)];
+        assert!(can_copy(&whole));
+        let trimmed = [clip("a.mp4", 1920, 30.0, true, 0.0, 60.0), clip("b.mp4", 1920, 30.0, true, 5.0, 20.0)];
+        assert!(!can_copy(&trimmed));
+
+        let mixed = [clip("a.mp4", 1280, 60.0, false, 0.0, 60.0), clip("b.mkv", 1920, 30.0, true, 5.0, 20.0)];
+        assert!(!can_copy(&mixed));
+        assert!(is_appendable(Path::new("/v/a_recovered.MKV")));
+        assert!(!is_appendable(Path::new("/v/a.mp4")));
+        assert_eq!(
+            normalize_args(&mixed, Path::new("a_merged.mp4")).join(" "),
+            "-ss 0.000 -t 60.000 -i a.mp4 -ss 5.000 -t 15.000 -i b.mkv -filter_complex \
+             [0:v:0]scale=1280:720:force_original_aspect_ratio=decrease,pad=1280:720:(ow-iw)/2:(oh-ih)/2,setsar=1,fps=60,format=yuv420p[v0];\
+             anullsrc=r=48000:cl=stereo,atrim=duration=60.000[a0];\
+             [1:v:0]scale=1280:720:force_original_aspect_ratio=decrease,pad=1280:720:(ow-iw)/2:(oh-ih)/2,setsar=1,fps=60,format=yuv420p[v1];\
+             [1:a:0]aresample=48000,aformat=channel_layouts=stereo[a1];\
+             [v0][a0][v1][a1]concat=n=2:v=1:a=1[v][a] \
+             -map [v] -map [a] -c:a aac -b:a 192k -c:v libx264 -preset medium -crf 20 -movflags +faststart -y a_merged.mp4"
+        );
+    }
+}
@@ -0,0 +1,338 @@
+//! Cancelling the application's sound the microphone picks up.
+//!
+//! Recording an open microphone next to speakers puts the application's
+//! audio into the recording twice: once captured directly, and once through
+//! the room, a little later and colored by it. The directly captured audio
+//! is the reference: an adaptive filter learns how the room turns it into
+//! the echo in the microphone track, and the echo it predicts is subtracted
+//! before the sources are mixed (see `postprocess::mix`).
+//!
+//! The filter is a partitioned-block frequency-domain adaptive filter (as in
+//! Speex's echo canceller) covering about 85 ms of echo. Device buffering
+//! usually delays the echo by more than that, so that delay is found first by
+//! cross-correlating the start of both tracks, and the reference is delayed
+//! to match. While someone speaks over the reference, the filter adapts
+//! more slowly so their voice isn't learned as echo.
+
+use crate::capture::audio::{WavWriter, BLOCK_ALIGN, SAMPLE_RATE};
+use realfft::num_complex::Complex;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Size of the header [`WavWriter`] writes.
+const HEADER_LEN: u64 = 44;
+
+/// Samples processed at a time, and the length of each filter partition.
+const BLOCK: usize = 256;
+
+/// Filter partitions; with [`BLOCK`] they cover 4096 samples of echo.
+const PARTITIONS: usize = 16;
+
+/// Longest echo delay looked for, in milliseconds.
+const MAX_DELAY_MS: usize = 500;
+
+/// Start of the tracks the delay is found from, in seconds.
+const DELAY_WINDOW_SECS: usize = 30;
+
+/// The delay is found at this fraction of the sample rate.
+const DELAY_DECIMATION: usize = 6;
+
+/// Filter length kept ahead of the found delay, for echo arriving earlier
+/// than it, in milliseconds.
+const DELAY_MARGIN_MS: usize = 10;
+
+/// Adaptation step size.
+const STEP: f32 = 0.5;
+
+/// Smallest fraction of [`STEP`] the filter adapts at while someone speaks
+/// over the reference.
+const MIN_STEP_FRACTION: f32 = 0.1;
+
+/// Reference power per frequency bin below which the filter doesn't adapt,
+/// relative to full scale; about -60 dBFS.
+const MIN_POWER: f32 = 1e-6;
+
+/// Reads 16-bit stereo WAV files written by [`WavWriter`] as mono samples.
+struct MonoReader {
+    reader: BufReader<File>,
+    /// Sample frames left in the file
+    remaining: u64,
+    bytes: Vec<u8>,
+}
+
+impl MonoReader {
+    fn open(path: &Path) -> Result<Self, String> {
+        let mut file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let len = file
+            .metadata()
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+            .len();
+        let mut header = [0u8; HEADER_LEN as usize];
+        file.read_exact(&mut header)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Ok(Self {
+            reader: BufReader::new(file),
+            remaining: len.saturating_sub(HEADER_LEN) / BLOCK_ALIGN as u64,
+            bytes: Vec::new(),
+        })
+    }
+
+    /// Read the next `frames.len()` sample frames, zeros past the end.
+    /// Returns how many were in the file.
+    fn read(&mut self, frames: &mut [[f32; 2]]) -> Result<usize, String> {
+        let read = self.remaining.min(frames.len() as u64) as usize;
+        self.remaining -= read as u64;
+        self.bytes.resize(read * BLOCK_ALIGN as usize, 0);
+        self.reader
+            .read_exact(&mut self.bytes)
+            .map_err(|e| format!("Failed to read audio: {}", e))?;
+        frames.fill([0.0; 2]);
+        for (frame, bytes) in frames.iter_mut().zip(self.bytes.chunks_exact(4)) {
+            *frame = [
+                i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32768.0,
+                i16::from_le_bytes([bytes[2], bytes[3]]) as f32 / 32768.0,
+            ];
+        }
+        Ok(read)
+    }
+
+    /// Read up to `frames` sample frames, mixed to mono.
+    fn read_mono(&mut self, frames: usize) -> Result<Vec<f32>, String> {
+        let mut stereo = vec![[0.0; 2]; frames.min(self.remaining as usize)];
+        self.read(&mut stereo)?;
+        Ok(stereo.iter().map(|[left, right]| (left + right) / 2.0).collect())
+    }
+}
+
+/// Remove the echo of `reference` from `microphone`, writing the result to
+/// `output`.
+pub fn cancel_echo(microphone: &Path, reference: &Path, output: &Path) -> Result<(), String> {
+    let delay = estimate_delay(microphone, reference)?;
+    let delay = delay.saturating_sub(DELAY_MARGIN_MS * SAMPLE_RATE as usize / 1000);
+    tracing::info!(target: "Echo", "Echo delayed by about {} ms", delay * 1000 / SAMPLE_RATE as usize);
+
+    let mut microphone = MonoReader::open(microphone)?;
+    let mut reference = MonoReader::open(reference)?;
+    let mut writer = WavWriter::create(output)?;
+    let mut canceller = EchoCanceller::new();
+    // The reference starts late by the delay, so it lines up with its echo
+    let mut delayed: VecDeque<f32> = std::iter::repeat_n(0.0, delay).collect();
+    let mut mic_frames = [[0.0f32; 2]; BLOCK];
+    let mut ref_frames = [[0.0f32; 2]; BLOCK];
+    let mut out = Vec::with_capacity(BLOCK * BLOCK_ALIGN as usize);
+    while microphone.remaining > 0 {
+        let frames = microphone.read(&mut mic_frames)?;
+        reference.read(&mut ref_frames)?;
+        delayed.extend(ref_frames.iter().map(|[left, right]| (left + right) / 2.0));
+        let reference: Vec<f32> = delayed.drain(..BLOCK).collect();
+        let mic: Vec<f32> = mic_frames.iter().map(|[left, right]| (left + right) / 2.0).collect();
+
+        let echo = canceller.process(&reference, &mic);
+        out.clear();
+        for (frame, &echo) in mic_frames[..frames].iter().zip(&echo) {
+            for sample in frame {
+                let sample = ((sample - echo) * 32768.0).clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+                out.extend_from_slice(&sample.to_le_bytes());
+            }
+        }
+        writer.write(&out)?;
+    }
+    writer.finish()
+}
+
+/// How many samples the echo of `reference` in `microphone` lags behind it,
+/// from the start of both; 0 if there's no clear echo.
+fn estimate_delay(microphone: &Path, reference: &Path) -> Result<usize, String> {
+    let frames = DELAY_WINDOW_SECS * SAMPLE_RATE as usize;
+    let decimate = |samples: Vec<f32>| -> Vec<f32> {
+        samples
+            .chunks(DELAY_DECIMATION)
+            .map(|chunk| chunk.iter().sum::<f32>() / chunk.len() as f32)
+            .collect()
+    };
+    let mic = decimate(MonoReader::open(microphone)?.read_mono(frames)?);
+    let reference = decimate(MonoReader::open(reference)?.read_mono(frames)?);
+    let max_lag = MAX_DELAY_MS * SAMPLE_RATE as usize / 1000 / DELAY_DECIMATION;
+    if mic.len() <= max_lag || reference.is_empty() {
+        return Ok(0);
+    }
+
+    // Cross-correlation of the two, mic[t + lag] * reference[t], by FFT
+    let len = (mic.len() + reference.len()).next_power_of_two();
+    let mut planner = RealFftPlanner::<f32>::new();
+    let forward = planner.plan_fft_forward(len);
+    let inverse = planner.plan_fft_inverse(len);
+    let spectrum = |samples: &[f32]| -> Result<Vec<Complex<f32>>, String> {
+        let mut input = forward.make_input_vec();
+        input[..samples.len()].copy_from_slice(samples);
+        let mut output = forward.make_output_vec();
+        forward.process(&mut input, &mut output).map_err(|e| e.to_string())?;
+        Ok(output)
+    };
+    let mut product: Vec<Complex<f32>> = spectrum(&mic)?
+        .iter()
+        .zip(spectrum(&reference)?)
+        .map(|(mic, reference)| mic * reference.conj())
+        .collect();
+    product[0].im = 0.0;
+    if let Some(last) = product.last_mut() {
+        last.im = 0.0;
+    }
+    let mut correlation = inverse.make_output_vec();
+    inverse.process(&mut product, &mut correlation).map_err(|e| e.to_string())?;
+
+    let lags = &correlation[..=max_lag];
+    let (lag, peak) = lags
+        .iter()
+        .enumerate()
+        .map(|(lag, value)| (lag, value.abs()))
+        .fold((0, 0.0f32), |best, candidate| if candidate.1 > best.1 { candidate } else { best });
+    let mean = lags.iter().map(|value| value.abs()).sum::<f32>() / lags.len() as f32;
+    // A peak that doesn't stand out is noise, not an echo
+    Ok(if peak > 4.0 * mean { lag * DELAY_DECIMATION } else { 0 })
+}
+
+/// Partitioned-block frequency-domain adaptive filter predicting the echo of
+/// a reference signal.
+struct EchoCanceller {
+    forward: Arc<dyn RealToComplex<f32>>,
+    inverse: Arc<dyn ComplexToReal<f32>>,
+    /// The previous block of reference samples
+    previous: Vec<f32>,
+    /// Spectra of the last [`PARTITIONS`] reference blocks, newest first
+    history: VecDeque<Vec<Complex<f32>>>,
+    /// The filter, one spectrum per partition
+    weights: Vec<Vec<Complex<f32>>>,
+    /// Smoothed reference power per frequency bin
+    power: Vec<f32>,
+    /// Partition whose filter is trimmed to its length next
+    constrain_next: usize,
+}
+
+impl EchoCanceller {
+    fn new() -> Self {
+        let mut planner = RealFftPlanner::<f32>::new();
+        let forward = planner.plan_fft_forward(2 * BLOCK);
+        let inverse = planner.plan_fft_inverse(2 * BLOCK);
+        let bins = BLOCK + 1;
+        Self {
+            forward,
+            inverse,
+            previous: vec![0.0; BLOCK],
+            history: VecDeque::from(vec![vec![Complex::default(); bins]; PARTITIONS]),
+            weights: vec![vec![Complex::default(); bins]; PARTITIONS],
+            power: vec![0.0; bins],
+            constrain_next: 0,
+        }
+    }
+
+    fn fft(&self, samples: &mut [f32]) -> Vec<Complex<f32>> {
+        let mut spectrum = self.forward.make_output_vec();
+        // Lengths always match the plan
+        let _ = self.forward.process(samples, &mut spectrum);
+        spectrum
+    }
+
+    fn ifft(&self, spectrum: &mut [Complex<f32>]) -> Vec<f32> {
+        spectrum[0].im = 0.0;
+        spectrum[BLOCK].im = 0.0;
+        let mut samples = self.inverse.make_output_vec();
+        let _ = self.inverse.process(spectrum, &mut samples);
+        samples.iter_mut().for_each(|sample| *sample /= (2 * BLOCK) as f32);
+        samples
+    }
+
+    /// Take the next [`BLOCK`] samples of the reference and the microphone,
+    /// and return the echo predicted in the microphone's.
+    fn process(&mut self, reference: &[f32], microphone: &[f32]) -> Vec<f32> {
+        let mut input = [self.previous.as_slice(), reference].concat();
+        self.previous.copy_from_slice(reference);
+        let spectrum = self.fft(&mut input);
+        for (power, bin) in self.power.iter_mut().zip(&spectrum) {
+            *power = 0.9 * *power + 0.1 * bin.norm_sqr();
+        }
+        self.history.pop_back();
+        self.history.push_front(spectrum);
+
+        // The echo: the reference filtered by each partition, summed
+        let mut predicted = vec![Complex::default(); BLOCK + 1];
+        for (weights, spectrum) in self.weights.iter().zip(&self.history) {
+            for ((predicted, weight), bin) in predicted.iter_mut().zip(weights).zip(spectrum) {
+                *predicted += weight * bin;
+            }
+        }
+        let echo = self.ifft(&mut predicted)[BLOCK..].to_vec();
+        let error: Vec<f32> = microphone.iter().zip(&echo).map(|(mic, echo)| mic - echo).collect();
+
+        // Speech over the reference leaves more error than the echo
+        // explains; adapt more slowly then
+        let echo_energy: f32 = echo.iter().map(|s| s * s).sum();
+        let error_energy: f32 = error.iter().map(|s| s * s).sum();
+        let step = STEP * (echo_energy / error_energy.max(f32::MIN_POSITIVE)).clamp(MIN_STEP_FRACTION, 1.0);
+
+        let mut padded = vec![0.0; BLOCK];
+        padded.extend_from_slice(&error);
+        let error_spectrum = self.fft(&mut padded);
+        let min_power = MIN_POWER * (2 * BLOCK) as f32;
+        for (weights, spectrum) in self.weights.iter_mut().zip(&self.history) {
+            for (k, weight) in weights.iter_mut().enumerate() {
+                if self.power[k] > min_power {
+                    *weight += error_spectrum[k] * spectrum[k].conj() * (step / (PARTITIONS as f32 * self.power[k]));
+                }
+            }
+        }
+
+        // Keep one partition at a time to its length, so the filter stays a
+        // linear rather than circular one
+        let partition = self.constrain_next;
+        self.constrain_next = (partition + 1) % PARTITIONS;
+        let mut weights = std::mem::take(&mut self.weights[partition]);
+        let mut filter = self.ifft(&mut weights);
+        filter[BLOCK..].fill(0.0);
+        self.weights[partition] = self.fft(&mut filter);
+
+        echo
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic white noise.
+    fn noise(len: usize, mut seed: u32) -> Vec<f32> {
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (seed >> 8) as f32 / (1u32 << 24) as f32 - 0.5
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_echo_canceller() {
+        // The room: a direct path and a reflection, 20 ms apart
+        let reference = noise(SAMPLE_RATE as usize * 4, 1);
+        let echo = |t: usize| {
+            let at = |delay: usize| if t >= delay { reference[t - delay] } else { 0.0 };
+            0.5 * at(300) - 0.2 * at(1260)
+        };
+        let microphone: Vec<f32> = (0..reference.len()).map(echo).collect();
+
+        let mut canceller = EchoCanceller::new();
+        let mut residual = Vec::new();
+        for (reference, microphone) in reference.chunks_exact(BLOCK).zip(microphone.chunks_exact(BLOCK)) {
+            let predicted = canceller.process(reference, microphone);
+            residual.extend(microphone.iter().zip(predicted).map(|(mic, echo)| mic - echo));
+        }
+        // The last second, once the filter has converged
+        let last = SAMPLE_RATE as usize;
+        let energy = |samples: &[f32]| samples.iter().map(|s| s * s).sum::<f32>();
+        let reduction = energy(&microphone[microphone.len() - last..]) / energy(&residual[residual.len() - last..]);
+        assert!(10.0 * reduction.log10() > 20.0, "echo reduced by only {} dB", 10.0 * reduction.log10());
+    }
+}
@@ -0,0 +1,160 @@
+//! Crop and resize export of saved recordings.
+
+use super::derived_path;
+use crate::encoder::ffmpeg::{probe, run_ffmpeg_with_progress};
+use crate::jobs::JobContext;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// A sub-rectangle of the video frame, in source pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// What to do to the video when exporting.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExportOptions {
+    /// Crop to this rectangle first
+    pub crop: Option<CropRect>,
+    /// Target width; if only one dimension is given the aspect ratio is kept
+    pub width: Option<u32>,
+    /// Target height
+    pub height: Option<u32>,
+}
+
+/// Export a cropped and/or scaled copy of `input` next to it. Returns the output path.
+pub fn export(input: &Path, options: &ExportOptions, context: &JobContext) -> Result<PathBuf, String> {
+    let info = probe(input)?;
+    let filter = build_filter(options, info.width, info.height)?;
+
+    let output = derived_path(input, "export");
+    tracing::info!(target: "Export", "{} ({}) -> {}", input.display(), filter, output.display());
+
+    let result = run_ffmpeg_with_progress(
+        [
+            "-i".to_string(),
+            input.to_string_lossy().to_string(),
+            "-map".to_string(),
+            "0".to_string(),
+            "-vf".to_string(),
+            filter,
+            "-c:v".to_string(),
+            "libx264".to_string(),
+            // Exports are deliverables, so favor quality over encoding speed
+            "-preset".to_string(),
+            "medium".to_string(),
+            "-crf".to_string(),
+            "20".to_string(),
+            "-pix_fmt".to_string(),
+            "yuv420p".to_string(),
+            "-c:a".to_string(),
+            "copy".to_string(),
+            "-movflags".to_string(),
+            "+faststart".to_string(),
+            "-y".to_string(),
+            output.to_string_lossy().to_string(),
+        ],
+        info.duration,
+        context.cancel_flag(),
+        |fraction| context.report(fraction),
+    );
+
+    if let Err(e) = result {
+        // Don't leave a partial file behind
+        let _ = std::fs::remove_file(&output);
+        return Err(e);
+    }
+
+    Ok(output)
+}
+
+/// Build the FFmpeg video filter for the export options.
+///
+/// `source_width`/`source_height` are used to validate the crop; pass 0 if unknown.
+fn build_filter(options: &ExportOptions, source_width: u32, source_height: u32) -> Result<String, String> {
+    let mut filters = Vec::new();
+
+    if let Some(crop) = options.crop {
+        if crop.width < 2 || crop.height < 2 {
+            return Err("Crop rectangle is too small".to_string());
+        }
+        let known_size = source_width > 0 && source_height > 0;
+        if known_size && (crop.x + crop.width > source_width || crop.y + crop.height > source_height) {
+            return Err(format!(
+                "Crop rectangle {}x{} at ({}, {}) exceeds the {}x{} video",
+                crop.width, crop.height, crop.x, crop.y, source_width, source_height
+            ));
+        }
+        // H.264 with yuv420p needs even dimensions
+        filters.push(format!(
+            "crop={}:{}:{}:{}",
+            crop.width & !1,
+            crop.height & !1,
+            crop.x,
+            crop.y
+        ));
+    }
+
+    // -2 keeps the aspect ratio while rounding to an even size
+    let scale = match (options.width, options.height) {
+        (Some(width), Some(height)) => Some(format!("{}:{}", width & !1, height & !1)),
+        (Some(width), None) => Some(format!("{}:-2", width & !1)),
+        (None, Some(height)) => Some(format!("-2:{}", height & !1)),
+        (None, None) => None,
+    };
+    if let Some(scale) = scale {
+        if scale.starts_with("0:") || scale.ends_with(":0") {
+            return Err("Target resolution is too small".to_string());
+        }
+        filters.push(format!("scale={}:flags=lanczos", scale));
+    }
+
+    if filters.is_empty() {
+        return Err("Nothing to export: specify a crop rectangle and/or a target resolution".to_string());
+    }
+
+    Ok(filters.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_filter() {
+        let options = ExportOptions {
+            crop: Some(CropRect {
+                x: 10,
+                y: 20,
+                width: 1281,
+                height: 721,
+            }),
+            width: None,
+            height: Some(1080),
+        };
+        assert_eq!(
+            build_filter(&options, 3840, 2160).unwrap(),
+            "crop=1280:720:10:20,scale=-2:1080:flags=lanczos"
+        );
+    }
+
+    #[test]
+    fn test_build_filter_invalid() {
+        assert!(build_filter(&ExportOptions::default(), 1920, 1080).is_err());
+
+        let out_of_bounds = ExportOptions {
+            crop: Some(CropRect {
+                x: 1000,
+                y: 0,
+                width: 1000,
+                height: 100,
+            }),
+            ..Default::default()
+        };
+        assert!(build_filter(&out_of_bounds, 1920, 1080).is_err());
+    }
+}
@@ -0,0 +1,132 @@
+//! Exporting recordings to intermediate codecs for editing.
+//!
+//! H.264 and the other delivery codecs compress across long groups of
+//! frames, so an editor scrubbing through them has to decode from the last
+//! keyframe for every frame it shows. ProRes and DNxHR compress each frame on
+//! its own, which makes them scrub smoothly in Premiere and Resolve at the
+//! cost of much larger files. Exports are written as MOV with PCM audio,
+//! which both editors take without conversion.
+
+use super::derived_path;
+use crate::encoder::ffmpeg::{probe, run_ffmpeg_with_progress};
+use crate::jobs::JobContext;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Smallest frame the DNxHR encoder accepts.
+const DNXHR_MIN_SIZE: (u32, u32) = (256, 120);
+
+/// Intermediate codec and profile to export to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntermediateCodec {
+    /// ProRes 422: 10-bit, the usual choice for editing
+    Prores422,
+    /// ProRes 422 HQ: 10-bit at a higher data rate
+    Prores422Hq,
+    /// ProRes 4444: 10-bit with full chroma, for keying and compositing
+    Prores4444,
+    /// DNxHR SQ: 8-bit, the smallest DNxHR files
+    DnxhrSq,
+    /// DNxHR HQ: 8-bit at a higher data rate
+    DnxhrHq,
+    /// DNxHR HQX: 10-bit
+    DnxhrHqx,
+    /// DNxHR 444: 10-bit with full chroma
+    Dnxhr444,
+}
+
+impl IntermediateCodec {
+    fn is_prores(&self) -> bool {
+        matches!(
+            self,
+            IntermediateCodec::Prores422 | IntermediateCodec::Prores422Hq | IntermediateCodec::Prores4444
+        )
+    }
+
+    /// FFmpeg encoder, profile and pixel format.
+    fn encoder_args(&self) -> [&'static str; 6] {
+        let (encoder, profile, pixel_format) = match self {
+            IntermediateCodec::Prores422 => ("prores_ks", "2", "yuv422p10le"),
+            IntermediateCodec::Prores422Hq => ("prores_ks", "3", "yuv422p10le"),
+            IntermediateCodec::Prores4444 => ("prores_ks", "4", "yuv444p10le"),
+            IntermediateCodec::DnxhrSq => ("dnxhd", "dnxhr_sq", "yuv422p"),
+            IntermediateCodec::DnxhrHq => ("dnxhd", "dnxhr_hq", "yuv422p"),
+            IntermediateCodec::DnxhrHqx => ("dnxhd", "dnxhr_hqx", "yuv422p10le"),
+            IntermediateCodec::Dnxhr444 => ("dnxhd", "dnxhr_444", "yuv444p10le"),
+        };
+        ["-c:v", encoder, "-profile:v", profile, "-pix_fmt", pixel_format]
+    }
+}
+
+/// Export `input` to `codec`, writing a MOV file next to it. Returns the
+/// output path.
+pub fn export(input: &Path, codec: IntermediateCodec, context: &JobContext) -> Result<PathBuf, String> {
+    let info = probe(input)?;
+    if !codec.is_prores() && (info.width < DNXHR_MIN_SIZE.0 || info.height < DNXHR_MIN_SIZE.1) {
+        return Err(format!(
+            "DNxHR needs frames of at least {}x{}, but the recording is {}x{}",
+            DNXHR_MIN_SIZE.0, DNXHR_MIN_SIZE.1, info.width, info.height
+        ));
+    }
+
+    let suffix = if codec.is_prores() { "prores" } else { "dnxhr" };
+    let output = derived_path(&input.with_extension("mov"), suffix);
+    tracing::info!(target: "Intermediate", "{} ({:?}) -> {}", input.display(), codec, output.display());
+
+    let result = run_ffmpeg_with_progress(
+        intermediate_args(input, &output, codec),
+        info.duration,
+        context.cancel_flag(),
+        |fraction| context.report(fraction),
+    );
+
+    if let Err(e) = result {
+        // Don't leave a partial file behind
+        let _ = std::fs::remove_file(&output);
+        return Err(e);
+    }
+
+    Ok(output)
+}
+
+/// FFmpeg arguments re-encoding the video of `input` to `codec` and its
+/// audio to PCM.
+fn intermediate_args(input: &Path, output: &Path, codec: IntermediateCodec) -> Vec<String> {
+    let mut args = vec![
+        "-i".to_string(),
+        input.to_string_lossy().to_string(),
+        // Subtitle and data streams don't carry over to editors
+        "-map".to_string(),
+        "0:v:0".to_string(),
+        "-map".to_string(),
+        "0:a?".to_string(),
+    ];
+    args.extend(codec.encoder_args().map(String::from));
+    if codec.is_prores() {
+        // Tag the files as Apple's encoder would, which some tools check
+        args.extend(["-vendor", "apl0"].map(String::from));
+    }
+    args.extend(["-c:a", "pcm_s16le", "-y"].map(String::from));
+    args.push(output.to_string_lossy().to_string());
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intermediate_args() {
+        let args = intermediate_args(Path::new("a.mp4"), Path::new("a_prores.mov"), IntermediateCodec::Prores422);
+        assert_eq!(
+            args.join(" "),
+            "-i a.mp4 -map 0:v:0 -map 0:a? -c:v prores_ks -profile:v 2 -pix_fmt yuv422p10le -vendor apl0 -c:a pcm_s16le -y a_prores.mov"
+        );
+        let args = intermediate_args(Path::new("a.mkv"), Path::new("a_dnxhr.mov"), IntermediateCodec::DnxhrHqx);
+        assert_eq!(
+            args.join(" "),
+            "-i a.mkv -map 0:v:0 -map 0:a? -c:v dnxhd -profile:v dnxhr_hqx -pix_fmt yuv422p10le -c:a pcm_s16le -y a_dnxhr.mov"
+        );
+    }
+}
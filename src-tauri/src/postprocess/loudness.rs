@@ -0,0 +1,207 @@
+//! EBU R128 loudness normalization.
+//!
+//! Platforms play everything at a set loudness (around -14 LUFS on YouTube,
+//! -16 LUFS for podcasts) and turn down anything louder, so recordings that
+//! are already there sound as intended. FFmpeg's `loudnorm` filter does this
+//! best in two passes: the first measures the audio, and the second applies
+//! one gain for the whole file from the measurement, which keeps the
+//! dynamics intact instead of riding the level as it goes.
+//!
+//! With a target set, recordings are normalized when their audio is added
+//! on finalize, and a remux can normalize as it copies.
+
+use super::mux::audio_codec;
+use crate::encoder::ffmpeg::{ffmpeg_stderr, run_ffmpeg};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Loudness range allowed, in LU. Audio with a wider range than this is
+/// allowed its own, so the gain stays linear.
+const TARGET_LRA: f64 = 11.0;
+
+/// Range of integrated loudness targets, in LUFS.
+const LUFS_RANGE: (f64, f64) = (-30.0, -5.0);
+/// Range of true peak limits, in dBTP.
+const TRUE_PEAK_RANGE: (f64, f64) = (-9.0, 0.0);
+
+/// Loudness to normalize to.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LoudnessTarget {
+    /// Integrated loudness, in LUFS, e.g. -14 for YouTube or -16 for podcasts
+    pub lufs: f64,
+    /// Highest true peak, in dBTP
+    #[serde(default = "default_true_peak_db")]
+    pub true_peak_db: f64,
+}
+
+fn default_true_peak_db() -> f64 {
+    -1.0
+}
+
+/// The first pass's measurement of a file's audio.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurement {
+    /// Integrated loudness, in LUFS
+    pub input_i: f64,
+    /// True peak, in dBTP
+    pub input_tp: f64,
+    /// Loudness range, in LU
+    pub input_lra: f64,
+    /// Gating threshold, in LUFS
+    pub input_thresh: f64,
+    /// Gain left over after the normalization, in LU
+    pub target_offset: f64,
+}
+
+static TARGET: Mutex<Option<LoudnessTarget>> = Mutex::new(None);
+
+/// Set the loudness recordings are normalized to on finalize, or `None` to
+/// leave them as recorded.
+pub fn set_target(target: Option<LoudnessTarget>) -> Result<(), String> {
+    if let Some(target) = target {
+        if !(LUFS_RANGE.0..=LUFS_RANGE.1).contains(&target.lufs)
+            || !(TRUE_PEAK_RANGE.0..=TRUE_PEAK_RANGE.1).contains(&target.true_peak_db)
+        {
+            return Err(format!(
+                "The loudness target must be {} to {} LUFS and the true peak {} to {} dBTP",
+                LUFS_RANGE.0, LUFS_RANGE.1, TRUE_PEAK_RANGE.0, TRUE_PEAK_RANGE.1
+            ));
+        }
+    }
+    *TARGET.lock().unwrap() = target;
+    Ok(())
+}
+
+/// The loudness recordings are normalized to on finalize, if any.
+pub fn target() -> Option<LoudnessTarget> {
+    *TARGET.lock().unwrap()
+}
+
+impl LoudnessTarget {
+    fn loudnorm(&self, lra: f64) -> String {
+        format!("loudnorm=I={}:TP={}:LRA={}", self.lufs, self.true_peak_db, lra)
+    }
+}
+
+/// Measure the loudness of the first audio stream of `input`. This reads
+/// the whole file; call it from a blocking task.
+pub fn measure(input: &Path, target: &LoudnessTarget) -> Result<Measurement, String> {
+    let stderr = ffmpeg_stderr([
+        "-i".to_string(),
+        input.to_string_lossy().to_string(),
+        "-map".to_string(),
+        "0:a:0".to_string(),
+        "-af".to_string(),
+        format!("{}:print_format=json", target.loudnorm(TARGET_LRA)),
+        "-f".to_string(),
+        "null".to_string(),
+        "-".to_string(),
+    ])?;
+    let measurement = parse_measurement(&stderr).ok_or_else(|| {
+        format!(
+            "Failed to measure the loudness of {}: {}",
+            input.display(),
+            stderr.lines().last().unwrap_or("no output")
+        )
+    })?;
+    tracing::info!(
+        target: "Loudness",
+        "{}: {:.1} LUFS, {:.1} dBTP, {:.1} LU",
+        input.display(),
+        measurement.input_i,
+        measurement.input_tp,
+        measurement.input_lra
+    );
+    Ok(measurement)
+}
+
+/// The second pass's filter, normalizing audio measured as `measurement`
+/// to `target` with one gain.
+pub fn normalize_filter(target: &LoudnessTarget, measurement: &Measurement) -> String {
+    format!(
+        "{}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true,aresample=48000",
+        target.loudnorm(TARGET_LRA.max(measurement.input_lra)),
+        measurement.input_i,
+        measurement.input_tp,
+        measurement.input_lra,
+        measurement.input_thresh,
+        measurement.target_offset
+    )
+}
+
+/// Normalize the audio of `video` to `target` in place, copying the other
+/// streams.
+pub fn normalize_in_place(video: &Path, target: &LoudnessTarget) -> Result<(), String> {
+    let measurement = measure(video, target)?;
+    let extension = video
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let codec = audio_codec(&extension).ok_or_else(|| format!("{} files can't contain audio", extension))?;
+
+    // Write next to the video so the final rename stays on one filesystem
+    let normalized = video.with_extension(format!("normalizing.{}", extension));
+    let mut args = vec![
+        "-i".to_string(),
+        video.to_string_lossy().to_string(),
+        "-map".to_string(),
+        "0".to_string(),
+        "-c".to_string(),
+        "copy".to_string(),
+        "-af".to_string(),
+        normalize_filter(target, &measurement),
+        "-c:a".to_string(),
+        codec.to_string(),
+        "-b:a".to_string(),
+        "192k".to_string(),
+    ];
+    if extension == "mp4" || extension == "mov" {
+        args.extend(["-movflags".to_string(), "+faststart".to_string()]);
+    }
+    args.extend(["-y".to_string(), normalized.to_string_lossy().to_string()]);
+
+    if let Err(e) = run_ffmpeg(args) {
+        let _ = std::fs::remove_file(&normalized);
+        return Err(e);
+    }
+    std::fs::rename(&normalized, video).map_err(|e| format!("Failed to replace recording: {}", e))
+}
+
+/// The measurement in the JSON block `loudnorm` prints at the end of the
+/// first pass.
+fn parse_measurement(stderr: &str) -> Option<Measurement> {
+    let json = &stderr[stderr.rfind('{')?..=stderr.rfind('}')?];
+    let values: serde_json::Value = serde_json::from_str(json).ok()?;
+    // The values are strings, and "-inf" for silence, which can't be normalized
+    let value = |key: &str| values.get(key)?.as_str()?.parse::<f64>().ok().filter(|v| v.is_finite());
+    Some(Measurement {
+        input_i: value("input_i")?,
+        input_tp: value("input_tp")?,
+        input_lra: value("input_lra")?,
+        input_thresh: value("input_thresh")?,
+        target_offset: value("target_offset")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_measurement() {
+        let stderr = "[Parsed_loudnorm_0 @ 0x5581]\n{\n\t\"input_i\" : \"-27.61\",\n\t\"input_tp\" : \"-4.47\",\n\t\"input_lra\" : \"18.06\",\n\t\"input_thresh\" : \"-39.20\",\n\t\"output_i\" : \"-16.58\",\n\t\"normalization_type\" : \"dynamic\",\n\t\"target_offset\" : \"0.58\"\n}\n";
+        let measurement = parse_measurement(stderr).unwrap();
+        assert_eq!(
+            measurement,
+            Measurement { input_i: -27.61, input_tp: -4.47, input_lra: 18.06, input_thresh: -39.2, target_offset: 0.58 }
+        );
+        let target = LoudnessTarget { lufs: -14.0, true_peak_db: -1.0 };
+        assert_eq!(
+            normalize_filter(&target, &measurement),
+            "loudnorm=I=-14:TP=-1:LRA=18.06:measured_I=-27.61:measured_TP=-4.47:measured_LRA=18.06:\
+             measured_thresh=-39.2:offset=0.58:linear=true,aresample=48000"
+        );
+        assert_eq!(parse_measurement(&stderr.replace("\"-27.61\"", "\"-inf\"")), None);
+    }
+}
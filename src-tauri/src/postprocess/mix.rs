@@ -0,0 +1,160 @@
+//! Mixing a recording's audio sources into one track.
+//!
+//! Each source is captured into a WAV file of its own, all starting at the
+//! recording clock's zero. Sources rarely have the same latency, so each is
+//! shifted by its own sync offset while they're summed; the offset the whole
+//! mix still needs is applied when it's muxed (see `avsync::AudioSync`).
+
+use crate::capture::audio::{WavWriter, BLOCK_ALIGN, SAMPLE_RATE};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Size of the header [`WavWriter`] writes.
+const HEADER_LEN: u64 = 44;
+
+/// Sample frames mixed at a time.
+const BLOCK_FRAMES: usize = 4096;
+
+/// A captured source to mix.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+    pub path: PathBuf,
+    /// How far to delay the source against the others, in milliseconds;
+    /// negative moves it earlier
+    pub delay_ms: i32,
+}
+
+/// Sample frames `delay_ms` covers.
+fn delay_frames(delay_ms: i32) -> i64 {
+    delay_ms as i64 * SAMPLE_RATE as i64 / 1000
+}
+
+/// Reads a track's samples with its delay applied: silence first when it's
+/// delayed, its first samples skipped when it's moved earlier.
+struct DelayedReader {
+    reader: BufReader<File>,
+    /// Silent sample frames still to come before the track's own
+    silence: u64,
+    /// Sample frames left in the file
+    remaining: u64,
+}
+
+impl DelayedReader {
+    fn open(track: &Track) -> Result<Self, String> {
+        let mut file = File::open(&track.path).map_err(|e| format!("Failed to open {}: {}", track.path.display(), e))?;
+        let len = file
+            .metadata()
+            .map_err(|e| format!("Failed to read {}: {}", track.path.display(), e))?
+            .len();
+        let frames = len.saturating_sub(HEADER_LEN) / BLOCK_ALIGN as u64;
+        let delay = delay_frames(track.delay_ms);
+        let skipped = (-delay).clamp(0, frames as i64) as u64;
+        file.seek(SeekFrom::Start(HEADER_LEN + skipped * BLOCK_ALIGN as u64))
+            .map_err(|e| format!("Failed to read {}: {}", track.path.display(), e))?;
+        Ok(Self {
+            reader: BufReader::new(file),
+            silence: delay.max(0) as u64,
+            remaining: frames - skipped,
+        })
+    }
+
+    /// Sample frames left, counting the leading silence.
+    fn len(&self) -> u64 {
+        self.silence + self.remaining
+    }
+
+    /// Fill `samples` with the next interleaved samples, zeros past the end.
+    fn read(&mut self, samples: &mut [i16], bytes: &mut Vec<u8>) -> Result<(), String> {
+        let channels = BLOCK_ALIGN as usize / 2;
+        let frames = samples.len() / channels;
+        let silent = (self.silence.min(frames as u64)) as usize;
+        self.silence -= silent as u64;
+        let read = (self.remaining.min((frames - silent) as u64)) as usize;
+        self.remaining -= read as u64;
+
+        samples.fill(0);
+        bytes.resize(read * BLOCK_ALIGN as usize, 0);
+        self.reader
+            .read_exact(bytes)
+            .map_err(|e| format!("Failed to read audio: {}", e))?;
+        for (sample, bytes) in samples[silent * channels..].iter_mut().zip(bytes.chunks_exact(2)) {
+            *sample = i16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+        Ok(())
+    }
+}
+
+/// Mix `tracks` into a WAV file at `output`, as long as the longest of them
+/// once delayed.
+pub fn mix_tracks(tracks: &[Track], output: &Path) -> Result<(), String> {
+    let mut readers = tracks.iter().map(DelayedReader::open).collect::<Result<Vec<_>, _>>()?;
+    let mut writer = WavWriter::create(output)?;
+    let channels = BLOCK_ALIGN as usize / 2;
+    let mut remaining = readers.iter().map(DelayedReader::len).max().unwrap_or(0);
+    let mut track = vec![0i16; BLOCK_FRAMES * channels];
+    let mut mixed = vec![0i32; BLOCK_FRAMES * channels];
+    let mut bytes = Vec::new();
+    let mut out = Vec::with_capacity(BLOCK_FRAMES * BLOCK_ALIGN as usize);
+    while remaining > 0 {
+        let frames = remaining.min(BLOCK_FRAMES as u64) as usize;
+        let samples = frames * channels;
+        mixed[..samples].fill(0);
+        for reader in &mut readers {
+            reader.read(&mut track[..samples], &mut bytes)?;
+            for (mixed, &sample) in mixed.iter_mut().zip(&track[..samples]) {
+                *mixed += sample as i32;
+            }
+        }
+        out.clear();
+        for &sample in &mixed[..samples] {
+            out.extend_from_slice(&(sample.clamp(i16::MIN as i32, i16::MAX as i32) as i16).to_le_bytes());
+        }
+        writer.write(&out)?;
+        remaining -= frames as u64;
+    }
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_track(name: &str, frames: &[[i16; 2]]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("mix-test-{}-{}.wav", name, std::process::id()));
+        let mut writer = WavWriter::create(&path).unwrap();
+        let bytes: Vec<u8> = frames.iter().flatten().flat_map(|s| s.to_le_bytes()).collect();
+        writer.write(&bytes).unwrap();
+        writer.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn test_mix_tracks() {
+        // 48 frames are 1 ms
+        let application = write_track("application", &[[3000, 3000]; 144]);
+        let microphone = write_track("microphone", &[[30000, -30000]; 48]);
+        let output = std::env::temp_dir().join(format!("mix-test-output-{}.wav", std::process::id()));
+        let tracks = [
+            Track { path: application.clone(), delay_ms: -1 },
+            Track { path: microphone.clone(), delay_ms: 1 },
+        ];
+        mix_tracks(&tracks, &output).unwrap();
+        let bytes = std::fs::read(&output).unwrap();
+        for path in [&application, &microphone, &output] {
+            let _ = std::fs::remove_file(path);
+        }
+
+        let samples: Vec<i16> = bytes[HEADER_LEN as usize..]
+            .chunks_exact(2)
+            .map(|s| i16::from_le_bytes([s[0], s[1]]))
+            .collect();
+        // The application moved 1 ms earlier, the microphone 1 ms later
+        assert_eq!(samples.len(), 96 * 2);
+        assert_eq!(samples[..2], [3000, 3000]);
+        assert_eq!(samples[47 * 2..47 * 2 + 2], [3000, 3000]);
+        // Summed, and clipped rather than wrapped
+        assert_eq!(samples[48 * 2..48 * 2 + 2], [i16::MAX, -27000]);
+        assert_eq!(samples[95 * 2..], [i16::MAX, -27000]);
+    }
+}
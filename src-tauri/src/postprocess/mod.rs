@@ -0,0 +1,54 @@
+//! Post-processing of finished recordings.
+
+pub mod chapters;
+pub mod concat;
+pub mod echo;
+pub mod export;
+pub mod intermediate;
+pub mod loudness;
+pub mod mix;
+pub mod mux;
+pub mod remux;
+pub mod scrub;
+pub mod speed;
+pub mod subtitles;
+pub mod thumbnail;
+pub mod trim;
+
+use std::path::{Path, PathBuf};
+
+/// Path for a file derived from `input`, e.g. `recording_trimmed.mp4`.
+///
+/// A numeric suffix is added if the file already exists so nothing is overwritten.
+pub fn derived_path(input: &Path, suffix: &str) -> PathBuf {
+    let stem = input
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "recording".to_string());
+    let extension = input
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_else(|| "mp4".to_string());
+
+    let mut candidate = input.with_file_name(format!("{}_{}.{}", stem, suffix, extension));
+    let mut counter = 2;
+    while candidate.exists() {
+        candidate = input.with_file_name(format!("{}_{}_{}.{}", stem, suffix, counter, extension));
+        counter += 1;
+    }
+    candidate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_derived_path() {
+        let input = Path::new("/nonexistent/recording_2024-01-01_120000.mp4");
+        assert_eq!(
+            derived_path(input, "trimmed"),
+            Path::new("/nonexistent/recording_2024-01-01_120000_trimmed.mp4")
+        );
+    }
+}
@@ -0,0 +1,77 @@
+//! Adding separately captured audio to a finished recording.
+
+use crate::avsync::AudioSync;
+use crate::capture::audio::SAMPLE_RATE;
+use crate::dynamics::DynamicsSettings;
+use crate::encoder::ffmpeg::run_ffmpeg;
+use std::path::Path;
+
+/// Mux `audio` into `video` in place, copying the video stream. The audio
+/// is lined up with the video by `sync`, then processed by `dynamics`.
+pub fn add_audio(video: &Path, audio: &Path, sync: &AudioSync, dynamics: &DynamicsSettings) -> Result<(), String> {
+    let extension = video
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let codec = audio_codec(&extension).ok_or_else(|| format!("{} files can't contain audio", extension))?;
+
+    // Write next to the video so the final rename stays on one filesystem
+    let muxed = video.with_extension(format!("muxing.{}", extension));
+    let mut args = vec![
+        "-i".to_string(),
+        video.to_string_lossy().to_string(),
+        "-i".to_string(),
+        audio.to_string_lossy().to_string(),
+        "-map".to_string(),
+        "0:v".to_string(),
+        "-map".to_string(),
+        "1:a".to_string(),
+    ];
+    let filters: Vec<String> = [sync.filter(SAMPLE_RATE), dynamics.filter()].into_iter().flatten().collect();
+    if !filters.is_empty() {
+        args.extend(["-af".to_string(), filters.join(",")]);
+    }
+    args.extend([
+        "-c:v".to_string(),
+        "copy".to_string(),
+        "-c:a".to_string(),
+        codec.to_string(),
+        "-b:a".to_string(),
+        "192k".to_string(),
+        "-shortest".to_string(),
+    ]);
+    if extension == "mp4" || extension == "mov" {
+        args.extend(["-movflags".to_string(), "+faststart".to_string()]);
+    }
+    args.extend(["-y".to_string(), muxed.to_string_lossy().to_string()]);
+
+    if let Err(e) = run_ffmpeg(args) {
+        let _ = std::fs::remove_file(&muxed);
+        return Err(e);
+    }
+    std::fs::rename(&muxed, video).map_err(|e| format!("Failed to replace recording: {}", e))
+}
+
+/// Audio encoder for a container, or `None` if it can't hold audio.
+pub(super) fn audio_codec(extension: &str) -> Option<&'static str> {
+    match extension {
+        "gif" => None,
+        "webm" => Some("libopus"),
+        // Lossless, to match the lossless codecs recorded to MKV
+        "mkv" => Some("flac"),
+        _ => Some("aac"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audio_codec() {
+        assert_eq!(audio_codec("mp4"), Some("aac"));
+        assert_eq!(audio_codec("webm"), Some("libopus"));
+        assert_eq!(audio_codec("mkv"), Some("flac"));
+        assert_eq!(audio_codec("gif"), None);
+    }
+}
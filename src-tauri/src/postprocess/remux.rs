@@ -0,0 +1,114 @@
+//! Remuxing recordings into another container.
+//!
+//! The streams are copied rather than re-encoded, so remuxing is lossless
+//! and only takes as long as reading the file, e.g. to turn an MKV recording
+//! (which survives a crash) into an MP4 that editors and browsers open.
+//! Normalizing the loudness on the way re-encodes the audio, but still only
+//! copies the video.
+
+use super::derived_path;
+use super::loudness::{self, LoudnessTarget};
+use super::mux::audio_codec;
+use crate::encoder::ffmpeg::{probe, run_ffmpeg_with_progress};
+use crate::jobs::JobContext;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Container to remux into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Container {
+    Mp4,
+    Mkv,
+    Mov,
+    Webm,
+}
+
+impl Container {
+    fn extension(&self) -> &'static str {
+        match self {
+            Container::Mp4 => "mp4",
+            Container::Mkv => "mkv",
+            Container::Mov => "mov",
+            Container::Webm => "webm",
+        }
+    }
+}
+
+/// Remux `input` into `container`, writing a new file next to it, with its
+/// audio normalized to `loudness` if given. Returns the output path.
+pub fn remux(
+    input: &Path,
+    container: Container,
+    loudness: Option<LoudnessTarget>,
+    context: &JobContext,
+) -> Result<PathBuf, String> {
+    let info = probe(input)?;
+    let duration = info.duration;
+    let audio_filter = match loudness {
+        Some(ref target) if info.has_audio => {
+            Some(loudness::normalize_filter(target, &loudness::measure(input, target)?))
+        }
+        _ => None,
+    };
+    let output = derived_path(&input.with_extension(container.extension()), "remuxed");
+    tracing::info!(target: "Remux", "{} -> {}", input.display(), output.display());
+
+    let result = run_ffmpeg_with_progress(
+        remux_args(input, &output, container, audio_filter.as_deref()),
+        duration,
+        context.cancel_flag(),
+        |fraction| context.report(fraction),
+    );
+
+    if let Err(e) = result {
+        // Don't leave a partial file behind
+        let _ = std::fs::remove_file(&output);
+        return Err(e);
+    }
+
+    Ok(output)
+}
+
+/// FFmpeg arguments copying every stream of `input` into `output`, except
+/// the audio if it's to go through `audio_filter`.
+fn remux_args(input: &Path, output: &Path, container: Container, audio_filter: Option<&str>) -> Vec<String> {
+    let mut args = vec![
+        "-i".to_string(),
+        input.to_string_lossy().to_string(),
+        "-map".to_string(),
+        "0".to_string(),
+        "-c".to_string(),
+        "copy".to_string(),
+    ];
+    if let Some(filter) = audio_filter {
+        let codec = audio_codec(container.extension()).unwrap_or("aac");
+        args.extend(["-af", filter, "-c:a", codec, "-b:a", "192k"].map(String::from));
+    }
+    if matches!(container, Container::Mp4 | Container::Mov) {
+        // MP4 only carries text subtitles as mov_text, and plays sooner
+        // with its index at the front
+        args.extend(["-c:s", "mov_text", "-movflags", "+faststart"].map(String::from));
+    }
+    args.extend(["-y".to_string(), output.to_string_lossy().to_string()]);
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remux_args() {
+        let args = remux_args(Path::new("a.mkv"), Path::new("a_remuxed.mp4"), Container::Mp4, None);
+        assert_eq!(
+            args.join(" "),
+            "-i a.mkv -map 0 -c copy -c:s mov_text -movflags +faststart -y a_remuxed.mp4"
+        );
+        let args = remux_args(Path::new("a.mp4"), Path::new("a_remuxed.mkv"), Container::Mkv, Some("loudnorm"));
+        assert_eq!(
+            args.join(" "),
+            "-i a.mp4 -map 0 -c copy -af loudnorm -c:a flac -b:a 192k -y a_remuxed.mkv"
+        );
+    }
+}
@@ -0,0 +1,232 @@
+//! Filmstrip and waveform extraction for trim and scrub UIs.
+//!
+//! The frontend can't decode recordings itself, so for a timeline it gets a
+//! filmstrip, frames sampled at a fixed interval and tiled into one image,
+//! and the audio's peak levels as JSON. Like thumbnails, both are stored
+//! next to the recording (`name.filmstrip.jpg` and `name.waveform.json`)
+//! and regenerated when the recording changes.
+
+use super::thumbnail::is_fresh;
+use crate::encoder::ffmpeg::{ffmpeg_stream_stdout, probe, run_ffmpeg};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Width of each filmstrip frame in pixels.
+const FRAME_WIDTH: u32 = 160;
+/// Frames per row of the filmstrip image.
+const COLUMNS: u32 = 10;
+/// Fewest and most frames in a filmstrip; between them, one every
+/// [`FRAME_INTERVAL_SECS`].
+const FRAME_LIMITS: (u32, u32) = (10, 200);
+const FRAME_INTERVAL_SECS: f64 = 2.0;
+
+/// Rate the audio is decoded at for the waveform; plenty for peaks.
+const WAVEFORM_SAMPLE_RATE: u32 = 8000;
+/// Most peaks per second of audio, and in all.
+const MAX_PEAKS_PER_SEC: u32 = 50;
+const MAX_PEAKS: f64 = 20_000.0;
+
+/// Where a recording's filmstrip and waveform are, and how to read them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScrubInfo {
+    pub filmstrip_path: String,
+    /// Number of frames, laid out left to right, then top to bottom
+    pub frames: u32,
+    /// Frames per row
+    pub columns: u32,
+    pub frame_width: u32,
+    pub frame_height: u32,
+    /// Time between frames, in seconds; frame `n` is at `n * frame_interval_secs`
+    pub frame_interval_secs: f64,
+    /// [`Waveform`] JSON path
+    pub waveform_path: String,
+    pub duration: f64,
+}
+
+/// Peak levels of a recording's audio, as stored in the waveform JSON.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Waveform {
+    /// Peaks per second of audio
+    pub peaks_per_sec: f64,
+    /// Highest level of each stretch of audio, from 0 to 1 (full scale);
+    /// empty if the recording has no audio
+    pub peaks: Vec<f32>,
+}
+
+/// Path of the filmstrip image for a recording.
+pub fn filmstrip_path(video: &Path) -> PathBuf {
+    video.with_extension("filmstrip.jpg")
+}
+
+/// Path of the waveform JSON for a recording.
+pub fn waveform_path(video: &Path) -> PathBuf {
+    video.with_extension("waveform.json")
+}
+
+/// Get the filmstrip and waveform for a recording, generating them if
+/// they're missing or stale.
+///
+/// This blocks while FFmpeg runs; call it from a blocking task.
+pub fn ensure_scrub_data(video: &Path) -> Result<ScrubInfo, String> {
+    if !video.is_file() {
+        return Err(format!("Recording not found: {}", video.display()));
+    }
+    let info = probe(video)?;
+    if info.duration <= 0.0 || info.width == 0 {
+        return Err("Recording has no video to scrub".to_string());
+    }
+
+    let frames = ((info.duration / FRAME_INTERVAL_SECS).ceil() as u32).clamp(FRAME_LIMITS.0, FRAME_LIMITS.1);
+    let frame_height = ((FRAME_WIDTH * info.height / info.width) & !1).max(2);
+    let filmstrip = filmstrip_path(video);
+    if !is_fresh(&filmstrip, video) {
+        generate_filmstrip(video, &filmstrip, info.duration, frames, frame_height)?;
+    }
+
+    let waveform = waveform_path(video);
+    if !is_fresh(&waveform, video) {
+        let data = match info.has_audio {
+            true => extract_waveform(video, info.duration)?,
+            false => Waveform { peaks_per_sec: 0.0, peaks: Vec::new() },
+        };
+        let json = serde_json::to_string(&data).map_err(|e| format!("Failed to serialize waveform: {}", e))?;
+        std::fs::write(&waveform, json).map_err(|e| format!("Failed to write waveform: {}", e))?;
+    }
+
+    Ok(ScrubInfo {
+        filmstrip_path: filmstrip.to_string_lossy().to_string(),
+        frames,
+        columns: COLUMNS.min(frames),
+        frame_width: FRAME_WIDTH,
+        frame_height,
+        frame_interval_secs: info.duration / frames as f64,
+        waveform_path: waveform.to_string_lossy().to_string(),
+        duration: info.duration,
+    })
+}
+
+/// Sample `frames` frames evenly across the recording and tile them.
+fn generate_filmstrip(video: &Path, output: &Path, duration: f64, frames: u32, frame_height: u32) -> Result<(), String> {
+    let columns = COLUMNS.min(frames);
+    let filter = format!(
+        "fps={:.6},scale={}:{},tile={}x{}",
+        frames as f64 / duration,
+        FRAME_WIDTH,
+        frame_height,
+        columns,
+        frames.div_ceil(columns)
+    );
+    run_ffmpeg([
+        "-i".to_string(),
+        video.to_string_lossy().to_string(),
+        "-vf".to_string(),
+        filter,
+        "-frames:v".to_string(),
+        "1".to_string(),
+        "-q:v".to_string(),
+        "5".to_string(),
+        "-y".to_string(),
+        output.to_string_lossy().to_string(),
+    ])
+}
+
+/// Decode the first audio stream as mono and measure its peaks.
+fn extract_waveform(video: &Path, duration: f64) -> Result<Waveform, String> {
+    let peaks_per_sec = (MAX_PEAKS / duration).clamp(1.0, MAX_PEAKS_PER_SEC as f64);
+    let mut peaks = PeakMeter::new((WAVEFORM_SAMPLE_RATE as f64 / peaks_per_sec).round() as usize);
+    ffmpeg_stream_stdout(
+        [
+            "-i".to_string(),
+            video.to_string_lossy().to_string(),
+            "-map".to_string(),
+            "0:a:0".to_string(),
+            "-ac".to_string(),
+            "1".to_string(),
+            "-ar".to_string(),
+            WAVEFORM_SAMPLE_RATE.to_string(),
+            "-f".to_string(),
+            "s16le".to_string(),
+            "-".to_string(),
+        ],
+        |data| peaks.push(data),
+    )?;
+    Ok(Waveform {
+        peaks_per_sec: WAVEFORM_SAMPLE_RATE as f64 / peaks.samples_per_peak as f64,
+        peaks: peaks.finish(),
+    })
+}
+
+/// The highest level of each run of `samples_per_peak` 16-bit samples.
+struct PeakMeter {
+    samples_per_peak: usize,
+    /// A sample split across two reads
+    odd_byte: Option<u8>,
+    peak: u16,
+    samples: usize,
+    peaks: Vec<f32>,
+}
+
+impl PeakMeter {
+    fn new(samples_per_peak: usize) -> Self {
+        Self { samples_per_peak: samples_per_peak.max(1), odd_byte: None, peak: 0, samples: 0, peaks: Vec::new() }
+    }
+
+    /// Add little-endian 16-bit samples.
+    fn push(&mut self, mut data: &[u8]) {
+        if let Some(low) = self.odd_byte.take() {
+            let Some((&high, rest)) = data.split_first() else {
+                self.odd_byte = Some(low);
+                return;
+            };
+            self.add(i16::from_le_bytes([low, high]));
+            data = rest;
+        }
+        let mut samples = data.chunks_exact(2);
+        for sample in &mut samples {
+            self.add(i16::from_le_bytes([sample[0], sample[1]]));
+        }
+        self.odd_byte = samples.remainder().first().copied();
+    }
+
+    fn add(&mut self, sample: i16) {
+        self.peak = self.peak.max(sample.unsigned_abs());
+        self.samples += 1;
+        if self.samples == self.samples_per_peak {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        // Three decimals keep the JSON small
+        let level = (self.peak as f32 / i16::MAX as f32).min(1.0);
+        self.peaks.push((level * 1000.0).round() / 1000.0);
+        self.peak = 0;
+        self.samples = 0;
+    }
+
+    /// The peaks, including that of a last, shorter run.
+    fn finish(mut self) -> Vec<f32> {
+        if self.samples > 0 {
+            self.flush();
+        }
+        self.peaks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peak_meter() {
+        let samples: Vec<u8> = [100i16, -16384, 50, 32767, -32768]
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        let mut meter = PeakMeter::new(2);
+        // Split mid-sample, as pipe reads can be
+        meter.push(&samples[..3]);
+        meter.push(&samples[3..]);
+        assert_eq!(meter.finish(), vec![0.5, 1.0, 1.0]);
+    }
+}
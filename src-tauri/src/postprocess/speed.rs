@@ -0,0 +1,267 @@
+//! Speed change export: playing a whole recording, or sections of it, faster
+//! or slower.
+//!
+//! The usual use is compressing the dull parts of a demo, like an installer
+//! running, without cutting them out. The recording is split at the edges of
+//! the changed ranges, each section is retimed, and the sections are joined
+//! again. Audio in a changed section is either kept at its pitch with
+//! `atempo` or muted; sections outside the ranges are left as they are.
+
+use super::derived_path;
+use crate::encoder::ffmpeg::{probe, run_ffmpeg_with_progress};
+use crate::jobs::JobContext;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Slowest and fastest speeds a range may play at.
+const SPEED_LIMITS: (f64, f64) = (0.25, 16.0);
+
+/// Frame rate used when the recording doesn't report one.
+const DEFAULT_FPS: f64 = 30.0;
+
+/// A part of the recording and the speed to play it at.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SpeedRange {
+    /// Start of the range in seconds
+    #[serde(default)]
+    pub start_secs: f64,
+    /// End of the range; the end of the recording if unset
+    #[serde(default)]
+    pub end_secs: Option<f64>,
+    /// Playback speed, e.g. 4.0 for four times as fast
+    pub speed: f64,
+}
+
+/// What happens to the audio of the changed ranges.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpeedAudio {
+    /// Retime the audio but keep its pitch
+    #[default]
+    PitchCorrected,
+    /// Silence it
+    Mute,
+}
+
+/// What to speed up or slow down.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SpeedOptions {
+    /// Ranges to change, which may not overlap; a single range without an
+    /// end changes the whole recording
+    pub ranges: Vec<SpeedRange>,
+    #[serde(default)]
+    pub audio: SpeedAudio,
+}
+
+/// A section of the recording, from `start` to `end` seconds, played at `speed`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Section {
+    start: f64,
+    end: f64,
+    speed: f64,
+}
+
+impl Section {
+    /// Length of the section once retimed.
+    fn output_duration(&self) -> f64 {
+        (self.end - self.start) / self.speed
+    }
+}
+
+/// Export a copy of `input` with the speed changes in `options`, next to it.
+/// Returns the output path.
+pub fn export(input: &Path, options: &SpeedOptions, context: &JobContext) -> Result<PathBuf, String> {
+    let info = probe(input)?;
+    let sections = sections(&options.ranges, info.duration)?;
+    let fps = if info.fps > 0.0 { info.fps } else { DEFAULT_FPS };
+    let duration = sections.iter().map(Section::output_duration).sum();
+
+    let output = derived_path(&input.with_extension("mp4"), "speed");
+    tracing::info!(
+        target: "Speed",
+        "{} ({} sections, {:.1}s -> {:.1}s) -> {}",
+        input.display(),
+        sections.len(),
+        info.duration,
+        duration,
+        output.display()
+    );
+
+    let result = run_ffmpeg_with_progress(
+        speed_args(input, &output, &sections, fps, info.has_audio, options.audio),
+        duration,
+        context.cancel_flag(),
+        |fraction| context.report(fraction),
+    );
+
+    if let Err(e) = result {
+        // Don't leave a partial file behind
+        let _ = std::fs::remove_file(&output);
+        return Err(e);
+    }
+
+    Ok(output)
+}
+
+/// Split a recording `duration` seconds long into sections at the edges of
+/// `ranges`, with the parts between them at normal speed.
+fn sections(ranges: &[SpeedRange], duration: f64) -> Result<Vec<Section>, String> {
+    if ranges.is_empty() {
+        return Err("Choose a part of the recording to change the speed of".to_string());
+    }
+    let mut ranges: Vec<Section> = ranges
+        .iter()
+        .map(|range| Section {
+            start: range.start_secs.max(0.0),
+            end: range.end_secs.unwrap_or(duration).min(duration),
+            speed: range.speed,
+        })
+        .collect();
+    ranges.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+    let mut sections = Vec::new();
+    let mut position = 0.0;
+    for range in ranges {
+        if !(SPEED_LIMITS.0..=SPEED_LIMITS.1).contains(&range.speed) {
+            return Err(format!(
+                "Speed {}x is outside {}x to {}x",
+                range.speed, SPEED_LIMITS.0, SPEED_LIMITS.1
+            ));
+        }
+        if range.start >= range.end {
+            return Err(format!("Invalid range {:.2}s to {:.2}s", range.start, range.end));
+        }
+        if range.start < position {
+            return Err(format!("The range starting at {:.2}s overlaps the one before it", range.start));
+        }
+        if range.start > position {
+            sections.push(Section { start: position, end: range.start, speed: 1.0 });
+        }
+        position = range.end;
+        sections.push(range);
+    }
+    if position < duration {
+        sections.push(Section { start: position, end: duration, speed: 1.0 });
+    }
+    Ok(sections)
+}
+
+/// `atempo` factors multiplying to `speed`. Older FFmpeg builds take factors
+/// from 0.5 to 2 only, so larger changes are chained.
+fn atempo_chain(speed: f64) -> Vec<f64> {
+    let mut factors = Vec::new();
+    let mut remaining = speed;
+    while remaining > 2.0 {
+        factors.push(2.0);
+        remaining /= 2.0;
+    }
+    while remaining < 0.5 {
+        factors.push(0.5);
+        remaining /= 0.5;
+    }
+    factors.push(remaining);
+    factors
+}
+
+/// FFmpeg arguments retiming each of `sections` of `input` and joining them.
+fn speed_args(
+    input: &Path,
+    output: &Path,
+    sections: &[Section],
+    fps: f64,
+    has_audio: bool,
+    audio: SpeedAudio,
+) -> Vec<String> {
+    let mut filters = Vec::new();
+    let mut joined = String::new();
+    for (index, section) in sections.iter().enumerate() {
+        let Section { start, end, speed } = *section;
+        // Resample to the source frame rate, or sped-up sections keep every
+        // frame at many times the rate
+        filters.push(format!(
+            "[0:v:0]trim=start={start:.3}:end={end:.3},setpts=(PTS-STARTPTS)/{speed},fps={fps}[v{index}]"
+        ));
+        joined.push_str(&format!("[v{}]", index));
+        if has_audio {
+            let mut filter = format!("[0:a:0]atrim=start={start:.3}:end={end:.3},asetpts=PTS-STARTPTS");
+            if speed != 1.0 {
+                if audio == SpeedAudio::Mute {
+                    filter.push_str(",volume=0");
+                }
+                for factor in atempo_chain(speed) {
+                    filter.push_str(&format!(",atempo={}", factor));
+                }
+            }
+            filters.push(format!("{}[a{}]", filter, index));
+            joined.push_str(&format!("[a{}]", index));
+        }
+    }
+    filters.push(format!(
+        "{}concat=n={}:v=1:a={}[v]{}",
+        joined,
+        sections.len(),
+        u8::from(has_audio),
+        if has_audio { "[a]" } else { "" }
+    ));
+
+    let mut args = vec![
+        "-i".to_string(),
+        input.to_string_lossy().to_string(),
+        "-filter_complex".to_string(),
+        filters.join(";"),
+        "-map".to_string(),
+        "[v]".to_string(),
+    ];
+    if has_audio {
+        args.extend(["-map", "[a]", "-c:a", "aac", "-b:a", "192k"].map(String::from));
+    }
+    // Exports are deliverables, so favor quality over encoding speed
+    args.extend(
+        ["-c:v", "libx264", "-preset", "medium", "-crf", "20", "-pix_fmt", "yuv420p", "-movflags", "+faststart", "-y"]
+            .map(String::from),
+    );
+    args.push(output.to_string_lossy().to_string());
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sections() {
+        let range = |start_secs, end_secs, speed| SpeedRange { start_secs, end_secs, speed };
+        assert_eq!(
+            sections(&[range(40.0, Some(50.0), 0.5), range(10.0, Some(30.0), 8.0)], 60.0).unwrap(),
+            vec![
+                Section { start: 0.0, end: 10.0, speed: 1.0 },
+                Section { start: 10.0, end: 30.0, speed: 8.0 },
+                Section { start: 30.0, end: 40.0, speed: 1.0 },
+                Section { start: 40.0, end: 50.0, speed: 0.5 },
+                Section { start: 50.0, end: 60.0, speed: 1.0 },
+            ]
+        );
+        assert_eq!(
+            sections(&[range(0.0, None, 2.0)], 60.0).unwrap(),
+            vec![Section { start: 0.0, end: 60.0, speed: 2.0 }]
+        );
+        assert!(sections(&[range(10.0, Some(30.0), 2.0), range(20.0, None, 2.0)], 60.0).is_err());
+        assert!(sections(&[range(0.0, None, 100.0)], 60.0).is_err());
+        assert_eq!(atempo_chain(8.0), vec![2.0, 2.0, 2.0]);
+        assert_eq!(atempo_chain(0.25), vec![0.5, 0.5]);
+    }
+
+    #[test]
+    fn test_speed_args() {
+        let sections = [Section { start: 0.0, end: 5.0, speed: 1.0 }, Section { start: 5.0, end: 25.0, speed: 4.0 }];
+        let args = speed_args(Path::new("a.mp4"), Path::new("a_speed.mp4"), &sections, 30.0, true, SpeedAudio::Mute);
+        assert_eq!(
+            args[3],
+            "[0:v:0]trim=start=0.000:end=5.000,setpts=(PTS-STARTPTS)/1,fps=30[v0];\
+             [0:a:0]atrim=start=0.000:end=5.000,asetpts=PTS-STARTPTS[a0];\
+             [0:v:0]trim=start=5.000:end=25.000,setpts=(PTS-STARTPTS)/4,fps=30[v1];\
+             [0:a:0]atrim=start=5.000:end=25.000,asetpts=PTS-STARTPTS,volume=0,atempo=2,atempo=2[a1];\
+             [v0][a0][v1][a1]concat=n=2:v=1:a=1[v][a]"
+        );
+    }
+}
@@ -0,0 +1,214 @@
+//! Subtitles generated from a recording's narration.
+//!
+//! Speech is transcribed locally with a whisper.cpp model through FFmpeg's
+//! `whisper` audio filter (FFmpeg 8.0 or later, built with whisper.cpp), so
+//! nothing leaves the machine. The result is written as an SRT file next to
+//! the recording, and optionally embedded as a subtitle track in a copy of it.
+
+use super::derived_path;
+use crate::config;
+use crate::encoder::ffmpeg::{probe, run_ffmpeg, run_ffmpeg_with_progress};
+use crate::jobs::JobContext;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Model used when none is given, looked up in the `models` folder of the
+/// app's data directory.
+pub const DEFAULT_MODEL: &str = "ggml-base.bin";
+
+/// How to generate subtitles.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SubtitleOptions {
+    /// Spoken language as an ISO 639-1 code such as "en"; detected if unset
+    #[serde(default)]
+    pub language: Option<String>,
+    /// whisper.cpp model file; defaults to [`DEFAULT_MODEL`]
+    #[serde(default)]
+    pub model_path: Option<String>,
+    /// Also write a copy of the recording with the subtitles as a track
+    #[serde(default)]
+    pub embed: bool,
+}
+
+/// Transcribe the audio of `input` into subtitles. Returns the SRT file, or
+/// the subtitled copy of the recording if `embed` is set.
+pub fn generate(input: &Path, options: &SubtitleOptions, context: &JobContext) -> Result<PathBuf, String> {
+    let language = language_option(options.language.as_deref())?;
+    let model = model_path(options.model_path.as_deref())?;
+    let subtitle_track = match extension(input).as_str() {
+        "gif" => return Err("GIF recordings have no audio to transcribe".to_string()),
+        extension => subtitle_codec(extension),
+    };
+
+    let info = probe(input)?;
+    let srt = input.with_extension("srt");
+    tracing::info!(target: "Subtitles", "Transcribing {} ({}) -> {}", input.display(), language, srt.display());
+
+    // Transcription is nearly all of the work; leave a little for embedding
+    let share = if options.embed { 0.9 } else { 1.0 };
+    let result = run_ffmpeg_with_progress(
+        [
+            "-i".to_string(),
+            input.to_string_lossy().to_string(),
+            "-vn".to_string(),
+            "-af".to_string(),
+            whisper_filter(&model, &language, 10, &srt, "srt"),
+            "-f".to_string(),
+            "null".to_string(),
+            "-".to_string(),
+        ],
+        info.duration,
+        context.cancel_flag(),
+        |fraction| context.report(fraction * share),
+    );
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&srt);
+        if e.contains("No such filter") {
+            return Err("This FFmpeg build can't transcribe speech (it needs FFmpeg 8 with whisper.cpp)".to_string());
+        }
+        return Err(e);
+    }
+    if !srt.is_file() {
+        return Err("No speech was found in the recording".to_string());
+    }
+    if !options.embed {
+        return Ok(srt);
+    }
+
+    let output = derived_path(input, "subtitled");
+    if let Err(e) = embed(input, &srt, subtitle_track, &language, &output) {
+        let _ = std::fs::remove_file(&output);
+        return Err(e);
+    }
+    Ok(output)
+}
+
+/// Replace `video` with a copy that has `srt` as a subtitle track in
+/// `language` ("auto" if unknown).
+pub fn embed_in_place(video: &Path, srt: &Path, language: &str) -> Result<(), String> {
+    let extension = extension(video);
+    if extension == "gif" {
+        return Err("GIF recordings can't hold subtitles".to_string());
+    }
+    // Written next to the video so the final rename stays on one filesystem
+    let output = video.with_extension(format!("subtitled.{}", extension));
+    if let Err(e) = embed(video, srt, subtitle_codec(&extension), language, &output) {
+        let _ = std::fs::remove_file(&output);
+        return Err(e);
+    }
+    std::fs::rename(&output, video).map_err(|e| format!("Failed to replace recording: {}", e))
+}
+
+/// Write `input` with `srt` as a subtitle track encoded with `codec` to
+/// `output`.
+fn embed(input: &Path, srt: &Path, codec: &str, language: &str, output: &Path) -> Result<(), String> {
+    let mut args = vec![
+        "-i".to_string(),
+        input.to_string_lossy().to_string(),
+        "-i".to_string(),
+        srt.to_string_lossy().to_string(),
+        "-map".to_string(),
+        "0".to_string(),
+        "-map".to_string(),
+        "1".to_string(),
+        "-c".to_string(),
+        "copy".to_string(),
+        "-c:s".to_string(),
+        codec.to_string(),
+    ];
+    if language != "auto" {
+        args.extend(["-metadata:s:s:0".to_string(), format!("language={}", language)]);
+    }
+    if matches!(extension(input).as_str(), "mp4" | "mov") {
+        args.extend(["-movflags".to_string(), "+faststart".to_string()]);
+    }
+    args.extend(["-y".to_string(), output.to_string_lossy().to_string()]);
+    run_ffmpeg(args)
+}
+
+/// The whisper filter's language option for a requested `language`:
+/// "auto" to detect it, or the checked code.
+pub fn language_option(language: Option<&str>) -> Result<String, String> {
+    match language.map(str::trim) {
+        None | Some("") | Some("auto") => Ok("auto".to_string()),
+        Some(language) => validate_language(language),
+    }
+}
+
+/// The whisper.cpp model at `path`, or [`DEFAULT_MODEL`] in the data
+/// directory, checking it's there.
+pub fn model_path(path: Option<&str>) -> Result<PathBuf, String> {
+    let model = match path {
+        Some(path) => PathBuf::from(path),
+        None => config::data_dir()?.join("models").join(DEFAULT_MODEL),
+    };
+    if !model.is_file() {
+        return Err(format!(
+            "Speech recognition model not found at {}; download a whisper.cpp model (e.g. {}) there",
+            model.display(),
+            DEFAULT_MODEL
+        ));
+    }
+    Ok(model)
+}
+
+/// Check a language code, returning it lowercased.
+fn validate_language(language: &str) -> Result<String, String> {
+    let language = language.to_ascii_lowercase();
+    if !(2..=3).contains(&language.len()) || !language.chars().all(|c| c.is_ascii_lowercase()) {
+        return Err(format!("Invalid language code {:?}; use an ISO 639-1 code such as \"en\"", language));
+    }
+    Ok(language)
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Subtitle encoder for a container.
+fn subtitle_codec(extension: &str) -> &'static str {
+    match extension {
+        "mp4" | "mov" => "mov_text",
+        "webm" => "webvtt",
+        _ => "srt",
+    }
+}
+
+/// The `whisper` filter transcribing `language` `queue_secs` of audio at a
+/// time, writing `format` (`srt` or `json`) to `destination`.
+pub fn whisper_filter(model: &Path, language: &str, queue_secs: u32, destination: &Path, format: &str) -> String {
+    format!(
+        "whisper=model={}:language={}:queue={}:destination={}:format={}",
+        filter_escape(model),
+        language,
+        queue_secs,
+        filter_escape(destination),
+        format
+    )
+}
+
+/// Quote a path as a filter option value, where `:` and `\` (as in Windows
+/// paths) would otherwise be taken as separators and escapes.
+pub fn filter_escape(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_whisper_filter() {
+        assert_eq!(
+            whisper_filter(Path::new(r"C:\Models\ggml-base.bin"), "en", 10, Path::new(r"C:\Videos\it's.srt"), "srt"),
+            r"whisper=model='C:\Models\ggml-base.bin':language=en:queue=10:destination='C:\Videos\it'\''s.srt':format=srt"
+        );
+        assert_eq!(validate_language("EN").unwrap(), "en");
+        assert_eq!(language_option(Some(" ")).unwrap(), "auto");
+        assert!(validate_language("english").is_err());
+        assert_eq!(subtitle_codec("mp4"), "mov_text");
+        assert_eq!(subtitle_codec("mkv"), "srt");
+    }
+}
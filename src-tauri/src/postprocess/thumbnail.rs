@@ -0,0 +1,164 @@
+//! Thumbnail and hover-scrub sprite generation.
+//!
+//! Images are stored next to the recording (`name.thumb.jpg` and
+//! `name.sprite.jpg`) so they move and get deleted along with it.
+
+use crate::encoder::ffmpeg::{probe, run_ffmpeg};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Width of the poster-frame thumbnail in pixels.
+const THUMBNAIL_WIDTH: u32 = 320;
+/// Width of each frame in the sprite sheet in pixels.
+const SPRITE_FRAME_WIDTH: u32 = 160;
+/// Number of frames in the sprite sheet (laid out in a single row).
+const SPRITE_FRAMES: u32 = 10;
+
+/// Thumbnail images available for a recording.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThumbnailInfo {
+    /// Poster-frame image path
+    pub thumbnail_path: String,
+    /// Sprite sheet path, if requested
+    pub sprite_path: Option<String>,
+    /// Number of frames in the sprite sheet
+    pub sprite_frames: u32,
+    /// Width of each sprite frame in pixels
+    pub sprite_frame_width: u32,
+}
+
+/// Path of the poster-frame thumbnail for a recording.
+pub fn thumbnail_path(video: &Path) -> PathBuf {
+    video.with_extension("thumb.jpg")
+}
+
+/// Path of the hover-scrub sprite sheet for a recording.
+pub fn sprite_path(video: &Path) -> PathBuf {
+    video.with_extension("sprite.jpg")
+}
+
+/// Paths of every file generated for a recording, including the scrub
+/// filmstrip and waveform, which move and get deleted along with it.
+pub fn generated_paths(video: &Path) -> [PathBuf; 4] {
+    [
+        thumbnail_path(video),
+        sprite_path(video),
+        super::scrub::filmstrip_path(video),
+        super::scrub::waveform_path(video),
+    ]
+}
+
+/// Whether a generated image exists and is not older than the recording.
+pub(super) fn is_fresh(image: &Path, video: &Path) -> bool {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    match (modified(image), modified(video)) {
+        (Some(image_time), Some(video_time)) => image_time >= video_time,
+        _ => false,
+    }
+}
+
+/// Get the thumbnail (and optionally sprite sheet) for a recording,
+/// generating any missing or stale images.
+///
+/// This blocks while FFmpeg runs; call it from a blocking task.
+pub fn ensure_thumbnails(video: &Path, include_sprite: bool) -> Result<ThumbnailInfo, String> {
+    if !video.is_file() {
+        return Err(format!("Recording not found: {}", video.display()));
+    }
+
+    let thumbnail = thumbnail_path(video);
+    let sprite = sprite_path(video);
+    let need_thumbnail = !is_fresh(&thumbnail, video);
+    let need_sprite = include_sprite && !is_fresh(&sprite, video);
+
+    if need_thumbnail || need_sprite {
+        let duration = probe(video)?.duration;
+        if need_thumbnail {
+            generate_thumbnail(video, &thumbnail, duration)?;
+        }
+        if need_sprite {
+            generate_sprite(video, &sprite, duration)?;
+        }
+    }
+
+    Ok(ThumbnailInfo {
+        thumbnail_path: thumbnail.to_string_lossy().to_string(),
+        sprite_path: include_sprite.then(|| sprite.to_string_lossy().to_string()),
+        sprite_frames: SPRITE_FRAMES,
+        sprite_frame_width: SPRITE_FRAME_WIDTH,
+    })
+}
+
+/// Time of the poster frame: a little way in, to skip any black first frames.
+fn poster_time(duration: f64) -> f64 {
+    (duration * 0.1).min(1.0)
+}
+
+/// Extract a single scaled frame as the poster thumbnail.
+fn generate_thumbnail(video: &Path, output: &Path, duration: f64) -> Result<(), String> {
+    run_ffmpeg([
+        "-ss".to_string(),
+        format!("{:.3}", poster_time(duration)),
+        "-i".to_string(),
+        video.to_string_lossy().to_string(),
+        "-frames:v".to_string(),
+        "1".to_string(),
+        "-vf".to_string(),
+        format!("scale={}:-2", THUMBNAIL_WIDTH),
+        "-q:v".to_string(),
+        "4".to_string(),
+        "-y".to_string(),
+        output.to_string_lossy().to_string(),
+    ])
+}
+
+/// Sample frames evenly across the recording and tile them into one image.
+fn generate_sprite(video: &Path, output: &Path, duration: f64) -> Result<(), String> {
+    if duration <= 0.0 {
+        return Err("Recording has no duration".to_string());
+    }
+
+    let filter = format!(
+        "fps={:.6},scale={}:-2,tile={}x1",
+        SPRITE_FRAMES as f64 / duration,
+        SPRITE_FRAME_WIDTH,
+        SPRITE_FRAMES
+    );
+
+    run_ffmpeg([
+        "-i".to_string(),
+        video.to_string_lossy().to_string(),
+        "-vf".to_string(),
+        filter,
+        "-frames:v".to_string(),
+        "1".to_string(),
+        "-q:v".to_string(),
+        "5".to_string(),
+        "-y".to_string(),
+        output.to_string_lossy().to_string(),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_paths() {
+        let video = Path::new("/videos/recording_2024-01-01_120000.mp4");
+        assert_eq!(
+            thumbnail_path(video),
+            Path::new("/videos/recording_2024-01-01_120000.thumb.jpg")
+        );
+        assert_eq!(
+            sprite_path(video),
+            Path::new("/videos/recording_2024-01-01_120000.sprite.jpg")
+        );
+    }
+
+    #[test]
+    fn test_poster_time() {
+        assert_eq!(poster_time(60.0), 1.0);
+        assert!((poster_time(2.0) - 0.2).abs() < f64::EPSILON);
+    }
+}
@@ -0,0 +1,269 @@
+//! Trimming the head and tail of a recording.
+//!
+//! Where possible the video is cut with a stream copy, which is lossless and
+//! nearly instant. A stream copy can only start on a keyframe, so when the
+//! start point falls between keyframes only the short stretch up to the next
+//! keyframe is re-encoded and joined to a stream copy of the remainder.
+
+use super::derived_path;
+use crate::encoder::ffmpeg::{keyframe_times, probe, run_ffmpeg_with_progress};
+use crate::jobs::JobContext;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Cut points closer than this to a keyframe (in seconds) count as aligned.
+/// Half a frame at the recording frame rate.
+const KEYFRAME_TOLERANCE: f64 = 1.0 / 60.0;
+
+/// x264 preset matching the recorder's own output. Segments that are joined
+/// to stream-copied video must use the same H.264 profile as the original.
+const RECORDING_PRESET: &str = "ultrafast";
+
+/// How a trim is performed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrimMode {
+    /// Stream copy, re-encoding only around a start point between keyframes
+    #[default]
+    Auto,
+    /// Stream copy only; the start snaps back to the preceding keyframe
+    Copy,
+    /// Re-encode the whole trimmed range
+    Reencode,
+}
+
+/// Trim `input` to the range `start`..`end` (seconds), writing a new file
+/// next to it. Returns the output path.
+pub fn trim(
+    input: &Path,
+    start: f64,
+    end: f64,
+    mode: TrimMode,
+    context: &JobContext,
+) -> Result<PathBuf, String> {
+    let duration = probe(input)?.duration;
+    let start = start.max(0.0);
+    let end = if end <= 0.0 { duration } else { end.min(duration) };
+    if start >= end {
+        return Err(format!(
+            "Invalid trim range {:.2}s to {:.2}s (recording is {:.2}s)",
+            start, end, duration
+        ));
+    }
+
+    let output = derived_path(input, "trimmed");
+    tracing::info!(
+        target: "Trim",
+        "{} ({:.2}s to {:.2}s, {:?}) -> {}",
+        input.display(),
+        start,
+        end,
+        mode,
+        output.display()
+    );
+
+    let result = match mode {
+        TrimMode::Copy => copy_range(input, &output, start, end, context, (0.0, 1.0)),
+        TrimMode::Reencode => reencode_range(input, &output, start, end, context, (0.0, 1.0)),
+        TrimMode::Auto => auto_trim(input, &output, start, end, context),
+    };
+    if let Err(e) = result {
+        // Don't leave a partial file behind
+        let _ = std::fs::remove_file(&output);
+        return Err(e);
+    }
+
+    Ok(output)
+}
+
+/// Stream copy where possible, re-encoding only what a keyframe-aligned copy can't cover.
+fn auto_trim(
+    input: &Path,
+    output: &Path,
+    start: f64,
+    end: f64,
+    context: &JobContext,
+) -> Result<(), String> {
+    let keyframes = keyframe_times(input)?;
+    match plan_cut(&keyframes, start, end) {
+        CutPlan::Copy => copy_range(input, output, start, end, context, (0.0, 1.0)),
+        CutPlan::Reencode => reencode_range(input, output, start, end, context, (0.0, 1.0)),
+        CutPlan::Smart { keyframe } => {
+            smart_cut(input, output, start, keyframe, end, context).or_else(|e| {
+                if context.is_cancelled() {
+                    return Err(e);
+                }
+                tracing::warn!(target: "Trim", "Smart cut failed ({}), re-encoding instead", e);
+                reencode_range(input, output, start, end, context, (0.0, 1.0))
+            })
+        }
+    }
+}
+
+/// Strategy for an automatic trim.
+#[derive(Debug, PartialEq)]
+enum CutPlan {
+    /// Start is on a keyframe: stream copy the whole range
+    Copy,
+    /// No keyframe inside the range: re-encode it all
+    Reencode,
+    /// Re-encode up to `keyframe`, stream copy from there
+    Smart { keyframe: f64 },
+}
+
+fn plan_cut(keyframes: &[f64], start: f64, end: f64) -> CutPlan {
+    if start <= KEYFRAME_TOLERANCE || keyframes.iter().any(|k| (k - start).abs() <= KEYFRAME_TOLERANCE) {
+        return CutPlan::Copy;
+    }
+
+    match keyframes.iter().copied().find(|&k| k > start) {
+        Some(keyframe) if keyframe < end - KEYFRAME_TOLERANCE => CutPlan::Smart { keyframe },
+        _ => CutPlan::Reencode,
+    }
+}
+
+/// Map stage-local progress into the `(from, to)` slice of the overall job.
+fn scaled(context: &JobContext, (from, to): (f64, f64)) -> impl FnMut(f64) + '_ {
+    move |fraction| context.report(from + (to - from) * fraction)
+}
+
+/// Stream copy `start..end` into `output`.
+fn copy_range(
+    input: &Path,
+    output: &Path,
+    start: f64,
+    end: f64,
+    context: &JobContext,
+    range: (f64, f64),
+) -> Result<(), String> {
+    run_ffmpeg_with_progress(
+        [
+            "-ss".to_string(),
+            format!("{:.3}", start),
+            "-i".to_string(),
+            input.to_string_lossy().to_string(),
+            "-t".to_string(),
+            format!("{:.3}", end - start),
+            "-map".to_string(),
+            "0".to_string(),
+            "-c".to_string(),
+            "copy".to_string(),
+            "-avoid_negative_ts".to_string(),
+            "make_zero".to_string(),
+            "-movflags".to_string(),
+            "+faststart".to_string(),
+            "-y".to_string(),
+            output.to_string_lossy().to_string(),
+        ],
+        end - start,
+        context.cancel_flag(),
+        scaled(context, range),
+    )
+}
+
+/// Re-encode `start..end` into `output` with frame-accurate cut points.
+fn reencode_range(
+    input: &Path,
+    output: &Path,
+    start: f64,
+    end: f64,
+    context: &JobContext,
+    range: (f64, f64),
+) -> Result<(), String> {
+    run_ffmpeg_with_progress(
+        [
+            "-ss".to_string(),
+            format!("{:.3}", start),
+            "-i".to_string(),
+            input.to_string_lossy().to_string(),
+            "-t".to_string(),
+            format!("{:.3}", end - start),
+            "-map".to_string(),
+            "0".to_string(),
+            "-c:v".to_string(),
+            "libx264".to_string(),
+            "-preset".to_string(),
+            RECORDING_PRESET.to_string(),
+            "-crf".to_string(),
+            "18".to_string(),
+            "-pix_fmt".to_string(),
+            "yuv420p".to_string(),
+            "-c:a".to_string(),
+            "aac".to_string(),
+            "-movflags".to_string(),
+            "+faststart".to_string(),
+            "-y".to_string(),
+            output.to_string_lossy().to_string(),
+        ],
+        end - start,
+        context.cancel_flag(),
+        scaled(context, range),
+    )
+}
+
+/// Re-encode `start..keyframe`, stream copy `keyframe..end`, and join them.
+fn smart_cut(
+    input: &Path,
+    output: &Path,
+    start: f64,
+    keyframe: f64,
+    end: f64,
+    context: &JobContext,
+) -> Result<(), String> {
+    let head = output.with_extension("head.mp4");
+    let tail = output.with_extension("tail.mp4");
+    let list = output.with_extension("concat.txt");
+
+    let result = (|| {
+        reencode_range(input, &head, start, keyframe, context, (0.0, 0.5))?;
+        copy_range(input, &tail, keyframe, end, context, (0.5, 0.8))?;
+
+        let list_contents = format!("file '{}'\nfile '{}'\n", concat_escape(&head), concat_escape(&tail));
+        std::fs::write(&list, list_contents)
+            .map_err(|e| format!("Failed to write concat list: {}", e))?;
+
+        run_ffmpeg_with_progress(
+            [
+                "-f".to_string(),
+                "concat".to_string(),
+                "-safe".to_string(),
+                "0".to_string(),
+                "-i".to_string(),
+                list.to_string_lossy().to_string(),
+                "-c".to_string(),
+                "copy".to_string(),
+                "-movflags".to_string(),
+                "+faststart".to_string(),
+                "-y".to_string(),
+                output.to_string_lossy().to_string(),
+            ],
+            end - start,
+            context.cancel_flag(),
+            scaled(context, (0.8, 1.0)),
+        )
+    })();
+
+    for temp in [&head, &tail, &list] {
+        let _ = std::fs::remove_file(temp);
+    }
+    result
+}
+
+/// Escape a path for a single-quoted entry in an FFmpeg concat list.
+pub(super) fn concat_escape(path: &Path) -> String {
+    path.to_string_lossy().replace('\'', "'\\''")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_cut() {
+        let keyframes = [0.0, 8.0, 16.0];
+        assert_eq!(plan_cut(&keyframes, 0.0, 10.0), CutPlan::Copy);
+        assert_eq!(plan_cut(&keyframes, 8.005, 20.0), CutPlan::Copy);
+        assert_eq!(plan_cut(&keyframes, 3.0, 20.0), CutPlan::Smart { keyframe: 8.0 });
+        assert_eq!(plan_cut(&keyframes, 3.0, 7.0), CutPlan::Reencode);
+    }
+}
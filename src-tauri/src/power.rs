@@ -0,0 +1,308 @@
+//! Lowering recording quality on battery or when the machine is throttling.
+//!
+//! With the power policy enabled, recordings started on battery power or
+//! while the CPU is thermally throttled are made cheaper: the frame rate and
+//! resolution are capped, and the hardware encoder is used if it was turned
+//! off. What was changed, and why, is sent to the frontend as a
+//! `quality-degraded` event. Power state changes during a recording are
+//! emitted as `power-state` events; they take effect from the next recording,
+//! since the encoder's frame rate and size are fixed once it starts.
+
+use crate::encoder::EncoderSettings;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+
+/// Event emitted with a [`Degradation`] when a recording starts degraded.
+pub const DEGRADED_EVENT: &str = "quality-degraded";
+
+/// Event emitted with the new [`PowerState`] when it changes.
+pub const POWER_EVENT: &str = "power-state";
+
+/// How often the power state is checked.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Whether the machine is running on battery or throttling.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PowerState {
+    pub on_battery: bool,
+    /// The CPU is slowed down to keep it cool. Not detected on Windows.
+    pub thermal_throttling: bool,
+}
+
+/// Why a recording was degraded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DegradeReason {
+    Battery,
+    Thermal,
+}
+
+/// How a recording was made cheaper, for the frontend.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Degradation {
+    pub reasons: Vec<DegradeReason>,
+    /// Human-readable descriptions of each change, such as "60 fps reduced to 30 fps"
+    pub changes: Vec<String>,
+}
+
+/// What recordings give up on battery or when throttling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PowerPolicy {
+    /// Whether the policy applies at all
+    pub enabled: bool,
+    /// Highest frame rate to record at
+    pub max_fps: u32,
+    /// Scale the output down to fit within this height
+    pub max_height: Option<u32>,
+    /// Turn hardware encoding on if it was off
+    pub prefer_hardware: bool,
+}
+
+impl PowerPolicy {
+    const DEFAULT: PowerPolicy = PowerPolicy {
+        enabled: false,
+        max_fps: 30,
+        max_height: Some(1080),
+        prefer_hardware: true,
+    };
+
+    /// Make `settings` cheaper if `state` calls for it. Returns what was
+    /// changed, or `None` if nothing was.
+    pub fn apply(&self, state: PowerState, settings: &mut EncoderSettings) -> Option<Degradation> {
+        let mut reasons = Vec::new();
+        if state.on_battery {
+            reasons.push(DegradeReason::Battery);
+        }
+        if state.thermal_throttling {
+            reasons.push(DegradeReason::Thermal);
+        }
+        if !self.enabled || reasons.is_empty() {
+            return None;
+        }
+
+        let mut changes = Vec::new();
+        if settings.fps > self.max_fps {
+            changes.push(format!("{} fps reduced to {} fps", settings.fps, self.max_fps));
+            settings.fps = self.max_fps;
+        }
+        if let Some(max_height) = self.max_height {
+            if settings.max_height.is_none_or(|height| height > max_height) {
+                changes.push(format!("Resolution limited to {}p", max_height));
+                settings.max_height = Some(max_height);
+            }
+        }
+        if self.prefer_hardware && !settings.hardware_encoding {
+            changes.push("Hardware encoding turned on".to_string());
+            settings.hardware_encoding = true;
+        }
+        (!changes.is_empty()).then_some(Degradation { reasons, changes })
+    }
+}
+
+impl Default for PowerPolicy {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+static POLICY: Mutex<PowerPolicy> = Mutex::new(PowerPolicy::DEFAULT);
+
+/// Set the policy for subsequent recordings.
+pub fn set_policy(policy: PowerPolicy) -> Result<(), String> {
+    if policy.max_fps == 0 {
+        return Err("Frame rate must be greater than zero".to_string());
+    }
+    if policy.max_height == Some(0) {
+        return Err("Height must be greater than zero".to_string());
+    }
+    *POLICY.lock().unwrap() = policy;
+    Ok(())
+}
+
+/// The policy for subsequent recordings.
+pub fn policy() -> PowerPolicy {
+    *POLICY.lock().unwrap()
+}
+
+/// Read the machine's power state.
+///
+/// May run a command (on macOS); call it from a blocking task.
+pub fn current() -> PowerState {
+    #[cfg(target_os = "windows")]
+    return windows::current();
+    #[cfg(target_os = "linux")]
+    return linux::current();
+    #[cfg(target_os = "macos")]
+    return macos::current();
+    #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+    return PowerState::default();
+}
+
+/// Check the power state every few seconds and emit changes to the frontend.
+///
+/// Runs for the lifetime of the app.
+pub async fn run_monitor(app: AppHandle) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    let mut last = None;
+    loop {
+        interval.tick().await;
+
+        let Ok(state) = tokio::task::spawn_blocking(current).await else {
+            continue;
+        };
+        if last.is_some_and(|last| last != state) {
+            tracing::info!(target: "Power", "On battery: {}, throttling: {}", state.on_battery, state.thermal_throttling);
+            if let Err(e) = app.emit(POWER_EVENT, state) {
+                tracing::error!(target: "Power", "Failed to emit power state: {}", e);
+            }
+        }
+        last = Some(state);
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod windows {
+    use super::PowerState;
+    use windows::Win32::System::Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+
+    pub fn current() -> PowerState {
+        let mut status = SYSTEM_POWER_STATUS::default();
+        let on_battery = unsafe { GetSystemPowerStatus(&mut status) }.is_ok() && status.ACLineStatus == 0;
+        PowerState {
+            on_battery,
+            thermal_throttling: false,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::PowerState;
+    use std::path::Path;
+
+    fn read(path: &Path) -> Option<String> {
+        std::fs::read_to_string(path).ok().map(|value| value.trim().to_string())
+    }
+
+    pub fn current() -> PowerState {
+        PowerState {
+            on_battery: on_battery(),
+            thermal_throttling: thermal_throttling(),
+        }
+    }
+
+    /// Whether no mains supply is online and a battery is discharging.
+    fn on_battery() -> bool {
+        let Ok(supplies) = std::fs::read_dir("/sys/class/power_supply") else {
+            return false;
+        };
+        let mut discharging = false;
+        for supply in supplies.flatten().map(|entry| entry.path()) {
+            match read(&supply.join("type")).as_deref() {
+                Some("Mains") if read(&supply.join("online")).as_deref() == Some("1") => return false,
+                Some("Battery") => discharging |= read(&supply.join("status")).as_deref() == Some("Discharging"),
+                _ => {}
+            }
+        }
+        discharging
+    }
+
+    /// Whether any thermal zone has reached its passive (throttling) trip point.
+    fn thermal_throttling() -> bool {
+        let Ok(zones) = std::fs::read_dir("/sys/class/thermal") else {
+            return false;
+        };
+        zones.flatten().map(|entry| entry.path()).any(|zone| {
+            let Some(temp) = read(&zone.join("temp")).and_then(|temp| temp.parse::<i64>().ok()) else {
+                return false;
+            };
+            (0..16).any(|trip| {
+                read(&zone.join(format!("trip_point_{}_type", trip))).as_deref() == Some("passive")
+                    && read(&zone.join(format!("trip_point_{}_temp", trip)))
+                        .and_then(|limit| limit.parse::<i64>().ok())
+                        .is_some_and(|limit| limit > 0 && temp >= limit)
+            })
+        })
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use super::{parse_pmset_batt, parse_pmset_therm, PowerState};
+    use std::process::Command;
+
+    fn pmset(arg: &str) -> String {
+        Command::new("pmset")
+            .args(["-g", arg])
+            .output()
+            .map(|output| String::from_utf8_lossy(&output.stdout).to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn current() -> PowerState {
+        PowerState {
+            on_battery: parse_pmset_batt(&pmset("batt")),
+            thermal_throttling: parse_pmset_therm(&pmset("therm")),
+        }
+    }
+}
+
+/// Whether `pmset -g batt` output says the Mac is on battery.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn parse_pmset_batt(output: &str) -> bool {
+    output.contains("'Battery Power'")
+}
+
+/// Whether `pmset -g therm` output shows the CPU speed limited.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn parse_pmset_therm(output: &str) -> bool {
+    output.lines().any(|line| {
+        line.split_once('=')
+            .filter(|(name, _)| name.trim() == "CPU_Speed_Limit")
+            .and_then(|(_, limit)| limit.trim().parse::<u32>().ok())
+            .is_some_and(|limit| limit < 100)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_policy() {
+        let policy = PowerPolicy {
+            enabled: true,
+            ..PowerPolicy::default()
+        };
+        let mut settings = EncoderSettings {
+            fps: 60,
+            hardware_encoding: false,
+            ..EncoderSettings::default()
+        };
+        let plugged_in = PowerState::default();
+        assert_eq!(policy.apply(plugged_in, &mut settings), None);
+
+        let battery = PowerState {
+            on_battery: true,
+            thermal_throttling: false,
+        };
+        let degradation = policy.apply(battery, &mut settings).unwrap();
+        assert_eq!(degradation.reasons, [DegradeReason::Battery]);
+        assert_eq!(degradation.changes.len(), 3);
+        assert_eq!((settings.fps, settings.max_height, settings.hardware_encoding), (30, Some(1080), true));
+
+        // Already cheap enough, or the policy is off
+        assert_eq!(policy.apply(battery, &mut settings), None);
+        assert_eq!(PowerPolicy::default().apply(battery, &mut EncoderSettings::default()), None);
+    }
+
+    #[test]
+    fn test_parse_pmset() {
+        assert!(parse_pmset_batt("Now drawing from 'Battery Power'\n -InternalBattery-0 (id=1234)\t85%; discharging"));
+        assert!(!parse_pmset_batt("Now drawing from 'AC Power'\n"));
+        assert!(parse_pmset_therm("CPU Power notify\n\tCPU_Scheduler_Limit \t= 100\n\tCPU_Speed_Limit \t= 70\n"));
+        assert!(!parse_pmset_therm("\tCPU_Speed_Limit \t= 100\n"));
+    }
+}
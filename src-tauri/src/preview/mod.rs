@@ -0,0 +1,486 @@
+//! Live low-latency preview of the capture for the frontend.
+//!
+//! Frames are downscaled and encoded to JPEG by FFmpeg, then served as an
+//! MJPEG stream (`multipart/x-mixed-replace`) on a loopback-only HTTP port,
+//! which the webview can display with a plain `<img>` element. While
+//! recording, the preview is fed from the recording pipeline; before a
+//! recording starts it can run its own capture of the selected source.
+//! Nothing is captured or encoded while the preview is disabled, and frames
+//! aren't encoded while no client is connected to the stream.
+//!
+//! Frames never go through Tauri's IPC, so there's no base64 to avoid; the
+//! webview decodes the JPEGs natively. Frontends that would rather skip the
+//! JPEG encoding can turn on the raw frames in shared memory (see
+//! [`shared`]).
+
+mod shared;
+
+pub use shared::SharedPreviewInfo;
+
+use crate::capture::{start_capture, CaptureTarget, CapturedFrame, StopHandle};
+use crate::encoder::sink::FrameSink;
+use crate::encoder::write_raw_frame;
+use crate::http;
+use shared::SharedFrames;
+use ffmpeg_sidecar::command::FfmpegCommand;
+use std::io::Read;
+use std::process::{Child, ChildStdin, Stdio};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+
+/// Preview frame rate. Low enough to keep the cost small, high enough to follow motion.
+const PREVIEW_FPS: u32 = 10;
+
+/// Maximum preview width; larger captures are scaled down.
+const PREVIEW_WIDTH: u32 = 640;
+
+/// Path of the MJPEG stream on the preview server.
+const STREAM_PATH: &str = "/preview.mjpg";
+
+/// Multipart boundary between JPEG frames.
+const BOUNDARY: &str = "frame";
+
+/// Path of the latest shared frame on the preview server.
+const FRAME_PATH: &str = "/frame";
+
+/// Most recent preview JPEG, if any.
+type LatestFrame = watch::Sender<Option<Arc<Vec<u8>>>>;
+
+/// Raw frames in shared memory, while turned on.
+type Shared = Arc<Mutex<Option<SharedFrames>>>;
+
+/// Live preview state, shared by the preview server and its frame sources.
+pub struct Preview {
+    latest: Arc<LatestFrame>,
+    shared: Shared,
+    /// Port and task of the running preview server, while enabled
+    server: Mutex<Option<(u16, tokio::task::JoinHandle<()>)>>,
+    /// Standalone capture feeding the preview outside of a recording
+    capture: Mutex<Option<(StopHandle, tokio::task::JoinHandle<()>)>>,
+}
+
+impl Preview {
+    pub fn new() -> Self {
+        Self {
+            latest: Arc::new(watch::channel(None).0),
+            shared: Arc::new(Mutex::new(None)),
+            server: Mutex::new(None),
+            capture: Mutex::new(None),
+        }
+    }
+
+    /// Whether the preview is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.server.lock().unwrap().is_some()
+    }
+
+    /// URL of the MJPEG stream, while enabled.
+    pub fn url(&self) -> Option<String> {
+        self.server
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|(port, _)| format!("http://127.0.0.1:{}{}", port, STREAM_PATH))
+    }
+
+    /// Start the preview server and return the stream URL.
+    ///
+    /// Must be called from within the tokio runtime.
+    pub fn enable(&self) -> Result<String, String> {
+        if let Some(url) = self.url() {
+            return Ok(url);
+        }
+
+        // Loopback only: the preview is for this machine's webview
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0))
+            .map_err(|e| format!("Failed to bind preview server: {}", e))?;
+        let port = listener
+            .local_addr()
+            .map_err(|e| format!("Failed to get preview server address: {}", e))?
+            .port();
+        listener
+            .set_nonblocking(true)
+            .map_err(|e| format!("Failed to configure preview server socket: {}", e))?;
+        let listener = TcpListener::from_std(listener)
+            .map_err(|e| format!("Failed to start preview server: {}", e))?;
+
+        let server = tokio::spawn(serve(listener, self.latest.clone(), self.shared.clone()));
+        *self.server.lock().unwrap() = Some((port, server));
+
+        let url = self.url().unwrap_or_default();
+        tracing::info!(target: "Preview", "Serving preview at {}", url);
+        Ok(url)
+    }
+
+    /// Publish raw frames in shared memory as well, or stop. Returns where
+    /// to find them when turned on.
+    pub fn set_shared(&self, enabled: bool) -> Result<Option<SharedPreviewInfo>, String> {
+        let mut shared = self.shared.lock().unwrap();
+        if !enabled {
+            *shared = None;
+            return Ok(None);
+        }
+        let Some(port) = self.server.lock().unwrap().as_ref().map(|(port, _)| *port) else {
+            return Err("Preview is not enabled".to_string());
+        };
+        if shared.is_none() {
+            *shared = Some(SharedFrames::create()?);
+        }
+        let path = shared.as_ref().map(|frames| frames.path().to_path_buf()).unwrap_or_default();
+        tracing::info!(target: "Preview", "Sharing frames in {}", path.display());
+        Ok(Some(SharedPreviewInfo {
+            path,
+            version: shared::VERSION,
+            max_size: shared::MAX_SIZE,
+            url: format!("http://127.0.0.1:{}{}", port, FRAME_PATH),
+        }))
+    }
+
+    /// Stop the preview server and any standalone preview capture.
+    pub fn disable(&self) {
+        self.stop_capture();
+        *self.shared.lock().unwrap() = None;
+        if let Some((_, server)) = self.server.lock().unwrap().take() {
+            server.abort();
+            tracing::info!(target: "Preview", "Preview stopped");
+        }
+        self.latest.send_replace(None);
+    }
+
+    /// A sink feeding recorded frames to the preview, if enabled.
+    pub fn sink(&self) -> Option<Box<dyn FrameSink>> {
+        if !self.is_enabled() {
+            return None;
+        }
+        Some(Box::new(PreviewSink::new(self.latest.clone(), self.shared.clone())))
+    }
+
+    /// Preview `target` outside of a recording, replacing any previous source.
+    pub fn start_capture(&self, target: CaptureTarget) -> Result<(), String> {
+        if !self.is_enabled() {
+            return Err("Preview is not enabled".to_string());
+        }
+        self.stop_capture();
+
+        let (mut frame_rx, stop_flag) = start_capture(target).map_err(|e| e.to_string())?;
+        let mut sink = PreviewSink::new(self.latest.clone(), self.shared.clone());
+        let task = tokio::spawn(async move {
+            while let Some(frame) = frame_rx.recv().await {
+                if let Err(e) = sink.write_frame(&frame) {
+                    tracing::error!(target: "Preview", "{}", e);
+                    break;
+                }
+            }
+        });
+
+        *self.capture.lock().unwrap() = Some((stop_flag, task));
+        Ok(())
+    }
+
+    /// Stop the standalone preview capture, if running.
+    pub fn stop_capture(&self) {
+        if let Some((stop_flag, task)) = self.capture.lock().unwrap().take() {
+            stop_flag.store(true, Ordering::Relaxed);
+            task.abort();
+        }
+    }
+}
+
+impl Default for Preview {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Frame sink that encodes a throttled, downscaled MJPEG preview, and
+/// publishes the raw frames while they're shared.
+struct PreviewSink {
+    latest: Arc<LatestFrame>,
+    shared: Shared,
+    child: Option<Child>,
+    stdin: Option<ChildStdin>,
+    width: u32,
+    height: u32,
+    last_frame: Option<Instant>,
+}
+
+impl PreviewSink {
+    fn new(latest: Arc<LatestFrame>, shared: Shared) -> Self {
+        Self {
+            latest,
+            shared,
+            child: None,
+            stdin: None,
+            width: 0,
+            height: 0,
+            last_frame: None,
+        }
+    }
+
+    /// Start FFmpeg for the given frame dimensions.
+    fn start_encoder(&mut self, width: u32, height: u32) -> Result<(), String> {
+        if width == 0 || height == 0 {
+            return Err(format!("Invalid dimensions: {}x{}", width, height));
+        }
+
+        let mut command = FfmpegCommand::new();
+        command
+            .args(["-f", "rawvideo"])
+            .args(["-pix_fmt", "bgra"])
+            .args(["-s", &format!("{}x{}", width, height)])
+            .args(["-r", &PREVIEW_FPS.to_string()])
+            .args(["-i", "-"])
+            // Downscale only; -2 keeps the aspect ratio with an even height
+            .args(["-vf", &format!("scale='min({},iw)':-2", PREVIEW_WIDTH)])
+            .args(["-c:v", "mjpeg"])
+            .args(["-q:v", "7"])
+            .args(["-f", "image2pipe"])
+            .arg("-");
+
+        let inner_command = command.as_inner_mut();
+        inner_command.stdin(Stdio::piped());
+        inner_command.stdout(Stdio::piped());
+        inner_command.stderr(Stdio::null());
+
+        let mut child = inner_command
+            .spawn()
+            .map_err(|e| format!("Failed to start FFmpeg for preview: {}", e))?;
+        let stdin = child.stdin.take().ok_or("Failed to get FFmpeg stdin")?;
+        let stdout = child.stdout.take().ok_or("Failed to get FFmpeg stdout")?;
+
+        let latest = self.latest.clone();
+        std::thread::spawn(move || read_jpegs(stdout, &latest));
+
+        self.width = width;
+        self.height = height;
+        self.stdin = Some(stdin);
+        self.child = Some(child);
+        Ok(())
+    }
+
+    /// Close the encoder input and wait for FFmpeg to exit.
+    fn stop_encoder(&mut self) {
+        drop(self.stdin.take());
+        if let Some(mut child) = self.child.take() {
+            let _ = child.wait();
+        }
+    }
+}
+
+impl FrameSink for PreviewSink {
+    fn name(&self) -> &str {
+        "preview"
+    }
+
+    fn write_frame(&mut self, frame: &CapturedFrame) -> Result<(), String> {
+        // Each connected client holds a receiver; encode only while one does
+        let streaming = self.latest.receiver_count() > 0;
+        let sharing = self.shared.lock().unwrap().is_some();
+        if !streaming && !sharing {
+            return Ok(());
+        }
+        let interval = Duration::from_millis(1000 / PREVIEW_FPS as u64);
+        if self.last_frame.is_some_and(|last| last.elapsed() < interval) {
+            return Ok(());
+        }
+        self.last_frame = Some(Instant::now());
+
+        if let Some(shared) = self.shared.lock().unwrap().as_mut() {
+            shared.write(frame);
+        }
+        if !streaming {
+            return Ok(());
+        }
+
+        // Restart on size changes (e.g. a resized window) instead of cropping
+        if self.child.is_some() && (frame.width != self.width || frame.height != self.height) {
+            self.stop_encoder();
+        }
+        if self.child.is_none() {
+            self.start_encoder(frame.width, frame.height)?;
+        }
+
+        match self.stdin {
+            Some(ref mut stdin) => write_raw_frame(stdin, frame, self.width, self.height),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), String> {
+        self.stop_encoder();
+        Ok(())
+    }
+}
+
+impl Drop for PreviewSink {
+    fn drop(&mut self) {
+        self.stop_encoder();
+    }
+}
+
+/// Read FFmpeg's concatenated JPEG output and publish each complete image.
+fn read_jpegs(mut stdout: impl Read, latest: &LatestFrame) {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 64 * 1024];
+    loop {
+        match stdout.read(&mut chunk) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+        }
+        if let Some(jpeg) = split_jpegs(&mut buf).pop() {
+            latest.send_replace(Some(Arc::new(jpeg)));
+        }
+    }
+}
+
+/// Remove all complete JPEG images from the front of `buf`.
+///
+/// Images are delimited by the SOI (`FF D8`) and EOI (`FF D9`) markers; `FF`
+/// bytes inside the entropy-coded data are always stuffed, so EOI can't
+/// appear inside an image.
+fn split_jpegs(buf: &mut Vec<u8>) -> Vec<Vec<u8>> {
+    let mut images = Vec::new();
+    let mut consumed = 0;
+
+    while let Some(start) = find_marker(&buf[consumed..], 0xD8).map(|i| consumed + i) {
+        let Some(end) = find_marker(&buf[start + 2..], 0xD9).map(|i| start + 2 + i + 2) else {
+            // Keep the partial image for the next read
+            consumed = start;
+            break;
+        };
+        images.push(buf[start..end].to_vec());
+        consumed = end;
+    }
+
+    buf.drain(..consumed);
+    images
+}
+
+/// Position of the `FF <marker>` byte pair in `data`.
+fn find_marker(data: &[u8], marker: u8) -> Option<usize> {
+    data.windows(2).position(|w| w == [0xFF, marker])
+}
+
+/// Accept preview connections until the task is aborted.
+async fn serve(listener: TcpListener, latest: Arc<LatestFrame>, shared: Shared) {
+    let limit = http::client_limit();
+    loop {
+        match http::accept(&listener, &limit).await {
+            Ok((stream, permit)) => {
+                let latest = latest.clone();
+                let shared = shared.clone();
+                tokio::spawn(async move {
+                    let _permit = permit;
+                    // Clients disconnecting mid-stream is expected; not worth logging
+                    let _ = handle_client(stream, &latest, &shared).await;
+                });
+            }
+            Err(e) => {
+                tracing::error!(target: "Preview", "Accept error: {}", e);
+            }
+        }
+    }
+}
+
+/// Stream preview frames to a client until it disconnects, or send it the
+/// latest shared frame.
+async fn handle_client(mut stream: TcpStream, latest: &LatestFrame, shared: &Shared) -> std::io::Result<()> {
+    let Some(request) = http::read_request(&mut stream).await? else {
+        return Ok(());
+    };
+
+    if request.method != "GET" {
+        return http::write_response(&mut stream, 405, "text/plain", &[], b"Method not allowed").await;
+    }
+    if request.path == FRAME_PATH {
+        return send_shared_frame(&mut stream, &request, shared).await;
+    }
+    if request.path != STREAM_PATH {
+        return http::write_response(&mut stream, 404, "text/plain", &[], b"Not found").await;
+    }
+    let mut frames = latest.subscribe();
+
+    let head = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: multipart/x-mixed-replace; boundary={}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        BOUNDARY
+    );
+    stream.write_all(head.as_bytes()).await?;
+
+    // Send the current frame right away, then each new one
+    frames.mark_changed();
+    while frames.changed().await.is_ok() {
+        let Some(jpeg) = frames.borrow_and_update().clone() else {
+            continue;
+        };
+        let part = format!(
+            "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+            BOUNDARY,
+            jpeg.len()
+        );
+        stream.write_all(part.as_bytes()).await?;
+        stream.write_all(&jpeg).await?;
+        stream.write_all(b"\r\n").await?;
+        stream.flush().await?;
+    }
+
+    Ok(())
+}
+
+/// Send the latest shared frame as raw RGBA, with its size and sequence in
+/// `X-Frame-*` headers, or 204 if there's none newer than `?after=`.
+///
+/// Only the app's own webview may read it: unlike the MJPEG stream shown in
+/// an `<img>`, the pixels would be readable by any page that found the port.
+async fn send_shared_frame(stream: &mut TcpStream, request: &http::Request, shared: &Shared) -> std::io::Result<()> {
+    let Some(origin) = request.header("origin").filter(|origin| is_webview_origin(origin)) else {
+        return http::write_response(stream, 401, "text/plain", &[], b"Unauthorized").await;
+    };
+    let after = request.query("after").and_then(|after| after.parse().ok()).unwrap_or(0);
+    let frame = shared.lock().unwrap().as_ref().map(|frames| frames.read(after));
+    let Some(frame) = frame else {
+        return http::write_response(stream, 404, "text/plain", &[], b"Not shared").await;
+    };
+    let Some(frame) = frame else {
+        return http::write_response(stream, 204, "text/plain", &[("Access-Control-Allow-Origin", origin)], b"").await;
+    };
+    let (width, height, sequence) = (frame.width.to_string(), frame.height.to_string(), frame.sequence.to_string());
+    let headers = [
+        ("Access-Control-Allow-Origin", origin),
+        ("Access-Control-Expose-Headers", "X-Frame-Width, X-Frame-Height, X-Frame-Sequence"),
+        ("Cache-Control", "no-cache"),
+        ("X-Frame-Width", width.as_str()),
+        ("X-Frame-Height", height.as_str()),
+        ("X-Frame-Sequence", sequence.as_str()),
+    ];
+    http::write_response(stream, 200, "application/octet-stream", &headers, &frame.pixels).await
+}
+
+/// Whether `origin` is the app's webview: where Tauri serves it from, or the
+/// dev server in debug builds.
+fn is_webview_origin(origin: &str) -> bool {
+    ["tauri://localhost", "http://tauri.localhost", "https://tauri.localhost"].contains(&origin)
+        || (cfg!(debug_assertions) && origin == "http://localhost:1420")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_jpegs() {
+        let mut buf = vec![0xFF, 0xD8, 1, 2, 0xFF, 0xD9, 0xFF, 0xD8, 3, 0xFF, 0x00, 0xFF, 0xD9, 0xFF, 0xD8, 4];
+        let images = split_jpegs(&mut buf);
+        assert_eq!(
+            images,
+            vec![
+                vec![0xFF, 0xD8, 1, 2, 0xFF, 0xD9],
+                vec![0xFF, 0xD8, 3, 0xFF, 0x00, 0xFF, 0xD9],
+            ]
+        );
+        assert_eq!(buf, vec![0xFF, 0xD8, 4]);
+    }
+}
@@ -0,0 +1,248 @@
+//! Raw preview frames in shared memory.
+//!
+//! Besides the MJPEG stream, the preview can publish its frames downscaled
+//! but unencoded into a memory-mapped file. A frontend that maps the file
+//! reads each frame in place: nothing is JPEG encoded or decoded, and no
+//! pixels go through IPC. The webview can't map files itself, so the preview
+//! server also serves the mapped frame at `/frame`, copied straight out of
+//! the mapping.
+//!
+//! The file starts with a header, all fields little-endian:
+//!
+//! | Offset | Field                                                    |
+//! |--------|----------------------------------------------------------|
+//! | 0      | magic `SRPV`                                             |
+//! | 4      | `u32` version, currently 1                               |
+//! | 8      | `u64` sequence: odd while a frame is written, even after |
+//! | 16     | `u32` width                                              |
+//! | 20     | `u32` height                                             |
+//! | 24     | `u32` bytes per row                                      |
+//! | 28     | `u32` pixel format: 0 for 8-bit RGBA                     |
+//!
+//! The pixels follow at offset 64. A reader reads the sequence, copies the
+//! frame, and reads the sequence again; the copy is whole if both reads
+//! return the same even number, otherwise it tries again.
+
+use crate::capture::{CapturedFrame, PixelFormat};
+use memmap2::MmapMut;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+
+const MAGIC: &[u8; 4] = b"SRPV";
+
+/// Version of the header layout.
+pub const VERSION: u32 = 1;
+
+/// Bytes before the pixels.
+const HEADER_LEN: usize = 64;
+
+/// Frames are scaled down to fit within this many pixels each way.
+pub const MAX_SIZE: u32 = 640;
+
+/// The `RGBA8` pixel format value.
+const FORMAT_RGBA8: u32 = 0;
+
+/// Where a frontend finds the shared frames.
+#[derive(Debug, Clone, Serialize)]
+pub struct SharedPreviewInfo {
+    /// The memory-mapped file
+    pub path: PathBuf,
+    /// Header layout version
+    pub version: u32,
+    /// Frames fit within this many pixels each way
+    pub max_size: u32,
+    /// The latest frame on the preview server, for webviews
+    pub url: String,
+}
+
+/// A frame copied out of the shared memory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharedFrame {
+    pub sequence: u64,
+    pub width: u32,
+    pub height: u32,
+    /// Tightly packed RGBA
+    pub pixels: Vec<u8>,
+}
+
+/// The memory-mapped file preview frames are written to.
+pub struct SharedFrames {
+    path: PathBuf,
+    map: MmapMut,
+}
+
+impl SharedFrames {
+    /// Create the file, in the runtime directory where there is one so it's
+    /// kept in memory.
+    pub fn create() -> Result<Self, String> {
+        let dir = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_dir())
+            .unwrap_or_else(std::env::temp_dir);
+        Self::create_in(&dir)
+    }
+
+    fn create_in(dir: &Path) -> Result<Self, String> {
+        let path = dir.join(format!("screen-recorder-preview-{}", std::process::id()));
+        let mut options = std::fs::File::options();
+        options.read(true).write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let file = options
+            .open(&path)
+            .map_err(|e| format!("Failed to create the shared preview: {}", e))?;
+        let len = HEADER_LEN + (MAX_SIZE * MAX_SIZE * 4) as usize;
+        file.set_len(len as u64)
+            .map_err(|e| format!("Failed to size the shared preview: {}", e))?;
+        // Safety: the file is ours, and other processes only read it
+        let mut map = unsafe { MmapMut::map_mut(&file) }
+            .map_err(|e| format!("Failed to map the shared preview: {}", e))?;
+        map[..4].copy_from_slice(MAGIC);
+        map[4..8].copy_from_slice(&VERSION.to_le_bytes());
+        map[28..32].copy_from_slice(&FORMAT_RGBA8.to_le_bytes());
+        Ok(Self { path, map })
+    }
+
+    /// The memory-mapped file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn sequence(&self) -> &AtomicU64 {
+        // Safety: the mapping is page-aligned, so offset 8 is aligned for a
+        // u64, and it lives as long as `self`
+        unsafe { &*(self.map.as_ptr().add(8) as *const AtomicU64) }
+    }
+
+    /// Publish `frame`, scaled down to fit. Half-float HDR frames are
+    /// skipped.
+    pub fn write(&mut self, frame: &CapturedFrame) {
+        let Some(sample) = sampler(frame.format) else {
+            return;
+        };
+        let (width, height) = fit(frame.width, frame.height);
+        if width == 0 || height == 0 || frame.data.len() < frame.width as usize * frame.height as usize * 4 {
+            return;
+        }
+        let sequence = self.sequence().load(Ordering::Relaxed);
+        self.sequence().store(sequence + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        self.map[16..20].copy_from_slice(&width.to_le_bytes());
+        self.map[20..24].copy_from_slice(&height.to_le_bytes());
+        self.map[24..28].copy_from_slice(&(width * 4).to_le_bytes());
+        let source_stride = frame.width as usize * 4;
+        let pixels = &mut self.map[HEADER_LEN..HEADER_LEN + (width * height * 4) as usize];
+        for (y, row) in pixels.chunks_exact_mut(width as usize * 4).enumerate() {
+            let source_y = y * frame.height as usize / height as usize;
+            let source_row = &frame.data[source_y * source_stride..][..source_stride];
+            for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                let source_x = x * frame.width as usize / width as usize;
+                pixel.copy_from_slice(&sample(&source_row[source_x * 4..][..4]));
+            }
+        }
+
+        self.sequence().store(sequence + 2, Ordering::Release);
+    }
+
+    /// The latest frame, if there's one newer than `after`.
+    pub fn read(&self, after: u64) -> Option<SharedFrame> {
+        let sequence = self.sequence().load(Ordering::Acquire);
+        if sequence == 0 || sequence <= after {
+            return None;
+        }
+        let field = |offset: usize| u32::from_le_bytes(self.map[offset..offset + 4].try_into().unwrap());
+        let (width, height) = (field(16), field(20));
+        Some(SharedFrame {
+            sequence,
+            width,
+            height,
+            pixels: self.map[HEADER_LEN..HEADER_LEN + (width * height * 4) as usize].to_vec(),
+        })
+    }
+}
+
+impl Drop for SharedFrames {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Size of a `width`x`height` frame scaled down to fit within `MAX_SIZE`.
+fn fit(width: u32, height: u32) -> (u32, u32) {
+    let largest = width.max(height);
+    if largest <= MAX_SIZE {
+        return (width, height);
+    }
+    let scale = |size: u32| ((size as u64 * MAX_SIZE as u64 / largest as u64) as u32).max(1);
+    (scale(width), scale(height))
+}
+
+/// Converts one pixel to RGBA.
+type Sampler = fn(&[u8]) -> [u8; 4];
+
+/// The [`Sampler`] for `format`, keeping the high 8 bits of deep formats.
+/// `None` for half-float HDR frames.
+fn sampler(format: PixelFormat) -> Option<Sampler> {
+    fn word(pixel: &[u8]) -> u32 {
+        u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]])
+    }
+    match format {
+        PixelFormat::Bgra8 => Some(|p| [p[2], p[1], p[0], p[3]]),
+        PixelFormat::X2Rgb10 => Some(|p| {
+            let w = word(p);
+            [(w >> 22) as u8, (w >> 12) as u8, (w >> 2) as u8, 255]
+        }),
+        PixelFormat::X2Bgr10 => Some(|p| {
+            let w = word(p);
+            [(w >> 2) as u8, (w >> 12) as u8, (w >> 22) as u8, 255]
+        }),
+        PixelFormat::Rgba16F => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::ColorSpace;
+
+    #[test]
+    fn test_shared_frames() {
+        let mut shared = SharedFrames::create_in(&std::env::temp_dir()).unwrap();
+        assert_eq!(shared.read(0), None);
+
+        // A 1280x720 frame, blue on the left half and red on the right
+        let frame = CapturedFrame {
+            width: 1280,
+            height: 720,
+            data: (0..720)
+                .flat_map(|_| (0..1280).flat_map(|x| if x < 640 { [255, 0, 0, 255] } else { [0, 0, 255, 255] }))
+                .collect(),
+            format: PixelFormat::Bgra8,
+            color: ColorSpace::SRGB,
+            captured_at: std::time::Instant::now(),
+            presented_at: None,
+        };
+        shared.write(&frame);
+
+        let read = shared.read(0).unwrap();
+        assert_eq!((read.sequence, read.width, read.height), (2, 640, 360));
+        assert_eq!(read.pixels[..4], [0, 0, 255, 255]);
+        assert_eq!(read.pixels[639 * 4..640 * 4], [255, 0, 0, 255]);
+        assert_eq!(shared.read(2), None);
+
+        // Other processes see the same bytes
+        let mapped = std::fs::read(shared.path()).unwrap();
+        assert_eq!(&mapped[..4], MAGIC);
+        assert_eq!(u64::from_le_bytes(mapped[8..16].try_into().unwrap()), 2);
+        assert_eq!(mapped[HEADER_LEN..HEADER_LEN + 4], [0, 0, 255, 255]);
+
+        let path = shared.path().to_path_buf();
+        drop(shared);
+        assert!(!path.exists());
+    }
+}
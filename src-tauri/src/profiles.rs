@@ -0,0 +1,279 @@
+//! Named recording profiles.
+//!
+//! A profile bundles the output settings for a kind of recording (resolution
+//! cap, frame rate, codec, rate control, audio sources) under a name.
+//! Profiles are stored in `profiles.json` in the app data directory and can be
+//! shared as standalone JSON.
+
+use crate::config::{load_json, save_json};
+use crate::capture::audio::AudioSource;
+use crate::capture::hdr::HdrMode;
+use crate::encoder::codec::VideoCodec;
+use crate::encoder::rate::RateControl;
+use crate::encoder::EncoderSettings;
+use serde::{Deserialize, Serialize};
+
+const PROFILES_FILE: &str = "profiles.json";
+
+/// Highest accepted frame rate.
+const MAX_FPS: u32 = 240;
+
+/// A named set of recording settings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordingProfile {
+    pub name: String,
+    /// Scale the output down to fit within this width
+    #[serde(default)]
+    pub max_width: Option<u32>,
+    /// Scale the output down to fit within this height
+    #[serde(default)]
+    pub max_height: Option<u32>,
+    pub fps: u32,
+    #[serde(default)]
+    pub codec: VideoCodec,
+    /// How file size is traded for quality. Older profiles' `bitrate_kbps`
+    /// is read as VBR at that rate.
+    #[serde(default, alias = "bitrate_kbps", deserialize_with = "RateControl::deserialize_compat")]
+    pub rate_control: RateControl,
+    /// How HDR displays are recorded; HDR is kept only with HEVC and AV1
+    #[serde(default)]
+    pub hdr: HdrMode,
+    /// Encode 10 bits per channel (HEVC, VP9 and AV1)
+    #[serde(default)]
+    pub ten_bit: bool,
+    /// Audio sources to record; the current choice is kept if not given
+    #[serde(default)]
+    pub audio_sources: Option<Vec<AudioSource>>,
+}
+
+impl RecordingProfile {
+    /// Apply the profile's video and audio settings on top of `settings`.
+    pub fn apply(&self, settings: &mut EncoderSettings) {
+        settings.fps = self.fps;
+        settings.max_width = self.max_width;
+        settings.max_height = self.max_height;
+        settings.codec = self.codec;
+        settings.rate_control = self.rate_control;
+        settings.hdr = self.hdr;
+        settings.ten_bit = self.ten_bit;
+        if let Some(sources) = &self.audio_sources {
+            settings.application_audio = sources.contains(&AudioSource::Application);
+            settings.microphone = sources.contains(&AudioSource::Microphone);
+        }
+        settings.profile = Some(self.name.clone());
+    }
+
+    /// Check that the profile can be used.
+    fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Profile name cannot be empty".to_string());
+        }
+        if self.fps == 0 || self.fps > MAX_FPS {
+            return Err(format!("Frame rate must be between 1 and {} fps", MAX_FPS));
+        }
+        if self.max_width == Some(0) || self.max_height == Some(0) {
+            return Err("Resolution limits must be greater than zero".to_string());
+        }
+        self.rate_control.validate()?;
+        if self.rate_control == RateControl::Lossless && !self.codec.supports_lossless() {
+            return Err(format!("{:?} has no lossless mode", self.codec));
+        }
+        Ok(())
+    }
+}
+
+/// Profiles shipped with the app.
+fn builtin_profiles() -> Vec<RecordingProfile> {
+    vec![
+        RecordingProfile {
+            name: "Tutorial 1080p30".to_string(),
+            max_width: Some(1920),
+            max_height: Some(1080),
+            fps: 30,
+            codec: VideoCodec::H264,
+            rate_control: RateControl::default(),
+            hdr: HdrMode::ToneMap,
+            ten_bit: false,
+            audio_sources: Some(vec![AudioSource::Application, AudioSource::Microphone]),
+        },
+        RecordingProfile {
+            name: "Gameplay 4K60".to_string(),
+            max_width: Some(3840),
+            max_height: Some(2160),
+            fps: 60,
+            codec: VideoCodec::H264,
+            rate_control: RateControl::Vbr { bitrate_kbps: 40_000, max_bitrate_kbps: None },
+            hdr: HdrMode::ToneMap,
+            ten_bit: false,
+            audio_sources: Some(vec![AudioSource::Application]),
+        },
+        RecordingProfile {
+            name: "HDR 4K60".to_string(),
+            max_width: Some(3840),
+            max_height: Some(2160),
+            fps: 60,
+            codec: VideoCodec::H265,
+            rate_control: RateControl::Vbr { bitrate_kbps: 40_000, max_bitrate_kbps: None },
+            hdr: HdrMode::Passthrough,
+            ten_bit: true,
+            audio_sources: None,
+        },
+        RecordingProfile {
+            name: "Lossless Archive".to_string(),
+            max_width: None,
+            max_height: None,
+            fps: 30,
+            codec: VideoCodec::Ffv1,
+            rate_control: RateControl::Lossless,
+            hdr: HdrMode::ToneMap,
+            ten_bit: false,
+            audio_sources: None,
+        },
+        RecordingProfile {
+            name: "Tiny GIF".to_string(),
+            max_width: Some(640),
+            max_height: None,
+            fps: 10,
+            codec: VideoCodec::Gif,
+            rate_control: RateControl::default(),
+            hdr: HdrMode::ToneMap,
+            ten_bit: false,
+            audio_sources: None,
+        },
+    ]
+}
+
+/// On-disk profile store.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ProfileStore {
+    profiles: Vec<RecordingProfile>,
+    /// Name of the profile applied to new recordings
+    selected: Option<String>,
+}
+
+impl Default for ProfileStore {
+    fn default() -> Self {
+        Self {
+            profiles: builtin_profiles(),
+            selected: None,
+        }
+    }
+}
+
+/// The saved recording profiles.
+pub struct Profiles {
+    store: ProfileStore,
+}
+
+impl Profiles {
+    /// Load the profiles from disk, starting with the built-in ones on first run.
+    pub fn load() -> Self {
+        Self {
+            store: load_json(PROFILES_FILE),
+        }
+    }
+
+    fn save(&self) -> Result<(), String> {
+        save_json(PROFILES_FILE, &self.store)
+    }
+
+    /// All profiles.
+    pub fn list(&self) -> Vec<RecordingProfile> {
+        self.store.profiles.clone()
+    }
+
+    /// The profile applied to new recordings, if one is selected.
+    pub fn selected(&self) -> Option<&RecordingProfile> {
+        let name = self.store.selected.as_ref()?;
+        self.get(name)
+    }
+
+    /// A profile by name.
+    pub fn get(&self, name: &str) -> Option<&RecordingProfile> {
+        self.store.profiles.iter().find(|p| p.name == name)
+    }
+
+    /// Add a profile, replacing any existing profile with the same name.
+    pub fn upsert(&mut self, profile: RecordingProfile) -> Result<(), String> {
+        profile.validate()?;
+        match self.store.profiles.iter_mut().find(|p| p.name == profile.name) {
+            Some(existing) => *existing = profile,
+            None => self.store.profiles.push(profile),
+        }
+        self.save()
+    }
+
+    /// Delete a profile by name.
+    pub fn delete(&mut self, name: &str) -> Result<(), String> {
+        let before = self.store.profiles.len();
+        self.store.profiles.retain(|p| p.name != name);
+        if self.store.profiles.len() == before {
+            return Err(format!("Profile {:?} not found", name));
+        }
+        if self.store.selected.as_deref() == Some(name) {
+            self.store.selected = None;
+        }
+        self.save()
+    }
+
+    /// Select the profile applied to new recordings.
+    pub fn select(&mut self, name: &str) -> Result<RecordingProfile, String> {
+        let profile = self
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("Profile {:?} not found", name))?;
+        self.store.selected = Some(name.to_string());
+        self.save()?;
+        Ok(profile)
+    }
+
+    /// Serialize a profile as JSON for sharing.
+    pub fn export(&self, name: &str) -> Result<String, String> {
+        let profile = self.get(name).ok_or_else(|| format!("Profile {:?} not found", name))?;
+        serde_json::to_string_pretty(profile).map_err(|e| format!("Failed to serialize profile: {}", e))
+    }
+
+    /// Add a profile shared as JSON.
+    pub fn import(&mut self, json: &str) -> Result<RecordingProfile, String> {
+        let profile: RecordingProfile =
+            serde_json::from_str(json).map_err(|e| format!("Invalid profile: {}", e))?;
+        self.upsert(profile.clone())?;
+        Ok(profile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_round_trip() {
+        let profile = builtin_profiles().remove(1);
+        let json = serde_json::to_string(&profile).unwrap();
+        assert_eq!(serde_json::from_str::<RecordingProfile>(&json).unwrap(), profile);
+
+        // Optional fields can be left out of shared profiles
+        let minimal: RecordingProfile = serde_json::from_str(r#"{"name": "Minimal", "fps": 24}"#).unwrap();
+        assert_eq!(minimal.codec, VideoCodec::H264);
+        assert!(minimal.validate().is_ok());
+
+        let mut settings = EncoderSettings::default();
+        profile.apply(&mut settings);
+        assert_eq!((settings.fps, settings.max_height), (60, Some(2160)));
+        assert_eq!(settings.rate_control, RateControl::Vbr { bitrate_kbps: 40_000, max_bitrate_kbps: None });
+        assert_eq!((settings.application_audio, settings.microphone), (true, false));
+
+        // Audio sources are named, and left alone when not given
+        let tutorial: RecordingProfile =
+            serde_json::from_str(r#"{"name": "Talk", "fps": 30, "audio_sources": ["microphone"]}"#).unwrap();
+        tutorial.apply(&mut settings);
+        assert_eq!((settings.application_audio, settings.microphone), (false, true));
+        minimal.apply(&mut settings);
+        assert_eq!((settings.application_audio, settings.microphone), (false, true));
+
+        // Profiles saved before rate control modes keep their bitrate
+        let legacy: RecordingProfile =
+            serde_json::from_str(r#"{"name": "Old", "fps": 30, "bitrate_kbps": 6000}"#).unwrap();
+        assert_eq!(legacy.rate_control, RateControl::Vbr { bitrate_kbps: 6000, max_bitrate_kbps: None });
+    }
+}
@@ -0,0 +1,323 @@
+//! Recording recipes: a profile plus pre-roll actions.
+//!
+//! Some recordings need the desktop set up first: icons hidden, the monitor
+//! switched to the resolution the video is for, notifications silenced, the
+//! app being shown opened. A recipe bundles those pre-roll actions with a
+//! profile. Recording from a recipe applies its profile and runs its actions
+//! in order before the capture starts; if one fails, the ones already run
+//! are undone and the recording isn't started. Everything is undone, in
+//! reverse order, once the recording ends. Recipes are saved in
+//! `recipes.json`.
+//!
+//! Hiding the desktop icons and setting the resolution work as in
+//! [`crate::environment`] and [`crate::display_mode`].
+
+use crate::capture::refresh;
+use crate::capture::{CaptureTarget, MonitorInfo};
+use crate::config::{load_json, save_json};
+use crate::display_mode::{self, DisplayMode, ModeChanged};
+use crate::dnd;
+use crate::environment::{self, IconsHidden};
+use crate::encoder::EncoderSettings;
+use crate::state::RecordingPhase;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use std::process::{Child, Command};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+const RECIPES_FILE: &str = "recipes.json";
+
+/// Something done to the desktop before a recording starts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PrerollAction {
+    HideDesktopIcons,
+    /// Switch the captured monitor to this mode
+    SetResolution(DisplayMode),
+    DoNotDisturb,
+    LaunchApp {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+        /// How long to give the app to open before recording
+        #[serde(default)]
+        wait_ms: u64,
+        /// Quit the app once the recording ends
+        #[serde(default)]
+        close_on_stop: bool,
+    },
+}
+
+impl PrerollAction {
+    fn describe(&self) -> String {
+        match self {
+            PrerollAction::HideDesktopIcons => "Hiding desktop icons".to_string(),
+            PrerollAction::SetResolution(mode) => format!("Setting the resolution to {}x{}", mode.width, mode.height),
+            PrerollAction::DoNotDisturb => "Turning on do not disturb".to_string(),
+            PrerollAction::LaunchApp { command, .. } => format!("Launching {}", command),
+        }
+    }
+}
+
+/// A named profile and pre-roll actions to record with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recipe {
+    pub name: String,
+    /// Profile applied to the recording; the current settings if not given
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Run in order before the recording starts
+    #[serde(default)]
+    pub actions: Vec<PrerollAction>,
+}
+
+impl Recipe {
+    fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Recipe name cannot be empty".to_string());
+        }
+        for action in &self.actions {
+            match action {
+                PrerollAction::SetResolution(mode) => mode.validate()?,
+                PrerollAction::LaunchApp { command, .. } if command.trim().is_empty() => {
+                    return Err("The app to launch cannot be empty".to_string());
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Saved recipes.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Recipes {
+    pub recipes: Vec<Recipe>,
+}
+
+impl Recipes {
+    /// Load the saved recipes.
+    pub fn load() -> Self {
+        load_json(RECIPES_FILE)
+    }
+
+    /// A recipe by name.
+    pub fn get(&self, name: &str) -> Option<&Recipe> {
+        self.recipes.iter().find(|r| r.name == name)
+    }
+
+    /// Add a recipe, or replace the one with the same name.
+    pub fn upsert(&mut self, recipe: Recipe) -> Result<(), String> {
+        recipe.validate()?;
+        match self.recipes.iter_mut().find(|r| r.name == recipe.name) {
+            Some(existing) => *existing = recipe,
+            None => self.recipes.push(recipe),
+        }
+        save_json(RECIPES_FILE, self)
+    }
+
+    /// Delete a recipe by name.
+    pub fn delete(&mut self, name: &str) -> Result<(), String> {
+        let before = self.recipes.len();
+        self.recipes.retain(|r| r.name != name);
+        if self.recipes.len() == before {
+            return Err(format!("Recipe {:?} not found", name));
+        }
+        save_json(RECIPES_FILE, self)
+    }
+}
+
+/// How to undo an action that changed something.
+enum Undo {
+    DesktopIcons(IconsHidden),
+    Resolution(ModeChanged),
+    DoNotDisturb,
+    CloseApp(Child),
+}
+
+/// What the recipe of the current recording changed.
+struct Applied {
+    undo: Vec<Undo>,
+    /// Encoder settings before the recipe's profile, to put back
+    settings: Option<EncoderSettings>,
+}
+
+static APPLIED: Mutex<Option<Applied>> = Mutex::new(None);
+
+/// Apply `recipe` for a recording of `target`: its profile, then its
+/// actions. Either all of it is applied or none of it is.
+pub async fn apply(app: &AppHandle, recipe: &Recipe, target: &CaptureTarget) -> Result<(), String> {
+    if APPLIED.lock().unwrap().is_some() {
+        return Err("A recipe is already applied to a recording".to_string());
+    }
+    let state = app.state::<AppState>();
+    let profile = match &recipe.profile {
+        Some(name) => Some(
+            state
+                .profiles
+                .lock()
+                .await
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("Profile {:?} not found", name))?,
+        ),
+        None => None,
+    };
+    let monitor = {
+        let target = target.clone();
+        tokio::task::spawn_blocking(move || refresh::target_monitor(&target))
+            .await
+            .ok()
+            .flatten()
+    };
+
+    let mut undo = Vec::new();
+    for action in &recipe.actions {
+        match run_action(action, monitor.as_ref()).await {
+            Ok(Some(done)) => undo.push(done),
+            Ok(None) => {}
+            Err(e) => {
+                tracing::error!(target: "Recipes", "{} failed, undoing \"{}\": {}", action.describe(), recipe.name, e);
+                let _ = tokio::task::spawn_blocking(move || undo_all(undo)).await;
+                return Err(format!("{} failed: {}", action.describe(), e));
+            }
+        }
+    }
+
+    let settings = match profile {
+        Some(profile) => {
+            let manager = state.recording_manager.lock().await;
+            let mut settings = manager.get_encoder_settings().await;
+            let previous = settings.clone();
+            profile.apply(&mut settings);
+            manager.set_encoder_settings(settings).await;
+            Some(previous)
+        }
+        None => None,
+    };
+    tracing::info!(target: "Recipes", "Applied \"{}\"", recipe.name);
+    *APPLIED.lock().unwrap() = Some(Applied { undo, settings });
+    Ok(())
+}
+
+/// Undo the applied recipe, if there is one.
+pub async fn revert(app: &AppHandle) {
+    let Some(applied) = APPLIED.lock().unwrap().take() else {
+        return;
+    };
+    if let Some(settings) = applied.settings {
+        let manager = app.state::<AppState>().recording_manager.clone();
+        manager.lock().await.set_encoder_settings(settings).await;
+    }
+    let _ = tokio::task::spawn_blocking(move || undo_all(applied.undo)).await;
+    tracing::info!(target: "Recipes", "Reverted");
+}
+
+/// Undo the applied recipe's actions, for when the app quits mid-recording.
+///
+/// May run commands; call it from a blocking context.
+pub fn restore() {
+    if let Some(applied) = APPLIED.lock().unwrap().take() {
+        undo_all(applied.undo);
+    }
+}
+
+/// Undo applied recipes as recordings end.
+///
+/// Runs for the lifetime of the app.
+pub async fn run(app: AppHandle) {
+    use tokio::sync::broadcast::error::RecvError;
+
+    let manager = app.state::<AppState>().recording_manager.clone();
+    let mut phases = manager.lock().await.subscribe_phase_events();
+    loop {
+        match phases.recv().await {
+            Ok(RecordingPhase::Done { .. } | RecordingPhase::Failed { .. }) => revert(&app).await,
+            Ok(_) => {}
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Run `action`, returning how to undo it if it changed anything.
+async fn run_action(action: &PrerollAction, monitor: Option<&MonitorInfo>) -> Result<Option<Undo>, String> {
+    if *action == PrerollAction::DoNotDisturb {
+        dnd::engage().await?;
+        return Ok(Some(Undo::DoNotDisturb));
+    }
+    let action = action.clone();
+    let monitor = monitor.cloned();
+    tokio::task::spawn_blocking(move || match action {
+        PrerollAction::HideDesktopIcons => Ok(environment::hide_desktop_icons()?.map(Undo::DesktopIcons)),
+        PrerollAction::SetResolution(mode) => {
+            let monitor = monitor.ok_or("The captured monitor wasn't found")?;
+            Ok(display_mode::set(&monitor, &mode)?.map(Undo::Resolution))
+        }
+        PrerollAction::LaunchApp { command, args, wait_ms, close_on_stop } => {
+            let mut child = Command::new(&command)
+                .args(&args)
+                .spawn()
+                .map_err(|e| format!("Failed to execute {}: {}", command, e))?;
+            std::thread::sleep(Duration::from_millis(wait_ms));
+            if let Ok(Some(status)) = child.try_wait() {
+                if !status.success() {
+                    return Err(format!("{} exited with {}", command, status));
+                }
+            }
+            Ok(close_on_stop.then_some(Undo::CloseApp(child)))
+        }
+        PrerollAction::DoNotDisturb => Ok(None),
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Undo actions in the reverse order they were run.
+fn undo_all(undo: Vec<Undo>) {
+    for done in undo.into_iter().rev() {
+        let result = match done {
+            Undo::DesktopIcons(hidden) => environment::show_desktop_icons(hidden),
+            Undo::Resolution(changed) => display_mode::reset(changed),
+            Undo::DoNotDisturb => {
+                dnd::restore();
+                Ok(())
+            }
+            Undo::CloseApp(mut child) => child.kill().and_then(|_| child.wait()).map(drop).map_err(|e| e.to_string()),
+        };
+        if let Err(e) = result {
+            tracing::error!(target: "Recipes", "Failed to undo a pre-roll action: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recipe() {
+        let recipe: Recipe = serde_json::from_str(
+            r#"{"name":"Demo","profile":"Tutorial 1080p30","actions":[
+                {"type":"hide_desktop_icons"},
+                {"type":"set_resolution","width":1920,"height":1080},
+                {"type":"launch_app","command":"code","close_on_stop":true}]}"#,
+        )
+        .unwrap();
+        let mode = DisplayMode { width: 1920, height: 1080, refresh_rate: None, scale: None };
+        assert_eq!(recipe.actions[1], PrerollAction::SetResolution(mode));
+        assert!(recipe.validate().is_ok());
+        let invalid = Recipe {
+            actions: vec![PrerollAction::SetResolution(DisplayMode { width: 0, ..mode })],
+            ..recipe.clone()
+        };
+        assert!(invalid.validate().is_err());
+        let invalid = Recipe {
+            actions: vec![PrerollAction::LaunchApp { command: " ".to_string(), args: Vec::new(), wait_ms: 0, close_on_stop: false }],
+            ..recipe
+        };
+        assert!(invalid.validate().is_err());
+    }
+}
@@ -0,0 +1,124 @@
+//! Region selector settings.
+//!
+//! A selection can be locked to an aspect ratio, so recordings fit the
+//! platform they're made for without cropping afterwards, or start from a
+//! preset size. The selector reopens on the region last recorded.
+
+use crate::capture::{CaptureRegion, CoordinateSpace, MonitorInfo};
+use crate::config::{load_json, save_json};
+use serde::{Deserialize, Serialize};
+
+const SETTINGS_FILE: &str = "region_selector.json";
+
+/// Ratio of a selection's width to its height, e.g. 16:9.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AspectRatio {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl AspectRatio {
+    /// The size closest to `width` x `height` with this ratio, keeping the
+    /// width if `keep_width` is set and the height otherwise.
+    pub fn fit(&self, width: u32, height: u32, keep_width: bool) -> (u32, u32) {
+        let scale = |value: u32, from: u32, to: u32| ((value as f64 * to as f64 / from as f64).round() as u32).max(1);
+        if keep_width {
+            (width, scale(width, self.width, self.height))
+        } else {
+            (scale(height, self.height, self.width), height)
+        }
+    }
+}
+
+/// Region selector settings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RegionSelectorSettings {
+    /// Ratio selections are locked to, if any
+    pub aspect_ratio: Option<AspectRatio>,
+    /// Region last recorded, where the selector opens
+    pub last_region: Option<CaptureRegion>,
+}
+
+impl RegionSelectorSettings {
+    /// Load the saved settings.
+    pub fn load() -> Self {
+        load_json(SETTINGS_FILE)
+    }
+
+    /// Check and save the settings.
+    pub fn save(&self) -> Result<(), String> {
+        if let Some(ratio) = self.aspect_ratio {
+            if ratio.width == 0 || ratio.height == 0 {
+                return Err("Both sides of an aspect ratio must be above zero".to_string());
+            }
+        }
+        save_json(SETTINGS_FILE, self)
+    }
+
+    /// Remember `region` as the one last recorded.
+    pub fn remember_region(region: &CaptureRegion) {
+        let mut settings = Self::load();
+        settings.last_region = Some(region.clone());
+        if let Err(e) = settings.save() {
+            tracing::error!(target: "RegionSelector", "Failed to save the last region: {}", e);
+        }
+    }
+}
+
+/// A `width` x `height` region in the middle of the monitor with the given
+/// ID, or of the primary monitor, shrunk to fit it if needed.
+///
+/// Returns `None` if there are no monitors.
+pub fn preset_region(monitors: &[MonitorInfo], monitor_id: Option<&str>, width: u32, height: u32) -> Option<CaptureRegion> {
+    let monitor = monitor_id
+        .and_then(|id| monitors.iter().find(|m| m.id == id))
+        .or_else(|| monitors.iter().find(|m| m.is_primary))
+        .or_else(|| monitors.first())?;
+    let (width, height) = (width.min(monitor.width), height.min(monitor.height));
+    Some(CaptureRegion {
+        monitor_id: monitor.id.clone(),
+        x: ((monitor.width - width) / 2) as i32,
+        y: ((monitor.height - height) / 2) as i32,
+        width,
+        height,
+        space: CoordinateSpace::Physical,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit() {
+        let widescreen = AspectRatio { width: 16, height: 9 };
+        assert_eq!(widescreen.fit(1280, 700, true), (1280, 720));
+        assert_eq!(widescreen.fit(1000, 1080, false), (1920, 1080));
+        let square = AspectRatio { width: 1, height: 1 };
+        assert_eq!(square.fit(300, 200, false), (200, 200));
+    }
+
+    #[test]
+    fn test_preset_region() {
+        let monitor = |id: &str, is_primary: bool| MonitorInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            x: 0,
+            y: 0,
+            width: 2560,
+            height: 1440,
+            is_primary,
+            scale_factor: 1.0,
+            model: None,
+            refresh_rate: None,
+            rotation: 0,
+        };
+        let monitors = [monitor("side", false), monitor("main", true)];
+        let region = preset_region(&monitors, None, 1280, 720).unwrap();
+        assert_eq!((region.monitor_id.as_str(), region.x, region.y), ("main", 640, 360));
+        // Vertical 1080p doesn't fit a 1440p monitor
+        let region = preset_region(&monitors, Some("side"), 1080, 1920).unwrap();
+        assert_eq!((region.monitor_id.as_str(), region.y, region.width, region.height), ("side", 0, 1080, 1440));
+    }
+}
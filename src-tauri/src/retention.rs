@@ -0,0 +1,269 @@
+//! Automatic cleanup of old recordings.
+//!
+//! A retention policy removes library recordings older than a number of days
+//! and, oldest first, those over a total size budget. Recordings are either
+//! deleted or moved to an archive folder, which doesn't count toward the
+//! policy. The policy runs on a schedule while the app is open; a dry run
+//! reports what it would remove without touching anything.
+
+use crate::config::{load_json, save_json};
+use crate::library::{Library, RecordingEntry};
+use chrono::{DateTime, Duration as ChronoDuration, Local};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+const SETTINGS_FILE: &str = "retention.json";
+
+/// How often the scheduler checks whether the policy is due.
+const CHECK_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// What happens to recordings the policy removes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RetentionAction {
+    #[default]
+    Delete,
+    /// Move to the archive folder
+    Archive,
+}
+
+/// Retention policy settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionSettings {
+    /// Run the policy on a schedule
+    pub enabled: bool,
+    /// Remove recordings older than this many days
+    pub max_age_days: Option<u32>,
+    /// Remove the oldest recordings while the library is larger than this
+    pub max_total_gb: Option<f64>,
+    pub action: RetentionAction,
+    /// Where archived recordings are moved
+    pub archive_dir: Option<PathBuf>,
+    /// Hours between scheduled runs
+    pub interval_hours: u32,
+}
+
+impl Default for RetentionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_age_days: Some(30),
+            max_total_gb: None,
+            action: RetentionAction::default(),
+            archive_dir: None,
+            interval_hours: 24,
+        }
+    }
+}
+
+impl RetentionSettings {
+    /// Load the saved settings.
+    pub fn load() -> Self {
+        load_json(SETTINGS_FILE)
+    }
+
+    /// Check and save the settings.
+    pub fn save(&self) -> Result<(), String> {
+        if self.action == RetentionAction::Archive && self.archive_dir.is_none() {
+            return Err("Choose a folder to archive recordings to".to_string());
+        }
+        if self.interval_hours == 0 {
+            return Err("The retention interval must be at least an hour".to_string());
+        }
+        save_json(SETTINGS_FILE, self)
+    }
+
+    /// Whether `entry` is already in the archive folder.
+    fn is_archived(&self, entry: &RecordingEntry) -> bool {
+        self.action == RetentionAction::Archive
+            && self
+                .archive_dir
+                .as_ref()
+                .is_some_and(|dir| Path::new(&entry.file_path).starts_with(dir))
+    }
+}
+
+/// Why a recording is removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RetentionReason {
+    /// Older than the maximum age
+    Age,
+    /// Over the total size budget
+    Size,
+}
+
+/// A recording the policy removes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionCandidate {
+    pub id: u64,
+    pub file_path: String,
+    pub size_bytes: u64,
+    pub reason: RetentionReason,
+}
+
+/// What a run of the policy removed, or would remove in a dry run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionReport {
+    pub dry_run: bool,
+    pub action: RetentionAction,
+    pub candidates: Vec<RetentionCandidate>,
+    /// Space removed from the library
+    pub freed_bytes: u64,
+    /// Recordings that couldn't be removed, with the reason
+    pub errors: Vec<String>,
+}
+
+/// Recordings `settings` removes from `entries` at `now`, oldest first.
+/// Recordings whose files are missing or already archived are left alone.
+pub fn plan(entries: &[RecordingEntry], settings: &RetentionSettings, now: DateTime<Local>) -> Vec<RetentionCandidate> {
+    let mut entries: Vec<(&RecordingEntry, Option<DateTime<Local>>)> = entries
+        .iter()
+        .filter(|entry| !entry.missing && !settings.is_archived(entry))
+        .map(|entry| {
+            let created = DateTime::parse_from_rfc3339(&entry.created_at).ok().map(|t| t.with_timezone(&Local));
+            (entry, created)
+        })
+        .collect();
+    // Recordings without a creation time sort first, as the oldest
+    entries.sort_by_key(|(_, created)| *created);
+
+    let cutoff = settings
+        .max_age_days
+        .map(|days| now - ChronoDuration::days(days as i64));
+    let mut total: u64 = entries.iter().map(|(entry, _)| entry.size_bytes).sum();
+    let budget = settings
+        .max_total_gb
+        .map(|gb| (gb.max(0.0) * 1024.0 * 1024.0 * 1024.0) as u64);
+
+    let mut candidates = Vec::new();
+    for (entry, created) in entries {
+        let reason = if cutoff.is_some_and(|cutoff| created.is_some_and(|created| created < cutoff)) {
+            RetentionReason::Age
+        } else if budget.is_some_and(|budget| total > budget) {
+            RetentionReason::Size
+        } else {
+            continue;
+        };
+        total -= entry.size_bytes;
+        candidates.push(RetentionCandidate {
+            id: entry.id,
+            file_path: entry.file_path.clone(),
+            size_bytes: entry.size_bytes,
+            reason,
+        });
+    }
+    candidates
+}
+
+/// Apply `settings` to the library, or with `dry_run` only report what
+/// would be removed.
+pub fn run(library: &mut Library, settings: &RetentionSettings, dry_run: bool) -> RetentionReport {
+    let candidates = plan(&library.list(None), settings, Local::now());
+    let mut report = RetentionReport {
+        dry_run,
+        action: settings.action,
+        candidates: Vec::new(),
+        freed_bytes: 0,
+        errors: Vec::new(),
+    };
+
+    for candidate in candidates {
+        if !dry_run {
+            let removed = match (settings.action, &settings.archive_dir) {
+                (RetentionAction::Archive, Some(dir)) => library.move_to(candidate.id, dir).map(|_| ()),
+                (RetentionAction::Archive, None) => Err("No archive folder is set".to_string()),
+                (RetentionAction::Delete, _) => library.delete(candidate.id, true),
+            };
+            if let Err(e) = removed {
+                report.errors.push(format!("{}: {}", candidate.file_path, e));
+                continue;
+            }
+        }
+        report.freed_bytes += candidate.size_bytes;
+        report.candidates.push(candidate);
+    }
+
+    if !dry_run && !report.candidates.is_empty() {
+        tracing::info!(
+            target: "Retention",
+            "Removed {} recordings ({} MB)",
+            report.candidates.len(),
+            report.freed_bytes / (1024 * 1024)
+        );
+    }
+    report
+}
+
+/// Run the policy whenever it's due, for the lifetime of the app. The first
+/// run happens shortly after startup.
+pub async fn run_scheduler(library: Arc<Mutex<Library>>) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    let mut last_run: Option<DateTime<Local>> = None;
+    loop {
+        interval.tick().await;
+
+        let settings = RetentionSettings::load();
+        if !settings.enabled {
+            continue;
+        }
+        let due = last_run.is_none_or(|last| Local::now() - last >= ChronoDuration::hours(settings.interval_hours as i64));
+        if !due {
+            continue;
+        }
+        last_run = Some(Local::now());
+
+        let library = library.clone();
+        let report = tokio::task::spawn_blocking(move || run(&mut library.blocking_lock(), &settings, false)).await;
+        if let Ok(report) = report {
+            for error in report.errors {
+                tracing::warn!(target: "Retention", "{}", error);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: u64, days_old: i64, size_gb: u64, now: DateTime<Local>) -> RecordingEntry {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "file_path": format!("/v/{}.mp4", id),
+            "name": id.to_string(),
+            "created_at": (now - ChronoDuration::days(days_old)).to_rfc3339(),
+            "duration_secs": 0.0,
+            "width": 0,
+            "height": 0,
+            "size_bytes": size_gb * 1024 * 1024 * 1024,
+            "source": null,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_plan() {
+        let now = Local::now();
+        let entries = vec![entry(1, 1, 4, now), entry(2, 40, 1, now), entry(3, 10, 4, now), entry(4, 5, 4, now)];
+        let settings = RetentionSettings {
+            max_age_days: Some(30),
+            max_total_gb: Some(9.0),
+            ..RetentionSettings::default()
+        };
+        let plan: Vec<_> = plan(&entries, &settings, now).iter().map(|c| (c.id, c.reason)).collect();
+        // 2 is too old; 3 is the oldest left while the other 12 GB exceed 9 GB
+        assert_eq!(plan, [(2, RetentionReason::Age), (3, RetentionReason::Size)]);
+
+        let archived = RetentionSettings {
+            action: RetentionAction::Archive,
+            archive_dir: Some(PathBuf::from("/v")),
+            ..settings
+        };
+        assert!(super::plan(&entries, &archived, now).is_empty());
+    }
+}
@@ -0,0 +1,108 @@
+//! Detecting whether the app runs sandboxed, and reaching the host from it.
+//!
+//! Inside a Flatpak the app shares little with the host: it can't see host
+//! processes or programs, its runtime directory is private except for
+//! `$XDG_RUNTIME_DIR/app/<app id>`, and it may not be able to reach the
+//! PipeWire daemon directly. The pieces that care ask [`detect`] and take the
+//! sandbox-friendly route: IPC sockets go in the shared directory, screen
+//! casts use the PipeWire connection the portal hands out, and host programs
+//! are run through `flatpak-spawn --host` (see [`host_command`]).
+
+use std::sync::OnceLock;
+
+/// File Flatpak places at the root of every sandbox.
+const FLATPAK_INFO: &str = "/.flatpak-info";
+
+/// A sandbox the app is running in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sandbox {
+    Flatpak { app_id: String },
+    Snap,
+}
+
+impl Sandbox {
+    /// Short name, as reported in the capabilities.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Sandbox::Flatpak { .. } => "flatpak",
+            Sandbox::Snap => "snap",
+        }
+    }
+}
+
+/// The sandbox the app is running in, if any.
+pub fn detect() -> Option<&'static Sandbox> {
+    static SANDBOX: OnceLock<Option<Sandbox>> = OnceLock::new();
+    SANDBOX
+        .get_or_init(|| {
+            let info = std::fs::read_to_string(FLATPAK_INFO).ok();
+            let sandbox = detect_from(info.as_deref(), |name| std::env::var(name).ok());
+            if let Some(ref sandbox) = sandbox {
+                tracing::info!(target: "Sandbox", "Running in {:?}", sandbox);
+            }
+            sandbox
+        })
+        .as_ref()
+}
+
+/// The Flatpak app ID, if running in a Flatpak.
+pub fn flatpak_app_id() -> Option<&'static str> {
+    match detect()? {
+        Sandbox::Flatpak { app_id } => Some(app_id),
+        Sandbox::Snap => None,
+    }
+}
+
+/// A command running `program` on the host, which from inside a Flatpak
+/// means through `flatpak-spawn --host`. That needs the
+/// `org.freedesktop.Flatpak` talk permission.
+pub fn host_command(program: &str) -> tokio::process::Command {
+    if flatpak_app_id().is_some() {
+        let mut command = tokio::process::Command::new("flatpak-spawn");
+        command.args(["--host", program]);
+        command
+    } else {
+        tokio::process::Command::new(program)
+    }
+}
+
+/// The sandbox described by the contents of `/.flatpak-info`, if it exists,
+/// and the environment variables from `env`.
+fn detect_from(flatpak_info: Option<&str>, env: impl Fn(&str) -> Option<String>) -> Option<Sandbox> {
+    if let Some(info) = flatpak_info {
+        // The [Application] group's name key holds the app ID
+        let app_id = info
+            .lines()
+            .skip_while(|line| line.trim() != "[Application]")
+            .skip(1)
+            .take_while(|line| !line.trim_start().starts_with('['))
+            .find_map(|line| {
+                let (key, value) = line.split_once('=')?;
+                (key.trim() == "name").then(|| value.trim().to_string())
+            })
+            .or_else(|| env("FLATPAK_ID"))
+            .unwrap_or_default();
+        return Some(Sandbox::Flatpak { app_id });
+    }
+    if let Some(app_id) = env("FLATPAK_ID") {
+        return Some(Sandbox::Flatpak { app_id });
+    }
+    env("SNAP").map(|_| Sandbox::Snap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_from() {
+        let info = "[Application]\nname=com.keath.screen-recorder\nruntime=runtime/org.gnome.Platform/x86_64/47\n\n[Instance]\nname=other\n";
+        assert_eq!(
+            detect_from(Some(info), |_| None),
+            Some(Sandbox::Flatpak { app_id: "com.keath.screen-recorder".to_string() })
+        );
+        let env = |name: &str| (name == "SNAP").then(|| "/snap/screen-recorder/1".to_string());
+        assert_eq!(detect_from(None, env), Some(Sandbox::Snap));
+        assert_eq!(detect_from(None, |_| None), None);
+    }
+}
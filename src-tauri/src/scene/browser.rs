@@ -0,0 +1,438 @@
+//! Web page sources, rendered by a headless browser.
+//!
+//! The page is loaded in a headless Chrome, Edge or Chromium, which sends a
+//! picture of it whenever it changes over the DevTools protocol. The page's
+//! background is transparent, so captions and stream widgets built as
+//! overlays show only what they draw. `SCREEN_RECORDER_BROWSER` can point at
+//! a browser that isn't found on its own.
+
+use crate::capture::queue::frame_channel;
+use crate::capture::{CapturedFrame, ColorSpace, FrameReceiver, PixelFormat, StopHandle};
+use crate::http::{client_websocket_frame, opcode};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Environment variable naming the browser to use.
+const BROWSER_ENV: &str = "SCREEN_RECORDER_BROWSER";
+
+/// Browsers looked for by path.
+#[cfg(target_os = "windows")]
+const BROWSER_PATHS: &[&str] = &[
+    r"C:\Program Files (x86)\Microsoft\Edge\Application\msedge.exe",
+    r"C:\Program Files\Microsoft\Edge\Application\msedge.exe",
+    r"C:\Program Files\Google\Chrome\Application\chrome.exe",
+    r"C:\Program Files (x86)\Google\Chrome\Application\chrome.exe",
+];
+#[cfg(target_os = "macos")]
+const BROWSER_PATHS: &[&str] = &[
+    "/Applications/Google Chrome.app/Contents/MacOS/Google Chrome",
+    "/Applications/Microsoft Edge.app/Contents/MacOS/Microsoft Edge",
+    "/Applications/Chromium.app/Contents/MacOS/Chromium",
+];
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const BROWSER_PATHS: &[&str] = &[];
+
+/// Browsers looked for on the `PATH`.
+const BROWSER_NAMES: &[&str] = &[
+    "chromium",
+    "chromium-browser",
+    "google-chrome",
+    "google-chrome-stable",
+    "microsoft-edge",
+    "microsoft-edge-stable",
+];
+
+/// Longest the browser gets to start.
+const START_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How often the stop flag is checked while the page is unchanged.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Largest DevTools message read, which a full-size picture fits in.
+const MAX_MESSAGE_BYTES: usize = 128 * 1024 * 1024;
+
+/// Numbers the browser profiles of the sources running at once.
+static NEXT_PROFILE: AtomicU32 = AtomicU32::new(0);
+
+/// Render `url` at `width`x`height`, delivering a frame each time the page
+/// changes.
+pub fn start_browser(url: &str, width: u32, height: u32) -> Result<(FrameReceiver, StopHandle), String> {
+    if width == 0 || height == 0 {
+        return Err(format!("Invalid dimensions: {}x{}", width, height));
+    }
+    let browser = find_browser().ok_or_else(|| {
+        format!("Browser sources need Chrome, Edge or Chromium; set {} to use another", BROWSER_ENV)
+    })?;
+
+    let profile = std::env::temp_dir().join(format!(
+        "screen-recorder-browser-{}-{}",
+        std::process::id(),
+        NEXT_PROFILE.fetch_add(1, Ordering::Relaxed)
+    ));
+    let mut child = Command::new(&browser)
+        .arg("--headless=new")
+        .arg("--remote-debugging-port=0")
+        .arg(format!("--user-data-dir={}", profile.display()))
+        .arg(format!("--window-size={},{}", width, height))
+        .args(["--hide-scrollbars", "--mute-audio", "--no-first-run", "--no-default-browser-check"])
+        .arg("about:blank")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to start {}: {}", browser.display(), e))?;
+
+    let session = open_page(&mut child, url, width, height);
+    let mut devtools = match session {
+        Ok(devtools) => devtools,
+        Err(e) => {
+            close_browser(&mut child, &profile);
+            return Err(e);
+        }
+    };
+    tracing::info!(target: "Scene", "Rendering {} with {}", url, browser.display());
+
+    let (frame_tx, frame_rx) = frame_channel(2);
+    let stop_flag: StopHandle = Arc::new(AtomicBool::new(false));
+    let stop = stop_flag.clone();
+    let url = url.to_string();
+    std::thread::spawn(move || {
+        while !stop.load(Ordering::Relaxed) {
+            let message = match devtools.receive(&stop) {
+                Ok(Some(message)) => message,
+                Ok(None) => continue,
+                Err(e) => {
+                    tracing::warn!(target: "Scene", "Browser source {} stopped: {}", url, e);
+                    break;
+                }
+            };
+            if message["method"] != "Page.screencastFrame" {
+                continue;
+            }
+            let params = &message["params"];
+            let _ = devtools.send("Page.screencastFrameAck", json!({ "sessionId": params["sessionId"] }));
+            let frame = params["data"]
+                .as_str()
+                .ok_or_else(|| "No picture in the frame".to_string())
+                .and_then(|data| decode_frame(data, width, height));
+            match frame {
+                Ok(frame) => {
+                    if frame_tx.send(frame).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => tracing::warn!(target: "Scene", "Dropped a frame of {}: {}", url, e),
+            }
+        }
+        close_browser(&mut child, &profile);
+    });
+
+    Ok((frame_rx, stop_flag))
+}
+
+/// The browser to render with.
+fn find_browser() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(BROWSER_ENV) {
+        return Some(PathBuf::from(path));
+    }
+    let on_path = std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).collect::<Vec<_>>())
+        .unwrap_or_default();
+    BROWSER_PATHS
+        .iter()
+        .map(PathBuf::from)
+        .chain(BROWSER_NAMES.iter().flat_map(|name| on_path.iter().map(move |dir| dir.join(name))))
+        .find(|path| path.is_file())
+}
+
+/// Stop the browser and delete its profile.
+fn close_browser(child: &mut Child, profile: &PathBuf) {
+    let _ = child.kill();
+    let _ = child.wait();
+    let _ = std::fs::remove_dir_all(profile);
+}
+
+/// Wait for the browser to start, then load `url` in its page with a
+/// transparent background and start streaming pictures of it.
+fn open_page(child: &mut Child, url: &str, width: u32, height: u32) -> Result<DevTools, String> {
+    let stderr = child.stderr.take().ok_or("Failed to get the browser's output")?;
+    let (port_tx, port_rx) = std::sync::mpsc::channel();
+    // Keep draining the output so the browser never blocks writing it
+    std::thread::spawn(move || {
+        for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+            if let Some(port) = devtools_port(&line) {
+                let _ = port_tx.send(port);
+            }
+        }
+    });
+    let port = port_rx
+        .recv_timeout(START_TIMEOUT)
+        .map_err(|_| "The browser didn't start its DevTools server".to_string())?;
+
+    let pages = http_get(port, "/json/list")?;
+    let pages: Vec<Value> = serde_json::from_str(&pages).map_err(|e| format!("Unexpected page list: {}", e))?;
+    let socket_url = pages
+        .iter()
+        .find(|page| page["type"] == "page")
+        .and_then(|page| page["webSocketDebuggerUrl"].as_str())
+        .ok_or("The browser has no page to render")?;
+    let path = socket_url
+        .strip_prefix("ws://")
+        .and_then(|rest| rest.find('/').map(|slash| &rest[slash..]))
+        .ok_or_else(|| format!("Unexpected DevTools address: {}", socket_url))?;
+
+    let mut devtools = DevTools::connect(port, path)?;
+    devtools.send(
+        "Emulation.setDeviceMetricsOverride",
+        json!({ "width": width, "height": height, "deviceScaleFactor": 1, "mobile": false }),
+    )?;
+    devtools.send(
+        "Emulation.setDefaultBackgroundColorOverride",
+        json!({ "color": { "r": 0, "g": 0, "b": 0, "a": 0 } }),
+    )?;
+    devtools.send("Page.navigate", json!({ "url": url }))?;
+    devtools.send(
+        "Page.startScreencast",
+        json!({ "format": "png", "maxWidth": width, "maxHeight": height, "everyNthFrame": 1 }),
+    )?;
+    Ok(devtools)
+}
+
+/// The DevTools port in the line the browser prints once it's listening,
+/// `DevTools listening on ws://127.0.0.1:<port>/devtools/browser/<id>`.
+fn devtools_port(line: &str) -> Option<u16> {
+    let address = line.trim().strip_prefix("DevTools listening on ws://")?;
+    let host = address.split('/').next()?;
+    host.rsplit_once(':')?.1.parse().ok()
+}
+
+/// The body of a GET request to the DevTools server.
+fn http_get(port: u16, path: &str) -> Result<String, String> {
+    let error = |e: std::io::Error| format!("DevTools request failed: {}", e);
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).map_err(error)?;
+    stream.set_read_timeout(Some(START_TIMEOUT)).map_err(error)?;
+    write!(stream, "GET {} HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nConnection: close\r\n\r\n", path, port).map_err(error)?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).map_err(error)?;
+    response
+        .split_once("\r\n\r\n")
+        .map(|(_, body)| body.to_string())
+        .ok_or_else(|| "Incomplete DevTools response".to_string())
+}
+
+/// A DevTools protocol connection to a page.
+struct DevTools {
+    stream: TcpStream,
+    next_id: u64,
+}
+
+impl DevTools {
+    /// Open the page's WebSocket at `path`.
+    fn connect(port: u16, path: &str) -> Result<Self, String> {
+        let error = |e: std::io::Error| format!("DevTools connection failed: {}", e);
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).map_err(error)?;
+        let key = base64_key();
+        write!(
+            stream,
+            "GET {} HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            path, port, key
+        )
+        .map_err(error)?;
+
+        // Read the response head a byte at a time, so none of the first
+        // frame is consumed with it
+        let mut head = Vec::new();
+        let mut byte = [0u8; 1];
+        while !head.ends_with(b"\r\n\r\n") {
+            stream.read_exact(&mut byte).map_err(error)?;
+            head.push(byte[0]);
+        }
+        let status = String::from_utf8_lossy(&head);
+        if !status.starts_with("HTTP/1.1 101") {
+            return Err(format!("DevTools refused the connection: {}", status.lines().next().unwrap_or_default()));
+        }
+        Ok(Self { stream, next_id: 1 })
+    }
+
+    /// Call a DevTools method without waiting for its result.
+    fn send(&mut self, method: &str, params: Value) -> Result<(), String> {
+        let message = json!({ "id": self.next_id, "method": method, "params": params }).to_string();
+        self.next_id += 1;
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.subsec_nanos())
+            .unwrap_or_default();
+        let mask = (nanos ^ self.next_id as u32).to_le_bytes();
+        self.stream
+            .write_all(&client_websocket_frame(opcode::TEXT, message.as_bytes(), mask))
+            .map_err(|e| format!("DevTools send failed: {}", e))
+    }
+
+    /// The next message, or `None` if none arrived before `stop` was checked.
+    fn receive(&mut self, stop: &AtomicBool) -> Result<Option<Value>, String> {
+        let error = |e: std::io::Error| format!("DevTools connection lost: {}", e);
+        let mut message = Vec::new();
+        loop {
+            // Wait for a frame in short steps, then read all of it
+            let mut first = [0u8; 1];
+            self.stream.set_read_timeout(Some(STOP_POLL_INTERVAL)).map_err(error)?;
+            match self.stream.read(&mut first) {
+                Ok(0) => return Err("The browser closed the connection".to_string()),
+                Ok(_) => {}
+                Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {
+                    if stop.load(Ordering::Relaxed) || message.is_empty() {
+                        return Ok(None);
+                    }
+                    continue;
+                }
+                Err(e) => return Err(error(e)),
+            }
+            self.stream.set_read_timeout(None).map_err(error)?;
+            let (fin, frame_opcode, payload) = self.read_frame(first[0])?;
+            match frame_opcode {
+                opcode::PING => {
+                    let frame = client_websocket_frame(opcode::PONG, &payload, [0; 4]);
+                    self.stream.write_all(&frame).map_err(error)?;
+                }
+                opcode::CLOSE => return Err("The browser closed the connection".to_string()),
+                _ => {
+                    message.extend_from_slice(&payload);
+                    if message.len() > MAX_MESSAGE_BYTES {
+                        return Err("DevTools message too large".to_string());
+                    }
+                    if fin {
+                        return serde_json::from_slice(&message)
+                            .map(Some)
+                            .map_err(|e| format!("Invalid DevTools message: {}", e));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Read the rest of a frame starting with `first`. Returns whether it's
+    /// the last of its message, its opcode and payload.
+    fn read_frame(&mut self, first: u8) -> Result<(bool, u8, Vec<u8>), String> {
+        let error = |e: std::io::Error| format!("DevTools connection lost: {}", e);
+        let mut second = [0u8; 1];
+        self.stream.read_exact(&mut second).map_err(error)?;
+        let mut len = (second[0] & 0x7F) as u64;
+        if len == 126 {
+            let mut bytes = [0u8; 2];
+            self.stream.read_exact(&mut bytes).map_err(error)?;
+            len = u16::from_be_bytes(bytes) as u64;
+        } else if len == 127 {
+            let mut bytes = [0u8; 8];
+            self.stream.read_exact(&mut bytes).map_err(error)?;
+            len = u64::from_be_bytes(bytes);
+        }
+        if len > MAX_MESSAGE_BYTES as u64 {
+            return Err("DevTools message too large".to_string());
+        }
+        // Servers don't mask frames, but allow for it
+        let mut mask = [0u8; 4];
+        if second[0] & 0x80 != 0 {
+            self.stream.read_exact(&mut mask).map_err(error)?;
+        }
+        let mut payload = vec![0u8; len as usize];
+        self.stream.read_exact(&mut payload).map_err(error)?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+        Ok((first & 0x80 != 0, first & 0x0F, payload))
+    }
+}
+
+/// A `Sec-WebSocket-Key`; the browser only checks that there is one.
+fn base64_key() -> String {
+    use base64::Engine;
+    let seed = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos())
+        .unwrap_or_default();
+    base64::engine::general_purpose::STANDARD.encode(seed.to_le_bytes())
+}
+
+/// A base64-encoded PNG picture of the page as a `width`x`height` BGRA
+/// frame, cropped or padded with transparency if the page came out another
+/// size.
+fn decode_frame(data: &str, width: u32, height: u32) -> Result<CapturedFrame, String> {
+    use base64::Engine;
+    let png = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|e| format!("Invalid picture: {}", e))?;
+    let mut decoder = png::Decoder::new(png.as_slice());
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| format!("Invalid picture: {}", e))?;
+    let mut pixels = vec![0u8; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).map_err(|e| format!("Invalid picture: {}", e))?;
+    let channels = info.color_type.samples();
+
+    let mut data = vec![0u8; width as usize * height as usize * 4];
+    for y in 0..height.min(info.height) as usize {
+        let row = &pixels[y * info.line_size..];
+        for x in 0..width.min(info.width) as usize {
+            let pixel = &row[x * channels..(x + 1) * channels];
+            let [r, g, b, a] = match channels {
+                1 => [pixel[0], pixel[0], pixel[0], 255],
+                2 => [pixel[0], pixel[0], pixel[0], pixel[1]],
+                3 => [pixel[0], pixel[1], pixel[2], 255],
+                _ => [pixel[0], pixel[1], pixel[2], pixel[3]],
+            };
+            let i = (y * width as usize + x) * 4;
+            data[i..i + 4].copy_from_slice(&[b, g, r, a]);
+        }
+    }
+    Ok(CapturedFrame {
+        width,
+        height,
+        data,
+        format: PixelFormat::Bgra8,
+        color: ColorSpace::SRGB,
+        captured_at: Instant::now(),
+        presented_at: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_devtools_port() {
+        assert_eq!(
+            devtools_port("DevTools listening on ws://127.0.0.1:40231/devtools/browser/5b0e-41c9"),
+            Some(40231)
+        );
+        assert_eq!(devtools_port("[1234:5678:ERROR:gpu_init.cc(523)] Passthrough is not supported"), None);
+    }
+
+    #[test]
+    fn test_decode_frame() {
+        use base64::Engine;
+        // 2x1 RGBA: opaque red, half-transparent blue
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, 2, 1);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(&[255, 0, 0, 255, 0, 0, 255, 128])
+            .unwrap();
+        let data = base64::engine::general_purpose::STANDARD.encode(png);
+
+        // Padded to 3x2 with transparency
+        let frame = decode_frame(&data, 3, 2).unwrap();
+        assert_eq!((frame.width, frame.height), (3, 2));
+        assert_eq!(&frame.data[..12], &[0, 0, 255, 255, 255, 0, 0, 128, 0, 0, 0, 0]);
+        assert!(frame.data[12..].iter().all(|&c| c == 0));
+    }
+}
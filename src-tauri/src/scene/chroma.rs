@@ -0,0 +1,139 @@
+//! Chroma keying, for webcams in front of a green screen.
+//!
+//! Pixels are compared with the key color by their chroma, so shadows and
+//! creases in the backdrop key out as well as its lit parts. Pixels within
+//! the similarity of the key color become transparent, fading back in over
+//! the smoothness. The backdrop's color also reflects onto the subject; near
+//! the key, that tint is taken out of the chroma (spill suppression).
+
+use crate::capture::{CapturedFrame, PixelFormat};
+use serde::{Deserialize, Serialize};
+
+/// Settings for keying out a backdrop color.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChromaKey {
+    /// Backdrop color as RGB
+    pub color: [u8; 3],
+    /// How close to the key color a pixel's chroma must be to become
+    /// transparent, from 0 to 1
+    pub similarity: f32,
+    /// How gradually pixels past the similarity become opaque, from 0 to 1
+    pub smoothness: f32,
+    /// How far past the similarity the key color's tint is removed, from 0 to 1
+    pub spill: f32,
+}
+
+impl Default for ChromaKey {
+    fn default() -> Self {
+        Self {
+            color: [0, 255, 0],
+            similarity: 0.35,
+            smoothness: 0.05,
+            spill: 0.2,
+        }
+    }
+}
+
+/// BT.709 luma and chroma of an RGB color with channels from 0 to 1.
+fn to_ycbcr([r, g, b]: [f32; 3]) -> [f32; 3] {
+    [
+        0.2126 * r + 0.7152 * g + 0.0722 * b,
+        -0.1146 * r - 0.3854 * g + 0.5 * b,
+        0.5 * r - 0.4542 * g - 0.0458 * b,
+    ]
+}
+
+fn to_rgb([y, cb, cr]: [f32; 3]) -> [f32; 3] {
+    [y + 1.5748 * cr, y - 0.1873 * cb - 0.4681 * cr, y + 1.8556 * cb]
+}
+
+impl ChromaKey {
+    /// Check that the settings are usable.
+    pub fn validate(&self) -> Result<(), String> {
+        let in_range = |value: f32| (0.0..=1.0).contains(&value);
+        if !in_range(self.similarity) || !in_range(self.smoothness) || !in_range(self.spill) {
+            return Err("Chroma key similarity, smoothness and spill must be between 0 and 1".to_string());
+        }
+        Ok(())
+    }
+
+    /// Key `frame`, an 8-bit BGRA frame, giving it alpha.
+    pub fn apply(&self, frame: &CapturedFrame) -> CapturedFrame {
+        let [r, g, b] = self.color.map(|c| c as f32 / 255.0);
+        let [_, key_cb, key_cr] = to_ycbcr([r, g, b]);
+        let key_length = key_cb.hypot(key_cr);
+        // A gray key has no chroma to take out
+        let key_direction = (key_length > 0.0).then(|| (key_cb / key_length, key_cr / key_length));
+
+        let mut data = frame.data.clone();
+        if frame.format == PixelFormat::Bgra8 {
+            for pixel in data.chunks_exact_mut(4) {
+                let rgb = [pixel[2], pixel[1], pixel[0]].map(|c| c as f32 / 255.0);
+                let [y, cb, cr] = to_ycbcr(rgb);
+                let distance = (cb - key_cb).hypot(cr - key_cr);
+                let alpha = if self.smoothness > 0.0 {
+                    ((distance - self.similarity) / self.smoothness).clamp(0.0, 1.0)
+                } else if distance > self.similarity {
+                    1.0
+                } else {
+                    0.0
+                };
+                pixel[3] = (pixel[3] as f32 * alpha).round() as u8;
+
+                let suppression = if self.spill > 0.0 {
+                    (1.0 - (distance - self.similarity) / self.spill).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                if let (true, Some((dir_cb, dir_cr))) = (suppression > 0.0, key_direction) {
+                    let tint = (cb * dir_cb + cr * dir_cr).max(0.0) * suppression;
+                    let [r, g, b] = to_rgb([y, cb - tint * dir_cb, cr - tint * dir_cr])
+                        .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+                    pixel[..3].copy_from_slice(&[b, g, r]);
+                }
+            }
+        }
+        CapturedFrame {
+            width: frame.width,
+            height: frame.height,
+            data,
+            format: frame.format,
+            color: frame.color,
+            captured_at: frame.captured_at,
+            presented_at: frame.presented_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::ColorSpace;
+    use std::time::Instant;
+
+    #[test]
+    fn test_chroma_key() {
+        // Backdrop green, a darker green crease, skin, and a green-tinted edge
+        let pixels: [[u8; 4]; 4] = [[0, 255, 0, 255], [20, 150, 30, 255], [150, 180, 230, 255], [130, 190, 140, 255]];
+        let frame = CapturedFrame {
+            width: 4,
+            height: 1,
+            data: pixels.concat(),
+            format: PixelFormat::Bgra8,
+            color: ColorSpace::SRGB,
+            captured_at: Instant::now(),
+            presented_at: None,
+        };
+        let keyed = ChromaKey::default().apply(&frame);
+        let alpha: Vec<u8> = keyed.data.chunks_exact(4).map(|pixel| pixel[3]).collect();
+        assert_eq!(alpha[..3], [0, 0, 255]);
+        // Skin is left alone
+        assert_eq!(keyed.data[8..12], pixels[2]);
+        // The edge keeps its alpha but loses some green
+        assert_eq!(alpha[3], 255);
+        assert!(keyed.data[13] < 190);
+
+        assert!(ChromaKey { similarity: 1.5, ..ChromaKey::default() }.validate().is_err());
+    }
+}
@@ -0,0 +1,466 @@
+//! Drawing a scene's sources into one frame.
+//!
+//! Each item's source is cropped, scaled with bilinear filtering and drawn
+//! over the items below it on an opaque black canvas. Captures and devices
+//! cover what's under them; images are blended by their alpha, as are items
+//! with a chroma key once it's applied. Round items are cut out with a
+//! smoothed edge. Drawing is on the CPU, in 8-bit BGRA.
+
+use super::chroma::ChromaKey;
+use super::sources::{source_key, RunningSource, SourceContext};
+use super::{ItemShape, Scene, SceneItem, SceneSource, Transition};
+use crate::capture::queue::frame_channel;
+use crate::capture::{CapturedFrame, ColorSpace, FrameReceiver, PixelFormat, StopHandle};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+/// How long to wait for every source's first picture before drawing without
+/// the ones that haven't delivered.
+const FIRST_FRAME_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A request to switch a running compositor to another scene.
+struct SwitchRequest {
+    scene: Scene,
+    transition: Transition,
+    /// Told whether the scene's sources started
+    reply: oneshot::Sender<Result<(), String>>,
+}
+
+/// A change to a running compositor.
+enum Request {
+    Switch(SwitchRequest),
+    /// Key an item of the current or next scene, by the scenes' and item's names
+    ChromaKey {
+        scene: String,
+        item: String,
+        key: Option<ChromaKey>,
+    },
+}
+
+/// Switches the scene a running compositor draws, and changes its items'
+/// chroma keys.
+#[derive(Clone)]
+pub struct SceneSwitcher {
+    requests: mpsc::Sender<Request>,
+}
+
+impl SceneSwitcher {
+    /// Switch to `scene`, which must have the recording's canvas size. The
+    /// transition starts once the scene's sources are delivering; this
+    /// returns as soon as they've started.
+    pub async fn switch(&self, scene: Scene, transition: Transition) -> Result<(), String> {
+        scene.validate()?;
+        transition.validate()?;
+        let stopped = || "The scene is no longer being recorded".to_string();
+        let (reply, replied) = oneshot::channel();
+        self.requests
+            .send(Request::Switch(SwitchRequest { scene, transition, reply }))
+            .map_err(|_| stopped())?;
+        replied.await.map_err(|_| stopped())?
+    }
+
+    /// Set or clear the chroma key of `item` in `scene`, from the next frame.
+    /// Does nothing unless the scene is showing or being switched to.
+    pub fn set_chroma_key(&self, scene: &str, item: &str, key: Option<ChromaKey>) -> Result<(), String> {
+        self.requests
+            .send(Request::ChromaKey {
+                scene: scene.to_string(),
+                item: item.to_string(),
+                key,
+            })
+            .map_err(|_| "The scene is no longer being recorded".to_string())
+    }
+}
+
+/// Start the scene's sources and draw them into a frame whenever one of
+/// them changes, checking `fps` times a second.
+///
+/// The returned frames go to the encoder like a capture's; setting the stop
+/// handle stops the sources too.
+pub fn start_compositor(
+    scene: Scene,
+    fps: u32,
+    context: SourceContext,
+) -> Result<(FrameReceiver, StopHandle, SceneSwitcher), String> {
+    scene.validate()?;
+    let mut compositor = Compositor {
+        scene: scene.clone(),
+        next: None,
+        sources: HashMap::new(),
+        context,
+        created: Instant::now(),
+        drawn: false,
+        redraw: false,
+    };
+    compositor.start_sources(&scene)?;
+
+    let (frame_tx, frame_rx) = frame_channel(2);
+    let (requests, pending_requests) = mpsc::channel();
+    let stop_flag: StopHandle = Arc::new(AtomicBool::new(false));
+    let stop = stop_flag.clone();
+    std::thread::spawn(move || {
+        let interval = Duration::from_secs_f64(1.0 / fps.max(1) as f64);
+        while !stop.load(Ordering::Relaxed) {
+            let tick = Instant::now();
+            while let Ok(request) = pending_requests.try_recv() {
+                match request {
+                    Request::Switch(request) => compositor.request(request),
+                    Request::ChromaKey { scene, item, key } => compositor.set_chroma_key(&scene, &item, key),
+                }
+            }
+            let mut changed = false;
+            for source in compositor.sources.values_mut() {
+                changed |= source.poll();
+            }
+            if let Some(frame) = compositor.tick(changed) {
+                if frame_tx.send(frame).is_err() {
+                    break;
+                }
+            }
+            std::thread::sleep(interval.saturating_sub(tick.elapsed()));
+        }
+        tracing::info!(target: "Scene", "Compositor for {:?} stopped", compositor.scene.name);
+    });
+
+    Ok((frame_rx, stop_flag, SceneSwitcher { requests }))
+}
+
+/// A scene being switched to.
+struct PendingSwitch {
+    scene: Scene,
+    transition: Transition,
+    requested: Instant,
+    /// When the transition started, once the scene's sources were ready
+    started: Option<Instant>,
+}
+
+/// The running sources and the scenes drawn from them.
+struct Compositor {
+    scene: Scene,
+    next: Option<PendingSwitch>,
+    /// Sources of the current and next scene, by [`source_key`]
+    sources: HashMap<String, RunningSource>,
+    context: SourceContext,
+    created: Instant,
+    /// Whether a frame has been drawn yet
+    drawn: bool,
+    /// Whether the picture changed other than by a source's new picture
+    redraw: bool,
+}
+
+impl Compositor {
+    /// Start those of `scene`'s sources that aren't running yet. If one
+    /// fails, none are kept.
+    fn start_sources(&mut self, scene: &Scene) -> Result<(), String> {
+        let mut started = HashMap::new();
+        for item in &scene.items {
+            let key = source_key(&item.source);
+            if self.sources.contains_key(&key) || started.contains_key(&key) {
+                continue;
+            }
+            let source = RunningSource::start(&item.source, &self.context).map_err(|e| format!("{}: {}", item.name, e))?;
+            started.insert(key, source);
+        }
+        self.sources.extend(started);
+        Ok(())
+    }
+
+    /// Stop the sources neither the current nor the next scene shows.
+    fn retain_sources(&mut self) {
+        let used: HashSet<String> = self
+            .scene
+            .items
+            .iter()
+            .chain(self.next.iter().flat_map(|next| &next.scene.items))
+            .map(|item| source_key(&item.source))
+            .collect();
+        self.sources.retain(|key, _| used.contains(key));
+    }
+
+    /// Whether every source in `scene` has delivered a picture.
+    fn ready(&self, scene: &Scene) -> bool {
+        scene
+            .items
+            .iter()
+            .all(|item| self.sources.get(&source_key(&item.source)).is_some_and(|s| s.latest().is_some()))
+    }
+
+    /// Draw `scene` from its sources' latest pictures.
+    fn draw(&self, scene: &Scene) -> CapturedFrame {
+        let layers: Vec<_> = scene
+            .draw_order()
+            .into_iter()
+            .filter_map(|i| {
+                let item = &scene.items[i];
+                Some((item, self.sources.get(&source_key(&item.source))?.latest()?))
+            })
+            .collect();
+        compose(scene.width, scene.height, &layers)
+    }
+
+    /// Start the sources of a scene to switch to, replacing any switch
+    /// still waiting.
+    fn request(&mut self, request: SwitchRequest) {
+        let SwitchRequest { scene, transition, reply } = request;
+        let result = if (scene.width, scene.height) != (self.scene.width, self.scene.height) {
+            Err(format!(
+                "{:?} is {}x{}, but the recording is {}x{}",
+                scene.name, scene.width, scene.height, self.scene.width, self.scene.height
+            ))
+        } else {
+            self.start_sources(&scene)
+        };
+        if result.is_ok() {
+            tracing::info!(target: "Scene", "Switching to {:?}", scene.name);
+            self.next = Some(PendingSwitch {
+                scene,
+                transition,
+                requested: Instant::now(),
+                started: None,
+            });
+            self.retain_sources();
+        }
+        let _ = reply.send(result);
+    }
+
+    /// Key an item of the current or next scene.
+    fn set_chroma_key(&mut self, scene: &str, item: &str, key: Option<ChromaKey>) {
+        let scenes = std::iter::once(&mut self.scene).chain(self.next.as_mut().map(|next| &mut next.scene));
+        for scene in scenes.filter(|s| s.name == scene) {
+            for scene_item in scene.items.iter_mut().filter(|i| i.name == item) {
+                scene_item.chroma_key = key;
+                self.redraw = true;
+            }
+        }
+    }
+
+    /// The next frame to record, if the picture changed; `changed` is
+    /// whether any source delivered a new picture.
+    fn tick(&mut self, changed: bool) -> Option<CapturedFrame> {
+        let changed = changed || std::mem::take(&mut self.redraw);
+        // Transitions start once the new scene's sources are showing
+        let next_ready = self.next.as_ref().is_some_and(|next| {
+            next.started.is_none() && (self.ready(&next.scene) || next.requested.elapsed() >= FIRST_FRAME_TIMEOUT)
+        });
+        if let (true, Some(next)) = (next_ready, self.next.as_mut()) {
+            next.started = Some(Instant::now());
+        }
+
+        match &self.next {
+            Some(PendingSwitch { scene, transition, started: Some(started), .. }) => {
+                let progress = transition.progress(started.elapsed());
+                if progress < 1.0 {
+                    return Some(crossfade(&self.draw(&self.scene), &self.draw(scene), progress));
+                }
+                if let Some(next) = self.next.take() {
+                    self.scene = next.scene;
+                }
+                self.retain_sources();
+                tracing::info!(target: "Scene", "Switched to {:?}", self.scene.name);
+                Some(self.draw(&self.scene))
+            }
+            _ => {
+                let ready = self.drawn || self.ready(&self.scene) || self.created.elapsed() >= FIRST_FRAME_TIMEOUT;
+                if !ready || (self.drawn && !changed) {
+                    return None;
+                }
+                self.drawn = true;
+                Some(self.draw(&self.scene))
+            }
+        }
+    }
+}
+
+/// Blend from `from` to `to` by `progress`, between 0 and 1. The frames
+/// have the same size.
+fn crossfade(from: &CapturedFrame, to: &CapturedFrame, progress: f32) -> CapturedFrame {
+    let data = from
+        .data
+        .iter()
+        .zip(&to.data)
+        .map(|(&a, &b)| (a as f32 + (b as f32 - a as f32) * progress).round() as u8)
+        .collect();
+    CapturedFrame {
+        width: to.width,
+        height: to.height,
+        data,
+        format: to.format,
+        color: to.color,
+        captured_at: from.captured_at.max(to.captured_at),
+        presented_at: None,
+    }
+}
+
+/// Draw `layers`, bottom first, onto a `width`x`height` canvas.
+///
+/// The frame is timed by its most recently captured layer.
+pub fn compose(width: u32, height: u32, layers: &[(&SceneItem, &CapturedFrame)]) -> CapturedFrame {
+    let mut data = [0, 0, 0, 255].repeat(width as usize * height as usize);
+    for (item, frame) in layers {
+        draw(&mut data, width, height, item, frame);
+    }
+    CapturedFrame {
+        width,
+        height,
+        data,
+        format: PixelFormat::Bgra8,
+        color: ColorSpace::SRGB,
+        captured_at: layers.iter().map(|(_, frame)| frame.captured_at).max().unwrap_or_else(Instant::now),
+        presented_at: None,
+    }
+}
+
+/// Source coordinates sampled for each destination pixel along one axis, as
+/// (first, second, weight of second).
+fn sample_positions(dst_start: i64, dst_range: std::ops::Range<i64>, src_start: u32, src_len: u32, dst_len: i64) -> Vec<(usize, usize, f32)> {
+    let step = src_len as f32 / dst_len as f32;
+    let last = (src_start + src_len - 1) as f32;
+    dst_range
+        .map(|dst| {
+            // Pixel centers line up, so a scale of 1 copies pixels exactly
+            let src = (((dst - dst_start) as f32 + 0.5) * step - 0.5 + src_start as f32).clamp(src_start as f32, last);
+            let first = src.floor();
+            (first as usize, (first + 1.0).min(last) as usize, src - first)
+        })
+        .collect()
+}
+
+/// Draw one item's frame onto the canvas.
+fn draw(canvas: &mut [u8], width: u32, height: u32, item: &SceneItem, frame: &CapturedFrame) {
+    if frame.format != PixelFormat::Bgra8 {
+        return;
+    }
+    let crop = item.crop;
+    let src_width = frame.width.saturating_sub(crop.left.saturating_add(crop.right));
+    let src_height = frame.height.saturating_sub(crop.top.saturating_add(crop.bottom));
+    let dst_width = (src_width as f32 * item.scale).round() as i64;
+    let dst_height = (src_height as f32 * item.scale).round() as i64;
+    if src_width == 0 || src_height == 0 || dst_width < 1 || dst_height < 1 {
+        return;
+    }
+
+    // The part of the item on the canvas
+    let (x, y) = (item.x as i64, item.y as i64);
+    let columns = x.max(0)..(x + dst_width).min(width as i64);
+    let rows = y.max(0)..(y + dst_height).min(height as i64);
+    if columns.is_empty() || rows.is_empty() {
+        return;
+    }
+    let xs = sample_positions(x, columns.clone(), crop.left, src_width, dst_width);
+    let ys = sample_positions(y, rows.clone(), crop.top, src_height, dst_height);
+
+    let keyed;
+    let frame = match item.chroma_key {
+        Some(key) => {
+            keyed = key.apply(frame);
+            &keyed
+        }
+        None => frame,
+    };
+    let blend = matches!(item.source, SceneSource::Image { .. }) || item.chroma_key.is_some();
+    // How much of a pixel a round item covers, with its edge smoothed over a pixel
+    let radius = (dst_width as f32 / 2.0, dst_height as f32 / 2.0);
+    let coverage = |column: i64, row: i64| match item.shape {
+        ItemShape::Rectangle => 1.0,
+        ItemShape::Circle => {
+            let dx = ((column - x) as f32 + 0.5 - radius.0) / radius.0;
+            let dy = ((row - y) as f32 + 0.5 - radius.1) / radius.1;
+            ((1.0 - (dx * dx + dy * dy).sqrt()) * radius.0.min(radius.1) + 0.5).clamp(0.0, 1.0)
+        }
+    };
+    let stride = frame.width as usize * 4;
+    let pixel = |x: usize, y: usize| &frame.data[y * stride + x * 4..y * stride + x * 4 + 4];
+    for (row, &(y0, y1, fy)) in rows.zip(&ys) {
+        let out_row = row as usize * width as usize * 4;
+        for (column, &(x0, x1, fx)) in columns.clone().zip(&xs) {
+            let (a, b, c, d) = (pixel(x0, y0), pixel(x1, y0), pixel(x0, y1), pixel(x1, y1));
+            let mut sample = [0.0f32; 4];
+            for (channel, value) in sample.iter_mut().enumerate() {
+                let top = a[channel] as f32 + (b[channel] as f32 - a[channel] as f32) * fx;
+                let bottom = c[channel] as f32 + (d[channel] as f32 - c[channel] as f32) * fx;
+                *value = top + (bottom - top) * fy;
+            }
+            let out = &mut canvas[out_row + column as usize * 4..out_row + column as usize * 4 + 4];
+            let alpha = if blend { sample[3] / 255.0 } else { 1.0 } * coverage(column, row);
+            if alpha <= 0.0 {
+                continue;
+            }
+            for channel in 0..3 {
+                out[channel] = (sample[channel] * alpha + out[channel] as f32 * (1.0 - alpha)).round() as u8;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::Crop;
+    use std::path::PathBuf;
+
+    fn item(source: SceneSource, x: i32, y: i32, scale: f32, crop: Crop) -> SceneItem {
+        SceneItem {
+            name: "Item".to_string(),
+            source,
+            x,
+            y,
+            scale,
+            crop,
+            z_order: 0,
+            shape: ItemShape::Rectangle,
+            chroma_key: None,
+        }
+    }
+
+    fn solid(width: u32, height: u32, bgra: [u8; 4]) -> CapturedFrame {
+        CapturedFrame {
+            width,
+            height,
+            data: bgra.repeat((width * height) as usize),
+            format: PixelFormat::Bgra8,
+            color: ColorSpace::SRGB,
+            captured_at: Instant::now(),
+            presented_at: None,
+        }
+    }
+
+    #[test]
+    fn test_compose_layers() {
+        let device = SceneSource::Device { device_id: "cam".to_string(), width: 4, height: 4 };
+        let image = SceneSource::Image { path: PathBuf::from("logo.png") };
+        // A red 4x4 frame with its left half cropped, doubled to 4x8 at (1, 1)
+        let red = item(device, 1, 1, 2.0, Crop { left: 2, ..Crop::default() });
+        // Half-transparent white over the top-left corner
+        let white = item(image, -1, -1, 1.0, Crop::default());
+        let red_frame = solid(4, 4, [0, 0, 255, 255]);
+        let white_frame = solid(3, 3, [255, 255, 255, 128]);
+        let frame = compose(6, 10, &[(&red, &red_frame), (&white, &white_frame)]);
+
+        let at = |x: usize, y: usize| &frame.data[(y * 6 + x) * 4..(y * 6 + x) * 4 + 4];
+        assert_eq!(at(0, 0), [128, 128, 128, 255]);
+        assert_eq!(at(1, 1), [128, 128, 255, 255]);
+        assert_eq!(at(4, 8), [0, 0, 255, 255]);
+        // Outside the scaled, cropped frame
+        assert_eq!(at(5, 1), [0, 0, 0, 255]);
+        assert_eq!(at(1, 9), [0, 0, 0, 255]);
+
+        // A round item mostly leaves its corners uncovered
+        let round = SceneItem { shape: ItemShape::Circle, ..red };
+        let frame = compose(6, 10, &[(&round, &red_frame)]);
+        assert!(frame.data[(6 + 1) * 4 + 2] < 64);
+        assert_eq!(&frame.data[(5 * 6 + 3) * 4..(5 * 6 + 3) * 4 + 4], [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn test_crossfade() {
+        let transition = Transition::Crossfade { duration_ms: 400 };
+        assert_eq!(transition.progress(Duration::from_millis(100)), 0.25);
+        assert_eq!(Transition::Cut.progress(Duration::ZERO), 1.0);
+
+        let frame = crossfade(&solid(2, 2, [0, 0, 0, 255]), &solid(2, 2, [200, 100, 40, 255]), 0.25);
+        assert_eq!(&frame.data[..4], [50, 25, 10, 255]);
+    }
+}
@@ -0,0 +1,286 @@
+//! Layout presets arranging a scene's screen and camera.
+//!
+//! A layout places a scene's items in slots given as fractions of the
+//! canvas, so one layout suits any canvas size. Screen slots take the
+//! scene's captures and images and camera slots its webcams and capture
+//! cards, in list order. Each item is scaled to fit in its slot, or to fill
+//! it and cropped. The built-in layouts cover the usual screen and webcam
+//! compositions, and more can be saved.
+
+use super::{Crop, ItemShape, Scene, SceneItem, SceneSource};
+use crate::config::{load_json, save_json};
+use serde::{Deserialize, Serialize};
+
+const LAYOUTS_FILE: &str = "layouts.json";
+
+/// Which of a scene's items a slot takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SlotRole {
+    /// Captures, images and browser sources
+    Screen,
+    /// Webcams and capture cards
+    Camera,
+}
+
+impl SlotRole {
+    fn takes(&self, source: &SceneSource) -> bool {
+        matches!(source, SceneSource::Device { .. }) == (*self == SlotRole::Camera)
+    }
+}
+
+/// How an item is sized to its slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Fit {
+    /// Show all of the source, centered in the slot
+    Contain,
+    /// Fill the slot, cropping the source's edges evenly
+    Cover,
+}
+
+/// An area of the canvas for one item, in fractions of the canvas size.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayoutSlot {
+    pub role: SlotRole,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub fit: Fit,
+    #[serde(default)]
+    pub shape: ItemShape,
+    /// Shrink the slot to this width-to-height ratio, toward the canvas edge
+    /// it's nearest, such as 1 for a round webcam
+    #[serde(default)]
+    pub aspect: Option<f32>,
+}
+
+impl LayoutSlot {
+    /// The slot's area on a `width`x`height` canvas, as (x, y, width, height).
+    fn area(&self, width: u32, height: u32) -> (f32, f32, f32, f32) {
+        let (mut x, mut y) = (self.x * width as f32, self.y * height as f32);
+        let (mut w, mut h) = (self.width * width as f32, self.height * height as f32);
+        if let Some(aspect) = self.aspect {
+            let (fit_w, fit_h) = (w.min(h * aspect), h.min(w / aspect));
+            // Slots right of or below the center keep to the right or bottom
+            let align = |start: f32, size: f32, canvas: u32| match (start + size / 2.0) / canvas as f32 {
+                c if c > 0.5 => 1.0,
+                c if c < 0.5 => 0.0,
+                _ => 0.5,
+            };
+            x += (w - fit_w) * align(x, w, width);
+            y += (h - fit_h) * align(y, h, height);
+            (w, h) = (fit_w, fit_h);
+        }
+        (x, y, w, h)
+    }
+
+    /// Place `item`, whose source is `size`, in this slot.
+    fn place(&self, item: &mut SceneItem, size: (u32, u32), canvas: (u32, u32)) {
+        let (x, y, width, height) = self.area(canvas.0, canvas.1);
+        let (source_width, source_height) = (size.0 as f32, size.1 as f32);
+        match self.fit {
+            Fit::Contain => {
+                let scale = (width / source_width).min(height / source_height);
+                item.scale = scale;
+                item.crop = Crop::default();
+                item.x = (x + (width - source_width * scale) / 2.0).round() as i32;
+                item.y = (y + (height - source_height * scale) / 2.0).round() as i32;
+            }
+            Fit::Cover => {
+                let scale = (width / source_width).max(height / source_height);
+                let cut_x = ((source_width - width / scale) / 2.0).max(0.0).round() as u32;
+                let cut_y = ((source_height - height / scale) / 2.0).max(0.0).round() as u32;
+                item.scale = scale;
+                item.crop = Crop { left: cut_x, top: cut_y, right: cut_x, bottom: cut_y };
+                item.x = x.round() as i32;
+                item.y = y.round() as i32;
+            }
+        }
+        item.shape = self.shape;
+    }
+}
+
+/// A template arranging a scene's items.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Layout {
+    pub name: String,
+    /// Later slots are drawn over earlier ones
+    pub slots: Vec<LayoutSlot>,
+}
+
+impl Layout {
+    /// Check that the layout can be saved.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("A layout needs a name".to_string());
+        }
+        for slot in &self.slots {
+            let in_canvas = |start: f32, size: f32| (0.0..1.0).contains(&start) && size > 0.0 && start + size <= 1.0 + 1e-3;
+            if !in_canvas(slot.x, slot.width) || !in_canvas(slot.y, slot.height) {
+                return Err("Layout slots must be within the canvas".to_string());
+            }
+            if slot.aspect.is_some_and(|aspect| !aspect.is_finite() || aspect <= 0.0) {
+                return Err("A slot's aspect ratio must be above zero".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Arrange `scene`'s items in the slots. `source_size` tells the size of
+    /// a source's pictures; items whose size isn't known, or that no slot
+    /// takes, stay where they are.
+    pub fn apply(&self, scene: &mut Scene, source_size: impl Fn(&SceneSource) -> Option<(u32, u32)>) {
+        let canvas = (scene.width, scene.height);
+        let mut placed = vec![false; scene.items.len()];
+        for (z_order, slot) in self.slots.iter().enumerate() {
+            let Some(i) = (0..scene.items.len()).find(|&i| !placed[i] && slot.role.takes(&scene.items[i].source)) else {
+                continue;
+            };
+            placed[i] = true;
+            let item = &mut scene.items[i];
+            let Some(size) = source_size(&item.source).filter(|&(w, h)| w > 0 && h > 0) else {
+                tracing::info!(target: "Layout", "Size of {:?} is unknown; leaving it in place", item.name);
+                continue;
+            };
+            slot.place(item, size, canvas);
+            item.z_order = z_order as i32;
+        }
+    }
+}
+
+/// Layouts that come with the app.
+fn builtin_layouts() -> Vec<Layout> {
+    let slot = |role, x, y, width, height, fit| LayoutSlot {
+        role,
+        x,
+        y,
+        width,
+        height,
+        fit,
+        shape: ItemShape::Rectangle,
+        aspect: None,
+    };
+    vec![
+        Layout {
+            name: "Side by Side".to_string(),
+            slots: vec![
+                slot(SlotRole::Screen, 0.0, 0.0, 0.5, 1.0, Fit::Contain),
+                slot(SlotRole::Camera, 0.5, 0.0, 0.5, 1.0, Fit::Cover),
+            ],
+        },
+        Layout {
+            name: "Webcam Circle".to_string(),
+            slots: vec![
+                slot(SlotRole::Screen, 0.0, 0.0, 1.0, 1.0, Fit::Contain),
+                LayoutSlot {
+                    shape: ItemShape::Circle,
+                    aspect: Some(1.0),
+                    ..slot(SlotRole::Camera, 0.72, 0.64, 0.25, 0.32, Fit::Cover)
+                },
+            ],
+        },
+        Layout {
+            name: "70/30 Split".to_string(),
+            slots: vec![
+                slot(SlotRole::Screen, 0.0, 0.0, 0.7, 1.0, Fit::Contain),
+                slot(SlotRole::Camera, 0.7, 0.0, 0.3, 1.0, Fit::Cover),
+            ],
+        },
+    ]
+}
+
+/// The built-in and saved layouts.
+pub struct Layouts {
+    saved: Vec<Layout>,
+}
+
+impl Layouts {
+    /// Load the saved layouts from disk.
+    pub fn load() -> Self {
+        Self {
+            saved: load_json(LAYOUTS_FILE),
+        }
+    }
+
+    /// All layouts, built-in ones first.
+    pub fn list(&self) -> Vec<Layout> {
+        builtin_layouts().into_iter().chain(self.saved.iter().cloned()).collect()
+    }
+
+    /// A layout by name.
+    pub fn get(&self, name: &str) -> Option<Layout> {
+        self.list().into_iter().find(|l| l.name == name)
+    }
+
+    /// Save a layout, replacing any saved layout with the same name.
+    pub fn upsert(&mut self, layout: Layout) -> Result<(), String> {
+        layout.validate()?;
+        if builtin_layouts().iter().any(|l| l.name == layout.name) {
+            return Err(format!("{:?} is a built-in layout", layout.name));
+        }
+        match self.saved.iter_mut().find(|l| l.name == layout.name) {
+            Some(existing) => *existing = layout,
+            None => self.saved.push(layout),
+        }
+        save_json(LAYOUTS_FILE, &self.saved)
+    }
+
+    /// Delete a saved layout by name.
+    pub fn delete(&mut self, name: &str) -> Result<(), String> {
+        let before = self.saved.len();
+        self.saved.retain(|l| l.name != name);
+        if self.saved.len() == before {
+            return Err(format!("Saved layout {:?} not found", name));
+        }
+        save_json(LAYOUTS_FILE, &self.saved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::CaptureTarget;
+
+    #[test]
+    fn test_apply_layout() {
+        let item = |name: &str, source| SceneItem {
+            name: name.to_string(),
+            source,
+            x: 0,
+            y: 0,
+            scale: 1.0,
+            crop: Crop::default(),
+            z_order: 0,
+            shape: ItemShape::Rectangle,
+            chroma_key: None,
+        };
+        let camera = SceneSource::Device { device_id: "cam".to_string(), width: 1280, height: 720 };
+        let screen = SceneSource::Capture {
+            target: CaptureTarget::Display { monitor_id: "1".to_string(), width: 2560, height: 1440 },
+        };
+        let mut scene = Scene {
+            name: "Demo".to_string(),
+            width: 1920,
+            height: 1080,
+            items: vec![item("Camera", camera), item("Screen", screen)],
+            hotkey: None,
+        };
+        for layout in builtin_layouts() {
+            assert!(layout.validate().is_ok(), "{}", layout.name);
+        }
+
+        let circle = builtin_layouts().remove(1);
+        circle.apply(&mut scene, SceneSource::size);
+        // The screen fills the canvas under the camera
+        let screen = &scene.items[1];
+        assert_eq!((screen.x, screen.y, screen.scale, screen.z_order), (0, 0, 0.75, 0));
+        // The camera is cropped square, in the bottom-right corner
+        let camera = &scene.items[0];
+        assert_eq!(camera.shape, ItemShape::Circle);
+        assert_eq!(camera.crop, Crop { left: 280, top: 0, right: 280, bottom: 0 });
+        assert_eq!((camera.x, camera.y, camera.z_order), (1517, 691, 1));
+        assert!((camera.scale - 0.48).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,388 @@
+//! Scenes: recordings composed of several sources.
+//!
+//! A scene lays out sources (a captured monitor, window or region, a webcam
+//! or capture card, a still image or slideshow, a web page, or an audio
+//! visualizer) on a canvas of its own size. Each item
+//! is cropped, scaled and placed on the canvas, and items are stacked by
+//! their z-order. While a scene is recorded every source runs on its own and
+//! the [`compositor`] draws their latest frames into one picture, which the
+//! encoder records like any other capture. Scenes are saved by name.
+//!
+//! While a scene is recorded, another scene with the same canvas size can be
+//! switched to, from a command or the scene's hotkey, cutting or
+//! crossfading to it. Sources the scenes share keep running across the switch.
+//! Webcams in front of a green screen can be [`chroma`] keyed, and the key
+//! adjusted while recording.
+
+pub mod browser;
+pub mod chroma;
+pub mod compositor;
+pub mod layout;
+pub mod slideshow;
+pub mod sources;
+pub mod visualizer;
+
+use crate::capture::desktop::DesktopLayout;
+use crate::capture::{list_window_bounds, CaptureTarget};
+use crate::config::{load_json, save_json};
+use crate::encoder::ffmpeg::probe;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use chroma::ChromaKey;
+use visualizer::VisualizerStyle;
+
+const SCENES_FILE: &str = "scenes.json";
+
+/// Largest canvas a scene can have, in either direction.
+const MAX_CANVAS_SIZE: u32 = 7680;
+
+/// Shortest time a slideshow can show each slide, in milliseconds.
+const MIN_SLIDE_INTERVAL_MS: u32 = 100;
+
+/// Longest crossfade that can be set, in milliseconds.
+const MAX_CROSSFADE_MS: u32 = 10_000;
+
+/// How the picture changes when switching scenes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Transition {
+    #[default]
+    Cut,
+    /// Fade from the old scene to the new one
+    Crossfade { duration_ms: u32 },
+}
+
+impl Transition {
+    /// Check that the transition can be used.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Transition::Crossfade { duration_ms } if *duration_ms > MAX_CROSSFADE_MS => {
+                Err(format!("Crossfades can be at most {} ms", MAX_CROSSFADE_MS))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// How far through the transition it is `elapsed` after it started,
+    /// from 0 to 1.
+    pub fn progress(&self, elapsed: std::time::Duration) -> f32 {
+        match self {
+            Transition::Crossfade { duration_ms } if *duration_ms > 0 => {
+                (elapsed.as_secs_f32() * 1000.0 / *duration_ms as f32).min(1.0)
+            }
+            _ => 1.0,
+        }
+    }
+}
+
+static TRANSITION: Mutex<Transition> = Mutex::new(Transition::Cut);
+
+/// Set the transition used when switching scenes without choosing one.
+pub fn set_default_transition(transition: Transition) -> Result<(), String> {
+    transition.validate()?;
+    *TRANSITION.lock().unwrap() = transition;
+    Ok(())
+}
+
+/// The transition used when switching scenes without choosing one.
+pub fn default_transition() -> Transition {
+    *TRANSITION.lock().unwrap()
+}
+
+/// Where a scene item's picture comes from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SceneSource {
+    /// A window, region or display, captured as in a normal recording
+    Capture { target: CaptureTarget },
+    /// A webcam or capture card by its [`VideoDevice`](crate::devices::VideoDevice)
+    /// ID, opened in one of its modes
+    Device { device_id: String, width: u32, height: u32 },
+    /// A still image file (PNG, JPEG, or SVG if FFmpeg has librsvg);
+    /// transparent areas show the items below
+    Image { path: PathBuf },
+    /// Still images shown in turn for `interval_ms` each, fitted to the size
+    /// of the first; without `repeat` the last one stays up
+    Slideshow {
+        paths: Vec<PathBuf>,
+        #[serde(default = "default_slide_interval_ms")]
+        interval_ms: u32,
+        #[serde(default)]
+        repeat: bool,
+    },
+    /// A web page rendered at `width`x`height` by a headless browser, over a
+    /// transparent background
+    Browser {
+        url: String,
+        #[serde(default = "default_browser_width")]
+        width: u32,
+        #[serde(default = "default_browser_height")]
+        height: u32,
+    },
+    /// The sound of the application owning a window, drawn at
+    /// `width`x`height` in `color` (RGB) over a transparent background
+    Visualizer {
+        window_handle: isize,
+        #[serde(default)]
+        style: VisualizerStyle,
+        #[serde(default = "default_visualizer_width")]
+        width: u32,
+        #[serde(default = "default_visualizer_height")]
+        height: u32,
+        #[serde(default = "default_visualizer_color")]
+        color: [u8; 3],
+    },
+}
+
+impl SceneSource {
+    /// Size of the source's pictures, if it can be told before it starts.
+    ///
+    /// May enumerate windows or run FFmpeg; call it from a blocking task.
+    pub fn size(&self) -> Option<(u32, u32)> {
+        match self {
+            SceneSource::Capture { target: CaptureTarget::Display { width, height, .. } } => Some((*width, *height)),
+            SceneSource::Capture { target: CaptureTarget::Region { region } } => {
+                let layout = DesktopLayout::current();
+                let region = region.to_physical(layout.monitor(&region.monitor_id)?);
+                Some((region.width, region.height))
+            }
+            SceneSource::Capture { target: CaptureTarget::Window { window_handle } } => list_window_bounds()
+                .ok()?
+                .into_iter()
+                .find(|(window, _)| window.handle == *window_handle)
+                .map(|(_, bounds)| (bounds.width, bounds.height)),
+            SceneSource::Device { width, height, .. } => Some((*width, *height)),
+            SceneSource::Image { path } => probe(path).ok().map(|info| (info.width, info.height)),
+            SceneSource::Slideshow { paths, .. } => probe(paths.first()?).ok().map(|info| (info.width, info.height)),
+            SceneSource::Browser { width, height, .. } | SceneSource::Visualizer { width, height, .. } => {
+                Some((*width, *height))
+            }
+        }
+    }
+}
+
+fn default_slide_interval_ms() -> u32 {
+    5000
+}
+
+fn default_browser_width() -> u32 {
+    1920
+}
+
+fn default_browser_height() -> u32 {
+    1080
+}
+
+fn default_visualizer_width() -> u32 {
+    640
+}
+
+fn default_visualizer_height() -> u32 {
+    160
+}
+
+fn default_visualizer_color() -> [u8; 3] {
+    [255, 255, 255]
+}
+
+/// Outline an item is drawn in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemShape {
+    #[default]
+    Rectangle,
+    /// The ellipse filling the item, such as a round webcam
+    Circle,
+}
+
+/// Pixels cut from each edge of a source before it's scaled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Crop {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+/// A source placed in a scene.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneItem {
+    pub name: String,
+    pub source: SceneSource,
+    /// Canvas position of the item's top-left corner; may be off the canvas
+    #[serde(default)]
+    pub x: i32,
+    #[serde(default)]
+    pub y: i32,
+    /// Size of the item relative to its cropped source
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+    #[serde(default)]
+    pub crop: Crop,
+    /// Items are drawn over those with a lower z-order, and over earlier
+    /// items with the same one
+    #[serde(default)]
+    pub z_order: i32,
+    #[serde(default)]
+    pub shape: ItemShape,
+    /// Makes a backdrop color transparent, for webcams in front of a green screen
+    #[serde(default)]
+    pub chroma_key: Option<ChromaKey>,
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+/// A layout of sources recorded as one picture.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scene {
+    pub name: String,
+    /// Canvas size, which is the size of the recording
+    pub width: u32,
+    pub height: u32,
+    pub items: Vec<SceneItem>,
+    /// Global shortcut switching to this scene while a scene is recorded,
+    /// such as "CommandOrControl+Shift+1"
+    #[serde(default)]
+    pub hotkey: Option<String>,
+}
+
+impl Scene {
+    /// Check that the scene can be saved.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("A scene needs a name".to_string());
+        }
+        if !(2..=MAX_CANVAS_SIZE).contains(&self.width) || !(2..=MAX_CANVAS_SIZE).contains(&self.height) {
+            return Err(format!("The canvas must be between 2 and {} pixels each way", MAX_CANVAS_SIZE));
+        }
+        for item in &self.items {
+            if !item.scale.is_finite() || item.scale <= 0.0 {
+                return Err(format!("{:?} needs a scale above zero", item.name));
+            }
+            if let Some(key) = item.chroma_key {
+                key.validate()?;
+            }
+            if let SceneSource::Slideshow { paths, interval_ms, .. } = &item.source {
+                if paths.is_empty() {
+                    return Err(format!("{:?} needs at least one image", item.name));
+                }
+                if *interval_ms < MIN_SLIDE_INTERVAL_MS {
+                    return Err(format!("{:?} must show each slide for at least {} ms", item.name, MIN_SLIDE_INTERVAL_MS));
+                }
+            }
+            if let SceneSource::Browser { url, width, height } = &item.source {
+                if !["http://", "https://", "file://"].iter().any(|scheme| url.starts_with(scheme)) {
+                    return Err(format!("{:?} needs an http, https or file URL", item.name));
+                }
+                if !(1..=MAX_CANVAS_SIZE).contains(width) || !(1..=MAX_CANVAS_SIZE).contains(height) {
+                    return Err(format!("{:?} must be between 1 and {} pixels each way", item.name, MAX_CANVAS_SIZE));
+                }
+            }
+            if let SceneSource::Visualizer { width, height, .. } = &item.source {
+                if !(1..=MAX_CANVAS_SIZE).contains(width) || !(1..=MAX_CANVAS_SIZE).contains(height) {
+                    return Err(format!("{:?} must be between 1 and {} pixels each way", item.name, MAX_CANVAS_SIZE));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Indexes of the items from the bottom of the stack to the top.
+    pub fn draw_order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.items.len()).collect();
+        // Stable, so items with the same z-order keep their list order
+        order.sort_by_key(|&i| self.items[i].z_order);
+        order
+    }
+
+    /// The first captured window, region or display, which the recording
+    /// is filed under in the library.
+    pub fn primary_target(&self) -> Option<&CaptureTarget> {
+        self.draw_order().into_iter().rev().find_map(|i| match &self.items[i].source {
+            SceneSource::Capture { target } => Some(target),
+            _ => None,
+        })
+    }
+}
+
+/// The saved scenes.
+pub struct Scenes {
+    scenes: Vec<Scene>,
+}
+
+impl Scenes {
+    /// Load the scenes from disk.
+    pub fn load() -> Self {
+        Self {
+            scenes: load_json(SCENES_FILE),
+        }
+    }
+
+    /// All scenes.
+    pub fn list(&self) -> Vec<Scene> {
+        self.scenes.clone()
+    }
+
+    /// A scene by name.
+    pub fn get(&self, name: &str) -> Option<&Scene> {
+        self.scenes.iter().find(|s| s.name == name)
+    }
+
+    /// Add a scene, replacing any existing scene with the same name.
+    pub fn upsert(&mut self, scene: Scene) -> Result<(), String> {
+        scene.validate()?;
+        match self.scenes.iter_mut().find(|s| s.name == scene.name) {
+            Some(existing) => *existing = scene,
+            None => self.scenes.push(scene),
+        }
+        save_json(SCENES_FILE, &self.scenes)
+    }
+
+    /// Delete a scene by name.
+    pub fn delete(&mut self, name: &str) -> Result<(), String> {
+        let before = self.scenes.len();
+        self.scenes.retain(|s| s.name != name);
+        if self.scenes.len() == before {
+            return Err(format!("Scene {:?} not found", name));
+        }
+        save_json(SCENES_FILE, &self.scenes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scene_from_json() {
+        let scene: Scene = serde_json::from_str(
+            r#"{
+                "name": "Tutorial",
+                "width": 1920,
+                "height": 1080,
+                "items": [
+                    {"name": "Webcam", "source": {"type": "device", "device_id": "/dev/video0", "width": 640, "height": 480},
+                     "x": 1500, "y": 780, "scale": 0.6, "z_order": 1},
+                    {"name": "Screen", "source": {"type": "capture", "target": {"type": "display", "monitor_id": "1", "width": 1920, "height": 1080}},
+                     "crop": {"top": 40}}
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert!(scene.validate().is_ok());
+        assert_eq!(scene.items[1].scale, 1.0);
+        assert_eq!(scene.items[1].crop, Crop { top: 40, ..Crop::default() });
+        // The screen is drawn first, under the webcam
+        assert_eq!(scene.draw_order(), [1, 0]);
+        assert!(matches!(scene.primary_target(), Some(CaptureTarget::Display { .. })));
+
+        let scene = Scene { width: 0, ..scene };
+        assert!(scene.validate().is_err());
+        assert!(Transition::Crossfade { duration_ms: 20_000 }.validate().is_err());
+    }
+}
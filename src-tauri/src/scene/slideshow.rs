@@ -0,0 +1,80 @@
+//! Slideshow sources: still images shown one after another.
+//!
+//! The slides are decoded when the source starts, each fitted to the size of
+//! the first so the item keeps its place on the canvas, and the compositor
+//! picks up the next one as its time comes.
+
+use crate::capture::CapturedFrame;
+use crate::encoder::image::{load_image, load_image_fitted};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// A running slideshow.
+pub struct Slideshow {
+    slides: Vec<CapturedFrame>,
+    interval: Duration,
+    repeat: bool,
+    started: Instant,
+    /// Index of the slide last handed out
+    shown: Option<usize>,
+}
+
+impl Slideshow {
+    /// Decode the slides. Blocks while FFmpeg decodes them.
+    pub fn load(paths: &[PathBuf], interval_ms: u32, repeat: bool) -> Result<Self, String> {
+        let (first, rest) = paths.split_first().ok_or("A slideshow needs at least one image")?;
+        let first = load_image(first)?;
+        let mut slides = Vec::with_capacity(paths.len());
+        for path in rest {
+            slides.push(load_image_fitted(path, first.width, first.height)?);
+        }
+        slides.insert(0, first);
+        Ok(Self {
+            slides,
+            interval: Duration::from_millis(interval_ms as u64),
+            repeat,
+            started: Instant::now(),
+            shown: None,
+        })
+    }
+
+    /// The slide to show now, if it's not the one last returned.
+    pub fn next_slide(&mut self) -> Option<&CapturedFrame> {
+        let index = slide_index(self.started.elapsed(), self.interval, self.slides.len(), self.repeat);
+        if self.shown == Some(index) {
+            return None;
+        }
+        self.shown = Some(index);
+        Some(&self.slides[index])
+    }
+}
+
+/// Which of `count` slides shows `elapsed` into the slideshow. Without
+/// `repeat` it stays on the last one.
+fn slide_index(elapsed: Duration, interval: Duration, count: usize, repeat: bool) -> usize {
+    if count <= 1 || interval.is_zero() {
+        return 0;
+    }
+    let step = (elapsed.as_millis() / interval.as_millis()) as usize;
+    if repeat {
+        step % count
+    } else {
+        step.min(count - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slide_index() {
+        let interval = Duration::from_secs(5);
+        let at = |secs: u64, repeat: bool| slide_index(Duration::from_secs(secs), interval, 3, repeat);
+        assert_eq!(at(0, true), 0);
+        assert_eq!(at(7, true), 1);
+        assert_eq!(at(16, true), 0);
+        assert_eq!(at(16, false), 2);
+        assert_eq!(slide_index(Duration::from_secs(60), interval, 1, true), 0);
+    }
+}
@@ -0,0 +1,212 @@
+//! Running a scene's sources while it's recorded.
+
+use super::browser::start_browser;
+use super::slideshow::Slideshow;
+use super::visualizer::start_visualizer;
+use super::SceneSource;
+use crate::capture::exclusion::WindowExclusions;
+use crate::capture::mask::FrameMask;
+use crate::capture::queue::frame_channel;
+use crate::capture::recovery::{start_recoverable_capture, CaptureEvent, RecoveryOptions};
+use crate::capture::redaction::Redactions;
+use crate::capture::{CaptureOptions, CapturedFrame, ColorSpace, FrameReceiver, PixelFormat, StopHandle};
+use crate::devices;
+use crate::encoder::image::load_image;
+use ffmpeg_sidecar::command::FfmpegCommand;
+use std::io::Read;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::broadcast;
+
+/// What running captures share with the recording.
+#[derive(Clone)]
+pub struct SourceContext {
+    pub exclusions: WindowExclusions,
+    pub redactions: Redactions,
+    pub recovery: RecoveryOptions,
+    pub events: broadcast::Sender<CaptureEvent>,
+}
+
+/// Identifies a source, so items and scenes showing the same one share it.
+pub fn source_key(source: &SceneSource) -> String {
+    serde_json::to_string(source).unwrap_or_default()
+}
+
+/// A scene source delivering pictures. Stops when dropped.
+pub struct RunningSource {
+    /// Frames from captures, devices, web pages and visualizers; images and
+    /// slideshows have none
+    frames: Option<FrameReceiver>,
+    stop_flag: Option<StopHandle>,
+    /// Hides excluded windows and redactions in captures
+    mask: Option<FrameMask>,
+    latest: Option<CapturedFrame>,
+    /// Slides, which replace the latest picture as their time comes
+    slideshow: Option<Slideshow>,
+}
+
+impl RunningSource {
+    /// Start delivering pictures from `source`.
+    pub fn start(source: &SceneSource, context: &SourceContext) -> Result<Self, String> {
+        match source {
+            SceneSource::Capture { target } => {
+                // Items are composed in 8-bit SDR
+                let (frames, stop_flag) = start_recoverable_capture(
+                    target.clone(),
+                    CaptureOptions::default(),
+                    context.recovery,
+                    context.events.clone(),
+                )
+                .map_err(|e| e.to_string())?;
+                Ok(Self {
+                    frames: Some(frames),
+                    stop_flag: Some(stop_flag),
+                    mask: FrameMask::start(target, &context.exclusions, &context.redactions),
+                    latest: None,
+                    slideshow: None,
+                })
+            }
+            SceneSource::Device { device_id, width, height } => {
+                let (frames, stop_flag) = start_device(device_id, *width, *height)?;
+                Ok(Self {
+                    frames: Some(frames),
+                    stop_flag: Some(stop_flag),
+                    mask: None,
+                    latest: None,
+                    slideshow: None,
+                })
+            }
+            SceneSource::Image { path } => Ok(Self {
+                frames: None,
+                stop_flag: None,
+                mask: None,
+                latest: Some(load_image(path)?),
+                slideshow: None,
+            }),
+            SceneSource::Slideshow { paths, interval_ms, repeat } => Ok(Self {
+                frames: None,
+                stop_flag: None,
+                mask: None,
+                latest: None,
+                slideshow: Some(Slideshow::load(paths, *interval_ms, *repeat)?),
+            }),
+            SceneSource::Browser { url, width, height } => {
+                let (frames, stop_flag) = start_browser(url, *width, *height)?;
+                Ok(Self {
+                    frames: Some(frames),
+                    stop_flag: Some(stop_flag),
+                    mask: None,
+                    latest: None,
+                    slideshow: None,
+                })
+            }
+            SceneSource::Visualizer { window_handle, style, width, height, color } => {
+                let (frames, stop_flag) = start_visualizer(*window_handle, *style, *width, *height, *color)?;
+                Ok(Self {
+                    frames: Some(frames),
+                    stop_flag: Some(stop_flag),
+                    mask: None,
+                    latest: None,
+                    slideshow: None,
+                })
+            }
+        }
+    }
+
+    /// Take the newest frame waiting, dropping older ones. Returns whether
+    /// there was one.
+    pub fn poll(&mut self) -> bool {
+        if let Some(slide) = self.slideshow.as_mut().and_then(Slideshow::next_slide) {
+            self.latest = Some(slide.clone());
+            return true;
+        }
+        let Some(frames) = self.frames.as_mut() else {
+            return false;
+        };
+        let mut newest = None;
+        while let Ok(frame) = frames.try_recv() {
+            newest = Some(frame);
+        }
+        let Some(mut frame) = newest else {
+            return false;
+        };
+        if let Some(ref mask) = self.mask {
+            mask.apply(&mut frame);
+        }
+        self.latest = Some(frame);
+        true
+    }
+
+    /// The latest picture, once there is one.
+    pub fn latest(&self) -> Option<&CapturedFrame> {
+        self.latest.as_ref()
+    }
+}
+
+impl Drop for RunningSource {
+    fn drop(&mut self) {
+        if let Some(ref stop_flag) = self.stop_flag {
+            stop_flag.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Read frames from a webcam or capture card through FFmpeg, scaled to
+/// `width`x`height`.
+fn start_device(id: &str, width: u32, height: u32) -> Result<(FrameReceiver, StopHandle), String> {
+    if width == 0 || height == 0 {
+        return Err(format!("Invalid dimensions: {}x{}", width, height));
+    }
+
+    let mut command = FfmpegCommand::new();
+    command
+        .args(devices::input_args(id, width, height))
+        // Devices may not deliver exactly the mode asked for
+        .args(["-vf", &format!("scale={}:{}", width, height)])
+        .args(["-f", "rawvideo"])
+        .args(["-pix_fmt", "bgra"])
+        .arg("-");
+
+    let inner_command = command.as_inner_mut();
+    inner_command.stdin(Stdio::null());
+    inner_command.stdout(Stdio::piped());
+    inner_command.stderr(Stdio::null());
+
+    let mut child = inner_command
+        .spawn()
+        .map_err(|e| format!("Failed to start FFmpeg for {}: {}", id, e))?;
+    let mut stdout = child.stdout.take().ok_or("Failed to get FFmpeg stdout")?;
+
+    let (frame_tx, frame_rx) = frame_channel(2);
+    let stop_flag: StopHandle = Arc::new(AtomicBool::new(false));
+    let stop = stop_flag.clone();
+    let id = id.to_string();
+    std::thread::spawn(move || {
+        let size = width as usize * height as usize * PixelFormat::Bgra8.bytes_per_pixel();
+        while !stop.load(Ordering::Relaxed) {
+            let mut data = vec![0; size];
+            if let Err(e) = stdout.read_exact(&mut data) {
+                tracing::warn!(target: "Scene", "Device {} stopped delivering frames: {}", id, e);
+                break;
+            }
+            let frame = CapturedFrame {
+                width,
+                height,
+                data,
+                format: PixelFormat::Bgra8,
+                color: ColorSpace::SRGB,
+                captured_at: Instant::now(),
+                presented_at: None,
+            };
+            if frame_tx.send(frame).is_err() {
+                break;
+            }
+        }
+        let _ = child.kill();
+        let _ = child.wait();
+    });
+
+    Ok((frame_rx, stop_flag))
+}
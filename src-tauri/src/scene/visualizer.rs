@@ -0,0 +1,241 @@
+//! Audio visualizer sources, drawn from a window's sound.
+//!
+//! The audio of the application owning the window is captured as it would
+//! be for a recording of that window, and its last few samples are drawn
+//! several times a second: as frequency bars, or as an oscilloscope trace
+//! of the waveform. Everything but the drawing is transparent, so the
+//! visualizer can sit over other items. The captured audio itself isn't
+//! recorded.
+
+use crate::avsync::SyncClock;
+use crate::capture::audio::{start_app_audio_capture, SAMPLE_RATE};
+use crate::capture::queue::frame_channel;
+use crate::capture::{CapturedFrame, ColorSpace, FrameReceiver, PixelFormat, StopHandle};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How often the visualizer is redrawn.
+const FRAME_INTERVAL: Duration = Duration::from_millis(33);
+
+/// Number of frequency bars.
+const BARS: usize = 32;
+
+/// Frequencies the bars span, spaced evenly in pitch.
+const LOWEST_HZ: f32 = 50.0;
+const HIGHEST_HZ: f32 = 12_000.0;
+
+/// Frequencies each bar's band is measured at.
+const PROBES_PER_BAR: usize = 4;
+
+/// Levels shown, from an empty bar to a full one, in dB.
+const FLOOR_DB: f32 = -60.0;
+
+/// How much of its height a bar can drop each frame, so it falls smoothly.
+const FALL_PER_FRAME: f32 = 0.06;
+
+/// How the audio is drawn.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VisualizerStyle {
+    /// Bars for the loudness of each frequency band, low to high
+    #[default]
+    Bars,
+    /// The waveform, as a line across the middle
+    Oscilloscope,
+}
+
+/// Draw the audio of the application owning `window_handle` in `style`, at
+/// `width`x`height` in `color`.
+pub fn start_visualizer(
+    window_handle: isize,
+    style: VisualizerStyle,
+    width: u32,
+    height: u32,
+    color: [u8; 3],
+) -> Result<(FrameReceiver, StopHandle), String> {
+    if width == 0 || height == 0 {
+        return Err(format!("Invalid dimensions: {}x{}", width, height));
+    }
+    let audio = start_app_audio_capture(window_handle, Arc::new(SyncClock::new())).map_err(|e| e.to_string())?;
+
+    let (frame_tx, frame_rx) = frame_channel(2);
+    let stop_flag: StopHandle = Arc::new(AtomicBool::new(false));
+    let stop = stop_flag.clone();
+    std::thread::spawn(move || {
+        let mut canvas = Canvas::new(width, height, color);
+        let mut bars = [0.0; BARS];
+        while !stop.load(Ordering::Relaxed) {
+            let samples = audio.recent_samples();
+            canvas.clear();
+            match style {
+                VisualizerStyle::Bars => {
+                    for (bar, level) in bars.iter_mut().zip(band_levels(&samples)) {
+                        *bar = level.max(*bar - FALL_PER_FRAME);
+                    }
+                    canvas.draw_bars(&bars);
+                }
+                VisualizerStyle::Oscilloscope => canvas.draw_waveform(&samples),
+            }
+            if frame_tx.send(canvas.frame()).is_err() {
+                break;
+            }
+            std::thread::sleep(FRAME_INTERVAL);
+        }
+        // Dropping the capture stops it and deletes its audio
+        drop(audio);
+    });
+
+    Ok((frame_rx, stop_flag))
+}
+
+/// Loudness of each bar's frequency band in `samples`, from 0 (at or below
+/// [`FLOOR_DB`]) to 1 (full scale).
+fn band_levels(samples: &[f32]) -> [f32; BARS] {
+    let mut levels = [0.0; BARS];
+    if samples.is_empty() {
+        return levels;
+    }
+    // Hann window, so the edges of the excerpt don't smear every band
+    let n = samples.len();
+    let windowed: Vec<f32> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, s)| s * 0.5 * (1.0 - (std::f32::consts::TAU * i as f32 / n as f32).cos()))
+        .collect();
+    // Each band is probed at a few frequencies across it, so a tone between
+    // two bars' centers still shows
+    let step = (HIGHEST_HZ / LOWEST_HZ).powf(1.0 / (BARS - 1) as f32);
+    for (bar, level) in levels.iter_mut().enumerate() {
+        let hz = LOWEST_HZ * step.powi(bar as i32);
+        let magnitude = (0..PROBES_PER_BAR)
+            .map(|probe| hz * step.powf((probe as f32 + 0.5) / PROBES_PER_BAR as f32 - 0.5))
+            .map(|probe_hz| goertzel(&windowed, probe_hz))
+            .fold(0.0, f32::max);
+        // A full-scale sine comes out at 0 dB (the window halves it)
+        let amplitude = magnitude * 4.0 / n as f32;
+        let db = 20.0 * amplitude.max(1e-6).log10();
+        *level = ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0);
+    }
+    levels
+}
+
+/// Magnitude of `samples` at `hz`, by the Goertzel algorithm.
+fn goertzel(samples: &[f32], hz: f32) -> f32 {
+    let coefficient = 2.0 * (std::f32::consts::TAU * hz / SAMPLE_RATE as f32).cos();
+    let (mut previous, mut before) = (0.0f32, 0.0f32);
+    for &sample in samples {
+        let current = sample + coefficient * previous - before;
+        before = previous;
+        previous = current;
+    }
+    (previous * previous + before * before - coefficient * previous * before).max(0.0).sqrt()
+}
+
+/// A transparent BGRA picture being drawn in one color.
+struct Canvas {
+    width: u32,
+    height: u32,
+    pixel: [u8; 4],
+    data: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32, [r, g, b]: [u8; 3]) -> Self {
+        Self {
+            width,
+            height,
+            pixel: [b, g, r, 255],
+            data: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.data.fill(0);
+    }
+
+    /// Fill the rows `top..bottom` of the columns `left..right`.
+    fn fill(&mut self, left: u32, right: u32, top: u32, bottom: u32) {
+        for y in top..bottom.min(self.height) {
+            let row = y as usize * self.width as usize;
+            for x in left..right.min(self.width) {
+                let offset = (row + x as usize) * 4;
+                self.data[offset..offset + 4].copy_from_slice(&self.pixel);
+            }
+        }
+    }
+
+    /// Bars rising from the bottom to `levels` of the height, with gaps
+    /// between them.
+    fn draw_bars(&mut self, levels: &[f32]) {
+        let slot = self.width as f32 / levels.len() as f32;
+        for (i, level) in levels.iter().enumerate() {
+            let left = (i as f32 * slot + slot * 0.1) as u32;
+            let right = ((i + 1) as f32 * slot - slot * 0.1).max(left as f32 + 1.0) as u32;
+            let top = self.height - (level * self.height as f32).round() as u32;
+            self.fill(left, right, top, self.height);
+        }
+    }
+
+    /// The waveform across the width, joining each column to the last.
+    fn draw_waveform(&mut self, samples: &[f32]) {
+        let middle = self.height as f32 / 2.0;
+        let bottom = self.height as f32 - 1.0;
+        let y_of = |sample: f32| (middle - sample.clamp(-1.0, 1.0) * middle).clamp(0.0, bottom) as u32;
+        let mut last = y_of(0.0);
+        for x in 0..self.width {
+            let sample = match samples.len() {
+                0 => 0.0,
+                len => samples[x as usize * len / self.width as usize],
+            };
+            let y = y_of(sample);
+            // Two pixels thick, so the line survives scaling down
+            self.fill(x, x + 1, last.min(y), last.max(y) + 2);
+            last = y;
+        }
+    }
+
+    fn frame(&self) -> CapturedFrame {
+        CapturedFrame {
+            width: self.width,
+            height: self.height,
+            data: self.data.clone(),
+            format: PixelFormat::Bgra8,
+            color: ColorSpace::SRGB,
+            captured_at: Instant::now(),
+            presented_at: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_visualizer() {
+        // A full-scale 1 kHz tone fills its band and little else
+        let tone: Vec<f32> = (0..2048)
+            .map(|i| (std::f32::consts::TAU * 1000.0 * i as f32 / SAMPLE_RATE as f32).sin())
+            .collect();
+        let levels = band_levels(&tone);
+        let loudest = levels.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)).unwrap().0;
+        let band_hz = LOWEST_HZ * (HIGHEST_HZ / LOWEST_HZ).powf(loudest as f32 / (BARS - 1) as f32);
+        assert!((800.0..1250.0).contains(&band_hz));
+        assert!(levels[loudest] > 0.9);
+        assert!(levels[BARS - 1] < 0.3);
+        assert_eq!(band_levels(&[0.0; 2048]), [0.0; BARS]);
+
+        // Silence is a flat line across the middle, drawn in the color
+        let mut canvas = Canvas::new(8, 4, [255, 0, 0]);
+        canvas.draw_waveform(&[]);
+        let row = |canvas: &Canvas, y: usize| canvas.data[y * 32..(y + 1) * 32].to_vec();
+        assert!(row(&canvas, 0).iter().all(|&b| b == 0));
+        assert_eq!(row(&canvas, 2)[..4], [0, 0, 255, 255]);
+        canvas.clear();
+        canvas.draw_bars(&[1.0, 0.0]);
+        assert_eq!(row(&canvas, 0)[4..8], [0, 0, 255, 255]);
+        assert!(row(&canvas, 3)[16..].iter().all(|&b| b == 0));
+    }
+}
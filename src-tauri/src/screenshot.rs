@@ -0,0 +1,71 @@
+//! One-shot screenshot capture.
+//!
+//! Reuses the recording capture backends: a capture is started for the
+//! requested target, the first frame is taken, and the capture is stopped.
+
+use crate::capture::{start_capture, CaptureTarget, CapturedFrame};
+use crate::encoder::image::{encode_image, generate_screenshot_path, ImageFormat};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+/// How long to wait for the backend to deliver a frame.
+///
+/// Generous because portal-based backends may need to set up a session first.
+const FRAME_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Result of a screenshot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScreenshotResult {
+    /// Path of the saved image, if saved to a file
+    pub file_path: Option<String>,
+    /// Whether the image was copied to the clipboard
+    pub copied_to_clipboard: bool,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Capture a single frame of the given target.
+pub async fn capture_frame(target: CaptureTarget) -> Result<CapturedFrame, String> {
+    let (mut frame_rx, stop_flag) = start_capture(target).map_err(|e| e.to_string())?;
+
+    let frame = tokio::time::timeout(FRAME_TIMEOUT, frame_rx.recv()).await;
+    stop_flag.store(true, Ordering::Relaxed);
+
+    match frame {
+        Ok(Some(frame)) => Ok(frame),
+        Ok(None) => Err("Capture ended before a frame was received".to_string()),
+        Err(_) => Err("Timed out waiting for a frame".to_string()),
+    }
+}
+
+/// Encode a captured frame to a new file in the Pictures folder.
+pub async fn save_frame(frame: CapturedFrame, format: ImageFormat) -> Result<PathBuf, String> {
+    let path = generate_screenshot_path(format)?;
+    tokio::task::spawn_blocking(move || {
+        encode_image(&frame, format, &path)?;
+        Ok(path)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Convert BGRA pixel data to RGBA (as expected by clipboard APIs).
+pub fn bgra_to_rgba(data: &[u8]) -> Vec<u8> {
+    let mut rgba = data.to_vec();
+    for pixel in rgba.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+    }
+    rgba
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bgra_to_rgba() {
+        assert_eq!(bgra_to_rgba(&[1, 2, 3, 4, 5, 6, 7, 8]), vec![3, 2, 1, 4, 7, 6, 5, 8]);
+    }
+}
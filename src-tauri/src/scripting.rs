@@ -0,0 +1,214 @@
+//! Lua scripts automating the recorder.
+//!
+//! Scripts are the `.lua` files in the `scripts` folder of the app's data
+//! directory, loaded in name order at startup. Each runs in a Lua state of
+//! its own, on its own thread, and may define hooks the app calls:
+//!
+//! - `on_start()` when a recording starts
+//! - `on_frame_stats(stats)` once a second while recording, with the same
+//!   statistics as the `get_recording_stats` command
+//! - `on_stop(phase)` when a recording is saved or fails, with the
+//!   `recording-phase` event's fields
+//!
+//! Scripts drive the app through the `recorder` table, which calls the same
+//! commands as the UI: `recorder.monitors()`, `recorder.start_display(id)`,
+//! `recorder.stop()`, `recorder.add_marker(label)`,
+//! `recorder.upload(path, target)` and `recorder.log(message)`. A hook that
+//! fails is logged; the script keeps getting events.
+//!
+//! Scripting is behind the `scripting` feature, which embeds Lua 5.4.
+
+use crate::capture::list_monitors;
+use crate::config::data_dir;
+use crate::state::RecordingPhase;
+use crate::stats::RecordingStats;
+use crate::AppState;
+use mlua::{Function, Lua, LuaSerdeExt, Value};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+const SCRIPTS_DIR: &str = "scripts";
+
+/// Something a script's hooks are told about.
+#[derive(Clone)]
+enum ScriptEvent {
+    Start,
+    FrameStats(RecordingStats),
+    Stop(RecordingPhase),
+}
+
+/// Load the scripts and pass them the recorder's events until the app exits.
+pub async fn run(app: AppHandle) {
+    let files = match data_dir().map(|dir| script_files(&dir.join(SCRIPTS_DIR))) {
+        Ok(files) => files,
+        Err(e) => {
+            tracing::error!(target: "Script", "{}", e);
+            return;
+        }
+    };
+    let mut scripts: Vec<_> = files.iter().filter_map(|path| start_script(&app, path)).collect();
+    if scripts.is_empty() {
+        return;
+    }
+
+    let manager = app.state::<AppState>().recording_manager.clone();
+    let mut phases = manager.lock().await.subscribe_phase_events();
+    let mut ticks = tokio::time::interval(Duration::from_secs(1));
+    let mut previous = RecordingPhase::Idle;
+    loop {
+        use tokio::sync::broadcast::error::RecvError;
+
+        let event = tokio::select! {
+            phase = phases.recv() => {
+                let phase = match phase {
+                    Ok(phase) => phase,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                };
+                match (std::mem::replace(&mut previous, phase.clone()), phase) {
+                    // Resuming from a pause enters the recording phase too
+                    (RecordingPhase::Preparing, RecordingPhase::Recording) => ScriptEvent::Start,
+                    (_, phase @ (RecordingPhase::Done { .. } | RecordingPhase::Failed { .. })) => ScriptEvent::Stop(phase),
+                    _ => continue,
+                }
+            }
+            _ = ticks.tick() => {
+                let manager = manager.lock().await;
+                if manager.get_phase() != RecordingPhase::Recording {
+                    continue;
+                }
+                match manager.get_stats().await {
+                    Ok(stats) => ScriptEvent::FrameStats(stats),
+                    Err(_) => continue,
+                }
+            }
+        };
+        // Scripts that failed to load have stopped listening
+        scripts.retain(|script| script.send(event.clone()).is_ok());
+    }
+}
+
+/// The scripts in `dir`, in name order.
+fn script_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "lua"))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Run the script at `path` on a thread of its own. Returns where to send
+/// it events.
+fn start_script(app: &AppHandle, path: &Path) -> Option<mpsc::Sender<ScriptEvent>> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| tracing::error!(target: "Script", "Failed to read {}: {}", path.display(), e))
+        .ok()?;
+    let name = path.file_name()?.to_string_lossy().to_string();
+    let app = app.clone();
+    let (events, received) = mpsc::channel();
+    std::thread::spawn(move || {
+        let lua = Lua::new();
+        let loaded = install_api(&lua, &app, &name).and_then(|()| lua.load(&source).set_name(name.as_str()).exec());
+        if let Err(e) = loaded {
+            tracing::error!(target: "Script", "{} failed to load: {}", name, e);
+            return;
+        }
+        tracing::info!(target: "Script", "Loaded {}", name);
+        for event in received {
+            if let Err(e) = dispatch(&lua, event) {
+                tracing::error!(target: "Script", "{}: {}", name, e);
+            }
+        }
+    });
+    Some(events)
+}
+
+/// Call the script's hook for `event`, if it defines one.
+fn dispatch(lua: &Lua, event: ScriptEvent) -> mlua::Result<()> {
+    let (hook, argument) = match event {
+        ScriptEvent::Start => ("on_start", Value::Nil),
+        ScriptEvent::FrameStats(stats) => ("on_frame_stats", lua.to_value(&stats)?),
+        ScriptEvent::Stop(phase) => ("on_stop", lua.to_value(&phase)?),
+    };
+    match lua.globals().get::<Option<Function>>(hook)? {
+        Some(hook) => hook.call::<()>(argument),
+        None => Ok(()),
+    }
+}
+
+/// Turn a command's error into a Lua error.
+fn lua_result<T>(result: Result<T, String>) -> mlua::Result<T> {
+    result.map_err(mlua::Error::RuntimeError)
+}
+
+/// Give the script the `recorder` table.
+fn install_api(lua: &Lua, app: &AppHandle, name: &str) -> mlua::Result<()> {
+    let recorder = lua.create_table()?;
+    recorder.set("monitors", lua.create_function(|lua, ()| lua.to_value(&list_monitors()))?)?;
+
+    // Commands are async; the script's thread waits for them
+    let handle = app.clone();
+    let start_display = lua.create_function(move |_, monitor_id: String| {
+        let app = handle.clone();
+        lua_result(tauri::async_runtime::block_on(async {
+            crate::start_display_recording(monitor_id, app.clone(), app.state()).await
+        }))
+    })?;
+    recorder.set("start_display", start_display)?;
+
+    let handle = app.clone();
+    let stop = lua.create_function(move |lua, ()| {
+        let result = tauri::async_runtime::block_on(crate::stop_recording(handle.state()));
+        lua.to_value(&lua_result(result)?)
+    })?;
+    recorder.set("stop", stop)?;
+
+    let handle = app.clone();
+    let add_marker = lua.create_function(move |lua, label: Option<String>| {
+        let marker = tauri::async_runtime::block_on(crate::add_marker(label, handle.state()));
+        lua.to_value(&lua_result(marker)?)
+    })?;
+    recorder.set("add_marker", add_marker)?;
+
+    let handle = app.clone();
+    let upload = lua.create_function(move |_, (file_path, target): (String, String)| {
+        let app = handle.clone();
+        lua_result(tauri::async_runtime::block_on(async {
+            crate::upload_recording(file_path, target, app.clone(), app.state()).await
+        }))
+    })?;
+    recorder.set("upload", upload)?;
+
+    let name = name.to_string();
+    let log = lua.create_function(move |_, message: String| {
+        tracing::info!(target: "Script", "{}: {}", name, message);
+        Ok(())
+    })?;
+    recorder.set("log", log)?;
+
+    lua.globals().set("recorder", recorder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script_files() {
+        let dir = std::env::temp_dir().join(format!("scripts-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for file in ["b.lua", "a.lua", "notes.txt"] {
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+        let files = script_files(&dir);
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(files, [dir.join("a.lua"), dir.join("b.lua")]);
+        assert!(script_files(&dir).is_empty());
+    }
+}
@@ -0,0 +1,276 @@
+//! Pausing recordings while the session is locked.
+//!
+//! The session is checked every second for a locked screen or a running
+//! screensaver. When one starts mid-recording, the recording is paused, or
+//! stopped and saved, so it doesn't fill up with the lock screen; a paused
+//! recording can resume once the session is unlocked. Each change is sent to
+//! the frontend as a `session-lock` event. Machines lock as they suspend by
+//! default, so this covers suspending too.
+//!
+//! Live outputs are switched to a standby picture while the session is
+//! locked, so viewers see "be right back" rather than the lock screen or a
+//! frozen frame, and switched back on unlock.
+
+use crate::encoder::standby;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+
+/// Event emitted with a [`SessionLockEvent`] when the session locks or unlocks.
+pub const SESSION_EVENT: &str = "session-lock";
+
+/// How often the session is checked.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What happens to a recording when the session locks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LockAction {
+    /// Keep recording
+    Ignore,
+    Pause,
+    /// Stop and save the recording
+    Stop,
+}
+
+/// What live outputs show while the session is locked.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamStandby {
+    /// Show the standby picture rather than the lock screen or a frozen frame
+    pub enabled: bool,
+    /// "Be right back" image, fitted to the stream; black without one
+    #[serde(default)]
+    pub image: Option<PathBuf>,
+}
+
+/// How recordings respond to the session locking.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockPolicy {
+    pub action: LockAction,
+    /// Resume recordings paused by the lock when the session is unlocked
+    pub resume_on_unlock: bool,
+    /// What live outputs (NDI, HLS) show while locked
+    #[serde(default = "default_stream_standby")]
+    pub stream_standby: StreamStandby,
+}
+
+impl LockPolicy {
+    const DEFAULT: LockPolicy = LockPolicy {
+        action: LockAction::Pause,
+        resume_on_unlock: true,
+        stream_standby: StreamStandby {
+            enabled: true,
+            image: None,
+        },
+    };
+}
+
+fn default_stream_standby() -> StreamStandby {
+    LockPolicy::DEFAULT.stream_standby
+}
+
+impl Default for LockPolicy {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// What was done to the recording when the session locked or unlocked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LockResponse {
+    Paused,
+    /// Paused; the frontend should stop the recording to save it
+    Stopping,
+    Resumed,
+}
+
+/// The session locking or unlocking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SessionLockEvent {
+    pub locked: bool,
+    /// `None` if the recording was left alone, or nothing was recording
+    pub response: Option<LockResponse>,
+}
+
+static POLICY: Mutex<LockPolicy> = Mutex::new(LockPolicy::DEFAULT);
+
+/// Set how recordings respond to the session locking.
+pub fn set_policy(policy: LockPolicy) {
+    *POLICY.lock().unwrap() = policy;
+}
+
+/// How recordings respond to the session locking.
+pub fn policy() -> LockPolicy {
+    POLICY.lock().unwrap().clone()
+}
+
+/// Whether the session is locked or the screensaver is running.
+///
+/// May run a command (on macOS and Linux); call it from a blocking task.
+pub fn is_locked() -> bool {
+    #[cfg(target_os = "windows")]
+    return windows::is_locked();
+    #[cfg(target_os = "linux")]
+    return linux::is_locked();
+    #[cfg(target_os = "macos")]
+    return macos::is_locked();
+    #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+    return false;
+}
+
+/// Check the session every second, pausing, stopping and resuming the
+/// recording by the policy as it locks and unlocks.
+///
+/// Runs for the lifetime of the app.
+pub async fn run_monitor(app: AppHandle) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    let mut locked = false;
+    // Whether the current pause was ours, so a pause the user chose isn't undone
+    let mut paused_by_lock = false;
+    loop {
+        interval.tick().await;
+
+        let Ok(now_locked) = tokio::task::spawn_blocking(is_locked).await else {
+            continue;
+        };
+        if now_locked == locked {
+            continue;
+        }
+        locked = now_locked;
+        tracing::info!(target: "Session", "Session {}", if locked { "locked" } else { "unlocked" });
+
+        let policy = policy();
+        let manager = app.state::<AppState>().recording_manager.clone();
+        let manager = manager.lock().await;
+        let recording = manager.get_phase().is_recording();
+        if locked && recording && policy.stream_standby.enabled {
+            standby::begin(policy.stream_standby.image.clone());
+        } else if !locked {
+            standby::end();
+        }
+        let response = if locked {
+            match policy.action {
+                _ if !recording => None,
+                LockAction::Ignore => None,
+                LockAction::Pause => {
+                    paused_by_lock = manager.pause_recording().await.is_ok();
+                    paused_by_lock.then_some(LockResponse::Paused)
+                }
+                LockAction::Stop => {
+                    // Nothing more is recorded while the frontend stops it
+                    let _ = manager.pause_recording().await;
+                    Some(LockResponse::Stopping)
+                }
+            }
+        } else if std::mem::take(&mut paused_by_lock) && policy.resume_on_unlock && recording {
+            manager.resume_recording().await.ok().map(|_| LockResponse::Resumed)
+        } else {
+            None
+        };
+
+        if let Err(e) = app.emit(SESSION_EVENT, SessionLockEvent { locked, response }) {
+            tracing::error!(target: "Session", "Failed to emit session lock: {}", e);
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod windows {
+    use windows::Win32::Foundation::BOOL;
+    use windows::Win32::System::StationsAndDesktops::{
+        CloseDesktop, OpenInputDesktop, DESKTOP_CONTROL_FLAGS, DESKTOP_SWITCHDESKTOP,
+    };
+    use windows::Win32::UI::WindowsAndMessaging::{
+        SystemParametersInfoW, SPI_GETSCREENSAVERRUNNING, SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS,
+    };
+
+    pub fn is_locked() -> bool {
+        // The input desktop can't be opened while the lock screen is showing
+        let locked = match unsafe { OpenInputDesktop(DESKTOP_CONTROL_FLAGS(0), BOOL(0), DESKTOP_SWITCHDESKTOP) } {
+            Ok(desktop) => {
+                let _ = unsafe { CloseDesktop(desktop) };
+                false
+            }
+            Err(_) => true,
+        };
+        let mut screensaver = BOOL(0);
+        let screensaver = unsafe {
+            SystemParametersInfoW(
+                SPI_GETSCREENSAVERRUNNING,
+                0,
+                Some(&mut screensaver as *mut BOOL as *mut std::ffi::c_void),
+                SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0),
+            )
+        }
+        .is_ok()
+            && screensaver.as_bool();
+        locked || screensaver
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::process::Command;
+
+    /// Whether logind says the session is locked. Desktop environments set
+    /// this when their lock screen or locking screensaver starts.
+    pub fn is_locked() -> bool {
+        Command::new("loginctl")
+            .args(["show-session", "auto", "-p", "LockedHint", "--value"])
+            .output()
+            .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).trim() == "yes")
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use super::parse_ioreg_locked;
+    use std::process::Command;
+
+    pub fn is_locked() -> bool {
+        let ioreg = Command::new("ioreg")
+            .args(["-n", "Root", "-d1"])
+            .output()
+            .map(|output| String::from_utf8_lossy(&output.stdout).to_string())
+            .unwrap_or_default();
+        let screensaver = Command::new("pgrep")
+            .args(["-x", "ScreenSaverEngine"])
+            .output()
+            .is_ok_and(|output| output.status.success());
+        parse_ioreg_locked(&ioreg) || screensaver
+    }
+}
+
+/// Whether `ioreg -n Root -d1` output shows the console session's screen locked.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn parse_ioreg_locked(output: &str) -> bool {
+    output
+        .lines()
+        .filter(|line| line.contains("IOConsoleUsers"))
+        .any(|line| line.contains("\"CGSSessionScreenIsLocked\"=Yes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ioreg_locked() {
+        let unlocked = r#"  |   "IOConsoleUsers" = ({"kCGSSessionOnConsoleKey"=Yes,"kCGSessionLoginDoneKey"=Yes,"kCGSSessionUserNameKey"="me"})"#;
+        let locked = r#"  |   "IOConsoleUsers" = ({"kCGSSessionOnConsoleKey"=Yes,"CGSSessionScreenIsLocked"=Yes,"kCGSSessionUserNameKey"="me"})"#;
+        assert!(!parse_ioreg_locked(unlocked));
+        assert!(parse_ioreg_locked(locked));
+    }
+
+    #[test]
+    fn test_lock_policy_from_json() {
+        // Policies from before stream standby turn it on
+        let policy: LockPolicy = serde_json::from_str(r#"{"action": "ignore", "resume_on_unlock": false}"#).unwrap();
+        assert_eq!(policy.action, LockAction::Ignore);
+        assert_eq!(policy.stream_standby, StreamStandby { enabled: true, image: None });
+    }
+}
@@ -0,0 +1,142 @@
+//! Orderly shutdown when the app quits.
+//!
+//! Quitting mid-recording would otherwise leave the recording to be cut off
+//! by the process exiting, with no trailer or index written, the portal
+//! still casting and the picker socket left behind. Instead, quitting is
+//! held off while [`run`] takes things down in order:
+//!
+//! 1. The recording is stopped: capture stops, the encoder works through
+//!    the frames queued for it, and the files are finalized
+//! 2. Portal sessions are closed (Linux)
+//! 3. The app's [`CancellationToken`] is cancelled, stopping the background
+//!    tasks that watch it, and the IPC server is torn down
+//!
+//! Each step has a time limit, so one that hangs can't keep the app open.
+
+use crate::AppState;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tokio::sync::watch;
+
+/// Longest to wait for the recording's files to be finalized; writing out a
+/// large memory buffer takes a while.
+const FINALIZE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Longest to wait for portal sessions and the IPC server to close.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Tells tasks to stop. Clones share their cancellation.
+#[derive(Clone)]
+pub struct CancellationToken {
+    cancelled: Arc<watch::Sender<bool>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self {
+            cancelled: Arc::new(watch::channel(false).0),
+        }
+    }
+
+    /// Tell everything waiting on the token to stop.
+    pub fn cancel(&self) {
+        self.cancelled.send_replace(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancelled.borrow()
+    }
+
+    /// Wait until the token is cancelled.
+    pub async fn cancelled(&self) {
+        let mut cancelled = self.cancelled.subscribe();
+        // The sender lives as long as the token, so this only ends on cancellation
+        let _ = cancelled.wait_for(|cancelled| *cancelled).await;
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The app's token, cancelled once it's shutting down.
+pub fn token() -> &'static CancellationToken {
+    static TOKEN: OnceLock<CancellationToken> = OnceLock::new();
+    TOKEN.get_or_init(CancellationToken::new)
+}
+
+/// Whether [`run`] has been started.
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Start shutting down, unless it already has been. Returns whether this
+/// call started it.
+pub fn begin() -> bool {
+    !STARTED.swap(true, Ordering::SeqCst)
+}
+
+/// Whether shutdown has finished and the app can exit.
+pub fn finished() -> bool {
+    token().is_cancelled()
+}
+
+/// Take the app's subsystems down in order, ending with the token cancelled.
+pub async fn run(app: &AppHandle) {
+    tracing::info!(target: "Shutdown", "Shutting down");
+
+    let manager = app.state::<AppState>().recording_manager.clone();
+    let stopped = tokio::time::timeout(FINALIZE_TIMEOUT, async {
+        let manager = manager.lock().await;
+        manager.preview().stop_capture();
+        if manager.get_phase().is_recording() {
+            manager.stop_recording().await.map(Some)
+        } else {
+            Ok(None)
+        }
+    })
+    .await;
+    match stopped {
+        Ok(Ok(Some(result))) => tracing::info!(target: "Shutdown", "Recording saved to {:?}", result.file_path),
+        Ok(Ok(None)) => {}
+        Ok(Err(e)) => tracing::error!(target: "Shutdown", "Failed to stop the recording: {}", e),
+        Err(_) => tracing::warn!(target: "Shutdown", "Gave up waiting for the recording to finish"),
+    }
+
+    #[cfg(target_os = "linux")]
+    if tokio::time::timeout(CLOSE_TIMEOUT, crate::capture::linux::close_sessions()).await.is_err() {
+        tracing::warn!(target: "Shutdown", "Gave up waiting for portal sessions to close");
+    }
+
+    token().cancel();
+    #[cfg(target_os = "linux")]
+    if tokio::time::timeout(CLOSE_TIMEOUT, crate::capture::linux::stop_ipc_server()).await.is_err() {
+        tracing::warn!(target: "Shutdown", "Gave up waiting for the IPC server to stop");
+    }
+    tracing::info!(target: "Shutdown", "Done");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cancellation_token() {
+        let token = CancellationToken::new();
+        let waiter = tokio::spawn({
+            let token = token.clone();
+            async move { token.cancelled().await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        token.cancel();
+        tokio::time::timeout(Duration::from_secs(1), waiter).await.unwrap().unwrap();
+        assert!(token.is_cancelled());
+        // Waiting after cancellation returns at once
+        token.cancelled().await;
+    }
+}
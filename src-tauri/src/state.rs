@@ -1,24 +1,119 @@
 //! Recording state management.
+//!
+//! A recording goes through the phases of [`RecordingPhase`], and each change
+//! of phase is broadcast (and sent to the frontend as a `recording-phase`
+//! event) so listeners follow the backend rather than guessing from the
+//! commands they sent. Changes the state machine doesn't allow are refused,
+//! which also keeps a second recording from starting while one is prepared.
 
-use crate::capture::{get_backend, CaptureBackend, CapturedFrame, CaptureRegion};
-use crate::encoder::encode_frames;
+use crate::avsync::{self, AudioSync, SyncClock};
+use crate::capture::audio::{
+    start_app_audio_capture, start_microphone_capture, wav_duration, AudioLevel, AudioRecording, AudioSource,
+};
+use crate::capture::exclusion::WindowExclusions;
+use crate::capture::mask::FrameMask;
+use crate::capture::recovery::{start_recoverable_capture, CaptureEvent};
+use crate::capture::redaction::Redactions;
+use crate::capture::refresh;
+use crate::capture::{CaptureRegion, CaptureTarget, FrameReceiver};
+use crate::display_mode;
+use crate::disk::{self, DiskSpaceEvent, DiskSpaceSettings};
+use crate::dynamics;
+use crate::encoder::filter::FilterChain;
+use crate::encoder::{encode_frames, EncoderSettings, RecordedFile, RecordingHandle};
+use crate::encoder::ffmpeg::probe;
+use crate::encoder::generate_output_path;
+use crate::encoder::hls::HlsSettings;
+use crate::encoder::ndi::{self, NdiSettings};
+use crate::encoder::replay::{ReplayBuffer, ReplaySettings, ReplaySink};
+use crate::encoder::sink::{FrameSink, OutputSettings};
+use crate::encoder::still::StillGrabber;
+use crate::encryption;
+use crate::environment;
+use crate::error::AppError;
+use crate::gpu::GpuPlacement;
+use crate::library::{Library, Marker, RecordingEntry};
+use crate::limits::{self, LimitReached, RecordingLimits, StopAt, StopPoint};
+use crate::permissions::{self, Permission};
+use crate::power::{self, Degradation};
+use crate::postprocess::echo;
+use crate::postprocess::mix::{self, Track};
+use crate::postprocess::{chapters, concat, loudness, mux, thumbnail};
+use crate::preview::Preview;
+use crate::scene::chroma::ChromaKey;
+use crate::scene::compositor::{start_compositor, SceneSwitcher};
+use crate::scene::sources::SourceContext;
+use crate::scene::{Scene, Transition};
+use crate::stats::{RecordingStats, StatsCollector};
+use crate::tags::{self, RecordingContext, TagSettings};
+use crate::webhooks::{self, WebhookEvent, WebhookPayload};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Instant;
-use tokio::sync::{Mutex, RwLock};
+use tokio::sync::{broadcast, Mutex};
 
-/// Recording state enum.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
-pub enum RecordingState {
-    /// Not recording, ready to start
+/// A phase of the recording lifecycle, with what listeners need to know
+/// about it.
+///
+/// Recordings go Preparing → Recording ⇄ Paused → Finalizing → Done or
+/// Failed, and start over from Idle, Done or Failed. Preparing fails if
+/// the recording can't start.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "phase", rename_all = "lowercase")]
+pub enum RecordingPhase {
+    /// No recording has been made yet
     Idle,
-    /// Currently recording
+    /// Starting the capture and encoder
+    Preparing,
     Recording,
-    /// Finalizing the recording (encoding remaining frames, writing file)
-    Saving,
+    Paused,
+    /// Encoding the remaining frames and adding audio, chapters and the
+    /// library entry
+    Finalizing,
+    /// The recording was saved
+    Done { file_path: Option<String>, segments: Vec<String> },
+    /// The recording couldn't start or wasn't saved
+    Failed { error: String },
+}
+
+impl RecordingPhase {
+    /// Whether a recording can go from `from` to this phase.
+    fn follows(&self, from: &RecordingPhase) -> bool {
+        use RecordingPhase::*;
+        matches!(
+            (from, self),
+            (Idle | Done { .. } | Failed { .. }, Preparing)
+                | (Preparing, Recording | Failed { .. })
+                | (Recording, Paused | Finalizing)
+                | (Paused, Recording | Finalizing)
+                | (Finalizing, Done { .. } | Failed { .. })
+        )
+    }
+
+    /// Whether a recording is running, paused or not.
+    pub fn is_recording(&self) -> bool {
+        matches!(self, RecordingPhase::Recording | RecordingPhase::Paused)
+    }
+
+    /// Whether a new recording can start.
+    pub fn is_idle(&self) -> bool {
+        matches!(self, RecordingPhase::Idle | RecordingPhase::Done { .. } | RecordingPhase::Failed { .. })
+    }
+
+    /// Short name of the phase, as in its `phase` tag.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RecordingPhase::Idle => "idle",
+            RecordingPhase::Preparing => "preparing",
+            RecordingPhase::Recording => "recording",
+            RecordingPhase::Paused => "paused",
+            RecordingPhase::Finalizing => "finalizing",
+            RecordingPhase::Done { .. } => "done",
+            RecordingPhase::Failed { .. } => "failed",
+        }
+    }
 }
 
 /// Result of a completed recording.
@@ -26,78 +121,355 @@ pub enum RecordingState {
 pub struct RecordingResult {
     pub success: bool,
     pub file_path: Option<String>,
+    /// Files the recording continued in after the capture changed resolution
+    pub segments: Vec<String>,
+    /// Every file written, in order, with its size and length
+    pub files: Vec<RecordedFile>,
     pub error: Option<String>,
+    /// What the recording is missing though it was saved, such as its audio
+    #[serde(default)]
+    pub warnings: Vec<String>,
 }
 
 /// Global recording state manager.
 pub struct RecordingManager {
-    state: RwLock<RecordingState>,
-    stop_flag: Mutex<Option<Arc<AtomicBool>>>,
+    /// Where the recording is in its lifecycle
+    phase: std::sync::Mutex<RecordingPhase>,
+    /// Changes of phase
+    phase_events: broadcast::Sender<RecordingPhase>,
     recording_start: Mutex<Option<Instant>>,
-    encoding_task: Mutex<Option<tokio::task::JoinHandle<Result<PathBuf, String>>>>,
+    /// The recording being encoded
+    recording: Mutex<Option<RecordingHandle>>,
+    output_settings: Mutex<OutputSettings>,
+    encoder_settings: Mutex<EncoderSettings>,
+    /// What the current recording is capturing
+    current_target: Mutex<Option<CaptureTarget>>,
+    /// Switches the current recording's scene, if it's recording one
+    scene_switcher: Mutex<Option<SceneSwitcher>>,
+    /// Markers placed during the current recording
+    markers: Mutex<Vec<Marker>>,
+    library: Arc<Mutex<Library>>,
+    /// Library recording the next recording is appended to
+    continue_into: Mutex<Option<u64>>,
+    /// Live statistics for the current recording
+    stats: Mutex<Option<Arc<StatsCollector>>>,
+    preview: Arc<Preview>,
+    /// Grabs stills from the current recording
+    stills: StillGrabber,
+    /// Audio captured alongside the current recording
+    audio: Mutex<Vec<AudioRecording>>,
+    /// The clock the current recording's streams are timed against
+    clock: Mutex<Arc<SyncClock>>,
+    /// Replay buffer kept alongside the current recording
+    replay: Mutex<Option<Arc<ReplayBuffer>>>,
+    /// Windows hidden in display and region recordings
+    exclusions: WindowExclusions,
+    /// Regions hidden in every recording
+    redactions: Redactions,
+    /// What happens to the current recording's capture
+    capture_events: broadcast::Sender<CaptureEvent>,
+    /// How recordings were made cheaper by the power policy
+    quality_events: broadcast::Sender<Degradation>,
+    /// When recordings warn or stop as the disk fills up
+    disk_settings: Mutex<DiskSpaceSettings>,
+    /// Free space on the current recording's disk crossing a threshold
+    disk_events: broadcast::Sender<DiskSpaceEvent>,
+    /// Duration and size limits applied to each recording
+    limits: Mutex<RecordingLimits>,
+    /// The current recording reaching one of its limits
+    limit_events: broadcast::Sender<LimitReached>,
+    /// Where the current recording stops on an exact frame, if scheduled
+    stop_at: Arc<StopAt>,
 }
 
 impl RecordingManager {
     /// Create a new recording manager.
     pub fn new() -> Self {
+        let limit_events = broadcast::channel(16).0;
         Self {
-            state: RwLock::new(RecordingState::Idle),
-            stop_flag: Mutex::new(None),
+            phase: std::sync::Mutex::new(RecordingPhase::Idle),
+            phase_events: broadcast::channel(16).0,
             recording_start: Mutex::new(None),
-            encoding_task: Mutex::new(None),
+            recording: Mutex::new(None),
+            output_settings: Mutex::new(OutputSettings::default()),
+            encoder_settings: Mutex::new(EncoderSettings::default()),
+            current_target: Mutex::new(None),
+            scene_switcher: Mutex::new(None),
+            markers: Mutex::new(Vec::new()),
+            library: Arc::new(Mutex::new(Library::load())),
+            continue_into: Mutex::new(None),
+            stats: Mutex::new(None),
+            preview: Arc::new(Preview::new()),
+            stills: StillGrabber::new(),
+            audio: Mutex::new(Vec::new()),
+            clock: Mutex::new(Arc::new(SyncClock::new())),
+            replay: Mutex::new(None),
+            exclusions: WindowExclusions::load(),
+            redactions: Redactions::load(),
+            capture_events: broadcast::channel(16).0,
+            quality_events: broadcast::channel(16).0,
+            disk_settings: Mutex::new(DiskSpaceSettings::default()),
+            disk_events: broadcast::channel(16).0,
+            limits: Mutex::new(RecordingLimits::default()),
+            stop_at: Arc::new(StopAt::new(limit_events.clone())),
+            limit_events,
+        }
+    }
+
+    /// Use `settings` for the video encoder instead of the defaults.
+    pub fn with_encoder_settings(mut self, settings: EncoderSettings) -> Self {
+        self.encoder_settings = Mutex::new(settings);
+        self
+    }
+
+    /// Where the recording is in its lifecycle.
+    pub fn get_phase(&self) -> RecordingPhase {
+        self.phase.lock().unwrap().clone()
+    }
+
+    /// Receive each change of the recording's phase.
+    pub fn subscribe_phase_events(&self) -> broadcast::Receiver<RecordingPhase> {
+        self.phase_events.subscribe()
+    }
+
+    /// Move the recording to `phase`, if the state machine allows it.
+    fn transition(&self, phase: RecordingPhase) -> Result<(), String> {
+        let mut current = self.phase.lock().unwrap();
+        if !phase.follows(&current) {
+            return Err(format!("A {} recording can't become {}", current.name(), phase.name()));
         }
+        *current = phase.clone();
+        let _ = self.phase_events.send(phase);
+        Ok(())
     }
 
-    /// Get the current recording state.
-    pub async fn get_state(&self) -> RecordingState {
-        *self.state.read().await
+    /// Enter the recording phase once a recording has been prepared, or fail
+    /// with the error that kept it from starting.
+    async fn finish_preparing(&self, started: Result<(), AppError>) -> Result<(), AppError> {
+        let source = self.current_target.lock().await.clone();
+        match &started {
+            Ok(()) => {
+                self.transition(RecordingPhase::Recording)?;
+                webhooks::fire(WebhookPayload { source, ..WebhookPayload::new(WebhookEvent::RecordingStarted) });
+            }
+            Err(error) => {
+                self.transition(RecordingPhase::Failed { error: error.to_string() })?;
+                webhooks::fire(WebhookPayload {
+                    source,
+                    error: Some(error.to_string()),
+                    ..WebhookPayload::new(WebhookEvent::RecordingFailed)
+                });
+            }
+        }
+        started
     }
 
     /// Get elapsed recording time in seconds.
     pub async fn get_elapsed_seconds(&self) -> u64 {
         let start = self.recording_start.lock().await;
         match *start {
-            Some(instant) => instant.elapsed().as_secs(),
+            Some(_) => self.clock.lock().await.video_time().as_secs(),
             None => 0,
         }
     }
 
-    /// Start recording the specified window.
-    pub async fn start_recording(&self, window_handle: isize) -> Result<(), String> {
-        // Check current state
-        {
-            let state = self.state.read().await;
-            if *state != RecordingState::Idle {
-                return Err("Already recording or saving".to_string());
-            }
+    /// Get the settings for additional recording outputs.
+    pub async fn get_output_settings(&self) -> OutputSettings {
+        self.output_settings.lock().await.clone()
+    }
+
+    /// Update the NDI output settings used by subsequent recordings.
+    pub async fn set_ndi_settings(&self, settings: NdiSettings) -> Result<(), String> {
+        if settings.enabled {
+            // Fail early if the NDI runtime isn't installed
+            ndi::check_runtime()?;
         }
+        self.output_settings.lock().await.ndi = settings;
+        Ok(())
+    }
 
-        // Start capture using platform backend
-        let backend = get_backend();
-        let (frame_rx, stop_flag) = backend
-            .start_window_capture(window_handle)
-            .map_err(|e| e.to_string())?;
+    /// Update the HLS output settings used by subsequent recordings.
+    pub async fn set_hls_settings(&self, settings: HlsSettings) -> Result<(), String> {
+        if settings.enabled && settings.port == 0 {
+            return Err("HLS port must be non-zero".to_string());
+        }
+        if settings.lan && settings.token.is_empty() {
+            return Err("Serving HLS to the LAN needs a token".to_string());
+        }
+        if settings.adaptive.enabled {
+            settings.adaptive.validate()?;
+        }
+        self.output_settings.lock().await.hls = settings;
+        Ok(())
+    }
 
-        self.start_encoding(frame_rx, stop_flag).await
+    /// Update the replay buffer settings used by subsequent recordings.
+    pub async fn set_replay_settings(&self, settings: ReplaySettings) -> Result<(), String> {
+        if settings.enabled && settings.duration_secs == 0 {
+            return Err("Replay duration must be greater than zero".to_string());
+        }
+        self.output_settings.lock().await.replay = settings;
+        Ok(())
     }
 
-    /// Start recording a screen region.
-    pub async fn start_region_recording(&self, region: CaptureRegion) -> Result<(), String> {
-        // Check current state
-        {
-            let state = self.state.read().await;
-            if *state != RecordingState::Idle {
-                return Err("Already recording or saving".to_string());
+    /// Get the video encoder settings.
+    pub async fn get_encoder_settings(&self) -> EncoderSettings {
+        self.encoder_settings.lock().await.clone()
+    }
+
+    /// Update the video encoder settings used by subsequent recordings.
+    pub async fn set_encoder_settings(&self, settings: EncoderSettings) {
+        *self.encoder_settings.lock().await = settings;
+    }
+
+    /// Get the free disk space thresholds.
+    pub async fn get_disk_space_settings(&self) -> DiskSpaceSettings {
+        self.disk_settings.lock().await.clone()
+    }
+
+    /// Set the free disk space thresholds used by subsequent recordings.
+    pub async fn set_disk_space_settings(&self, settings: DiskSpaceSettings) {
+        *self.disk_settings.lock().await = settings;
+    }
+
+    /// Get the duration and size limits.
+    pub async fn get_limits(&self) -> RecordingLimits {
+        self.limits.lock().await.clone()
+    }
+
+    /// Set the duration and size limits of subsequent recordings.
+    pub async fn set_limits(&self, limits: RecordingLimits) -> Result<(), String> {
+        limits.validate()?;
+        *self.limits.lock().await = limits;
+        Ok(())
+    }
+
+    /// Encoder settings for a new recording of `target` (a scene if
+    /// `None`), at its display's refresh rate if matched and made cheaper if
+    /// the power policy calls for it.
+    async fn recording_settings(&self, target: Option<&CaptureTarget>) -> EncoderSettings {
+        let mut settings = self.get_encoder_settings().await;
+        if settings.match_refresh_rate {
+            let fps = match target.cloned() {
+                Some(target) => tokio::task::spawn_blocking(move || refresh::target_fps(&target)).await.ok().flatten(),
+                None => None,
+            };
+            match fps {
+                Some(fps) => {
+                    tracing::info!(target: "Recording", "Matching the display's refresh rate: {} fps", fps);
+                    settings.fps = fps;
+                }
+                None => {
+                    tracing::info!(target: "Recording", "Refresh rate unknown; recording at {} fps", settings.fps);
+                    settings.match_refresh_rate = false;
+                }
+            }
+        }
+        let policy = power::policy();
+        if policy.enabled {
+            let state = tokio::task::spawn_blocking(power::current).await.unwrap_or_default();
+            if let Some(degradation) = policy.apply(state, &mut settings) {
+                tracing::info!(target: "Power", "Degrading recording ({:?}): {}", degradation.reasons, degradation.changes.join(", "));
+                let _ = self.quality_events.send(degradation);
             }
         }
+        settings
+    }
+
+    /// Create the additional output sinks for a new recording with `settings`.
+    ///
+    /// The replay buffer and HLS stream keep unencrypted video in temporary
+    /// files, so encrypted recordings can't have them.
+    async fn create_sinks(&self, settings: &EncoderSettings) -> Result<Vec<Box<dyn FrameSink>>, String> {
+        let output_settings = self.output_settings.lock().await.clone();
+        if settings.encrypt && (output_settings.replay.enabled || output_settings.hls.enabled) {
+            return Err(
+                "Encrypted recordings can't keep a replay buffer or serve an HLS stream; turn those off or record unencrypted"
+                    .to_string(),
+            );
+        }
+        let fps = settings.fps;
+        let mut sinks = output_settings.create_sinks(fps)?;
+        sinks.extend(self.preview.sink());
+        sinks.push(self.stills.sink());
 
-        // Start region capture using platform backend
-        let backend = get_backend();
-        let (frame_rx, stop_flag) = backend
-            .start_region_capture(region)
-            .map_err(|e| e.to_string())?;
+        let mut replay = self.replay.lock().await;
+        *replay = None;
+        if output_settings.replay.enabled {
+            let sink = ReplaySink::new(&output_settings.replay, fps)?;
+            *replay = Some(sink.buffer());
+            sinks.push(Box::new(sink));
+        }
+        Ok(sinks)
+    }
+
+    /// Get the live preview.
+    pub fn preview(&self) -> Arc<Preview> {
+        self.preview.clone()
+    }
 
-        self.start_encoding(frame_rx, stop_flag).await
+    /// Get the window exclusion list.
+    pub fn exclusions(&self) -> WindowExclusions {
+        self.exclusions.clone()
+    }
+
+    /// Get the saved redaction regions.
+    pub fn redactions(&self) -> Redactions {
+        self.redactions.clone()
+    }
+
+    /// Stop the current recording on the exact frame of `point`, replacing
+    /// any stop point scheduled before. Reaching it sends a limit event.
+    pub async fn schedule_stop(&self, point: StopPoint) -> Result<(), String> {
+        if !self.get_phase().is_recording() {
+            return Err("Not currently recording".to_string());
+        }
+        self.stop_at.schedule(point)?;
+        tracing::info!(target: "Recording", "Stopping at {:?}", point);
+        Ok(())
+    }
+
+    /// Remove the current recording's stop point.
+    pub fn cancel_scheduled_stop(&self) {
+        self.stop_at.cancel();
+    }
+
+    /// Receive an event when the current recording reaches its duration or
+    /// size limit, or its stop point. The recording should then be stopped
+    /// to finish the file.
+    pub fn subscribe_limit_events(&self) -> broadcast::Receiver<LimitReached> {
+        self.limit_events.subscribe()
+    }
+
+    /// Receive warnings as the current recording's disk fills up. When an
+    /// event is `stopping`, the recording should be stopped to finish the file.
+    pub fn subscribe_disk_events(&self) -> broadcast::Receiver<DiskSpaceEvent> {
+        self.disk_events.subscribe()
+    }
+
+    /// Receive the changes the power policy makes to recordings as they start.
+    pub fn subscribe_quality_events(&self) -> broadcast::Receiver<Degradation> {
+        self.quality_events.subscribe()
+    }
+
+    /// Receive events about recordings' captures, such as the captured window
+    /// closing. On [`CaptureEvent::Ended`] the recording should be stopped.
+    pub fn subscribe_capture_events(&self) -> broadcast::Receiver<CaptureEvent> {
+        self.capture_events.subscribe()
+    }
+
+    /// Start recording the specified window.
+    ///
+    /// With `app_audio`, the audio of the window's application is recorded too.
+    pub async fn start_recording(&self, window_handle: isize, app_audio: bool) -> Result<(), AppError> {
+        self.start_target_recording(CaptureTarget::Window { window_handle }, app_audio)
+            .await
+    }
+
+    /// Start recording a screen region.
+    pub async fn start_region_recording(&self, region: CaptureRegion) -> Result<(), AppError> {
+        self.start_target_recording(CaptureTarget::Region { region }, false)
+            .await
     }
 
     /// Start recording an entire display.
@@ -106,49 +478,423 @@ impl RecordingManager {
         monitor_id: String,
         width: u32,
         height: u32,
-    ) -> Result<(), String> {
-        // Check current state
-        {
-            let state = self.state.read().await;
-            if *state != RecordingState::Idle {
-                return Err("Already recording or saving".to_string());
+    ) -> Result<(), AppError> {
+        self.start_target_recording(
+            CaptureTarget::Display {
+                monitor_id,
+                width,
+                height,
+            },
+            false,
+        )
+        .await
+    }
+
+    /// Start recording the given capture target.
+    async fn start_target_recording(&self, target: CaptureTarget, app_audio: bool) -> Result<(), AppError> {
+        permissions::preflight(Permission::ScreenRecording)?;
+        self.transition(RecordingPhase::Preparing).map_err(|_| AppError::Busy)?;
+        let started = match display_mode::engage(target).await {
+            Ok(target) => {
+                environment::engage(&target).await;
+                self.prepare_target_recording(target, app_audio).await
             }
+            Err(e) => Err(AppError::NotSupported(e)),
+        };
+        self.finish_preparing(started).await
+    }
+
+    async fn prepare_target_recording(&self, target: CaptureTarget, app_audio: bool) -> Result<(), AppError> {
+        // The recording feeds the preview from here on
+        self.preview.stop_capture();
+
+        // Start capture using platform backend
+        let settings = self.recording_settings(Some(&target)).await;
+        let app_window = match (app_audio, &target) {
+            (true, CaptureTarget::Window { window_handle }) if settings.application_audio => Some(*window_handle),
+            _ => None,
+        };
+        check_audio_encryption(app_window.is_some(), &settings)?;
+        let sinks = self.create_sinks(&settings).await?;
+        let (frame_rx, stop_flag) =
+            start_recoverable_capture(
+                target.clone(),
+                settings.capture_options(),
+                settings.recovery_options(),
+                self.capture_events.clone(),
+            )?;
+
+        let clock = Arc::new(SyncClock::new());
+        *self.clock.lock().await = clock.clone();
+        if let Err(e) = self.start_audio(app_window, &settings, clock).await {
+            stop_flag.store(true, Ordering::Relaxed);
+            return Err(e);
         }
 
-        // Start display capture using platform backend
-        let backend = get_backend();
-        let (frame_rx, stop_flag) = backend
-            .start_display_capture(monitor_id, width, height)
-            .map_err(|e| e.to_string())?;
+        let mask = FrameMask::start(&target, &self.exclusions, &self.redactions);
+        if mask.is_none() {
+            tracing::warn!(target: "Recording", "Capture target not found; excluded windows and redactions will not be hidden");
+        }
+        *self.current_target.lock().await = Some(target);
+        self.markers.lock().await.clear();
 
-        self.start_encoding(frame_rx, stop_flag).await
+        self.start_encoding(frame_rx, stop_flag, sinks, settings, mask)
+            .await
+            .map_err(AppError::EncoderFailed)
     }
 
-    /// Common encoding startup logic.
-    async fn start_encoding(
+    /// Start recording the audio of the application owning `app_window`, if
+    /// given, and the microphone if `settings` ask for it, timed against
+    /// `clock`.
+    async fn start_audio(
         &self,
-        frame_rx: tokio::sync::mpsc::Receiver<CapturedFrame>,
-        stop_flag: Arc<AtomicBool>,
-    ) -> Result<(), String> {
-        // Store stop flag
-        {
-            let mut flag = self.stop_flag.lock().await;
-            *flag = Some(stop_flag.clone());
+        app_window: Option<isize>,
+        settings: &EncoderSettings,
+        clock: Arc<SyncClock>,
+    ) -> Result<(), AppError> {
+        let mut audio = self.audio.lock().await;
+        audio.clear();
+        if !settings.codec.has_audio() {
+            if app_window.is_some() || settings.microphone {
+                tracing::warn!(target: "Recording", "{:?} recordings have no audio; skipping audio", settings.codec);
+            }
+            return Ok(());
         }
+        if let Some(window_handle) = app_window {
+            let recording = start_app_audio_capture(window_handle, clock.clone())
+                .map_err(|e| AppError::AudioDeviceUnavailable(e.to_string()))?;
+            audio.push(recording);
+        }
+        if settings.microphone {
+            permissions::preflight(Permission::Microphone)?;
+            match start_microphone_capture(clock) {
+                Ok(recording) => audio.push(recording),
+                Err(e) => {
+                    audio.clear();
+                    return Err(AppError::AudioDeviceUnavailable(e.to_string()));
+                }
+            }
+        }
+        Ok(())
+    }
 
-        // Start encoding task
-        let encoding_handle = tokio::spawn(encode_frames(frame_rx, stop_flag));
+    /// Start recording a scene, composing its sources into one picture.
+    pub async fn start_scene_recording(&self, scene: Scene) -> Result<(), String> {
+        permissions::preflight(Permission::ScreenRecording)?;
+        self.transition(RecordingPhase::Preparing)
+            .map_err(|_| "Already recording or saving".to_string())?;
+        let started = self.prepare_scene_recording(scene).await.map_err(AppError::from);
+        self.finish_preparing(started).await.map_err(String::from)
+    }
 
-        {
-            let mut task = self.encoding_task.lock().await;
-            *task = Some(encoding_handle);
+    async fn prepare_scene_recording(&self, scene: Scene) -> Result<(), String> {
+        // The recording feeds the preview from here on
+        self.preview.stop_capture();
+
+        let settings = self.recording_settings(None).await;
+        check_audio_encryption(false, &settings)?;
+        let sinks = self.create_sinks(&settings).await?;
+        let target = scene.primary_target().cloned();
+        let context = SourceContext {
+            exclusions: self.exclusions.clone(),
+            redactions: self.redactions.clone(),
+            recovery: settings.recovery_options(),
+            events: self.capture_events.clone(),
+        };
+        // Excluded windows and redactions are hidden in each capture before it's composed
+        let (frame_rx, stop_flag, switcher) = start_compositor(scene, settings.fps, context)?;
+
+        let clock = Arc::new(SyncClock::new());
+        *self.clock.lock().await = clock.clone();
+        if let Err(e) = self.start_audio(None, &settings, clock).await {
+            stop_flag.store(true, Ordering::Relaxed);
+            return Err(e.to_string());
         }
+        *self.current_target.lock().await = target;
+        *self.scene_switcher.lock().await = Some(switcher);
+        self.markers.lock().await.clear();
 
-        // Update state
-        {
-            let mut state = self.state.write().await;
-            *state = RecordingState::Recording;
+        self.start_encoding(frame_rx, stop_flag, sinks, settings, None).await
+    }
+
+    /// Switch the scene being recorded to `scene`, with `transition`.
+    pub async fn switch_scene(&self, scene: Scene, transition: Transition) -> Result<(), String> {
+        let switcher = self
+            .scene_switcher
+            .lock()
+            .await
+            .clone()
+            .ok_or("Not recording a scene")?;
+        switcher.switch(scene, transition).await
+    }
+
+    /// Set the chroma key of `item` in `scene`, if that scene is being recorded.
+    pub async fn set_chroma_key(&self, scene: &str, item: &str, key: Option<ChromaKey>) -> Result<(), String> {
+        match self.scene_switcher.lock().await.as_ref() {
+            Some(switcher) => switcher.set_chroma_key(scene, item, key),
+            None => Ok(()),
+        }
+    }
+
+    /// Place a marker at the current position of the recording.
+    pub async fn add_marker(&self, label: Option<String>) -> Result<Marker, String> {
+        let time_secs = match *self.recording_start.lock().await {
+            Some(_) if self.get_phase().is_recording() => {
+                self.clock.lock().await.video_time().as_secs_f64()
+            }
+            _ => return Err("Not currently recording".to_string()),
+        };
+
+        let marker = Marker { time_secs, label };
+        // A marker the recording stops at would be an empty chapter at its end
+        if !self.stop_at.marker_added(time_secs) {
+            self.markers.lock().await.push(marker.clone());
         }
+        Ok(marker)
+    }
+
+    /// Grabs stills from the current recording, while one is running.
+    pub async fn stills(&self) -> Result<StillGrabber, String> {
+        if !self.get_phase().is_recording() {
+            return Err("Not currently recording".to_string());
+        }
+        Ok(self.stills.clone())
+    }
+
+    /// Pause the current recording. Nothing is recorded until it's resumed,
+    /// and the paused time is left out of the file.
+    pub async fn pause_recording(&self) -> Result<(), String> {
+        if !self.get_phase().is_recording() {
+            return Err("Not currently recording".to_string());
+        }
+        if !self.clock.lock().await.pause().set_paused(true) {
+            return Err("The recording is already paused".to_string());
+        }
+        self.transition(RecordingPhase::Paused)?;
+        tracing::info!(target: "Recording", "Paused");
+        Ok(())
+    }
+
+    /// Resume the current recording after [`pause_recording`](Self::pause_recording).
+    pub async fn resume_recording(&self) -> Result<(), String> {
+        if !self.get_phase().is_recording() {
+            return Err("Not currently recording".to_string());
+        }
+        if !self.clock.lock().await.pause().set_paused(false) {
+            return Err("The recording isn't paused".to_string());
+        }
+        self.transition(RecordingPhase::Recording)?;
+        tracing::info!(target: "Recording", "Resumed");
+        Ok(())
+    }
+
+    /// Whether the current recording is paused.
+    pub async fn is_paused(&self) -> bool {
+        self.get_phase() == RecordingPhase::Paused
+    }
+
+    /// Save the last seconds of the replay buffer as a clip in the Videos
+    /// folder, without interrupting the recording. The clip is added to the
+    /// library.
+    pub async fn save_replay(&self) -> Result<PathBuf, String> {
+        if !self.get_phase().is_recording() {
+            return Err("Not currently recording".to_string());
+        }
+        let buffer = self
+            .replay
+            .lock()
+            .await
+            .clone()
+            .ok_or("The replay buffer isn't enabled for this recording")?;
+
+        let clip = generate_output_path("replay", "mp4")?;
+        let library = self.library.clone();
+        let source = self.current_target.lock().await.clone();
+        tokio::task::spawn_blocking(move || {
+            buffer.save(&clip)?;
+            if let Err(e) = library.blocking_lock().add(&clip, source, Vec::new()) {
+                tracing::error!(target: "Library", "Failed to add replay: {}", e);
+            }
+            if let Err(e) = thumbnail::ensure_thumbnails(&clip, false) {
+                tracing::error!(target: "Thumbnail", "Failed to generate thumbnail: {}", e);
+            }
+            Ok(clip)
+        })
+        .await
+        .map_err(|e| format!("Task error: {}", e))?
+    }
+
+    /// Append the next recording to the library recording `id` instead of
+    /// adding it as a new one, e.g. to carry on with a recording recovered
+    /// after a crash, or stop doing so with `None`. Returns the recording
+    /// that will be continued.
+    pub async fn set_continue_target(&self, id: Option<u64>) -> Result<Option<RecordingEntry>, String> {
+        if !self.get_phase().is_idle() {
+            return Err("Can't choose a recording to continue while recording".to_string());
+        }
+        let entry = match id {
+            Some(id) => {
+                let entry = self.library.lock().await.get(id)?.clone();
+                let path = Path::new(&entry.file_path);
+                if !path.is_file() {
+                    return Err(format!("Recording not found: {}", path.display()));
+                }
+                if !concat::is_appendable(path) {
+                    return Err("Only MKV and WebM recordings can be continued".to_string());
+                }
+                if encryption::is_encrypted(path) {
+                    return Err("Encrypted recordings can't be continued".to_string());
+                }
+                Some(entry)
+            }
+            None => None,
+        };
+        *self.continue_into.lock().await = entry.as_ref().map(|entry| entry.id);
+        Ok(entry)
+    }
+
+    /// The library recording the next recording is appended to, if any.
+    pub async fn continue_target(&self) -> Option<RecordingEntry> {
+        let id = (*self.continue_into.lock().await)?;
+        self.library.lock().await.get(id).ok().cloned()
+    }
+
+    /// Append the recording in `result` to the library recording `id`,
+    /// pointing `result` at the joined file. Returns whether it was
+    /// appended; if it wasn't, the recording is kept as a file of its own.
+    async fn append_to_continued(&self, id: u64, result: &mut RecordingResult, markers: Vec<Marker>) -> bool {
+        // A split recording's segments are files of their own by design
+        let Some(next) = result.file_path.clone().filter(|_| result.segments.is_empty()).map(PathBuf::from) else {
+            tracing::warn!(target: "Recording", "Split recordings can't continue another recording");
+            return false;
+        };
+        let existing = match self.library.lock().await.get(id) {
+            Ok(entry) => PathBuf::from(&entry.file_path),
+            Err(e) => {
+                tracing::warn!(target: "Recording", "Can't continue a recording: {}", e);
+                return false;
+            }
+        };
+
+        let library = self.library.clone();
+        let joined = existing.clone();
+        let appended = tokio::task::spawn_blocking(move || {
+            concat::append(&joined, &next)?;
+            let entry = library.blocking_lock().append(id, markers);
+            match &entry {
+                // Chapters for the markers of every session
+                Ok(entry) => {
+                    if let Err(e) = chapters::add_chapters(&joined, &entry.markers) {
+                        tracing::error!(target: "Recording", "Failed to add chapters: {}", e);
+                    }
+                }
+                Err(e) => tracing::error!(target: "Library", "Failed to update recording: {}", e),
+            }
+            if let Err(e) = thumbnail::ensure_thumbnails(&joined, false) {
+                tracing::error!(target: "Thumbnail", "Failed to generate thumbnail: {}", e);
+            }
+            Ok::<_, String>(entry.ok())
+        })
+        .await
+        .map_err(|e| format!("Task error: {}", e))
+        .and_then(|appended| appended);
+
+        match appended {
+            Ok(entry) => {
+                tracing::info!(target: "Recording", "Continued {}", existing.display());
+                result.file_path = Some(existing.to_string_lossy().to_string());
+                if let Some(file) = result.files.first_mut() {
+                    file.path = existing;
+                    if let Some(entry) = entry {
+                        file.duration_secs = entry.duration_secs;
+                    }
+                }
+                true
+            }
+            Err(e) => {
+                tracing::error!(target: "Recording", "Failed to continue {}: {}", existing.display(), e);
+                false
+            }
+        }
+    }
+
+    /// Get the recording library.
+    pub fn library(&self) -> Arc<Mutex<Library>> {
+        self.library.clone()
+    }
+
+    /// Levels of each audio source of the current recording since the last
+    /// call, `interval` ago. Empty when no audio is being recorded.
+    pub async fn audio_levels(&self, interval: std::time::Duration) -> Vec<AudioLevel> {
+        self.audio.lock().await.iter().map(|audio| audio.levels(interval)).collect()
+    }
+
+    /// The clock the current recording's streams are timed against.
+    pub async fn clock(&self) -> Arc<SyncClock> {
+        self.clock.lock().await.clone()
+    }
+
+    /// What the current recording captures, if known.
+    pub async fn current_target(&self) -> Option<CaptureTarget> {
+        self.current_target.lock().await.clone()
+    }
+
+    /// Get the statistics collector for the current recording, if any.
+    pub async fn stats_collector(&self) -> Option<Arc<StatsCollector>> {
+        self.stats.lock().await.clone()
+    }
+
+    /// Get the latest statistics for the current recording.
+    pub async fn get_stats(&self) -> Result<RecordingStats, String> {
+        self.stats_collector()
+            .await
+            .map(|collector| collector.latest())
+            .ok_or_else(|| "Not currently recording".to_string())
+    }
+
+    /// Common encoding startup logic.
+    async fn start_encoding(
+        &self,
+        frame_rx: FrameReceiver,
+        stop_flag: Arc<AtomicBool>,
+        sinks: Vec<Box<dyn FrameSink>>,
+        settings: EncoderSettings,
+        mask: Option<FrameMask>,
+    ) -> Result<(), String> {
+        // Start encoding task
+        let placement = match self.current_target.lock().await.as_ref() {
+            Some(target) => GpuPlacement::resolve(target, settings.gpu_adapter),
+            None => GpuPlacement::default(),
+        };
+        let stats = Arc::new(StatsCollector::new());
+        *self.stats.lock().await = Some(stats.clone());
+        tokio::spawn(disk::watch(
+            stats.clone(),
+            stop_flag.clone(),
+            self.get_disk_space_settings().await,
+            self.disk_events.clone(),
+        ));
+        tokio::spawn(limits::watch(
+            stats.clone(),
+            stop_flag.clone(),
+            self.get_limits().await,
+            self.limit_events.clone(),
+        ));
+        let clock = self.clock.lock().await.clone();
+        let filters = FilterChain::for_recording(mask);
+        self.stop_at.reset(settings.fps);
+        let encoding = encode_frames(
+            frame_rx,
+            stop_flag.clone(),
+            sinks,
+            settings,
+            placement,
+            stats,
+            filters,
+            clock,
+            self.stop_at.clone(),
+        );
+        *self.recording.lock().await = Some(RecordingHandle::spawn(stop_flag, encoding));
 
         // Record start time
         {
@@ -161,80 +907,314 @@ impl RecordingManager {
 
     /// Stop the current recording and save the file.
     pub async fn stop_recording(&self) -> Result<RecordingResult, String> {
-        // Check current state
-        {
-            let state = self.state.read().await;
-            if *state != RecordingState::Recording {
-                return Err("Not currently recording".to_string());
-            }
+        if !self.get_phase().is_recording() {
+            return Err("Not currently recording".to_string());
         }
+        self.transition(RecordingPhase::Finalizing)?;
 
-        // Set state to saving
-        {
-            let mut state = self.state.write().await;
-            *state = RecordingState::Saving;
-        }
+        // Stop and wait for the files to be complete
+        let recording = self.recording.lock().await.take();
+        let finalized = match recording {
+            Some(recording) => recording.finalize().await,
+            None => Err("No encoding task found".to_string()),
+        };
+        let mut result = match finalized {
+            Ok(files) => {
+                let mut paths = files.iter().map(|file| file.path.to_string_lossy().to_string());
+                RecordingResult {
+                    success: true,
+                    file_path: paths.next(),
+                    segments: paths.collect(),
+                    files,
+                    error: None,
+                    warnings: Vec::new(),
+                }
+            }
+            Err(e) => RecordingResult {
+                success: false,
+                file_path: None,
+                segments: Vec::new(),
+                files: Vec::new(),
+                error: Some(e),
+                warnings: Vec::new(),
+            },
+        };
 
-        // Signal stop
-        {
-            let flag = self.stop_flag.lock().await;
-            if let Some(ref stop_flag) = *flag {
-                stop_flag.store(true, Ordering::Relaxed);
-            }
-        }
-
-        // Wait for encoding to complete
-        let result = {
-            let mut task = self.encoding_task.lock().await;
-            if let Some(handle) = task.take() {
-                match handle.await {
-                    Ok(Ok(path)) => RecordingResult {
-                        success: true,
-                        file_path: Some(path.to_string_lossy().to_string()),
-                        error: None,
-                    },
-                    Ok(Err(e)) => RecordingResult {
-                        success: false,
-                        file_path: None,
-                        error: Some(e),
-                    },
-                    Err(e) => RecordingResult {
-                        success: false,
-                        file_path: None,
-                        error: Some(format!("Task error: {}", e)),
-                    },
+        // Add the audio, keeping the video-only file if that fails
+        let recordings = std::mem::take(&mut *self.audio.lock().await);
+        if !recordings.is_empty() {
+            // The audio spans every segment, so it's only added to unsplit recordings
+            let video = result.file_path.clone().map(PathBuf::from).filter(|_| result.segments.is_empty());
+            if video.is_none() && result.success {
+                result
+                    .warnings
+                    .push("The audio wasn't added because the recording was split into segments".to_string());
+            }
+            let video_start = self.clock.lock().await.video_start();
+            let stopped_secs = self.stop_at.stopped_secs();
+            let dynamics = dynamics::settings();
+            let loudness = loudness::target();
+            let echo_cancellation = self.encoder_settings.lock().await.echo_cancellation;
+            let muxed = tokio::task::spawn_blocking(move || {
+                let mut warnings = Vec::new();
+                let (wav, offset_ms) = match mix_audio(recordings, echo_cancellation, &mut warnings) {
+                    Ok(Some(mixed)) => mixed,
+                    Ok(None) => return Ok(warnings),
+                    Err(e) => return Err(e),
+                };
+                let muxed = video.map_or(Ok(()), |video| {
+                    let audio_secs = wav_duration(&wav)?;
+                    // Stopped on an exact frame, the audio is cut on the matching sample
+                    let sync = match stopped_secs {
+                        Some(video_secs) => AudioSync::plan_exact(video_start, offset_ms, audio_secs, video_secs),
+                        None => AudioSync::plan(video_start, offset_ms, audio_secs, probe(&video)?.duration),
+                    };
+                    mux::add_audio(&video, &wav, &sync, &dynamics)?;
+                    // The recording keeps its audio as recorded if this fails
+                    if let Some(target) = loudness {
+                        if let Err(e) = loudness::normalize_in_place(&video, &target) {
+                            tracing::error!(target: "Recording", "Failed to normalize loudness: {}", e);
+                        }
+                    }
+                    Ok::<_, String>(())
+                });
+                let _ = std::fs::remove_file(&wav);
+                muxed.map(|()| warnings)
+            })
+            .await;
+            match muxed {
+                Ok(Ok(warnings)) => result.warnings.extend(warnings),
+                Ok(Err(e)) => {
+                    tracing::error!(target: "Recording", "Failed to add audio: {}", e);
+                    result.warnings.push(format!("The audio wasn't added: {}", e));
                 }
-            } else {
-                RecordingResult {
-                    success: false,
-                    file_path: None,
-                    error: Some("No encoding task found".to_string()),
+                Err(e) => {
+                    tracing::error!(target: "Recording", "Audio task error: {}", e);
+                    result.warnings.push(format!("The audio wasn't added: Task error: {}", e));
                 }
             }
+        }
+
+        let source = self.current_target.lock().await.take();
+        self.scene_switcher.lock().await.take();
+        let markers = std::mem::take(&mut *self.markers.lock().await);
+
+        // Append to the recording being continued rather than adding a new one
+        let continued = match self.continue_into.lock().await.take() {
+            Some(id) if result.success => self.append_to_continued(id, &mut result, markers.clone()).await,
+            _ => false,
         };
 
-        // Clean up
-        {
-            let mut flag = self.stop_flag.lock().await;
-            *flag = None;
+        // Describe the recording for the tags written into its files
+        let tag_settings = TagSettings::load();
+        let tag_context = RecordingContext {
+            source: source.as_ref().map(|target| match target {
+                CaptureTarget::Window { .. } => tags::describe_source(target, &crate::capture::list_windows()),
+                _ => tags::describe_source(target, &[]),
+            }),
+            profile: self.encoder_settings.lock().await.profile.clone(),
+        };
+
+        let mut markers = Some(markers);
+        for path in result.file_path.iter().chain(&result.segments).filter(|_| !continued) {
+            let video = PathBuf::from(path);
+            // Image sequences aren't videos the library can play
+            if video.is_dir() {
+                tracing::info!(target: "Recording", "Saved image sequence to {}", video.display());
+                continue;
+            }
+            let encrypted = encryption::is_encrypted(&video);
+
+            // Record the new file in the library, with the markers on the first segment
+            let library = self.library.clone();
+            let library_video = video.clone();
+            let source = source.clone();
+            let markers = markers.take().unwrap_or_default();
+
+            // Write the markers into the file as chapters so players can jump
+            // between them, and tag it so it describes itself once shared
+            let tags = tags::recording_tags(&tag_settings, &video, &tag_context);
+            if (!markers.is_empty() || !tags.is_empty()) && !encrypted {
+                let chapters_video = video.clone();
+                let chapters = markers.clone();
+                let written = tokio::task::spawn_blocking(move || {
+                    chapters::add_metadata(&chapters_video, &chapters, &tags)
+                })
+                .await;
+                match written {
+                    Ok(Err(e)) => tracing::error!(target: "Recording", "Failed to add chapters and tags: {}", e),
+                    Err(e) => tracing::error!(target: "Recording", "Chapters task error: {}", e),
+                    Ok(Ok(())) => {}
+                }
+            }
+
+            let added = tokio::task::spawn_blocking(move || {
+                library.blocking_lock().add(&library_video, source, markers)
+            })
+            .await;
+            if let Ok(Err(e)) = added {
+                tracing::error!(target: "Library", "Failed to add recording: {}", e);
+            }
+
+            // Thumbnails of encrypted recordings would be stored in the clear
+            if encrypted {
+                continue;
+            }
+
+            // Generate the poster thumbnail in the background so it's ready for the library view
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = thumbnail::ensure_thumbnails(&video, false) {
+                    tracing::error!(target: "Thumbnail", "Failed to generate thumbnail: {}", e);
+                }
+            });
         }
+
+        // Audio and chapters were added since the files were finalized
+        for file in &mut result.files {
+            file.update_size();
+        }
+
+        // Clean up
         {
             let mut start = self.recording_start.lock().await;
             *start = None;
         }
+        *self.stats.lock().await = None;
+        *self.replay.lock().await = None;
 
-        // Reset state to idle
-        {
-            let mut state = self.state.write().await;
-            *state = RecordingState::Idle;
-        }
+        let payload = match &result.error {
+            Some(error) if !result.success => WebhookPayload {
+                error: Some(error.clone()),
+                ..WebhookPayload::new(WebhookEvent::RecordingFailed)
+            },
+            _ => WebhookPayload {
+                file_path: result.file_path.clone(),
+                duration_secs: Some(result.files.iter().map(|file| file.duration_secs).sum()),
+                ..WebhookPayload::new(WebhookEvent::RecordingStopped)
+            },
+        };
+        webhooks::fire(WebhookPayload { source, ..payload });
 
+        let phase = match &result.error {
+            Some(error) if !result.success => RecordingPhase::Failed { error: error.clone() },
+            _ => RecordingPhase::Done {
+                file_path: result.file_path.clone(),
+                segments: result.segments.clone(),
+            },
+        };
+        // The files are saved by now, so this doesn't fail the recording
+        if let Err(e) = self.transition(phase) {
+            tracing::error!(target: "Recording", "{}", e);
+        }
         Ok(result)
     }
 }
 
+/// Refuse recording audio, with the application's if `app_audio` is set,
+/// into an encrypted recording: each source is kept in an unencrypted file
+/// until it's muxed.
+fn check_audio_encryption(app_audio: bool, settings: &EncoderSettings) -> Result<(), AppError> {
+    if settings.encrypt && (app_audio || settings.microphone) {
+        return Err(AppError::InvalidParameters("Audio can't be recorded into encrypted recordings".to_string()));
+    }
+    Ok(())
+}
+
+/// Stop `recordings` and mix what they captured into one file, returned
+/// with how far it still has to be delayed against the video. With
+/// `echo_cancellation`, the application audio's echo is removed from the
+/// microphone first. Sources that failed are left out, and said so in
+/// `warnings`.
+fn mix_audio(
+    recordings: Vec<AudioRecording>,
+    echo_cancellation: bool,
+    warnings: &mut Vec<String>,
+) -> Result<Option<(PathBuf, i32)>, String> {
+    let mut tracks = Vec::new();
+    for recording in recordings {
+        let source = recording.source();
+        match recording.stop() {
+            Ok(wav) => tracks.push((source, wav)),
+            Err(e) => {
+                tracing::error!(target: "Recording", "Failed to record the {} audio: {}", source.name(), e);
+                warnings.push(format!("The {} audio wasn't added: {}", source.name(), e));
+            }
+        }
+    }
+    if tracks.len() <= 1 {
+        return Ok(tracks.pop().map(|(source, wav)| (wav, avsync::offset_for_source_ms(source))));
+    }
+
+    let track = |source: AudioSource| tracks.iter().find(|(s, _)| *s == source).map(|(_, wav)| wav.clone());
+    if let (true, Some(microphone), Some(application)) =
+        (echo_cancellation, track(AudioSource::Microphone), track(AudioSource::Application))
+    {
+        let cancelled = microphone.with_extension("echo.wav");
+        match echo::cancel_echo(&microphone, &application, &cancelled) {
+            Ok(()) => {
+                let _ = std::fs::rename(&cancelled, &microphone);
+            }
+            Err(e) => {
+                let _ = std::fs::remove_file(&cancelled);
+                tracing::error!(target: "Recording", "Failed to cancel echo: {}", e);
+                warnings.push(format!("The application audio's echo wasn't removed from the microphone: {}", e));
+            }
+        }
+    }
+
+    // Each source is shifted by its own offset, less the one the mix gets
+    let offset_ms = avsync::audio_offset_ms();
+    let mixed = tracks[0].1.with_extension("mix.wav");
+    let inputs: Vec<Track> = tracks
+        .iter()
+        .map(|(source, wav)| Track {
+            path: wav.clone(),
+            delay_ms: avsync::offset_for_source_ms(*source) - offset_ms,
+        })
+        .collect();
+    let result = mix::mix_tracks(&inputs, &mixed);
+    for (_, wav) in &tracks {
+        let _ = std::fs::remove_file(wav);
+    }
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&mixed);
+        return Err(e);
+    }
+    Ok(Some((mixed, offset_ms)))
+}
+
 impl Default for RecordingManager {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phase_transitions() {
+        let manager = RecordingManager::new();
+        let mut events = manager.subscribe_phase_events();
+        assert!(manager.transition(RecordingPhase::Recording).is_err());
+        manager.transition(RecordingPhase::Preparing).unwrap();
+        // A second recording can't start while one is prepared
+        assert!(manager.transition(RecordingPhase::Preparing).is_err());
+        manager.transition(RecordingPhase::Recording).unwrap();
+        manager.transition(RecordingPhase::Paused).unwrap();
+        manager.transition(RecordingPhase::Finalizing).unwrap();
+        let done = RecordingPhase::Done { file_path: Some("a.mp4".to_string()), segments: Vec::new() };
+        manager.transition(done.clone()).unwrap();
+        assert!(manager.transition(RecordingPhase::Paused).is_err());
+        manager.transition(RecordingPhase::Preparing).unwrap();
+
+        assert_eq!(events.try_recv().unwrap(), RecordingPhase::Preparing);
+        let sent: Vec<_> = std::iter::from_fn(|| events.try_recv().ok()).collect();
+        assert_eq!(sent[3], done);
+        assert_eq!(
+            serde_json::to_value(&done).unwrap(),
+            serde_json::json!({"phase": "done", "file_path": "a.mp4", "segments": []})
+        );
+    }
+}
@@ -0,0 +1,393 @@
+//! Live capture and encode statistics for the current recording.
+//!
+//! The encoder updates a shared [`StatsCollector`] as it works. Once a second
+//! the collector is sampled into a [`RecordingStats`] snapshot, which is
+//! emitted to the frontend as a `recording-stats` event and returned by the
+//! `get_recording_stats` command. Audio levels are sampled more often, for
+//! VU meters, and emitted as `audio-levels` events.
+
+use crate::capture::audio::AudioLevel;
+use crate::capture::queue::QueueStats;
+use crate::gpu::GpuAdapter;
+use crate::state::RecordingManager;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+
+/// Event emitted with a [`RecordingStats`] snapshot while recording.
+pub const STATS_EVENT: &str = "recording-stats";
+
+/// How often stats are sampled and emitted.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Snapshots kept for diagnostics bundles: the last two minutes recorded.
+const HISTORY_LEN: usize = 120;
+
+static HISTORY: Mutex<VecDeque<RecordingStats>> = Mutex::new(VecDeque::new());
+
+/// Event emitted with the [`AudioLevel`] of each audio source while recording.
+pub const LEVELS_EVENT: &str = "audio-levels";
+
+/// How often audio levels are sampled and emitted.
+const LEVELS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Snapshot of recording health.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecordingStats {
+    /// Seconds since the recording started
+    pub elapsed_secs: f64,
+    /// Frames per second arriving from the capture source
+    pub capture_fps: f64,
+    /// Frames per second written to the output file
+    pub encode_fps: f64,
+    /// Frames dropped by the capture queue since the recording started
+    pub dropped_frames: u64,
+    /// Average time to hand a frame to the encoder, in milliseconds
+    pub encode_latency_ms: f64,
+    /// Average time each stage of the pipeline spends on a frame
+    pub stages: StageTimings,
+    /// Frames waiting in the capture queue
+    pub queue_depth: usize,
+    /// Output bitrate over the last interval, in kilobits per second
+    pub bitrate_kbps: f64,
+    /// Current size of the output file
+    pub file_size_bytes: u64,
+    /// Whether capture is using the zero-copy (DMA-BUF) buffer path
+    pub zero_copy: bool,
+    /// FFmpeg encoder writing the output, such as `h264_nvenc`
+    pub encoder: String,
+    /// GPU the frames are captured on, if known
+    pub capture_gpu: Option<String>,
+    /// GPU the encoder runs on, or `None` for the software encoder
+    pub encode_gpu: Option<String>,
+    /// Whether frames are copied from the capturing GPU to a different one
+    /// for encoding
+    pub cross_adapter: bool,
+}
+
+/// A stage of the recording pipeline, after capture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Filtering and motion detection of each captured frame
+    Process,
+    /// Conversion to the encoder's input format
+    Convert,
+    /// Piping to FFmpeg
+    Write,
+}
+
+/// Average time each pipeline stage spends on a frame, in milliseconds.
+///
+/// Whichever takes longest limits the frame rate: at 60 fps each has under
+/// 16.7 ms.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct StageTimings {
+    pub process_ms: f64,
+    pub convert_ms: f64,
+    pub write_ms: f64,
+}
+
+/// Counters at the previous sample, for computing rates.
+#[derive(Clone, Copy)]
+struct Sample {
+    at: Instant,
+    frames_captured: u64,
+    frames_encoded: u64,
+    encode_time_us: u64,
+    file_size_bytes: u64,
+    /// Time spent in and frames through each [`Stage`]
+    stage_time_us: [u64; 3],
+    stage_frames: [u64; 3],
+}
+
+/// Counters updated by the encoder during a recording.
+pub struct StatsCollector {
+    started: Instant,
+    frames_captured: AtomicU64,
+    frames_encoded: AtomicU64,
+    encode_time_us: AtomicU64,
+    dropped_frames: AtomicU64,
+    queue_depth: AtomicU64,
+    stage_time_us: [AtomicU64; 3],
+    stage_frames: [AtomicU64; 3],
+    output_path: Mutex<Option<PathBuf>>,
+    encoder: Mutex<&'static str>,
+    capture_gpu: Mutex<Option<GpuAdapter>>,
+    encode_gpu: Mutex<Option<GpuAdapter>>,
+    last_sample: Mutex<Sample>,
+    latest: Mutex<RecordingStats>,
+    /// When the picture last changed, if the encoder is looking
+    last_change: Mutex<Option<Instant>>,
+}
+
+impl StatsCollector {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            started: now,
+            frames_captured: AtomicU64::new(0),
+            frames_encoded: AtomicU64::new(0),
+            encode_time_us: AtomicU64::new(0),
+            dropped_frames: AtomicU64::new(0),
+            queue_depth: AtomicU64::new(0),
+            stage_time_us: Default::default(),
+            stage_frames: Default::default(),
+            output_path: Mutex::new(None),
+            encoder: Mutex::new(""),
+            capture_gpu: Mutex::new(None),
+            encode_gpu: Mutex::new(None),
+            last_sample: Mutex::new(Sample {
+                at: now,
+                frames_captured: 0,
+                frames_encoded: 0,
+                encode_time_us: 0,
+                file_size_bytes: 0,
+                stage_time_us: [0; 3],
+                stage_frames: [0; 3],
+            }),
+            latest: Mutex::new(RecordingStats::default()),
+            last_change: Mutex::new(None),
+        }
+    }
+
+    /// Record the file the encoder is writing to.
+    pub fn set_output_path(&self, path: PathBuf) {
+        *self.output_path.lock().unwrap() = Some(path);
+    }
+
+    /// The file the encoder is writing to, once it has started.
+    pub fn output_path(&self) -> Option<PathBuf> {
+        self.output_path.lock().unwrap().clone()
+    }
+
+    /// Record which encoder is writing the output, and the GPU it runs on.
+    pub fn set_encoder(&self, encoder: &'static str, gpu: Option<GpuAdapter>) {
+        *self.encoder.lock().unwrap() = encoder;
+        *self.encode_gpu.lock().unwrap() = gpu;
+    }
+
+    /// Record which GPU the frames are captured on.
+    pub fn set_capture_gpu(&self, gpu: Option<GpuAdapter>) {
+        *self.capture_gpu.lock().unwrap() = gpu;
+    }
+
+    /// Record a new frame taken from the capture queue.
+    pub fn frame_captured(&self) {
+        self.frames_captured.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a frame written to the encoder and how long the write took.
+    pub fn frame_encoded(&self, took: Duration) {
+        self.frames_encoded.fetch_add(1, Ordering::Relaxed);
+        self.encode_time_us.fetch_add(took.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Record a frame through `stage` and how long it took.
+    pub fn stage_done(&self, stage: Stage, took: Duration) {
+        self.stage_frames[stage as usize].fetch_add(1, Ordering::Relaxed);
+        self.stage_time_us[stage as usize].fetch_add(took.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Record the capture queue's counters.
+    pub fn update_queue(&self, queue: QueueStats) {
+        self.dropped_frames.store(queue.dropped, Ordering::Relaxed);
+        self.queue_depth.store(queue.depth as u64, Ordering::Relaxed);
+    }
+
+    /// Record that the picture changed at `at`.
+    pub fn screen_changed(&self, at: Instant) {
+        *self.last_change.lock().unwrap() = Some(at);
+    }
+
+    /// Time since the picture last changed, or since the recording started
+    /// if it hasn't.
+    pub fn since_screen_change(&self) -> Duration {
+        self.last_change.lock().unwrap().unwrap_or(self.started).elapsed()
+    }
+
+    /// Time since the recording started.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Current size of the file the encoder is writing to.
+    pub fn file_size(&self) -> u64 {
+        self.output_path()
+            .and_then(|path| std::fs::metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .unwrap_or(0)
+    }
+
+    /// Compute a new snapshot from the counters since the previous sample.
+    pub fn sample(&self) -> RecordingStats {
+        let now = Instant::now();
+        let current = Sample {
+            at: now,
+            frames_captured: self.frames_captured.load(Ordering::Relaxed),
+            frames_encoded: self.frames_encoded.load(Ordering::Relaxed),
+            encode_time_us: self.encode_time_us.load(Ordering::Relaxed),
+            file_size_bytes: self.file_size(),
+            stage_time_us: self.stage_time_us.each_ref().map(|time| time.load(Ordering::Relaxed)),
+            stage_frames: self.stage_frames.each_ref().map(|frames| frames.load(Ordering::Relaxed)),
+        };
+        let previous = std::mem::replace(&mut *self.last_sample.lock().unwrap(), current);
+
+        let stats = compute_stats(&previous, &current, self.started);
+        let capture_gpu = self.capture_gpu.lock().unwrap().clone();
+        let encode_gpu = self.encode_gpu.lock().unwrap().clone();
+        let stats = RecordingStats {
+            dropped_frames: self.dropped_frames.load(Ordering::Relaxed),
+            queue_depth: self.queue_depth.load(Ordering::Relaxed) as usize,
+            zero_copy: crate::capture::zero_copy_active(),
+            encoder: self.encoder.lock().unwrap().to_string(),
+            cross_adapter: matches!((&capture_gpu, &encode_gpu), (Some(capture), Some(encode)) if capture.index != encode.index),
+            capture_gpu: capture_gpu.map(|gpu| gpu.name),
+            encode_gpu: encode_gpu.map(|gpu| gpu.name),
+            ..stats
+        };
+        *self.latest.lock().unwrap() = stats.clone();
+        stats
+    }
+
+    /// The most recent snapshot.
+    pub fn latest(&self) -> RecordingStats {
+        self.latest.lock().unwrap().clone()
+    }
+}
+
+impl Default for StatsCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Rates and averages between two samples.
+fn compute_stats(previous: &Sample, current: &Sample, started: Instant) -> RecordingStats {
+    let interval = current.at.duration_since(previous.at).as_secs_f64();
+    let rate = |delta: u64| if interval > 0.0 { delta as f64 / interval } else { 0.0 };
+
+    let frames_encoded = current.frames_encoded.saturating_sub(previous.frames_encoded);
+    let encode_time_us = current.encode_time_us.saturating_sub(previous.encode_time_us);
+    let stage_ms = |stage: Stage| {
+        let i = stage as usize;
+        let frames = current.stage_frames[i].saturating_sub(previous.stage_frames[i]);
+        let time_us = current.stage_time_us[i].saturating_sub(previous.stage_time_us[i]);
+        if frames > 0 {
+            time_us as f64 / frames as f64 / 1000.0
+        } else {
+            0.0
+        }
+    };
+
+    RecordingStats {
+        elapsed_secs: current.at.duration_since(started).as_secs_f64(),
+        capture_fps: rate(current.frames_captured.saturating_sub(previous.frames_captured)),
+        encode_fps: rate(frames_encoded),
+        encode_latency_ms: if frames_encoded > 0 {
+            encode_time_us as f64 / frames_encoded as f64 / 1000.0
+        } else {
+            0.0
+        },
+        stages: StageTimings {
+            process_ms: stage_ms(Stage::Process),
+            convert_ms: stage_ms(Stage::Convert),
+            write_ms: stage_ms(Stage::Write),
+        },
+        bitrate_kbps: rate(current.file_size_bytes.saturating_sub(previous.file_size_bytes)) * 8.0 / 1000.0,
+        file_size_bytes: current.file_size_bytes,
+        ..Default::default()
+    }
+}
+
+/// Keep `stats` among the recent snapshots, dropping the oldest.
+fn remember(stats: RecordingStats) {
+    let mut history = HISTORY.lock().unwrap();
+    if history.len() == HISTORY_LEN {
+        history.pop_front();
+    }
+    history.push_back(stats);
+}
+
+/// The snapshots emitted over the last couple of minutes of recording,
+/// oldest first. They may be from recordings that have since stopped.
+pub fn recent() -> Vec<RecordingStats> {
+    HISTORY.lock().unwrap().iter().cloned().collect()
+}
+
+/// Sample the active recording's stats every second and emit them to the frontend.
+///
+/// Runs for the lifetime of the app.
+pub async fn run_reporter(app: AppHandle, manager: Arc<tokio::sync::Mutex<RecordingManager>>) {
+    let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let collector = manager.lock().await.stats_collector().await;
+        if let Some(collector) = collector {
+            let stats = collector.sample();
+            remember(stats.clone());
+            if let Err(e) = app.emit(STATS_EVENT, stats) {
+                tracing::error!(target: "Stats", "Failed to emit stats: {}", e);
+            }
+        }
+    }
+}
+
+/// Sample the active recording's audio levels ten times a second and emit
+/// them to the frontend.
+///
+/// Runs for the lifetime of the app.
+pub async fn run_level_reporter(app: AppHandle, manager: Arc<tokio::sync::Mutex<RecordingManager>>) {
+    let mut interval = tokio::time::interval(LEVELS_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let levels: Vec<AudioLevel> = manager.lock().await.audio_levels(LEVELS_INTERVAL).await;
+        if !levels.is_empty() {
+            if let Err(e) = app.emit(LEVELS_EVENT, levels) {
+                tracing::error!(target: "Stats", "Failed to emit audio levels: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compute_stats() {
+        let started = Instant::now();
+        let previous = Sample {
+            at: started,
+            frames_captured: 0,
+            frames_encoded: 0,
+            encode_time_us: 0,
+            file_size_bytes: 0,
+            stage_time_us: [0; 3],
+            stage_frames: [0; 3],
+        };
+        let current = Sample {
+            at: started + Duration::from_secs(2),
+            frames_captured: 120,
+            frames_encoded: 60,
+            encode_time_us: 300_000,
+            file_size_bytes: 500_000,
+            stage_time_us: [60_000, 240_000, 0],
+            stage_frames: [120, 60, 0],
+        };
+
+        let stats = compute_stats(&previous, &current, started);
+        assert_eq!(stats.elapsed_secs, 2.0);
+        assert_eq!(stats.capture_fps, 60.0);
+        assert_eq!(stats.encode_fps, 30.0);
+        assert_eq!(stats.encode_latency_ms, 5.0);
+        assert_eq!(stats.stages, StageTimings { process_ms: 0.5, convert_ms: 4.0, write_ms: 0.0 });
+        assert_eq!(stats.bitrate_kbps, 2000.0);
+        assert_eq!(stats.file_size_bytes, 500_000);
+    }
+}
@@ -0,0 +1,164 @@
+//! Metadata tags written into recordings.
+//!
+//! Recordings get passed around long after they leave the library, so the
+//! file itself says what it is: a title, author and comment set by the
+//! user, and what was captured, with which app version and which profile.
+//! The settings are saved in `tags.json`. Title, author and comment may use
+//! `{name}` (the file name without extension) and `{date}` (the day the
+//! recording ended).
+//!
+//! Every container gets the title, artist (the author), comment, and a
+//! readable description of the source, version and profile. MKV and WebM
+//! take any tag, so they also get those three on their own for tools to
+//! read; MP4 and MOV only keep the standard ones.
+
+use crate::capture::{CaptureTarget, WindowInfo};
+use crate::config::{load_json, save_json};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const SETTINGS_FILE: &str = "tags.json";
+
+/// Tags the user sets for recordings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TagSettings {
+    /// Write tags into recordings as they're finalized
+    pub enabled: bool,
+    pub title: String,
+    pub author: String,
+    pub comment: String,
+}
+
+impl Default for TagSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            title: "{name}".to_string(),
+            author: String::new(),
+            comment: String::new(),
+        }
+    }
+}
+
+impl TagSettings {
+    /// Load the saved tag settings.
+    pub fn load() -> Self {
+        load_json(SETTINGS_FILE)
+    }
+
+    /// Save the tag settings.
+    pub fn save(&self) -> Result<(), String> {
+        save_json(SETTINGS_FILE, self)
+    }
+}
+
+/// What a recording was made from, for its tags.
+#[derive(Debug, Clone, Default)]
+pub struct RecordingContext {
+    /// What was captured, described
+    pub source: Option<String>,
+    /// Profile the settings came from
+    pub profile: Option<String>,
+}
+
+/// The tags to write into `video`, as container keys and values. Empty
+/// values are left out.
+pub fn recording_tags(settings: &TagSettings, video: &Path, context: &RecordingContext) -> Vec<(String, String)> {
+    if !settings.enabled {
+        return Vec::new();
+    }
+    let name = video.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+    let expand = |template: &str| template.replace("{name}", &name).replace("{date}", &date).trim().to_string();
+    let version = env!("CARGO_PKG_VERSION");
+
+    let mut description = "Recorded".to_string();
+    if let Some(source) = &context.source {
+        description.push_str(&format!(" from {}", source));
+    }
+    description.push_str(&format!(" with Screen Recorder {}", version));
+    if let Some(profile) = &context.profile {
+        description.push_str(&format!(" using the {} profile", profile));
+    }
+
+    let mut tags = vec![
+        ("title", expand(&settings.title)),
+        ("artist", expand(&settings.author)),
+        ("comment", expand(&settings.comment)),
+        ("description", description),
+    ];
+    let extension = video.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    if extension == "mkv" || extension == "webm" {
+        tags.extend([
+            ("capture_source", context.source.clone().unwrap_or_default()),
+            ("app_version", version.to_string()),
+            ("profile", context.profile.clone().unwrap_or_default()),
+        ]);
+    }
+    tags.into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(key, value)| (key.to_string(), value))
+        .collect()
+}
+
+/// Describe `target` for a recording's tags, naming windows from `windows`
+/// if they're still open.
+pub fn describe_source(target: &CaptureTarget, windows: &[WindowInfo]) -> String {
+    match target {
+        CaptureTarget::Window { window_handle } => match windows.iter().find(|w| w.handle == *window_handle) {
+            Some(window) if !window.process_name.is_empty() => {
+                format!("the window \"{}\" ({})", window.title, window.process_name)
+            }
+            Some(window) => format!("the window \"{}\"", window.title),
+            None => "a window".to_string(),
+        },
+        CaptureTarget::Region { region } => {
+            format!("a {}x{} region of display {}", region.width, region.height, region.monitor_id)
+        }
+        CaptureTarget::Display { monitor_id, width, height } => {
+            format!("display {} ({}x{})", monitor_id, width, height)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recording_tags() {
+        let settings = TagSettings {
+            author: "Sam".to_string(),
+            ..TagSettings::default()
+        };
+        let context = RecordingContext {
+            source: Some(describe_source(
+                &CaptureTarget::Display { monitor_id: "DP-1".to_string(), width: 2560, height: 1440 },
+                &[],
+            )),
+            profile: Some("Tutorial".to_string()),
+        };
+        let version = env!("CARGO_PKG_VERSION");
+        let tags = recording_tags(&settings, Path::new("/videos/Demo.mp4"), &context);
+        assert_eq!(
+            tags,
+            [
+                ("title".to_string(), "Demo".to_string()),
+                ("artist".to_string(), "Sam".to_string()),
+                (
+                    "description".to_string(),
+                    format!("Recorded from display DP-1 (2560x1440) with Screen Recorder {} using the Tutorial profile", version)
+                ),
+            ]
+        );
+
+        // Matroska keeps the details as tags of their own
+        let tags = recording_tags(&settings, Path::new("/videos/Demo.mkv"), &RecordingContext::default());
+        assert!(tags.contains(&("app_version".to_string(), version.to_string())));
+        assert!(!tags.iter().any(|(key, _)| key == "profile"));
+
+        let disabled = TagSettings { enabled: false, ..settings };
+        assert!(recording_tags(&disabled, Path::new("/videos/Demo.mkv"), &context).is_empty());
+    }
+}
@@ -0,0 +1,477 @@
+//! Test-signal recordings, for checking the pipeline end to end.
+//!
+//! A test-signal recording goes through the same encoder, muxer and audio
+//! sync as a real one, with the current encoder settings, but from
+//! generated input instead of a capture: SMPTE color bars with a burned-in
+//! timecode, and a 1 kHz tone. The tone sounds for the first tenth of every
+//! second, and a box in the corner of the bars flashes white at the same
+//! moments. Once the file is finished it's decoded again and each flash is
+//! matched with its beep, so the report tells whether the encoder and muxer
+//! produced a file of the right length with both tracks, and how far the
+//! audio ended up from the picture. On a new machine that separates
+//! encoding and sync problems from capture ones.
+//!
+//! The generated audio has no device latency, so the audio offsets set for
+//! real sources (see [`crate::avsync`]) aren't applied.
+
+use crate::avsync::{AudioSync, SyncClock};
+use crate::capture::audio::{WavWriter, CHANNELS, SAMPLE_RATE};
+use crate::capture::glyphs;
+use crate::capture::queue::{frame_channel, FrameSender};
+use crate::capture::{CapturedFrame, ColorSpace, PixelFormat};
+use crate::dynamics::DynamicsSettings;
+use crate::encoder::codec::VideoCodec;
+use crate::encoder::ffmpeg::{ffmpeg_stream_stdout, probe};
+use crate::encoder::filter::FilterChain;
+use crate::encoder::{encode_frames, generate_output_path, EncoderSettings};
+use crate::gpu::GpuPlacement;
+use crate::limits::{StopAt, StopPoint};
+use crate::postprocess::mux;
+use crate::stats::StatsCollector;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Size of the generated frames.
+const WIDTH: u32 = 1280;
+const HEIGHT: u32 = 720;
+
+/// Default and longest length of a test recording, in seconds.
+pub const DEFAULT_SECS: f64 = 10.0;
+const MAX_SECS: f64 = 600.0;
+
+/// Frequency of the tone, and its level (-18 dBFS).
+const TONE_HZ: f64 = 1000.0;
+const TONE_AMPLITUDE: f64 = 0.125;
+
+/// Share of every second the tone sounds and the box is white.
+const MARK_LENGTH: f64 = 0.1;
+
+/// The flashing box, as fractions of the frame: left, top, right, bottom.
+const FLASH_BOX: [f64; 4] = [0.86, 0.78, 0.98, 0.96];
+
+/// The strip under the bars: blue, black, magenta, black, cyan, black, white (BGRA)
+const REVERSE_BARS: [[u8; 4]; 7] = [
+    [191, 0, 0, 255],
+    [19, 19, 19, 255],
+    [191, 0, 191, 255],
+    [19, 19, 19, 255],
+    [191, 191, 0, 255],
+    [19, 19, 19, 255],
+    [191, 191, 191, 255],
+];
+
+/// The bottom row: -I, 100% white, +Q, then black with the PLUGE bars
+/// (below, at and above black) in the fifth column (BGRA)
+const BOTTOM_ROW: [[u8; 4]; 4] = [[76, 33, 0, 255], [255, 255, 255, 255], [106, 0, 50, 255], [19, 19, 19, 255]];
+const PLUGE: [[u8; 4]; 3] = [[9, 9, 9, 255], [19, 19, 19, 255], [29, 29, 29, 255]];
+
+/// What a test-signal recording showed.
+#[derive(Debug, Clone, Serialize)]
+pub struct TestSignalReport {
+    /// The recording, kept for a look
+    pub path: String,
+    /// FFmpeg encoder that wrote it, such as `h264_nvenc`
+    pub encoder: String,
+    /// Video codec, size and frame rate as read back from the file
+    pub codec: String,
+    pub width: u32,
+    pub height: u32,
+    pub fps: f64,
+    pub duration_secs: f64,
+    pub expected_secs: f64,
+    pub has_audio: bool,
+    /// Generated frames dropped before the encoder took them, usually while
+    /// it was starting
+    pub dropped_frames: u64,
+    /// How far each beep is from its flash, in milliseconds; positive when
+    /// the audio is late
+    pub sync_offsets_ms: Vec<f64>,
+    /// What's wrong, if anything
+    pub problems: Vec<String>,
+    pub passed: bool,
+}
+
+/// Record `secs` of test signal with `settings`, then check the file.
+///
+/// The recording is written to `output`, or next to the user's recordings.
+pub async fn record(mut settings: EncoderSettings, secs: f64, output: Option<PathBuf>) -> Result<TestSignalReport, String> {
+    if !(1.0..=MAX_SECS).contains(&secs) {
+        return Err(format!("Test recordings must be between 1 and {} seconds long", MAX_SECS));
+    }
+    if settings.codec.is_image_sequence() {
+        return Err("Test signals can't be checked in image sequences".to_string());
+    }
+    // The file is read back, and nothing is captured to pause on or caption
+    settings.encrypt = false;
+    settings.motion.enabled = false;
+    settings.idle_pause.enabled = false;
+    settings.live_captions.enabled = false;
+    let path = match output {
+        Some(path) => path,
+        None => generate_output_path("test-signal", settings.codec.extension())?,
+    };
+    settings.output_path = Some(path.clone());
+    let fps = settings.fps.max(1);
+    tracing::info!(target: "TestSignal", "Recording {:.0}s to {}", secs, path.display());
+
+    let clock = Arc::new(SyncClock::new());
+    let stats = Arc::new(StatsCollector::new());
+    let stop_at = Arc::new(StopAt::new(tokio::sync::broadcast::channel(4).0));
+    stop_at.reset(fps);
+    stop_at.schedule(StopPoint::Duration { secs })?;
+    let stop_flag = Arc::new(AtomicBool::new(false));
+
+    let (frame_tx, frame_rx) = frame_channel(30);
+    let generator_stop = Arc::new(AtomicBool::new(false));
+    let started = Instant::now();
+    let tone_start = clock.now();
+    {
+        let stop = generator_stop.clone();
+        std::thread::spawn(move || generate_frames(frame_tx, &stop, started, fps));
+    }
+    // The encoder stops itself on the last frame; this only covers one that
+    // never gets there
+    let deadline = {
+        let stop_flag = stop_flag.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs_f64(secs) + Duration::from_secs(30)).await;
+            tracing::warn!(target: "TestSignal", "The encoder didn't stop on time; stopping it");
+            stop_flag.store(true, Ordering::Relaxed);
+        })
+    };
+    let files = encode_frames(
+        frame_rx,
+        stop_flag.clone(),
+        Vec::new(),
+        settings.clone(),
+        GpuPlacement::default(),
+        stats.clone(),
+        FilterChain::default(),
+        clock.clone(),
+        stop_at.clone(),
+    )
+    .await;
+    deadline.abort();
+    generator_stop.store(true, Ordering::Relaxed);
+    let files = files?;
+    let video = files.first().map(|file| file.path.clone()).ok_or("Nothing was recorded")?;
+    if files.len() > 1 {
+        tracing::info!(target: "TestSignal", "The recording was split into {} files; checking the first", files.len());
+    }
+    let latest = stats.latest();
+    let audio_length = clock.now();
+    let video_start = clock.video_start();
+    let video_secs = stop_at.stopped_secs().unwrap_or(secs);
+
+    let with_audio = settings.codec != VideoCodec::Gif;
+    let check = move || {
+        if with_audio {
+            add_tone(&video, tone_start, audio_length, video_start, video_secs)?;
+        }
+        check(&video, secs, fps, with_audio)
+    };
+    let mut report = tokio::task::spawn_blocking(check)
+        .await
+        .map_err(|e| format!("Task error: {}", e))??;
+    report.encoder = latest.encoder;
+    report.dropped_frames = latest.dropped_frames;
+    for problem in &report.problems {
+        tracing::warn!(target: "TestSignal", "{}", problem);
+    }
+    tracing::info!(target: "TestSignal", "{}", if report.passed { "Passed" } else { "Failed" });
+    Ok(report)
+}
+
+/// Send frames at `fps` until `stop` is set, each stamped with the time it
+/// was due so its flash lines up with the tone exactly.
+fn generate_frames(frame_tx: FrameSender, stop: &AtomicBool, started: Instant, fps: u32) {
+    let bars = color_bars(WIDTH, HEIGHT);
+    let mut index = 0u64;
+    while !stop.load(Ordering::Relaxed) {
+        let due = started + Duration::from_secs_f64(index as f64 / fps as f64);
+        if let Some(wait) = due.checked_duration_since(Instant::now()) {
+            std::thread::sleep(wait);
+        }
+        let mut frame = CapturedFrame { captured_at: due, ..bars.clone() };
+        draw_overlay(&mut frame, index, fps);
+        if frame_tx.send(frame).is_err() {
+            break;
+        }
+        index += 1;
+    }
+}
+
+/// SMPTE color bars at `width`x`height`.
+fn color_bars(width: u32, height: u32) -> CapturedFrame {
+    let (w, h) = (width as usize, height as usize);
+    let mut frame = CapturedFrame {
+        width,
+        height,
+        data: vec![0; w * h * 4],
+        format: PixelFormat::Bgra8,
+        color: ColorSpace::SRGB,
+        captured_at: Instant::now(),
+        presented_at: None,
+    };
+    let column = |i: usize, of: usize| i * w / of;
+    let (bars_end, strip_end) = (h * 2 / 3, h * 3 / 4);
+    for (i, (bar, reverse)) in glyphs::COLOR_BARS.iter().zip(&REVERSE_BARS).enumerate() {
+        glyphs::fill_rect(&mut frame, column(i, 7), 0, column(i + 1, 7), bars_end, bar);
+        glyphs::fill_rect(&mut frame, column(i, 7), bars_end, column(i + 1, 7), strip_end, reverse);
+    }
+    // The bottom row is in sixths, with black after the first three
+    for (i, pixel) in BOTTOM_ROW.iter().enumerate() {
+        let end = if i == BOTTOM_ROW.len() - 1 { w } else { column(i + 1, 6) };
+        glyphs::fill_rect(&mut frame, column(i, 6), strip_end, end, h, pixel);
+    }
+    let (pluge_start, pluge_end) = (column(4, 6), column(5, 6));
+    for (i, pixel) in PLUGE.iter().enumerate() {
+        let x0 = pluge_start + (pluge_end - pluge_start) * i / 3;
+        let x1 = pluge_start + (pluge_end - pluge_start) * (i + 1) / 3;
+        glyphs::fill_rect(&mut frame, x0, strip_end, x1, h, pixel);
+    }
+    frame
+}
+
+/// Draw frame `index`'s timecode and, at the start of each second, the
+/// flash.
+fn draw_overlay(frame: &mut CapturedFrame, index: u64, fps: u32) {
+    let (w, h) = (frame.width as usize, frame.height as usize);
+    let scale = (h / 72).max(1);
+    let text = timecode(index, fps);
+    let (text_width, text_height) = (glyphs::text_width(&text, scale), glyphs::text_height(scale));
+    let (x, y) = ((w - text_width) / 2, h / 3 - text_height / 2);
+    glyphs::fill_rect(frame, x - 2 * scale, y - 2 * scale, x + text_width + 2 * scale, y + text_height + 2 * scale, &[0, 0, 0, 255]);
+    glyphs::draw_text(frame, x, y, scale, &text, &[255, 255, 255, 255]);
+
+    let flash = (index % fps as u64) as f64 / (fps as f64) < MARK_LENGTH;
+    let [left, top, right, bottom] = FLASH_BOX;
+    let pixel = if flash { [255, 255, 255, 255] } else { [0, 0, 0, 255] };
+    let at = |fraction: f64, size: usize| (fraction * size as f64) as usize;
+    glyphs::fill_rect(frame, at(left, w), at(top, h), at(right, w), at(bottom, h), &pixel);
+}
+
+/// Frame `index` at `fps` as an `HH:MM:SS:FF` timecode.
+fn timecode(index: u64, fps: u32) -> String {
+    let fps = fps.max(1) as u64;
+    let secs = index / fps;
+    format!("{:02}:{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60, index % fps)
+}
+
+/// Sample `n` of the tone, for a generator started at `start` seconds on
+/// the clock.
+fn tone_sample(n: u64, start: f64) -> i16 {
+    let t = n as f64 / SAMPLE_RATE as f64 - start;
+    if t < 0.0 || t.rem_euclid(1.0) >= MARK_LENGTH {
+        return 0;
+    }
+    let value = (2.0 * std::f64::consts::PI * TONE_HZ * t).sin() * TONE_AMPLITUDE;
+    (value * i16::MAX as f64) as i16
+}
+
+/// Write the tone heard over `length` of clock time, with the beeps lined
+/// up with the flashes of frames generated from `start`, and mux it into
+/// `video` as a recording's audio would be.
+fn add_tone(video: &Path, start: Duration, length: Duration, video_start: Duration, video_secs: f64) -> Result<(), String> {
+    let wav = std::env::temp_dir().join(format!("screen-recorder-test-tone-{}.wav", std::process::id()));
+    let written = (|| {
+        let mut writer = WavWriter::create(&wav)?;
+        let total = (length.as_secs_f64() * SAMPLE_RATE as f64) as u64;
+        let mut chunk = Vec::with_capacity(SAMPLE_RATE as usize * CHANNELS as usize * 2);
+        for n in 0..total {
+            let sample = tone_sample(n, start.as_secs_f64()).to_le_bytes();
+            for _ in 0..CHANNELS {
+                chunk.extend_from_slice(&sample);
+            }
+            if chunk.len() == chunk.capacity() {
+                writer.write(&chunk)?;
+                chunk.clear();
+            }
+        }
+        writer.write(&chunk)?;
+        writer.finish()
+    })();
+    let muxed = written.and_then(|()| {
+        let sync = AudioSync::plan_exact(video_start, 0, length.as_secs_f64(), video_secs);
+        mux::add_audio(video, &wav, &sync, &DynamicsSettings::default())
+    });
+    let _ = std::fs::remove_file(&wav);
+    muxed
+}
+
+/// Read `video` back and check it against a `secs` recording at `fps`,
+/// with the tone if `with_audio`.
+fn check(video: &Path, secs: f64, fps: u32, with_audio: bool) -> Result<TestSignalReport, String> {
+    let info = probe(video)?;
+    let input = video.to_string_lossy().to_string();
+    let mut problems = Vec::new();
+
+    let frame_secs = 1.0 / fps as f64;
+    if (info.duration - secs).abs() > 2.0 * frame_secs + 0.05 {
+        problems.push(format!("The recording is {:.3}s long, expected {:.3}s", info.duration, secs));
+    }
+
+    // The middle of the box at the recording's frame rate, one byte a frame
+    let [left, top, right, bottom] = FLASH_BOX;
+    let (margin_x, margin_y) = ((right - left) / 4.0, (bottom - top) / 4.0);
+    let filter = format!(
+        "fps=fps={}:start_time=0,crop=iw*{:.3}:ih*{:.3}:iw*{:.3}:ih*{:.3},scale=1:1:flags=area,format=gray",
+        fps,
+        right - left - 2.0 * margin_x,
+        bottom - top - 2.0 * margin_y,
+        left + margin_x,
+        top + margin_y
+    );
+    let mut levels = Vec::new();
+    ffmpeg_stream_stdout(
+        ["-i", &input, "-map", "0:v:0", "-vf", &filter, "-f", "rawvideo", "-"],
+        |data| levels.extend_from_slice(data),
+    )?;
+    let flashes = flash_times(&levels, fps);
+    let expected_marks = secs.floor() as usize;
+    if flashes.len() + 1 < expected_marks {
+        problems.push(format!("Only {} of {} flashes were found in the video", flashes.len(), expected_marks));
+    }
+
+    let mut sync_offsets_ms = Vec::new();
+    if info.has_audio {
+        let mut bytes = Vec::new();
+        let rate = SAMPLE_RATE.to_string();
+        ffmpeg_stream_stdout(
+            [
+                "-i", &input, "-map", "0:a:0", "-af", "aresample=async=1:first_pts=0", "-ac", "1", "-ar", &rate,
+                "-f", "s16le", "-",
+            ],
+            |data| bytes.extend_from_slice(data),
+        )?;
+        let samples: Vec<i16> = bytes.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+        let beeps = beep_times(&samples, SAMPLE_RATE);
+        if beeps.len() + 1 < expected_marks {
+            problems.push(format!("Only {} of {} beeps were found in the audio", beeps.len(), expected_marks));
+        }
+        sync_offsets_ms = sync_offsets(&flashes, &beeps).into_iter().map(|offset| offset * 1000.0).collect();
+        // Flashes can only show on frame times, so up to a frame late
+        let tolerance_ms = frame_secs * 1000.0 + 10.0;
+        if let Some(worst) = sync_offsets_ms.iter().copied().max_by(|a, b| a.abs().total_cmp(&b.abs())) {
+            if worst.abs() > tolerance_ms {
+                problems.push(format!(
+                    "The audio is up to {:.0} ms {} the picture",
+                    worst.abs(),
+                    if worst > 0.0 { "behind" } else { "ahead of" }
+                ));
+            }
+        } else if !flashes.is_empty() && !beeps.is_empty() {
+            problems.push("No beep lined up with a flash".to_string());
+        }
+    } else if with_audio {
+        problems.push("The recording has no audio".to_string());
+    }
+
+    Ok(TestSignalReport {
+        path: video.to_string_lossy().to_string(),
+        encoder: String::new(),
+        codec: info.video_codec,
+        width: info.width,
+        height: info.height,
+        fps: info.fps,
+        duration_secs: info.duration,
+        expected_secs: secs,
+        has_audio: info.has_audio,
+        dropped_frames: 0,
+        sync_offsets_ms,
+        passed: problems.is_empty(),
+        problems,
+    })
+}
+
+/// Times the box turns white, from its brightness in each frame at `fps`.
+fn flash_times(levels: &[u8], fps: u32) -> Vec<f64> {
+    let lit = |level: u8| level > 128;
+    levels
+        .iter()
+        .enumerate()
+        .filter(|&(i, &level)| lit(level) && (i == 0 || !lit(levels[i - 1])))
+        .map(|(i, _)| i as f64 / fps as f64)
+        .collect()
+}
+
+/// Times the tone starts, from mono `samples` at `rate`: the first
+/// millisecond loud enough after at least a fifth of a second of quiet.
+fn beep_times(samples: &[i16], rate: u32) -> Vec<f64> {
+    let block = (rate / 1000).max(1) as usize;
+    let threshold = (TONE_AMPLITUDE * i16::MAX as f64 / 4.0) as u16;
+    let quiet_blocks = 200;
+    let mut beeps = Vec::new();
+    let mut quiet = quiet_blocks;
+    for (i, chunk) in samples.chunks(block).enumerate() {
+        let peak = chunk.iter().map(|s| s.unsigned_abs()).max().unwrap_or(0);
+        if peak < threshold {
+            quiet += 1;
+            continue;
+        }
+        if quiet >= quiet_blocks {
+            // The first sample over the threshold, within the millisecond
+            let first = chunk.iter().position(|s| s.unsigned_abs() >= threshold).unwrap_or(0);
+            beeps.push((i * block + first) as f64 / rate as f64);
+        }
+        quiet = 0;
+    }
+    beeps
+}
+
+/// How far each beep is from the nearest flash, in seconds, for beeps
+/// within half a second of one.
+fn sync_offsets(flashes: &[f64], beeps: &[f64]) -> Vec<f64> {
+    flashes
+        .iter()
+        .filter_map(|flash| {
+            beeps
+                .iter()
+                .map(|beep| beep - flash)
+                .min_by(|a, b| a.abs().total_cmp(&b.abs()))
+                .filter(|offset| offset.abs() < 0.5)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timecode_and_bars() {
+        assert_eq!(timecode(0, 30), "00:00:00:00");
+        assert_eq!(timecode(30 * 3661 + 29, 30), "01:01:01:29");
+        let mut frame = color_bars(140, 72);
+        assert_eq!(&frame.data[..4], &glyphs::COLOR_BARS[0]);
+        assert_eq!(&frame.data[(139 * 4)..(140 * 4)], &glyphs::COLOR_BARS[6]);
+        // The box is white on the first frames of each second
+        let box_pixel = ((62 * 140) + 130) * 4;
+        draw_overlay(&mut frame, 30, 30);
+        assert_eq!(frame.data[box_pixel], 255);
+        draw_overlay(&mut frame, 40, 30);
+        assert_eq!(frame.data[box_pixel], 0);
+    }
+
+    #[test]
+    fn test_sync_analysis() {
+        // Beeps at 0.25s and 1.25s
+        let samples: Vec<i16> = (0..2 * SAMPLE_RATE as u64).map(|n| tone_sample(n, 0.25)).collect();
+        let beeps = beep_times(&samples, SAMPLE_RATE);
+        assert_eq!(beeps.len(), 2);
+        assert!((beeps[0] - 0.25).abs() < 0.001, "{:?}", beeps);
+
+        // The box lights on frames 8 to 10 and 38 to 40 at 30 fps
+        let levels: Vec<u8> = (0..60).map(|i| if (8..11).contains(&(i % 30)) { 235 } else { 16 }).collect();
+        let flashes = flash_times(&levels, 30);
+        assert_eq!(flashes.len(), 2);
+        let offsets = sync_offsets(&flashes, &beeps);
+        assert_eq!(offsets.len(), 2);
+        // The flash is on the frame after the beep starts
+        assert!(offsets.iter().all(|offset| (-0.034..=0.0).contains(offset)), "{:?}", offsets);
+        assert!(sync_offsets(&flashes, &[5.0]).is_empty());
+    }
+}
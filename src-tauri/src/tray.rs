@@ -0,0 +1,255 @@
+//! System tray icon with recording controls.
+//!
+//! The tray menu starts and stops recordings, selects the recording profile
+//! and opens the recordings folder, so the main window can stay minimized.
+//! Starting and stopping are sent to the frontend as `tray-action` events,
+//! since it holds the selected capture target. While recording, the icon
+//! flashes a red dot.
+
+use crate::i18n;
+use crate::state::RecordingPhase;
+use crate::AppState;
+use serde::Serialize;
+use std::time::Duration;
+use tauri::image::Image;
+use tauri::menu::{CheckMenuItem, IsMenuItem, Menu, MenuEvent, MenuItem, PredefinedMenuItem, Submenu};
+use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
+use tauri::{AppHandle, Emitter, Manager};
+
+const TRAY_ID: &str = "main";
+
+/// Event asking the frontend to start or stop recording.
+pub const TRAY_EVENT: &str = "tray-action";
+
+/// How often the icon is updated, which is also the flashing rate.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(600);
+
+/// Size of the recording indicator icon, in pixels.
+const ICON_SIZE: u32 = 32;
+
+/// Prefix of the profile menu items' IDs, followed by the profile name.
+const PROFILE_PREFIX: &str = "profile:";
+
+/// A recording control chosen from the tray menu.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrayAction {
+    Start,
+    Stop,
+}
+
+/// What the menu shows, rebuilt when it changes.
+#[derive(Debug, Clone, PartialEq)]
+struct MenuState {
+    phase: RecordingPhase,
+    profiles: Vec<String>,
+    selected: Option<String>,
+    /// Locale the menu is in
+    locale: String,
+}
+
+/// Add the tray icon.
+pub fn create(app: &AppHandle) -> tauri::Result<()> {
+    let state = MenuState {
+        phase: RecordingPhase::Idle,
+        profiles: Vec::new(),
+        selected: None,
+        locale: i18n::locale(),
+    };
+    let mut builder = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip(i18n::tr("tray-tooltip"))
+        .menu(&build_menu(app, &state)?)
+        .show_menu_on_left_click(false)
+        .on_menu_event(handle_menu_event)
+        .on_tray_icon_event(|tray, event| {
+            if let TrayIconEvent::Click {
+                button: MouseButton::Left,
+                button_state: MouseButtonState::Up,
+                ..
+            } = event
+            {
+                show_main_window(tray.app_handle());
+            }
+        });
+    if let Some(icon) = app.default_window_icon() {
+        builder = builder.icon(icon.clone());
+    }
+    builder.build(app)?;
+    Ok(())
+}
+
+fn build_menu(app: &AppHandle, state: &MenuState) -> tauri::Result<Menu<tauri::Wry>> {
+    let idle = state.phase.is_idle();
+    let start = MenuItem::with_id(app, "start", i18n::tr("tray-start"), idle, None::<&str>)?;
+    let stop = MenuItem::with_id(
+        app,
+        "stop",
+        i18n::tr("tray-stop"),
+        state.phase.is_recording(),
+        None::<&str>,
+    )?;
+
+    let profiles = state
+        .profiles
+        .iter()
+        .map(|name| {
+            let checked = state.selected.as_ref() == Some(name);
+            CheckMenuItem::with_id(app, format!("{}{}", PROFILE_PREFIX, name), name, idle, checked, None::<&str>)
+        })
+        .collect::<tauri::Result<Vec<_>>>()?;
+    let profile_items: Vec<&dyn IsMenuItem<tauri::Wry>> =
+        profiles.iter().map(|item| item as &dyn IsMenuItem<tauri::Wry>).collect();
+    let profile_menu = Submenu::with_items(app, i18n::tr("tray-profile"), !profiles.is_empty(), &profile_items)?;
+
+    Menu::with_items(
+        app,
+        &[
+            &start,
+            &stop,
+            &PredefinedMenuItem::separator(app)?,
+            &profile_menu,
+            &MenuItem::with_id(app, "open-folder", i18n::tr("tray-open-folder"), true, None::<&str>)?,
+            &PredefinedMenuItem::separator(app)?,
+            &MenuItem::with_id(app, "show", i18n::tr("tray-show"), true, None::<&str>)?,
+            &MenuItem::with_id(app, "quit", i18n::tr("tray-quit"), true, None::<&str>)?,
+        ],
+    )
+}
+
+fn handle_menu_event(app: &AppHandle, event: MenuEvent) {
+    match event.id.as_ref() {
+        "start" => send_action(app, TrayAction::Start),
+        "stop" => send_action(app, TrayAction::Stop),
+        "open-folder" => {
+            let opened = crate::encoder::default_output_dir()
+                .and_then(|dir| tauri_plugin_opener::open_path(dir, None::<&str>).map_err(|e| e.to_string()));
+            if let Err(e) = opened {
+                tracing::error!(target: "Tray", "Failed to open recordings folder: {}", e);
+            }
+        }
+        "show" => show_main_window(app),
+        "quit" => app.exit(0),
+        id => {
+            if let Some(name) = id.strip_prefix(PROFILE_PREFIX) {
+                let app = app.clone();
+                let name = name.to_string();
+                tauri::async_runtime::spawn(async move {
+                    let state = app.state::<AppState>();
+                    let selected = state.profiles.lock().await.select(&name);
+                    match selected {
+                        Ok(profile) => {
+                            crate::apply_profile(&state, &profile).await;
+                            let _ = app.emit("profile-selected", profile);
+                        }
+                        Err(e) => tracing::error!(target: "Tray", "{}", e),
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// Ask the frontend to start or stop recording.
+fn send_action(app: &AppHandle, action: TrayAction) {
+    if let Err(e) = app.emit(TRAY_EVENT, action) {
+        tracing::error!(target: "Tray", "Failed to send {:?}: {}", action, e);
+    }
+}
+
+fn show_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
+
+/// Keep the tray's menu and icon in step with the recording state, flashing
+/// the recording indicator while recording.
+///
+/// Runs for the lifetime of the app.
+pub async fn run_indicator(app: AppHandle) {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    let recording_icon = recording_icon();
+    let mut interval = tokio::time::interval(UPDATE_INTERVAL);
+    let mut shown: Option<MenuState> = None;
+    let mut lit = false;
+    loop {
+        interval.tick().await;
+
+        let app_state = app.state::<AppState>();
+        let phase = app_state.recording_manager.lock().await.get_phase();
+        let (profiles, selected) = {
+            let profiles = app_state.profiles.lock().await;
+            let names = profiles.list().into_iter().map(|profile| profile.name).collect();
+            (names, profiles.selected().map(|profile| profile.name.clone()))
+        };
+        let state = MenuState {
+            phase,
+            profiles,
+            selected,
+            locale: i18n::locale(),
+        };
+
+        if shown.as_ref() != Some(&state) {
+            match build_menu(&app, &state) {
+                Ok(menu) => {
+                    let _ = tray.set_menu(Some(menu));
+                }
+                Err(e) => tracing::error!(target: "Tray", "Failed to build menu: {}", e),
+            }
+            let tooltip = match state.phase {
+                RecordingPhase::Paused => "tray-tooltip-paused",
+                RecordingPhase::Recording => "tray-tooltip-recording",
+                RecordingPhase::Finalizing => "tray-tooltip-saving",
+                _ => "tray-tooltip",
+            };
+            let _ = tray.set_tooltip(Some(i18n::tr(tooltip)));
+        }
+
+        let flash = state.phase == RecordingPhase::Recording && !lit;
+        if flash != lit {
+            let icon = if flash {
+                Some(recording_icon.clone())
+            } else {
+                app.default_window_icon().cloned()
+            };
+            let _ = tray.set_icon(icon);
+        }
+        lit = flash;
+        shown = Some(state);
+    }
+}
+
+/// A red dot on a transparent background.
+fn recording_icon() -> Image<'static> {
+    let center = (ICON_SIZE as f32 - 1.0) / 2.0;
+    let radius = ICON_SIZE as f32 * 0.4;
+    let mut rgba = Vec::with_capacity((ICON_SIZE * ICON_SIZE * 4) as usize);
+    for y in 0..ICON_SIZE {
+        for x in 0..ICON_SIZE {
+            let distance = ((x as f32 - center).powi(2) + (y as f32 - center).powi(2)).sqrt();
+            // One pixel of antialiasing at the edge
+            let alpha = (radius - distance + 0.5).clamp(0.0, 1.0);
+            rgba.extend_from_slice(&[220, 30, 30, (alpha * 255.0) as u8]);
+        }
+    }
+    Image::new_owned(rgba, ICON_SIZE, ICON_SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recording_icon() {
+        let icon = recording_icon();
+        let pixel = |x: u32, y: u32| {
+            let i = ((y * ICON_SIZE + x) * 4) as usize;
+            icon.rgba()[i..i + 4].to_vec()
+        };
+        assert_eq!(pixel(ICON_SIZE / 2, ICON_SIZE / 2), [220, 30, 30, 255]);
+        assert_eq!(pixel(0, 0)[3], 0);
+    }
+}
@@ -0,0 +1,668 @@
+//! Uploading finished recordings to configured destinations.
+//!
+//! Destinations are S3-compatible buckets (AWS, MinIO, R2...), YouTube, or a
+//! generic HTTP endpoint, saved by name in `upload_targets.json`. Their
+//! credentials (S3 secret keys, YouTube tokens, webhook header values) are
+//! kept in the OS keychain instead, and handed back to the frontend as
+//! [`SECRET_PLACEHOLDER`], which saving a target takes as "unchanged". Each upload
+//! is an entry in the [`UploadQueue`] and runs as a background job reporting
+//! progress through `job-progress` events, retrying a few times before it's
+//! marked failed. Transfers go through the system `curl`, which also signs
+//! S3 requests; secrets are passed to it on stdin rather than the command
+//! line.
+
+mod s3;
+mod webhook;
+mod youtube;
+
+use crate::config::{load_json, save_json};
+use crate::jobs::JobContext;
+use crate::webhooks::{WebhookEvent, WebhookPayload};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub use s3::S3Destination;
+pub use webhook::WebhookDestination;
+pub use youtube::YouTubeDestination;
+
+const TARGETS_FILE: &str = "upload_targets.json";
+
+/// Keychain service the targets' credentials are saved under, one entry per
+/// target.
+const KEYCHAIN_SERVICE: &str = "screen-recorder-uploads";
+
+/// Stands in for a saved credential in targets given to the frontend.
+pub const SECRET_PLACEHOLDER: &str = "********";
+
+/// Attempts per upload before it's marked failed.
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Wait before the first retry, doubled for each one after.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Where an upload target sends recordings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Destination {
+    S3(S3Destination),
+    YouTube(YouTubeDestination),
+    Webhook(WebhookDestination),
+}
+
+impl Destination {
+    /// The destination's credentials, by a name that's stable across saves.
+    fn secrets_mut(&mut self) -> Vec<(String, &mut String)> {
+        match self {
+            Destination::S3(s3) => vec![("secret_key".to_string(), &mut s3.secret_key)],
+            Destination::YouTube(youtube) => vec![("access_token".to_string(), &mut youtube.access_token)],
+            Destination::Webhook(webhook) => webhook
+                .headers
+                .iter_mut()
+                .map(|(name, value)| (format!("header:{}", name), value))
+                .collect(),
+        }
+    }
+
+    /// Take the credentials out, leaving them empty.
+    fn take_secrets(&mut self) -> BTreeMap<String, String> {
+        self.secrets_mut()
+            .into_iter()
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(name, value)| (name, std::mem::take(value)))
+            .collect()
+    }
+
+    /// Put `secrets` back where they were taken from.
+    fn fill_secrets(&mut self, secrets: &BTreeMap<String, String>) {
+        for (name, value) in self.secrets_mut() {
+            if let Some(secret) = secrets.get(&name) {
+                *value = secret.clone();
+            }
+        }
+    }
+}
+
+/// A named, saved upload destination.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UploadTarget {
+    pub name: String,
+    #[serde(flatten)]
+    pub destination: Destination,
+}
+
+/// Saved upload targets.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UploadTargets {
+    pub targets: Vec<UploadTarget>,
+}
+
+impl UploadTargets {
+    /// Load the saved targets with their credentials from the keychain.
+    /// Credentials still saved in the file, as they were before they moved
+    /// to the keychain, are moved there.
+    pub fn load() -> Self {
+        let mut targets: Self = load_json(TARGETS_FILE);
+        let mut migrated = false;
+        for target in &mut targets.targets {
+            let plaintext = target.destination.take_secrets();
+            let mut secrets = load_secrets(&target.name).unwrap_or_else(|e| {
+                tracing::warn!(target: "Upload", "{}", e);
+                BTreeMap::new()
+            });
+            if !plaintext.is_empty() {
+                secrets.extend(plaintext);
+                match save_secrets(&target.name, &secrets) {
+                    Ok(()) => migrated = true,
+                    Err(e) => tracing::error!(target: "Upload", "Failed to move credentials to the keychain: {}", e),
+                }
+            }
+            target.destination.fill_secrets(&secrets);
+        }
+        if migrated {
+            tracing::info!(target: "Upload", "Moved upload credentials to the keychain");
+            if let Err(e) = targets.save() {
+                tracing::error!(target: "Upload", "{}", e);
+            }
+        }
+        targets
+    }
+
+    /// The targets with their credentials replaced by [`SECRET_PLACEHOLDER`],
+    /// to hand to the frontend.
+    pub fn redacted(mut self) -> Self {
+        for target in &mut self.targets {
+            for (_, value) in target.destination.secrets_mut() {
+                if !value.is_empty() {
+                    *value = SECRET_PLACEHOLDER.to_string();
+                }
+            }
+        }
+        self
+    }
+
+    /// Add `target`, replacing any target with the same name, and save.
+    /// Credentials given as [`SECRET_PLACEHOLDER`] keep their saved value.
+    pub fn save_target(mut target: UploadTarget) -> Result<Self, String> {
+        if target.name.trim().is_empty() {
+            return Err("Upload targets need a name".to_string());
+        }
+        let mut targets = Self::load();
+        let saved = targets
+            .get(&target.name)
+            .map(|existing| existing.destination.clone().take_secrets())
+            .unwrap_or_default();
+        for (name, value) in target.destination.secrets_mut() {
+            if value == SECRET_PLACEHOLDER {
+                *value = saved.get(&name).cloned().unwrap_or_default();
+            }
+        }
+        save_secrets(&target.name, &target.destination.clone().take_secrets())?;
+        match targets.targets.iter_mut().find(|t| t.name == target.name) {
+            Some(existing) => *existing = target,
+            None => targets.targets.push(target),
+        }
+        targets.save()?;
+        Ok(targets)
+    }
+
+    /// Remove the target called `name`, with its credentials, and save.
+    pub fn remove_target(name: &str) -> Result<Self, String> {
+        let mut targets = Self::load();
+        targets.targets.retain(|t| t.name != name);
+        targets.save()?;
+        save_secrets(name, &BTreeMap::new())?;
+        Ok(targets)
+    }
+
+    /// Save the targets without their credentials.
+    fn save(&self) -> Result<(), String> {
+        let mut targets = self.clone();
+        for target in &mut targets.targets {
+            target.destination.take_secrets();
+        }
+        save_json(TARGETS_FILE, &targets)
+    }
+
+    fn get(&self, name: &str) -> Option<&UploadTarget> {
+        self.targets.iter().find(|t| t.name == name)
+    }
+}
+
+fn keychain_entry(name: &str) -> Result<keyring::Entry, String> {
+    keyring::Entry::new(KEYCHAIN_SERVICE, name).map_err(|e| format!("Failed to open the keychain: {}", e))
+}
+
+/// The credentials saved for the target called `name`.
+fn load_secrets(name: &str) -> Result<BTreeMap<String, String>, String> {
+    match keychain_entry(name)?.get_password() {
+        Ok(json) => serde_json::from_str(&json).map_err(|e| format!("Invalid credentials for {:?}: {}", name, e)),
+        Err(keyring::Error::NoEntry) => Ok(BTreeMap::new()),
+        Err(e) => Err(format!("Failed to read the credentials for {:?}: {}", name, e)),
+    }
+}
+
+/// Save `secrets` for the target called `name`, deleting the entry if
+/// there are none.
+fn save_secrets(name: &str, secrets: &BTreeMap<String, String>) -> Result<(), String> {
+    let entry = keychain_entry(name)?;
+    if secrets.is_empty() {
+        return match entry.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(format!("Failed to delete the credentials for {:?}: {}", name, e)),
+        };
+    }
+    let json = serde_json::to_string(secrets).map_err(|e| format!("Failed to serialize credentials: {}", e))?;
+    entry
+        .set_password(&json)
+        .map_err(|e| format!("Failed to save the credentials for {:?}: {}", name, e))
+}
+
+/// Where an upload is in its lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UploadStatus {
+    Queued,
+    Uploading,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// An upload in the queue.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadEntry {
+    pub id: u64,
+    pub file_path: PathBuf,
+    /// Name of the upload target
+    pub target: String,
+    pub status: UploadStatus,
+    /// Completed fraction, 0.0 to 1.0
+    pub progress: f64,
+    /// Attempts made so far
+    pub attempts: u32,
+    /// Background job running the upload, while it runs
+    pub job_id: Option<u64>,
+    /// Where the uploaded recording can be found, if the destination says
+    pub url: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Uploads requested this session.
+#[derive(Clone, Default)]
+pub struct UploadQueue {
+    next_id: Arc<AtomicU64>,
+    entries: Arc<Mutex<Vec<UploadEntry>>>,
+}
+
+impl UploadQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue `file_path` for upload to the target called `target` and
+    /// return the entry's ID.
+    pub fn add(&self, file_path: PathBuf, target: &str) -> Result<u64, String> {
+        if UploadTargets::load().get(target).is_none() {
+            return Err(format!("Upload target {:?} not found", target));
+        }
+        if !file_path.is_file() {
+            return Err(format!("File not found: {}", file_path.display()));
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        self.entries.lock().unwrap().push(UploadEntry {
+            id,
+            file_path,
+            target: target.to_string(),
+            status: UploadStatus::Queued,
+            progress: 0.0,
+            attempts: 0,
+            job_id: None,
+            url: None,
+            error: None,
+        });
+        Ok(id)
+    }
+
+    /// All entries, oldest first.
+    pub fn list(&self) -> Vec<UploadEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    /// Record the job running the entry `id`.
+    pub fn set_job(&self, id: u64, job_id: u64) {
+        self.update(id, |entry| entry.job_id = Some(job_id));
+    }
+
+    /// The job running the entry `id`, if it's uploading.
+    pub fn job_id(&self, id: u64) -> Result<u64, String> {
+        self.list()
+            .into_iter()
+            .find(|entry| entry.id == id)
+            .and_then(|entry| entry.job_id)
+            .ok_or_else(|| format!("Upload {} is not running", id))
+    }
+
+    /// Where the entry `id` was uploaded to, once it's completed.
+    pub fn url(&self, id: u64) -> Option<String> {
+        self.list().into_iter().find(|entry| entry.id == id).and_then(|entry| entry.url)
+    }
+
+    /// Queue the failed or cancelled entry `id` again.
+    pub fn requeue(&self, id: u64) -> Result<(), String> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries
+            .iter_mut()
+            .find(|entry| entry.id == id)
+            .ok_or_else(|| format!("Upload {} not found", id))?;
+        if !matches!(entry.status, UploadStatus::Failed | UploadStatus::Cancelled) {
+            return Err("Only failed or cancelled uploads can be retried".to_string());
+        }
+        entry.status = UploadStatus::Queued;
+        entry.progress = 0.0;
+        entry.attempts = 0;
+        entry.error = None;
+        Ok(())
+    }
+
+    /// Remove the entry `id`, unless it's still queued or uploading.
+    pub fn remove(&self, id: u64) -> Result<(), String> {
+        let mut entries = self.entries.lock().unwrap();
+        if entries
+            .iter()
+            .any(|entry| entry.id == id && matches!(entry.status, UploadStatus::Queued | UploadStatus::Uploading))
+        {
+            return Err("Cancel the upload before removing it".to_string());
+        }
+        entries.retain(|entry| entry.id != id);
+        Ok(())
+    }
+
+    fn update(&self, id: u64, change: impl FnOnce(&mut UploadEntry)) {
+        if let Some(entry) = self.entries.lock().unwrap().iter_mut().find(|entry| entry.id == id) {
+            change(entry);
+        }
+    }
+
+    /// Upload the entry `id` from a background job, retrying failed attempts.
+    pub fn run(&self, id: u64, context: &JobContext) -> Result<PathBuf, String> {
+        let entry = self
+            .list()
+            .into_iter()
+            .find(|entry| entry.id == id)
+            .ok_or_else(|| format!("Upload {} not found", id))?;
+        let result = UploadTargets::load()
+            .get(&entry.target)
+            .cloned()
+            .ok_or_else(|| format!("Upload target {:?} not found", entry.target))
+            .and_then(|target| self.upload_with_retries(&entry, &target.destination, context));
+
+        match result {
+            Ok(url) => {
+                tracing::info!(target: "Upload", "Uploaded {} to {}", entry.file_path.display(), entry.target);
+                crate::webhooks::fire(WebhookPayload {
+                    file_path: Some(entry.file_path.to_string_lossy().to_string()),
+                    upload_target: Some(entry.target.clone()),
+                    url: url.clone(),
+                    ..WebhookPayload::new(WebhookEvent::UploadCompleted)
+                });
+                self.update(id, |entry| {
+                    entry.status = UploadStatus::Completed;
+                    entry.progress = 1.0;
+                    entry.url = url;
+                    entry.job_id = None;
+                });
+                Ok(entry.file_path)
+            }
+            Err(e) => {
+                let status = if context.is_cancelled() {
+                    UploadStatus::Cancelled
+                } else {
+                    UploadStatus::Failed
+                };
+                self.update(id, |entry| {
+                    entry.status = status;
+                    entry.error = Some(e.clone());
+                    entry.job_id = None;
+                });
+                Err(e)
+            }
+        }
+    }
+
+    fn upload_with_retries(
+        &self,
+        entry: &UploadEntry,
+        destination: &Destination,
+        context: &JobContext,
+    ) -> Result<Option<String>, String> {
+        let mut resume = None;
+        let mut delay = RETRY_DELAY;
+        for attempt in 1..=MAX_ATTEMPTS {
+            self.update(entry.id, |entry| {
+                entry.status = UploadStatus::Uploading;
+                entry.attempts = attempt;
+            });
+            let on_progress = |progress: f64| {
+                self.update(entry.id, |entry| entry.progress = progress);
+                context.report(progress);
+            };
+            let result = match destination {
+                Destination::S3(s3) => s3.upload(&entry.file_path, context.cancel_flag(), on_progress),
+                Destination::YouTube(youtube) => {
+                    youtube.upload(&entry.file_path, &mut resume, context.cancel_flag(), on_progress)
+                }
+                Destination::Webhook(webhook) => webhook.upload(&entry.file_path, context.cancel_flag(), on_progress),
+            };
+            match result {
+                Ok(url) => return Ok(url),
+                Err(e) if context.is_cancelled() || attempt == MAX_ATTEMPTS => return Err(e),
+                Err(e) => {
+                    tracing::warn!(target: "Upload", "Attempt {} failed ({}), retrying in {}s", attempt, e, delay.as_secs());
+                    // Sleep in steps so cancelling doesn't wait out the delay
+                    let until = std::time::Instant::now() + delay;
+                    while std::time::Instant::now() < until {
+                        if context.is_cancelled() {
+                            return Err("Cancelled".to_string());
+                        }
+                        std::thread::sleep(Duration::from_millis(200));
+                    }
+                    delay *= 2;
+                }
+            }
+        }
+        Err("No attempts made".to_string())
+    }
+}
+
+/// Response to a curl request.
+#[derive(Debug, Default, PartialEq)]
+struct CurlResponse {
+    status: u16,
+    /// Header names are lowercased; only present for requests made with `-i`
+    headers: BTreeMap<String, String>,
+    body: String,
+}
+
+impl CurlResponse {
+    fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Error for an unsuccessful response, with the start of its body.
+    fn error(&self) -> String {
+        let body: String = self.body.trim().chars().take(200).collect();
+        format!("Server returned HTTP {}: {}", self.status, body)
+    }
+}
+
+/// POST `body` as JSON to `url`, for notifications that don't upload a file.
+pub(crate) fn post_json(url: &str, headers: &BTreeMap<String, String>, body: &str) -> Result<(), String> {
+    let args = [
+        "-X",
+        "POST",
+        "-H",
+        "Content-Type: application/json",
+        "--max-time",
+        "15",
+        "--data-raw",
+        body,
+        url,
+    ]
+    .map(String::from);
+    // Headers often carry credentials
+    let secrets: Vec<(&str, String)> = headers
+        .iter()
+        .map(|(name, value)| ("header", format!("{}: {}", name, value)))
+        .collect();
+    let response = run_curl(&args, &secrets, &AtomicBool::new(false), |_| {})?;
+    if !response.is_success() {
+        return Err(response.error());
+    }
+    Ok(())
+}
+
+/// Run curl with `args`, reporting upload progress and honoring cancellation.
+///
+/// `secrets` are curl options (such as `user` or `header`) passed through a
+/// config file on stdin so they don't appear in the process list.
+/// `on_progress` receives the completed fraction (0.0 to 1.0). Setting
+/// `cancel` kills curl.
+fn run_curl(
+    args: &[String],
+    secrets: &[(&str, String)],
+    cancel: &AtomicBool,
+    mut on_progress: impl FnMut(f64),
+) -> Result<CurlResponse, String> {
+    let include_headers = args.iter().any(|arg| arg == "-i");
+    let mut child = Command::new("curl")
+        // Progress bar on stderr; the status code follows the body on stdout
+        .args(["-#", "-S", "-L", "-K", "-", "-w", "\n%{http_code}"])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to start curl: {}", e))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        let config: String = secrets
+            .iter()
+            .map(|(option, value)| format!("{} = \"{}\"\n", option, value.replace('\\', "\\\\").replace('"', "\\\"")))
+            .collect();
+        stdin
+            .write_all(config.as_bytes())
+            .map_err(|e| format!("Failed to configure curl: {}", e))?;
+    }
+
+    // Drain stdout on a separate thread so curl never blocks on a full pipe
+    let stdout_reader = child.stdout.take().map(|mut stdout| {
+        std::thread::spawn(move || {
+            let mut output = Vec::new();
+            let _ = stdout.read_to_end(&mut output);
+            String::from_utf8_lossy(&output).to_string()
+        })
+    });
+
+    // The progress bar redraws itself with carriage returns
+    let mut last_message = String::new();
+    if let Some(mut stderr) = child.stderr.take() {
+        let mut pending = Vec::new();
+        let mut buffer = [0u8; 512];
+        loop {
+            if cancel.load(Ordering::Relaxed) {
+                let _ = child.kill();
+                break;
+            }
+            let Ok(read) = stderr.read(&mut buffer) else { break };
+            if read == 0 {
+                break;
+            }
+            pending.extend_from_slice(&buffer[..read]);
+            while let Some(end) = pending.iter().position(|&b| b == b'\r' || b == b'\n') {
+                let segment = String::from_utf8_lossy(&pending[..end]).to_string();
+                pending.drain(..=end);
+                match parse_progress(&segment) {
+                    Some(percent) => on_progress((percent / 100.0).clamp(0.0, 1.0)),
+                    None if !segment.trim().is_empty() => last_message = segment.trim().to_string(),
+                    None => {}
+                }
+            }
+        }
+    }
+
+    let status = child.wait().map_err(|e| format!("curl process error: {}", e))?;
+    let stdout = stdout_reader.and_then(|handle| handle.join().ok()).unwrap_or_default();
+
+    if cancel.load(Ordering::Relaxed) {
+        return Err("Cancelled".to_string());
+    }
+    if !status.success() {
+        return Err(if last_message.is_empty() {
+            "curl failed".to_string()
+        } else {
+            last_message
+        });
+    }
+    Ok(parse_response(&stdout, include_headers))
+}
+
+/// Percentage shown by a curl progress bar segment, such as `###   45.3%`.
+fn parse_progress(segment: &str) -> Option<f64> {
+    segment
+        .trim_end()
+        .strip_suffix('%')?
+        .split_whitespace()
+        .last()?
+        .parse()
+        .ok()
+}
+
+/// Split curl's stdout into the final response's headers (with `-i`), body
+/// and the status code written by `-w`.
+fn parse_response(stdout: &str, include_headers: bool) -> CurlResponse {
+    let (rest, status) = stdout.rsplit_once('\n').unwrap_or(("", stdout));
+    let status = status.trim().parse().unwrap_or(0);
+    let mut rest = rest;
+    let mut headers = BTreeMap::new();
+    if include_headers {
+        // Interim responses (100 Continue) and redirects each add a header block
+        while rest.starts_with("HTTP/") {
+            let (block, body) = rest
+                .split_once("\r\n\r\n")
+                .or_else(|| rest.split_once("\n\n"))
+                .unwrap_or((rest, ""));
+            headers = block
+                .lines()
+                .skip(1)
+                .filter_map(|line| line.split_once(':'))
+                .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+                .collect();
+            rest = body;
+        }
+    }
+    CurlResponse {
+        status,
+        headers,
+        body: rest.to_string(),
+    }
+}
+
+/// Content type to upload `path` with.
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase).as_deref() {
+        Some("mp4") => "video/mp4",
+        Some("mov") => "video/quicktime",
+        Some("mkv") => "video/x-matroska",
+        Some("webm") => "video/webm",
+        Some("gif") => "image/gif",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_secrets() {
+        let mut destination = Destination::Webhook(WebhookDestination {
+            url: "https://example.com/upload".to_string(),
+            method: None,
+            headers: BTreeMap::from([("X-Api-Key".to_string(), "hunter2".to_string())]),
+            form_field: None,
+        });
+        let secrets = destination.take_secrets();
+        assert_eq!(secrets.get("header:X-Api-Key").map(String::as_str), Some("hunter2"));
+        assert!(!serde_json::to_string(&destination).unwrap().contains("hunter2"));
+
+        destination.fill_secrets(&secrets);
+        let targets = UploadTargets {
+            targets: vec![UploadTarget {
+                name: "api".to_string(),
+                destination,
+            }],
+        };
+        let redacted = serde_json::to_string(&targets.redacted()).unwrap();
+        assert!(!redacted.contains("hunter2"));
+        assert!(redacted.contains(SECRET_PLACEHOLDER));
+    }
+
+    #[test]
+    fn test_parse_curl_output() {
+        assert_eq!(parse_progress("######                      12.5%"), Some(12.5));
+        assert_eq!(parse_progress("curl: (6) Could not resolve host: example"), None);
+
+        let stdout = "HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\nLocation: https://example.com/session\r\n\r\n{\"id\":\"abc\"}\n200";
+        let response = parse_response(stdout, true);
+        assert_eq!(response.status, 200);
+        assert_eq!(response.headers.get("location").map(String::as_str), Some("https://example.com/session"));
+        assert_eq!(response.body, "{\"id\":\"abc\"}");
+        assert_eq!(parse_response("not found\n404", false).error(), "Server returned HTTP 404: not found");
+    }
+}
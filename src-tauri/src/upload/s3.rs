@@ -0,0 +1,126 @@
+//! Uploads to S3-compatible object storage.
+
+use super::{content_type, run_curl};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+
+/// An S3-compatible bucket: AWS S3, MinIO, Cloudflare R2 and the like.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct S3Destination {
+    /// Service endpoint, such as `https://s3.us-east-1.amazonaws.com` or
+    /// `http://localhost:9000`
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    /// Prepended to the file name to form the object key, such as `recordings/`
+    #[serde(default)]
+    pub prefix: String,
+    pub access_key: String,
+    pub secret_key: String,
+    /// Address the bucket in the path rather than the host name, as MinIO
+    /// and most self-hosted services need
+    #[serde(default)]
+    pub path_style: bool,
+    /// Base URL uploads are shared from, such as a CDN or the bucket's public
+    /// domain; without it, the object's URL is shared, which only works if
+    /// the bucket allows public reads
+    #[serde(default)]
+    pub public_url: Option<String>,
+}
+
+impl S3Destination {
+    /// URL of the object `key`.
+    fn object_url(&self, key: &str) -> Result<String, String> {
+        let endpoint = self.endpoint.trim_end_matches('/');
+        let key = encode_key(key);
+        if self.path_style {
+            return Ok(format!("{}/{}/{}", endpoint, self.bucket, key));
+        }
+        let (scheme, host) = endpoint
+            .split_once("://")
+            .ok_or_else(|| format!("Invalid S3 endpoint {:?}", self.endpoint))?;
+        Ok(format!("{}://{}.{}/{}", scheme, self.bucket, host, key))
+    }
+
+    /// Link for sharing the object `key`.
+    fn share_url(&self, key: &str) -> Result<String, String> {
+        match self.public_url {
+            Some(ref base) => Ok(format!("{}/{}", base.trim_end_matches('/'), encode_key(key))),
+            None => self.object_url(key),
+        }
+    }
+
+    /// Upload `path` with a single signed PUT and return its share link.
+    ///
+    /// Single PUTs are limited to 5 GB by S3.
+    pub fn upload(
+        &self,
+        path: &Path,
+        cancel: &AtomicBool,
+        on_progress: impl FnMut(f64),
+    ) -> Result<Option<String>, String> {
+        let file_name = path
+            .file_name()
+            .ok_or("Upload path has no file name")?
+            .to_string_lossy();
+        let key = format!("{}{}", self.prefix, file_name);
+        let url = self.object_url(&key)?;
+        let args = [
+            "--aws-sigv4".to_string(),
+            format!("aws:amz:{}:s3", self.region),
+            // Signing the payload would mean reading the whole file first
+            "-H".to_string(),
+            "x-amz-content-sha256: UNSIGNED-PAYLOAD".to_string(),
+            "-H".to_string(),
+            format!("Content-Type: {}", content_type(path)),
+            "-T".to_string(),
+            path.to_string_lossy().to_string(),
+            url,
+        ];
+        let secrets = [("user", format!("{}:{}", self.access_key, self.secret_key))];
+        let response = run_curl(&args, &secrets, cancel, on_progress)?;
+        if !response.is_success() {
+            return Err(response.error());
+        }
+        self.share_url(&key).map(Some)
+    }
+}
+
+/// Percent-encode an object key, keeping `/` separators.
+fn encode_key(key: &str) -> String {
+    key.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_object_url() {
+        let mut destination = S3Destination {
+            endpoint: "https://s3.eu-west-1.amazonaws.com/".to_string(),
+            region: "eu-west-1".to_string(),
+            bucket: "clips".to_string(),
+            prefix: "recordings/".to_string(),
+            access_key: String::new(),
+            secret_key: String::new(),
+            path_style: false,
+            public_url: None,
+        };
+        assert_eq!(
+            destination.object_url("recordings/my clip.mp4").unwrap(),
+            "https://clips.s3.eu-west-1.amazonaws.com/recordings/my%20clip.mp4"
+        );
+        destination.endpoint = "http://localhost:9000".to_string();
+        destination.path_style = true;
+        assert_eq!(destination.object_url("a.mp4").unwrap(), "http://localhost:9000/clips/a.mp4");
+        destination.public_url = Some("https://cdn.example.com/".to_string());
+        assert_eq!(destination.share_url("a b.mp4").unwrap(), "https://cdn.example.com/a%20b.mp4");
+    }
+}
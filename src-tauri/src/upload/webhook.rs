@@ -0,0 +1,63 @@
+//! Uploads to a generic HTTP endpoint.
+
+use super::{content_type, run_curl};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+
+/// An HTTP endpoint that accepts recordings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookDestination {
+    pub url: String,
+    /// HTTP method; POST by default
+    #[serde(default)]
+    pub method: Option<String>,
+    /// Extra headers, such as an API key
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Send the file as this multipart form field instead of as the raw body
+    #[serde(default)]
+    pub form_field: Option<String>,
+}
+
+impl WebhookDestination {
+    /// Send `path` to the endpoint. If it responds with JSON containing a
+    /// `url`, that's returned as where the recording can be found.
+    pub fn upload(
+        &self,
+        path: &Path,
+        cancel: &AtomicBool,
+        on_progress: impl FnMut(f64),
+    ) -> Result<Option<String>, String> {
+        let method = self.method.clone().unwrap_or_else(|| "POST".to_string());
+        let mut args = vec!["-X".to_string(), method];
+        match self.form_field {
+            Some(ref field) => args.extend([
+                "-F".to_string(),
+                format!("{}=@\"{}\";type={}", field, path.to_string_lossy().replace('"', "\\\""), content_type(path)),
+            ]),
+            None => args.extend([
+                "-H".to_string(),
+                format!("Content-Type: {}", content_type(path)),
+                "-T".to_string(),
+                path.to_string_lossy().to_string(),
+            ]),
+        }
+        args.push(self.url.clone());
+
+        // Headers often carry credentials
+        let secrets: Vec<(&str, String)> = self
+            .headers
+            .iter()
+            .map(|(name, value)| ("header", format!("{}: {}", name, value)))
+            .collect();
+        let response = run_curl(&args, &secrets, cancel, on_progress)?;
+        if !response.is_success() {
+            return Err(response.error());
+        }
+        Ok(serde_json::from_str::<serde_json::Value>(&response.body)
+            .ok()
+            .and_then(|body| body["url"].as_str().map(str::to_string)))
+    }
+}
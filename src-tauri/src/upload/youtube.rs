@@ -0,0 +1,183 @@
+//! Uploads to YouTube with the resumable upload protocol.
+//!
+//! An upload session is created first; if the transfer is interrupted, the
+//! next attempt asks the session how much arrived and sends the rest.
+
+use super::{content_type, run_curl, CurlResponse};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+
+const UPLOAD_URL: &str = "https://www.googleapis.com/upload/youtube/v3/videos?uploadType=resumable&part=snippet,status";
+
+/// Who can see uploaded videos.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum YouTubePrivacy {
+    #[default]
+    Private,
+    Unlisted,
+    Public,
+}
+
+/// A YouTube channel.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct YouTubeDestination {
+    /// OAuth 2.0 access token with the `youtube.upload` scope. Google's
+    /// tokens expire after an hour, so this has to be refreshed by the caller.
+    pub access_token: String,
+    #[serde(default)]
+    pub privacy: YouTubePrivacy,
+    #[serde(default)]
+    pub description: String,
+}
+
+impl YouTubeDestination {
+    /// Upload `path` as a video titled after the file and return its URL.
+    ///
+    /// `session` holds the upload session between attempts, so a retry
+    /// continues where the previous attempt stopped.
+    pub fn upload(
+        &self,
+        path: &Path,
+        session: &mut Option<String>,
+        cancel: &AtomicBool,
+        mut on_progress: impl FnMut(f64),
+    ) -> Result<Option<String>, String> {
+        let size = std::fs::metadata(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+            .len();
+        let secrets = [("header", format!("Authorization: Bearer {}", self.access_token))];
+
+        let (url, offset) = match session.clone() {
+            Some(url) => {
+                let response = run_curl(
+                    &[
+                        "-i".to_string(),
+                        "-X".to_string(),
+                        "PUT".to_string(),
+                        "-H".to_string(),
+                        format!("Content-Range: bytes */{}", size),
+                        "-H".to_string(),
+                        "Content-Length: 0".to_string(),
+                        url.clone(),
+                    ],
+                    &secrets,
+                    cancel,
+                    |_| {},
+                )?;
+                if response.is_success() {
+                    return video_url(&response);
+                }
+                if response.status != 308 {
+                    // The session expired; start over
+                    *session = None;
+                    return Err(response.error());
+                }
+                let offset = response.headers.get("range").and_then(|range| uploaded_bytes(range)).unwrap_or(0);
+                tracing::info!(target: "Upload", "Resuming YouTube upload at {} of {} bytes", offset, size);
+                (url, offset)
+            }
+            None => {
+                let url = self.create_session(path, size, &secrets, cancel)?;
+                *session = Some(url.clone());
+                (url, 0)
+            }
+        };
+
+        let mut args = vec![
+            "-H".to_string(),
+            format!("Content-Type: {}", content_type(path)),
+        ];
+        if offset > 0 {
+            args.extend([
+                "-C".to_string(),
+                offset.to_string(),
+                "-H".to_string(),
+                format!("Content-Range: bytes {}-{}/{}", offset, size.saturating_sub(1), size),
+            ]);
+        }
+        args.extend(["-T".to_string(), path.to_string_lossy().to_string(), url]);
+
+        // curl only counts the bytes it sends this time
+        let remaining = (size - offset.min(size)) as f64;
+        let response = run_curl(&args, &secrets, cancel, |fraction| {
+            on_progress((offset as f64 + fraction * remaining) / size.max(1) as f64)
+        })?;
+        if !response.is_success() {
+            return Err(response.error());
+        }
+        video_url(&response)
+    }
+
+    /// Start an upload session for `path` and return its URL.
+    fn create_session(
+        &self,
+        path: &Path,
+        size: u64,
+        secrets: &[(&str, String)],
+        cancel: &AtomicBool,
+    ) -> Result<String, String> {
+        let title = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "Recording".to_string());
+        let metadata = serde_json::json!({
+            "snippet": { "title": title, "description": self.description },
+            "status": { "privacyStatus": self.privacy },
+        });
+        let args = [
+            "-i".to_string(),
+            "-X".to_string(),
+            "POST".to_string(),
+            "-H".to_string(),
+            "Content-Type: application/json; charset=UTF-8".to_string(),
+            "-H".to_string(),
+            format!("X-Upload-Content-Length: {}", size),
+            "-H".to_string(),
+            format!("X-Upload-Content-Type: {}", content_type(path)),
+            "--data-binary".to_string(),
+            metadata.to_string(),
+            UPLOAD_URL.to_string(),
+        ];
+        let response = run_curl(&args, secrets, cancel, |_| {})?;
+        if !response.is_success() {
+            return Err(response.error());
+        }
+        response
+            .headers
+            .get("location")
+            .cloned()
+            .ok_or_else(|| "YouTube didn't return an upload session".to_string())
+    }
+}
+
+/// URL of the video described by a completed upload's response.
+fn video_url(response: &CurlResponse) -> Result<Option<String>, String> {
+    let video: serde_json::Value =
+        serde_json::from_str(&response.body).map_err(|e| format!("Invalid YouTube response: {}", e))?;
+    Ok(video["id"].as_str().map(|id| format!("https://youtu.be/{}", id)))
+}
+
+/// Bytes a session has received, from its `Range: bytes=0-N` header.
+fn uploaded_bytes(range: &str) -> Option<u64> {
+    let last: u64 = range.strip_prefix("bytes=")?.split_once('-')?.1.parse().ok()?;
+    Some(last + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resume_offset() {
+        assert_eq!(uploaded_bytes("bytes=0-1048575"), Some(1_048_576));
+        assert_eq!(uploaded_bytes("0-10"), None);
+        let response = CurlResponse {
+            status: 200,
+            body: r#"{"kind": "youtube#video", "id": "dQw4w9WgXcQ"}"#.to_string(),
+            ..CurlResponse::default()
+        };
+        assert_eq!(video_url(&response).unwrap().as_deref(), Some("https://youtu.be/dQw4w9WgXcQ"));
+    }
+}
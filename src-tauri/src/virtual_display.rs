@@ -0,0 +1,124 @@
+//! Virtual displays to record.
+//!
+//! A virtual display is a monitor with no screen attached: windows can be
+//! moved onto it and it's recorded like any other display, which suits
+//! automated environments without a monitor and recording a workspace that
+//! isn't on screen.
+//!
+//! On Hyprland these are headless outputs. Windows needs an indirect display
+//! driver to create monitors, which isn't something the app can install; any
+//! monitors such a driver adds are listed and recorded as usual.
+
+use std::sync::Mutex;
+
+/// Name of the virtual displays this app creates, followed by a number.
+const NAME_PREFIX: &str = "SR-VIRTUAL-";
+
+/// Virtual displays created by this app, which it removes when it exits.
+static CREATED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Create a `width` x `height` virtual display, returning its monitor ID.
+pub fn create(width: u32, height: u32) -> Result<String, String> {
+    if width < 64 || height < 64 || width > 7680 || height > 4320 {
+        return Err(format!("Unsupported virtual display size: {}x{}", width, height));
+    }
+    let mut created = CREATED.lock().unwrap();
+    let name = next_name(&created);
+    platform::create(&name, width, height)?;
+    tracing::info!(target: "VirtualDisplay", "Created {} ({}x{})", name, width, height);
+    created.push(name.clone());
+    Ok(name)
+}
+
+/// Remove a virtual display this app created.
+pub fn remove(monitor_id: &str) -> Result<(), String> {
+    let mut created = CREATED.lock().unwrap();
+    let index = created
+        .iter()
+        .position(|name| name == monitor_id)
+        .ok_or_else(|| format!("{} is not a virtual display created here", monitor_id))?;
+    platform::remove(monitor_id)?;
+    tracing::info!(target: "VirtualDisplay", "Removed {}", monitor_id);
+    created.remove(index);
+    Ok(())
+}
+
+/// IDs of the virtual displays this app created.
+pub fn created() -> Vec<String> {
+    CREATED.lock().unwrap().clone()
+}
+
+/// Remove every virtual display this app created.
+pub fn remove_all() {
+    for name in CREATED.lock().unwrap().drain(..) {
+        if let Err(e) = platform::remove(&name) {
+            tracing::error!(target: "VirtualDisplay", "Failed to remove {}: {}", name, e);
+        }
+    }
+}
+
+/// The first name not already taken by one of `created`.
+fn next_name(created: &[String]) -> String {
+    (1..)
+        .map(|n| format!("{}{}", NAME_PREFIX, n))
+        .find(|name| !created.contains(name))
+        .unwrap()
+}
+
+/// Hyprland monitor rule giving the display its size, to the right of the
+/// others at 1x scale.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn monitor_rule(name: &str, width: u32, height: u32) -> String {
+    format!("{},{}x{}@60,auto,1", name, width, height)
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::monitor_rule;
+    use crate::capture::linux::hyprctl;
+
+    pub fn create(name: &str, width: u32, height: u32) -> Result<(), String> {
+        if std::env::var("HYPRLAND_INSTANCE_SIGNATURE").is_err() {
+            return Err("Virtual displays require the Hyprland compositor".to_string());
+        }
+        hyprctl(&["output", "create", "headless", name])?;
+        if let Err(e) = hyprctl(&["keyword", "monitor", &monitor_rule(name, width, height)]) {
+            let _ = hyprctl(&["output", "remove", name]);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    pub fn remove(name: &str) -> Result<(), String> {
+        hyprctl(&["output", "remove", name])
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    pub fn create(_name: &str, _width: u32, _height: u32) -> Result<(), String> {
+        #[cfg(target_os = "windows")]
+        return Err("Creating virtual displays on Windows needs an indirect display driver; \
+                    install one and its displays are listed like any other monitor"
+            .to_string());
+        #[cfg(not(target_os = "windows"))]
+        Err("Virtual displays are not supported on this platform".to_string())
+    }
+
+    pub fn remove(_name: &str) -> Result<(), String> {
+        Err("Virtual displays are not supported on this platform".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_name() {
+        assert_eq!(next_name(&[]), "SR-VIRTUAL-1");
+        let created = ["SR-VIRTUAL-1".to_string(), "SR-VIRTUAL-3".to_string()];
+        assert_eq!(next_name(&created), "SR-VIRTUAL-2");
+        assert_eq!(monitor_rule("SR-VIRTUAL-2", 1920, 1080), "SR-VIRTUAL-2,1920x1080@60,auto,1");
+    }
+}
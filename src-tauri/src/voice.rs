@@ -0,0 +1,286 @@
+//! Starting and stopping recordings by voice.
+//!
+//! With the voice trigger enabled, the default microphone is monitored
+//! through FFmpeg at a low sample rate. Speaking above a threshold for long
+//! enough starts a recording, and a stretch of silence can stop it again, so
+//! narrated demos can be recorded hands-free. Like the tray, the trigger
+//! sends `voice-trigger` events to the frontend, which holds the selected
+//! capture target.
+
+use crate::capture::audio::LevelMeter;
+use crate::config::{load_json, save_json};
+use crate::permissions::{self, Permission};
+use crate::state::RecordingPhase;
+use crate::AppState;
+use ffmpeg_sidecar::command::FfmpegCommand;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+
+const SETTINGS_FILE: &str = "voice_trigger.json";
+
+/// Event asking the frontend to start or stop recording.
+pub const VOICE_EVENT: &str = "voice-trigger";
+
+/// Sample rate the microphone is monitored at; plenty for measuring levels.
+const SAMPLE_RATE: usize = 16_000;
+
+/// Length of audio each level is measured over.
+const BLOCK: Duration = Duration::from_millis(100);
+
+/// How long a requested start may take, including its countdown, before
+/// speech can request another.
+const START_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Stop flag of the running monitor, if any.
+static MONITOR: Mutex<Option<Arc<AtomicBool>>> = Mutex::new(None);
+
+/// Voice trigger settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VoiceTriggerSettings {
+    /// Monitor the microphone while the app is open
+    pub enabled: bool,
+    /// Level speech must reach, in dBFS
+    pub threshold_db: f32,
+    /// How long the level must stay above the threshold to start recording
+    pub start_after_ms: u32,
+    /// Stop after this many seconds of silence; `None` leaves stopping to the user
+    pub stop_after_silence_secs: Option<u32>,
+}
+
+impl Default for VoiceTriggerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold_db: -35.0,
+            start_after_ms: 500,
+            stop_after_silence_secs: Some(10),
+        }
+    }
+}
+
+impl VoiceTriggerSettings {
+    /// Load the saved settings.
+    pub fn load() -> Self {
+        load_json(SETTINGS_FILE)
+    }
+
+    /// Check and save the settings.
+    pub fn save(&self) -> Result<(), String> {
+        if !(-90.0..0.0).contains(&self.threshold_db) {
+            return Err("The voice threshold must be between -90 and 0 dB".to_string());
+        }
+        if self.start_after_ms == 0 || self.stop_after_silence_secs == Some(0) {
+            return Err("Voice trigger times must be greater than zero".to_string());
+        }
+        save_json(SETTINGS_FILE, self)
+    }
+}
+
+/// A recording control requested by voice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VoiceAction {
+    Start,
+    Stop,
+}
+
+/// Decides when to start and stop recording from successive microphone
+/// levels, one per [`BLOCK`].
+struct Trigger {
+    settings: VoiceTriggerSettings,
+    /// How long the level has been above the threshold
+    loud: Duration,
+    /// How long the level has been below the threshold while recording
+    silent: Duration,
+    /// Time since a start was requested that hasn't begun yet
+    start_pending: Option<Duration>,
+}
+
+impl Trigger {
+    fn new(settings: VoiceTriggerSettings) -> Self {
+        Self {
+            settings,
+            loud: Duration::ZERO,
+            silent: Duration::ZERO,
+            start_pending: None,
+        }
+    }
+
+    fn update(&mut self, peak_db: f32, phase: &RecordingPhase) -> Option<VoiceAction> {
+        let speaking = peak_db >= self.settings.threshold_db;
+        match phase {
+            RecordingPhase::Recording | RecordingPhase::Paused => {
+                self.loud = Duration::ZERO;
+                self.start_pending = None;
+                self.silent = if speaking { Duration::ZERO } else { self.silent + BLOCK };
+                let limit = Duration::from_secs(self.settings.stop_after_silence_secs? as u64);
+                if self.silent >= limit {
+                    self.silent = Duration::ZERO;
+                    return Some(VoiceAction::Stop);
+                }
+                None
+            }
+            RecordingPhase::Preparing | RecordingPhase::Finalizing => {
+                self.loud = Duration::ZERO;
+                self.silent = Duration::ZERO;
+                None
+            }
+            RecordingPhase::Idle | RecordingPhase::Done { .. } | RecordingPhase::Failed { .. } => {
+                self.silent = Duration::ZERO;
+                // The requested recording may still be counting down
+                if let Some(waited) = self.start_pending {
+                    if waited < START_TIMEOUT {
+                        self.start_pending = Some(waited + BLOCK);
+                        return None;
+                    }
+                    self.start_pending = None;
+                }
+                self.loud = if speaking { self.loud + BLOCK } else { Duration::ZERO };
+                if self.loud >= Duration::from_millis(self.settings.start_after_ms as u64) {
+                    self.loud = Duration::ZERO;
+                    self.start_pending = Some(Duration::ZERO);
+                    return Some(VoiceAction::Start);
+                }
+                None
+            }
+        }
+    }
+}
+
+/// Start or stop monitoring the microphone to match `settings`, replacing
+/// any running monitor.
+pub fn apply(app: &AppHandle, settings: VoiceTriggerSettings) {
+    let mut monitor = MONITOR.lock().unwrap();
+    if let Some(stop) = monitor.take() {
+        stop.store(true, Ordering::Relaxed);
+    }
+    if settings.enabled {
+        let stop = Arc::new(AtomicBool::new(false));
+        *monitor = Some(stop.clone());
+        tauri::async_runtime::spawn(run(app.clone(), settings, stop));
+    }
+}
+
+/// Send voice actions to the frontend until `stop` is set.
+async fn run(app: AppHandle, settings: VoiceTriggerSettings, stop: Arc<AtomicBool>) {
+    let (sender, mut levels) = tokio::sync::mpsc::channel(32);
+    std::thread::spawn(move || {
+        // Asks for the microphone the first time, rather than recording silence
+        if permissions::preflight(Permission::Microphone).is_err() {
+            tracing::warn!(target: "Voice", "No microphone permission; not listening");
+            return;
+        }
+        if let Err(e) = read_levels(&stop, |level| sender.blocking_send(level).is_ok()) {
+            tracing::error!(target: "Voice", "{}", e);
+        }
+    });
+
+    tracing::info!(target: "Voice", "Listening for speech above {} dB", settings.threshold_db);
+    let mut trigger = Trigger::new(settings);
+    while let Some(peak_db) = levels.recv().await {
+        let phase = app.state::<AppState>().recording_manager.lock().await.get_phase();
+        if let Some(action) = trigger.update(peak_db, &phase) {
+            tracing::info!(target: "Voice", "{:?} requested", action);
+            if let Err(e) = app.emit(VOICE_EVENT, action) {
+                tracing::error!(target: "Voice", "Failed to send {:?}: {}", action, e);
+            }
+        }
+    }
+}
+
+/// Read the microphone's peak level for each [`BLOCK`], passing it to
+/// `on_level` until `stop` is set or `on_level` returns false.
+fn read_levels(stop: &AtomicBool, mut on_level: impl FnMut(f32) -> bool) -> Result<(), String> {
+    let mut command = FfmpegCommand::new();
+    command
+        .args(["-hide_banner", "-nostdin"])
+        .args(microphone_input()?)
+        .args(["-ac", "1", "-ar", &SAMPLE_RATE.to_string(), "-f", "s16le", "pipe:1"]);
+
+    let inner_command = command.as_inner_mut();
+    inner_command.stdin(Stdio::null());
+    inner_command.stdout(Stdio::piped());
+    inner_command.stderr(Stdio::null());
+
+    let mut child = inner_command
+        .spawn()
+        .map_err(|e| format!("Failed to start FFmpeg: {}", e))?;
+    let mut stdout = child.stdout.take().ok_or("Failed to read from the microphone")?;
+
+    let meter = LevelMeter::default();
+    let mut block = vec![0u8; SAMPLE_RATE * BLOCK.as_millis() as usize / 1000 * 2];
+    let mut result = Ok(());
+    while !stop.load(Ordering::Relaxed) {
+        if stdout.read_exact(&mut block).is_err() {
+            result = Err("The microphone stopped".to_string());
+            break;
+        }
+        meter.measure(&block);
+        if !on_level(meter.take("microphone", BLOCK).peak_db) {
+            break;
+        }
+    }
+
+    let _ = child.kill();
+    let _ = child.wait();
+    result
+}
+
+/// FFmpeg input arguments for the default microphone.
+pub fn microphone_input() -> Result<Vec<String>, String> {
+    #[cfg(target_os = "windows")]
+    {
+        use crate::encoder::ffmpeg::ffmpeg_stderr;
+
+        let stderr = ffmpeg_stderr(["-list_devices", "true", "-f", "dshow", "-i", "dummy"])?;
+        let (id, name) = crate::devices::parse_dshow_devices(&stderr, "audio")
+            .into_iter()
+            .next()
+            .ok_or("No microphone found")?;
+        tracing::info!(target: "Voice", "Using microphone {}", name);
+        Ok(vec!["-f".into(), "dshow".into(), "-i".into(), format!("audio={}", id)])
+    }
+    #[cfg(target_os = "macos")]
+    {
+        // The first audio device, which is the built-in microphone if there is one
+        Ok(vec!["-f".into(), "avfoundation".into(), "-i".into(), ":0".into()])
+    }
+    #[cfg(target_os = "linux")]
+    {
+        Ok(vec!["-f".into(), "pulse".into(), "-i".into(), "default".into()])
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+    Err("The voice trigger is not supported on this platform".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trigger() {
+        let mut trigger = Trigger::new(VoiceTriggerSettings {
+            enabled: true,
+            threshold_db: -30.0,
+            start_after_ms: 300,
+            stop_after_silence_secs: Some(1),
+        });
+        let feed = |trigger: &mut Trigger, level: f32, blocks: usize, phase: RecordingPhase| {
+            (0..blocks).filter_map(|_| trigger.update(level, &phase)).collect::<Vec<_>>()
+        };
+
+        // A short noise doesn't start recording; sustained speech does, once
+        assert!(feed(&mut trigger, -10.0, 2, RecordingPhase::Idle).is_empty());
+        assert!(feed(&mut trigger, -50.0, 1, RecordingPhase::Idle).is_empty());
+        assert_eq!(feed(&mut trigger, -10.0, 10, RecordingPhase::Idle), [VoiceAction::Start]);
+
+        assert!(feed(&mut trigger, -10.0, 20, RecordingPhase::Recording).is_empty());
+        assert_eq!(feed(&mut trigger, -50.0, 10, RecordingPhase::Recording), [VoiceAction::Stop]);
+    }
+}
@@ -0,0 +1,167 @@
+//! Webhooks notified of recording events.
+//!
+//! Teams wire the recorder into chat notifications and asset pipelines by
+//! giving it URLs to POST a JSON payload to when a recording starts, stops,
+//! fails or finishes uploading. Webhooks are saved in `webhooks.json`; each
+//! can pick the events it gets (all of them by default) and send extra
+//! headers, such as a token. They're fired in the background, once, and a
+//! webhook that fails is only logged, so a dead endpoint never holds up a
+//! recording.
+
+use crate::capture::CaptureTarget;
+use crate::config::{load_json, save_json};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const SETTINGS_FILE: &str = "webhooks.json";
+
+/// Something a webhook can be notified of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    RecordingStarted,
+    RecordingStopped,
+    RecordingFailed,
+    UploadCompleted,
+}
+
+/// A URL notified of recording events.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Webhook {
+    pub url: String,
+    /// Events to send; all of them if empty
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+    /// Extra headers, such as an API key
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Webhook {
+    fn wants(&self, event: WebhookEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+}
+
+/// Saved webhooks.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WebhookSettings {
+    pub webhooks: Vec<Webhook>,
+}
+
+impl WebhookSettings {
+    /// Load the saved webhooks.
+    pub fn load() -> Self {
+        load_json(SETTINGS_FILE)
+    }
+
+    /// Check and save the webhooks.
+    pub fn save(&self) -> Result<(), String> {
+        if let Some(webhook) = self
+            .webhooks
+            .iter()
+            .find(|webhook| !webhook.url.starts_with("http://") && !webhook.url.starts_with("https://"))
+        {
+            return Err(format!("Webhook URLs must be http:// or https://: {}", webhook.url));
+        }
+        save_json(SETTINGS_FILE, self)
+    }
+}
+
+/// The JSON a webhook receives.
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookPayload {
+    pub event: WebhookEvent,
+    /// When it happened (RFC 3339)
+    pub timestamp: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_secs: Option<f64>,
+    /// What was recorded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<CaptureTarget>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Name of the upload target, for uploads
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload_target: Option<String>,
+    /// Where the upload can be found, if the destination says
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+impl WebhookPayload {
+    /// A payload for `event` happening now, without details.
+    pub fn new(event: WebhookEvent) -> Self {
+        Self {
+            event,
+            timestamp: chrono::Local::now().to_rfc3339(),
+            file_path: None,
+            duration_secs: None,
+            source: None,
+            error: None,
+            upload_target: None,
+            url: None,
+        }
+    }
+}
+
+/// Send `payload` to every webhook that wants its event, in the background.
+pub fn fire(payload: WebhookPayload) {
+    let webhooks: Vec<Webhook> = WebhookSettings::load()
+        .webhooks
+        .into_iter()
+        .filter(|webhook| webhook.wants(payload.event))
+        .collect();
+    if webhooks.is_empty() {
+        return;
+    }
+    let body = match serde_json::to_string(&payload) {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::error!(target: "Webhooks", "Failed to serialize {:?}: {}", payload.event, e);
+            return;
+        }
+    };
+    std::thread::spawn(move || {
+        for webhook in webhooks {
+            match crate::upload::post_json(&webhook.url, &webhook.headers, &body) {
+                Ok(()) => tracing::info!(target: "Webhooks", "Sent {:?} to {}", payload.event, webhook.url),
+                Err(e) => tracing::error!(target: "Webhooks", "Failed to send {:?} to {}: {}", payload.event, webhook.url, e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_webhook_payload() {
+        let settings: WebhookSettings = serde_json::from_str(
+            r#"{"webhooks":[{"url":"https://hooks.example.com/a"},{"url":"https://hooks.example.com/b","events":["recording_failed"]}]}"#,
+        )
+        .unwrap();
+        let wanting = |event| settings.webhooks.iter().filter(|webhook| webhook.wants(event)).count();
+        assert_eq!(wanting(WebhookEvent::RecordingStopped), 1);
+        assert_eq!(wanting(WebhookEvent::RecordingFailed), 2);
+
+        let payload = WebhookPayload {
+            file_path: Some("/videos/demo.mp4".to_string()),
+            duration_secs: Some(12.5),
+            source: Some(CaptureTarget::Window { window_handle: 42 }),
+            timestamp: "2026-01-02T03:04:05+00:00".to_string(),
+            ..WebhookPayload::new(WebhookEvent::RecordingStopped)
+        };
+        assert_eq!(
+            serde_json::to_string(&payload).unwrap(),
+            r#"{"event":"recording_stopped","timestamp":"2026-01-02T03:04:05+00:00","file_path":"/videos/demo.mp4","duration_secs":12.5,"source":{"type":"window","window_handle":42}}"#
+        );
+        let invalid = WebhookSettings {
+            webhooks: vec![Webhook { url: "ftp://example.com".to_string(), events: Vec::new(), headers: BTreeMap::new() }],
+        };
+        assert!(invalid.save().is_err());
+    }
+}
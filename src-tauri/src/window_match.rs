@@ -0,0 +1,129 @@
+//! Finding a window by application name and title, for automation.
+//!
+//! Window handles change every time a window opens, so scripts name the
+//! window instead ("Chrome's window whose title contains 'Meet'"). Both
+//! names match loosely, case-insensitively and without `.exe`: exactly,
+//! then as a prefix, then anywhere, then as a subsequence ("chrm" finds
+//! "chrome"). The best match wins; equally good ones are told apart by
+//! title, then process ID, then handle, so the same windows always give
+//! the same answer.
+
+use crate::capture::WindowInfo;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+
+/// Which window to find. At least one of the fields is needed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowQuery {
+    /// Application (process) name, e.g. "chrome"
+    #[serde(default)]
+    pub app: Option<String>,
+    /// Text in the window title, e.g. "Meet"
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+/// How well a name matches, best last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Match {
+    Subsequence,
+    Contains,
+    Prefix,
+    Exact,
+}
+
+/// The open window in `windows` that best matches `query`.
+pub fn find_window<'a>(windows: &'a [WindowInfo], query: &WindowQuery) -> Result<&'a WindowInfo, String> {
+    let app = query.app.as_deref().map(normalize).filter(|app| !app.is_empty());
+    let title = query.title.as_deref().map(normalize).filter(|title| !title.is_empty());
+    if app.is_none() && title.is_none() {
+        return Err("Give an application name or window title to look for".to_string());
+    }
+
+    windows
+        .iter()
+        .filter_map(|window| {
+            let app_match = match app {
+                Some(ref app) => Some(name_match(&normalize(&window.process_name), app)?),
+                None => None,
+            };
+            let title_match = match title {
+                Some(ref title) => Some(name_match(&window.title.to_lowercase(), title)?),
+                None => None,
+            };
+            Some(((app_match, title_match), window))
+        })
+        .min_by_key(|&(matched, window)| (Reverse(matched), window.title.to_lowercase(), window.pid, window.handle))
+        .map(|(_, window)| window)
+        .ok_or_else(|| {
+            let mut wanted = Vec::new();
+            if let Some(ref app) = query.app {
+                wanted.push(format!("application \"{}\"", app));
+            }
+            if let Some(ref title) = query.title {
+                wanted.push(format!("title \"{}\"", title));
+            }
+            format!("No open window matches {}", wanted.join(" and "))
+        })
+}
+
+/// Lowercase, without an executable extension.
+fn normalize(name: &str) -> String {
+    let name = name.trim().to_lowercase();
+    name.strip_suffix(".exe").map(str::to_string).unwrap_or(name)
+}
+
+/// How well `candidate` matches `wanted`, if at all.
+fn name_match(candidate: &str, wanted: &str) -> Option<Match> {
+    if candidate == wanted {
+        Some(Match::Exact)
+    } else if candidate.starts_with(wanted) {
+        Some(Match::Prefix)
+    } else if candidate.contains(wanted) {
+        Some(Match::Contains)
+    } else {
+        let mut chars = candidate.chars();
+        wanted
+            .chars()
+            .all(|c| c.is_whitespace() || chars.any(|other| other == c))
+            .then_some(Match::Subsequence)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(handle: isize, title: &str, process_name: &str) -> WindowInfo {
+        WindowInfo {
+            handle,
+            title: title.to_string(),
+            process_name: process_name.to_string(),
+            pid: 0,
+            class: String::new(),
+            icon: None,
+            thumbnail: None,
+        }
+    }
+
+    #[test]
+    fn test_find_window() {
+        let windows = [
+            window(5, "Inbox - Gmail - Google Chrome", "chrome.exe"),
+            window(4, "Meet - Standup - Google Chrome", "chrome.exe"),
+            window(3, "Meeting notes - Editor", "editor"),
+            window(2, "Chromium Dev", "chromium"),
+        ];
+        let found = |app: Option<&str>, title: Option<&str>| {
+            let query = WindowQuery { app: app.map(String::from), title: title.map(String::from) };
+            find_window(&windows, &query).map(|w| w.handle)
+        };
+        assert_eq!(found(Some("Chrome"), Some("meet")), Ok(4));
+        // Ties go to the first title in order
+        assert_eq!(found(Some("chrome"), None), Ok(5));
+        assert_eq!(found(Some("chrm"), Some("standup")), Ok(4));
+        assert_eq!(found(None, Some("Meeting")), Ok(3));
+        assert!(found(Some("firefox"), None).is_err());
+        assert!(found(None, None).is_err());
+    }
+}
@@ -0,0 +1,31 @@
+import { getCurrentWindow } from "@tauri-apps/api/window";
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+
+interface CountdownEvent {
+  remaining: number;
+  cancelled: boolean;
+}
+
+window.addEventListener("DOMContentLoaded", async () => {
+  const countEl = document.getElementById("count")!;
+  const currentWindow = getCurrentWindow();
+
+  // The first second is passed in the URL, since it's sent before this window exists
+  countEl.textContent = new URLSearchParams(window.location.search).get("remaining") ?? "";
+
+  await listen<CountdownEvent>("countdown", async (event) => {
+    if (event.payload.remaining === 0 || event.payload.cancelled) {
+      // Close before capture starts so the overlay isn't recorded
+      await currentWindow.close();
+    } else {
+      countEl.textContent = String(event.payload.remaining);
+    }
+  });
+
+  const cancel = () => invoke("cancel_countdown");
+  document.body.addEventListener("click", cancel);
+  window.addEventListener("keydown", (e) => {
+    if (e.key === "Escape") cancel();
+  });
+});
@@ -1,13 +1,17 @@
 import { invoke } from "@tauri-apps/api/core";
-import { revealItemInDir } from "@tauri-apps/plugin-opener";
+import { openUrl, revealItemInDir } from "@tauri-apps/plugin-opener";
 import { WebviewWindow } from "@tauri-apps/api/webviewWindow";
-import { listen } from "@tauri-apps/api/event";
+import { emit, listen } from "@tauri-apps/api/event";
 
 // Types matching Rust structs
 interface WindowInfo {
   handle: number;
   title: string;
   process_name: string;
+  pid: number;
+  class: string;
+  icon?: string;
+  thumbnail?: string;
 }
 
 interface MonitorInfo {
@@ -18,6 +22,10 @@ interface MonitorInfo {
   width: number;
   height: number;
   is_primary: boolean;
+  scale_factor: number;
+  model?: string | null;
+  refresh_rate?: number | null;
+  rotation: number;
 }
 
 interface CaptureRegion {
@@ -27,17 +35,157 @@ interface CaptureRegion {
   y: number;
   width: number;
   height: number;
+  space: "logical" | "physical";
+}
+
+interface Capabilities {
+  window_capture: boolean;
+  region_capture: boolean;
+  display_capture: boolean;
+  cursor_metadata: boolean;
+  cursor_modes: string[];
+  app_audio: boolean;
+  hdr: boolean;
+  deep_color: boolean;
+  highlight: boolean;
+  max_fps: number | null;
+  sandbox: string | null;
+}
+
+type PermissionStatus = "granted" | "denied" | "not_determined" | "not_required";
+
+interface PermissionReport {
+  screen_recording: PermissionStatus;
+  microphone: PermissionStatus;
+  accessibility: PermissionStatus;
+}
+
+interface MonitorChange {
+  added: MonitorInfo[];
+  removed: MonitorInfo[];
+  changed: MonitorInfo[];
+  monitors: MonitorInfo[];
+}
+
+interface AudioLevel {
+  source: string;
+  peak_db: number;
+  rms_db: number;
+  silent_secs: number;
+}
+
+interface Marker {
+  time_secs: number;
+  label: string | null;
+}
+
+interface Degradation {
+  reasons: ("battery" | "thermal")[];
+  changes: string[];
+}
+
+interface CongestionEvent {
+  congested: boolean;
+  quality: { bitrate_kbps: number; half_fps: boolean };
+}
+
+interface DiskSpaceEvent {
+  free_bytes: number;
+  threshold_mb: number;
+  stopping: boolean;
+}
+
+interface RecoveredRecording {
+  id: number;
+  file_path: string;
+  name: string;
+}
+
+interface LimitReached {
+  reason: "duration" | "file_size" | "stop_point";
+  elapsed_secs: number;
+  file_size_bytes: number;
+}
+
+interface SessionLockEvent {
+  locked: boolean;
+  response: "paused" | "stopping" | "resumed" | null;
+}
+
+interface CountdownEvent {
+  remaining: number;
+  cancelled: boolean;
+}
+
+interface ShareLink {
+  file_path: string;
+  url: string;
+}
+
+interface AspectRatio {
+  width: number;
+  height: number;
+}
+
+interface RegionSelectorSettings {
+  aspect_ratio: AspectRatio | null;
+  last_region: CaptureRegion | null;
+}
+
+type LastTarget =
+  | { kind: "window"; window: WindowInfo }
+  | { kind: "display"; monitor: MonitorInfo }
+  | { kind: "region"; region: Omit<CaptureRegion, "monitor_name"> };
+
+type CaptureEvent = {
+  event: "window_minimized" | "window_restored" | "window_closed" | "window_reattached" | "lost" | "recovered" | "ended";
+};
+
+type PortalEvent =
+  | { event: "started"; source_type: string; source_id: string; external: boolean }
+  | { event: "denied"; reason: string }
+  | { event: "ended" };
+
+interface PickerHealth {
+  state: "not_installed" | "not_configured" | "portal_not_running" | "ipc_server_down" | "unresponsive" | "healthy";
+  problem: string | null;
+  last_request: string | null;
 }
 
 type CaptureMode = "window" | "region" | "display";
-type RecordingState = "idle" | "recording" | "saving";
+type RecordingState = "idle" | "starting" | "recording" | "saving";
+
+// Where the recording is in its lifecycle, from `recording-phase` events
+type RecordingPhase =
+  | { phase: "idle" | "preparing" | "recording" | "paused" | "finalizing" }
+  | { phase: "done"; file_path: string | null; segments: string[] }
+  | { phase: "failed"; error: string };
+
+interface RecordedFile {
+  path: string;
+  width: number;
+  height: number;
+  frames: number;
+  duration_secs: number;
+  size_bytes: number;
+}
 
 interface RecordingResult {
   success: boolean;
   file_path: string | null;
+  segments: string[];
+  files: RecordedFile[];
   error: string | null;
 }
 
+// Error from a command, with a stable code and what to do about it
+interface AppError {
+  code: string;
+  message: string;
+  remediation: string | null;
+  settings_url: string | null;
+}
+
 // DOM Elements
 let windowListEl: HTMLElement | null;
 let windowSelectionEl: HTMLElement | null;
@@ -46,10 +194,16 @@ let regionDisplayEl: HTMLElement | null;
 let displaySelectionEl: HTMLElement | null;
 let displayListEl: HTMLElement | null;
 let recordBtn: HTMLButtonElement | null;
+let recordLastBtn: HTMLButtonElement | null;
 let refreshBtn: HTMLButtonElement | null;
 let refreshDisplaysBtn: HTMLButtonElement | null;
+let addVirtualDisplayBtn: HTMLButtonElement | null;
 let testPortalBtn: HTMLButtonElement | null;
+let restartPickerBtn: HTMLButtonElement | null;
+let installPickerBtn: HTMLButtonElement | null;
 let selectRegionBtn: HTMLButtonElement | null;
+let aspectRatioSelect: HTMLSelectElement | null;
+let aspectRatioCustomInput: HTMLInputElement | null;
 let modeWindowBtn: HTMLButtonElement | null;
 let modeRegionBtn: HTMLButtonElement | null;
 let modeDisplayBtn: HTMLButtonElement | null;
@@ -58,6 +212,7 @@ let statusEl: HTMLElement | null;
 let resultEl: HTMLElement | null;
 let resultPathEl: HTMLElement | null;
 let openFolderBtn: HTMLButtonElement | null;
+let errorDialog: HTMLDialogElement | null;
 
 // State
 let captureMode: CaptureMode = "window";
@@ -65,6 +220,10 @@ let selectedWindow: WindowInfo | null = null;
 let selectedRegion: CaptureRegion | null = null;
 let selectedDisplay: MonitorInfo | null = null;
 let regionSelectorWindow: WebviewWindow | null = null;
+let regionSelectorSettings: RegionSelectorSettings = { aspect_ratio: null, last_region: null };
+// Region to place the selector on once it opens, instead of the last one recorded
+let pendingPlacement: CaptureRegion | null = null;
+let countdownWindow: WebviewWindow | null = null;
 let currentState: RecordingState = "idle";
 let timerInterval: number | null = null;
 let recordingStartTime: number = 0;
@@ -78,10 +237,16 @@ window.addEventListener("DOMContentLoaded", () => {
   displaySelectionEl = document.querySelector("#display-selection");
   displayListEl = document.querySelector("#display-list");
   recordBtn = document.querySelector("#record-btn");
+  recordLastBtn = document.querySelector("#record-last-btn");
   refreshBtn = document.querySelector("#refresh-btn");
   refreshDisplaysBtn = document.querySelector("#refresh-displays-btn");
+  addVirtualDisplayBtn = document.querySelector("#add-virtual-display-btn");
   testPortalBtn = document.querySelector("#test-portal-btn");
+  restartPickerBtn = document.querySelector("#restart-picker-btn");
+  installPickerBtn = document.querySelector("#install-picker-btn");
   selectRegionBtn = document.querySelector("#select-region-btn");
+  aspectRatioSelect = document.querySelector("#aspect-ratio-select");
+  aspectRatioCustomInput = document.querySelector("#aspect-ratio-custom");
   modeWindowBtn = document.querySelector("#mode-window-btn");
   modeRegionBtn = document.querySelector("#mode-region-btn");
   modeDisplayBtn = document.querySelector("#mode-display-btn");
@@ -90,18 +255,39 @@ window.addEventListener("DOMContentLoaded", () => {
   resultEl = document.querySelector("#result");
   resultPathEl = document.querySelector("#result-path");
   openFolderBtn = document.querySelector("#open-folder-btn");
+  errorDialog = document.querySelector("#error-dialog");
 
   // Set up event listeners
   refreshBtn?.addEventListener("click", loadWindows);
   refreshDisplaysBtn?.addEventListener("click", loadDisplays);
+  addVirtualDisplayBtn?.addEventListener("click", addVirtualDisplay);
   testPortalBtn?.addEventListener("click", testLinuxPortal);
+  restartPickerBtn?.addEventListener("click", restartPickerService);
+  installPickerBtn?.addEventListener("click", installPicker);
   recordBtn?.addEventListener("click", handleRecordClick);
+  recordLastBtn?.addEventListener("click", recordLastSelection);
   openFolderBtn?.addEventListener("click", handleOpenFolder);
+  errorDialog?.querySelector("#error-dialog-close")?.addEventListener("click", () => errorDialog?.close());
+  errorDialog?.querySelector("#error-dialog-settings")?.addEventListener("click", openErrorSettings);
   selectRegionBtn?.addEventListener("click", openRegionSelector);
+  aspectRatioSelect?.addEventListener("change", handleAspectRatioChange);
+  aspectRatioCustomInput?.addEventListener("change", handleAspectRatioChange);
+  document.querySelectorAll<HTMLButtonElement>(".preset-btn").forEach((button) => {
+    button.addEventListener("click", () =>
+      selectRegionPreset(Number(button.dataset.width), Number(button.dataset.height)),
+    );
+  });
   modeWindowBtn?.addEventListener("click", () => setCaptureMode("window"));
   modeRegionBtn?.addEventListener("click", () => setCaptureMode("region"));
   modeDisplayBtn?.addEventListener("click", () => setCaptureMode("display"));
 
+  // The backend's recording phase drives the UI, whatever started or
+  // stopped the recording
+  listen<RecordingPhase>("recording-phase", (event) => applyPhase(event.payload));
+  invoke<RecordingPhase>("get_recording_phase")
+    .then(applyPhase)
+    .catch((error) => console.error("Failed to get the recording phase:", error));
+
   // Listen for region updates from selector window (continuous updates as user moves/resizes)
   listen<CaptureRegion>("region-updated", (event) => {
     console.log("Received region-updated:", event.payload);
@@ -110,9 +296,19 @@ window.addEventListener("DOMContentLoaded", () => {
     updateRecordButton();
   });
 
+  // Place a newly opened selector on a preset or the last region recorded
+  listen("region-selector-ready", () => {
+    const placement = pendingPlacement ?? regionSelectorSettings.last_region;
+    pendingPlacement = null;
+    if (placement) {
+      emit("region-selector-place", placement);
+    }
+  });
+
   // Listen for selector window closed
   listen("region-selector-closed", () => {
     regionSelectorWindow = null;
+    invoke("release_magnifier").catch(() => {});
     if (currentState === "idle") {
       selectedRegion = null;
       updateRegionDisplay();
@@ -120,10 +316,246 @@ window.addEventListener("DOMContentLoaded", () => {
     }
   });
 
+  // Listen for screen sharing through the portal picker (Linux only)
+  listen<PortalEvent>("portal-event", (event) => {
+    const portalEvent = event.payload;
+    if (portalEvent.event === "started" && portalEvent.external) {
+      setStatus(`Your ${portalEvent.source_type} ${portalEvent.source_id} is being shared with another app`);
+    } else if (portalEvent.event === "denied") {
+      setStatus(`Screen sharing request refused: ${portalEvent.reason}`, true);
+    }
+  });
+
+  // Listen for the portal picker breaking or recovering (Linux only)
+  listen<PickerHealth>("picker-health", (event) => showPickerHealth(event.payload));
+
+  // Listen for the recording's capture being lost, or its window minimized or closed
+  listen<CaptureEvent>("capture-event", (event) => {
+    if (currentState !== "recording") return;
+    switch (event.payload.event) {
+      case "window_minimized":
+        setStatus("The recorded window was minimized");
+        break;
+      case "window_restored":
+      case "window_reattached":
+      case "recovered":
+        setStatus("Recording...");
+        break;
+      case "window_closed":
+        setStatus("The recorded window was closed", true);
+        break;
+      case "lost":
+        setStatus("Capture lost, trying to reconnect...", true);
+        break;
+      case "ended":
+        // Nothing more will be captured; finish the file
+        stopRecording();
+        break;
+    }
+  });
+
+  // Listen for monitors being connected, disconnected or reconfigured
+  listen<MonitorChange>("monitors-changed", (event) => {
+    const { removed } = event.payload;
+    const recordedMonitor =
+      captureMode === "display" ? selectedDisplay?.id : captureMode === "region" ? selectedRegion?.monitor_id : undefined;
+    if (currentState === "recording" && removed.some((m) => m.id === recordedMonitor)) {
+      setStatus("The monitor being recorded was disconnected", true);
+    }
+    if (currentState === "idle" && captureMode === "display") {
+      loadDisplays();
+    }
+  });
+
+  // Warn when the recording's audio has gone quiet, e.g. a muted source
+  let warnedSilent = false;
+  listen<AudioLevel[]>("audio-levels", (event) => {
+    const silent = event.payload.find((level) => level.silent_secs >= 10);
+    if (silent && !warnedSilent) {
+      setStatus(`No ${silent.source} audio for ${Math.floor(silent.silent_secs)} seconds - check it isn't muted`, true);
+    } else if (!silent && warnedSilent) {
+      setStatus("Recording...");
+    }
+    warnedSilent = silent !== undefined;
+  });
+
+  // Confirm markers placed with the hotkey
+  listen<Marker>("marker-added", (event) => {
+    const secs = Math.floor(event.payload.time_secs);
+    const time = `${Math.floor(secs / 60)}:${String(secs % 60).padStart(2, "0")}`;
+    setStatus(`Marker added at ${time}`);
+  });
+
+  listen<string>("still-saved", (event) => {
+    setStatus(`Still saved to ${event.payload}`);
+  });
+
+  listen<boolean>("recording-idle", (event) => {
+    setStatus(event.payload ? "Paused while idle" : "Resumed on activity");
+  });
+
+  // Show the countdown before a recording starts; the overlay counts down
+  // and closes itself
+  listen<CountdownEvent>("countdown", (event) => {
+    if (event.payload.remaining > 0 && !countdownWindow) {
+      openCountdownOverlay(event.payload.remaining);
+    } else if (event.payload.remaining > 0) {
+      setStatus(`Recording in ${event.payload.remaining}...`);
+    }
+  });
+
+  // Start and stop from the tray menu
+  listen<"start" | "stop">("tray-action", (event) => {
+    if (event.payload === "start" && currentState === "idle") {
+      startRecording();
+    } else if (event.payload === "stop" && currentState === "recording") {
+      stopRecording();
+    }
+  });
+
+  // Record the last selection again from the hotkey
+  listen("record-last", () => {
+    recordLastSelection();
+  });
+
+  // Hands-free recording: speech starts a recording and silence stops it
+  listen<"start" | "stop">("voice-trigger", (event) => {
+    if (event.payload === "start" && currentState === "idle") {
+      setStatus("Speech detected, starting recording");
+      startRecording();
+    } else if (event.payload === "stop" && currentState === "recording") {
+      setStatus("Silence detected, stopping recording");
+      stopRecording();
+    }
+  });
+
+  // Uploads copy their link to the clipboard when they finish
+  listen<ShareLink>("share-link", (event) => {
+    setStatus(`Link copied: ${event.payload.url}`);
+  });
+
+  // Recordings a crash left unfinished are repaired at launch
+  listen<RecoveredRecording[]>("recordings-recovered", (event) => {
+    const count = event.payload.length;
+    setStatus(`Recovered ${count} interrupted recording${count === 1 ? "" : "s"} into the library`);
+  });
+
+  // Warn as the disk fills up, and finish the file before it's full
+  listen<DiskSpaceEvent>("disk-space", (event) => {
+    if (currentState !== "recording") return;
+    const free = (event.payload.free_bytes / (1024 * 1024 * 1024)).toFixed(1);
+    if (event.payload.stopping) {
+      setStatus(`Stopped recording: only ${free} GB free on the output disk`, true);
+      stopRecording();
+    } else {
+      setStatus(`Low disk space: ${free} GB free`, true);
+    }
+  });
+
+  // Finish the file when a recording reaches its duration or size limit
+  listen<LimitReached>("recording-limit", (event) => {
+    if (currentState !== "recording") return;
+    if (event.payload.reason === "stop_point") {
+      setStatus(`Stopped recording at ${event.payload.elapsed_secs.toFixed(3)}s`);
+      stopRecording();
+      return;
+    }
+    const limit = event.payload.reason === "duration"
+      ? `${Math.round(event.payload.elapsed_secs / 60)} minute limit`
+      : `${(event.payload.file_size_bytes / (1024 * 1024)).toFixed(0)} MB size limit`;
+    setStatus(`Stopped recording: reached the ${limit}`);
+    stopRecording();
+  });
+
+  // Recordings pause, or stop, while the session is locked
+  listen<SessionLockEvent>("session-lock", (event) => {
+    switch (event.payload.response) {
+      case "paused":
+        setStatus("Recording paused while the screen is locked");
+        break;
+      case "resumed":
+        setStatus("Recording resumed");
+        break;
+      case "stopping":
+        if (currentState === "recording") {
+          setStatus("Stopped recording: the screen was locked");
+          stopRecording();
+        }
+        break;
+    }
+  });
+
+  // Explain why a recording started at lower quality
+  listen<Degradation>("quality-degraded", (event) => {
+    const why = event.payload.reasons.includes("thermal") ? "the system is throttling" : "on battery";
+    setStatus(`Recording at reduced quality because ${why}: ${event.payload.changes.join(", ")}`);
+  });
+
+  // Warn when the live stream's viewers can't keep up
+  listen<CongestionEvent>("network-congestion", (event) => {
+    const { bitrate_kbps, half_fps } = event.payload.quality;
+    const rate = `${(bitrate_kbps / 1000).toFixed(1)} Mbps${half_fps ? " at half frame rate" : ""}`;
+    if (event.payload.congested) {
+      setStatus(`Network congestion: live stream lowered to ${rate}`, true);
+    } else {
+      setStatus(`Live stream raised to ${rate}`);
+    }
+  });
+
+  // Show the backend's text (tray menu, errors) in the UI's language
+  invoke("set_locale", { locale: navigator.language }).catch(() => {});
+
   // Initial load
+  loadCapabilities();
+  checkScreenPermission();
   loadWindows();
+  loadRegionSelectorSettings();
 });
 
+// Ask for screen recording permission up front, so the first recording
+// doesn't fail on it
+async function checkScreenPermission(): Promise<void> {
+  try {
+    const permissions = await invoke<PermissionReport>("get_permissions");
+    let status = permissions.screen_recording;
+    if (status === "not_determined") {
+      status = await invoke<PermissionStatus>("request_permission", { permission: "screen_recording" });
+    }
+    if (status === "denied") {
+      setStatus("Screen recording permission is off; allow it in the system settings to record", true);
+    }
+  } catch (error) {
+    console.error("Failed to check permissions:", error);
+  }
+}
+
+// Hide capture modes the backend can't do on this system
+async function loadCapabilities(): Promise<void> {
+  let capabilities: Capabilities;
+  try {
+    capabilities = await invoke<Capabilities>("get_capabilities");
+  } catch (error) {
+    console.error("Failed to get capabilities:", error);
+    return;
+  }
+
+  const modes: [CaptureMode, boolean, HTMLButtonElement | null][] = [
+    ["window", capabilities.window_capture, modeWindowBtn],
+    ["region", capabilities.region_capture, modeRegionBtn],
+    ["display", capabilities.display_capture, modeDisplayBtn],
+  ];
+  for (const [, supported, button] of modes) {
+    button?.classList.toggle("hidden", !supported);
+  }
+
+  const available = modes.filter(([, supported]) => supported);
+  if (available.length === 0) {
+    setStatus("Screen capture is not supported on this system", true);
+  } else if (!available.some(([mode]) => mode === captureMode)) {
+    setCaptureMode(available[0][0]);
+  }
+}
+
 // Set capture mode
 function setCaptureMode(mode: CaptureMode): void {
   if (currentState !== "idle") return;
@@ -187,6 +619,115 @@ function updateRegionDisplay(): void {
   }
 }
 
+// Parse a ratio such as "16:9"
+function parseAspectRatio(value: string): AspectRatio | null {
+  const match = value.trim().match(/^(\d+)\s*[:x×]\s*(\d+)$/);
+  if (!match) return null;
+  const width = parseInt(match[1], 10);
+  const height = parseInt(match[2], 10);
+  return width > 0 && height > 0 ? { width, height } : null;
+}
+
+// Show the saved aspect ratio lock
+async function loadRegionSelectorSettings(): Promise<void> {
+  if (!aspectRatioSelect || !aspectRatioCustomInput) return;
+  try {
+    regionSelectorSettings = await invoke<RegionSelectorSettings>("get_region_selector_settings");
+    const ratio = regionSelectorSettings.aspect_ratio;
+    const value = ratio ? `${ratio.width}:${ratio.height}` : "";
+    const preset = Array.from(aspectRatioSelect.options).some((option) => option.value === value);
+    aspectRatioSelect.value = preset ? value : "custom";
+    aspectRatioCustomInput.value = preset ? "" : value;
+    aspectRatioCustomInput.classList.toggle("hidden", preset);
+  } catch (error) {
+    console.error("Failed to load region selector settings:", error);
+  }
+}
+
+// Save the aspect ratio lock; an open selector picks it up straight away
+async function handleAspectRatioChange(): Promise<void> {
+  if (!aspectRatioSelect || !aspectRatioCustomInput) return;
+  const custom = aspectRatioSelect.value === "custom";
+  aspectRatioCustomInput.classList.toggle("hidden", !custom);
+
+  let aspectRatio: AspectRatio | null = null;
+  if (custom) {
+    aspectRatio = parseAspectRatio(aspectRatioCustomInput.value);
+    if (!aspectRatio) {
+      if (aspectRatioCustomInput.value) setStatus("Enter a ratio such as 21:9", true);
+      return;
+    }
+  } else if (aspectRatioSelect.value) {
+    aspectRatio = parseAspectRatio(aspectRatioSelect.value);
+  }
+
+  try {
+    const settings = { ...regionSelectorSettings, aspect_ratio: aspectRatio };
+    await invoke("set_region_selector_settings", { settings });
+    regionSelectorSettings = settings;
+  } catch (error) {
+    setStatus(`Failed to save aspect ratio: ${error}`, true);
+  }
+}
+
+// Show a preset-sized selection, on the monitor of the current one, for the user to place
+async function selectRegionPreset(width: number, height: number): Promise<void> {
+  try {
+    const region = await invoke<CaptureRegion>("preset_region", {
+      width,
+      height,
+      monitorId: selectedRegion?.monitor_id ?? null,
+    });
+    if (regionSelectorWindow) {
+      await emit("region-selector-place", region);
+      await regionSelectorWindow.setFocus();
+    } else {
+      pendingPlacement = region;
+      await openRegionSelector();
+    }
+  } catch (error) {
+    setStatus(`Failed to place ${width}×${height} region: ${error}`, true);
+  }
+}
+
+// Open the countdown overlay in the middle of the primary monitor
+async function openCountdownOverlay(remaining: number): Promise<void> {
+  setStatus(`Recording in ${remaining}...`);
+  try {
+    const monitors = await invoke<MonitorInfo[]>("get_monitors");
+    const primary = monitors.find(m => m.is_primary) || monitors[0];
+    const size = 200;
+    const scale = primary?.scale_factor || 1;
+    const isDev = window.location.hostname === "localhost";
+    const overlayUrl = isDev
+      ? "http://localhost:1420/src/countdown-overlay.html"
+      : "src/countdown-overlay.html";
+
+    const overlay = new WebviewWindow("countdown", {
+      url: `${overlayUrl}?remaining=${remaining}`,
+      title: "Countdown",
+      decorations: false,
+      transparent: true,
+      alwaysOnTop: true,
+      skipTaskbar: true,
+      resizable: false,
+      x: primary ? primary.x + Math.floor((primary.width / scale - size) / 2) : undefined,
+      y: primary ? primary.y + Math.floor((primary.height / scale - size) / 2) : undefined,
+      width: size,
+      height: size,
+    });
+    countdownWindow = overlay;
+    overlay.once("tauri://destroyed", () => {
+      countdownWindow = null;
+    });
+    overlay.once("tauri://error", () => {
+      countdownWindow = null;
+    });
+  } catch (error) {
+    console.warn("Failed to show countdown:", error);
+  }
+}
+
 // Open region selector - creates a draggable/resizable selection rectangle
 async function openRegionSelector(): Promise<void> {
   console.log("openRegionSelector called");
@@ -213,11 +754,22 @@ async function openRegionSelector(): Promise<void> {
     // Find primary monitor or use first one
     const primaryMonitor = monitors.find(m => m.is_primary) || monitors[0];
 
-    // Default selection size and position (centered on primary monitor)
+    // Default selection size and position (centered on primary monitor).
+    // Window sizes are logical, monitor sizes are physical
     const defaultWidth = 640;
     const defaultHeight = 480;
-    const startX = primaryMonitor.x + Math.floor((primaryMonitor.width - defaultWidth) / 2);
-    const startY = primaryMonitor.y + Math.floor((primaryMonitor.height - defaultHeight) / 2);
+    const scale = primaryMonitor.scale_factor || 1;
+    const offsetX = Math.floor((primaryMonitor.width / scale - defaultWidth) / 2);
+    const offsetY = Math.floor((primaryMonitor.height / scale - defaultHeight) / 2);
+    const startX = primaryMonitor.x + offsetX;
+    const startY = primaryMonitor.y + offsetY;
+
+    // Capture the screens for the magnifier before the selector covers them
+    try {
+      await invoke("prepare_magnifier");
+    } catch (e) {
+      console.warn("Magnifier unavailable:", e);
+    }
 
     // Determine the URL based on environment
     const isDev = window.location.hostname === "localhost";
@@ -273,10 +825,11 @@ async function openRegionSelector(): Promise<void> {
     selectedRegion = {
       monitor_id: primaryMonitor.id,
       monitor_name: primaryMonitor.name,
-      x: startX - primaryMonitor.x,
-      y: startY - primaryMonitor.y,
+      x: offsetX,
+      y: offsetY,
       width: defaultWidth,
       height: defaultHeight,
+      space: "logical",
     };
     updateRegionDisplay();
     updateRecordButton();
@@ -307,14 +860,78 @@ async function loadWindows(): Promise<void> {
     }
 
     windowListEl.innerHTML = "";
-    for (const win of windows) {
-      const item = createWindowItem(win);
-      windowListEl.appendChild(item);
+    for (const group of groupWindowsByApp(windows)) {
+      windowListEl.appendChild(createWindowGroup(group));
     }
   } catch (error) {
     windowListEl.innerHTML = `<p class="error">Error loading windows: ${error}</p>`;
     setStatus(`Error: ${error}`, true);
+    return;
+  }
+
+  loadWindowThumbnails(++windowListGeneration);
+}
+
+// Bumped on every window list load, so slow thumbnails from an earlier
+// load don't land in a newer list
+let windowListGeneration = 0;
+
+// Add thumbnails to the listed windows. They take a while to capture, so
+// the list is shown without them first.
+async function loadWindowThumbnails(generation: number): Promise<void> {
+  try {
+    const windows = await invoke<WindowInfo[]>("get_windows", { thumbnails: true });
+    if (generation !== windowListGeneration || !windowListEl) return;
+    for (const win of windows) {
+      if (!win.thumbnail) continue;
+      const item = windowListEl.querySelector<HTMLElement>(`.window-item[data-handle="${win.handle}"]`);
+      if (!item || item.querySelector(".window-item__thumbnail")) continue;
+      const img = document.createElement("img");
+      img.className = "window-item__thumbnail";
+      img.src = `data:image/png;base64,${win.thumbnail}`;
+      img.alt = "";
+      item.prepend(img);
+    }
+  } catch (error) {
+    console.error("Failed to load window thumbnails:", error);
+  }
+}
+
+// Group windows by application, in the order each application first appears
+function groupWindowsByApp(windows: WindowInfo[]): WindowInfo[][] {
+  const groups = new Map<string, WindowInfo[]>();
+  for (const win of windows) {
+    const key = win.process_name.toLowerCase();
+    const group = groups.get(key);
+    if (group) {
+      group.push(win);
+    } else {
+      groups.set(key, [win]);
+    }
+  }
+  return [...groups.values()];
+}
+
+// Create an application's group of window list items, headed by its icon and name
+function createWindowGroup(windows: WindowInfo[]): HTMLElement {
+  const group = document.createElement("div");
+  group.className = "window-group";
+
+  const app = windows[0];
+  const icon = windows.find((win) => win.icon)?.icon;
+  const header = document.createElement("div");
+  header.className = "window-group__header";
+  header.innerHTML = `
+    ${icon ? `<img class="window-group__icon" src="data:image/png;base64,${icon}" alt="" />` : '<span class="window-group__icon"></span>'}
+    <span class="window-group__name">${escapeHtml(app.process_name)}</span>
+    ${windows.length > 1 ? `<span class="window-group__count">${windows.length}</span>` : ""}
+  `;
+  group.appendChild(header);
+
+  for (const win of windows) {
+    group.appendChild(createWindowItem(win));
   }
+  return group;
 }
 
 // Create a window list item element
@@ -322,10 +939,10 @@ function createWindowItem(win: WindowInfo): HTMLElement {
   const item = document.createElement("div");
   item.className = "window-item";
   item.dataset.handle = String(win.handle);
+  item.title = win.pid ? `${win.process_name} (PID ${win.pid})` : win.process_name;
 
   item.innerHTML = `
     <div class="window-item__title">${escapeHtml(win.title)}</div>
-    <div class="window-item__process">${escapeHtml(win.process_name)}</div>
   `;
 
   item.addEventListener("click", () => selectWindow(win, item));
@@ -342,7 +959,10 @@ async function loadDisplays(): Promise<void> {
   updateRecordButton();
 
   try {
-    const displays = await invoke<MonitorInfo[]>("get_monitors");
+    const [displays, virtualDisplays] = await Promise.all([
+      invoke<MonitorInfo[]>("get_monitors"),
+      invoke<string[]>("list_virtual_displays"),
+    ]);
 
     if (displays.length === 0) {
       displayListEl.innerHTML = '<p class="empty">No displays found</p>';
@@ -351,7 +971,7 @@ async function loadDisplays(): Promise<void> {
 
     displayListEl.innerHTML = "";
     for (const display of displays) {
-      const item = createDisplayItem(display);
+      const item = createDisplayItem(display, virtualDisplays.includes(display.id));
       displayListEl.appendChild(item);
     }
   } catch (error) {
@@ -360,20 +980,62 @@ async function loadDisplays(): Promise<void> {
   }
 }
 
+// Create a virtual display to record
+async function addVirtualDisplay(): Promise<void> {
+  try {
+    const monitorId = await invoke<string>("create_virtual_display", { width: 1920, height: 1080 });
+    setStatus(`Created virtual display ${monitorId}`);
+    await loadDisplays();
+  } catch (error) {
+    setStatus(`Failed to create a virtual display: ${error}`, true);
+  }
+}
+
+// Remove a virtual display this app created
+async function removeVirtualDisplay(monitorId: string): Promise<void> {
+  try {
+    await invoke("remove_virtual_display", { monitorId });
+    setStatus(`Removed virtual display ${monitorId}`);
+    await loadDisplays();
+  } catch (error) {
+    setStatus(`Failed to remove the virtual display: ${error}`, true);
+  }
+}
+
 // Create a display list item element
-function createDisplayItem(display: MonitorInfo): HTMLElement {
+function createDisplayItem(display: MonitorInfo, isVirtual = false): HTMLElement {
   const item = document.createElement("div");
   item.className = "display-item";
   item.dataset.id = display.id;
 
   const primaryBadge = display.is_primary
     ? '<span class="display-item__primary">Primary</span>'
-    : "";
+    : isVirtual
+      ? '<span class="display-item__primary">Virtual</span>'
+      : "";
+
+  const details = [`${display.width} x ${display.height}`];
+  if (display.refresh_rate) details.push(`${Math.round(display.refresh_rate)} Hz`);
+  if (display.scale_factor !== 1) details.push(`${Math.round(display.scale_factor * 100)}%`);
+  if (display.rotation) details.push(`rotated ${display.rotation}°`);
 
   item.innerHTML = `
     <div class="display-item__name">${escapeHtml(display.name)}${primaryBadge}</div>
-    <div class="display-item__resolution">${display.width} x ${display.height}</div>
+    <div class="display-item__resolution">${details.join(" · ")}</div>
   `;
+  item.title = display.id;
+
+  if (isVirtual) {
+    const remove = document.createElement("button");
+    remove.type = "button";
+    remove.className = "display-item__remove";
+    remove.textContent = "Remove";
+    remove.addEventListener("click", (event) => {
+      event.stopPropagation();
+      removeVirtualDisplay(display.id);
+    });
+    item.appendChild(remove);
+  }
 
   item.addEventListener("click", () => selectDisplayItem(display, item));
 
@@ -434,6 +1096,43 @@ async function testLinuxPortal(): Promise<void> {
   }
 }
 
+// Show a broken link between the portal and the picker, offering a restart
+let pickerWasHealthy = true;
+function showPickerHealth(health: PickerHealth): void {
+  const restartable = health.state === "ipc_server_down" || health.state === "unresponsive" || health.state === "portal_not_running";
+  const installable = health.state === "not_installed" || health.state === "not_configured";
+  restartPickerBtn?.classList.toggle("hidden", !restartable);
+  installPickerBtn?.classList.toggle("hidden", !installable);
+  if (health.problem) {
+    setStatus(`Screen sharing won't work: ${health.problem}`, true);
+  } else if (!pickerWasHealthy) {
+    setStatus("Screen sharing picker is working again");
+  }
+  pickerWasHealthy = health.problem === null;
+}
+
+async function restartPickerService(): Promise<void> {
+  setStatus("Restarting the portal picker...");
+  try {
+    const health = await invoke<PickerHealth>("restart_picker_service");
+    showPickerHealth(health);
+    if (!health.problem) setStatus("Portal picker restarted");
+  } catch (error) {
+    setStatus(`Failed to restart the picker: ${error}`, true);
+  }
+}
+
+async function installPicker(): Promise<void> {
+  setStatus("Installing the portal picker...");
+  try {
+    const health = await invoke<PickerHealth>("install_picker");
+    showPickerHealth(health);
+    if (!health.problem) setStatus("Portal picker installed");
+  } catch (error) {
+    setStatus(`Failed to install the picker: ${error}`, true);
+  }
+}
+
 // Select a window for recording
 function selectWindow(win: WindowInfo, element: HTMLElement): void {
   if (currentState !== "idle") return;
@@ -490,7 +1189,10 @@ async function startRecording(): Promise<void> {
         y: Math.round(selectedRegion.y),
         width: Math.round(selectedRegion.width),
         height: Math.round(selectedRegion.height),
+        space: selectedRegion.space,
       });
+      // The backend saved it too; the selector reopens on it next time
+      regionSelectorSettings.last_region = { ...selectedRegion };
     } else if (captureMode === "display" && selectedDisplay) {
       console.log("Starting display recording with:", selectedDisplay);
       await invoke("start_display_recording", {
@@ -498,39 +1200,93 @@ async function startRecording(): Promise<void> {
       });
     }
 
-    currentState = "recording";
-    updateRecordButton();
-    startTimer();
     setStatus("Recording...");
   } catch (error) {
-    setStatus(`Failed to start recording: ${error}`, true);
+    reportError("Failed to start recording", error);
     disableSelection(false);
   }
 }
 
+// Record the last window, display, or region again, skipping the picker
+async function recordLastSelection(): Promise<void> {
+  if (currentState !== "idle") return;
+
+  let target: LastTarget;
+  try {
+    target = await invoke<LastTarget>("resolve_last_selection");
+  } catch (error) {
+    setStatus(`Can't record the last selection: ${error}`, true);
+    return;
+  }
+
+  setCaptureMode(target.kind);
+  switch (target.kind) {
+    case "window":
+      selectedWindow = target.window;
+      break;
+    case "display":
+      selectedDisplay = target.monitor;
+      break;
+    case "region": {
+      const monitors = await invoke<MonitorInfo[]>("get_monitors");
+      const monitor = monitors.find((m) => m.id === target.region.monitor_id);
+      selectedRegion = { ...target.region, monitor_name: monitor?.name ?? target.region.monitor_id };
+      updateRegionDisplay();
+      break;
+    }
+  }
+  await startRecording();
+}
+
 // Stop recording
 async function stopRecording(): Promise<void> {
   setStatus("Stopping recording...");
-  currentState = "saving";
-  updateRecordButton();
-  stopTimer();
 
   try {
     const result = await invoke<RecordingResult>("stop_recording");
 
     if (result.success && result.file_path) {
       showResult(result.file_path);
-      setStatus("Recording saved successfully!");
+      if (result.segments.length > 0) {
+        setStatus(`Recording saved in ${result.segments.length + 1} parts after resolution changes`);
+      } else {
+        setStatus("Recording saved successfully!");
+      }
     } else {
       setStatus(`Recording failed: ${result.error || "Unknown error"}`, true);
     }
   } catch (error) {
     setStatus(`Error stopping recording: ${error}`, true);
   }
+}
+
+// Follow the backend's recording phase: the record button, the timer and
+// whether the selection can change
+function applyPhase(phase: RecordingPhase): void {
+  const previous = currentState;
+  switch (phase.phase) {
+    case "preparing":
+      currentState = "starting";
+      break;
+    case "recording":
+    case "paused":
+      currentState = "recording";
+      break;
+    case "finalizing":
+      currentState = "saving";
+      break;
+    default:
+      currentState = "idle";
+  }
+  if (currentState === previous) return;
 
-  currentState = "idle";
+  if (currentState === "recording") {
+    startTimer();
+  } else if (previous === "recording") {
+    stopTimer();
+  }
+  disableSelection(currentState !== "idle");
   updateRecordButton();
-  disableSelection(false);
 }
 
 // Update record button state
@@ -538,6 +1294,7 @@ function updateRecordButton(): void {
   if (!recordBtn) return;
 
   recordBtn.classList.remove("recording", "saving");
+  if (recordLastBtn) recordLastBtn.disabled = currentState !== "idle";
 
   switch (currentState) {
     case "idle":
@@ -555,6 +1312,10 @@ function updateRecordButton(): void {
       recordBtn.disabled = false;
       recordBtn.classList.add("recording");
       break;
+    case "starting":
+      recordBtn.textContent = "Starting...";
+      recordBtn.disabled = true;
+      break;
     case "saving":
       recordBtn.textContent = "Saving...";
       recordBtn.disabled = true;
@@ -593,6 +1354,9 @@ function disableSelection(disabled: boolean): void {
   if (refreshBtn) {
     refreshBtn.disabled = disabled;
   }
+  if (addVirtualDisplayBtn) {
+    addVirtualDisplayBtn.disabled = disabled;
+  }
   if (refreshDisplaysBtn) {
     refreshDisplaysBtn.disabled = disabled;
   }
@@ -658,6 +1422,44 @@ function setStatus(message: string, isError = false): void {
   statusEl.classList.toggle("error", isError);
 }
 
+function isAppError(error: unknown): error is AppError {
+  return typeof error === "object" && error !== null && "code" in error && "message" in error;
+}
+
+// Show a failed command's error, with a dialog saying what to do about it
+// when the backend knows
+function reportError(context: string, error: unknown): void {
+  if (!isAppError(error)) {
+    setStatus(`${context}: ${error}`, true);
+    return;
+  }
+  setStatus(`${context}: ${error.message}`, true);
+  if (!errorDialog || !error.remediation) return;
+
+  const setText = (selector: string, text: string) => {
+    const el = errorDialog?.querySelector(selector);
+    if (el) el.textContent = text;
+  };
+  errorDialog.dataset.settingsUrl = error.settings_url ?? "";
+  setText("#error-dialog-title", context);
+  setText("#error-dialog-message", error.message);
+  setText("#error-dialog-remediation", error.remediation);
+  errorDialog.querySelector("#error-dialog-settings")?.classList.toggle("hidden", !error.settings_url);
+  errorDialog.showModal();
+}
+
+// Open the system settings that fix the error in the dialog
+async function openErrorSettings(): Promise<void> {
+  const url = errorDialog?.dataset.settingsUrl;
+  if (!url) return;
+  try {
+    await openUrl(url);
+    errorDialog?.close();
+  } catch (error) {
+    setStatus(`Failed to open settings: ${error}`, true);
+  }
+}
+
 // HTML escape helper
 function escapeHtml(text: string): string {
   const div = document.createElement("div");
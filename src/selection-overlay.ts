@@ -1,5 +1,6 @@
-import { getCurrentWindow, Window } from "@tauri-apps/api/window";
+import { getCurrentWindow, Window, PhysicalPosition, PhysicalSize } from "@tauri-apps/api/window";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 
 interface MonitorInfo {
   id: string;
@@ -9,6 +10,7 @@ interface MonitorInfo {
   width: number;
   height: number;
   is_primary: boolean;
+  scale_factor: number;
 }
 
 
@@ -20,6 +22,39 @@ interface CaptureRegion {
   y: number;
   width: number;
   height: number;
+  space: CoordinateSpace;
+}
+
+// Units of a region: logical pixels are physical pixels divided by the display scale
+type CoordinateSpace = "logical" | "physical";
+
+// Screen-space bounds of an area
+interface WindowBounds {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+}
+
+// Ratio of a selection's width to its height
+interface AspectRatio {
+  width: number;
+  height: number;
+}
+
+interface RegionSelectorSettings {
+  aspect_ratio: AspectRatio | null;
+  last_region: CaptureRegion | null;
+}
+
+// Pixels around the cursor, from the backend's magnifier
+interface MagnifierSample {
+  monitor_id: string;
+  x: number;
+  y: number;
+  color: string;
+  size: number;
+  pixels: number[];
 }
 
 // Tauri resize direction type
@@ -27,16 +62,37 @@ type ResizeDirection = "North" | "South" | "East" | "West" | "NorthEast" | "Nort
 
 // Constants
 const BORDER_WIDTH = 3; // Must match CSS --border-width
+const SETTLE_DELAY_MS = 200; // Snap and fit the selection once it has been still this long
+const LOUPE_ZOOM = 8; // Screen pixels per magnified pixel
+const LOUPE_OFFSET = 16; // Distance of the loupe from the cursor
 
 // State
 let monitors: MonitorInfo[] = [];
 let currentWindow: Awaited<ReturnType<typeof getCurrentWindow>>;
 let emitTimeout: number | null = null;
 let isHyprland = false;
+let settleTimeout: number | null = null;
+// Holding Alt turns snapping off
+let snapDisabled = false;
+// Ratio the selection is locked to, from the settings
+let aspectRatio: AspectRatio | null = null;
+// Holding Shift keeps the selection's current ratio while resizing
+let ratioHeld = false;
+// Record area as last settled, to tell which side the user resized
+let settledArea: WindowBounds | null = null;
+// Last known placement of the selector, for placing the cursor on the desktop
+let placement: SelectorPlacement | null = null;
+// Cursor position within the window, while it's over the window
+let pointer: { x: number; y: number } | null = null;
+let sampling = false;
+let samplePending = false;
 
 // DOM elements
 let dimensionsEl: HTMLElement;
 let dragAreaEl: HTMLElement;
+let loupeEl: HTMLElement;
+let loupeCanvas: HTMLCanvasElement;
+let loupeInfoEl: HTMLElement;
 
 // Map handle names to Tauri resize directions
 const handleToDirection: Record<string, ResizeDirection> = {
@@ -58,6 +114,9 @@ window.addEventListener("DOMContentLoaded", async () => {
 
   dimensionsEl = document.getElementById("dimensions")!;
   dragAreaEl = document.getElementById("drag-area")!;
+  loupeEl = document.getElementById("loupe")!;
+  loupeCanvas = document.getElementById("loupe-canvas") as HTMLCanvasElement;
+  loupeInfoEl = document.getElementById("loupe-info")!;
 
   // Check if running on Hyprland
   try {
@@ -114,24 +173,66 @@ window.addEventListener("DOMContentLoaded", async () => {
 
   // Close on Escape
   document.addEventListener("keydown", (e) => {
+    snapDisabled = e.altKey;
+    ratioHeld = e.shiftKey;
     if (e.key === "Escape") {
       closeOverlay();
     }
   });
+  document.addEventListener("keyup", (e) => {
+    snapDisabled = e.altKey;
+    ratioHeld = e.shiftKey;
+  });
+  document.addEventListener("mousemove", (e) => {
+    snapDisabled = e.altKey;
+    ratioHeld = e.shiftKey;
+    pointer = { x: e.clientX, y: e.clientY };
+    updateLoupe();
+  });
+  document.addEventListener("mouseleave", () => {
+    pointer = null;
+    loupeEl.classList.remove("visible");
+  });
 
   // Listen for window move/resize events to update display (throttled)
   currentWindow.onMoved(() => {
     throttledEmitRegionUpdate();
+    scheduleSettle();
   });
 
   currentWindow.onResized(() => {
     updateDisplay();
     throttledEmitRegionUpdate();
+    scheduleSettle();
   });
 
+  // Lock to the aspect ratio from the settings, and follow changes to it
+  try {
+    const settings = await invoke<RegionSelectorSettings>("get_region_selector_settings");
+    aspectRatio = settings.aspect_ratio;
+  } catch (e) {
+    console.error("Failed to get region selector settings:", e);
+  }
+  listen<RegionSelectorSettings>("region-selector-settings", (event) => {
+    aspectRatio = event.payload.aspect_ratio;
+    settledArea = null;
+    scheduleSettle();
+  });
+
+  // The main window places the selection on presets and the last region
+  // recorded, once it knows the selector is listening
+  listen<CaptureRegion>("region-selector-place", (event) => {
+    placeSelection(event.payload);
+  });
+  const mainWindow = await Window.getByLabel("main");
+  if (mainWindow) {
+    await mainWindow.emit("region-selector-ready", {});
+  }
+
   // Initial update
   updateDisplay();
   emitRegionUpdate();
+  scheduleSettle();
 });
 
 async function updateDisplay(): Promise<void> {
@@ -163,81 +264,149 @@ function throttledEmitRegionUpdate(): void {
   }, 50); // Max 20 updates per second
 }
 
-async function emitRegionUpdate(): Promise<void> {
+// Where the selector window is, and how far its border reaches in
+interface SelectorPlacement {
+  bounds: WindowBounds;
+  space: CoordinateSpace;
+  borderOffset: number;
+}
+
+async function getSelectorPlacement(): Promise<SelectorPlacement> {
   // On Wayland, Tauri's outerPosition() returns (0,0) - it doesn't work
   // Instead, query Hyprland directly for the window position
-  let windowX: number;
-  let windowY: number;
-  let windowWidth: number;
-  let windowHeight: number;
-  
   try {
-    // Get position from Hyprland (returns physical pixels)
-    const [x, y, w, h] = await invoke<[number, number, number, number]>("get_region_selector_position");
-    windowX = x;
-    windowY = y;
-    windowWidth = w;
-    windowHeight = h;
-    console.log("Position from Hyprland:", windowX, windowY, windowWidth, "x", windowHeight);
+    // Get position from Hyprland (returns logical layout coordinates)
+    const [x, y, width, height] = await invoke<[number, number, number, number]>("get_region_selector_position");
+    console.log("Position from Hyprland:", x, y, width, "x", height);
+    return { bounds: { x, y, width, height }, space: "logical", borderOffset: BORDER_WIDTH + 1 };
   } catch (e) {
     console.error("Failed to get position from Hyprland:", e);
     // Fallback to Tauri (won't work correctly on Wayland but better than nothing)
     const pos = await currentWindow.outerPosition();
     const size = await currentWindow.innerSize();
-    windowX = pos.x;
-    windowY = pos.y;
-    windowWidth = size.width;
-    windowHeight = size.height;
-    console.log("Fallback to Tauri position:", windowX, windowY, windowWidth, "x", windowHeight);
+    console.log("Fallback to Tauri position:", pos.x, pos.y, size.width, "x", size.height);
+    return {
+      bounds: { x: pos.x, y: pos.y, width: size.width, height: size.height },
+      space: "physical",
+      borderOffset: Math.round((BORDER_WIDTH + 1) * window.devicePixelRatio),
+    };
+  }
+}
+
+// Snap the selection to nearby window and monitor edges, and fit it to the
+// aspect ratio, once it stops moving
+function scheduleSettle(): void {
+  if (settleTimeout !== null) {
+    window.clearTimeout(settleTimeout);
   }
+  settleTimeout = window.setTimeout(() => {
+    settleTimeout = null;
+    settleSelection();
+  }, SETTLE_DELAY_MS);
+}
+
+async function settleSelection(): Promise<void> {
+  const { bounds, space, borderOffset } = await getSelectorPlacement();
+  const area: WindowBounds = {
+    x: Math.round(bounds.x + borderOffset),
+    y: Math.round(bounds.y + borderOffset),
+    width: Math.max(0, Math.round(bounds.width - borderOffset * 2)),
+    height: Math.max(0, Math.round(bounds.height - borderOffset * 2)),
+  };
+
+  const previous = settledArea;
+  if (previous && previous.x === area.x && previous.y === area.y && previous.width === area.width && previous.height === area.height) {
+    return; // Unchanged since it last settled, e.g. after being placed
+  }
+  const ratio = aspectRatio ?? (ratioHeld && previous ? { width: previous.width, height: previous.height } : null);
+  // Keep the side the user changed most
+  const keepWidth = !previous || Math.abs(area.width - previous.width) >= Math.abs(area.height - previous.height);
+  const settled = await invoke<WindowBounds>("fit_desktop_region", {
+    ...area,
+    snap: !snapDisabled,
+    aspectRatio: ratio,
+    keepWidth,
+  });
+  settledArea = settled;
+  if (settled.x === area.x && settled.y === area.y && settled.width === area.width && settled.height === area.height) {
+    return;
+  }
+  console.log("Settled selection at:", settled);
+
+  await moveSelector(settled, space, borderOffset);
+}
+
+// Place the selection on a region, e.g. a preset size or the last one recorded
+async function placeSelection(region: CaptureRegion): Promise<void> {
+  const { space, borderOffset } = await getSelectorPlacement();
+  try {
+    const area = await invoke<WindowBounds>("desktop_region_bounds", { region });
+    settledArea = area;
+    console.log("Placing selection at:", area);
+    await moveSelector(area, space, borderOffset);
+  } catch (e) {
+    console.error("Failed to place selection:", e);
+  }
+}
+
+// Move the selector so its record area covers `area`
+async function moveSelector(area: WindowBounds, space: CoordinateSpace, borderOffset: number): Promise<void> {
+  // Keep the border outside the area
+  const target: WindowBounds = {
+    x: area.x - borderOffset,
+    y: area.y - borderOffset,
+    width: area.width + borderOffset * 2,
+    height: area.height + borderOffset * 2,
+  };
+  try {
+    if (space === "logical") {
+      await invoke("move_region_selector", target);
+    } else {
+      await currentWindow.setPosition(new PhysicalPosition(target.x, target.y));
+      await currentWindow.setSize(new PhysicalSize(target.width, target.height));
+    }
+  } catch (e) {
+    console.error("Failed to move selection:", e);
+  }
+}
+
+async function emitRegionUpdate(): Promise<void> {
+  placement = await getSelectorPlacement();
+  const { bounds, space, borderOffset } = placement;
+  // The cursor moves with the window while dragging, so it's over new pixels
+  updateLoupe();
 
   // The actual recording area is inside the border
-  // Hyprland returns physical pixels
   // Add 1 extra pixel to ensure the border is completely outside the recording area
   // This accounts for any rounding issues due to scaling
-  const borderOffset = BORDER_WIDTH + 1;
-  const recordX = windowX + borderOffset;
-  const recordY = windowY + borderOffset;
-  const recordWidth = windowWidth - (borderOffset * 2);
-  const recordHeight = windowHeight - (borderOffset * 2);
-
-  console.log("Record area (physical):", recordX, recordY, recordWidth, "x", recordHeight);
-
-  // Find which monitor the center of the selection is on
-  const centerX = recordX + recordWidth / 2;
-  const centerY = recordY + recordHeight / 2;
-
-  console.log("Looking for monitor containing point:", centerX, centerY);
-  console.log("Available monitors:", monitors);
-
-  let monitor = findMonitorAt(centerX, centerY);
-  if (!monitor) {
-    console.log("No monitor found at center point, trying all monitors...");
-    // Debug: show all monitor bounds
-    for (const m of monitors) {
-      console.log(`  ${m.id}: (${m.x}, ${m.y}) to (${m.x + m.width}, ${m.y + m.height})`);
-    }
-    if (monitors.length > 0) {
-      monitor = monitors[0];
-    }
-  }
+  const recordX = bounds.x + borderOffset;
+  const recordY = bounds.y + borderOffset;
+  const recordWidth = bounds.width - (borderOffset * 2);
+  const recordHeight = bounds.height - (borderOffset * 2);
+
+  console.log(`Record area (${space}):`, recordX, recordY, recordWidth, "x", recordHeight);
 
-  if (!monitor) {
-    console.log("No monitors available!");
+  // Let the backend place the area in the desktop layout; selections that
+  // span monitors are clipped to the monitor showing most of them
+  let resolved: Omit<CaptureRegion, "monitor_name">;
+  try {
+    resolved = await invoke<Omit<CaptureRegion, "monitor_name">>("resolve_desktop_region", {
+      x: Math.round(recordX),
+      y: Math.round(recordY),
+      width: Math.max(0, Math.round(recordWidth)),
+      height: Math.max(0, Math.round(recordHeight)),
+    });
+  } catch (e) {
+    console.log("Selection is not on any monitor:", e);
     return;
   }
 
-  // Convert to monitor-relative coordinates (both in physical pixels from Hyprland)
+  const monitor = monitors.find((m) => m.id === resolved.monitor_id);
   const region: CaptureRegion = {
-    monitor_id: monitor.id,
-    monitor_name: monitor.name,
-    x: recordX - monitor.x,
-    y: recordY - monitor.y,
-    width: recordWidth,
-    height: recordHeight,
+    ...resolved,
+    monitor_name: monitor?.name ?? resolved.monitor_id,
   };
 
-  console.log("Selected monitor:", monitor.id, "at", monitor.x, ",", monitor.y);
   console.log("Final region:", region);
   
   // Emit to main window
@@ -247,18 +416,76 @@ async function emitRegionUpdate(): Promise<void> {
   }
 }
 
-function findMonitorAt(x: number, y: number): MonitorInfo | null {
-  for (const monitor of monitors) {
-    if (
-      x >= monitor.x &&
-      x < monitor.x + monitor.width &&
-      y >= monitor.y &&
-      y < monitor.y + monitor.height
-    ) {
-      return monitor;
+// Show the pixels under the cursor, magnified, next to it
+async function updateLoupe(): Promise<void> {
+  if (sampling) {
+    samplePending = true;
+    return;
+  }
+  if (!pointer || !placement) {
+    return;
+  }
+  sampling = true;
+
+  // Cursor position on the desktop, in the selector's units
+  const scale = placement.space === "logical" ? 1 : window.devicePixelRatio;
+  const at = pointer;
+  try {
+    const sample = await invoke<MagnifierSample>("sample_magnifier", {
+      x: placement.bounds.x + at.x * scale,
+      y: placement.bounds.y + at.y * scale,
+    });
+    drawLoupe(sample, at);
+  } catch {
+    // Off the monitors, or the magnifier couldn't capture them
+    loupeEl.classList.remove("visible");
+  }
+
+  sampling = false;
+  if (samplePending) {
+    samplePending = false;
+    updateLoupe();
+  }
+}
+
+function drawLoupe(sample: MagnifierSample, at: { x: number; y: number }): void {
+  const side = sample.size * LOUPE_ZOOM;
+  loupeCanvas.width = side;
+  loupeCanvas.height = side;
+  const ctx = loupeCanvas.getContext("2d")!;
+  ctx.clearRect(0, 0, side, side);
+  for (let row = 0; row < sample.size; row++) {
+    for (let column = 0; column < sample.size; column++) {
+      const i = (row * sample.size + column) * 4;
+      if (sample.pixels[i + 3] === 0) {
+        continue;
+      }
+      ctx.fillStyle = `rgb(${sample.pixels[i]}, ${sample.pixels[i + 1]}, ${sample.pixels[i + 2]})`;
+      ctx.fillRect(column * LOUPE_ZOOM, row * LOUPE_ZOOM, LOUPE_ZOOM, LOUPE_ZOOM);
     }
   }
-  return null;
+
+  // Crosshair around the pixel under the cursor
+  const center = Math.floor(sample.size / 2) * LOUPE_ZOOM;
+  ctx.strokeStyle = "#fff";
+  ctx.lineWidth = 1;
+  ctx.strokeRect(center + 0.5, center + 0.5, LOUPE_ZOOM - 1, LOUPE_ZOOM - 1);
+  ctx.strokeStyle = "#000";
+  ctx.strokeRect(center - 0.5, center - 0.5, LOUPE_ZOOM + 1, LOUPE_ZOOM + 1);
+
+  loupeInfoEl.innerHTML = "";
+  const swatch = document.createElement("span");
+  swatch.id = "loupe-swatch";
+  swatch.style.background = sample.color;
+  loupeInfoEl.append(swatch, sample.color, document.createElement("br"), `${sample.x}, ${sample.y}`);
+
+  // Below-right of the cursor, flipped to stay inside the window
+  loupeEl.classList.add("visible");
+  const { offsetWidth: width, offsetHeight: height } = loupeEl;
+  const left = at.x + LOUPE_OFFSET + width > window.innerWidth ? at.x - LOUPE_OFFSET - width : at.x + LOUPE_OFFSET;
+  const top = at.y + LOUPE_OFFSET + height > window.innerHeight ? at.y - LOUPE_OFFSET - height : at.y + LOUPE_OFFSET;
+  loupeEl.style.left = `${Math.max(0, left)}px`;
+  loupeEl.style.top = `${Math.max(0, top)}px`;
 }
 
 async function closeOverlay(): Promise<void> {
@@ -16,6 +16,7 @@ export default defineConfig(async () => ({
       input: {
         main: resolve(__dirname, "index.html"),
         "selection-overlay": resolve(__dirname, "src/selection-overlay.html"),
+        "countdown-overlay": resolve(__dirname, "src/countdown-overlay.html"),
       },
     },
   },